@@ -4,21 +4,57 @@
 
 use anyhow::{anyhow, Result};
 use cranelift::prelude::*;
-use cranelift_codegen::ir::AbiParam;
+use cranelift_codegen::control::ControlPlane;
+use cranelift_codegen::ir::{AbiParam, InstructionData, Opcode, UserExternalName, ValueDef, ValueLabel};
 use cranelift_codegen::settings::{self, Configurable};
 use cranelift_codegen::Context;
-use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext, Variable};
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext, Switch, Variable};
 use cranelift_module::{DataDescription, Init, Linkage, Module};
 use cranelift_object::{ObjectBuilder, ObjectModule};
-use std::cell::Cell;
+use perry_diagnostics::{SourceCache, Span};
+use perry_runtime::symbols::{Symbol, SymbolKind};
+
+use crate::debuginfo::{self, DebugInfo, FunctionSource};
+use crate::function_cache::FunctionCache;
+use crate::module::CodegenModule;
+use crate::shards::{self, PendingFunction};
+use crate::wasm::WasmModule;
+use std::cell::{Cell, RefCell};
 use std::collections::{HashMap, HashSet};
 use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 
 /// Thread-local tracking of the current function being compiled (for self-recursive call optimization)
 thread_local! {
     static CURRENT_FUNC_HIR_ID: Cell<Option<u32>> = Cell::new(None);
 }
 
+// Sources of the program being compiled, for turning HIR spans into `path:line:col`
+thread_local! {
+    static SOURCES: RefCell<Option<Arc<SourceCache>>> = RefCell::new(None);
+}
+
+/// `path:line:col` of a span, when it points into a known source file
+fn source_location(span: Span) -> Option<String> {
+    SOURCES.with(|sources| {
+        let sources = sources.borrow();
+        sources.as_ref()?.location(span).map(|location| location.to_string())
+    })
+}
+
+/// `def_var` that also labels the value with its variable, so debug info can tell where
+/// each local lives (a no-op unless the function collects debug info)
+trait DefLocal {
+    fn def_local(&mut self, var: Variable, val: Value);
+}
+
+impl DefLocal for FunctionBuilder<'_> {
+    fn def_local(&mut self, var: Variable, val: Value) {
+        self.def_var(var, val);
+        self.set_val_label(val, ValueLabel::from_u32(var.as_u32()));
+    }
+}
+
 /// Global counter for generating unique temporary variable IDs
 static TEMP_VAR_COUNTER: AtomicUsize = AtomicUsize::new(10000);
 
@@ -44,7 +80,8 @@ fn next_js_data_id() -> usize {
 }
 
 use perry_hir::{
-    ArrayElement, BinaryOp, CallArg, CatchClause, Class, ClassField, CompareOp, Decorator, Expr, Function, LogicalOp, Module as HirModule, Stmt, UnaryOp, UpdateOp,
+    ArrayElement, BinaryOp, CallArg, CatchClause, Class, ClassField, CompareOp, Decorator, Export, Expr, Function, ImportMetaProperty, LogicalOp, Memoize, Module as HirModule, OptLevel, Stmt, SwitchCase, UnaryOp, UpdateOp,
+    SYMBOL_ASYNC_DISPOSE_KEY, SYMBOL_DISPOSE_KEY,
 };
 use perry_types::LocalId;
 use cranelift_codegen::ir::{Block, StackSlot, StackSlotData, StackSlotKind, TrapCode};
@@ -107,6 +144,8 @@ struct LocalInfo {
     product_cache: Option<HashMap<LocalId, Variable>>,
     /// Cached raw I64 pointer for arrays (avoids redundant js_nanbox_get_pointer calls in loops)
     cached_array_ptr: Option<Variable>,
+    /// Member classes of an object union (`Circle | Square`), see `object_union_classes`
+    union_classes: Vec<String>,
 }
 
 /// Check if a block has been filled with a terminating instruction
@@ -118,6 +157,80 @@ fn is_block_filled(builder: &FunctionBuilder, block: Block) -> bool {
     }
 }
 
+/// The class a value of `ty` is an instance of, so its fields keep the class's offsets: the
+/// class itself, the class among an intersection's members, or the class under `Readonly<C>`
+/// or `Required<C>`, which only change modifiers. `Partial`, `Pick` and `Omit` values are
+/// often plain objects with another layout, so they get none.
+fn instance_class(ty: &perry_types::Type, classes: &HashMap<String, ClassMeta>) -> Option<String> {
+    match ty {
+        perry_types::Type::Named(name) if classes.contains_key(name) => Some(name.clone()),
+        perry_types::Type::Intersection(members) => members.iter().find_map(|member| instance_class(member, classes)),
+        perry_types::Type::Generic { base, type_args } if matches!(base.as_str(), "Readonly" | "Required") => {
+            instance_class(type_args.first()?, classes)
+        }
+        _ => None,
+    }
+}
+
+/// Member classes of a union whose every member is a class instance, e.g. `Circle | Square`.
+/// Such a value is an unboxed object pointer tagged by the class id in its header, so its
+/// fields can be read at the offset the tag selects (see `union_field_address`) instead of
+/// looked up by name. Empty for any other type.
+fn object_union_classes(ty: &perry_types::Type, classes: &HashMap<String, ClassMeta>) -> Vec<String> {
+    let perry_types::Type::Union(members) = ty else { return Vec::new() };
+    let mut names = Vec::new();
+    for member in members {
+        let Some(name) = instance_class(member, classes) else { return Vec::new() };
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    if names.len() > 1 { names } else { Vec::new() }
+}
+
+/// Address of `property` in an object union's payload. When the field has the same index in
+/// every member this is a constant offset; otherwise the class id in the object header is
+/// compared against each member's and the offset chosen with `select`, so the access stays
+/// branch-free. None if a member lacks the field or routes it through an accessor, or has
+/// subclasses, whose instances carry class ids the tag can't enumerate.
+fn union_field_address(
+    builder: &mut FunctionBuilder,
+    obj_val: Value,
+    members: &[String],
+    classes: &HashMap<String, ClassMeta>,
+    property: &str,
+) -> Option<Value> {
+    let mut slots = Vec::new();
+    for name in members {
+        let meta = classes.get(name)?;
+        if meta.getter_ids.contains_key(property) || meta.setter_ids.contains_key(property) {
+            return None;
+        }
+        if classes.values().any(|other| other.parent_class.as_deref() == Some(name.as_str())) {
+            return None;
+        }
+        slots.push((meta.id, *meta.field_indices.get(property)?));
+    }
+    // ObjectHeader is 24 bytes, fields start after that
+    let field_offset = |index: u32| 24 + index as i64 * 8;
+
+    // Union values may arrive NaN-boxed; the payload is the low 48 bits either way
+    let obj_ptr = ensure_i64(builder, obj_val);
+    let obj_ptr = builder.ins().band_imm(obj_ptr, 0x0000_FFFF_FFFF_FFFF);
+    let (_, default_index) = *slots.last()?;
+    let mut offset = builder.ins().iconst(types::I64, field_offset(default_index));
+    if slots.iter().any(|&(_, index)| index != default_index) {
+        // ObjectHeader.class_id follows the u32 object_type
+        let class_id = builder.ins().load(types::I32, MemFlags::trusted(), obj_ptr, 4);
+        for &(id, index) in slots.iter().filter(|&&(_, index)| index != default_index) {
+            let is_member = builder.ins().icmp_imm(IntCC::Equal, class_id, id as i64);
+            let member_offset = builder.ins().iconst(types::I64, field_offset(index));
+            offset = builder.ins().select(is_member, member_offset, offset);
+        }
+    }
+    Some(builder.ins().iadd(obj_ptr, offset))
+}
+
 /// Convert a HIR Type to a Cranelift ABI type (standalone version)
 fn type_to_cranelift_abi(ty: &perry_types::Type) -> types::Type {
     use perry_types::Type;
@@ -127,7 +240,7 @@ fn type_to_cranelift_abi(ty: &perry_types::Type) -> types::Type {
         // Booleans can be f64 (0.0 or 1.0) for simplicity
         Type::Boolean => types::F64,
         // Strings, arrays, objects, promises are pointers (i64)
-        Type::String | Type::Array(_) | Type::Object(_) |
+        Type::String | Type::Array(_) | Type::Object(_) | Type::Intersection(_) |
         Type::Promise(_) | Type::Named(_) | Type::Generic { .. } => types::I64,
         // Void/Null/Undefined return f64 (will be 0)
         Type::Void | Type::Null => types::F64,
@@ -137,14 +250,18 @@ fn type_to_cranelift_abi(ty: &perry_types::Type) -> types::Type {
         Type::Function(_) => types::I64,
         // Tuples use i64 (could be more complex)
         Type::Tuple(_) => types::I64,
+        // Unions of string literals are strings
+        Type::Union(_) if ty.is_string() => types::I64,
         // Union types use f64 (NaN-boxed values can be numbers or pointers)
         Type::Union(_) => types::F64,
         // Never type - use f64 as fallback (never actually returned)
         Type::Never => types::F64,
         // TypeVar should be substituted before codegen; default to f64
-        Type::TypeVar(_) => types::F64,
+        Type::TypeVar(_) | Type::Conditional(_) | Type::Infer(_) | Type::KeyOf(_) | Type::IndexedAccess { .. } => types::F64,
         // Symbol is an i64 id
         Type::Symbol => types::I64,
+        // Literal types share their primitive's representation
+        Type::Literal(lit) => type_to_cranelift_abi(&lit.widened()),
     }
 }
 
@@ -178,6 +295,209 @@ fn ensure_f64(builder: &mut FunctionBuilder, val: Value) -> Value {
     }
 }
 
+/// Value passed for an omitted argument: NaN-boxed undefined, which the callee's
+/// default-parameter prologue (`if (param === undefined) ...`) recognizes
+fn missing_arg(builder: &mut FunctionBuilder) -> Value {
+    const TAG_UNDEFINED: u64 = 0x7FFC_0000_0000_0001;
+    builder.ins().f64const(f64::from_bits(TAG_UNDEFINED))
+}
+
+/// The untagged i32 behind a number that is statically an int32: an i32 converted to f64
+/// (loop counters, lengths, bit operation results) or an integral f64 constant in range.
+/// None for anything else, including -0, which has no int32 form
+fn int32_value(builder: &mut FunctionBuilder, val: Value) -> Option<Value> {
+    let ValueDef::Result(inst, _) = builder.func.dfg.value_def(val) else {
+        return None;
+    };
+    match builder.func.dfg.insts[inst] {
+        InstructionData::Unary { opcode: Opcode::FcvtFromSint, arg } if builder.func.dfg.value_type(arg) == types::I32 => Some(arg),
+        InstructionData::UnaryIeee64 { opcode: Opcode::F64const, imm } => {
+            let n = f64::from_bits(imm.bits());
+            let is_int32 = n.fract() == 0.0 && n >= i32::MIN as f64 && n <= i32::MAX as f64 && !(n == 0.0 && n.is_sign_negative());
+            is_int32.then(|| builder.ins().iconst(types::I32, n as i64))
+        }
+        _ => None,
+    }
+}
+
+/// ECMAScript ToInt32 of a number, as bit operations apply it: NaN and infinities are 0,
+/// anything else is truncated and wrapped modulo 2^32. Unlike `fcvt_to_sint`, never traps.
+fn to_int32(builder: &mut FunctionBuilder, val: Value) -> Value {
+    let val = ensure_f64(builder, val);
+    if let Some(int32) = int32_value(builder, val) {
+        return int32;
+    }
+    // A uint32 (the result of >>>) has the same bits as its int32
+    if let ValueDef::Result(inst, _) = builder.func.dfg.value_def(val) {
+        if let InstructionData::Unary { opcode: Opcode::FcvtFromUint, arg } = builder.func.dfg.insts[inst] {
+            if builder.func.dfg.value_type(arg) == types::I32 {
+                return arg;
+            }
+        }
+    }
+    // x - trunc(x / 2^32) * 2^32 is exact and within (-2^32, 2^32), so the saturating
+    // conversion to i64 keeps it; NaN, also what infinities turn into here, converts to 0
+    let inverse_scale = builder.ins().f64const(1.0 / 4294967296.0);
+    let scale = builder.ins().f64const(4294967296.0);
+    let quotient = builder.ins().fmul(val, inverse_scale);
+    let quotient = builder.ins().trunc(quotient);
+    let whole = builder.ins().fmul(quotient, scale);
+    let remainder = builder.ins().fsub(val, whole);
+    let wide = builder.ins().fcvt_to_sint_sat(types::I64, remainder);
+    builder.ins().ireduce(types::I32, wide)
+}
+
+/// An array or string index as i32: an int32 is used as is, anything else is truncated,
+/// saturating rather than trapping on NaN or values out of range
+fn index_i32(builder: &mut FunctionBuilder, val: Value) -> Value {
+    let val = ensure_f64(builder, val);
+    match int32_value(builder, val) {
+        Some(index) => index,
+        None => builder.ins().fcvt_to_sint_sat(types::I32, val),
+    }
+}
+
+/// `a op b` (`op` is +, - or *) on untagged int32 operands, as an f64. The i32 instruction
+/// computes it; a result that overflows int32 deopts to the f64 instruction on `lhs` and
+/// `rhs`, as does a zero product with a negative operand, which is -0 in JavaScript
+fn int32_arith(builder: &mut FunctionBuilder, op: BinaryOp, a: Value, b: Value, lhs: Value, rhs: Value) -> Value {
+    let (result, overflow) = match op {
+        BinaryOp::Add => builder.ins().sadd_overflow(a, b),
+        BinaryOp::Sub => builder.ins().ssub_overflow(a, b),
+        BinaryOp::Mul => builder.ins().smul_overflow(a, b),
+        _ => unreachable!("int32_arith on {:?}", op),
+    };
+    let deopt = if op == BinaryOp::Mul {
+        let is_zero = builder.ins().icmp_imm(IntCC::Equal, result, 0);
+        let signs = builder.ins().bor(a, b);
+        let has_negative = builder.ins().icmp_imm(IntCC::SignedLessThan, signs, 0);
+        let negative_zero = builder.ins().band(is_zero, has_negative);
+        builder.ins().bor(overflow, negative_zero)
+    } else {
+        overflow
+    };
+
+    let fast_block = builder.create_block();
+    let deopt_block = builder.create_block();
+    let merge_block = builder.create_block();
+    builder.append_block_param(merge_block, types::F64);
+    builder.ins().brif(deopt, deopt_block, &[], fast_block, &[]);
+
+    builder.switch_to_block(fast_block);
+    builder.seal_block(fast_block);
+    let fast = builder.ins().fcvt_from_sint(types::F64, result);
+    builder.ins().jump(merge_block, &[fast]);
+
+    builder.switch_to_block(deopt_block);
+    builder.seal_block(deopt_block);
+    let slow = match op {
+        BinaryOp::Add => builder.ins().fadd(lhs, rhs),
+        BinaryOp::Sub => builder.ins().fsub(lhs, rhs),
+        _ => builder.ins().fmul(lhs, rhs),
+    };
+    builder.ins().jump(merge_block, &[slow]);
+
+    builder.switch_to_block(merge_block);
+    builder.seal_block(merge_block);
+    builder.block_params(merge_block)[0]
+}
+
+/// Record a closure's parameter count (see js_closure_set_arity)
+fn set_closure_arity(
+    builder: &mut FunctionBuilder,
+    module: &mut CodegenModule,
+    extern_funcs: &HashMap<String, cranelift_module::FuncId>,
+    closure_ptr: Value,
+    arity: usize,
+) -> Result<()> {
+    let set_arity_func = extern_funcs.get("js_closure_set_arity")
+        .ok_or_else(|| anyhow!("js_closure_set_arity not declared"))?;
+    let set_arity_ref = module.declare_func_in_func(*set_arity_func, builder.func);
+    let arity_val = builder.ins().iconst(types::I32, arity as i64);
+    builder.ins().call(set_arity_ref, &[closure_ptr, arity_val]);
+    Ok(())
+}
+
+/// str.localeCompare(that, locales, options), str.toLocaleUpperCase(locales) or
+/// str.toLocaleLowerCase(locales) on the string at `str_ptr`. Omitted arguments are passed
+/// as undefined, which the runtime reads as the default locale and options.
+fn compile_locale_string_method(
+    builder: &mut FunctionBuilder,
+    module: &mut CodegenModule,
+    extern_funcs: &HashMap<String, cranelift_module::FuncId>,
+    method: &str,
+    str_ptr: Value,
+    arg_vals: &[Value],
+) -> Result<Value> {
+    let arg = |builder: &mut FunctionBuilder, i: usize| match arg_vals.get(i) {
+        Some(&val) => ensure_f64(builder, val),
+        None => missing_arg(builder),
+    };
+    if method == "localeCompare" {
+        let that = arg(builder, 0);
+        let locales = arg(builder, 1);
+        let options = arg(builder, 2);
+        let get_str_ptr_func = extern_funcs.get("js_get_string_pointer_unified")
+            .ok_or_else(|| anyhow!("js_get_string_pointer_unified not declared"))?;
+        let get_str_ptr_ref = module.declare_func_in_func(*get_str_ptr_func, builder.func);
+        let that_call = builder.ins().call(get_str_ptr_ref, &[that]);
+        let that_ptr = builder.inst_results(that_call)[0];
+        let compare_func = extern_funcs.get("js_string_locale_compare")
+            .ok_or_else(|| anyhow!("js_string_locale_compare not declared"))?;
+        let func_ref = module.declare_func_in_func(*compare_func, builder.func);
+        let call = builder.ins().call(func_ref, &[str_ptr, that_ptr, locales, options]);
+        return Ok(builder.inst_results(call)[0]);
+    }
+
+    let func_name = if method == "toLocaleUpperCase" {
+        "js_string_to_locale_upper_case"
+    } else {
+        "js_string_to_locale_lower_case"
+    };
+    let locales = arg(builder, 0);
+    let case_func = extern_funcs.get(func_name)
+        .ok_or_else(|| anyhow!("{} not declared", func_name))?;
+    let func_ref = module.declare_func_in_func(*case_func, builder.func);
+    let call = builder.ins().call(func_ref, &[str_ptr, locales]);
+    let result_ptr = builder.inst_results(call)[0];
+    // NaN-box the result string pointer
+    let nanbox_func = extern_funcs.get("js_nanbox_string")
+        .ok_or_else(|| anyhow!("js_nanbox_string not declared"))?;
+    let nanbox_ref = module.declare_func_in_func(*nanbox_func, builder.func);
+    let nanbox_call = builder.ins().call(nanbox_ref, &[result_ptr]);
+    Ok(builder.inst_results(nanbox_call)[0])
+}
+
+/// A value passed into the JS runtime, as f64. Instances of compiled classes are raw
+/// pointers; they are NaN-boxed so the V8 bridge can wrap them as objects.
+fn js_interop_arg(
+    builder: &mut FunctionBuilder,
+    module: &mut CodegenModule,
+    extern_funcs: &HashMap<String, cranelift_module::FuncId>,
+    classes: &HashMap<String, ClassMeta>,
+    locals: &HashMap<LocalId, LocalInfo>,
+    arg: &Expr,
+    val: Value,
+) -> Result<Value> {
+    let is_instance = match arg {
+        Expr::This => true,
+        Expr::New { class_name, .. } => classes.contains_key(class_name),
+        Expr::LocalGet(id) => locals.get(id)
+            .and_then(|info| info.class_name.as_ref())
+            .is_some_and(|name| classes.contains_key(name)),
+        _ => false,
+    };
+    if !is_instance {
+        return Ok(ensure_f64(builder, val));
+    }
+    let nanbox_func = extern_funcs.get("js_nanbox_pointer")
+        .ok_or_else(|| anyhow!("js_nanbox_pointer not declared"))?;
+    let nanbox_ref = module.declare_func_in_func(*nanbox_func, builder.func);
+    let ptr = ensure_i64(builder, val);
+    let call = builder.ins().call(nanbox_ref, &[ptr]);
+    Ok(builder.inst_results(call)[0])
+}
+
 /// Metadata about a compiled class
 #[derive(Debug, Clone)]
 struct ClassMeta {
@@ -222,8 +542,8 @@ pub enum EnumMemberValue {
 
 /// The main compiler that generates native code from HIR
 pub struct Compiler {
-    /// Cranelift module for the object file
-    module: ObjectModule,
+    /// Cranelift module for the object file (or WebAssembly object)
+    module: CodegenModule,
     /// Cranelift context for function compilation
     ctx: Context,
     /// Function builder context (reused across functions)
@@ -285,29 +605,131 @@ pub struct Compiler {
     /// Imported function parameter counts: function name -> param count
     /// Used to ensure consistent wrapper signatures for functions with optional params
     imported_func_param_counts: HashMap<String, usize>,
+    /// Whether to emit crash reporting hooks (handler install + TS shadow stack frames)
+    crash_reporting: bool,
+    /// Function name data for crash shadow stack frames: name -> data ID
+    crash_frame_names: HashMap<String, cranelift_module::DataId>,
+    /// Heap ceiling in bytes baked into the entry module (None = unlimited)
+    max_heap: Option<u64>,
+    /// Abort with a report instead of throwing a RangeError when the ceiling is hit
+    oom_abort: bool,
+    /// OpenAPI document the entry module hands to the Fastify runtime to serve at /docs
+    docs_document: Option<String>,
+    /// Time zone table (`--embed-tzdata`) the entry module hands to the Temporal runtime
+    tzdata: Option<Vec<u8>>,
+    /// Allocator the executable is linked against (`--allocator`), reported by
+    /// process.allocationStats(); None for the system allocator
+    allocator: Option<String>,
+    /// Reflection metadata of this module's classes, registered at init for perry/reflect:
+    /// (class id, `name\tparent_id` line followed by a `name\ttype\treadonly` line per field)
+    class_metadata: Vec<(u32, String)>,
+    /// Serialized perry.toml `[permissions]` the entry module installs before anything runs
+    permissions: Option<String>,
+    /// Sources the spans of HIR nodes point into
+    sources: Option<Arc<SourceCache>>,
+    /// Name of the module being compiled, the first component of its mangled symbols
+    module_name: String,
+    /// Monomorphized functions and classes: specialized name -> (generic name, type args)
+    specializations: HashMap<String, (String, Vec<String>)>,
+    /// Function or class member each closure is defined in: closure id -> path
+    closure_owners: HashMap<u32, Vec<String>>,
+    /// DWARF debug info for the functions defined so far (`--debug`)
+    debug_info: Option<DebugInfo>,
+    /// Machine code of the module's functions from its previous build
+    function_cache: Option<Arc<FunctionCache>>,
+    /// Functions whose IR is built, compiled together before the object is written
+    queued_functions: Vec<PendingFunction>,
+    queued_function_ids: HashSet<cranelift_module::FuncId>,
+    /// Name, span and named locals of the function being built, for its debug info
+    debug_function: Option<FunctionSource>,
+    /// ISA of the x86-64-v3 versions of numeric functions (`// @perry-opt: multiversion`);
+    /// None when the module isn't multiversioned or the baseline already has the features
+    cpu_variant_isa: Option<isa::OwnedTargetIsa>,
+    /// Dispatch slots of multiversioned functions, with the x86-64-v3 version each is
+    /// pointed at when the CPU has the features
+    cpu_variant_slots: Vec<(cranelift_module::DataId, cranelift_module::FuncId)>,
+    /// Build for `perry dev`'s hot reload (`--hot-reload`): functions and writable globals
+    /// get exported symbols a patch can bind to, and main starts the reload agent
+    hot_reload: bool,
+}
+
+/// Cranelift settings for a build, beyond the optimization level each module picks
+#[derive(Debug, Clone, Default)]
+pub struct CraneliftSettings {
+    /// Run the Cranelift IR verifier on every function. Off by default: it catches
+    /// codegen bugs but slows release builds down
+    pub verifier: bool,
+    /// Further settings by name, in order: shared ones like `regalloc_checker` or
+    /// `enable_alias_analysis`, then ones for the target ISA like `has_avx2`
+    pub flags: Vec<(String, String)>,
+    /// Triple to generate code for, e.g. `aarch64-unknown-linux-gnu`. None for the host,
+    /// with the CPU features detected on it; a named target gets the ISA's baseline.
+    /// `wasm32-*` triples get WebAssembly (see `wasm`).
+    pub target: Option<String>,
 }
 
 impl Compiler {
     /// Create a new compiler for the host target
     pub fn new() -> Result<Self> {
+        Self::with_settings(OptLevel::Speed, &CraneliftSettings::default())
+    }
+
+    /// Create a compiler for the settings' target (the host by default) that optimizes at
+    /// `opt_level` (the build's level, or a module's `// @perry-opt:` directive)
+    pub fn with_settings(opt_level: OptLevel, cranelift: &CraneliftSettings) -> Result<Self> {
         let mut flag_builder = settings::builder();
         flag_builder.set("use_colocated_libcalls", "false").unwrap();
-        // Enable PIC for macOS compatibility
-        flag_builder.set("is_pic", "true").unwrap();
-        // Enable maximum optimization
-        flag_builder.set("opt_level", "speed").unwrap();
-
-        let isa_builder = cranelift_native::builder().map_err(|e| anyhow!("{}", e))?;
+        let opt_level = match opt_level {
+            OptLevel::Speed => "speed",
+            OptLevel::Size => "speed_and_size",
+            OptLevel::None => "none",
+        };
+        flag_builder.set("opt_level", opt_level).unwrap();
+        flag_builder.set("enable_verifier", if cranelift.verifier { "true" } else { "false" }).unwrap();
+
+        // Names the shared settings don't know go to the ISA, which detected the host's
+        // features (or starts from the target's baseline) and lets them be overridden.
+        // WebAssembly is translated from IR built for a 64-bit surrogate.
+        let wasm = cranelift.target.as_deref().is_some_and(|triple| triple.starts_with("wasm32"));
+        let mut isa_builder = match &cranelift.target {
+            Some(_) if wasm => cranelift_codegen::isa::lookup_by_name(WASM_SURROGATE_TRIPLE)
+                .map_err(|e| anyhow!("Cannot generate code for {}: {}", WASM_SURROGATE_TRIPLE, e))?,
+            Some(triple) => cranelift_codegen::isa::lookup_by_name(triple)
+                .map_err(|e| anyhow!("Cannot generate code for {}: {}", triple, e))?,
+            None => cranelift_native::builder().map_err(|e| anyhow!("{}", e))?,
+        };
+        if isa::CallConv::triple_default(isa_builder.triple()) == isa::CallConv::WindowsFastcall {
+            // COFF executables are linked at a fixed base and relocated by the loader.
+            // Windows commits the stack a page at a time behind a single guard page, so
+            // frames larger than a page have to touch each page in turn.
+            flag_builder.set("is_pic", "false").unwrap();
+            flag_builder.set("enable_probestack", "true").unwrap();
+            flag_builder.set("probestack_strategy", "inline").unwrap();
+        } else {
+            // Enable PIC for macOS compatibility
+            flag_builder.set("is_pic", "true").unwrap();
+        }
+        for (name, value) in &cranelift.flags {
+            let result = match flag_builder.set(name, value) {
+                Err(settings::SetError::BadName(_)) => isa_builder.set(name, value),
+                result => result,
+            };
+            result.map_err(|e| anyhow!("Cranelift setting `{}` = `{}`: {}", name, value, e))?;
+        }
         let isa = isa_builder
             .finish(settings::Flags::new(flag_builder))
             .map_err(|e| anyhow!("{}", e))?;
 
-        let builder = ObjectBuilder::new(
-            isa,
-            "perry_output",
-            cranelift_module::default_libcall_names(),
-        )?;
-        let module = ObjectModule::new(builder);
+        let module = if wasm {
+            CodegenModule::Wasm(WasmModule::new(isa))
+        } else {
+            let builder = ObjectBuilder::new(
+                isa,
+                "perry_output",
+                cranelift_module::default_libcall_names(),
+            )?;
+            CodegenModule::Object(ObjectModule::new(builder))
+        };
         let ctx = module.make_context();
 
         Ok(Self {
@@ -340,6 +762,27 @@ impl Compiler {
             module_var_data_ids: HashMap::new(),
             module_level_locals: HashMap::new(),
             imported_func_param_counts: HashMap::new(),
+            crash_reporting: false,
+            crash_frame_names: HashMap::new(),
+            max_heap: None,
+            oom_abort: false,
+            docs_document: None,
+            tzdata: None,
+            allocator: None,
+            class_metadata: Vec::new(),
+            permissions: None,
+            sources: None,
+            module_name: String::new(),
+            specializations: HashMap::new(),
+            closure_owners: HashMap::new(),
+            debug_info: None,
+            function_cache: None,
+            queued_functions: Vec::new(),
+            queued_function_ids: HashSet::new(),
+            debug_function: None,
+            cpu_variant_isa: None,
+            cpu_variant_slots: Vec::new(),
+            hot_reload: false,
         })
     }
 
@@ -348,6 +791,80 @@ impl Compiler {
         self.needs_js_runtime = needs;
     }
 
+    /// Set whether crash reporting hooks should be emitted.
+    /// The entry module installs the signal/panic handlers and every function
+    /// records itself on the runtime's TS shadow stack.
+    pub fn set_crash_reporting(&mut self, enabled: bool) {
+        self.crash_reporting = enabled;
+    }
+
+    /// Also compile the module's numeric functions for x86-64-v3 CPUs, picking the version
+    /// to run at startup. Nothing changes when the target isn't x86_64 or its baseline
+    /// already has the features (a host build on such a CPU).
+    pub fn set_multiversion(&mut self, enabled: bool) -> Result<()> {
+        // WebAssembly's surrogate ISA is x86_64 but its code isn't
+        let enabled = enabled && !self.module.is_wasm();
+        self.cpu_variant_isa = if enabled { cpu_variant_isa(self.module.isa())? } else { None };
+        Ok(())
+    }
+
+    /// Build for hot reload: every function and writable global the module defines gets a
+    /// preemptible symbol, named the same in every build of the module, and main starts the
+    /// agent that swaps recompiled functions in (see perry_runtime::hot_reload). Expects
+    /// position-independent code, which a patch linked from the module's object needs.
+    pub fn set_hot_reload(&mut self, enabled: bool) {
+        self.hot_reload = enabled;
+    }
+
+    /// Reuse the machine code `cache` holds for functions whose Cranelift IR didn't change,
+    /// and record the code of the rest in it. Not used for debug builds.
+    pub fn set_function_cache(&mut self, cache: Arc<FunctionCache>) {
+        self.function_cache = Some(cache);
+    }
+
+    /// Emit DWARF debug info: function names, line tables and the locations of locals.
+    /// Expects the frame pointers preserved (`preserve_frame_pointers`), which locals on
+    /// the stack are addressed from. WebAssembly output has no debug info.
+    pub fn set_debug_info(&mut self, enabled: bool) {
+        self.debug_info = (enabled && !self.module.is_wasm()).then(|| DebugInfo::new(self.module.isa()));
+    }
+
+    /// Set the runtime heap ceiling and OOM policy applied by the entry module's main.
+    /// PERRY_MAX_HEAP / PERRY_OOM_POLICY still override these at run time.
+    pub fn set_memory_limit(&mut self, max_heap: Option<u64>, oom_abort: bool) {
+        self.max_heap = max_heap;
+        self.oom_abort = oom_abort;
+    }
+
+    /// Compile an OpenAPI document into the entry module; Fastify apps serve it at
+    /// /docs/json with a browsable page at /docs
+    pub fn set_docs_document(&mut self, document: Option<String>) {
+        self.docs_document = document;
+    }
+
+    /// Compile a time zone table (see `perry_runtime::temporal::pack_tzdata`) into the entry
+    /// module, so Temporal finds those zones without a zoneinfo directory on the host
+    pub fn set_tzdata(&mut self, tzdata: Option<Vec<u8>>) {
+        self.tzdata = tzdata;
+    }
+
+    /// Record the allocator the executable is linked against in the entry module
+    pub fn set_allocator(&mut self, allocator: Option<String>) {
+        self.allocator = allocator;
+    }
+
+    /// Restrict the program to the capabilities a perry.toml `[permissions]` table allows,
+    /// serialized as one `allow-<kind> <entry>` line per entry. Nothing overrides it at run time.
+    pub fn set_permissions(&mut self, permissions: Option<String>) {
+        self.permissions = permissions;
+    }
+
+    /// Sources of the program, so spans become `path:line:col` in Error stacks, crash
+    /// report frames and compile errors
+    pub fn set_sources(&mut self, sources: Arc<SourceCache>) {
+        self.sources = Some(sources);
+    }
+
     /// Set whether this is the entry module (generates main function)
     pub fn set_is_entry_module(&mut self, is_entry: bool) {
         self.is_entry_module = is_entry;
@@ -501,6 +1018,8 @@ impl Compiler {
             }
             // Generic types are pointers
             Type::Generic { .. } => types::I64,
+            // Intersections of object types are object pointers
+            Type::Intersection(_) => types::I64,
             // Void/Null/Undefined return f64 (will be 0)
             Type::Void | Type::Null => types::F64,
             // Any/Unknown use f64 (NaN-boxed values for JS interop)
@@ -509,32 +1028,46 @@ impl Compiler {
             Type::Function(_) => types::I64,
             // Tuples use i64 (could be more complex)
             Type::Tuple(_) => types::I64,
+            // Unions of string literals are strings
+            Type::Union(_) if ty.is_string() => types::I64,
             // Union types use f64 (NaN-boxed values can be numbers or pointers)
             Type::Union(_) => types::F64,
             // Never type - use f64 as fallback (never actually returned)
             Type::Never => types::F64,
             // TypeVar should be substituted before codegen; default to f64
-            Type::TypeVar(_) => types::F64,
+            Type::TypeVar(_) | Type::Conditional(_) | Type::Infer(_) | Type::KeyOf(_) | Type::IndexedAccess { .. } => types::F64,
             // Symbol is an i64 id
             Type::Symbol => types::I64,
+            // Literal types share their primitive's representation
+            Type::Literal(lit) => self.type_to_abi(&lit.widened()),
         }
     }
 
     /// Create global data slots for module-level variables
     /// These allow functions to access variables defined in init statements
     fn create_module_var_globals(&mut self, init_stmts: &[Stmt]) -> Result<()> {
-        self.create_module_var_globals_recursive(init_stmts)
+        self.create_module_var_globals_recursive(init_stmts, &mut HashMap::new())
     }
 
-    /// Recursively create globals for variables in nested statements (for loops, if blocks, etc.)
-    fn create_module_var_globals_recursive(&mut self, stmts: &[Stmt]) -> Result<()> {
+    /// Recursively create globals for variables in nested statements (for loops, if blocks, etc.).
+    /// `seen` counts the variables of each name so far, which hot-reload builds name them by
+    fn create_module_var_globals_recursive(&mut self, stmts: &[Stmt], seen: &mut HashMap<String, usize>) -> Result<()> {
         for stmt in stmts {
             match stmt {
                 Stmt::Let { id, name, .. } => {
                     // Create a global data slot for this variable
                     // Each slot holds an f64 (8 bytes)
-                    let global_name = format!("__modvar_{}_{}", name, id);
-                    let data_id = self.module.declare_data(&global_name, Linkage::Local, true, false)?;
+                    let global_name = if self.hot_reload {
+                        // Local ids shift with edits elsewhere in the module; a patch has to
+                        // name the same variable as the running program
+                        let index = seen.entry(name.clone()).or_default();
+                        let symbol = self.local_symbol(SymbolKind::ModuleVariable, vec![name.clone(), index.to_string()]);
+                        *index += 1;
+                        symbol
+                    } else {
+                        format!("__modvar_{}_{}", name, id)
+                    };
+                    let data_id = self.module.declare_data(&global_name, self.definition_linkage(Linkage::Local), true, false)?;
                     let mut data_desc = DataDescription::new();
                     data_desc.define_zeroinit(8); // 8 bytes for f64
                     self.module.define_data(data_id, &data_desc)?;
@@ -543,32 +1076,32 @@ impl Compiler {
                 Stmt::For { init, body, .. } => {
                     // Walk init statement if present
                     if let Some(init_stmt) = init {
-                        self.create_module_var_globals_recursive(&[*init_stmt.clone()])?;
+                        self.create_module_var_globals_recursive(&[*init_stmt.clone()], seen)?;
                     }
                     // Walk body statements
-                    self.create_module_var_globals_recursive(body)?;
+                    self.create_module_var_globals_recursive(body, seen)?;
                 }
                 Stmt::While { body, .. } => {
-                    self.create_module_var_globals_recursive(body)?;
+                    self.create_module_var_globals_recursive(body, seen)?;
                 }
                 Stmt::If { then_branch, else_branch, .. } => {
-                    self.create_module_var_globals_recursive(then_branch)?;
+                    self.create_module_var_globals_recursive(then_branch, seen)?;
                     if let Some(else_stmts) = else_branch {
-                        self.create_module_var_globals_recursive(else_stmts)?;
+                        self.create_module_var_globals_recursive(else_stmts, seen)?;
                     }
                 }
                 Stmt::Try { body, catch, finally } => {
-                    self.create_module_var_globals_recursive(body)?;
+                    self.create_module_var_globals_recursive(body, seen)?;
                     if let Some(c) = catch {
-                        self.create_module_var_globals_recursive(&c.body)?;
+                        self.create_module_var_globals_recursive(&c.body, seen)?;
                     }
                     if let Some(f) = finally {
-                        self.create_module_var_globals_recursive(f)?;
+                        self.create_module_var_globals_recursive(f, seen)?;
                     }
                 }
                 Stmt::Switch { cases, .. } => {
                     for case in cases {
-                        self.create_module_var_globals_recursive(&case.body)?;
+                        self.create_module_var_globals_recursive(&case.body, seen)?;
                     }
                 }
                 _ => {}
@@ -622,7 +1155,7 @@ impl Compiler {
                 // Determine if this variable is a pointer type
                 // Note: String is NOT included because strings are now NaN-boxed (f64 values)
                 let is_pointer = matches!(ty, HirType::Array(_) |
-                    HirType::Object(_) | HirType::Named(_) | HirType::Generic { .. } |
+                    HirType::Object(_) | HirType::Intersection(_) | HirType::Named(_) | HirType::Generic { .. } |
                     HirType::Function(_));
 
                 // Also check the init expression type for better inference
@@ -632,8 +1165,9 @@ impl Compiler {
                     match init_expr {
                         Expr::New { class_name, .. } => {
                             // Native handle classes use f64, not i64
-                            !matches!(class_name.as_str(),
+                            !(matches!(class_name.as_str(),
                                 "EventEmitter" | "Decimal" | "Big" | "BigNumber" | "LRUCache" | "Command" | "Redis")
+                                || matches!(class_name.as_str(), "Level" | "MiniSearch") && !self.classes.contains_key(class_name))
                         }
                         Expr::Array(_) | Expr::Object(_) | Expr::ArraySpread(_) |
                         Expr::Closure { .. } | Expr::MapNew | Expr::SetNew |
@@ -675,13 +1209,14 @@ impl Compiler {
                 } else {
                     false
                 };
-                let is_string = matches!(ty, HirType::String) || matches!(init, Some(Expr::String(_))) || is_string_from_native || is_string_from_call;
+                let is_string = ty.is_string() || matches!(init, Some(Expr::String(_))) || is_string_from_native || is_string_from_call;
                 let is_array = matches!(ty, HirType::Array(_)) || matches!(init, Some(Expr::Array(_))) || matches!(init, Some(Expr::ArraySpread(_))) || matches!(init, Some(Expr::ProcessArgv));
                 let is_closure = matches!(ty, HirType::Function(_)) || matches!(init, Some(Expr::Closure { .. }));
                 // Check for buffer expressions
                 let is_buffer = matches!(init, Some(Expr::BufferFrom { .. }) | Some(Expr::BufferAlloc { .. }) |
                     Some(Expr::BufferAllocUnsafe(_)) | Some(Expr::BufferConcat(_)) |
-                    Some(Expr::BufferSlice { .. }) | Some(Expr::ChildProcessExecSync { .. }));
+                    Some(Expr::BufferSlice { .. }) | Some(Expr::ChildProcessExecSync { .. })) ||
+                    matches!(init, Some(Expr::NativeMethodCall { module, method, .. }) if module == "perry/buffer-pool" && method == "acquire");
 
                 // Store the type info
                 let info = LocalInfo {
@@ -711,6 +1246,7 @@ impl Compiler {
                     squared_cache: None,
                     product_cache: None,
                     cached_array_ptr: None,
+                    union_classes: Vec::new(),
                 };
                 self.module_level_locals.insert(*id, info);
                 }
@@ -721,6 +1257,16 @@ impl Compiler {
 
     /// Compile a HIR module to an object file
     pub fn compile_module(mut self, hir: &HirModule) -> Result<Vec<u8>> {
+        SOURCES.with(|sources| *sources.borrow_mut() = self.sources.clone());
+        debuginfo::begin_module(self.debug_info.is_some());
+
+        // Module-local symbols are mangled with the module and generic names (see symbols)
+        self.module_name = hir.name.clone();
+        for spec in &hir.specializations {
+            let type_args = spec.type_args.iter().map(|ty| ty.to_string()).collect();
+            self.specializations.insert(spec.name.clone(), (spec.generic.clone(), type_args));
+        }
+
         // Store HIR functions for wrapper generation
         self.hir_functions = hir.functions.clone();
 
@@ -757,6 +1303,22 @@ impl Compiler {
         // Resolve class inheritance (merge parent fields into child classes)
         self.resolve_class_inheritance();
 
+        // Names and declared (non-private) fields of this module's classes for perry/reflect.
+        // Types are printed as the compiler sees them; tabs and newlines would break the format
+        self.class_metadata = hir.classes.iter()
+            .map(|class| {
+                let parent_id = class.extends
+                    .or_else(|| class.extends_name.as_ref().and_then(|name| self.classes.get(name)).map(|meta| meta.id))
+                    .unwrap_or(0);
+                let mut text = format!("{}\t{}\n", class.name, parent_id);
+                for field in class.fields.iter().filter(|field| !field.is_private) {
+                    let ty = field.ty.to_string().replace(['\t', '\n'], " ");
+                    text.push_str(&format!("{}\t{}\t{}\n", field.name, ty, field.is_readonly as u8));
+                }
+                (class.id, text)
+            })
+            .collect();
+
         // Process enums to store their member values
         for en in &hir.enums {
             self.process_enum(en)?;
@@ -803,29 +1365,49 @@ impl Compiler {
         // Tuple: (func_id, params, body, captures, mutable_captures, captures_this, enclosing_class)
         let mut all_closures: Vec<(u32, Vec<perry_hir::Param>, Vec<Stmt>, Vec<LocalId>, Vec<LocalId>, bool, Option<String>, bool)> = Vec::new();
 
+        // Closures collected since `first` are defined in `owner`, for their symbol names
+        let record_owner = |compiler: &mut Self, closures: &[(u32, Vec<perry_hir::Param>, Vec<Stmt>, Vec<LocalId>, Vec<LocalId>, bool, Option<String>, bool)], first: usize, owner: &[&str]| {
+            for closure in &closures[first..] {
+                compiler.closure_owners.entry(closure.0)
+                    .or_insert_with(|| owner.iter().map(|name| name.to_string()).collect());
+            }
+        };
+
         // Collect from function bodies (no enclosing class)
         for func in &hir.functions {
+            let first = all_closures.len();
             self.collect_closures_from_stmts_into(&func.body, &mut all_closures, None);
+            record_owner(&mut self, &all_closures, first, &[func.name.as_str()]);
         }
 
         // Collect from class methods and constructors (pass class name for this capture)
         for class in &hir.classes {
             let class_name = class.name.as_str();
             for method in &class.methods {
+                let first = all_closures.len();
                 self.collect_closures_from_stmts_into(&method.body, &mut all_closures, Some(class_name));
+                record_owner(&mut self, &all_closures, first, &[class_name, method.name.as_str()]);
             }
-            for (_, getter) in &class.getters {
+            for (name, getter) in &class.getters {
+                let first = all_closures.len();
                 self.collect_closures_from_stmts_into(&getter.body, &mut all_closures, Some(class_name));
+                record_owner(&mut self, &all_closures, first, &[class_name, name.as_str()]);
             }
-            for (_, setter) in &class.setters {
+            for (name, setter) in &class.setters {
+                let first = all_closures.len();
                 self.collect_closures_from_stmts_into(&setter.body, &mut all_closures, Some(class_name));
+                record_owner(&mut self, &all_closures, first, &[class_name, name.as_str()]);
             }
             for method in &class.static_methods {
                 // Static methods don't have `this`, so pass None
+                let first = all_closures.len();
                 self.collect_closures_from_stmts_into(&method.body, &mut all_closures, None);
+                record_owner(&mut self, &all_closures, first, &[class_name, method.name.as_str()]);
             }
             if let Some(ctor) = &class.constructor {
+                let first = all_closures.len();
                 self.collect_closures_from_stmts_into(&ctor.body, &mut all_closures, Some(class_name));
+                record_owner(&mut self, &all_closures, first, &[class_name, "constructor"]);
             }
 
             // Collect from class field initializers
@@ -961,7 +1543,7 @@ impl Compiler {
         // These will be filled in during compile_init and accessed by other modules
         for (export_name, _module_name, _class_name) in &hir.exported_native_instances {
             let global_name = format!("__export_{}", export_name);
-            let data_id = self.module.declare_data(&global_name, Linkage::Export, true, false)?;
+            let data_id = self.module.declare_data(&global_name, self.definition_linkage(Linkage::Export), true, false)?;
             // Create a data description with space for one f64 (8 bytes), initialized to 0
             let mut data_desc = DataDescription::new();
             data_desc.define_zeroinit(8);
@@ -982,10 +1564,12 @@ impl Compiler {
                 continue;
             }
             let global_name = format!("__export_{}", export_name);
-            let data_id = self.module.declare_data(&global_name, Linkage::Export, true, false)?;
-            // Create a data description with space for one f64 (8 bytes), initialized to 0
+            let data_id = self.module.declare_data(&global_name, self.definition_linkage(Linkage::Export), true, false)?;
+            // One f64 (8 bytes), `undefined` until compile_init stores the value, so a module
+            // in an import cycle that reads the binding before it is initialized sees undefined
+            const TAG_UNDEFINED: u64 = 0x7FFC_0000_0000_0001;
             let mut data_desc = DataDescription::new();
-            data_desc.define_zeroinit(8);
+            data_desc.define(TAG_UNDEFINED.to_le_bytes().to_vec().into_boxed_slice());
             self.module.define_data(data_id, &data_desc)?;
             self.exported_object_ids.insert(export_name.clone(), data_id);
         }
@@ -994,7 +1578,7 @@ impl Compiler {
         // These allow functions to be passed as values to other modules
         for (func_name, func_id) in &hir.exported_functions {
             let global_name = format!("__export_{}", func_name);
-            let data_id = self.module.declare_data(&global_name, Linkage::Export, true, false)?;
+            let data_id = self.module.declare_data(&global_name, self.definition_linkage(Linkage::Export), true, false)?;
             // Create a data description with space for one f64 (8 bytes), initialized to 0
             let mut data_desc = DataDescription::new();
             data_desc.define_zeroinit(8);
@@ -1046,7 +1630,7 @@ impl Compiler {
             let sig = self.module.declarations().get_function_decl(original_wrapper_id).signature.clone();
 
             // Declare the alias function
-            if let Ok(alias_id) = self.module.declare_function(&alias_wrapper_name, Linkage::Export, &sig) {
+            if let Ok(alias_id) = self.module.declare_function(&alias_wrapper_name, self.definition_linkage(Linkage::Export), &sig) {
                 // Build a simple trampoline that tail-calls the original wrapper
                 self.ctx.func.signature = sig.clone();
                 let mut alias_func_ctx = FunctionBuilderContext::new();
@@ -1078,7 +1662,7 @@ impl Compiler {
                     builder.finalize();
                 }
 
-                if let Err(e) = self.module.define_function(alias_id, &mut self.ctx) {
+                if let Err(e) = self.define_function(alias_id) {
                     eprintln!("[WRAPPER ALIAS] Failed to define {}: {}", alias_wrapper_name, e);
                 }
                 self.module.clear_context(&mut self.ctx);
@@ -1088,7 +1672,7 @@ impl Compiler {
         // Generate wrapper functions for exported closures (export const fn = () => {})
         // These are stored in exported_objects, not exported_functions, but callers expect
         // __wrapper_functionName to exist for uniform cross-module calling
-        self.generate_exported_closure_wrappers(&hir.init, &hir.exported_objects)?;
+        self.generate_exported_closure_wrappers(&hir.init, &hir.exports, &hir.exported_objects)?;
 
         // Compile init statements as main (entry) or module init function (non-entry)
         // For non-entry modules, always generate the init function even if empty,
@@ -1098,14 +1682,24 @@ impl Compiler {
             || !self.is_entry_module
             || !self.native_module_inits.is_empty()
             || self.needs_js_runtime
-            || self.needs_dotenv_init;
+            || self.needs_dotenv_init
+            || !self.cpu_variant_slots.is_empty();
 
         if should_compile_init {
-            self.compile_init(&hir.name, &hir.init, &hir.exported_native_instances, &hir.exported_objects, &hir.exported_functions)?;
+            self.compile_init(&hir.name, &hir.init, &hir.exports, &hir.exported_native_instances, &hir.exported_objects, &hir.exported_functions)?;
         }
 
+        self.define_queued_functions()?;
+
         // Emit object file
-        let product = self.module.finish();
+        let module = match self.module {
+            CodegenModule::Object(module) => module,
+            CodegenModule::Wasm(module) => return Ok(module.finish().to_bytes()),
+        };
+        let mut product = module.finish();
+        if let Some(debug_info) = self.debug_info.take() {
+            debug_info.write(&mut product, self.sources.as_deref(), &hir.name)?;
+        }
         Ok(product.emit()?)
     }
 
@@ -1240,9 +1834,9 @@ impl Compiler {
         // Recursively resolve parent first
         self.resolve_methods_for_class(&parent_name);
 
-        // Get parent's method IDs
-        let parent_method_ids = self.classes.get(&parent_name)
-            .map(|m| m.method_ids.clone())
+        // Get parent's method and accessor IDs
+        let (parent_method_ids, parent_getter_ids, parent_setter_ids) = self.classes.get(&parent_name)
+            .map(|m| (m.method_ids.clone(), m.getter_ids.clone(), m.setter_ids.clone()))
             .unwrap_or_default();
 
         // Inherit parent methods (child methods override parent)
@@ -1252,6 +1846,18 @@ impl Compiler {
                     meta.method_ids.insert(method_name, method_id);
                 }
             }
+            // Inherit accessors too, unless the child shadows them with its own accessor
+            // or an own field of the same name
+            for (prop_name, getter_id) in parent_getter_ids {
+                if !meta.getter_ids.contains_key(&prop_name) && !meta.field_indices.contains_key(&prop_name) {
+                    meta.getter_ids.insert(prop_name, getter_id);
+                }
+            }
+            for (prop_name, setter_id) in parent_setter_ids {
+                if !meta.setter_ids.contains_key(&prop_name) && !meta.field_indices.contains_key(&prop_name) {
+                    meta.setter_ids.insert(prop_name, setter_id);
+                }
+            }
         }
     }
 
@@ -1268,7 +1874,7 @@ impl Compiler {
 
     fn declare_class_methods(&mut self, class: &Class) -> Result<()> {
         // Export methods for exported classes so other modules can call them
-        let linkage = if class.is_exported { Linkage::Export } else { Linkage::Local };
+        let linkage = self.definition_linkage(if class.is_exported { Linkage::Export } else { Linkage::Local });
 
         for method in &class.methods {
             let mut sig = self.module.make_signature();
@@ -1284,7 +1890,11 @@ impl Compiler {
             // Return type (f64 for now)
             sig.returns.push(AbiParam::new(types::F64));
 
-            let func_name = format!("{}_{}", class.name, method.name);
+            let func_name = if class.is_exported {
+                format!("{}_{}", class.name, method.name)
+            } else {
+                self.local_symbol(SymbolKind::Method, vec![class.name.clone(), method.name.clone()])
+            };
             let func_id = self.module.declare_function(&func_name, linkage, &sig)?;
 
             if let Some(meta) = self.classes.get_mut(&class.name) {
@@ -1335,7 +1945,7 @@ impl Compiler {
             let this_var = Variable::new(0);
             builder.declare_var(this_var, types::I64);
             let this_val = builder.block_params(entry_block)[0];
-            builder.def_var(this_var, this_val);
+            builder.def_local(this_var, this_val);
 
             // Create variables for other parameters
             let mut locals: HashMap<LocalId, LocalInfo> = HashMap::new();
@@ -1345,10 +1955,10 @@ impl Compiler {
                 next_var += 1;
                 builder.declare_var(var, types::F64);
                 let val = builder.block_params(entry_block)[i + 1]; // +1 to skip 'this'
-                builder.def_var(var, val);
+                builder.def_local(var, val);
                 // Check parameter types for correct handling of string methods, array methods, etc.
                 let is_closure = matches!(param.ty, perry_types::Type::Function(_));
-                let is_string = matches!(param.ty, perry_types::Type::String);
+                let is_string = param.ty.is_string();
                 let is_array = matches!(param.ty, perry_types::Type::Array(_));
                 let is_pointer = is_closure || is_string || is_array;
                 locals.insert(param.id, LocalInfo {
@@ -1371,7 +1981,7 @@ impl Compiler {
                     bounded_by_array: None,
                     bounded_by_constant: None,
                     scalar_fields: None,
-                    squared_cache: None, product_cache: None, cached_array_ptr: None,
+                    squared_cache: None, product_cache: None, cached_array_ptr: None, union_classes: Vec::new(),
                 });
             }
 
@@ -1405,7 +2015,7 @@ impl Compiler {
                         bounded_by_array: None,
                         bounded_by_constant: None,
                         scalar_fields: None,
-                        squared_cache: None, product_cache: None, cached_array_ptr: None,
+                        squared_cache: None, product_cache: None, cached_array_ptr: None, union_classes: Vec::new(),
                     })
                 };
 
@@ -1422,7 +2032,7 @@ impl Compiler {
                 let global_val = self.module.declare_data_in_func(*data_id, builder.func);
                 let ptr = builder.ins().global_value(types::I64, global_val);
                 let val = builder.ins().load(var_type, MemFlags::new(), ptr, 0);
-                builder.def_var(var, val);
+                builder.def_local(var, val);
 
                 // Insert into locals so LocalGet can find it
                 let mut info = local_info_template;
@@ -1489,7 +2099,12 @@ impl Compiler {
             builder.finalize();
         }
 
-        if let Err(e) = self.module.define_function(func_id, &mut self.ctx) {
+        // Methods take every argument NaN-boxed, so strings are recognized by their tag
+        let defined = match method.memoize() {
+            Some(memoize) => self.define_memoized(vec![class.name.clone(), method.name.clone()], func_id, memoize, 0),
+            None => self.define_function(func_id).map_err(anyhow::Error::from),
+        };
+        if let Err(e) = defined {
             eprintln!("=== VERIFIER ERROR in instance method '{}' ===", method.name);
             eprintln!("Error: {}", e);
             return Err(anyhow!("Error compiling instance method '{}': {}", method.name, e));
@@ -1556,6 +2171,20 @@ impl Compiler {
         Ok(data_ids)
     }
 
+    /// Get or create the static UTF-8 bytes for a function name used by crash shadow stack frames
+    fn crash_frame_name_data(&mut self, name: &str) -> Result<cranelift_module::DataId> {
+        if let Some(&existing) = self.crash_frame_names.get(name) {
+            return Ok(existing);
+        }
+        let mut data_desc = DataDescription::new();
+        data_desc.define(name.as_bytes().to_vec().into_boxed_slice());
+        let data_name = format!("crash_frame_name_{}", self.crash_frame_names.len());
+        let data_id = self.module.declare_data(&data_name, Linkage::Local, false, false)?;
+        self.module.define_data(data_id, &data_desc)?;
+        self.crash_frame_names.insert(name.to_string(), data_id);
+        Ok(data_id)
+    }
+
     fn declare_class_getters(&mut self, class: &Class) -> Result<()> {
         for (prop_name, getter) in &class.getters {
             let mut sig = self.module.make_signature();
@@ -1568,8 +2197,8 @@ impl Compiler {
             // Return type (f64 for now)
             sig.returns.push(AbiParam::new(types::F64));
 
-            let func_name = format!("{}_get_{}", class.name, prop_name);
-            let func_id = self.module.declare_function(&func_name, Linkage::Local, &sig)?;
+            let func_name = self.local_symbol(SymbolKind::Getter, vec![class.name.clone(), prop_name.clone()]);
+            let func_id = self.module.declare_function(&func_name, self.definition_linkage(Linkage::Local), &sig)?;
 
             if let Some(meta) = self.classes.get_mut(&class.name) {
                 meta.getter_ids.insert(prop_name.clone(), func_id);
@@ -1593,8 +2222,8 @@ impl Compiler {
             // Return type void (f64 for consistency)
             sig.returns.push(AbiParam::new(types::F64));
 
-            let func_name = format!("{}_set_{}", class.name, prop_name);
-            let func_id = self.module.declare_function(&func_name, Linkage::Local, &sig)?;
+            let func_name = self.local_symbol(SymbolKind::Setter, vec![class.name.clone(), prop_name.clone()]);
+            let func_id = self.module.declare_function(&func_name, self.definition_linkage(Linkage::Local), &sig)?;
 
             if let Some(meta) = self.classes.get_mut(&class.name) {
                 meta.setter_ids.insert(prop_name.clone(), func_id);
@@ -1636,7 +2265,7 @@ impl Compiler {
             let this_var = Variable::new(0);
             builder.declare_var(this_var, types::I64);
             let this_val = builder.block_params(entry_block)[0];
-            builder.def_var(this_var, this_val);
+            builder.def_local(this_var, this_val);
 
             // No other parameters for getters
             let mut locals: HashMap<LocalId, LocalInfo> = HashMap::new();
@@ -1659,7 +2288,7 @@ impl Compiler {
                         is_buffer: false, is_event_emitter: false, is_union: false, is_mixed_array: false, is_integer: false,
                         is_integer_array: false, is_i32: false, i32_shadow: None,
                         bounded_by_array: None, bounded_by_constant: None, scalar_fields: None,
-                        squared_cache: None, product_cache: None, cached_array_ptr: None,
+                        squared_cache: None, product_cache: None, cached_array_ptr: None, union_classes: Vec::new(),
                     })
                 };
                 let var = Variable::new(next_var);
@@ -1668,7 +2297,7 @@ impl Compiler {
                 let global_val = self.module.declare_data_in_func(*data_id, builder.func);
                 let ptr = builder.ins().global_value(types::I64, global_val);
                 let val = builder.ins().load(var_type, MemFlags::new(), ptr, 0);
-                builder.def_var(var, val);
+                builder.def_local(var, val);
                 let mut info = local_info_template;
                 info.var = var;
                 locals.insert(*local_id, info);
@@ -1722,7 +2351,7 @@ impl Compiler {
             builder.finalize();
         }
 
-        if let Err(e) = self.module.define_function(func_id, &mut self.ctx) {
+        if let Err(e) = self.define_function(func_id) {
             eprintln!("=== VERIFIER ERROR in getter '{}::get_{}' ===", class.name, prop_name);
             eprintln!("Error: {}", e);
             return Err(anyhow!("Error compiling getter '{}::get_{}': {}", class.name, prop_name, e));
@@ -1768,7 +2397,7 @@ impl Compiler {
             let this_var = Variable::new(0);
             builder.declare_var(this_var, types::I64);
             let this_val = builder.block_params(entry_block)[0];
-            builder.def_var(this_var, this_val);
+            builder.def_local(this_var, this_val);
 
             // Create variables for value parameters
             let mut locals: HashMap<LocalId, LocalInfo> = HashMap::new();
@@ -1778,10 +2407,10 @@ impl Compiler {
                 next_var += 1;
                 builder.declare_var(var, types::F64);
                 let val = builder.block_params(entry_block)[i + 1]; // +1 to skip 'this'
-                builder.def_var(var, val);
+                builder.def_local(var, val);
                 // Check parameter types for correct handling of string methods, array methods, etc.
                 let is_closure = matches!(param.ty, perry_types::Type::Function(_));
-                let is_string = matches!(param.ty, perry_types::Type::String);
+                let is_string = param.ty.is_string();
                 let is_array = matches!(param.ty, perry_types::Type::Array(_));
                 let is_pointer = is_closure || is_string || is_array;
                 locals.insert(param.id, LocalInfo {
@@ -1804,7 +2433,7 @@ impl Compiler {
                     bounded_by_array: None,
                     bounded_by_constant: None,
                     scalar_fields: None,
-                    squared_cache: None, product_cache: None, cached_array_ptr: None,
+                    squared_cache: None, product_cache: None, cached_array_ptr: None, union_classes: Vec::new(),
                 });
             }
 
@@ -1825,7 +2454,7 @@ impl Compiler {
                         is_buffer: false, is_event_emitter: false, is_union: false, is_mixed_array: false, is_integer: false,
                         is_integer_array: false, is_i32: false, i32_shadow: None,
                         bounded_by_array: None, bounded_by_constant: None, scalar_fields: None,
-                        squared_cache: None, product_cache: None, cached_array_ptr: None,
+                        squared_cache: None, product_cache: None, cached_array_ptr: None, union_classes: Vec::new(),
                     })
                 };
                 let var = Variable::new(next_var);
@@ -1834,7 +2463,7 @@ impl Compiler {
                 let global_val = self.module.declare_data_in_func(*data_id, builder.func);
                 let ptr = builder.ins().global_value(types::I64, global_val);
                 let val = builder.ins().load(var_type, MemFlags::new(), ptr, 0);
-                builder.def_var(var, val);
+                builder.def_local(var, val);
                 let mut info = local_info_template;
                 info.var = var;
                 locals.insert(*local_id, info);
@@ -1888,7 +2517,7 @@ impl Compiler {
             builder.finalize();
         }
 
-        if let Err(e) = self.module.define_function(func_id, &mut self.ctx) {
+        if let Err(e) = self.define_function(func_id) {
             eprintln!("=== VERIFIER ERROR in setter '{}::set_{}' ===", class.name, prop_name);
             eprintln!("Error: {}", e);
             return Err(anyhow!("Error compiling setter '{}::set_{}': {}", class.name, prop_name, e));
@@ -1910,8 +2539,8 @@ impl Compiler {
             // Return type (f64 for now)
             sig.returns.push(AbiParam::new(types::F64));
 
-            let func_name = format!("{}_{}_static", class.name, method.name);
-            let func_id = self.module.declare_function(&func_name, Linkage::Local, &sig)?;
+            let func_name = self.local_symbol(SymbolKind::StaticMethod, vec![class.name.clone(), method.name.clone()]);
+            let func_id = self.module.declare_function(&func_name, self.definition_linkage(Linkage::Local), &sig)?;
 
             if let Some(meta) = self.classes.get_mut(&class.name) {
                 meta.static_method_ids.insert(method.name.clone(), func_id);
@@ -1923,8 +2552,12 @@ impl Compiler {
     fn declare_static_fields(&mut self, class: &Class) -> Result<()> {
         for field in &class.static_fields {
             // Static fields are global variables stored as 8 bytes (f64)
-            let data_name = format!("{}_{}_static_field", class.name, field.name);
-            let data_id = self.module.declare_data(&data_name, Linkage::Local, true, false)?;
+            let data_name = if self.hot_reload {
+                self.local_symbol(SymbolKind::StaticField, vec![class.name.clone(), field.name.clone()])
+            } else {
+                format!("{}_{}_static_field", class.name, field.name)
+            };
+            let data_id = self.module.declare_data(&data_name, self.definition_linkage(Linkage::Local), true, false)?;
 
             if let Some(meta) = self.classes.get_mut(&class.name) {
                 meta.static_field_ids.insert(field.name.clone(), data_id);
@@ -1968,10 +2601,10 @@ impl Compiler {
                 next_var += 1;
                 builder.declare_var(var, types::F64);
                 let val = builder.block_params(entry_block)[i];
-                builder.def_var(var, val);
+                builder.def_local(var, val);
                 // Check parameter types for correct handling of string methods, array methods, etc.
                 let is_closure = matches!(param.ty, perry_types::Type::Function(_));
-                let is_string = matches!(param.ty, perry_types::Type::String);
+                let is_string = param.ty.is_string();
                 let is_array = matches!(param.ty, perry_types::Type::Array(_));
                 let is_pointer = is_closure || is_string || is_array;
                 locals.insert(param.id, LocalInfo {
@@ -1994,7 +2627,7 @@ impl Compiler {
                     bounded_by_array: None,
                     bounded_by_constant: None,
                     scalar_fields: None,
-                    squared_cache: None, product_cache: None, cached_array_ptr: None,
+                    squared_cache: None, product_cache: None, cached_array_ptr: None, union_classes: Vec::new(),
                 });
             }
 
@@ -2015,7 +2648,7 @@ impl Compiler {
                         is_buffer: false, is_event_emitter: false, is_union: false, is_mixed_array: false, is_integer: false,
                         is_integer_array: false, is_i32: false, i32_shadow: None,
                         bounded_by_array: None, bounded_by_constant: None, scalar_fields: None,
-                        squared_cache: None, product_cache: None, cached_array_ptr: None,
+                        squared_cache: None, product_cache: None, cached_array_ptr: None, union_classes: Vec::new(),
                     })
                 };
                 let var = Variable::new(next_var);
@@ -2024,7 +2657,7 @@ impl Compiler {
                 let global_val = self.module.declare_data_in_func(*data_id, builder.func);
                 let ptr = builder.ins().global_value(types::I64, global_val);
                 let val = builder.ins().load(var_type, MemFlags::new(), ptr, 0);
-                builder.def_var(var, val);
+                builder.def_local(var, val);
                 let mut info = local_info_template;
                 info.var = var;
                 locals.insert(*local_id, info);
@@ -2073,7 +2706,7 @@ impl Compiler {
             builder.finalize();
         }
 
-        if let Err(e) = self.module.define_function(func_id, &mut self.ctx) {
+        if let Err(e) = self.define_function(func_id) {
             eprintln!("=== VERIFIER ERROR in static method '{}::{}' ===", class.name, method.name);
             eprintln!("Error: {}", e);
             return Err(anyhow!("Error compiling static method '{}::{}': {}", class.name, method.name, e));
@@ -2121,10 +2754,13 @@ impl Compiler {
             }
             // Constructor returns void - the object is passed in
 
-            let func_name = format!("{}_constructor", class.name);
             // Export constructors for exported classes so other modules can call them
-            let linkage = if class.is_exported { Linkage::Export } else { Linkage::Local };
-            let func_id = self.module.declare_function(&func_name, linkage, &sig)?;
+            let (func_name, linkage) = if class.is_exported {
+                (format!("{}_constructor", class.name), Linkage::Export)
+            } else {
+                (self.local_symbol(SymbolKind::Constructor, vec![class.name.clone()]), Linkage::Local)
+            };
+            let func_id = self.module.declare_function(&func_name, self.definition_linkage(linkage), &sig)?;
 
             if let Some(meta) = self.classes.get_mut(&class.name) {
                 meta.constructor_id = Some(func_id);
@@ -2171,7 +2807,7 @@ impl Compiler {
             let mut locals: HashMap<LocalId, LocalInfo> = HashMap::new();
             let this_var = Variable::new(0);
             builder.declare_var(this_var, types::I64);
-            builder.def_var(this_var, obj_ptr);
+            builder.def_local(this_var, obj_ptr);
 
             // Create variables for user parameters (starting from index 1 in block params)
             let mut next_var = 1usize;
@@ -2180,16 +2816,17 @@ impl Compiler {
                 next_var += 1;
                 // Check parameter types for correct handling of string methods, array methods, etc.
                 let is_closure = matches!(param.ty, perry_types::Type::Function(_));
-                let is_string = matches!(param.ty, perry_types::Type::String);
+                let is_string = param.ty.is_string();
                 let is_array = matches!(param.ty, perry_types::Type::Array(_));
-                let is_union = matches!(param.ty, perry_types::Type::Any | perry_types::Type::Union(_) | perry_types::Type::Unknown);
+                let is_union = !is_string && matches!(param.ty, perry_types::Type::Any | perry_types::Type::Union(_) | perry_types::Type::Unknown);
                 let is_pointer = is_closure || is_string || is_array ||
-                    matches!(param.ty, perry_types::Type::Object(_) | perry_types::Type::Named(_) | perry_types::Type::Promise(_));
+                    matches!(param.ty, perry_types::Type::Object(_) | perry_types::Type::Intersection(_) |
+                        perry_types::Type::Named(_) | perry_types::Type::Promise(_));
                 // Constructor params come in as NaN-boxed F64 values (from the signature)
                 // Always use F64 for variable type - is_pointer flag is for extraction, not storage
                 builder.declare_var(var, types::F64);
                 let val = builder.block_params(entry_block)[i + 1]; // +1 to skip 'this'
-                builder.def_var(var, val);
+                builder.def_local(var, val);
                 // Constructor params are NaN-boxed F64, so is_pointer is false (not raw I64)
                 // The is_array/is_string/is_closure flags indicate the type for proper extraction
                 locals.insert(param.id, LocalInfo {
@@ -2212,7 +2849,7 @@ impl Compiler {
                     bounded_by_array: None,
                     bounded_by_constant: None,
                     scalar_fields: None,
-                    squared_cache: None, product_cache: None, cached_array_ptr: None,
+                    squared_cache: None, product_cache: None, cached_array_ptr: None, union_classes: Vec::new(),
                 });
             }
 
@@ -2233,7 +2870,7 @@ impl Compiler {
                         is_buffer: false, is_event_emitter: false, is_union: false, is_mixed_array: false, is_integer: false,
                         is_integer_array: false, is_i32: false, i32_shadow: None,
                         bounded_by_array: None, bounded_by_constant: None, scalar_fields: None,
-                        squared_cache: None, product_cache: None, cached_array_ptr: None,
+                        squared_cache: None, product_cache: None, cached_array_ptr: None, union_classes: Vec::new(),
                     })
                 };
                 let var = Variable::new(next_var);
@@ -2242,7 +2879,7 @@ impl Compiler {
                 let global_val = self.module.declare_data_in_func(*data_id, builder.func);
                 let ptr = builder.ins().global_value(types::I64, global_val);
                 let val = builder.ins().load(var_type, MemFlags::new(), ptr, 0);
-                builder.def_var(var, val);
+                builder.def_local(var, val);
                 let mut info = local_info_template;
                 info.var = var;
                 locals.insert(*local_id, info);
@@ -2283,7 +2920,7 @@ impl Compiler {
             builder.finalize();
         }
 
-        if let Err(e) = self.module.define_function(func_id, &mut self.ctx) {
+        if let Err(e) = self.define_function(func_id) {
             eprintln!("=== VERIFIER ERROR in constructor '{}' ===", class.name);
             eprintln!("Error: {}", e);
             eprintln!("Debug: {:?}", e);
@@ -2296,6 +2933,21 @@ impl Compiler {
         Ok(())
     }
 
+    /// Declare runtime functions that take `arity` NaN-boxed values and return one, from a
+    /// (name, arity) table
+    fn declare_nanboxed_externs(&mut self, table: &[(&str, usize)]) -> Result<()> {
+        for &(name, arity) in table {
+            let mut sig = self.module.make_signature();
+            for _ in 0..arity {
+                sig.params.push(AbiParam::new(types::F64));
+            }
+            sig.returns.push(AbiParam::new(types::F64));
+            let func_id = self.module.declare_function(name, Linkage::Import, &sig)?;
+            self.extern_funcs.insert(name.to_string(), func_id);
+        }
+        Ok(())
+    }
+
     fn declare_runtime_functions(&mut self) -> Result<()> {
         // Declare js_console_log_number(f64) -> void
         {
@@ -2794,6 +3446,42 @@ impl Compiler {
             self.extern_funcs.insert("js_object_set_field_by_name".to_string(), func_id);
         }
 
+        // js_register_class_getter/setter(class_id: u32, name_ptr: i64, name_len: i64, func: i64) -> void
+        for name in ["js_register_class_getter", "js_register_class_setter"] {
+            let mut sig = self.module.make_signature();
+            sig.params.push(AbiParam::new(types::I32)); // class id
+            sig.params.push(AbiParam::new(types::I64)); // property name bytes
+            sig.params.push(AbiParam::new(types::I64)); // property name length
+            sig.params.push(AbiParam::new(types::I64)); // accessor function pointer
+            let func_id = self.module.declare_function(name, Linkage::Import, &sig)?;
+            self.extern_funcs.insert(name.to_string(), func_id);
+        }
+
+        // js_register_class_field(class_id: u32, name_ptr: i64, name_len: i64, index: u32) -> void
+        // js_register_class_method(class_id: u32, name_ptr: i64, name_len: i64, func: i64, arity: u32) -> void
+        for (name, with_func) in [("js_register_class_field", false), ("js_register_class_method", true)] {
+            let mut sig = self.module.make_signature();
+            sig.params.push(AbiParam::new(types::I32)); // class id
+            sig.params.push(AbiParam::new(types::I64)); // member name bytes
+            sig.params.push(AbiParam::new(types::I64)); // member name length
+            if with_func {
+                sig.params.push(AbiParam::new(types::I64)); // method function pointer
+            }
+            sig.params.push(AbiParam::new(types::I32)); // field index or method arity
+            let func_id = self.module.declare_function(name, Linkage::Import, &sig)?;
+            self.extern_funcs.insert(name.to_string(), func_id);
+        }
+
+        // js_register_class_metadata(class_id: u32, data: i64, len: i64) -> void
+        {
+            let mut sig = self.module.make_signature();
+            sig.params.push(AbiParam::new(types::I32)); // class id
+            sig.params.push(AbiParam::new(types::I64)); // metadata bytes
+            sig.params.push(AbiParam::new(types::I64)); // metadata length
+            let func_id = self.module.declare_function("js_register_class_metadata", Linkage::Import, &sig)?;
+            self.extern_funcs.insert("js_register_class_metadata".to_string(), func_id);
+        }
+
         // js_object_set_keys(obj: i64, keys_array: i64) -> void
         {
             let mut sig = self.module.make_signature();
@@ -2807,6 +3495,19 @@ impl Compiler {
             self.extern_funcs.insert("js_object_set_keys".to_string(), func_id);
         }
 
+        // js_object_shape_of(keys_array: i64) -> i64
+        {
+            let mut sig = self.module.make_signature();
+            sig.params.push(AbiParam::new(types::I64)); // keys array pointer
+            sig.returns.push(AbiParam::new(types::I64)); // shared shape, or the array itself
+            let func_id = self.module.declare_function(
+                "js_object_shape_of",
+                Linkage::Import,
+                &sig,
+            )?;
+            self.extern_funcs.insert("js_object_shape_of".to_string(), func_id);
+        }
+
         // Array runtime functions
         // js_array_from_f64(elements: *const f64, count: u32) -> *mut ArrayHeader
         {
@@ -3139,6 +3840,23 @@ impl Compiler {
             self.extern_funcs.insert("js_dynamic_object_get_property".to_string(), func_id);
         }
 
+        // js_dynamic_object_get_property_ic(obj_value: f64, name_ptr: i64, name_len: usize, cache: *mut PropertyCache) -> f64
+        // Slow path of an inline-cached `obj.name` read; fills the site's cache
+        {
+            let mut sig = self.module.make_signature();
+            sig.params.push(AbiParam::new(types::F64)); // object value
+            sig.params.push(AbiParam::new(types::I64)); // property name ptr
+            sig.params.push(AbiParam::new(types::I64)); // property name length
+            sig.params.push(AbiParam::new(types::I64)); // the site's cache
+            sig.returns.push(AbiParam::new(types::F64)); // property value as f64
+            let func_id = self.module.declare_function(
+                "js_dynamic_object_get_property_ic",
+                Linkage::Import,
+                &sig,
+            )?;
+            self.extern_funcs.insert("js_dynamic_object_get_property_ic".to_string(), func_id);
+        }
+
         // js_array_forEach(arr: *const ArrayHeader, callback: *const ClosureHeader) -> void
         {
             let mut sig = self.module.make_signature();
@@ -3369,6 +4087,45 @@ impl Compiler {
             self.extern_funcs.insert("js_map_clear".to_string(), func_id);
         }
 
+        // js_map_keys(map: *const MapHeader) -> *mut ArrayHeader (array of keys)
+        {
+            let mut sig = self.module.make_signature();
+            sig.params.push(AbiParam::new(types::I64)); // map pointer
+            sig.returns.push(AbiParam::new(types::I64)); // array pointer
+            let func_id = self.module.declare_function(
+                "js_map_keys",
+                Linkage::Import,
+                &sig,
+            )?;
+            self.extern_funcs.insert("js_map_keys".to_string(), func_id);
+        }
+
+        // js_map_values(map: *const MapHeader) -> *mut ArrayHeader (array of values)
+        {
+            let mut sig = self.module.make_signature();
+            sig.params.push(AbiParam::new(types::I64)); // map pointer
+            sig.returns.push(AbiParam::new(types::I64)); // array pointer
+            let func_id = self.module.declare_function(
+                "js_map_values",
+                Linkage::Import,
+                &sig,
+            )?;
+            self.extern_funcs.insert("js_map_values".to_string(), func_id);
+        }
+
+        // js_map_entries(map: *const MapHeader) -> *mut ArrayHeader (array of [key, value] pairs)
+        {
+            let mut sig = self.module.make_signature();
+            sig.params.push(AbiParam::new(types::I64)); // map pointer
+            sig.returns.push(AbiParam::new(types::I64)); // array pointer
+            let func_id = self.module.declare_function(
+                "js_map_entries",
+                Linkage::Import,
+                &sig,
+            )?;
+            self.extern_funcs.insert("js_map_entries".to_string(), func_id);
+        }
+
         // Set runtime functions
         // js_set_alloc(capacity: u32) -> *mut SetHeader
         {
@@ -3451,6 +4208,19 @@ impl Compiler {
             self.extern_funcs.insert("js_set_clear".to_string(), func_id);
         }
 
+        // js_set_values(set: *const SetHeader) -> *mut ArrayHeader (array of values)
+        {
+            let mut sig = self.module.make_signature();
+            sig.params.push(AbiParam::new(types::I64)); // set pointer
+            sig.returns.push(AbiParam::new(types::I64)); // array pointer
+            let func_id = self.module.declare_function(
+                "js_set_values",
+                Linkage::Import,
+                &sig,
+            )?;
+            self.extern_funcs.insert("js_set_values".to_string(), func_id);
+        }
+
         // String runtime functions
         // js_string_from_bytes(data: *const u8, len: u32) -> *mut StringHeader
         {
@@ -3688,6 +4458,32 @@ impl Compiler {
             self.extern_funcs.insert("js_string_to_upper_case".to_string(), func_id);
         }
 
+        // js_string_to_locale_upper_case / js_string_to_locale_lower_case(s: *const StringHeader, locales: f64) -> *mut StringHeader
+        for name in ["js_string_to_locale_upper_case", "js_string_to_locale_lower_case"] {
+            let mut sig = self.module.make_signature();
+            sig.params.push(AbiParam::new(types::I64)); // string pointer
+            sig.params.push(AbiParam::new(types::F64)); // locales (NaN-boxed, may be undefined)
+            sig.returns.push(AbiParam::new(types::I64)); // result pointer
+            let func_id = self.module.declare_function(name, Linkage::Import, &sig)?;
+            self.extern_funcs.insert(name.to_string(), func_id);
+        }
+
+        // js_string_locale_compare(s: *const StringHeader, that: *const StringHeader, locales: f64, options: f64) -> f64
+        {
+            let mut sig = self.module.make_signature();
+            sig.params.push(AbiParam::new(types::I64)); // string pointer
+            sig.params.push(AbiParam::new(types::I64)); // other string pointer
+            sig.params.push(AbiParam::new(types::F64)); // locales (NaN-boxed, may be undefined)
+            sig.params.push(AbiParam::new(types::F64)); // options object (NaN-boxed, may be undefined)
+            sig.returns.push(AbiParam::new(types::F64)); // negative, 0 or positive
+            let func_id = self.module.declare_function(
+                "js_string_locale_compare",
+                Linkage::Import,
+                &sig,
+            )?;
+            self.extern_funcs.insert("js_string_locale_compare".to_string(), func_id);
+        }
+
         // js_string_index_of(haystack: *const StringHeader, needle: *const StringHeader) -> i32
         {
             let mut sig = self.module.make_signature();
@@ -3865,24 +4661,37 @@ impl Compiler {
             self.extern_funcs.insert("js_fs_append_file_sync".to_string(), func_id);
         }
 
-        // js_fs_exists_sync(path_ptr: *const StringHeader) -> i32
+        // js_fs_create_read_stream(path_ptr: *const StringHeader) -> f64 (NaN-boxed handle)
         {
             let mut sig = self.module.make_signature();
             sig.params.push(AbiParam::new(types::I64)); // path string pointer
-            sig.returns.push(AbiParam::new(types::I32)); // 1 if exists, 0 if not
+            sig.returns.push(AbiParam::new(types::F64)); // ReadStream handle
             let func_id = self.module.declare_function(
-                "js_fs_exists_sync",
+                "js_fs_create_read_stream",
                 Linkage::Import,
                 &sig,
             )?;
-            self.extern_funcs.insert("js_fs_exists_sync".to_string(), func_id);
+            self.extern_funcs.insert("js_fs_create_read_stream".to_string(), func_id);
         }
 
-        // js_fs_mkdir_sync(path_ptr: *const StringHeader) -> i32
+        // js_fs_exists_sync(path_ptr: *const StringHeader) -> i32
         {
             let mut sig = self.module.make_signature();
             sig.params.push(AbiParam::new(types::I64)); // path string pointer
-            sig.returns.push(AbiParam::new(types::I32)); // 1 on success, 0 on failure
+            sig.returns.push(AbiParam::new(types::I32)); // 1 if exists, 0 if not
+            let func_id = self.module.declare_function(
+                "js_fs_exists_sync",
+                Linkage::Import,
+                &sig,
+            )?;
+            self.extern_funcs.insert("js_fs_exists_sync".to_string(), func_id);
+        }
+
+        // js_fs_mkdir_sync(path_ptr: *const StringHeader) -> i32
+        {
+            let mut sig = self.module.make_signature();
+            sig.params.push(AbiParam::new(types::I64)); // path string pointer
+            sig.returns.push(AbiParam::new(types::I32)); // 1 on success, 0 on failure
             let func_id = self.module.declare_function(
                 "js_fs_mkdir_sync",
                 Linkage::Import,
@@ -3932,6 +4741,27 @@ impl Compiler {
             self.extern_funcs.insert("js_path_dirname".to_string(), func_id);
         }
 
+        // js_import_meta_exe_path(relative_path: f64, property: i32) -> *mut StringHeader
+        {
+            let mut sig = self.module.make_signature();
+            sig.params.push(AbiParam::new(types::F64)); // project-relative source path (string)
+            sig.params.push(AbiParam::new(types::I32)); // property (0=url, 1=filename, 2=dirname)
+            sig.returns.push(AbiParam::new(types::I64)); // result string pointer
+            let func_id = self.module.declare_function("js_import_meta_exe_path", Linkage::Import, &sig)?;
+            self.extern_funcs.insert("js_import_meta_exe_path".to_string(), func_id);
+        }
+
+        // js_import_meta_resolve(module_path: f64, specifier: f64, exe_relative: i32) -> *mut StringHeader
+        {
+            let mut sig = self.module.make_signature();
+            sig.params.push(AbiParam::new(types::F64)); // module path (string)
+            sig.params.push(AbiParam::new(types::F64)); // specifier (string)
+            sig.params.push(AbiParam::new(types::I32)); // resolve relative to the executable
+            sig.returns.push(AbiParam::new(types::I64)); // result string pointer
+            let func_id = self.module.declare_function("js_import_meta_resolve", Linkage::Import, &sig)?;
+            self.extern_funcs.insert("js_import_meta_resolve".to_string(), func_id);
+        }
+
         // js_path_basename(path: *const StringHeader) -> *mut StringHeader
         {
             let mut sig = self.module.make_signature();
@@ -4207,6 +5037,19 @@ impl Compiler {
             self.extern_funcs.insert("js_closure_alloc".to_string(), func_id);
         }
 
+        // js_closure_set_arity(closure: *mut ClosureHeader, arity: u32)
+        {
+            let mut sig = self.module.make_signature();
+            sig.params.push(AbiParam::new(types::I64)); // closure pointer
+            sig.params.push(AbiParam::new(types::I32)); // arity
+            let func_id = self.module.declare_function(
+                "js_closure_set_arity",
+                Linkage::Import,
+                &sig,
+            )?;
+            self.extern_funcs.insert("js_closure_set_arity".to_string(), func_id);
+        }
+
         // js_closure_set_capture_f64(closure: *mut ClosureHeader, index: u32, value: f64)
         {
             let mut sig = self.module.make_signature();
@@ -4443,7 +5286,20 @@ impl Compiler {
 
         // setjmp(env: *mut i32) -> i32 (0 if normal entry, non-zero if from longjmp)
         // This is a libc function that must be called directly from generated code
-        {
+        if self.module.isa().default_call_conv() == isa::CallConv::WindowsFastcall {
+            // The MSVC CRT's _setjmp(env, frame); the try block passes a null frame so
+            // longjmp restores registers without SEH unwinding (see exception.rs)
+            let mut sig = self.module.make_signature();
+            sig.params.push(AbiParam::new(types::I64)); // pointer
+            sig.params.push(AbiParam::new(types::I64)); // frame
+            sig.returns.push(AbiParam::new(types::I32));
+            let func_id = self.module.declare_function(
+                "_setjmp",
+                Linkage::Import,
+                &sig,
+            )?;
+            self.extern_funcs.insert("_setjmp".to_string(), func_id);
+        } else {
             let mut sig = self.module.make_signature();
             sig.params.push(AbiParam::new(types::I64)); // pointer
             sig.returns.push(AbiParam::new(types::I32));
@@ -4808,6 +5664,18 @@ impl Compiler {
             self.extern_funcs.insert("js_sleep_ms".to_string(), func_id);
         }
 
+        // js_await_wait(ms: f64) - sleeps, or runs the host run loop (perry/ui) meanwhile
+        {
+            let mut sig = self.module.make_signature();
+            sig.params.push(AbiParam::new(types::F64));
+            let func_id = self.module.declare_function(
+                "js_await_wait",
+                Linkage::Import,
+                &sig,
+            )?;
+            self.extern_funcs.insert("js_await_wait".to_string(), func_id);
+        }
+
         // setInterval(callback: i64, interval_ms: f64) -> i64
         // JS-style setInterval that takes a callback function and interval
         // Returns an interval ID for use with clearInterval
@@ -4837,6 +5705,57 @@ impl Compiler {
             self.extern_funcs.insert("clearInterval".to_string(), func_id);
         }
 
+        // clearTimeout(timer_id: i64)
+        // Cancels a setTimeout callback (or an interval) by its ID
+        {
+            let mut sig = self.module.make_signature();
+            sig.params.push(AbiParam::new(types::I64)); // timer_id
+            let func_id = self.module.declare_function(
+                "clearTimeout",
+                Linkage::Import,
+                &sig,
+            )?;
+            self.extern_funcs.insert("clearTimeout".to_string(), func_id);
+        }
+
+        // js_timer_unref(timer_id: i64) -> i64 / js_timer_ref(timer_id: i64) -> i64
+        // timer.unref() / timer.ref(); both return the timer for chaining
+        for name in ["js_timer_unref", "js_timer_ref"] {
+            let mut sig = self.module.make_signature();
+            sig.params.push(AbiParam::new(types::I64)); // timer_id
+            sig.returns.push(AbiParam::new(types::I64)); // timer_id
+            let func_id = self.module.declare_function(name, Linkage::Import, &sig)?;
+            self.extern_funcs.insert(name.to_string(), func_id);
+        }
+
+        // js_timer_has_ref(timer_id: i64) -> f64
+        // timer.hasRef()
+        {
+            let mut sig = self.module.make_signature();
+            sig.params.push(AbiParam::new(types::I64)); // timer_id
+            sig.returns.push(AbiParam::new(types::F64)); // boolean
+            let func_id = self.module.declare_function("js_timer_has_ref", Linkage::Import, &sig)?;
+            self.extern_funcs.insert("js_timer_has_ref".to_string(), func_id);
+        }
+
+        // js_timer_active_list() -> f64 / js_process_active_resources() -> f64 /
+        // js_memory_allocation_stats() -> f64
+        // process.getActiveTimers() / process.getActiveResourcesInfo() / process.allocationStats()
+        for name in ["js_timer_active_list", "js_process_active_resources", "js_memory_allocation_stats"] {
+            let mut sig = self.module.make_signature();
+            sig.returns.push(AbiParam::new(types::F64)); // array or object
+            let func_id = self.module.declare_function(name, Linkage::Import, &sig)?;
+            self.extern_funcs.insert(name.to_string(), func_id);
+        }
+
+        // js_run_event_loop() -> void
+        // Runs timers at the end of main until none keeps the process alive
+        {
+            let sig = self.module.make_signature();
+            let func_id = self.module.declare_function("js_run_event_loop", Linkage::Import, &sig)?;
+            self.extern_funcs.insert("js_run_event_loop".to_string(), func_id);
+        }
+
         // js_interval_timer_tick() -> i32
         // Process expired interval timers
         {
@@ -5815,6 +6734,19 @@ impl Compiler {
             self.extern_funcs.insert("fetch".to_string(), func_id);
         }
 
+        // js_fetch_upload_stream(url: i64, method: i64, body: f64, headers_json: i64, on_progress: i64) -> Promise (i64)
+        {
+            let mut sig = self.module.make_signature();
+            sig.params.push(AbiParam::new(types::I64)); // url string ptr
+            sig.params.push(AbiParam::new(types::I64)); // method string ptr
+            sig.params.push(AbiParam::new(types::F64)); // body: ReadStream handle or string
+            sig.params.push(AbiParam::new(types::I64)); // headers JSON string ptr
+            sig.params.push(AbiParam::new(types::I64)); // onUploadProgress closure ptr (nullable)
+            sig.returns.push(AbiParam::new(types::I64)); // Promise ptr
+            let func_id = self.module.declare_function("js_fetch_upload_stream", Linkage::Import, &sig)?;
+            self.extern_funcs.insert("js_fetch_upload_stream".to_string(), func_id);
+        }
+
         // js_fetch_text(url: i64) -> Promise (i64)
         {
             let mut sig = self.module.make_signature();
@@ -6089,6 +7021,219 @@ impl Compiler {
             self.extern_funcs.insert("js_async_local_storage_disable".to_string(), func_id);
         }
 
+        // ========================================================================
+        // Dependency injection (perry/di)
+        // ========================================================================
+        self.declare_nanboxed_externs(&[
+            ("js_di_provide", 4),               // (name, scope, deps, factory)
+            ("js_di_provide_value", 2),         // (token, value)
+            ("js_di_route", 4),                 // (controller, method, path, handler)
+            ("js_di_resolve", 1),               // (token) -> instance
+            ("js_di_register_controllers", 1),  // (app)
+        ])?;
+
+        // ========================================================================
+        // Structured concurrency (perry/concurrency)
+        // ========================================================================
+        // (body, options) -> promise, all NaN-boxed
+        for name in ["js_task_group", "js_cancel_scope"] {
+            let mut sig = self.module.make_signature();
+            sig.params.push(AbiParam::new(types::F64));
+            sig.params.push(AbiParam::new(types::F64));
+            sig.returns.push(AbiParam::new(types::F64));
+            let func_id = self.module.declare_function(name, Linkage::Import, &sig)?;
+            self.extern_funcs.insert(name.to_string(), func_id);
+        }
+
+        // ========================================================================
+        // Emitter streams and pipelines (perry/streams)
+        // ========================================================================
+        self.declare_nanboxed_externs(&[
+            ("js_streams_from_emitter", 3), // (emitter, event, options) -> stream
+            ("js_streams_pipeline", 8),     // (source, ...stages) -> promise
+        ])?;
+
+        // ========================================================================
+        // WebAssembly plugins (perry/wasm)
+        // ========================================================================
+        // js_wasm_load_plugin(path, options) -> NaN-boxed plugin handle
+        {
+            let mut sig = self.module.make_signature();
+            sig.params.push(AbiParam::new(types::F64)); // path
+            sig.params.push(AbiParam::new(types::F64)); // options
+            sig.returns.push(AbiParam::new(types::F64));
+            let func_id = self.module.declare_function("js_wasm_load_plugin", Linkage::Import, &sig)?;
+            self.extern_funcs.insert("js_wasm_load_plugin".to_string(), func_id);
+        }
+
+        // ========================================================================
+        // Embedded key-value store (level)
+        // ========================================================================
+        // js_level_open(location, options) -> NaN-boxed database handle
+        {
+            let mut sig = self.module.make_signature();
+            sig.params.push(AbiParam::new(types::F64)); // location
+            sig.params.push(AbiParam::new(types::F64)); // options
+            sig.returns.push(AbiParam::new(types::F64));
+            let func_id = self.module.declare_function("js_level_open", Linkage::Import, &sig)?;
+            self.extern_funcs.insert("js_level_open".to_string(), func_id);
+        }
+
+        // ========================================================================
+        // Full-text search (minisearch)
+        // ========================================================================
+        // js_minisearch_new(options) -> NaN-boxed index handle
+        {
+            let mut sig = self.module.make_signature();
+            sig.params.push(AbiParam::new(types::F64)); // options
+            sig.returns.push(AbiParam::new(types::F64));
+            let func_id = self.module.declare_function("js_minisearch_new", Linkage::Import, &sig)?;
+            self.extern_funcs.insert("js_minisearch_new".to_string(), func_id);
+        }
+        // js_minisearch_load_json(json, options) / js_minisearch_load_buffer(buffer, options)
+        for name in ["js_minisearch_load_json", "js_minisearch_load_buffer"] {
+            let mut sig = self.module.make_signature();
+            sig.params.push(AbiParam::new(types::F64)); // saved index
+            sig.params.push(AbiParam::new(types::F64)); // options
+            sig.returns.push(AbiParam::new(types::F64));
+            let func_id = self.module.declare_function(name, Linkage::Import, &sig)?;
+            self.extern_funcs.insert(name.to_string(), func_id);
+        }
+
+        // ========================================================================
+        // Append-only event log (perry/eventlog)
+        // ========================================================================
+        // js_eventlog_open(dir, options) -> NaN-boxed log handle
+        {
+            let mut sig = self.module.make_signature();
+            sig.params.push(AbiParam::new(types::F64)); // dir
+            sig.params.push(AbiParam::new(types::F64)); // options
+            sig.returns.push(AbiParam::new(types::F64));
+            let func_id = self.module.declare_function("js_eventlog_open", Linkage::Import, &sig)?;
+            self.extern_funcs.insert("js_eventlog_open".to_string(), func_id);
+        }
+
+        // ========================================================================
+        // Geo and spatial utilities (perry/geo)
+        // ========================================================================
+        // All take NaN-boxed values and return a number, boolean, string or array
+        self.declare_nanboxed_externs(&[
+            ("js_geo_distance", 3),
+            ("js_geo_bearing", 2),
+            ("js_geo_destination", 4),
+            ("js_geo_point_in_polygon", 3),
+            ("js_geo_bbox", 1),
+            ("js_geo_geohash_encode", 2),
+            ("js_geo_geohash_decode", 1),
+            ("js_geo_geohash_bounds", 1),
+            ("js_geo_geohash_neighbors", 1),
+        ])?;
+
+        // ========================================================================
+        // HTTP client cache (perry/http-cache)
+        // ========================================================================
+        // js_http_cache_configure(options) -> undefined
+        {
+            let mut sig = self.module.make_signature();
+            sig.params.push(AbiParam::new(types::F64)); // options
+            sig.returns.push(AbiParam::new(types::F64));
+            let func_id = self.module.declare_function("js_http_cache_configure", Linkage::Import, &sig)?;
+            self.extern_funcs.insert("js_http_cache_configure".to_string(), func_id);
+        }
+        // () -> undefined
+        for name in ["js_http_cache_disable", "js_http_cache_clear"] {
+            let mut sig = self.module.make_signature();
+            sig.returns.push(AbiParam::new(types::F64));
+            let func_id = self.module.declare_function(name, Linkage::Import, &sig)?;
+            self.extern_funcs.insert(name.to_string(), func_id);
+        }
+
+        // ========================================================================
+        // OAuth 2.0 / OpenID Connect client (openid-client)
+        // ========================================================================
+        // All take and return NaN-boxed values; grants and discovery return promises
+        self.declare_nanboxed_externs(&[
+            ("js_oidc_discovery", 3),
+            ("js_oidc_random_pkce_code_verifier", 0),
+            ("js_oidc_calculate_pkce_code_challenge", 1),
+            ("js_oidc_random_state", 0),
+            ("js_oidc_random_nonce", 0),
+            ("js_oidc_build_authorization_url", 2),
+            ("js_oidc_authorization_code_grant", 4),
+            ("js_oidc_client_credentials_grant", 2),
+            ("js_oidc_refresh_token_grant", 3),
+            ("js_oidc_fetch_user_info", 3),
+        ])?;
+
+        // ========================================================================
+        // Runtime type reflection (perry/reflect)
+        // ========================================================================
+        // (value) -> NaN-boxed type info, class name or field list
+        for name in ["js_reflect_type_of", "js_reflect_class_name", "js_reflect_fields"] {
+            let mut sig = self.module.make_signature();
+            sig.params.push(AbiParam::new(types::F64));
+            sig.returns.push(AbiParam::new(types::F64));
+            let func_id = self.module.declare_function(name, Linkage::Import, &sig)?;
+            self.extern_funcs.insert(name.to_string(), func_id);
+        }
+
+        // ========================================================================
+        // Buffer pool (perry/buffer-pool)
+        // ========================================================================
+        // acquire(size) -> Buffer pointer as f64 bits; release(buffer) -> undefined
+        for name in ["js_buffer_pool_acquire", "js_buffer_pool_release"] {
+            let mut sig = self.module.make_signature();
+            sig.params.push(AbiParam::new(types::F64));
+            sig.returns.push(AbiParam::new(types::F64));
+            let func_id = self.module.declare_function(name, Linkage::Import, &sig)?;
+            self.extern_funcs.insert(name.to_string(), func_id);
+        }
+
+        // ========================================================================
+        // JS handle lifecycle (perry/js-handles)
+        // ========================================================================
+        // dispose(value) -> NaN-boxed boolean; retain(value) -> value
+        for name in ["js_handles_dispose", "js_handles_retain"] {
+            let mut sig = self.module.make_signature();
+            sig.params.push(AbiParam::new(types::F64));
+            sig.returns.push(AbiParam::new(types::F64));
+            let func_id = self.module.declare_function(name, Linkage::Import, &sig)?;
+            self.extern_funcs.insert(name.to_string(), func_id);
+        }
+        // liveHandles() -> NaN-boxed array of strings
+        {
+            let mut sig = self.module.make_signature();
+            sig.returns.push(AbiParam::new(types::F64));
+            let func_id = self.module.declare_function("js_handles_live", Linkage::Import, &sig)?;
+            self.extern_funcs.insert("js_handles_live".to_string(), func_id);
+        }
+
+        // ========================================================================
+        // BigInt number theory (perry/bigint)
+        // ========================================================================
+        // NaN-boxed BigInts in and out
+        self.declare_nanboxed_externs(&[
+            ("js_bigint_mod_pow", 3), // (base, exponent, modulus)
+            ("js_bigint_gcd", 2),
+        ])?;
+
+        // ========================================================================
+        // Temporal (perry/temporal)
+        // ========================================================================
+        self.declare_nanboxed_externs(&[
+            ("js_temporal_plain_date_new", 3),          // (year, month, day)
+            ("js_temporal_plain_date_from", 1),
+            ("js_temporal_plain_date_compare", 2),
+            ("js_temporal_duration_new", 10),           // (years, ..., nanoseconds)
+            ("js_temporal_duration_from", 1),
+            ("js_temporal_duration_compare", 2),
+            ("js_temporal_zoned_date_time_from", 1),
+            ("js_temporal_zoned_date_time_compare", 2),
+            ("js_temporal_now_zoned_date_time_iso", 1), // (timeZone?)
+            ("js_temporal_now_plain_date_iso", 1),      // (timeZone?)
+            ("js_temporal_now_time_zone_id", 0),
+        ])?;
+
         // ========================================================================
         // LRUCache
         // ========================================================================
@@ -6625,6 +7770,17 @@ impl Compiler {
             self.extern_funcs.insert("js_pg_pool_query".to_string(), func_id);
         }
 
+        // js_pg_pool_query_params(pool: i64, sql: i64, params: i64) -> Promise (i64)
+        {
+            let mut sig = self.module.make_signature();
+            sig.params.push(AbiParam::new(types::I64)); // pool handle
+            sig.params.push(AbiParam::new(types::I64)); // sql string
+            sig.params.push(AbiParam::new(types::I64)); // params array
+            sig.returns.push(AbiParam::new(types::I64)); // Promise
+            let func_id = self.module.declare_function("js_pg_pool_query_params", Linkage::Import, &sig)?;
+            self.extern_funcs.insert("js_pg_pool_query_params".to_string(), func_id);
+        }
+
         // js_pg_pool_end(pool: i64) -> Promise (i64)
         {
             let mut sig = self.module.make_signature();
@@ -7944,14 +9100,14 @@ impl Compiler {
             self.extern_funcs.insert("js_json_parse".to_string(), func_id);
         }
 
-        // js_json_stringify(value: f64, type_hint: u32) -> i64 (generic stringify for any JSValue)
-        {
+        // js_json_stringify / js_json_canonicalize(value: f64, type_hint: u32) -> i64 (any JSValue)
+        for name in &["js_json_stringify", "js_json_canonicalize"] {
             let mut sig = self.module.make_signature();
             sig.params.push(AbiParam::new(types::F64)); // value (JSValue)
             sig.params.push(AbiParam::new(types::I32)); // type_hint (0=unknown, 1=object, 2=array)
             sig.returns.push(AbiParam::new(types::I64)); // json string
-            let func_id = self.module.declare_function("js_json_stringify", Linkage::Import, &sig)?;
-            self.extern_funcs.insert("js_json_stringify".to_string(), func_id);
+            let func_id = self.module.declare_function(name, Linkage::Import, &sig)?;
+            self.extern_funcs.insert(name.to_string(), func_id);
         }
 
         // JSON stringify functions (various types) -> i64 (string)
@@ -8178,6 +9334,15 @@ impl Compiler {
             self.extern_funcs.insert("js_error_new_with_message".to_string(), func_id);
         }
 
+        // js_error_set_location(error: *mut ErrorHeader, location: *mut StringHeader) -> void
+        {
+            let mut sig = self.module.make_signature();
+            sig.params.push(AbiParam::new(types::I64));
+            sig.params.push(AbiParam::new(types::I64));
+            let func_id = self.module.declare_function("js_error_set_location", Linkage::Import, &sig)?;
+            self.extern_funcs.insert("js_error_set_location".to_string(), func_id);
+        }
+
         // js_error_get_message(error: *mut ErrorHeader) -> *mut StringHeader
         {
             let mut sig = self.module.make_signature();
@@ -8502,6 +9667,26 @@ impl Compiler {
             self.extern_funcs.insert("js_string_equals".to_string(), func_id);
         }
 
+        // js_string_switch_tag(s: *const StringHeader) -> u64
+        {
+            let mut sig = self.module.make_signature();
+            sig.params.push(AbiParam::new(types::I64)); // string pointer
+            sig.returns.push(AbiParam::new(types::I64)); // tag
+            let func_id = self.module.declare_function("js_string_switch_tag", Linkage::Import, &sig)?;
+            self.extern_funcs.insert("js_string_switch_tag".to_string(), func_id);
+        }
+
+        // js_string_equals_bytes(s: *const StringHeader, bytes: *const u8, len: u32) -> bool
+        {
+            let mut sig = self.module.make_signature();
+            sig.params.push(AbiParam::new(types::I64)); // string pointer
+            sig.params.push(AbiParam::new(types::I64)); // bytes
+            sig.params.push(AbiParam::new(types::I32)); // len
+            sig.returns.push(AbiParam::new(types::I32)); // bool (i32)
+            let func_id = self.module.declare_function("js_string_equals_bytes", Linkage::Import, &sig)?;
+            self.extern_funcs.insert("js_string_equals_bytes".to_string(), func_id);
+        }
+
         // js_dynamic_string_equals(a: f64, b: f64) -> i32
         // Compares strings that may be NaN-boxed (from PropertyGet) or raw pointers (from literals)
         {
@@ -8736,6 +9921,37 @@ impl Compiler {
             self.extern_funcs.insert("js_fastify_route".to_string(), func_id);
         }
 
+        // js_fastify_route_with_opts(app: Handle, method: i64, path: i64, opts: f64, handler: i64) -> bool (i32)
+        {
+            let mut sig = self.module.make_signature();
+            sig.params.push(AbiParam::new(types::I64));
+            sig.params.push(AbiParam::new(types::I64));
+            sig.params.push(AbiParam::new(types::I64));
+            sig.params.push(AbiParam::new(types::F64));
+            sig.params.push(AbiParam::new(types::I64));
+            sig.returns.push(AbiParam::new(types::I32));
+            let func_id = self.module.declare_function("js_fastify_route_with_opts", Linkage::Import, &sig)?;
+            self.extern_funcs.insert("js_fastify_route_with_opts".to_string(), func_id);
+        }
+
+        // js_fastify_set_docs(document: *const u8, len: i64) -> void
+        {
+            let mut sig = self.module.make_signature();
+            sig.params.push(AbiParam::new(types::I64));
+            sig.params.push(AbiParam::new(types::I64));
+            let func_id = self.module.declare_function("js_fastify_set_docs", Linkage::Import, &sig)?;
+            self.extern_funcs.insert("js_fastify_set_docs".to_string(), func_id);
+        }
+
+        // js_temporal_set_tzdata(data: *const u8, len: i64) -> void
+        {
+            let mut sig = self.module.make_signature();
+            sig.params.push(AbiParam::new(types::I64));
+            sig.params.push(AbiParam::new(types::I64));
+            let func_id = self.module.declare_function("js_temporal_set_tzdata", Linkage::Import, &sig)?;
+            self.extern_funcs.insert("js_temporal_set_tzdata".to_string(), func_id);
+        }
+
         // js_fastify_add_hook(app: Handle, hook_name: i64, handler: i64) -> bool (i32)
         {
             let mut sig = self.module.make_signature();
@@ -9044,26 +10260,287 @@ impl Compiler {
         }
 
         // ============================================
-        // V8 JavaScript Runtime FFI functions
+        // Memory limits
         // ============================================
 
-        // js_runtime_init() -> void
-        // Initialize the V8 JavaScript runtime
+        // js_memory_configure(limit: f64, policy: i32) -> void
+        // Apply --max-heap / --oom-policy at startup
         {
-            let sig = self.module.make_signature();
-            let func_id = self.module.declare_function("js_runtime_init", Linkage::Import, &sig)?;
-            self.extern_funcs.insert("js_runtime_init".to_string(), func_id);
+            let mut sig = self.module.make_signature();
+            sig.params.push(AbiParam::new(types::F64)); // limit in bytes
+            sig.params.push(AbiParam::new(types::I32)); // 0 = throw, 1 = abort
+            let func_id = self.module.declare_function("js_memory_configure", Linkage::Import, &sig)?;
+            self.extern_funcs.insert("js_memory_configure".to_string(), func_id);
         }
 
-        // js_load_module(path_ptr: i64, path_len: i64) -> u64 (module handle)
-        // Load a JavaScript module and return a handle
+        // js_memory_set_allocator(name: *const u8, len: i64) -> void
+        // Record --allocator for process.allocationStats()
         {
             let mut sig = self.module.make_signature();
-            sig.params.push(AbiParam::new(types::I64)); // path pointer
-            sig.params.push(AbiParam::new(types::I64)); // path length
-            sig.returns.push(AbiParam::new(types::I64)); // module handle (u64)
-            let func_id = self.module.declare_function("js_load_module", Linkage::Import, &sig)?;
-            self.extern_funcs.insert("js_load_module".to_string(), func_id);
+            sig.params.push(AbiParam::new(types::I64));
+            sig.params.push(AbiParam::new(types::I64));
+            let func_id = self.module.declare_function("js_memory_set_allocator", Linkage::Import, &sig)?;
+            self.extern_funcs.insert("js_memory_set_allocator".to_string(), func_id);
+        }
+
+        // js_permissions_configure(policy: *const u8, len: i64) -> void
+        // Install the compiled-in [permissions] policy at startup
+        {
+            let mut sig = self.module.make_signature();
+            sig.params.push(AbiParam::new(types::I64));
+            sig.params.push(AbiParam::new(types::I64));
+            let func_id = self.module.declare_function("js_permissions_configure", Linkage::Import, &sig)?;
+            self.extern_funcs.insert("js_permissions_configure".to_string(), func_id);
+        }
+
+        // js_memory_set_limit(limit: f64, policy: f64) -> void
+        // process.setMemoryLimit(bytes, policy?)
+        {
+            let mut sig = self.module.make_signature();
+            sig.params.push(AbiParam::new(types::F64)); // limit in bytes
+            sig.params.push(AbiParam::new(types::F64)); // policy string or undefined
+            let func_id = self.module.declare_function("js_memory_set_limit", Linkage::Import, &sig)?;
+            self.extern_funcs.insert("js_memory_set_limit".to_string(), func_id);
+        }
+
+        // js_memory_on_low(callback: i64) -> void
+        // process.onLowMemory(callback)
+        {
+            let mut sig = self.module.make_signature();
+            sig.params.push(AbiParam::new(types::I64)); // closure pointer
+            let func_id = self.module.declare_function("js_memory_on_low", Linkage::Import, &sig)?;
+            self.extern_funcs.insert("js_memory_on_low".to_string(), func_id);
+        }
+
+        // ============================================
+        // Standard streams
+        // ============================================
+
+        // js_stdio_write(fd: i32, data: f64, callback: i64) -> f64
+        // process.stdout.write(data, callback?) / process.stderr.write(...)
+        {
+            let mut sig = self.module.make_signature();
+            sig.params.push(AbiParam::new(types::I32)); // 1 = stdout, 2 = stderr
+            sig.params.push(AbiParam::new(types::F64)); // data
+            sig.params.push(AbiParam::new(types::I64)); // closure pointer or 0
+            sig.returns.push(AbiParam::new(types::F64)); // boolean
+            let func_id = self.module.declare_function("js_stdio_write", Linkage::Import, &sig)?;
+            self.extern_funcs.insert("js_stdio_write".to_string(), func_id);
+        }
+
+        // js_stdin_read() -> f64
+        // process.stdin.read(), and each step of `for await (const chunk of process.stdin)`
+        {
+            let mut sig = self.module.make_signature();
+            sig.returns.push(AbiParam::new(types::F64)); // string chunk or null
+            let func_id = self.module.declare_function("js_stdin_read", Linkage::Import, &sig)?;
+            self.extern_funcs.insert("js_stdin_read".to_string(), func_id);
+        }
+
+        // js_stdin_on(event: f64, listener: i64) -> void
+        // process.stdin.on('data' | 'end', listener)
+        {
+            let mut sig = self.module.make_signature();
+            sig.params.push(AbiParam::new(types::F64)); // event name
+            sig.params.push(AbiParam::new(types::I64)); // closure pointer
+            let func_id = self.module.declare_function("js_stdin_on", Linkage::Import, &sig)?;
+            self.extern_funcs.insert("js_stdin_on".to_string(), func_id);
+        }
+
+        // js_stdin_pipe(fd: i32) -> void
+        // process.stdin.pipe(process.stdout | process.stderr)
+        {
+            let mut sig = self.module.make_signature();
+            sig.params.push(AbiParam::new(types::I32)); // 1 = stdout, 2 = stderr
+            let func_id = self.module.declare_function("js_stdin_pipe", Linkage::Import, &sig)?;
+            self.extern_funcs.insert("js_stdin_pipe".to_string(), func_id);
+        }
+
+        // js_stdio_flow() -> void
+        // Feeds stdin to 'data' listeners and pipes once the entry module's top level has run
+        {
+            let sig = self.module.make_signature();
+            let func_id = self.module.declare_function("js_stdio_flow", Linkage::Import, &sig)?;
+            self.extern_funcs.insert("js_stdio_flow".to_string(), func_id);
+        }
+
+        // js_dispose_resource(value: f64, is_async: i32) -> f64
+        // `using` disposal for values whose class is not known statically
+        {
+            let mut sig = self.module.make_signature();
+            sig.params.push(AbiParam::new(types::F64)); // resource
+            sig.params.push(AbiParam::new(types::I32)); // is_async
+            sig.returns.push(AbiParam::new(types::F64)); // disposer result
+            let func_id = self.module.declare_function("js_dispose_resource", Linkage::Import, &sig)?;
+            self.extern_funcs.insert("js_dispose_resource".to_string(), func_id);
+        }
+
+        // js_disposable_stack_new() -> f64
+        // new DisposableStack()
+        {
+            let mut sig = self.module.make_signature();
+            sig.returns.push(AbiParam::new(types::F64));
+            let func_id = self.module.declare_function("js_disposable_stack_new", Linkage::Import, &sig)?;
+            self.extern_funcs.insert("js_disposable_stack_new".to_string(), func_id);
+        }
+
+        // js_disposable_stack_adopt(stack: f64, value: f64, on_dispose: f64) -> f64
+        // stack.adopt(value, onDispose) / stack.use(value)
+        {
+            let mut sig = self.module.make_signature();
+            sig.params.push(AbiParam::new(types::F64)); // stack
+            sig.params.push(AbiParam::new(types::F64)); // value
+            sig.params.push(AbiParam::new(types::F64)); // onDispose closure
+            sig.returns.push(AbiParam::new(types::F64));
+            let func_id = self.module.declare_function("js_disposable_stack_adopt", Linkage::Import, &sig)?;
+            self.extern_funcs.insert("js_disposable_stack_adopt".to_string(), func_id);
+        }
+
+        // js_disposable_stack_defer(stack: f64, on_dispose: f64) -> f64
+        {
+            let mut sig = self.module.make_signature();
+            sig.params.push(AbiParam::new(types::F64)); // stack
+            sig.params.push(AbiParam::new(types::F64)); // onDispose closure
+            sig.returns.push(AbiParam::new(types::F64));
+            let func_id = self.module.declare_function("js_disposable_stack_defer", Linkage::Import, &sig)?;
+            self.extern_funcs.insert("js_disposable_stack_defer".to_string(), func_id);
+        }
+
+        // js_disposable_stack_dispose(stack: f64) -> f64
+        {
+            let mut sig = self.module.make_signature();
+            sig.params.push(AbiParam::new(types::F64)); // stack
+            sig.returns.push(AbiParam::new(types::F64));
+            let func_id = self.module.declare_function("js_disposable_stack_dispose", Linkage::Import, &sig)?;
+            self.extern_funcs.insert("js_disposable_stack_dispose".to_string(), func_id);
+        }
+
+        // js_disposable_stack_move(stack: f64) -> f64
+        {
+            let mut sig = self.module.make_signature();
+            sig.params.push(AbiParam::new(types::F64)); // stack
+            sig.returns.push(AbiParam::new(types::F64));
+            let func_id = self.module.declare_function("js_disposable_stack_move", Linkage::Import, &sig)?;
+            self.extern_funcs.insert("js_disposable_stack_move".to_string(), func_id);
+        }
+
+        // js_disposable_stack_disposed(stack: f64) -> f64 (boolean)
+        {
+            let mut sig = self.module.make_signature();
+            sig.params.push(AbiParam::new(types::F64)); // stack
+            sig.returns.push(AbiParam::new(types::F64));
+            let func_id = self.module.declare_function("js_disposable_stack_disposed", Linkage::Import, &sig)?;
+            self.extern_funcs.insert("js_disposable_stack_disposed".to_string(), func_id);
+        }
+
+        // ============================================
+        // Crash reporting
+        // ============================================
+
+        // js_crash_install() -> void
+        // Install signal handlers and panic hook for crash reports
+        {
+            let sig = self.module.make_signature();
+            let func_id = self.module.declare_function("js_crash_install", Linkage::Import, &sig)?;
+            self.extern_funcs.insert("js_crash_install".to_string(), func_id);
+        }
+
+        // js_stack_guard_install() -> void
+        // Turn stack overflows into catchable RangeErrors
+        {
+            let sig = self.module.make_signature();
+            let func_id = self.module.declare_function("js_stack_guard_install", Linkage::Import, &sig)?;
+            self.extern_funcs.insert("js_stack_guard_install".to_string(), func_id);
+        }
+
+        // js_hot_reload_start() -> void
+        // Listen for `perry dev`'s patches (hot-reload builds)
+        {
+            let sig = self.module.make_signature();
+            let func_id = self.module.declare_function("js_hot_reload_start", Linkage::Import, &sig)?;
+            self.extern_funcs.insert("js_hot_reload_start".to_string(), func_id);
+        }
+
+        // js_cpu_has_x86_64_v3() -> i32
+        // Whether multiversioned functions may run their x86-64-v3 versions
+        {
+            let mut sig = self.module.make_signature();
+            sig.returns.push(AbiParam::new(types::I32));
+            let func_id = self.module.declare_function("js_cpu_has_x86_64_v3", Linkage::Import, &sig)?;
+            self.extern_funcs.insert("js_cpu_has_x86_64_v3".to_string(), func_id);
+        }
+
+        // js_memo_lookup(slot: i64, max_entries: i32, ttl_ms: i64, args: i64, argc: i32, string_args: i64, out: i64) -> i32
+        // Look a memoized function's arguments up in its cache
+        {
+            let mut sig = self.module.make_signature();
+            sig.params.push(AbiParam::new(types::I64)); // the function's cache slot
+            sig.params.push(AbiParam::new(types::I32)); // max entries
+            sig.params.push(AbiParam::new(types::I64)); // ttl in ms (0 = none)
+            sig.params.push(AbiParam::new(types::I64)); // argument bits
+            sig.params.push(AbiParam::new(types::I32)); // argument count
+            sig.params.push(AbiParam::new(types::I64)); // mask of raw string arguments
+            sig.params.push(AbiParam::new(types::I64)); // where a hit's result bits go
+            sig.returns.push(AbiParam::new(types::I32));
+            let func_id = self.module.declare_function("js_memo_lookup", Linkage::Import, &sig)?;
+            self.extern_funcs.insert("js_memo_lookup".to_string(), func_id);
+        }
+
+        // js_memo_store(slot: i64, args: i64, argc: i32, string_args: i64, value: i64) -> void
+        // Cache a memoized function's result
+        {
+            let mut sig = self.module.make_signature();
+            sig.params.push(AbiParam::new(types::I64)); // the function's cache slot
+            sig.params.push(AbiParam::new(types::I64)); // argument bits
+            sig.params.push(AbiParam::new(types::I32)); // argument count
+            sig.params.push(AbiParam::new(types::I64)); // mask of raw string arguments
+            sig.params.push(AbiParam::new(types::I64)); // result bits
+            let func_id = self.module.declare_function("js_memo_store", Linkage::Import, &sig)?;
+            self.extern_funcs.insert("js_memo_store".to_string(), func_id);
+        }
+
+        // js_crash_frame_enter(name_ptr: i64, name_len: i64) -> void
+        // Record a TS function on the crash shadow stack
+        {
+            let mut sig = self.module.make_signature();
+            sig.params.push(AbiParam::new(types::I64)); // name pointer
+            sig.params.push(AbiParam::new(types::I64)); // name length
+            let func_id = self.module.declare_function("js_crash_frame_enter", Linkage::Import, &sig)?;
+            self.extern_funcs.insert("js_crash_frame_enter".to_string(), func_id);
+        }
+
+        // js_napi_load(path_ptr: i64, path_len: i64) -> f64
+        // Load an N-API addon and return its exports object
+        {
+            let mut sig = self.module.make_signature();
+            sig.params.push(AbiParam::new(types::I64)); // path pointer
+            sig.params.push(AbiParam::new(types::I64)); // path length
+            sig.returns.push(AbiParam::new(types::F64)); // exports (NaN-boxed)
+            let func_id = self.module.declare_function("js_napi_load", Linkage::Import, &sig)?;
+            self.extern_funcs.insert("js_napi_load".to_string(), func_id);
+        }
+
+        // ============================================
+        // V8 JavaScript Runtime FFI functions
+        // ============================================
+
+        // js_runtime_init() -> void
+        // Initialize the V8 JavaScript runtime
+        {
+            let sig = self.module.make_signature();
+            let func_id = self.module.declare_function("js_runtime_init", Linkage::Import, &sig)?;
+            self.extern_funcs.insert("js_runtime_init".to_string(), func_id);
+        }
+
+        // js_load_module(path_ptr: i64, path_len: i64) -> u64 (module handle)
+        // Load a JavaScript module and return a handle
+        {
+            let mut sig = self.module.make_signature();
+            sig.params.push(AbiParam::new(types::I64)); // path pointer
+            sig.params.push(AbiParam::new(types::I64)); // path length
+            sig.returns.push(AbiParam::new(types::I64)); // module handle (u64)
+            let func_id = self.module.declare_function("js_load_module", Linkage::Import, &sig)?;
+            self.extern_funcs.insert("js_load_module".to_string(), func_id);
         }
 
         // js_get_export(module_handle: u64, name_ptr: i64, name_len: i64) -> f64 (NaN-boxed value)
@@ -9205,20 +10682,23 @@ impl Compiler {
             sig.returns.push(AbiParam::new(return_abi));
         }
 
-        // Rename user "main" to "_user_main" to avoid conflict with C entry point
-        let symbol_name = if func.name == "main" {
-            "_user_main"
+        // Other modules link against exported functions by name; the rest get mangled
+        // names. Rename user "main" to "_user_main" to avoid conflict with C entry point
+        let symbol_name = if !func.is_exported {
+            self.local_symbol(SymbolKind::Function, vec![func.name.clone()])
+        } else if func.name == "main" {
+            "_user_main".to_string()
         } else {
-            &func.name
+            func.name.clone()
         };
 
-        let linkage = if func.is_exported {
+        let linkage = self.definition_linkage(if func.is_exported {
             Linkage::Export
         } else {
             Linkage::Local
-        };
+        });
 
-        let func_id = match self.module.declare_function(symbol_name, linkage, &sig) {
+        let func_id = match self.module.declare_function(&symbol_name, linkage, &sig) {
             Ok(id) => id,
             Err(e) => {
                 // Check if this is an incompatible declaration error
@@ -9232,7 +10712,7 @@ impl Compiler {
                 if is_incompatible {
                     // Try to find existing function by iterating all declarations
                     for (id, decl) in self.module.declarations().get_functions() {
-                        if decl.name.as_deref() == Some(symbol_name) {
+                        if decl.name.as_deref() == Some(symbol_name.as_str()) {
                             // Already have this function declared, map our func.id to the existing ID
                             self.func_ids.insert(func.id, id);
                             return Ok(());
@@ -9250,6 +10730,24 @@ impl Compiler {
         Ok(())
     }
 
+    /// Mangled name of a module-local function. `path` starts with the function or class
+    /// name; a monomorphized one is named after its generic, with its type arguments.
+    fn local_symbol(&self, kind: SymbolKind, mut path: Vec<String>) -> String {
+        let mut type_args = Vec::new();
+        if let Some((generic, args)) = path.first().and_then(|name| self.specializations.get(name)) {
+            type_args = args.clone();
+            path[0] = generic.clone();
+        }
+        Symbol::new(kind, self.module_name.as_str(), path).with_type_args(type_args).mangle()
+    }
+
+    /// Linkage of a function or writable global this module defines. Under hot reload they
+    /// are all preemptible: the executable exports them, and a patch built from the module
+    /// reaches them through the GOT, so it binds to the running program's definitions
+    fn definition_linkage(&self, linkage: Linkage) -> Linkage {
+        if self.hot_reload { Linkage::Preemptible } else { linkage }
+    }
+
     /// Check if a function body is fully integer-compatible (no strings, objects, floats, etc.)
     /// This enables generating an i64 specialization for better performance
     fn is_integer_only_function(func: &Function) -> bool {
@@ -9312,18 +10810,248 @@ impl Compiler {
         func.body.iter().all(|s| is_integer_stmt(s, func.id))
     }
 
+    /// Define the function built in `self.ctx` as `func_id`. It's queued and compiled along
+    /// with the module's other functions before the object is written (see `shards`), or
+    /// compiled right away when its debug info has to be recorded. WebAssembly is
+    /// translated right away.
+    fn define_function(&mut self, func_id: cranelift_module::FuncId) -> cranelift_module::ModuleResult<()> {
+        let source = self.debug_function.take();
+        if self.debug_info.is_none() && !self.module.is_wasm() {
+            return self.queue_function(func_id, None);
+        }
+        self.module.define_function(func_id, &mut self.ctx)?;
+        if let Some(debug_info) = self.debug_info.as_mut() {
+            let symbol = self.module.declarations().get_function_decl(func_id).linkage_name(func_id).into_owned();
+            debug_info.add_function(func_id, &symbol, source, &self.ctx, self.module.isa());
+        }
+        Ok(())
+    }
+
+    /// Move the function built in `self.ctx` to the queue `define_queued_functions`
+    /// compiles, to be compiled for `isa` (the module's by default) and defined as
+    /// `func_id`. What defining it would reject is rejected here already.
+    fn queue_function(&mut self, func_id: cranelift_module::FuncId, isa: Option<isa::OwnedTargetIsa>) -> cranelift_module::ModuleResult<()> {
+        let decl = self.module.declarations().get_function_decl(func_id);
+        if !decl.linkage.is_definable() {
+            return Err(cranelift_module::ModuleError::InvalidImportDefinition(decl.linkage_name(func_id).into_owned()));
+        }
+        if !self.queued_function_ids.insert(func_id) {
+            return Err(cranelift_module::ModuleError::DuplicateDefinition(decl.linkage_name(func_id).into_owned()));
+        }
+        let func = std::mem::replace(&mut self.ctx.func, cranelift_codegen::ir::Function::new());
+        self.queued_functions.push(PendingFunction { id: func_id, func, isa });
+        Ok(())
+    }
+
+    /// Compile the queued functions in parallel and define their machine code
+    fn define_queued_functions(&mut self) -> Result<()> {
+        let queued = std::mem::take(&mut self.queued_functions);
+        let compiled = shards::compile(queued, self.module.declarations(), self.module.isa(), self.function_cache.as_deref());
+        for (function, code) in compiled {
+            let symbol = self.module.declarations().get_function_decl(function.id).linkage_name(function.id).into_owned();
+            let code = code.map_err(|e| {
+                eprintln!("=== VERIFIER ERROR in '{}' ===", symbol);
+                eprintln!("Error: {}", e);
+                eprintln!("=== CLIF IR ===");
+                eprintln!("{}", function.func.display());
+                anyhow!("Error compiling function '{}': {}", symbol, e)
+            })?;
+            self.module.define_function_bytes(function.id, &function.func, code.alignment, &code.code, &code.relocs)?;
+        }
+        Ok(())
+    }
+
+    /// Functions worth a version per CPU level: numbers in and out, so the body is the
+    /// floating-point and bit arithmetic the newer instructions speed up
+    fn is_numeric_function(func: &Function) -> bool {
+        !func.is_async
+            && func.captures.is_empty()
+            && matches!(func.return_type, perry_types::Type::Number)
+            && func.params.iter().all(|p| !p.is_rest && matches!(p.ty, perry_types::Type::Number))
+    }
+
+    /// Define the function built in `self.ctx` for the baseline and for x86-64-v3, and
+    /// make `func_id` a stub calling the version its dispatch slot points at. The slot
+    /// starts at the baseline version; the module's init moves it to the x86-64-v3 one
+    /// when the CPU has the features (see `perry_runtime::cpu`).
+    fn define_multiversioned(&mut self, func: &Function, func_id: cranelift_module::FuncId) -> Result<()> {
+        let sig = self.ctx.func.signature.clone();
+        let baseline_name = self.local_symbol(SymbolKind::BaselineFunction, vec![func.name.clone()]);
+        let baseline_id = self.module.declare_function(&baseline_name, self.definition_linkage(Linkage::Local), &sig)?;
+        let variant_name = self.local_symbol(SymbolKind::CpuVariant, vec![func.name.clone()]);
+        let variant_id = self.module.declare_function(&variant_name, self.definition_linkage(Linkage::Local), &sig)?;
+
+        // Recursive calls stay in their version rather than going through the slot
+        let mut variant_func = self.ctx.func.clone();
+        retarget_calls(&mut self.ctx.func, func_id, baseline_id);
+        retarget_calls(&mut variant_func, func_id, variant_id);
+
+        let source = self.debug_function.clone();
+        self.define_function(baseline_id)?;
+        self.module.clear_context(&mut self.ctx);
+
+        self.ctx.func = variant_func;
+        let variant_isa = self.cpu_variant_isa.clone()
+            .ok_or_else(|| anyhow!("No x86-64-v3 ISA for {}", func.name))?;
+        if self.debug_info.is_none() {
+            self.queue_function(variant_id, Some(variant_isa))?;
+        } else {
+            let alignment = self.ctx.compile(&*variant_isa, &mut ControlPlane::default())
+                .map_err(cranelift_module::ModuleError::from)?
+                .buffer.alignment as u64;
+            let compiled = self.ctx.compiled_code().expect("function was just compiled");
+            self.module.define_function_bytes(variant_id, &self.ctx.func, alignment, compiled.code_buffer(), compiled.buffer.relocs())?;
+            if let Some(debug_info) = self.debug_info.as_mut() {
+                debug_info.add_function(variant_id, &variant_name, source, &self.ctx, &*variant_isa);
+            }
+        }
+        self.module.clear_context(&mut self.ctx);
+
+        // The slot holds the address of the version to run
+        let slot_id = self.module.declare_anonymous_data(true, false)?;
+        let mut slot = DataDescription::new();
+        slot.define_zeroinit(8);
+        slot.set_align(8);
+        let baseline_ref = self.module.declare_func_in_data(baseline_id, &mut slot);
+        slot.write_function_addr(0, baseline_ref);
+        self.module.define_data(slot_id, &slot)?;
+        self.cpu_variant_slots.push((slot_id, variant_id));
+
+        self.ctx.func.signature = sig.clone();
+        {
+            let mut builder = FunctionBuilder::new(&mut self.ctx.func, &mut self.func_ctx);
+            let entry_block = builder.create_block();
+            builder.append_block_params_for_function_params(entry_block);
+            builder.switch_to_block(entry_block);
+            builder.seal_block(entry_block);
+
+            let args = builder.block_params(entry_block).to_vec();
+            let slot_gv = self.module.declare_data_in_func(slot_id, builder.func);
+            let slot_addr = builder.ins().global_value(types::I64, slot_gv);
+            let callee = builder.ins().load(types::I64, MemFlags::trusted(), slot_addr, 0);
+            let sig_ref = builder.import_signature(sig);
+            let call = builder.ins().call_indirect(sig_ref, callee, &args);
+            let results = builder.inst_results(call).to_vec();
+            builder.ins().return_(&results);
+            builder.finalize();
+        }
+        self.define_function(func_id)?;
+        Ok(())
+    }
+
+    /// Define the function built in `self.ctx` as the uncached body of a memoized function
+    /// (named by `path`), and make `func_id` a wrapper that returns a cached result when
+    /// the arguments were seen before (see `perry_runtime::memoize`). Bit i of
+    /// `string_args` marks parameter i as a raw string pointer, compared by content.
+    fn define_memoized(&mut self, path: Vec<String>, func_id: cranelift_module::FuncId, memoize: Memoize, string_args: u64) -> Result<()> {
+        let sig = self.ctx.func.signature.clone();
+        if sig.returns.len() != 1 {
+            return Ok(self.define_function(func_id)?);
+        }
+        let body_name = self.local_symbol(SymbolKind::MemoizedBody, path);
+        let body_id = self.module.declare_function(&body_name, self.definition_linkage(Linkage::Local), &sig)?;
+        self.define_function(body_id)?;
+        self.module.clear_context(&mut self.ctx);
+
+        // The slot holds the function's cache once it is first called
+        let slot_id = self.module.declare_anonymous_data(true, false)?;
+        let mut slot = DataDescription::new();
+        slot.define_zeroinit(8);
+        slot.set_align(8);
+        self.module.define_data(slot_id, &slot)?;
+
+        let lookup_id = *self.extern_funcs.get("js_memo_lookup")
+            .ok_or_else(|| anyhow!("js_memo_lookup not declared"))?;
+        let store_id = *self.extern_funcs.get("js_memo_store")
+            .ok_or_else(|| anyhow!("js_memo_store not declared"))?;
+
+        self.ctx.func.signature = sig.clone();
+        {
+            let mut builder = FunctionBuilder::new(&mut self.ctx.func, &mut self.func_ctx);
+            let entry_block = builder.create_block();
+            builder.append_block_params_for_function_params(entry_block);
+            builder.switch_to_block(entry_block);
+            builder.seal_block(entry_block);
+
+            // The arguments' bits, as the cache key
+            let params = builder.block_params(entry_block).to_vec();
+            let args_slot = builder.create_sized_stack_slot(StackSlotData::new(
+                StackSlotKind::ExplicitSlot,
+                (params.len().max(1) * 8) as u32,
+                3,
+            ));
+            for (i, &param) in params.iter().enumerate() {
+                let bits = if builder.func.dfg.value_type(param) == types::F64 {
+                    builder.ins().bitcast(types::I64, MemFlags::new(), param)
+                } else {
+                    param
+                };
+                builder.ins().stack_store(bits, args_slot, (i * 8) as i32);
+            }
+            let out_slot = builder.create_sized_stack_slot(StackSlotData::new(StackSlotKind::ExplicitSlot, 8, 3));
+
+            let slot_gv = self.module.declare_data_in_func(slot_id, builder.func);
+            let slot_addr = builder.ins().global_value(types::I64, slot_gv);
+            let args_addr = builder.ins().stack_addr(types::I64, args_slot, 0);
+            let out_addr = builder.ins().stack_addr(types::I64, out_slot, 0);
+            let max_entries = builder.ins().iconst(types::I32, memoize.max_entries as i64);
+            let ttl_ms = builder.ins().iconst(types::I64, memoize.ttl_ms as i64);
+            let argc = builder.ins().iconst(types::I32, params.len() as i64);
+            let string_mask = builder.ins().iconst(types::I64, string_args as i64);
+            let lookup_ref = self.module.declare_func_in_func(lookup_id, builder.func);
+            let call = builder.ins().call(lookup_ref, &[slot_addr, max_entries, ttl_ms, args_addr, argc, string_mask, out_addr]);
+            let hit = builder.inst_results(call)[0];
+
+            let hit_block = builder.create_block();
+            let miss_block = builder.create_block();
+            builder.ins().brif(hit, hit_block, &[], miss_block, &[]);
+
+            let return_type = sig.returns[0].value_type;
+            builder.switch_to_block(hit_block);
+            builder.seal_block(hit_block);
+            let cached = builder.ins().stack_load(types::I64, out_slot, 0);
+            let cached = if return_type == types::F64 {
+                builder.ins().bitcast(types::F64, MemFlags::new(), cached)
+            } else {
+                cached
+            };
+            builder.ins().return_(&[cached]);
+
+            builder.switch_to_block(miss_block);
+            builder.seal_block(miss_block);
+            let body_ref = self.module.declare_func_in_func(body_id, builder.func);
+            let call = builder.ins().call(body_ref, &params);
+            let result = builder.inst_results(call)[0];
+            let result_bits = if return_type == types::F64 {
+                builder.ins().bitcast(types::I64, MemFlags::new(), result)
+            } else {
+                result
+            };
+            let store_ref = self.module.declare_func_in_func(store_id, builder.func);
+            builder.ins().call(store_ref, &[slot_addr, args_addr, argc, string_mask, result_bits]);
+            builder.ins().return_(&[result]);
+            builder.finalize();
+        }
+        self.define_function(func_id)?;
+        Ok(())
+    }
+
     fn compile_function(&mut self, func: &Function) -> Result<()> {
         // Track current function for self-recursive call optimization
         CURRENT_FUNC_HIR_ID.with(|c| c.set(Some(func.id)));
 
-        let result = if Self::is_integer_only_function(func) && func.params.len() <= 4 {
+        let result = if Self::is_integer_only_function(func) && func.params.len() <= 4 && func.memoize().is_none() {
             self.compile_integer_specialized_function(func)
         } else {
             self.compile_function_inner(func)
         };
 
         CURRENT_FUNC_HIR_ID.with(|c| c.set(None));
-        result
+        // Point compile errors at the function's source
+        result.map_err(|e| match source_location(func.span) {
+            Some(location) => anyhow!("{} (in {} at {})", e, func.name, location),
+            None => e,
+        })
     }
 
     fn compile_function_inner(&mut self, func: &Function) -> Result<()> {
@@ -9354,6 +11082,21 @@ impl Compiler {
         // Collect all variables that will be mutably captured by closures (before borrowing self.ctx)
         let boxed_vars = self.collect_mutable_captures_from_stmts(&func.body);
 
+        let crash_frame = if self.crash_reporting {
+            let name = if func.name.is_empty() { "<anonymous>" } else { func.name.as_str() };
+            let frame_name = match source_location(func.span) {
+                Some(location) => format!("{} ({})", name, location),
+                None => name.to_string(),
+            };
+            Some((self.crash_frame_name_data(&frame_name)?, frame_name.len()))
+        } else {
+            None
+        };
+
+        if self.debug_info.is_some() {
+            self.ctx.func.dfg.collect_debug_info();
+        }
+
         {
             // Build the function
             let mut builder = FunctionBuilder::new(&mut self.ctx.func, &mut self.func_ctx);
@@ -9362,6 +11105,20 @@ impl Compiler {
             builder.append_block_params_for_function_params(entry_block);
             builder.switch_to_block(entry_block);
             builder.seal_block(entry_block);
+            if let Some(loc) = debuginfo::srcloc(func.span) {
+                builder.set_srcloc(loc);
+            }
+
+            // Record this function on the crash shadow stack
+            if let Some((name_data_id, name_len)) = crash_frame {
+                let enter_id = self.extern_funcs.get("js_crash_frame_enter")
+                    .ok_or_else(|| anyhow!("js_crash_frame_enter not declared"))?;
+                let enter_ref = self.module.declare_func_in_func(*enter_id, builder.func);
+                let name_gv = self.module.declare_data_in_func(name_data_id, builder.func);
+                let name_ptr = builder.ins().symbol_value(types::I64, name_gv);
+                let name_len_val = builder.ins().iconst(types::I64, name_len as i64);
+                builder.ins().call(enter_ref, &[name_ptr, name_len_val]);
+            }
 
             // Create variables for parameters using sequential indices (0, 1, 2, ...)
             let mut locals: HashMap<LocalId, LocalInfo> = HashMap::new();
@@ -9370,19 +11127,20 @@ impl Compiler {
                 let abi_type = param_abi_types[i];
                 builder.declare_var(var, abi_type);
                 let val = builder.block_params(entry_block)[i];
-                builder.def_var(var, val);
+                builder.def_local(var, val);
                 // Determine local info flags based on type
-                let is_string = matches!(param.ty, perry_types::Type::String);
+                let is_string = param.ty.is_string();
                 let is_array = matches!(&param.ty, perry_types::Type::Array(_));
                 let is_closure = matches!(param.ty, perry_types::Type::Function(_));
                 let is_bigint = matches!(param.ty, perry_types::Type::BigInt);
                 let is_pointer = abi_type == types::I64;
                 // Named types (interfaces) and Object types may contain NaN-boxed values
                 // when accessed via PropertyGet, so treat them as potentially union
-                let is_union = matches!(param.ty,
+                let is_union = !is_string && matches!(param.ty,
                     perry_types::Type::Union(_) |
                     perry_types::Type::Named(_) |
                     perry_types::Type::Object(_) |
+                    perry_types::Type::Intersection(_) |
                     perry_types::Type::Any);
                 // Check if array has mixed element types (union or any)
                 let is_mixed_array = if let perry_types::Type::Array(elem_ty) = &param.ty {
@@ -9410,7 +11168,7 @@ impl Compiler {
                     bounded_by_array: None,
                     bounded_by_constant: None,
                     scalar_fields: None,
-                    squared_cache: None, product_cache: None, cached_array_ptr: None,
+                    squared_cache: None, product_cache: None, cached_array_ptr: None, union_classes: object_union_classes(&param.ty, &self.classes),
                 });
             }
 
@@ -9446,7 +11204,7 @@ impl Compiler {
                         bounded_by_array: None,
                         bounded_by_constant: None,
                         scalar_fields: None,
-                        squared_cache: None, product_cache: None, cached_array_ptr: None,
+                        squared_cache: None, product_cache: None, cached_array_ptr: None, union_classes: Vec::new(),
                     })
                 };
 
@@ -9465,7 +11223,7 @@ impl Compiler {
                 let global_val = self.module.declare_data_in_func(*data_id, builder.func);
                 let ptr = builder.ins().global_value(types::I64, global_val);
                 let val = builder.ins().load(var_type, MemFlags::new(), ptr, 0);
-                builder.def_var(var, val);
+                builder.def_local(var, val);
 
                 let mut info = local_info_template;
                 info.var = var;
@@ -9484,7 +11242,7 @@ impl Compiler {
                 let func_ref = self.module.declare_func_in_func(*promise_new, builder.func);
                 let call = builder.ins().call(func_ref, &[]);
                 let promise_ptr = builder.inst_results(call)[0];
-                builder.def_var(var, promise_ptr);
+                builder.def_local(var, promise_ptr);
 
                 Some(var)
             } else {
@@ -9536,11 +11294,33 @@ impl Compiler {
                 }
             }
 
+            // Named parameters and locals, leaving out the copies of module-level variables
+            if self.debug_info.is_some() {
+                let variables = locals.iter()
+                    .filter(|(id, _)| !self.module_var_data_ids.contains_key(*id))
+                    .filter_map(|(id, info)| {
+                        let name = info.name.clone()?;
+                        Some((info.var.as_u32(), name, func.params.iter().any(|param| param.id == *id)))
+                    })
+                    .collect();
+                self.debug_function = Some(FunctionSource { name: func.name.clone(), span: func.span, variables });
+            }
             builder.finalize();
         }
 
-        // Compile and define the function
-        if let Err(e) = self.module.define_function(func_id, &mut self.ctx) {
+        // Compile and define the function, behind its cache when memoized and once per
+        // CPU level when multiversioned
+        let defined = if let Some(memoize) = func.memoize() {
+            let string_args = func.params.iter().enumerate()
+                .filter(|(i, p)| *i < 64 && p.ty.is_string() && self.type_to_abi(&p.ty) == types::I64)
+                .fold(0u64, |mask, (i, _)| mask | 1 << i);
+            self.define_memoized(vec![func.name.clone()], func_id, memoize, string_args)
+        } else if self.cpu_variant_isa.is_some() && Self::is_numeric_function(func) {
+            self.define_multiversioned(func, func_id)
+        } else {
+            self.define_function(func_id).map_err(anyhow::Error::from)
+        };
+        if let Err(e) = defined {
             // Print detailed error info
             eprintln!("=== VERIFIER ERROR in function '{}' ===", func.name);
             eprintln!("Error: {}", e);
@@ -9553,20 +11333,20 @@ impl Compiler {
     }
 
     /// Compile an integer-only function with i64 specialization for better performance.
-    /// Creates a `{name}_i64` inner function using integer instructions (icmp/iadd/isub)
+    /// Creates an i64 inner function (kind `N` in `symbols`) using integer instructions (icmp/iadd/isub)
     /// and makes the original function a thin wrapper that converts f64 <-> i64.
     fn compile_integer_specialized_function(&mut self, func: &Function) -> Result<()> {
         let orig_func_id = *self.func_ids.get(&func.id)
             .ok_or_else(|| anyhow!("Function not declared: {}", func.name))?;
 
         // Step 1: Declare the i64 specialized function
-        let i64_name = format!("{}_i64", func.name);
+        let i64_name = self.local_symbol(SymbolKind::IntegerFunction, vec![func.name.clone()]);
         let mut i64_sig = self.module.make_signature();
         for _ in &func.params {
             i64_sig.params.push(AbiParam::new(types::I64));
         }
         i64_sig.returns.push(AbiParam::new(types::I64));
-        let i64_func_id = self.module.declare_function(&i64_name, Linkage::Local, &i64_sig)?;
+        let i64_func_id = self.module.declare_function(&i64_name, self.definition_linkage(Linkage::Local), &i64_sig)?;
 
         // Step 2: Compile the i64 specialized function body
         self.ctx.func.signature = i64_sig.clone();
@@ -9583,7 +11363,7 @@ impl Compiler {
                 let var = Variable::new(i);
                 builder.declare_var(var, types::I64);
                 let val = builder.block_params(entry_block)[i];
-                builder.def_var(var, val);
+                builder.def_local(var, val);
                 param_vars.insert(param.id, var);
             }
 
@@ -9603,7 +11383,7 @@ impl Compiler {
             builder.finalize();
         }
 
-        if let Err(e) = self.module.define_function(i64_func_id, &mut self.ctx) {
+        if let Err(e) = self.define_function(i64_func_id) {
             eprintln!("=== VERIFIER ERROR in i64-specialized function '{}' ===", i64_name);
             eprintln!("Error: {}", e);
             return Err(anyhow!("Error compiling i64-specialized function '{}': {}", i64_name, e));
@@ -9646,7 +11426,7 @@ impl Compiler {
             builder.finalize();
         }
 
-        if let Err(e) = self.module.define_function(orig_func_id, &mut self.ctx) {
+        if let Err(e) = self.define_function(orig_func_id) {
             eprintln!("=== VERIFIER ERROR in wrapper for '{}' ===", func.name);
             eprintln!("Error: {}", e);
             return Err(anyhow!("Error compiling wrapper for '{}': {}", func.name, e));
@@ -9706,7 +11486,7 @@ impl Compiler {
                 } else {
                     builder.ins().iconst(types::I64, 0)
                 };
-                builder.def_var(var, val);
+                builder.def_local(var, val);
                 vars.insert(*id, var);
             }
             Stmt::If { condition, then_branch, else_branch } => {
@@ -10146,6 +11926,9 @@ impl Compiler {
             Expr::InstanceOf { expr, .. } => {
                 self.collect_closures_from_expr(expr, closures, enclosing_class);
             }
+            Expr::Narrowed { value, .. } => {
+                self.collect_closures_from_expr(value, closures, enclosing_class);
+            }
             Expr::In { property, object } => {
                 self.collect_closures_from_expr(property, closures, enclosing_class);
                 self.collect_closures_from_expr(object, closures, enclosing_class);
@@ -10210,13 +11993,16 @@ impl Compiler {
                 self.collect_closures_from_expr(index, closures, enclosing_class);
             }
             // Additional expressions that may contain closures
-            Expr::FetchWithOptions { url, method, body, headers } => {
+            Expr::FetchWithOptions { url, method, body, headers, on_upload_progress, .. } => {
                 self.collect_closures_from_expr(url, closures, enclosing_class);
                 self.collect_closures_from_expr(method, closures, enclosing_class);
                 self.collect_closures_from_expr(body, closures, enclosing_class);
                 for (_, header_val) in headers {
                     self.collect_closures_from_expr(header_val, closures, enclosing_class);
                 }
+                if let Some(cb) = on_upload_progress {
+                    self.collect_closures_from_expr(cb, closures, enclosing_class);
+                }
             }
             Expr::MathMin(args) | Expr::MathMax(args) => {
                 for arg in args {
@@ -10336,7 +12122,7 @@ impl Compiler {
                 self.collect_closures_from_expr(value, closures, enclosing_class);
             }
             // File system operations
-            Expr::FsReadFileSync(path) | Expr::FsExistsSync(path) | Expr::FsMkdirSync(path) | Expr::FsUnlinkSync(path) => {
+            Expr::FsReadFileSync(path) | Expr::FsExistsSync(path) | Expr::FsMkdirSync(path) | Expr::FsUnlinkSync(path) | Expr::FsCreateReadStream(path) => {
                 self.collect_closures_from_expr(path, closures, enclosing_class);
             }
             // Dynamic environment variable access
@@ -10355,8 +12141,45 @@ impl Compiler {
             Expr::PathDirname(path) | Expr::PathBasename(path) | Expr::PathExtname(path) | Expr::PathResolve(path) => {
                 self.collect_closures_from_expr(path, closures, enclosing_class);
             }
+            Expr::ProcessSetMemoryLimit { limit, policy } => {
+                self.collect_closures_from_expr(limit, closures, enclosing_class);
+                if let Some(p) = policy {
+                    self.collect_closures_from_expr(p, closures, enclosing_class);
+                }
+            }
+            Expr::ProcessOnLowMemory(cb) => {
+                self.collect_closures_from_expr(cb, closures, enclosing_class);
+            }
+            Expr::ProcessStdioWrite { data, callback, .. } => {
+                self.collect_closures_from_expr(data, closures, enclosing_class);
+                if let Some(cb) = callback {
+                    self.collect_closures_from_expr(cb, closures, enclosing_class);
+                }
+            }
+            Expr::ProcessStdinOn { event, listener } => {
+                self.collect_closures_from_expr(event, closures, enclosing_class);
+                self.collect_closures_from_expr(listener, closures, enclosing_class);
+            }
+            Expr::DisposeResource { resource, .. } => {
+                self.collect_closures_from_expr(resource, closures, enclosing_class);
+            }
+            Expr::DisposableStackAdopt { stack, value, on_dispose } => {
+                self.collect_closures_from_expr(stack, closures, enclosing_class);
+                self.collect_closures_from_expr(value, closures, enclosing_class);
+                self.collect_closures_from_expr(on_dispose, closures, enclosing_class);
+            }
+            Expr::DisposableStackDefer { stack, on_dispose } => {
+                self.collect_closures_from_expr(stack, closures, enclosing_class);
+                self.collect_closures_from_expr(on_dispose, closures, enclosing_class);
+            }
+            Expr::DisposableStackDispose(stack) | Expr::DisposableStackMove(stack) | Expr::DisposableStackDisposed(stack) => {
+                self.collect_closures_from_expr(stack, closures, enclosing_class);
+            }
+            Expr::ImportMetaResolve { specifier, .. } => {
+                self.collect_closures_from_expr(specifier, closures, enclosing_class);
+            }
             // JSON operations
-            Expr::JsonParse(expr) | Expr::JsonStringify(expr) => {
+            Expr::JsonParse(expr) | Expr::JsonStringify(expr) | Expr::JsonCanonicalize(expr) => {
                 self.collect_closures_from_expr(expr, closures, enclosing_class);
             }
             // Math operations
@@ -10428,10 +12251,11 @@ impl Compiler {
                 self.collect_closures_from_expr(index, closures, enclosing_class);
             }
             // Map/Set size and clear operations
-            Expr::MapSize(map) | Expr::MapClear(map) => {
+            Expr::MapSize(map) | Expr::MapClear(map) | Expr::MapKeys(map) |
+            Expr::MapValues(map) | Expr::MapEntries(map) => {
                 self.collect_closures_from_expr(map, closures, enclosing_class);
             }
-            Expr::SetSize(set) | Expr::SetClear(set) => {
+            Expr::SetSize(set) | Expr::SetClear(set) | Expr::SetValues(set) => {
                 self.collect_closures_from_expr(set, closures, enclosing_class);
             }
             // Date operations
@@ -10444,7 +12268,7 @@ impl Compiler {
                 self.collect_closures_from_expr(date, closures, enclosing_class);
             }
             // Error operations
-            Expr::ErrorNew(Some(msg)) => {
+            Expr::ErrorNew { message: Some(msg), .. } => {
                 self.collect_closures_from_expr(msg, closures, enclosing_class);
             }
             Expr::ErrorMessage(err) => {
@@ -10493,16 +12317,18 @@ impl Compiler {
             Expr::NativeModuleRef(_) | Expr::StaticFieldGet { .. } | Expr::This |
             Expr::EnumMember { .. } | Expr::ClassRef(_) | Expr::EnvGet(_) |
             Expr::ProcessUptime | Expr::ProcessCwd | Expr::ProcessArgv | Expr::ProcessMemoryUsage |
+            Expr::ProcessStdinRead | Expr::ProcessStdinPipe(_) |
             Expr::MathRandom | Expr::CryptoRandomUUID |
             Expr::OsPlatform | Expr::OsArch | Expr::OsHostname | Expr::OsHomedir |
             Expr::OsTmpdir | Expr::OsTotalmem | Expr::OsFreemem | Expr::OsUptime |
             Expr::OsType | Expr::OsRelease | Expr::OsCpus | Expr::OsNetworkInterfaces |
             Expr::OsUserInfo | Expr::OsEOL |
-            Expr::MapNew | Expr::SetNew | Expr::DateNow |
+            Expr::MapNew | Expr::SetNew | Expr::DisposableStackNew | Expr::DateNow |
             Expr::ArrayPop(_) | Expr::ArrayShift(_) |
-            Expr::Uint8ArrayNew(None) | Expr::DateNew(None) | Expr::ErrorNew(None) |
+            Expr::Uint8ArrayNew(None) | Expr::DateNew(None) | Expr::ErrorNew { message: None, .. } |
             Expr::UrlSearchParamsNew(None) |
-            Expr::RegExp { .. } | Expr::JsLoadModule { .. } | Expr::ImportMetaUrl(_) => {
+            Expr::RegExp { .. } | Expr::JsLoadModule { .. } | Expr::NativeAddonLoad { .. } |
+            Expr::ImportMetaRuntime { .. } => {
                 // No inner expressions to traverse
             }
         }
@@ -10630,6 +12456,22 @@ impl Compiler {
                 self.collect_mutable_captures_from_expr(then_expr, captures);
                 self.collect_mutable_captures_from_expr(else_expr, captures);
             }
+            Expr::ProcessOnLowMemory(callback) => {
+                self.collect_mutable_captures_from_expr(callback, captures);
+            }
+            Expr::ProcessStdioWrite { data, callback, .. } => {
+                self.collect_mutable_captures_from_expr(data, captures);
+                if let Some(callback) = callback {
+                    self.collect_mutable_captures_from_expr(callback, captures);
+                }
+            }
+            Expr::ProcessStdinOn { event, listener } => {
+                self.collect_mutable_captures_from_expr(event, captures);
+                self.collect_mutable_captures_from_expr(listener, captures);
+            }
+            Expr::DisposableStackAdopt { on_dispose: callback, .. } | Expr::DisposableStackDefer { on_dispose: callback, .. } => {
+                self.collect_mutable_captures_from_expr(callback, captures);
+            }
             Expr::ArrayForEach { array, callback } => {
                 self.collect_mutable_captures_from_expr(array, captures);
                 self.collect_mutable_captures_from_expr(callback, captures);
@@ -10847,6 +12689,33 @@ impl Compiler {
             Expr::Closure { func_id, body, .. } => {
                 self.collect_func_refs_needing_wrappers_from_stmts(body, func_refs);
             }
+            Expr::ProcessOnLowMemory(callback) | Expr::DisposableStackAdopt { on_dispose: callback, .. } | Expr::DisposableStackDefer { on_dispose: callback, .. } => {
+                match callback.as_ref() {
+                    Expr::FuncRef(func_id) => {
+                        func_refs.insert(*func_id);
+                    }
+                    _ => self.collect_func_refs_from_expr(callback, func_refs),
+                }
+            }
+            Expr::ProcessStdioWrite { data, callback, .. } => {
+                self.collect_func_refs_from_expr(data, func_refs);
+                match callback.as_deref() {
+                    Some(Expr::FuncRef(func_id)) => {
+                        func_refs.insert(*func_id);
+                    }
+                    Some(callback) => self.collect_func_refs_from_expr(callback, func_refs),
+                    None => {}
+                }
+            }
+            Expr::ProcessStdinOn { event, listener } => {
+                self.collect_func_refs_from_expr(event, func_refs);
+                match listener.as_ref() {
+                    Expr::FuncRef(func_id) => {
+                        func_refs.insert(*func_id);
+                    }
+                    _ => self.collect_func_refs_from_expr(listener, func_refs),
+                }
+            }
             Expr::ArrayForEach { array, callback } | Expr::ArrayMap { array, callback } | Expr::ArrayFilter { array, callback } | Expr::ArrayFind { array, callback } | Expr::ArrayFindIndex { array, callback } => {
                 self.collect_func_refs_from_expr(array, func_refs);
                 match callback.as_ref() {
@@ -10891,8 +12760,10 @@ impl Compiler {
             self.async_func_ids.insert(func_id);
         }
 
-        let func_name = format!("__closure_{}", func_id);
-        let clif_func_id = self.module.declare_function(&func_name, Linkage::Local, &sig)?;
+        let mut path = self.closure_owners.get(&func_id).cloned().unwrap_or_default();
+        path.push(func_id.to_string());
+        let func_name = self.local_symbol(SymbolKind::Closure, path);
+        let clif_func_id = self.module.declare_function(&func_name, self.definition_linkage(Linkage::Local), &sig)?;
         self.closure_func_ids.insert(func_id, clif_func_id);
 
         Ok(())
@@ -10946,7 +12817,7 @@ impl Compiler {
             let closure_ptr_var = Variable::new(0);
             builder.declare_var(closure_ptr_var, types::I64);
             let closure_ptr = builder.block_params(entry_block)[0];
-            builder.def_var(closure_ptr_var, closure_ptr);
+            builder.def_local(closure_ptr_var, closure_ptr);
 
             // Create variables for regular parameters
             let mut locals: HashMap<LocalId, LocalInfo> = HashMap::new();
@@ -10957,13 +12828,15 @@ impl Compiler {
                 next_var += 1;
                 // Check parameter type to set appropriate LocalInfo flags
                 let is_closure = matches!(param.ty, perry_types::Type::Function(_));
-                let is_string = matches!(param.ty, perry_types::Type::String);
+                let is_string = param.ty.is_string();
                 let is_array = matches!(param.ty, perry_types::Type::Array(_));
                 // Any/Unknown are union types - they could be numbers, strings, objects, etc.
                 // Don't treat them as pointers since we can't extract pointer from plain numbers
                 let is_union_type = matches!(param.ty, perry_types::Type::Any | perry_types::Type::Unknown);
-                let is_pointer = is_closure || is_string || is_array ||
-                    matches!(param.ty, perry_types::Type::Object(_) | perry_types::Type::Named(_));
+                // Class-typed params hold the unboxed instance pointer, so methods can be called directly
+                let class_name = instance_class(&param.ty, &self.classes);
+                let is_pointer = is_closure || is_string || is_array || class_name.is_some() ||
+                    matches!(param.ty, perry_types::Type::Object(_) | perry_types::Type::Intersection(_) | perry_types::Type::Named(_));
                 // Use i64 for known pointer types, f64 for numbers and union types
                 let var_type = if is_pointer && !is_union_type { types::I64 } else { types::F64 };
                 builder.declare_var(var, var_type);
@@ -10980,11 +12853,11 @@ impl Compiler {
                 } else {
                     val
                 };
-                builder.def_var(var, final_val);
+                builder.def_local(var, final_val);
                 locals.insert(param.id, LocalInfo {
                     var,
                     name: Some(param.name.clone()),
-                    class_name: None,
+                    class_name,
                     type_args: Vec::new(),
                     is_pointer: is_pointer && !is_union_type,
                     is_array,
@@ -11001,7 +12874,7 @@ impl Compiler {
                     bounded_by_array: None,
                     bounded_by_constant: None,
                     scalar_fields: None,
-                    squared_cache: None, product_cache: None, cached_array_ptr: None,
+                    squared_cache: None, product_cache: None, cached_array_ptr: None, union_classes: object_union_classes(&param.ty, &self.classes),
                 });
             }
 
@@ -11030,7 +12903,7 @@ impl Compiler {
                 let var = Variable::new(next_var);
                 next_var += 1;
                 builder.declare_var(var, types::I64);
-                builder.def_var(var, this_ptr);
+                builder.def_local(var, this_ptr);
 
                 Some(var)
             } else {
@@ -11053,7 +12926,7 @@ impl Compiler {
                     next_var += 1;
                     builder.declare_var(var, types::I64);
                     let box_ptr = builder.ins().bitcast(types::I64, MemFlags::new(), val_f64);
-                    builder.def_var(var, box_ptr);
+                    builder.def_local(var, box_ptr);
 
                     locals.insert(*capture_id, LocalInfo {
                         var,
@@ -11075,14 +12948,14 @@ impl Compiler {
                         bounded_by_array: None,
                         bounded_by_constant: None,
                         scalar_fields: None,
-                        squared_cache: None, product_cache: None, cached_array_ptr: None,
+                        squared_cache: None, product_cache: None, cached_array_ptr: None, union_classes: Vec::new(),
                     });
                 } else {
                     // For immutable captures, store the value directly
                     let var = Variable::new(next_var);
                     next_var += 1;
                     builder.declare_var(var, types::F64);
-                    builder.def_var(var, val_f64);
+                    builder.def_local(var, val_f64);
 
                     locals.insert(*capture_id, LocalInfo {
                         var,
@@ -11104,7 +12977,7 @@ impl Compiler {
                         bounded_by_array: None,
                         bounded_by_constant: None,
                         scalar_fields: None,
-                        squared_cache: None, product_cache: None, cached_array_ptr: None,
+                        squared_cache: None, product_cache: None, cached_array_ptr: None, union_classes: Vec::new(),
                     });
                 }
             }
@@ -11144,7 +13017,7 @@ impl Compiler {
                         bounded_by_array: None,
                         bounded_by_constant: None,
                         scalar_fields: None,
-                        squared_cache: None, product_cache: None, cached_array_ptr: None,
+                        squared_cache: None, product_cache: None, cached_array_ptr: None, union_classes: Vec::new(),
                     })
                 };
 
@@ -11161,7 +13034,7 @@ impl Compiler {
                 let global_val = self.module.declare_data_in_func(*data_id, builder.func);
                 let ptr = builder.ins().global_value(types::I64, global_val);
                 let val = builder.ins().load(var_type, MemFlags::new(), ptr, 0);
-                builder.def_var(var, val);
+                builder.def_local(var, val);
 
                 // Insert into locals so LocalGet can find it
                 let mut info = local_info_template;
@@ -11190,7 +13063,7 @@ impl Compiler {
                 let func_ref = self.module.declare_func_in_func(*promise_new, builder.func);
                 let call = builder.ins().call(func_ref, &[]);
                 let promise_ptr = builder.inst_results(call)[0];
-                builder.def_var(var, promise_ptr);
+                builder.def_local(var, promise_ptr);
 
                 Some(var)
             } else {
@@ -11291,7 +13164,7 @@ impl Compiler {
             builder.finalize();
         }
 
-        if let Err(e) = self.module.define_function(clif_func_id, &mut self.ctx) {
+        if let Err(e) = self.define_function(clif_func_id) {
             eprintln!("=== VERIFIER ERROR in closure_{} ({} params) ===", func_id, params.len());
             eprintln!("Error: {}", e);
             eprintln!("Debug: {:?}", e);
@@ -11337,10 +13210,10 @@ impl Compiler {
         // Always use Export linkage for wrappers since they are meant to be called cross-module.
         // Even if func.is_exported is false, the function might be exported via `export { func }`
         // which is handled separately, and the wrapper still needs to be visible to the linker.
-        let wrapper_id = self.module.declare_function(&wrapper_name, Linkage::Export, &sig)?;
+        let wrapper_id = self.module.declare_function(&wrapper_name, self.definition_linkage(Linkage::Export), &sig)?;
         // Track whether we need to NaN-box the return value (always needed since we return f64)
         let needs_return_boxing = original_return_abi == types::I64;
-        let is_string_return = matches!(func.return_type, perry_types::Type::String);
+        let is_string_return = func.return_type.is_string();
 
         // Pre-compute expected types for each parameter before borrowing self.ctx
         let param_expected_types: Vec<types::Type> = func.params.iter()
@@ -11434,7 +13307,7 @@ impl Compiler {
                 if expected_type == types::I64 {
                     final_call_args.push(builder.ins().iconst(types::I64, 0));
                 } else {
-                    final_call_args.push(builder.ins().f64const(f64::NAN));
+                    final_call_args.push(missing_arg(&mut builder));
                 }
             }
             final_call_args.truncate(expected_param_count);
@@ -11459,7 +13332,7 @@ impl Compiler {
             builder.finalize();
         }
 
-        if let Err(e) = self.module.define_function(wrapper_id, &mut self.ctx) {
+        if let Err(e) = self.define_function(wrapper_id) {
             eprintln!("=== VERIFIER ERROR in wrapper '{}' ===", func.name);
             eprintln!("Error: {}", e);
             return Err(anyhow!("Error compiling wrapper '{}': {}", func.name, e));
@@ -11478,123 +13351,131 @@ impl Compiler {
     /// 1. Load the closure from the `__export_fn` global
     /// 2. Call the closure with the provided arguments
     /// 3. Return the result
-    fn generate_exported_closure_wrappers(&mut self, init_stmts: &[Stmt], exported_objects: &[String]) -> Result<()> {
+    fn generate_exported_closure_wrappers(&mut self, init_stmts: &[Stmt], exports: &[Export], exported_objects: &[String]) -> Result<()> {
         use perry_hir::ir::{Stmt, Expr};
 
         let exported_set: std::collections::HashSet<&String> = exported_objects.iter().collect();
 
-        // Scan init statements for exported closures
+        // Scan init statements for exported closures; a variable can be exported under
+        // names other than its own (`export { f as g }`, CommonJS `exports.g = ...`)
         for stmt in init_stmts {
-            if let Stmt::Let { name, init: Some(init_expr), .. } = stmt {
-                if !exported_set.contains(name) {
-                    continue;
-                }
-
-                // Check if the initializer is a Closure
-                let (params, is_async) = match init_expr {
-                    Expr::Closure { params, is_async, .. } => {
-                        (params.clone(), *is_async)
-                    },
-                    _other => {
-                        continue;
-                    }
-                };
-
-                // Generate wrapper for this closure
-                log::debug!("Generating closure wrapper for exported const: {}", name);
-
-                // Build wrapper signature: (closure_ptr, ...args) -> f64
-                let mut sig = self.module.make_signature();
-                sig.params.push(AbiParam::new(types::I64)); // closure_ptr (will be ignored, we load from global)
-                for _ in &params {
-                    sig.params.push(AbiParam::new(types::F64));
-                }
-                sig.returns.push(AbiParam::new(types::F64));
-
-                let wrapper_name = format!("__wrapper_{}", name);
-                let wrapper_id = self.module.declare_function(&wrapper_name, Linkage::Export, &sig)?;
+            if let Stmt::Let { name: local, init: Some(init_expr), .. } = stmt {
+                let mut names: Vec<&String> = exports.iter()
+                    .filter_map(|export| match export {
+                        Export::Named { local: l, exported } if l == local && exported_set.contains(exported) => Some(exported),
+                        _ => None,
+                    })
+                    .collect();
+                if exported_set.contains(local) && !names.contains(&local) {
+                    names.push(local);
+                }
+                for name in names {
+                    // Check if the initializer is a Closure
+                    let (params, is_async) = match init_expr {
+                        Expr::Closure { params, is_async, .. } => {
+                            (params.clone(), *is_async)
+                        },
+                        _other => {
+                            continue;
+                        }
+                    };
 
-                // Get the data ID for the exported global
-                let export_global_name = format!("__export_{}", name);
-                let data_id = match self.exported_object_ids.get(name) {
-                    Some(id) => *id,
-                    None => {
-                        // If not already declared, declare it now
-                        self.module.declare_data(&export_global_name, Linkage::Local, true, false)?
-                    }
-                };
+                    // Generate wrapper for this closure
+                    log::debug!("Generating closure wrapper for exported const: {}", name);
 
-                // Get closure call function based on param count
-                let call_func_name = match params.len() {
-                    0 => "js_closure_call0",
-                    1 => "js_closure_call1",
-                    2 => "js_closure_call2",
-                    3 => "js_closure_call3",
-                    4 => "js_closure_call4",
-                    5 => "js_closure_call5",
-                    6 => "js_closure_call6",
-                    7 => "js_closure_call7",
-                    8 => "js_closure_call8",
-                    _ => {
-                        log::warn!("Exported closure {} has too many params ({}), skipping wrapper", name, params.len());
-                        continue;
+                    // Build wrapper signature: (closure_ptr, ...args) -> f64
+                    let mut sig = self.module.make_signature();
+                    sig.params.push(AbiParam::new(types::I64)); // closure_ptr (will be ignored, we load from global)
+                    for _ in &params {
+                        sig.params.push(AbiParam::new(types::F64));
                     }
-                };
-                let closure_call_id = self.extern_funcs.get(call_func_name)
-                    .copied()
-                    .ok_or_else(|| anyhow!("{} not declared for closure wrapper", call_func_name))?;
-
-                // Compile the wrapper function
-                self.ctx.func.signature = sig.clone();
-                let mut wrapper_func_ctx = FunctionBuilderContext::new();
+                    sig.returns.push(AbiParam::new(types::F64));
 
-                {
-                    let mut builder = FunctionBuilder::new(&mut self.ctx.func, &mut wrapper_func_ctx);
+                    let wrapper_name = format!("__wrapper_{}", name);
+                    let wrapper_id = self.module.declare_function(&wrapper_name, self.definition_linkage(Linkage::Export), &sig)?;
 
-                    let entry_block = builder.create_block();
-                    builder.append_block_params_for_function_params(entry_block);
-                    builder.switch_to_block(entry_block);
-                    builder.seal_block(entry_block);
+                    // Get the data ID for the exported global
+                    let export_global_name = format!("__export_{}", name);
+                    let data_id = match self.exported_object_ids.get(name) {
+                        Some(id) => *id,
+                        None => {
+                            // If not already declared, declare it now
+                            self.module.declare_data(&export_global_name, self.definition_linkage(Linkage::Local), true, false)?
+                        }
+                    };
 
-                    // Get block params (skip closure_ptr at index 0, we load from global instead)
-                    let block_params: Vec<Value> = builder.block_params(entry_block).to_vec();
+                    // Get closure call function based on param count
+                    let call_func_name = match params.len() {
+                        0 => "js_closure_call0",
+                        1 => "js_closure_call1",
+                        2 => "js_closure_call2",
+                        3 => "js_closure_call3",
+                        4 => "js_closure_call4",
+                        5 => "js_closure_call5",
+                        6 => "js_closure_call6",
+                        7 => "js_closure_call7",
+                        8 => "js_closure_call8",
+                        _ => {
+                            log::warn!("Exported closure {} has too many params ({}), skipping wrapper", name, params.len());
+                            continue;
+                        }
+                    };
+                    let closure_call_id = self.extern_funcs.get(call_func_name)
+                        .copied()
+                        .ok_or_else(|| anyhow!("{} not declared for closure wrapper", call_func_name))?;
+
+                    // Compile the wrapper function
+                    self.ctx.func.signature = sig.clone();
+                    let mut wrapper_func_ctx = FunctionBuilderContext::new();
+
+                    {
+                        let mut builder = FunctionBuilder::new(&mut self.ctx.func, &mut wrapper_func_ctx);
+
+                        let entry_block = builder.create_block();
+                        builder.append_block_params_for_function_params(entry_block);
+                        builder.switch_to_block(entry_block);
+                        builder.seal_block(entry_block);
+
+                        // Get block params (skip closure_ptr at index 0, we load from global instead)
+                        let block_params: Vec<Value> = builder.block_params(entry_block).to_vec();
+
+                        // Load the closure from the exported global
+                        let data_gv = self.module.declare_data_in_func(data_id, builder.func);
+                        let data_ptr = builder.ins().global_value(types::I64, data_gv);
+                        // The global stores an f64 (NaN-boxed closure pointer)
+                        let closure_f64 = builder.ins().load(types::F64, MemFlags::new(), data_ptr, 0);
+                        // Convert to i64 for js_closure_call* which expects i64 closure pointer
+                        let closure_ptr = builder.ins().bitcast(types::I64, MemFlags::new(), closure_f64);
+
+                        // Build call args: [closure_ptr, ...args]
+                        let mut call_args = vec![closure_ptr];
+                        for i in 0..params.len() {
+                            call_args.push(block_params[i + 1]); // +1 to skip wrapper's closure_ptr param
+                        }
 
-                    // Load the closure from the exported global
-                    let data_gv = self.module.declare_data_in_func(data_id, builder.func);
-                    let data_ptr = builder.ins().global_value(types::I64, data_gv);
-                    // The global stores an f64 (NaN-boxed closure pointer)
-                    let closure_f64 = builder.ins().load(types::F64, MemFlags::new(), data_ptr, 0);
-                    // Convert to i64 for js_closure_call* which expects i64 closure pointer
-                    let closure_ptr = builder.ins().bitcast(types::I64, MemFlags::new(), closure_f64);
+                        // Call the closure
+                        let closure_call_ref = self.module.declare_func_in_func(closure_call_id, builder.func);
+                        let call = builder.ins().call(closure_call_ref, &call_args);
+                        let result = builder.inst_results(call)[0];
 
-                    // Build call args: [closure_ptr, ...args]
-                    let mut call_args = vec![closure_ptr];
-                    for i in 0..params.len() {
-                        call_args.push(block_params[i + 1]); // +1 to skip wrapper's closure_ptr param
+                        builder.ins().return_(&[result]);
+                        builder.finalize();
                     }
 
-                    // Call the closure
-                    let closure_call_ref = self.module.declare_func_in_func(closure_call_id, builder.func);
-                    let call = builder.ins().call(closure_call_ref, &call_args);
-                    let result = builder.inst_results(call)[0];
-
-                    builder.ins().return_(&[result]);
-                    builder.finalize();
-                }
-
-                if let Err(e) = self.module.define_function(wrapper_id, &mut self.ctx) {
-                    eprintln!("=== VERIFIER ERROR in closure wrapper '{}' ===", name);
-                    eprintln!("Error: {}", e);
-                    return Err(anyhow!("Error compiling closure wrapper '{}': {}", name, e));
+                    if let Err(e) = self.define_function(wrapper_id) {
+                        eprintln!("=== VERIFIER ERROR in closure wrapper '{}' ===", name);
+                        eprintln!("Error: {}", e);
+                        return Err(anyhow!("Error compiling closure wrapper '{}': {}", name, e));
+                    }
+                    self.module.clear_context(&mut self.ctx);
                 }
-                self.module.clear_context(&mut self.ctx);
             }
         }
 
         Ok(())
     }
 
-    fn compile_init(&mut self, module_name: &str, stmts: &[Stmt], exported_native_instances: &[(String, String, String)], exported_objects: &[String], exported_functions: &[(String, u32)]) -> Result<()> {
+    fn compile_init(&mut self, module_name: &str, stmts: &[Stmt], exports: &[Export], exported_native_instances: &[(String, String, String)], exported_objects: &[String], exported_functions: &[(String, u32)]) -> Result<()> {
         // Create main function for init statements (entry module) or module init function (non-entry)
         let mut sig = self.module.make_signature();
         sig.returns.push(AbiParam::new(types::I32)); // returns i32
@@ -11630,20 +13511,35 @@ impl Compiler {
             None
         };
 
-        // Collect exported native instance names for post-processing
-        let exported_native_names: HashSet<String> = exported_native_instances.iter()
-            .map(|(name, _, _)| name.clone())
-            .collect();
-        let exported_object_names: HashSet<String> = exported_objects.iter().cloned().collect();
-        // Combine all exported names
-        let exported_names: HashSet<String> = exported_native_names.iter()
-            .chain(exported_object_names.iter())
-            .cloned()
-            .collect();
+        // Map each exported module-level variable to the globals of the names it is exported
+        // under (`export { local as exported }` can export one variable several times)
+        let export_global_id = |name: &str| {
+            exported_native_instances.iter().any(|(n, _, _)| n == name)
+                .then(|| self.exported_native_instance_ids.get(name))
+                .flatten()
+                .or_else(|| exported_objects.iter().any(|n| n == name).then(|| self.exported_object_ids.get(name)).flatten())
+                .copied()
+        };
+        let mut export_globals: HashMap<String, Vec<cranelift_module::DataId>> = HashMap::new();
+        for export in exports {
+            if let Export::Named { local, exported } = export {
+                if let Some(data_id) = export_global_id(exported) {
+                    let ids = export_globals.entry(local.clone()).or_default();
+                    if !ids.contains(&data_id) {
+                        ids.push(data_id);
+                    }
+                }
+            }
+        }
+        for (name, _, _) in exported_native_instances {
+            if let Some(data_id) = export_global_id(name) {
+                export_globals.entry(name.clone()).or_insert_with(|| vec![data_id]);
+            }
+        }
 
         // Collect exported function info for initializing their globals
-        // Each entry is (func_name, data_id, wrapper_or_func_id)
-        let exported_func_info: Vec<(String, cranelift_module::DataId, cranelift_module::FuncId)> = exported_functions
+        // Each entry is (func_name, data_id, wrapper_or_func_id, arity)
+        let exported_func_info: Vec<(String, cranelift_module::DataId, cranelift_module::FuncId, usize)> = exported_functions
             .iter()
             .filter_map(|(func_name, hir_func_id)| {
                 // Get the data ID for this exported function
@@ -11652,10 +13548,45 @@ impl Compiler {
                 let func_id = self.func_wrapper_ids.get(hir_func_id)
                     .copied()
                     .or_else(|| self.func_ids.get(hir_func_id).copied())?;
-                Some((func_name.clone(), *data_id, func_id))
+                let arity = self.func_param_types.get(hir_func_id).map_or(0, |params| params.len());
+                Some((func_name.clone(), *data_id, func_id, arity))
             })
             .collect();
 
+        // Accessors of this module's classes, registered with the runtime so property access
+        // on values of statically unknown class still reaches `get x()` / `set x(v)`
+        let mut class_accessors: Vec<(u32, String, cranelift_module::FuncId, &'static str)> = Vec::new();
+        for meta in self.classes.values() {
+            for (prop_name, &getter_id) in &meta.getter_ids {
+                class_accessors.push((meta.id, prop_name.clone(), getter_id, "js_register_class_getter"));
+            }
+            for (prop_name, &setter_id) in &meta.setter_ids {
+                class_accessors.push((meta.id, prop_name.clone(), setter_id, "js_register_class_setter"));
+            }
+        }
+        class_accessors.sort_by(|a, b| (a.0, &a.1, a.3).cmp(&(b.0, &b.1, b.3)));
+
+        // Fields and methods of this module's classes by name, when the program uses the JS
+        // runtime, so instances handed to JavaScript can be read, written and called
+        let mut class_fields: Vec<(u32, String, u32)> = Vec::new();
+        let mut class_methods: Vec<(u32, String, cranelift_module::FuncId, u32)> = Vec::new();
+        if self.needs_js_runtime {
+            for meta in self.classes.values() {
+                for (field_name, &index) in &meta.field_indices {
+                    class_fields.push((meta.id, field_name.clone(), index));
+                }
+                for (method_name, &method_id) in &meta.method_ids {
+                    // Parameters after `this`
+                    let arity = self.module.declarations().get_function_decl(method_id).signature.params.len().saturating_sub(1);
+                    class_methods.push((meta.id, method_name.clone(), method_id, arity as u32));
+                }
+            }
+        }
+        class_fields.sort();
+        class_fields.dedup();
+        class_methods.sort_by(|a, b| (a.0, &a.1).cmp(&(b.0, &b.1)));
+        class_methods.dedup_by(|a, b| (a.0, &a.1) == (b.0, &b.1));
+
         // Get js_closure_alloc function ID if we have exported functions
         let closure_alloc_id = if !exported_func_info.is_empty() {
             self.extern_funcs.get("js_closure_alloc").copied()
@@ -11670,6 +13601,92 @@ impl Compiler {
             builder.switch_to_block(entry_block);
             builder.seal_block(entry_block);
 
+            // Install crash handlers first so init failures are reported too
+            if self.is_entry_module && self.crash_reporting {
+                if let Some(install_id) = self.extern_funcs.get("js_crash_install") {
+                    let install_ref = self.module.declare_func_in_func(*install_id, builder.func);
+                    builder.ins().call(install_ref, &[]);
+                }
+            }
+
+            // After the crash handlers, which the overflow handler passes other faults on to
+            if self.is_entry_module {
+                if let Some(guard_id) = self.extern_funcs.get("js_stack_guard_install") {
+                    let guard_ref = self.module.declare_func_in_func(*guard_id, builder.func);
+                    builder.ins().call(guard_ref, &[]);
+                }
+            }
+
+            if self.is_entry_module && self.hot_reload {
+                if let Some(start_id) = self.extern_funcs.get("js_hot_reload_start") {
+                    let start_ref = self.module.declare_func_in_func(*start_id, builder.func);
+                    builder.ins().call(start_ref, &[]);
+                }
+            }
+
+            // Install the permission policy before any module code can reach fs, net or env
+            if self.is_entry_module {
+                if let (Some(policy), Some(configure_id)) = (&self.permissions, self.extern_funcs.get("js_permissions_configure")) {
+                    let data_id = self.module.declare_data("__perry_permissions", Linkage::Local, false, false)?;
+                    let mut data_desc = cranelift_module::DataDescription::new();
+                    data_desc.define(policy.as_bytes().to_vec().into_boxed_slice());
+                    self.module.define_data(data_id, &data_desc)?;
+                    let policy_gv = self.module.declare_data_in_func(data_id, builder.func);
+                    let policy_ptr = builder.ins().global_value(types::I64, policy_gv);
+                    let policy_len = builder.ins().iconst(types::I64, policy.len() as i64);
+                    let configure_ref = self.module.declare_func_in_func(*configure_id, builder.func);
+                    builder.ins().call(configure_ref, &[policy_ptr, policy_len]);
+                }
+            }
+
+            // Apply the compiled-in heap ceiling before anything allocates
+            if self.is_entry_module && (self.max_heap.is_some() || self.oom_abort) {
+                if let Some(configure_id) = self.extern_funcs.get("js_memory_configure") {
+                    let configure_ref = self.module.declare_func_in_func(*configure_id, builder.func);
+                    let limit_val = builder.ins().f64const(self.max_heap.unwrap_or(0) as f64);
+                    let policy_val = builder.ins().iconst(types::I32, self.oom_abort as i64);
+                    builder.ins().call(configure_ref, &[limit_val, policy_val]);
+                }
+            }
+
+            // Record the linked allocator for process.allocationStats()
+            if self.is_entry_module {
+                if let (Some(allocator), Some(set_allocator_id)) = (&self.allocator, self.extern_funcs.get("js_memory_set_allocator")) {
+                    let data_id = self.module.declare_data("__perry_allocator", Linkage::Local, false, false)?;
+                    let mut data_desc = cranelift_module::DataDescription::new();
+                    data_desc.define(allocator.as_bytes().to_vec().into_boxed_slice());
+                    self.module.define_data(data_id, &data_desc)?;
+                    let name_gv = self.module.declare_data_in_func(data_id, builder.func);
+                    let name_ptr = builder.ins().global_value(types::I64, name_gv);
+                    let name_len = builder.ins().iconst(types::I64, allocator.len() as i64);
+                    let set_allocator_ref = self.module.declare_func_in_func(*set_allocator_id, builder.func);
+                    builder.ins().call(set_allocator_ref, &[name_ptr, name_len]);
+                }
+            }
+
+            // Switch multiversioned functions to their x86-64-v3 versions before any of
+            // this module's code calls them
+            if let (false, Some(has_v3_id)) = (self.cpu_variant_slots.is_empty(), self.extern_funcs.get("js_cpu_has_x86_64_v3")) {
+                let has_v3_ref = self.module.declare_func_in_func(*has_v3_id, builder.func);
+                let call = builder.ins().call(has_v3_ref, &[]);
+                let has_v3 = builder.inst_results(call)[0];
+                let switch_block = builder.create_block();
+                let done_block = builder.create_block();
+                builder.ins().brif(has_v3, switch_block, &[], done_block, &[]);
+                builder.switch_to_block(switch_block);
+                builder.seal_block(switch_block);
+                for (slot_id, variant_id) in self.cpu_variant_slots.clone() {
+                    let slot_gv = self.module.declare_data_in_func(slot_id, builder.func);
+                    let slot_addr = builder.ins().global_value(types::I64, slot_gv);
+                    let variant_ref = self.module.declare_func_in_func(variant_id, builder.func);
+                    let variant_addr = builder.ins().func_addr(types::I64, variant_ref);
+                    builder.ins().store(MemFlags::trusted(), variant_addr, slot_addr, 0);
+                }
+                builder.ins().jump(done_block, &[]);
+                builder.switch_to_block(done_block);
+                builder.seal_block(done_block);
+            }
+
             // Initialize handle method dispatch (must be before any module inits)
             // This allows js_native_call_method to handle Fastify/ioredis handles
             if self.is_entry_module {
@@ -11679,6 +13696,36 @@ impl Compiler {
                 }
             }
 
+            // Hand the compiled-in OpenAPI document to the Fastify runtime
+            if self.is_entry_module {
+                if let (Some(document), Some(set_docs_id)) = (&self.docs_document, self.extern_funcs.get("js_fastify_set_docs")) {
+                    let data_id = self.module.declare_data("__perry_docs_document", Linkage::Local, false, false)?;
+                    let mut data_desc = cranelift_module::DataDescription::new();
+                    data_desc.define(document.as_bytes().to_vec().into_boxed_slice());
+                    self.module.define_data(data_id, &data_desc)?;
+                    let document_gv = self.module.declare_data_in_func(data_id, builder.func);
+                    let document_ptr = builder.ins().global_value(types::I64, document_gv);
+                    let document_len = builder.ins().iconst(types::I64, document.len() as i64);
+                    let set_docs_ref = self.module.declare_func_in_func(*set_docs_id, builder.func);
+                    builder.ins().call(set_docs_ref, &[document_ptr, document_len]);
+                }
+            }
+
+            // Hand the compiled-in time zone table to the Temporal runtime
+            if self.is_entry_module {
+                if let (Some(tzdata), Some(set_tzdata_id)) = (&self.tzdata, self.extern_funcs.get("js_temporal_set_tzdata")) {
+                    let data_id = self.module.declare_data("__perry_tzdata", Linkage::Local, false, false)?;
+                    let mut data_desc = cranelift_module::DataDescription::new();
+                    data_desc.define(tzdata.clone().into_boxed_slice());
+                    self.module.define_data(data_id, &data_desc)?;
+                    let tzdata_gv = self.module.declare_data_in_func(data_id, builder.func);
+                    let tzdata_ptr = builder.ins().global_value(types::I64, tzdata_gv);
+                    let tzdata_len = builder.ins().iconst(types::I64, tzdata.len() as i64);
+                    let set_tzdata_ref = self.module.declare_func_in_func(*set_tzdata_id, builder.func);
+                    builder.ins().call(set_tzdata_ref, &[tzdata_ptr, tzdata_len]);
+                }
+            }
+
             // Initialize JS runtime at the start of main() if needed
             if let Some(init_func_id) = js_runtime_init_id {
                 let init_func_ref = self.module.declare_func_in_func(init_func_id, builder.func);
@@ -11707,69 +13754,93 @@ impl Compiler {
                 }
             }
 
-            let mut locals: HashMap<LocalId, LocalInfo> = HashMap::new();
-            let mut next_var = 0;
-
-            for stmt in stmts {
-                // Check if this statement is a Let for a module-level variable
-                if let Stmt::Let { name, init: Some(_init_expr), id, .. } = stmt {
-                    // Compile the statement to get the value (this creates the local variable)
-                    compile_stmt(&mut builder, &mut self.module, &self.func_ids, &self.closure_func_ids, &self.func_wrapper_ids, &self.extern_funcs, &self.async_func_ids, &self.closure_returning_funcs, &self.classes, &self.enums, &self.func_param_types, &self.func_union_params, &self.func_return_types, &self.func_hir_return_types, &self.func_rest_param_index, &self.imported_func_param_counts, &mut locals, &mut next_var, stmt, None, None, &boxed_vars)?;
+            for (class_id, prop_name, accessor_id, register_name) in &class_accessors {
+                let register_id = self.extern_funcs.get(*register_name)
+                    .ok_or_else(|| anyhow!("{} not declared", register_name))?;
+                let register_ref = self.module.declare_func_in_func(*register_id, builder.func);
+                let accessor_ref = self.module.declare_func_in_func(*accessor_id, builder.func);
+                let accessor_ptr = builder.ins().func_addr(types::I64, accessor_ref);
 
-                    // Get the value from the local variable
-                    if let Some(local_info) = locals.get(id).cloned() {
-                        let val = builder.use_var(local_info.var);
+                let prop_bytes = prop_name.as_bytes();
+                let slot = builder.create_sized_stack_slot(StackSlotData::new(
+                    StackSlotKind::ExplicitSlot,
+                    prop_bytes.len() as u32,
+                    0,
+                ));
+                for (i, &byte) in prop_bytes.iter().enumerate() {
+                    let byte_val = builder.ins().iconst(types::I8, byte as i64);
+                    builder.ins().stack_store(byte_val, slot, i as i32);
+                }
+                let name_ptr = builder.ins().stack_addr(types::I64, slot, 0);
+                let name_len = builder.ins().iconst(types::I64, prop_bytes.len() as i64);
+                let class_id_val = builder.ins().iconst(types::I32, *class_id as i64);
+                builder.ins().call(register_ref, &[class_id_val, name_ptr, name_len, accessor_ptr]);
+            }
 
-                        // Store to exported global if this is an exported variable
-                        if exported_names.contains(name) {
-                            let data_id = self.exported_native_instance_ids.get(name)
-                                .or_else(|| self.exported_object_ids.get(name))
-                                .copied();
-                            if let Some(data_id) = data_id {
-                                let global_val = self.module.declare_data_in_func(data_id, builder.func);
-                                let ptr = builder.ins().global_value(types::I64, global_val);
+            for (class_id, field_name, index) in &class_fields {
+                let register_id = self.extern_funcs.get("js_register_class_field")
+                    .ok_or_else(|| anyhow!("js_register_class_field not declared"))?;
+                let register_ref = self.module.declare_func_in_func(*register_id, builder.func);
+                let name_bytes = field_name.as_bytes();
+                let slot = builder.create_sized_stack_slot(StackSlotData::new(
+                    StackSlotKind::ExplicitSlot,
+                    name_bytes.len() as u32,
+                    0,
+                ));
+                for (i, &byte) in name_bytes.iter().enumerate() {
+                    let byte_val = builder.ins().iconst(types::I8, byte as i64);
+                    builder.ins().stack_store(byte_val, slot, i as i32);
+                }
+                let name_ptr = builder.ins().stack_addr(types::I64, slot, 0);
+                let name_len = builder.ins().iconst(types::I64, name_bytes.len() as i64);
+                let class_id_val = builder.ins().iconst(types::I32, *class_id as i64);
+                let index_val = builder.ins().iconst(types::I32, *index as i64);
+                builder.ins().call(register_ref, &[class_id_val, name_ptr, name_len, index_val]);
+            }
 
-                                // For pointer types (arrays, objects), NaN-box the pointer before storing
-                                // so that importing modules can load them uniformly as f64
-                                // Only NaN-box if the value is stored as i64 (raw pointer)
-                                // If already stored as f64 (union or NaN-boxed), it's already in the right format
-                                let val_to_store = if local_info.is_pointer && !local_info.is_string && !local_info.is_union {
-                                    // Check if val is i64 type - only then do we need to NaN-box
-                                    let val_type = builder.func.dfg.value_type(val);
-                                    if val_type == types::I64 {
-                                        // Get the NaN-boxing function
-                                        let nanbox_func_id = self.extern_funcs.get("js_nanbox_pointer")
-                                            .ok_or_else(|| anyhow!("js_nanbox_pointer not declared"))?;
-                                        let nanbox_ref = self.module.declare_func_in_func(*nanbox_func_id, builder.func);
-                                        let call = builder.ins().call(nanbox_ref, &[val]);
-                                        builder.inst_results(call)[0]
-                                    } else {
-                                        // Already f64 (NaN-boxed), use as-is
-                                        val
-                                    }
-                                } else {
-                                    val
-                                };
-                                builder.ins().store(MemFlags::new(), val_to_store, ptr, 0);
-                            }
-                        }
+            for (class_id, method_name, method_id, arity) in &class_methods {
+                let register_id = self.extern_funcs.get("js_register_class_method")
+                    .ok_or_else(|| anyhow!("js_register_class_method not declared"))?;
+                let register_ref = self.module.declare_func_in_func(*register_id, builder.func);
+                let method_ref = self.module.declare_func_in_func(*method_id, builder.func);
+                let method_ptr = builder.ins().func_addr(types::I64, method_ref);
+                let name_bytes = method_name.as_bytes();
+                let slot = builder.create_sized_stack_slot(StackSlotData::new(
+                    StackSlotKind::ExplicitSlot,
+                    name_bytes.len() as u32,
+                    0,
+                ));
+                for (i, &byte) in name_bytes.iter().enumerate() {
+                    let byte_val = builder.ins().iconst(types::I8, byte as i64);
+                    builder.ins().stack_store(byte_val, slot, i as i32);
+                }
+                let name_ptr = builder.ins().stack_addr(types::I64, slot, 0);
+                let name_len = builder.ins().iconst(types::I64, name_bytes.len() as i64);
+                let class_id_val = builder.ins().iconst(types::I32, *class_id as i64);
+                let arity_val = builder.ins().iconst(types::I32, *arity as i64);
+                builder.ins().call(register_ref, &[class_id_val, name_ptr, name_len, method_ptr, arity_val]);
+            }
 
-                        // Also store to module variable global for function access
-                        if let Some(data_id) = self.module_var_data_ids.get(id).copied() {
-                            let global_val = self.module.declare_data_in_func(data_id, builder.func);
-                            let ptr = builder.ins().global_value(types::I64, global_val);
-                            builder.ins().store(MemFlags::new(), val, ptr, 0);
-                            // Store the LocalInfo so compile_function knows the type
-                            self.module_level_locals.insert(*id, local_info);
-                        }
-                    }
-                    continue;
+            // Class names and declared fields, for perry/reflect
+            let class_metadata = std::mem::take(&mut self.class_metadata);
+            if let Some(register_id) = self.extern_funcs.get("js_register_class_metadata").copied() {
+                for (class_id, text) in &class_metadata {
+                    let data_id = self.module.declare_data(&format!("__perry_class_meta_{}", class_id), Linkage::Local, false, false)?;
+                    let mut data_desc = cranelift_module::DataDescription::new();
+                    data_desc.define(text.as_bytes().to_vec().into_boxed_slice());
+                    self.module.define_data(data_id, &data_desc)?;
+                    let data_gv = self.module.declare_data_in_func(data_id, builder.func);
+                    let data_ptr = builder.ins().global_value(types::I64, data_gv);
+                    let data_len = builder.ins().iconst(types::I64, text.len() as i64);
+                    let class_id_val = builder.ins().iconst(types::I32, *class_id as i64);
+                    let register_ref = self.module.declare_func_in_func(register_id, builder.func);
+                    builder.ins().call(register_ref, &[class_id_val, data_ptr, data_len]);
                 }
-                compile_stmt(&mut builder, &mut self.module, &self.func_ids, &self.closure_func_ids, &self.func_wrapper_ids, &self.extern_funcs, &self.async_func_ids, &self.closure_returning_funcs, &self.classes, &self.enums, &self.func_param_types, &self.func_union_params, &self.func_return_types, &self.func_hir_return_types, &self.func_rest_param_index, &self.imported_func_param_counts, &mut locals, &mut next_var, stmt, None, None, &boxed_vars)?;
             }
 
-            // Initialize exported function globals with closure values
-            // This allows functions to be passed as values to other modules
+            // Initialize exported function globals with closure values before any statement
+            // runs: function declarations are hoisted, so modules importing this one (and
+            // modules in an import cycle with it) can use them during their own init
             if let Some(alloc_func_id) = closure_alloc_id {
                 let alloc_ref = self.module.declare_func_in_func(alloc_func_id, builder.func);
                 // Get js_nanbox_pointer function for proper NaN-boxing
@@ -11777,7 +13848,7 @@ impl Compiler {
                     .ok_or_else(|| anyhow!("js_nanbox_pointer not declared"))?;
                 let nanbox_ref = self.module.declare_func_in_func(*nanbox_func_id, builder.func);
 
-                for (_func_name, data_id, wrapper_func_id) in &exported_func_info {
+                for (_func_name, data_id, wrapper_func_id, arity) in &exported_func_info {
                     // Get the function address
                     let func_ref = self.module.declare_func_in_func(*wrapper_func_id, builder.func);
                     let func_ptr = builder.ins().func_addr(types::I64, func_ref);
@@ -11786,6 +13857,9 @@ impl Compiler {
                     let capture_count = builder.ins().iconst(types::I32, 0);
                     let call = builder.ins().call(alloc_ref, &[func_ptr, capture_count]);
                     let closure_ptr = builder.inst_results(call)[0];
+                    if *arity > 0 {
+                        set_closure_arity(&mut builder, &mut self.module, &self.extern_funcs, closure_ptr, *arity)?;
+                    }
 
                     // Properly NaN-box the closure pointer using js_nanbox_pointer
                     // This ensures typeof returns "object" (closures are objects) and
@@ -11793,16 +13867,86 @@ impl Compiler {
                     let nanbox_call = builder.ins().call(nanbox_ref, &[closure_ptr]);
                     let closure_val = builder.inst_results(nanbox_call)[0];
 
-                    // Store to the exported global
-                    let global_val = self.module.declare_data_in_func(*data_id, builder.func);
-                    let ptr = builder.ins().global_value(types::I64, global_val);
-                    builder.ins().store(MemFlags::new(), closure_val, ptr, 0);
+                    // Store to the exported global
+                    let global_val = self.module.declare_data_in_func(*data_id, builder.func);
+                    let ptr = builder.ins().global_value(types::I64, global_val);
+                    builder.ins().store(MemFlags::new(), closure_val, ptr, 0);
+                }
+            }
+
+            let mut locals: HashMap<LocalId, LocalInfo> = HashMap::new();
+            let mut next_var = 0;
+            // Exported mutable variables declared so far, with their export globals
+            let mut live_exports: Vec<(LocalId, Vec<cranelift_module::DataId>)> = Vec::new();
+
+            for stmt in stmts {
+                // Check if this statement is a Let for a module-level variable
+                if let Stmt::Let { name, init: Some(_init_expr), id, mutable, .. } = stmt {
+                    // Compile the statement to get the value (this creates the local variable)
+                    compile_stmt(&mut builder, &mut self.module, &self.func_ids, &self.closure_func_ids, &self.func_wrapper_ids, &self.extern_funcs, &self.async_func_ids, &self.closure_returning_funcs, &self.classes, &self.enums, &self.func_param_types, &self.func_union_params, &self.func_return_types, &self.func_hir_return_types, &self.func_rest_param_index, &self.imported_func_param_counts, &mut locals, &mut next_var, stmt, None, None, &boxed_vars)?;
+
+                    // Get the value from the local variable
+                    if let Some(local_info) = locals.get(id).cloned() {
+                        let val = builder.use_var(local_info.var);
+
+                        // Store to exported globals if this is an exported variable
+                        if let Some(data_ids) = export_globals.get(name) {
+                            let export_val = export_value(&mut builder, &mut self.module, &self.extern_funcs, &local_info, val)?;
+                            for data_id in data_ids {
+                                let global_val = self.module.declare_data_in_func(*data_id, builder.func);
+                                let ptr = builder.ins().global_value(types::I64, global_val);
+                                builder.ins().store(MemFlags::new(), export_val, ptr, 0);
+                            }
+                            if *mutable {
+                                live_exports.push((*id, data_ids.clone()));
+                            }
+                        }
+
+                        // Also store to module variable global for function access
+                        if let Some(data_id) = self.module_var_data_ids.get(id).copied() {
+                            let global_val = self.module.declare_data_in_func(data_id, builder.func);
+                            let ptr = builder.ins().global_value(types::I64, global_val);
+                            builder.ins().store(MemFlags::new(), val, ptr, 0);
+                            // Store the LocalInfo so compile_function knows the type
+                            self.module_level_locals.insert(*id, local_info);
+                        }
+                    }
+                    continue;
+                }
+                compile_stmt(&mut builder, &mut self.module, &self.func_ids, &self.closure_func_ids, &self.func_wrapper_ids, &self.extern_funcs, &self.async_func_ids, &self.closure_returning_funcs, &self.classes, &self.enums, &self.func_param_types, &self.func_union_params, &self.func_return_types, &self.func_hir_return_types, &self.func_rest_param_index, &self.imported_func_param_counts, &mut locals, &mut next_var, stmt, None, None, &boxed_vars)?;
+
+                // Keep exported `let`/`var` bindings live: importers load the export global on
+                // every access, so publish values assigned by top-level statements
+                if live_exports.is_empty() || is_block_filled(&builder, builder.current_block().unwrap()) {
+                    continue;
+                }
+                for (id, data_ids) in &live_exports {
+                    let Some(local_info) = locals.get(id) else { continue };
+                    let val = builder.use_var(local_info.var);
+                    let export_val = export_value(&mut builder, &mut self.module, &self.extern_funcs, local_info, val)?;
+                    for data_id in data_ids {
+                        let global_val = self.module.declare_data_in_func(*data_id, builder.func);
+                        let ptr = builder.ins().global_value(types::I64, global_val);
+                        builder.ins().store(MemFlags::new(), export_val, ptr, 0);
+                    }
                 }
             }
 
             // Return 0 from main (if not already terminated)
             let current_block = builder.current_block().unwrap();
             if !is_block_filled(&builder, current_block) {
+                if self.is_entry_module {
+                    // process.stdin 'data' listeners and pipes run once the top level is done
+                    let flow_func = self.extern_funcs.get("js_stdio_flow")
+                        .ok_or_else(|| anyhow!("js_stdio_flow not declared"))?;
+                    let flow_ref = self.module.declare_func_in_func(*flow_func, builder.func);
+                    builder.ins().call(flow_ref, &[]);
+                    // Keep running timers until none is left that holds the process open
+                    let loop_func = self.extern_funcs.get("js_run_event_loop")
+                        .ok_or_else(|| anyhow!("js_run_event_loop not declared"))?;
+                    let loop_ref = self.module.declare_func_in_func(*loop_func, builder.func);
+                    builder.ins().call(loop_ref, &[]);
+                }
                 let zero = builder.ins().iconst(types::I32, 0);
                 builder.ins().return_(&[zero]);
             }
@@ -11812,7 +13956,7 @@ impl Compiler {
         }
 
         let func_name = if self.is_entry_module { "main" } else { module_name };
-        if let Err(e) = self.module.define_function(func_id, &mut self.ctx) {
+        if let Err(e) = self.define_function(func_id) {
             eprintln!("=== VERIFIER ERROR in init/main '{}' ===", func_name);
             eprintln!("Error: {}", e);
             eprintln!("Debug: {:?}", e);
@@ -11827,6 +13971,39 @@ impl Compiler {
     }
 }
 
+/// Convert a module-level variable's value to the NaN-boxed f64 stored in its export
+/// global, which importing modules load uniformly as f64
+fn export_value(
+    builder: &mut FunctionBuilder,
+    module: &mut CodegenModule,
+    extern_funcs: &HashMap<String, cranelift_module::FuncId>,
+    info: &LocalInfo,
+    val: Value,
+) -> Result<Value> {
+    let call_extern = |builder: &mut FunctionBuilder, module: &mut CodegenModule, name: &str, arg: Value| -> Result<Value> {
+        let func_id = extern_funcs.get(name)
+            .ok_or_else(|| anyhow!("{} not declared", name))?;
+        let func_ref = module.declare_func_in_func(*func_id, builder.func);
+        let call = builder.ins().call(func_ref, &[arg]);
+        Ok(builder.inst_results(call)[0])
+    };
+    if info.is_boxed {
+        // Mutable captures hold a box pointer; export the current value
+        return call_extern(builder, module, "js_box_get", val);
+    }
+    let val_type = builder.func.dfg.value_type(val);
+    if val_type == types::I32 {
+        Ok(builder.ins().fcvt_from_sint(types::F64, val))
+    } else if val_type == types::I64 && info.is_string {
+        call_extern(builder, module, "js_nanbox_string", val)
+    } else if val_type == types::I64 {
+        // Raw pointers (arrays, objects) are NaN-boxed; f64 values are already boxed
+        call_extern(builder, module, "js_nanbox_pointer", val)
+    } else {
+        Ok(val)
+    }
+}
+
 /// Context for 'this' in constructors/methods
 struct ThisContext {
     /// Variable holding 'this' pointer (i64)
@@ -11877,7 +14054,7 @@ fn contains_loop_control(stmts: &[Stmt]) -> bool {
 /// Compile a statement inside an async function (handles return specially)
 fn compile_async_stmt(
     builder: &mut FunctionBuilder,
-    module: &mut ObjectModule,
+    module: &mut CodegenModule,
     func_ids: &HashMap<u32, cranelift_module::FuncId>,
     closure_func_ids: &HashMap<u32, cranelift_module::FuncId>,
     func_wrapper_ids: &HashMap<u32, cranelift_module::FuncId>,
@@ -11958,7 +14135,7 @@ fn compile_async_stmt(
                     // Property access on strings (like str.substring()) returns string
                     Expr::PropertyGet { object, property } => {
                         if is_string_expr(object, locals) {
-                            matches!(property.as_str(), "substring" | "slice" | "toLowerCase" | "toUpperCase"
+                            matches!(property.as_str(), "substring" | "slice" | "toLowerCase" | "toUpperCase" | "toLocaleLowerCase" | "toLocaleUpperCase"
                                 | "trim" | "trimStart" | "trimEnd" | "charAt" | "padStart" | "padEnd"
                                 | "repeat" | "replace" | "replaceAll" | "concat")
                         } else {
@@ -11969,7 +14146,7 @@ fn compile_async_stmt(
                         // Check if it's a string method call
                         if let Expr::PropertyGet { object, property } = callee.as_ref() {
                             if is_string_expr(object, locals) {
-                                return matches!(property.as_str(), "substring" | "slice" | "toLowerCase" | "toUpperCase"
+                                return matches!(property.as_str(), "substring" | "slice" | "toLowerCase" | "toUpperCase" | "toLocaleLowerCase" | "toLocaleUpperCase"
                                     | "trim" | "trimStart" | "trimEnd" | "charAt" | "padStart" | "padEnd"
                                     | "repeat" | "replace" | "replaceAll" | "concat" | "split" | "join");
                             }
@@ -12114,7 +14291,7 @@ fn compile_async_stmt(
 
 fn compile_stmt(
     builder: &mut FunctionBuilder,
-    module: &mut ObjectModule,
+    module: &mut CodegenModule,
     func_ids: &HashMap<u32, cranelift_module::FuncId>,
     closure_func_ids: &HashMap<u32, cranelift_module::FuncId>,
     func_wrapper_ids: &HashMap<u32, cranelift_module::FuncId>,
@@ -12141,9 +14318,9 @@ fn compile_stmt(
             // Note: Type::Any and Type::Unknown use expression inference, not pointer type
             use perry_types::Type as HirType;
             let is_typed_pointer = matches!(ty, HirType::String | HirType::Array(_) |
-                HirType::Object(_) | HirType::Named(_) | HirType::Generic { .. } |
+                HirType::Object(_) | HirType::Intersection(_) | HirType::Named(_) | HirType::Generic { .. } |
                 HirType::Function(_));
-            let is_typed_string = matches!(ty, HirType::String);
+            let is_typed_string = ty.is_string();
             let is_typed_bigint_check = matches!(ty, HirType::BigInt);
 
             // Helper to detect if an expression produces a string (fallback for untyped cases)
@@ -12155,8 +14332,9 @@ fn compile_stmt(
                     // EnvGet is NOT included - it returns string OR undefined (handled as union)
                     Expr::FsReadFileSync(_) => true, // fs.readFileSync returns a string
                     // All path operations return strings
+                    Expr::ImportMetaRuntime { .. } | Expr::ImportMetaResolve { .. } |
                     Expr::PathJoin(_, _) | Expr::PathDirname(_) | Expr::PathBasename(_) |
-                    Expr::PathExtname(_) | Expr::PathResolve(_) | Expr::JsonStringify(_) => true,
+                    Expr::PathExtname(_) | Expr::PathResolve(_) | Expr::JsonStringify(_) | Expr::JsonCanonicalize(_) => true,
                     // All crypto operations return strings (hex or UUID format)
                     Expr::CryptoRandomBytes(_) | Expr::CryptoRandomUUID |
                     Expr::CryptoSha256(_) | Expr::CryptoMd5(_) => true,
@@ -12173,7 +14351,7 @@ fn compile_stmt(
                         // Check if it's a string method call (slice/substring/trim/toLowerCase/toUpperCase returns string)
                         if let Expr::PropertyGet { object, property } = callee.as_ref() {
                             if property == "slice" || property == "substring" || property == "trim"
-                               || property == "toLowerCase" || property == "toUpperCase" || property == "replace"
+                               || property == "toLowerCase" || property == "toUpperCase" || property == "toLocaleLowerCase" || property == "toLocaleUpperCase" || property == "replace"
                                || property == "padStart" || property == "padEnd" || property == "repeat" || property == "charAt" {
                                 if let Expr::LocalGet(id) = object.as_ref() {
                                     // If we can find the local, check if it's a string
@@ -12195,7 +14373,7 @@ fn compile_stmt(
                         // Check if it's an external function that returns a string
                         if let Expr::ExternFuncRef { name: func_name, return_type, .. } = callee.as_ref() {
                             // Use the return type if available
-                            if matches!(return_type, perry_types::Type::String) {
+                            if return_type.is_string() {
                                 return true;
                             }
                             // Fallback: HTTP request methods that return strings
@@ -12239,8 +14417,9 @@ fn compile_stmt(
                     }
                     // NativeMethodCall methods that return BigInt
                     Expr::NativeMethodCall { module, method, .. } => {
-                        // ethers parseUnits/parseEther return BigInt
-                        module == "ethers" && (method == "parseUnits" || method == "parseEther")
+                        // ethers parseUnits/parseEther and perry/bigint return BigInt
+                        (module == "ethers" && (method == "parseUnits" || method == "parseEther"))
+                            || module == "perry/bigint"
                     }
                     _ => false,
                 }
@@ -12298,9 +14477,11 @@ fn compile_stmt(
             let is_typed_closure = matches!(ty, HirType::Function(_));
             let is_typed_map = matches!(ty, HirType::Generic { base, .. } if base == "Map");
             let is_typed_set = matches!(ty, HirType::Generic { base, .. } if base == "Set");
-            let is_typed_union = matches!(ty, HirType::Union(_));
+            let is_typed_union = matches!(ty, HirType::Union(_)) && !is_typed_string;
+            // Unions of class instances keep the pointer and select fields by the header's tag
+            let union_classes = object_union_classes(ty, classes);
             // Named/Object types may contain NaN-boxed values when fields are accessed
-            let is_typed_generic_object = matches!(ty, HirType::Named(_) | HirType::Object(_) | HirType::Any);
+            let is_typed_generic_object = matches!(ty, HirType::Named(_) | HirType::Object(_) | HirType::Intersection(_) | HirType::Any);
 
             // Helper to detect mixed-type array from expression
             fn is_mixed_array_expr(expr: &Expr, _locals: &HashMap<LocalId, LocalInfo>) -> bool {
@@ -12345,6 +14526,8 @@ fn compile_stmt(
                         None
                     }
                 })
+            } else if let HirType::Intersection(_) | HirType::Generic { .. } = ty {
+                instance_class(ty, classes)
             } else {
                 None
             };
@@ -12355,7 +14538,7 @@ fn compile_stmt(
                     Expr::BufferFrom { .. } | Expr::BufferAlloc { .. } | Expr::BufferAllocUnsafe(_) |
                     Expr::BufferConcat(_) | Expr::BufferSlice { .. } |
                     Expr::ChildProcessExecSync { .. }
-                )
+                ) || matches!(expr, Expr::NativeMethodCall { module, method, .. } if module == "perry/buffer-pool" && method == "acquire")
             }
 
             // Use declared type information first, fall back to expression inference
@@ -12385,7 +14568,8 @@ fn compile_stmt(
                     Some(Expr::New { class_name, .. }) => {
                         // Native handle-based classes use f64 (handles bitcast to f64), not i64 pointers
                         let is_native_handle_class = matches!(class_name.as_str(),
-                            "Decimal" | "Big" | "BigNumber" | "LRUCache" | "Command" | "EventEmitter" | "Redis");
+                            "Decimal" | "Big" | "BigNumber" | "LRUCache" | "Command" | "EventEmitter" | "Redis")
+                            || matches!(class_name.as_str(), "Level" | "MiniSearch") && !classes.contains_key(class_name);
                         let is_event_emitter = class_name == "EventEmitter";
                         (Some(class_name.clone()), !is_native_handle_class, false, false, false, false, false, false, false, is_event_emitter)
                     }
@@ -12480,7 +14664,7 @@ fn compile_stmt(
                                     (None, false, false, false, false, false, false, false, false, false)
                                 }
                             } else if property == "substring" || property == "trim" || property == "toLowerCase"
-                                || property == "toUpperCase" || property == "charAt" || property == "padStart"
+                                || property == "toUpperCase" || property == "toLocaleLowerCase" || property == "toLocaleUpperCase" || property == "charAt" || property == "padStart"
                                 || property == "padEnd" || property == "repeat" || property == "replace" {
                                 // String methods that return NaN-boxed strings (f64, not i64 pointers)
                                 // is_pointer must be false so the variable uses f64 type
@@ -12609,7 +14793,7 @@ fn compile_stmt(
                             builder.ins().fcvt_to_sint(types::I32, val_f64)
                         }
                     };
-                    builder.def_var(var, init_val);
+                    builder.def_local(var, init_val);
                 } else {
                     // Compile the expression and assign directly - typed expressions now return correct types
                     let val = compile_expr(builder, module, func_ids, closure_func_ids, func_wrapper_ids, extern_funcs, async_func_ids, classes, enums, func_param_types, func_union_params, func_return_types, func_hir_return_types, func_rest_param_index, imported_func_param_counts, locals, init_expr, this_ctx)?;
@@ -12704,22 +14888,22 @@ fn compile_stmt(
                             val
                         }
                     };
-                    builder.def_var(var, val);
+                    builder.def_local(var, val);
                 }
             } else {
                 // Initialize to undefined/null/0 depending on the type
                 if is_pointer && !is_union {
                     // Raw pointer type - use null pointer (0)
                     let zero = builder.ins().iconst(types::I64, 0);
-                    builder.def_var(var, zero);
+                    builder.def_local(var, zero);
                 } else if should_use_i32 {
                     let zero = builder.ins().iconst(types::I32, 0);
-                    builder.def_var(var, zero);
+                    builder.def_local(var, zero);
                 } else {
                     // f64 type - use TAG_UNDEFINED for proper JavaScript semantics
                     const TAG_UNDEFINED: u64 = 0x7FFC_0000_0000_0001;
                     let undef = builder.ins().f64const(f64::from_bits(TAG_UNDEFINED));
-                    builder.def_var(var, undef);
+                    builder.def_local(var, undef);
                 }
             }
 
@@ -12729,7 +14913,7 @@ fn compile_stmt(
 
             let i32_shadow: Option<Variable> = None;
 
-            locals.insert(*id, LocalInfo { var, name: Some(var_name.clone()), class_name, type_args, is_pointer, is_array, is_string, is_bigint, is_closure, is_boxed: false, is_map, is_set, is_buffer, is_event_emitter, is_union, is_mixed_array, is_integer, is_integer_array: false, is_i32: should_use_i32, i32_shadow, bounded_by_array: None, bounded_by_constant: None, scalar_fields: None, squared_cache: None, product_cache: None, cached_array_ptr: None });
+            locals.insert(*id, LocalInfo { var, name: Some(var_name.clone()), class_name, type_args, is_pointer, is_array, is_string, is_bigint, is_closure, is_boxed: false, is_map, is_set, is_buffer, is_event_emitter, is_union, is_mixed_array, is_integer, is_integer_array: false, is_i32: should_use_i32, i32_shadow, bounded_by_array: None, bounded_by_constant: None, scalar_fields: None, squared_cache: None, product_cache: None, cached_array_ptr: None, union_classes });
         }
         Stmt::Return(expr) => {
             // Check if this is a void function (no return type) - e.g., constructors
@@ -12943,7 +15127,7 @@ fn compile_stmt(
                                 let limit_var = Variable::new(*next_var);
                                 *next_var += 1;
                                 builder.declare_var(limit_var, types::I32);
-                                builder.def_var(limit_var, limit_val);
+                                builder.def_local(limit_var, limit_val);
 
                                 // Create i32 variable for counter
                                 let i32_var = Variable::new(*next_var);
@@ -12954,7 +15138,7 @@ fn compile_stmt(
                                 let counter_val = builder.use_var(counter_info.var);
                                 let counter_f64 = ensure_f64(builder, counter_val);
                                 let counter_i32 = builder.ins().fcvt_to_sint(types::I32, counter_f64);
-                                builder.def_var(i32_var, counter_i32);
+                                builder.def_local(i32_var, counter_i32);
 
                                 // Store original f64 var for restoration
                                 original_f64_var = Some(counter_info.var);
@@ -13086,7 +15270,7 @@ fn compile_stmt(
                 builder.declare_var(cache_var, types::F64);
                 // Initialize to 0 (will be computed at loop header)
                 let zero = builder.ins().f64const(0.0);
-                builder.def_var(cache_var, zero);
+                builder.def_local(cache_var, zero);
 
                 if let Some(info) = locals.get_mut(var_id) {
                     info.squared_cache = Some(cache_var);
@@ -13100,7 +15284,7 @@ fn compile_stmt(
                 *next_var += 1;
                 builder.declare_var(cache_var, types::F64);
                 let zero = builder.ins().f64const(0.0);
-                builder.def_var(cache_var, zero);
+                builder.def_local(cache_var, zero);
 
                 product_cache_vars.insert((*id1, *id2), cache_var);
 
@@ -13138,7 +15322,7 @@ fn compile_stmt(
                     if let Some(cache_var) = info.squared_cache {
                         let val = builder.use_var(info.var);
                         let squared = builder.ins().fmul(val, val);
-                        builder.def_var(cache_var, squared);
+                        builder.def_local(cache_var, squared);
                     }
                 }
             }
@@ -13149,7 +15333,7 @@ fn compile_stmt(
                     let val1 = builder.use_var(info1.var);
                     let val2 = builder.use_var(info2.var);
                     let product = builder.ins().fmul(val1, val2);
-                    builder.def_var(*cache_var, product);
+                    builder.def_local(*cache_var, product);
                 }
             }
 
@@ -13315,7 +15499,7 @@ fn compile_stmt(
                         if let Some(cache_var) = info.squared_cache {
                             let val = builder.use_var(info.var);
                             let squared = builder.ins().fmul(val, val);
-                            builder.def_var(cache_var, squared);
+                            builder.def_local(cache_var, squared);
                         }
                     }
                 }
@@ -13324,7 +15508,7 @@ fn compile_stmt(
                         let val1 = builder.use_var(info1.var);
                         let val2 = builder.use_var(info2.var);
                         let product = builder.ins().fmul(val1, val2);
-                        builder.def_var(*cache_var, product);
+                        builder.def_local(*cache_var, product);
                     }
                 }
                 let cond_val2_raw = compile_expr(builder, module, func_ids, closure_func_ids, func_wrapper_ids, extern_funcs, async_func_ids, classes, enums, func_param_types, func_union_params, func_return_types, func_hir_return_types, func_rest_param_index, imported_func_param_counts, locals, condition, this_ctx)?;
@@ -13351,7 +15535,7 @@ fn compile_stmt(
                         if let Some(cache_var) = info.squared_cache {
                             let val = builder.use_var(info.var);
                             let squared = builder.ins().fmul(val, val);
-                            builder.def_var(cache_var, squared);
+                            builder.def_local(cache_var, squared);
                         }
                     }
                 }
@@ -13360,7 +15544,7 @@ fn compile_stmt(
                         let val1 = builder.use_var(info1.var);
                         let val2 = builder.use_var(info2.var);
                         let product = builder.ins().fmul(val1, val2);
-                        builder.def_var(*cache_var, product);
+                        builder.def_local(*cache_var, product);
                     }
                 }
                 let cond_val3_raw = compile_expr(builder, module, func_ids, closure_func_ids, func_wrapper_ids, extern_funcs, async_func_ids, classes, enums, func_param_types, func_union_params, func_return_types, func_hir_return_types, func_rest_param_index, imported_func_param_counts, locals, condition, this_ctx)?;
@@ -13387,7 +15571,7 @@ fn compile_stmt(
                         if let Some(cache_var) = info.squared_cache {
                             let val = builder.use_var(info.var);
                             let squared = builder.ins().fmul(val, val);
-                            builder.def_var(cache_var, squared);
+                            builder.def_local(cache_var, squared);
                         }
                     }
                 }
@@ -13396,7 +15580,7 @@ fn compile_stmt(
                         let val1 = builder.use_var(info1.var);
                         let val2 = builder.use_var(info2.var);
                         let product = builder.ins().fmul(val1, val2);
-                        builder.def_var(*cache_var, product);
+                        builder.def_local(*cache_var, product);
                     }
                 }
                 let cond_val4_raw = compile_expr(builder, module, func_ids, closure_func_ids, func_wrapper_ids, extern_funcs, async_func_ids, classes, enums, func_param_types, func_union_params, func_return_types, func_hir_return_types, func_rest_param_index, imported_func_param_counts, locals, condition, this_ctx)?;
@@ -13423,7 +15607,7 @@ fn compile_stmt(
                         if let Some(cache_var) = info.squared_cache {
                             let val = builder.use_var(info.var);
                             let squared = builder.ins().fmul(val, val);
-                            builder.def_var(cache_var, squared);
+                            builder.def_local(cache_var, squared);
                         }
                     }
                 }
@@ -13432,7 +15616,7 @@ fn compile_stmt(
                         let val1 = builder.use_var(info1.var);
                         let val2 = builder.use_var(info2.var);
                         let product = builder.ins().fmul(val1, val2);
-                        builder.def_var(*cache_var, product);
+                        builder.def_local(*cache_var, product);
                     }
                 }
                 let cond_val5_raw = compile_expr(builder, module, func_ids, closure_func_ids, func_wrapper_ids, extern_funcs, async_func_ids, classes, enums, func_param_types, func_union_params, func_return_types, func_hir_return_types, func_rest_param_index, imported_func_param_counts, locals, condition, this_ctx)?;
@@ -13459,7 +15643,7 @@ fn compile_stmt(
                         if let Some(cache_var) = info.squared_cache {
                             let val = builder.use_var(info.var);
                             let squared = builder.ins().fmul(val, val);
-                            builder.def_var(cache_var, squared);
+                            builder.def_local(cache_var, squared);
                         }
                     }
                 }
@@ -13468,7 +15652,7 @@ fn compile_stmt(
                         let val1 = builder.use_var(info1.var);
                         let val2 = builder.use_var(info2.var);
                         let product = builder.ins().fmul(val1, val2);
-                        builder.def_var(*cache_var, product);
+                        builder.def_local(*cache_var, product);
                     }
                 }
                 let cond_val6_raw = compile_expr(builder, module, func_ids, closure_func_ids, func_wrapper_ids, extern_funcs, async_func_ids, classes, enums, func_param_types, func_union_params, func_return_types, func_hir_return_types, func_rest_param_index, imported_func_param_counts, locals, condition, this_ctx)?;
@@ -13495,7 +15679,7 @@ fn compile_stmt(
                         if let Some(cache_var) = info.squared_cache {
                             let val = builder.use_var(info.var);
                             let squared = builder.ins().fmul(val, val);
-                            builder.def_var(cache_var, squared);
+                            builder.def_local(cache_var, squared);
                         }
                     }
                 }
@@ -13504,7 +15688,7 @@ fn compile_stmt(
                         let val1 = builder.use_var(info1.var);
                         let val2 = builder.use_var(info2.var);
                         let product = builder.ins().fmul(val1, val2);
-                        builder.def_var(*cache_var, product);
+                        builder.def_local(*cache_var, product);
                     }
                 }
                 let cond_val7_raw = compile_expr(builder, module, func_ids, closure_func_ids, func_wrapper_ids, extern_funcs, async_func_ids, classes, enums, func_param_types, func_union_params, func_return_types, func_hir_return_types, func_rest_param_index, imported_func_param_counts, locals, condition, this_ctx)?;
@@ -13531,7 +15715,7 @@ fn compile_stmt(
                         if let Some(cache_var) = info.squared_cache {
                             let val = builder.use_var(info.var);
                             let squared = builder.ins().fmul(val, val);
-                            builder.def_var(cache_var, squared);
+                            builder.def_local(cache_var, squared);
                         }
                     }
                 }
@@ -13540,7 +15724,7 @@ fn compile_stmt(
                         let val1 = builder.use_var(info1.var);
                         let val2 = builder.use_var(info2.var);
                         let product = builder.ins().fmul(val1, val2);
-                        builder.def_var(*cache_var, product);
+                        builder.def_local(*cache_var, product);
                     }
                 }
                 let cond_val8_raw = compile_expr(builder, module, func_ids, closure_func_ids, func_wrapper_ids, extern_funcs, async_func_ids, classes, enums, func_param_types, func_union_params, func_return_types, func_hir_return_types, func_rest_param_index, imported_func_param_counts, locals, condition, this_ctx)?;
@@ -13592,7 +15776,7 @@ fn compile_stmt(
                     // Convert final i32 value back to f64
                     let final_i32 = builder.use_var(i32_var);
                     let final_f64 = builder.ins().fcvt_from_sint(types::F64, final_i32);
-                    builder.def_var(orig_f64_var, final_f64);
+                    builder.def_local(orig_f64_var, final_f64);
 
                     // Restore LocalInfo
                     if let Some(info) = locals.get_mut(&counter_id) {
@@ -13693,7 +15877,7 @@ fn compile_stmt(
                                 let len_var = Variable::new(*next_var);
                                 *next_var += 1;
                                 builder.declare_var(len_var, types::I32);
-                                builder.def_var(len_var, limit_val);
+                                builder.def_local(len_var, limit_val);
 
                                 cached_length_var = Some(len_var);
                                 bce_index_var = Some(*index_id);
@@ -13708,7 +15892,7 @@ fn compile_stmt(
                                 let len_var = Variable::new(*next_var);
                                 *next_var += 1;
                                 builder.declare_var(len_var, types::I32);
-                                builder.def_var(len_var, limit_val);
+                                builder.def_local(len_var, limit_val);
 
                                 // Create new i32 variable to replace the f64 one
                                 let i32_var = Variable::new(*next_var);
@@ -13719,7 +15903,7 @@ fn compile_stmt(
                                 let idx_val = builder.use_var(idx_v);
                                 let idx_f64 = ensure_f64(builder, idx_val);
                                 let idx_i32 = builder.ins().fcvt_to_sint(types::I32, idx_f64);
-                                builder.def_var(i32_var, idx_i32);
+                                builder.def_local(i32_var, idx_i32);
 
                                 cached_length_var = Some(len_var);
                                 bce_index_var = Some(*index_id);
@@ -13987,7 +16171,7 @@ fn compile_stmt(
                         let zero = builder.ins().f64const(0.0);
                         let zero_vec = builder.ins().splat(f64x2_type, zero);
                         for acc in &vec_accs {
-                            builder.def_var(*acc, zero_vec);
+                            builder.def_local(*acc, zero_vec);
                         }
 
                         simd_accumulators = Some((vec_accs, arr_id));
@@ -14010,7 +16194,7 @@ fn compile_stmt(
                         // Initialize all accumulators to 0
                         let zero = builder.ins().f64const(0.0);
                         for acc in &accs {
-                            builder.def_var(*acc, zero);
+                            builder.def_local(*acc, zero);
                         }
 
                         generic_accumulators = Some((accs, sum_id, sum_info.var));
@@ -14029,7 +16213,7 @@ fn compile_stmt(
                         builder.declare_var(var, types::F64);
                         // Initialize to 0 (will be set in loop body)
                         let zero = builder.ins().f64const(0.0);
-                        builder.def_var(var, zero);
+                        builder.def_local(var, zero);
                         field_vars.insert(field_name.clone(), var);
                     }
 
@@ -14039,7 +16223,7 @@ fn compile_stmt(
                     *next_var += 1;
                     builder.declare_var(dummy_var, types::F64);
                     let zero = builder.ins().f64const(0.0);
-                    builder.def_var(dummy_var, zero);
+                    builder.def_local(dummy_var, zero);
 
                     locals.insert(obj_id, LocalInfo {
                         var: dummy_var,
@@ -14061,7 +16245,7 @@ fn compile_stmt(
                         bounded_by_array: None,
                         bounded_by_constant: None,
                         scalar_fields: Some(field_vars.clone()),
-                        squared_cache: None, product_cache: None, cached_array_ptr: None,
+                        squared_cache: None, product_cache: None, cached_array_ptr: None, union_classes: Vec::new(),
                     });
 
                     scalar_replacement_vars = Some((obj_id, field_vars));
@@ -14104,7 +16288,7 @@ fn compile_stmt(
                         let current = builder.use_var(info.var);
                         let add_val = builder.ins().f64const(combined_const);
                         let new_val = builder.ins().fadd(current, add_val);
-                        builder.def_var(info.var, new_val);
+                        builder.def_local(info.var, new_val);
                         optimized = true;
                     }
                 }
@@ -14142,7 +16326,7 @@ fn compile_stmt(
                             let acc_var = vec_accs[k as usize];
                             let acc_val = builder.use_var(acc_var);
                             let new_acc = builder.ins().fadd(acc_val, vec_val);
-                            builder.def_var(acc_var, new_acc);
+                            builder.def_local(acc_var, new_acc);
                         }
                         optimized = true;
                     }
@@ -14190,7 +16374,7 @@ fn compile_stmt(
                             for (field_name, arg_expr) in field_args {
                                 if let Some(var) = field_vars.get(field_name) {
                                     let val = compile_expr(builder, module, func_ids, closure_func_ids, func_wrapper_ids, extern_funcs, async_func_ids, classes, enums, func_param_types, func_union_params, func_return_types, func_hir_return_types, func_rest_param_index, imported_func_param_counts, locals, arg_expr, this_ctx)?;
-                                    builder.def_var(*var, val);
+                                    builder.def_local(*var, val);
                                 }
                             }
 
@@ -14205,7 +16389,7 @@ fn compile_stmt(
                                 let idx_info = locals.get(&idx_id).ok_or_else(|| anyhow!("Index variable not found"))?;
                                 let current = builder.use_var(idx_info.var);
                                 let incremented = builder.ins().iadd_imm(current, stride);
-                                builder.def_var(idx_info.var, incremented);
+                                builder.def_local(idx_info.var, incremented);
                             }
                         }
                         optimized = true;
@@ -14232,7 +16416,7 @@ fn compile_stmt(
                             let idx_info = locals.get(&idx_id).ok_or_else(|| anyhow!("Index variable not found"))?;
                             let current = builder.use_var(idx_info.var);
                             let incremented = builder.ins().iadd_imm(current, stride);
-                            builder.def_var(idx_info.var, incremented);
+                            builder.def_local(idx_info.var, incremented);
                         }
                     }
 
@@ -14250,7 +16434,7 @@ fn compile_stmt(
                     let idx_info = locals.get(&idx_id).ok_or_else(|| anyhow!("Index variable not found"))?;
                     let current = builder.use_var(idx_info.var);
                     let incremented = builder.ins().iadd_imm(current, (UNROLL_FACTOR - 1) * stride);
-                    builder.def_var(idx_info.var, incremented);
+                    builder.def_local(idx_info.var, incremented);
                 } else if !optimized {
                     // Normal unrolling: compile body UNROLL_FACTOR times
                     for unroll_iter in 0..UNROLL_FACTOR {
@@ -14264,7 +16448,7 @@ fn compile_stmt(
                             let idx_info = locals.get(&idx_id).ok_or_else(|| anyhow!("Index variable not found"))?;
                             let current = builder.use_var(idx_info.var);
                             let incremented = builder.ins().iadd_imm(current, stride);
-                            builder.def_var(idx_info.var, incremented);
+                            builder.def_local(idx_info.var, incremented);
                         }
                     }
                 }
@@ -14315,7 +16499,7 @@ fn compile_stmt(
                             let accum_total = builder.ins().fadd(lane0, lane1);
 
                             let total = builder.ins().fadd(original_sum, accum_total);
-                            builder.def_var(sum_info.var, total);
+                            builder.def_local(sum_info.var, total);
                         }
                     }
                 }
@@ -14338,7 +16522,7 @@ fn compile_stmt(
                         let accum_total = builder.ins().fadd(sum0123, sum4567);
 
                         let total = builder.ins().fadd(original_sum, accum_total);
-                        builder.def_var(sum_info.var, total);
+                        builder.def_local(sum_info.var, total);
                     }
                 }
 
@@ -14389,7 +16573,7 @@ fn compile_stmt(
                     if let Some(idx_info) = locals.get_mut(&idx_id) {
                         let final_i32 = builder.use_var(idx_info.var);
                         let final_f64 = builder.ins().fcvt_from_sint(types::F64, final_i32);
-                        builder.def_var(orig_f64_var, final_f64);
+                        builder.def_local(orig_f64_var, final_f64);
                         idx_info.var = orig_f64_var;
                         idx_info.is_i32 = false;
                         idx_info.bounded_by_array = None;
@@ -14491,7 +16675,7 @@ fn compile_stmt(
                             let cache_var = Variable::new(*next_var);
                             *next_var += 1;
                             builder.declare_var(cache_var, types::I64);
-                            builder.def_var(cache_var, raw_ptr);
+                            builder.def_local(cache_var, raw_ptr);
 
                             cached_array_ids.push(*arr_id);
                             // We need to set it after collecting all, to avoid borrow conflict
@@ -14591,7 +16775,7 @@ fn compile_stmt(
                     if let Some(idx_info) = locals.get_mut(&idx_id) {
                         let final_i32 = builder.use_var(idx_info.var);
                         let final_f64 = builder.ins().fcvt_from_sint(types::F64, final_i32);
-                        builder.def_var(orig_f64_var, final_f64);
+                        builder.def_local(orig_f64_var, final_f64);
                         idx_info.var = orig_f64_var;
                         idx_info.is_i32 = false;
                         idx_info.bounded_by_array = None;
@@ -14691,6 +16875,22 @@ fn compile_stmt(
                     Stmt::Return(Some(expr)) | Stmt::Throw(expr) => {
                         collect_assigned_in_expr(expr, assigned);
                     }
+                    // Lowering nests the regions of try/catch/finally (see perry-hir's lower_try)
+                    Stmt::Try { body, catch, finally } => {
+                        collect_assigned_in_stmts(body, assigned);
+                        if let Some(catch_clause) = catch {
+                            collect_assigned_in_stmts(&catch_clause.body, assigned);
+                        }
+                        if let Some(finally_stmts) = finally {
+                            collect_assigned_in_stmts(finally_stmts, assigned);
+                        }
+                    }
+                    Stmt::Switch { discriminant, cases } => {
+                        collect_assigned_in_expr(discriminant, assigned);
+                        for case in cases {
+                            collect_assigned_in_stmts(&case.body, assigned);
+                        }
+                    }
                     _ => {}
                 }
             }
@@ -14755,10 +16955,16 @@ fn compile_stmt(
 
             // Call setjmp directly with the jmp_buf pointer
             // This is critical: setjmp must be called from this stack frame, not from inside a helper function
-            let setjmp_func = extern_funcs.get("setjmp")
-                .ok_or_else(|| anyhow!("setjmp not declared"))?;
-            let setjmp_ref = module.declare_func_in_func(*setjmp_func, builder.func);
-            let call = builder.ins().call(setjmp_ref, &[jmp_buf_ptr]);
+            let call = if let Some(setjmp_func) = extern_funcs.get("_setjmp") {
+                let setjmp_ref = module.declare_func_in_func(*setjmp_func, builder.func);
+                let no_frame = builder.ins().iconst(types::I64, 0);
+                builder.ins().call(setjmp_ref, &[jmp_buf_ptr, no_frame])
+            } else {
+                let setjmp_func = extern_funcs.get("setjmp")
+                    .ok_or_else(|| anyhow!("setjmp not declared"))?;
+                let setjmp_ref = module.declare_func_in_func(*setjmp_func, builder.func);
+                builder.ins().call(setjmp_ref, &[jmp_buf_ptr])
+            };
             let setjmp_result = builder.inst_results(call)[0];
 
             // Branch: if setjmp returned 0, go to try body; otherwise go to catch
@@ -14807,17 +17013,16 @@ fn compile_stmt(
                     }
                 }
             }
-            // Jump to finally (or merge if no finally)
+            // Pop this try level, then jump to finally (or merge if no finally)
             let current = builder.current_block().unwrap();
             if !is_block_filled(builder, current) {
+                let try_end_func = extern_funcs.get("js_try_end")
+                    .ok_or_else(|| anyhow!("js_try_end not declared"))?;
+                let try_end_ref = module.declare_func_in_func(*try_end_func, builder.func);
+                builder.ins().call(try_end_ref, &[]);
                 if finally.is_some() {
                     builder.ins().jump(finally_block, &[]);
                 } else {
-                    // Call js_try_end() before leaving
-                    let try_end_func = extern_funcs.get("js_try_end")
-                        .ok_or_else(|| anyhow!("js_try_end not declared"))?;
-                    let try_end_ref = module.declare_func_in_func(*try_end_func, builder.func);
-                    builder.ins().call(try_end_ref, &[]);
                     builder.ins().jump(merge_block, &[]);
                 }
             }
@@ -14832,7 +17037,7 @@ fn compile_stmt(
             for (local_id, (slot, slot_type, orig_var, was_i32)) in &try_var_slots {
                 let val = builder.ins().stack_load(*slot_type, *slot, 0);
                 // Always restore to the original variable (which has the correct declared type)
-                builder.def_var(*orig_var, val);
+                builder.def_local(*orig_var, val);
                 // Restore the LocalInfo to its original state
                 if let Some(info) = locals.get_mut(local_id) {
                     info.var = *orig_var;
@@ -14862,7 +17067,7 @@ fn compile_stmt(
                     let var = Variable::new(*next_var);
                     *next_var += 1;
                     builder.declare_var(var, types::F64);
-                    builder.def_var(var, exc_val);
+                    builder.def_local(var, exc_val);
                     locals.insert(*param_id, LocalInfo {
                         var,
                         name: Some(param_name.clone()),
@@ -14884,7 +17089,7 @@ fn compile_stmt(
                         bounded_by_array: None,
                         bounded_by_constant: None,
                         scalar_fields: None,
-                        squared_cache: None, product_cache: None, cached_array_ptr: None,
+                        squared_cache: None, product_cache: None, cached_array_ptr: None, union_classes: Vec::new(),
                     });
                 }
 
@@ -14942,12 +17147,7 @@ fn compile_stmt(
                 builder.ins().call(leave_finally_ref, &[]);
             }
 
-            // Call js_try_end() after finally
-            let try_end_func = extern_funcs.get("js_try_end")
-                .ok_or_else(|| anyhow!("js_try_end not declared"))?;
-            let try_end_ref = module.declare_func_in_func(*try_end_func, builder.func);
-            builder.ins().call(try_end_ref, &[]);
-
+            // Both paths into finally have already popped the try level
             let current = builder.current_block().unwrap();
             if !is_block_filled(builder, current) {
                 builder.ins().jump(merge_block, &[]);
@@ -14985,58 +17185,103 @@ fn compile_stmt(
             // Find the default case index (if any)
             let default_idx = cases.iter().position(|c| c.test.is_none());
 
-            // Create a block for each case's test (for non-default cases)
-            let mut test_blocks: Vec<_> = (0..cases.len()).map(|_| builder.create_block()).collect();
-
-            // Start by jumping to the first test block (or default/merge if no cases)
-            if cases.is_empty() {
-                builder.ins().jump(merge_block, &[]);
+            if let Some(tagged) = disc_str_ptr.and_then(|_| string_case_tags(cases)) {
+                // Every case is a string literal (a string union discriminant, as in reducers
+                // and event dispatch): jump on the discriminant's tag instead of comparing it
+                // with each case, then confirm the one candidate byte for byte
+                let disc_str_ptr = disc_str_ptr.unwrap();
+                let tag_func = extern_funcs.get("js_string_switch_tag")
+                    .ok_or_else(|| anyhow!("js_string_switch_tag not declared"))?;
+                let tag_ref = module.declare_func_in_func(*tag_func, builder.func);
+                let tag_call = builder.ins().call(tag_ref, &[disc_str_ptr]);
+                let tag = builder.inst_results(tag_call)[0];
+
+                let no_match = default_idx.map_or(merge_block, |d| case_blocks[d]);
+                let mut switch = Switch::new();
+                let mut confirm_blocks = Vec::new();
+                for (i, literal, tag) in tagged {
+                    let confirm = builder.create_block();
+                    switch.set_entry(tag as u128, confirm);
+                    confirm_blocks.push((confirm, i, literal));
+                }
+                switch.emit(builder, tag, no_match);
+
+                let equals_func = extern_funcs.get("js_string_equals_bytes")
+                    .ok_or_else(|| anyhow!("js_string_equals_bytes not declared"))?;
+                let equals_ref = module.declare_func_in_func(*equals_func, builder.func);
+                for (confirm, i, literal) in confirm_blocks {
+                    builder.switch_to_block(confirm);
+                    builder.seal_block(confirm);
+                    let bytes_ptr = if literal.is_empty() {
+                        builder.ins().iconst(types::I64, 0)
+                    } else {
+                        let data_id = module.declare_anonymous_data(false, false)?;
+                        let mut data_desc = DataDescription::new();
+                        data_desc.define(literal.as_bytes().to_vec().into_boxed_slice());
+                        module.define_data(data_id, &data_desc)?;
+                        let data = module.declare_data_in_func(data_id, builder.func);
+                        builder.ins().global_value(types::I64, data)
+                    };
+                    let len = builder.ins().iconst(types::I32, literal.len() as i64);
+                    let cmp_call = builder.ins().call(equals_ref, &[disc_str_ptr, bytes_ptr, len]);
+                    let result = builder.inst_results(cmp_call)[0];
+                    let eq = builder.ins().icmp_imm(IntCC::NotEqual, result, 0);
+                    builder.ins().brif(eq, case_blocks[i], &[], no_match, &[]);
+                }
             } else {
-                builder.ins().jump(test_blocks[0], &[]);
-            }
+                // Create a block for each case's test (for non-default cases)
+                let mut test_blocks: Vec<_> = (0..cases.len()).map(|_| builder.create_block()).collect();
 
-            // Generate test blocks - each tests its case value and jumps accordingly
-            for (i, case) in cases.iter().enumerate() {
-                builder.switch_to_block(test_blocks[i]);
-                builder.seal_block(test_blocks[i]);
+                // Start by jumping to the first test block (or default/merge if no cases)
+                if cases.is_empty() {
+                    builder.ins().jump(merge_block, &[]);
+                } else {
+                    builder.ins().jump(test_blocks[0], &[]);
+                }
 
-                if let Some(ref test_expr) = case.test {
-                    // Compare discriminant with case value
-                    let test_val_raw = compile_expr(builder, module, func_ids, closure_func_ids, func_wrapper_ids, extern_funcs, async_func_ids, classes, enums, func_param_types, func_union_params, func_return_types, func_hir_return_types, func_rest_param_index, imported_func_param_counts, locals, test_expr, this_ctx)?;
-                    let test_val = ensure_f64(builder, test_val_raw);
+                // Generate test blocks - each tests its case value and jumps accordingly
+                for (i, case) in cases.iter().enumerate() {
+                    builder.switch_to_block(test_blocks[i]);
+                    builder.seal_block(test_blocks[i]);
 
-                    let eq = if is_string_switch {
-                        // String comparison: use js_string_equals
-                        let get_str_ptr_func = extern_funcs.get("js_get_string_pointer_unified")
-                            .ok_or_else(|| anyhow!("js_get_string_pointer_unified not declared"))?;
-                        let get_str_ptr_ref = module.declare_func_in_func(*get_str_ptr_func, builder.func);
-                        let test_call = builder.ins().call(get_str_ptr_ref, &[test_val]);
-                        let test_str_ptr = builder.inst_results(test_call)[0];
-
-                        let equals_func = extern_funcs.get("js_string_equals")
-                            .ok_or_else(|| anyhow!("js_string_equals not declared"))?;
-                        let equals_ref = module.declare_func_in_func(*equals_func, builder.func);
-                        let cmp_call = builder.ins().call(equals_ref, &[disc_str_ptr.unwrap(), test_str_ptr]);
-                        let result = builder.inst_results(cmp_call)[0]; // i32 bool
-                        builder.ins().icmp_imm(IntCC::NotEqual, result, 0)
-                    } else {
-                        // Numeric comparison
-                        builder.ins().fcmp(FloatCC::Equal, disc_val, test_val)
-                    };
+                    if let Some(ref test_expr) = case.test {
+                        // Compare discriminant with case value
+                        let test_val_raw = compile_expr(builder, module, func_ids, closure_func_ids, func_wrapper_ids, extern_funcs, async_func_ids, classes, enums, func_param_types, func_union_params, func_return_types, func_hir_return_types, func_rest_param_index, imported_func_param_counts, locals, test_expr, this_ctx)?;
+                        let test_val = ensure_f64(builder, test_val_raw);
+
+                        let eq = if is_string_switch {
+                            // String comparison: use js_string_equals
+                            let get_str_ptr_func = extern_funcs.get("js_get_string_pointer_unified")
+                                .ok_or_else(|| anyhow!("js_get_string_pointer_unified not declared"))?;
+                            let get_str_ptr_ref = module.declare_func_in_func(*get_str_ptr_func, builder.func);
+                            let test_call = builder.ins().call(get_str_ptr_ref, &[test_val]);
+                            let test_str_ptr = builder.inst_results(test_call)[0];
+
+                            let equals_func = extern_funcs.get("js_string_equals")
+                                .ok_or_else(|| anyhow!("js_string_equals not declared"))?;
+                            let equals_ref = module.declare_func_in_func(*equals_func, builder.func);
+                            let cmp_call = builder.ins().call(equals_ref, &[disc_str_ptr.unwrap(), test_str_ptr]);
+                            let result = builder.inst_results(cmp_call)[0]; // i32 bool
+                            builder.ins().icmp_imm(IntCC::NotEqual, result, 0)
+                        } else {
+                            // Numeric comparison
+                            builder.ins().fcmp(FloatCC::Equal, disc_val, test_val)
+                        };
 
-                    // If equal, jump to case body; otherwise, try next case
-                    let next_test = if i + 1 < cases.len() {
-                        test_blocks[i + 1]
-                    } else if let Some(def_idx) = default_idx {
-                        case_blocks[def_idx]
+                        // If equal, jump to case body; otherwise, try next case
+                        let next_test = if i + 1 < cases.len() {
+                            test_blocks[i + 1]
+                        } else if let Some(def_idx) = default_idx {
+                            case_blocks[def_idx]
+                        } else {
+                            merge_block
+                        };
+                        builder.ins().brif(eq, case_blocks[i], &[], next_test, &[]);
                     } else {
-                        merge_block
-                    };
-                    builder.ins().brif(eq, case_blocks[i], &[], next_test, &[]);
-                } else {
-                    // Default case - will be reached via fallthrough from last non-matching test
-                    // Just jump to the case body
-                    builder.ins().jump(case_blocks[i], &[]);
+                        // Default case - will be reached via fallthrough from last non-matching test
+                        // Just jump to the case body
+                        builder.ins().jump(case_blocks[i], &[]);
+                    }
                 }
             }
 
@@ -15081,9 +17326,72 @@ fn compile_stmt(
     Ok(())
 }
 
+/// x86-64-v3 features the second version of a multiversioned function is compiled with
+const X86_64_V3_FEATURES: &[&str] = &[
+    "has_sse3", "has_ssse3", "has_sse41", "has_sse42", "has_popcnt", "has_avx", "has_avx2",
+    "has_fma", "has_bmi1", "has_bmi2", "has_lzcnt",
+];
+
+/// ISA whose IR `wasm` translates for wasm32 targets: 64-bit like the native targets, so
+/// the IR is built the same
+const WASM_SURROGATE_TRIPLE: &str = "x86_64-unknown-linux-gnu";
+
+/// The baseline ISA plus x86-64-v3's features, for `// @perry-opt: multiversion`. None
+/// when the target isn't x86_64 (aarch64's baseline already includes NEON) or the
+/// baseline has the features already.
+fn cpu_variant_isa(baseline: &dyn isa::TargetIsa) -> Result<Option<isa::OwnedTargetIsa>> {
+    let has_avx2 = baseline.isa_flags().iter().any(|flag| flag.name == "has_avx2" && flag.as_bool() == Some(true));
+    if baseline.name() != "x64" || has_avx2 {
+        return Ok(None);
+    }
+    let mut builder = isa::Builder::from_target_isa(baseline);
+    for feature in X86_64_V3_FEATURES {
+        builder.enable(feature).map_err(|e| anyhow!("Cranelift setting `{}`: {}", feature, e))?;
+    }
+    let variant = builder.finish(baseline.flags().clone()).map_err(|e| anyhow!("{}", e))?;
+    Ok(Some(variant))
+}
+
+/// Point the calls `func` makes to `from` at `to`
+fn retarget_calls(func: &mut cranelift_codegen::ir::Function, from: cranelift_module::FuncId, to: cranelift_module::FuncId) {
+    let refs: Vec<_> = func.params.user_named_funcs().iter()
+        .filter(|(_, name)| name.namespace == 0 && name.index == from.as_u32())
+        .map(|(name_ref, _)| name_ref)
+        .collect();
+    for name_ref in refs {
+        func.params.reset_user_func_name(name_ref, UserExternalName::new(0, to.as_u32()));
+    }
+}
+
+/// The string literal of every non-default case with its `js_string_switch_tag`, first
+/// occurrence only (a later duplicate is unreachable). None when a case isn't a string
+/// literal, or two literals share a tag and have to be told apart by comparison.
+fn string_case_tags(cases: &[SwitchCase]) -> Option<Vec<(usize, &str, u64)>> {
+    let mut tagged: Vec<(usize, &str, u64)> = Vec::new();
+    for (i, case) in cases.iter().enumerate() {
+        let literal = match &case.test {
+            None => continue,
+            Some(Expr::String(literal)) => literal.as_str(),
+            Some(_) => return None,
+        };
+        let tag = string_switch_tag(literal.as_bytes());
+        match tagged.iter().find(|(_, _, t)| *t == tag) {
+            Some((_, seen, _)) if *seen == literal => {}
+            Some(_) => return None,
+            None => tagged.push((i, literal, tag)),
+        }
+    }
+    Some(tagged)
+}
+
+/// FNV-1a over a string's bytes, as `js_string_switch_tag` computes it at runtime
+fn string_switch_tag(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3))
+}
+
 fn compile_expr(
     builder: &mut FunctionBuilder,
-    module: &mut ObjectModule,
+    module: &mut CodegenModule,
     func_ids: &HashMap<u32, cranelift_module::FuncId>,
     closure_func_ids: &HashMap<u32, cranelift_module::FuncId>,
     func_wrapper_ids: &HashMap<u32, cranelift_module::FuncId>,
@@ -15100,6 +17408,12 @@ fn compile_expr(
     expr: &Expr,
     this_ctx: Option<&ThisContext>,
 ) -> Result<Value> {
+    // Attribute the code that follows to this call, for debug info line tables
+    if let Expr::Call { span, .. } | Expr::New { span, .. } = expr {
+        if let Some(loc) = debuginfo::srcloc(*span) {
+            builder.set_srcloc(loc);
+        }
+    }
     match expr {
         Expr::Number(n) => Ok(builder.ins().f64const(*n)),
         Expr::Integer(n) => Ok(builder.ins().f64const(*n as f64)),
@@ -15229,49 +17543,8 @@ fn compile_expr(
                     Ok(builder.ins().f64const(*n as f64))
                 }
                 Some(EnumMemberValue::String(s)) => {
-                    // String enum - create a string at runtime
-                    let bytes = s.as_bytes();
-                    let len = bytes.len();
-
-                    if len == 0 {
-                        let alloc_func = extern_funcs.get("js_string_from_bytes")
-                            .ok_or_else(|| anyhow!("js_string_from_bytes not declared"))?;
-                        let alloc_ref = module.declare_func_in_func(*alloc_func, builder.func);
-                        let null_ptr = builder.ins().iconst(types::I64, 0);
-                        let zero_len = builder.ins().iconst(types::I32, 0);
-                        let call = builder.ins().call(alloc_ref, &[null_ptr, zero_len]);
-                        let str_ptr = builder.inst_results(call)[0];
-                        return Ok(builder.ins().bitcast(types::F64, MemFlags::new(), str_ptr));
-                    }
-
-                    let slot = builder.create_sized_stack_slot(StackSlotData::new(
-                        StackSlotKind::ExplicitSlot,
-                        len as u32,
-                        0,
-                    ));
-
-                    let mut offset = 0usize;
-                    while offset + 8 <= len {
-                        let chunk = &bytes[offset..offset + 8];
-                        let val = u64::from_le_bytes(chunk.try_into().unwrap());
-                        let val_i64 = builder.ins().iconst(types::I64, val as i64);
-                        builder.ins().stack_store(val_i64, slot, offset as i32);
-                        offset += 8;
-                    }
-                    while offset < len {
-                        let byte_val = builder.ins().iconst(types::I8, bytes[offset] as i64);
-                        builder.ins().stack_store(byte_val, slot, offset as i32);
-                        offset += 1;
-                    }
-
-                    let slot_addr = builder.ins().stack_addr(types::I64, slot, 0);
-                    let alloc_func = extern_funcs.get("js_string_from_bytes")
-                        .ok_or_else(|| anyhow!("js_string_from_bytes not declared"))?;
-                    let alloc_ref = module.declare_func_in_func(*alloc_func, builder.func);
-                    let len_val = builder.ins().iconst(types::I32, len as i64);
-                    let call = builder.ins().call(alloc_ref, &[slot_addr, len_val]);
-                    let str_ptr = builder.inst_results(call)[0];
-                    Ok(builder.ins().bitcast(types::F64, MemFlags::new(), str_ptr))
+                    // String enum - compiled like the equivalent string literal
+                    compile_expr(builder, module, func_ids, closure_func_ids, func_wrapper_ids, extern_funcs, async_func_ids, classes, enums, func_param_types, func_union_params, func_return_types, func_hir_return_types, func_rest_param_index, imported_func_param_counts, locals, &Expr::String(s.clone()), this_ctx)
                 }
                 None => Err(anyhow!("Unknown enum member: {}.{}", enum_name, member_name)),
             }
@@ -15336,8 +17609,8 @@ fn compile_expr(
             // Pad or truncate arguments to match expected count
             let mut final_call_args = converted_args;
             while final_call_args.len() < expected_param_count {
-                // Add undefined (f64 NaN) for missing optional arguments
-                final_call_args.push(builder.ins().f64const(f64::NAN));
+                // Add undefined for missing optional arguments
+                final_call_args.push(missing_arg(builder));
             }
             final_call_args.truncate(expected_param_count);
 
@@ -15550,6 +17823,23 @@ fn compile_expr(
             // Return as f64 (1.0 = true, 0.0 = false)
             Ok(builder.ins().fcvt_from_sint(types::F64, result_i32))
         }
+        Expr::FsCreateReadStream(path_expr) => {
+            // Compile the path expression and extract the string pointer
+            let path_val = compile_expr(builder, module, func_ids, closure_func_ids, func_wrapper_ids, extern_funcs, async_func_ids, classes, enums, func_param_types, func_union_params, func_return_types, func_hir_return_types, func_rest_param_index, imported_func_param_counts, locals, path_expr, this_ctx)?;
+            let path_f64 = ensure_f64(builder, path_val);
+            let get_str_func = extern_funcs.get("js_get_string_pointer_unified")
+                .ok_or_else(|| anyhow!("js_get_string_pointer_unified not declared"))?;
+            let get_str_ref = module.declare_func_in_func(*get_str_func, builder.func);
+            let call = builder.ins().call(get_str_ref, &[path_f64]);
+            let path_ptr = builder.inst_results(call)[0];
+
+            // Call js_fs_create_read_stream - returns the NaN-boxed stream handle
+            let func = extern_funcs.get("js_fs_create_read_stream")
+                .ok_or_else(|| anyhow!("js_fs_create_read_stream not declared"))?;
+            let func_ref = module.declare_func_in_func(*func, builder.func);
+            let call = builder.ins().call(func_ref, &[path_ptr]);
+            Ok(builder.inst_results(call)[0])
+        }
         Expr::FsMkdirSync(path_expr) => {
             // Compile the path expression
             let path_val = compile_expr(builder, module, func_ids, closure_func_ids, func_wrapper_ids, extern_funcs, async_func_ids, classes, enums, func_param_types, func_union_params, func_return_types, func_hir_return_types, func_rest_param_index, imported_func_param_counts, locals, path_expr, this_ctx)?;
@@ -15608,6 +17898,36 @@ fn compile_expr(
 
             Ok(builder.ins().bitcast(types::F64, MemFlags::new(), result_ptr))
         }
+        Expr::ImportMetaRuntime { property, relative_path } => {
+            let path_val = compile_expr(builder, module, func_ids, closure_func_ids, func_wrapper_ids, extern_funcs, async_func_ids, classes, enums, func_param_types, func_union_params, func_return_types, func_hir_return_types, func_rest_param_index, imported_func_param_counts, locals, &Expr::String(relative_path.clone()), this_ctx)?;
+            let property_val = builder.ins().iconst(types::I32, match property {
+                ImportMetaProperty::Url => 0,
+                ImportMetaProperty::Filename => 1,
+                ImportMetaProperty::Dirname => 2,
+            });
+
+            let func = extern_funcs.get("js_import_meta_exe_path")
+                .ok_or_else(|| anyhow!("js_import_meta_exe_path not declared"))?;
+            let func_ref = module.declare_func_in_func(*func, builder.func);
+            let call = builder.ins().call(func_ref, &[path_val, property_val]);
+            let result_ptr = builder.inst_results(call)[0];
+
+            Ok(builder.ins().bitcast(types::F64, MemFlags::new(), result_ptr))
+        }
+        Expr::ImportMetaResolve { module_path, exe_relative, specifier } => {
+            let path_val = compile_expr(builder, module, func_ids, closure_func_ids, func_wrapper_ids, extern_funcs, async_func_ids, classes, enums, func_param_types, func_union_params, func_return_types, func_hir_return_types, func_rest_param_index, imported_func_param_counts, locals, &Expr::String(module_path.clone()), this_ctx)?;
+            let spec_val = compile_expr(builder, module, func_ids, closure_func_ids, func_wrapper_ids, extern_funcs, async_func_ids, classes, enums, func_param_types, func_union_params, func_return_types, func_hir_return_types, func_rest_param_index, imported_func_param_counts, locals, specifier, this_ctx)?;
+            let spec_val = ensure_f64(builder, spec_val);
+            let exe_relative_val = builder.ins().iconst(types::I32, *exe_relative as i64);
+
+            let func = extern_funcs.get("js_import_meta_resolve")
+                .ok_or_else(|| anyhow!("js_import_meta_resolve not declared"))?;
+            let func_ref = module.declare_func_in_func(*func, builder.func);
+            let call = builder.ins().call(func_ref, &[path_val, spec_val, exe_relative_val]);
+            let result_ptr = builder.inst_results(call)[0];
+
+            Ok(builder.ins().bitcast(types::F64, MemFlags::new(), result_ptr))
+        }
         Expr::PathBasename(path_expr) => {
             let path_val = compile_expr(builder, module, func_ids, closure_func_ids, func_wrapper_ids, extern_funcs, async_func_ids, classes, enums, func_param_types, func_union_params, func_return_types, func_hir_return_types, func_rest_param_index, imported_func_param_counts, locals, path_expr, this_ctx)?;
             let path_ptr = ensure_i64(builder, path_val);
@@ -15658,13 +17978,17 @@ fn compile_expr(
             // Bitcast i64 bits to f64 value
             Ok(builder.ins().bitcast(types::F64, MemFlags::new(), result_bits))
         }
-        Expr::JsonStringify(value_expr) => {
+        Expr::JsonStringify(value_expr) | Expr::JsonCanonicalize(value_expr) => {
+            // JSON.canonicalize shares the boxing below; a string's JSON form is already canonical
+            let stringify = if matches!(expr, Expr::JsonCanonicalize(_)) { "js_json_canonicalize" } else { "js_json_stringify" };
+
             // Check if the value is a string expression - need to call specialized stringify
             fn is_string_value_expr(expr: &Expr, locals: &HashMap<LocalId, LocalInfo>) -> bool {
                 match expr {
                     Expr::String(_) => true,
                     Expr::LocalGet(id) => locals.get(id).map(|i| i.is_string).unwrap_or(false),
                     Expr::EnvGet(_) | Expr::EnvGetDynamic(_) | Expr::FsReadFileSync(_) |
+                    Expr::ImportMetaRuntime { .. } | Expr::ImportMetaResolve { .. } |
                     Expr::PathJoin(_, _) | Expr::PathDirname(_) | Expr::PathBasename(_) |
                     Expr::PathExtname(_) | Expr::PathResolve(_) => true,
                     Expr::Binary { op: BinaryOp::Add, left, right } => {
@@ -15690,8 +18014,8 @@ fn compile_expr(
                 Ok(builder.ins().bitcast(types::F64, MemFlags::new(), result_ptr))
             } else {
                 // Use generic stringify - handles numbers, booleans, etc.
-                let func = extern_funcs.get("js_json_stringify")
-                    .ok_or_else(|| anyhow!("js_json_stringify not declared"))?;
+                let func = extern_funcs.get(stringify)
+                    .ok_or_else(|| anyhow!("{} not declared", stringify))?;
                 let func_ref = module.declare_func_in_func(*func, builder.func);
 
                 // Ensure the value is f64 (js_json_stringify expects f64 JSValue)
@@ -16008,6 +18332,182 @@ fn compile_expr(
             let call = builder.ins().call(func_ref, &[]);
             Ok(builder.inst_results(call)[0])
         }
+        Expr::ProcessSetMemoryLimit { limit, policy } => {
+            let limit_val = compile_expr(builder, module, func_ids, closure_func_ids, func_wrapper_ids, extern_funcs, async_func_ids, classes, enums, func_param_types, func_union_params, func_return_types, func_hir_return_types, func_rest_param_index, imported_func_param_counts, locals, limit, this_ctx)?;
+            let limit_val = ensure_f64(builder, limit_val);
+            let policy_val = match policy {
+                Some(p) => {
+                    let val = compile_expr(builder, module, func_ids, closure_func_ids, func_wrapper_ids, extern_funcs, async_func_ids, classes, enums, func_param_types, func_union_params, func_return_types, func_hir_return_types, func_rest_param_index, imported_func_param_counts, locals, p, this_ctx)?;
+                    ensure_f64(builder, val)
+                }
+                None => {
+                    const TAG_UNDEFINED: u64 = 0x7FFC_0000_0000_0001;
+                    builder.ins().f64const(f64::from_bits(TAG_UNDEFINED))
+                }
+            };
+
+            let func = extern_funcs.get("js_memory_set_limit")
+                .ok_or_else(|| anyhow!("js_memory_set_limit not declared"))?;
+            let func_ref = module.declare_func_in_func(*func, builder.func);
+            builder.ins().call(func_ref, &[limit_val, policy_val]);
+            Ok(builder.ins().f64const(0.0))
+        }
+        Expr::ProcessOnLowMemory(callback) => {
+            let cb_val = compile_expr(builder, module, func_ids, closure_func_ids, func_wrapper_ids, extern_funcs, async_func_ids, classes, enums, func_param_types, func_union_params, func_return_types, func_hir_return_types, func_rest_param_index, imported_func_param_counts, locals, callback, this_ctx)?;
+            let cb_ptr = ensure_i64(builder, cb_val);
+
+            let func = extern_funcs.get("js_memory_on_low")
+                .ok_or_else(|| anyhow!("js_memory_on_low not declared"))?;
+            let func_ref = module.declare_func_in_func(*func, builder.func);
+            builder.ins().call(func_ref, &[cb_ptr]);
+            Ok(builder.ins().f64const(0.0))
+        }
+        Expr::ProcessStdioWrite { fd, data, callback } => {
+            let data_val = compile_expr(builder, module, func_ids, closure_func_ids, func_wrapper_ids, extern_funcs, async_func_ids, classes, enums, func_param_types, func_union_params, func_return_types, func_hir_return_types, func_rest_param_index, imported_func_param_counts, locals, data, this_ctx)?;
+            let data_val = if builder.func.dfg.value_type(data_val) == types::I64 {
+                // Raw string pointers are NaN-boxed so the runtime can tell them from numbers
+                let nanbox_func = extern_funcs.get("js_nanbox_string")
+                    .ok_or_else(|| anyhow!("js_nanbox_string not declared"))?;
+                let nanbox_ref = module.declare_func_in_func(*nanbox_func, builder.func);
+                let call = builder.ins().call(nanbox_ref, &[data_val]);
+                builder.inst_results(call)[0]
+            } else {
+                ensure_f64(builder, data_val)
+            };
+            let cb_ptr = match callback {
+                Some(cb) => {
+                    let cb_val = compile_expr(builder, module, func_ids, closure_func_ids, func_wrapper_ids, extern_funcs, async_func_ids, classes, enums, func_param_types, func_union_params, func_return_types, func_hir_return_types, func_rest_param_index, imported_func_param_counts, locals, cb, this_ctx)?;
+                    ensure_i64(builder, cb_val)
+                }
+                None => builder.ins().iconst(types::I64, 0),
+            };
+            let fd_val = builder.ins().iconst(types::I32, *fd as i64);
+
+            let func = extern_funcs.get("js_stdio_write")
+                .ok_or_else(|| anyhow!("js_stdio_write not declared"))?;
+            let func_ref = module.declare_func_in_func(*func, builder.func);
+            let call = builder.ins().call(func_ref, &[fd_val, data_val, cb_ptr]);
+            Ok(builder.inst_results(call)[0])
+        }
+        Expr::ProcessStdinRead => {
+            let func = extern_funcs.get("js_stdin_read")
+                .ok_or_else(|| anyhow!("js_stdin_read not declared"))?;
+            let func_ref = module.declare_func_in_func(*func, builder.func);
+            let call = builder.ins().call(func_ref, &[]);
+            Ok(builder.inst_results(call)[0])
+        }
+        Expr::ProcessStdinOn { event, listener } => {
+            let event_val = compile_expr(builder, module, func_ids, closure_func_ids, func_wrapper_ids, extern_funcs, async_func_ids, classes, enums, func_param_types, func_union_params, func_return_types, func_hir_return_types, func_rest_param_index, imported_func_param_counts, locals, event, this_ctx)?;
+            let event_val = if builder.func.dfg.value_type(event_val) == types::I64 {
+                let nanbox_func = extern_funcs.get("js_nanbox_string")
+                    .ok_or_else(|| anyhow!("js_nanbox_string not declared"))?;
+                let nanbox_ref = module.declare_func_in_func(*nanbox_func, builder.func);
+                let call = builder.ins().call(nanbox_ref, &[event_val]);
+                builder.inst_results(call)[0]
+            } else {
+                ensure_f64(builder, event_val)
+            };
+            let listener_val = compile_expr(builder, module, func_ids, closure_func_ids, func_wrapper_ids, extern_funcs, async_func_ids, classes, enums, func_param_types, func_union_params, func_return_types, func_hir_return_types, func_rest_param_index, imported_func_param_counts, locals, listener, this_ctx)?;
+            let listener_ptr = ensure_i64(builder, listener_val);
+
+            let func = extern_funcs.get("js_stdin_on")
+                .ok_or_else(|| anyhow!("js_stdin_on not declared"))?;
+            let func_ref = module.declare_func_in_func(*func, builder.func);
+            builder.ins().call(func_ref, &[event_val, listener_ptr]);
+            Ok(builder.ins().f64const(0.0))
+        }
+        Expr::ProcessStdinPipe(fd) => {
+            let fd_val = builder.ins().iconst(types::I32, *fd as i64);
+            let func = extern_funcs.get("js_stdin_pipe")
+                .ok_or_else(|| anyhow!("js_stdin_pipe not declared"))?;
+            let func_ref = module.declare_func_in_func(*func, builder.func);
+            builder.ins().call(func_ref, &[fd_val]);
+            Ok(builder.ins().f64const(0.0))
+        }
+        Expr::DisposeResource { resource, is_async } => {
+            // Class instances with a known type: call the [Symbol.dispose] method directly
+            if let Expr::LocalGet(id) = resource.as_ref() {
+                let class_meta = locals.get(id)
+                    .and_then(|info| info.class_name.as_ref())
+                    .and_then(|class_name| classes.get(class_name));
+                if let Some(class_meta) = class_meta {
+                    let key = if *is_async && class_meta.method_ids.contains_key(SYMBOL_ASYNC_DISPOSE_KEY) {
+                        Some(SYMBOL_ASYNC_DISPOSE_KEY)
+                    } else if class_meta.method_ids.contains_key(SYMBOL_DISPOSE_KEY) {
+                        Some(SYMBOL_DISPOSE_KEY)
+                    } else {
+                        None
+                    };
+                    if let Some(key) = key {
+                        let call = Expr::Call {
+                            callee: Box::new(Expr::PropertyGet { object: resource.clone(), property: key.to_string() }),
+                            args: vec![],
+                            type_args: vec![],
+                            span: Span::DUMMY,
+                        };
+                        return compile_expr(builder, module, func_ids, closure_func_ids, func_wrapper_ids, extern_funcs, async_func_ids, classes, enums, func_param_types, func_union_params, func_return_types, func_hir_return_types, func_rest_param_index, imported_func_param_counts, locals, &call, this_ctx);
+                    }
+                }
+            }
+
+            // Everything else is looked up at runtime (object literals, DisposableStack, null/undefined)
+            let val = compile_expr(builder, module, func_ids, closure_func_ids, func_wrapper_ids, extern_funcs, async_func_ids, classes, enums, func_param_types, func_union_params, func_return_types, func_hir_return_types, func_rest_param_index, imported_func_param_counts, locals, resource, this_ctx)?;
+            let val = ensure_f64(builder, val);
+            let is_async_val = builder.ins().iconst(types::I32, *is_async as i64);
+            let func = extern_funcs.get("js_dispose_resource")
+                .ok_or_else(|| anyhow!("js_dispose_resource not declared"))?;
+            let func_ref = module.declare_func_in_func(*func, builder.func);
+            let call = builder.ins().call(func_ref, &[val, is_async_val]);
+            Ok(builder.inst_results(call)[0])
+        }
+        Expr::DisposableStackNew => {
+            let func = extern_funcs.get("js_disposable_stack_new")
+                .ok_or_else(|| anyhow!("js_disposable_stack_new not declared"))?;
+            let func_ref = module.declare_func_in_func(*func, builder.func);
+            let call = builder.ins().call(func_ref, &[]);
+            Ok(builder.inst_results(call)[0])
+        }
+        Expr::DisposableStackAdopt { stack, value, on_dispose } => {
+            let stack_val = compile_expr(builder, module, func_ids, closure_func_ids, func_wrapper_ids, extern_funcs, async_func_ids, classes, enums, func_param_types, func_union_params, func_return_types, func_hir_return_types, func_rest_param_index, imported_func_param_counts, locals, stack, this_ctx)?;
+            let stack_val = ensure_f64(builder, stack_val);
+            let value_val = compile_expr(builder, module, func_ids, closure_func_ids, func_wrapper_ids, extern_funcs, async_func_ids, classes, enums, func_param_types, func_union_params, func_return_types, func_hir_return_types, func_rest_param_index, imported_func_param_counts, locals, value, this_ctx)?;
+            let value_val = ensure_f64(builder, value_val);
+            let cb_val = compile_expr(builder, module, func_ids, closure_func_ids, func_wrapper_ids, extern_funcs, async_func_ids, classes, enums, func_param_types, func_union_params, func_return_types, func_hir_return_types, func_rest_param_index, imported_func_param_counts, locals, on_dispose, this_ctx)?;
+            let cb_val = ensure_f64(builder, cb_val);
+
+            let func = extern_funcs.get("js_disposable_stack_adopt")
+                .ok_or_else(|| anyhow!("js_disposable_stack_adopt not declared"))?;
+            let func_ref = module.declare_func_in_func(*func, builder.func);
+            let call = builder.ins().call(func_ref, &[stack_val, value_val, cb_val]);
+            Ok(builder.inst_results(call)[0])
+        }
+        Expr::DisposableStackDefer { stack, on_dispose } => {
+            let stack_val = compile_expr(builder, module, func_ids, closure_func_ids, func_wrapper_ids, extern_funcs, async_func_ids, classes, enums, func_param_types, func_union_params, func_return_types, func_hir_return_types, func_rest_param_index, imported_func_param_counts, locals, stack, this_ctx)?;
+            let stack_val = ensure_f64(builder, stack_val);
+            let cb_val = compile_expr(builder, module, func_ids, closure_func_ids, func_wrapper_ids, extern_funcs, async_func_ids, classes, enums, func_param_types, func_union_params, func_return_types, func_hir_return_types, func_rest_param_index, imported_func_param_counts, locals, on_dispose, this_ctx)?;
+            let cb_val = ensure_f64(builder, cb_val);
+
+            let func = extern_funcs.get("js_disposable_stack_defer")
+                .ok_or_else(|| anyhow!("js_disposable_stack_defer not declared"))?;
+            let func_ref = module.declare_func_in_func(*func, builder.func);
+            let call = builder.ins().call(func_ref, &[stack_val, cb_val]);
+            Ok(builder.inst_results(call)[0])
+        }
+        Expr::DisposableStackDispose(stack) | Expr::DisposableStackMove(stack) | Expr::DisposableStackDisposed(stack) => {
+            let func_name = match expr {
+                Expr::DisposableStackDispose(_) => "js_disposable_stack_dispose",
+                Expr::DisposableStackMove(_) => "js_disposable_stack_move",
+                _ => "js_disposable_stack_disposed",
+            };
+            let stack_val = compile_expr(builder, module, func_ids, closure_func_ids, func_wrapper_ids, extern_funcs, async_func_ids, classes, enums, func_param_types, func_union_params, func_return_types, func_hir_return_types, func_rest_param_index, imported_func_param_counts, locals, stack, this_ctx)?;
+            let stack_val = ensure_f64(builder, stack_val);
+
+            let func = extern_funcs.get(func_name)
+                .ok_or_else(|| anyhow!("{} not declared", func_name))?;
+            let func_ref = module.declare_func_in_func(*func, builder.func);
+            let call = builder.ins().call(func_ref, &[stack_val]);
+            Ok(builder.inst_results(call)[0])
+        }
         Expr::OsType => {
             let func = extern_funcs.get("js_os_type")
                 .ok_or_else(|| anyhow!("js_os_type not declared"))?;
@@ -16410,7 +18910,11 @@ fn compile_expr(
             Ok(builder.ins().f64const(f64::NAN))
         }
         // Fetch operations
-        Expr::FetchWithOptions { url, method, body, headers } => {
+        Expr::FetchWithOptions { url, method, body, headers, stream_body, on_upload_progress } => {
+            // Streamed uploads pass the raw body value so the runtime can tell a
+            // ReadStream handle from a string
+            let streamed = *stream_body || on_upload_progress.is_some();
+
             // Get string pointer extraction function
             let get_str_func = extern_funcs.get("js_get_string_pointer_unified")
                 .ok_or_else(|| anyhow!("js_get_string_pointer_unified not declared"))?;
@@ -16428,14 +18932,18 @@ fn compile_expr(
             let method_call = builder.ins().call(get_str_ref, &[method_f64]);
             let method_ptr = builder.inst_results(method_call)[0];
 
-            // Compile body (can be Undefined or a string)
+            // Compile body (can be Undefined, a string, or a ReadStream when streamed)
             let body_ptr = if matches!(body.as_ref(), Expr::Undefined) {
                 builder.ins().iconst(types::I64, 0)
             } else {
                 let body_val = compile_expr(builder, module, func_ids, closure_func_ids, func_wrapper_ids, extern_funcs, async_func_ids, classes, enums, func_param_types, func_union_params, func_return_types, func_hir_return_types, func_rest_param_index, imported_func_param_counts, locals, body, this_ctx)?;
                 let body_f64 = ensure_f64(builder, body_val);
-                let body_call = builder.ins().call(get_str_ref, &[body_f64]);
-                builder.inst_results(body_call)[0]
+                if streamed {
+                    builder.ins().bitcast(types::I64, MemFlags::new(), body_f64)
+                } else {
+                    let body_call = builder.ins().call(get_str_ref, &[body_f64]);
+                    builder.inst_results(body_call)[0]
+                }
             };
 
             // Build headers JSON string at runtime to support dynamic values
@@ -16535,6 +19043,30 @@ fn compile_expr(
             let call = builder.ins().call(string_concat_ref, &[result, close_str]);
             let headers_json = builder.inst_results(call)[0];
 
+            if streamed {
+                // Compile the progress callback and extract the closure pointer
+                let progress_ptr = if let Some(cb) = on_upload_progress {
+                    let cb_val = compile_expr(builder, module, func_ids, closure_func_ids, func_wrapper_ids, extern_funcs, async_func_ids, classes, enums, func_param_types, func_union_params, func_return_types, func_hir_return_types, func_rest_param_index, imported_func_param_counts, locals, cb, this_ctx)?;
+                    let cb_f64 = ensure_f64(builder, cb_val);
+                    let get_ptr_func = extern_funcs.get("js_nanbox_get_pointer")
+                        .ok_or_else(|| anyhow!("js_nanbox_get_pointer not declared"))?;
+                    let get_ptr_ref = module.declare_func_in_func(*get_ptr_func, builder.func);
+                    let call = builder.ins().call(get_ptr_ref, &[cb_f64]);
+                    builder.inst_results(call)[0]
+                } else {
+                    builder.ins().iconst(types::I64, 0)
+                };
+                let body_f64 = builder.ins().bitcast(types::F64, MemFlags::new(), body_ptr);
+
+                // Call js_fetch_upload_stream(url, method, body, headers_json, on_progress)
+                let func = extern_funcs.get("js_fetch_upload_stream")
+                    .ok_or_else(|| anyhow!("js_fetch_upload_stream not declared"))?;
+                let func_ref = module.declare_func_in_func(*func, builder.func);
+                let call = builder.ins().call(func_ref, &[url_ptr, method_ptr, body_f64, headers_json, progress_ptr]);
+                let result_ptr = builder.inst_results(call)[0];
+                return Ok(builder.ins().bitcast(types::F64, MemFlags::new(), result_ptr));
+            }
+
             // Call js_fetch_with_options(url, method, body, headers_json)
             let func = extern_funcs.get("js_fetch_with_options")
                 .ok_or_else(|| anyhow!("js_fetch_with_options not declared"))?;
@@ -16691,21 +19223,15 @@ fn compile_expr(
             Ok(builder.inst_results(call)[0])
         }
         // Error operations
-        Expr::ErrorNew(message_opt) => {
-            match message_opt {
+        Expr::ErrorNew { message, span } => {
+            let error_ptr = match message {
                 None => {
                     // new Error() - empty message
                     let func = extern_funcs.get("js_error_new")
                         .ok_or_else(|| anyhow!("js_error_new not declared"))?;
                     let func_ref = module.declare_func_in_func(*func, builder.func);
                     let call = builder.ins().call(func_ref, &[]);
-                    let error_ptr = builder.inst_results(call)[0];
-                    // NaN-box the error pointer so typeof recognizes it as an object
-                    let nanbox_func = extern_funcs.get("js_nanbox_pointer")
-                        .ok_or_else(|| anyhow!("js_nanbox_pointer not declared"))?;
-                    let nanbox_ref = module.declare_func_in_func(*nanbox_func, builder.func);
-                    let call = builder.ins().call(nanbox_ref, &[error_ptr]);
-                    Ok(builder.inst_results(call)[0])
+                    builder.inst_results(call)[0]
                 }
                 Some(msg_expr) => {
                     // new Error(message)
@@ -16721,15 +19247,29 @@ fn compile_expr(
                         .ok_or_else(|| anyhow!("js_error_new_with_message not declared"))?;
                     let func_ref = module.declare_func_in_func(*func, builder.func);
                     let call = builder.ins().call(func_ref, &[msg_ptr]);
-                    let error_ptr = builder.inst_results(call)[0];
-                    // NaN-box the error pointer so typeof recognizes it as an object
-                    let nanbox_func = extern_funcs.get("js_nanbox_pointer")
-                        .ok_or_else(|| anyhow!("js_nanbox_pointer not declared"))?;
-                    let nanbox_ref = module.declare_func_in_func(*nanbox_func, builder.func);
-                    let call = builder.ins().call(nanbox_ref, &[error_ptr]);
-                    Ok(builder.inst_results(call)[0])
+                    builder.inst_results(call)[0]
                 }
+            };
+            // Record where the error was created in its stack
+            if let Some(location) = source_location(*span) {
+                let location_val = compile_expr(builder, module, func_ids, closure_func_ids, func_wrapper_ids, extern_funcs, async_func_ids, classes, enums, func_param_types, func_union_params, func_return_types, func_hir_return_types, func_rest_param_index, imported_func_param_counts, locals, &Expr::String(location), this_ctx)?;
+                let location_f64 = ensure_f64(builder, location_val);
+                let get_str_ptr_func = extern_funcs.get("js_get_string_pointer_unified")
+                    .ok_or_else(|| anyhow!("js_get_string_pointer_unified not declared"))?;
+                let get_str_ptr_ref = module.declare_func_in_func(*get_str_ptr_func, builder.func);
+                let str_call = builder.ins().call(get_str_ptr_ref, &[location_f64]);
+                let location_ptr = builder.inst_results(str_call)[0];
+                let func = extern_funcs.get("js_error_set_location")
+                    .ok_or_else(|| anyhow!("js_error_set_location not declared"))?;
+                let func_ref = module.declare_func_in_func(*func, builder.func);
+                builder.ins().call(func_ref, &[error_ptr, location_ptr]);
             }
+            // NaN-box the error pointer so typeof recognizes it as an object
+            let nanbox_func = extern_funcs.get("js_nanbox_pointer")
+                .ok_or_else(|| anyhow!("js_nanbox_pointer not declared"))?;
+            let nanbox_ref = module.declare_func_in_func(*nanbox_func, builder.func);
+            let call = builder.ins().call(nanbox_ref, &[error_ptr]);
+            Ok(builder.inst_results(call)[0])
         }
         Expr::ErrorMessage(error_expr) => {
             let error_val = compile_expr(builder, module, func_ids, closure_func_ids, func_wrapper_ids, extern_funcs, async_func_ids, classes, enums, func_param_types, func_union_params, func_return_types, func_hir_return_types, func_rest_param_index, imported_func_param_counts, locals, error_expr, this_ctx)?;
@@ -17239,7 +19779,7 @@ fn compile_expr(
             } else {
                 builder.ins().bitcast(types::F64, MemFlags::new(), new_arr_ptr)
             };
-            builder.def_var(info.var, store_val);
+            builder.def_local(info.var, store_val);
 
             // Get and return the new length
             let len_func = extern_funcs.get("js_array_length")
@@ -17296,7 +19836,7 @@ fn compile_expr(
             } else {
                 builder.ins().bitcast(types::F64, MemFlags::new(), new_arr_ptr)
             };
-            builder.def_var(info.var, store_val);
+            builder.def_local(info.var, store_val);
 
             // Get and return the new length
             let len_func = extern_funcs.get("js_array_length")
@@ -17546,10 +20086,10 @@ fn compile_expr(
             let new_arr_ptr = builder.ins().load(types::I64, MemFlags::new(), out_arr_addr, 0);
             // Variable is i64 only if is_pointer && !is_union (see variable declaration logic)
             if info.is_pointer && !info.is_union {
-                builder.def_var(info.var, new_arr_ptr);
+                builder.def_local(info.var, new_arr_ptr);
             } else {
                 let new_arr_f64 = builder.ins().bitcast(types::F64, MemFlags::new(), new_arr_ptr);
-                builder.def_var(info.var, new_arr_f64);
+                builder.def_local(info.var, new_arr_f64);
             }
 
             // Return deleted elements array as f64
@@ -17980,6 +20520,44 @@ fn compile_expr(
             // clear() returns undefined (0.0)
             Ok(builder.ins().f64const(0.0))
         }
+        Expr::MapKeys(map) | Expr::MapValues(map) | Expr::MapEntries(map) => {
+            // map.keys() / map.values() / map.entries() - snapshot the map into a new array
+            // Get map pointer - if it's a local map variable, use directly as i64
+            let map_ptr = if let Expr::LocalGet(id) = map.as_ref() {
+                if let Some(info) = locals.get(id) {
+                    if (info.is_map || info.is_pointer) && !info.is_union {
+                        builder.use_var(info.var)
+                    } else {
+                        let map_val = compile_expr(builder, module, func_ids, closure_func_ids, func_wrapper_ids, extern_funcs, async_func_ids, classes, enums, func_param_types, func_union_params, func_return_types, func_hir_return_types, func_rest_param_index, imported_func_param_counts, locals, map, this_ctx)?;
+                        builder.ins().bitcast(types::I64, MemFlags::new(), map_val)
+                    }
+                } else {
+                    let map_val = compile_expr(builder, module, func_ids, closure_func_ids, func_wrapper_ids, extern_funcs, async_func_ids, classes, enums, func_param_types, func_union_params, func_return_types, func_hir_return_types, func_rest_param_index, imported_func_param_counts, locals, map, this_ctx)?;
+                    builder.ins().bitcast(types::I64, MemFlags::new(), map_val)
+                }
+            } else {
+                let map_val = compile_expr(builder, module, func_ids, closure_func_ids, func_wrapper_ids, extern_funcs, async_func_ids, classes, enums, func_param_types, func_union_params, func_return_types, func_hir_return_types, func_rest_param_index, imported_func_param_counts, locals, map, this_ctx)?;
+                builder.ins().bitcast(types::I64, MemFlags::new(), map_val)
+            };
+
+            let func_name = match expr {
+                Expr::MapKeys(_) => "js_map_keys",
+                Expr::MapValues(_) => "js_map_values",
+                _ => "js_map_entries",
+            };
+            let func = extern_funcs.get(func_name)
+                .ok_or_else(|| anyhow!("{} not declared", func_name))?;
+            let func_ref = module.declare_func_in_func(*func, builder.func);
+            let call = builder.ins().call(func_ref, &[map_ptr]);
+            let result_ptr = builder.inst_results(call)[0];
+
+            // NaN-box the array pointer with POINTER_TAG for proper handling
+            let nanbox_func = extern_funcs.get("js_nanbox_pointer")
+                .ok_or_else(|| anyhow!("js_nanbox_pointer not declared"))?;
+            let nanbox_ref = module.declare_func_in_func(*nanbox_func, builder.func);
+            let nanbox_call = builder.ins().call(nanbox_ref, &[result_ptr]);
+            Ok(builder.inst_results(nanbox_call)[0])
+        }
         // Set operations
         Expr::SetNew => {
             // Allocate a new empty set
@@ -18034,7 +20612,7 @@ fn compile_expr(
             } else {
                 builder.ins().bitcast(types::F64, MemFlags::new(), new_set_ptr)
             };
-            builder.def_var(info.var, store_val);
+            builder.def_local(info.var, store_val);
 
             // Return as f64 (NaN-boxed pointer) - for chaining
             Ok(builder.ins().bitcast(types::F64, MemFlags::new(), new_set_ptr))
@@ -18188,6 +20766,40 @@ fn compile_expr(
             // clear() returns undefined (0.0)
             Ok(builder.ins().f64const(0.0))
         }
+        Expr::SetValues(set) => {
+            // set.values() - snapshot the set into a new array
+            // Get set pointer - if it's a local set variable, use directly as i64
+            let set_ptr = if let Expr::LocalGet(id) = set.as_ref() {
+                if let Some(info) = locals.get(id) {
+                    if (info.is_set || info.is_pointer) && !info.is_union {
+                        builder.use_var(info.var)
+                    } else {
+                        let set_val = compile_expr(builder, module, func_ids, closure_func_ids, func_wrapper_ids, extern_funcs, async_func_ids, classes, enums, func_param_types, func_union_params, func_return_types, func_hir_return_types, func_rest_param_index, imported_func_param_counts, locals, set, this_ctx)?;
+                        builder.ins().bitcast(types::I64, MemFlags::new(), set_val)
+                    }
+                } else {
+                    let set_val = compile_expr(builder, module, func_ids, closure_func_ids, func_wrapper_ids, extern_funcs, async_func_ids, classes, enums, func_param_types, func_union_params, func_return_types, func_hir_return_types, func_rest_param_index, imported_func_param_counts, locals, set, this_ctx)?;
+                    builder.ins().bitcast(types::I64, MemFlags::new(), set_val)
+                }
+            } else {
+                let set_val = compile_expr(builder, module, func_ids, closure_func_ids, func_wrapper_ids, extern_funcs, async_func_ids, classes, enums, func_param_types, func_union_params, func_return_types, func_hir_return_types, func_rest_param_index, imported_func_param_counts, locals, set, this_ctx)?;
+                builder.ins().bitcast(types::I64, MemFlags::new(), set_val)
+            };
+
+            let func_name = "js_set_values";
+            let func = extern_funcs.get(func_name)
+                .ok_or_else(|| anyhow!("{} not declared", func_name))?;
+            let func_ref = module.declare_func_in_func(*func, builder.func);
+            let call = builder.ins().call(func_ref, &[set_ptr]);
+            let result_ptr = builder.inst_results(call)[0];
+
+            // NaN-box the array pointer with POINTER_TAG for proper handling
+            let nanbox_func = extern_funcs.get("js_nanbox_pointer")
+                .ok_or_else(|| anyhow!("js_nanbox_pointer not declared"))?;
+            let nanbox_ref = module.declare_func_in_func(*nanbox_func, builder.func);
+            let nanbox_call = builder.ins().call(nanbox_ref, &[result_ptr]);
+            Ok(builder.inst_results(nanbox_call)[0])
+        }
         Expr::LocalGet(id) => {
             let info = locals.get(id)
                 .ok_or_else(|| {
@@ -18234,8 +20846,9 @@ fn compile_expr(
                     Expr::String(_) => true,
                     Expr::EnvGet(_) | Expr::EnvGetDynamic(_) => true,
                     Expr::FsReadFileSync(_) => true,
+                    Expr::ImportMetaRuntime { .. } | Expr::ImportMetaResolve { .. } |
                     Expr::PathJoin(_, _) | Expr::PathDirname(_) | Expr::PathBasename(_) |
-                    Expr::PathExtname(_) | Expr::PathResolve(_) | Expr::JsonStringify(_) => true,
+                    Expr::PathExtname(_) | Expr::PathResolve(_) | Expr::JsonStringify(_) | Expr::JsonCanonicalize(_) => true,
                     // OS operations that return strings
                     Expr::OsPlatform | Expr::OsArch | Expr::OsHostname | Expr::OsHomedir |
                     Expr::OsTmpdir | Expr::OsType | Expr::OsRelease | Expr::OsEOL => true,
@@ -18274,7 +20887,7 @@ fn compile_expr(
                                             let current = builder.use_var(info.var);
                                             let rhs = builder.use_var(right_info.var);
                                             let new_val = builder.ins().iadd(current, rhs);
-                                            builder.def_var(info.var, new_val);
+                                            builder.def_local(info.var, new_val);
                                             return Ok(builder.ins().fcvt_from_sint(types::F64, new_val));
                                         } else if !right_info.is_boxed && !right_info.is_string && !right_info.is_bigint {
                                             // y is f64: convert to i32 and use iadd (faster than f64 arithmetic)
@@ -18283,7 +20896,7 @@ fn compile_expr(
                                             let rhs_f64 = ensure_f64(builder, rhs_val);
                                             let rhs_i32 = builder.ins().fcvt_to_sint(types::I32, rhs_f64);
                                             let new_val = builder.ins().iadd(current, rhs_i32);
-                                            builder.def_var(info.var, new_val);
+                                            builder.def_local(info.var, new_val);
                                             return Ok(builder.ins().fcvt_from_sint(types::F64, new_val));
                                         }
                                     }
@@ -18296,7 +20909,7 @@ fn compile_expr(
                                 // Use iadd_imm for x = x + constant
                                 let current = builder.use_var(info.var);
                                 let new_val = builder.ins().iadd_imm(current, delta);
-                                builder.def_var(info.var, new_val);
+                                builder.def_local(info.var, new_val);
                                 // Return f64 for expression value
                                 return Ok(builder.ins().fcvt_from_sint(types::F64, new_val));
                             }
@@ -18307,7 +20920,7 @@ fn compile_expr(
                 let val = compile_expr(builder, module, func_ids, closure_func_ids, func_wrapper_ids, extern_funcs, async_func_ids, classes, enums, func_param_types, func_union_params, func_return_types, func_hir_return_types, func_rest_param_index, imported_func_param_counts, locals, value, this_ctx)?;
                 let val_f64 = ensure_f64(builder, val);
                 let i32_val = builder.ins().fcvt_to_sint(types::I32, val_f64);
-                builder.def_var(info.var, i32_val);
+                builder.def_local(info.var, i32_val);
                 return Ok(val);
             }
 
@@ -18379,7 +20992,7 @@ fn compile_expr(
                             let new_f64 = builder.inst_results(nanbox_call)[0];
 
                             // Update the variable with the (possibly new) pointer
-                            builder.def_var(info.var, new_f64);
+                            builder.def_local(info.var, new_f64);
 
                             // Return as f64 for consistency
                             return Ok(new_f64);
@@ -18406,19 +21019,19 @@ fn compile_expr(
                 let nanbox_ref = module.declare_func_in_func(*nanbox_func, builder.func);
                 let call = builder.ins().call(nanbox_ref, &[ptr]);
                 let boxed_val = builder.inst_results(call)[0];
-                builder.def_var(info.var, boxed_val);
+                builder.def_local(info.var, boxed_val);
                 Ok(boxed_val)
             } else if info.is_pointer && !info.is_union {
                 // Variable is stored as i64 (pointer type without union)
                 let val_type = builder.func.dfg.value_type(val);
                 if val_type == types::I64 {
                     // Already I64, just store directly
-                    builder.def_var(info.var, val);
+                    builder.def_local(info.var, val);
                     Ok(val)
                 } else {
                     // Need to bitcast
                     let ptr = builder.ins().bitcast(types::I64, MemFlags::new(), val);
-                    builder.def_var(info.var, ptr);
+                    builder.def_local(info.var, ptr);
                     Ok(val)
                 }
             } else {
@@ -18448,12 +21061,12 @@ fn compile_expr(
                 } else {
                     val
                 };
-                builder.def_var(info.var, converted_val);
+                builder.def_local(info.var, converted_val);
                 // OPTIMIZATION: Update i32 shadow for integer variables
                 if let Some(shadow_var) = info.i32_shadow {
                     let val_f64 = ensure_f64(builder, val);
                     let i32_val = builder.ins().fcvt_to_sint(types::I32, val_f64);
-                    builder.def_var(shadow_var, i32_val);
+                    builder.def_local(shadow_var, i32_val);
                 }
                 Ok(val)
             }
@@ -18470,7 +21083,7 @@ fn compile_expr(
                     UpdateOp::Increment => builder.ins().iadd_imm(current_i32, 1),
                     UpdateOp::Decrement => builder.ins().iadd_imm(current_i32, -1),
                 };
-                builder.def_var(info.var, new_i32);
+                builder.def_local(info.var, new_i32);
 
                 // Return f64 for expression value
                 let return_i32 = if *prefix { new_i32 } else { current_i32 };
@@ -18493,7 +21106,7 @@ fn compile_expr(
                 } else {
                     new_val
                 };
-                builder.def_var(info.var, store_val);
+                builder.def_local(info.var, store_val);
 
                 // OPTIMIZATION: Update i32 shadow for integer variables (common for loop counters)
                 if let Some(shadow_var) = info.i32_shadow {
@@ -18504,7 +21117,7 @@ fn compile_expr(
                         UpdateOp::Increment => builder.ins().iadd(current_i32, one_i32),
                         UpdateOp::Decrement => builder.ins().isub(current_i32, one_i32),
                     };
-                    builder.def_var(shadow_var, new_i32);
+                    builder.def_local(shadow_var, new_i32);
                 }
 
                 // Return value depends on prefix/postfix
@@ -18526,6 +21139,10 @@ fn compile_expr(
                     _ => None,
                 }
             }
+            // ToInt32 (see to_int32)
+            fn const_to_int32(n: f64) -> i32 {
+                if n.is_finite() { (n.trunc() % 4294967296.0) as i64 as i32 } else { 0 }
+            }
             if let (Some(lhs_const), Some(rhs_const)) = (get_constant_value(left), get_constant_value(right)) {
                 let result = match op {
                     BinaryOp::Add => Some(lhs_const + rhs_const),
@@ -18534,12 +21151,12 @@ fn compile_expr(
                     BinaryOp::Div => Some(lhs_const / rhs_const),
                     BinaryOp::Mod => Some(lhs_const % rhs_const),
                     BinaryOp::Pow => Some(lhs_const.powf(rhs_const)),
-                    BinaryOp::BitAnd => Some(((lhs_const as i32) & (rhs_const as i32)) as f64),
-                    BinaryOp::BitOr => Some(((lhs_const as i32) | (rhs_const as i32)) as f64),
-                    BinaryOp::BitXor => Some(((lhs_const as i32) ^ (rhs_const as i32)) as f64),
-                    BinaryOp::Shl => Some(((lhs_const as i32) << ((rhs_const as i32) & 0x1f)) as f64),
-                    BinaryOp::Shr => Some(((lhs_const as i32) >> ((rhs_const as i32) & 0x1f)) as f64),
-                    BinaryOp::UShr => Some((((lhs_const as i32) as u32) >> ((rhs_const as i32) & 0x1f)) as f64),
+                    BinaryOp::BitAnd => Some((const_to_int32(lhs_const) & const_to_int32(rhs_const)) as f64),
+                    BinaryOp::BitOr => Some((const_to_int32(lhs_const) | const_to_int32(rhs_const)) as f64),
+                    BinaryOp::BitXor => Some((const_to_int32(lhs_const) ^ const_to_int32(rhs_const)) as f64),
+                    BinaryOp::Shl => Some(const_to_int32(lhs_const).wrapping_shl(const_to_int32(rhs_const) as u32 & 0x1f) as f64),
+                    BinaryOp::Shr => Some((const_to_int32(lhs_const) >> (const_to_int32(rhs_const) & 0x1f)) as f64),
+                    BinaryOp::UShr => Some(((const_to_int32(lhs_const) as u32) >> (const_to_int32(rhs_const) & 0x1f)) as f64),
                 };
                 if let Some(val) = result {
                     return Ok(builder.ins().f64const(val));
@@ -18554,8 +21171,9 @@ fn compile_expr(
                     Expr::StringFromCharCode(_) => true,
                     Expr::LocalGet(id) => locals.get(id).map(|i| i.is_string && !i.is_union).unwrap_or(false),
                     Expr::FsReadFileSync(_) |
+                    Expr::ImportMetaRuntime { .. } | Expr::ImportMetaResolve { .. } |
                     Expr::PathJoin(_, _) | Expr::PathDirname(_) | Expr::PathBasename(_) |
-                    Expr::PathExtname(_) | Expr::PathResolve(_) | Expr::JsonStringify(_) => true,
+                    Expr::PathExtname(_) | Expr::PathResolve(_) | Expr::JsonStringify(_) | Expr::JsonCanonicalize(_) => true,
                     Expr::Binary { op: BinaryOp::Add, left, right } => {
                         is_string_operand(left, locals) || is_string_operand(right, locals)
                     }
@@ -18567,7 +21185,7 @@ fn compile_expr(
                     Expr::Call { callee, .. } => {
                         if let Expr::PropertyGet { object, property } = callee.as_ref() {
                             if property == "slice" || property == "substring" || property == "trim"
-                               || property == "toLowerCase" || property == "toUpperCase" || property == "replace"
+                               || property == "toLowerCase" || property == "toUpperCase" || property == "toLocaleLowerCase" || property == "toLocaleUpperCase" || property == "replace"
                                || property == "padStart" || property == "padEnd" || property == "repeat" || property == "charAt" {
                                 // Check if the object is a string
                                 if let Expr::LocalGet(id) = object.as_ref() {
@@ -18900,15 +21518,9 @@ fn compile_expr(
                 }
             }
 
-            // I32 FAST PATH: DISABLED
-            // This optimization produced i32 results that weren't converted back to f64
-            // when used in contexts expecting f64 (Math.*, function calls, etc.).
-            // The loop counter optimization already uses i32 for array indexing via
-            // the IndexGet special handling. Disabling this broader optimization
-            // fixes many type mismatch errors.
-            //
-            // If performance is a concern, this could be re-enabled with careful
-            // tracking of which expressions are ONLY used for array indexing.
+            // I32 FAST PATH: add, subtract and multiply of two untagged int32s (see
+            // int32_value) run as i32 instructions below, with a deopt to f64 on overflow.
+            // The result is always an f64, so callers never see an i32.
 
             // CSE OPTIMIZATION: Check for cached squared value (var * var) or product (var * other_var)
             if *op == BinaryOp::Mul {
@@ -19032,6 +21644,12 @@ fn compile_expr(
                 // Ensure f64 for arithmetic operations (values may be i64 for Any-typed parameters)
                 (ensure_f64(builder, l), ensure_f64(builder, r))
             };
+            // INT32 FAST PATH: see int32_arith
+            if matches!(op, BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul) {
+                if let (Some(a), Some(b)) = (int32_value(builder, lhs), int32_value(builder, rhs)) {
+                    return Ok(int32_arith(builder, *op, a, b, lhs, rhs));
+                }
+            }
             let result = match op {
                 BinaryOp::Add => builder.ins().fadd(lhs, rhs),
                 BinaryOp::Sub => builder.ins().fsub(lhs, rhs),
@@ -19076,28 +21694,29 @@ fn compile_expr(
                     builder.inst_results(call)[0]
                 }
                 // Bitwise operations - JavaScript works on 32-bit integers
-                // Convert f64 to i32, perform op, convert back to f64
+                // ToInt32 both operands, perform op, convert back to f64; operands that are
+                // already int32 (nested bit operations, loop counters) skip the round trip
                 BinaryOp::BitAnd => {
-                    let lhs_i32 = builder.ins().fcvt_to_sint(types::I32, lhs);
-                    let rhs_i32 = builder.ins().fcvt_to_sint(types::I32, rhs);
+                    let lhs_i32 = to_int32(builder, lhs);
+                    let rhs_i32 = to_int32(builder, rhs);
                     let result = builder.ins().band(lhs_i32, rhs_i32);
                     builder.ins().fcvt_from_sint(types::F64, result)
                 }
                 BinaryOp::BitOr => {
-                    let lhs_i32 = builder.ins().fcvt_to_sint(types::I32, lhs);
-                    let rhs_i32 = builder.ins().fcvt_to_sint(types::I32, rhs);
+                    let lhs_i32 = to_int32(builder, lhs);
+                    let rhs_i32 = to_int32(builder, rhs);
                     let result = builder.ins().bor(lhs_i32, rhs_i32);
                     builder.ins().fcvt_from_sint(types::F64, result)
                 }
                 BinaryOp::BitXor => {
-                    let lhs_i32 = builder.ins().fcvt_to_sint(types::I32, lhs);
-                    let rhs_i32 = builder.ins().fcvt_to_sint(types::I32, rhs);
+                    let lhs_i32 = to_int32(builder, lhs);
+                    let rhs_i32 = to_int32(builder, rhs);
                     let result = builder.ins().bxor(lhs_i32, rhs_i32);
                     builder.ins().fcvt_from_sint(types::F64, result)
                 }
                 BinaryOp::Shl => {
-                    let lhs_i32 = builder.ins().fcvt_to_sint(types::I32, lhs);
-                    let rhs_i32 = builder.ins().fcvt_to_sint(types::I32, rhs);
+                    let lhs_i32 = to_int32(builder, lhs);
+                    let rhs_i32 = to_int32(builder, rhs);
                     // JavaScript only uses the low 5 bits of the shift amount
                     let shift_mask = builder.ins().iconst(types::I32, 0x1f);
                     let shift_amt = builder.ins().band(rhs_i32, shift_mask);
@@ -19106,8 +21725,8 @@ fn compile_expr(
                 }
                 BinaryOp::Shr => {
                     // Signed right shift
-                    let lhs_i32 = builder.ins().fcvt_to_sint(types::I32, lhs);
-                    let rhs_i32 = builder.ins().fcvt_to_sint(types::I32, rhs);
+                    let lhs_i32 = to_int32(builder, lhs);
+                    let rhs_i32 = to_int32(builder, rhs);
                     // JavaScript only uses the low 5 bits of the shift amount
                     let shift_mask = builder.ins().iconst(types::I32, 0x1f);
                     let shift_amt = builder.ins().band(rhs_i32, shift_mask);
@@ -19116,8 +21735,8 @@ fn compile_expr(
                 }
                 BinaryOp::UShr => {
                     // Unsigned right shift - returns unsigned result
-                    let lhs_i32 = builder.ins().fcvt_to_sint(types::I32, lhs);
-                    let rhs_i32 = builder.ins().fcvt_to_sint(types::I32, rhs);
+                    let lhs_i32 = to_int32(builder, lhs);
+                    let rhs_i32 = to_int32(builder, rhs);
                     // JavaScript only uses the low 5 bits of the shift amount
                     let shift_mask = builder.ins().iconst(types::I32, 0x1f);
                     let shift_amt = builder.ins().band(rhs_i32, shift_mask);
@@ -19142,8 +21761,8 @@ fn compile_expr(
                 }
                 UnaryOp::BitNot => {
                     // Bitwise NOT: ~x
-                    // Convert f64 to i32, apply bnot, convert back to f64
-                    let val_i32 = builder.ins().fcvt_to_sint(types::I32, val);
+                    // ToInt32, apply bnot, convert back to f64
+                    let val_i32 = to_int32(builder, val);
                     let result = builder.ins().bnot(val_i32);
                     Ok(builder.ins().fcvt_from_sint(types::F64, result))
                 }
@@ -19266,6 +21885,16 @@ fn compile_expr(
                 };
                 let cmp = builder.ins().icmp(icc, lhs_i64, rhs_i64);
                 Ok(builder.ins().select(cmp, one, zero))
+            } else if is_null_compare && (*op == CompareOp::Eq || *op == CompareOp::Ne) {
+                // NaN-boxed null/undefined never compare equal with fcmp; compare bit patterns
+                // (e.g. the `param === undefined` check in default-parameter prologues)
+                let lhs_f64 = ensure_f64(builder, lhs);
+                let rhs_f64 = ensure_f64(builder, rhs);
+                let lhs_i64 = builder.ins().bitcast(types::I64, MemFlags::new(), lhs_f64);
+                let rhs_i64 = builder.ins().bitcast(types::I64, MemFlags::new(), rhs_f64);
+                let icc = if *op == CompareOp::Eq { IntCC::Equal } else { IntCC::NotEqual };
+                let cmp = builder.ins().icmp(icc, lhs_i64, rhs_i64);
+                Ok(builder.ins().select(cmp, one, zero))
             } else {
                 // Check if this is a BigInt comparison
                 fn is_bigint_compare_expr(expr: &Expr, locals: &HashMap<LocalId, LocalInfo>) -> bool {
@@ -19332,6 +21961,19 @@ fn compile_expr(
                         };
                         let cmp = builder.ins().icmp(icc, lhs_i64, rhs_i64);
                         Ok(builder.ins().select(cmp, one, zero))
+                    } else if let (Some(lhs_i32), Some(rhs_i32)) = (int32_value(builder, lhs), int32_value(builder, rhs)) {
+                        // Both sides are untagged int32s (loop counters, lengths, bit operation
+                        // results, integer literals): compare the integers
+                        let cc = match op {
+                            CompareOp::Eq => IntCC::Equal,
+                            CompareOp::Ne => IntCC::NotEqual,
+                            CompareOp::Lt => IntCC::SignedLessThan,
+                            CompareOp::Le => IntCC::SignedLessThanOrEqual,
+                            CompareOp::Gt => IntCC::SignedGreaterThan,
+                            CompareOp::Ge => IntCC::SignedGreaterThanOrEqual,
+                        };
+                        let cmp = builder.ins().icmp(cc, lhs_i32, rhs_i32);
+                        Ok(builder.ins().select(cmp, one, zero))
                     } else {
                         // Regular float comparison - ensure both operands are f64
                         let lhs_f64 = ensure_f64(builder, lhs);
@@ -19520,7 +22162,11 @@ fn compile_expr(
                 }
             }
         }
-        Expr::Call { callee, args, .. } => {
+        Expr::Call { callee, args, span, .. } => {
+            // Tag the call's instructions with its offset in the source file
+            if !span.is_dummy() {
+                builder.set_srcloc(cranelift_codegen::ir::SourceLoc::new(span.start));
+            }
             // Compile arguments
             let arg_vals: Vec<Value> = args.iter()
                 .map(|a| compile_expr(builder, module, func_ids, closure_func_ids, func_wrapper_ids, extern_funcs, async_func_ids, classes, enums, func_param_types, func_union_params, func_return_types, func_hir_return_types, func_rest_param_index, imported_func_param_counts, locals, a, this_ctx))
@@ -19694,7 +22340,7 @@ fn compile_expr(
                         if expected_type == types::I64 {
                             final_call_args.push(builder.ins().iconst(types::I64, 0));
                         } else {
-                            final_call_args.push(builder.ins().f64const(f64::NAN));
+                            final_call_args.push(missing_arg(builder));
                         }
                     }
                     // Truncate if we have too many (handles variadic-like patterns)
@@ -19754,8 +22400,9 @@ fn compile_expr(
                                     match expr {
                                         Expr::String(_) => true,
                                         Expr::EnvGet(_) | Expr::EnvGetDynamic(_) | Expr::FsReadFileSync(_) => true,
+                                        Expr::ImportMetaRuntime { .. } | Expr::ImportMetaResolve { .. } |
                                         Expr::PathJoin(_, _) | Expr::PathDirname(_) | Expr::PathBasename(_) |
-                                        Expr::PathExtname(_) | Expr::PathResolve(_) | Expr::JsonStringify(_) => true,
+                                        Expr::PathExtname(_) | Expr::PathResolve(_) | Expr::JsonStringify(_) | Expr::JsonCanonicalize(_) => true,
                                         Expr::CryptoRandomBytes(_) | Expr::CryptoRandomUUID |
                                         Expr::CryptoSha256(_) | Expr::CryptoMd5(_) => true,
                                         Expr::DateToISOString(_) => true,
@@ -19771,7 +22418,7 @@ fn compile_expr(
                                         Expr::Call { callee, .. } => {
                                             if let Expr::PropertyGet { object, property } = callee.as_ref() {
                                                 if property == "slice" || property == "substring" || property == "trim"
-                                                    || property == "toLowerCase" || property == "toUpperCase"
+                                                    || property == "toLowerCase" || property == "toUpperCase" || property == "toLocaleLowerCase" || property == "toLocaleUpperCase"
                                                     || property == "charAt" || property == "padStart" || property == "padEnd"
                                                     || property == "repeat" || property == "replace" {
                                                     // Check if object is a string
@@ -19846,7 +22493,7 @@ fn compile_expr(
 
                                 let arr_var = Variable::new(next_temp_var_id());
                                 builder.declare_var(arr_var, types::I64);
-                                builder.def_var(arr_var, initial_arr_ptr);
+                                builder.def_local(arr_var, initial_arr_ptr);
 
                                 let push_func = extern_funcs.get("js_array_push_f64")
                                     .ok_or_else(|| anyhow!("js_array_push_f64 not declared"))?;
@@ -19887,7 +22534,7 @@ fn compile_expr(
                                     let arr_ptr = builder.use_var(arr_var);
                                     let push_call = builder.ins().call(push_ref, &[arr_ptr, val_f64]);
                                     let new_arr_ptr = builder.inst_results(push_call)[0];
-                                    builder.def_var(arr_var, new_arr_ptr);
+                                    builder.def_local(arr_var, new_arr_ptr);
                                 }
 
                                 // Call js_console_log_spread with the built array
@@ -19914,7 +22561,8 @@ fn compile_expr(
                                 Expr::String(_) => true,
                                 Expr::EnvGet(_) | Expr::EnvGetDynamic(_) => true,
                                 Expr::FsReadFileSync(_) => true,
-                                Expr::JsonStringify(_) => true,
+                                Expr::JsonStringify(_) | Expr::JsonCanonicalize(_) => true,
+                                Expr::ImportMetaRuntime { .. } | Expr::ImportMetaResolve { .. } |
                                 Expr::PathJoin(_, _) | Expr::PathDirname(_) | Expr::PathBasename(_) |
                                 Expr::PathExtname(_) | Expr::PathResolve(_) => true,
                                 // All crypto operations return strings (hex or UUID format)
@@ -19947,7 +22595,8 @@ fn compile_expr(
                                         match expr {
                                             Expr::String(_) => true,
                                             Expr::LocalGet(id) => locals.get(id).map(|i| i.is_string).unwrap_or(false),
-                                            Expr::EnvGet(_) | Expr::EnvGetDynamic(_) | Expr::FsReadFileSync(_) | Expr::JsonStringify(_) |
+                                            Expr::EnvGet(_) | Expr::EnvGetDynamic(_) | Expr::FsReadFileSync(_) | Expr::JsonStringify(_) | Expr::JsonCanonicalize(_) |
+                                            Expr::ImportMetaRuntime { .. } | Expr::ImportMetaResolve { .. } |
                                             Expr::PathJoin(_, _) | Expr::PathDirname(_) | Expr::PathBasename(_) |
                                             Expr::PathExtname(_) | Expr::PathResolve(_) => true,
                                             Expr::OsPlatform | Expr::OsArch | Expr::OsHostname | Expr::OsHomedir |
@@ -19966,7 +22615,7 @@ fn compile_expr(
                                     if let Expr::PropertyGet { object, property: method_name } = callee.as_ref() {
                                         // Check for string methods first - these always return strings
                                         if method_name == "slice" || method_name == "substring" || method_name == "trim"
-                                            || method_name == "toLowerCase" || method_name == "toUpperCase" || method_name == "replace"
+                                            || method_name == "toLowerCase" || method_name == "toUpperCase" || method_name == "toLocaleLowerCase" || method_name == "toLocaleUpperCase" || method_name == "replace"
                                             || method_name == "padStart" || method_name == "padEnd" || method_name == "repeat" || method_name == "charAt" {
                                             // String methods always return strings, regardless of what the object is
                                             // This handles cases like query.queryText.substring(0, 20)
@@ -20002,7 +22651,7 @@ fn compile_expr(
                                                                     }
                                                                     _ => return_type.clone()
                                                                 };
-                                                                matches!(actual_type, perry_types::Type::String)
+                                                                actual_type.is_string()
                                                             } else { false }
                                                         } else { false }
                                                     } else { false }
@@ -20022,7 +22671,7 @@ fn compile_expr(
                                             if let Some(class_name) = &info.class_name {
                                                 if let Some(class_meta) = classes.get(class_name) {
                                                     if let Some(field_type) = class_meta.field_types.get(property.as_str()) {
-                                                        matches!(field_type, perry_types::Type::String)
+                                                        field_type.is_string()
                                                     } else { false }
                                                 } else { false }
                                             } else { false }
@@ -20068,10 +22717,14 @@ fn compile_expr(
                                 // Boolean literals need dynamic printing (NaN-boxed)
                                 Expr::Bool(_) => true,
                                 Expr::LocalGet(id) => locals.get(id).map(|i| i.is_union).unwrap_or(false),
-                                // Mixed-type array element access returns a union-like value
+                                // Mixed-type array element access returns a union-like value,
+                                // and so does indexing a generic object (e.g. an enum's reverse mapping)
                                 Expr::IndexGet { object, .. } => {
                                     if let Expr::LocalGet(arr_id) = object.as_ref() {
-                                        locals.get(arr_id).map(|i| i.is_mixed_array).unwrap_or(false)
+                                        locals.get(arr_id).map(|i| {
+                                            i.is_mixed_array
+                                                || (i.is_pointer && i.class_name.is_none() && !i.is_array && !i.is_string && !i.is_map && !i.is_set && !i.is_buffer)
+                                        }).unwrap_or(false)
                                     } else {
                                         false
                                     }
@@ -20097,6 +22750,9 @@ fn compile_expr(
                                 }
                                 // Conditional (ternary) expressions can return different types
                                 Expr::Conditional { .. } => true,
+                                // Object.keys/values/entries and Map/Set snapshots return NaN-boxed arrays
+                                Expr::ObjectKeys(_) | Expr::ObjectValues(_) | Expr::ObjectEntries(_) |
+                                Expr::MapKeys(_) | Expr::MapValues(_) | Expr::MapEntries(_) | Expr::SetValues(_) => true,
                                 _ => false,
                             }
                         } else {
@@ -20196,8 +22852,9 @@ fn compile_expr(
                                     match expr {
                                         Expr::String(_) => true,
                                         Expr::EnvGet(_) | Expr::EnvGetDynamic(_) | Expr::FsReadFileSync(_) => true,
+                                        Expr::ImportMetaRuntime { .. } | Expr::ImportMetaResolve { .. } |
                                         Expr::PathJoin(_, _) | Expr::PathDirname(_) | Expr::PathBasename(_) |
-                                        Expr::PathExtname(_) | Expr::PathResolve(_) | Expr::JsonStringify(_) => true,
+                                        Expr::PathExtname(_) | Expr::PathResolve(_) | Expr::JsonStringify(_) | Expr::JsonCanonicalize(_) => true,
                                         Expr::CryptoRandomBytes(_) | Expr::CryptoRandomUUID |
                                         Expr::CryptoSha256(_) | Expr::CryptoMd5(_) => true,
                                         Expr::DateToISOString(_) => true,
@@ -20211,7 +22868,7 @@ fn compile_expr(
                                         Expr::Call { callee, .. } => {
                                             if let Expr::PropertyGet { object, property } = callee.as_ref() {
                                                 if property == "slice" || property == "substring" || property == "trim"
-                                                    || property == "toLowerCase" || property == "toUpperCase"
+                                                    || property == "toLowerCase" || property == "toUpperCase" || property == "toLocaleLowerCase" || property == "toLocaleUpperCase"
                                                     || property == "charAt" || property == "padStart" || property == "padEnd"
                                                     || property == "repeat" || property == "replace" {
                                                     if let Expr::LocalGet(id) = object.as_ref() {
@@ -20281,7 +22938,7 @@ fn compile_expr(
 
                                 let arr_var = Variable::new(next_temp_var_id());
                                 builder.declare_var(arr_var, types::I64);
-                                builder.def_var(arr_var, initial_arr_ptr);
+                                builder.def_local(arr_var, initial_arr_ptr);
 
                                 let push_func = extern_funcs.get("js_array_push_f64")
                                     .ok_or_else(|| anyhow!("js_array_push_f64 not declared"))?;
@@ -20316,7 +22973,7 @@ fn compile_expr(
                                     let arr_ptr = builder.use_var(arr_var);
                                     let push_call = builder.ins().call(push_ref, &[arr_ptr, val_f64]);
                                     let new_arr_ptr = builder.inst_results(push_call)[0];
-                                    builder.def_var(arr_var, new_arr_ptr);
+                                    builder.def_local(arr_var, new_arr_ptr);
                                 }
 
                                 // Call js_console_error_spread with the built array
@@ -20333,7 +22990,8 @@ fn compile_expr(
                                 Expr::String(_) => true,
                                 Expr::EnvGet(_) | Expr::EnvGetDynamic(_) => true,
                                 Expr::FsReadFileSync(_) => true,
-                                Expr::JsonStringify(_) => true,
+                                Expr::JsonStringify(_) | Expr::JsonCanonicalize(_) => true,
+                                Expr::ImportMetaRuntime { .. } | Expr::ImportMetaResolve { .. } |
                                 Expr::PathJoin(_, _) | Expr::PathDirname(_) | Expr::PathBasename(_) |
                                 Expr::PathExtname(_) | Expr::PathResolve(_) => true,
                                 Expr::CryptoRandomBytes(_) | Expr::CryptoRandomUUID |
@@ -20357,7 +23015,8 @@ fn compile_expr(
                                         match expr {
                                             Expr::String(_) => true,
                                             Expr::LocalGet(id) => locals.get(id).map(|i| i.is_string).unwrap_or(false),
-                                            Expr::EnvGet(_) | Expr::EnvGetDynamic(_) | Expr::FsReadFileSync(_) | Expr::JsonStringify(_) |
+                                            Expr::EnvGet(_) | Expr::EnvGetDynamic(_) | Expr::FsReadFileSync(_) | Expr::JsonStringify(_) | Expr::JsonCanonicalize(_) |
+                                            Expr::ImportMetaRuntime { .. } | Expr::ImportMetaResolve { .. } |
                                             Expr::PathJoin(_, _) | Expr::PathDirname(_) | Expr::PathBasename(_) |
                                             Expr::PathExtname(_) | Expr::PathResolve(_) => true,
                                             Expr::OsPlatform | Expr::OsArch | Expr::OsHostname | Expr::OsHomedir |
@@ -20373,7 +23032,7 @@ fn compile_expr(
                                 Expr::Call { callee, .. } => {
                                     if let Expr::PropertyGet { object, property: method_name } = callee.as_ref() {
                                         if method_name == "slice" || method_name == "substring" || method_name == "trim"
-                                            || method_name == "toLowerCase" || method_name == "toUpperCase" || method_name == "replace" {
+                                            || method_name == "toLowerCase" || method_name == "toUpperCase" || method_name == "toLocaleLowerCase" || method_name == "toLocaleUpperCase" || method_name == "replace" {
                                             if let Expr::LocalGet(id) = object.as_ref() {
                                                 locals.get(id).map(|i| i.is_string).unwrap_or(false)
                                             } else {
@@ -20391,7 +23050,7 @@ fn compile_expr(
                                                     .and_then(|info| info.class_name.as_ref())
                                                     .and_then(|class_name| classes.get(class_name))
                                                     .and_then(|class_meta| class_meta.method_return_types.get(method_name.as_str()))
-                                                    .map(|return_type| matches!(return_type, perry_types::Type::String))
+                                                    .map(|return_type| return_type.is_string())
                                                     .unwrap_or(false)
                                             } else {
                                                 false
@@ -20511,7 +23170,8 @@ fn compile_expr(
                                 Expr::String(_) => true,
                                 Expr::EnvGet(_) | Expr::EnvGetDynamic(_) => true,
                                 Expr::FsReadFileSync(_) => true,
-                                Expr::JsonStringify(_) => true,
+                                Expr::JsonStringify(_) | Expr::JsonCanonicalize(_) => true,
+                                Expr::ImportMetaRuntime { .. } | Expr::ImportMetaResolve { .. } |
                                 Expr::PathJoin(_, _) | Expr::PathDirname(_) | Expr::PathBasename(_) |
                                 Expr::PathExtname(_) | Expr::PathResolve(_) => true,
                                 Expr::CryptoRandomBytes(_) | Expr::CryptoRandomUUID |
@@ -20535,7 +23195,8 @@ fn compile_expr(
                                         match expr {
                                             Expr::String(_) => true,
                                             Expr::LocalGet(id) => locals.get(id).map(|i| i.is_string).unwrap_or(false),
-                                            Expr::EnvGet(_) | Expr::EnvGetDynamic(_) | Expr::FsReadFileSync(_) | Expr::JsonStringify(_) |
+                                            Expr::EnvGet(_) | Expr::EnvGetDynamic(_) | Expr::FsReadFileSync(_) | Expr::JsonStringify(_) | Expr::JsonCanonicalize(_) |
+                                            Expr::ImportMetaRuntime { .. } | Expr::ImportMetaResolve { .. } |
                                             Expr::PathJoin(_, _) | Expr::PathDirname(_) | Expr::PathBasename(_) |
                                             Expr::PathExtname(_) | Expr::PathResolve(_) => true,
                                             Expr::OsPlatform | Expr::OsArch | Expr::OsHostname | Expr::OsHomedir |
@@ -20551,7 +23212,7 @@ fn compile_expr(
                                 Expr::Call { callee, .. } => {
                                     if let Expr::PropertyGet { object, property: method_name } = callee.as_ref() {
                                         if method_name == "slice" || method_name == "substring" || method_name == "trim"
-                                            || method_name == "toLowerCase" || method_name == "toUpperCase" || method_name == "replace" {
+                                            || method_name == "toLowerCase" || method_name == "toUpperCase" || method_name == "toLocaleLowerCase" || method_name == "toLocaleUpperCase" || method_name == "replace" {
                                             if let Expr::LocalGet(id) = object.as_ref() {
                                                 locals.get(id).map(|i| i.is_string).unwrap_or(false)
                                             } else {
@@ -20569,7 +23230,7 @@ fn compile_expr(
                                                     .and_then(|info| info.class_name.as_ref())
                                                     .and_then(|class_name| classes.get(class_name))
                                                     .and_then(|class_meta| class_meta.method_return_types.get(method_name.as_str()))
-                                                    .map(|return_type| matches!(return_type, perry_types::Type::String))
+                                                    .map(|return_type| return_type.is_string())
                                                     .unwrap_or(false)
                                             } else {
                                                 false
@@ -20937,6 +23598,9 @@ fn compile_expr(
                                         let nanbox_call = builder.ins().call(nanbox_ref, &[result_ptr]);
                                         return Ok(builder.inst_results(nanbox_call)[0]);
                                     }
+                                    "localeCompare" | "toLocaleUpperCase" | "toLocaleLowerCase" => {
+                                        return compile_locale_string_method(builder, module, extern_funcs, property, str_ptr, &arg_vals);
+                                    }
                                     "toUpperCase" => {
                                         // str.toUpperCase()
                                         let upper_func = extern_funcs.get("js_string_to_upper_case")
@@ -21585,7 +24249,7 @@ fn compile_expr(
                                             if expected_type == types::I64 {
                                                 final_call_args.push(builder.ins().iconst(types::I64, 0));
                                             } else {
-                                                final_call_args.push(builder.ins().f64const(f64::NAN));
+                                                final_call_args.push(missing_arg(builder));
                                             }
                                         }
                                         final_call_args.truncate(expected_param_count);
@@ -21713,7 +24377,7 @@ fn compile_expr(
                                     if expected_type == types::I64 {
                                         final_call_args.push(builder.ins().iconst(types::I64, 0));
                                     } else {
-                                        final_call_args.push(builder.ins().f64const(f64::NAN));
+                                        final_call_args.push(missing_arg(builder));
                                     }
                                 }
                                 final_call_args.truncate(expected_param_count);
@@ -21968,7 +24632,7 @@ fn compile_expr(
                     // Handle string methods on any expression (e.g., query.queryText.substring(0, 20))
                     // This handles property access chains and other cases where the object is not a LocalGet
                     match property.as_str() {
-                        "substring" | "slice" | "trim" | "toLowerCase" | "toUpperCase" | "indexOf" | "includes" | "split" | "replace" | "startsWith" | "endsWith" | "padStart" | "padEnd" | "repeat" | "charAt" | "charCodeAt" => {
+                        "substring" | "slice" | "trim" | "toLowerCase" | "toUpperCase" | "toLocaleLowerCase" | "toLocaleUpperCase" | "localeCompare" | "indexOf" | "includes" | "split" | "replace" | "startsWith" | "endsWith" | "padStart" | "padEnd" | "repeat" | "charAt" | "charCodeAt" => {
                             // Compile the object expression to get a string value
                             let str_val = compile_expr(builder, module, func_ids, closure_func_ids, func_wrapper_ids, extern_funcs, async_func_ids, classes, enums, func_param_types, func_union_params, func_return_types, func_hir_return_types, func_rest_param_index, imported_func_param_counts, locals, object, this_ctx)?;
 
@@ -22041,6 +24705,9 @@ fn compile_expr(
                                     let nanbox_call = builder.ins().call(nanbox_ref, &[result_ptr]);
                                     return Ok(builder.inst_results(nanbox_call)[0]);
                                 }
+                                "localeCompare" | "toLocaleUpperCase" | "toLocaleLowerCase" => {
+                                    return compile_locale_string_method(builder, module, extern_funcs, property, str_ptr, &arg_vals);
+                                }
                                 "toUpperCase" => {
                                     let upper_func = extern_funcs.get("js_string_to_upper_case")
                                         .ok_or_else(|| anyhow!("js_string_to_upper_case not declared"))?;
@@ -22483,14 +25150,9 @@ fn compile_expr(
                             ));
                             let args_ptr = builder.ins().stack_addr(types::I64, stack_slot, 0);
 
-                            for (i, &arg_val_raw) in arg_vals.iter().enumerate() {
-                                // Ensure argument is f64 for JS interop
-                                let arg_val_type = builder.func.dfg.value_type(arg_val_raw);
-                                let arg_val = if arg_val_type == types::I64 {
-                                    builder.ins().bitcast(types::F64, MemFlags::new(), arg_val_raw)
-                                } else {
-                                    arg_val_raw
-                                };
+                            for (i, (arg, &arg_val_raw)) in args.iter().zip(arg_vals.iter()).enumerate() {
+                                // Ensure argument is f64 for JS interop; class instances are NaN-boxed
+                                let arg_val = js_interop_arg(builder, module, extern_funcs, classes, locals, arg, arg_val_raw)?;
                                 let offset = (i * 8) as i32;
                                 builder.ins().store(MemFlags::new(), arg_val, args_ptr, offset);
                             }
@@ -22612,7 +25274,7 @@ fn compile_expr(
                             if expected_type == types::I64 {
                                 converted_args.push(builder.ins().iconst(types::I64, 0));
                             } else {
-                                converted_args.push(builder.ins().f64const(f64::NAN));
+                                converted_args.push(missing_arg(builder));
                             }
                         }
                         // Truncate if we have too many
@@ -22774,7 +25436,7 @@ fn compile_expr(
                     if let Expr::PropertyGet { object, property } = else_expr.as_ref() {
                         match property.as_str() {
                             "substring" | "slice" | "trim" | "toLowerCase" | "toUpperCase" |
-                            "indexOf" | "includes" | "split" | "replace" | "startsWith" |
+                            "toLocaleLowerCase" | "toLocaleUpperCase" | "localeCompare" | "indexOf" | "includes" | "split" | "replace" | "startsWith" |
                             "endsWith" | "padStart" | "padEnd" | "repeat" | "charAt" | "charCodeAt" => {
                                 // Compile the object to get the string value
                                 let str_val = compile_expr(builder, module, func_ids, closure_func_ids, func_wrapper_ids, extern_funcs, async_func_ids, classes, enums, func_param_types, func_union_params, func_return_types, func_hir_return_types, func_rest_param_index, imported_func_param_counts, locals, object, this_ctx)?;
@@ -22842,6 +25504,9 @@ fn compile_expr(
                                         let nanbox_call = builder.ins().call(nanbox_ref, &[result_ptr]);
                                         return Ok(builder.inst_results(nanbox_call)[0]);
                                     }
+                                    "localeCompare" | "toLocaleUpperCase" | "toLocaleLowerCase" => {
+                                        return compile_locale_string_method(builder, module, extern_funcs, property, str_ptr, &arg_vals);
+                                    }
                                     "toUpperCase" => {
                                         let upper_func = extern_funcs.get("js_string_to_upper_case")
                                             .ok_or_else(|| anyhow!("js_string_to_upper_case not declared"))?;
@@ -22886,14 +25551,9 @@ fn compile_expr(
                             ));
                             let args_ptr = builder.ins().stack_addr(types::I64, stack_slot, 0);
 
-                            for (i, &arg_val_raw) in arg_vals.iter().enumerate() {
-                                // Ensure argument is f64 for JS interop
-                                let arg_val_type = builder.func.dfg.value_type(arg_val_raw);
-                                let arg_val = if arg_val_type == types::I64 {
-                                    builder.ins().bitcast(types::F64, cranelift_codegen::ir::MemFlags::new(), arg_val_raw)
-                                } else {
-                                    arg_val_raw
-                                };
+                            for (i, (arg, &arg_val_raw)) in args.iter().zip(arg_vals.iter()).enumerate() {
+                                // Ensure argument is f64 for JS interop; class instances are NaN-boxed
+                                let arg_val = js_interop_arg(builder, module, extern_funcs, classes, locals, arg, arg_val_raw)?;
                                 let offset = (i * 8) as i32;
                                 builder.ins().store(cranelift_codegen::ir::MemFlags::new(), arg_val, args_ptr, offset);
                             }
@@ -22940,14 +25600,9 @@ fn compile_expr(
                             ));
                             let args_ptr = builder.ins().stack_addr(types::I64, stack_slot, 0);
 
-                            for (i, &arg_val_raw) in arg_vals.iter().enumerate() {
-                                // Ensure argument is f64 for JS interop
-                                let arg_val_type = builder.func.dfg.value_type(arg_val_raw);
-                                let arg_val = if arg_val_type == types::I64 {
-                                    builder.ins().bitcast(types::F64, cranelift_codegen::ir::MemFlags::new(), arg_val_raw)
-                                } else {
-                                    arg_val_raw
-                                };
+                            for (i, (arg, &arg_val_raw)) in args.iter().zip(arg_vals.iter()).enumerate() {
+                                // Ensure argument is f64 for JS interop; class instances are NaN-boxed
+                                let arg_val = js_interop_arg(builder, module, extern_funcs, classes, locals, arg, arg_val_raw)?;
                                 let offset = (i * 8) as i32;
                                 builder.ins().store(cranelift_codegen::ir::MemFlags::new(), arg_val, args_ptr, offset);
                             }
@@ -22997,7 +25652,8 @@ fn compile_expr(
                     let param_types = func_param_types.get(func_id);
 
                     if spread_count == 1 && regular_args.is_empty() {
-                        // Single spread argument: fn(...arr)
+                        // Single spread argument: fn(...arr). Lowering merges mixed and multiple
+                        // spreads into one array, so this covers every spread call of a function.
                         // We need to unpack the array at runtime
 
                         // Find the spread expression
@@ -23007,16 +25663,22 @@ fn compile_expr(
 
                         // Compile the spread array
                         let arr_val = compile_expr(builder, module, func_ids, closure_func_ids, func_wrapper_ids, extern_funcs, async_func_ids, classes, enums, func_param_types, func_union_params, func_return_types, func_hir_return_types, func_rest_param_index, imported_func_param_counts, locals, spread_expr, this_ctx)?;
-                        let arr_ptr = builder.ins().bitcast(types::I64, MemFlags::new(), arr_val);
+                        let arr_ptr = ensure_i64(builder, arr_val);
 
                         // Get array elements
                         let get_func = extern_funcs.get("js_array_get_f64")
                             .ok_or_else(|| anyhow!("js_array_get_f64 not declared"))?;
                         let get_ref = module.declare_func_in_func(*get_func, builder.func);
 
+                        // Parameters before a rest parameter take the leading elements,
+                        // the rest parameter takes what is left
+                        let rest_idx = func_rest_param_index.get(func_id).copied()
+                            .filter(|&idx| idx < expected_params);
+                        let indexed_params = rest_idx.unwrap_or(expected_params);
+
                         // Extract elements from the array based on expected params from actual signature
                         let mut arg_vals = Vec::new();
-                        for i in 0..expected_params {
+                        for i in 0..indexed_params {
                             let idx = builder.ins().iconst(types::I32, i as i64);
                             let call = builder.ins().call(get_ref, &[arr_ptr, idx]);
                             let elem = builder.inst_results(call)[0];
@@ -23039,6 +25701,15 @@ fn compile_expr(
                             };
                             arg_vals.push(converted);
                         }
+                        if let Some(rest_idx) = rest_idx {
+                            let slice_func = extern_funcs.get("js_array_slice")
+                                .ok_or_else(|| anyhow!("js_array_slice not declared"))?;
+                            let slice_ref = module.declare_func_in_func(*slice_func, builder.func);
+                            let start = builder.ins().iconst(types::I32, rest_idx as i64);
+                            let end = builder.ins().iconst(types::I32, i32::MAX as i64);
+                            let call = builder.ins().call(slice_ref, &[arr_ptr, start, end]);
+                            arg_vals.push(builder.inst_results(call)[0]);
+                        }
 
                         // Call the function with unpacked arguments
                         let func_ref = module.declare_func_in_func(*clif_func_id, builder.func);
@@ -23078,7 +25749,7 @@ fn compile_expr(
                             if expected_type == types::I64 {
                                 final_args.push(builder.ins().iconst(types::I64, 0));
                             } else {
-                                final_args.push(builder.ins().f64const(f64::NAN));
+                                final_args.push(missing_arg(builder));
                             }
                         }
                         final_args.truncate(expected_param_count);
@@ -23118,7 +25789,7 @@ fn compile_expr(
                             // Track the array pointer (may change during push operations)
                             let arr_var = Variable::new(next_temp_var_id());
                             builder.declare_var(arr_var, types::I64);
-                            builder.def_var(arr_var, initial_arr_ptr);
+                            builder.def_local(arr_var, initial_arr_ptr);
 
                             // Get function references we'll need
                             let push_func = extern_funcs.get("js_array_push_f64")
@@ -23143,8 +25814,9 @@ fn compile_expr(
                                 match expr {
                                     Expr::String(_) => true,
                                     Expr::EnvGet(_) | Expr::EnvGetDynamic(_) | Expr::FsReadFileSync(_) => true,
+                                    Expr::ImportMetaRuntime { .. } | Expr::ImportMetaResolve { .. } |
                                     Expr::PathJoin(_, _) | Expr::PathDirname(_) | Expr::PathBasename(_) |
-                                    Expr::PathExtname(_) | Expr::PathResolve(_) | Expr::JsonStringify(_) => true,
+                                    Expr::PathExtname(_) | Expr::PathResolve(_) | Expr::JsonStringify(_) | Expr::JsonCanonicalize(_) => true,
                                     Expr::CryptoRandomBytes(_) | Expr::CryptoRandomUUID |
                                     Expr::CryptoSha256(_) | Expr::CryptoMd5(_) => true,
                                     Expr::DateToISOString(_) => true,
@@ -23175,7 +25847,7 @@ fn compile_expr(
                                         let arr_ptr = builder.use_var(arr_var);
                                         let push_call = builder.ins().call(push_ref, &[arr_ptr, val_f64]);
                                         let new_arr_ptr = builder.inst_results(push_call)[0];
-                                        builder.def_var(arr_var, new_arr_ptr);
+                                        builder.def_local(arr_var, new_arr_ptr);
                                     }
                                     CallArg::Spread(spread_expr) => {
                                         // Spread argument - iterate and push each element
@@ -23196,7 +25868,7 @@ fn compile_expr(
                                         let idx_var = Variable::new(next_temp_var_id());
                                         builder.declare_var(idx_var, types::I32);
                                         let zero = builder.ins().iconst(types::I32, 0);
-                                        builder.def_var(idx_var, zero);
+                                        builder.def_local(idx_var, zero);
 
                                         // Jump to loop header
                                         builder.ins().jump(loop_header, &[]);
@@ -23216,13 +25888,13 @@ fn compile_expr(
                                         let arr_ptr = builder.use_var(arr_var);
                                         let push_call = builder.ins().call(push_ref, &[arr_ptr, elem_val]);
                                         let new_arr_ptr = builder.inst_results(push_call)[0];
-                                        builder.def_var(arr_var, new_arr_ptr);
+                                        builder.def_local(arr_var, new_arr_ptr);
 
                                         // Increment counter
                                         let idx = builder.use_var(idx_var);
                                         let one = builder.ins().iconst(types::I32, 1);
                                         let next_idx = builder.ins().iadd(idx, one);
-                                        builder.def_var(idx_var, next_idx);
+                                        builder.def_local(idx_var, next_idx);
 
                                         // Jump back to header
                                         builder.ins().jump(loop_header, &[]);
@@ -23349,7 +26021,7 @@ fn compile_expr(
                         // Track the array pointer (may change during push operations)
                         let arr_var = Variable::new(next_temp_var_id());
                         builder.declare_var(arr_var, types::I64);
-                        builder.def_var(arr_var, initial_arr_ptr);
+                        builder.def_local(arr_var, initial_arr_ptr);
 
                         // Get function references we'll need
                         let push_func = extern_funcs.get("js_array_push_f64")
@@ -23374,7 +26046,7 @@ fn compile_expr(
                                     let arr_ptr = builder.use_var(arr_var);
                                     let push_call = builder.ins().call(push_ref, &[arr_ptr, val_f64]);
                                     let new_arr_ptr = builder.inst_results(push_call)[0];
-                                    builder.def_var(arr_var, new_arr_ptr);
+                                    builder.def_local(arr_var, new_arr_ptr);
                                 }
                                 CallArg::Spread(spread_expr) => {
                                     // Spread argument - iterate and push each element
@@ -23394,7 +26066,7 @@ fn compile_expr(
                                     let idx_var = Variable::new(next_temp_var_id());
                                     builder.declare_var(idx_var, types::I32);
                                     let zero = builder.ins().iconst(types::I32, 0);
-                                    builder.def_var(idx_var, zero);
+                                    builder.def_local(idx_var, zero);
 
                                     // Jump to loop header
                                     builder.ins().jump(loop_header, &[]);
@@ -23414,13 +26086,13 @@ fn compile_expr(
                                     let arr_ptr = builder.use_var(arr_var);
                                     let push_call = builder.ins().call(push_ref, &[arr_ptr, elem_val]);
                                     let new_arr_ptr = builder.inst_results(push_call)[0];
-                                    builder.def_var(arr_var, new_arr_ptr);
+                                    builder.def_local(arr_var, new_arr_ptr);
 
                                     // Increment counter
                                     let idx = builder.use_var(idx_var);
                                     let one = builder.ins().iconst(types::I32, 1);
                                     let next_idx = builder.ins().iadd(idx, one);
-                                    builder.def_var(idx_var, next_idx);
+                                    builder.def_local(idx_var, next_idx);
 
                                     // Jump back to header
                                     builder.ins().jump(loop_header, &[]);
@@ -23951,6 +26623,43 @@ fn compile_expr(
                 return Ok(builder.ins().bitcast(types::F64, MemFlags::new(), handle));
             }
 
+            // new Level(location, options?) - embedded key-value store, and
+            // new MiniSearch(options) - full-text search index, unless the program
+            // declares its own class of that name
+            let handle_ctor = match class_name.as_str() {
+                "Level" => Some(("js_level_open", 2)),
+                "MiniSearch" => Some(("js_minisearch_new", 1)),
+                _ => None,
+            };
+            if let Some((ctor_name, arity)) = handle_ctor.filter(|_| !classes.contains_key(class_name)) {
+                const TAG_UNDEFINED: u64 = 0x7FFC_0000_0000_0001;
+                let mut open_args = Vec::with_capacity(arity);
+                for arg in args.iter().take(arity) {
+                    let val = compile_expr(builder, module, func_ids, closure_func_ids, func_wrapper_ids, extern_funcs, async_func_ids, classes, enums, func_param_types, func_union_params, func_return_types, func_hir_return_types, func_rest_param_index, imported_func_param_counts, locals, arg, this_ctx)?;
+                    if builder.func.dfg.value_type(val) == types::I64 {
+                        // String and object locals arrive as raw pointers
+                        let is_string = matches!(arg, Expr::LocalGet(id) if locals.get(id).map(|i| i.is_string).unwrap_or(false))
+                            || matches!(arg, Expr::String(_));
+                        let nanbox_name = if is_string { "js_nanbox_string" } else { "js_nanbox_pointer" };
+                        let nanbox_func = extern_funcs.get(nanbox_name)
+                            .ok_or_else(|| anyhow!("{} not declared", nanbox_name))?;
+                        let nanbox_ref = module.declare_func_in_func(*nanbox_func, builder.func);
+                        let call = builder.ins().call(nanbox_ref, &[val]);
+                        open_args.push(builder.inst_results(call)[0]);
+                    } else {
+                        open_args.push(ensure_f64(builder, val));
+                    }
+                }
+                while open_args.len() < arity {
+                    open_args.push(builder.ins().f64const(f64::from_bits(TAG_UNDEFINED)));
+                }
+                let open_func = extern_funcs.get(ctor_name)
+                    .ok_or_else(|| anyhow!("{} not declared", ctor_name))?;
+                let func_ref = module.declare_func_in_func(*open_func, builder.func);
+                let call = builder.ins().call(func_ref, &open_args);
+                return Ok(builder.inst_results(call)[0]);
+            }
+
             // new AbortController() - Web standard abort controller
             if class_name == "AbortController" {
                 let new_func = extern_funcs.get("js_abort_controller_new")
@@ -24086,7 +26795,7 @@ fn compile_expr(
                         if expected_type == types::I64 {
                             final_call_args.push(builder.ins().iconst(types::I64, 0));
                         } else {
-                            final_call_args.push(builder.ins().f64const(f64::NAN));
+                            final_call_args.push(missing_arg(builder));
                         }
                     }
                     final_call_args.truncate(expected_param_count);
@@ -24130,8 +26839,8 @@ fn compile_expr(
 
                     for (i, arg) in args.iter().enumerate() {
                         let arg_val_raw = compile_expr(builder, module, func_ids, closure_func_ids, func_wrapper_ids, extern_funcs, async_func_ids, classes, enums, func_param_types, func_union_params, func_return_types, func_hir_return_types, func_rest_param_index, imported_func_param_counts, locals, arg, this_ctx)?;
-                        // Ensure argument is f64 for JS interop
-                        let arg_val = ensure_f64(builder, arg_val_raw);
+                        // Ensure argument is f64 for JS interop; class instances are NaN-boxed
+                        let arg_val = js_interop_arg(builder, module, extern_funcs, classes, locals, arg, arg_val_raw)?;
                         let offset = (i * 8) as i32;
                         builder.ins().store(cranelift_codegen::ir::MemFlags::new(), arg_val, args_ptr, offset);
                     }
@@ -24222,6 +26931,7 @@ fn compile_expr(
                         class_name: class_name.clone(),
                         args: args.clone(),
                         type_args: Vec::new(),
+                        span: Span::DUMMY,
                     };
                     compile_expr(builder, module, func_ids, closure_func_ids, func_wrapper_ids,
                         extern_funcs, async_func_ids, classes, enums, func_param_types,
@@ -24275,6 +26985,17 @@ fn compile_expr(
             // Check for setter first
             if let Expr::LocalGet(id) = object.as_ref() {
                 if let Some(info) = locals.get(id) {
+                    // Object unions write the field at the offset their tag selects
+                    if !info.union_classes.is_empty() {
+                        let obj_val = builder.use_var(info.var);
+                        if let Some(addr) = union_field_address(builder, obj_val, &info.union_classes, classes, property) {
+                            let val = compile_expr(builder, module, func_ids, closure_func_ids, func_wrapper_ids, extern_funcs, async_func_ids, classes, enums, func_param_types, func_union_params, func_return_types, func_hir_return_types, func_rest_param_index, imported_func_param_counts, locals, value, this_ctx)?;
+                            let val_f64 = ensure_f64(builder, val);
+                            builder.ins().store(MemFlags::new(), val_f64, addr, 0);
+                            return Ok(val);
+                        }
+                    }
+
                     if let Some(ref class_name) = info.class_name {
                         if let Some(class_meta) = classes.get(class_name) {
                             // Check if there's a setter for this property
@@ -24730,6 +27451,14 @@ fn compile_expr(
                         return Ok(builder.inst_results(call)[0]);
                     }
 
+                    // Object unions read the field at the offset their tag selects
+                    if !info.union_classes.is_empty() {
+                        let obj_val = builder.use_var(info.var);
+                        if let Some(addr) = union_field_address(builder, obj_val, &info.union_classes, classes, property) {
+                            return Ok(builder.ins().load(types::F64, MemFlags::new(), addr, 0));
+                        }
+                    }
+
                     // Handle object field access (check for getter first)
                     if let Some(ref class_name) = info.class_name {
                         if let Some(class_meta) = classes.get(class_name) {
@@ -24883,12 +27612,73 @@ fn compile_expr(
             }
 
             // For generic object property access (object literals, JS handles, etc.)
-            // Use js_dynamic_object_get_property which handles both JS handles and native objects
+            // Each site gets an inline cache (see PropertyCache in perry-runtime): plain
+            // objects of the cached shape are read directly at the cached slot; everything
+            // else goes through js_dynamic_object_get_property_ic, which handles JS handles
+            // and native objects and refills the cache
 
             // Compile the object expression
             let obj_val = compile_expr(builder, module, func_ids, closure_func_ids, func_wrapper_ids, extern_funcs, async_func_ids, classes, enums, func_param_types, func_union_params, func_return_types, func_hir_return_types, func_rest_param_index, imported_func_param_counts, locals, object, this_ctx)?;
             let obj_f64 = ensure_f64(builder, obj_val);
 
+            // The site's cache: { shape: u64, index: u64 }, empty while shape is 1 (no keys
+            // array has that address, and a null one must not match)
+            let cache_id = module.declare_anonymous_data(true, false)?;
+            let mut cache_desc = DataDescription::new();
+            let mut cache_init = 1u64.to_le_bytes().to_vec();
+            cache_init.extend_from_slice(&0u64.to_le_bytes());
+            cache_desc.define(cache_init.into_boxed_slice());
+            cache_desc.set_align(8);
+            module.define_data(cache_id, &cache_desc)?;
+            let cache_gv = module.declare_data_in_func(cache_id, builder.func);
+            let cache_addr = builder.ins().global_value(types::I64, cache_gv);
+
+            let check_block = builder.create_block();
+            let header_block = builder.create_block();
+            let shape_block = builder.create_block();
+            let hit_block = builder.create_block();
+            let slow_block = builder.create_block();
+            let merge_block = builder.create_block();
+            builder.append_block_param(merge_block, types::F64);
+
+            // A POINTER_TAG value above the handle range...
+            let bits = builder.ins().bitcast(types::I64, MemFlags::new(), obj_f64);
+            let tag = builder.ins().ushr_imm(bits, 48);
+            let is_pointer = builder.ins().icmp_imm(IntCC::Equal, tag, 0x7FFD);
+            builder.ins().brif(is_pointer, check_block, &[], slow_block, &[]);
+            builder.switch_to_block(check_block);
+            builder.seal_block(check_block);
+            let obj_ptr = builder.ins().band_imm(bits, 0x0000_FFFF_FFFF_FFFF);
+            let is_heap = builder.ins().icmp_imm(IntCC::UnsignedGreaterThanOrEqual, obj_ptr, 0x100000);
+            builder.ins().brif(is_heap, header_block, &[], slow_block, &[]);
+
+            // ...to a plain object: object_type 1 and class_id 0, read as one u64...
+            builder.switch_to_block(header_block);
+            builder.seal_block(header_block);
+            let header = builder.ins().load(types::I64, MemFlags::new(), obj_ptr, 0);
+            let is_plain = builder.ins().icmp_imm(IntCC::Equal, header, 1);
+            builder.ins().brif(is_plain, shape_block, &[], slow_block, &[]);
+
+            // ...of the cached shape
+            builder.switch_to_block(shape_block);
+            builder.seal_block(shape_block);
+            let keys_ptr = builder.ins().load(types::I64, MemFlags::new(), obj_ptr, 16);
+            let cached_shape = builder.ins().load(types::I64, MemFlags::new(), cache_addr, 0);
+            let same_shape = builder.ins().icmp(IntCC::Equal, keys_ptr, cached_shape);
+            builder.ins().brif(same_shape, hit_block, &[], slow_block, &[]);
+
+            // Hit: ObjectHeader is 24 bytes, fields start after that
+            builder.switch_to_block(hit_block);
+            builder.seal_block(hit_block);
+            let cached_index = builder.ins().load(types::I64, MemFlags::new(), cache_addr, 8);
+            let byte_offset = builder.ins().ishl_imm(cached_index, 3);
+            let field_addr = builder.ins().iadd(obj_ptr, byte_offset);
+            let field = builder.ins().load(types::F64, MemFlags::new(), field_addr, 24);
+            builder.ins().jump(merge_block, &[field]);
+
+            builder.switch_to_block(slow_block);
+            builder.seal_block(slow_block);
+
             // Create the property name string in the stack
             let prop_bytes = property.as_bytes();
             let prop_len = prop_bytes.len();
@@ -24907,12 +27697,16 @@ fn compile_expr(
             let slot_addr = builder.ins().stack_addr(types::I64, slot, 0);
             let len_val = builder.ins().iconst(types::I64, prop_len as i64);
 
-            // Use unified dynamic property access that handles both JS handles and native objects
-            let get_func = extern_funcs.get("js_dynamic_object_get_property")
-                .ok_or_else(|| anyhow!("js_dynamic_object_get_property not declared"))?;
+            let get_func = extern_funcs.get("js_dynamic_object_get_property_ic")
+                .ok_or_else(|| anyhow!("js_dynamic_object_get_property_ic not declared"))?;
             let get_ref = module.declare_func_in_func(*get_func, builder.func);
-            let call = builder.ins().call(get_ref, &[obj_f64, slot_addr, len_val]);
-            Ok(builder.inst_results(call)[0])
+            let call = builder.ins().call(get_ref, &[obj_f64, slot_addr, len_val, cache_addr]);
+            let slow_value = builder.inst_results(call)[0];
+            builder.ins().jump(merge_block, &[slow_value]);
+
+            builder.switch_to_block(merge_block);
+            builder.seal_block(merge_block);
+            Ok(builder.block_params(merge_block)[0])
         }
         Expr::This => {
             if let Some(ctx) = this_ctx {
@@ -24980,7 +27774,7 @@ fn compile_expr(
 
                             // Pad or truncate arguments to match expected count
                             while call_args.len() < expected_param_count {
-                                call_args.push(builder.ins().f64const(f64::NAN));
+                                call_args.push(missing_arg(builder));
                             }
                             call_args.truncate(expected_param_count);
 
@@ -25063,7 +27857,7 @@ fn compile_expr(
                                 if expected_type == types::I64 {
                                     final_call_args.push(builder.ins().iconst(types::I64, 0));
                                 } else {
-                                    final_call_args.push(builder.ins().f64const(f64::NAN));
+                                    final_call_args.push(missing_arg(builder));
                                 }
                             }
                             final_call_args.truncate(expected_param_count);
@@ -25097,8 +27891,9 @@ fn compile_expr(
                 match expr {
                     Expr::String(_) => true,
                     Expr::EnvGet(_) | Expr::EnvGetDynamic(_) | Expr::FsReadFileSync(_) => true,
+                    Expr::ImportMetaRuntime { .. } | Expr::ImportMetaResolve { .. } |
                     Expr::PathJoin(_, _) | Expr::PathDirname(_) | Expr::PathBasename(_) |
-                    Expr::PathExtname(_) | Expr::PathResolve(_) | Expr::JsonStringify(_) => true,
+                    Expr::PathExtname(_) | Expr::PathResolve(_) | Expr::JsonStringify(_) | Expr::JsonCanonicalize(_) => true,
                     Expr::CryptoRandomBytes(_) | Expr::CryptoRandomUUID |
                     Expr::CryptoSha256(_) | Expr::CryptoMd5(_) => true,
                     Expr::DateToISOString(_) => true,
@@ -25263,7 +28058,7 @@ fn compile_expr(
             // We need to track the current array pointer as it may change during push operations
             let arr_var = Variable::new(next_temp_var_id());
             builder.declare_var(arr_var, types::I64);
-            builder.def_var(arr_var, initial_arr_ptr);
+            builder.def_local(arr_var, initial_arr_ptr);
 
             // Get function references we'll need
             let push_f64_func = extern_funcs.get("js_array_push_f64")
@@ -25302,7 +28097,7 @@ fn compile_expr(
                             builder.ins().call(push_f64_ref, &[arr_ptr, val])
                         };
                         let new_arr_ptr = builder.inst_results(call)[0];
-                        builder.def_var(arr_var, new_arr_ptr);
+                        builder.def_local(arr_var, new_arr_ptr);
                     }
                     ArrayElement::Spread(spread_expr) => {
                         // Spread element - iterate over source array and push each element
@@ -25322,7 +28117,7 @@ fn compile_expr(
                         let idx_var = Variable::new(next_temp_var_id());
                         builder.declare_var(idx_var, types::I32);
                         let zero = builder.ins().iconst(types::I32, 0);
-                        builder.def_var(idx_var, zero);
+                        builder.def_local(idx_var, zero);
 
                         // Jump to loop header
                         builder.ins().jump(loop_header, &[]);
@@ -25343,13 +28138,13 @@ fn compile_expr(
                         // js_array_get_f64 returns f64, so use push_f64
                         let push_call = builder.ins().call(push_f64_ref, &[arr_ptr, elem_val]);
                         let new_arr_ptr = builder.inst_results(push_call)[0];
-                        builder.def_var(arr_var, new_arr_ptr);
+                        builder.def_local(arr_var, new_arr_ptr);
 
                         // Increment counter
                         let idx = builder.use_var(idx_var);
                         let one = builder.ins().iconst(types::I32, 1);
                         let next_idx = builder.ins().iadd(idx, one);
-                        builder.def_var(idx_var, next_idx);
+                        builder.def_local(idx_var, next_idx);
 
                         // Jump back to header
                         builder.ins().jump(loop_header, &[]);
@@ -25396,8 +28191,8 @@ fn compile_expr(
                 let val = compile_expr(builder, module, func_ids, closure_func_ids, func_wrapper_ids, extern_funcs, async_func_ids, classes, enums, func_param_types, func_union_params, func_return_types, func_hir_return_types, func_rest_param_index, imported_func_param_counts, locals, value_expr, this_ctx)?;
 
                 // Check if this is a string value that needs NaN-boxing for dynamic storage
+                // (string literals are already NaN-boxed by compile_expr)
                 let is_string = match value_expr {
-                    Expr::String(_) => true,
                     Expr::LocalGet(id) => locals.get(id).map(|info| info.is_string).unwrap_or(false),
                     _ => false,
                 };
@@ -25419,9 +28214,27 @@ fn compile_expr(
                 builder.ins().store(MemFlags::new(), final_val, obj_ptr, offset);
             }
 
-            // Create a keys array containing the property names as strings
-            // This enables Object.keys() to work at runtime
+            // Give the object the shape of its keys, which also makes Object.keys() work at
+            // runtime. Every object from this site has the same keys, so the site looks the
+            // shape up on its first run and then stores it directly
             if !props.is_empty() {
+                let shape_slot_id = module.declare_anonymous_data(true, false)?;
+                let mut shape_slot = DataDescription::new();
+                shape_slot.define_zeroinit(8);
+                shape_slot.set_align(8);
+                module.define_data(shape_slot_id, &shape_slot)?;
+                let shape_slot_gv = module.declare_data_in_func(shape_slot_id, builder.func);
+                let shape_slot_addr = builder.ins().global_value(types::I64, shape_slot_gv);
+                let cached_shape = builder.ins().load(types::I64, MemFlags::new(), shape_slot_addr, 0);
+
+                let build_block = builder.create_block();
+                let shape_done_block = builder.create_block();
+                builder.append_block_param(shape_done_block, types::I64);
+                builder.ins().brif(cached_shape, shape_done_block, &[cached_shape], build_block, &[]);
+
+                builder.switch_to_block(build_block);
+                builder.seal_block(build_block);
+
                 // Allocate array for keys
                 let arr_alloc_func = extern_funcs.get("js_array_alloc")
                     .ok_or_else(|| anyhow!("js_array_alloc not declared"))?;
@@ -25442,7 +28255,7 @@ fn compile_expr(
                 // We need to track the current keys array pointer as push may reallocate
                 let keys_arr_var = Variable::new(next_temp_var_id());
                 builder.declare_var(keys_arr_var, types::I64);
-                builder.def_var(keys_arr_var, keys_arr_ptr);
+                builder.def_local(keys_arr_var, keys_arr_ptr);
 
                 for (key, _value_expr) in props.iter() {
                     // Create string from key bytes
@@ -25463,7 +28276,6 @@ fn compile_expr(
                     let data_ptr = module.declare_data_in_func(data_id, builder.func);
                     let data_addr = builder.ins().global_value(types::I64, data_ptr);
                     let len_val = builder.ins().iconst(types::I32, key_len as i64);
-
                     let string_call = builder.ins().call(string_from_bytes_ref, &[data_addr, len_val]);
                     let key_string_ptr = builder.inst_results(string_call)[0];
 
@@ -25478,18 +28290,28 @@ fn compile_expr(
                     let current_keys_arr = builder.use_var(keys_arr_var);
                     let push_call = builder.ins().call(arr_push_ref, &[current_keys_arr, key_string_f64]);
                     let new_keys_arr = builder.inst_results(push_call)[0];
-                    builder.def_var(keys_arr_var, new_keys_arr);
+                    builder.def_local(keys_arr_var, new_keys_arr);
                 }
 
-                // Set the keys array on the object
-                let set_keys_func = extern_funcs.get("js_object_set_keys")
-                    .ok_or_else(|| anyhow!("js_object_set_keys not declared"))?;
-                let set_keys_ref = module.declare_func_in_func(*set_keys_func, builder.func);
+                let shape_of_func = extern_funcs.get("js_object_shape_of")
+                    .ok_or_else(|| anyhow!("js_object_shape_of not declared"))?;
+                let shape_of_ref = module.declare_func_in_func(*shape_of_func, builder.func);
                 let final_keys_arr = builder.use_var(keys_arr_var);
-                // Ensure both arguments are i64 (they should be, but make it explicit)
+                let shape_call = builder.ins().call(shape_of_ref, &[final_keys_arr]);
+                let shape = builder.inst_results(shape_call)[0];
+                // Keys that can't share a shape come back as is: they are this object's own,
+                // so the slot stays empty and the next object builds its own too
+                let is_shared = builder.ins().icmp(IntCC::NotEqual, shape, final_keys_arr);
+                let zero = builder.ins().iconst(types::I64, 0);
+                let slot_value = builder.ins().select(is_shared, shape, zero);
+                builder.ins().store(MemFlags::new(), slot_value, shape_slot_addr, 0);
+                builder.ins().jump(shape_done_block, &[shape]);
+
+                builder.switch_to_block(shape_done_block);
+                builder.seal_block(shape_done_block);
+                let object_shape = builder.block_params(shape_done_block)[0];
                 let obj_ptr_i64 = ensure_i64(builder, obj_ptr);
-                let keys_arr_i64 = ensure_i64(builder, final_keys_arr);
-                builder.ins().call(set_keys_ref, &[obj_ptr_i64, keys_arr_i64]);
+                builder.ins().store(MemFlags::new(), object_shape, obj_ptr_i64, 16);
             }
 
             // NaN-box the object pointer with POINTER_TAG for proper runtime identification
@@ -25535,20 +28357,19 @@ fn compile_expr(
                                     builder.use_var(shadow_var)
                                 } else {
                                     let idx_val = builder.use_var(idx_info.var);
-                                    let idx_f64 = ensure_f64(builder, idx_val);
-                                    builder.ins().fcvt_to_sint(types::I32, idx_f64)
+                                    index_i32(builder, idx_val)
                                 }
                             } else {
                                 let idx_val = compile_expr(builder, module, func_ids, closure_func_ids, func_wrapper_ids, extern_funcs, async_func_ids, classes, enums, func_param_types, func_union_params, func_return_types, func_hir_return_types, func_rest_param_index, imported_func_param_counts, locals, index, this_ctx)?;
                                 let idx_vt = builder.func.dfg.value_type(idx_val);
                                 if idx_vt == types::I32 { idx_val }
-                                else { let idx_f64 = ensure_f64(builder, idx_val); builder.ins().fcvt_to_sint(types::I32, idx_f64) }
+                                else { index_i32(builder, idx_val) }
                             }
                         } else {
                             let idx_val = compile_expr(builder, module, func_ids, closure_func_ids, func_wrapper_ids, extern_funcs, async_func_ids, classes, enums, func_param_types, func_union_params, func_return_types, func_hir_return_types, func_rest_param_index, imported_func_param_counts, locals, index, this_ctx)?;
                             let idx_vt = builder.func.dfg.value_type(idx_val);
                             if idx_vt == types::I32 { idx_val }
-                            else { let idx_f64 = ensure_f64(builder, idx_val); builder.ins().fcvt_to_sint(types::I32, idx_f64) }
+                            else { index_i32(builder, idx_val) }
                         };
 
                         // Use safe js_array_get_jsvalue for:
@@ -25593,8 +28414,7 @@ fn compile_expr(
                             builder.ins().iconst(types::I32, *n)
                         } else {
                             let idx_val = compile_expr(builder, module, func_ids, closure_func_ids, func_wrapper_ids, extern_funcs, async_func_ids, classes, enums, func_param_types, func_union_params, func_return_types, func_hir_return_types, func_rest_param_index, imported_func_param_counts, locals, index, this_ctx)?;
-                            let idx_f64 = ensure_f64(builder, idx_val);
-                            builder.ins().fcvt_to_sint(types::I32, idx_f64)
+                            index_i32(builder, idx_val)
                         };
 
                         // Call js_string_char_at to get single-char string
@@ -25623,6 +28443,7 @@ fn compile_expr(
                     Expr::String(_) => true,
                     Expr::LocalGet(id) => locals.get(id).map(|i| i.is_string).unwrap_or(false),
                     Expr::EnvGet(_) | Expr::EnvGetDynamic(_) => true,
+                    Expr::ImportMetaRuntime { .. } | Expr::ImportMetaResolve { .. } |
                     Expr::PathJoin(_, _) | Expr::PathDirname(_) | Expr::PathBasename(_) |
                     Expr::PathExtname(_) | Expr::PathResolve(_) => true,
                     Expr::Binary { op: BinaryOp::Add, left, right } => {
@@ -25676,7 +28497,7 @@ fn compile_expr(
                             if let Expr::PropertyGet { property, .. } = callee.as_ref() {
                                 // String methods that return strings
                                 matches!(property.as_str(),
-                                    "substring" | "slice" | "trim" | "toLowerCase" | "toUpperCase" |
+                                    "substring" | "slice" | "trim" | "toLowerCase" | "toUpperCase" | "toLocaleLowerCase" | "toLocaleUpperCase" |
                                     "padStart" | "padEnd" | "repeat" | "charAt" | "replace")
                             } else {
                                 false
@@ -25704,8 +28525,7 @@ fn compile_expr(
                         builder.ins().iconst(types::I32, *n)
                     } else {
                         let idx_val = compile_expr(builder, module, func_ids, closure_func_ids, func_wrapper_ids, extern_funcs, async_func_ids, classes, enums, func_param_types, func_union_params, func_return_types, func_hir_return_types, func_rest_param_index, imported_func_param_counts, locals, index, this_ctx)?;
-                        let idx_f64 = ensure_f64(builder, idx_val);
-                        builder.ins().fcvt_to_sint(types::I32, idx_f64)
+                        index_i32(builder, idx_val)
                     };
 
                     // Call js_string_char_at to get single-char string
@@ -25727,8 +28547,7 @@ fn compile_expr(
                     builder.ins().iconst(types::I32, *n)
                 } else {
                     let idx_val = compile_expr(builder, module, func_ids, closure_func_ids, func_wrapper_ids, extern_funcs, async_func_ids, classes, enums, func_param_types, func_union_params, func_return_types, func_hir_return_types, func_rest_param_index, imported_func_param_counts, locals, index, this_ctx)?;
-                    let idx_f64 = ensure_f64(builder, idx_val);
-                    builder.ins().fcvt_to_sint(types::I32, idx_f64)
+                    index_i32(builder, idx_val)
                 };
 
                 // Use the unified dynamic array access that handles both JS handles and native arrays
@@ -25909,7 +28728,7 @@ fn compile_expr(
                                 } else {
                                     builder.ins().bitcast(types::F64, MemFlags::new(), new_arr_ptr)
                                 };
-                                builder.def_var(info.var, store_val);
+                                builder.def_local(info.var, store_val);
                             }
                             builder.ins().jump(continue_block, &[]);
 
@@ -25934,6 +28753,7 @@ fn compile_expr(
                     Expr::String(_) => true,
                     Expr::LocalGet(id) => locals.get(id).map(|i| i.is_string).unwrap_or(false),
                     Expr::EnvGet(_) | Expr::EnvGetDynamic(_) => true,
+                    Expr::ImportMetaRuntime { .. } | Expr::ImportMetaResolve { .. } |
                     Expr::PathJoin(_, _) | Expr::PathDirname(_) | Expr::PathBasename(_) |
                     Expr::PathExtname(_) | Expr::PathResolve(_) => true,
                     Expr::Binary { op: BinaryOp::Add, left, right } => {
@@ -26004,7 +28824,7 @@ fn compile_expr(
                 Ok(val)
             }
         }
-        Expr::Closure { func_id, captures, mutable_captures, captures_this, enclosing_class, .. } => {
+        Expr::Closure { func_id, params, captures, mutable_captures, captures_this, enclosing_class, .. } => {
             // Create a closure object at runtime
             // 1. Get the function pointer for the closure function
             // 2. Allocate a closure object with space for captures (and `this` if captured)
@@ -26032,6 +28852,11 @@ fn compile_expr(
             let call = builder.ins().call(alloc_ref, &[func_ptr, capture_count]);
             let closure_ptr = builder.inst_results(call)[0];
 
+            // Record the arity so calls with fewer arguments pass undefined for the rest
+            if !params.is_empty() {
+                set_closure_arity(builder, module, extern_funcs, closure_ptr, params.len())?;
+            }
+
             // Get set_capture function reference (needed for both `this` and regular captures)
             let set_capture_func = extern_funcs.get("js_closure_set_capture_f64")
                 .ok_or_else(|| anyhow!("js_closure_set_capture_f64 not declared"))?;
@@ -26232,9 +29057,10 @@ fn compile_expr(
                 builder.ins().call(stdlib_ref, &[]);
             }
 
-            // Sleep for 1ms to avoid busy-waiting (allows timers to advance)
-            let sleep_func = extern_funcs.get("js_sleep_ms")
-                .ok_or_else(|| anyhow!("js_sleep_ms not declared"))?;
+            // Wait 1ms to avoid busy-waiting (allows timers to advance). Under perry/ui the
+            // wait dispatches UI events, so the window stays responsive while a handler awaits
+            let sleep_func = extern_funcs.get("js_await_wait")
+                .ok_or_else(|| anyhow!("js_await_wait not declared"))?;
             let sleep_ref = module.declare_func_in_func(*sleep_func, builder.func);
             let one_ms = builder.ins().f64const(1.0);
             builder.ins().call(sleep_ref, &[one_ms]);
@@ -26536,6 +29362,10 @@ fn compile_expr(
                 ("mysql2" | "mysql2/promise", false, "createPool") => "js_mysql2_create_pool",
 
                 // mysql2 methods - use Pool, PoolConnection, or Connection functions based on class_name
+                // query(sql, values) binds its values like execute
+                ("mysql2" | "mysql2/promise", true, "query") if args.len() > 1 && is_pool => "js_mysql2_pool_execute",
+                ("mysql2" | "mysql2/promise", true, "query") if args.len() > 1 && is_pool_connection => "js_mysql2_pool_connection_execute",
+                ("mysql2" | "mysql2/promise", true, "query") if args.len() > 1 => "js_mysql2_connection_execute",
                 ("mysql2" | "mysql2/promise", true, "query") if is_pool => "js_mysql2_pool_query",
                 ("mysql2" | "mysql2/promise", true, "query") if is_pool_connection => "js_mysql2_pool_connection_query",
                 ("mysql2" | "mysql2/promise", true, "query") => "js_mysql2_connection_query",
@@ -26719,6 +29549,9 @@ fn compile_expr(
                 // Tier 4: pg (PostgreSQL) module
                 ("pg", false, "connect") => "js_pg_connect",
                 ("pg", false, "Pool") => "js_pg_create_pool",
+                ("pg", true, "query") if args.len() > 1 && is_pool => "js_pg_pool_query_params",
+                ("pg", true, "query") if is_pool => "js_pg_pool_query",
+                ("pg", true, "query") if args.len() > 1 => "js_pg_client_query_params",
                 ("pg", true, "query") => "js_pg_client_query",
                 ("pg", true, "end") => "js_pg_client_end",
 
@@ -27037,6 +29870,115 @@ fn compile_expr(
                 ("perry/ui", true, "value") => "perry_ui_state_get",
                 ("perry/ui", true, "set") => "perry_ui_state_set",
 
+                // ========================================================================
+                // Dependency injection (perry/di)
+                // ========================================================================
+                // provide/route are emitted by lowering for decorated classes
+                ("perry/di", false, "provide") => "js_di_provide",
+                ("perry/di", false, "route") => "js_di_route",
+                ("perry/di", false, "provideValue") => "js_di_provide_value",
+                ("perry/di", false, "resolve") => "js_di_resolve",
+                ("perry/di", false, "registerControllers") => "js_di_register_controllers",
+
+                // ========================================================================
+                // Structured concurrency (perry/concurrency)
+                // ========================================================================
+                ("perry/concurrency", false, "taskGroup") => "js_task_group",
+                ("perry/concurrency", false, "cancelScope") => "js_cancel_scope",
+
+                // ========================================================================
+                // Emitter streams and pipelines (perry/streams)
+                // ========================================================================
+                ("perry/streams", false, "fromEmitter") => "js_streams_from_emitter",
+                ("perry/streams", false, "pipeline") => "js_streams_pipeline",
+
+                // ========================================================================
+                // WebAssembly plugins (perry/wasm)
+                // ========================================================================
+                ("perry/wasm", false, "loadPlugin") => "js_wasm_load_plugin",
+
+                // ========================================================================
+                // Append-only event log (perry/eventlog)
+                // ========================================================================
+                ("perry/eventlog", false, "openLog") => "js_eventlog_open",
+
+                // ========================================================================
+                // Full-text search (minisearch)
+                // ========================================================================
+                ("minisearch", false, "loadJSON") => "js_minisearch_load_json",
+                ("minisearch", false, "loadBuffer") => "js_minisearch_load_buffer",
+
+                // ========================================================================
+                // Geo and spatial utilities (perry/geo)
+                // ========================================================================
+                ("perry/geo", false, "distance") => "js_geo_distance",
+                ("perry/geo", false, "bearing") => "js_geo_bearing",
+                ("perry/geo", false, "destination") => "js_geo_destination",
+                ("perry/geo", false, "pointInPolygon") => "js_geo_point_in_polygon",
+                ("perry/geo", false, "bbox") => "js_geo_bbox",
+                ("perry/geo", false, "geohashEncode") => "js_geo_geohash_encode",
+                ("perry/geo", false, "geohashDecode") => "js_geo_geohash_decode",
+                ("perry/geo", false, "geohashBounds") => "js_geo_geohash_bounds",
+                ("perry/geo", false, "geohashNeighbors") => "js_geo_geohash_neighbors",
+
+                // ========================================================================
+                // HTTP client cache (perry/http-cache)
+                // ========================================================================
+                ("perry/http-cache", false, "configure") => "js_http_cache_configure",
+                ("perry/http-cache", false, "disable") => "js_http_cache_disable",
+                ("perry/http-cache", false, "clear") => "js_http_cache_clear",
+
+                // ========================================================================
+                // OAuth 2.0 / OpenID Connect client (openid-client)
+                // ========================================================================
+                ("openid-client", false, "discovery") => "js_oidc_discovery",
+                ("openid-client", false, "randomPKCECodeVerifier") => "js_oidc_random_pkce_code_verifier",
+                ("openid-client", false, "calculatePKCECodeChallenge") => "js_oidc_calculate_pkce_code_challenge",
+                ("openid-client", false, "randomState") => "js_oidc_random_state",
+                ("openid-client", false, "randomNonce") => "js_oidc_random_nonce",
+                ("openid-client", false, "buildAuthorizationUrl") => "js_oidc_build_authorization_url",
+                ("openid-client", false, "authorizationCodeGrant") => "js_oidc_authorization_code_grant",
+                ("openid-client", false, "clientCredentialsGrant") => "js_oidc_client_credentials_grant",
+                ("openid-client", false, "refreshTokenGrant") => "js_oidc_refresh_token_grant",
+                ("openid-client", false, "fetchUserInfo") => "js_oidc_fetch_user_info",
+
+                // ========================================================================
+                // Runtime type reflection (perry/reflect)
+                // ========================================================================
+                ("perry/reflect", false, "typeOf") => "js_reflect_type_of",
+                ("perry/reflect", false, "className") => "js_reflect_class_name",
+                ("perry/reflect", false, "fields") => "js_reflect_fields",
+
+                // ========================================================================
+                // Buffer pool (perry/buffer-pool)
+                // ========================================================================
+                ("perry/buffer-pool", false, "acquire") => "js_buffer_pool_acquire",
+                ("perry/buffer-pool", false, "release") => "js_buffer_pool_release",
+                ("perry/js-handles", false, "dispose") => "js_handles_dispose",
+                ("perry/js-handles", false, "retain") => "js_handles_retain",
+                ("perry/js-handles", false, "liveHandles") => "js_handles_live",
+
+                // ========================================================================
+                // BigInt number theory (perry/bigint)
+                // ========================================================================
+                ("perry/bigint", false, "modPow") => "js_bigint_mod_pow",
+                ("perry/bigint", false, "gcd") => "js_bigint_gcd",
+
+                // ========================================================================
+                // Temporal (perry/temporal), lowered from the global `Temporal`
+                // ========================================================================
+                ("perry/temporal", false, "PlainDate") => "js_temporal_plain_date_new",
+                ("perry/temporal", false, "PlainDate.from") => "js_temporal_plain_date_from",
+                ("perry/temporal", false, "PlainDate.compare") => "js_temporal_plain_date_compare",
+                ("perry/temporal", false, "Duration") => "js_temporal_duration_new",
+                ("perry/temporal", false, "Duration.from") => "js_temporal_duration_from",
+                ("perry/temporal", false, "Duration.compare") => "js_temporal_duration_compare",
+                ("perry/temporal", false, "ZonedDateTime.from") => "js_temporal_zoned_date_time_from",
+                ("perry/temporal", false, "ZonedDateTime.compare") => "js_temporal_zoned_date_time_compare",
+                ("perry/temporal", false, "Now.zonedDateTimeISO") => "js_temporal_now_zoned_date_time_iso",
+                ("perry/temporal", false, "Now.plainDateISO") => "js_temporal_now_plain_date_iso",
+                ("perry/temporal", false, "Now.timeZoneId") => "js_temporal_now_time_zone_id",
+
                 _ => {
                     // If JS runtime is enabled, fall back to JS runtime for unsupported native methods
                     // For module-level calls (object is None), use js_call_function
@@ -27098,14 +30040,9 @@ fn compile_expr(
                             ));
                             let args_ptr = builder.ins().stack_addr(types::I64, stack_slot, 0);
 
-                            for (i, &arg_val_raw) in arg_vals.iter().enumerate() {
-                                // Ensure argument is f64 for JS interop
-                                let arg_val_type = builder.func.dfg.value_type(arg_val_raw);
-                                let arg_val = if arg_val_type == types::I64 {
-                                    builder.ins().bitcast(types::F64, MemFlags::new(), arg_val_raw)
-                                } else {
-                                    arg_val_raw
-                                };
+                            for (i, (arg, &arg_val_raw)) in args.iter().zip(arg_vals.iter()).enumerate() {
+                                // Ensure argument is f64 for JS interop; class instances are NaN-boxed
+                                let arg_val = js_interop_arg(builder, module, extern_funcs, classes, locals, arg, arg_val_raw)?;
                                 let offset = (i * 8) as i32;
                                 builder.ins().store(MemFlags::new(), arg_val, args_ptr, offset);
                             }
@@ -27168,14 +30105,9 @@ fn compile_expr(
                             ));
                             let args_ptr = builder.ins().stack_addr(types::I64, stack_slot, 0);
 
-                            for (i, &arg_val_raw) in arg_vals.iter().enumerate() {
-                                // Ensure argument is f64 for JS interop
-                                let arg_val_type = builder.func.dfg.value_type(arg_val_raw);
-                                let arg_val = if arg_val_type == types::I64 {
-                                    builder.ins().bitcast(types::F64, MemFlags::new(), arg_val_raw)
-                                } else {
-                                    arg_val_raw
-                                };
+                            for (i, (arg, &arg_val_raw)) in args.iter().zip(arg_vals.iter()).enumerate() {
+                                // Ensure argument is f64 for JS interop; class instances are NaN-boxed
+                                let arg_val = js_interop_arg(builder, module, extern_funcs, classes, locals, arg, arg_val_raw)?;
                                 let offset = (i * 8) as i32;
                                 builder.ins().store(MemFlags::new(), arg_val, args_ptr, offset);
                             }
@@ -27200,6 +30132,12 @@ fn compile_expr(
                 }
             };
 
+            // Route shorthands given an options object, get(path, { schema }, handler),
+            // register through the generic entry point that reads the options
+            let is_route_with_opts = native_module == "fastify" && object.is_some() && args.len() >= 3
+                && matches!(method.as_str(), "get" | "post" | "put" | "delete" | "patch" | "head" | "options" | "all");
+            let func_name = if is_route_with_opts { "js_fastify_route_with_opts" } else { func_name };
+
             // Get the extern function
             let ext_func = extern_funcs.get(func_name)
                 .ok_or_else(|| anyhow!("{} not declared", func_name))?;
@@ -27274,8 +30212,8 @@ fn compile_expr(
                         call_args.push(sql_ptr);
                     }
                     // For execute, always add params array (as i64 pointer)
-                    // If no params provided, use null (0)
-                    if method == "execute" {
+                    // If no params provided, use null (0). query(sql, values) passes its values too.
+                    if method == "execute" || arg_vals.len() > 1 {
                         if arg_vals.len() > 1 {
                             // Params array is NaN-boxed, extract the raw pointer
                             let params_f64 = ensure_f64(builder, arg_vals[1]);
@@ -27558,6 +30496,18 @@ fn compile_expr(
                     // The runtime calls js_get_string_pointer_unified itself to extract strings.
                     match method.as_str() {
                         // Route methods: get(path, handler), post(path, handler), etc.
+                        "get" | "post" | "put" | "delete" | "patch" | "head" | "options" | "all" if is_route_with_opts => {
+                            // (method: string, path: string, opts: object, handler: closure)
+                            let method_val = compile_expr(builder, module, func_ids, closure_func_ids, func_wrapper_ids, extern_funcs, async_func_ids, classes, enums, func_param_types, func_union_params, func_return_types, func_hir_return_types, func_rest_param_index, imported_func_param_counts, locals, &Expr::String(method.to_uppercase()), this_ctx)?;
+                            let method_f64 = ensure_f64(builder, method_val);
+                            call_args.push(builder.ins().bitcast(types::I64, MemFlags::new(), method_f64));
+
+                            let path_f64 = ensure_f64(builder, arg_vals[0]);
+                            call_args.push(builder.ins().bitcast(types::I64, MemFlags::new(), path_f64));
+
+                            call_args.push(ensure_f64(builder, arg_vals[1]));
+                            call_args.push(ensure_i64(builder, arg_vals[2]));
+                        }
                         "get" | "post" | "put" | "delete" | "patch" | "head" | "options" | "all" => {
                             // (path: string, handler: closure)
                             if arg_vals.len() >= 2 {
@@ -28069,6 +31019,188 @@ fn compile_expr(
                         }
                         _ => arg_vals.clone()
                     }
+                } else if native_module == "perry/di" {
+                    // All values, padded with undefined to the function's arity
+                    const TAG_UNDEFINED: u64 = 0x7FFC_0000_0000_0001;
+                    let arity = match method.as_str() {
+                        "provide" | "route" => 4,
+                        "provideValue" => 2,
+                        _ => 1,
+                    };
+                    let mut args: Vec<Value> = arg_vals.iter().take(arity).map(|&val| ensure_f64(builder, val)).collect();
+                    while args.len() < arity {
+                        args.push(builder.ins().f64const(f64::from_bits(TAG_UNDEFINED)));
+                    }
+                    args
+                } else if native_module == "perry/concurrency" {
+                    // (body, options?), options padded with undefined
+                    const TAG_UNDEFINED: u64 = 0x7FFC_0000_0000_0001;
+                    let mut args: Vec<Value> = arg_vals.iter().take(2).map(|&val| ensure_f64(builder, val)).collect();
+                    while args.len() < 2 {
+                        args.push(builder.ins().f64const(f64::from_bits(TAG_UNDEFINED)));
+                    }
+                    args
+                } else if native_module == "perry/streams" {
+                    // All values, padded with undefined to the function's arity (pipeline takes up to 7 stages)
+                    const TAG_UNDEFINED: u64 = 0x7FFC_0000_0000_0001;
+                    let arity = if method == "pipeline" { 8 } else { 3 };
+                    let mut args: Vec<Value> = arg_vals.iter().take(arity).map(|&val| ensure_f64(builder, val)).collect();
+                    while args.len() < arity {
+                        args.push(builder.ins().f64const(f64::from_bits(TAG_UNDEFINED)));
+                    }
+                    args
+                } else if native_module == "perry/reflect" {
+                    // The value, NaN-boxed: string and object locals arrive as raw pointers
+                    const TAG_UNDEFINED: u64 = 0x7FFC_0000_0000_0001;
+                    match (args.first(), arg_vals.first()) {
+                        (Some(arg), Some(&val)) if builder.func.dfg.value_type(val) == types::I64 => {
+                            let is_string = matches!(arg, Expr::LocalGet(id) if locals.get(id).map(|i| i.is_string).unwrap_or(false));
+                            let nanbox_name = if is_string { "js_nanbox_string" } else { "js_nanbox_pointer" };
+                            let nanbox_func = extern_funcs.get(nanbox_name)
+                                .ok_or_else(|| anyhow!("{} not declared", nanbox_name))?;
+                            let nanbox_ref = module.declare_func_in_func(*nanbox_func, builder.func);
+                            let call = builder.ins().call(nanbox_ref, &[val]);
+                            vec![builder.inst_results(call)[0]]
+                        }
+                        (_, Some(&val)) => vec![ensure_f64(builder, val)],
+                        _ => vec![builder.ins().f64const(f64::from_bits(TAG_UNDEFINED))],
+                    }
+                } else if native_module == "perry/buffer-pool" {
+                    // A size or a Buffer; Buffer locals are raw pointers bitcast to f64
+                    match arg_vals.first() {
+                        Some(&val) => vec![ensure_f64(builder, val)],
+                        None => vec![builder.ins().f64const(0.0)],
+                    }
+                } else if native_module == "perry/js-handles" {
+                    // A NaN-boxed JS handle for dispose and retain; liveHandles takes nothing
+                    const TAG_UNDEFINED: u64 = 0x7FFC_0000_0000_0001;
+                    if method == "liveHandles" {
+                        Vec::new()
+                    } else {
+                        match arg_vals.first() {
+                            Some(&val) => vec![ensure_f64(builder, val)],
+                            None => vec![builder.ins().f64const(f64::from_bits(TAG_UNDEFINED))],
+                        }
+                    }
+                } else if native_module == "perry/bigint" {
+                    // NaN-boxed BigInts, padded with undefined (which the runtime rejects)
+                    const TAG_UNDEFINED: u64 = 0x7FFC_0000_0000_0001;
+                    let arity = if method == "modPow" { 3 } else { 2 };
+                    let mut args: Vec<Value> = arg_vals.iter().take(arity).map(|&val| ensure_f64(builder, val)).collect();
+                    while args.len() < arity {
+                        args.push(builder.ins().f64const(f64::from_bits(TAG_UNDEFINED)));
+                    }
+                    args
+                } else if native_module == "perry/wasm" || native_module == "perry/eventlog" || native_module == "minisearch"
+                    || native_module == "perry/geo"
+                {
+                    // (path or saved index, options), or a perry/geo function's values, padded with
+                    // undefined. String and object locals arrive as raw pointers and are NaN-boxed first.
+                    const TAG_UNDEFINED: u64 = 0x7FFC_0000_0000_0001;
+                    let arity = match (native_module.as_str(), method.as_str()) {
+                        ("perry/geo", "destination") => 4,
+                        ("perry/geo", "distance" | "pointInPolygon") => 3,
+                        ("perry/geo", "bearing" | "geohashEncode") => 2,
+                        ("perry/geo", _) => 1,
+                        _ => 2,
+                    };
+                    let mut prepared = Vec::with_capacity(arity);
+                    for (arg, &val) in args.iter().zip(&arg_vals).take(arity) {
+                        if builder.func.dfg.value_type(val) == types::I64 {
+                            let is_string = matches!(arg, Expr::LocalGet(id) if locals.get(id).map(|i| i.is_string).unwrap_or(false))
+                                || matches!(arg, Expr::String(_));
+                            let nanbox_name = if is_string { "js_nanbox_string" } else { "js_nanbox_pointer" };
+                            let nanbox_func = extern_funcs.get(nanbox_name)
+                                .ok_or_else(|| anyhow!("{} not declared", nanbox_name))?;
+                            let nanbox_ref = module.declare_func_in_func(*nanbox_func, builder.func);
+                            let call = builder.ins().call(nanbox_ref, &[val]);
+                            prepared.push(builder.inst_results(call)[0]);
+                        } else {
+                            prepared.push(ensure_f64(builder, val));
+                        }
+                    }
+                    while prepared.len() < arity {
+                        prepared.push(builder.ins().f64const(f64::from_bits(TAG_UNDEFINED)));
+                    }
+                    prepared
+                } else if native_module == "perry/http-cache" {
+                    // configure(options) takes the options object (undefined if omitted),
+                    // NaN-boxed when it arrives as a raw pointer; the rest take nothing
+                    const TAG_UNDEFINED: u64 = 0x7FFC_0000_0000_0001;
+                    if method != "configure" {
+                        Vec::new()
+                    } else {
+                        match arg_vals.first() {
+                            Some(&val) if builder.func.dfg.value_type(val) == types::I64 => {
+                                let nanbox_func = extern_funcs.get("js_nanbox_pointer")
+                                    .ok_or_else(|| anyhow!("js_nanbox_pointer not declared"))?;
+                                let nanbox_ref = module.declare_func_in_func(*nanbox_func, builder.func);
+                                let call = builder.ins().call(nanbox_ref, &[val]);
+                                vec![builder.inst_results(call)[0]]
+                            }
+                            Some(&val) => vec![ensure_f64(builder, val)],
+                            None => vec![builder.ins().f64const(f64::from_bits(TAG_UNDEFINED))],
+                        }
+                    }
+                } else if native_module == "openid-client" {
+                    // All values, padded with undefined to the function's arity. String and
+                    // object locals arrive as raw pointers and are NaN-boxed first.
+                    const TAG_UNDEFINED: u64 = 0x7FFC_0000_0000_0001;
+                    let arity = match method.as_str() {
+                        "randomPKCECodeVerifier" | "randomState" | "randomNonce" => 0,
+                        "calculatePKCECodeChallenge" => 1,
+                        "buildAuthorizationUrl" | "clientCredentialsGrant" => 2,
+                        "authorizationCodeGrant" => 4,
+                        _ => 3,
+                    };
+                    let mut prepared = Vec::with_capacity(arity);
+                    for (arg, &val) in args.iter().zip(&arg_vals).take(arity) {
+                        if builder.func.dfg.value_type(val) == types::I64 {
+                            let is_string = matches!(arg, Expr::LocalGet(id) if locals.get(id).map(|i| i.is_string).unwrap_or(false))
+                                || matches!(arg, Expr::String(_));
+                            let nanbox_name = if is_string { "js_nanbox_string" } else { "js_nanbox_pointer" };
+                            let nanbox_func = extern_funcs.get(nanbox_name)
+                                .ok_or_else(|| anyhow!("{} not declared", nanbox_name))?;
+                            let nanbox_ref = module.declare_func_in_func(*nanbox_func, builder.func);
+                            let call = builder.ins().call(nanbox_ref, &[val]);
+                            prepared.push(builder.inst_results(call)[0]);
+                        } else {
+                            prepared.push(ensure_f64(builder, val));
+                        }
+                    }
+                    while prepared.len() < arity {
+                        prepared.push(builder.ins().f64const(f64::from_bits(TAG_UNDEFINED)));
+                    }
+                    prepared
+                } else if native_module == "perry/temporal" {
+                    // All values, padded with undefined to the function's arity. String and
+                    // object locals arrive as raw pointers and are NaN-boxed first.
+                    const TAG_UNDEFINED: u64 = 0x7FFC_0000_0000_0001;
+                    let arity = match method.as_str() {
+                        "Duration" => 10,
+                        "PlainDate" => 3,
+                        "PlainDate.compare" | "Duration.compare" | "ZonedDateTime.compare" => 2,
+                        "Now.timeZoneId" => 0,
+                        _ => 1,
+                    };
+                    let mut prepared = Vec::with_capacity(arity);
+                    for (arg, &val) in args.iter().zip(&arg_vals).take(arity) {
+                        if builder.func.dfg.value_type(val) == types::I64 {
+                            let is_string = matches!(arg, Expr::LocalGet(id) if locals.get(id).map(|i| i.is_string).unwrap_or(false));
+                            let nanbox_name = if is_string { "js_nanbox_string" } else { "js_nanbox_pointer" };
+                            let nanbox_func = extern_funcs.get(nanbox_name)
+                                .ok_or_else(|| anyhow!("{} not declared", nanbox_name))?;
+                            let nanbox_ref = module.declare_func_in_func(*nanbox_func, builder.func);
+                            let call = builder.ins().call(nanbox_ref, &[val]);
+                            prepared.push(builder.inst_results(call)[0]);
+                        } else {
+                            prepared.push(ensure_f64(builder, val));
+                        }
+                    }
+                    while prepared.len() < arity {
+                        prepared.push(builder.ins().f64const(f64::from_bits(TAG_UNDEFINED)));
+                    }
+                    prepared
                 } else if native_module == "perry/ui" {
                     match method.as_str() {
                         "Text" => {
@@ -28229,6 +31361,48 @@ fn compile_expr(
                             Ok(builder.inst_results(call)[0])
                         }
                     }
+                } else if native_module == "perry/di" {
+                    // Already a NaN-boxed value
+                    Ok(result)
+                } else if native_module == "perry/concurrency" {
+                    // A NaN-boxed promise
+                    Ok(result)
+                } else if native_module == "perry/streams" {
+                    // A NaN-boxed stream handle or promise
+                    Ok(result)
+                } else if native_module == "perry/wasm" {
+                    // A NaN-boxed plugin handle
+                    Ok(result)
+                } else if native_module == "perry/eventlog" {
+                    // A NaN-boxed log handle
+                    Ok(result)
+                } else if native_module == "minisearch" {
+                    // A NaN-boxed index handle
+                    Ok(result)
+                } else if native_module == "perry/geo" {
+                    // A number, or a NaN-boxed boolean, string or array
+                    Ok(result)
+                } else if native_module == "perry/http-cache" {
+                    // undefined
+                    Ok(result)
+                } else if native_module == "openid-client" {
+                    // A NaN-boxed promise, string or URL object
+                    Ok(result)
+                } else if native_module == "perry/reflect" {
+                    // A NaN-boxed object, array, string or undefined
+                    Ok(result)
+                } else if native_module == "perry/buffer-pool" {
+                    // A Buffer pointer as f64 bits, like Buffer.alloc, or undefined
+                    Ok(result)
+                } else if native_module == "perry/js-handles" {
+                    // A NaN-boxed boolean, the JS handle passed in, or an array of strings
+                    Ok(result)
+                } else if native_module == "perry/bigint" {
+                    // A NaN-boxed BigInt
+                    Ok(result)
+                } else if native_module == "perry/temporal" {
+                    // A NaN-boxed Temporal handle, number or string
+                    Ok(result)
                 } else if native_module == "perry/ui" {
                     // perry/ui result handling
                     match method.as_str() {
@@ -28271,6 +31445,11 @@ fn compile_expr(
                 let call = builder.ins().call(alloc_ref, &[func_ptr, capture_count]);
                 let closure_ptr = builder.inst_results(call)[0];
 
+                let arity = func_param_types.get(func_id).map_or(0, |params| params.len());
+                if arity > 0 {
+                    set_closure_arity(builder, module, extern_funcs, closure_ptr, arity)?;
+                }
+
                 // Return closure pointer as i64
                 Ok(closure_ptr)
             } else {
@@ -28526,6 +31705,18 @@ fn compile_expr(
             let call = builder.ins().call(instanceof_ref, &[val_f64, class_id_val]);
             Ok(builder.inst_results(call)[0])
         }
+        Expr::Narrowed { value, ty } => {
+            // The guard has already checked the type at runtime, so the value is used as it is:
+            // a NaN-boxed number is the f64 itself. What changes is the local it initializes:
+            // unlike a plain copy of a dynamic local (`Let { init: LocalGet }`) it doesn't inherit
+            // the union flag, so it is compiled by its declared, narrowed type
+            let val = compile_expr(builder, module, func_ids, closure_func_ids, func_wrapper_ids, extern_funcs, async_func_ids, classes, enums, func_param_types, func_union_params, func_return_types, func_hir_return_types, func_rest_param_index, imported_func_param_counts, locals, value, this_ctx)?;
+            if matches!(ty, perry_types::Type::Number) {
+                Ok(ensure_f64(builder, val))
+            } else {
+                Ok(val)
+            }
+        }
         Expr::In { property, object } => {
             // Compile the 'in' operator: "key" in obj
             // Returns true (1.0) if the property exists in the object, false (0.0) otherwise
@@ -28751,6 +31942,30 @@ fn compile_expr(
             Ok(builder.inst_results(call)[0])
         }
 
+        Expr::NativeAddonLoad { path } => {
+            let path_bytes = path.as_bytes();
+            let path_data_id = module.declare_data(
+                &format!("__napi_addon_path_{}", next_js_data_id()),
+                Linkage::Local,
+                false,
+                false,
+            )?;
+            let mut data_desc = cranelift_module::DataDescription::new();
+            data_desc.define(path_bytes.to_vec().into_boxed_slice());
+            module.define_data(path_data_id, &data_desc)?;
+
+            let path_gv = module.declare_data_in_func(path_data_id, builder.func);
+            let path_ptr = builder.ins().global_value(types::I64, path_gv);
+            let path_len_val = builder.ins().iconst(types::I64, path_bytes.len() as i64);
+
+            // js_napi_load(path_ptr, path_len) -> exports (NaN-boxed)
+            let load_func = extern_funcs.get("js_napi_load")
+                .ok_or_else(|| anyhow!("js_napi_load not declared"))?;
+            let load_ref = module.declare_func_in_func(*load_func, builder.func);
+            let call = builder.ins().call(load_ref, &[path_ptr, path_len_val]);
+            Ok(builder.inst_results(call)[0])
+        }
+
         // ============================================
         // V8 JavaScript Runtime expressions
         // ============================================
@@ -28857,8 +32072,9 @@ fn compile_expr(
                         Expr::String(_) => true,
                         Expr::LocalGet(id) => locals.get(id).map(|i| i.is_string).unwrap_or(false),
                         Expr::EnvGet(_) | Expr::EnvGetDynamic(_) | Expr::FsReadFileSync(_) => true,
+                        Expr::ImportMetaRuntime { .. } | Expr::ImportMetaResolve { .. } |
                         Expr::PathJoin(_, _) | Expr::PathDirname(_) | Expr::PathBasename(_) |
-                        Expr::PathExtname(_) | Expr::PathResolve(_) | Expr::JsonStringify(_) => true,
+                        Expr::PathExtname(_) | Expr::PathResolve(_) | Expr::JsonStringify(_) | Expr::JsonCanonicalize(_) => true,
                         _ => false,
                     }
                 }
@@ -28878,13 +32094,8 @@ fn compile_expr(
                         let call = builder.ins().call(nanbox_string_ref, &[ptr]);
                         builder.inst_results(call)[0]
                     } else {
-                        // Ensure non-string arguments are f64 for JS interop
-                        let arg_val_type = builder.func.dfg.value_type(arg_val);
-                        if arg_val_type == types::I64 {
-                            builder.ins().bitcast(types::F64, cranelift_codegen::ir::MemFlags::new(), arg_val)
-                        } else {
-                            arg_val
-                        }
+                        // Ensure non-string arguments are f64 for JS interop; class instances are NaN-boxed
+                        js_interop_arg(builder, module, extern_funcs, classes, locals, arg, arg_val)?
                     };
 
                     let offset = (i * 8) as i32;
@@ -28953,13 +32164,8 @@ fn compile_expr(
 
                 for (i, arg) in args.iter().enumerate() {
                     let arg_val_raw = compile_expr(builder, module, func_ids, closure_func_ids, func_wrapper_ids, extern_funcs, async_func_ids, classes, enums, func_param_types, func_union_params, func_return_types, func_hir_return_types, func_rest_param_index, imported_func_param_counts, locals, arg, this_ctx)?;
-                    // Ensure argument is f64 for JS interop
-                    let arg_val_type = builder.func.dfg.value_type(arg_val_raw);
-                    let arg_val = if arg_val_type == types::I64 {
-                        builder.ins().bitcast(types::F64, cranelift_codegen::ir::MemFlags::new(), arg_val_raw)
-                    } else {
-                        arg_val_raw
-                    };
+                    // Ensure argument is f64 for JS interop; class instances are NaN-boxed
+                    let arg_val = js_interop_arg(builder, module, extern_funcs, classes, locals, arg, arg_val_raw)?;
                     let offset = (i * 8) as i32;
                     builder.ins().store(cranelift_codegen::ir::MemFlags::new(), arg_val, args_ptr, offset);
                 }
@@ -29050,13 +32256,8 @@ fn compile_expr(
 
             // Compile value expression
             let value_val_raw = compile_expr(builder, module, func_ids, closure_func_ids, func_wrapper_ids, extern_funcs, async_func_ids, classes, enums, func_param_types, func_union_params, func_return_types, func_hir_return_types, func_rest_param_index, imported_func_param_counts, locals, value, this_ctx)?;
-            // Ensure value is f64 for JS interop
-            let value_val_type = builder.func.dfg.value_type(value_val_raw);
-            let value_val = if value_val_type == types::I64 {
-                builder.ins().bitcast(types::F64, cranelift_codegen::ir::MemFlags::new(), value_val_raw)
-            } else {
-                value_val_raw
-            };
+            // Ensure value is f64 for JS interop; class instances are NaN-boxed
+            let value_val = js_interop_arg(builder, module, extern_funcs, classes, locals, value, value_val_raw)?;
 
             let call_func = extern_funcs.get("js_set_property")
                 .ok_or_else(|| anyhow!("js_set_property not declared"))?;
@@ -29104,13 +32305,8 @@ fn compile_expr(
 
                 for (i, arg) in args.iter().enumerate() {
                     let arg_val_raw = compile_expr(builder, module, func_ids, closure_func_ids, func_wrapper_ids, extern_funcs, async_func_ids, classes, enums, func_param_types, func_union_params, func_return_types, func_hir_return_types, func_rest_param_index, imported_func_param_counts, locals, arg, this_ctx)?;
-                    // Ensure argument is f64 for JS interop
-                    let arg_val_type = builder.func.dfg.value_type(arg_val_raw);
-                    let arg_val = if arg_val_type == types::I64 {
-                        builder.ins().bitcast(types::F64, cranelift_codegen::ir::MemFlags::new(), arg_val_raw)
-                    } else {
-                        arg_val_raw
-                    };
+                    // Ensure argument is f64 for JS interop; class instances are NaN-boxed
+                    let arg_val = js_interop_arg(builder, module, extern_funcs, classes, locals, arg, arg_val_raw)?;
                     let offset = (i * 8) as i32;
                     builder.ins().store(cranelift_codegen::ir::MemFlags::new(), arg_val, args_ptr, offset);
                 }
@@ -29152,13 +32348,8 @@ fn compile_expr(
 
                 for (i, arg) in args.iter().enumerate() {
                     let arg_val_raw = compile_expr(builder, module, func_ids, closure_func_ids, func_wrapper_ids, extern_funcs, async_func_ids, classes, enums, func_param_types, func_union_params, func_return_types, func_hir_return_types, func_rest_param_index, imported_func_param_counts, locals, arg, this_ctx)?;
-                    // Ensure argument is f64 for JS interop
-                    let arg_val_type = builder.func.dfg.value_type(arg_val_raw);
-                    let arg_val = if arg_val_type == types::I64 {
-                        builder.ins().bitcast(types::F64, cranelift_codegen::ir::MemFlags::new(), arg_val_raw)
-                    } else {
-                        arg_val_raw
-                    };
+                    // Ensure argument is f64 for JS interop; class instances are NaN-boxed
+                    let arg_val = js_interop_arg(builder, module, extern_funcs, classes, locals, arg, arg_val_raw)?;
                     let offset = (i * 8) as i32;
                     builder.ins().store(cranelift_codegen::ir::MemFlags::new(), arg_val, args_ptr, offset);
                 }
@@ -29258,7 +32449,7 @@ fn compile_expr(
 /// Compile statement with this context for constructors
 fn compile_stmt_with_this(
     builder: &mut FunctionBuilder,
-    module: &mut ObjectModule,
+    module: &mut CodegenModule,
     func_ids: &HashMap<u32, cranelift_module::FuncId>,
     closure_func_ids: &HashMap<u32, cranelift_module::FuncId>,
     func_wrapper_ids: &HashMap<u32, cranelift_module::FuncId>,
@@ -0,0 +1,435 @@
+//! DWARF debug info for compiled modules (`perry compile --debug`)
+//!
+//! While a module is built with debug info, the code generated for each function and for
+//! each call or `new` expression is tagged with a Cranelift `SourceLoc` indexing a table of
+//! HIR spans. After a function is defined, the offsets Cranelift reports for those tags
+//! become rows of the DWARF line program, so debuggers and profilers attribute addresses
+//! to TypeScript lines.
+//!
+//! Every function gets a `DW_TAG_subprogram` with its TypeScript name (the symbol name for
+//! the wrappers and glue the compiler synthesizes), and functions compiled from source also
+//! list their named parameters and locals. Their locations come from Cranelift's value
+//! labels: registers, or stack slots addressed from the frame pointer, which `--debug`
+//! keeps (`preserve_frame_pointers`). Locals are typed by their machine representation:
+//! `f64` values are numbers or NaN-boxed JS values, `i64` values raw pointers.
+//!
+//! The sections are added to the object with relocations against the functions' symbols.
+//! ELF linkers keep them in the executable; on macOS they stay in the object files and
+//! `dsymutil` collects them into a `.dSYM` bundle.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use cranelift_codegen::gimli::write::{
+    Address, AttributeValue, DwarfUnit, EndianVec, Expression, FileId, LineProgram, LineString, Location as DwarfLocation,
+    LocationList, Range, RangeList, RelocateWriter, Relocation, RelocationTarget, Sections, UnitEntryId,
+};
+use cranelift_codegen::gimli::{self, Encoding, Format, LineEncoding, Register, RunTimeEndian};
+use cranelift_codegen::ir::{types, Endianness, SourceLoc, Type, ValueLabel, ValueLabelAssignments};
+use cranelift_codegen::isa::TargetIsa;
+use cranelift_codegen::{Context, LabelValueLoc};
+use cranelift_module::FuncId;
+use cranelift_object::ObjectProduct;
+use object::write::{Relocation as ObjectRelocation, SectionId as ObjectSectionId, StandardSegment, SymbolId};
+use object::{BinaryFormat, RelocationEncoding, RelocationFlags, RelocationKind, SectionKind};
+use perry_diagnostics::{Location, SourceCache, Span};
+
+thread_local! {
+    /// Spans the code being generated points at, indexed by `SourceLoc`; None while the
+    /// module is built without debug info
+    static SPANS: RefCell<Option<Vec<Span>>> = RefCell::new(None);
+}
+
+/// Start tagging code with source locations, if the module is built with debug info
+pub(crate) fn begin_module(enabled: bool) {
+    SPANS.with(|spans| *spans.borrow_mut() = enabled.then(Vec::new));
+}
+
+/// The `SourceLoc` to tag the code for `span` with, when building with debug info
+pub(crate) fn srcloc(span: Span) -> Option<SourceLoc> {
+    if span.is_dummy() {
+        return None;
+    }
+    SPANS.with(|spans| {
+        let mut spans = spans.borrow_mut();
+        let spans = spans.as_mut()?;
+        spans.push(span);
+        Some(SourceLoc::new(spans.len() as u32 - 1))
+    })
+}
+
+fn span_of(loc: SourceLoc) -> Option<Span> {
+    if loc.is_default() {
+        return None;
+    }
+    SPANS.with(|spans| spans.borrow().as_ref()?.get(loc.bits() as usize).copied())
+}
+
+/// What the function being built knows about itself, handed over before it is defined
+#[derive(Clone)]
+pub(crate) struct FunctionSource {
+    pub name: String,
+    pub span: Span,
+    /// Named locals: Cranelift variable index, name, and whether it is a parameter
+    pub variables: Vec<(u32, String, bool)>,
+}
+
+/// Where a local lives over a range of code offsets
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum VariableLocation {
+    /// DWARF register number
+    Register(u16),
+    /// Offset from the canonical frame address
+    Frame(i64),
+}
+
+struct Variable {
+    name: String,
+    is_param: bool,
+    ty: Type,
+    ranges: Vec<(u32, u32, VariableLocation)>,
+}
+
+struct Function {
+    id: FuncId,
+    name: String,
+    span: Option<Span>,
+    size: u32,
+    /// (code offset, span) in address order
+    rows: Vec<(u32, Span)>,
+    variables: Vec<Variable>,
+}
+
+/// Debug info collected for the functions of one module
+pub(crate) struct DebugInfo {
+    endian: RunTimeEndian,
+    /// DWARF number of the frame pointer register, where that's known
+    frame_register: Option<u16>,
+    functions: Vec<Function>,
+}
+
+impl DebugInfo {
+    pub(crate) fn new(isa: &dyn TargetIsa) -> Self {
+        let endian = match isa.endianness() {
+            Endianness::Little => RunTimeEndian::Little,
+            Endianness::Big => RunTimeEndian::Big,
+        };
+        // rbp on x86-64 and x29 on AArch64: with frame pointers preserved, the canonical
+        // frame address is 16 bytes above both
+        let frame_register = match isa.name() {
+            "x64" => Some(6),
+            "aarch64" => Some(29),
+            _ => None,
+        };
+        Self { endian, frame_register, functions: Vec::new() }
+    }
+
+    /// Record the function just defined from `ctx`
+    pub(crate) fn add_function(&mut self, id: FuncId, symbol: &str, source: Option<FunctionSource>, ctx: &Context, isa: &dyn TargetIsa) {
+        let Some(compiled) = ctx.compiled_code() else { return };
+        let mut rows: Vec<(u32, Span)> = Vec::new();
+        for srcloc in compiled.buffer.get_srclocs_sorted() {
+            if let Some(span) = span_of(srcloc.loc) {
+                if rows.last().map(|(_, last)| *last != span).unwrap_or(true) {
+                    rows.push((srcloc.start, span));
+                }
+            }
+        }
+
+        let mut variables = Vec::new();
+        if let Some(source) = &source {
+            // The type of the values labeled with each variable
+            let mut label_types: HashMap<u32, Type> = HashMap::new();
+            if let Some(labels) = &ctx.func.dfg.values_labels {
+                for (value, assignments) in labels {
+                    if let ValueLabelAssignments::Starts(starts) = assignments {
+                        for start in starts {
+                            label_types.entry(start.label.as_u32()).or_insert_with(|| ctx.func.dfg.value_type(*value));
+                        }
+                    }
+                }
+            }
+            for (var, name, is_param) in &source.variables {
+                let ranges = compiled
+                    .value_labels_ranges
+                    .get(&ValueLabel::from_u32(*var))
+                    .map(|ranges| {
+                        ranges
+                            .iter()
+                            .filter_map(|range| {
+                                let location = match range.loc {
+                                    LabelValueLoc::Reg(reg) => VariableLocation::Register(isa.map_regalloc_reg_to_dwarf(reg).ok()?),
+                                    LabelValueLoc::CFAOffset(offset) => VariableLocation::Frame(offset),
+                                };
+                                (range.start < range.end).then_some((range.start, range.end, location))
+                            })
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                let ty = label_types.get(var).copied().unwrap_or(types::F64);
+                variables.push(Variable { name: name.clone(), is_param: *is_param, ty, ranges });
+            }
+        }
+
+        let (name, span) = match source {
+            Some(source) if !source.name.is_empty() => (source.name, Some(source.span)),
+            Some(source) => ("<anonymous>".to_string(), Some(source.span)),
+            None => (symbol.to_string(), None),
+        };
+        let size = compiled.buffer.total_size();
+        self.functions.push(Function { id, name, span: span.filter(|span| !span.is_dummy()), size, rows, variables });
+    }
+
+    /// Add the `.debug_*` sections describing the recorded functions to the object
+    pub(crate) fn write(self, product: &mut ObjectProduct, sources: Option<&SourceCache>, module_name: &str) -> Result<()> {
+        if self.functions.is_empty() {
+            return Ok(());
+        }
+        let locate = |span: Span| sources.and_then(|sources| sources.location(span));
+        let encoding = Encoding { format: Format::Dwarf32, version: 4, address_size: 8 };
+        let mut dwarf = DwarfUnit::new(encoding);
+
+        // The unit is named after the module's source file
+        let unit_file = self
+            .functions
+            .iter()
+            .find_map(|function| function.span.and_then(locate))
+            .map(|location| location.file)
+            .unwrap_or_else(|| module_name.to_string());
+        let comp_dir = std::env::current_dir().map(|dir| dir.display().to_string()).unwrap_or_else(|_| ".".to_string());
+        dwarf.unit.line_program = LineProgram::new(
+            encoding,
+            LineEncoding::default(),
+            LineString::new(comp_dir.as_bytes(), encoding, &mut dwarf.line_strings),
+            LineString::new(unit_file.as_bytes(), encoding, &mut dwarf.line_strings),
+            None,
+        );
+        let mut files: HashMap<String, FileId> = HashMap::new();
+        let mut file_id = |dwarf: &mut DwarfUnit, path: &str| -> FileId {
+            if let Some(id) = files.get(path) {
+                return *id;
+            }
+            let path_ref = Path::new(path);
+            let dir = path_ref.parent().map(|dir| dir.display().to_string()).filter(|dir| !dir.is_empty());
+            let name = path_ref.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_else(|| path.to_string());
+            let directory = match dir {
+                Some(dir) => {
+                    let dir = LineString::new(dir.as_bytes(), encoding, &mut dwarf.line_strings);
+                    dwarf.unit.line_program.add_directory(dir)
+                }
+                None => dwarf.unit.line_program.default_directory(),
+            };
+            let name = LineString::new(name.as_bytes(), encoding, &mut dwarf.line_strings);
+            let id = dwarf.unit.line_program.add_file(name, directory, None);
+            files.insert(path.to_string(), id);
+            id
+        };
+
+        let root = dwarf.unit.root();
+        let producer = dwarf.strings.add(format!("perry {}", env!("CARGO_PKG_VERSION")));
+        let unit_name = dwarf.strings.add(unit_file.as_str());
+        let unit_dir = dwarf.strings.add(comp_dir.as_str());
+        let ranges = RangeList(
+            self.functions
+                .iter()
+                .enumerate()
+                .map(|(index, function)| Range::StartLength { begin: Address::Symbol { symbol: index, addend: 0 }, length: function.size as u64 })
+                .collect(),
+        );
+        let ranges = dwarf.unit.ranges.add(ranges);
+        let entry = dwarf.unit.get_mut(root);
+        entry.set(gimli::DW_AT_producer, AttributeValue::StringRef(producer));
+        // DWARF has no code for TypeScript; C makes debuggers print locals as plain values
+        entry.set(gimli::DW_AT_language, AttributeValue::Language(gimli::DW_LANG_C99));
+        entry.set(gimli::DW_AT_name, AttributeValue::StringRef(unit_name));
+        entry.set(gimli::DW_AT_comp_dir, AttributeValue::StringRef(unit_dir));
+        entry.set(gimli::DW_AT_low_pc, AttributeValue::Address(Address::Constant(0)));
+        entry.set(gimli::DW_AT_ranges, AttributeValue::RangeListRef(ranges));
+
+        let mut base_types: HashMap<Type, UnitEntryId> = HashMap::new();
+        for (index, function) in self.functions.iter().enumerate() {
+            let address = |offset: u32| Address::Symbol { symbol: index, addend: offset as i64 };
+
+            dwarf.unit.line_program.begin_sequence(Some(address(0)));
+            for (offset, span) in &function.rows {
+                let Some(location) = locate(*span) else { continue };
+                let file = file_id(&mut dwarf, &location.file);
+                let row = dwarf.unit.line_program.row();
+                row.address_offset = *offset as u64;
+                row.file = file;
+                row.line = location.line as u64;
+                row.column = location.column as u64;
+                dwarf.unit.line_program.generate_row();
+            }
+            dwarf.unit.line_program.end_sequence(function.size as u64);
+
+            let subprogram = dwarf.unit.add(root, gimli::DW_TAG_subprogram);
+            let name = dwarf.strings.add(function.name.as_str());
+            let declaration: Option<Location> = function.span.and_then(locate);
+            let decl_file = declaration.as_ref().map(|location| file_id(&mut dwarf, &location.file));
+            let entry = dwarf.unit.get_mut(subprogram);
+            entry.set(gimli::DW_AT_name, AttributeValue::StringRef(name));
+            entry.set(gimli::DW_AT_external, AttributeValue::Flag(true));
+            entry.set(gimli::DW_AT_low_pc, AttributeValue::Address(address(0)));
+            entry.set(gimli::DW_AT_high_pc, AttributeValue::Udata(function.size as u64));
+            if let (Some(location), Some(file)) = (&declaration, decl_file) {
+                entry.set(gimli::DW_AT_decl_file, AttributeValue::FileIndex(Some(file)));
+                entry.set(gimli::DW_AT_decl_line, AttributeValue::Udata(location.line as u64));
+            }
+            if let Some(register) = self.frame_register {
+                let mut frame_base = Expression::new();
+                frame_base.op_breg(Register(register), 16);
+                entry.set(gimli::DW_AT_frame_base, AttributeValue::Exprloc(frame_base));
+            }
+
+            for variable in &function.variables {
+                let ty = *base_types.entry(variable.ty).or_insert_with(|| {
+                    let id = dwarf.unit.add(root, gimli::DW_TAG_base_type);
+                    let (name, encoding) = if variable.ty.is_float() { ("f64", gimli::DW_ATE_float) } else { ("i64", gimli::DW_ATE_signed) };
+                    let name = if variable.ty.bytes() == 4 { if variable.ty.is_float() { "f32" } else { "i32" } } else { name };
+                    let name = dwarf.strings.add(name);
+                    let entry = dwarf.unit.get_mut(id);
+                    entry.set(gimli::DW_AT_name, AttributeValue::StringRef(name));
+                    entry.set(gimli::DW_AT_encoding, AttributeValue::Encoding(encoding));
+                    entry.set(gimli::DW_AT_byte_size, AttributeValue::Data1(variable.ty.bytes() as u8));
+                    id
+                });
+                let locations: Vec<DwarfLocation> = variable
+                    .ranges
+                    .iter()
+                    .filter(|(_, _, location)| matches!(location, VariableLocation::Register(_)) || self.frame_register.is_some())
+                    .map(|(start, end, location)| {
+                        let mut data = Expression::new();
+                        match location {
+                            VariableLocation::Register(register) => data.op_reg(Register(*register)),
+                            VariableLocation::Frame(offset) => data.op_fbreg(*offset),
+                        }
+                        DwarfLocation::StartEnd { begin: address(*start), end: address(*end), data }
+                    })
+                    .collect();
+                let tag = if variable.is_param { gimli::DW_TAG_formal_parameter } else { gimli::DW_TAG_variable };
+                let id = dwarf.unit.add(subprogram, tag);
+                let name = dwarf.strings.add(variable.name.as_str());
+                let location = (!locations.is_empty()).then(|| dwarf.unit.locations.add(LocationList(locations)));
+                let entry = dwarf.unit.get_mut(id);
+                entry.set(gimli::DW_AT_name, AttributeValue::StringRef(name));
+                entry.set(gimli::DW_AT_type, AttributeValue::UnitRef(ty));
+                if let Some(location) = location {
+                    entry.set(gimli::DW_AT_location, AttributeValue::LocationListRef(location));
+                }
+            }
+        }
+
+        let mut sections = Sections::new(SectionWriter::new(self.endian));
+        dwarf.write(&mut sections).map_err(|e| anyhow!("Failed to write debug info: {}", e))?;
+
+        // Each non-empty section becomes an object section, then the relocations within
+        // them are resolved against the function symbols and the other debug sections
+        let mut section_ids: HashMap<gimli::SectionId, (ObjectSectionId, SymbolId)> = HashMap::new();
+        let _: Result<(), ()> = sections.for_each(|id, section| {
+            if section.data.slice().is_empty() {
+                return Ok(());
+            }
+            let object = &mut product.object;
+            let name = match object.format() {
+                BinaryFormat::MachO => id.name().replace('.', "__"),
+                _ => id.name().to_string(),
+            };
+            let segment = object.segment_name(StandardSegment::Debug).to_vec();
+            let section_id = object.add_section(segment, name.into_bytes(), SectionKind::Debug);
+            object.set_section_data(section_id, section.data.slice().to_vec(), 1);
+            let symbol = object.section_symbol(section_id);
+            section_ids.insert(id, (section_id, symbol));
+            Ok(())
+        });
+        let functions = &self.functions;
+        sections.for_each(|id, section| {
+            let Some(&(section_id, _)) = section_ids.get(&id) else { return Ok(()) };
+            for relocation in &section.relocations {
+                let (symbol, addend) = match relocation.target {
+                    RelocationTarget::Section(target) => {
+                        let (_, symbol) = section_ids.get(&target).ok_or_else(|| anyhow!("debug info refers to the empty section {}", target.name()))?;
+                        (*symbol, relocation.addend)
+                    }
+                    RelocationTarget::Symbol(index) => {
+                        let symbol = product.function_symbol(functions[index].id);
+                        let (symbol, offset) = product.object.symbol_section_and_offset(symbol).unwrap_or((symbol, 0));
+                        (symbol, offset as i64 + relocation.addend)
+                    }
+                };
+                product
+                    .object
+                    .add_relocation(
+                        section_id,
+                        ObjectRelocation {
+                            offset: relocation.offset as u64,
+                            symbol,
+                            addend,
+                            flags: RelocationFlags::Generic {
+                                kind: RelocationKind::Absolute,
+                                encoding: RelocationEncoding::Generic,
+                                size: relocation.size * 8,
+                            },
+                        },
+                    )
+                    .map_err(|e| anyhow!("Failed to relocate debug info: {}", e))?;
+            }
+            Ok(())
+        })
+    }
+}
+
+/// A debug section's bytes and the relocations they need
+#[derive(Clone)]
+struct SectionWriter {
+    data: EndianVec<RunTimeEndian>,
+    relocations: Vec<Relocation>,
+}
+
+impl SectionWriter {
+    fn new(endian: RunTimeEndian) -> Self {
+        Self { data: EndianVec::new(endian), relocations: Vec::new() }
+    }
+}
+
+impl RelocateWriter for SectionWriter {
+    type Writer = EndianVec<RunTimeEndian>;
+
+    fn writer(&self) -> &Self::Writer {
+        &self.data
+    }
+
+    fn writer_mut(&mut self) -> &mut Self::Writer {
+        &mut self.data
+    }
+
+    fn relocate(&mut self, relocation: Relocation) {
+        self.relocations.push(relocation);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use perry_diagnostics::FileId;
+
+    #[test]
+    fn test_srcloc_table() {
+        let span = Span::new(FileId(0), 4, 9);
+        begin_module(false);
+        assert_eq!(srcloc(span), None);
+
+        begin_module(true);
+        let first = srcloc(span).unwrap();
+        let second = srcloc(Span { start: 12, ..span }).unwrap();
+        assert_ne!(first, second);
+        assert_eq!(span_of(first), Some(span));
+        assert_eq!(span_of(second).map(|span| span.start), Some(12));
+        assert_eq!(span_of(SourceLoc::default()), None);
+        // Synthesized code has no location
+        assert_eq!(srcloc(Span::DUMMY), None);
+        begin_module(false);
+    }
+}
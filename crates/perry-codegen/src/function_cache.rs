@@ -0,0 +1,267 @@
+//! Machine code of single functions, reused across builds of a module
+//!
+//! An edit to one function changes its module's object code, so the whole module misses
+//! the object cache, yet its other functions compile to the same machine code as before.
+//! Each function is looked up here by a hash of the Cranelift IR perry lowered it to,
+//! just before Cranelift would compile it: the IR reflects the function's HIR and all it
+//! depends on (signatures, class layouts, module variables), with the functions and data
+//! it references named by symbol rather than by their ids in this build. The target ISA
+//! and its flags are part of the key. On a hit the cached code and relocations are defined
+//! directly and Cranelift's optimizer, register allocator and emitter are skipped.
+//!
+//! A cache holds one module's functions: loaded from its previous build, it keeps the
+//! entries this build hit or compiled and drops the rest. Functions built with debug info
+//! always compile, since their DWARF comes from Cranelift's compile results.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use cranelift_codegen::binemit::Reloc;
+use cranelift_codegen::ir::{ExternalName, KnownSymbol, LibCall, UserExternalName, UserExternalNameRef};
+use cranelift_codegen::isa::TargetIsa;
+use cranelift_codegen::{CodegenResult, Context, FinalizedMachReloc, FinalizedRelocTarget};
+use cranelift_module::{DataId, FuncId, ModuleDeclarations};
+use serde::{Deserialize, Serialize};
+
+use crate::shards::MachineCode;
+
+/// Relocation kinds the x86-64 and AArch64 backends emit, by their `Debug` names
+const RELOC_KINDS: [Reloc; 9] = [
+    Reloc::Abs4,
+    Reloc::Abs8,
+    Reloc::X86PCRel4,
+    Reloc::X86CallPCRel4,
+    Reloc::X86CallPLTRel4,
+    Reloc::X86GOTPCRel4,
+    Reloc::Arm64Call,
+    Reloc::Aarch64AdrGotPage21,
+    Reloc::Aarch64Ld64GotLo12Nc,
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedFunction {
+    alignment: u64,
+    code: Vec<u8>,
+    relocs: Vec<CachedReloc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedReloc {
+    offset: u32,
+    kind: String,
+    target: CachedTarget,
+    addend: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+enum CachedTarget {
+    /// Index into the function's external names, which the key fixes
+    User(u32),
+    LibCall(String),
+    KnownSymbol(String),
+    /// Offset within the function itself
+    Func(u32),
+}
+
+impl CachedFunction {
+    /// None for relocations the cache can't represent; such functions always compile
+    fn new(alignment: u64, code: &[u8], relocs: &[FinalizedMachReloc]) -> Option<Self> {
+        let relocs = relocs.iter()
+            .map(|reloc| {
+                let kind = RELOC_KINDS.iter().find(|kind| **kind == reloc.kind)?;
+                let target = match &reloc.target {
+                    FinalizedRelocTarget::ExternalName(ExternalName::User(name)) => CachedTarget::User(name.as_u32()),
+                    FinalizedRelocTarget::ExternalName(ExternalName::LibCall(libcall)) => CachedTarget::LibCall(libcall.to_string()),
+                    FinalizedRelocTarget::ExternalName(ExternalName::KnownSymbol(symbol)) => CachedTarget::KnownSymbol(symbol.to_string()),
+                    FinalizedRelocTarget::ExternalName(ExternalName::TestCase(_)) => return None,
+                    FinalizedRelocTarget::Func(offset) => CachedTarget::Func(*offset),
+                };
+                Some(CachedReloc { offset: reloc.offset, kind: format!("{:?}", kind), target, addend: reloc.addend })
+            })
+            .collect::<Option<Vec<_>>>()?;
+        Some(CachedFunction { alignment, code: code.to_vec(), relocs })
+    }
+
+    fn relocs(&self) -> Option<Vec<FinalizedMachReloc>> {
+        self.relocs.iter()
+            .map(|reloc| {
+                let kind = *RELOC_KINDS.iter().find(|kind| format!("{:?}", kind) == reloc.kind)?;
+                let target = match &reloc.target {
+                    CachedTarget::User(index) => {
+                        FinalizedRelocTarget::ExternalName(ExternalName::User(UserExternalNameRef::from_u32(*index)))
+                    }
+                    CachedTarget::LibCall(name) => {
+                        FinalizedRelocTarget::ExternalName(ExternalName::LibCall(name.parse::<LibCall>().ok()?))
+                    }
+                    CachedTarget::KnownSymbol(name) => {
+                        FinalizedRelocTarget::ExternalName(ExternalName::KnownSymbol(name.parse::<KnownSymbol>().ok()?))
+                    }
+                    CachedTarget::Func(offset) => FinalizedRelocTarget::Func(*offset),
+                };
+                Some(FinalizedMachReloc { offset: reloc.offset, kind, target, addend: reloc.addend })
+            })
+            .collect()
+    }
+}
+
+/// Machine code of one module's functions from its previous build, and of this build's
+#[derive(Debug, Default)]
+pub struct FunctionCache {
+    previous: HashMap<u64, CachedFunction>,
+    current: Mutex<HashMap<u64, CachedFunction>>,
+    hits: AtomicUsize,
+    misses: AtomicUsize,
+}
+
+impl FunctionCache {
+    /// The cache written by `to_bytes`; empty if `bytes` isn't one
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        FunctionCache { previous: bincode::deserialize(bytes).unwrap_or_default(), ..Default::default() }
+    }
+
+    /// The functions this build hit or compiled
+    pub fn to_bytes(&self) -> Vec<u8> {
+        bincode::serialize(&*self.current.lock().unwrap()).unwrap_or_default()
+    }
+
+    /// Functions defined from cached machine code
+    pub fn hits(&self) -> usize {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    /// Functions Cranelift compiled
+    pub fn misses(&self) -> usize {
+        self.misses.load(Ordering::Relaxed)
+    }
+
+    /// Machine code of the function built in `ctx`: what's cached for the same IR, or else
+    /// compiled for `isa`. Leaves no compiled code in `ctx` on a hit.
+    pub(crate) fn compile(&self, ctx: &mut Context, declarations: &ModuleDeclarations, isa: &dyn TargetIsa) -> CodegenResult<MachineCode> {
+        let key = key(ctx, declarations, isa);
+        let cached = self.previous.get(&key).or(self.current.lock().unwrap().get(&key)).cloned();
+        if let Some((cached, relocs)) = cached.and_then(|cached| cached.relocs().map(|relocs| (cached, relocs))) {
+            let code = MachineCode { alignment: cached.alignment, code: cached.code.clone(), relocs };
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            self.current.lock().unwrap().insert(key, cached);
+            return Ok(code);
+        }
+        let code = MachineCode::compile(ctx, isa)?;
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        if let Some(cached) = CachedFunction::new(code.alignment, &code.code, &code.relocs) {
+            self.current.lock().unwrap().insert(key, cached);
+        }
+        Ok(code)
+    }
+}
+
+/// Hash of the function built in `ctx`, as compiled for `isa`
+fn key(ctx: &mut Context, declarations: &ModuleDeclarations, isa: &dyn TargetIsa) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    // The IR names the function itself and what it refers to by their ids in this build's
+    // module. Print it with those replaced by position, in namespaces ids don't use.
+    let name = std::mem::take(&mut ctx.func.name);
+    let names: Vec<UserExternalName> = ctx.func.params.user_named_funcs().values().cloned().collect();
+    for (index, name) in names.iter().enumerate() {
+        let placeholder = UserExternalName::new(name.namespace + 2, index as u32);
+        ctx.func.params.reset_user_func_name(UserExternalNameRef::from_u32(index as u32), placeholder);
+    }
+    ctx.func.display().to_string().hash(&mut hasher);
+    for (index, name) in names.iter().enumerate() {
+        ctx.func.params.reset_user_func_name(UserExternalNameRef::from_u32(index as u32), name.clone());
+    }
+    ctx.func.name = name;
+    // What the function refers to, in the order of the indices its IR uses
+    for name in &names {
+        let (symbol, linkage) = match name.namespace {
+            0 => {
+                let id = FuncId::from_u32(name.index);
+                let decl = declarations.get_function_decl(id);
+                (decl.linkage_name(id).into_owned(), decl.linkage)
+            }
+            _ => {
+                let id = DataId::from_u32(name.index);
+                let decl = declarations.get_data_decl(id);
+                (decl.linkage_name(id).into_owned(), decl.linkage)
+            }
+        };
+        (name.namespace, symbol, format!("{:?}", linkage)).hash(&mut hasher);
+    }
+    isa.triple().to_string().hash(&mut hasher);
+    isa.flags().to_string().hash(&mut hasher);
+    for flag in isa.isa_flags() {
+        flag.to_string().hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cranelift_codegen::ir::{types, AbiParam, InstBuilder, UserFuncName};
+    use cranelift_codegen::settings::{self, Configurable};
+    use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext};
+    use cranelift_module::{default_libcall_names, Linkage, Module};
+    use cranelift_object::{ObjectBuilder, ObjectModule};
+
+    fn isa() -> cranelift_codegen::isa::OwnedTargetIsa {
+        let mut flags = settings::builder();
+        flags.set("is_pic", "true").unwrap();
+        cranelift_codegen::isa::lookup_by_name("x86_64-unknown-linux-gnu").unwrap()
+            .finish(settings::Flags::new(flags)).unwrap()
+    }
+
+    /// Define `f(x) = callee(x) + k` in a module that declares `padding` other functions
+    /// first, so `callee` and `f` get different ids than with no padding
+    fn build(cache: &FunctionCache, padding: usize, k: i64) -> Vec<u8> {
+        let mut module = ObjectModule::new(ObjectBuilder::new(isa(), "test", default_libcall_names()).unwrap());
+        let mut sig = module.make_signature();
+        sig.params.push(AbiParam::new(types::I64));
+        sig.returns.push(AbiParam::new(types::I64));
+        for i in 0..padding {
+            module.declare_function(&format!("padding{}", i), Linkage::Import, &sig).unwrap();
+        }
+        let callee = module.declare_function("callee", Linkage::Import, &sig).unwrap();
+        let f = module.declare_function("f", Linkage::Export, &sig).unwrap();
+        let mut ctx = module.make_context();
+        ctx.func.signature = sig;
+        ctx.func.name = UserFuncName::user(0, f.as_u32());
+        let mut builder_ctx = FunctionBuilderContext::new();
+        let mut builder = FunctionBuilder::new(&mut ctx.func, &mut builder_ctx);
+        let block = builder.create_block();
+        builder.append_block_params_for_function_params(block);
+        builder.switch_to_block(block);
+        builder.seal_block(block);
+        let x = builder.block_params(block)[0];
+        let callee_ref = module.declare_func_in_func(callee, builder.func);
+        let call = builder.ins().call(callee_ref, &[x]);
+        let result = builder.inst_results(call)[0];
+        let sum = builder.ins().iadd_imm(result, k);
+        builder.ins().return_(&[sum]);
+        builder.finalize();
+        let code = cache.compile(&mut ctx, module.declarations(), &*isa()).unwrap();
+        module.define_function_bytes(f, &ctx.func, code.alignment, &code.code, &code.relocs).unwrap();
+        module.finish().emit().unwrap()
+    }
+
+    #[test]
+    fn test_unchanged_function_is_reused() {
+        let first = FunctionCache::default();
+        build(&first, 0, 1);
+        assert_eq!((first.hits(), first.misses()), (0, 1));
+
+        // Other ids, same function
+        let second = FunctionCache::from_bytes(&first.to_bytes());
+        build(&second, 5, 1);
+        assert_eq!((second.hits(), second.misses()), (1, 0));
+
+        let edited = FunctionCache::from_bytes(&second.to_bytes());
+        build(&edited, 0, 2);
+        assert_eq!((edited.hits(), edited.misses()), (0, 1));
+        // Only what this build used is kept
+        assert_eq!(FunctionCache::from_bytes(&edited.to_bytes()).previous.len(), 1);
+        assert!(FunctionCache::from_bytes(b"not a cache").previous.is_empty());
+    }
+}
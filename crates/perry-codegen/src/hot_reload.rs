@@ -0,0 +1,114 @@
+//! Object code of hot-reload builds (`Compiler::set_hot_reload`), as `perry dev` sees it
+//!
+//! A patch redirects the running program's functions to their recompiled versions by
+//! symbol name, and binds to the program's module state by symbol name. Every function
+//! and writable global a hot-reload module defines has a global symbol, so both are read
+//! back from the module's object code, which works the same for object code loaded from
+//! the cache.
+
+use anyhow::{anyhow, Result};
+use object::{Object, ObjectSection, ObjectSymbol, SymbolKind};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+/// The symbols of a module's object code a patch depends on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ObjectSymbols {
+    /// Functions a patch can swap, sorted, as the dynamic linker knows them
+    pub functions: Vec<String>,
+    /// Writable globals (module variables, export slots, static fields), sorted
+    pub data: Vec<String>,
+    /// Hash of the code of `main` and the module init function. They only run at
+    /// startup, so a patch can't change what they do.
+    pub startup_hash: u64,
+}
+
+fn is_startup(name: &str) -> bool {
+    matches!(name, "main" | "_perry_main") || name.starts_with("_perry_init_")
+}
+
+/// Read the symbols of a module's object code
+pub fn read_symbols(object_code: &[u8]) -> Result<ObjectSymbols> {
+    let file = object::File::parse(object_code).map_err(|e| anyhow!("Malformed object code: {}", e))?;
+    // Mach-O prefixes C symbol names with an underscore that dlsym doesn't take
+    let prefix = if file.format() == object::BinaryFormat::MachO { "_" } else { "" };
+    let mut symbols = ObjectSymbols { functions: Vec::new(), data: Vec::new(), startup_hash: 0 };
+    let mut startup = DefaultHasher::new();
+    for symbol in file.symbols().filter(|symbol| symbol.is_definition() && symbol.is_global()) {
+        let Some(name) = symbol.name().ok().and_then(|name| name.strip_prefix(prefix)) else {
+            continue;
+        };
+        match symbol.kind() {
+            SymbolKind::Text if is_startup(name) => {
+                let code = symbol.section_index()
+                    .and_then(|index| file.section_by_index(index).ok())
+                    .and_then(|section| {
+                        let start = symbol.address().checked_sub(section.address())? as usize;
+                        section.data().ok()?.get(start..start + symbol.size() as usize)
+                    });
+                (name, code).hash(&mut startup);
+            }
+            SymbolKind::Text => symbols.functions.push(name.to_string()),
+            SymbolKind::Data => symbols.data.push(name.to_string()),
+            _ => {}
+        }
+    }
+    symbols.functions.sort();
+    symbols.data.sort();
+    symbols.startup_hash = startup.finish();
+    Ok(symbols)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use object::write::{Object as WriteObject, StandardSection, Symbol, SymbolSection};
+    use object::{Architecture, BinaryFormat, Endianness, SymbolFlags, SymbolScope};
+
+    fn object_code(init: &[u8]) -> Vec<u8> {
+        let mut obj = WriteObject::new(BinaryFormat::Elf, Architecture::X86_64, Endianness::Little);
+        let text = obj.section_id(StandardSection::Text);
+        let data = obj.section_id(StandardSection::Data);
+        let mut define = |name: &str, section, kind, bytes: &[u8], scope| {
+            let offset = obj.append_section_data(section, bytes, 16);
+            obj.add_symbol(Symbol {
+                name: name.as_bytes().to_vec(),
+                value: offset,
+                size: bytes.len() as u64,
+                kind,
+                scope,
+                weak: false,
+                section: SymbolSection::Section(section),
+                flags: SymbolFlags::None,
+            });
+        };
+        define("handler", text, SymbolKind::Text, &[0xC3; 16], SymbolScope::Dynamic);
+        define("_PF7main$2Ets5countE", text, SymbolKind::Text, &[0xC3; 16], SymbolScope::Dynamic);
+        define("helper", text, SymbolKind::Text, &[0xC3; 16], SymbolScope::Compilation);
+        define("main", text, SymbolKind::Text, &[0xC3; 16], SymbolScope::Dynamic);
+        define("_perry_init_routes_ts", text, SymbolKind::Text, init, SymbolScope::Dynamic);
+        define("_PD7main$2Ets5count3$30E", data, SymbolKind::Data, &[0; 8], SymbolScope::Dynamic);
+        obj.add_symbol(Symbol {
+            name: b"js_array_push".to_vec(),
+            value: 0,
+            size: 0,
+            kind: SymbolKind::Text,
+            scope: SymbolScope::Dynamic,
+            weak: false,
+            section: SymbolSection::Undefined,
+            flags: SymbolFlags::None,
+        });
+        obj.write().unwrap()
+    }
+
+    #[test]
+    fn test_read_symbols() {
+        let symbols = read_symbols(&object_code(&[0x90, 0xC3])).unwrap();
+        assert_eq!(symbols.functions, vec!["_PF7main$2Ets5countE", "handler"]);
+        assert_eq!(symbols.data, vec!["_PD7main$2Ets5count3$30E"]);
+        assert_eq!(read_symbols(&object_code(&[0x90, 0xC3])).unwrap(), symbols);
+        // Other startup code
+        assert_ne!(read_symbols(&object_code(&[0x90, 0x90, 0xC3])).unwrap().startup_hash, symbols.startup_hash);
+        assert!(read_symbols(b"not an object").is_err());
+    }
+}
@@ -1,7 +1,16 @@
 //! Cranelift Code Generation for Perry
 //!
-//! Translates HIR to Cranelift IR and generates native machine code.
+//! Translates HIR to Cranelift IR and generates native machine code, or WebAssembly for
+//! wasm32 targets.
 
 pub mod codegen;
+mod debuginfo;
+pub mod function_cache;
+pub mod hot_reload;
+mod module;
+mod shards;
+pub mod wasm;
+pub mod wasm_link;
 
-pub use codegen::Compiler;
+pub use codegen::{Compiler, CraneliftSettings};
+pub use function_cache::FunctionCache;
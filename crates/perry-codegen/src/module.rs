@@ -0,0 +1,87 @@
+//! The Cranelift module a `Compiler` fills: a native object, or WebAssembly for
+//! `--target wasm32-wasip1` (see `wasm`)
+
+use cranelift_codegen::control::ControlPlane;
+use cranelift_codegen::ir::{Function, Signature};
+use cranelift_codegen::isa::TargetIsa;
+use cranelift_codegen::{Context, FinalizedMachReloc};
+use cranelift_module::{DataDescription, DataId, FuncId, Linkage, Module, ModuleDeclarations, ModuleResult};
+use cranelift_object::ObjectModule;
+
+use crate::wasm::WasmModule;
+
+pub(crate) enum CodegenModule {
+    Object(ObjectModule),
+    Wasm(WasmModule),
+}
+
+impl CodegenModule {
+    pub fn is_wasm(&self) -> bool {
+        matches!(self, CodegenModule::Wasm(_))
+    }
+}
+
+impl Module for CodegenModule {
+    fn isa(&self) -> &dyn TargetIsa {
+        match self {
+            CodegenModule::Object(module) => module.isa(),
+            CodegenModule::Wasm(module) => module.isa(),
+        }
+    }
+
+    fn declarations(&self) -> &ModuleDeclarations {
+        match self {
+            CodegenModule::Object(module) => module.declarations(),
+            CodegenModule::Wasm(module) => module.declarations(),
+        }
+    }
+
+    fn declare_function(&mut self, name: &str, linkage: Linkage, signature: &Signature) -> ModuleResult<FuncId> {
+        match self {
+            CodegenModule::Object(module) => module.declare_function(name, linkage, signature),
+            CodegenModule::Wasm(module) => module.declare_function(name, linkage, signature),
+        }
+    }
+
+    fn declare_anonymous_function(&mut self, signature: &Signature) -> ModuleResult<FuncId> {
+        match self {
+            CodegenModule::Object(module) => module.declare_anonymous_function(signature),
+            CodegenModule::Wasm(module) => module.declare_anonymous_function(signature),
+        }
+    }
+
+    fn declare_data(&mut self, name: &str, linkage: Linkage, writable: bool, tls: bool) -> ModuleResult<DataId> {
+        match self {
+            CodegenModule::Object(module) => module.declare_data(name, linkage, writable, tls),
+            CodegenModule::Wasm(module) => module.declare_data(name, linkage, writable, tls),
+        }
+    }
+
+    fn declare_anonymous_data(&mut self, writable: bool, tls: bool) -> ModuleResult<DataId> {
+        match self {
+            CodegenModule::Object(module) => module.declare_anonymous_data(writable, tls),
+            CodegenModule::Wasm(module) => module.declare_anonymous_data(writable, tls),
+        }
+    }
+
+    fn define_function_with_control_plane(&mut self, func: FuncId, ctx: &mut Context, ctrl_plane: &mut ControlPlane) -> ModuleResult<()> {
+        match self {
+            CodegenModule::Object(module) => module.define_function_with_control_plane(func, ctx, ctrl_plane),
+            CodegenModule::Wasm(module) => module.define_function_with_control_plane(func, ctx, ctrl_plane),
+        }
+    }
+
+    fn define_function_bytes(&mut self, func_id: FuncId, func: &Function, alignment: u64, bytes: &[u8], relocs: &[FinalizedMachReloc]) -> ModuleResult<()> {
+        match self {
+            CodegenModule::Object(module) => module.define_function_bytes(func_id, func, alignment, bytes, relocs),
+            CodegenModule::Wasm(module) => module.define_function_bytes(func_id, func, alignment, bytes, relocs),
+        }
+    }
+
+    fn define_data(&mut self, data: DataId, data_decl: &DataDescription) -> ModuleResult<()> {
+        match self {
+            CodegenModule::Object(module) => module.define_data(data, data_decl),
+            CodegenModule::Wasm(module) => module.define_data(data, data_decl),
+        }
+    }
+}
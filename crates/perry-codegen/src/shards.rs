@@ -0,0 +1,170 @@
+//! A module's functions compiled in parallel
+//!
+//! Building a function's Cranelift IR reads and extends the compiler's module-wide state
+//! (declarations, string constants, wrappers, class metadata), so IR is built one function
+//! at a time. What Cranelift does with it next - optimizing, allocating registers and
+//! emitting machine code, most of codegen's time - needs only the IR, the ISA and the
+//! module's declarations. `Compiler::define_function` therefore queues each function once
+//! its IR is built, and before the module's object is written the queue is split into
+//! shards of consecutive functions with about the same number of instructions. The shards
+//! compile on the rayon pool, each with its own Cranelift `Context`, and their machine code
+//! is merged into the module's object in the order the functions were built, so the object
+//! is the same whatever the number of threads.
+//!
+//! Functions built with debug info don't queue: their DWARF is taken from the context
+//! right after they compile.
+
+use cranelift_codegen::control::ControlPlane;
+use cranelift_codegen::ir::Function;
+use cranelift_codegen::isa::{OwnedTargetIsa, TargetIsa};
+use cranelift_codegen::{CodegenResult, Context, FinalizedMachReloc};
+use cranelift_module::{FuncId, ModuleDeclarations};
+use rayon::prelude::*;
+
+use crate::function_cache::FunctionCache;
+
+/// Instructions below which a shard isn't worth a thread
+const MIN_SHARD_INSTS: usize = 4096;
+
+/// A function whose IR is built, waiting to be compiled
+pub(crate) struct PendingFunction {
+    pub id: FuncId,
+    pub func: Function,
+    /// ISA to compile for when it isn't the module's (x86-64-v3 versions)
+    pub isa: Option<OwnedTargetIsa>,
+}
+
+/// Machine code of one function, as `Module::define_function_bytes` takes it
+#[derive(Debug, Clone)]
+pub(crate) struct MachineCode {
+    pub alignment: u64,
+    pub code: Vec<u8>,
+    pub relocs: Vec<FinalizedMachReloc>,
+}
+
+impl MachineCode {
+    /// Compile the function built in `ctx` for `isa`
+    pub fn compile(ctx: &mut Context, isa: &dyn TargetIsa) -> CodegenResult<Self> {
+        let compiled = ctx.compile(isa, &mut ControlPlane::default()).map_err(|e| e.inner)?;
+        Ok(MachineCode {
+            alignment: compiled.buffer.alignment as u64,
+            code: compiled.code_buffer().to_vec(),
+            relocs: compiled.buffer.relocs().to_vec(),
+        })
+    }
+}
+
+/// Split `pending` into runs of consecutive functions with about the same number of
+/// instructions: `shards` of them, or fewer so there's one per `MIN_SHARD_INSTS`
+fn split(mut pending: Vec<PendingFunction>, shards: usize) -> Vec<Vec<PendingFunction>> {
+    let total: usize = pending.iter().map(|function| function.func.dfg.num_insts()).sum();
+    let shards = shards.min(total / MIN_SHARD_INSTS).max(1);
+    let mut split = Vec::with_capacity(shards);
+    // Instructions in the shards so far
+    let mut done = 0;
+    while !pending.is_empty() {
+        // A function goes to the shard its middle instruction falls in
+        let end = total * (split.len() + 1) / shards;
+        let mut len = 0;
+        while len < pending.len()
+            && (len == 0 || split.len() + 1 == shards || done + pending[len].func.dfg.num_insts() / 2 < end)
+        {
+            done += pending[len].func.dfg.num_insts();
+            len += 1;
+        }
+        let rest = pending.split_off(len);
+        split.push(std::mem::replace(&mut pending, rest));
+    }
+    split
+}
+
+/// Compile `pending` on the rayon pool, for the module's `isa` unless a function names
+/// another, reusing what `cache` holds. Returns each function with its result, in order.
+pub(crate) fn compile(
+    pending: Vec<PendingFunction>,
+    declarations: &ModuleDeclarations,
+    isa: &dyn TargetIsa,
+    cache: Option<&FunctionCache>,
+) -> Vec<(PendingFunction, CodegenResult<MachineCode>)> {
+    let shards = split(pending, rayon::current_num_threads());
+    let compiled: Vec<Vec<_>> = shards.into_par_iter()
+        .map(|shard| {
+            let mut ctx = Context::new();
+            shard.into_iter()
+                .map(|mut function| {
+                    ctx.func = std::mem::replace(&mut function.func, Function::new());
+                    let isa = function.isa.as_deref().unwrap_or(isa);
+                    let code = match cache {
+                        Some(cache) => cache.compile(&mut ctx, declarations, isa),
+                        None => MachineCode::compile(&mut ctx, isa),
+                    };
+                    function.func = std::mem::replace(&mut ctx.func, Function::new());
+                    ctx.clear();
+                    (function, code)
+                })
+                .collect()
+        })
+        .collect();
+    compiled.into_iter().flatten().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cranelift_codegen::ir::{types, AbiParam, InstBuilder, UserFuncName};
+    use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext};
+
+    /// `f(x) = x + 1 + 1 + ...` with `adds` additions
+    fn function(index: u32, adds: usize) -> PendingFunction {
+        let mut func = Function::new();
+        func.name = UserFuncName::user(0, index);
+        func.signature.params.push(AbiParam::new(types::I64));
+        func.signature.returns.push(AbiParam::new(types::I64));
+        let mut builder_ctx = FunctionBuilderContext::new();
+        let mut builder = FunctionBuilder::new(&mut func, &mut builder_ctx);
+        let block = builder.create_block();
+        builder.append_block_params_for_function_params(block);
+        builder.switch_to_block(block);
+        builder.seal_block(block);
+        let mut value = builder.block_params(block)[0];
+        for _ in 0..adds {
+            value = builder.ins().iadd_imm(value, 1);
+        }
+        builder.ins().return_(&[value]);
+        builder.finalize();
+        PendingFunction { id: FuncId::from_u32(index), func, isa: None }
+    }
+
+    fn ids(shards: &[Vec<PendingFunction>]) -> Vec<Vec<u32>> {
+        shards.iter().map(|shard| shard.iter().map(|function| function.id.as_u32()).collect()).collect()
+    }
+
+    #[test]
+    fn test_split_balances_instructions() {
+        let big = |index| function(index, MIN_SHARD_INSTS);
+        let pending = vec![big(0), function(1, 10), big(2), big(3), function(4, 10)];
+        assert_eq!(ids(&split(pending, 3)), [vec![0, 1], vec![2], vec![3, 4]]);
+
+        // Small modules stay on one thread
+        let pending = (0..8).map(|index| function(index, 10)).collect();
+        assert_eq!(ids(&split(pending, 8)), [(0..8).collect::<Vec<_>>()]);
+        assert!(split(Vec::new(), 4).is_empty());
+    }
+
+    #[test]
+    fn test_compile_keeps_order() {
+        let mut flags = cranelift_codegen::settings::builder();
+        cranelift_codegen::settings::Configurable::set(&mut flags, "is_pic", "true").unwrap();
+        let isa = cranelift_codegen::isa::lookup_by_name("x86_64-unknown-linux-gnu").unwrap()
+            .finish(cranelift_codegen::settings::Flags::new(flags)).unwrap();
+        let pending: Vec<_> = (0..6).map(|index| function(index, MIN_SHARD_INSTS * (index as usize % 2))).collect();
+        let compiled = compile(pending, &ModuleDeclarations::default(), &*isa, None);
+        assert_eq!(compiled.iter().map(|(function, _)| function.id.as_u32()).collect::<Vec<_>>(), [0, 1, 2, 3, 4, 5]);
+        for (function, code) in &compiled {
+            let code = code.as_ref().unwrap();
+            assert!(!code.code.is_empty());
+            // The IR comes back for defining the code
+            assert_eq!(function.func.dfg.num_insts() > MIN_SHARD_INSTS, function.id.as_u32() % 2 == 1);
+        }
+    }
+}
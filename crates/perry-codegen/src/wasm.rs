@@ -0,0 +1,1456 @@
+//! WebAssembly output for `--target wasm32-wasip1`
+//!
+//! Cranelift only emits native machine code, so for WebAssembly perry builds the same
+//! Cranelift IR as for native targets, runs Cranelift's target-independent optimizations
+//! on it and translates the result itself. The IR is built for a 64-bit surrogate ISA:
+//! pointers stay `i64` as on native targets, and loads and stores wrap them to the 32-bit
+//! addresses of wasm32 memory.
+//!
+//! [`WasmModule`] takes the place of cranelift-object's `ObjectModule`. It translates each
+//! function as it is defined and collects the module's code and data in a [`WasmObject`],
+//! perry's relocatable form of a module's WebAssembly, written where the native object
+//! file would be. `wasm_link::link` merges a program's objects with the WASI runtime
+//! (perry-runtime-wasi) into one WebAssembly module.
+//!
+//! Every SSA value lives in a wasm local. A function's blocks are laid out in order inside
+//! a `loop` and one wasm `block` per IR block, so a jump forward is a `br` out of the
+//! blocks in between, and a jump back stores the target's index and restarts the loop,
+//! which dispatches on it with `br_table`. `i8` and `i16` values are kept zero-extended
+//! in `i32` locals. Stack slots live on a shadow stack in linear memory whose pointer is a
+//! wasm global the linker adds.
+
+use std::collections::HashMap;
+
+use anyhow::anyhow;
+use cranelift_codegen::control::ControlPlane;
+use cranelift_codegen::entity::SecondaryMap;
+use cranelift_codegen::ir::condcodes::{FloatCC, IntCC};
+use cranelift_codegen::ir::{
+    types, Block, BlockCall, ExternalName, Function, GlobalValue, GlobalValueData, Inst, InstructionData, Opcode,
+    Signature, StackSlot, Type, Value,
+};
+use cranelift_codegen::isa::{OwnedTargetIsa, TargetIsa};
+use cranelift_codegen::{Context, FinalizedMachReloc};
+use cranelift_module::{
+    DataDescription, DataId, FuncId, Init, Module, ModuleDeclarations, ModuleError, ModuleRelocTarget, ModuleResult,
+};
+use serde::{Deserialize, Serialize};
+use wasm_encoder::{BlockType, Encode, Instruction, MemArg};
+
+/// Leads a serialized [`WasmObject`]
+const MAGIC: &[u8; 8] = b"\0perrywo";
+
+/// Bytes of a relocated index or `i32.const` in code: a LEB128 padded to its longest form
+pub const RELOC_LEB_LEN: usize = 5;
+
+/// Type of a wasm value
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ValType {
+    I32,
+    I64,
+    F32,
+    F64,
+    V128,
+}
+
+impl From<ValType> for wasm_encoder::ValType {
+    fn from(ty: ValType) -> Self {
+        match ty {
+            ValType::I32 => wasm_encoder::ValType::I32,
+            ValType::I64 => wasm_encoder::ValType::I64,
+            ValType::F32 => wasm_encoder::ValType::F32,
+            ValType::F64 => wasm_encoder::ValType::F64,
+            ValType::V128 => wasm_encoder::ValType::V128,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct FuncType {
+    pub params: Vec<ValType>,
+    pub results: Vec<ValType>,
+}
+
+/// A function or data object an object defines or refers to
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Symbol {
+    pub name: String,
+    /// Index in `types` of a function's type; `None` for data
+    pub function: Option<u32>,
+    /// Resolvable from other objects and the runtime, unlike `Linkage::Local` symbols
+    pub global: bool,
+    pub defined: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RelocKind {
+    /// Index of the function called
+    FunctionIndex,
+    /// The function's slot in the indirect call table
+    TableIndex,
+    /// Address of the data object plus the addend
+    DataAddress,
+    /// Index in `types` of the type a `call_indirect` expects
+    TypeIndex,
+    /// Index of the global holding the shadow stack pointer
+    StackPointer,
+}
+
+/// A value the linker fills in: in code a padded LEB128 of `RELOC_LEB_LEN` bytes, in
+/// data the low 4 bytes of an 8-byte pointer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Reloc {
+    pub offset: u32,
+    pub kind: RelocKind,
+    /// Symbol, or type for `TypeIndex`
+    pub index: u32,
+    pub addend: i32,
+}
+
+/// Body of a defined function: locals and instructions, as in the code section
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Code {
+    pub symbol: u32,
+    pub body: Vec<u8>,
+    pub relocs: Vec<Reloc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Data {
+    pub symbol: u32,
+    pub align: u32,
+    pub bytes: Vec<u8>,
+    pub relocs: Vec<Reloc>,
+}
+
+/// A module's WebAssembly, before linking
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct WasmObject {
+    pub types: Vec<FuncType>,
+    pub symbols: Vec<Symbol>,
+    pub code: Vec<Code>,
+    pub data: Vec<Data>,
+}
+
+impl WasmObject {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(bincode::serialize(self).expect("WebAssembly objects serialize"));
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> anyhow::Result<Self> {
+        match bytes.strip_prefix(MAGIC) {
+            Some(rest) => Ok(bincode::deserialize(rest)?),
+            None => Err(anyhow!("not a perry WebAssembly object")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum SymbolKey {
+    Function(FuncId),
+    Data(DataId),
+    /// Runtime function for what wasm has no instruction for
+    Helper(&'static str),
+}
+
+/// `cranelift_module::Module` collecting a [`WasmObject`]
+pub struct WasmModule {
+    isa: OwnedTargetIsa,
+    declarations: ModuleDeclarations,
+    object: WasmObject,
+    symbols: Symbols,
+}
+
+/// Symbols and types of the object, added as code refers to them
+#[derive(Default)]
+struct Symbols {
+    keys: Vec<SymbolKey>,
+    indices: HashMap<SymbolKey, u32>,
+    types: HashMap<FuncType, u32>,
+}
+
+impl Symbols {
+    fn symbol(&mut self, key: SymbolKey, declarations: &ModuleDeclarations, object: &mut WasmObject) -> Result<u32, String> {
+        if let Some(&index) = self.indices.get(&key) {
+            return Ok(index);
+        }
+        let symbol = match key {
+            SymbolKey::Function(id) => {
+                let decl = declarations.get_function_decl(id);
+                let ty = self.ty(func_type(&decl.signature)?, object);
+                Symbol { name: decl.linkage_name(id).into_owned(), function: Some(ty), global: false, defined: false }
+            }
+            SymbolKey::Data(id) => {
+                let decl = declarations.get_data_decl(id);
+                Symbol { name: decl.linkage_name(id).into_owned(), function: None, global: false, defined: false }
+            }
+            SymbolKey::Helper(name) => {
+                let ty = if name.ends_with('f') { ValType::F32 } else { ValType::F64 };
+                let ty = self.ty(FuncType { params: vec![ty; 3], results: vec![ty] }, object);
+                Symbol { name: name.to_string(), function: Some(ty), global: true, defined: false }
+            }
+        };
+        let index = object.symbols.len() as u32;
+        object.symbols.push(symbol);
+        self.keys.push(key);
+        self.indices.insert(key, index);
+        Ok(index)
+    }
+
+    fn ty(&mut self, ty: FuncType, object: &mut WasmObject) -> u32 {
+        *self.types.entry(ty).or_insert_with_key(|ty| {
+            object.types.push(ty.clone());
+            object.types.len() as u32 - 1
+        })
+    }
+}
+
+impl WasmModule {
+    /// `isa` is the surrogate the IR is built for; it has to have 64-bit pointers
+    pub fn new(isa: OwnedTargetIsa) -> Self {
+        WasmModule { isa, declarations: ModuleDeclarations::default(), object: WasmObject::default(), symbols: Symbols::default() }
+    }
+
+    /// The object, with the final linkage of its symbols
+    pub fn finish(mut self) -> WasmObject {
+        for (symbol, key) in self.object.symbols.iter_mut().zip(&self.symbols.keys) {
+            symbol.global = match *key {
+                SymbolKey::Function(id) => self.declarations.get_function_decl(id).linkage != cranelift_module::Linkage::Local,
+                SymbolKey::Data(id) => self.declarations.get_data_decl(id).linkage != cranelift_module::Linkage::Local,
+                SymbolKey::Helper(_) => true,
+            };
+        }
+        self.object
+    }
+
+    /// Mark `key`'s symbol defined
+    fn define(&mut self, key: SymbolKey, name: &str) -> Result<u32, Box<ModuleError>> {
+        let symbol = self.symbols.symbol(key, &self.declarations, &mut self.object)
+            .map_err(|e| ModuleError::Backend(anyhow!("{}: {}", name, e)))?;
+        if std::mem::replace(&mut self.object.symbols[symbol as usize].defined, true) {
+            return Err(Box::new(ModuleError::DuplicateDefinition(name.to_string())));
+        }
+        Ok(symbol)
+    }
+}
+
+impl Module for WasmModule {
+    fn isa(&self) -> &dyn TargetIsa {
+        &*self.isa
+    }
+
+    fn declarations(&self) -> &ModuleDeclarations {
+        &self.declarations
+    }
+
+    fn declare_function(&mut self, name: &str, linkage: cranelift_module::Linkage, signature: &Signature) -> ModuleResult<FuncId> {
+        Ok(self.declarations.declare_function(name, linkage, signature)?.0)
+    }
+
+    fn declare_anonymous_function(&mut self, signature: &Signature) -> ModuleResult<FuncId> {
+        self.declarations.declare_anonymous_function(signature)
+    }
+
+    fn declare_data(&mut self, name: &str, linkage: cranelift_module::Linkage, writable: bool, tls: bool) -> ModuleResult<DataId> {
+        Ok(self.declarations.declare_data(name, linkage, writable, tls)?.0)
+    }
+
+    fn declare_anonymous_data(&mut self, writable: bool, tls: bool) -> ModuleResult<DataId> {
+        self.declarations.declare_anonymous_data(writable, tls)
+    }
+
+    fn define_function_with_control_plane(&mut self, func_id: FuncId, ctx: &mut Context, ctrl_plane: &mut ControlPlane) -> ModuleResult<()> {
+        let decl = self.declarations.get_function_decl(func_id);
+        let name = decl.linkage_name(func_id).into_owned();
+        if !decl.linkage.is_definable() {
+            return Err(ModuleError::InvalidImportDefinition(name));
+        }
+        ctx.verify_if(&*self.isa)?;
+        ctx.optimize(&*self.isa, ctrl_plane)?;
+        let symbol = self.define(SymbolKey::Function(func_id), &name).map_err(|e| *e)?;
+        let mut translator = Translator::new(&ctx.func, &self.declarations, &mut self.symbols, &mut self.object);
+        let (body, relocs) = translator.translate()
+            .map_err(|e| ModuleError::Backend(anyhow!("cannot translate {} to WebAssembly: {}", name, e)))?;
+        self.object.code.push(Code { symbol, body, relocs });
+        Ok(())
+    }
+
+    fn define_function_bytes(&mut self, func_id: FuncId, _func: &Function, _alignment: u64, _bytes: &[u8], _relocs: &[FinalizedMachReloc]) -> ModuleResult<()> {
+        let name = self.declarations.get_function_decl(func_id).linkage_name(func_id).into_owned();
+        Err(ModuleError::Backend(anyhow!("{}: WebAssembly functions can't be defined from machine code", name)))
+    }
+
+    fn define_data(&mut self, data_id: DataId, data: &DataDescription) -> ModuleResult<()> {
+        let decl = self.declarations.get_data_decl(data_id);
+        let name = decl.linkage_name(data_id).into_owned();
+        if !decl.linkage.is_definable() {
+            return Err(ModuleError::InvalidImportDefinition(name));
+        }
+        let bytes = match &data.init {
+            Init::Uninitialized => panic!("data {} is not initialized", name),
+            Init::Zeros { size } => vec![0; *size],
+            Init::Bytes { contents } => contents.to_vec(),
+        };
+        let symbol = self.define(SymbolKey::Data(data_id), &name).map_err(|e| *e)?;
+        let mut relocs = Vec::new();
+        let mut target = |target: &ModuleRelocTarget| match target {
+            ModuleRelocTarget::User { namespace: 0, index } => {
+                self.symbols.symbol(SymbolKey::Function(FuncId::from_u32(*index)), &self.declarations, &mut self.object)
+                    .map(|symbol| (RelocKind::TableIndex, symbol))
+            }
+            ModuleRelocTarget::User { namespace: 1, index } => {
+                self.symbols.symbol(SymbolKey::Data(DataId::from_u32(*index)), &self.declarations, &mut self.object)
+                    .map(|symbol| (RelocKind::DataAddress, symbol))
+            }
+            target => Err(format!("unsupported relocation target {}", target)),
+        };
+        let relocations = data.function_relocs.iter()
+            .map(|&(offset, func_ref)| (offset, &data.function_decls[func_ref], 0))
+            .chain(data.data_relocs.iter().map(|&(offset, gv, addend)| (offset, &data.data_decls[gv], addend)));
+        for (offset, reloc_target, addend) in relocations {
+            let (kind, index) = target(reloc_target).map_err(|e| ModuleError::Backend(anyhow!("{}: {}", name, e)))?;
+            relocs.push(Reloc { offset, kind, index, addend: addend as i32 });
+        }
+        let align = data.align.unwrap_or(8).max(1) as u32;
+        self.object.data.push(Data { symbol, align, bytes, relocs });
+        Ok(())
+    }
+}
+
+fn val_type(ty: Type) -> Result<ValType, String> {
+    match ty {
+        types::I8 | types::I16 | types::I32 => Ok(ValType::I32),
+        types::I64 => Ok(ValType::I64),
+        types::F32 => Ok(ValType::F32),
+        types::F64 => Ok(ValType::F64),
+        ty if ty.is_vector() && ty.bits() == 128 => Ok(ValType::V128),
+        ty => Err(format!("unsupported type {}", ty)),
+    }
+}
+
+fn func_type(signature: &Signature) -> Result<FuncType, String> {
+    Ok(FuncType {
+        params: signature.params.iter().map(|param| val_type(param.value_type)).collect::<Result<_, _>>()?,
+        results: signature.returns.iter().map(|ret| val_type(ret.value_type)).collect::<Result<_, _>>()?,
+    })
+}
+
+/// Append `value` as a LEB128 of `RELOC_LEB_LEN` bytes
+pub fn write_padded_leb(out: &mut Vec<u8>, value: u32, signed: bool) {
+    let mut value = if signed { value as i32 as i64 } else { value as i64 };
+    for i in 0..RELOC_LEB_LEN {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        out.push(if i + 1 < RELOC_LEB_LEN { byte | 0x80 } else { byte & 0x7f });
+    }
+}
+
+/// Translates one function's IR
+struct Translator<'a> {
+    func: &'a Function,
+    declarations: &'a ModuleDeclarations,
+    symbols: &'a mut Symbols,
+    object: &'a mut WasmObject,
+    code: Vec<u8>,
+    relocs: Vec<Reloc>,
+    /// Local of each value
+    locals: SecondaryMap<Value, u32>,
+    /// Types of the locals after the parameters
+    local_types: Vec<ValType>,
+    params: u32,
+    /// Position of each block in the layout
+    order: SecondaryMap<Block, u32>,
+    blocks: u32,
+    /// Local holding the block to dispatch to when the loop restarts
+    label: Option<u32>,
+    /// Local holding the frame's address on the shadow stack, and the frame's size
+    frame: Option<(u32, u32)>,
+    slot_offsets: SecondaryMap<StackSlot, u32>,
+}
+
+impl<'a> Translator<'a> {
+    fn new(func: &'a Function, declarations: &'a ModuleDeclarations, symbols: &'a mut Symbols, object: &'a mut WasmObject) -> Self {
+        Translator {
+            func,
+            declarations,
+            symbols,
+            object,
+            code: Vec::new(),
+            relocs: Vec::new(),
+            locals: SecondaryMap::with_default(u32::MAX),
+            local_types: Vec::new(),
+            params: 0,
+            order: SecondaryMap::new(),
+            blocks: 0,
+            label: None,
+            frame: None,
+            slot_offsets: SecondaryMap::new(),
+        }
+    }
+
+    /// The function's body, as in the code section, and its relocations
+    fn translate(&mut self) -> Result<(Vec<u8>, Vec<Reloc>), String> {
+        let func = self.func;
+        let entry = func.layout.entry_block().ok_or("function has no body")?;
+        for (index, &param) in func.dfg.block_params(entry).iter().enumerate() {
+            self.locals[param] = index as u32;
+        }
+        self.params = func.dfg.block_params(entry).len() as u32;
+        let mut back_edges = false;
+        for block in func.layout.blocks() {
+            self.order[block] = self.blocks;
+            self.blocks += 1;
+        }
+        for block in func.layout.blocks() {
+            if block != entry {
+                for &param in func.dfg.block_params(block) {
+                    self.locals[param] = self.new_local(val_type(func.dfg.value_type(param))?);
+                }
+            }
+            for inst in func.layout.block_insts(block) {
+                for &result in func.dfg.inst_results(inst) {
+                    self.locals[result] = self.new_local(val_type(func.dfg.value_type(result))?);
+                }
+                for dest in func.dfg.insts[inst].branch_destination(&func.dfg.jump_tables) {
+                    back_edges |= self.order[dest.block(&func.dfg.value_lists)] <= self.order[block];
+                }
+            }
+        }
+        if back_edges {
+            self.label = Some(self.new_local(ValType::I32));
+        }
+
+        if !func.dynamic_stack_slots.is_empty() {
+            return Err("dynamic stack slots are not supported".to_string());
+        }
+        let mut size = 0u32;
+        for (slot, data) in func.sized_stack_slots.iter() {
+            let align = 1u32 << data.align_shift;
+            size = size.next_multiple_of(align);
+            self.slot_offsets[slot] = size;
+            size += data.size;
+        }
+        if size > 0 {
+            let size = size.next_multiple_of(16);
+            let frame = self.new_local(ValType::I32);
+            self.frame = Some((frame, size));
+            self.reloc_op(0x23, RelocKind::StackPointer, 0, 0);
+            self.op(Instruction::I32Const(size as i32));
+            self.op(Instruction::I32Sub);
+            self.op(Instruction::LocalTee(frame));
+            self.reloc_op(0x24, RelocKind::StackPointer, 0, 0);
+        }
+
+        self.op(Instruction::Loop(BlockType::Empty));
+        for _ in 0..self.blocks {
+            self.op(Instruction::Block(BlockType::Empty));
+        }
+        if let Some(label) = self.label {
+            self.op(Instruction::LocalGet(label));
+            self.op(Instruction::BrTable((0..self.blocks).collect::<Vec<_>>().into(), 0));
+        }
+        for block in func.layout.blocks() {
+            self.op(Instruction::End);
+            for inst in func.layout.block_insts(block) {
+                self.inst(inst, block)?;
+            }
+        }
+        self.op(Instruction::End);
+        self.op(Instruction::Unreachable);
+        self.op(Instruction::End);
+
+        // Locals, as runs of one type
+        let mut body = Vec::new();
+        let mut runs: Vec<(u32, ValType)> = Vec::new();
+        for &ty in &self.local_types {
+            match runs.last_mut() {
+                Some((count, last)) if *last == ty => *count += 1,
+                _ => runs.push((1, ty)),
+            }
+        }
+        (runs.len() as u32).encode(&mut body);
+        for (count, ty) in runs {
+            count.encode(&mut body);
+            wasm_encoder::ValType::from(ty).encode(&mut body);
+        }
+        let prefix = body.len() as u32;
+        body.append(&mut self.code);
+        let relocs = self.relocs.drain(..).map(|reloc| Reloc { offset: reloc.offset + prefix, ..reloc }).collect();
+        Ok((body, relocs))
+    }
+
+    fn new_local(&mut self, ty: ValType) -> u32 {
+        self.local_types.push(ty);
+        self.params + self.local_types.len() as u32 - 1
+    }
+
+    fn op(&mut self, instruction: Instruction) {
+        instruction.encode(&mut self.code);
+    }
+
+    /// An instruction with one relocated immediate: `opcode` then the padded LEB128
+    fn reloc_op(&mut self, opcode: u8, kind: RelocKind, index: u32, addend: i32) {
+        self.code.push(opcode);
+        self.relocs.push(Reloc { offset: self.code.len() as u32, kind, index, addend });
+        write_padded_leb(&mut self.code, 0, opcode == 0x41);
+    }
+
+    fn get(&mut self, value: Value) {
+        let local = self.locals[self.func.dfg.resolve_aliases(value)];
+        self.op(Instruction::LocalGet(local));
+    }
+
+    fn set(&mut self, value: Value) {
+        let local = self.locals[value];
+        self.op(Instruction::LocalSet(local));
+    }
+
+    fn ty(&self, value: Value) -> Type {
+        self.func.dfg.value_type(value)
+    }
+
+    /// Push `value` as an i32 that is nonzero when it is
+    fn get_condition(&mut self, value: Value) {
+        self.get(value);
+        if self.ty(value) == types::I64 {
+            self.op(Instruction::I64Eqz);
+            self.op(Instruction::I32Eqz);
+        }
+    }
+
+    /// Push an integer operand of type `ty`, sign-extended to i32 when narrower
+    fn get_signed(&mut self, value: Value) {
+        self.get(value);
+        self.sign_extend(self.ty(value));
+    }
+
+    fn sign_extend(&mut self, ty: Type) {
+        match ty {
+            types::I8 => self.op(Instruction::I32Extend8S),
+            types::I16 => self.op(Instruction::I32Extend16S),
+            _ => {}
+        }
+    }
+
+    /// Clear the bits of an i32 above a narrower `ty`
+    fn mask(&mut self, ty: Type) {
+        if ty.bits() < 32 {
+            self.op(Instruction::I32Const(((1u32 << ty.bits()) - 1) as i32));
+            self.op(Instruction::I32And);
+        }
+    }
+
+    fn symbol(&mut self, key: SymbolKey) -> Result<u32, String> {
+        self.symbols.symbol(key, self.declarations, self.object)
+    }
+
+    /// Symbol of a function or data object the IR names, and whether it is a function
+    fn external(&mut self, name: &ExternalName) -> Result<(u32, bool), String> {
+        let ExternalName::User(name) = name else {
+            return Err(format!("unsupported external name {:?}", name));
+        };
+        let name = &self.func.params.user_named_funcs()[*name];
+        match name.namespace {
+            0 => Ok((self.symbol(SymbolKey::Function(FuncId::from_u32(name.index)))?, true)),
+            1 => Ok((self.symbol(SymbolKey::Data(DataId::from_u32(name.index)))?, false)),
+            namespace => Err(format!("unsupported name namespace {}", namespace)),
+        }
+    }
+
+    /// Push the address of a function (its table slot) or data object, as an i32
+    fn address(&mut self, gv: GlobalValue) -> Result<(), String> {
+        let GlobalValueData::Symbol { name, offset, .. } = &self.func.global_values[gv] else {
+            return Err(format!("unsupported global value {}", self.func.global_values[gv]));
+        };
+        let (symbol, is_function) = self.external(name)?;
+        let kind = if is_function { RelocKind::TableIndex } else { RelocKind::DataAddress };
+        self.reloc_op(0x41, kind, symbol, offset.bits() as i32);
+        Ok(())
+    }
+
+    /// Push `pointer + offset` as an i32 address, returning the offset left for `MemArg`
+    fn memory_address(&mut self, pointer: Value, offset: i32) -> MemArg {
+        self.get(pointer);
+        if self.ty(pointer) == types::I64 {
+            self.op(Instruction::I32WrapI64);
+        }
+        let offset = if offset < 0 {
+            self.op(Instruction::I32Const(offset));
+            self.op(Instruction::I32Add);
+            0
+        } else {
+            offset as u64
+        };
+        MemArg { offset, align: 0, memory_index: 0 }
+    }
+
+    /// Push the address of a stack slot plus `offset`, as an i32
+    fn slot_address(&mut self, slot: StackSlot, offset: i32) {
+        let (frame, _) = self.frame.expect("functions with stack slots have a frame");
+        self.op(Instruction::LocalGet(frame));
+        self.op(Instruction::I32Const(self.slot_offsets[slot] as i32 + offset));
+        self.op(Instruction::I32Add);
+    }
+
+    /// Widen an i32 address to the IR's pointer type
+    fn pointer_result(&mut self, result: Value) {
+        if self.ty(result) == types::I64 {
+            self.op(Instruction::I64ExtendI32U);
+        }
+        self.set(result);
+    }
+
+    /// Go to `dest` from the code of block `from`, nested `depth` wasm blocks deeper
+    fn jump(&mut self, from: Block, dest: BlockCall, depth: u32) {
+        let func = self.func;
+        let target = dest.block(&func.dfg.value_lists);
+        // A parallel copy: arguments may be the target's own parameters
+        let args = dest.args_slice(&func.dfg.value_lists);
+        for &arg in args {
+            self.get(arg);
+        }
+        for &param in func.dfg.block_params(target).iter().rev() {
+            self.set(param);
+        }
+        let (from, to) = (self.order[from], self.order[target]);
+        if to > from {
+            if to > from + 1 || depth > 0 {
+                self.op(Instruction::Br(to - from - 1 + depth));
+            }
+        } else {
+            let label = self.label.expect("functions with loops have a label");
+            self.op(Instruction::I32Const(to as i32));
+            self.op(Instruction::LocalSet(label));
+            self.op(Instruction::Br(self.blocks - 1 - from + depth));
+        }
+    }
+
+    fn inst(&mut self, inst: Inst, block: Block) -> Result<(), String> {
+        let func = self.func;
+        let dfg = &func.dfg;
+        let data = &dfg.insts[inst];
+        let opcode = data.opcode();
+        let args = dfg.inst_args(inst);
+        let results = dfg.inst_results(inst);
+        let result = results.first().copied();
+        let ty = result.map(|result| dfg.value_type(result)).unwrap_or(types::INVALID);
+        let unsupported = || format!("unsupported instruction {}", dfg.display_inst(inst));
+
+        match *data {
+            InstructionData::Jump { destination, .. } => self.jump(block, destination, 0),
+            InstructionData::Brif { arg, blocks: [then, otherwise], .. } => {
+                self.get_condition(arg);
+                self.op(Instruction::If(BlockType::Empty));
+                self.jump(block, then, 1);
+                self.op(Instruction::End);
+                self.jump(block, otherwise, 0);
+            }
+            InstructionData::BranchTable { arg, table, .. } => {
+                let table = &dfg.jump_tables[table];
+                // One wasm block per distinct target, the table's entries branching out of them
+                let mut targets: Vec<BlockCall> = Vec::new();
+                let mut index = |call: BlockCall| match targets.iter().position(|&target| target == call) {
+                    Some(index) => index as u32,
+                    None => {
+                        targets.push(call);
+                        targets.len() as u32 - 1
+                    }
+                };
+                let default = index(table.default_block());
+                let entries: Vec<u32> = table.as_slice().iter().map(|&call| index(call)).collect();
+                for _ in 0..targets.len() {
+                    self.op(Instruction::Block(BlockType::Empty));
+                }
+                self.get(arg);
+                self.op(Instruction::BrTable(entries.into(), default));
+                for (i, &target) in targets.iter().enumerate() {
+                    self.op(Instruction::End);
+                    self.jump(block, target, (targets.len() - 1 - i) as u32);
+                }
+            }
+            InstructionData::MultiAry { opcode: Opcode::Return, .. } => {
+                if let Some((frame, size)) = self.frame {
+                    self.op(Instruction::LocalGet(frame));
+                    self.op(Instruction::I32Const(size as i32));
+                    self.op(Instruction::I32Add);
+                    self.reloc_op(0x24, RelocKind::StackPointer, 0, 0);
+                }
+                for &arg in args {
+                    self.get(arg);
+                }
+                self.op(Instruction::Return);
+            }
+            InstructionData::Trap { .. } => self.op(Instruction::Unreachable),
+            InstructionData::CondTrap { arg, .. } => {
+                self.get_condition(arg);
+                if opcode == Opcode::Trapz {
+                    self.op(Instruction::I32Eqz);
+                }
+                self.op(Instruction::If(BlockType::Empty));
+                self.op(Instruction::Unreachable);
+                self.op(Instruction::End);
+            }
+            InstructionData::NullAry { opcode: Opcode::Nop, .. } => {}
+
+            InstructionData::UnaryImm { opcode: Opcode::Iconst, imm } => {
+                let bits = imm.bits();
+                match ty {
+                    types::I64 => self.op(Instruction::I64Const(bits)),
+                    _ => self.op(Instruction::I32Const((bits as u64 & ((1u64 << ty.bits()) - 1)) as u32 as i32)),
+                }
+                self.set(results[0]);
+            }
+            InstructionData::UnaryIeee32 { imm, .. } => {
+                self.op(Instruction::F32Const(f32::from_bits(imm.bits())));
+                self.set(results[0]);
+            }
+            InstructionData::UnaryIeee64 { imm, .. } => {
+                self.op(Instruction::F64Const(f64::from_bits(imm.bits())));
+                self.set(results[0]);
+            }
+            InstructionData::UnaryConst { constant_handle, .. } => {
+                let bytes: [u8; 16] = dfg.constants.get(constant_handle).as_slice().try_into().map_err(|_| unsupported())?;
+                self.op(Instruction::V128Const(i128::from_le_bytes(bytes)));
+                self.set(results[0]);
+            }
+
+            InstructionData::UnaryGlobalValue { opcode: Opcode::SymbolValue | Opcode::TlsValue, global_value } => {
+                self.address(global_value)?;
+                self.pointer_result(results[0]);
+            }
+            InstructionData::FuncAddr { func_ref, .. } => {
+                let (symbol, _) = self.external(&dfg.ext_funcs[func_ref].name)?;
+                self.reloc_op(0x41, RelocKind::TableIndex, symbol, 0);
+                self.pointer_result(results[0]);
+            }
+            InstructionData::StackLoad { opcode: Opcode::StackAddr, stack_slot, offset } => {
+                self.slot_address(stack_slot, offset.into());
+                self.pointer_result(results[0]);
+            }
+            InstructionData::Call { func_ref, .. } => {
+                for &arg in args {
+                    self.get(arg);
+                }
+                let (symbol, _) = self.external(&dfg.ext_funcs[func_ref].name)?;
+                self.reloc_op(0x10, RelocKind::FunctionIndex, symbol, 0);
+                for &result in results.iter().rev() {
+                    self.set(result);
+                }
+            }
+            InstructionData::CallIndirect { sig_ref, .. } => {
+                for &arg in &args[1..] {
+                    self.get(arg);
+                }
+                self.get(args[0]);
+                if self.ty(args[0]) == types::I64 {
+                    self.op(Instruction::I32WrapI64);
+                }
+                let ty = self.symbols.ty(func_type(&dfg.signatures[sig_ref])?, self.object);
+                self.reloc_op(0x11, RelocKind::TypeIndex, ty, 0);
+                // Table 0
+                self.code.push(0);
+                for &result in results.iter().rev() {
+                    self.set(result);
+                }
+            }
+
+            InstructionData::Load { arg, offset, .. } => {
+                let memarg = self.memory_address(arg, offset.into());
+                let wide = ty == types::I64;
+                let load = match (opcode, ty) {
+                    (Opcode::Load, types::I8) => Instruction::I32Load8U(memarg),
+                    (Opcode::Load, types::I16) => Instruction::I32Load16U(memarg),
+                    (Opcode::Load, types::I32) => Instruction::I32Load(memarg),
+                    (Opcode::Load, types::I64) => Instruction::I64Load(memarg),
+                    (Opcode::Load, types::F32) => Instruction::F32Load(memarg),
+                    (Opcode::Load, types::F64) => Instruction::F64Load(memarg),
+                    (Opcode::Load, ty) if val_type(ty) == Ok(ValType::V128) => Instruction::V128Load(memarg),
+                    (Opcode::Uload8, _) if wide => Instruction::I64Load8U(memarg),
+                    (Opcode::Uload8, _) => Instruction::I32Load8U(memarg),
+                    (Opcode::Sload8, _) if wide => Instruction::I64Load8S(memarg),
+                    (Opcode::Sload8, _) => Instruction::I32Load8S(memarg),
+                    (Opcode::Uload16, _) if wide => Instruction::I64Load16U(memarg),
+                    (Opcode::Uload16, _) => Instruction::I32Load16U(memarg),
+                    (Opcode::Sload16, _) if wide => Instruction::I64Load16S(memarg),
+                    (Opcode::Sload16, _) => Instruction::I32Load16S(memarg),
+                    (Opcode::Uload32, _) => Instruction::I64Load32U(memarg),
+                    (Opcode::Sload32, _) => Instruction::I64Load32S(memarg),
+                    _ => return Err(unsupported()),
+                };
+                self.op(load);
+                if matches!(opcode, Opcode::Sload8 | Opcode::Sload16) {
+                    self.mask(ty);
+                }
+                self.set(results[0]);
+            }
+            InstructionData::Store { args: [value, pointer], offset, .. } => {
+                let memarg = self.memory_address(pointer, offset.into());
+                self.get(value);
+                let wide = self.ty(value) == types::I64;
+                let store = match (opcode, self.ty(value)) {
+                    (Opcode::Store, types::I8) => Instruction::I32Store8(memarg),
+                    (Opcode::Store, types::I16) => Instruction::I32Store16(memarg),
+                    (Opcode::Store, types::I32) => Instruction::I32Store(memarg),
+                    (Opcode::Store, types::I64) => Instruction::I64Store(memarg),
+                    (Opcode::Store, types::F32) => Instruction::F32Store(memarg),
+                    (Opcode::Store, types::F64) => Instruction::F64Store(memarg),
+                    (Opcode::Store, ty) if val_type(ty) == Ok(ValType::V128) => Instruction::V128Store(memarg),
+                    (Opcode::Istore8, _) if wide => Instruction::I64Store8(memarg),
+                    (Opcode::Istore8, _) => Instruction::I32Store8(memarg),
+                    (Opcode::Istore16, _) if wide => Instruction::I64Store16(memarg),
+                    (Opcode::Istore16, _) => Instruction::I32Store16(memarg),
+                    (Opcode::Istore32, _) => Instruction::I64Store32(memarg),
+                    _ => return Err(unsupported()),
+                };
+                self.op(store);
+            }
+
+            InstructionData::IntCompare { cond, args: [a, b], .. } => {
+                let wide = match self.ty(a) {
+                    types::I64 => true,
+                    types::I8 | types::I16 | types::I32 => false,
+                    _ => return Err(unsupported()),
+                };
+                let signed = matches!(cond, IntCC::SignedLessThan | IntCC::SignedLessThanOrEqual
+                    | IntCC::SignedGreaterThan | IntCC::SignedGreaterThanOrEqual);
+                if signed {
+                    self.get_signed(a);
+                    self.get_signed(b);
+                } else {
+                    self.get(a);
+                    self.get(b);
+                }
+                self.op(int_compare(cond, wide));
+                self.set(results[0]);
+            }
+            InstructionData::FloatCompare { cond, args: [a, b], .. } => {
+                let wide = match self.ty(a) {
+                    types::F64 => true,
+                    types::F32 => false,
+                    _ => return Err(unsupported()),
+                };
+                self.float_compare(cond, a, b, wide);
+                self.set(results[0]);
+            }
+            InstructionData::Ternary { opcode: Opcode::Select | Opcode::SelectSpectreGuard, args: [cond, a, b] } => {
+                self.get(a);
+                self.get(b);
+                self.get_condition(cond);
+                self.op(Instruction::Select);
+                self.set(results[0]);
+            }
+            InstructionData::Ternary { opcode: Opcode::Fma, args: [a, b, c] } => {
+                // Fused: wasm has no instruction that rounds once
+                let helper = match ty {
+                    types::F64 => "js_wasi_fma",
+                    types::F32 => "js_wasi_fmaf",
+                    _ => return Err(unsupported()),
+                };
+                self.get(a);
+                self.get(b);
+                self.get(c);
+                let symbol = self.symbol(SymbolKey::Helper(helper))?;
+                self.reloc_op(0x10, RelocKind::FunctionIndex, symbol, 0);
+                self.set(results[0]);
+            }
+
+            InstructionData::Binary { args: [a, b], .. } if ty.is_float() || (ty.is_vector() && ty.lane_type().is_float()) => {
+                self.get(a);
+                self.get(b);
+                self.op(float_binary(opcode, ty).ok_or_else(unsupported)?);
+                self.set(results[0]);
+            }
+            InstructionData::Binary { args: [a, b], .. } if matches!(opcode, Opcode::SaddOverflow | Opcode::SsubOverflow
+                | Opcode::SmulOverflow | Opcode::UaddOverflow | Opcode::UsubOverflow | Opcode::UmulOverflow) => {
+                self.overflow(opcode, a, b, results[0], results[1]).ok_or_else(unsupported)?;
+            }
+            InstructionData::Binary { args: [a, b], .. } if matches!(opcode, Opcode::Smin | Opcode::Smax | Opcode::Umin | Opcode::Umax) => {
+                let wide = match ty {
+                    types::I64 => true,
+                    types::I8 | types::I16 | types::I32 => false,
+                    _ => return Err(unsupported()),
+                };
+                self.get(a);
+                self.get(b);
+                let cond = match opcode {
+                    Opcode::Smin => IntCC::SignedLessThan,
+                    Opcode::Smax => IntCC::SignedGreaterThan,
+                    Opcode::Umin => IntCC::UnsignedLessThan,
+                    _ => IntCC::UnsignedGreaterThan,
+                };
+                if matches!(opcode, Opcode::Smin | Opcode::Smax) {
+                    self.get_signed(a);
+                    self.get_signed(b);
+                } else {
+                    self.get(a);
+                    self.get(b);
+                }
+                self.op(int_compare(cond, wide));
+                self.op(Instruction::Select);
+                self.set(results[0]);
+            }
+            InstructionData::Binary { args: [a, b], .. } if ty.is_vector() => {
+                self.get(a);
+                self.get(b);
+                self.op(vector_int_binary(opcode, ty).ok_or_else(unsupported)?);
+                self.set(results[0]);
+            }
+            InstructionData::Binary { args: [a, b], .. } => {
+                let instruction = int_binary(opcode, ty).ok_or_else(unsupported)?;
+                let narrow = ty.bits() < 32;
+                if narrow && matches!(opcode, Opcode::Rotl | Opcode::Rotr) {
+                    return Err(unsupported());
+                }
+                let signed = matches!(opcode, Opcode::Sdiv | Opcode::Srem | Opcode::Sshr);
+                if signed {
+                    self.get_signed(a);
+                } else {
+                    self.get(a);
+                }
+                if matches!(opcode, Opcode::Ishl | Opcode::Ushr | Opcode::Sshr | Opcode::Rotl | Opcode::Rotr) {
+                    // Shift amounts can have any integer type; narrow shifts take them modulo the width
+                    self.get(b);
+                    match (ty == types::I64, self.ty(b) == types::I64) {
+                        (true, false) => self.op(Instruction::I64ExtendI32U),
+                        (false, true) => self.op(Instruction::I32WrapI64),
+                        _ => {}
+                    }
+                    if narrow {
+                        self.op(Instruction::I32Const(ty.bits() as i32 - 1));
+                        self.op(Instruction::I32And);
+                    }
+                } else if signed {
+                    self.get_signed(b);
+                } else {
+                    self.get(b);
+                }
+                if matches!(opcode, Opcode::BandNot | Opcode::BorNot | Opcode::BxorNot) {
+                    self.op(if ty == types::I64 { Instruction::I64Const(-1) } else { Instruction::I32Const(-1) });
+                    self.op(if ty == types::I64 { Instruction::I64Xor } else { Instruction::I32Xor });
+                }
+                self.op(instruction);
+                if matches!(opcode, Opcode::Iadd | Opcode::Isub | Opcode::Imul | Opcode::Ishl | Opcode::Sshr
+                    | Opcode::Sdiv | Opcode::Srem | Opcode::BorNot | Opcode::BxorNot) {
+                    self.mask(ty);
+                }
+                self.set(results[0]);
+            }
+
+            InstructionData::Unary { arg, .. } => self.unary(opcode, arg, results[0]).ok_or_else(unsupported)?,
+            InstructionData::BinaryImm8 { opcode: Opcode::Extractlane, arg, imm } => {
+                self.get(arg);
+                let lane = imm;
+                self.op(match self.ty(arg) {
+                    types::F64X2 => Instruction::F64x2ExtractLane(lane),
+                    types::F32X4 => Instruction::F32x4ExtractLane(lane),
+                    types::I64X2 => Instruction::I64x2ExtractLane(lane),
+                    types::I32X4 => Instruction::I32x4ExtractLane(lane),
+                    _ => return Err(unsupported()),
+                });
+                self.set(results[0]);
+            }
+            InstructionData::TernaryImm8 { opcode: Opcode::Insertlane, args: [vector, value], imm } => {
+                self.get(vector);
+                self.get(value);
+                let lane = imm;
+                self.op(match ty {
+                    types::F64X2 => Instruction::F64x2ReplaceLane(lane),
+                    types::F32X4 => Instruction::F32x4ReplaceLane(lane),
+                    types::I64X2 => Instruction::I64x2ReplaceLane(lane),
+                    types::I32X4 => Instruction::I32x4ReplaceLane(lane),
+                    _ => return Err(unsupported()),
+                });
+                self.set(results[0]);
+            }
+            _ => return Err(unsupported()),
+        }
+        Ok(())
+    }
+
+    fn unary(&mut self, opcode: Opcode, arg: Value, result: Value) -> Option<()> {
+        let ty = self.ty(result);
+        let from = self.ty(arg);
+        let wide = ty == types::I64;
+        match opcode {
+            Opcode::Ineg | Opcode::Bnot | Opcode::Iabs if ty.is_int() && !ty.is_vector() && ty.bits() <= 64 => {
+                let zero = if wide { Instruction::I64Const(0) } else { Instruction::I32Const(0) };
+                let sub = if wide { Instruction::I64Sub } else { Instruction::I32Sub };
+                match opcode {
+                    Opcode::Ineg => {
+                        self.op(zero);
+                        self.get(arg);
+                        self.op(sub);
+                    }
+                    Opcode::Bnot => {
+                        self.get(arg);
+                        self.op(if wide { Instruction::I64Const(-1) } else { Instruction::I32Const(-1) });
+                        self.op(if wide { Instruction::I64Xor } else { Instruction::I32Xor });
+                    }
+                    _ => {
+                        self.op(zero.clone());
+                        self.get_signed(arg);
+                        self.op(sub);
+                        self.get_signed(arg);
+                        self.get_signed(arg);
+                        self.op(zero);
+                        self.op(if wide { Instruction::I64LtS } else { Instruction::I32LtS });
+                        self.op(Instruction::Select);
+                    }
+                }
+                self.mask(ty);
+            }
+            Opcode::Clz | Opcode::Ctz | Opcode::Popcnt if ty == types::I32 || wide => {
+                self.get(arg);
+                self.op(match (opcode, wide) {
+                    (Opcode::Clz, false) => Instruction::I32Clz,
+                    (Opcode::Clz, true) => Instruction::I64Clz,
+                    (Opcode::Ctz, false) => Instruction::I32Ctz,
+                    (Opcode::Ctz, true) => Instruction::I64Ctz,
+                    (_, false) => Instruction::I32Popcnt,
+                    (_, true) => Instruction::I64Popcnt,
+                });
+            }
+            Opcode::Bmask if ty.is_int() && !ty.is_vector() && ty.bits() <= 64 => {
+                self.op(if wide { Instruction::I64Const(-1) } else { Instruction::I32Const(-1) });
+                self.op(if wide { Instruction::I64Const(0) } else { Instruction::I32Const(0) });
+                self.get_condition(arg);
+                self.op(Instruction::Select);
+                self.mask(ty);
+            }
+            Opcode::Uextend => {
+                self.get(arg);
+                if wide && from != types::I64 {
+                    self.op(Instruction::I64ExtendI32U);
+                }
+            }
+            Opcode::Sextend => {
+                self.get_signed(arg);
+                if wide && from != types::I64 {
+                    self.op(Instruction::I64ExtendI32S);
+                }
+                self.mask(ty);
+            }
+            Opcode::Ireduce => {
+                self.get(arg);
+                if from == types::I64 {
+                    self.op(Instruction::I32WrapI64);
+                }
+                self.mask(ty);
+            }
+            Opcode::Bitcast => {
+                self.get(arg);
+                match (val_type(from).ok()?, val_type(ty).ok()?) {
+                    (ValType::I64, ValType::F64) => self.op(Instruction::F64ReinterpretI64),
+                    (ValType::F64, ValType::I64) => self.op(Instruction::I64ReinterpretF64),
+                    (ValType::I32, ValType::F32) if from == types::I32 => self.op(Instruction::F32ReinterpretI32),
+                    (ValType::F32, ValType::I32) if ty == types::I32 => self.op(Instruction::I32ReinterpretF32),
+                    (a, b) if a == b && from.bits() == ty.bits() => {}
+                    _ => return None,
+                }
+            }
+            Opcode::Fpromote if ty == types::F64 => {
+                self.get(arg);
+                self.op(Instruction::F64PromoteF32);
+            }
+            Opcode::Fdemote if ty == types::F32 => {
+                self.get(arg);
+                self.op(Instruction::F32DemoteF64);
+            }
+            Opcode::FcvtFromSint | Opcode::FcvtFromUint if ty.is_float() => {
+                let signed = opcode == Opcode::FcvtFromSint;
+                if signed {
+                    self.get_signed(arg);
+                } else {
+                    self.get(arg);
+                }
+                self.op(match (ty == types::F64, from == types::I64, signed) {
+                    (true, true, true) => Instruction::F64ConvertI64S,
+                    (true, true, false) => Instruction::F64ConvertI64U,
+                    (true, false, true) => Instruction::F64ConvertI32S,
+                    (true, false, false) => Instruction::F64ConvertI32U,
+                    (false, true, true) => Instruction::F32ConvertI64S,
+                    (false, true, false) => Instruction::F32ConvertI64U,
+                    (false, false, true) => Instruction::F32ConvertI32S,
+                    (false, false, false) => Instruction::F32ConvertI32U,
+                });
+            }
+            Opcode::FcvtToSint | Opcode::FcvtToUint | Opcode::FcvtToSintSat | Opcode::FcvtToUintSat
+                if (ty == types::I32 || wide) && from.is_float() => {
+                self.get(arg);
+                let double = from == types::F64;
+                self.op(match (opcode, wide, double) {
+                    (Opcode::FcvtToSint, false, false) => Instruction::I32TruncF32S,
+                    (Opcode::FcvtToSint, false, true) => Instruction::I32TruncF64S,
+                    (Opcode::FcvtToSint, true, false) => Instruction::I64TruncF32S,
+                    (Opcode::FcvtToSint, true, true) => Instruction::I64TruncF64S,
+                    (Opcode::FcvtToUint, false, false) => Instruction::I32TruncF32U,
+                    (Opcode::FcvtToUint, false, true) => Instruction::I32TruncF64U,
+                    (Opcode::FcvtToUint, true, false) => Instruction::I64TruncF32U,
+                    (Opcode::FcvtToUint, true, true) => Instruction::I64TruncF64U,
+                    (Opcode::FcvtToSintSat, false, false) => Instruction::I32TruncSatF32S,
+                    (Opcode::FcvtToSintSat, false, true) => Instruction::I32TruncSatF64S,
+                    (Opcode::FcvtToSintSat, true, false) => Instruction::I64TruncSatF32S,
+                    (Opcode::FcvtToSintSat, true, true) => Instruction::I64TruncSatF64S,
+                    (_, false, false) => Instruction::I32TruncSatF32U,
+                    (_, false, true) => Instruction::I32TruncSatF64U,
+                    (_, true, false) => Instruction::I64TruncSatF32U,
+                    (_, true, true) => Instruction::I64TruncSatF64U,
+                });
+            }
+            Opcode::Sqrt | Opcode::Fabs | Opcode::Fneg | Opcode::Ceil | Opcode::Floor | Opcode::Trunc | Opcode::Nearest => {
+                self.get(arg);
+                self.op(float_unary(opcode, ty)?);
+            }
+            Opcode::Splat => {
+                self.get(arg);
+                self.op(match ty {
+                    types::F64X2 => Instruction::F64x2Splat,
+                    types::F32X4 => Instruction::F32x4Splat,
+                    types::I64X2 => Instruction::I64x2Splat,
+                    types::I32X4 => Instruction::I32x4Splat,
+                    _ => return None,
+                });
+            }
+            _ => return None,
+        }
+        self.set(result);
+        Some(())
+    }
+
+    fn float_compare(&mut self, cond: FloatCC, a: Value, b: Value, wide: bool) {
+        let op = |cond: FloatCC| -> Instruction<'static> {
+            match (cond, wide) {
+                (FloatCC::Equal, false) => Instruction::F32Eq,
+                (FloatCC::Equal, true) => Instruction::F64Eq,
+                (FloatCC::NotEqual, false) => Instruction::F32Ne,
+                (FloatCC::NotEqual, true) => Instruction::F64Ne,
+                (FloatCC::LessThan, false) => Instruction::F32Lt,
+                (FloatCC::LessThan, true) => Instruction::F64Lt,
+                (FloatCC::LessThanOrEqual, false) => Instruction::F32Le,
+                (FloatCC::LessThanOrEqual, true) => Instruction::F64Le,
+                (FloatCC::GreaterThan, false) => Instruction::F32Gt,
+                (FloatCC::GreaterThan, true) => Instruction::F64Gt,
+                (FloatCC::GreaterThanOrEqual, false) => Instruction::F32Ge,
+                (FloatCC::GreaterThanOrEqual, true) => Instruction::F64Ge,
+                (cond, _) => unreachable!("{} is built from the ordered conditions", cond),
+            }
+        };
+        // The ordered conditions wasm has, and the rest as combinations of them
+        let (first, second, combine, negate) = match cond {
+            FloatCC::Equal | FloatCC::NotEqual | FloatCC::LessThan | FloatCC::LessThanOrEqual
+            | FloatCC::GreaterThan | FloatCC::GreaterThanOrEqual => ((cond, a, b), None, Instruction::Nop, false),
+            FloatCC::Ordered => ((FloatCC::Equal, a, a), Some((FloatCC::Equal, b, b)), Instruction::I32And, false),
+            FloatCC::Unordered => ((FloatCC::NotEqual, a, a), Some((FloatCC::NotEqual, b, b)), Instruction::I32Or, false),
+            FloatCC::OrderedNotEqual => ((FloatCC::LessThan, a, b), Some((FloatCC::GreaterThan, a, b)), Instruction::I32Or, false),
+            FloatCC::UnorderedOrEqual => ((FloatCC::LessThan, a, b), Some((FloatCC::GreaterThan, a, b)), Instruction::I32Or, true),
+            FloatCC::UnorderedOrLessThan => ((FloatCC::GreaterThanOrEqual, a, b), None, Instruction::Nop, true),
+            FloatCC::UnorderedOrLessThanOrEqual => ((FloatCC::GreaterThan, a, b), None, Instruction::Nop, true),
+            FloatCC::UnorderedOrGreaterThan => ((FloatCC::LessThanOrEqual, a, b), None, Instruction::Nop, true),
+            FloatCC::UnorderedOrGreaterThanOrEqual => ((FloatCC::LessThan, a, b), None, Instruction::Nop, true),
+        };
+        for (cond, a, b) in std::iter::once(first).chain(second) {
+            self.get(a);
+            self.get(b);
+            self.op(op(cond));
+        }
+        if second.is_some() {
+            self.op(combine);
+        }
+        if negate {
+            self.op(Instruction::I32Eqz);
+        }
+    }
+
+    /// `a op b` and whether it overflowed, for i32 in i64 arithmetic
+    fn overflow(&mut self, opcode: Opcode, a: Value, b: Value, result: Value, overflow: Value) -> Option<()> {
+        let signed = matches!(opcode, Opcode::SaddOverflow | Opcode::SsubOverflow | Opcode::SmulOverflow);
+        match self.ty(result) {
+            types::I32 => {
+                let extend = if signed { Instruction::I64ExtendI32S } else { Instruction::I64ExtendI32U };
+                let wide = self.new_local(ValType::I64);
+                self.get(a);
+                self.op(extend.clone());
+                self.get(b);
+                self.op(extend.clone());
+                self.op(match opcode {
+                    Opcode::SaddOverflow | Opcode::UaddOverflow => Instruction::I64Add,
+                    Opcode::SsubOverflow | Opcode::UsubOverflow => Instruction::I64Sub,
+                    _ => Instruction::I64Mul,
+                });
+                self.op(Instruction::LocalTee(wide));
+                self.op(Instruction::I32WrapI64);
+                self.set(result);
+                self.get(result);
+                self.op(extend);
+                self.op(Instruction::LocalGet(wide));
+                self.op(Instruction::I64Ne);
+            }
+            types::I64 => {
+                self.get(a);
+                self.get(b);
+                self.op(match opcode {
+                    Opcode::SaddOverflow | Opcode::UaddOverflow => Instruction::I64Add,
+                    Opcode::SsubOverflow | Opcode::UsubOverflow => Instruction::I64Sub,
+                    _ => return None,
+                });
+                self.set(result);
+                match opcode {
+                    Opcode::UaddOverflow => {
+                        self.get(result);
+                        self.get(a);
+                        self.op(Instruction::I64LtU);
+                    }
+                    Opcode::UsubOverflow => {
+                        self.get(a);
+                        self.get(b);
+                        self.op(Instruction::I64LtU);
+                    }
+                    _ => {
+                        // Signed: (a ^ r) & (b ^ r) for +, (a ^ r) & (a ^ b) for -, is negative
+                        self.get(a);
+                        self.get(result);
+                        self.op(Instruction::I64Xor);
+                        self.get(if opcode == Opcode::SaddOverflow { result } else { a });
+                        self.get(b);
+                        self.op(Instruction::I64Xor);
+                        self.op(Instruction::I64And);
+                        self.op(Instruction::I64Const(0));
+                        self.op(Instruction::I64LtS);
+                    }
+                }
+            }
+            _ => return None,
+        }
+        self.set(overflow);
+        Some(())
+    }
+}
+
+fn int_compare(cond: IntCC, wide: bool) -> Instruction<'static> {
+    match (cond, wide) {
+        (IntCC::Equal, false) => Instruction::I32Eq,
+        (IntCC::Equal, true) => Instruction::I64Eq,
+        (IntCC::NotEqual, false) => Instruction::I32Ne,
+        (IntCC::NotEqual, true) => Instruction::I64Ne,
+        (IntCC::SignedLessThan, false) => Instruction::I32LtS,
+        (IntCC::SignedLessThan, true) => Instruction::I64LtS,
+        (IntCC::SignedLessThanOrEqual, false) => Instruction::I32LeS,
+        (IntCC::SignedLessThanOrEqual, true) => Instruction::I64LeS,
+        (IntCC::SignedGreaterThan, false) => Instruction::I32GtS,
+        (IntCC::SignedGreaterThan, true) => Instruction::I64GtS,
+        (IntCC::SignedGreaterThanOrEqual, false) => Instruction::I32GeS,
+        (IntCC::SignedGreaterThanOrEqual, true) => Instruction::I64GeS,
+        (IntCC::UnsignedLessThan, false) => Instruction::I32LtU,
+        (IntCC::UnsignedLessThan, true) => Instruction::I64LtU,
+        (IntCC::UnsignedLessThanOrEqual, false) => Instruction::I32LeU,
+        (IntCC::UnsignedLessThanOrEqual, true) => Instruction::I64LeU,
+        (IntCC::UnsignedGreaterThan, false) => Instruction::I32GtU,
+        (IntCC::UnsignedGreaterThan, true) => Instruction::I64GtU,
+        (IntCC::UnsignedGreaterThanOrEqual, false) => Instruction::I32GeU,
+        (IntCC::UnsignedGreaterThanOrEqual, true) => Instruction::I64GeU,
+    }
+}
+
+fn int_binary(opcode: Opcode, ty: Type) -> Option<Instruction<'static>> {
+    let wide = match ty {
+        types::I64 => true,
+        types::I8 | types::I16 | types::I32 => false,
+        _ => return None,
+    };
+    Some(match (opcode, wide) {
+        (Opcode::Iadd, false) => Instruction::I32Add,
+        (Opcode::Iadd, true) => Instruction::I64Add,
+        (Opcode::Isub, false) => Instruction::I32Sub,
+        (Opcode::Isub, true) => Instruction::I64Sub,
+        (Opcode::Imul, false) => Instruction::I32Mul,
+        (Opcode::Imul, true) => Instruction::I64Mul,
+        (Opcode::Udiv, false) => Instruction::I32DivU,
+        (Opcode::Udiv, true) => Instruction::I64DivU,
+        (Opcode::Sdiv, false) => Instruction::I32DivS,
+        (Opcode::Sdiv, true) => Instruction::I64DivS,
+        (Opcode::Urem, false) => Instruction::I32RemU,
+        (Opcode::Urem, true) => Instruction::I64RemU,
+        (Opcode::Srem, false) => Instruction::I32RemS,
+        (Opcode::Srem, true) => Instruction::I64RemS,
+        (Opcode::Band | Opcode::BandNot, false) => Instruction::I32And,
+        (Opcode::Band | Opcode::BandNot, true) => Instruction::I64And,
+        (Opcode::Bor | Opcode::BorNot, false) => Instruction::I32Or,
+        (Opcode::Bor | Opcode::BorNot, true) => Instruction::I64Or,
+        (Opcode::Bxor | Opcode::BxorNot, false) => Instruction::I32Xor,
+        (Opcode::Bxor | Opcode::BxorNot, true) => Instruction::I64Xor,
+        (Opcode::Ishl, false) => Instruction::I32Shl,
+        (Opcode::Ishl, true) => Instruction::I64Shl,
+        (Opcode::Ushr, false) => Instruction::I32ShrU,
+        (Opcode::Ushr, true) => Instruction::I64ShrU,
+        (Opcode::Sshr, false) => Instruction::I32ShrS,
+        (Opcode::Sshr, true) => Instruction::I64ShrS,
+        (Opcode::Rotl, false) => Instruction::I32Rotl,
+        (Opcode::Rotl, true) => Instruction::I64Rotl,
+        (Opcode::Rotr, false) => Instruction::I32Rotr,
+        (Opcode::Rotr, true) => Instruction::I64Rotr,
+        _ => return None,
+    })
+}
+
+fn vector_int_binary(opcode: Opcode, ty: Type) -> Option<Instruction<'static>> {
+    Some(match (opcode, ty) {
+        (Opcode::Band, _) => Instruction::V128And,
+        (Opcode::Bor, _) => Instruction::V128Or,
+        (Opcode::Bxor, _) => Instruction::V128Xor,
+        (Opcode::Iadd, types::I64X2) => Instruction::I64x2Add,
+        (Opcode::Iadd, types::I32X4) => Instruction::I32x4Add,
+        (Opcode::Isub, types::I64X2) => Instruction::I64x2Sub,
+        (Opcode::Isub, types::I32X4) => Instruction::I32x4Sub,
+        (Opcode::Imul, types::I32X4) => Instruction::I32x4Mul,
+        _ => return None,
+    })
+}
+
+fn float_binary(opcode: Opcode, ty: Type) -> Option<Instruction<'static>> {
+    Some(match (opcode, ty) {
+        (Opcode::Fadd, types::F32) => Instruction::F32Add,
+        (Opcode::Fadd, types::F64) => Instruction::F64Add,
+        (Opcode::Fadd, types::F32X4) => Instruction::F32x4Add,
+        (Opcode::Fadd, types::F64X2) => Instruction::F64x2Add,
+        (Opcode::Fsub, types::F32) => Instruction::F32Sub,
+        (Opcode::Fsub, types::F64) => Instruction::F64Sub,
+        (Opcode::Fsub, types::F32X4) => Instruction::F32x4Sub,
+        (Opcode::Fsub, types::F64X2) => Instruction::F64x2Sub,
+        (Opcode::Fmul, types::F32) => Instruction::F32Mul,
+        (Opcode::Fmul, types::F64) => Instruction::F64Mul,
+        (Opcode::Fmul, types::F32X4) => Instruction::F32x4Mul,
+        (Opcode::Fmul, types::F64X2) => Instruction::F64x2Mul,
+        (Opcode::Fdiv, types::F32) => Instruction::F32Div,
+        (Opcode::Fdiv, types::F64) => Instruction::F64Div,
+        (Opcode::Fdiv, types::F32X4) => Instruction::F32x4Div,
+        (Opcode::Fdiv, types::F64X2) => Instruction::F64x2Div,
+        (Opcode::Fmin, types::F32) => Instruction::F32Min,
+        (Opcode::Fmin, types::F64) => Instruction::F64Min,
+        (Opcode::Fmin, types::F32X4) => Instruction::F32x4Min,
+        (Opcode::Fmin, types::F64X2) => Instruction::F64x2Min,
+        (Opcode::Fmax, types::F32) => Instruction::F32Max,
+        (Opcode::Fmax, types::F64) => Instruction::F64Max,
+        (Opcode::Fmax, types::F32X4) => Instruction::F32x4Max,
+        (Opcode::Fmax, types::F64X2) => Instruction::F64x2Max,
+        (Opcode::Fcopysign, types::F32) => Instruction::F32Copysign,
+        (Opcode::Fcopysign, types::F64) => Instruction::F64Copysign,
+        _ => return None,
+    })
+}
+
+fn float_unary(opcode: Opcode, ty: Type) -> Option<Instruction<'static>> {
+    Some(match (opcode, ty) {
+        (Opcode::Sqrt, types::F32) => Instruction::F32Sqrt,
+        (Opcode::Sqrt, types::F64) => Instruction::F64Sqrt,
+        (Opcode::Sqrt, types::F64X2) => Instruction::F64x2Sqrt,
+        (Opcode::Fabs, types::F32) => Instruction::F32Abs,
+        (Opcode::Fabs, types::F64) => Instruction::F64Abs,
+        (Opcode::Fabs, types::F64X2) => Instruction::F64x2Abs,
+        (Opcode::Fneg, types::F32) => Instruction::F32Neg,
+        (Opcode::Fneg, types::F64) => Instruction::F64Neg,
+        (Opcode::Fneg, types::F64X2) => Instruction::F64x2Neg,
+        (Opcode::Ceil, types::F32) => Instruction::F32Ceil,
+        (Opcode::Ceil, types::F64) => Instruction::F64Ceil,
+        (Opcode::Ceil, types::F64X2) => Instruction::F64x2Ceil,
+        (Opcode::Floor, types::F32) => Instruction::F32Floor,
+        (Opcode::Floor, types::F64) => Instruction::F64Floor,
+        (Opcode::Floor, types::F64X2) => Instruction::F64x2Floor,
+        (Opcode::Trunc, types::F32) => Instruction::F32Trunc,
+        (Opcode::Trunc, types::F64) => Instruction::F64Trunc,
+        (Opcode::Trunc, types::F64X2) => Instruction::F64x2Trunc,
+        (Opcode::Nearest, types::F32) => Instruction::F32Nearest,
+        (Opcode::Nearest, types::F64) => Instruction::F64Nearest,
+        (Opcode::Nearest, types::F64X2) => Instruction::F64x2Nearest,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use cranelift_codegen::ir::{AbiParam, InstBuilder, UserFuncName};
+    use cranelift_codegen::isa::CallConv;
+    use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext};
+    use cranelift_module::Linkage;
+
+    pub(crate) fn surrogate_isa() -> OwnedTargetIsa {
+        let mut flags = cranelift_codegen::settings::builder();
+        cranelift_codegen::settings::Configurable::set(&mut flags, "is_pic", "true").unwrap();
+        cranelift_codegen::isa::lookup_by_name("x86_64-unknown-linux-gnu").unwrap()
+            .finish(cranelift_codegen::settings::Flags::new(flags)).unwrap()
+    }
+
+    /// `main` prints 0 to 9 with the runtime's `js_print(i64)` and returns 0
+    pub(crate) fn counting_module(print: &str) -> WasmObject {
+        let mut module = WasmModule::new(surrogate_isa());
+        let mut print_sig = Signature::new(CallConv::SystemV);
+        print_sig.params.push(AbiParam::new(types::I64));
+        let print = module.declare_function(print, Linkage::Import, &print_sig).unwrap();
+        let mut main_sig = Signature::new(CallConv::SystemV);
+        main_sig.returns.push(AbiParam::new(types::I32));
+        let main = module.declare_function("main", Linkage::Export, &main_sig).unwrap();
+
+        let mut ctx = Context::new();
+        ctx.func.signature = main_sig;
+        ctx.func.name = UserFuncName::user(0, main.as_u32());
+        let mut builder_ctx = FunctionBuilderContext::new();
+        let mut builder = FunctionBuilder::new(&mut ctx.func, &mut builder_ctx);
+        let print = module.declare_func_in_func(print, builder.func);
+        let entry = builder.create_block();
+        let header = builder.create_block();
+        let body = builder.create_block();
+        let exit = builder.create_block();
+        builder.append_block_param(header, types::I64);
+        builder.switch_to_block(entry);
+        let zero = builder.ins().iconst(types::I64, 0);
+        builder.ins().jump(header, &[zero]);
+        builder.switch_to_block(header);
+        let i = builder.block_params(header)[0];
+        let more = builder.ins().icmp_imm(IntCC::SignedLessThan, i, 10);
+        builder.ins().brif(more, body, &[], exit, &[]);
+        builder.switch_to_block(body);
+        builder.ins().call(print, &[i]);
+        let next = builder.ins().iadd_imm(i, 1);
+        builder.ins().jump(header, &[next]);
+        builder.switch_to_block(exit);
+        let code = builder.ins().iconst(types::I32, 0);
+        builder.ins().return_(&[code]);
+        builder.seal_all_blocks();
+        builder.finalize();
+        module.define_function(main, &mut ctx).unwrap();
+        module.finish()
+    }
+
+    #[test]
+    fn test_module_collects_symbols_and_relocs() {
+        let object = counting_module("js_print");
+        let names: Vec<_> = object.symbols.iter().map(|symbol| (symbol.name.as_str(), symbol.global, symbol.defined)).collect();
+        assert!(names.contains(&("js_print", true, false)));
+        assert!(names.contains(&("main", true, true)));
+        assert_eq!(object.code.len(), 1);
+        let code = &object.code[0];
+        assert_eq!(object.symbols[code.symbol as usize].name, "main");
+        // The call's target is left for the linker, padded so any index fits
+        let call = code.relocs.iter().find(|reloc| reloc.kind == RelocKind::FunctionIndex).unwrap();
+        assert_eq!(object.symbols[call.index as usize].name, "js_print");
+        assert!(call.offset as usize + RELOC_LEB_LEN <= code.body.len());
+    }
+
+    #[test]
+    fn test_object_round_trip() {
+        let object = counting_module("js_print");
+        assert_eq!(WasmObject::from_bytes(&object.to_bytes()).unwrap(), object);
+        // A native object written by an older build isn't taken for one
+        assert!(WasmObject::from_bytes(b"\x7fELF").is_err());
+    }
+
+    #[test]
+    fn test_padded_leb() {
+        let mut out = Vec::new();
+        write_padded_leb(&mut out, 3, false);
+        write_padded_leb(&mut out, u32::MAX, false);
+        assert_eq!(out, [0x83, 0x80, 0x80, 0x80, 0x00, 0xff, 0xff, 0xff, 0xff, 0x0f]);
+    }
+}
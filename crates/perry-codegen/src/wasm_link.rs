@@ -0,0 +1,540 @@
+//! Linking `--target wasm32-wasip1` programs
+//!
+//! [`link`] merges the [`WasmObject`]s of a program's modules into the WASI runtime
+//! (perry-runtime-wasi, built as a wasm32-wasip1 cdylib) and adds the `_start` entry
+//! point. The runtime's sections are kept as they are, so its function, type, global and
+//! table indices don't change; the program's types, functions, table slots, data and
+//! shadow stack pointer are appended after them. The program's data goes above the
+//! runtime's initial memory, followed by its shadow stack. The runtime's allocator hands
+//! out memory past its initial size (`__heap_end`) by growing the memory, so the two don't
+//! overlap.
+//!
+//! A symbol resolves to the object's own definition, then to another object's global
+//! one, then to the runtime's export of that name; a program using more of the runtime
+//! than perry-runtime-wasi implements fails here, naming the missing function.
+
+use std::collections::HashMap;
+
+use anyhow::{anyhow, bail, Result};
+use wasm_encoder::{
+    ConstExpr, DataSection, ElementSection, Elements, Encode, ExportKind, ExportSection, GlobalSection, GlobalType,
+    MemorySection, MemoryType, RefType, TableSection, TableType, TypeSection,
+};
+use wasmparser::{Parser, Payload};
+
+use crate::wasm::{FuncType, Reloc, RelocKind, ValType, WasmObject, RELOC_LEB_LEN};
+
+/// Bytes of shadow stack for the program's stack slots
+const STACK_SIZE: u32 = 1 << 20;
+
+const PAGE_SIZE: u64 = 1 << 16;
+
+/// Function the runtime exports to end the program with main's exit code
+const EXIT_FUNCTION: &str = "js_wasi_exit";
+
+/// Link the program's `objects` (the entry module's first or last, in any order) into
+/// `runtime`, returning the WebAssembly module to run
+pub fn link(runtime: &[u8], objects: &[WasmObject]) -> Result<Vec<u8>> {
+    let runtime = Runtime::parse(runtime)?;
+    let mut linker = Linker::new(&runtime);
+    linker.resolve(objects)?;
+    linker.emit(objects)
+}
+
+/// What linking needs from the runtime: its sections, and the indices and types it defines
+struct Runtime<'a> {
+    bytes: &'a [u8],
+    /// Id and contents of each section but custom ones, in order
+    sections: Vec<(u8, std::ops::Range<usize>)>,
+    types: Vec<FuncType>,
+    /// Type of each function, imported ones first
+    functions: Vec<u32>,
+    imported_functions: u32,
+    globals: u32,
+    table: Option<wasmparser::TableType>,
+    memory: wasmparser::MemoryType,
+    exports: HashMap<String, u32>,
+    data_count: Option<u32>,
+}
+
+fn val_type(ty: wasmparser::ValType) -> Result<ValType> {
+    Ok(match ty {
+        wasmparser::ValType::I32 => ValType::I32,
+        wasmparser::ValType::I64 => ValType::I64,
+        wasmparser::ValType::F32 => ValType::F32,
+        wasmparser::ValType::F64 => ValType::F64,
+        wasmparser::ValType::V128 => ValType::V128,
+        ty => bail!("unsupported runtime value type {}", ty),
+    })
+}
+
+impl<'a> Runtime<'a> {
+    fn parse(bytes: &'a [u8]) -> Result<Self> {
+        let mut runtime = Runtime {
+            bytes,
+            sections: Vec::new(),
+            types: Vec::new(),
+            functions: Vec::new(),
+            imported_functions: 0,
+            globals: 0,
+            table: None,
+            memory: wasmparser::MemoryType { memory64: false, shared: false, initial: 0, maximum: None, page_size_log2: None },
+            exports: HashMap::new(),
+            data_count: None,
+        };
+        let mut has_memory = false;
+        for payload in Parser::new(0).parse_all(bytes) {
+            let payload = payload.map_err(|e| anyhow!("malformed WASI runtime: {}", e))?;
+            if let Some((id, range)) = payload.as_section() {
+                if id != 0 {
+                    runtime.sections.push((id, range));
+                }
+            }
+            match payload {
+                Payload::TypeSection(reader) => {
+                    for ty in reader.into_iter_err_on_gc_types() {
+                        let ty = ty?;
+                        runtime.types.push(FuncType {
+                            params: ty.params().iter().map(|&ty| val_type(ty)).collect::<Result<_>>()?,
+                            results: ty.results().iter().map(|&ty| val_type(ty)).collect::<Result<_>>()?,
+                        });
+                    }
+                }
+                Payload::ImportSection(reader) => {
+                    for import in reader {
+                        match import?.ty {
+                            wasmparser::TypeRef::Func(ty) => {
+                                runtime.functions.push(ty);
+                                runtime.imported_functions += 1;
+                            }
+                            wasmparser::TypeRef::Global(_) => runtime.globals += 1,
+                            _ => bail!("the WASI runtime imports a memory or table"),
+                        }
+                    }
+                }
+                Payload::FunctionSection(reader) => {
+                    for ty in reader {
+                        runtime.functions.push(ty?);
+                    }
+                }
+                Payload::TableSection(reader) => {
+                    for table in reader {
+                        if runtime.table.replace(table?.ty).is_some() {
+                            bail!("the WASI runtime has more than one table");
+                        }
+                    }
+                }
+                Payload::MemorySection(reader) => {
+                    for memory in reader {
+                        if std::mem::replace(&mut has_memory, true) {
+                            bail!("the WASI runtime has more than one memory");
+                        }
+                        runtime.memory = memory?;
+                    }
+                }
+                Payload::GlobalSection(reader) => runtime.globals += reader.count(),
+                Payload::ExportSection(reader) => {
+                    for export in reader {
+                        let export = export?;
+                        if export.kind == wasmparser::ExternalKind::Func {
+                            runtime.exports.insert(export.name.to_string(), export.index);
+                        }
+                    }
+                }
+                Payload::DataCountSection { count, .. } => runtime.data_count = Some(count),
+                _ => {}
+            }
+        }
+        if !has_memory {
+            bail!("the WASI runtime has no memory");
+        }
+        Ok(runtime)
+    }
+
+    fn section(&self, id: u8) -> Option<&'a [u8]> {
+        self.sections.iter().find(|(section, _)| *section == id).map(|(_, range)| &self.bytes[range.clone()])
+    }
+}
+
+/// Where an object's symbol ended up
+#[derive(Debug, Clone, Copy)]
+enum Target {
+    Function(u32),
+    Data(u32),
+}
+
+struct Linker<'a> {
+    runtime: &'a Runtime<'a>,
+    /// Output types: the runtime's, then the program's
+    types: Vec<FuncType>,
+    type_indices: HashMap<FuncType, u32>,
+    /// Each object's symbols, resolved
+    targets: Vec<Vec<Target>>,
+    /// Type index of each program function, in output order
+    functions: Vec<u32>,
+    /// Functions placed in the table after the runtime's slots
+    table: Vec<u32>,
+    table_slots: HashMap<u32, u32>,
+    data_base: u32,
+    data_end: u32,
+}
+
+impl<'a> Linker<'a> {
+    fn new(runtime: &'a Runtime<'a>) -> Self {
+        let type_indices = runtime.types.iter().enumerate()
+            .rev()
+            .map(|(index, ty)| (ty.clone(), index as u32))
+            .collect();
+        let data_base = (runtime.memory.initial * PAGE_SIZE) as u32;
+        Linker {
+            runtime,
+            types: runtime.types.clone(),
+            type_indices,
+            targets: Vec::new(),
+            functions: Vec::new(),
+            table: Vec::new(),
+            table_slots: HashMap::new(),
+            data_base,
+            data_end: data_base,
+        }
+    }
+
+    fn ty(&mut self, ty: &FuncType) -> u32 {
+        if let Some(&index) = self.type_indices.get(ty) {
+            return index;
+        }
+        self.types.push(ty.clone());
+        self.type_indices.insert(ty.clone(), self.types.len() as u32 - 1);
+        self.types.len() as u32 - 1
+    }
+
+    /// Place the program's functions and data and resolve every symbol
+    fn resolve(&mut self, objects: &[WasmObject]) -> Result<()> {
+        let first_function = self.runtime.functions.len() as u32;
+        let mut definitions: Vec<HashMap<u32, Target>> = Vec::new();
+        for object in objects {
+            let mut defined = HashMap::new();
+            for code in &object.code {
+                let ty = self.ty(&object.types[object.symbols[code.symbol as usize].function.unwrap_or(0) as usize]);
+                defined.insert(code.symbol, Target::Function(first_function + self.functions.len() as u32));
+                self.functions.push(ty);
+            }
+            for data in &object.data {
+                let address = self.data_end.next_multiple_of(data.align);
+                defined.insert(data.symbol, Target::Data(address));
+                self.data_end = address + data.bytes.len() as u32;
+            }
+            definitions.push(defined);
+        }
+
+        let mut globals: HashMap<&str, Target> = HashMap::new();
+        for (object, defined) in objects.iter().zip(&definitions) {
+            for (&symbol, &target) in defined {
+                let symbol = &object.symbols[symbol as usize];
+                if symbol.global && globals.insert(&symbol.name, target).is_some() {
+                    bail!("{} is defined by more than one module", symbol.name);
+                }
+            }
+        }
+
+        for (object, defined) in objects.iter().zip(&definitions) {
+            let mut targets = Vec::with_capacity(object.symbols.len());
+            for (index, symbol) in object.symbols.iter().enumerate() {
+                let target = match defined.get(&(index as u32)).or_else(|| globals.get(symbol.name.as_str())) {
+                    Some(&target) => target,
+                    None => match (symbol.function, self.runtime.exports.get(&symbol.name)) {
+                        (Some(ty), Some(&function)) => {
+                            let runtime_ty = &self.runtime.types[self.runtime.functions[function as usize] as usize];
+                            if *runtime_ty != object.types[ty as usize] {
+                                bail!("{} has type {:?} in perry-runtime-wasi but {:?} in the program",
+                                    symbol.name, runtime_ty, object.types[ty as usize]);
+                            }
+                            Target::Function(function)
+                        }
+                        (Some(_), None) => bail!("perry-runtime-wasi doesn't provide {}", symbol.name),
+                        (None, _) => bail!("undefined symbol {}", symbol.name),
+                    },
+                };
+                targets.push(target);
+            }
+            self.targets.push(targets);
+        }
+        Ok(())
+    }
+
+    fn table_slot(&mut self, function: u32) -> u32 {
+        let base = self.runtime.table.map_or(0, |table| table.initial as u32);
+        *self.table_slots.entry(function).or_insert_with(|| {
+            self.table.push(function);
+            base + self.table.len() as u32 - 1
+        })
+    }
+
+    /// Value of a relocation in object `object`, against one of its symbols or types
+    fn reloc_value(&mut self, object: usize, reloc: &Reloc, types: &[u32]) -> Result<u32> {
+        let symbol = |linker: &Self| linker.targets[object][reloc.index as usize];
+        Ok(match reloc.kind {
+            RelocKind::TypeIndex => types[reloc.index as usize],
+            RelocKind::StackPointer => self.runtime.globals,
+            kind => match (kind, symbol(self)) {
+                (RelocKind::FunctionIndex, Target::Function(function)) => function,
+                (RelocKind::TableIndex, Target::Function(function)) => self.table_slot(function),
+                (RelocKind::DataAddress, Target::Data(address)) => address.wrapping_add(reloc.addend as u32),
+                (kind, target) => bail!("relocation {:?} against {:?}", kind, target),
+            },
+        })
+    }
+
+    fn emit(mut self, objects: &[WasmObject]) -> Result<Vec<u8>> {
+        // Program code and data, relocated
+        let mut code = Vec::new();
+        let mut data = vec![0u8; (self.data_end - self.data_base) as usize];
+        for (index, object) in objects.iter().enumerate() {
+            let types: Vec<u32> = object.types.iter().map(|ty| self.ty(ty)).collect();
+            for function in &object.code {
+                let mut body = function.body.clone();
+                for reloc in &function.relocs {
+                    let value = self.reloc_value(index, reloc, &types)?;
+                    let mut leb = Vec::with_capacity(RELOC_LEB_LEN);
+                    crate::wasm::write_padded_leb(&mut leb, value, reloc.kind == RelocKind::DataAddress || reloc.kind == RelocKind::TableIndex);
+                    body[reloc.offset as usize..reloc.offset as usize + RELOC_LEB_LEN].copy_from_slice(&leb);
+                }
+                (body.len() as u32).encode(&mut code);
+                code.extend(body);
+            }
+            for object_data in &object.data {
+                let Target::Data(address) = self.targets[index][object_data.symbol as usize] else { unreachable!() };
+                let start = (address - self.data_base) as usize;
+                data[start..start + object_data.bytes.len()].copy_from_slice(&object_data.bytes);
+                for reloc in &object_data.relocs {
+                    let value = self.reloc_value(index, reloc, &types)?;
+                    let at = start + reloc.offset as usize;
+                    data[at..at + 4].copy_from_slice(&value.to_le_bytes());
+                }
+            }
+        }
+
+        // _start: the runtime's initializer, main, then exit with main's status
+        let entry = objects.iter().zip(&self.targets)
+            .flat_map(|(object, targets)| object.symbols.iter().zip(targets))
+            .filter(|(symbol, _)| symbol.global && symbol.defined)
+            .find(|(symbol, _)| symbol.name == "main")
+            .or_else(|| objects.iter().zip(&self.targets)
+                .flat_map(|(object, targets)| object.symbols.iter().zip(targets))
+                .find(|(symbol, _)| symbol.global && symbol.defined && symbol.name == "_perry_main"))
+            .and_then(|(_, target)| match target {
+                Target::Function(function) => Some(*function),
+                Target::Data(_) => None,
+            })
+            .ok_or_else(|| anyhow!("no module defines main"))?;
+        let exit = *self.runtime.exports.get(EXIT_FUNCTION)
+            .ok_or_else(|| anyhow!("perry-runtime-wasi doesn't provide {}", EXIT_FUNCTION))?;
+        let mut start = vec![0u8];
+        if let Some(&initialize) = self.runtime.exports.get("_initialize") {
+            wasm_encoder::Instruction::Call(initialize).encode(&mut start);
+        }
+        wasm_encoder::Instruction::Call(entry).encode(&mut start);
+        wasm_encoder::Instruction::Call(exit).encode(&mut start);
+        wasm_encoder::Instruction::End.encode(&mut start);
+        let start_ty = self.ty(&FuncType { params: Vec::new(), results: Vec::new() });
+        let start_index = self.runtime.functions.len() as u32 + self.functions.len() as u32;
+        self.functions.push(start_ty);
+        (start.len() as u32).encode(&mut code);
+        code.extend(start);
+
+        // Shadow stack above the data
+        let stack_top = (self.data_end.next_multiple_of(16) as u64 + STACK_SIZE as u64) as u32;
+        let pages = (stack_top as u64).div_ceil(PAGE_SIZE);
+
+        let mut module = Vec::new();
+        module.extend_from_slice(&[0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00]);
+        let runtime = self.runtime;
+
+        let mut types = TypeSection::new();
+        for ty in &self.types[runtime.types.len()..] {
+            let params: Vec<wasm_encoder::ValType> = ty.params.iter().map(|&ty| ty.into()).collect();
+            let results: Vec<wasm_encoder::ValType> = ty.results.iter().map(|&ty| ty.into()).collect();
+            types.ty().function(params, results);
+        }
+        section(&mut module, 1, &merge(runtime.section(1), &types.encoded())?);
+        if let Some(imports) = runtime.section(2) {
+            section(&mut module, 2, imports);
+        }
+        let mut functions = Vec::new();
+        for ty in &self.functions {
+            ty.encode(&mut functions);
+        }
+        section(&mut module, 3, &merge(runtime.section(3), &counted(self.functions.len(), &functions))?);
+
+        let mut tables = TableSection::new();
+        let added = self.table.len() as u64;
+        tables.table(match runtime.table {
+            Some(table) => TableType {
+                element_type: RefType::FUNCREF,
+                table64: false,
+                minimum: table.initial + added,
+                maximum: table.maximum.map(|maximum| maximum + added),
+                shared: false,
+            },
+            None => TableType { element_type: RefType::FUNCREF, table64: false, minimum: added, maximum: Some(added), shared: false },
+        });
+        section(&mut module, 4, &tables.encoded());
+
+        let mut memories = MemorySection::new();
+        memories.memory(MemoryType {
+            minimum: runtime.memory.initial.max(pages),
+            maximum: runtime.memory.maximum.map(|maximum| maximum.max(pages)),
+            memory64: false,
+            shared: false,
+            page_size_log2: None,
+        });
+        section(&mut module, 5, &memories.encoded());
+
+        let mut globals = GlobalSection::new();
+        globals.global(GlobalType { val_type: wasm_encoder::ValType::I32, mutable: true, shared: false }, &ConstExpr::i32_const(stack_top as i32));
+        section(&mut module, 6, &merge(runtime.section(6), &globals.encoded())?);
+
+        let mut exports = ExportSection::new();
+        exports.export("memory", ExportKind::Memory, 0);
+        exports.export("_start", ExportKind::Func, start_index);
+        section(&mut module, 7, &exports.encoded());
+
+        if let Some((_, range)) = runtime.sections.iter().find(|(id, _)| *id == 8) {
+            section(&mut module, 8, &runtime.bytes[range.clone()]);
+        }
+
+        let mut elements = ElementSection::new();
+        if !self.table.is_empty() {
+            let base = runtime.table.map_or(0, |table| table.initial as i32);
+            elements.active(None, &ConstExpr::i32_const(base), Elements::Functions(self.table.as_slice().into()));
+        }
+        if runtime.section(9).is_some() || !elements.is_empty() {
+            section(&mut module, 9, &merge(runtime.section(9), &elements.encoded())?);
+        }
+        let mut data_section = DataSection::new();
+        data_section.active(0, &ConstExpr::i32_const(self.data_base as i32), data);
+        if let Some(count) = runtime.data_count {
+            let mut data_count = Vec::new();
+            (count + 1).encode(&mut data_count);
+            section(&mut module, 12, &data_count);
+        }
+        section(&mut module, 10, &merge(runtime.section(10), &counted(self.functions.len(), &code))?);
+        section(&mut module, 11, &merge(runtime.section(11), &data_section.encoded())?);
+        Ok(module)
+    }
+}
+
+fn section(module: &mut Vec<u8>, id: u8, contents: &[u8]) {
+    module.push(id);
+    contents.encode(module);
+}
+
+/// A section's encoding without its size prefix: the item count and items
+trait Encoded {
+    fn encoded(&self) -> Vec<u8>;
+}
+
+impl<T: wasm_encoder::Section> Encoded for T {
+    fn encoded(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        self.encode(&mut bytes);
+        let (_, size_len) = read_leb(&bytes).expect("sections start with their size");
+        bytes.split_off(size_len)
+    }
+}
+
+fn counted(count: usize, items: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    (count as u32).encode(&mut bytes);
+    bytes.extend_from_slice(items);
+    bytes
+}
+
+/// The contents of a vector section holding the runtime's items, then ours
+fn merge(runtime: Option<&[u8]>, ours: &[u8]) -> Result<Vec<u8>> {
+    let (count, count_len) = read_leb(ours)?;
+    let Some(runtime) = runtime else {
+        return Ok(ours.to_vec());
+    };
+    let (runtime_count, runtime_count_len) = read_leb(runtime)?;
+    let mut merged = counted((runtime_count + count) as usize, &runtime[runtime_count_len..]);
+    merged.extend_from_slice(&ours[count_len..]);
+    Ok(merged)
+}
+
+/// An unsigned LEB128 and its length in bytes
+fn read_leb(bytes: &[u8]) -> Result<(u32, usize)> {
+    let mut value = 0u32;
+    for (i, &byte) in bytes.iter().enumerate().take(5) {
+        value |= ((byte & 0x7f) as u32) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Ok((value, i + 1));
+        }
+    }
+    Err(anyhow!("malformed LEB128"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wasm::tests::counting_module;
+    use wasm_encoder::{
+        CodeSection, EntityType, ExportKind, ExportSection, Function, FunctionSection, ImportSection, Instruction,
+        MemorySection, MemoryType, Module, TypeSection,
+    };
+
+    /// A runtime exporting `js_print(i64)` and `js_wasi_exit(i32)`, imported from the host
+    fn runtime() -> Vec<u8> {
+        let mut module = Module::new();
+        let mut types = TypeSection::new();
+        types.ty().function([wasm_encoder::ValType::I64], []);
+        types.ty().function([wasm_encoder::ValType::I32], []);
+        module.section(&types);
+        let mut imports = ImportSection::new();
+        imports.import("env", "print", EntityType::Function(0));
+        imports.import("env", "exit", EntityType::Function(1));
+        module.section(&imports);
+        let mut functions = FunctionSection::new();
+        functions.function(0);
+        functions.function(1);
+        module.section(&functions);
+        let mut memories = MemorySection::new();
+        memories.memory(MemoryType { minimum: 1, maximum: None, memory64: false, shared: false, page_size_log2: None });
+        module.section(&memories);
+        let mut exports = ExportSection::new();
+        exports.export("memory", ExportKind::Memory, 0);
+        exports.export("js_print", ExportKind::Func, 2);
+        exports.export("js_wasi_exit", ExportKind::Func, 3);
+        module.section(&exports);
+        let mut code = CodeSection::new();
+        for import in [0, 1] {
+            let mut body = Function::new([]);
+            body.instruction(&Instruction::LocalGet(0)).instruction(&Instruction::Call(import)).instruction(&Instruction::End);
+            code.function(&body);
+        }
+        module.section(&code);
+        module.finish()
+    }
+
+    #[test]
+    fn test_link_produces_a_valid_command() {
+        let wasm = link(&runtime(), &[counting_module("js_print")]).unwrap();
+        wasmparser::Validator::new().validate_all(&wasm).unwrap();
+        let mut exports = Vec::new();
+        for payload in Parser::new(0).parse_all(&wasm) {
+            if let Payload::ExportSection(reader) = payload.unwrap() {
+                exports.extend(reader.into_iter().map(|export| export.unwrap().name.to_string()));
+            }
+        }
+        assert!(exports.contains(&"_start".to_string()));
+        assert!(exports.contains(&"memory".to_string()));
+    }
+
+    #[test]
+    fn test_link_reports_what_the_runtime_lacks() {
+        let error = link(&runtime(), &[counting_module("js_fs_watch")]).unwrap_err();
+        assert_eq!(error.to_string(), "perry-runtime-wasi doesn't provide js_fs_watch");
+        let error = link(&runtime(), &[counting_module("js_print"), counting_module("js_print")]).unwrap_err();
+        assert_eq!(error.to_string(), "main is defined by more than one module");
+    }
+}
@@ -49,6 +49,18 @@ pub enum DiagnosticCode {
     ImplicitAny,
     /// Unsupported type construct
     UnsupportedType,
+    /// Required property missing from an object literal
+    MissingProperty,
+    /// Object literal property not declared by the target type
+    ExcessProperty,
+    /// Write to a readonly property or `as const` object
+    ReadonlyAssignment,
+    /// `switch` over a discriminated union that misses a member and has no `default`
+    NonExhaustiveSwitch,
+    /// Type argument does not satisfy its type parameter's `extends` constraint
+    ConstraintViolation,
+    /// Property read or written that the object's declared type literal doesn't have
+    UnknownProperty,
 
     // Unsupported features (U001-U099)
     /// Unsupported binary operator
@@ -69,6 +81,8 @@ pub enum DiagnosticCode {
     UnsupportedAssignmentTarget,
     /// Unsupported callee type
     UnsupportedCalleeType,
+    /// Statement, declaration or class member lowering leaves out of the program
+    DroppedConstruct,
 
     // Dynamic code (D001-D099)
     /// Dynamic property access with non-constant key
@@ -90,6 +104,16 @@ pub enum DiagnosticCode {
     /// Non-deterministic code patterns
     NonDeterministicCode,
 
+    // Performance lints (F001-F099), reported by `perry check --perf`
+    /// `await` inside a loop over an array serializes independent work
+    AwaitInLoop,
+    /// Synchronous blocking work inside an HTTP handler or hook
+    BlockingInHandler,
+
+    // Security lints (S001-S099)
+    /// SQL text built by concatenation or interpolation passed to a query
+    SqlConcatenation,
+
     // Resolution errors (R001-R099)
     /// Undefined variable reference
     UndefinedVariable,
@@ -116,6 +140,12 @@ impl DiagnosticCode {
             Self::AnyTypeUsage => "T003",
             Self::ImplicitAny => "T004",
             Self::UnsupportedType => "T005",
+            Self::MissingProperty => "T006",
+            Self::ExcessProperty => "T007",
+            Self::ReadonlyAssignment => "T008",
+            Self::NonExhaustiveSwitch => "T009",
+            Self::ConstraintViolation => "T010",
+            Self::UnknownProperty => "T011",
 
             // Unsupported features
             Self::UnsupportedBinaryOp => "U001",
@@ -127,6 +157,7 @@ impl DiagnosticCode {
             Self::UnsupportedPropertyKey => "U007",
             Self::UnsupportedAssignmentTarget => "U008",
             Self::UnsupportedCalleeType => "U009",
+            Self::DroppedConstruct => "U010",
 
             // Dynamic code
             Self::DynamicPropertyAccess => "D001",
@@ -140,6 +171,13 @@ impl DiagnosticCode {
             Self::LooseEquality => "C002",
             Self::NonDeterministicCode => "C003",
 
+            // Performance lints
+            Self::AwaitInLoop => "F001",
+            Self::BlockingInHandler => "F002",
+
+            // Security lints
+            Self::SqlConcatenation => "S001",
+
             // Resolution errors
             Self::UndefinedVariable => "R001",
             Self::UndefinedFunction => "R002",
@@ -156,6 +194,11 @@ impl DiagnosticCode {
             // Errors
             Self::ParseError
             | Self::TypeMismatch
+            | Self::MissingProperty
+            | Self::ExcessProperty
+            | Self::ReadonlyAssignment
+            | Self::ConstraintViolation
+            | Self::UnknownProperty
             | Self::UnsupportedBinaryOp
             | Self::UnsupportedUnaryOp
             | Self::UnsupportedExpression
@@ -165,6 +208,7 @@ impl DiagnosticCode {
             | Self::UnsupportedPropertyKey
             | Self::UnsupportedAssignmentTarget
             | Self::UnsupportedCalleeType
+            | Self::DroppedConstruct
             | Self::EvalUsage
             | Self::NewFunctionUsage
             | Self::UndefinedVariable
@@ -176,12 +220,16 @@ impl DiagnosticCode {
             Self::AnyTypeUsage
             | Self::ImplicitAny
             | Self::UnsupportedType
+            | Self::NonExhaustiveSwitch
             | Self::DynamicPropertyAccess
             | Self::ReflectionUsage
             | Self::DynamicImport
             | Self::ImplicitCoercion
             | Self::LooseEquality
-            | Self::NonDeterministicCode => Severity::Warning,
+            | Self::NonDeterministicCode
+            | Self::AwaitInLoop
+            | Self::BlockingInHandler
+            | Self::SqlConcatenation => Severity::Warning,
 
             // Hints
             Self::MissingTypeAnnotation => Severity::Hint,
@@ -196,7 +244,7 @@ impl std::fmt::Display for DiagnosticCode {
 }
 
 /// How applicable a suggested fix is.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Applicability {
     /// Can be applied automatically with high confidence
     MachineApplicable,
@@ -207,7 +255,7 @@ pub enum Applicability {
 }
 
 /// A suggested fix for a diagnostic.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Suggestion {
     /// Description of what this fix does
     pub message: String,
@@ -242,7 +290,7 @@ impl Suggestion {
 }
 
 /// Related information for a diagnostic.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RelatedInfo {
     /// Location of related information
     pub span: Span,
@@ -251,7 +299,7 @@ pub struct RelatedInfo {
 }
 
 /// A compiler diagnostic with rich information.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Diagnostic {
     /// Unique error code
     pub code: DiagnosticCode,
@@ -389,7 +437,7 @@ impl DiagnosticBuilder {
 }
 
 /// Collection of diagnostics with summary statistics.
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Diagnostics {
     /// All diagnostics
     pub items: Vec<Diagnostic>,
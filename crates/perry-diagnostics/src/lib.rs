@@ -4,7 +4,7 @@
 //! - Source location tracking (file, line, column)
 //! - Rich diagnostic types with error codes
 //! - Multiple output formats (terminal, JSON, simple text)
-//! - Suggestions for fixes
+//! - Suggestions for fixes, and "did you mean" names for typos
 //!
 //! # Example
 //!
@@ -35,6 +35,7 @@ pub mod diagnostic;
 pub mod emitter;
 pub mod source_cache;
 pub mod span;
+pub mod suggest;
 
 // Re-export commonly used types
 pub use diagnostic::{
@@ -44,3 +45,4 @@ pub use diagnostic::{
 pub use emitter::{DiagnosticEmitter, JsonEmitter, SimpleEmitter, TerminalEmitter};
 pub use source_cache::{SourceCache, SourceFile};
 pub use span::{FileId, Label, LabelStyle, Location, Span};
+pub use suggest::closest_name;
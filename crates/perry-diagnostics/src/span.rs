@@ -80,7 +80,7 @@ impl Default for Span {
 }
 
 /// Resolved location with file path, line, and column.
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Location {
     /// File path
     pub file: String,
@@ -97,7 +97,7 @@ impl std::fmt::Display for Location {
 }
 
 /// A labeled span for multi-span diagnostics.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Label {
     /// The span to highlight
     pub span: Span,
@@ -128,7 +128,7 @@ impl Label {
 }
 
 /// Style for diagnostic labels.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum LabelStyle {
     /// Primary label - the main error location (typically red underline)
     Primary,
@@ -0,0 +1,56 @@
+//! "Did you mean" suggestions for misspelled names.
+
+/// The candidate closest to `name` by edit distance, if it is close enough to be a typo:
+/// at most one edit per three characters of `name`.
+pub fn closest_name<'n>(name: &str, candidates: &[&'n str]) -> Option<&'n str> {
+    let max_distance = (name.len() / 3).max(1);
+    candidates.iter()
+        .map(|c| (*c, edit_distance(name, c)))
+        .filter(|(_, d)| *d <= max_distance)
+        .min_by_key(|(_, d)| *d)
+        .map(|(c, _)| c)
+}
+
+/// Edit distance counting an adjacent transposition (`hsot` → `host`) as one edit.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut d = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in d[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+            d[i][j] = (d[i - 1][j] + 1).min(d[i][j - 1] + 1).min(d[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+    d[a.len()][b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("host", "host"), 0);
+        assert_eq!(edit_distance("hsot", "host"), 1);
+        assert_eq!(edit_distance("port", "ports"), 1);
+        assert_eq!(edit_distance("", "abc"), 3);
+    }
+
+    #[test]
+    fn test_closest_name() {
+        let candidates = ["host", "port", "timeout"];
+        assert_eq!(closest_name("hots", &candidates), Some("host"));
+        assert_eq!(closest_name("timeuot", &candidates), Some("timeout"));
+        assert_eq!(closest_name("user", &candidates), None);
+    }
+}
@@ -0,0 +1,389 @@
+//! Cross-module constant folding
+//!
+//! Modules are lowered one at a time, so a module importing an enum or a constant
+//! only sees an `ExternFuncRef` to the exporter's global. Once every module is lowered,
+//! the compiler collects each module's `exported_constants` and this pass replaces reads
+//! of imported constants (`Color.Red`, `LIMITS.max`, `SCALE`) with their literal values,
+//! and imported enum names used as annotations (`c: Color`) with the enum's value type.
+
+use std::collections::HashMap;
+
+use perry_types::Type;
+
+use crate::ir::*;
+
+/// Fold reads of constants imported from other native modules into literals
+///
+/// # Arguments
+/// * `module` - The HIR module to transform
+/// * `exported_constants` - Map from (resolved_path, export_name) to the constant's value
+pub fn fold_imported_constants(
+    module: &mut Module,
+    exported_constants: &HashMap<(String, String), ConstValue>,
+) {
+    let mut folder = ConstFolder::default();
+
+    for import in &module.imports {
+        if import.module_kind != ModuleKind::NativeCompiled {
+            continue;
+        }
+        let resolved_path = match &import.resolved_path {
+            Some(p) => p.clone(),
+            None => continue,
+        };
+        for spec in &import.specifiers {
+            // Imported bindings are referenced by their exported name (ExternFuncRef),
+            // types by the local name
+            let ImportSpecifier::Named { imported, local } = spec else { continue };
+            let Some(value) = exported_constants.get(&(resolved_path.clone(), imported.clone())) else { continue };
+            if let ConstValue::Enum(members) = value {
+                if let Some(ty) = enum_value_type(members) {
+                    folder.enum_types.insert(local.clone(), ty);
+                }
+            }
+            folder.values.insert(imported.clone(), value.clone());
+        }
+    }
+
+    if folder.values.is_empty() {
+        return;
+    }
+
+    folder.fold_stmts(&mut module.init);
+    for func in &mut module.functions {
+        folder.fold_function(func);
+    }
+    for class in &mut module.classes {
+        for field in class.fields.iter_mut().chain(class.static_fields.iter_mut()) {
+            folder.fold_type(&mut field.ty);
+            if let Some(init) = &mut field.init {
+                folder.fold_expr(init);
+            }
+        }
+        if let Some(ctor) = &mut class.constructor {
+            folder.fold_function(ctor);
+        }
+        for method in class.methods.iter_mut().chain(class.static_methods.iter_mut()) {
+            folder.fold_function(method);
+        }
+        for (_, accessor) in class.getters.iter_mut().chain(class.setters.iter_mut()) {
+            folder.fold_function(accessor);
+        }
+    }
+}
+
+/// Type of an enum's values: `number` or `string`, `None` for mixed enums
+fn enum_value_type(members: &[EnumMember]) -> Option<Type> {
+    if members.iter().all(|m| matches!(m.value, EnumValue::Number(_))) {
+        Some(Type::Number)
+    } else if members.iter().all(|m| matches!(m.value, EnumValue::String(_))) {
+        Some(Type::String)
+    } else {
+        None
+    }
+}
+
+#[derive(Default)]
+struct ConstFolder {
+    /// Imported constants by exported name
+    values: HashMap<String, ConstValue>,
+    /// Value types of imported enums by local name
+    enum_types: HashMap<String, Type>,
+}
+
+impl ConstFolder {
+    /// Constant an expression refers to: an imported binding or a property/member of one
+    fn resolve(&self, expr: &Expr) -> Option<ConstValue> {
+        match expr {
+            Expr::ExternFuncRef { name, .. } => self.values.get(name).cloned(),
+            Expr::PropertyGet { object, property } => self.resolve(object)?.member(property),
+            Expr::IndexGet { object, index } => match index.as_ref() {
+                Expr::String(key) => self.resolve(object)?.member(key),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Replace `expr` with a literal if it reads a primitive constant
+    fn fold_read(&self, expr: &mut Expr) -> bool {
+        let literal = match self.resolve(expr) {
+            Some(ConstValue::Number(n)) => Expr::Number(n),
+            Some(ConstValue::String(s)) => Expr::String(s),
+            Some(ConstValue::Bool(b)) => Expr::Bool(b),
+            _ => return false,
+        };
+        *expr = literal;
+        true
+    }
+
+    fn fold_type(&self, ty: &mut Type) {
+        match ty {
+            Type::Named(name) => {
+                if let Some(value_ty) = self.enum_types.get(name) {
+                    *ty = value_ty.clone();
+                }
+            }
+            Type::Array(inner) | Type::Promise(inner) => self.fold_type(inner),
+            Type::Union(members) | Type::Intersection(members) | Type::Tuple(members) => {
+                for member in members {
+                    self.fold_type(member);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn fold_function(&self, func: &mut Function) {
+        for param in &mut func.params {
+            self.fold_type(&mut param.ty);
+            if let Some(default) = &mut param.default {
+                self.fold_expr(default);
+            }
+        }
+        self.fold_type(&mut func.return_type);
+        self.fold_stmts(&mut func.body);
+    }
+
+    fn fold_stmts(&self, stmts: &mut [Stmt]) {
+        for stmt in stmts {
+            self.fold_stmt(stmt);
+        }
+    }
+
+    fn fold_stmt(&self, stmt: &mut Stmt) {
+        match stmt {
+            Stmt::Let { ty, init, .. } => {
+                self.fold_type(ty);
+                if let Some(e) = init {
+                    self.fold_expr(e);
+                }
+            }
+            Stmt::Expr(e) | Stmt::Throw(e) | Stmt::Return(Some(e)) => self.fold_expr(e),
+            Stmt::Return(None) | Stmt::Break | Stmt::Continue => {}
+            Stmt::If { condition, then_branch, else_branch } => {
+                self.fold_expr(condition);
+                self.fold_stmts(then_branch);
+                if let Some(else_stmts) = else_branch {
+                    self.fold_stmts(else_stmts);
+                }
+            }
+            Stmt::While { condition, body } => {
+                self.fold_expr(condition);
+                self.fold_stmts(body);
+            }
+            Stmt::For { init, condition, update, body } => {
+                if let Some(init_stmt) = init {
+                    self.fold_stmt(init_stmt);
+                }
+                if let Some(e) = condition {
+                    self.fold_expr(e);
+                }
+                if let Some(e) = update {
+                    self.fold_expr(e);
+                }
+                self.fold_stmts(body);
+            }
+            Stmt::Switch { discriminant, cases } => {
+                self.fold_expr(discriminant);
+                for case in cases {
+                    if let Some(test) = &mut case.test {
+                        self.fold_expr(test);
+                    }
+                    self.fold_stmts(&mut case.body);
+                }
+            }
+            Stmt::Try { body, catch, finally } => {
+                self.fold_stmts(body);
+                if let Some(catch_clause) = catch {
+                    self.fold_stmts(&mut catch_clause.body);
+                }
+                if let Some(finally_stmts) = finally {
+                    self.fold_stmts(finally_stmts);
+                }
+            }
+        }
+    }
+
+    fn fold_exprs(&self, exprs: &mut [Expr]) {
+        for e in exprs {
+            self.fold_expr(e);
+        }
+    }
+
+    fn fold_expr(&self, expr: &mut Expr) {
+        match expr {
+            Expr::ExternFuncRef { .. } | Expr::PropertyGet { .. } | Expr::IndexGet { .. } => {
+                if self.fold_read(expr) {
+                    return;
+                }
+                match expr {
+                    Expr::PropertyGet { object, .. } => self.fold_expr(object),
+                    Expr::IndexGet { object, index } => {
+                        self.fold_expr(object);
+                        self.fold_expr(index);
+                    }
+                    _ => {}
+                }
+            }
+            Expr::Binary { left, right, .. } | Expr::Compare { left, right, .. } |
+            Expr::Logical { left, right, .. } => {
+                self.fold_expr(left);
+                self.fold_expr(right);
+            }
+            Expr::Unary { operand, .. } => self.fold_expr(operand),
+            Expr::Conditional { condition, then_expr, else_expr } => {
+                self.fold_expr(condition);
+                self.fold_expr(then_expr);
+                self.fold_expr(else_expr);
+            }
+            Expr::LocalSet(_, value) | Expr::GlobalSet(_, value) => self.fold_expr(value),
+            Expr::Call { callee, args, .. } => {
+                self.fold_expr(callee);
+                self.fold_exprs(args);
+            }
+            Expr::CallSpread { callee, args, .. } => {
+                self.fold_expr(callee);
+                for arg in args {
+                    match arg {
+                        CallArg::Expr(e) | CallArg::Spread(e) => self.fold_expr(e),
+                    }
+                }
+            }
+            Expr::New { args, .. } | Expr::SuperCall(args) | Expr::Array(args) |
+            Expr::StaticMethodCall { args, .. } | Expr::SuperMethodCall { args, .. } |
+            Expr::MathMin(args) | Expr::MathMax(args) | Expr::Sequence(args) => self.fold_exprs(args),
+            Expr::NewDynamic { callee, args } => {
+                self.fold_expr(callee);
+                self.fold_exprs(args);
+            }
+            Expr::NativeMethodCall { object, args, .. } => {
+                if let Some(obj) = object {
+                    self.fold_expr(obj);
+                }
+                self.fold_exprs(args);
+            }
+            Expr::ArraySpread(elements) => {
+                for elem in elements {
+                    match elem {
+                        ArrayElement::Expr(e) | ArrayElement::Spread(e) => self.fold_expr(e),
+                    }
+                }
+            }
+            Expr::Object(properties) => {
+                for (_, value) in properties {
+                    self.fold_expr(value);
+                }
+            }
+            // Writes keep their target; only the written value and computed keys fold
+            Expr::PropertySet { object, value, .. } => {
+                self.fold_expr(object);
+                self.fold_expr(value);
+            }
+            Expr::PropertyUpdate { object, .. } => self.fold_expr(object),
+            Expr::IndexSet { object, index, value } => {
+                self.fold_expr(object);
+                self.fold_expr(index);
+                self.fold_expr(value);
+            }
+            Expr::IndexUpdate { object, index, .. } => {
+                self.fold_expr(object);
+                self.fold_expr(index);
+            }
+            Expr::StaticFieldSet { value, .. } => self.fold_expr(value),
+            Expr::Await(inner) | Expr::TypeOf(inner) | Expr::Delete(inner) |
+            Expr::JsonStringify(inner) | Expr::JsonCanonicalize(inner) => self.fold_expr(inner),
+            Expr::InstanceOf { expr: inner, .. } | Expr::Narrowed { value: inner, .. } => self.fold_expr(inner),
+            Expr::In { property, object } => {
+                self.fold_expr(property);
+                self.fold_expr(object);
+            }
+            Expr::Closure { params, return_type, body, .. } => {
+                for param in params {
+                    self.fold_type(&mut param.ty);
+                    if let Some(default) = &mut param.default {
+                        self.fold_expr(default);
+                    }
+                }
+                self.fold_type(return_type);
+                self.fold_stmts(body);
+            }
+            Expr::ArrayPush { value, .. } | Expr::ArrayUnshift { value, .. } => self.fold_expr(value),
+            Expr::ArrayIndexOf { array, value } | Expr::ArrayIncludes { array, value } => {
+                self.fold_expr(array);
+                self.fold_expr(value);
+            }
+            Expr::ArrayForEach { array, callback } | Expr::ArrayMap { array, callback } |
+            Expr::ArrayFilter { array, callback } | Expr::ArrayFind { array, callback } |
+            Expr::ArrayFindIndex { array, callback } => {
+                self.fold_expr(array);
+                self.fold_expr(callback);
+            }
+            Expr::ArrayReduce { array, callback, initial } => {
+                self.fold_expr(array);
+                self.fold_expr(callback);
+                if let Some(init) = initial {
+                    self.fold_expr(init);
+                }
+            }
+            Expr::MapSet { map, key, value } => {
+                self.fold_expr(map);
+                self.fold_expr(key);
+                self.fold_expr(value);
+            }
+            Expr::MapGet { map, key } | Expr::MapHas { map, key } | Expr::MapDelete { map, key } => {
+                self.fold_expr(map);
+                self.fold_expr(key);
+            }
+            Expr::SetAdd { value, .. } => self.fold_expr(value),
+            Expr::SetHas { set, value } | Expr::SetDelete { set, value } => {
+                self.fold_expr(set);
+                self.fold_expr(value);
+            }
+            // Other expressions rarely contain imported constants; they keep reading the
+            // exporter's global at runtime
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lower(source: &str) -> Module {
+        let ast_module = perry_parser::parse_typescript(source, "test.ts").unwrap();
+        crate::lower_module(&ast_module, "test.ts", "/tmp/test.ts").unwrap()
+    }
+
+    #[test]
+    fn test_fold_imported_constants() {
+        let defs = lower(
+            "export const enum Color { Red, Green = 5 }\n\
+             export const LIMITS = { max: 10 } as const;\n",
+        );
+        let exported: HashMap<(String, String), ConstValue> = defs.exported_constants.iter()
+            .map(|(name, value)| (("/tmp/defs.ts".to_string(), name.clone()), value.clone()))
+            .collect();
+
+        let mut module = lower(
+            "import { Color, LIMITS as L } from './defs';\n\
+             function f(c: Color): number { return c; }\n\
+             const g = Color.Green;\n\
+             f(L.max);\n",
+        );
+        for import in &mut module.imports {
+            import.resolved_path = Some("/tmp/defs.ts".to_string());
+            import.module_kind = ModuleKind::NativeCompiled;
+        }
+        fold_imported_constants(&mut module, &exported);
+
+        let func = module.functions.iter().find(|f| f.name == "f").unwrap();
+        assert_eq!(func.params[0].ty, Type::Number);
+        assert!(matches!(&module.init[0], Stmt::Let { init: Some(Expr::Number(n)), .. } if *n == 5.0));
+        match &module.init[1] {
+            Stmt::Expr(Expr::Call { args, .. }) => assert!(matches!(args[0], Expr::Number(n) if n == 10.0)),
+            other => panic!("unexpected statement: {:?}", other),
+        }
+    }
+}
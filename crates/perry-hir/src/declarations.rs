@@ -0,0 +1,257 @@
+//! Signatures from TypeScript declaration files
+//!
+//! Packages that run through the JS runtime have no TypeScript source for perry to lower,
+//! so their exports are untyped. Their `.d.ts` typings (the package's own `types` entry or
+//! `@types/<package>`) still describe them: this module reads the exported functions and
+//! classes out of a parsed declaration file, and lowering registers them as the signatures
+//! of the extern functions and classes a module imports from the package.
+//!
+//! Only the declaration file itself is read. Re-exports from other files
+//! (`export * from './lib'`) and namespace members aren't followed.
+
+use std::collections::BTreeMap;
+
+use perry_types::Type;
+use swc_ecma_ast as ast;
+
+use crate::lower::{extract_param_type_with_ctx, extract_ts_type};
+
+/// Signature of a declared function or method
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionSignature {
+    pub params: Vec<Type>,
+    pub return_type: Type,
+}
+
+/// Signature of a declared class
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ClassSignature {
+    pub constructor: Vec<Type>,
+    pub methods: BTreeMap<String, FunctionSignature>,
+    pub properties: BTreeMap<String, Type>,
+}
+
+/// The exports of one package's declaration file, keyed by export name
+/// (`default` for `export default` and `export =`)
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PackageDeclarations {
+    pub functions: BTreeMap<String, FunctionSignature>,
+    pub classes: BTreeMap<String, ClassSignature>,
+}
+
+impl PackageDeclarations {
+    pub fn is_empty(&self) -> bool {
+        self.functions.is_empty() && self.classes.is_empty()
+    }
+}
+
+/// Collect the exported function and class signatures of a parsed declaration file
+pub fn collect_declarations(module: &ast::Module) -> PackageDeclarations {
+    let mut collector = Collector::default();
+    collector.visit_items(&module.body);
+
+    let mut declarations = PackageDeclarations::default();
+    for (exported, local) in &collector.exports {
+        if let Some(sig) = collector.functions.get(local) {
+            declarations.functions.entry(exported.clone()).or_insert_with(|| sig.clone());
+        }
+        if let Some(sig) = collector.classes.get(local) {
+            declarations.classes.entry(exported.clone()).or_insert_with(|| sig.clone());
+        }
+    }
+    declarations
+}
+
+/// Declarations by local name, and the (exported, local) names of the exports
+#[derive(Default)]
+struct Collector {
+    functions: BTreeMap<String, FunctionSignature>,
+    classes: BTreeMap<String, ClassSignature>,
+    exports: Vec<(String, String)>,
+}
+
+impl Collector {
+    fn visit_items(&mut self, items: &[ast::ModuleItem]) {
+        for item in items {
+            match item {
+                ast::ModuleItem::Stmt(ast::Stmt::Decl(decl)) => self.visit_decl(decl, false),
+                ast::ModuleItem::ModuleDecl(ast::ModuleDecl::ExportDecl(export)) => self.visit_decl(&export.decl, true),
+                ast::ModuleItem::ModuleDecl(ast::ModuleDecl::ExportDefaultDecl(export)) => match &export.decl {
+                    ast::DefaultDecl::Fn(fn_expr) => {
+                        let local = fn_expr.ident.as_ref().map_or("default".to_string(), |id| id.sym.to_string());
+                        self.add_function(&local, &fn_expr.function);
+                        self.exports.push(("default".to_string(), local));
+                    }
+                    ast::DefaultDecl::Class(class_expr) => {
+                        let local = class_expr.ident.as_ref().map_or("default".to_string(), |id| id.sym.to_string());
+                        self.add_class(&local, &class_expr.class);
+                        self.exports.push(("default".to_string(), local));
+                    }
+                    ast::DefaultDecl::TsInterfaceDecl(_) => {}
+                },
+                // export default foo;
+                ast::ModuleItem::ModuleDecl(ast::ModuleDecl::ExportDefaultExpr(export)) => {
+                    if let ast::Expr::Ident(ident) = export.expr.as_ref() {
+                        self.exports.push(("default".to_string(), ident.sym.to_string()));
+                    }
+                }
+                // export = foo; (CommonJS typings)
+                ast::ModuleItem::ModuleDecl(ast::ModuleDecl::TsExportAssignment(export)) => {
+                    if let ast::Expr::Ident(ident) = export.expr.as_ref() {
+                        self.exports.push(("default".to_string(), ident.sym.to_string()));
+                    }
+                }
+                // export { foo, bar as baz };
+                ast::ModuleItem::ModuleDecl(ast::ModuleDecl::ExportNamed(named)) if named.src.is_none() => {
+                    for spec in &named.specifiers {
+                        if let ast::ExportSpecifier::Named(spec) = spec {
+                            let local = export_name(&spec.orig);
+                            let exported = spec.exported.as_ref().map_or_else(|| local.clone(), export_name);
+                            self.exports.push((exported, local));
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn visit_decl(&mut self, decl: &ast::Decl, exported: bool) {
+        match decl {
+            ast::Decl::Fn(fn_decl) => {
+                let name = fn_decl.ident.sym.to_string();
+                self.add_function(&name, &fn_decl.function);
+                if exported {
+                    self.exports.push((name.clone(), name));
+                }
+            }
+            ast::Decl::Class(class_decl) => {
+                let name = class_decl.ident.sym.to_string();
+                self.add_class(&name, &class_decl.class);
+                if exported {
+                    self.exports.push((name.clone(), name));
+                }
+            }
+            // declare module 'pkg' { ... } describes the package itself
+            ast::Decl::TsModule(module_decl) if matches!(module_decl.id, ast::TsModuleName::Str(_)) => {
+                if let Some(ast::TsNamespaceBody::TsModuleBlock(block)) = &module_decl.body {
+                    self.visit_items(&block.body);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Overloads keep the first signature
+    fn add_function(&mut self, name: &str, function: &ast::Function) {
+        self.functions.entry(name.to_string()).or_insert_with(|| function_signature(function));
+    }
+
+    fn add_class(&mut self, name: &str, class: &ast::Class) {
+        let mut sig = ClassSignature::default();
+        for member in &class.body {
+            match member {
+                ast::ClassMember::Constructor(ctor) if sig.constructor.is_empty() => {
+                    sig.constructor = ctor.params.iter().map(|param| match param {
+                        ast::ParamOrTsParamProp::Param(param) => extract_param_type_with_ctx(&param.pat, None),
+                        ast::ParamOrTsParamProp::TsParamProp(_) => Type::Any,
+                    }).collect();
+                }
+                ast::ClassMember::Method(method) if !method.is_static => {
+                    let Some(key) = prop_name(&method.key) else { continue };
+                    match method.kind {
+                        ast::MethodKind::Method => {
+                            sig.methods.entry(key).or_insert_with(|| function_signature(&method.function));
+                        }
+                        ast::MethodKind::Getter => {
+                            sig.properties.entry(key).or_insert_with(|| function_signature(&method.function).return_type);
+                        }
+                        ast::MethodKind::Setter => {}
+                    }
+                }
+                ast::ClassMember::ClassProp(prop) if !prop.is_static => {
+                    let Some(key) = prop_name(&prop.key) else { continue };
+                    let ty = prop.type_ann.as_ref().map_or(Type::Any, |ann| extract_ts_type(&ann.type_ann));
+                    sig.properties.entry(key).or_insert(ty);
+                }
+                _ => {}
+            }
+        }
+        self.classes.entry(name.to_string()).or_insert(sig);
+    }
+}
+
+/// A missing return type is `any` in a declaration file
+fn function_signature(function: &ast::Function) -> FunctionSignature {
+    FunctionSignature {
+        params: function.params.iter().map(|param| extract_param_type_with_ctx(&param.pat, None)).collect(),
+        return_type: function.return_type.as_ref().map_or(Type::Any, |rt| extract_ts_type(&rt.type_ann)),
+    }
+}
+
+fn prop_name(key: &ast::PropName) -> Option<String> {
+    match key {
+        ast::PropName::Ident(ident) => Some(ident.sym.to_string()),
+        ast::PropName::Str(s) => Some(s.value.as_str().unwrap_or("").to_string()),
+        _ => None,
+    }
+}
+
+fn export_name(name: &ast::ModuleExportName) -> String {
+    match name {
+        ast::ModuleExportName::Ident(ident) => ident.sym.to_string(),
+        ast::ModuleExportName::Str(s) => s.value.as_str().unwrap_or("").to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn declarations(source: &str) -> PackageDeclarations {
+        collect_declarations(&perry_parser::parse_declarations(source, "index.d.ts").unwrap())
+    }
+
+    #[test]
+    fn test_collect_exported_functions_and_classes() {
+        let decls = declarations(
+            "export declare function slugify(input: string, sep?: string): string;\n\
+             export declare function slugify(input: number): string;\n\
+             declare function helper(): number;\n\
+             export declare class Counter {\n\
+                 constructor(start: number);\n\
+                 readonly count: number;\n\
+                 increment(by: number): number;\n\
+                 get label(): string;\n\
+                 static create(): Counter;\n\
+             }\n",
+        );
+        let slugify = &decls.functions["slugify"];
+        assert_eq!(slugify.params, vec![Type::String, Type::String]);
+        assert_eq!(slugify.return_type, Type::String);
+        assert!(!decls.functions.contains_key("helper"));
+
+        let counter = &decls.classes["Counter"];
+        assert_eq!(counter.constructor, vec![Type::Number]);
+        assert_eq!(counter.methods["increment"].return_type, Type::Number);
+        assert!(!counter.methods.contains_key("create"));
+        assert_eq!(counter.properties["count"], Type::Number);
+        assert_eq!(counter.properties["label"], Type::String);
+    }
+
+    #[test]
+    fn test_collect_default_and_commonjs_exports() {
+        let decls = declarations("declare function pad(s: string, n: number): string;\nexport = pad;\n");
+        assert_eq!(decls.functions["default"].params, vec![Type::String, Type::Number]);
+
+        let decls = declarations(
+            "declare module 'left-pad' {\n\
+                 function leftPad(s: string, n: number): string;\n\
+                 export { leftPad as pad };\n\
+                 export default leftPad;\n\
+             }\n",
+        );
+        assert_eq!(decls.functions["pad"].return_type, Type::String);
+        assert_eq!(decls.functions["default"].return_type, Type::String);
+    }
+}
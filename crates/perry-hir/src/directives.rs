@@ -0,0 +1,109 @@
+//! File-level optimization directives
+//!
+//! A module can tune how it is compiled with `// @perry-opt:` line comments at the top
+//! of the file, before any code (a shebang, blank lines and other comments may come
+//! first). Each directive lists one or more settings, separated by commas or spaces:
+//!
+//! - `speed` - full optimization (the default)
+//! - `size` - optimize, preferring smaller code
+//! - `none` - skip optimization and inlining, for the fastest compile
+//! - `no-inline` / `inline` - turn inlining of the module's calls off or back on
+//! - `multiversion` / `no-multiversion` - also compile the module's numeric functions for
+//!   newer CPUs (x86-64-v3: AVX2, FMA, BMI), the version to run being picked at startup
+//!
+//! A level turns inlining on (`speed`, `size`) or off (`none`), and later settings win, so
+//! `// @perry-opt: none, inline` compiles fast but still inlines. Without directives a
+//! module gets the build's settings (`perry compile --opt-level`, perry.toml).
+
+use crate::ir::{OptLevel, OptSettings};
+
+const DIRECTIVE: &str = "@perry-opt";
+
+impl OptSettings {
+    /// Settings for a build-wide level; like the directive, `none` also skips inlining
+    pub fn with_level(level: OptLevel) -> Self {
+        Self { level, inline: level != OptLevel::None, multiversion: false }
+    }
+
+    /// Apply the `// @perry-opt:` directives at the top of a source file to `defaults`
+    /// (the build's settings). Fails with a message naming the setting when one isn't
+    /// recognized.
+    pub fn from_source(source: &str, defaults: OptSettings) -> Result<Self, String> {
+        let mut settings = defaults;
+        let mut in_block_comment = false;
+        for (index, line) in source.lines().enumerate() {
+            let line = line.trim();
+            if in_block_comment {
+                in_block_comment = !line.contains("*/");
+                continue;
+            }
+            if line.is_empty() || (index == 0 && line.starts_with("#!")) {
+                continue;
+            }
+            if line.starts_with("/*") {
+                in_block_comment = !line[2..].contains("*/");
+                continue;
+            }
+            let Some(comment) = line.strip_prefix("//") else { break };
+            let Some(rest) = comment.trim_start().strip_prefix(DIRECTIVE) else { continue };
+            let rest = rest.trim_start();
+            let rest = rest.strip_prefix(':').unwrap_or(rest);
+            for setting in rest.split(|c: char| c == ',' || c.is_whitespace()).filter(|s| !s.is_empty()) {
+                match setting {
+                    "speed" | "size" | "none" => {
+                        let level = match setting {
+                            "speed" => OptLevel::Speed,
+                            "size" => OptLevel::Size,
+                            _ => OptLevel::None,
+                        };
+                        // A level leaves multiversioning as it was
+                        settings = OptSettings { multiversion: settings.multiversion, ..OptSettings::with_level(level) };
+                    }
+                    "inline" => settings.inline = true,
+                    "no-inline" => settings.inline = false,
+                    "multiversion" => settings.multiversion = true,
+                    "no-multiversion" => settings.multiversion = false,
+                    other => {
+                        return Err(format!(
+                            "unknown {} setting `{}` (expected speed, size, none, inline, no-inline, multiversion or no-multiversion)",
+                            DIRECTIVE, other
+                        ))
+                    }
+                }
+            }
+        }
+        Ok(settings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_directives_in_file_header() {
+        let settings = OptSettings::from_source("#!/usr/bin/env perry\n/* Admin routes\n   rarely change */\n// @perry-opt: size\n// @perry-opt: no-inline\nimport x from './x';\n", OptSettings::default()).unwrap();
+        assert_eq!(settings, OptSettings { level: OptLevel::Size, inline: false, multiversion: false });
+        let settings = OptSettings::from_source("// @perry-opt: none, inline\n", OptSettings::default()).unwrap();
+        assert_eq!(settings, OptSettings { level: OptLevel::None, inline: true, multiversion: false });
+        // Without directives the build's settings apply
+        let build = OptSettings::with_level(OptLevel::None);
+        assert_eq!(OptSettings::from_source("const a = 1;\n", build).unwrap(), build);
+        assert_eq!(OptSettings::from_source("// @perry-opt: speed\n", build).unwrap(), OptSettings::default());
+    }
+
+    #[test]
+    fn test_directives_after_code_ignored() {
+        let settings = OptSettings::from_source("export const a = 1;\n// @perry-opt: none\n", OptSettings::default()).unwrap();
+        assert_eq!(settings, OptSettings::default());
+        assert!(OptSettings::from_source("// @perry-opt: fastest\n", OptSettings::default()).unwrap_err().contains("`fastest`"));
+    }
+
+    #[test]
+    fn test_multiversion_directive() {
+        let settings = OptSettings::from_source("// @perry-opt: multiversion, size\n", OptSettings::default()).unwrap();
+        assert_eq!(settings, OptSettings { level: OptLevel::Size, inline: true, multiversion: true });
+        let settings = OptSettings::from_source("// @perry-opt: multiversion\n// @perry-opt: no-multiversion\n", OptSettings::default()).unwrap();
+        assert!(!settings.multiversion);
+    }
+}
@@ -0,0 +1,790 @@
+//! Local type inference
+//!
+//! Un-annotated bindings are lowered with `Type::Any`, which codegen treats as a dynamic
+//! value: a NaN-boxed union that goes through runtime checks wherever it's used. Once
+//! monomorphization has made every type concrete, this pass gives such bindings the type of
+//! their initializer, so `const n = items.length * 2` compiles like `const n: number`.
+//!
+//! Types flow both ways:
+//! - bottom-up from initializers, including calls of functions whose return expressions all
+//!   have the same type (`function area(r: number) { return r * r * Math.PI; }`)
+//! - top-down into the callbacks of array methods, whose parameters take the element type
+//!   (`prices.map(p => p * 1.2)` types `p` as `number`, and the result as `number[]`)
+//!
+//! A reassigned binding keeps its type only if every assignment has that type too.
+//!
+//! Only types codegen represents the same way as the dynamic value they replace are written
+//! back: numbers, strings, bigints, and arrays built by literals and array methods. Booleans
+//! stay dynamic (a non-union local is printed as a number), and function signatures keep
+//! their declared types, since those determine the calling convention.
+
+use std::collections::{HashMap, HashSet};
+
+use perry_types::{FuncId, LocalId, Type};
+
+use crate::ir::*;
+
+/// Rounds before giving up on reaching a fixed point
+const MAX_ROUNDS: usize = 16;
+
+/// Scope of module-level bindings; functions and methods are numbered from 1
+const MODULE_SCOPE: usize = 0;
+
+/// Give un-annotated `let`/`const` bindings and array callback parameters the types of
+/// their initializers and array elements
+pub fn infer_local_types(module: &mut Module) {
+    let mut inference = LocalInference::default();
+    for func in &module.functions {
+        if func.is_async {
+            continue;
+        }
+        if let Some(ty) = inferable(&func.return_type) {
+            inference.declared_returns.insert(func.id, ty);
+        }
+    }
+
+    // Bindings depend on return types and vice versa, and a function can read module-level
+    // bindings declared after it, so walk the module until nothing changes
+    for _ in 0..MAX_ROUNDS {
+        let previous = (inference.types.clone(), inference.returns.clone());
+        inference.assignments.clear();
+        inference.inferred.clear();
+        inference.visit_module(module);
+        if (&inference.types, &inference.returns) != (&previous.0, &previous.1) {
+            continue;
+        }
+
+        let conflicting = inference.conflicting_assignments();
+        if conflicting.is_empty() {
+            inference.apply = true;
+            inference.visit_module(module);
+            return;
+        }
+        // Anything inferred so far may derive from the conflicting bindings
+        inference.blocked.extend(conflicting);
+        inference.types.clear();
+        inference.returns.clear();
+    }
+}
+
+/// Key of a binding: LocalIds are unique within a module, but specializations of a generic
+/// function share the ids of its body
+type BindingKey = (usize, LocalId);
+
+#[derive(Default)]
+struct LocalInference {
+    /// Known types of bindings, declared or inferred
+    types: HashMap<BindingKey, Type>,
+    /// Bindings declared so far, so an assignment resolves to the function's own binding
+    /// rather than a module-level one
+    declared: HashSet<BindingKey>,
+    /// Bindings whose type was inferred this round
+    inferred: HashSet<BindingKey>,
+    /// Bindings with an assignment of another type than their initializer's; they stay `Any`
+    blocked: HashSet<BindingKey>,
+    /// Type of every value assigned to a binding this round
+    assignments: Vec<(BindingKey, Option<Type>)>,
+    /// Declared return types of module functions
+    declared_returns: HashMap<FuncId, Type>,
+    /// Inferred return types of un-annotated module functions
+    returns: HashMap<FuncId, Type>,
+    /// Scope of the function being walked
+    scope: usize,
+    /// Whether to write inferred types back into the HIR (the final walk)
+    apply: bool,
+}
+
+/// The inference-relevant part of a type: primitives and arrays of them
+fn inferable(ty: &Type) -> Option<Type> {
+    match ty {
+        Type::Number | Type::Int32 => Some(Type::Number),
+        Type::String | Type::Boolean | Type::BigInt => Some(ty.clone()),
+        Type::Literal(lit) => Some(lit.widened()),
+        Type::Array(elem) => Some(Type::Array(Box::new(inferable(elem)?))),
+        _ => None,
+    }
+}
+
+/// Whether codegen represents a binding of type `ty` initialized with `init` the same way as
+/// the untyped binding, so writing the type back only drops the dynamic checks
+fn writable(ty: &Type, init: &Expr) -> bool {
+    match ty {
+        Type::Number | Type::String | Type::BigInt => true,
+        // Literals and array methods produce array pointers either way; other array-valued
+        // expressions (e.g. `split`) produce NaN-boxed arrays
+        Type::Array(elem) => {
+            matches!(elem.as_ref(), Type::Number | Type::String)
+                && matches!(init, Expr::Array(_) | Expr::ArrayMap { .. } | Expr::ArrayFilter { .. } | Expr::ArraySlice { .. })
+        }
+        _ => false,
+    }
+}
+
+/// Whether a statement list can't complete normally (fall off its end)
+fn ends_in_return(stmts: &[Stmt]) -> bool {
+    match stmts.last() {
+        Some(Stmt::Return(_) | Stmt::Throw(_)) => true,
+        Some(Stmt::If { then_branch, else_branch: Some(else_branch), .. }) => {
+            ends_in_return(then_branch) && ends_in_return(else_branch)
+        }
+        Some(Stmt::Try { body, catch, finally }) => {
+            let catch_returns = match catch {
+                Some(catch) => ends_in_return(&catch.body),
+                None => true,
+            };
+            finally.as_deref().is_some_and(ends_in_return) || (ends_in_return(body) && catch_returns)
+        }
+        _ => false,
+    }
+}
+
+/// Return statements of a body, not counting those of nested closures
+fn collect_returns<'a>(stmts: &'a [Stmt], returns: &mut Vec<Option<&'a Expr>>) {
+    for stmt in stmts {
+        match stmt {
+            Stmt::Return(value) => returns.push(value.as_ref()),
+            Stmt::If { then_branch, else_branch, .. } => {
+                collect_returns(then_branch, returns);
+                if let Some(else_branch) = else_branch {
+                    collect_returns(else_branch, returns);
+                }
+            }
+            Stmt::While { body, .. } | Stmt::For { body, .. } => collect_returns(body, returns),
+            Stmt::Try { body, catch, finally } => {
+                collect_returns(body, returns);
+                if let Some(catch) = catch {
+                    collect_returns(&catch.body, returns);
+                }
+                if let Some(finally) = finally {
+                    collect_returns(finally, returns);
+                }
+            }
+            Stmt::Switch { cases, .. } => {
+                for case in cases {
+                    collect_returns(&case.body, returns);
+                }
+            }
+            Stmt::Let { .. } | Stmt::Expr(_) | Stmt::Throw(_) | Stmt::Break | Stmt::Continue => {}
+        }
+    }
+}
+
+/// Result type of calling `method` on a value of type `receiver`
+fn method_type(receiver: &Type, method: &str) -> Option<Type> {
+    match (receiver, method) {
+        (Type::String, "toUpperCase" | "toLowerCase" | "toLocaleUpperCase" | "toLocaleLowerCase" | "trim" | "trimStart" | "trimEnd" | "slice" | "substring"
+            | "substr" | "charAt" | "padStart" | "padEnd" | "repeat" | "replace" | "replaceAll" | "concat"
+            | "normalize" | "toString") => Some(Type::String),
+        (Type::String, "indexOf" | "lastIndexOf" | "charCodeAt" | "localeCompare" | "search") => Some(Type::Number),
+        (Type::String, "includes" | "startsWith" | "endsWith") => Some(Type::Boolean),
+        (Type::String, "split") => Some(Type::Array(Box::new(Type::String))),
+        (Type::Array(_), "indexOf" | "lastIndexOf" | "findIndex" | "findLastIndex") => Some(Type::Number),
+        (Type::Array(_), "includes" | "some" | "every") => Some(Type::Boolean),
+        (Type::Array(_), "join") => Some(Type::String),
+        (Type::Array(_), "slice" | "filter" | "reverse" | "sort" | "toSorted" | "toReversed") => Some(receiver.clone()),
+        (Type::Number, "toFixed" | "toPrecision" | "toExponential" | "toString") => Some(Type::String),
+        _ => None,
+    }
+}
+
+impl LocalInference {
+    fn visit_module(&mut self, module: &mut Module) {
+        self.scope = MODULE_SCOPE;
+        self.visit_stmts(&mut module.init);
+
+        let mut scope = MODULE_SCOPE;
+        for func in &mut module.functions {
+            scope += 1;
+            self.scope = scope;
+            self.visit_function(func, true);
+        }
+        // Method calls aren't resolved, so methods only get their bindings inferred
+        for class in &mut module.classes {
+            let accessors = class.getters.iter_mut().chain(class.setters.iter_mut()).map(|(_, f)| f);
+            let methods = class.constructor.iter_mut()
+                .chain(class.methods.iter_mut())
+                .chain(class.static_methods.iter_mut())
+                .chain(accessors);
+            for method in methods {
+                scope += 1;
+                self.scope = scope;
+                self.visit_function(method, false);
+            }
+        }
+    }
+
+    fn visit_function(&mut self, func: &mut Function, infer_return: bool) {
+        self.bind_params(&mut func.params);
+        self.visit_stmts(&mut func.body);
+        if infer_return && matches!(func.return_type, Type::Any) && !func.is_async && func.type_params.is_empty() {
+            match self.body_return_type(&func.body) {
+                Some(ty) => self.returns.insert(func.id, ty),
+                None => self.returns.remove(&func.id),
+            };
+        }
+    }
+
+    /// Bind parameters to their declared types
+    fn bind_params(&mut self, params: &mut [Param]) {
+        for param in params {
+            if let Some(default) = &mut param.default {
+                self.visit_expr(default);
+            }
+            let key = (self.scope, param.id);
+            self.declared.insert(key);
+            match inferable(&param.ty) {
+                Some(ty) => self.types.insert(key, ty),
+                None => self.types.remove(&key),
+            };
+        }
+    }
+
+    fn lookup(&self, id: LocalId) -> Option<Type> {
+        self.types.get(&(self.scope, id))
+            .or_else(|| self.types.get(&(MODULE_SCOPE, id)))
+            .cloned()
+    }
+
+    /// The binding an assignment to `id` in the current scope writes
+    fn resolve(&self, id: LocalId) -> BindingKey {
+        if self.declared.contains(&(self.scope, id)) {
+            (self.scope, id)
+        } else {
+            (MODULE_SCOPE, id)
+        }
+    }
+
+    /// Record an inferred binding type, or forget a stale one
+    fn infer_binding(&mut self, key: BindingKey, ty: Option<Type>) -> Option<Type> {
+        match ty.filter(|_| !self.blocked.contains(&key)) {
+            Some(ty) => {
+                self.types.insert(key, ty.clone());
+                self.inferred.insert(key);
+                Some(ty)
+            }
+            None => {
+                self.types.remove(&key);
+                None
+            }
+        }
+    }
+
+    /// Inferred bindings assigned a value of another (or unknown) type
+    fn conflicting_assignments(&self) -> HashSet<BindingKey> {
+        self.assignments.iter()
+            .filter(|(key, ty)| self.inferred.contains(key) && self.types.get(key) != ty.as_ref())
+            .map(|(key, _)| *key)
+            .collect()
+    }
+
+    /// Type every return in `body` agrees on, if the body can't fall off its end
+    fn body_return_type(&self, body: &[Stmt]) -> Option<Type> {
+        if !ends_in_return(body) {
+            return None;
+        }
+        let mut returns = Vec::new();
+        collect_returns(body, &mut returns);
+        let mut types = returns.into_iter().map(|value| value.and_then(|v| self.expr_type(v)));
+        let first = types.next()??;
+        types.all(|ty| ty.as_ref() == Some(&first)).then_some(first)
+    }
+
+    /// Element type of an array-valued expression
+    fn element_type(&self, array: &Expr) -> Option<Type> {
+        match self.expr_type(array)? {
+            Type::Array(elem) => Some(*elem),
+            _ => None,
+        }
+    }
+
+    fn callback_return_type(&self, callback: &Expr) -> Option<Type> {
+        match callback {
+            Expr::Closure { body, is_async: false, .. } => self.body_return_type(body),
+            _ => None,
+        }
+    }
+
+    /// Whether codegen compiles `+` with this operand as a string concatenation; it only
+    /// looks at literals, string locals and nested concatenations
+    fn is_string_operand(&self, expr: &Expr) -> bool {
+        match expr {
+            Expr::String(_) | Expr::StringFromCharCode(_) => true,
+            Expr::LocalGet(id) => self.lookup(*id) == Some(Type::String),
+            Expr::Binary { op: BinaryOp::Add, left, right } => self.is_string_operand(left) || self.is_string_operand(right),
+            Expr::Conditional { then_expr, else_expr, .. } => {
+                self.is_string_operand(then_expr) && self.is_string_operand(else_expr)
+            }
+            _ => false,
+        }
+    }
+
+    fn binary_type(&self, op: BinaryOp, left: &Expr, right: &Expr) -> Option<Type> {
+        let (l, r) = (self.expr_type(left), self.expr_type(right));
+        match op {
+            BinaryOp::Add if self.is_string_operand(left) || self.is_string_operand(right) => Some(Type::String),
+            BinaryOp::Add => match (l?, r?) {
+                (Type::Number, Type::Number) => Some(Type::Number),
+                (Type::BigInt, Type::BigInt) => Some(Type::BigInt),
+                _ => None,
+            },
+            BinaryOp::UShr => Some(Type::Number),
+            // Mixing a bigint with anything else throws, so one number operand decides it
+            _ => match (l, r) {
+                (Some(Type::BigInt), Some(Type::BigInt)) => Some(Type::BigInt),
+                (Some(Type::Number), _) | (_, Some(Type::Number)) => Some(Type::Number),
+                _ => None,
+            },
+        }
+    }
+
+    fn call_type(&self, callee: &Expr, args: &[Expr]) -> Option<Type> {
+        match callee {
+            Expr::FuncRef(id) => self.declared_returns.get(id).or_else(|| self.returns.get(id)).cloned(),
+            Expr::ExternFuncRef { return_type, .. } => inferable(return_type),
+            Expr::PropertyGet { object, property } => {
+                let receiver = self.expr_type(object)?;
+                match (&receiver, property.as_str()) {
+                    (Type::Array(_), "map") => {
+                        let ret = self.callback_return_type(args.first()?)?;
+                        Some(Type::Array(Box::new(ret)))
+                    }
+                    (Type::Array(_), "reduce") => self.reduce_type(args.first()?, args.get(1)),
+                    _ => method_type(&receiver, property),
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// `arr.reduce(callback, initial)` has the initial value's type when the callback keeps it
+    fn reduce_type(&self, callback: &Expr, initial: Option<&Expr>) -> Option<Type> {
+        let acc = self.expr_type(initial?)?;
+        (self.callback_return_type(callback)? == acc).then_some(acc)
+    }
+
+    /// Static type of an expression, if inference knows it
+    fn expr_type(&self, expr: &Expr) -> Option<Type> {
+        match expr {
+            Expr::Number(_) | Expr::Integer(_) => Some(Type::Number),
+            Expr::String(_) => Some(Type::String),
+            Expr::Bool(_) => Some(Type::Boolean),
+            Expr::BigInt(_) => Some(Type::BigInt),
+            Expr::LocalGet(id) => self.lookup(*id),
+            Expr::LocalSet(_, value) => self.expr_type(value),
+            Expr::Update { id, .. } => (self.lookup(*id) == Some(Type::Number)).then_some(Type::Number),
+            Expr::Binary { op, left, right } => self.binary_type(*op, left, right),
+            Expr::Unary { op, operand } => match op {
+                UnaryOp::Not => Some(Type::Boolean),
+                UnaryOp::Pos => Some(Type::Number),
+                UnaryOp::Neg | UnaryOp::BitNot => match self.expr_type(operand)? {
+                    Type::Number => Some(Type::Number),
+                    Type::BigInt => Some(Type::BigInt),
+                    _ => None,
+                },
+            },
+            Expr::Compare { .. } | Expr::InstanceOf { .. } | Expr::In { .. } => Some(Type::Boolean),
+            Expr::TypeOf(_) => Some(Type::String),
+            Expr::Logical { left, right, .. } => {
+                let ty = self.expr_type(left)?;
+                (self.expr_type(right)? == ty).then_some(ty)
+            }
+            Expr::Conditional { then_expr, else_expr, .. } => {
+                let ty = self.expr_type(then_expr)?;
+                (self.expr_type(else_expr)? == ty).then_some(ty)
+            }
+            Expr::Narrowed { ty, .. } => inferable(ty),
+            Expr::Sequence(exprs) => self.expr_type(exprs.last()?),
+            Expr::Call { callee, args, .. } => self.call_type(callee, args),
+            Expr::PropertyGet { object, property } if property == "length" => {
+                matches!(self.expr_type(object)?, Type::String | Type::Array(_)).then_some(Type::Number)
+            }
+            Expr::Array(elements) => {
+                let (first, rest) = elements.split_first()?;
+                let ty = self.expr_type(first)?;
+                rest.iter()
+                    .all(|e| self.expr_type(e).as_ref() == Some(&ty))
+                    .then(|| Type::Array(Box::new(ty)))
+            }
+            Expr::ArrayMap { callback, .. } => Some(Type::Array(Box::new(self.callback_return_type(callback)?))),
+            Expr::ArrayFilter { array, .. } | Expr::ArraySlice { array, .. } => {
+                self.expr_type(array).filter(|ty| matches!(ty, Type::Array(_)))
+            }
+            Expr::ArrayReduce { callback, initial, .. } => self.reduce_type(callback, initial.as_deref()),
+            Expr::ArrayIndexOf { .. } | Expr::ArrayFindIndex { .. } => Some(Type::Number),
+            Expr::ArrayIncludes { .. } | Expr::MapHas { .. } | Expr::SetHas { .. } => Some(Type::Boolean),
+            Expr::ArrayJoin { .. } | Expr::StringFromCharCode(_) | Expr::DateToISOString(_) => Some(Type::String),
+            Expr::StringSplit(..) => Some(Type::Array(Box::new(Type::String))),
+            Expr::MathFloor(_) | Expr::MathCeil(_) | Expr::MathRound(_) | Expr::MathAbs(_) | Expr::MathSqrt(_) |
+            Expr::MathPow(..) | Expr::MathMin(_) | Expr::MathMax(_) | Expr::MathRandom |
+            Expr::DateNow | Expr::DateGetTime(_) | Expr::DateGetFullYear(_) | Expr::DateGetMonth(_) |
+            Expr::DateGetDate(_) | Expr::DateGetHours(_) | Expr::DateGetMinutes(_) | Expr::DateGetSeconds(_) |
+            Expr::DateGetMilliseconds(_) => Some(Type::Number),
+            _ => None,
+        }
+    }
+
+    fn visit_stmts(&mut self, stmts: &mut [Stmt]) {
+        for stmt in stmts {
+            self.visit_stmt(stmt);
+        }
+    }
+
+    fn visit_stmt(&mut self, stmt: &mut Stmt) {
+        match stmt {
+            Stmt::Let { id, ty, init, .. } => {
+                if let Some(init) = init {
+                    self.visit_expr(init);
+                }
+                let key = (self.scope, *id);
+                self.declared.insert(key);
+                if !matches!(ty, Type::Any) {
+                    match inferable(ty) {
+                        Some(declared) => self.types.insert(key, declared),
+                        None => self.types.remove(&key),
+                    };
+                    return;
+                }
+                let inferred = init.as_ref().and_then(|e| self.expr_type(e));
+                if let (Some(inferred), Some(init)) = (self.infer_binding(key, inferred), init.as_ref()) {
+                    if self.apply && writable(&inferred, init) {
+                        *ty = inferred;
+                    }
+                }
+            }
+            Stmt::Expr(e) | Stmt::Throw(e) | Stmt::Return(Some(e)) => self.visit_expr(e),
+            Stmt::Return(None) | Stmt::Break | Stmt::Continue => {}
+            Stmt::If { condition, then_branch, else_branch } => {
+                self.visit_expr(condition);
+                self.visit_stmts(then_branch);
+                if let Some(else_stmts) = else_branch {
+                    self.visit_stmts(else_stmts);
+                }
+            }
+            Stmt::While { condition, body } => {
+                self.visit_expr(condition);
+                self.visit_stmts(body);
+            }
+            Stmt::For { init, condition, update, body } => {
+                if let Some(init_stmt) = init {
+                    self.visit_stmt(init_stmt);
+                }
+                if let Some(e) = condition {
+                    self.visit_expr(e);
+                }
+                if let Some(e) = update {
+                    self.visit_expr(e);
+                }
+                self.visit_stmts(body);
+            }
+            Stmt::Switch { discriminant, cases } => {
+                self.visit_expr(discriminant);
+                for case in cases {
+                    if let Some(test) = &mut case.test {
+                        self.visit_expr(test);
+                    }
+                    self.visit_stmts(&mut case.body);
+                }
+            }
+            Stmt::Try { body, catch, finally } => {
+                self.visit_stmts(body);
+                if let Some(catch_clause) = catch {
+                    if let Some((id, _)) = catch_clause.param {
+                        let key = (self.scope, id);
+                        self.declared.insert(key);
+                        self.types.remove(&key);
+                    }
+                    self.visit_stmts(&mut catch_clause.body);
+                }
+                if let Some(finally_stmts) = finally {
+                    self.visit_stmts(finally_stmts);
+                }
+            }
+        }
+    }
+
+    fn visit_exprs(&mut self, exprs: &mut [Expr]) {
+        for e in exprs {
+            self.visit_expr(e);
+        }
+    }
+
+    /// Walk a callback whose leading parameters have the `contextual` types (`None` where
+    /// unknown). Parameters typed this way are checked like bindings: assigning them
+    /// another type, or a reducer returning another type than its accumulator, keeps them `Any`.
+    fn visit_callback(&mut self, callback: &mut Expr, contextual: &[Option<Type>]) {
+        let Expr::Closure { params, body, .. } = callback else {
+            self.visit_expr(callback);
+            return;
+        };
+        let (typed, rest) = params.split_at_mut(contextual.len().min(params.len()));
+        for (param, ty) in typed.iter_mut().zip(contextual) {
+            if !matches!(param.ty, Type::Any) || param.is_rest {
+                self.bind_params(std::slice::from_mut(param));
+                continue;
+            }
+            if let Some(default) = &mut param.default {
+                self.visit_expr(default);
+            }
+            let key = (self.scope, param.id);
+            self.declared.insert(key);
+            if let Some(inferred) = self.infer_binding(key, ty.clone()) {
+                if self.apply && matches!(inferred, Type::Number | Type::String | Type::BigInt) {
+                    param.ty = inferred;
+                }
+            }
+        }
+        self.bind_params(rest);
+        self.visit_stmts(body);
+    }
+
+    fn visit_array_callback(&mut self, array: &mut Expr, callback: &mut Expr) {
+        self.visit_expr(array);
+        let elem = self.element_type(array);
+        self.visit_callback(callback, &[elem, Some(Type::Number)]);
+    }
+
+    /// Walk a reducer callback; its accumulator takes the initial value's type, which the
+    /// callback must return too
+    fn visit_reducer(&mut self, elem: Option<Type>, callback: &mut Expr, initial: Option<&mut Expr>) {
+        let acc = initial.and_then(|init| {
+            self.visit_expr(init);
+            self.expr_type(init)
+        });
+        self.visit_callback(callback, &[acc.clone(), elem, Some(Type::Number)]);
+        if let (Some(_), Expr::Closure { params, .. }) = (&acc, &*callback) {
+            if let Some(param) = params.first() {
+                let key = (self.scope, param.id);
+                let ret = self.callback_return_type(callback);
+                self.assignments.push((key, ret));
+            }
+        }
+    }
+
+    fn visit_expr(&mut self, expr: &mut Expr) {
+        match expr {
+            Expr::LocalSet(id, value) => {
+                self.visit_expr(value);
+                let key = self.resolve(*id);
+                let ty = self.expr_type(value);
+                self.assignments.push((key, ty));
+            }
+            Expr::Update { id, .. } => {
+                let key = self.resolve(*id);
+                self.assignments.push((key, Some(Type::Number)));
+            }
+            Expr::GlobalSet(_, value) => self.visit_expr(value),
+            Expr::Binary { left, right, .. } | Expr::Compare { left, right, .. } |
+            Expr::Logical { left, right, .. } => {
+                self.visit_expr(left);
+                self.visit_expr(right);
+            }
+            Expr::Unary { operand, .. } => self.visit_expr(operand),
+            Expr::Conditional { condition, then_expr, else_expr } => {
+                self.visit_expr(condition);
+                self.visit_expr(then_expr);
+                self.visit_expr(else_expr);
+            }
+            Expr::Call { callee, args, .. } => {
+                self.visit_expr(callee);
+                // Array methods on values not lowered to the dedicated expressions
+                let receiver = match callee.as_ref() {
+                    Expr::PropertyGet { object, property } => Some((self.element_type(object), property.as_str())),
+                    _ => None,
+                };
+                match (receiver, args.as_mut_slice()) {
+                    (Some((Some(elem), "map" | "filter" | "forEach" | "find" | "findIndex" | "findLast"
+                        | "findLastIndex" | "some" | "every" | "flatMap")), [callback, rest @ ..]) => {
+                        self.visit_callback(callback, &[Some(elem), Some(Type::Number)]);
+                        self.visit_exprs(rest);
+                    }
+                    (Some((Some(elem), "reduce")), [callback, initial, rest @ ..]) => {
+                        self.visit_reducer(Some(elem), callback, Some(initial));
+                        self.visit_exprs(rest);
+                    }
+                    (_, args) => self.visit_exprs(args),
+                }
+            }
+            Expr::CallSpread { callee, args, .. } => {
+                self.visit_expr(callee);
+                for arg in args {
+                    match arg {
+                        CallArg::Expr(e) | CallArg::Spread(e) => self.visit_expr(e),
+                    }
+                }
+            }
+            Expr::New { args, .. } | Expr::SuperCall(args) | Expr::Array(args) |
+            Expr::StaticMethodCall { args, .. } | Expr::SuperMethodCall { args, .. } |
+            Expr::MathMin(args) | Expr::MathMax(args) | Expr::Sequence(args) => self.visit_exprs(args),
+            Expr::NewDynamic { callee, args } => {
+                self.visit_expr(callee);
+                self.visit_exprs(args);
+            }
+            Expr::NativeMethodCall { object, args, .. } => {
+                if let Some(obj) = object {
+                    self.visit_expr(obj);
+                }
+                self.visit_exprs(args);
+            }
+            Expr::ArraySpread(elements) => {
+                for elem in elements {
+                    match elem {
+                        ArrayElement::Expr(e) | ArrayElement::Spread(e) => self.visit_expr(e),
+                    }
+                }
+            }
+            Expr::Object(properties) => {
+                for (_, value) in properties {
+                    self.visit_expr(value);
+                }
+            }
+            Expr::PropertyGet { object, .. } | Expr::PropertyUpdate { object, .. } => self.visit_expr(object),
+            Expr::PropertySet { object, value, .. } => {
+                self.visit_expr(object);
+                self.visit_expr(value);
+            }
+            Expr::IndexGet { object, index } | Expr::IndexUpdate { object, index, .. } => {
+                self.visit_expr(object);
+                self.visit_expr(index);
+            }
+            Expr::IndexSet { object, index, value } => {
+                self.visit_expr(object);
+                self.visit_expr(index);
+                self.visit_expr(value);
+            }
+            Expr::StaticFieldSet { value, .. } => self.visit_expr(value),
+            Expr::Await(inner) | Expr::TypeOf(inner) | Expr::Delete(inner) | Expr::JsonParse(inner) |
+            Expr::JsonStringify(inner) | Expr::JsonCanonicalize(inner) | Expr::StringFromCharCode(inner) |
+            Expr::MathFloor(inner) | Expr::MathCeil(inner) | Expr::MathRound(inner) | Expr::MathAbs(inner) |
+            Expr::MathSqrt(inner) => self.visit_expr(inner),
+            Expr::MathPow(base, exp) => {
+                self.visit_expr(base);
+                self.visit_expr(exp);
+            }
+            Expr::InstanceOf { expr: inner, .. } | Expr::Narrowed { value: inner, .. } => self.visit_expr(inner),
+            Expr::In { property, object } => {
+                self.visit_expr(property);
+                self.visit_expr(object);
+            }
+            Expr::Closure { params, body, .. } => {
+                self.bind_params(params);
+                self.visit_stmts(body);
+            }
+            Expr::ArrayPush { value, .. } | Expr::ArrayUnshift { value, .. } => self.visit_expr(value),
+            Expr::ArrayIndexOf { array, value } | Expr::ArrayIncludes { array, value } => {
+                self.visit_expr(array);
+                self.visit_expr(value);
+            }
+            Expr::ArraySlice { array, start, end } => {
+                self.visit_expr(array);
+                self.visit_expr(start);
+                if let Some(end) = end {
+                    self.visit_expr(end);
+                }
+            }
+            Expr::ArrayJoin { array, separator } => {
+                self.visit_expr(array);
+                if let Some(separator) = separator {
+                    self.visit_expr(separator);
+                }
+            }
+            Expr::StringSplit(string, delimiter) => {
+                self.visit_expr(string);
+                self.visit_expr(delimiter);
+            }
+            Expr::ArrayForEach { array, callback } | Expr::ArrayMap { array, callback } |
+            Expr::ArrayFilter { array, callback } | Expr::ArrayFind { array, callback } |
+            Expr::ArrayFindIndex { array, callback } => self.visit_array_callback(array, callback),
+            Expr::ArrayReduce { array, callback, initial } => {
+                self.visit_expr(array);
+                let elem = self.element_type(array);
+                self.visit_reducer(elem, callback, initial.as_deref_mut());
+            }
+            Expr::MapSet { map, key, value } => {
+                self.visit_expr(map);
+                self.visit_expr(key);
+                self.visit_expr(value);
+            }
+            Expr::MapGet { map, key } | Expr::MapHas { map, key } | Expr::MapDelete { map, key } => {
+                self.visit_expr(map);
+                self.visit_expr(key);
+            }
+            Expr::SetAdd { value, .. } => self.visit_expr(value),
+            Expr::SetHas { set, value } | Expr::SetDelete { set, value } => {
+                self.visit_expr(set);
+                self.visit_expr(value);
+            }
+            // Other expressions don't contain bindings or callbacks inference looks into
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lower(source: &str) -> Module {
+        let ast_module = perry_parser::parse_typescript(source, "test.ts").unwrap();
+        crate::lower_module(&ast_module, "test.ts", "/tmp/test.ts").unwrap()
+    }
+
+    fn binding<'a>(module: &'a Module, name: &str) -> (&'a Type, &'a Expr) {
+        module.init.iter()
+            .find_map(|stmt| match stmt {
+                Stmt::Let { name: n, ty, init: Some(init), .. } if n == name => Some((ty, init)),
+                _ => None,
+            })
+            .unwrap_or_else(|| panic!("no binding {}", name))
+    }
+
+    #[test]
+    fn test_infer_from_initializers() {
+        let mut module = lower(
+            "const items: string[] = ['a', 'b'];\n\
+             const n = items.length * 2;\n\
+             const label = 'n = ' + n;\n\
+             const big = n > 1;\n\
+             function area(r: number) { return r * r * 3.14; }\n\
+             const a = area(2);\n\
+             let count = 0;\n\
+             count += 1;\n\
+             let changes = 0;\n\
+             changes = JSON.parse('1');\n",
+        );
+        infer_local_types(&mut module);
+
+        assert_eq!(binding(&module, "n").0, &Type::Number);
+        assert_eq!(binding(&module, "label").0, &Type::String);
+        // Booleans keep their dynamic representation
+        assert_eq!(binding(&module, "big").0, &Type::Any);
+        assert_eq!(binding(&module, "a").0, &Type::Number);
+        assert_eq!(binding(&module, "count").0, &Type::Number);
+        // Assigned a value of unknown type
+        assert_eq!(binding(&module, "changes").0, &Type::Any);
+        // Function signatures are left alone
+        let area = module.functions.iter().find(|f| f.name == "area").unwrap();
+        assert_eq!(area.return_type, Type::Any);
+    }
+
+    #[test]
+    fn test_infer_callback_parameters() {
+        let mut module = lower(
+            "const prices = [10, 20];\n\
+             const taxed = prices.map(p => p * 1.2);\n\
+             const total = prices.reduce((sum, p) => sum + p, 0);\n",
+        );
+        infer_local_types(&mut module);
+
+        assert_eq!(binding(&module, "prices").0, &Type::Array(Box::new(Type::Number)));
+        let (ty, init) = binding(&module, "taxed");
+        assert_eq!(ty, &Type::Array(Box::new(Type::Number)));
+        let callback = match init {
+            Expr::ArrayMap { callback, .. } => callback.as_ref(),
+            Expr::Call { args, .. } => &args[0],
+            other => panic!("unexpected map lowering: {:?}", other),
+        };
+        let Expr::Closure { params, .. } = callback else { panic!("callback is not a closure") };
+        assert_eq!(params[0].ty, Type::Number);
+        assert_eq!(binding(&module, "total").0, &Type::Number);
+    }
+}
@@ -3,7 +3,9 @@
 //! The HIR is a typed, lowered representation of TypeScript that is
 //! easier to compile to native code than the raw AST.
 
+use perry_diagnostics::Span;
 use perry_types::{FuncId, GlobalId, LocalId, Type, TypeParam};
+use serde::{Deserialize, Serialize};
 
 /// Known native module names that map to stdlib implementations.
 /// These are npm packages that have native Rust replacements.
@@ -45,12 +47,40 @@ pub const NATIVE_MODULES: &[&str] = &[
     "decimal.js",
     "bignumber.js",
     "exponential-backoff",
+    // Embedded key-value store (new Level(...))
+    "level",
+    // Full-text search (new MiniSearch(...), MiniSearch.loadJSON/loadBuffer)
+    "minisearch",
     // HTTP framework
     "fastify",
     // Node.js built-in modules
     "async_hooks",
     // Perry native UI
     "perry/ui",
+    // Perry dependency injection
+    "perry/di",
+    // Perry structured concurrency
+    "perry/concurrency",
+    // Perry emitter streams and pipelines
+    "perry/streams",
+    // Perry BigInt number theory (modPow, gcd)
+    "perry/bigint",
+    // Perry runtime type reflection (typeOf, className, fields)
+    "perry/reflect",
+    // Perry WebAssembly plugin host
+    "perry/wasm",
+    // Perry append-only event log (segment files, offsets, compaction)
+    "perry/eventlog",
+    // Perry geo and spatial utilities (distance, point in polygon, geohash)
+    "perry/geo",
+    // Perry HTTP client cache for fetch and axios
+    "perry/http-cache",
+    // Perry pool of reusable Buffers (acquire, release)
+    "perry/buffer-pool",
+    // Perry lifetime of JavaScript values held by native code (dispose, retain, liveHandles)
+    "perry/js-handles",
+    // OAuth 2.0 / OpenID Connect client
+    "openid-client",
 ];
 
 /// Check if a module path refers to a native stdlib module
@@ -60,7 +90,7 @@ pub fn is_native_module(path: &str) -> bool {
 }
 
 /// The kind of module being imported, determining how it's executed
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum ModuleKind {
     /// Native TypeScript compiled to machine code (default for .ts/.tsx files)
     NativeCompiled,
@@ -108,11 +138,20 @@ pub type EnumId = u32;
 /// Unique identifier for an interface
 pub type InterfaceId = u32;
 
+/// Property key that `[Symbol.dispose]` members lower to (must match perry-runtime's disposable.rs)
+pub const SYMBOL_DISPOSE_KEY: &str = "__symbol_dispose";
+
+/// Property key that `[Symbol.asyncDispose]` members lower to
+pub const SYMBOL_ASYNC_DISPOSE_KEY: &str = "__symbol_asyncDispose";
+
+/// Property key that `[Symbol.iterator]` members lower to
+pub const SYMBOL_ITERATOR_KEY: &str = "__symbol_iterator";
+
 /// Unique identifier for a type alias
 pub type TypeAliasId = u32;
 
 /// A complete HIR module (corresponds to one TypeScript file)
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Module {
     /// Module name/path
     pub name: String,
@@ -144,10 +183,85 @@ pub struct Module {
     /// This tracks functions like `export function foo() { ... }` or `export async function bar() { ... }`
     /// that may be imported and used as values (not just called) by other modules
     pub exported_functions: Vec<(String, FuncId)>,
+    /// Exported bindings whose values are known at compile time, by exported name:
+    /// enums, `const`s of literals and `as const` objects. Importing modules fold
+    /// reads of these into literals (see `fold_imported_constants`)
+    pub exported_constants: Vec<(String, ConstValue)>,
+    /// Diagnostics found while lowering (e.g. writes to readonly properties).
+    /// `perry check` reports them; compilation ignores them
+    pub diagnostics: Vec<perry_diagnostics::Diagnostic>,
+    /// Paths of the N-API addons (`.node` files) the module loads. The executable must
+    /// export the N-API symbols for them (see perry-runtime's `napi` module)
+    pub native_addons: Vec<String>,
+    /// Routes registered on Fastify apps with a literal path, in registration order.
+    /// `perry compile --openapi` documents them
+    pub http_routes: Vec<HttpRoute>,
+    /// Optimization settings from the file's `// @perry-opt:` directives
+    pub opt: OptSettings,
+    /// Functions and classes monomorphization created, so codegen can name their
+    /// symbols after the generic and its type arguments
+    pub specializations: Vec<Specialization>,
+}
+
+/// How hard codegen optimizes a module
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum OptLevel {
+    /// Full optimization (the default)
+    #[default]
+    Speed,
+    /// Optimize, preferring smaller code
+    Size,
+    /// No optimization, for the fastest compile
+    None,
+}
+
+/// Per-module optimization settings (see `OptSettings::from_source`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OptSettings {
+    pub level: OptLevel,
+    /// Whether calls in the module may be inlined
+    pub inline: bool,
+    /// Whether numeric functions also get a version for newer CPUs, picked at startup
+    pub multiversion: bool,
+}
+
+impl Default for OptSettings {
+    fn default() -> Self {
+        Self { level: OptLevel::Speed, inline: true, multiversion: false }
+    }
+}
+
+/// A function or class specialized for one set of type arguments
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Specialization {
+    /// Name of the specialized function or class (`identity$num`)
+    pub name: String,
+    /// Name of the generic function or class
+    pub generic: String,
+    pub type_args: Vec<Type>,
+}
+
+/// A route registered on a Fastify app: `app.get(path, handler)` or
+/// `app.post(path, { schema }, handler)`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HttpRoute {
+    /// Upper-case HTTP method, or `ALL`
+    pub method: String,
+    /// Path as written, e.g. `/users/:id`
+    pub path: String,
+    /// The `schema` property of the route options object
+    pub schema: Option<Expr>,
+    /// Request shape `{ Body, Querystring, Params, Headers, Reply }`, from the route's type
+    /// argument (`app.get<{ ... }>`) or the handler's `FastifyRequest<{ ... }>` parameter
+    pub request_type: Option<Type>,
+    /// Return type annotation of an inline handler
+    pub reply_type: Option<Type>,
+    /// Handler defined as a named function, whose signature supplies the types instead
+    pub handler_func: Option<FuncId>,
 }
 
 /// An enum definition
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Enum {
     pub id: EnumId,
     pub name: String,
@@ -156,14 +270,14 @@ pub struct Enum {
 }
 
 /// An enum member
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct EnumMember {
     pub name: String,
     pub value: EnumValue,
 }
 
 /// Value of an enum member
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum EnumValue {
     /// Numeric value (auto-incremented or explicit)
     Number(i64),
@@ -171,8 +285,38 @@ pub enum EnumValue {
     String(String),
 }
 
+/// Compile-time value of a module-level constant
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ConstValue {
+    Number(f64),
+    String(String),
+    Bool(bool),
+    /// `as const` object literal, properties in declaration order
+    Object(Vec<(String, ConstValue)>),
+    /// Enum (`enum` or `const enum`); its name is also usable as a type
+    Enum(Vec<EnumMember>),
+}
+
+impl ConstValue {
+    /// Value of a property of an object or a member of an enum
+    pub fn member(&self, name: &str) -> Option<ConstValue> {
+        match self {
+            ConstValue::Object(properties) => properties.iter()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.clone()),
+            ConstValue::Enum(members) => members.iter()
+                .find(|member| member.name == name)
+                .map(|member| match &member.value {
+                    EnumValue::Number(n) => ConstValue::Number(*n as f64),
+                    EnumValue::String(s) => ConstValue::String(s.clone()),
+                }),
+            _ => None,
+        }
+    }
+}
+
 /// An interface definition
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Interface {
     pub id: InterfaceId,
     pub name: String,
@@ -187,8 +331,30 @@ pub struct Interface {
     pub is_exported: bool,
 }
 
+impl Interface {
+    /// Merge a later declaration of the same interface into this one: parents and
+    /// properties are combined, and the later declaration's methods come first, so its
+    /// overloads take precedence as in TypeScript
+    pub fn merge(&mut self, later: Interface) {
+        for parent in later.extends {
+            if !self.extends.contains(&parent) {
+                self.extends.push(parent);
+            }
+        }
+        for property in later.properties {
+            if !self.properties.iter().any(|p| p.name == property.name) {
+                self.properties.push(property);
+            }
+        }
+        let mut methods = later.methods;
+        methods.append(&mut self.methods);
+        self.methods = methods;
+        self.is_exported |= later.is_exported;
+    }
+}
+
 /// A property in an interface
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct InterfaceProperty {
     pub name: String,
     pub ty: Type,
@@ -197,7 +363,7 @@ pub struct InterfaceProperty {
 }
 
 /// A method signature in an interface
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct InterfaceMethod {
     pub name: String,
     /// Method's own type parameters (separate from interface's)
@@ -207,7 +373,7 @@ pub struct InterfaceMethod {
 }
 
 /// A type alias definition
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TypeAlias {
     pub id: TypeAliasId,
     pub name: String,
@@ -219,7 +385,7 @@ pub struct TypeAlias {
 }
 
 /// An import declaration
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Import {
     /// Source module path (e.g., "./utils" or "fs")
     pub source: String,
@@ -231,10 +397,13 @@ pub struct Import {
     pub module_kind: ModuleKind,
     /// Resolved absolute path to the module file (if available)
     pub resolved_path: Option<String>,
+    /// True if this import comes from a require() call (selects the "require"
+    /// condition of package.json exports instead of "import")
+    pub is_require: bool,
 }
 
 /// Import specifier
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum ImportSpecifier {
     /// Named import: import { foo, bar as baz } from "..."
     Named {
@@ -252,7 +421,7 @@ pub enum ImportSpecifier {
 }
 
 /// An export declaration
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Export {
     /// Named export: export { foo, bar as baz }
     Named {
@@ -272,7 +441,7 @@ pub enum Export {
 }
 
 /// A class definition
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Class {
     pub id: ClassId,
     pub name: String,
@@ -303,7 +472,7 @@ pub struct Class {
 }
 
 /// A class field
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ClassField {
     pub name: String,
     pub ty: Type,
@@ -313,7 +482,7 @@ pub struct ClassField {
 }
 
 /// A global variable
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Global {
     pub id: GlobalId,
     pub name: String,
@@ -323,7 +492,7 @@ pub struct Global {
 }
 
 /// A decorator applied to a method or class
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Decorator {
     /// The decorator function name (e.g., "log" for @log)
     pub name: String,
@@ -331,8 +500,39 @@ pub struct Decorator {
     pub args: Vec<Expr>,
 }
 
+/// Entries a memoized function keeps when no size is given
+pub const DEFAULT_MEMOIZE_ENTRIES: u32 = 1024;
+
+/// How a memoized function caches its results: `@memoize(maxEntries?, ttlMs?)` on a
+/// method, or the `"use memoize(maxEntries?, ttlMs?)"` directive in a function body
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Memoize {
+    /// Most results kept; the oldest is dropped to make room
+    pub max_entries: u32,
+    /// How long a result stays valid, in milliseconds; 0 for as long as it is kept
+    pub ttl_ms: u64,
+}
+
+impl Decorator {
+    /// The cache options if this is `@memoize`; non-numeric arguments count as missing
+    pub fn memoize(&self) -> Option<Memoize> {
+        if self.name != "memoize" {
+            return None;
+        }
+        let arg = |i: usize| match self.args.get(i) {
+            Some(Expr::Integer(n)) if *n >= 0 => Some(*n as u64),
+            Some(Expr::Number(n)) if *n >= 0.0 && n.is_finite() => Some(*n as u64),
+            _ => None,
+        };
+        Some(Memoize {
+            max_entries: arg(0).map_or(DEFAULT_MEMOIZE_ENTRIES, |n| n.clamp(1, u32::MAX as u64) as u32),
+            ttl_ms: arg(1).unwrap_or(0),
+        })
+    }
+}
+
 /// A function definition
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Function {
     pub id: FuncId,
     pub name: String,
@@ -347,10 +547,19 @@ pub struct Function {
     pub captures: Vec<LocalId>,
     /// Decorators applied to this function/method
     pub decorators: Vec<Decorator>,
+    /// Where the function is declared (dummy for functions the compiler synthesizes)
+    pub span: Span,
+}
+
+impl Function {
+    /// The cache options if the function is memoized
+    pub fn memoize(&self) -> Option<Memoize> {
+        self.decorators.iter().find_map(Decorator::memoize)
+    }
 }
 
 /// A function parameter
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Param {
     pub id: LocalId,
     pub name: String,
@@ -361,7 +570,7 @@ pub struct Param {
 }
 
 /// Statement in function body
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Stmt {
     /// Local variable declaration: let/const x = expr
     Let {
@@ -413,7 +622,7 @@ pub enum Stmt {
 }
 
 /// A case in a switch statement
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SwitchCase {
     /// Test expression (None for default case)
     pub test: Option<Expr>,
@@ -422,14 +631,14 @@ pub struct SwitchCase {
 }
 
 /// Catch clause in try statement
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CatchClause {
     pub param: Option<(LocalId, String)>,
     pub body: Vec<Stmt>,
 }
 
 /// Expression
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Expr {
     // Literals
     Undefined,
@@ -484,6 +693,8 @@ pub enum Expr {
         args: Vec<Expr>,
         /// Explicit type arguments (e.g., identity<number>(x))
         type_args: Vec<Type>,
+        /// Source of the call expression
+        span: Span,
     },
 
     /// Function call with spread arguments (e.g., fn(a, ...arr, b))
@@ -581,6 +792,13 @@ pub enum Expr {
         expr: Box<Expr>,
         ty: String,
     },
+    /// `value` where a type guard (`typeof x === "number"`, `x instanceof C`) has established
+    /// that it is a `ty`. Evaluates to `value` unchanged; a local initialized from it gets
+    /// `ty`'s representation in codegen instead of being treated as a dynamic value
+    Narrowed {
+        value: Box<Expr>,
+        ty: Type,
+    },
     /// The 'in' operator: checks if property exists in object
     /// e.g., "prop" in obj or key in obj
     In {
@@ -597,6 +815,8 @@ pub enum Expr {
         args: Vec<Expr>,
         /// Explicit type arguments (e.g., new Box<number>(42))
         type_args: Vec<Type>,
+        /// Source of the new expression
+        span: Span,
     },
 
     /// Dynamic new expression (new with non-identifier callee)
@@ -662,6 +882,18 @@ pub enum Expr {
     ProcessArgv,
     // Process memory usage: process.memoryUsage() -> object { rss, heapTotal, heapUsed, external, arrayBuffers }
     ProcessMemoryUsage,
+    // process.setMemoryLimit(bytes, policy?) -> void (policy: "throw" | "abort")
+    ProcessSetMemoryLimit { limit: Box<Expr>, policy: Option<Box<Expr>> },
+    // process.onLowMemory(callback) -> void
+    ProcessOnLowMemory(Box<Expr>),
+    // process.stdout.write(data, callback?) / process.stderr.write(...) -> boolean (fd: 1 or 2)
+    ProcessStdioWrite { fd: u8, data: Box<Expr>, callback: Option<Box<Expr>> },
+    // process.stdin.read() -> next chunk as a string, or null at the end of input
+    ProcessStdinRead,
+    // process.stdin.on('data' | 'end', listener) -> void
+    ProcessStdinOn { event: Box<Expr>, listener: Box<Expr> },
+    // process.stdin.pipe(process.stdout | process.stderr) -> void (fd: 1 or 2)
+    ProcessStdinPipe(u8),
 
     // File system operations
     FsReadFileSync(Box<Expr>),           // fs.readFileSync(path) -> string
@@ -670,6 +902,7 @@ pub enum Expr {
     FsMkdirSync(Box<Expr>),              // fs.mkdirSync(path) -> void
     FsUnlinkSync(Box<Expr>),             // fs.unlinkSync(path) -> void
     FsAppendFileSync(Box<Expr>, Box<Expr>), // fs.appendFileSync(path, content) -> void
+    FsCreateReadStream(Box<Expr>),       // fs.createReadStream(path) -> ReadStream
 
     // Path operations
     PathJoin(Box<Expr>, Box<Expr>),      // path.join(a, b) -> string
@@ -681,6 +914,7 @@ pub enum Expr {
     // JSON operations
     JsonParse(Box<Expr>),                // JSON.parse(string) -> value
     JsonStringify(Box<Expr>),            // JSON.stringify(value) -> string
+    JsonCanonicalize(Box<Expr>),         // JSON.canonicalize(value) -> RFC 8785 string
 
     // Math operations
     MathFloor(Box<Expr>),                // Math.floor(x) -> number
@@ -806,6 +1040,8 @@ pub enum Expr {
         method: Box<Expr>,
         body: Box<Expr>,
         headers: Vec<(String, Expr)>,
+        stream_body: bool,               // body is a ReadStream; upload it in chunks
+        on_upload_progress: Option<Box<Expr>>, // onUploadProgress(sent, total)
     },
 
     // Net operations
@@ -855,6 +1091,9 @@ pub enum Expr {
     MapDelete { map: Box<Expr>, key: Box<Expr> },              // map.delete(key) -> boolean
     MapSize(Box<Expr>),                                        // map.size -> number
     MapClear(Box<Expr>),                                       // map.clear() -> void
+    MapKeys(Box<Expr>),                                        // map.keys() -> array of keys
+    MapValues(Box<Expr>),                                      // map.values() -> array of values
+    MapEntries(Box<Expr>),                                     // map.entries() -> array of [key, value]
 
     // Set operations
     SetNew,                                                    // new Set() -> empty set
@@ -863,6 +1102,7 @@ pub enum Expr {
     SetDelete { set: Box<Expr>, value: Box<Expr> },            // set.delete(value) -> boolean
     SetSize(Box<Expr>),                                        // set.size -> number
     SetClear(Box<Expr>),                                       // set.clear() -> void
+    SetValues(Box<Expr>),                                      // set.values() -> array of values
 
     // Sequence expression (comma operator)
     Sequence(Vec<Expr>),
@@ -881,7 +1121,11 @@ pub enum Expr {
     DateGetMilliseconds(Box<Expr>),       // date.getMilliseconds() -> number (0-999)
 
     // Error operations
-    ErrorNew(Option<Box<Expr>>),          // new Error() or new Error(message) -> Error object
+    /// new Error() or new Error(message) -> Error object; `span` locates it in the stack
+    ErrorNew {
+        message: Option<Box<Expr>>,
+        span: Span,
+    },
     ErrorMessage(Box<Expr>),              // error.message -> string
 
     // URL operations
@@ -949,6 +1193,33 @@ pub enum Expr {
         name: Box<Expr>,
     },
 
+    // Explicit resource management (`using` / `await using`, DisposableStack)
+    /// resource[Symbol.dispose]() at scope exit; with `is_async`, [Symbol.asyncDispose]() falling
+    /// back to [Symbol.dispose](). Skipped for null/undefined.
+    DisposeResource {
+        resource: Box<Expr>,
+        is_async: bool,
+    },
+    /// new DisposableStack()
+    DisposableStackNew,
+    /// stack.adopt(value, onDispose) -> value (stack.use(value) lowers to this too)
+    DisposableStackAdopt {
+        stack: Box<Expr>,
+        value: Box<Expr>,
+        on_dispose: Box<Expr>,
+    },
+    /// stack.defer(onDispose)
+    DisposableStackDefer {
+        stack: Box<Expr>,
+        on_dispose: Box<Expr>,
+    },
+    /// stack.dispose()
+    DisposableStackDispose(Box<Expr>),
+    /// stack.move() -> DisposableStack
+    DisposableStackMove(Box<Expr>),
+    /// stack.disposed -> boolean
+    DisposableStackDisposed(Box<Expr>),
+
     // Delete operator
     Delete(Box<Expr>),                    // delete obj.prop or delete obj["prop"] -> bool
 
@@ -1036,6 +1307,13 @@ pub enum Expr {
     /// Check if value is finite
     IsFinite(Box<Expr>),
 
+    /// Load an N-API addon (`require('./addon.node')` / `import addon from './addon.node'`)
+    /// and return its exports object. Loads are cached by path
+    NativeAddonLoad {
+        /// Absolute path for relative specifiers, otherwise the specifier as written
+        path: String,
+    },
+
     // V8 JavaScript Runtime interop
     // These expressions are used for modules loaded via the V8 interpreter
 
@@ -1118,13 +1396,35 @@ pub enum Expr {
         param_count: usize,
     },
 
-    /// import.meta.url - returns the URL of the current module
-    /// The string is the file:// URL of the source file
-    ImportMetaUrl(String),
+    /// import.meta.url / import.meta.filename / import.meta.dirname computed at runtime
+    /// relative to the executable's directory (compiled with `--import-meta=exe`).
+    /// By default these are baked in as string literals during lowering instead.
+    ImportMetaRuntime {
+        property: ImportMetaProperty,
+        /// Source file path relative to the project root
+        relative_path: String,
+    },
+
+    /// import.meta.resolve(specifier) - returns the file:// URL (or `node:` URL for builtins)
+    ImportMetaResolve {
+        /// Absolute source file path, or project-relative when `exe_relative` is set
+        module_path: String,
+        /// Resolve relative to the executable's directory at runtime
+        exe_relative: bool,
+        specifier: Box<Expr>,
+    },
+}
+
+/// `import.meta` properties that describe the current module's location
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ImportMetaProperty {
+    Url,      // file:// URL
+    Filename, // absolute path
+    Dirname,  // absolute directory
 }
 
 /// Binary operators
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum BinaryOp {
     Add,
     Sub,
@@ -1141,7 +1441,7 @@ pub enum BinaryOp {
 }
 
 /// Unary operators
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum UnaryOp {
     Neg,
     Not,
@@ -1150,7 +1450,7 @@ pub enum UnaryOp {
 }
 
 /// Comparison operators
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum CompareOp {
     Eq,    // ===
     Ne,    // !==
@@ -1161,7 +1461,7 @@ pub enum CompareOp {
 }
 
 /// Logical operators
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum LogicalOp {
     And, // &&
     Or,  // ||
@@ -1169,14 +1469,14 @@ pub enum LogicalOp {
 }
 
 /// Update operators (++/--)
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum UpdateOp {
     Increment, // ++
     Decrement, // --
 }
 
 /// Element in an array literal with spread support
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ArrayElement {
     /// Regular element: [1, 2, 3]
     Expr(Expr),
@@ -1185,7 +1485,7 @@ pub enum ArrayElement {
 }
 
 /// Argument in a function call with spread support
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum CallArg {
     /// Regular argument: fn(x, y)
     Expr(Expr),
@@ -1209,6 +1509,20 @@ impl Module {
             exported_native_instances: Vec::new(),
             exported_objects: Vec::new(),
             exported_functions: Vec::new(),
+            exported_constants: Vec::new(),
+            diagnostics: Vec::new(),
+            native_addons: Vec::new(),
+            http_routes: Vec::new(),
+            opt: OptSettings::default(),
+            specializations: Vec::new(),
+        }
+    }
+
+    /// Add an interface declaration, merging it into an earlier one of the same name
+    pub fn add_interface(&mut self, iface: Interface) {
+        match self.interfaces.iter_mut().find(|existing| existing.name == iface.name) {
+            Some(existing) => existing.merge(iface),
+            None => self.interfaces.push(iface),
         }
     }
 }
@@ -563,7 +563,7 @@ fn transform_expr(
         Expr::TypeOf(inner) => {
             transform_expr(inner, js_imports, extern_func_to_js, local_name_to_js, tracker);
         }
-        Expr::InstanceOf { expr: inner, .. } => {
+        Expr::InstanceOf { expr: inner, .. } | Expr::Narrowed { value: inner, .. } => {
             transform_expr(inner, js_imports, extern_func_to_js, local_name_to_js, tracker);
         }
         Expr::Await(inner) => {
@@ -609,7 +609,7 @@ fn transform_expr(
             transform_expr(e, js_imports, extern_func_to_js, local_name_to_js, tracker);
         }
         // File system / path / JSON / Math / Crypto operations
-        Expr::FsReadFileSync(e) | Expr::FsExistsSync(e) | Expr::FsMkdirSync(e) | Expr::FsUnlinkSync(e) => {
+        Expr::FsReadFileSync(e) | Expr::FsExistsSync(e) | Expr::FsMkdirSync(e) | Expr::FsUnlinkSync(e) | Expr::FsCreateReadStream(e) => {
             transform_expr(e, js_imports, extern_func_to_js, local_name_to_js, tracker);
         }
         Expr::FsWriteFileSync(a, b) | Expr::FsAppendFileSync(a, b) | Expr::PathJoin(a, b) | Expr::MathPow(a, b) => {
@@ -619,7 +619,44 @@ fn transform_expr(
         Expr::PathDirname(e) | Expr::PathBasename(e) | Expr::PathExtname(e) | Expr::PathResolve(e) => {
             transform_expr(e, js_imports, extern_func_to_js, local_name_to_js, tracker);
         }
-        Expr::JsonParse(e) | Expr::JsonStringify(e) => {
+        Expr::ProcessSetMemoryLimit { limit, policy } => {
+            transform_expr(limit, js_imports, extern_func_to_js, local_name_to_js, tracker);
+            if let Some(p) = policy {
+                transform_expr(p, js_imports, extern_func_to_js, local_name_to_js, tracker);
+            }
+        }
+        Expr::ProcessOnLowMemory(cb) => {
+            transform_expr(cb, js_imports, extern_func_to_js, local_name_to_js, tracker);
+        }
+        Expr::ProcessStdioWrite { data, callback, .. } => {
+            transform_expr(data, js_imports, extern_func_to_js, local_name_to_js, tracker);
+            if let Some(cb) = callback {
+                transform_expr(cb, js_imports, extern_func_to_js, local_name_to_js, tracker);
+            }
+        }
+        Expr::ProcessStdinOn { event, listener } => {
+            transform_expr(event, js_imports, extern_func_to_js, local_name_to_js, tracker);
+            transform_expr(listener, js_imports, extern_func_to_js, local_name_to_js, tracker);
+        }
+        Expr::DisposeResource { resource, .. } => {
+            transform_expr(resource, js_imports, extern_func_to_js, local_name_to_js, tracker);
+        }
+        Expr::DisposableStackAdopt { stack, value, on_dispose } => {
+            transform_expr(stack, js_imports, extern_func_to_js, local_name_to_js, tracker);
+            transform_expr(value, js_imports, extern_func_to_js, local_name_to_js, tracker);
+            transform_expr(on_dispose, js_imports, extern_func_to_js, local_name_to_js, tracker);
+        }
+        Expr::DisposableStackDefer { stack, on_dispose } => {
+            transform_expr(stack, js_imports, extern_func_to_js, local_name_to_js, tracker);
+            transform_expr(on_dispose, js_imports, extern_func_to_js, local_name_to_js, tracker);
+        }
+        Expr::DisposableStackDispose(stack) | Expr::DisposableStackMove(stack) | Expr::DisposableStackDisposed(stack) => {
+            transform_expr(stack, js_imports, extern_func_to_js, local_name_to_js, tracker);
+        }
+        Expr::ImportMetaResolve { specifier, .. } => {
+            transform_expr(specifier, js_imports, extern_func_to_js, local_name_to_js, tracker);
+        }
+        Expr::JsonParse(e) | Expr::JsonStringify(e) | Expr::JsonCanonicalize(e) => {
             transform_expr(e, js_imports, extern_func_to_js, local_name_to_js, tracker);
         }
         Expr::MathFloor(e) | Expr::MathCeil(e) | Expr::MathRound(e) | Expr::MathAbs(e) | Expr::MathSqrt(e) => {
@@ -691,7 +728,8 @@ fn transform_expr(
             transform_expr(map, js_imports, extern_func_to_js, local_name_to_js, tracker);
             transform_expr(key, js_imports, extern_func_to_js, local_name_to_js, tracker);
         }
-        Expr::MapSize(e) | Expr::MapClear(e) => {
+        Expr::MapSize(e) | Expr::MapClear(e) | Expr::MapKeys(e) |
+        Expr::MapValues(e) | Expr::MapEntries(e) => {
             transform_expr(e, js_imports, extern_func_to_js, local_name_to_js, tracker);
         }
         Expr::SetAdd { value, .. } => {
@@ -701,7 +739,7 @@ fn transform_expr(
             transform_expr(set, js_imports, extern_func_to_js, local_name_to_js, tracker);
             transform_expr(value, js_imports, extern_func_to_js, local_name_to_js, tracker);
         }
-        Expr::SetSize(e) | Expr::SetClear(e) => {
+        Expr::SetSize(e) | Expr::SetClear(e) | Expr::SetValues(e) => {
             transform_expr(e, js_imports, extern_func_to_js, local_name_to_js, tracker);
         }
         // Date methods
@@ -742,7 +780,7 @@ fn transform_expr(
             transform_expr(e, js_imports, extern_func_to_js, local_name_to_js, tracker);
         }
         // JS Runtime expressions (already transformed, just recurse into subexpressions)
-        Expr::JsLoadModule { .. } => {}
+        Expr::JsLoadModule { .. } | Expr::NativeAddonLoad { .. } => {}
         Expr::JsGetExport { module_handle, .. } => {
             transform_expr(module_handle, js_imports, extern_func_to_js, local_name_to_js, tracker);
         }
@@ -785,7 +823,7 @@ fn transform_expr(
         Expr::Null | Expr::Undefined | Expr::This | Expr::LocalGet(_) | Expr::GlobalGet(_) |
         Expr::FuncRef(_) | Expr::ClassRef(_) | Expr::EnumMember { .. } |
         Expr::RegExp { .. } | Expr::NativeModuleRef(_) | Expr::StaticFieldGet { .. } |
-        Expr::EnvGet(_) | Expr::ProcessUptime | Expr::ProcessMemoryUsage | Expr::MathRandom | Expr::CryptoRandomUUID | Expr::DateNow |
+        Expr::EnvGet(_) | Expr::ProcessUptime | Expr::ProcessMemoryUsage | Expr::ProcessStdinRead | Expr::ProcessStdinPipe(_) | Expr::MathRandom | Expr::CryptoRandomUUID | Expr::DateNow |
         Expr::DateNew(None) | Expr::MapNew | Expr::SetNew | Expr::Update { .. } |
         Expr::ArrayPop(_) | Expr::ArrayShift(_) |
         // OS module expressions
@@ -3,12 +3,20 @@
 //! The HIR is a typed, simplified representation of TypeScript code
 //! that is easier to analyze and transform than the raw AST.
 
+pub mod constants;
+pub mod declarations;
+pub mod directives;
+pub mod infer;
 pub mod ir;
 pub mod js_transform;
 pub mod lower;
 pub mod monomorph;
+pub mod walk;
 
+pub use constants::fold_imported_constants;
+pub use declarations::{collect_declarations, ClassSignature, FunctionSignature, PackageDeclarations};
+pub use infer::infer_local_types;
 pub use ir::*;
 pub use js_transform::{transform_js_imports, fix_cross_module_native_instances, fix_local_native_instances, ExportedNativeInstance};
-pub use lower::lower_module;
-pub use monomorph::monomorphize_module;
+pub use lower::{lower_module, lower_module_with_options, LowerOptions};
+pub use monomorph::{monomorphize_module, monomorphize_program};
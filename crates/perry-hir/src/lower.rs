@@ -3,10 +3,17 @@
 //! Converts SWC's TypeScript AST into our HIR representation.
 
 use anyhow::{anyhow, Result};
-use perry_types::{FuncId, GlobalId, LocalId, Type, TypeParam};
+use perry_diagnostics::{closest_name, Applicability, Diagnostic, DiagnosticCode, FileId, Span, Suggestion};
+use perry_types::conditional;
+use perry_types::indexed;
+use perry_types::mapped::{self, ModifierEdit};
+use perry_types::{ConditionalType, DiscriminatedUnion, FuncId, GlobalId, LiteralType, LocalId, ObjectType, PropertyInfo, Type, TypeParam};
+use swc_common::{Spanned, DUMMY_SP};
 use swc_ecma_ast as ast;
-use std::collections::HashSet;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashSet};
 
+use crate::declarations::PackageDeclarations;
 use crate::ir::*;
 
 /// Context for lowering, tracks variable bindings
@@ -31,8 +38,6 @@ pub struct LoweringContext {
     globals: Vec<(String, GlobalId, Type)>,
     /// Functions: name -> id
     functions: Vec<(String, FuncId)>,
-    /// Function parameter defaults: func_id -> (defaults, param_local_ids)
-    func_defaults: Vec<(FuncId, Vec<Option<Expr>>, Vec<LocalId>)>,
     /// Classes: name -> id
     classes: Vec<(String, ClassId)>,
     /// Static members of classes: class_name -> (static_field_names, static_method_names)
@@ -51,6 +56,15 @@ pub struct LoweringContext {
     native_modules: Vec<(String, String, Option<String>)>,
     /// Built-in module aliases from require(): local_name -> module_name (e.g., "myFs" -> "fs")
     builtin_module_aliases: Vec<(String, String)>,
+    /// Module bindings from require(): local_name -> source (e.g., "lib" -> "./lib")
+    /// Member accesses on these (lib.foo) resolve to named imports of the source module
+    require_namespaces: Vec<(String, String)>,
+    /// Imports collected from require() calls: source -> specifiers
+    /// Appended to module.imports after lowering so require() joins the ESM module graph
+    require_imports: Vec<(String, Vec<ImportSpecifier>)>,
+    /// Names exported through `exports.foo = ...` / `module.exports = { foo }`, gathered
+    /// into the module's default export (module.exports as a whole) after lowering
+    commonjs_exports: Vec<String>,
     /// Stack of type parameter scopes (for nested generics)
     type_param_scopes: Vec<HashSet<String>>,
     /// Native class instances: local_name -> (module_name, class_name)
@@ -63,9 +77,56 @@ pub struct LoweringContext {
     extern_func_types: Vec<(String, Vec<Type>, Type)>,
     /// Source file path (for import.meta.url)
     source_file_path: String,
+    /// Source path relative to the project root when import.meta is resolved at runtime
+    /// against the executable's directory (`--import-meta=exe`)
+    import_meta_relative_path: Option<String>,
     /// Variables that hold closures or other values needing cross-module export globals
     /// (arrow functions, object literals, call expressions, arrays, new expressions)
     exportable_object_vars: HashSet<String>,
+    /// Top-level `using` bindings: (index in module.init where the binding's scope starts,
+    /// local, type, is_await). The rest of the module init is wrapped in try/finally after lowering.
+    module_disposables: Vec<(usize, LocalId, Type, bool)>,
+    /// Object shapes of the module's classes, interfaces and object type aliases:
+    /// (name, names it extends, properties). Collected before lowering so writes to
+    /// readonly properties are recognized whatever the declaration order
+    declared_shapes: Vec<(String, Vec<String>, ObjectType)>,
+    /// Instance methods of the module's classes and interfaces with their return types,
+    /// collected with `declared_shapes` (for-of uses them to recognize iterables and iterators)
+    declared_methods: Vec<(String, Vec<(String, Type)>)>,
+    /// Members of the module's union type aliases (`type Shape = Circle | Square`), collected
+    /// with `declared_shapes` so switches over a union's tag can be checked (see `lower_switch`)
+    declared_unions: Vec<(String, Vec<Type>)>,
+    /// Conditional type aliases (`type Unwrap<T> = T extends ...`): (name, type parameters,
+    /// body). Collected with `declared_shapes`; references expand to the body
+    conditional_aliases: Vec<(String, Vec<String>, Type)>,
+    /// Names bound by `infer` in the conditional types being lowered, innermost last.
+    /// Types are lowered through shared references, hence the cell
+    infer_scopes: RefCell<Vec<Vec<String>>>,
+    /// Parameter shapes of the constructors and methods of the module's classes:
+    /// (class, member, is_static, shape). Calls to them are fitted to the shape
+    /// (see `fit_args_to_shape`)
+    member_params: Vec<(String, String, bool, ParamShape)>,
+    /// Source spans (raw BytePos) of `readonly` modifiers that a fix can remove:
+    /// (type name, property, lo, hi)
+    readonly_modifiers: Vec<(String, String, u32, u32)>,
+    /// Shapes of locals known from their initializer rather than an annotation
+    /// (`new C()`, `{ ... } as const`), with the span of ` as const` for the latter
+    inferred_shapes: Vec<(LocalId, Type, Option<(u32, u32)>)>,
+    /// Locals holding a readonly array or tuple (`readonly T[]`, `ReadonlyArray<T>`,
+    /// `[...] as const`), with where the readonly-ness comes from
+    readonly_arrays: Vec<(LocalId, ReadonlyOrigin)>,
+    /// Types of top-level `const x = ... as const` values, for `typeof x` in type positions
+    const_values: Vec<(String, Type)>,
+    /// Set while lowering a constructor body, where a class may assign its readonly fields
+    in_constructor: bool,
+    /// File the spans of `diagnostics` and of HIR nodes refer to
+    file_id: FileId,
+    /// Diagnostics found while lowering (writes to readonly properties)
+    diagnostics: Vec<Diagnostic>,
+    /// Paths of the N-API addons the module loads (see `Module::native_addons`)
+    native_addons: Vec<String>,
+    /// Fastify routes registered with a literal path (see `Module::http_routes`)
+    http_routes: Vec<HttpRoute>,
 }
 
 impl LoweringContext {
@@ -81,7 +142,6 @@ impl LoweringContext {
             locals: Vec::new(),
             globals: Vec::new(),
             functions: Vec::new(),
-            func_defaults: Vec::new(),
             classes: Vec::new(),
             class_statics: Vec::new(),
             enums: Vec::new(),
@@ -90,16 +150,44 @@ impl LoweringContext {
             imported_functions: Vec::new(),
             native_modules: Vec::new(),
             builtin_module_aliases: Vec::new(),
+            require_namespaces: Vec::new(),
+            require_imports: Vec::new(),
+            commonjs_exports: Vec::new(),
             type_param_scopes: Vec::new(),
             native_instances: Vec::new(),
             current_class: None,
             extern_func_types: Vec::new(),
             source_file_path: source_file_path.into(),
+            import_meta_relative_path: None,
             exportable_object_vars: HashSet::new(),
+            module_disposables: Vec::new(),
+            declared_shapes: Vec::new(),
+            declared_methods: Vec::new(),
+            declared_unions: Vec::new(),
+            conditional_aliases: Vec::new(),
+            infer_scopes: RefCell::new(Vec::new()),
+            member_params: Vec::new(),
+            readonly_modifiers: Vec::new(),
+            inferred_shapes: Vec::new(),
+            readonly_arrays: Vec::new(),
+            const_values: Vec::new(),
+            in_constructor: false,
+            file_id: FileId::DUMMY,
+            diagnostics: Vec::new(),
+            native_addons: Vec::new(),
+            http_routes: Vec::new(),
         }
     }
 
-    fn fresh_interface(&mut self) -> InterfaceId {
+    /// HIR span of an AST node in the module being lowered
+    fn span(&self, span: swc_common::Span) -> Span {
+        if span.is_dummy() {
+            return Span::DUMMY;
+        }
+        Span::new(self.file_id, span.lo.0.saturating_sub(1), span.hi.0.saturating_sub(1))
+    }
+
+        fn fresh_interface(&mut self) -> InterfaceId {
         let id = self.next_interface_id;
         self.next_interface_id += 1;
         id
@@ -127,6 +215,10 @@ impl LoweringContext {
         self.type_param_scopes.iter().any(|scope| scope.contains(name))
     }
 
+    fn is_inferred(&self, name: &str) -> bool {
+        self.infer_scopes.borrow().iter().any(|scope| scope.iter().any(|n| n == name))
+    }
+
     fn fresh_local(&mut self) -> LocalId {
         let id = self.next_local_id;
         self.next_local_id += 1;
@@ -199,6 +291,28 @@ impl LoweringContext {
             })
     }
 
+    /// Literal type a type name stands for: an enum (the union of its members), an enum
+    /// member `E.A`, or an alias of a union of literals and primitives such as `"fast" | "safe"`
+    fn named_literal_type(&self, name: &str) -> Option<Type> {
+        if let Some((_, members)) = self.lookup_enum(name) {
+            let mut literals: Vec<Type> = members.iter().map(|(_, value)| Type::Literal(enum_literal(value))).collect();
+            return match literals.len() {
+                0 => None,
+                1 => literals.pop(),
+                _ => Some(Type::Union(literals)),
+            };
+        }
+        if let Some((enum_name, member)) = name.rsplit_once('.') {
+            return self.lookup_enum_member(enum_name, member).map(|value| Type::Literal(enum_literal(value)));
+        }
+        self.declared_unions.iter()
+            .find(|(n, _)| n == name)
+            .filter(|(_, members)| {
+                members.iter().all(Type::is_primitive) && members.iter().any(|member| matches!(member, Type::Literal(_)))
+            })
+            .map(|(_, members)| Type::Union(members.clone()))
+    }
+
     fn define_local(&mut self, name: String, ty: Type) -> LocalId {
         let id = self.fresh_local();
         self.locals.push((name, id, ty));
@@ -213,99 +327,173 @@ impl LoweringContext {
         self.locals.iter().rev().find(|(n, _, _)| n == name).map(|(_, _, ty)| ty)
     }
 
-    fn lookup_func(&self, name: &str) -> Option<FuncId> {
-        self.functions.iter().find(|(n, _)| n == name).map(|(_, id)| *id)
-    }
-
-    fn lookup_func_defaults(&self, func_id: FuncId) -> Option<(&[Option<Expr>], &[LocalId])> {
-        self.func_defaults.iter()
-            .find(|(id, _, _)| *id == func_id)
-            .map(|(_, defaults, param_ids)| (defaults.as_slice(), param_ids.as_slice()))
+    /// Find a property of an object type, class or interface (following `extends`).
+    /// Returns the property and the name of the type declaring it (None for type literals).
+    fn lookup_property(&self, ty: &Type, prop: &str) -> Option<(PropertyInfo, Option<String>)> {
+        self.lookup_property_depth(ty, prop, 0)
     }
 
-    /// Substitute parameter references in a default expression.
-    /// Replaces LocalGet(callee_param_id) with the corresponding caller argument expression.
-    fn substitute_param_refs_in_default(expr: &Expr, param_map: &[(LocalId, Expr)]) -> Expr {
-        match expr {
-            Expr::LocalGet(id) => {
-                // Check if this LocalGet references one of the callee's parameters
-                for (param_id, replacement) in param_map {
-                    if id == param_id {
-                        return replacement.clone();
-                    }
-                }
-                // Not a parameter reference - keep as-is
-                expr.clone()
-            }
-            Expr::Array(elements) => {
-                Expr::Array(elements.iter().map(|e| Self::substitute_param_refs_in_default(e, param_map)).collect())
-            }
-            Expr::Object(fields) => {
-                Expr::Object(fields.iter().map(|(k, v)| (k.clone(), Self::substitute_param_refs_in_default(v, param_map))).collect())
-            }
-            Expr::Binary { op, left, right } => {
-                Expr::Binary {
-                    op: *op,
-                    left: Box::new(Self::substitute_param_refs_in_default(left, param_map)),
-                    right: Box::new(Self::substitute_param_refs_in_default(right, param_map)),
-                }
-            }
-            Expr::Compare { op, left, right } => {
-                Expr::Compare {
-                    op: *op,
-                    left: Box::new(Self::substitute_param_refs_in_default(left, param_map)),
-                    right: Box::new(Self::substitute_param_refs_in_default(right, param_map)),
-                }
-            }
-            Expr::Logical { op, left, right } => {
-                Expr::Logical {
-                    op: *op,
-                    left: Box::new(Self::substitute_param_refs_in_default(left, param_map)),
-                    right: Box::new(Self::substitute_param_refs_in_default(right, param_map)),
-                }
+    fn lookup_property_depth(&self, ty: &Type, prop: &str, depth: usize) -> Option<(PropertyInfo, Option<String>)> {
+        if depth > 16 {
+            return None;
+        }
+        match ty {
+            Type::Object(obj) => obj.property(prop).map(|info| (info, obj.name.clone())),
+            Type::Generic { base, type_args } if base == "Readonly" => {
+                let (mut info, owner) = self.lookup_property_depth(type_args.first()?, prop, depth + 1)?;
+                info.readonly = true;
+                Some((info, owner))
             }
-            Expr::Unary { op, operand } => {
-                Expr::Unary {
-                    op: *op,
-                    operand: Box::new(Self::substitute_param_refs_in_default(operand, param_map)),
-                }
+            Type::Generic { base, .. } if mapped::MAPPED_UTILITIES.contains(&base.as_str()) => {
+                self.object_shape_depth(ty, depth + 1)?.property(prop).map(|info| (info, None))
             }
-            Expr::Call { callee, args, type_args } => {
-                Expr::Call {
-                    callee: Box::new(Self::substitute_param_refs_in_default(callee, param_map)),
-                    args: args.iter().map(|a| Self::substitute_param_refs_in_default(a, param_map)).collect(),
-                    type_args: type_args.clone(),
+            Type::Named(name) | Type::Generic { base: name, .. } => {
+                let (_, extends, shape) = self.declared_shapes.iter().find(|(n, _, _)| n == name)?;
+                if let Some(info) = shape.properties.get(prop) {
+                    return Some((info.clone(), Some(name.clone())));
                 }
+                extends.iter()
+                    .find_map(|parent| self.lookup_property_depth(&Type::Named(parent.clone()), prop, depth + 1))
             }
-            Expr::Conditional { condition, then_expr, else_expr } => {
-                Expr::Conditional {
-                    condition: Box::new(Self::substitute_param_refs_in_default(condition, param_map)),
-                    then_expr: Box::new(Self::substitute_param_refs_in_default(then_expr, param_map)),
-                    else_expr: Box::new(Self::substitute_param_refs_in_default(else_expr, param_map)),
+            // Members are merged: the property comes from the first member that has it
+            Type::Intersection(members) => members.iter()
+                .find_map(|member| self.lookup_property_depth(member, prop, depth + 1)),
+            _ => None,
+        }
+    }
+
+    /// Properties of `ty` as a single object type: classes and interfaces are flattened
+    /// across `extends`, intersections merged and mapped utility types evaluated
+    fn object_shape(&self, ty: &Type) -> Option<ObjectType> {
+        self.object_shape_depth(ty, 0)
+    }
+
+    fn object_shape_depth(&self, ty: &Type, depth: usize) -> Option<ObjectType> {
+        if depth > 16 {
+            return None;
+        }
+        match ty {
+            Type::Object(obj) => Some(obj.clone()),
+            Type::Named(name) => {
+                let (_, extends, shape) = self.declared_shapes.iter().find(|(n, _, _)| n == name)?;
+                let mut flattened = ObjectType::default();
+                // Parents declared elsewhere contribute nothing
+                for parent in extends {
+                    if let Some(inherited) = self.object_shape_depth(&Type::Named(parent.clone()), depth + 1) {
+                        flattened.merge(inherited);
+                    }
                 }
+                flattened.merge(shape.clone());
+                Some(flattened)
             }
-            Expr::PropertyGet { object, property } => {
-                Expr::PropertyGet {
-                    object: Box::new(Self::substitute_param_refs_in_default(object, param_map)),
-                    property: property.clone(),
+            Type::Intersection(members) => {
+                let mut merged = ObjectType::default();
+                for member in members {
+                    merged.merge(self.object_shape_depth(member, depth + 1)?);
                 }
+                Some(merged)
             }
-            Expr::IndexGet { object, index } => {
-                Expr::IndexGet {
-                    object: Box::new(Self::substitute_param_refs_in_default(object, param_map)),
-                    index: Box::new(Self::substitute_param_refs_in_default(index, param_map)),
-                }
+            Type::Generic { base, type_args } => {
+                let source = type_args.first().and_then(|arg| self.object_shape_depth(arg, depth + 1));
+                mapped::evaluate_utility(base, type_args, source.as_ref())
             }
-            Expr::New { class_name, args, type_args } => {
-                Expr::New {
-                    class_name: class_name.clone(),
-                    args: args.iter().map(|a| Self::substitute_param_refs_in_default(a, param_map)).collect(),
-                    type_args: type_args.clone(),
-                }
+            _ => None,
+        }
+    }
+
+    /// Model `ty` as a union told apart by its `tag` property, following union type aliases.
+    /// Nullish members are left out: reading the tag of one would already have thrown.
+    fn discriminated_union(&self, ty: &Type, tag: &str) -> Option<DiscriminatedUnion> {
+        let members = self.union_members(ty, 0);
+        if members.len() < 2 {
+            return None;
+        }
+        DiscriminatedUnion::new(tag, members.into_iter().map(|member| {
+            let literal = match self.lookup_property(&member, tag) {
+                Some((PropertyInfo { ty: Type::Literal(literal), .. }, _)) => Some(literal),
+                _ => None,
+            };
+            (member, literal)
+        }))
+    }
+
+    fn union_members(&self, ty: &Type, depth: usize) -> Vec<Type> {
+        let aliased = match ty {
+            Type::Named(name) => self.declared_unions.iter().find(|(n, _)| n == name).map(|(_, members)| members),
+            _ => None,
+        };
+        match (ty, aliased) {
+            _ if depth > 16 => vec![ty.clone()],
+            (Type::Union(members), _) | (_, Some(members)) => {
+                members.iter().flat_map(|member| self.union_members(member, depth + 1)).collect()
             }
-            // Leaf expressions that don't contain LocalGet - return as-is
-            _ => expr.clone(),
+            (Type::Null | Type::Void, _) => Vec::new(),
+            _ => vec![ty.clone()],
+        }
+    }
+
+    /// Whether a class or interface of this module declares or inherits the given method
+    fn has_declared_method(&self, type_name: &str, method: &str) -> bool {
+        self.declared_method_type(type_name, method).is_some()
+    }
+
+    /// Return type of a method a class or interface of this module declares or inherits
+    fn declared_method_type(&self, type_name: &str, method: &str) -> Option<Type> {
+        self.declared_method_type_depth(type_name, method, 0)
+    }
+
+    fn declared_method_type_depth(&self, type_name: &str, method: &str, depth: usize) -> Option<Type> {
+        if depth > 16 {
+            return None;
+        }
+        let declared = self.declared_methods.iter()
+            .filter(|(n, _)| n == type_name)
+            .flat_map(|(_, methods)| methods)
+            .find(|(m, _)| m == method);
+        if let Some((_, ty)) = declared {
+            return Some(ty.clone());
+        }
+        self.declared_shapes.iter()
+            .filter(|(n, _, _)| n == type_name)
+            .flat_map(|(_, extends, _)| extends)
+            .find_map(|parent| self.declared_method_type_depth(parent, method, depth + 1))
+    }
+
+    /// Parameter shape of a method a class of this module declares or inherits
+    fn method_param_shape(&self, class_name: &str, method: &str, is_static: bool) -> Option<ParamShape> {
+        self.member_param_shape_depth(class_name, method, is_static, 0)
+    }
+
+    /// Parameter shape of a class's constructor, inherited from its parent when it declares
+    /// none. A class of this module without a constructor anywhere up its chain takes no arguments
+    fn constructor_param_shape(&self, class_name: &str) -> Option<ParamShape> {
+        self.member_param_shape_depth(class_name, "constructor", false, 0)
+            .or_else(|| self.declared_shapes.iter().any(|(n, _, _)| n == class_name).then(ParamShape::default))
+    }
+
+    fn member_param_shape_depth(&self, class_name: &str, member: &str, is_static: bool, depth: usize) -> Option<ParamShape> {
+        if depth > 16 {
+            return None;
         }
+        let declared = self.member_params.iter()
+            .find(|(c, m, s, _)| c == class_name && m == member && *s == is_static)
+            .map(|(_, _, _, shape)| *shape);
+        if declared.is_some() {
+            return declared;
+        }
+        self.class_parent(class_name)
+            .and_then(|parent| self.member_param_shape_depth(parent, member, is_static, depth + 1))
+    }
+
+    fn class_parent(&self, class_name: &str) -> Option<&str> {
+        self.declared_shapes.iter()
+            .find(|(n, _, _)| n == class_name)
+            .and_then(|(_, extends, _)| extends.first())
+            .map(String::as_str)
+    }
+
+    fn lookup_func(&self, name: &str) -> Option<FuncId> {
+        self.functions.iter().find(|(n, _)| n == name).map(|(_, id)| *id)
     }
 
     fn lookup_imported_func(&self, name: &str) -> Option<&str> {
@@ -345,6 +533,35 @@ impl LoweringContext {
         self.builtin_module_aliases.iter().find(|(n, _)| n == name).map(|(_, m)| m.as_str())
     }
 
+    fn register_require_namespace(&mut self, local_name: String, source: String) {
+        self.require_namespaces.push((local_name, source));
+    }
+
+    /// Look up a require() module binding, unless it is shadowed by a local variable
+    fn lookup_require_namespace(&self, name: &str) -> Option<String> {
+        if self.lookup_local(name).is_some() {
+            return None;
+        }
+        self.require_namespaces.iter().rev().find(|(n, _)| n == name).map(|(_, s)| s.clone())
+    }
+
+    /// Record a require()'d module as an import, optionally with one binding from it
+    fn register_require_import(&mut self, source: &str, specifier: Option<ImportSpecifier>) {
+        let idx = match self.require_imports.iter().position(|(s, _)| s == source) {
+            Some(idx) => idx,
+            None => {
+                self.require_imports.push((source.to_string(), Vec::new()));
+                self.require_imports.len() - 1
+            }
+        };
+        if let Some(spec) = specifier {
+            let specs = &mut self.require_imports[idx].1;
+            if !specs.contains(&spec) {
+                specs.push(spec);
+            }
+        }
+    }
+
     fn register_native_instance(&mut self, local_name: String, module_name: String, class_name: String) {
         self.native_instances.push((local_name, module_name, class_name));
     }
@@ -385,7 +602,7 @@ fn extract_type_params(decl: &ast::TsTypeParamDecl) -> Vec<TypeParam> {
 
 /// Extract a Type from an SWC TypeScript type annotation
 /// This version doesn't have access to type parameter context
-fn extract_ts_type(ts_type: &ast::TsType) -> Type {
+pub(crate) fn extract_ts_type(ts_type: &ast::TsType) -> Type {
     extract_ts_type_with_ctx(ts_type, None)
 }
 
@@ -439,9 +656,8 @@ fn extract_ts_type_with_ctx(ts_type: &ast::TsType, ctx: Option<&LoweringContext>
                         .collect();
                     Type::Union(types)
                 }
-                ast::TsUnionOrIntersectionType::TsIntersectionType(_) => {
-                    // Intersection types are complex - treat as Any for now
-                    Type::Any
+                ast::TsUnionOrIntersectionType::TsIntersectionType(intersection) => {
+                    Type::intersection(intersection.types.iter().map(|t| extract_ts_type_with_ctx(t, ctx)))
                 }
             }
         }
@@ -456,17 +672,35 @@ fn extract_ts_type_with_ctx(ts_type: &ast::TsType, ctx: Option<&LoweringContext>
                 }
             };
 
-            // First check if this is a type parameter reference (like T, K, V)
+            // First check if this is a type parameter reference (like T, K, V), or a
+            // variable bound by `infer` in an enclosing conditional type
             if let Some(context) = ctx {
+                if context.is_inferred(&name) {
+                    return Type::Infer(name);
+                }
                 if context.is_type_param(&name) {
                     return Type::TypeVar(name);
                 }
+                if let Some((_, params, body)) = context.conditional_aliases.iter().find(|(alias, _, _)| *alias == name) {
+                    let args: Vec<Type> = type_ref.type_params.iter()
+                        .flat_map(|args| args.params.iter())
+                        .map(|arg| extract_ts_type_with_ctx(arg, ctx))
+                        .collect();
+                    if args.len() == params.len() {
+                        return body
+                            .map_types(&mut |ty| match ty {
+                                Type::TypeVar(param) => params.iter().position(|p| p == param).map(|i| args[i].clone()),
+                                _ => None,
+                            })
+                            .evaluate_conditionals();
+                    }
+                }
             }
 
             // Check for built-in generic types or generic instantiations
             if let Some(type_params) = &type_ref.type_params {
                 match name.as_str() {
-                    "Array" if !type_params.params.is_empty() => {
+                    "Array" | "ReadonlyArray" if !type_params.params.is_empty() => {
                         let elem_type = extract_ts_type_with_ctx(&type_params.params[0], ctx);
                         return Type::Array(Box::new(elem_type));
                     }
@@ -476,19 +710,44 @@ fn extract_ts_type_with_ctx(ts_type: &ast::TsType, ctx: Option<&LoweringContext>
                     }
                     _ => {
                         // Generic type instantiation (e.g., Box<number>, Map<string, number>)
+                        // The key arguments of mapped utilities and the arguments of
+                        // Exclude/Extract keep their literal types
+                        let is_key_arg = |i: usize| match name.as_str() {
+                            "Pick" | "Omit" => i == 1,
+                            "Record" => i == 0,
+                            "Exclude" | "Extract" => true,
+                            _ => false,
+                        };
                         let type_args: Vec<Type> = type_params
                             .params
                             .iter()
-                            .map(|t| extract_ts_type_with_ctx(t, ctx))
+                            .enumerate()
+                            .map(|(i, t)| if is_key_arg(i) {
+                                extract_key_type(t, ctx)
+                            } else {
+                                extract_ts_type_with_ctx(t, ctx)
+                            })
                             .collect();
-                        return Type::Generic {
+                        let is_conditional = conditional::CONDITIONAL_UTILITIES.contains(&name.as_str());
+                        let generic = Type::Generic {
                             base: name,
                             type_args,
                         };
+                        return if is_conditional { generic.evaluate_conditionals() } else { generic };
                     }
                 }
             }
 
+            // Enums, enum members and aliases of literal unions keep their literal types
+            if let Some(ty) = ctx.and_then(|context| context.named_literal_type(&name)) {
+                return ty;
+            }
+
+            // Temporal values are perry/temporal handles, kept NaN-boxed
+            if name.starts_with("Temporal.") {
+                return Type::Any;
+            }
+
             Type::Named(name)
         }
 
@@ -522,13 +781,10 @@ fn extract_ts_type_with_ctx(ts_type: &ast::TsType, ctx: Option<&LoweringContext>
             }
         }
 
-        // Literal types: "foo", 42, true
+        // Literal types: "foo", 42, true. Template literal types widen to string.
         TsLitType(lit) => match &lit.lit {
-            ast::TsLit::Number(_) => Type::Number,
-            ast::TsLit::Str(_) => Type::String,
-            ast::TsLit::Bool(_) => Type::Boolean,
             ast::TsLit::BigInt(_) => Type::BigInt,
-            ast::TsLit::Tpl(_) => Type::String,
+            other => literal_type(other).map(Type::Literal).unwrap_or(Type::String),
         },
 
         // Parenthesized type: (T)
@@ -540,20 +796,37 @@ fn extract_ts_type_with_ctx(ts_type: &ast::TsType, ctx: Option<&LoweringContext>
         // Rest type: ...T
         TsRestType(rest) => extract_ts_type_with_ctx(&rest.type_ann, ctx),
 
-        // Type query: typeof x
-        TsTypeQuery(_) => Type::Any,
+        // Type query: typeof x, known for top-level `as const` values
+        TsTypeQuery(query) => match (&query.expr_name, ctx) {
+            (ast::TsTypeQueryExpr::TsEntityName(ast::TsEntityName::Ident(ident)), Some(ctx)) => ctx.const_values.iter()
+                .find(|(name, _)| name == ident.sym.as_ref())
+                .map(|(_, ty)| ty.clone())
+                .unwrap_or(Type::Any),
+            _ => Type::Any,
+        },
 
         // Conditional type: T extends U ? X : Y
-        TsConditionalType(_) => Type::Any,
+        TsConditionalType(cond) => extract_conditional_type(cond, ctx),
 
         // Mapped type: { [K in T]: U }
-        TsMappedType(_) => Type::Any,
+        TsMappedType(mapped) => extract_mapped_type(mapped, ctx).map(Type::Object).unwrap_or(Type::Any),
 
         // Index access: T[K]
-        TsIndexedAccessType(_) => Type::Any,
+        TsIndexedAccessType(access) => {
+            let object = extract_ts_type_with_ctx(&access.obj_type, ctx);
+            let index_type = extract_key_type(&access.index_type, ctx);
+            let resolved = indexed::index(&object, &index_type, &|ty| lookup_shape(ty, ctx));
+            resolved.unwrap_or_else(|| {
+                if object.depends_on_type_params() || index_type.depends_on_type_params() {
+                    Type::IndexedAccess { object: Box::new(object), index: Box::new(index_type) }
+                } else {
+                    Type::Any
+                }
+            })
+        }
 
         // Infer type: infer T
-        TsInferType(_) => Type::Any,
+        TsInferType(infer) => Type::Infer(infer.type_param.name.sym.to_string()),
 
         // this type
         TsThisType(_) => Type::Any,
@@ -565,13 +838,77 @@ fn extract_ts_type_with_ctx(ts_type: &ast::TsType, ctx: Option<&LoweringContext>
         TsImportType(_) => Type::Any,
 
         // Type operator: keyof T, readonly T, unique symbol
-        TsTypeOperator(_) => Type::Any,
+        TsTypeOperator(op) => match op.op {
+            ast::TsTypeOperatorOp::KeyOf => {
+                let source = extract_ts_type_with_ctx(&op.type_ann, ctx);
+                indexed::key_of(&source, &|ty| lookup_shape(ty, ctx)).unwrap_or_else(|| {
+                    if source.depends_on_type_params() { Type::KeyOf(Box::new(source)) } else { Type::Any }
+                })
+            }
+            ast::TsTypeOperatorOp::ReadOnly => extract_ts_type_with_ctx(&op.type_ann, ctx),
+            ast::TsTypeOperatorOp::Unique => Type::Symbol,
+        },
+
+        // Type literal: { a: T, readonly b?: U }
+        TsTypeLit(lit) => Type::Object(extract_object_type(&lit.members, ctx)),
+    }
+}
 
-        // Type literal: { a: T, b: U }
-        TsTypeLit(_) => Type::Any, // TODO: Extract to ObjectType
+/// Object shape of a type for `keyof` and indexed access; only object literal types are
+/// known without a lowering context
+fn lookup_shape(ty: &Type, ctx: Option<&LoweringContext>) -> Option<ObjectType> {
+    match ctx {
+        Some(ctx) => ctx.object_shape(ty),
+        None => indexed::structural_shape(ty),
     }
 }
 
+/// Lower `T extends U ? X : Y`, evaluated right away unless it depends on type
+/// parameters. The true branch refers to the variables `U` binds with `infer` as
+/// `Type::Infer`, and when `T` is a bare type parameter both branches refer to it that
+/// way too, since a distributive conditional binds it to each union member in turn.
+fn extract_conditional_type(cond: &ast::TsConditionalType, ctx: Option<&LoweringContext>) -> Type {
+    let check = extract_ts_type_with_ctx(&cond.check_type, ctx);
+    let extends = extract_ts_type_with_ctx(&cond.extends_type, ctx);
+    let mut inferred = Vec::new();
+    extends.map_types(&mut |ty| {
+        if let Type::Infer(name) = ty {
+            inferred.push(name.clone());
+        }
+        None
+    });
+
+    if let Some(context) = ctx {
+        context.infer_scopes.borrow_mut().push(inferred.clone());
+    }
+    let true_type = extract_ts_type_with_ctx(&cond.true_type, ctx);
+    if let Some(context) = ctx {
+        context.infer_scopes.borrow_mut().pop();
+    }
+    let false_type = extract_ts_type_with_ctx(&cond.false_type, ctx);
+
+    let distributive = match &check {
+        Type::TypeVar(name) => Some(name.clone()),
+        _ => None,
+    };
+    let to_infer = |ty: &Type, names: &[String]| ty.map_types(&mut |ty| match ty {
+        // Without a context, references to inferred names were lowered as named types
+        Type::TypeVar(name) | Type::Named(name) if names.contains(name) => Some(Type::Infer(name.clone())),
+        _ => None,
+    });
+    let branch_names: Vec<String> = distributive.iter().cloned().collect();
+    let mut true_names = inferred;
+    true_names.extend(branch_names.iter().cloned());
+    let cond = ConditionalType {
+        true_type: to_infer(&true_type, &true_names),
+        false_type: to_infer(&false_type, &branch_names),
+        check,
+        extends,
+        distributive,
+    };
+    cond.evaluate().unwrap_or_else(|| Type::Conditional(Box::new(cond)))
+}
+
 /// Helper to get name from TsEntityName
 fn get_ts_entity_name(entity: &ast::TsEntityName) -> String {
     match entity {
@@ -685,7 +1022,7 @@ fn extract_param_type(pat: &ast::Pat) -> Type {
 }
 
 /// Alias for parameter type extraction with context
-fn extract_param_type_with_ctx(pat: &ast::Pat, ctx: Option<&LoweringContext>) -> Type {
+pub(crate) fn extract_param_type_with_ctx(pat: &ast::Pat, ctx: Option<&LoweringContext>) -> Type {
     extract_pattern_type_with_ctx(pat, ctx)
 }
 
@@ -738,11 +1075,74 @@ fn lower_decorators(_ctx: &mut LoweringContext, decorators: &[ast::Decorator]) -
     }).collect()
 }
 
+/// The `"use memoize"` / `"use memoize(maxEntries, ttlMs)"` directive in a function body's
+/// prologue, as the `@memoize` decorator it stands for (decorators can't go on functions)
+fn memoize_directive(body: Option<&ast::BlockStmt>) -> Result<Option<Decorator>> {
+    let prologue = body.into_iter().flat_map(|block| &block.stmts).map_while(|stmt| match stmt {
+        ast::Stmt::Expr(expr_stmt) => match expr_stmt.expr.as_ref() {
+            ast::Expr::Lit(ast::Lit::Str(s)) => Some(s.value.as_str().unwrap_or("").to_string()),
+            _ => None,
+        },
+        _ => None,
+    });
+    for directive in prologue {
+        let Some(rest) = directive.trim().strip_prefix("use memoize") else { continue };
+        let rest = rest.trim();
+        let args = if rest.is_empty() {
+            Vec::new()
+        } else {
+            let inner = rest.strip_prefix('(').and_then(|r| r.strip_suffix(')'))
+                .ok_or_else(|| anyhow!("Invalid directive \"{}\": expected \"use memoize\" or \"use memoize(maxEntries, ttlMs)\"", directive))?;
+            inner.split(',').map(|arg| arg.trim().parse::<u64>().map(|n| Expr::Integer(n as i64)))
+                .collect::<std::result::Result<Vec<_>, _>>()
+                .ok().filter(|args| args.len() <= 2)
+                .ok_or_else(|| anyhow!("Invalid directive \"{}\": memoize takes up to two whole numbers, the most entries to keep and their lifetime in milliseconds", directive))?
+        };
+        return Ok(Some(Decorator { name: "memoize".to_string(), args }));
+    }
+    Ok(None)
+}
+
+/// Options that affect how a module is lowered
+#[derive(Debug, Clone, Default)]
+pub struct LowerOptions {
+    /// When set, import.meta.url/filename/dirname/resolve are computed at runtime relative to
+    /// the executable's directory, using the source path relative to this project root.
+    /// Otherwise they are baked in from the absolute source path at compile time.
+    pub import_meta_exe_root: Option<String>,
+    /// File id for the spans of HIR nodes and of diagnostics reported while lowering
+    /// (`Module::diagnostics`)
+    pub file_id: Option<FileId>,
+    /// Typings of npm packages, keyed by package name; imports from these packages get
+    /// the declared signatures
+    pub declarations: BTreeMap<String, PackageDeclarations>,
+}
+
 /// Lower an SWC Module to HIR Module
 ///
 /// `source_file_path` should be the absolute path to the source file for import.meta.url support.
 pub fn lower_module(ast_module: &ast::Module, name: &str, source_file_path: &str) -> Result<Module> {
+    lower_module_with_options(ast_module, name, source_file_path, &LowerOptions::default())
+}
+
+/// Lower an SWC Module to HIR Module with explicit lowering options
+pub fn lower_module_with_options(
+    ast_module: &ast::Module,
+    name: &str,
+    source_file_path: &str,
+    options: &LowerOptions,
+) -> Result<Module> {
     let mut ctx = LoweringContext::new(source_file_path);
+    if let Some(root) = &options.import_meta_exe_root {
+        let source = std::path::Path::new(source_file_path);
+        let relative = source.strip_prefix(root).ok()
+            .or_else(|| source.file_name().map(std::path::Path::new))
+            .unwrap_or(source);
+        ctx.import_meta_relative_path = Some(relative.to_string_lossy().into_owned());
+    }
+    ctx.file_id = options.file_id.unwrap_or(FileId::DUMMY);
+    collect_declared_shapes(&mut ctx, ast_module);
+    register_declared_imports(&mut ctx, ast_module, &options.declarations);
     let mut module = Module::new(name);
 
     // Pre-scan: Find all function names that have implementations (bodies)
@@ -834,6 +1234,54 @@ pub fn lower_module(ast_module: &ast::Module, name: &str, source_file_path: &str
         }
     }
 
+    // Top-level `using`: dispose at the end of module init, innermost (last declared) first
+    for (start, id, ty, is_await) in std::mem::take(&mut ctx.module_disposables).into_iter().rev() {
+        let body = module.init.split_off(start);
+        let scope = using_scope(&mut ctx, body, id, ty, is_await);
+        module.init.extend(scope);
+    }
+
+    // CommonJS modules without `module.exports = value` still have module.exports as a
+    // whole: an object of their named exports, which `const lib = require(...)` and value
+    // uses of `lib` see as the default export
+    let commonjs_exports = std::mem::take(&mut ctx.commonjs_exports);
+    let has_default = module.exports.iter().any(|e| matches!(e, Export::Named { exported, .. } if exported == "default"));
+    if !commonjs_exports.is_empty() && !has_default {
+        let mut props = Vec::new();
+        for exported in commonjs_exports {
+            let local = module.exports.iter().find_map(|e| match e {
+                Export::Named { local, exported: name } if *name == exported => Some(local.clone()),
+                _ => None,
+            });
+            let value = match local {
+                Some(local) => match (ctx.lookup_local(&local), ctx.lookup_func(&local)) {
+                    (Some(id), _) => Expr::LocalGet(id),
+                    (None, Some(id)) => Expr::FuncRef(id),
+                    (None, None) => continue,
+                },
+                None => continue,
+            };
+            props.push((exported, value));
+        }
+        let name = "__module_exports".to_string();
+        let id = ctx.define_local(name.clone(), Type::Any);
+        module.init.push(Stmt::Let { id, name: name.clone(), ty: Type::Any, mutable: false, init: Some(Expr::Object(props)) });
+        export_local_binding(&ctx, &mut module, &name, "default");
+    }
+
+    // Imports discovered from require() calls
+    for (source, specifiers) in std::mem::take(&mut ctx.require_imports) {
+        let is_native = is_native_module(&source);
+        module.imports.push(Import {
+            source,
+            specifiers,
+            is_native,
+            module_kind: if is_native { ModuleKind::NativeRust } else { ModuleKind::NativeCompiled },
+            resolved_path: None, // Will be set by compiler driver during module resolution
+            is_require: true,
+        });
+    }
+
     // Populate exported_native_instances by matching native_instances with exports
     for (local_name, module_name, class_name) in &ctx.native_instances {
         // Check if this native instance is exported
@@ -850,6 +1298,12 @@ pub fn lower_module(ast_module: &ast::Module, name: &str, source_file_path: &str
         }
     }
 
+    collect_exported_constants(&mut module, ast_module);
+    module.diagnostics = std::mem::take(&mut ctx.diagnostics);
+    module.native_addons = std::mem::take(&mut ctx.native_addons);
+    module.native_addons.sort();
+    module.native_addons.dedup();
+    module.http_routes = std::mem::take(&mut ctx.http_routes);
     Ok(module)
 }
 
@@ -865,6 +1319,40 @@ fn lower_module_decl(
             // Normalize "node:" prefix (e.g., "node:async_hooks" -> "async_hooks")
             let source = raw_source.strip_prefix("node:").unwrap_or(&raw_source).to_string();
 
+            // N-API addon: each binding is a variable holding the exports object or one of its
+            // properties, like `const addon = require('./addon.node')`
+            if let Some(path) = native_addon_path(ctx, &source) {
+                ctx.native_addons.push(path.clone());
+                for spec in &import_decl.specifiers {
+                    let (local, init) = match spec {
+                        ast::ImportSpecifier::Named(named) => {
+                            let imported = match &named.imported {
+                                Some(ast::ModuleExportName::Ident(id)) => id.sym.to_string(),
+                                Some(ast::ModuleExportName::Str(s)) => s.value.as_str().unwrap_or("").to_string(),
+                                None => named.local.sym.to_string(),
+                            };
+                            let init = Expr::PropertyGet {
+                                object: Box::new(Expr::NativeAddonLoad { path: path.clone() }),
+                                property: imported,
+                            };
+                            (named.local.sym.to_string(), init)
+                        }
+                        ast::ImportSpecifier::Default(default) => {
+                            (default.local.sym.to_string(), Expr::NativeAddonLoad { path: path.clone() })
+                        }
+                        ast::ImportSpecifier::Namespace(ns) => {
+                            (ns.local.sym.to_string(), Expr::NativeAddonLoad { path: path.clone() })
+                        }
+                    };
+                    let id = ctx.define_local(local.clone(), Type::Any);
+                    module.init.push(Stmt::Let { id, name: local, ty: Type::Any, mutable: false, init: Some(init) });
+                }
+                if import_decl.specifiers.is_empty() {
+                    module.init.push(Stmt::Expr(Expr::NativeAddonLoad { path }));
+                }
+                return Ok(());
+            }
+
             // Check if this is a native module import
             let is_native = is_native_module(&source);
 
@@ -934,19 +1422,19 @@ fn lower_module_decl(
                 is_native,
                 module_kind,
                 resolved_path: None, // Will be set by compiler driver during module resolution
+                is_require: false,
             });
         }
         ast::ModuleDecl::ExportDecl(export) => {
             match &export.decl {
+                // export declare function: implemented natively (a `perry bindgen` module),
+                // calls link against its wrapper symbol like any other FFI declaration
+                ast::Decl::Fn(fn_decl) if fn_decl.function.body.is_none() => {}
                 ast::Decl::Fn(fn_decl) => {
                     let mut func = lower_fn_decl(ctx, fn_decl)?;
                     func.is_exported = true;
                     let func_name = func.name.clone();
                     let func_id = func.id;
-                    // Store parameter defaults for call-site resolution
-                    let defaults: Vec<Option<Expr>> = func.params.iter().map(|p| p.default.clone()).collect();
-                    let param_ids: Vec<LocalId> = func.params.iter().map(|p| p.id).collect();
-                    ctx.func_defaults.push((func.id, defaults, param_ids));
                     module.functions.push(func);
                     // Track in exports
                     module.exports.push(Export::Named {
@@ -1060,19 +1548,18 @@ fn lower_module_decl(
                                 }
                             }
 
-                            // Track exported values that need cross-module access
-                            // Include: object literals, call expressions (e.g., Router()), array literals,
-                            // new expressions (e.g., new Router()), and arrow functions (e.g., () => {})
-                            let needs_export_global = matches!(init.as_ref(),
-                                ast::Expr::Object(_) |
-                                ast::Expr::Call(_) |
-                                ast::Expr::Array(_) |
-                                ast::Expr::New(_) |
-                                ast::Expr::Arrow(_)
-                            );
+                            // Every exported variable gets a cross-module global (importers read
+                            // it on each access, which keeps the binding live), except aliases of
+                            // functions, which are exported through exported_functions below
+                            let needs_export_global = match init.as_ref() {
+                                ast::Expr::Ident(ident) => ctx.lookup_func(ident.sym.as_ref()).is_none(),
+                                _ => true,
+                            };
 
                             let expr = lower_expr(ctx, init)?;
                             let id = ctx.define_local(name.clone(), ty.clone());
+                            record_inferred_shape(ctx, id, &ty, init);
+                            record_readonly_array(ctx, id, pat_type_ann(&decl.name), Some(init.as_ref()));
                             module.init.push(Stmt::Let {
                                 id,
                                 name: name.clone(),
@@ -1106,6 +1593,8 @@ fn lower_module_decl(
                     let class = lower_class_decl(ctx, class_decl, true)?;
                     let class_name = class.name.clone();
                     module.classes.push(class);
+                    let providers = lower_injectable(ctx, &class_name, &class_decl.class)?;
+                    module.init.extend(providers);
                     module.exports.push(Export::Named {
                         local: class_name.clone(),
                         exported: class_name,
@@ -1114,6 +1603,10 @@ fn lower_module_decl(
                 ast::Decl::TsEnum(enum_decl) => {
                     let en = lower_enum_decl(ctx, enum_decl, true)?;
                     let enum_name = en.name.clone();
+                    if let Some(stmt) = lower_enum_object(ctx, enum_decl, &en) {
+                        module.init.push(stmt);
+                        module.exported_objects.push(enum_name.clone());
+                    }
                     module.enums.push(en);
                     module.exports.push(Export::Named {
                         local: enum_name.clone(),
@@ -1123,11 +1616,15 @@ fn lower_module_decl(
                 ast::Decl::TsInterface(iface_decl) => {
                     let iface = lower_interface_decl(ctx, iface_decl, true)?;
                     let iface_name = iface.name.clone();
-                    module.interfaces.push(iface);
-                    module.exports.push(Export::Named {
-                        local: iface_name.clone(),
-                        exported: iface_name,
-                    });
+                    let exported = module.exports.iter()
+                        .any(|export| matches!(export, Export::Named { exported, .. } if *exported == iface_name));
+                    module.add_interface(iface);
+                    if !exported {
+                        module.exports.push(Export::Named {
+                            local: iface_name.clone(),
+                            exported: iface_name,
+                        });
+                    }
                 }
                 ast::Decl::TsTypeAlias(alias_decl) => {
                     let alias = lower_type_alias_decl(ctx, alias_decl, true)?;
@@ -1182,34 +1679,7 @@ fn lower_module_decl(
                                 ast::ModuleExportName::Str(s) => s.value.as_str().unwrap_or("").to_string(),
                             })
                             .unwrap_or_else(|| local.clone());
-                        module.exports.push(Export::Named { local: local.clone(), exported: exported.clone() });
-
-                        // If the local name refers to a function, add it to exported_functions
-                        // so that a wrapper function is generated for cross-module calls
-                        if let Some(func_id) = ctx.lookup_func(&local) {
-                            module.exported_functions.push((exported.clone(), func_id));
-                        }
-
-                        // Check if the variable is a closure or other exportable object
-                        // by looking through init statements
-                        for stmt in &module.init {
-                            if let Stmt::Let { name, init: Some(init_expr), .. } = stmt {
-                                if name == &local {
-                                    let is_exportable = matches!(init_expr,
-                                        Expr::Closure { .. } |
-                                        Expr::Object(_) |
-                                        Expr::Array(_) |
-                                        Expr::Call { .. } |
-                                        Expr::New { .. } |
-                                        Expr::JsNew { .. }
-                                    );
-                                    if is_exportable {
-                                        module.exported_objects.push(exported.clone());
-                                    }
-                                    break;
-                                }
-                            }
-                        }
+                        export_local_binding(ctx, module, &local, &exported);
                     }
                 }
             }
@@ -1218,27 +1688,37 @@ fn lower_module_decl(
             // export default function foo() {} or export default class Foo {}
             match &export_default.decl {
                 ast::DefaultDecl::Fn(fn_expr) => {
-                    if let Some(ref ident) = fn_expr.ident {
-                        // Named function: export default function foo() {}
-                        // Create a function and mark it as default export
-                        let func_name = ident.sym.to_string();
-                        // TODO: properly lower function expression
-                        module.exports.push(Export::Named {
-                            local: func_name,
-                            exported: "default".to_string(),
-                        });
+                    let Some(ref ident) = fn_expr.ident else {
+                        report_dropped(ctx, export_default.span, "anonymous default-exported function", "name it: `export default function handler() {}`");
+                        return Ok(());
+                    };
+                    // Named function: export default function foo() {}
+                    let func_name = ident.sym.to_string();
+                    if ctx.lookup_func(&func_name).is_none() {
+                        let func_id = ctx.fresh_func();
+                        ctx.functions.push((func_name.clone(), func_id));
                     }
+                    let fn_decl = ast::FnDecl { ident: ident.clone(), declare: false, function: fn_expr.function.clone() };
+                    module.functions.push(lower_fn_decl(ctx, &fn_decl)?);
+                    export_local_binding(ctx, module, &func_name, "default");
                 }
                 ast::DefaultDecl::Class(class_expr) => {
-                    if let Some(ref ident) = class_expr.ident {
-                        let class_name = ident.sym.to_string();
-                        module.exports.push(Export::Named {
-                            local: class_name,
-                            exported: "default".to_string(),
-                        });
-                    }
+                    let Some(ref ident) = class_expr.ident else {
+                        report_dropped(ctx, export_default.span, "anonymous default-exported class", "name it: `export default class Service {}`");
+                        return Ok(());
+                    };
+                    let class_decl = ast::ClassDecl { ident: ident.clone(), declare: false, class: class_expr.class.clone() };
+                    let class = lower_class_decl(ctx, &class_decl, true)?;
+                    let class_name = class.name.clone();
+                    module.classes.push(class);
+                    let providers = lower_injectable(ctx, &class_name, &class_decl.class)?;
+                    module.init.extend(providers);
+                    module.exports.push(Export::Named {
+                        local: class_name,
+                        exported: "default".to_string(),
+                    });
                 }
-                _ => {}
+                ast::DefaultDecl::TsInterfaceDecl(_) => {}
             }
         }
         ast::ModuleDecl::ExportAll(export_all) => {
@@ -1246,13 +1726,40 @@ fn lower_module_decl(
             let source = export_all.src.value.as_str().unwrap_or("").to_string();
             module.exports.push(Export::ExportAll { source });
         }
-        ast::ModuleDecl::ExportDefaultExpr(_) => {
-            // export default expr
-            // TODO: handle this case
+        ast::ModuleDecl::ExportDefaultExpr(export_default) => {
+            if let ast::Expr::Ident(ident) = unwrap_parens(&export_default.expr) {
+                // export default foo - like export { foo as default }
+                export_local_binding(ctx, module, ident.sym.as_ref(), "default");
+            } else {
+                // export default <expr> - like const __default = <expr>; export { __default as default }
+                let value = lower_expr(ctx, &export_default.expr)?;
+                let id = ctx.define_local(DEFAULT_EXPORT_LOCAL.to_string(), Type::Any);
+                module.init.push(Stmt::Let {
+                    id,
+                    name: DEFAULT_EXPORT_LOCAL.to_string(),
+                    ty: Type::Any,
+                    mutable: false,
+                    init: Some(value),
+                });
+                export_local_binding(ctx, module, DEFAULT_EXPORT_LOCAL, "default");
+            }
         }
-        _ => {
-            // TsImportEquals, TsExportAssignment, TsNamespaceExport - TypeScript specific
+        ast::ModuleDecl::TsImportEquals(import) => match &import.module_ref {
+            // import fs = require('fs') - like const fs = require('fs')
+            ast::TsModuleRef::TsExternalModuleRef(module_ref) if !import.is_type_only => {
+                let source = module_ref.expr.value.as_str().unwrap_or("").to_string();
+                bind_require_namespace(ctx, import.id.sym.as_ref(), &source);
+            }
+            ast::TsModuleRef::TsExternalModuleRef(_) => {}
+            ast::TsModuleRef::TsEntityName(_) => {
+                report_dropped(ctx, import.span, "`import =` alias", "bind the value with `const` instead");
+            }
+        },
+        ast::ModuleDecl::TsExportAssignment(export) => {
+            report_dropped(ctx, export.span, "`export =` assignment", "use `export default` instead");
         }
+        // export as namespace X - only names a global for UMD consumers
+        ast::ModuleDecl::TsNamespaceExport(_) => {}
     }
     Ok(())
 }
@@ -1271,10 +1778,6 @@ fn lower_stmt(
                         return Ok(());
                     }
                     let func = lower_fn_decl(ctx, fn_decl)?;
-                    // Store parameter defaults for call-site resolution
-                    let defaults: Vec<Option<Expr>> = func.params.iter().map(|p| p.default.clone()).collect();
-                    let param_ids: Vec<LocalId> = func.params.iter().map(|p| p.id).collect();
-                    ctx.func_defaults.push((func.id, defaults, param_ids));
                     module.functions.push(func);
                 }
                 ast::Decl::Var(var_decl) => {
@@ -1286,20 +1789,39 @@ fn lower_stmt(
                 }
                 ast::Decl::Class(class_decl) => {
                     let class = lower_class_decl(ctx, class_decl, false)?;
+                    let providers = lower_injectable(ctx, &class.name, &class_decl.class)?;
                     module.classes.push(class);
+                    module.init.extend(providers);
                 }
                 ast::Decl::TsEnum(enum_decl) => {
                     let en = lower_enum_decl(ctx, enum_decl, false)?;
+                    if let Some(stmt) = lower_enum_object(ctx, enum_decl, &en) {
+                        module.init.push(stmt);
+                    }
                     module.enums.push(en);
                 }
                 ast::Decl::TsInterface(iface_decl) => {
                     let iface = lower_interface_decl(ctx, iface_decl, false)?;
-                    module.interfaces.push(iface);
+                    module.add_interface(iface);
+                }
+                ast::Decl::TsModule(module_decl) => {
+                    for iface_decl in augmented_interfaces(module_decl) {
+                        let iface = lower_interface_decl(ctx, iface_decl, false)?;
+                        module.add_interface(iface);
+                    }
+                    check_namespace_values(ctx, module_decl);
                 }
                 ast::Decl::TsTypeAlias(alias_decl) => {
                     let alias = lower_type_alias_decl(ctx, alias_decl, false)?;
                     module.type_aliases.push(alias);
                 }
+                ast::Decl::Using(using_decl) => {
+                    for decl in &using_decl.decls {
+                        let (stmts, id, ty) = lower_using_binding(ctx, decl)?;
+                        module.init.extend(stmts);
+                        ctx.module_disposables.push((module.init.len(), id, ty, using_decl.is_await));
+                    }
+                }
                 _ => {}
             }
         }
@@ -1313,21 +1835,21 @@ fn lower_stmt(
                     module.init.extend(stmts);
                     return Ok(());
                 }
+                // module.exports = ... / exports.foo = ...
+                if lower_commonjs_export(ctx, module, assign)? {
+                    return Ok(());
+                }
+            }
+            // require('./module') for its side effects only
+            if let Some(source) = require_call_source(ctx, &expr_stmt.expr) {
+                ctx.register_require_import(&source, None);
+                return Ok(());
             }
             let expr = lower_expr(ctx, &expr_stmt.expr)?;
             module.init.push(Stmt::Expr(expr));
         }
         ast::Stmt::If(if_stmt) => {
-            let condition = lower_expr(ctx, &if_stmt.test)?;
-            let then_branch = lower_body_stmt(ctx, &if_stmt.cons)?;
-            let else_branch = if_stmt.alt.as_ref()
-                .map(|s| lower_body_stmt(ctx, s))
-                .transpose()?;
-            module.init.push(Stmt::If {
-                condition,
-                then_branch,
-                else_branch,
-            });
+            module.init.push(lower_if(ctx, if_stmt)?);
         }
         ast::Stmt::While(while_stmt) => {
             let condition = lower_expr(ctx, &while_stmt.test)?;
@@ -1406,322 +1928,42 @@ fn lower_stmt(
                 None
             };
 
-            module.init.push(Stmt::Try { body, catch, finally });
+            module.init.extend(lower_try(ctx, body, catch, finally));
         }
         ast::Stmt::Throw(throw_stmt) => {
             let expr = lower_expr(ctx, &throw_stmt.arg)?;
             module.init.push(Stmt::Throw(expr));
         }
         ast::Stmt::Switch(switch_stmt) => {
-            let discriminant = lower_expr(ctx, &switch_stmt.discriminant)?;
-            let mut cases = Vec::new();
-
-            for case in &switch_stmt.cases {
-                let test = case.test.as_ref()
-                    .map(|e| lower_expr(ctx, e))
-                    .transpose()?;
-
-                let mut body = Vec::new();
-                for stmt in &case.cons {
-                    body.extend(lower_body_stmt(ctx, stmt)?);
-                }
-
-                cases.push(SwitchCase { test, body });
-            }
-
-            module.init.push(Stmt::Switch { discriminant, cases });
+            module.init.push(lower_switch(ctx, switch_stmt)?);
         }
         ast::Stmt::ForOf(for_of_stmt) => {
-            // Desugar for-of to a regular for loop:
-            // for (const x of arr) { body }
+            module.init.extend(lower_for_of(ctx, for_of_stmt)?);
+        }
+        ast::Stmt::ForIn(for_in_stmt) => {
+            // Desugar for-in to a for-of over Object.keys(obj):
+            // for (const key in obj) { body }
             // becomes:
-            // { let __arr = arr; for (let __i = 0; __i < __arr.length; __i++) { const x = __arr[__i]; body } }
-
-            // Lower the iterable expression (the array)
-            let arr_expr = lower_expr(ctx, &for_of_stmt.right)?;
-
-            // Create internal variables for the array and index
-            let arr_id = ctx.fresh_local();
-            let idx_id = ctx.fresh_local();
-            // Register these in the context so they can be looked up
-            ctx.locals.push((format!("__arr_{}", arr_id), arr_id, Type::Array(Box::new(Type::Any))));
-            ctx.locals.push((format!("__idx_{}", idx_id), idx_id, Type::Number));
-
-            // Store array reference: let __arr = arr
-            module.init.push(Stmt::Let {
-                id: arr_id,
-                name: format!("__arr_{}", arr_id),
-                ty: Type::Array(Box::new(Type::Any)),
-                mutable: false,
-                init: Some(arr_expr),
-            });
-
-            // IMPORTANT: Define iteration variables BEFORE lowering the body
-            // so the body can reference them
-            let item_id = ctx.fresh_local();
-            ctx.locals.push((format!("__item_{}", item_id), item_id, Type::Any));
+            // { let __keys = Object.keys(obj); for (let __i = 0; __i < __keys.length; __i++) { const key = __keys[__i]; body } }
 
-            // Pre-define all variables from the pattern so body can reference them
-            let var_ids: Vec<(String, u32)> = match &for_of_stmt.left {
+            // Get the iteration variable name
+            let key_name = match &for_in_stmt.left {
                 ast::ForHead::VarDecl(var_decl) => {
                     if let Some(decl) = var_decl.decls.first() {
-                        match &decl.name {
-                            ast::Pat::Ident(ident) => {
-                                let name = ident.id.sym.to_string();
-                                let id = ctx.define_local(name.clone(), Type::Any);
-                                vec![(name, id)]
-                            }
-                            ast::Pat::Array(arr_pat) => {
-                                let mut ids = Vec::new();
-                                for elem in &arr_pat.elems {
-                                    if let Some(elem_pat) = elem {
-                                        if let ast::Pat::Ident(ident) = elem_pat {
-                                            let name = ident.id.sym.to_string();
-                                            let id = ctx.define_local(name.clone(), Type::Any);
-                                            ids.push((name, id));
-                                        }
-                                    }
-                                }
-                                ids
-                            }
-                            ast::Pat::Object(obj_pat) => {
-                                let mut ids = Vec::new();
-                                for prop in &obj_pat.props {
-                                    match prop {
-                                        ast::ObjectPatProp::Assign(assign) => {
-                                            let name = assign.key.sym.to_string();
-                                            let id = ctx.define_local(name.clone(), Type::Any);
-                                            ids.push((name, id));
-                                        }
-                                        ast::ObjectPatProp::KeyValue(kv) => {
-                                            if let ast::Pat::Ident(ident) = &*kv.value {
-                                                let name = ident.id.sym.to_string();
-                                                let id = ctx.define_local(name.clone(), Type::Any);
-                                                ids.push((name, id));
-                                            }
-                                        }
-                                        _ => {}
-                                    }
-                                }
-                                ids
-                            }
-                            _ => {
-                                let name = get_binding_name(&decl.name)?;
-                                let id = ctx.define_local(name.clone(), Type::Any);
-                                vec![(name, id)]
-                            }
-                        }
+                        get_binding_name(&decl.name)?
                     } else {
-                        return Err(anyhow!("for-of requires a variable declaration"));
+                        return Err(anyhow!("for-in requires a variable declaration"));
                     }
                 }
-                ast::ForHead::Pat(pat) => {
-                    let name = get_pat_name(pat)?;
-                    let id = ctx.define_local(name.clone(), Type::Any);
-                    vec![(name, id)]
-                }
-                _ => return Err(anyhow!("Unsupported for-of left-hand side")),
+                ast::ForHead::Pat(pat) => get_pat_name(pat)?,
+                _ => return Err(anyhow!("Unsupported for-in left-hand side")),
             };
 
-            // NOW lower the body - variables are defined so body can reference them
-            let mut loop_body = lower_body_stmt(ctx, &for_of_stmt.body)?;
+            // Lower the object expression
+            let obj_expr = lower_expr(ctx, &for_in_stmt.right)?;
 
-            // Build binding statements using the pre-defined variable IDs
-            let binding_stmts = match &for_of_stmt.left {
-                ast::ForHead::VarDecl(var_decl) => {
-                    if let Some(decl) = var_decl.decls.first() {
-                        let item_expr = Expr::IndexGet {
-                            object: Box::new(Expr::LocalGet(arr_id)),
-                            index: Box::new(Expr::LocalGet(idx_id)),
-                        };
-
-                        match &decl.name {
-                            ast::Pat::Ident(_) => {
-                                // Simple binding: for (const x of arr)
-                                let (name, id) = var_ids[0].clone();
-                                vec![Stmt::Let {
-                                    id,
-                                    name,
-                                    ty: Type::Any,
-                                    mutable: false,
-                                    init: Some(item_expr),
-                                }]
-                            }
-                            ast::Pat::Array(arr_pat) => {
-                                // Array destructuring: for (const [a, b] of arr)
-                                let mut stmts = vec![Stmt::Let {
-                                    id: item_id,
-                                    name: format!("__item_{}", item_id),
-                                    ty: Type::Any,
-                                    mutable: false,
-                                    init: Some(item_expr),
-                                }];
-
-                                // Extract each element using pre-defined IDs
-                                let mut var_idx = 0;
-                                for (idx, elem) in arr_pat.elems.iter().enumerate() {
-                                    if let Some(elem_pat) = elem {
-                                        if let ast::Pat::Ident(_) = elem_pat {
-                                            let (name, id) = var_ids[var_idx].clone();
-                                            var_idx += 1;
-                                            stmts.push(Stmt::Let {
-                                                id,
-                                                name,
-                                                ty: Type::Any,
-                                                mutable: false,
-                                                init: Some(Expr::IndexGet {
-                                                    object: Box::new(Expr::LocalGet(item_id)),
-                                                    index: Box::new(Expr::Number(idx as f64)),
-                                                }),
-                                            });
-                                        }
-                                    }
-                                }
-                                stmts
-                            }
-                            ast::Pat::Object(obj_pat) => {
-                                // Object destructuring: for (const { a, b } of arr)
-                                let mut stmts = vec![Stmt::Let {
-                                    id: item_id,
-                                    name: format!("__item_{}", item_id),
-                                    ty: Type::Any,
-                                    mutable: false,
-                                    init: Some(item_expr),
-                                }];
-
-                                // Extract each property using pre-defined IDs
-                                let mut var_idx = 0;
-                                for prop in &obj_pat.props {
-                                    match prop {
-                                        ast::ObjectPatProp::Assign(assign) => {
-                                            let prop_name = assign.key.sym.to_string();
-                                            let (name, id) = var_ids[var_idx].clone();
-                                            var_idx += 1;
-                                            stmts.push(Stmt::Let {
-                                                id,
-                                                name,
-                                                ty: Type::Any,
-                                                mutable: false,
-                                                init: Some(Expr::PropertyGet {
-                                                    object: Box::new(Expr::LocalGet(item_id)),
-                                                    property: prop_name,
-                                                }),
-                                            });
-                                        }
-                                        ast::ObjectPatProp::KeyValue(kv) => {
-                                            let key = match &kv.key {
-                                                ast::PropName::Ident(ident) => ident.sym.to_string(),
-                                                _ => continue,
-                                            };
-                                            if let ast::Pat::Ident(_) = &*kv.value {
-                                                let (name, id) = var_ids[var_idx].clone();
-                                                var_idx += 1;
-                                                stmts.push(Stmt::Let {
-                                                    id,
-                                                    name,
-                                                    ty: Type::Any,
-                                                    mutable: false,
-                                                    init: Some(Expr::PropertyGet {
-                                                        object: Box::new(Expr::LocalGet(item_id)),
-                                                        property: key,
-                                                    }),
-                                                });
-                                            }
-                                        }
-                                        _ => {}
-                                    }
-                                }
-                                stmts
-                            }
-                            _ => {
-                                let (name, id) = var_ids[0].clone();
-                                vec![Stmt::Let {
-                                    id,
-                                    name,
-                                    ty: Type::Any,
-                                    mutable: false,
-                                    init: Some(Expr::IndexGet {
-                                        object: Box::new(Expr::LocalGet(arr_id)),
-                                        index: Box::new(Expr::LocalGet(idx_id)),
-                                    }),
-                                }]
-                            }
-                        }
-                    } else {
-                        return Err(anyhow!("for-of requires a variable declaration"));
-                    }
-                }
-                ast::ForHead::Pat(_) => {
-                    let (name, id) = var_ids[0].clone();
-                    vec![Stmt::Let {
-                        id,
-                        name,
-                        ty: Type::Any,
-                        mutable: false,
-                        init: Some(Expr::IndexGet {
-                            object: Box::new(Expr::LocalGet(arr_id)),
-                            index: Box::new(Expr::LocalGet(idx_id)),
-                        }),
-                    }]
-                }
-                _ => return Err(anyhow!("Unsupported for-of left-hand side")),
-            };
-
-            // Prepend the binding statements to the loop body
-            for (i, stmt) in binding_stmts.into_iter().enumerate() {
-                loop_body.insert(i, stmt);
-            }
-
-            // Create the for loop:
-            // for (let __i = 0; __i < __arr.length; __i++) { ... }
-            module.init.push(Stmt::For {
-                init: Some(Box::new(Stmt::Let {
-                    id: idx_id,
-                    name: format!("__idx_{}", idx_id),
-                    ty: Type::Number,
-                    mutable: true,
-                    init: Some(Expr::Number(0.0)),
-                })),
-                condition: Some(Expr::Compare {
-                    op: CompareOp::Lt,
-                    left: Box::new(Expr::LocalGet(idx_id)),
-                    right: Box::new(Expr::PropertyGet {
-                        object: Box::new(Expr::LocalGet(arr_id)),
-                        property: "length".to_string(),
-                    }),
-                }),
-                update: Some(Expr::Update {
-                    id: idx_id,
-                    op: UpdateOp::Increment,
-                    prefix: true,
-                }),
-                body: loop_body,
-            });
-        }
-        ast::Stmt::ForIn(for_in_stmt) => {
-            // Desugar for-in to a for-of over Object.keys(obj):
-            // for (const key in obj) { body }
-            // becomes:
-            // { let __keys = Object.keys(obj); for (let __i = 0; __i < __keys.length; __i++) { const key = __keys[__i]; body } }
-
-            // Get the iteration variable name
-            let key_name = match &for_in_stmt.left {
-                ast::ForHead::VarDecl(var_decl) => {
-                    if let Some(decl) = var_decl.decls.first() {
-                        get_binding_name(&decl.name)?
-                    } else {
-                        return Err(anyhow!("for-in requires a variable declaration"));
-                    }
-                }
-                ast::ForHead::Pat(pat) => get_pat_name(pat)?,
-                _ => return Err(anyhow!("Unsupported for-in left-hand side")),
-            };
-
-            // Lower the object expression
-            let obj_expr = lower_expr(ctx, &for_in_stmt.right)?;
-
-            // Create Object.keys(obj) expression to get the array of keys
-            let keys_expr = Expr::ObjectKeys(Box::new(obj_expr));
+            // Create Object.keys(obj) expression to get the array of keys
+            let keys_expr = Expr::ObjectKeys(Box::new(obj_expr));
 
             // Create internal variables for the keys array and index
             let keys_id = ctx.fresh_local();
@@ -1778,563 +2020,989 @@ fn lower_stmt(
                 body: loop_body,
             });
         }
+        ast::Stmt::DoWhile(do_while) => {
+            module.init.extend(lower_do_while(ctx, do_while)?);
+        }
+        ast::Stmt::Labeled(labeled) => {
+            // The label only matters to `break label`/`continue label`, reported where they are
+            lower_stmt(ctx, module, &labeled.body)?;
+        }
+        ast::Stmt::With(with_stmt) => {
+            report_dropped(ctx, with_stmt.span, "`with` statement", "refer to the object's properties explicitly");
+        }
+        // Empty and debugger statements; return, break and continue can't appear at module level
         _ => {}
     }
     Ok(())
 }
 
-fn lower_fn_decl(ctx: &mut LoweringContext, fn_decl: &ast::FnDecl) -> Result<Function> {
-    let name = fn_decl.ident.sym.to_string();
-    let func_id = ctx.lookup_func(&name).unwrap_or_else(|| ctx.fresh_func());
-
-    // Extract type parameters from generic function declaration (e.g., function foo<T, U>(...))
-    let type_params = fn_decl.function.type_params
-        .as_ref()
-        .map(|tp| extract_type_params(tp))
-        .unwrap_or_default();
-
-    // Enter type parameter scope for resolving T, U, etc. in body types
-    ctx.enter_type_param_scope(&type_params);
-
-    let scope_mark = ctx.enter_scope();
+/// Where a for-of loop (or spread, or `Array.from`) takes its values from, decided by the
+/// static type of the iterable
+enum ForOfSource {
+    /// Walked by index: arrays as they are, strings split into code points, and
+    /// snapshots of a Map's entries or a Set's values
+    Indexed(Expr),
+    /// An iterator object, driven through `next()` until the result is `done`. Iterables
+    /// (values with a `[Symbol.iterator]()` method) are iterated through the iterator it returns
+    Iterator(Expr, Type),
+    /// Values pulled one at a time from an expression that is null once the source is
+    /// exhausted: the chunks of process.stdin
+    Chunks(Expr),
+}
 
-    // Lower parameters with type extraction (using context for type param resolution)
-    let mut params = Vec::new();
-    for param in fn_decl.function.params.iter() {
-        let param_name = get_pat_name(&param.pat)?;
-        let param_type = extract_param_type_with_ctx(&param.pat, Some(ctx));
-        let param_default = get_param_default(ctx, &param.pat)?;
-        let param_id = ctx.define_local(param_name.clone(), param_type.clone());
-        let is_rest = is_rest_param(&param.pat);
-        params.push(Param {
-            id: param_id,
-            name: param_name,
-            ty: param_type,
-            default: param_default,
-            is_rest,
-        });
+fn for_of_source(ctx: &LoweringContext, right: &ast::Expr, iterable: Expr) -> ForOfSource {
+    if process_stdio_fd(unwrap_parens(right)) == Some(0) {
+        return ForOfSource::Chunks(Expr::ProcessStdinRead);
+    }
+    let ty = match unwrap_parens(right) {
+        ast::Expr::Lit(ast::Lit::Str(_)) | ast::Expr::Tpl(_) => Some(Type::String),
+        ast::Expr::New(new_expr) => match new_expr.callee.as_ref() {
+            ast::Expr::Ident(ident) if matches!(ident.sym.as_ref(), "Map" | "Set") => {
+                Some(Type::Generic { base: ident.sym.to_string(), type_args: Vec::new() })
+            }
+            _ => None,
+        },
+        _ => static_type_of(ctx, right),
+    };
+    match ty {
+        Some(Type::String) => {
+            ForOfSource::Indexed(Expr::StringSplit(Box::new(iterable), Box::new(Expr::String(String::new()))))
+        }
+        Some(Type::Generic { base, .. }) if base == "Map" => ForOfSource::Indexed(Expr::MapEntries(Box::new(iterable))),
+        Some(Type::Generic { base, .. }) if base == "Set" => ForOfSource::Indexed(Expr::SetValues(Box::new(iterable))),
+        Some(ty) => match iterable_iterator_type(ctx, &ty) {
+            Some(iter_ty) => {
+                let iterator = Expr::Call {
+                    callee: Box::new(Expr::PropertyGet {
+                        object: Box::new(iterable),
+                        property: SYMBOL_ITERATOR_KEY.to_string(),
+                    }),
+                    args: Vec::new(),
+                    type_args: Vec::new(),
+                    span: Span::DUMMY,
+                };
+                ForOfSource::Iterator(iterator, iter_ty)
+            }
+            None if is_iterator_type(ctx, &ty) => ForOfSource::Iterator(iterable, ty),
+            None => ForOfSource::Indexed(iterable),
+        },
+        None => ForOfSource::Indexed(iterable),
     }
+}
 
-    // Extract return type from function's type annotation (with context)
-    let return_type = fn_decl.function.return_type.as_ref()
-        .map(|rt| extract_ts_type_with_ctx(&rt.type_ann, Some(ctx)))
-        .unwrap_or(Type::Any);
+/// Type of the iterator `[Symbol.iterator]()` returns, if values of type `ty` are iterable:
+/// classes and interfaces of this module that declare the method, and object literals with it
+fn iterable_iterator_type(ctx: &LoweringContext, ty: &Type) -> Option<Type> {
+    match ty {
+        Type::Named(name) | Type::Generic { base: name, .. } => ctx.declared_method_type(name, SYMBOL_ITERATOR_KEY),
+        Type::Object(obj) if obj.properties.contains_key(SYMBOL_ITERATOR_KEY) => Some(Type::Any),
+        _ => None,
+    }
+}
 
-    // Lower body
-    let body = if let Some(ref block) = fn_decl.function.body {
-        lower_block_stmt(ctx, block)?
-    } else {
-        Vec::new()
+/// `const __step = __iter.next(); if (__step.done) break;`, the head of a loop over an iterator
+fn iterator_step(iter_id: LocalId, step_id: LocalId) -> [Stmt; 2] {
+    let step = Stmt::Let {
+        id: step_id,
+        name: format!("__step_{}", step_id),
+        ty: Type::Any,
+        mutable: false,
+        init: Some(Expr::Call {
+            callee: Box::new(Expr::PropertyGet {
+                object: Box::new(Expr::LocalGet(iter_id)),
+                property: "next".to_string(),
+            }),
+            args: Vec::new(),
+            type_args: Vec::new(),
+            span: Span::DUMMY,
+        }),
     };
+    let done = Stmt::If {
+        condition: Expr::PropertyGet {
+            object: Box::new(Expr::LocalGet(step_id)),
+            property: "done".to_string(),
+        },
+        then_branch: vec![Stmt::Break],
+        else_branch: None,
+    };
+    [step, done]
+}
 
-    ctx.exit_scope(scope_mark);
-
-    // Exit type parameter scope
-    ctx.exit_type_param_scope();
+/// `const __chunk = next; if (__chunk === null) break;`, the head of a loop over chunks
+fn chunk_step(chunk_id: LocalId, next: Expr) -> [Stmt; 2] {
+    let chunk = Stmt::Let {
+        id: chunk_id,
+        name: format!("__chunk_{}", chunk_id),
+        ty: Type::Any,
+        mutable: false,
+        init: Some(next),
+    };
+    let done = Stmt::If {
+        condition: Expr::Compare {
+            op: CompareOp::Eq,
+            left: Box::new(Expr::LocalGet(chunk_id)),
+            right: Box::new(Expr::Null),
+        },
+        then_branch: vec![Stmt::Break],
+        else_branch: None,
+    };
+    [chunk, done]
+}
 
-    Ok(Function {
-        id: func_id,
-        name,
-        type_params,
-        params,
-        return_type,
+/// All remaining chunks as an array, collected by `(() => { ... })()` like an iterator
+fn chunks_to_array(ctx: &mut LoweringContext, next: Expr) -> Expr {
+    let func_id = ctx.fresh_func();
+    let out_id = ctx.fresh_local();
+    ctx.locals.push((format!("__out_{}", out_id), out_id, Type::Array(Box::new(Type::Any))));
+    let chunk_id = ctx.fresh_local();
+    ctx.locals.push((format!("__chunk_{}", chunk_id), chunk_id, Type::Any));
+
+    let mut loop_body = Vec::from(chunk_step(chunk_id, next));
+    loop_body.push(Stmt::Expr(Expr::ArrayPush {
+        array_id: out_id,
+        value: Box::new(Expr::LocalGet(chunk_id)),
+    }));
+    let body = vec![
+        Stmt::Let {
+            id: out_id,
+            name: format!("__out_{}", out_id),
+            ty: Type::Array(Box::new(Type::Any)),
+            mutable: false,
+            init: Some(Expr::Array(Vec::new())),
+        },
+        Stmt::While { condition: Expr::Bool(true), body: loop_body },
+        Stmt::Return(Some(Expr::LocalGet(out_id))),
+    ];
+    let drain = Expr::Closure {
+        func_id,
+        params: Vec::new(),
+        return_type: Type::Array(Box::new(Type::Any)),
         body,
-        is_async: fn_decl.function.is_async,
-        is_exported: false,
         captures: Vec::new(),
-        decorators: Vec::new(),
-    })
+        mutable_captures: Vec::new(),
+        captures_this: false,
+        enclosing_class: None,
+        is_async: false,
+    };
+    Expr::Call { callee: Box::new(drain), args: Vec::new(), type_args: Vec::new(), span: Span::DUMMY }
 }
 
-fn lower_class_decl(ctx: &mut LoweringContext, class_decl: &ast::ClassDecl, is_exported: bool) -> Result<Class> {
-    let name = class_decl.ident.sym.to_string();
-    let class_id = ctx.lookup_class(&name).unwrap_or_else(|| {
-        let id = ctx.fresh_class();
-        ctx.classes.push((name.clone(), id));
-        id
-    });
-
-    // Set current class for arrow function `this` capture tracking
-    let old_class = ctx.current_class.take();
-    ctx.current_class = Some(name.clone());
-
-    // Extract type parameters from generic class declaration (e.g., class Box<T>)
-    let type_params = class_decl.class.type_params
-        .as_ref()
-        .map(|tp| extract_type_params(tp))
-        .unwrap_or_default();
-
-    // Enter type parameter scope for resolving T, U, etc. in member types
-    ctx.enter_type_param_scope(&type_params);
+/// File descriptor of `process.stdin` (0), `process.stdout` (1) or `process.stderr` (2)
+fn process_stdio_fd(expr: &ast::Expr) -> Option<u8> {
+    let ast::Expr::Member(member) = expr else { return None };
+    let ast::Expr::Ident(obj_ident) = member.obj.as_ref() else { return None };
+    let ast::MemberProp::Ident(prop_ident) = &member.prop else { return None };
+    if obj_ident.sym.as_ref() != "process" {
+        return None;
+    }
+    match prop_ident.sym.as_ref() {
+        "stdin" => Some(0),
+        "stdout" => Some(1),
+        "stderr" => Some(2),
+        _ => None,
+    }
+}
 
-    // Handle extends clause
-    let (extends, extends_name, native_extends) = if let Some(ref super_class) = class_decl.class.super_class {
-        if let ast::Expr::Ident(ident) = super_class.as_ref() {
-            let parent_name = ident.sym.to_string();
-            // First check if it's a native module class
-            let native_parent = match parent_name.as_str() {
-                "EventEmitter" => Some(("events".to_string(), "EventEmitter".to_string())),
-                "AsyncLocalStorage" => Some(("async_hooks".to_string(), "AsyncLocalStorage".to_string())),
-                "WebSocketServer" => Some(("ws".to_string(), "WebSocketServer".to_string())),
+/// Method calls on the standard streams; None for methods without native support
+fn lower_process_stdio_call(fd: u8, method: &str, ast_args: &[ast::ExprOrSpread], args: &[Expr]) -> Option<Expr> {
+    match (fd, method) {
+        (1 | 2, "write") => {
+            // write(data, callback) or write(data, encoding, callback)
+            let callback = match args {
+                [_, _, callback, ..] => Some(callback.clone()),
+                [_, callback] if !matches!(callback, Expr::String(_)) => Some(callback.clone()),
                 _ => None,
             };
-            if native_parent.is_some() {
-                (None, None, native_parent)
-            } else {
-                // Always capture the parent name for imported classes that may not have a ClassId
-                (ctx.lookup_class(&parent_name), Some(parent_name), None)
-            }
-        } else if let ast::Expr::Member(member) = super_class.as_ref() {
-            // Handle member expression like ethers.JsonRpcProvider or module.ClassName
-            let parent_name = extract_member_class_name(member);
-            // For member expressions, we don't have ClassId - just store the name
-            (None, Some(parent_name), None)
-        } else {
-            (None, None, None)
+            Some(Expr::ProcessStdioWrite {
+                fd,
+                data: Box::new(args.first().cloned().unwrap_or(Expr::String(String::new()))),
+                callback: callback.map(Box::new),
+            })
         }
-    } else {
-        (None, None, None)
+        (0, "read") => Some(Expr::ProcessStdinRead),
+        (0, "on" | "addListener") if args.len() >= 2 => Some(Expr::ProcessStdinOn {
+            event: Box::new(args[0].clone()),
+            listener: Box::new(args[1].clone()),
+        }),
+        (0, "pipe") => {
+            let target = ast_args.first().and_then(|arg| process_stdio_fd(unwrap_parens(&arg.expr)))?;
+            (target != 0).then_some(Expr::ProcessStdinPipe(target))
+        }
+        // Chunks are always decoded as UTF-8, and stdin starts flowing by itself once
+        // listeners are attached
+        (0, "setEncoding" | "resume") => Some(Expr::Undefined),
+        _ => None,
+    }
+}
+
+/// Call of a runtime function that codegen declares under `name`
+fn runtime_call(name: &str, args: Vec<Expr>) -> Expr {
+    Expr::Call {
+        callee: Box::new(Expr::ExternFuncRef {
+            name: name.to_string(),
+            param_types: Vec::new(),
+            return_type: Type::Any,
+        }),
+        args,
+        type_args: Vec::new(),
+        span: Span::DUMMY,
+    }
+}
+
+/// Record `app.<method>(path, [options,] handler)` on a Fastify app for OpenAPI output.
+/// Only routes with a string literal path are recorded.
+fn record_http_route(ctx: &mut LoweringContext, method: &str, call: &ast::CallExpr, args: &[Expr]) {
+    if !matches!(method, "get" | "post" | "put" | "delete" | "patch" | "head" | "options" | "all") {
+        return;
+    }
+    let (Some(Expr::String(path)), Some(handler)) = (args.first(), args.last()) else {
+        return;
+    };
+    if args.len() < 2 {
+        return;
+    }
+    let schema = match args.get(1) {
+        Some(Expr::Object(props)) if args.len() >= 3 => {
+            props.iter().find(|(key, _)| key == "schema").map(|(_, value)| value.clone())
+        }
+        _ => None,
     };
 
-    // First pass: collect static field/method names for early registration
-    // This allows static method bodies to reference static fields
-    let mut static_field_names = Vec::new();
-    let mut static_method_names = Vec::new();
-    for member in &class_decl.class.body {
-        match member {
-            ast::ClassMember::Method(method) if method.is_static => {
-                if let ast::PropName::Ident(ident) = &method.key {
-                    static_method_names.push(ident.sym.to_string());
-                }
-            }
-            ast::ClassMember::ClassProp(prop) if prop.is_static => {
-                if let ast::PropName::Ident(ident) = &prop.key {
-                    static_field_names.push(ident.sym.to_string());
+    // app.get<{ Querystring: Q; Reply: R }>(...) or (request: FastifyRequest<{ Body: B }>) => ...
+    let mut request_type = call.type_args.as_ref()
+        .and_then(|type_args| type_args.params.first())
+        .map(|ty| extract_ts_type_with_ctx(ty, Some(ctx)));
+    let mut reply_type = None;
+    let mut handler_func = None;
+    match handler {
+        Expr::Closure { params, .. } => {
+            if request_type.is_none() {
+                if let Some(Type::Generic { base, type_args }) = params.first().map(|p| &p.ty) {
+                    if base.ends_with("FastifyRequest") {
+                        request_type = type_args.first().cloned();
+                    }
                 }
             }
-            _ => {}
+            // Closures are lowered with an `any` return type, so read the annotation itself
+            let annotation = match call.args.last().map(|arg| arg.expr.as_ref()) {
+                Some(ast::Expr::Arrow(arrow)) => arrow.return_type.as_ref(),
+                Some(ast::Expr::Fn(func)) => func.function.return_type.as_ref(),
+                _ => None,
+            };
+            reply_type = annotation.map(|ann| extract_ts_type_with_ctx(&ann.type_ann, Some(ctx)));
         }
+        Expr::FuncRef(id) => handler_func = Some(*id),
+        _ => {}
     }
 
-    // Register static members early so method bodies can reference them
-    ctx.register_class_statics(name.clone(), static_field_names, static_method_names);
+    ctx.http_routes.push(HttpRoute {
+        method: method.to_uppercase(),
+        path: path.clone(),
+        schema,
+        request_type,
+        reply_type,
+        handler_func,
+    });
+}
 
-    let mut fields = Vec::new();
-    let mut static_fields = Vec::new();
-    let mut constructor = None;
-    let mut methods = Vec::new();
-    let mut static_methods = Vec::new();
-    let mut getters = Vec::new();
-    let mut setters = Vec::new();
-
-    // Second pass: actually lower the class members
-    for member in &class_decl.class.body {
-        match member {
-            ast::ClassMember::Constructor(ctor) => {
-                constructor = Some(lower_constructor(ctx, &name, ctor)?);
-            }
-            ast::ClassMember::Method(method) => {
-                // Get the property name for getters/setters
-                let prop_name = match &method.key {
-                    ast::PropName::Ident(ident) => ident.sym.to_string(),
-                    ast::PropName::Str(s) => s.value.as_str().unwrap_or("").to_string(),
-                    _ => continue,
-                };
+/// The `perry/di` export a decorator applies, with its arguments:
+/// `@Injectable({ scope })` -> ("Injectable", [{ scope }])
+fn di_decorator<'c, 'd>(ctx: &'c LoweringContext, decorator: &'d ast::Decorator) -> Option<(&'c str, &'d [ast::ExprOrSpread])> {
+    let (callee, args) = match decorator.expr.as_ref() {
+        ast::Expr::Call(call) => match &call.callee {
+            ast::Callee::Expr(callee) => (callee.as_ref(), call.args.as_slice()),
+            _ => return None,
+        },
+        expr => (expr, &[][..]),
+    };
+    let ast::Expr::Ident(ident) = callee else { return None };
+    match ctx.lookup_native_module(ident.sym.as_ref())? {
+        ("perry/di", Some(export)) => Some((export, args)),
+        _ => None,
+    }
+}
 
-                match method.kind {
-                    ast::MethodKind::Getter => {
-                        // Getter: no parameters, returns a value
-                        let func = lower_getter_method(ctx, method)?;
-                        getters.push((prop_name, func));
-                    }
-                    ast::MethodKind::Setter => {
-                        // Setter: takes one parameter
-                        let func = lower_setter_method(ctx, method)?;
-                        setters.push((prop_name, func));
-                    }
-                    ast::MethodKind::Method => {
-                        let func = lower_class_method(ctx, method)?;
-                        if method.is_static {
-                            static_methods.push(func);
-                        } else {
-                            methods.push(func);
-                        }
+/// The string a DI decorator argument names: `@Get(':id')`, `@Controller({ path: 'users' })`
+fn di_string_arg(args: &[ast::ExprOrSpread], key: &str) -> Option<String> {
+    match args.first().map(|arg| unwrap_parens(&arg.expr)) {
+        Some(ast::Expr::Lit(ast::Lit::Str(s))) => Some(s.value.as_str().unwrap_or("").to_string()),
+        Some(ast::Expr::Object(obj)) => obj.props.iter().find_map(|prop| match prop {
+            ast::PropOrSpread::Prop(prop) => match prop.as_ref() {
+                ast::Prop::KeyValue(kv) if matches!(&kv.key, ast::PropName::Ident(k) if k.sym.as_ref() == key) => {
+                    match unwrap_parens(&kv.value) {
+                        ast::Expr::Lit(ast::Lit::Str(s)) => Some(s.value.as_str().unwrap_or("").to_string()),
+                        _ => None,
                     }
                 }
-            }
-            ast::ClassMember::ClassProp(prop) => {
-                let field = lower_class_prop(ctx, prop)?;
-                if prop.is_static {
-                    static_fields.push(field);
-                } else {
-                    fields.push(field);
-                }
-            }
-            ast::ClassMember::PrivateProp(prop) => {
-                let field = lower_private_prop(ctx, prop)?;
-                if prop.is_static {
-                    static_fields.push(field);
-                } else {
-                    fields.push(field);
-                }
-            }
-            _ => {}
+                _ => None,
+            },
+            _ => None,
+        }),
+        _ => None,
+    }
+}
+
+/// `@Injectable({ scope: Scope.REQUEST })` -> 2, numbered like NestJS's `Scope` enum
+fn di_scope(args: &[ast::ExprOrSpread]) -> f64 {
+    let Some(ast::Expr::Object(obj)) = args.first().map(|arg| unwrap_parens(&arg.expr)) else { return 0.0 };
+    for prop in &obj.props {
+        let ast::PropOrSpread::Prop(prop) = prop else { continue };
+        let ast::Prop::KeyValue(kv) = prop.as_ref() else { continue };
+        if !matches!(&kv.key, ast::PropName::Ident(k) if k.sym.as_ref() == "scope") {
+            continue;
+        }
+        if let ast::Expr::Member(member) = unwrap_parens(&kv.value) {
+            return match member_prop_name(&member.prop).as_deref() {
+                Some("TRANSIENT") => 1.0,
+                Some("REQUEST") => 2.0,
+                _ => 0.0,
+            };
         }
     }
+    0.0
+}
 
-    // Exit type parameter scope
-    ctx.exit_type_param_scope();
+/// `/` + prefix + path, e.g. ("users", ":id") -> "/users/:id"
+fn join_route_path(prefix: &str, path: &str) -> String {
+    let parts: Vec<&str> = [prefix, path].iter()
+        .map(|part| part.trim_matches('/'))
+        .filter(|part| !part.is_empty())
+        .collect();
+    format!("/{}", parts.join("/"))
+}
 
-    // Restore previous current_class
-    ctx.current_class = old_class;
+fn di_ident(name: &str) -> ast::Ident {
+    ast::Ident::new_no_ctxt(name.into(), DUMMY_SP)
+}
 
-    Ok(Class {
-        id: class_id,
-        name,
-        type_params,
-        extends,
-        extends_name,
-        native_extends,
-        fields,
-        constructor,
-        methods,
-        getters,
-        setters,
-        static_fields,
-        static_methods,
-        is_exported,
+fn di_type_ann(class_name: &str) -> Box<ast::TsTypeAnn> {
+    Box::new(ast::TsTypeAnn {
+        span: DUMMY_SP,
+        type_ann: Box::new(ast::TsType::TsTypeRef(ast::TsTypeRef {
+            span: DUMMY_SP,
+            type_name: ast::TsEntityName::Ident(di_ident(class_name)),
+            type_params: None,
+        })),
     })
 }
 
-fn lower_enum_decl(ctx: &mut LoweringContext, enum_decl: &ast::TsEnumDecl, is_exported: bool) -> Result<Enum> {
-    let name = enum_decl.id.sym.to_string();
-    let enum_id = ctx.fresh_enum();
+/// `object.name`, or `object["name"]` when the name isn't an identifier
+fn di_field(object: ast::Expr, name: &str) -> ast::Expr {
+    let is_ident = name.chars().enumerate().all(|(i, c)| c == '_' || c == '$' || c.is_ascii_alphabetic() || (i > 0 && c.is_ascii_digit()));
+    let prop = if is_ident && !name.is_empty() {
+        ast::MemberProp::Ident(ast::IdentName::new(name.into(), DUMMY_SP))
+    } else {
+        ast::MemberProp::Computed(ast::ComputedPropName {
+            span: DUMMY_SP,
+            expr: Box::new(ast::Expr::Lit(ast::Lit::Str(name.into()))),
+        })
+    };
+    ast::Expr::Member(ast::MemberExpr { obj: Box::new(object), prop, ..Default::default() })
+}
 
-    let mut members = Vec::new();
-    let mut next_value: i64 = 0;
+fn di_call(method: &str, args: Vec<Expr>) -> Stmt {
+    Stmt::Expr(Expr::NativeMethodCall {
+        module: "perry/di".to_string(),
+        class_name: None,
+        object: None,
+        method: method.to_string(),
+        args,
+    })
+}
 
-    for member in &enum_decl.members {
-        // Get member name
-        let member_name = match &member.id {
-            ast::TsEnumMemberId::Ident(ident) => ident.sym.to_string(),
-            ast::TsEnumMemberId::Str(s) => s.value.as_str().unwrap_or("").to_string(),
-        };
+/// Token a `perry/di` function is given as its first argument: `resolve(UsersService)` passes
+/// the class's name
+fn di_token_arg(ctx: &LoweringContext, method: &str, call: &ast::CallExpr, args: &mut [Expr]) {
+    if !matches!(method, "resolve" | "provideValue") {
+        return;
+    }
+    if let (Some(ast::Expr::Ident(ident)), Some(first)) = (call.args.first().map(|arg| unwrap_parens(&arg.expr)), args.first_mut()) {
+        if ctx.lookup_local(ident.sym.as_ref()).is_none() {
+            *first = Expr::String(ident.sym.to_string());
+        }
+    }
+}
 
-        // Get member value
-        let value = if let Some(ref init) = member.init {
-            match init.as_ref() {
-                ast::Expr::Lit(ast::Lit::Num(n)) => {
-                    let v = n.value as i64;
-                    next_value = v + 1;
-                    EnumValue::Number(v)
-                }
-                ast::Expr::Lit(ast::Lit::Str(s)) => {
-                    EnumValue::String(s.value.as_str().unwrap_or("").to_string())
-                }
-                ast::Expr::Unary(unary) if unary.op == ast::UnaryOp::Minus => {
-                    // Handle negative numbers like -1
-                    if let ast::Expr::Lit(ast::Lit::Num(n)) = unary.arg.as_ref() {
-                        let v = -(n.value as i64);
-                        next_value = v + 1;
-                        EnumValue::Number(v)
-                    } else {
-                        // Default to auto-increment
-                        let v = next_value;
-                        next_value += 1;
-                        EnumValue::Number(v)
-                    }
-                }
-                _ => {
-                    // For complex expressions, default to auto-increment
-                    let v = next_value;
-                    next_value += 1;
-                    EnumValue::Number(v)
-                }
+/// The injection token of a constructor parameter: the name in `@Inject(token)`, else the
+/// name of its class type
+fn di_param_token(ctx: &LoweringContext, class_name: &str, index: usize, decorators: &[ast::Decorator], type_ann: Option<&ast::TsTypeAnn>) -> Result<String> {
+    for decorator in decorators {
+        if let Some(("Inject", args)) = di_decorator(ctx, decorator) {
+            return match args.first().map(|arg| unwrap_parens(&arg.expr)) {
+                Some(ast::Expr::Ident(ident)) => match ctx.lookup_native_module(ident.sym.as_ref()) {
+                    Some(("perry/di", Some(export))) => Ok(export.to_string()),
+                    _ => Ok(ident.sym.to_string()),
+                },
+                Some(ast::Expr::Lit(ast::Lit::Str(s))) => Ok(s.value.as_str().unwrap_or("").to_string()),
+                _ => Err(anyhow!("@Inject() on parameter {} of {} needs a class or a string token", index + 1, class_name)),
+            };
+        }
+    }
+    match type_ann.map(|ann| ann.type_ann.as_ref()) {
+        Some(ast::TsType::TsTypeRef(ast::TsTypeRef { type_name: ast::TsEntityName::Ident(ident), .. })) => Ok(ident.sym.to_string()),
+        _ => Err(anyhow!(
+            "Cannot inject parameter {} of {}: it needs a class type or @Inject(token)",
+            index + 1, class_name
+        )),
+    }
+}
+
+/// Registrations for a class decorated with `@Injectable()` or `@Controller()` from `perry/di`.
+///
+/// The class becomes a provider whose factory takes the constructor's dependencies:
+/// `provide("UsersController", scope, "UsersService,REQUEST", (d0: UsersService, d1) => new
+/// UsersController(d0, d1))`. Each method decorated with `@Get()`, `@Post()`, etc. becomes a route
+/// whose handler maps the parameter decorators (`@Param('id')`, `@Body()`, `@Req()`, ...) onto the
+/// Fastify request: `route("UsersController", "GET", "/users/:id", (controller: UsersController,
+/// request, reply) => controller.findOne(request.params.id))`.
+fn lower_injectable(ctx: &mut LoweringContext, name: &str, class: &ast::Class) -> Result<Vec<Stmt>> {
+    let mut scope = None;
+    let mut prefix = None;
+    for decorator in &class.decorators {
+        match di_decorator(ctx, decorator) {
+            Some(("Injectable", args)) => scope = Some(di_scope(args)),
+            Some(("Controller", args)) => {
+                scope = Some(di_scope(args));
+                prefix = Some(di_string_arg(args, "path").unwrap_or_default());
             }
-        } else {
-            // Auto-increment
-            let v = next_value;
-            next_value += 1;
-            EnumValue::Number(v)
+            _ => {}
+        }
+    }
+    let Some(scope) = scope else { return Ok(Vec::new()) };
+
+    // Factory: (d0: A, d1: B) => new C(d0, d1)
+    let ctor = class.body.iter().find_map(|member| match member {
+        ast::ClassMember::Constructor(ctor) => Some(ctor),
+        _ => None,
+    });
+    let mut tokens = Vec::new();
+    let mut params = Vec::new();
+    for (i, param) in ctor.map(|ctor| ctor.params.as_slice()).unwrap_or_default().iter().enumerate() {
+        let (decorators, binding) = match param {
+            ast::ParamOrTsParamProp::Param(param) => (&param.decorators, match &param.pat {
+                ast::Pat::Ident(binding) => Some(binding),
+                ast::Pat::Assign(assign) => assign.left.as_ident(),
+                _ => None,
+            }),
+            ast::ParamOrTsParamProp::TsParamProp(prop) => (&prop.decorators, match &prop.param {
+                ast::TsParamPropParam::Ident(binding) => Some(binding),
+                ast::TsParamPropParam::Assign(assign) => assign.left.as_ident(),
+            }),
         };
+        let type_ann = binding.and_then(|binding| binding.type_ann.clone());
+        tokens.push(di_param_token(ctx, name, i, decorators, type_ann.as_deref())?);
+        params.push(ast::Pat::Ident(ast::BindingIdent { id: di_ident(&format!("__dep{}", i)), type_ann }));
+    }
+    let args = (0..params.len()).map(|i| plain_arg(ast::Expr::Ident(di_ident(&format!("__dep{}", i))))).collect();
+    let factory = ast::Expr::Arrow(ast::ArrowExpr {
+        params,
+        body: Box::new(ast::BlockStmtOrExpr::Expr(Box::new(ast::Expr::New(ast::NewExpr {
+            callee: Box::new(ast::Expr::Ident(di_ident(name))),
+            args: Some(args),
+            ..Default::default()
+        })))),
+        ..Default::default()
+    });
+    let mut stmts = vec![di_call("provide", vec![
+        Expr::String(name.to_string()),
+        Expr::Number(scope),
+        Expr::String(tokens.join(",")),
+        lower_expr(ctx, &factory)?,
+    ])];
+
+    let Some(prefix) = prefix else { return Ok(stmts) };
+    for member in &class.body {
+        let ast::ClassMember::Method(method) = member else { continue };
+        let ast::PropName::Ident(method_name) = &method.key else { continue };
+        if method.is_static || method.kind != ast::MethodKind::Method {
+            continue;
+        }
+        for decorator in &method.function.decorators {
+            let (http_method, path) = match di_decorator(ctx, decorator) {
+                Some((export @ ("Get" | "Post" | "Put" | "Delete" | "Patch" | "Head" | "Options" | "All"), args)) => {
+                    (export.to_uppercase(), join_route_path(&prefix, &di_string_arg(args, "path").unwrap_or_default()))
+                }
+                _ => continue,
+            };
 
-        members.push(EnumMember {
-            name: member_name,
-            value,
-        });
+            // Arguments from the parameter decorators; an undecorated parameter gets undefined
+            let mut call_args = Vec::new();
+            for param in &method.function.params {
+                let request = || ast::Expr::Ident(di_ident("request"));
+                let source = param.decorators.iter().find_map(|decorator| di_decorator(ctx, decorator));
+                let arg = match source {
+                    Some((export @ ("Param" | "Query" | "Body" | "Headers"), args)) => {
+                        let field = match export {
+                            "Param" => "params",
+                            "Query" => "query",
+                            "Body" => "body",
+                            _ => "headers",
+                        };
+                        let object = di_field(request(), field);
+                        match di_string_arg(args, "") {
+                            Some(key) => di_field(object, &key),
+                            None => object,
+                        }
+                    }
+                    Some(("Req" | "Request", _)) => request(),
+                    Some(("Res" | "Response" | "Reply", _)) => ast::Expr::Ident(di_ident("reply")),
+                    _ => ast::Expr::Ident(di_ident("undefined")),
+                };
+                call_args.push(plain_arg(arg));
+            }
+            let handler = ast::Expr::Arrow(ast::ArrowExpr {
+                params: vec![
+                    ast::Pat::Ident(ast::BindingIdent { id: di_ident("__controller"), type_ann: Some(di_type_ann(name)) }),
+                    ast::Pat::Ident(di_ident("request").into()),
+                    ast::Pat::Ident(di_ident("reply").into()),
+                ],
+                body: Box::new(ast::BlockStmtOrExpr::Expr(Box::new(ast::Expr::Call(ast::CallExpr {
+                    callee: ast::Callee::Expr(Box::new(di_field(ast::Expr::Ident(di_ident("__controller")), method_name.sym.as_ref()))),
+                    args: call_args,
+                    ..Default::default()
+                })))),
+                ..Default::default()
+            });
+
+            ctx.http_routes.push(HttpRoute {
+                method: http_method.clone(),
+                path: path.clone(),
+                schema: None,
+                request_type: None,
+                reply_type: method.function.return_type.as_ref().map(|ann| extract_ts_type_with_ctx(&ann.type_ann, Some(ctx))),
+                handler_func: None,
+            });
+            stmts.push(di_call("route", vec![
+                Expr::String(name.to_string()),
+                Expr::String(http_method),
+                Expr::String(path),
+                lower_expr(ctx, &handler)?,
+            ]));
+        }
     }
+    Ok(stmts)
+}
 
-    // Register the enum in the context for later lookups
-    let member_values: Vec<(String, EnumValue)> = members.iter()
-        .map(|m| (m.name.clone(), m.value.clone()))
-        .collect();
-    ctx.define_enum(name.clone(), enum_id, member_values);
+/// `setTimeout(callback, ms)` or `setInterval(callback, ms)` of the global timer functions.
+/// `setTimeout(ms)` without a callback returns a promise rather than a timer.
+fn is_timer_call(ctx: &LoweringContext, expr: &ast::Expr) -> bool {
+    let ast::Expr::Call(call) = unwrap_parens(expr) else { return false };
+    let ast::Callee::Expr(callee) = &call.callee else { return false };
+    let ast::Expr::Ident(ident) = callee.as_ref() else { return false };
+    let name = ident.sym.as_ref();
+    if !matches!(name, "setTimeout" | "setInterval")
+        || ctx.lookup_local(name).is_some()
+        || ctx.lookup_func(name).is_some()
+        || ctx.lookup_imported_func(name).is_some()
+    {
+        return false;
+    }
+    call.args.len() >= 2
+        || call.args.first().is_some_and(|arg| matches!(unwrap_parens(&arg.expr), ast::Expr::Arrow(_) | ast::Expr::Fn(_)))
+}
 
-    Ok(Enum {
-        id: enum_id,
-        name,
-        members,
-        is_exported,
-    })
+/// Whether `expr` is a timer returned by setTimeout/setInterval: the call itself, or a
+/// local initialized from one or annotated as `NodeJS.Timeout`
+fn is_timer_handle(ctx: &LoweringContext, expr: &ast::Expr) -> bool {
+    is_timer_call(ctx, expr)
+        || matches!(
+            static_type_of(ctx, unwrap_parens(expr)),
+            Some(Type::Named(name)) if matches!(name.as_str(), "Timeout" | "NodeJS.Timeout" | "NodeJS.Timer")
+        )
 }
 
-fn lower_interface_decl(ctx: &mut LoweringContext, iface_decl: &ast::TsInterfaceDecl, is_exported: bool) -> Result<Interface> {
-    let name = iface_decl.id.sym.to_string();
-    let iface_id = ctx.fresh_interface();
+/// The values of an iterable as an array, for spread and `Array.from`. Arrays are used as
+/// they are; iterators are drained by `(__iter: ty) => { ... }` called on the spot, so
+/// `next()` is dispatched on the iterator's static type like in a for-of loop
+fn iterable_to_array(ctx: &mut LoweringContext, right: &ast::Expr, iterable: Expr) -> Expr {
+    let (iterator, iter_ty) = match for_of_source(ctx, right, iterable) {
+        ForOfSource::Indexed(arr) => return arr,
+        ForOfSource::Iterator(iterator, iter_ty) => (iterator, iter_ty),
+        ForOfSource::Chunks(next) => return chunks_to_array(ctx, next),
+    };
+    let func_id = ctx.fresh_func();
+    let scope_mark = ctx.enter_scope();
+    let name = "__iter".to_string();
+    let iter_id = ctx.define_local(name.clone(), iter_ty.clone());
+    let out_id = ctx.define_local("__out".to_string(), Type::Array(Box::new(Type::Any)));
+    let step_id = ctx.fresh_local();
+    ctx.locals.push((format!("__step_{}", step_id), step_id, Type::Any));
+    ctx.exit_scope(scope_mark);
 
-    // Extract type parameters
-    let type_params = iface_decl.type_params.as_ref()
-        .map(|tp| extract_type_params(tp))
-        .unwrap_or_default();
+    let mut loop_body = Vec::from(iterator_step(iter_id, step_id));
+    loop_body.push(Stmt::Expr(Expr::ArrayPush {
+        array_id: out_id,
+        value: Box::new(Expr::PropertyGet {
+            object: Box::new(Expr::LocalGet(step_id)),
+            property: "value".to_string(),
+        }),
+    }));
+    let body = vec![
+        Stmt::Let {
+            id: out_id,
+            name: "__out".to_string(),
+            ty: Type::Array(Box::new(Type::Any)),
+            mutable: false,
+            init: Some(Expr::Array(Vec::new())),
+        },
+        Stmt::While { condition: Expr::Bool(true), body: loop_body },
+        Stmt::Return(Some(Expr::LocalGet(out_id))),
+    ];
+    let drain = Expr::Closure {
+        func_id,
+        params: vec![Param { id: iter_id, name, ty: iter_ty, default: None, is_rest: false }],
+        return_type: Type::Array(Box::new(Type::Any)),
+        body,
+        captures: Vec::new(),
+        mutable_captures: Vec::new(),
+        captures_this: false,
+        enclosing_class: None,
+        is_async: false,
+    };
+    Expr::Call { callee: Box::new(drain), args: vec![iterator], type_args: Vec::new(), span: Span::DUMMY }
+}
 
-    // Enter type param scope for resolving type references in body
-    ctx.enter_type_param_scope(&type_params);
+/// `Iterator<T>` and friends, or a class/interface of this module with a `next` method
+fn is_iterator_type(ctx: &LoweringContext, ty: &Type) -> bool {
+    match ty {
+        Type::Named(name) | Type::Generic { base: name, .. } => {
+            matches!(name.as_str(), "Iterator" | "IterableIterator" | "Generator")
+                || ctx.has_declared_method(name, "next")
+                || ctx.lookup_property(ty, "next").is_some()
+        }
+        Type::Object(obj) => obj.properties.contains_key("next"),
+        _ => false,
+    }
+}
 
-    // Extract extended interfaces
-    let extends: Vec<Type> = iface_decl.extends.iter()
-        .map(|ext| {
-            let base_name = match &*ext.expr {
-                ast::Expr::Ident(id) => id.sym.to_string(),
-                _ => "unknown".to_string(),
-            };
-            // Handle type arguments if present
-            if let Some(ref type_args) = ext.type_args {
-                let args: Vec<Type> = type_args.params.iter()
-                    .map(|t| extract_ts_type_with_ctx(t, Some(ctx)))
-                    .collect();
-                if args.is_empty() {
-                    Type::Named(base_name)
-                } else {
-                    Type::Generic {
-                        base: base_name,
-                        type_args: args,
+/// Desugar `for (const x of iterable) body`. Arrays, strings, Maps and Sets become an
+/// indexed loop:
+///   { let __arr = <array>; for (let __i = 0; __i < __arr.length; __i++) { const x = __arr[__i]; body } }
+/// and iterator objects a loop over `next()`:
+///   { let __iter = it; while (true) { const __step = __iter.next(); if (__step.done) break; const x = __step.value; body } }
+fn lower_for_of(ctx: &mut LoweringContext, for_of_stmt: &ast::ForOfStmt) -> Result<Vec<Stmt>> {
+    let iterable = lower_expr(ctx, &for_of_stmt.right)?;
+    let source = for_of_source(ctx, &for_of_stmt.right, iterable);
+    let is_iterator = matches!(source, ForOfSource::Iterator(..));
+    let mut result = Vec::new();
+
+    // Store the array (or iterator) and create the loop's internal variables
+    let source_id = ctx.fresh_local();
+    let cursor_id = ctx.fresh_local();
+    // Expression producing the next chunk, for loops over chunks
+    let mut next_chunk = None;
+    let item_expr = match source {
+        ForOfSource::Indexed(arr_expr) => {
+            ctx.locals.push((format!("__arr_{}", source_id), source_id, Type::Array(Box::new(Type::Any))));
+            ctx.locals.push((format!("__idx_{}", cursor_id), cursor_id, Type::Number));
+            result.push(Stmt::Let {
+                id: source_id,
+                name: format!("__arr_{}", source_id),
+                ty: Type::Array(Box::new(Type::Any)),
+                mutable: false,
+                init: Some(arr_expr),
+            });
+            Expr::IndexGet {
+                object: Box::new(Expr::LocalGet(source_id)),
+                index: Box::new(Expr::LocalGet(cursor_id)),
+            }
+        }
+        ForOfSource::Iterator(iter_expr, iter_ty) => {
+            ctx.locals.push((format!("__iter_{}", source_id), source_id, iter_ty.clone()));
+            ctx.locals.push((format!("__step_{}", cursor_id), cursor_id, Type::Any));
+            result.push(Stmt::Let {
+                id: source_id,
+                name: format!("__iter_{}", source_id),
+                ty: iter_ty,
+                mutable: false,
+                init: Some(iter_expr),
+            });
+            Expr::PropertyGet {
+                object: Box::new(Expr::LocalGet(cursor_id)),
+                property: "value".to_string(),
+            }
+        }
+        ForOfSource::Chunks(next) => {
+            ctx.locals.push((format!("__chunk_{}", cursor_id), cursor_id, Type::Any));
+            next_chunk = Some(next);
+            Expr::LocalGet(cursor_id)
+        }
+    };
+
+    // IMPORTANT: Define iteration variables BEFORE lowering the body
+    // so the body can reference them
+    let item_id = ctx.fresh_local();
+    ctx.locals.push((format!("__item_{}", item_id), item_id, Type::Any));
+
+    // Pre-define all variables from the pattern so body can reference them
+    let var_ids: Vec<(String, u32)> = match &for_of_stmt.left {
+        ast::ForHead::VarDecl(var_decl) => {
+            if let Some(decl) = var_decl.decls.first() {
+                match &decl.name {
+                    ast::Pat::Ident(ident) => {
+                        let name = ident.id.sym.to_string();
+                        let id = ctx.define_local(name.clone(), Type::Any);
+                        vec![(name, id)]
+                    }
+                    ast::Pat::Array(arr_pat) => {
+                        let mut ids = Vec::new();
+                        for elem in &arr_pat.elems {
+                            if let Some(elem_pat) = elem {
+                                if let ast::Pat::Ident(ident) = elem_pat {
+                                    let name = ident.id.sym.to_string();
+                                    let id = ctx.define_local(name.clone(), Type::Any);
+                                    ids.push((name, id));
+                                }
+                            }
+                        }
+                        ids
+                    }
+                    ast::Pat::Object(obj_pat) => {
+                        let mut ids = Vec::new();
+                        for prop in &obj_pat.props {
+                            match prop {
+                                ast::ObjectPatProp::Assign(assign) => {
+                                    let name = assign.key.sym.to_string();
+                                    let id = ctx.define_local(name.clone(), Type::Any);
+                                    ids.push((name, id));
+                                }
+                                ast::ObjectPatProp::KeyValue(kv) => {
+                                    if let ast::Pat::Ident(ident) = &*kv.value {
+                                        let name = ident.id.sym.to_string();
+                                        let id = ctx.define_local(name.clone(), Type::Any);
+                                        ids.push((name, id));
+                                    }
+                                }
+                                _ => {}
+                            }
+                        }
+                        ids
+                    }
+                    _ => {
+                        let name = get_binding_name(&decl.name)?;
+                        let id = ctx.define_local(name.clone(), Type::Any);
+                        vec![(name, id)]
                     }
                 }
             } else {
-                Type::Named(base_name)
+                return Err(anyhow!("for-of requires a variable declaration"));
             }
-        })
-        .collect();
-
-    // Extract properties and methods from interface body
-    let mut properties = Vec::new();
-    let mut methods = Vec::new();
+        }
+        ast::ForHead::Pat(pat) => {
+            let name = get_pat_name(pat)?;
+            let id = ctx.define_local(name.clone(), Type::Any);
+            vec![(name, id)]
+        }
+        _ => return Err(anyhow!("Unsupported for-of left-hand side")),
+    };
 
-    for member in &iface_decl.body.body {
-        match member {
-            ast::TsTypeElement::TsPropertySignature(prop) => {
-                let prop_name = match &*prop.key {
-                    ast::Expr::Ident(id) => id.sym.to_string(),
-                    ast::Expr::Lit(ast::Lit::Str(s)) => s.value.as_str().unwrap_or("").to_string(),
-                    _ => continue,
-                };
-                let prop_type = prop.type_ann.as_ref()
-                    .map(|ta| extract_ts_type_with_ctx(&ta.type_ann, Some(ctx)))
-                    .unwrap_or(Type::Any);
-                properties.push(InterfaceProperty {
-                    name: prop_name,
-                    ty: prop_type,
-                    optional: prop.optional,
-                    readonly: prop.readonly,
-                });
-            }
-            ast::TsTypeElement::TsMethodSignature(method) => {
-                let method_name = match &*method.key {
-                    ast::Expr::Ident(id) => id.sym.to_string(),
-                    ast::Expr::Lit(ast::Lit::Str(s)) => s.value.as_str().unwrap_or("").to_string(),
-                    _ => continue,
-                };
-
-                // Method's own type parameters
-                let method_type_params = method.type_params.as_ref()
-                    .map(|tp| extract_type_params(tp))
-                    .unwrap_or_default();
-
-                // Enter method's type param scope
-                ctx.enter_type_param_scope(&method_type_params);
-
-                // Extract parameters
-                let params: Vec<(String, Type, bool)> = method.params.iter()
-                    .map(|p| {
-                        let (name, ty) = get_fn_param_name_and_type_with_ctx(p, Some(ctx));
-                        let optional = matches!(p, ast::TsFnParam::Ident(id) if id.optional);
-                        (name, ty, optional)
-                    })
-                    .collect();
-
-                // Extract return type
-                let return_type = method.type_ann.as_ref()
-                    .map(|ta| extract_ts_type_with_ctx(&ta.type_ann, Some(ctx)))
-                    .unwrap_or(Type::Void);
-
-                ctx.exit_type_param_scope();
-
-                methods.push(InterfaceMethod {
-                    name: method_name,
-                    type_params: method_type_params,
-                    params,
-                    return_type,
-                });
-            }
-            _ => {} // Skip other member types for now
-        }
-    }
-
-    ctx.exit_type_param_scope();
-
-    // Register interface in context
-    ctx.interfaces.push((name.clone(), iface_id));
-
-    Ok(Interface {
-        id: iface_id,
-        name,
-        type_params,
-        extends,
-        properties,
-        methods,
-        is_exported,
-    })
-}
-
-fn lower_type_alias_decl(ctx: &mut LoweringContext, alias_decl: &ast::TsTypeAliasDecl, is_exported: bool) -> Result<TypeAlias> {
-    let name = alias_decl.id.sym.to_string();
-    let alias_id = ctx.fresh_type_alias();
-
-    // Extract type parameters
-    let type_params = alias_decl.type_params.as_ref()
-        .map(|tp| extract_type_params(tp))
-        .unwrap_or_default();
-
-    // Enter type param scope for resolving type references
-    ctx.enter_type_param_scope(&type_params);
-
-    // Extract the aliased type
-    let ty = extract_ts_type_with_ctx(&alias_decl.type_ann, Some(ctx));
-
-    ctx.exit_type_param_scope();
-
-    // Register type alias in context
-    ctx.type_aliases.push((name.clone(), alias_id, type_params.clone(), ty.clone()));
-
-    Ok(TypeAlias {
-        id: alias_id,
-        name,
-        type_params,
-        ty,
-        is_exported,
-    })
-}
-
-fn lower_constructor(ctx: &mut LoweringContext, class_name: &str, ctor: &ast::Constructor) -> Result<Function> {
-    let scope_mark = ctx.enter_scope();
-
-    // Add 'this' as a special local
-    let _this_id = ctx.define_local("this".to_string(), Type::Any);
-
-    // Lower parameters with type extraction (using context for class type param resolution)
-    let mut params = Vec::new();
-    for param in &ctor.params {
-        match param {
-            ast::ParamOrTsParamProp::Param(p) => {
-                let param_name = get_pat_name(&p.pat)?;
-                let param_type = extract_param_type_with_ctx(&p.pat, Some(ctx));
-                let param_default = get_param_default(ctx, &p.pat)?;
-                let is_rest = is_rest_param(&p.pat);
-                let param_id = ctx.define_local(param_name.clone(), param_type.clone());
-                params.push(Param {
-                    id: param_id,
-                    name: param_name,
-                    ty: param_type,
-                    default: param_default,
-                    is_rest,
-                });
-            }
-            ast::ParamOrTsParamProp::TsParamProp(ts_prop) => {
-                // Handle parameter properties (e.g., constructor(public x: number))
-                let (param_name, param_type) = match &ts_prop.param {
-                    ast::TsParamPropParam::Ident(ident) => {
-                        let name = ident.id.sym.to_string();
-                        let ty = ident.type_ann.as_ref()
-                            .map(|ann| extract_ts_type_with_ctx(&ann.type_ann, Some(ctx)))
-                            .unwrap_or(Type::Any);
-                        (name, ty)
+    // NOW lower the body - variables are defined so body can reference them
+    let mut loop_body = lower_body_stmt(ctx, &for_of_stmt.body)?;
+
+    // Build binding statements using the pre-defined variable IDs
+    let binding_stmts = match &for_of_stmt.left {
+        ast::ForHead::VarDecl(var_decl) => {
+            if let Some(decl) = var_decl.decls.first() {
+                match &decl.name {
+                    ast::Pat::Ident(_) => {
+                        // Simple binding: for (const x of arr)
+                        let (name, id) = var_ids[0].clone();
+                        vec![Stmt::Let {
+                            id,
+                            name,
+                            ty: Type::Any,
+                            mutable: false,
+                            init: Some(item_expr),
+                        }]
                     }
-                    ast::TsParamPropParam::Assign(assign) => {
-                        let name = get_pat_name(&assign.left)?;
-                        let ty = extract_param_type_with_ctx(&assign.left, Some(ctx));
-                        (name, ty)
+                    ast::Pat::Array(arr_pat) => {
+                        // Array destructuring: for (const [a, b] of arr)
+                        let mut stmts = vec![Stmt::Let {
+                            id: item_id,
+                            name: format!("__item_{}", item_id),
+                            ty: Type::Any,
+                            mutable: false,
+                            init: Some(item_expr),
+                        }];
+
+                        // Extract each element using pre-defined IDs
+                        let mut var_idx = 0;
+                        for (idx, elem) in arr_pat.elems.iter().enumerate() {
+                            if let Some(elem_pat) = elem {
+                                if let ast::Pat::Ident(_) = elem_pat {
+                                    let (name, id) = var_ids[var_idx].clone();
+                                    var_idx += 1;
+                                    stmts.push(Stmt::Let {
+                                        id,
+                                        name,
+                                        ty: Type::Any,
+                                        mutable: false,
+                                        init: Some(Expr::IndexGet {
+                                            object: Box::new(Expr::LocalGet(item_id)),
+                                            index: Box::new(Expr::Number(idx as f64)),
+                                        }),
+                                    });
+                                }
+                            }
+                        }
+                        stmts
                     }
-                };
-                let param_id = ctx.define_local(param_name.clone(), param_type.clone());
-                params.push(Param {
-                    id: param_id,
-                    name: param_name,
-                    ty: param_type,
-                    default: None,
-                    is_rest: false, // TsParamProp cannot be a rest parameter
-                });
+                    ast::Pat::Object(obj_pat) => {
+                        // Object destructuring: for (const { a, b } of arr)
+                        let mut stmts = vec![Stmt::Let {
+                            id: item_id,
+                            name: format!("__item_{}", item_id),
+                            ty: Type::Any,
+                            mutable: false,
+                            init: Some(item_expr),
+                        }];
+
+                        // Extract each property using pre-defined IDs
+                        let mut var_idx = 0;
+                        for prop in &obj_pat.props {
+                            match prop {
+                                ast::ObjectPatProp::Assign(assign) => {
+                                    let prop_name = assign.key.sym.to_string();
+                                    let (name, id) = var_ids[var_idx].clone();
+                                    var_idx += 1;
+                                    stmts.push(Stmt::Let {
+                                        id,
+                                        name,
+                                        ty: Type::Any,
+                                        mutable: false,
+                                        init: Some(Expr::PropertyGet {
+                                            object: Box::new(Expr::LocalGet(item_id)),
+                                            property: prop_name,
+                                        }),
+                                    });
+                                }
+                                ast::ObjectPatProp::KeyValue(kv) => {
+                                    let key = match &kv.key {
+                                        ast::PropName::Ident(ident) => ident.sym.to_string(),
+                                        _ => continue,
+                                    };
+                                    if let ast::Pat::Ident(_) = &*kv.value {
+                                        let (name, id) = var_ids[var_idx].clone();
+                                        var_idx += 1;
+                                        stmts.push(Stmt::Let {
+                                            id,
+                                            name,
+                                            ty: Type::Any,
+                                            mutable: false,
+                                            init: Some(Expr::PropertyGet {
+                                                object: Box::new(Expr::LocalGet(item_id)),
+                                                property: key,
+                                            }),
+                                        });
+                                    }
+                                }
+                                _ => {}
+                            }
+                        }
+                        stmts
+                    }
+                    _ => {
+                        let (name, id) = var_ids[0].clone();
+                        vec![Stmt::Let {
+                            id,
+                            name,
+                            ty: Type::Any,
+                            mutable: false,
+                            init: Some(item_expr),
+                        }]
+                    }
+                }
+            } else {
+                return Err(anyhow!("for-of requires a variable declaration"));
             }
         }
-    }
-
-    // Lower body
-    let body = if let Some(ref block) = ctor.body {
-        lower_block_stmt(ctx, block)?
-    } else {
-        Vec::new()
+        ast::ForHead::Pat(_) => {
+            let (name, id) = var_ids[0].clone();
+            vec![Stmt::Let {
+                id,
+                name,
+                ty: Type::Any,
+                mutable: false,
+                init: Some(item_expr),
+            }]
+        }
+        _ => return Err(anyhow!("Unsupported for-of left-hand side")),
     };
 
-    ctx.exit_scope(scope_mark);
+    // Prepend the binding statements to the loop body
+    for (i, stmt) in binding_stmts.into_iter().enumerate() {
+        loop_body.insert(i, stmt);
+    }
 
-    Ok(Function {
-        id: ctx.fresh_func(),
-        name: format!("{}::constructor", class_name),
-        type_params: Vec::new(),
-        params,
-        return_type: Type::Void,
-        body,
-        is_async: false,
-        is_exported: false,
-        captures: Vec::new(),
-        decorators: Vec::new(),
-    })
+    if is_iterator {
+        loop_body.splice(0..0, iterator_step(source_id, cursor_id));
+        result.push(Stmt::While {
+            condition: Expr::Bool(true),
+            body: loop_body,
+        });
+        return Ok(result);
+    }
+    if let Some(next) = next_chunk {
+        loop_body.splice(0..0, chunk_step(cursor_id, next));
+        result.push(Stmt::While {
+            condition: Expr::Bool(true),
+            body: loop_body,
+        });
+        return Ok(result);
+    }
+
+    // for (let __i = 0; __i < __arr.length; __i++) { ... }
+    result.push(Stmt::For {
+        init: Some(Box::new(Stmt::Let {
+            id: cursor_id,
+            name: format!("__idx_{}", cursor_id),
+            ty: Type::Number,
+            mutable: true,
+            init: Some(Expr::Number(0.0)),
+        })),
+        condition: Some(Expr::Compare {
+            op: CompareOp::Lt,
+            left: Box::new(Expr::LocalGet(cursor_id)),
+            right: Box::new(Expr::PropertyGet {
+                object: Box::new(Expr::LocalGet(source_id)),
+                property: "length".to_string(),
+            }),
+        }),
+        update: Some(Expr::Update {
+            id: cursor_id,
+            op: UpdateOp::Increment,
+            prefix: true,
+        }),
+        body: loop_body,
+    });
+    Ok(result)
 }
 
-fn lower_class_method(ctx: &mut LoweringContext, method: &ast::ClassMethod) -> Result<Function> {
-    let name = match &method.key {
-        ast::PropName::Ident(ident) => ident.sym.to_string(),
-        ast::PropName::Str(s) => s.value.as_str().unwrap_or("").to_string(),
-        _ => return Err(anyhow!("Unsupported method key")),
-    };
 
-    // Lower decorators from the method's function
-    let decorators = lower_decorators(ctx, &method.function.decorators);
+fn lower_fn_decl(ctx: &mut LoweringContext, fn_decl: &ast::FnDecl) -> Result<Function> {
+    let name = fn_decl.ident.sym.to_string();
+    let func_id = ctx.lookup_func(&name).unwrap_or_else(|| ctx.fresh_func());
 
-    // Extract method-level type parameters (e.g., method<U>(x: U): T)
-    // Note: Class-level type params are already in scope from lower_class_decl
-    let type_params = method.function.type_params
+    // Extract type parameters from generic function declaration (e.g., function foo<T, U>(...))
+    let type_params = fn_decl.function.type_params
         .as_ref()
         .map(|tp| extract_type_params(tp))
         .unwrap_or_default();
 
-    // Enter method's type param scope (nested inside class scope if applicable)
+    // Enter type parameter scope for resolving T, U, etc. in body types
     ctx.enter_type_param_scope(&type_params);
 
     let scope_mark = ctx.enter_scope();
 
-    // Add 'this' for instance methods
-    if !method.is_static {
-        ctx.define_local("this".to_string(), Type::Any);
-    }
-
     // Lower parameters with type extraction (using context for type param resolution)
     let mut params = Vec::new();
-    for param in &method.function.params {
+    for param in fn_decl.function.params.iter() {
         let param_name = get_pat_name(&param.pat)?;
         let param_type = extract_param_type_with_ctx(&param.pat, Some(ctx));
         let param_default = get_param_default(ctx, &param.pat)?;
-        let is_rest = is_rest_param(&param.pat);
         let param_id = ctx.define_local(param_name.clone(), param_type.clone());
+        record_readonly_array(ctx, param_id, pat_type_ann(&param.pat), None);
+        let is_rest = is_rest_param(&param.pat);
         params.push(Param {
             id: param_id,
             name: param_name,
@@ -2344,48 +3012,789 @@ fn lower_class_method(ctx: &mut LoweringContext, method: &ast::ClassMethod) -> R
         });
     }
 
-    // Extract return type (with context)
-    let return_type = method.function.return_type.as_ref()
+    // Extract return type from function's type annotation (with context)
+    let return_type = fn_decl.function.return_type.as_ref()
         .map(|rt| extract_ts_type_with_ctx(&rt.type_ann, Some(ctx)))
         .unwrap_or(Type::Any);
 
+    let decorators = memoize_directive(fn_decl.function.body.as_ref())?.into_iter().collect();
+
     // Lower body
-    let body = if let Some(ref block) = method.function.body {
-        lower_block_stmt(ctx, block)?
+    let body = if let Some(ref block) = fn_decl.function.body {
+        with_default_params_prologue(&params, lower_block_stmt(ctx, block)?)
     } else {
         Vec::new()
     };
 
     ctx.exit_scope(scope_mark);
 
-    // Exit method's type param scope
+    // Exit type parameter scope
     ctx.exit_type_param_scope();
 
     Ok(Function {
-        id: ctx.fresh_func(),
+        id: func_id,
         name,
         type_params,
         params,
         return_type,
         body,
-        is_async: method.function.is_async,
+        is_async: fn_decl.function.is_async,
         is_exported: false,
         captures: Vec::new(),
         decorators,
+        span: ctx.span(fn_decl.function.span),
     })
 }
 
-/// Lower a getter method (get propertyName(): Type { ... })
-fn lower_getter_method(ctx: &mut LoweringContext, method: &ast::ClassMethod) -> Result<Function> {
-    let name = match &method.key {
-        ast::PropName::Ident(ident) => format!("get_{}", ident.sym),
-        ast::PropName::Str(s) => format!("get_{}", s.value.as_str().unwrap_or("")),
-        _ => return Err(anyhow!("Unsupported getter key")),
-    };
-
-    let scope_mark = ctx.enter_scope();
-
-    // Add 'this' for instance getters
+fn lower_class_decl(ctx: &mut LoweringContext, class_decl: &ast::ClassDecl, is_exported: bool) -> Result<Class> {
+    let name = class_decl.ident.sym.to_string();
+    let class_id = ctx.lookup_class(&name).unwrap_or_else(|| {
+        let id = ctx.fresh_class();
+        ctx.classes.push((name.clone(), id));
+        id
+    });
+
+    // Set current class for arrow function `this` capture tracking
+    let old_class = ctx.current_class.take();
+    ctx.current_class = Some(name.clone());
+
+    // Extract type parameters from generic class declaration (e.g., class Box<T>)
+    let type_params = class_decl.class.type_params
+        .as_ref()
+        .map(|tp| extract_type_params(tp))
+        .unwrap_or_default();
+
+    // Enter type parameter scope for resolving T, U, etc. in member types
+    ctx.enter_type_param_scope(&type_params);
+
+    // Handle extends clause
+    let (extends, extends_name, native_extends) = if let Some(ref super_class) = class_decl.class.super_class {
+        if let ast::Expr::Ident(ident) = super_class.as_ref() {
+            let parent_name = ident.sym.to_string();
+            // First check if it's a native module class
+            let native_parent = match parent_name.as_str() {
+                "EventEmitter" => Some(("events".to_string(), "EventEmitter".to_string())),
+                "AsyncLocalStorage" => Some(("async_hooks".to_string(), "AsyncLocalStorage".to_string())),
+                "WebSocketServer" => Some(("ws".to_string(), "WebSocketServer".to_string())),
+                _ => None,
+            };
+            if native_parent.is_some() {
+                (None, None, native_parent)
+            } else {
+                // Always capture the parent name for imported classes that may not have a ClassId
+                (ctx.lookup_class(&parent_name), Some(parent_name), None)
+            }
+        } else if let ast::Expr::Member(member) = super_class.as_ref() {
+            // Handle member expression like ethers.JsonRpcProvider or module.ClassName
+            let parent_name = extract_member_class_name(member);
+            // For member expressions, we don't have ClassId - just store the name
+            (None, Some(parent_name), None)
+        } else {
+            (None, None, None)
+        }
+    } else {
+        (None, None, None)
+    };
+
+    // First pass: collect static field/method names for early registration
+    // This allows static method bodies to reference static fields
+    let mut static_field_names = Vec::new();
+    let mut static_method_names = Vec::new();
+    for member in &class_decl.class.body {
+        match member {
+            ast::ClassMember::Method(method) if method.is_static => {
+                if let ast::PropName::Ident(ident) = &method.key {
+                    static_method_names.push(ident.sym.to_string());
+                }
+            }
+            ast::ClassMember::ClassProp(prop) if prop.is_static => {
+                if let ast::PropName::Ident(ident) = &prop.key {
+                    static_field_names.push(ident.sym.to_string());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Register static members early so method bodies can reference them
+    ctx.register_class_statics(name.clone(), static_field_names, static_method_names);
+
+    let mut fields = Vec::new();
+    let mut static_fields = Vec::new();
+    let mut constructor = None;
+    let mut methods = Vec::new();
+    let mut static_methods = Vec::new();
+    let mut getters = Vec::new();
+    let mut setters = Vec::new();
+
+    // Second pass: actually lower the class members
+    for member in &class_decl.class.body {
+        match member {
+            ast::ClassMember::Constructor(ctor) => {
+                constructor = Some(lower_constructor(ctx, &name, ctor)?);
+            }
+            ast::ClassMember::Method(method) => {
+                // Get the property name for getters/setters
+                let Some(prop_name) = static_prop_name(&method.key) else {
+                    report_dropped(ctx, method.span, "method with a computed name", "give the method a plain name");
+                    continue;
+                };
+
+                match method.kind {
+                    ast::MethodKind::Getter => {
+                        // Getter: no parameters, returns a value
+                        let func = lower_getter_method(ctx, method)?;
+                        getters.push((prop_name, func));
+                    }
+                    ast::MethodKind::Setter => {
+                        // Setter: takes one parameter
+                        let func = lower_setter_method(ctx, method)?;
+                        setters.push((prop_name, func));
+                    }
+                    ast::MethodKind::Method => {
+                        let func = lower_class_method(ctx, method)?;
+                        if method.is_static {
+                            static_methods.push(func);
+                        } else {
+                            methods.push(func);
+                        }
+                    }
+                }
+            }
+            ast::ClassMember::ClassProp(prop) => {
+                let field = lower_class_prop(ctx, prop)?;
+                if prop.is_static {
+                    static_fields.push(field);
+                } else {
+                    fields.push(field);
+                }
+            }
+            ast::ClassMember::PrivateProp(prop) => {
+                let field = lower_private_prop(ctx, prop)?;
+                if prop.is_static {
+                    static_fields.push(field);
+                } else {
+                    fields.push(field);
+                }
+            }
+            ast::ClassMember::PrivateMethod(method) => {
+                report_dropped(ctx, method.span, "private method", "make the method TypeScript `private` instead of `#`-private");
+            }
+            ast::ClassMember::StaticBlock(block) => {
+                report_dropped(ctx, block.span, "static initialization block", "move its statements after the class declaration");
+            }
+            ast::ClassMember::AutoAccessor(accessor) => {
+                report_dropped(ctx, accessor.span, "`accessor` field", "declare a plain field, or a getter and setter");
+            }
+            // Nothing to lower
+            ast::ClassMember::TsIndexSignature(_) | ast::ClassMember::Empty(_) => {}
+        }
+    }
+
+    // Exit type parameter scope
+    ctx.exit_type_param_scope();
+
+    // Restore previous current_class
+    ctx.current_class = old_class;
+
+    Ok(Class {
+        id: class_id,
+        name,
+        type_params,
+        extends,
+        extends_name,
+        native_extends,
+        fields,
+        constructor,
+        methods,
+        getters,
+        setters,
+        static_fields,
+        static_methods,
+        is_exported,
+    })
+}
+
+fn lower_enum_decl(ctx: &mut LoweringContext, enum_decl: &ast::TsEnumDecl, is_exported: bool) -> Result<Enum> {
+    let name = enum_decl.id.sym.to_string();
+    let enum_id = ctx.fresh_enum();
+
+    let mut members: Vec<EnumMember> = Vec::new();
+    let mut next_value: i64 = 0;
+
+    for member in &enum_decl.members {
+        // Get member name
+        let member_name = match &member.id {
+            ast::TsEnumMemberId::Ident(ident) => ident.sym.to_string(),
+            ast::TsEnumMemberId::Str(s) => s.value.as_str().unwrap_or("").to_string(),
+        };
+
+        // Get member value: constant initializers are folded like tsc does, including
+        // references to earlier members; anything else defaults to auto-increment
+        let value = match member.init.as_ref().and_then(|init| eval_enum_initializer(init, &name, &members)) {
+            Some(EnumConst::Number(v)) if v.fract() == 0.0 && v.is_finite() => {
+                let v = v as i64;
+                next_value = v + 1;
+                EnumValue::Number(v)
+            }
+            Some(EnumConst::String(s)) => EnumValue::String(s),
+            _ => {
+                let v = next_value;
+                next_value += 1;
+                EnumValue::Number(v)
+            }
+        };
+
+        members.push(EnumMember {
+            name: member_name,
+            value,
+        });
+    }
+
+    // Register the enum in the context for later lookups
+    let member_values: Vec<(String, EnumValue)> = members.iter()
+        .map(|m| (m.name.clone(), m.value.clone()))
+        .collect();
+    ctx.define_enum(name.clone(), enum_id, member_values);
+
+    Ok(Enum {
+        id: enum_id,
+        name,
+        members,
+        is_exported,
+    })
+}
+
+/// Constant value of an enum member initializer during evaluation
+enum EnumConst {
+    Number(f64),
+    String(String),
+}
+
+/// Evaluate an enum member initializer as a constant expression: literals, unary and
+/// binary operators, parentheses, substitution-free templates and references to earlier
+/// members (`A` or `Enum.A`). Returns None for computed (non-constant) initializers.
+fn eval_enum_initializer(expr: &ast::Expr, enum_name: &str, members: &[EnumMember]) -> Option<EnumConst> {
+    let member_value = |name: &str| members.iter().find(|m| m.name == name).map(|m| match &m.value {
+        EnumValue::Number(n) => EnumConst::Number(*n as f64),
+        EnumValue::String(s) => EnumConst::String(s.clone()),
+    });
+    let number = |expr: &ast::Expr| match eval_enum_initializer(expr, enum_name, members)? {
+        EnumConst::Number(n) => Some(n),
+        EnumConst::String(_) => None,
+    };
+    // ToInt32 for bitwise operators
+    let int32 = |v: f64| v as i64 as i32;
+
+    match expr {
+        ast::Expr::Lit(ast::Lit::Num(n)) => Some(EnumConst::Number(n.value)),
+        ast::Expr::Lit(ast::Lit::Str(s)) => Some(EnumConst::String(s.value.as_str().unwrap_or("").to_string())),
+        ast::Expr::Tpl(tpl) if tpl.exprs.is_empty() => {
+            let cooked = tpl.quasis.first()?.cooked.as_ref()?;
+            Some(EnumConst::String(cooked.as_str().unwrap_or("").to_string()))
+        }
+        ast::Expr::Paren(paren) => eval_enum_initializer(&paren.expr, enum_name, members),
+        ast::Expr::Ident(ident) => member_value(ident.sym.as_ref()),
+        ast::Expr::Member(member) => match (member.obj.as_ref(), &member.prop) {
+            (ast::Expr::Ident(obj), ast::MemberProp::Ident(prop)) if obj.sym.as_ref() == enum_name => {
+                member_value(prop.sym.as_ref())
+            }
+            _ => None,
+        },
+        ast::Expr::Unary(unary) => {
+            let v = number(&unary.arg)?;
+            match unary.op {
+                ast::UnaryOp::Minus => Some(EnumConst::Number(-v)),
+                ast::UnaryOp::Plus => Some(EnumConst::Number(v)),
+                ast::UnaryOp::Tilde => Some(EnumConst::Number(!int32(v) as f64)),
+                _ => None,
+            }
+        }
+        ast::Expr::Bin(bin) => {
+            if bin.op == ast::BinaryOp::Add {
+                let left = eval_enum_initializer(&bin.left, enum_name, members)?;
+                let right = eval_enum_initializer(&bin.right, enum_name, members)?;
+                return Some(match (left, right) {
+                    (EnumConst::Number(l), EnumConst::Number(r)) => EnumConst::Number(l + r),
+                    (EnumConst::String(l), EnumConst::String(r)) => EnumConst::String(l + r.as_str()),
+                    (EnumConst::String(l), EnumConst::Number(r)) => EnumConst::String(format!("{}{}", l, format_enum_number(r))),
+                    (EnumConst::Number(l), EnumConst::String(r)) => EnumConst::String(format!("{}{}", format_enum_number(l), r)),
+                });
+            }
+            let l = number(&bin.left)?;
+            let r = number(&bin.right)?;
+            let v = match bin.op {
+                ast::BinaryOp::Sub => l - r,
+                ast::BinaryOp::Mul => l * r,
+                ast::BinaryOp::Div => l / r,
+                ast::BinaryOp::Mod => l % r,
+                ast::BinaryOp::Exp => l.powf(r),
+                ast::BinaryOp::BitOr => (int32(l) | int32(r)) as f64,
+                ast::BinaryOp::BitAnd => (int32(l) & int32(r)) as f64,
+                ast::BinaryOp::BitXor => (int32(l) ^ int32(r)) as f64,
+                ast::BinaryOp::LShift => int32(l).wrapping_shl(int32(r) as u32 & 31) as f64,
+                ast::BinaryOp::RShift => (int32(l) >> (int32(r) as u32 & 31)) as f64,
+                ast::BinaryOp::ZeroFillRShift => ((int32(l) as u32) >> (int32(r) as u32 & 31)) as f64,
+                _ => return None,
+            };
+            Some(EnumConst::Number(v))
+        }
+        _ => None,
+    }
+}
+
+/// Format an integral enum value the way JS converts it to a string
+fn format_enum_number(v: f64) -> String {
+    if v.fract() == 0.0 && v.abs() < 1e21 {
+        format!("{}", v as i64)
+    } else {
+        format!("{}", v)
+    }
+}
+
+/// Build the runtime object for an enum, bound to a module-level local with the enum's
+/// name so the enum can be used as a value (`Object.values(E)`, `E[E.A]`, `f(E)`).
+/// Member access with a known name (`E.A`) still inlines the constant.
+/// Numeric members get a reverse mapping (value -> name) like tsc emits; as in JS,
+/// array-index keys come first in ascending order. `declare` enums and non-exported
+/// `const` enums have no runtime object; exported `const` enums keep one so importers can
+/// still use them as values where their members aren't folded to literals.
+fn lower_enum_object(ctx: &mut LoweringContext, enum_decl: &ast::TsEnumDecl, en: &Enum) -> Option<Stmt> {
+    if (enum_decl.is_const && !en.is_exported) || enum_decl.declare {
+        return None;
+    }
+
+    let mut props: Vec<(String, Expr)> = Vec::new();
+    let mut set = |key: String, value: Expr| {
+        match props.iter_mut().find(|(k, _)| *k == key) {
+            Some(existing) => existing.1 = value,
+            None => props.push((key, value)),
+        }
+    };
+    for member in &en.members {
+        match &member.value {
+            EnumValue::Number(n) => {
+                set(member.name.clone(), Expr::Number(*n as f64));
+                set(n.to_string(), Expr::String(member.name.clone()));
+            }
+            EnumValue::String(s) => set(member.name.clone(), Expr::String(s.clone())),
+        }
+    }
+    let array_index = |key: &str| key.parse::<u32>().ok().filter(|i| i.to_string() == key && *i != u32::MAX);
+    props.sort_by_key(|(key, _)| array_index(key).map_or((1, 0), |i| (0, i)));
+
+    let id = ctx.define_local(en.name.clone(), Type::Any);
+    Some(Stmt::Let {
+        id,
+        name: en.name.clone(),
+        ty: Type::Any,
+        mutable: false,
+        init: Some(Expr::Object(props)),
+    })
+}
+
+/// Record the exported bindings whose values are known at compile time in
+/// `module.exported_constants`: enums, top-level `const`s of literals (or of other
+/// constants) and `as const` objects, under every name they are exported as.
+fn collect_exported_constants(module: &mut Module, ast_module: &ast::Module) {
+    let mut constants: Vec<(String, ConstValue)> = module.enums.iter()
+        .map(|en| (en.name.clone(), ConstValue::Enum(en.members.clone())))
+        .collect();
+    for item in &ast_module.body {
+        let var_decl = match item {
+            ast::ModuleItem::Stmt(ast::Stmt::Decl(ast::Decl::Var(var_decl))) => var_decl,
+            ast::ModuleItem::ModuleDecl(ast::ModuleDecl::ExportDecl(ast::ExportDecl { decl: ast::Decl::Var(var_decl), .. })) => var_decl,
+            _ => continue,
+        };
+        if var_decl.kind != ast::VarDeclKind::Const {
+            continue;
+        }
+        for decl in &var_decl.decls {
+            if let (ast::Pat::Ident(binding), Some(init)) = (&decl.name, &decl.init) {
+                if let Some(value) = eval_const_expr(init, &constants, false) {
+                    constants.push((binding.id.sym.to_string(), value));
+                }
+            }
+        }
+    }
+
+    for export in &module.exports {
+        if let Export::Named { local, exported } = export {
+            if let Some((_, value)) = constants.iter().rev().find(|(name, _)| name == local) {
+                module.exported_constants.push((exported.clone(), value.clone()));
+            }
+        }
+    }
+}
+
+/// Evaluate a module-level `const` initializer: literals, templates without
+/// substitutions, negated numbers, references to other constants and enum members, and
+/// object literals under `as const` (only those are deeply immutable).
+fn eval_const_expr(expr: &ast::Expr, constants: &[(String, ConstValue)], frozen: bool) -> Option<ConstValue> {
+    match expr {
+        ast::Expr::Lit(ast::Lit::Num(n)) => Some(ConstValue::Number(n.value)),
+        ast::Expr::Lit(ast::Lit::Str(s)) => Some(ConstValue::String(s.value.as_str()?.to_string())),
+        ast::Expr::Lit(ast::Lit::Bool(b)) => Some(ConstValue::Bool(b.value)),
+        ast::Expr::Tpl(tpl) if tpl.exprs.is_empty() => {
+            let cooked = tpl.quasis.first()?.cooked.as_ref()?;
+            Some(ConstValue::String(cooked.as_str()?.to_string()))
+        }
+        ast::Expr::Unary(unary) if unary.op == ast::UnaryOp::Minus => {
+            match eval_const_expr(&unary.arg, constants, frozen)? {
+                ConstValue::Number(n) => Some(ConstValue::Number(-n)),
+                _ => None,
+            }
+        }
+        ast::Expr::Paren(paren) => eval_const_expr(&paren.expr, constants, frozen),
+        ast::Expr::TsConstAssertion(assertion) => eval_const_expr(&assertion.expr, constants, true),
+        ast::Expr::Ident(ident) => constants.iter().rev()
+            .find(|(name, _)| name == ident.sym.as_ref())
+            .map(|(_, value)| value.clone()),
+        ast::Expr::Member(member) => {
+            let property = match &member.prop {
+                ast::MemberProp::Ident(ident) => ident.sym.to_string(),
+                ast::MemberProp::Computed(computed) => match computed.expr.as_ref() {
+                    ast::Expr::Lit(ast::Lit::Str(s)) => s.value.as_str()?.to_string(),
+                    _ => return None,
+                },
+                ast::MemberProp::PrivateName(_) => return None,
+            };
+            eval_const_expr(&member.obj, constants, frozen)?.member(&property)
+        }
+        ast::Expr::Object(object) if frozen => {
+            let mut properties = Vec::new();
+            for prop in &object.props {
+                let ast::PropOrSpread::Prop(prop) = prop else { return None };
+                let ast::Prop::KeyValue(kv) = prop.as_ref() else { return None };
+                let key = match &kv.key {
+                    ast::PropName::Ident(ident) => ident.sym.to_string(),
+                    ast::PropName::Str(s) => s.value.as_str()?.to_string(),
+                    ast::PropName::Num(n) => format_enum_number(n.value),
+                    _ => return None,
+                };
+                let value = eval_const_expr(&kv.value, constants, true)?;
+                properties.retain(|(k, _): &(String, ConstValue)| *k != key);
+                properties.push((key, value));
+            }
+            Some(ConstValue::Object(properties))
+        }
+        _ => None,
+    }
+}
+
+fn lower_interface_decl(ctx: &mut LoweringContext, iface_decl: &ast::TsInterfaceDecl, is_exported: bool) -> Result<Interface> {
+    let name = iface_decl.id.sym.to_string();
+    // Later declarations of an interface merge into the first one
+    let declared = ctx.interfaces.iter().find(|(n, _)| *n == name).map(|(_, id)| *id);
+    let iface_id = declared.unwrap_or_else(|| ctx.fresh_interface());
+
+    // Extract type parameters
+    let type_params = iface_decl.type_params.as_ref()
+        .map(|tp| extract_type_params(tp))
+        .unwrap_or_default();
+
+    // Enter type param scope for resolving type references in body
+    ctx.enter_type_param_scope(&type_params);
+
+    // Extract extended interfaces
+    let extends: Vec<Type> = iface_decl.extends.iter()
+        .map(|ext| {
+            let base_name = match &*ext.expr {
+                ast::Expr::Ident(id) => id.sym.to_string(),
+                _ => "unknown".to_string(),
+            };
+            // Handle type arguments if present
+            if let Some(ref type_args) = ext.type_args {
+                let args: Vec<Type> = type_args.params.iter()
+                    .map(|t| extract_ts_type_with_ctx(t, Some(ctx)))
+                    .collect();
+                if args.is_empty() {
+                    Type::Named(base_name)
+                } else {
+                    Type::Generic {
+                        base: base_name,
+                        type_args: args,
+                    }
+                }
+            } else {
+                Type::Named(base_name)
+            }
+        })
+        .collect();
+
+    // Extract properties and methods from interface body
+    let mut properties = Vec::new();
+    let mut methods = Vec::new();
+
+    for member in &iface_decl.body.body {
+        match member {
+            ast::TsTypeElement::TsPropertySignature(prop) => {
+                let prop_name = match &*prop.key {
+                    ast::Expr::Ident(id) => id.sym.to_string(),
+                    ast::Expr::Lit(ast::Lit::Str(s)) => s.value.as_str().unwrap_or("").to_string(),
+                    _ => continue,
+                };
+                let prop_type = prop.type_ann.as_ref()
+                    .map(|ta| extract_ts_type_with_ctx(&ta.type_ann, Some(ctx)))
+                    .unwrap_or(Type::Any);
+                properties.push(InterfaceProperty {
+                    name: prop_name,
+                    ty: prop_type,
+                    optional: prop.optional,
+                    readonly: prop.readonly,
+                });
+            }
+            ast::TsTypeElement::TsMethodSignature(method) => {
+                let method_name = match &*method.key {
+                    ast::Expr::Ident(id) => id.sym.to_string(),
+                    ast::Expr::Lit(ast::Lit::Str(s)) => s.value.as_str().unwrap_or("").to_string(),
+                    _ => continue,
+                };
+
+                // Method's own type parameters
+                let method_type_params = method.type_params.as_ref()
+                    .map(|tp| extract_type_params(tp))
+                    .unwrap_or_default();
+
+                // Enter method's type param scope
+                ctx.enter_type_param_scope(&method_type_params);
+
+                // Extract parameters
+                let params: Vec<(String, Type, bool)> = method.params.iter()
+                    .map(|p| {
+                        let (name, ty) = get_fn_param_name_and_type_with_ctx(p, Some(ctx));
+                        let optional = matches!(p, ast::TsFnParam::Ident(id) if id.optional);
+                        (name, ty, optional)
+                    })
+                    .collect();
+
+                // Extract return type
+                let return_type = method.type_ann.as_ref()
+                    .map(|ta| extract_ts_type_with_ctx(&ta.type_ann, Some(ctx)))
+                    .unwrap_or(Type::Void);
+
+                ctx.exit_type_param_scope();
+
+                methods.push(InterfaceMethod {
+                    name: method_name,
+                    type_params: method_type_params,
+                    params,
+                    return_type,
+                });
+            }
+            _ => {} // Skip other member types for now
+        }
+    }
+
+    ctx.exit_type_param_scope();
+
+    // Register interface in context
+    if declared.is_none() {
+        ctx.interfaces.push((name.clone(), iface_id));
+    }
+
+    Ok(Interface {
+        id: iface_id,
+        name,
+        type_params,
+        extends,
+        properties,
+        methods,
+        is_exported,
+    })
+}
+
+fn lower_type_alias_decl(ctx: &mut LoweringContext, alias_decl: &ast::TsTypeAliasDecl, is_exported: bool) -> Result<TypeAlias> {
+    let name = alias_decl.id.sym.to_string();
+    let alias_id = ctx.fresh_type_alias();
+
+    // Extract type parameters
+    let type_params = alias_decl.type_params.as_ref()
+        .map(|tp| extract_type_params(tp))
+        .unwrap_or_default();
+
+    // Enter type param scope for resolving type references
+    ctx.enter_type_param_scope(&type_params);
+
+    // Extract the aliased type
+    let ty = extract_ts_type_with_ctx(&alias_decl.type_ann, Some(ctx));
+
+    ctx.exit_type_param_scope();
+
+    // Register type alias in context
+    ctx.type_aliases.push((name.clone(), alias_id, type_params.clone(), ty.clone()));
+
+    Ok(TypeAlias {
+        id: alias_id,
+        name,
+        type_params,
+        ty,
+        is_exported,
+    })
+}
+
+fn lower_constructor(ctx: &mut LoweringContext, class_name: &str, ctor: &ast::Constructor) -> Result<Function> {
+    let scope_mark = ctx.enter_scope();
+
+    // Add 'this' as a special local
+    let _this_id = ctx.define_local("this".to_string(), Type::Any);
+
+    // Lower parameters with type extraction (using context for class type param resolution)
+    let mut params = Vec::new();
+    for param in &ctor.params {
+        match param {
+            ast::ParamOrTsParamProp::Param(p) => {
+                let param_name = get_pat_name(&p.pat)?;
+                let param_type = extract_param_type_with_ctx(&p.pat, Some(ctx));
+                let param_default = get_param_default(ctx, &p.pat)?;
+                let is_rest = is_rest_param(&p.pat);
+                let param_id = ctx.define_local(param_name.clone(), param_type.clone());
+                record_readonly_array(ctx, param_id, pat_type_ann(&p.pat), None);
+                params.push(Param {
+                    id: param_id,
+                    name: param_name,
+                    ty: param_type,
+                    default: param_default,
+                    is_rest,
+                });
+            }
+            ast::ParamOrTsParamProp::TsParamProp(ts_prop) => {
+                // Handle parameter properties (e.g., constructor(public x: number))
+                let (param_name, param_type) = match &ts_prop.param {
+                    ast::TsParamPropParam::Ident(ident) => {
+                        let name = ident.id.sym.to_string();
+                        let ty = ident.type_ann.as_ref()
+                            .map(|ann| extract_ts_type_with_ctx(&ann.type_ann, Some(ctx)))
+                            .unwrap_or(Type::Any);
+                        (name, ty)
+                    }
+                    ast::TsParamPropParam::Assign(assign) => {
+                        let name = get_pat_name(&assign.left)?;
+                        let ty = extract_param_type_with_ctx(&assign.left, Some(ctx));
+                        (name, ty)
+                    }
+                };
+                let param_id = ctx.define_local(param_name.clone(), param_type.clone());
+                params.push(Param {
+                    id: param_id,
+                    name: param_name,
+                    ty: param_type,
+                    default: None,
+                    is_rest: false, // TsParamProp cannot be a rest parameter
+                });
+            }
+        }
+    }
+
+    // Lower body (the only place the class may assign its readonly fields)
+    let outer_in_constructor = std::mem::replace(&mut ctx.in_constructor, true);
+    let body = ctor.body.as_ref().map(|block| lower_block_stmt(ctx, block)).transpose();
+    ctx.in_constructor = outer_in_constructor;
+    let body = with_default_params_prologue(&params, body?.unwrap_or_default());
+
+    ctx.exit_scope(scope_mark);
+
+    Ok(Function {
+        id: ctx.fresh_func(),
+        name: format!("{}::constructor", class_name),
+        type_params: Vec::new(),
+        params,
+        return_type: Type::Void,
+        body,
+        is_async: false,
+        is_exported: false,
+        captures: Vec::new(),
+        decorators: Vec::new(),
+        span: ctx.span(ctor.span),
+    })
+}
+
+fn lower_class_method(ctx: &mut LoweringContext, method: &ast::ClassMethod) -> Result<Function> {
+    let name = static_prop_name(&method.key)
+        .ok_or_else(|| anyhow!("Unsupported method key"))?;
+
+    // Lower decorators from the method's function
+    let mut decorators = lower_decorators(ctx, &method.function.decorators);
+    decorators.extend(memoize_directive(method.function.body.as_ref())?);
+
+    // Extract method-level type parameters (e.g., method<U>(x: U): T)
+    // Note: Class-level type params are already in scope from lower_class_decl
+    let type_params = method.function.type_params
+        .as_ref()
+        .map(|tp| extract_type_params(tp))
+        .unwrap_or_default();
+
+    // Enter method's type param scope (nested inside class scope if applicable)
+    ctx.enter_type_param_scope(&type_params);
+
+    let scope_mark = ctx.enter_scope();
+
+    // Add 'this' for instance methods
+    if !method.is_static {
+        ctx.define_local("this".to_string(), Type::Any);
+    }
+
+    // Lower parameters with type extraction (using context for type param resolution)
+    let mut params = Vec::new();
+    for param in &method.function.params {
+        let param_name = get_pat_name(&param.pat)?;
+        let param_type = extract_param_type_with_ctx(&param.pat, Some(ctx));
+        let param_default = get_param_default(ctx, &param.pat)?;
+        let is_rest = is_rest_param(&param.pat);
+        let param_id = ctx.define_local(param_name.clone(), param_type.clone());
+        record_readonly_array(ctx, param_id, pat_type_ann(&param.pat), None);
+        params.push(Param {
+            id: param_id,
+            name: param_name,
+            ty: param_type,
+            default: param_default,
+            is_rest,
+        });
+    }
+
+    // Extract return type (with context)
+    let return_type = method.function.return_type.as_ref()
+        .map(|rt| extract_ts_type_with_ctx(&rt.type_ann, Some(ctx)))
+        .unwrap_or(Type::Any);
+
+    // Lower body
+    let body = if let Some(ref block) = method.function.body {
+        with_default_params_prologue(&params, lower_block_stmt(ctx, block)?)
+    } else {
+        Vec::new()
+    };
+
+    ctx.exit_scope(scope_mark);
+
+    // Exit method's type param scope
+    ctx.exit_type_param_scope();
+
+    Ok(Function {
+        id: ctx.fresh_func(),
+        name,
+        type_params,
+        params,
+        return_type,
+        body,
+        is_async: method.function.is_async,
+        is_exported: false,
+        captures: Vec::new(),
+        decorators,
+        span: ctx.span(method.span),
+    })
+}
+
+/// Lower a getter method (get propertyName(): Type { ... })
+fn lower_getter_method(ctx: &mut LoweringContext, method: &ast::ClassMethod) -> Result<Function> {
+    let name = match &method.key {
+        ast::PropName::Ident(ident) => format!("get_{}", ident.sym),
+        ast::PropName::Str(s) => format!("get_{}", s.value.as_str().unwrap_or("")),
+        _ => return Err(anyhow!("Unsupported getter key")),
+    };
+
+    let scope_mark = ctx.enter_scope();
+
+    // Add 'this' for instance getters
     ctx.define_local("this".to_string(), Type::Any);
 
     // Getters have no parameters
@@ -2415,6 +3824,7 @@ fn lower_getter_method(ctx: &mut LoweringContext, method: &ast::ClassMethod) ->
         is_exported: false,
         captures: Vec::new(),
         decorators: Vec::new(),
+        span: ctx.span(method.span),
     })
 }
 
@@ -2446,85 +3856,1064 @@ fn lower_setter_method(ctx: &mut LoweringContext, method: &ast::ClassMethod) ->
         });
     }
 
-    // Lower body
-    let body = if let Some(ref block) = method.function.body {
-        lower_block_stmt(ctx, block)?
-    } else {
-        Vec::new()
+    // Lower body
+    let body = if let Some(ref block) = method.function.body {
+        lower_block_stmt(ctx, block)?
+    } else {
+        Vec::new()
+    };
+
+    ctx.exit_scope(scope_mark);
+
+    Ok(Function {
+        id: ctx.fresh_func(),
+        name,
+        type_params: Vec::new(),
+        params,
+        return_type: Type::Void,
+        body,
+        is_async: false,
+        is_exported: false,
+        captures: Vec::new(),
+        decorators: Vec::new(),
+        span: ctx.span(method.span),
+    })
+}
+
+fn lower_class_prop(ctx: &mut LoweringContext, prop: &ast::ClassProp) -> Result<ClassField> {
+    let name = match &prop.key {
+        ast::PropName::Ident(ident) => ident.sym.to_string(),
+        ast::PropName::Str(s) => s.value.as_str().unwrap_or("").to_string(),
+        _ => return Err(anyhow!("Unsupported property key")),
+    };
+
+    // Extract type from type annotation (using context for class type param resolution)
+    let ty = prop.type_ann.as_ref()
+        .map(|ann| extract_ts_type_with_ctx(&ann.type_ann, Some(ctx)))
+        .unwrap_or(Type::Any);
+
+    // Lower initializer expression if present
+    let init = prop.value.as_ref()
+        .map(|e| lower_expr(ctx, e))
+        .transpose()?;
+
+    Ok(ClassField {
+        name,
+        ty,
+        init,
+        is_private: prop.accessibility == Some(ast::Accessibility::Private),
+        is_readonly: prop.readonly,
+    })
+}
+
+fn lower_private_prop(ctx: &mut LoweringContext, prop: &ast::PrivateProp) -> Result<ClassField> {
+    // Private fields use PrivateName which has a `name` field (without the # prefix in SWC)
+    // We store the name with the # prefix to distinguish private fields
+    let name = format!("#{}", prop.key.name.to_string());
+
+    // Extract type from type annotation (using context for class type param resolution)
+    let ty = prop.type_ann.as_ref()
+        .map(|ann| extract_ts_type_with_ctx(&ann.type_ann, Some(ctx)))
+        .unwrap_or(Type::Any);
+
+    // Lower initializer expression if present
+    let init = prop.value.as_ref()
+        .map(|e| lower_expr(ctx, e))
+        .transpose()?;
+
+    Ok(ClassField {
+        name,
+        ty,
+        init,
+        is_private: true,
+        is_readonly: prop.readonly,
+    })
+}
+
+fn lower_block_stmt(ctx: &mut LoweringContext, block: &ast::BlockStmt) -> Result<Vec<Stmt>> {
+    lower_stmt_list(ctx, &block.stmts)
+}
+
+fn lower_stmt_list(ctx: &mut LoweringContext, stmts: &[ast::Stmt]) -> Result<Vec<Stmt>> {
+    let mut result = Vec::new();
+    // Function declarations are hoisted: callable anywhere in the block
+    for stmt in stmts {
+        if let ast::Stmt::Decl(ast::Decl::Fn(fn_decl)) = stmt {
+            result.extend(lower_nested_fn_decl(ctx, fn_decl)?);
+        }
+    }
+    for (i, stmt) in stmts.iter().enumerate() {
+        if matches!(stmt, ast::Stmt::Decl(ast::Decl::Fn(_))) {
+            continue;
+        }
+        if let ast::Stmt::Decl(ast::Decl::Using(using_decl)) = stmt {
+            // The rest of the block becomes the body of the using scope
+            result.extend(lower_using_decl(ctx, &using_decl.decls, using_decl.is_await, &stmts[i + 1..])?);
+            return Ok(result);
+        }
+        result.extend(lower_body_stmt(ctx, stmt)?);
+        // After `if (x === null) return;` the rest of the block sees `x` narrowed
+        if let ast::Stmt::If(if_stmt) = stmt {
+            let rest = &stmts[i + 1..];
+            let guards = exit_guards(ctx, if_stmt);
+            if !guards.is_empty() && !rest.is_empty() {
+                let (narrowed, shadows) = shadow_narrowed(ctx, &guards, |name| rest.iter().any(|s| may_assign_stmt(s, name)));
+                result.extend(narrowed);
+                let body = lower_stmt_list(ctx, rest);
+                unshadow(ctx, shadows);
+                result.extend(body?);
+                return Ok(result);
+            }
+        }
+    }
+    Ok(result)
+}
+
+/// `using a = x, b = y; rest` ->
+/// `let a = x; try { let b = y; try { rest } finally { dispose(b) } } finally { dispose(a) }`
+fn lower_using_decl(
+    ctx: &mut LoweringContext,
+    decls: &[ast::VarDeclarator],
+    is_await: bool,
+    rest: &[ast::Stmt],
+) -> Result<Vec<Stmt>> {
+    let Some((decl, remaining)) = decls.split_first() else {
+        return lower_stmt_list(ctx, rest);
+    };
+    let (mut result, id, ty) = lower_using_binding(ctx, decl)?;
+    let body = lower_using_decl(ctx, remaining, is_await, rest)?;
+    result.extend(using_scope(ctx, body, id, ty, is_await));
+    Ok(result)
+}
+
+/// Lower one `using` declarator as a const binding, returning the resource local and its type
+fn lower_using_binding(ctx: &mut LoweringContext, decl: &ast::VarDeclarator) -> Result<(Vec<Stmt>, LocalId, Type)> {
+    let name = match &decl.name {
+        ast::Pat::Ident(ident) => ident.id.sym.to_string(),
+        _ => return Err(anyhow!("'using' declarations may not have binding patterns")),
+    };
+    let stmts = lower_var_decl_with_destructuring(ctx, decl, false)?;
+    let id = ctx.lookup_local(&name)
+        .ok_or_else(|| anyhow!("'using' binding {} was not defined", name))?;
+    let ty = ctx.lookup_local_type(&name).cloned().unwrap_or(Type::Any);
+    Ok((stmts, id, ty))
+}
+
+/// try { body } finally { resource[Symbol.dispose]() }
+fn using_scope(ctx: &mut LoweringContext, body: Vec<Stmt>, id: LocalId, ty: Type, is_await: bool) -> Vec<Stmt> {
+    let dispose = dispose_resource_expr(Expr::LocalGet(id), &ty, is_await);
+    let dispose = if is_await { Expr::Await(Box::new(dispose)) } else { dispose };
+    lower_try(ctx, body, None, Some(vec![Stmt::Expr(dispose)]))
+}
+
+/// Completion codes of a protected region (see `lower_try`); returns use
+/// `COMPLETION_RETURN + n` for the region's n-th `return`
+const COMPLETION_NORMAL: f64 = 0.0;
+const COMPLETION_THROW: f64 = 1.0;
+const COMPLETION_BREAK: f64 = 2.0;
+const COMPLETION_CONTINUE: f64 = 3.0;
+const COMPLETION_RETURN: f64 = 4.0;
+
+/// Lower `try`/`catch`/`finally` so that `finally` runs however the protected code is left.
+///
+/// Codegen implements `try` with setjmp/longjmp and only pops the handler when control
+/// falls off the end of the body or enters `catch`, so a `return`, `break` or `continue`
+/// may not jump out of a `try` body directly. Such an exit instead records a completion
+/// code (a `return` also saves its value) and skips the rest of the region; the exit is
+/// replayed once control is back outside:
+///
+/// ```text
+/// let __completion = 0;
+/// try { body } catch (e) { __completion = 1; __error = e; }
+/// if (__completion === 1) {                                   // only with a catch clause
+///     __completion = 0;
+///     try { let err = __error; handler } catch (e) { __completion = 1; __error = e; }
+/// }
+/// finalizer
+/// if (__completion === 1) throw __error;
+/// if (__completion === 2) break;  ...  if (__completion === 4) return __return_4;
+/// ```
+///
+/// The finalizer is emitted once, after both regions, so a `return` or `throw` inside it
+/// overrides the pending completion as in JS. Without a finalizer the catch clause is kept
+/// as is and only exits from the body are rewritten.
+fn lower_try(
+    ctx: &mut LoweringContext,
+    body: Vec<Stmt>,
+    catch: Option<CatchClause>,
+    finally: Option<Vec<Stmt>>,
+) -> Vec<Stmt> {
+    if finally.is_none() && !has_region_exits(&body, 0, 0) {
+        return vec![Stmt::Try { body, catch, finally }];
+    }
+    let completion = ctx.fresh_local();
+    let mut exits = RegionExits { completion, returns: Vec::new(), breaks: false, continues: false };
+    let mut result = vec![Stmt::Let {
+        id: completion,
+        name: format!("__completion_{}", completion),
+        ty: Type::Number,
+        mutable: true,
+        init: Some(Expr::Number(COMPLETION_NORMAL)),
+    }];
+    let (body, _) = exits.rewrite(ctx, body, 0, 0);
+
+    match finally {
+        None => result.push(Stmt::Try { body, catch, finally: None }),
+        Some(finalizer) => {
+            let error = ctx.fresh_local();
+            result.push(Stmt::Let {
+                id: error,
+                name: format!("__error_{}", error),
+                ty: Type::Any,
+                mutable: true,
+                init: None,
+            });
+            let record = exits.record_throw(ctx, error);
+            result.push(Stmt::Try { body, catch: Some(record), finally: None });
+
+            if let Some(CatchClause { param, body: handler }) = catch {
+                let mut handler_body = Vec::new();
+                if let Some((id, name)) = param {
+                    handler_body.push(Stmt::Let { id, name, ty: Type::Any, mutable: true, init: Some(Expr::LocalGet(error)) });
+                }
+                handler_body.extend(exits.rewrite(ctx, handler, 0, 0).0);
+                let record = exits.record_throw(ctx, error);
+                result.push(Stmt::If {
+                    condition: completion_is(completion, COMPLETION_THROW),
+                    then_branch: vec![
+                        set_completion(completion, COMPLETION_NORMAL),
+                        Stmt::Try { body: handler_body, catch: Some(record), finally: None },
+                    ],
+                    else_branch: None,
+                });
+            }
+
+            result.extend(finalizer);
+            result.push(Stmt::If {
+                condition: completion_is(completion, COMPLETION_THROW),
+                then_branch: vec![Stmt::Throw(Expr::LocalGet(error))],
+                else_branch: None,
+            });
+        }
+    }
+
+    let replay = |code: f64, stmt: Stmt| Stmt::If {
+        condition: completion_is(completion, code),
+        then_branch: vec![stmt],
+        else_branch: None,
+    };
+    if exits.breaks {
+        result.push(replay(COMPLETION_BREAK, Stmt::Break));
+    }
+    if exits.continues {
+        result.push(replay(COMPLETION_CONTINUE, Stmt::Continue));
+    }
+    for (code, value) in exits.returns {
+        result.push(replay(code, Stmt::Return(value.map(Expr::LocalGet))));
+    }
+    result
+}
+
+fn completion_is(completion: LocalId, code: f64) -> Expr {
+    Expr::Compare {
+        op: CompareOp::Eq,
+        left: Box::new(Expr::LocalGet(completion)),
+        right: Box::new(Expr::Number(code)),
+    }
+}
+
+fn set_completion(completion: LocalId, code: f64) -> Stmt {
+    Stmt::Expr(Expr::LocalSet(completion, Box::new(Expr::Number(code))))
+}
+
+/// Whether `stmts` (`loops` loops and `switches` switches deep inside a protected region)
+/// contain a `return`, `break` or `continue` that leaves the region
+fn has_region_exits(stmts: &[Stmt], loops: u32, switches: u32) -> bool {
+    stmts.iter().any(|stmt| match stmt {
+        Stmt::Return(_) => true,
+        Stmt::Break => loops == 0 && switches == 0,
+        Stmt::Continue => loops == 0,
+        Stmt::If { then_branch, else_branch, .. } => {
+            has_region_exits(then_branch, loops, switches)
+                || else_branch.as_ref().is_some_and(|b| has_region_exits(b, loops, switches))
+        }
+        Stmt::While { body, .. } | Stmt::For { body, .. } => has_region_exits(body, loops + 1, switches),
+        Stmt::Switch { cases, .. } => cases.iter().any(|c| has_region_exits(&c.body, loops, switches + 1)),
+        Stmt::Try { body, catch, finally } => {
+            has_region_exits(body, loops, switches)
+                || catch.as_ref().is_some_and(|c| has_region_exits(&c.body, loops, switches))
+                || finally.as_ref().is_some_and(|f| has_region_exits(f, loops, switches))
+        }
+        _ => false,
+    })
+}
+
+/// Exits taken out of a protected region, recorded while rewriting it (see `lower_try`)
+struct RegionExits {
+    completion: LocalId,
+    /// Completion code of each `return`, with the local holding its value
+    returns: Vec<(f64, Option<LocalId>)>,
+    breaks: bool,
+    continues: bool,
+}
+
+impl RegionExits {
+    /// `catch (e) { __completion = 1; __error = e; }`
+    fn record_throw(&self, ctx: &mut LoweringContext, error: LocalId) -> CatchClause {
+        let param = ctx.fresh_local();
+        CatchClause {
+            param: Some((param, format!("__caught_{}", param))),
+            body: vec![
+                Stmt::Expr(Expr::LocalSet(error, Box::new(Expr::LocalGet(param)))),
+                set_completion(self.completion, COMPLETION_THROW),
+            ],
+        }
+    }
+
+    /// Replace the exits in `stmts` (`loops` loops and `switches` switches deep) with
+    /// completion codes. At the top of the region the statements after a possible exit only
+    /// run while the completion is still normal (each one separately, so codegen keeps saving
+    /// locals after every statement of the `try` body); deeper down an exit also breaks out of
+    /// the enclosing loop or switch, and so does every loop or switch it breaks out of.
+    /// Returns the statements and whether any of them may exit.
+    fn rewrite(&mut self, ctx: &mut LoweringContext, stmts: Vec<Stmt>, loops: u32, switches: u32) -> (Vec<Stmt>, bool) {
+        let nested = loops + switches > 0;
+        let mut result = Vec::new();
+        let mut may_exit = false;
+        for stmt in stmts {
+            let (stmts, exits) = self.rewrite_stmt(ctx, stmt, loops, switches);
+            if may_exit && !nested {
+                result.push(Stmt::If {
+                    condition: completion_is(self.completion, COMPLETION_NORMAL),
+                    then_branch: stmts,
+                    else_branch: None,
+                });
+            } else {
+                result.extend(stmts);
+            }
+            may_exit |= exits;
+        }
+        (result, may_exit)
+    }
+
+    fn rewrite_stmt(&mut self, ctx: &mut LoweringContext, stmt: Stmt, loops: u32, switches: u32) -> (Vec<Stmt>, bool) {
+        let nested = loops + switches > 0;
+        let completion = self.completion;
+        let exit = |code: f64, mut stmts: Vec<Stmt>| {
+            stmts.push(set_completion(completion, code));
+            if nested {
+                stmts.push(Stmt::Break);
+            }
+            (stmts, true)
+        };
+        // After a loop or switch that may have been left by an exit, keep leaving
+        let leave_if_exited = |stmt: Stmt, exits: bool| {
+            let mut stmts = vec![stmt];
+            if exits && nested {
+                stmts.push(Stmt::If {
+                    condition: Expr::Compare {
+                        op: CompareOp::Ne,
+                        left: Box::new(Expr::LocalGet(completion)),
+                        right: Box::new(Expr::Number(COMPLETION_NORMAL)),
+                    },
+                    then_branch: vec![Stmt::Break],
+                    else_branch: None,
+                });
+            }
+            (stmts, exits)
+        };
+
+        match stmt {
+            Stmt::Return(value) => {
+                let code = COMPLETION_RETURN + self.returns.len() as f64;
+                let mut stmts = Vec::new();
+                let saved = value.map(|value| {
+                    let id = ctx.fresh_local();
+                    stmts.push(Stmt::Let {
+                        id,
+                        name: format!("__return_{}", id),
+                        ty: Type::Any,
+                        mutable: false,
+                        init: Some(value),
+                    });
+                    id
+                });
+                self.returns.push((code, saved));
+                exit(code, stmts)
+            }
+            Stmt::Break if loops == 0 && switches == 0 => {
+                self.breaks = true;
+                exit(COMPLETION_BREAK, Vec::new())
+            }
+            Stmt::Continue if loops == 0 => {
+                self.continues = true;
+                exit(COMPLETION_CONTINUE, Vec::new())
+            }
+            Stmt::If { condition, then_branch, else_branch } => {
+                let (then_branch, then_exits) = self.rewrite(ctx, then_branch, loops, switches);
+                let (else_branch, else_exits) = match else_branch {
+                    Some(branch) => {
+                        let (branch, exits) = self.rewrite(ctx, branch, loops, switches);
+                        (Some(branch), exits)
+                    }
+                    None => (None, false),
+                };
+                (vec![Stmt::If { condition, then_branch, else_branch }], then_exits || else_exits)
+            }
+            Stmt::While { condition, body } => {
+                let (body, exits) = self.rewrite(ctx, body, loops + 1, switches);
+                leave_if_exited(Stmt::While { condition, body }, exits)
+            }
+            Stmt::For { init, condition, update, body } => {
+                let (body, exits) = self.rewrite(ctx, body, loops + 1, switches);
+                leave_if_exited(Stmt::For { init, condition, update, body }, exits)
+            }
+            Stmt::Switch { discriminant, cases } => {
+                let mut any_exits = false;
+                let cases = cases
+                    .into_iter()
+                    .map(|case| {
+                        let (body, exits) = self.rewrite(ctx, case.body, loops, switches + 1);
+                        any_exits |= exits;
+                        SwitchCase { test: case.test, body }
+                    })
+                    .collect();
+                leave_if_exited(Stmt::Switch { discriminant, cases }, any_exits)
+            }
+            // A nested try whose own body had exits was already lowered by `lower_try`;
+            // what's left are exits from its catch clause, which runs outside its handler
+            Stmt::Try { body, catch, finally } => {
+                let (body, body_exits) = self.rewrite(ctx, body, loops, switches);
+                let (catch, catch_exits) = match catch {
+                    Some(CatchClause { param, body }) => {
+                        let (body, exits) = self.rewrite(ctx, body, loops, switches);
+                        (Some(CatchClause { param, body }), exits)
+                    }
+                    None => (None, false),
+                };
+                (vec![Stmt::Try { body, catch, finally }], body_exits || catch_exits)
+            }
+            stmt => (vec![stmt], false),
+        }
+    }
+}
+
+fn dispose_resource_expr(resource: Expr, ty: &Type, is_async: bool) -> Expr {
+    if matches!(ty, Type::Named(name) if name == "DisposableStack") {
+        Expr::DisposableStackDispose(Box::new(resource))
+    } else {
+        Expr::DisposeResource { resource: Box::new(resource), is_async }
+    }
+}
+
+fn is_disposable_stack_local(ctx: &LoweringContext, name: &str) -> bool {
+    matches!(ctx.lookup_local_type(name), Some(Type::Named(ty)) if ty == "DisposableStack")
+}
+
+/// Static type of a value passed to `stack.use()`, so its disposer can call [Symbol.dispose] directly
+fn disposable_value_type(ctx: &LoweringContext, expr: &ast::Expr) -> Type {
+    match expr {
+        ast::Expr::New(new_expr) => match new_expr.callee.as_ref() {
+            ast::Expr::Ident(ident) => Type::Named(ident.sym.to_string()),
+            _ => Type::Any,
+        },
+        ast::Expr::Ident(ident) => ctx.lookup_local_type(ident.sym.as_ref()).cloned().unwrap_or(Type::Any),
+        ast::Expr::Paren(paren) => disposable_value_type(ctx, &paren.expr),
+        _ => Type::Any,
+    }
+}
+
+/// `(resource: ty) => resource[Symbol.dispose]()`, the disposer `stack.use(value)` registers
+fn resource_disposer(ctx: &mut LoweringContext, ty: Type) -> Expr {
+    let func_id = ctx.fresh_func();
+    let scope_mark = ctx.enter_scope();
+    let name = "__resource".to_string();
+    let id = ctx.define_local(name.clone(), ty.clone());
+    ctx.exit_scope(scope_mark);
+
+    let body = vec![Stmt::Expr(dispose_resource_expr(Expr::LocalGet(id), &ty, false))];
+    Expr::Closure {
+        func_id,
+        params: vec![Param { id, name, ty, default: None, is_rest: false }],
+        return_type: Type::Void,
+        body,
+        captures: Vec::new(),
+        mutable_captures: Vec::new(),
+        captures_this: false,
+        enclosing_class: None,
+        is_async: false,
+    }
+}
+
+/// Lower a `switch`. Switching on the tag of a discriminated union (`switch (shape.kind)`)
+/// narrows `shape` to the matching member inside each case, and a switch without `default`
+/// that leaves members unhandled is reported (T009).
+fn lower_switch(ctx: &mut LoweringContext, switch_stmt: &ast::SwitchStmt) -> Result<Stmt> {
+    let discriminant = lower_expr(ctx, &switch_stmt.discriminant)?;
+    let subject = switch_subject(ctx, &switch_stmt.discriminant);
+    let mut cases = Vec::new();
+    let mut handled = Vec::new();
+    // Tags of the labels that share the next body, and whether one of them isn't a literal
+    let mut labels: Vec<LiteralType> = Vec::new();
+    let mut unknown_label = false;
+    let mut falls_through = false;
+
+    for case in &switch_stmt.cases {
+        let test = case.test.as_ref()
+            .map(|e| lower_expr(ctx, e))
+            .transpose()?;
+        match case.test.as_deref().map(|test| case_literal(ctx, test)) {
+            Some(Some(literal)) => {
+                handled.push(literal.clone());
+                labels.push(literal);
+            }
+            _ => unknown_label = true,
+        }
+        if case.cons.is_empty() {
+            cases.push(SwitchCase { test, body: Vec::new() });
+            continue;
+        }
+
+        // A body reached only through tag labels sees the members those tags select
+        let narrowed = match &subject {
+            Some((name, union)) if !unknown_label && !falls_through => {
+                union.narrow(&labels).map(|ty| (name.clone(), ty))
+            }
+            _ => None,
+        };
+        labels.clear();
+        unknown_label = false;
+        // Shadow the local's entry with the narrowed type for the length of the body
+        let shadow = narrowed.and_then(|(name, ty)| {
+            let id = ctx.lookup_local(&name)?;
+            ctx.locals.push((name, id, ty));
+            Some(ctx.locals.len() - 1)
+        });
+        let mut body = Vec::new();
+        for stmt in &case.cons {
+            body.extend(lower_body_stmt(ctx, stmt)?);
+        }
+        if let Some(index) = shadow {
+            ctx.locals.remove(index);
+        }
+        falls_through = !ends_abruptly(&case.cons);
+
+        cases.push(SwitchCase { test, body });
+    }
+
+    let has_default = switch_stmt.cases.iter().any(|case| case.test.is_none());
+    if let Some((name, union)) = &subject {
+        let unhandled = union.unhandled(&handled);
+        if !has_default && !unhandled.is_empty() {
+            let tags: Vec<String> = unhandled.iter().map(|variant| variant.tag.to_string()).collect();
+            let subject = format!("{}.{}", name, union.tag);
+            warn_non_exhaustive_switch(ctx, switch_stmt, &subject, &union.tag, &tags, "tag");
+        }
+    } else if let Some((name, members)) = literal_switch_subject(ctx, &switch_stmt.discriminant) {
+        let values: Vec<String> = members.iter()
+            .filter(|member| !handled.contains(member))
+            .map(|member| member.to_string())
+            .collect();
+        if !has_default && !values.is_empty() {
+            warn_non_exhaustive_switch(ctx, switch_stmt, &name, &name, &values, "value");
+        }
+    }
+
+    Ok(Stmt::Switch { discriminant, cases })
+}
+
+/// Warn (T009) that a switch without a `default` clause leaves some values of `subject`
+/// unhandled. `label` names what the discriminant stands for in the inline label.
+fn warn_non_exhaustive_switch(
+    ctx: &mut LoweringContext,
+    switch_stmt: &ast::SwitchStmt,
+    subject: &str,
+    label: &str,
+    missing: &[String],
+    kind: &str,
+) {
+    let (lo, hi) = (switch_stmt.span.lo.0, switch_stmt.span.hi.0);
+    let span = Span::new(ctx.file_id, lo.saturating_sub(1), hi.saturating_sub(1));
+    let discriminant_span = switch_stmt.discriminant.span();
+    let discriminant_span = Span::new(
+        ctx.file_id,
+        discriminant_span.lo.0.saturating_sub(1),
+        discriminant_span.hi.0.saturating_sub(1),
+    );
+    let diagnostic = Diagnostic::warning(
+        DiagnosticCode::NonExhaustiveSwitch,
+        format!("Switch on '{}' does not handle {}", subject, missing.join(", ")),
+    )
+        .with_span(span)
+        .with_primary_label(discriminant_span, format!("'{}' can also be {}", label, missing.join(" or ")))
+        .with_help(format!("add a case for each missing {}, or a `default` clause", kind));
+    ctx.diagnostics.push(diagnostic.build());
+}
+
+/// The path and literal members of a switch on a value of a literal union type, such as
+/// `mode: "fast" | "safe"` or an enum. `null` and `undefined` members are left out.
+fn literal_switch_subject(ctx: &LoweringContext, discriminant: &ast::Expr) -> Option<(String, Vec<LiteralType>)> {
+    let name = expr_path(discriminant)?;
+    let ty = static_type_of(ctx, discriminant)?;
+    let mut literals: Vec<LiteralType> = Vec::new();
+    for member in narrowable_members(ctx, &ty, 0) {
+        match member {
+            Type::Literal(literal) if !literals.contains(&literal) => literals.push(literal),
+            Type::Literal(_) | Type::Null | Type::Void => {}
+            _ => return None,
+        }
+    }
+    (literals.len() > 1).then_some((name, literals))
+}
+
+/// Source path of `a`, `this.a` or `a.b.c`
+fn expr_path(expr: &ast::Expr) -> Option<String> {
+    match expr {
+        ast::Expr::Ident(ident) => Some(ident.sym.to_string()),
+        ast::Expr::This(_) => Some("this".to_string()),
+        ast::Expr::Paren(paren) => expr_path(&paren.expr),
+        ast::Expr::TsNonNull(non_null) => expr_path(&non_null.expr),
+        ast::Expr::Member(member) => Some(format!("{}.{}", expr_path(&member.obj)?, member_prop_name(&member.prop)?)),
+        _ => None,
+    }
+}
+
+/// The local and discriminated union of a switch on `local.tag`
+fn switch_subject(ctx: &LoweringContext, discriminant: &ast::Expr) -> Option<(String, DiscriminatedUnion)> {
+    let ast::Expr::Member(member) = unwrap_parens(discriminant) else { return None };
+    let ast::Expr::Ident(ident) = unwrap_parens(&member.obj) else { return None };
+    let tag = member_prop_name(&member.prop)?;
+    let ty = static_type_of(ctx, &member.obj)?;
+    Some((ident.sym.to_string(), ctx.discriminated_union(&ty, &tag)?))
+}
+
+/// Literal value of a case label: a literal or an enum member (`case Color.Red:`)
+fn case_literal(ctx: &LoweringContext, test: &ast::Expr) -> Option<LiteralType> {
+    match unwrap_parens(test) {
+        ast::Expr::Member(member) => {
+            let ast::Expr::Ident(enum_name) = unwrap_parens(&member.obj) else { return None };
+            let value = ctx.lookup_enum_member(enum_name.sym.as_ref(), &member_prop_name(&member.prop)?)?;
+            Some(enum_literal(value))
+        }
+        ast::Expr::Lit(ast::Lit::Str(s)) => Some(LiteralType::String(s.value.as_str()?.to_string())),
+        ast::Expr::Lit(ast::Lit::Num(n)) => Some(LiteralType::Number(n.value)),
+        ast::Expr::Lit(ast::Lit::Bool(b)) => Some(LiteralType::Boolean(b.value)),
+        ast::Expr::Unary(unary) if unary.op == ast::UnaryOp::Minus => match unary.arg.as_ref() {
+            ast::Expr::Lit(ast::Lit::Num(n)) => Some(LiteralType::Number(-n.value)),
+            _ => None,
+        },
+        ast::Expr::Tpl(tpl) if tpl.exprs.is_empty() && tpl.quasis.len() == 1 => {
+            let cooked = tpl.quasis[0].cooked.as_ref()?;
+            Some(LiteralType::String(cooked.as_str()?.to_string()))
+        }
+        _ => None,
+    }
+}
+
+/// Whether control never runs off the end of these statements (into the next case)
+fn ends_abruptly(stmts: &[ast::Stmt]) -> bool {
+    match stmts.last() {
+        Some(ast::Stmt::Break(_) | ast::Stmt::Continue(_) | ast::Stmt::Return(_) | ast::Stmt::Throw(_)) => true,
+        Some(ast::Stmt::Block(block)) => ends_abruptly(&block.stmts),
+        Some(ast::Stmt::If(if_stmt)) => {
+            ends_abruptly(std::slice::from_ref(&if_stmt.cons))
+                && if_stmt.alt.as_ref().is_some_and(|alt| ends_abruptly(std::slice::from_ref(alt)))
+        }
+        _ => false,
+    }
+}
+
+/// Lower an `if`. Guards in the condition narrow the locals they test inside the branch
+/// they select (see `type_guards`): the branch starts with `let x = Narrowed(x)` typed as
+/// the narrowed type, so codegen works on a plain number, string or class instance there
+/// instead of a dynamic value.
+fn lower_if(ctx: &mut LoweringContext, if_stmt: &ast::IfStmt) -> Result<Stmt> {
+    let condition = lower_expr(ctx, &if_stmt.test)?;
+    let then_guards = type_guards(ctx, &if_stmt.test, true);
+    let then_branch = lower_narrowed(ctx, &then_guards, &if_stmt.cons)?;
+    let else_branch = match &if_stmt.alt {
+        Some(alt) => {
+            let else_guards = type_guards(ctx, &if_stmt.test, false);
+            Some(lower_narrowed(ctx, &else_guards, alt)?)
+        }
+        None => None,
+    };
+    Ok(Stmt::If { condition, then_branch, else_branch })
+}
+
+/// Guards that hold after an `if` one of whose branches always leaves the enclosing block
+/// (`if (x === null) return;`): the other branch's
+fn exit_guards(ctx: &LoweringContext, if_stmt: &ast::IfStmt) -> Vec<(String, Type)> {
+    let then_exits = ends_abruptly(std::slice::from_ref(&if_stmt.cons));
+    let else_exits = if_stmt.alt.as_ref().is_some_and(|alt| ends_abruptly(std::slice::from_ref(alt)));
+    match (then_exits, else_exits) {
+        (true, false) => type_guards(ctx, &if_stmt.test, false),
+        (false, true) => type_guards(ctx, &if_stmt.test, true),
+        // Either nothing follows or both paths reach it
+        _ => Vec::new(),
+    }
+}
+
+/// What a condition establishes about a local
+#[derive(Debug, Clone, PartialEq)]
+enum Guard {
+    /// `typeof x === "number"`, `x instanceof C`
+    Is(Type),
+    /// `typeof x !== "string"`
+    IsNot(Type),
+    /// `if (x)`: not null or undefined
+    Truthy,
+    /// `x === null`, `x === "a"`; loose equality (`==`) with null also matches undefined
+    Equals { value: Type, loose: bool },
+    NotEquals { value: Type, loose: bool },
+    /// `"name" in x`
+    Has(String),
+    Lacks(String),
+    /// `x.kind === "circle"` on a discriminated union
+    Tag { tag: String, literal: LiteralType, equal: bool },
+}
+
+/// Locals `test` narrows when it evaluates to `when_true`, with their narrowed types.
+/// Guards combined with `&&` (or `||` when false) narrow one after another, so
+/// `x !== null && typeof x === "string"` narrows a `string | number | null` to `string`.
+fn type_guards(ctx: &LoweringContext, test: &ast::Expr, when_true: bool) -> Vec<(String, Type)> {
+    let mut narrowed: Vec<(String, Type)> = Vec::new();
+    for (name, guard) in guards(ctx, test, when_true) {
+        let existing = narrowed.iter().position(|(n, _)| *n == name);
+        let Some(declared) = existing.map(|i| narrowed[i].1.clone()).or_else(|| ctx.lookup_local_type(&name).cloned()) else {
+            continue;
+        };
+        let ty = match narrow_type(ctx, &declared, &guard) {
+            Some(Type::Literal(LiteralType::String(_))) => Type::String,
+            Some(Type::Literal(LiteralType::Number(_))) => Type::Number,
+            // Only types a narrowed copy can represent unconverted; the rest stay dynamic
+            Some(ty @ (Type::Number | Type::String | Type::Named(_) | Type::Object(_) | Type::Array(_) | Type::Union(_))) => ty,
+            _ => continue,
+        };
+        match existing {
+            Some(i) => narrowed[i].1 = ty,
+            None => narrowed.push((name, ty)),
+        }
+    }
+    narrowed
+}
+
+/// The guards `test` establishes when it evaluates to `when_true`
+fn guards(ctx: &LoweringContext, test: &ast::Expr, when_true: bool) -> Vec<(String, Guard)> {
+    let test = unwrap_parens(test);
+    let bin = match test {
+        ast::Expr::Unary(unary) if unary.op == ast::UnaryOp::Bang => return guards(ctx, &unary.arg, !when_true),
+        ast::Expr::Ident(ident) if when_true => return vec![(ident.sym.to_string(), Guard::Truthy)],
+        ast::Expr::Bin(bin) => bin,
+        _ => return Vec::new(),
     };
+    let guard = match bin.op {
+        // Both sides hold when `a && b` is true, and neither does when `a || b` is false
+        ast::BinaryOp::LogicalAnd if when_true => {
+            let mut both = guards(ctx, &bin.left, true);
+            both.extend(guards(ctx, &bin.right, true));
+            return both;
+        }
+        ast::BinaryOp::LogicalOr if !when_true => {
+            let mut both = guards(ctx, &bin.left, false);
+            both.extend(guards(ctx, &bin.right, false));
+            return both;
+        }
+        ast::BinaryOp::EqEqEq | ast::BinaryOp::EqEq | ast::BinaryOp::NotEqEq | ast::BinaryOp::NotEq => {
+            let loose = matches!(bin.op, ast::BinaryOp::EqEq | ast::BinaryOp::NotEq);
+            let equal = matches!(bin.op, ast::BinaryOp::EqEqEq | ast::BinaryOp::EqEq) == when_true;
+            match equality_guard(ctx, &bin.left, &bin.right, loose, equal)
+                .or_else(|| equality_guard(ctx, &bin.right, &bin.left, loose, equal))
+            {
+                Some(guard) => guard,
+                None => return Vec::new(),
+            }
+        }
+        ast::BinaryOp::InstanceOf if when_true => {
+            let (ast::Expr::Ident(ident), ast::Expr::Ident(class)) = (unwrap_parens(&bin.left), unwrap_parens(&bin.right)) else {
+                return Vec::new();
+            };
+            let class_name = class.sym.to_string();
+            if ctx.lookup_class(&class_name).is_none() {
+                return Vec::new();
+            }
+            (ident.sym.to_string(), Guard::Is(Type::Named(class_name)))
+        }
+        ast::BinaryOp::In => {
+            let (ast::Expr::Lit(ast::Lit::Str(prop)), ast::Expr::Ident(ident)) = (unwrap_parens(&bin.left), unwrap_parens(&bin.right)) else {
+                return Vec::new();
+            };
+            let Some(prop) = prop.value.as_str() else { return Vec::new() };
+            let guard = if when_true { Guard::Has(prop.to_string()) } else { Guard::Lacks(prop.to_string()) };
+            (ident.sym.to_string(), guard)
+        }
+        _ => return Vec::new(),
+    };
+    vec![guard]
+}
 
-    ctx.exit_scope(scope_mark);
-
-    Ok(Function {
-        id: ctx.fresh_func(),
-        name,
-        type_params: Vec::new(),
-        params,
-        return_type: Type::Void,
-        body,
-        is_async: false,
-        is_exported: false,
-        captures: Vec::new(),
-        decorators: Vec::new(),
-    })
+/// `subject == value` where `subject` is `typeof x`, `x` or `x.tag`; `equal` is whether
+/// the comparison is known to hold
+fn equality_guard(ctx: &LoweringContext, subject: &ast::Expr, value: &ast::Expr, loose: bool, equal: bool) -> Option<(String, Guard)> {
+    let value = unwrap_parens(value);
+    match unwrap_parens(subject) {
+        ast::Expr::Unary(unary) if unary.op == ast::UnaryOp::TypeOf => {
+            let ast::Expr::Ident(ident) = unwrap_parens(&unary.arg) else { return None };
+            let ty = match value {
+                ast::Expr::Lit(ast::Lit::Str(s)) => match s.value.as_str()? {
+                    "number" => Type::Number,
+                    "string" => Type::String,
+                    _ => return None,
+                },
+                _ => return None,
+            };
+            Some((ident.sym.to_string(), if equal { Guard::Is(ty) } else { Guard::IsNot(ty) }))
+        }
+        ast::Expr::Ident(ident) => {
+            let value = match value {
+                ast::Expr::Lit(ast::Lit::Null(_)) => Type::Null,
+                ast::Expr::Ident(undefined) if &*undefined.sym == "undefined" => Type::Void,
+                value => Type::Literal(case_literal(ctx, value)?),
+            };
+            let guard = if equal { Guard::Equals { value, loose } } else { Guard::NotEquals { value, loose } };
+            Some((ident.sym.to_string(), guard))
+        }
+        ast::Expr::Member(member) => {
+            let ast::Expr::Ident(ident) = unwrap_parens(&member.obj) else { return None };
+            let tag = member_prop_name(&member.prop)?;
+            let literal = case_literal(ctx, value)?;
+            // Only worth a guard when the object is a union told apart by this property
+            let ty = static_type_of(ctx, &member.obj)?;
+            ctx.discriminated_union(&ty, &tag)?;
+            Some((ident.sym.to_string(), Guard::Tag { tag, literal, equal }))
+        }
+        _ => None,
+    }
 }
 
-fn lower_class_prop(ctx: &mut LoweringContext, prop: &ast::ClassProp) -> Result<ClassField> {
-    let name = match &prop.key {
-        ast::PropName::Ident(ident) => ident.sym.to_string(),
-        ast::PropName::Str(s) => s.value.as_str().unwrap_or("").to_string(),
-        _ => return Err(anyhow!("Unsupported property key")),
+/// `declared` narrowed by `guard`, or None when the guard doesn't make it more precise
+fn narrow_type(ctx: &LoweringContext, declared: &Type, guard: &Guard) -> Option<Type> {
+    let members = narrowable_members(ctx, declared, 0);
+    let keep = |pred: &dyn Fn(&Type) -> bool| {
+        let kept: Vec<Type> = members.iter().filter(|m| pred(m)).cloned().collect();
+        match kept.len() {
+            _ if kept.len() == members.len() => None,
+            0 => None,
+            1 => kept.into_iter().next(),
+            _ => Some(Type::Union(kept)),
+        }
     };
+    let nullish = |ty: &Type| matches!(ty, Type::Null | Type::Void);
+    match guard {
+        Guard::Is(ty) => match declared {
+            Type::Any | Type::Unknown | Type::Union(_) => Some(ty.clone()),
+            Type::Named(name) if members.len() > 1 || matches!(ty, Type::Named(class) if class != name) => Some(ty.clone()),
+            _ => None,
+        },
+        Guard::IsNot(ty) => keep(&|m| !typeof_matches(m, ty)),
+        Guard::Truthy => keep(&|m| !nullish(m)),
+        Guard::Equals { value, loose } if nullish(value) => {
+            keep(&|m| m == value || (*loose && nullish(m)))
+        }
+        Guard::NotEquals { value, loose } if nullish(value) => {
+            keep(&|m| m != value && !(*loose && nullish(m)))
+        }
+        // `x === "a"` keeps the members that could hold "a"
+        Guard::Equals { value: Type::Literal(lit), .. } => {
+            keep(&|m| matches!(m, Type::Literal(l) if l == lit) || typeof_matches(m, &lit.widened()))
+        }
+        Guard::NotEquals { value, .. } => keep(&|m| m != value),
+        Guard::Equals { .. } => None,
+        // `in` throws on primitives, so they drop out either way
+        Guard::Has(prop) => keep(&|m| {
+            ctx.lookup_property(m, prop).is_some() || (!m.is_primitive() && ctx.object_shape(m).is_none())
+        }),
+        Guard::Lacks(prop) => keep(&|m| {
+            !m.is_primitive() && !ctx.lookup_property(m, prop).is_some_and(|(info, _)| !info.optional)
+        }),
+        Guard::Tag { tag, literal, equal } => {
+            let union = ctx.discriminated_union(declared, tag)?;
+            if *equal {
+                union.narrow(std::slice::from_ref(literal))
+            } else {
+                let others: Vec<LiteralType> = union.unhandled(std::slice::from_ref(literal)).iter().map(|v| v.tag.clone()).collect();
+                union.narrow(&others)
+            }
+        }
+    }
+}
 
-    // Extract type from type annotation (using context for class type param resolution)
-    let ty = prop.type_ann.as_ref()
-        .map(|ann| extract_ts_type_with_ctx(&ann.type_ann, Some(ctx)))
-        .unwrap_or(Type::Any);
+/// Whether a value of type `member` has the `typeof` of `ty` (a primitive)
+fn typeof_matches(member: &Type, ty: &Type) -> bool {
+    match member {
+        Type::Literal(lit) => lit.widened() == *ty,
+        Type::Int32 => *ty == Type::Number,
+        member => member == ty,
+    }
+}
 
-    // Lower initializer expression if present
-    let init = prop.value.as_ref()
-        .map(|e| lower_expr(ctx, e))
-        .transpose()?;
+/// Members of a union type, following union type aliases; null and undefined included
+fn narrowable_members(ctx: &LoweringContext, ty: &Type, depth: usize) -> Vec<Type> {
+    let aliased = match ty {
+        Type::Named(name) => ctx.declared_unions.iter().find(|(n, _)| n == name).map(|(_, members)| members),
+        _ => None,
+    };
+    match (ty, aliased) {
+        _ if depth > 16 => vec![ty.clone()],
+        (Type::Union(members), _) | (_, Some(members)) => {
+            members.iter().flat_map(|member| narrowable_members(ctx, member, depth + 1)).collect()
+        }
+        _ => vec![ty.clone()],
+    }
+}
 
-    Ok(ClassField {
-        name,
-        ty,
-        init,
-        is_private: false, // TODO: check accessibility
-        is_readonly: prop.readonly,
-    })
+/// Shadow the locals `guards` narrow with narrowed copies, returning the `let`s that
+/// initialize them and their entries in `ctx.locals` (for `unshadow`). A local `assigns`
+/// says may be written keeps its own type: writes to a copy would be lost.
+fn shadow_narrowed(ctx: &mut LoweringContext, guards: &[(String, Type)], assigns: impl Fn(&str) -> bool) -> (Vec<Stmt>, Vec<usize>) {
+    let mut narrowed = Vec::new();
+    let mut shadows = Vec::new();
+    for (name, ty) in guards {
+        if narrowed.iter().any(|stmt| matches!(stmt, Stmt::Let { name: n, .. } if n == name)) || assigns(name) {
+            continue;
+        }
+        let Some(id) = ctx.lookup_local(name) else { continue };
+        let narrowed_id = ctx.define_local(name.clone(), ty.clone());
+        shadows.push(ctx.locals.len() - 1);
+        narrowed.push(Stmt::Let {
+            id: narrowed_id,
+            name: name.clone(),
+            ty: ty.clone(),
+            mutable: false,
+            init: Some(Expr::Narrowed { value: Box::new(Expr::LocalGet(id)), ty: ty.clone() }),
+        });
+    }
+    (narrowed, shadows)
 }
 
-fn lower_private_prop(ctx: &mut LoweringContext, prop: &ast::PrivateProp) -> Result<ClassField> {
-    // Private fields use PrivateName which has a `name` field (without the # prefix in SWC)
-    // We store the name with the # prefix to distinguish private fields
-    let name = format!("#{}", prop.key.name.to_string());
+fn unshadow(ctx: &mut LoweringContext, shadows: Vec<usize>) {
+    for index in shadows.into_iter().rev() {
+        ctx.locals.remove(index);
+    }
+}
 
-    // Extract type from type annotation (using context for class type param resolution)
-    let ty = prop.type_ann.as_ref()
-        .map(|ann| extract_ts_type_with_ctx(&ann.type_ann, Some(ctx)))
-        .unwrap_or(Type::Any);
+/// Lower one branch of an `if` with the locals its guards narrow shadowed by narrowed copies
+fn lower_narrowed(ctx: &mut LoweringContext, guards: &[(String, Type)], branch: &ast::Stmt) -> Result<Vec<Stmt>> {
+    let (mut narrowed, shadows) = shadow_narrowed(ctx, guards, |name| may_assign_stmt(branch, name));
+    let body = lower_body_stmt(ctx, branch);
+    unshadow(ctx, shadows);
+    narrowed.extend(body?);
+    Ok(narrowed)
+}
 
-    // Lower initializer expression if present
-    let init = prop.value.as_ref()
-        .map(|e| lower_expr(ctx, e))
-        .transpose()?;
+/// Whether `name` may be assigned in `stmt`, closures included. Statements and expressions
+/// not looked into count as assigning.
+fn may_assign_stmt(stmt: &ast::Stmt, name: &str) -> bool {
+    match stmt {
+        ast::Stmt::Empty(_) | ast::Stmt::Break(_) | ast::Stmt::Continue(_) => false,
+        ast::Stmt::Expr(expr_stmt) => may_assign_expr(&expr_stmt.expr, name),
+        ast::Stmt::Return(ret) => ret.arg.as_ref().is_some_and(|arg| may_assign_expr(arg, name)),
+        ast::Stmt::Throw(throw) => may_assign_expr(&throw.arg, name),
+        ast::Stmt::Block(block) => block.stmts.iter().any(|s| may_assign_stmt(s, name)),
+        ast::Stmt::If(if_stmt) => {
+            may_assign_expr(&if_stmt.test, name)
+                || may_assign_stmt(&if_stmt.cons, name)
+                || if_stmt.alt.as_ref().is_some_and(|alt| may_assign_stmt(alt, name))
+        }
+        ast::Stmt::While(while_stmt) => may_assign_expr(&while_stmt.test, name) || may_assign_stmt(&while_stmt.body, name),
+        ast::Stmt::DoWhile(do_while) => may_assign_expr(&do_while.test, name) || may_assign_stmt(&do_while.body, name),
+        ast::Stmt::For(for_stmt) => {
+            let init = match &for_stmt.init {
+                Some(ast::VarDeclOrExpr::VarDecl(var_decl)) => may_assign_var_decl(var_decl, name),
+                Some(ast::VarDeclOrExpr::Expr(expr)) => may_assign_expr(expr, name),
+                None => false,
+            };
+            init
+                || may_assign_any(for_stmt.test.iter().chain(for_stmt.update.iter()).map(|e| e.as_ref()), name)
+                || may_assign_stmt(&for_stmt.body, name)
+        }
+        ast::Stmt::ForOf(for_of) => {
+            !matches!(&for_of.left, ast::ForHead::VarDecl(var_decl) if !may_assign_var_decl(var_decl, name))
+                || may_assign_expr(&for_of.right, name)
+                || may_assign_stmt(&for_of.body, name)
+        }
+        ast::Stmt::ForIn(for_in) => {
+            !matches!(&for_in.left, ast::ForHead::VarDecl(var_decl) if !may_assign_var_decl(var_decl, name))
+                || may_assign_expr(&for_in.right, name)
+                || may_assign_stmt(&for_in.body, name)
+        }
+        ast::Stmt::Decl(ast::Decl::Var(var_decl)) => may_assign_var_decl(var_decl, name),
+        _ => true,
+    }
+}
 
-    Ok(ClassField {
-        name,
-        ty,
-        init,
-        is_private: true,
-        is_readonly: prop.readonly,
+/// A `var` of the same name is the same variable; `let`/`const` only shadow it
+fn may_assign_var_decl(var_decl: &ast::VarDecl, name: &str) -> bool {
+    var_decl.decls.iter().any(|decl| {
+        (var_decl.kind == ast::VarDeclKind::Var && !matches!(&decl.name, ast::Pat::Ident(ident) if ident.id.sym.as_ref() != name))
+            || decl.init.as_ref().is_some_and(|init| may_assign_expr(init, name))
     })
 }
 
-fn lower_block_stmt(ctx: &mut LoweringContext, block: &ast::BlockStmt) -> Result<Vec<Stmt>> {
-    let mut stmts = Vec::new();
-    for stmt in &block.stmts {
-        stmts.extend(lower_body_stmt(ctx, stmt)?);
+fn may_assign_any<'a>(mut exprs: impl Iterator<Item = &'a ast::Expr>, name: &str) -> bool {
+    exprs.any(|e| may_assign_expr(e, name))
+}
+
+fn may_assign_expr(expr: &ast::Expr, name: &str) -> bool {
+    match expr {
+        ast::Expr::Ident(_) | ast::Expr::Lit(_) | ast::Expr::This(_) => false,
+        ast::Expr::Assign(assign) => {
+            let target = match &assign.left {
+                ast::AssignTarget::Simple(ast::SimpleAssignTarget::Ident(ident)) => ident.id.sym.as_ref() == name,
+                ast::AssignTarget::Simple(ast::SimpleAssignTarget::Member(member)) => may_assign_expr(&member.obj, name),
+                _ => true,
+            };
+            target || may_assign_expr(&assign.right, name)
+        }
+        ast::Expr::Update(update) => match update.arg.as_ref() {
+            ast::Expr::Ident(ident) => ident.sym.as_ref() == name,
+            arg => may_assign_expr(arg, name),
+        },
+        ast::Expr::Bin(bin) => may_assign_expr(&bin.left, name) || may_assign_expr(&bin.right, name),
+        ast::Expr::Unary(unary) => may_assign_expr(&unary.arg, name),
+        ast::Expr::Paren(paren) => may_assign_expr(&paren.expr, name),
+        ast::Expr::Await(await_expr) => may_assign_expr(&await_expr.arg, name),
+        ast::Expr::TsAs(ts_as) => may_assign_expr(&ts_as.expr, name),
+        ast::Expr::TsNonNull(non_null) => may_assign_expr(&non_null.expr, name),
+        ast::Expr::Member(member) => {
+            may_assign_expr(&member.obj, name)
+                || matches!(&member.prop, ast::MemberProp::Computed(computed) if may_assign_expr(&computed.expr, name))
+        }
+        ast::Expr::Cond(cond) => {
+            may_assign_expr(&cond.test, name) || may_assign_expr(&cond.cons, name) || may_assign_expr(&cond.alt, name)
+        }
+        ast::Expr::Call(call) => {
+            !matches!(&call.callee, ast::Callee::Expr(callee) if !may_assign_expr(callee, name))
+                || may_assign_any(call.args.iter().map(|arg| arg.expr.as_ref()), name)
+        }
+        ast::Expr::New(new_expr) => {
+            may_assign_expr(&new_expr.callee, name)
+                || new_expr.args.as_ref().is_some_and(|args| may_assign_any(args.iter().map(|arg| arg.expr.as_ref()), name))
+        }
+        ast::Expr::Seq(seq) => may_assign_any(seq.exprs.iter().map(|e| e.as_ref()), name),
+        ast::Expr::Tpl(tpl) => may_assign_any(tpl.exprs.iter().map(|e| e.as_ref()), name),
+        ast::Expr::Array(array) => may_assign_any(array.elems.iter().flatten().map(|elem| elem.expr.as_ref()), name),
+        ast::Expr::Object(obj) => obj.props.iter().any(|prop| match prop {
+            ast::PropOrSpread::Spread(spread) => may_assign_expr(&spread.expr, name),
+            ast::PropOrSpread::Prop(prop) => match prop.as_ref() {
+                ast::Prop::Shorthand(_) => false,
+                ast::Prop::KeyValue(kv) => may_assign_expr(&kv.value, name),
+                _ => true,
+            },
+        }),
+        ast::Expr::Arrow(arrow) => match arrow.body.as_ref() {
+            ast::BlockStmtOrExpr::BlockStmt(block) => block.stmts.iter().any(|s| may_assign_stmt(s, name)),
+            ast::BlockStmtOrExpr::Expr(body) => may_assign_expr(body, name),
+        },
+        ast::Expr::Fn(fn_expr) => fn_expr.function.body.as_ref()
+            .map_or(false, |body| body.stmts.iter().any(|s| may_assign_stmt(s, name))),
+        _ => true,
     }
-    Ok(stmts)
 }
 
 fn lower_body_stmt(ctx: &mut LoweringContext, stmt: &ast::Stmt) -> Result<Vec<Stmt>> {
@@ -2536,16 +4925,7 @@ fn lower_body_stmt(ctx: &mut LoweringContext, stmt: &ast::Stmt) -> Result<Vec<St
             result.push(Stmt::Return(value));
         }
         ast::Stmt::If(if_stmt) => {
-            let condition = lower_expr(ctx, &if_stmt.test)?;
-            let then_branch = lower_body_stmt(ctx, &if_stmt.cons)?;
-            let else_branch = if_stmt.alt.as_ref()
-                .map(|s| lower_body_stmt(ctx, s))
-                .transpose()?;
-            result.push(Stmt::If {
-                condition,
-                then_branch,
-                else_branch,
-            });
+            result.push(lower_if(ctx, if_stmt)?);
         }
         ast::Stmt::Block(block) => {
             result.extend(lower_block_stmt(ctx, block)?);
@@ -2561,6 +4941,11 @@ fn lower_body_stmt(ctx: &mut LoweringContext, stmt: &ast::Stmt) -> Result<Vec<St
                     return Ok(result);
                 }
             }
+            // require('./module') for its side effects only
+            if let Some(source) = require_call_source(ctx, &expr_stmt.expr) {
+                ctx.register_require_import(&source, None);
+                return Ok(result);
+            }
             let expr = lower_expr(ctx, &expr_stmt.expr)?;
             result.push(Stmt::Expr(expr));
         }
@@ -2576,10 +4961,16 @@ fn lower_body_stmt(ctx: &mut LoweringContext, stmt: &ast::Stmt) -> Result<Vec<St
             let body = lower_body_stmt(ctx, &while_stmt.body)?;
             result.push(Stmt::While { condition, body });
         }
-        ast::Stmt::Break(_) => {
+        ast::Stmt::Break(break_stmt) => {
+            if let Some(label) = &break_stmt.label {
+                report_dropped(ctx, break_stmt.span, &format!("label of `break {}`", label.sym), "the break only exits the innermost loop or switch; use a flag, or return from a helper function");
+            }
             result.push(Stmt::Break);
         }
-        ast::Stmt::Continue(_) => {
+        ast::Stmt::Continue(continue_stmt) => {
+            if let Some(label) = &continue_stmt.label {
+                report_dropped(ctx, continue_stmt.span, &format!("label of `continue {}`", label.sym), "the continue only applies to the innermost loop; use a flag to skip the rest of the outer iteration");
+            }
             result.push(Stmt::Continue);
         }
         ast::Stmt::For(for_stmt) => {
@@ -2614,317 +5005,54 @@ fn lower_body_stmt(ctx: &mut LoweringContext, stmt: &ast::Stmt) -> Result<Vec<St
             let condition = for_stmt.test.as_ref().map(|e| lower_expr(ctx, e)).transpose()?;
             let update = for_stmt.update.as_ref().map(|e| lower_expr(ctx, e)).transpose()?;
             let body = lower_body_stmt(ctx, &for_stmt.body)?;
-
-            result.push(Stmt::For { init, condition, update, body });
-        }
-        ast::Stmt::Try(try_stmt) => {
-            // Lower try body
-            let body = lower_block_stmt(ctx, &try_stmt.block)?;
-
-            // Lower catch clause (if present)
-            let catch = if let Some(ref catch_clause) = try_stmt.handler {
-                let scope_mark = ctx.enter_scope();
-
-                // Lower catch parameter (if present)
-                let param = if let Some(ref pat) = catch_clause.param {
-                    let param_name = get_pat_name(pat)?;
-                    let param_id = ctx.define_local(param_name.clone(), Type::Any);
-                    Some((param_id, param_name))
-                } else {
-                    None
-                };
-
-                // Lower catch body
-                let catch_body = lower_block_stmt(ctx, &catch_clause.body)?;
-
-                ctx.exit_scope(scope_mark);
-
-                Some(CatchClause { param, body: catch_body })
-            } else {
-                None
-            };
-
-            // Lower finally (if present)
-            let finally = if let Some(ref finally_block) = try_stmt.finalizer {
-                Some(lower_block_stmt(ctx, finally_block)?)
-            } else {
-                None
-            };
-
-            result.push(Stmt::Try { body, catch, finally });
-        }
-        ast::Stmt::Throw(throw_stmt) => {
-            let expr = lower_expr(ctx, &throw_stmt.arg)?;
-            result.push(Stmt::Throw(expr));
-        }
-        ast::Stmt::Switch(switch_stmt) => {
-            let discriminant = lower_expr(ctx, &switch_stmt.discriminant)?;
-            let mut cases = Vec::new();
-
-            for case in &switch_stmt.cases {
-                let test = case.test.as_ref()
-                    .map(|e| lower_expr(ctx, e))
-                    .transpose()?;
-
-                let mut body = Vec::new();
-                for stmt in &case.cons {
-                    body.extend(lower_body_stmt(ctx, stmt)?);
-                }
-
-                cases.push(SwitchCase { test, body });
-            }
-
-            result.push(Stmt::Switch { discriminant, cases });
-        }
-        ast::Stmt::ForOf(for_of_stmt) => {
-            // Desugar for-of to a regular for loop (same as in lower_stmt)
-            let arr_expr = lower_expr(ctx, &for_of_stmt.right)?;
-            let arr_id = ctx.fresh_local();
-            let idx_id = ctx.fresh_local();
-            ctx.locals.push((format!("__arr_{}", arr_id), arr_id, Type::Array(Box::new(Type::Any))));
-            ctx.locals.push((format!("__idx_{}", idx_id), idx_id, Type::Number));
-
-            // Store array reference
-            result.push(Stmt::Let {
-                id: arr_id,
-                name: format!("__arr_{}", arr_id),
-                ty: Type::Array(Box::new(Type::Any)),
-                mutable: false,
-                init: Some(arr_expr),
-            });
-
-            // IMPORTANT: Define iteration variables BEFORE lowering the body
-            let item_id = ctx.fresh_local();
-            ctx.locals.push((format!("__item_{}", item_id), item_id, Type::Any));
-
-            // Pre-define all variables from the pattern
-            let var_ids: Vec<(String, u32)> = match &for_of_stmt.left {
-                ast::ForHead::VarDecl(var_decl) => {
-                    if let Some(decl) = var_decl.decls.first() {
-                        match &decl.name {
-                            ast::Pat::Ident(ident) => {
-                                let name = ident.id.sym.to_string();
-                                let id = ctx.define_local(name.clone(), Type::Any);
-                                vec![(name, id)]
-                            }
-                            ast::Pat::Array(arr_pat) => {
-                                let mut ids = Vec::new();
-                                for elem in &arr_pat.elems {
-                                    if let Some(elem_pat) = elem {
-                                        if let ast::Pat::Ident(ident) = elem_pat {
-                                            let name = ident.id.sym.to_string();
-                                            let id = ctx.define_local(name.clone(), Type::Any);
-                                            ids.push((name, id));
-                                        }
-                                    }
-                                }
-                                ids
-                            }
-                            ast::Pat::Object(obj_pat) => {
-                                let mut ids = Vec::new();
-                                for prop in &obj_pat.props {
-                                    match prop {
-                                        ast::ObjectPatProp::Assign(assign) => {
-                                            let name = assign.key.sym.to_string();
-                                            let id = ctx.define_local(name.clone(), Type::Any);
-                                            ids.push((name, id));
-                                        }
-                                        ast::ObjectPatProp::KeyValue(kv) => {
-                                            if let ast::Pat::Ident(ident) = &*kv.value {
-                                                let name = ident.id.sym.to_string();
-                                                let id = ctx.define_local(name.clone(), Type::Any);
-                                                ids.push((name, id));
-                                            }
-                                        }
-                                        _ => {}
-                                    }
-                                }
-                                ids
-                            }
-                            _ => {
-                                let name = get_binding_name(&decl.name)?;
-                                let id = ctx.define_local(name.clone(), Type::Any);
-                                vec![(name, id)]
-                            }
-                        }
-                    } else {
-                        return Err(anyhow!("for-of requires a variable declaration"));
-                    }
-                }
-                ast::ForHead::Pat(pat) => {
-                    let name = get_pat_name(pat)?;
-                    let id = ctx.define_local(name.clone(), Type::Any);
-                    vec![(name, id)]
-                }
-                _ => return Err(anyhow!("Unsupported for-of left-hand side")),
-            };
-
-            // NOW lower the body
-            let mut loop_body = lower_body_stmt(ctx, &for_of_stmt.body)?;
-
-            // Build binding statements using pre-defined variable IDs
-            let binding_stmts = match &for_of_stmt.left {
-                ast::ForHead::VarDecl(var_decl) => {
-                    if let Some(decl) = var_decl.decls.first() {
-                        let item_expr = Expr::IndexGet {
-                            object: Box::new(Expr::LocalGet(arr_id)),
-                            index: Box::new(Expr::LocalGet(idx_id)),
-                        };
-
-                        match &decl.name {
-                            ast::Pat::Ident(_) => {
-                                let (name, id) = var_ids[0].clone();
-                                vec![Stmt::Let {
-                                    id,
-                                    name,
-                                    ty: Type::Any,
-                                    mutable: false,
-                                    init: Some(item_expr),
-                                }]
-                            }
-                            ast::Pat::Array(arr_pat) => {
-                                let mut stmts = vec![Stmt::Let {
-                                    id: item_id,
-                                    name: format!("__item_{}", item_id),
-                                    ty: Type::Any,
-                                    mutable: false,
-                                    init: Some(item_expr),
-                                }];
-                                let mut var_idx = 0;
-                                for (idx, elem) in arr_pat.elems.iter().enumerate() {
-                                    if let Some(elem_pat) = elem {
-                                        if let ast::Pat::Ident(_) = elem_pat {
-                                            let (name, id) = var_ids[var_idx].clone();
-                                            var_idx += 1;
-                                            stmts.push(Stmt::Let {
-                                                id,
-                                                name,
-                                                ty: Type::Any,
-                                                mutable: false,
-                                                init: Some(Expr::IndexGet {
-                                                    object: Box::new(Expr::LocalGet(item_id)),
-                                                    index: Box::new(Expr::Number(idx as f64)),
-                                                }),
-                                            });
-                                        }
-                                    }
-                                }
-                                stmts
-                            }
-                            ast::Pat::Object(obj_pat) => {
-                                let mut stmts = vec![Stmt::Let {
-                                    id: item_id,
-                                    name: format!("__item_{}", item_id),
-                                    ty: Type::Any,
-                                    mutable: false,
-                                    init: Some(item_expr),
-                                }];
-                                let mut var_idx = 0;
-                                for prop in &obj_pat.props {
-                                    match prop {
-                                        ast::ObjectPatProp::Assign(assign) => {
-                                            let prop_name = assign.key.sym.to_string();
-                                            let (name, id) = var_ids[var_idx].clone();
-                                            var_idx += 1;
-                                            stmts.push(Stmt::Let {
-                                                id,
-                                                name,
-                                                ty: Type::Any,
-                                                mutable: false,
-                                                init: Some(Expr::PropertyGet {
-                                                    object: Box::new(Expr::LocalGet(item_id)),
-                                                    property: prop_name,
-                                                }),
-                                            });
-                                        }
-                                        ast::ObjectPatProp::KeyValue(kv) => {
-                                            let key = match &kv.key {
-                                                ast::PropName::Ident(ident) => ident.sym.to_string(),
-                                                _ => continue,
-                                            };
-                                            if let ast::Pat::Ident(_) = &*kv.value {
-                                                let (name, id) = var_ids[var_idx].clone();
-                                                var_idx += 1;
-                                                stmts.push(Stmt::Let {
-                                                    id,
-                                                    name,
-                                                    ty: Type::Any,
-                                                    mutable: false,
-                                                    init: Some(Expr::PropertyGet {
-                                                        object: Box::new(Expr::LocalGet(item_id)),
-                                                        property: key,
-                                                    }),
-                                                });
-                                            }
-                                        }
-                                        _ => {}
-                                    }
-                                }
-                                stmts
-                            }
-                            _ => {
-                                let (name, id) = var_ids[0].clone();
-                                vec![Stmt::Let {
-                                    id,
-                                    name,
-                                    ty: Type::Any,
-                                    mutable: false,
-                                    init: Some(Expr::IndexGet {
-                                        object: Box::new(Expr::LocalGet(arr_id)),
-                                        index: Box::new(Expr::LocalGet(idx_id)),
-                                    }),
-                                }]
-                            }
-                        }
-                    } else {
-                        return Err(anyhow!("for-of requires a variable declaration"));
-                    }
-                }
-                ast::ForHead::Pat(_) => {
-                    let (name, id) = var_ids[0].clone();
-                    vec![Stmt::Let {
-                        id,
-                        name,
-                        ty: Type::Any,
-                        mutable: false,
-                        init: Some(Expr::IndexGet {
-                            object: Box::new(Expr::LocalGet(arr_id)),
-                            index: Box::new(Expr::LocalGet(idx_id)),
-                        }),
-                    }]
-                }
-                _ => return Err(anyhow!("Unsupported for-of left-hand side")),
+
+            result.push(Stmt::For { init, condition, update, body });
+        }
+        ast::Stmt::Try(try_stmt) => {
+            // Lower try body
+            let body = lower_block_stmt(ctx, &try_stmt.block)?;
+
+            // Lower catch clause (if present)
+            let catch = if let Some(ref catch_clause) = try_stmt.handler {
+                let scope_mark = ctx.enter_scope();
+
+                // Lower catch parameter (if present)
+                let param = if let Some(ref pat) = catch_clause.param {
+                    let param_name = get_pat_name(pat)?;
+                    let param_id = ctx.define_local(param_name.clone(), Type::Any);
+                    Some((param_id, param_name))
+                } else {
+                    None
+                };
+
+                // Lower catch body
+                let catch_body = lower_block_stmt(ctx, &catch_clause.body)?;
+
+                ctx.exit_scope(scope_mark);
+
+                Some(CatchClause { param, body: catch_body })
+            } else {
+                None
             };
 
-            // Prepend the binding statements to the loop body
-            for (i, stmt) in binding_stmts.into_iter().enumerate() {
-                loop_body.insert(i, stmt);
-            }
+            // Lower finally (if present)
+            let finally = if let Some(ref finally_block) = try_stmt.finalizer {
+                Some(lower_block_stmt(ctx, finally_block)?)
+            } else {
+                None
+            };
 
-            // Create the for loop
-            result.push(Stmt::For {
-                init: Some(Box::new(Stmt::Let {
-                    id: idx_id,
-                    name: format!("__idx_{}", idx_id),
-                    ty: Type::Number,
-                    mutable: true,
-                    init: Some(Expr::Number(0.0)),
-                })),
-                condition: Some(Expr::Compare {
-                    op: CompareOp::Lt,
-                    left: Box::new(Expr::LocalGet(idx_id)),
-                    right: Box::new(Expr::PropertyGet {
-                        object: Box::new(Expr::LocalGet(arr_id)),
-                        property: "length".to_string(),
-                    }),
-                }),
-                update: Some(Expr::Update {
-                    id: idx_id,
-                    op: UpdateOp::Increment,
-                    prefix: true,
-                }),
-                body: loop_body,
-            });
+            result.extend(lower_try(ctx, body, catch, finally));
+        }
+        ast::Stmt::Throw(throw_stmt) => {
+            let expr = lower_expr(ctx, &throw_stmt.arg)?;
+            result.push(Stmt::Throw(expr));
+        }
+        ast::Stmt::Switch(switch_stmt) => {
+            result.push(lower_switch(ctx, switch_stmt)?);
+        }
+        ast::Stmt::ForOf(for_of_stmt) => {
+            result.extend(lower_for_of(ctx, for_of_stmt)?);
         }
         ast::Stmt::ForIn(for_in_stmt) => {
             // Desugar for-in to a for-of over Object.keys(obj) (same as in lower_stmt)
@@ -2993,14 +5121,370 @@ fn lower_body_stmt(ctx: &mut LoweringContext, stmt: &ast::Stmt) -> Result<Vec<St
                 body: loop_body,
             });
         }
-        _ => {
-            // TODO: handle more statement types
+        ast::Stmt::DoWhile(do_while) => {
+            result.extend(lower_do_while(ctx, do_while)?);
+        }
+        ast::Stmt::Labeled(labeled) => {
+            // The label only matters to `break label`/`continue label`, reported where they are
+            result.extend(lower_body_stmt(ctx, &labeled.body)?);
+        }
+        ast::Stmt::Decl(ast::Decl::Fn(fn_decl)) => {
+            // Only reached for a declaration that isn't in a block; blocks hoist them
+            result.extend(lower_nested_fn_decl(ctx, fn_decl)?);
+        }
+        ast::Stmt::Decl(ast::Decl::Class(class_decl)) => {
+            report_dropped(ctx, class_decl.class.span, &format!("class '{}' declared inside a function", class_decl.ident.sym), "move the class to module scope");
+        }
+        ast::Stmt::Decl(ast::Decl::TsEnum(enum_decl)) => {
+            report_dropped(ctx, enum_decl.span, &format!("enum '{}' declared inside a function", enum_decl.id.sym), "move the enum to module scope");
+        }
+        ast::Stmt::With(with_stmt) => {
+            report_dropped(ctx, with_stmt.span, "`with` statement", "refer to the object's properties explicitly");
         }
+        // Types have no code, `using` is lowered by lower_stmt_list, and namespaces are module-level
+        ast::Stmt::Decl(ast::Decl::TsInterface(_) | ast::Decl::TsTypeAlias(_) | ast::Decl::Using(_) | ast::Decl::TsModule(_)) => {}
+        ast::Stmt::Empty(_) | ast::Stmt::Debugger(_) => {}
     }
 
     Ok(result)
 }
 
+/// `do body while (test)` -> `let first = true; while (first || test) { first = false; body }`.
+/// The flag is cleared before the body so `continue` still goes to the test.
+fn lower_do_while(ctx: &mut LoweringContext, do_while: &ast::DoWhileStmt) -> Result<Vec<Stmt>> {
+    let first_id = ctx.fresh_local();
+    let mut body = vec![Stmt::Expr(Expr::LocalSet(first_id, Box::new(Expr::Bool(false))))];
+    body.extend(lower_body_stmt(ctx, &do_while.body)?);
+    let test = lower_expr(ctx, &do_while.test)?;
+    Ok(vec![
+        Stmt::Let {
+            id: first_id,
+            name: format!("__do_first_{}", first_id),
+            ty: Type::Boolean,
+            mutable: true,
+            init: Some(Expr::Bool(true)),
+        },
+        Stmt::While {
+            condition: Expr::Logical {
+                op: LogicalOp::Or,
+                left: Box::new(Expr::LocalGet(first_id)),
+                right: Box::new(test),
+            },
+            body,
+        },
+    ])
+}
+
+/// `function name() {}` inside a function: a closure bound to a local `name`.
+/// `declare function` has nothing to lower.
+fn lower_nested_fn_decl(ctx: &mut LoweringContext, fn_decl: &ast::FnDecl) -> Result<Option<Stmt>> {
+    if fn_decl.function.body.is_none() {
+        return Ok(None);
+    }
+    let closure = lower_expr(ctx, &ast::Expr::Fn(ast::FnExpr {
+        ident: Some(fn_decl.ident.clone()),
+        function: fn_decl.function.clone(),
+    }))?;
+    let name = fn_decl.ident.sym.to_string();
+    let id = ctx.define_local(name.clone(), Type::Any);
+    Ok(Some(Stmt::Let { id, name, ty: Type::Any, mutable: false, init: Some(closure) }))
+}
+
+fn plain_arg(expr: ast::Expr) -> ast::ExprOrSpread {
+    ast::ExprOrSpread { spread: None, expr: Box::new(expr) }
+}
+
+/// `array[index]`
+fn index_ast(array: &ast::Expr, index: usize) -> ast::Expr {
+    ast::Expr::Member(ast::MemberExpr {
+        obj: Box::new(array.clone()),
+        prop: ast::MemberProp::Computed(ast::ComputedPropName {
+            span: DUMMY_SP,
+            expr: Box::new(ast::Expr::Lit(ast::Lit::Num(index.into()))),
+        }),
+        ..Default::default()
+    })
+}
+
+/// `array.slice(start)`
+fn slice_ast(array: &ast::Expr, start: usize) -> ast::Expr {
+    ast::Expr::Call(ast::CallExpr {
+        callee: ast::Callee::Expr(Box::new(ast::Expr::Member(ast::MemberExpr {
+            obj: Box::new(array.clone()),
+            prop: ast::MemberProp::Ident(ast::IdentName::new("slice".into(), DUMMY_SP)),
+            ..Default::default()
+        }))),
+        args: vec![plain_arg(ast::Expr::Lit(ast::Lit::Num(start.into())))],
+        ..Default::default()
+    })
+}
+
+/// Whether `expr` is a local holding an array, which can be indexed repeatedly without
+/// evaluating anything twice
+fn is_array_local(ctx: &LoweringContext, expr: &ast::Expr) -> bool {
+    matches!(expr, ast::Expr::Ident(_))
+        && matches!(static_type_of(ctx, expr), Some(Type::Array(_) | Type::Tuple(_)))
+}
+
+/// Arguments with spreads of array literals and of tuple-typed locals replaced by their elements
+fn flatten_static_spreads(ctx: &LoweringContext, args: &[ast::ExprOrSpread]) -> Vec<ast::ExprOrSpread> {
+    let mut flattened = Vec::new();
+    for arg in args {
+        if arg.spread.is_none() {
+            flattened.push(arg.clone());
+            continue;
+        }
+        match unwrap_parens(&arg.expr) {
+            ast::Expr::Array(array) if array.elems.iter().all(Option::is_some) => {
+                let elems: Vec<_> = array.elems.iter().flatten().cloned().collect();
+                flattened.extend(flatten_static_spreads(ctx, &elems));
+            }
+            expr @ ast::Expr::Ident(_) => match static_type_of(ctx, expr) {
+                Some(Type::Tuple(elems)) => flattened.extend((0..elems.len()).map(|i| plain_arg(index_ast(expr, i)))),
+                _ => flattened.push(arg.clone()),
+            },
+            _ => flattened.push(arg.clone()),
+        }
+    }
+    flattened
+}
+
+/// Fit arguments to a callee's parameters. Arguments from the rest parameter's position on
+/// are packed into an array literal (codegen only does this for calls to module functions),
+/// and a spread of unknown length that ends the arguments is indexed in place when it is an
+/// array local. Returns None when a spread can't be indexed in place.
+fn fit_args_to_shape(ctx: &LoweringContext, args: &[ast::ExprOrSpread], shape: ParamShape) -> Option<Vec<ast::ExprOrSpread>> {
+    let mut fitted = Vec::new();
+    let mut extra = Vec::new();
+    for (i, arg) in args.iter().enumerate() {
+        if fitted.len() >= shape.fixed {
+            extra.extend(args[i..].iter().cloned());
+            break;
+        }
+        if arg.spread.is_none() {
+            fitted.push(arg.clone());
+            continue;
+        }
+        if i + 1 != args.len() || !is_array_local(ctx, &arg.expr) {
+            return None;
+        }
+        let start = fitted.len();
+        while fitted.len() < shape.fixed {
+            fitted.push(plain_arg(index_ast(&arg.expr, fitted.len() - start)));
+        }
+        if shape.rest {
+            fitted.push(plain_arg(slice_ast(&arg.expr, shape.fixed - start)));
+        }
+        return Some(fitted);
+    }
+    if shape.rest {
+        while fitted.len() < shape.fixed {
+            fitted.push(plain_arg(ast::Expr::Ident(ast::Ident::new_no_ctxt("undefined".into(), DUMMY_SP))));
+        }
+        fitted.push(plain_arg(ast::Expr::Array(ast::ArrayLit { elems: extra.into_iter().map(Some).collect(), ..Default::default() })));
+    } else {
+        // Arguments past the last parameter are only evaluated
+        fitted.extend(extra.into_iter().filter(|arg| arg.spread.is_none()));
+    }
+    Some(fitted)
+}
+
+/// `((__args: any[]) => <inner>)([...args])`, where `inner` is built around `...__args`.
+/// Evaluates the arguments into an array first, so a call whose spread can't be indexed in
+/// place is fitted against the `__args` local instead. The callee's receiver is evaluated
+/// after the arguments.
+fn spread_iife(args: Vec<ast::ExprOrSpread>, inner: impl FnOnce(Vec<ast::ExprOrSpread>) -> ast::Expr) -> ast::CallExpr {
+    let args_ident = ast::Ident::new_no_ctxt("__args".into(), DUMMY_SP);
+    let any_array = ast::TsType::TsArrayType(ast::TsArrayType {
+        span: DUMMY_SP,
+        elem_type: Box::new(ast::TsType::TsKeywordType(ast::TsKeywordType {
+            span: DUMMY_SP,
+            kind: ast::TsKeywordTypeKind::TsAnyKeyword,
+        })),
+    });
+    let param = ast::Pat::Ident(ast::BindingIdent {
+        id: args_ident.clone(),
+        type_ann: Some(Box::new(ast::TsTypeAnn { span: DUMMY_SP, type_ann: Box::new(any_array) })),
+    });
+    let spread = ast::ExprOrSpread { spread: Some(DUMMY_SP), expr: Box::new(ast::Expr::Ident(args_ident)) };
+    let arrow = ast::ArrowExpr {
+        params: vec![param],
+        body: Box::new(ast::BlockStmtOrExpr::Expr(Box::new(inner(vec![spread])))),
+        ..Default::default()
+    };
+    ast::CallExpr {
+        callee: ast::Callee::Expr(Box::new(ast::Expr::Paren(ast::ParenExpr {
+            span: DUMMY_SP,
+            expr: Box::new(ast::Expr::Arrow(arrow)),
+        }))),
+        args: vec![plain_arg(ast::Expr::Array(ast::ArrayLit { elems: args.into_iter().map(Some).collect(), ..Default::default() }))],
+        ..Default::default()
+    }
+}
+
+/// Whether a callee is a function of a native module, whose parameters are only known to codegen
+fn is_native_callee(ctx: &LoweringContext, callee: &ast::Expr) -> bool {
+    let name = match unwrap_parens(callee) {
+        ast::Expr::Member(member) => match unwrap_parens(&member.obj) {
+            ast::Expr::Ident(ident) => ident.sym.as_ref(),
+            _ => return false,
+        },
+        ast::Expr::Ident(ident) => ident.sym.as_ref(),
+        _ => return false,
+    };
+    ctx.lookup_native_module(name).is_some()
+        || ctx.lookup_builtin_module_alias(name).is_some()
+        || ctx.lookup_native_instance(name).is_some()
+}
+
+/// Rewrite the arguments of a call with spreads, or of a call to a class member with a rest
+/// parameter, into a form the call lowering handles:
+/// - spreads of array literals and tuples become their elements
+/// - `super(...)` and calls to the methods of the module's classes are fitted to the callee's
+///   parameters (see `fit_args_to_shape`), through `spread_iife` when needed
+/// - `Math.min`/`Math.max` over a spread of unknown length reduce the spread array
+///
+/// Remaining spreads of unknown length are left to `CallSpread`. Returns None when the call
+/// is lowered as written.
+fn fit_call_args(ctx: &LoweringContext, call: &ast::CallExpr) -> Result<Option<ast::CallExpr>> {
+    let args = flatten_static_spreads(ctx, &call.args);
+    let has_spread = args.iter().any(|arg| arg.spread.is_some());
+    let (shape, in_place_only) = match &call.callee {
+        ast::Callee::Super(_) => {
+            let parent = ctx.current_class.as_deref().and_then(|class| ctx.class_parent(class));
+            (parent.and_then(|parent| ctx.constructor_param_shape(parent)), true)
+        }
+        ast::Callee::Expr(callee) => match unwrap_parens(callee) {
+            ast::Expr::Member(member) => {
+                let shape = member_prop_name(&member.prop).and_then(|method| match unwrap_parens(&member.obj) {
+                    ast::Expr::Ident(ident) if ctx.lookup_local(ident.sym.as_ref()).is_none() => {
+                        ctx.method_param_shape(ident.sym.as_ref(), &method, true)
+                    }
+                    obj => match static_type_of(ctx, obj)? {
+                        Type::Named(class) => ctx.method_param_shape(&class, &method, false),
+                        _ => None,
+                    },
+                });
+                (shape, false)
+            }
+            ast::Expr::SuperProp(super_prop) => {
+                let method = match &super_prop.prop {
+                    ast::SuperProp::Ident(ident) => Some(ident.sym.to_string()),
+                    ast::SuperProp::Computed(_) => None,
+                };
+                let parent = ctx.current_class.as_deref().and_then(|class| ctx.class_parent(class));
+                let shape = parent.zip(method).and_then(|(parent, method)| ctx.method_param_shape(parent, &method, false));
+                (shape, true)
+            }
+            _ => (None, false),
+        },
+        ast::Callee::Import(_) => (None, false),
+    };
+    let rebuild = |args: Vec<ast::ExprOrSpread>| ast::CallExpr { args, ..call.clone() };
+
+    if let Some(shape) = shape.filter(|shape| shape.rest || has_spread) {
+        if let Some(fitted) = fit_args_to_shape(ctx, &args, shape) {
+            return Ok(Some(rebuild(fitted)));
+        }
+        if !in_place_only {
+            return Ok(Some(spread_iife(args, |spread| ast::Expr::Call(rebuild(spread)))));
+        }
+    }
+    // `super` can't be referenced from the closure `spread_iife` creates
+    if in_place_only && has_spread {
+        return Err(anyhow!("Spread arguments to super calls must be array literals, tuples, or array variables passed to a class of this module"));
+    }
+
+    if has_spread {
+        if let ast::Callee::Expr(callee) = &call.callee {
+            if let ast::Expr::Member(member) = unwrap_parens(callee) {
+                let method = member_prop_name(&member.prop);
+                let is_math = matches!(unwrap_parens(&member.obj), ast::Expr::Ident(obj) if obj.sym.as_ref() == "Math")
+                    && ctx.lookup_local("Math").is_none();
+                if let (true, Some("min" | "max")) = (is_math, method.as_deref()) {
+                    // Math.max(...xs) -> ((__args) => __args.reduce((a, b) => Math.max(a, b), -Infinity))([...xs])
+                    let initial = if method.as_deref() == Some("max") { f64::NEG_INFINITY } else { f64::INFINITY };
+                    let a = ast::Ident::new_no_ctxt("__a".into(), DUMMY_SP);
+                    let b = ast::Ident::new_no_ctxt("__b".into(), DUMMY_SP);
+                    let pairwise = ast::ArrowExpr {
+                        params: vec![ast::Pat::Ident(a.clone().into()), ast::Pat::Ident(b.clone().into())],
+                        body: Box::new(ast::BlockStmtOrExpr::Expr(Box::new(ast::Expr::Call(rebuild(vec![
+                            plain_arg(ast::Expr::Ident(a)),
+                            plain_arg(ast::Expr::Ident(b)),
+                        ]))))),
+                        ..Default::default()
+                    };
+                    return Ok(Some(spread_iife(args, |spread| {
+                        let array = spread.into_iter().next().unwrap().expr;
+                        ast::Expr::Call(ast::CallExpr {
+                            callee: ast::Callee::Expr(Box::new(ast::Expr::Member(ast::MemberExpr {
+                                obj: array,
+                                prop: ast::MemberProp::Ident(ast::IdentName::new("reduce".into(), DUMMY_SP)),
+                                ..Default::default()
+                            }))),
+                            args: vec![
+                                plain_arg(ast::Expr::Arrow(pairwise)),
+                                plain_arg(ast::Expr::Lit(ast::Lit::Num(initial.into()))),
+                            ],
+                            ..Default::default()
+                        })
+                    })));
+                }
+            }
+            if is_native_callee(ctx, callee) {
+                return Err(anyhow!(
+                    "Spread arguments to native module functions must be array literals or tuples"
+                ));
+            }
+        }
+    }
+
+    Ok((args != call.args).then(|| rebuild(args)))
+}
+
+/// `fit_call_args` for `new C(...)`: arguments are fitted to the constructor of a class of
+/// this module. The result is either the `new` expression to lower or a `spread_iife` call.
+fn fit_new_args(ctx: &LoweringContext, new_expr: &ast::NewExpr) -> Result<Option<ast::Expr>> {
+    let Some(call_args) = &new_expr.args else { return Ok(None) };
+    let args = flatten_static_spreads(ctx, call_args);
+    let has_spread = args.iter().any(|arg| arg.spread.is_some());
+    let class_name = match unwrap_parens(&new_expr.callee) {
+        ast::Expr::Ident(ident) if ctx.lookup_local(ident.sym.as_ref()).is_none() => Some(ident.sym.as_ref()),
+        _ => None,
+    };
+    let rebuild = |args: Vec<ast::ExprOrSpread>| ast::Expr::New(ast::NewExpr { args: Some(args), ..new_expr.clone() });
+
+    match class_name.and_then(|class| ctx.constructor_param_shape(class)) {
+        Some(shape) if shape.rest || has_spread => Ok(Some(match fit_args_to_shape(ctx, &args, shape) {
+            Some(fitted) => rebuild(fitted),
+            None => ast::Expr::Call(spread_iife(args, rebuild)),
+        })),
+        Some(_) => Ok((args != *call_args).then(|| rebuild(args))),
+        None if has_spread => Err(anyhow!(
+            "Spread arguments to `new {}` must be array literals or tuples",
+            class_name.unwrap_or("<expression>")
+        )),
+        None => Ok((args != *call_args).then(|| rebuild(args))),
+    }
+}
+
+/// `CallSpread` with its arguments merged into a single spread of an array holding them in
+/// order when the callee is a function or closure, whose codegen unpacks a single array
+fn call_spread(callee: Box<Expr>, args: Vec<CallArg>, type_args: Vec<Type>) -> Expr {
+    let args = if args.len() > 1 && matches!(callee.as_ref(), Expr::FuncRef(_) | Expr::LocalGet(_)) {
+        let elements = args.into_iter()
+            .map(|arg| match arg {
+                CallArg::Expr(expr) => ArrayElement::Expr(expr),
+                CallArg::Spread(expr) => ArrayElement::Spread(expr),
+            })
+            .collect();
+        vec![CallArg::Spread(Expr::ArraySpread(elements))]
+    } else {
+        args
+    };
+    Expr::CallSpread { callee, args, type_args }
+}
+
 fn lower_expr(ctx: &mut LoweringContext, expr: &ast::Expr) -> Result<Expr> {
     match expr {
         ast::Expr::Lit(lit) => lower_lit(lit),
@@ -3013,6 +5497,9 @@ fn lower_expr(ctx: &mut LoweringContext, expr: &ast::Expr) -> Result<Expr> {
             } else if let Some((module_name, _method_name)) = ctx.lookup_native_module(&name) {
                 // Native module reference (e.g., mysql from 'mysql2/promise')
                 Ok(Expr::NativeModuleRef(module_name.to_string()))
+            } else if let Some(source) = ctx.lookup_require_namespace(&name) {
+                // Module bound by require() used as a value - module.exports, i.e. the default export
+                Ok(require_named_import(ctx, &source, "default"))
             } else if let Some(orig_name) = ctx.lookup_imported_func(&name) {
                 // Imported function - reference by its original exported name
                 // Look up type information if available
@@ -3122,6 +5609,9 @@ fn lower_expr(ctx: &mut LoweringContext, expr: &ast::Expr) -> Result<Expr> {
             }
         }
         ast::Expr::Unary(unary) => {
+            if let (ast::UnaryOp::Delete, ast::Expr::Member(member)) = (unary.op, unwrap_parens(&unary.arg)) {
+                check_readonly_write(ctx, member, true);
+            }
             let operand = Box::new(lower_expr(ctx, &unary.arg)?);
             match unary.op {
                 ast::UnaryOp::Minus => Ok(Expr::Unary { op: UnaryOp::Neg, operand }),
@@ -3134,6 +5624,15 @@ fn lower_expr(ctx: &mut LoweringContext, expr: &ast::Expr) -> Result<Expr> {
             }
         }
         ast::Expr::Call(call) => {
+            if let ast::Callee::Expr(callee) = &call.callee {
+                if let ast::Expr::Member(member) = unwrap_parens(callee) {
+                    check_readonly_array_call(ctx, member);
+                    check_known_property(ctx, member);
+                }
+            }
+            let fitted = fit_call_args(ctx, call)?;
+            let call = fitted.as_ref().unwrap_or(call);
+
             // Check if any argument has spread
             let has_spread = call.args.iter().any(|arg| arg.spread.is_some());
 
@@ -3146,7 +5645,7 @@ fn lower_expr(ctx: &mut LoweringContext, expr: &ast::Expr) -> Result<Expr> {
                 Some(call.args.iter().zip(args.iter())
                     .map(|(ast_arg, lowered)| {
                         if ast_arg.spread.is_some() {
-                            CallArg::Spread(lowered.clone())
+                            CallArg::Spread(iterable_to_array(ctx, &ast_arg.expr, lowered.clone()))
                         } else {
                             CallArg::Expr(lowered.clone())
                         }
@@ -3172,6 +5671,117 @@ fn lower_expr(ctx: &mut LoweringContext, expr: &ast::Expr) -> Result<Expr> {
                         }
                     }
 
+                    // Temporal.PlainDate.from(...), Temporal.Now.zonedDateTimeISO(...)
+                    if let Some(method) = temporal_function(ctx, expr).filter(|path| path.contains('.')) {
+                        return Ok(Expr::NativeMethodCall {
+                            module: "perry/temporal".to_string(),
+                            class_name: None,
+                            object: None,
+                            method,
+                            args,
+                        });
+                    }
+
+                    // require('./addon.node') - the addon's exports object
+                    if let Some(path) = require_call_literal(ctx, call).and_then(|source| native_addon_path(ctx, &source)) {
+                        ctx.native_addons.push(path.clone());
+                        return Ok(Expr::NativeAddonLoad { path });
+                    }
+
+                    // require('./module') used as a value - the module's default export
+                    if let Some(source) = require_call_expr_source(ctx, call) {
+                        return Ok(require_named_import(ctx, &source, "default"));
+                    }
+
+                    // lib.foo(...) where `const lib = require('./lib')` - call the named export directly
+                    if let ast::Expr::Member(member) = expr.as_ref() {
+                        if let (ast::Expr::Ident(obj_ident), ast::MemberProp::Ident(prop_ident)) = (member.obj.as_ref(), &member.prop) {
+                            if let Some(source) = ctx.lookup_require_namespace(obj_ident.sym.as_ref()) {
+                                let prop_name = prop_ident.sym.to_string();
+                                let callee = Box::new(require_named_import(ctx, &source, &prop_name));
+                                return Ok(match spread_args {
+                                    Some(spread_args) => call_spread(callee, spread_args, vec![]),
+                                    None => Expr::Call { callee, args, type_args: vec![], span: ctx.span(call.span) },
+                                });
+                            }
+                        }
+                    }
+
+                    // DisposableStack methods
+                    if let ast::Expr::Member(member) = expr.as_ref() {
+                        if let (ast::Expr::Ident(obj_ident), ast::MemberProp::Ident(prop_ident)) = (member.obj.as_ref(), &member.prop) {
+                            if is_disposable_stack_local(ctx, obj_ident.sym.as_ref()) {
+                                let stack = Box::new(lower_expr(ctx, &member.obj)?);
+                                let mut args_iter = args.into_iter();
+                                match (prop_ident.sym.as_ref(), args_iter.next(), args_iter.next()) {
+                                    ("use", Some(value), _) => {
+                                        // use(value) = adopt(value, v => v[Symbol.dispose]())
+                                        let ty = disposable_value_type(ctx, &call.args[0].expr);
+                                        return Ok(Expr::DisposableStackAdopt {
+                                            stack,
+                                            value: Box::new(value),
+                                            on_dispose: Box::new(resource_disposer(ctx, ty)),
+                                        });
+                                    }
+                                    ("adopt", Some(value), Some(on_dispose)) => {
+                                        return Ok(Expr::DisposableStackAdopt {
+                                            stack,
+                                            value: Box::new(value),
+                                            on_dispose: Box::new(on_dispose),
+                                        });
+                                    }
+                                    ("defer", Some(on_dispose), _) => {
+                                        return Ok(Expr::DisposableStackDefer { stack, on_dispose: Box::new(on_dispose) });
+                                    }
+                                    ("dispose", _, _) => return Ok(Expr::DisposableStackDispose(stack)),
+                                    ("move", _, _) => return Ok(Expr::DisposableStackMove(stack)),
+                                    (method, _, _) => {
+                                        return Err(anyhow!("Unsupported DisposableStack method: {}", method));
+                                    }
+                                }
+                            }
+                        }
+                    }
+
+                    // import.meta.resolve(specifier)
+                    if let ast::Expr::Member(member) = expr.as_ref() {
+                        if is_import_meta(&member.obj) {
+                            if let ast::MemberProp::Ident(prop_ident) = &member.prop {
+                                if prop_ident.sym.as_ref() == "resolve" {
+                                    let specifier = args.into_iter().next().unwrap_or(Expr::Undefined);
+                                    return Ok(lower_import_meta_resolve(ctx, specifier));
+                                }
+                            }
+                        }
+                    }
+
+                    // process.stdout.write(data), process.stdin.on('data', listener), ...
+                    if let ast::Expr::Member(member) = expr.as_ref() {
+                        if let (Some(fd), ast::MemberProp::Ident(method_ident)) = (process_stdio_fd(&member.obj), &member.prop) {
+                            if let Some(stdio_call) = lower_process_stdio_call(fd, method_ident.sym.as_ref(), &call.args, &args) {
+                                return Ok(stdio_call);
+                            }
+                        }
+                    }
+
+                    // timer.unref(), timer.ref() and timer.hasRef() on a setTimeout/setInterval timer
+                    if let ast::Expr::Member(member) = expr.as_ref() {
+                        if let ast::MemberProp::Ident(method_ident) = &member.prop {
+                            let runtime_fn = match method_ident.sym.as_ref() {
+                                "unref" => Some("js_timer_unref"),
+                                "ref" => Some("js_timer_ref"),
+                                "hasRef" => Some("js_timer_has_ref"),
+                                _ => None,
+                            };
+                            if let Some(runtime_fn) = runtime_fn {
+                                if is_timer_handle(ctx, &member.obj) {
+                                    let timer = lower_expr(ctx, &member.obj)?;
+                                    return Ok(runtime_call(runtime_fn, vec![timer]));
+                                }
+                            }
+                        }
+                    }
+
                     // Check for native module method calls (e.g., mysql.createConnection())
                     if let ast::Expr::Member(member) = expr.as_ref() {
                         if let ast::Expr::Ident(obj_ident) = member.obj.as_ref() {
@@ -3185,6 +5795,20 @@ fn lower_expr(ctx: &mut LoweringContext, expr: &ast::Expr) -> Result<Expr> {
                                         "uptime" => return Ok(Expr::ProcessUptime),
                                         "cwd" => return Ok(Expr::ProcessCwd),
                                         "memoryUsage" => return Ok(Expr::ProcessMemoryUsage),
+                                        "setMemoryLimit" if !args.is_empty() => {
+                                            let mut args = args.into_iter();
+                                            let limit = Box::new(args.next().unwrap());
+                                            let policy = args.next().map(Box::new);
+                                            return Ok(Expr::ProcessSetMemoryLimit { limit, policy });
+                                        }
+                                        "onLowMemory" if !args.is_empty() => {
+                                            return Ok(Expr::ProcessOnLowMemory(Box::new(args.into_iter().next().unwrap())));
+                                        }
+                                        "getActiveResourcesInfo" => return Ok(runtime_call("js_process_active_resources", Vec::new())),
+                                        // Perry extension: the pending timers in detail, for debugging hangs
+                                        "getActiveTimers" => return Ok(runtime_call("js_timer_active_list", Vec::new())),
+                                        // Perry extension: allocation counts and bytes per value type
+                                        "allocationStats" => return Ok(runtime_call("js_memory_allocation_stats", Vec::new())),
                                         _ => {} // Fall through to generic handling
                                     }
                                 }
@@ -3302,6 +5926,18 @@ fn lower_expr(ctx: &mut LoweringContext, expr: &ast::Expr) -> Result<Expr> {
                                             let value = args.get(0).cloned().unwrap_or(Expr::Undefined);
                                             return Ok(Expr::ArrayIsArray(Box::new(value)));
                                         }
+                                        // Array.from(iterable, mapFn?) -> a fresh array of the iterable's values
+                                        "from" if !call.args.is_empty() => {
+                                            let source = iterable_to_array(ctx, &call.args[0].expr, args[0].clone());
+                                            let array = Expr::ArraySpread(vec![ArrayElement::Spread(source)]);
+                                            return Ok(match args.get(1) {
+                                                Some(map_fn) => Expr::ArrayMap {
+                                                    array: Box::new(array),
+                                                    callback: Box::new(map_fn.clone()),
+                                                },
+                                                None => array,
+                                            });
+                                        }
                                         _ => {} // Fall through to generic handling
                                     }
                                 }
@@ -3379,6 +6015,12 @@ fn lower_expr(ctx: &mut LoweringContext, expr: &ast::Expr) -> Result<Expr> {
                             if let Some((module_name, class_name)) = native_instance {
                                 if let ast::MemberProp::Ident(method_ident) = &member.prop {
                                     let method_name = method_ident.sym.to_string();
+                                    if module_name == "fastify" {
+                                        record_http_route(ctx, &method_name, call, &args);
+                                    }
+                                    if SQL_TAG_MODULES.contains(&module_name.as_str()) && matches!(method_name.as_str(), "query" | "execute") {
+                                        spread_query_config(&mut args);
+                                    }
                                     // Get the object expression (the instance variable)
                                     let object_expr = lower_expr(ctx, &member.obj)?;
                                     return Ok(Expr::NativeMethodCall {
@@ -3475,6 +6117,11 @@ fn lower_expr(ctx: &mut LoweringContext, expr: &ast::Expr) -> Result<Expr> {
                                                 return Ok(Expr::FsUnlinkSync(Box::new(args.into_iter().next().unwrap())));
                                             }
                                         }
+                                        "createReadStream" => {
+                                            if args.len() >= 1 {
+                                                return Ok(Expr::FsCreateReadStream(Box::new(args.into_iter().next().unwrap())));
+                                            }
+                                        }
                                         _ => {} // Fall through to generic handling
                                     }
                                 }
@@ -3535,6 +6182,11 @@ fn lower_expr(ctx: &mut LoweringContext, expr: &ast::Expr) -> Result<Expr> {
                                                 return Ok(Expr::JsonStringify(Box::new(args.into_iter().next().unwrap())));
                                             }
                                         }
+                                        "canonicalize" => {
+                                            if args.len() >= 1 {
+                                                return Ok(Expr::JsonCanonicalize(Box::new(args.into_iter().next().unwrap())));
+                                            }
+                                        }
                                         _ => {} // Fall through to generic handling
                                     }
                                 }
@@ -3900,8 +6552,8 @@ fn lower_expr(ctx: &mut LoweringContext, expr: &ast::Expr) -> Result<Expr> {
                                 // includes, split) — those are handled by the general dispatch which
                                 // checks is_string at codegen time.
                                 let type_info = ctx.lookup_local_type(&arr_name);
-                                let is_known_string = type_info.map(|ty| matches!(ty, Type::String)).unwrap_or(false);
-                                let is_known_not_string = type_info.map(|ty| !matches!(ty, Type::String | Type::Any | Type::Unknown)).unwrap_or(false);
+                                let is_known_string = type_info.is_some_and(Type::is_string);
+                                let is_known_not_string = type_info.is_some_and(|ty| !ty.is_string() && !matches!(ty, Type::Any | Type::Unknown));
                                 let is_ambiguous_method = matches!(method_name,
                                     "indexOf" | "includes" | "slice"
                                 );
@@ -4141,6 +6793,26 @@ fn lower_expr(ctx: &mut LoweringContext, expr: &ast::Expr) -> Result<Expr> {
                                             }
                                             // Fall through if neither Set nor Map
                                         }
+                                        "keys" | "values" | "entries" => {
+                                            // Map/Set iteration methods return an array snapshot
+                                            let is_set = ctx.lookup_local_type(&arr_name)
+                                                .map(|ty| matches!(ty, Type::Generic { base, .. } if base == "Set"))
+                                                .unwrap_or(false);
+                                            let is_map = ctx.lookup_local_type(&arr_name)
+                                                .map(|ty| matches!(ty, Type::Generic { base, .. } if base == "Map"))
+                                                .unwrap_or(false);
+                                            let receiver = Box::new(Expr::LocalGet(array_id));
+                                            if is_map {
+                                                return Ok(match method_name {
+                                                    "keys" => Expr::MapKeys(receiver),
+                                                    "values" => Expr::MapValues(receiver),
+                                                    _ => Expr::MapEntries(receiver),
+                                                });
+                                            } else if is_set && method_name != "entries" {
+                                                return Ok(Expr::SetValues(receiver));
+                                            }
+                                            // Fall through if neither Set nor Map
+                                        }
                                         // Set methods
                                         "add" => {
                                             // Check if this is a Set type before treating as Set.add()
@@ -4513,6 +7185,8 @@ fn lower_expr(ctx: &mut LoweringContext, expr: &ast::Expr) -> Result<Expr> {
                                             // Extract method, body, and headers from options
                                             let mut method = Expr::String("GET".to_string());
                                             let mut body = Expr::Undefined;
+                                            let mut stream_body = false;
+                                            let mut on_upload_progress = None;
                                             let mut headers_obj: Vec<(String, Expr)> = Vec::new();
 
                                             for prop in &obj.props {
@@ -4529,6 +7203,14 @@ fn lower_expr(ctx: &mut LoweringContext, expr: &ast::Expr) -> Result<Expr> {
                                                             }
                                                             "body" => {
                                                                 body = lower_expr(ctx, &kv.value)?;
+                                                                stream_body = match &*kv.value {
+                                                                    ast::Expr::Ident(ident) => ctx.lookup_native_instance(ident.sym.as_ref())
+                                                                        == Some(("fs", "ReadStream")),
+                                                                    _ => matches!(body, Expr::FsCreateReadStream(_)),
+                                                                };
+                                                            }
+                                                            "onUploadProgress" => {
+                                                                on_upload_progress = Some(Box::new(lower_expr(ctx, &kv.value)?));
                                                             }
                                                             "headers" => {
                                                                 // Extract headers object
@@ -4560,6 +7242,8 @@ fn lower_expr(ctx: &mut LoweringContext, expr: &ast::Expr) -> Result<Expr> {
                                                 method: Box::new(method),
                                                 body: Box::new(body),
                                                 headers: headers_obj,
+                                                stream_body,
+                                                on_upload_progress,
                                             });
                                         }
                                     }
@@ -4571,6 +7255,8 @@ fn lower_expr(ctx: &mut LoweringContext, expr: &ast::Expr) -> Result<Expr> {
                                     method: Box::new(Expr::String("GET".to_string())),
                                     body: Box::new(Expr::Undefined),
                                     headers: Vec::new(),
+                                    stream_body: false,
+                                    on_upload_progress: None,
                                 });
                             }
                             _ => {} // Fall through to generic handling
@@ -4708,6 +7394,11 @@ fn lower_expr(ctx: &mut LoweringContext, expr: &ast::Expr) -> Result<Expr> {
                                             return Ok(Expr::FsAppendFileSync(Box::new(path), Box::new(content)));
                                         }
                                     }
+                                    "createReadStream" => {
+                                        if args.len() >= 1 {
+                                            return Ok(Expr::FsCreateReadStream(Box::new(args.into_iter().next().unwrap())));
+                                        }
+                                    }
                                     _ => {} // Fall through
                                 }
                             }
@@ -4716,6 +7407,10 @@ fn lower_expr(ctx: &mut LoweringContext, expr: &ast::Expr) -> Result<Expr> {
                         // Check if this is a direct call on an aliased named import
                         // e.g., uuid() where import { v4 as uuid } from 'uuid'
                         if let Some((module_name, Some(method_name))) = ctx.lookup_native_module(func_name) {
+                            let mut args = args;
+                            if module_name == "perry/di" {
+                                di_token_arg(ctx, method_name, call, &mut args);
+                            }
                             return Ok(Expr::NativeMethodCall {
                                 module: module_name.to_string(),
                                 class_name: None,
@@ -4738,41 +7433,7 @@ fn lower_expr(ctx: &mut LoweringContext, expr: &ast::Expr) -> Result<Expr> {
                         }
                     }
 
-                    let callee_expr = lower_expr(ctx, expr)?;
-
-                    // Fill in default arguments if callee is a known function
-                    let mut args = args;
-                    if let Expr::FuncRef(func_id) = &callee_expr {
-                        if let Some((defaults, param_ids)) = ctx.lookup_func_defaults(*func_id) {
-                            let defaults = defaults.to_vec();
-                            let param_ids = param_ids.to_vec();
-                            let num_provided = args.len();
-                            // Build substitution map: callee param LocalId -> actual arg expression
-                            // For provided args, map to the caller's arg expression
-                            // For defaulted args, map to the expanded default (built incrementally)
-                            let mut param_map: Vec<(LocalId, Expr)> = Vec::new();
-                            for i in 0..param_ids.len().min(num_provided) {
-                                param_map.push((param_ids[i], args[i].clone()));
-                            }
-                            // Fill in missing arguments with their defaults, substituting
-                            // any parameter references to use the caller's scope
-                            for i in num_provided..defaults.len() {
-                                if let Some(default_expr) = &defaults[i] {
-                                    let substituted = LoweringContext::substitute_param_refs_in_default(
-                                        default_expr, &param_map
-                                    );
-                                    // Add this expanded default to the map so later defaults
-                                    // can reference it (e.g., c = b where b was also defaulted)
-                                    if i < param_ids.len() {
-                                        param_map.push((param_ids[i], substituted.clone()));
-                                    }
-                                    args.push(substituted);
-                                }
-                            }
-                        }
-                    }
-
-                    let callee = Box::new(callee_expr);
+                    let callee = Box::new(lower_expr(ctx, expr)?);
                     // Extract explicit type arguments if present (e.g., identity<number>(x))
                     let type_args = call.type_args.as_ref()
                         .map(|ta| ta.params.iter()
@@ -4782,9 +7443,9 @@ fn lower_expr(ctx: &mut LoweringContext, expr: &ast::Expr) -> Result<Expr> {
 
                     // Use CallSpread if any argument has spread
                     if let Some(spread_args) = spread_args {
-                        Ok(Expr::CallSpread { callee, args: spread_args, type_args })
+                        Ok(call_spread(callee, spread_args, type_args))
                     } else {
-                        Ok(Expr::Call { callee, args, type_args })
+                        Ok(Expr::Call { callee, args, type_args, span: ctx.span(call.span) })
                     }
                 }
                 ast::Callee::Import(_) => {
@@ -4807,6 +7468,41 @@ fn lower_expr(ctx: &mut LoweringContext, expr: &ast::Expr) -> Result<Expr> {
             }
         }
         ast::Expr::Member(member) => {
+            // Well-known symbols evaluate to the key their members are stored under
+            if let Some(key) = well_known_symbol_key(expr) {
+                return Ok(Expr::String(key.to_string()));
+            }
+            check_known_property(ctx, member);
+
+            // stack.disposed
+            if let (ast::Expr::Ident(obj_ident), ast::MemberProp::Ident(prop_ident)) = (member.obj.as_ref(), &member.prop) {
+                if prop_ident.sym.as_ref() == "disposed" && is_disposable_stack_local(ctx, obj_ident.sym.as_ref()) {
+                    let stack = lower_expr(ctx, &member.obj)?;
+                    return Ok(Expr::DisposableStackDisposed(Box::new(stack)));
+                }
+            }
+
+            // import.meta.url / import.meta.filename / import.meta.dirname
+            if is_import_meta(&member.obj) {
+                if let ast::MemberProp::Ident(prop_ident) = &member.prop {
+                    if let Some(expr) = lower_import_meta_property(ctx, prop_ident.sym.as_ref()) {
+                        return Ok(expr);
+                    }
+                }
+            }
+
+            // lib.foo where `const lib = require('./lib')`, or require('./lib').foo
+            if let ast::MemberProp::Ident(prop_ident) = &member.prop {
+                let source = match member.obj.as_ref() {
+                    ast::Expr::Ident(obj_ident) => ctx.lookup_require_namespace(obj_ident.sym.as_ref()),
+                    obj => require_call_source(ctx, obj),
+                };
+                if let Some(source) = source {
+                    let prop_name = prop_ident.sym.to_string();
+                    return Ok(require_named_import(ctx, &source, &prop_name));
+                }
+            }
+
             // Check if this is process.argv access
             if let ast::Expr::Ident(obj_ident) = member.obj.as_ref() {
                 if obj_ident.sym.as_ref() == "process" {
@@ -4933,6 +7629,10 @@ fn lower_expr(ctx: &mut LoweringContext, expr: &ast::Expr) -> Result<Expr> {
             lower_expr(ctx, &paren.expr)
         }
         ast::Expr::Assign(assign) => {
+            if let ast::AssignTarget::Simple(ast::SimpleAssignTarget::Member(member)) = &assign.left {
+                check_readonly_write(ctx, member, false);
+                check_known_property(ctx, member);
+            }
             let rhs = lower_expr(ctx, &assign.right)?;
 
             // Handle compound assignment operators (+=, -=, *=, /=, etc.)
@@ -5137,7 +7837,7 @@ fn lower_expr(ctx: &mut LoweringContext, expr: &ast::Expr) -> Result<Expr> {
                     .map(|elem| {
                         let expr = lower_expr(ctx, &elem.expr)?;
                         if elem.spread.is_some() {
-                            Ok(ArrayElement::Spread(expr))
+                            Ok(ArrayElement::Spread(iterable_to_array(ctx, &elem.expr, expr)))
                         } else {
                             Ok(ArrayElement::Expr(expr))
                         }
@@ -5160,14 +7860,20 @@ fn lower_expr(ctx: &mut LoweringContext, expr: &ast::Expr) -> Result<Expr> {
                         ast::PropOrSpread::Prop(prop) => {
                             match prop.as_ref() {
                                 ast::Prop::KeyValue(kv) => {
-                                    let key = match &kv.key {
-                                        ast::PropName::Ident(ident) => ident.sym.to_string(),
-                                        ast::PropName::Str(s) => s.value.as_str().unwrap_or("").to_string(),
-                                        _ => return None,
-                                    };
+                                    let key = static_prop_name(&kv.key)?;
                                     let value = lower_expr(ctx, &kv.value).ok()?;
                                     Some(Ok((key, value)))
                                 }
+                                ast::Prop::Method(method) => {
+                                    // { name() {} } / { [Symbol.dispose]() {} } - stored as a function-valued field
+                                    let key = static_prop_name(&method.key)?;
+                                    let fn_expr = ast::Expr::Fn(ast::FnExpr {
+                                        ident: None,
+                                        function: method.function.clone(),
+                                    });
+                                    let value = lower_expr(ctx, &fn_expr).ok()?;
+                                    Some(Ok((key, value)))
+                                }
                                 _ => None,
                             }
                         }
@@ -5182,6 +7888,12 @@ fn lower_expr(ctx: &mut LoweringContext, expr: &ast::Expr) -> Result<Expr> {
             Ok(Expr::This)
         }
         ast::Expr::New(new_expr) => {
+            let fitted = fit_new_args(ctx, new_expr)?;
+            let new_expr = match &fitted {
+                Some(ast::Expr::New(fitted_new)) => fitted_new,
+                Some(iife) => return lower_expr(ctx, iife),
+                None => new_expr,
+            };
             // Try to extract class name from callee
             match new_expr.callee.as_ref() {
                 ast::Expr::Ident(ident) => {
@@ -5196,6 +7908,9 @@ fn lower_expr(ctx: &mut LoweringContext, expr: &ast::Expr) -> Result<Expr> {
                         // new Set() -> create empty set
                         return Ok(Expr::SetNew);
                     }
+                    if class_name == "DisposableStack" {
+                        return Ok(Expr::DisposableStackNew);
+                    }
                     if class_name == "Date" {
                         // new Date() or new Date(timestamp)
                         let args = new_expr.args.as_ref()
@@ -5217,11 +7932,8 @@ fn lower_expr(ctx: &mut LoweringContext, expr: &ast::Expr) -> Result<Expr> {
                             .map(|args| args.iter().map(|a| lower_expr(ctx, &a.expr)).collect::<Result<Vec<_>>>())
                             .transpose()?
                             .unwrap_or_default();
-                        if args.is_empty() {
-                            return Ok(Expr::ErrorNew(None));
-                        } else {
-                            return Ok(Expr::ErrorNew(Some(Box::new(args.into_iter().next().unwrap()))));
-                        }
+                        let message = args.into_iter().next().map(Box::new);
+                        return Ok(Expr::ErrorNew { message, span: ctx.span(new_expr.span) });
                     }
 
                     // Handle URL class
@@ -5276,15 +7988,25 @@ fn lower_expr(ctx: &mut LoweringContext, expr: &ast::Expr) -> Result<Expr> {
                             .map(|t| extract_ts_type_with_ctx(t, Some(ctx)))
                             .collect())
                         .unwrap_or_default();
-                    Ok(Expr::New { class_name, args, type_args })
+                    Ok(Expr::New { class_name, args, type_args, span: ctx.span(new_expr.span) })
                 }
                 // Non-identifier callee (e.g., new (condition ? A : B)() or new someVar())
                 _ => {
-                    let callee = Box::new(lower_expr(ctx, &new_expr.callee)?);
                     let args = new_expr.args.as_ref()
                         .map(|args| args.iter().map(|a| lower_expr(ctx, &a.expr)).collect::<Result<Vec<_>>>())
                         .transpose()?
                         .unwrap_or_default();
+                    // new Temporal.PlainDate(...), new Temporal.Duration(...)
+                    if let Some(method) = temporal_function(ctx, &new_expr.callee).filter(|path| !path.contains('.')) {
+                        return Ok(Expr::NativeMethodCall {
+                            module: "perry/temporal".to_string(),
+                            class_name: None,
+                            object: None,
+                            method,
+                            args,
+                        });
+                    }
+                    let callee = Box::new(lower_expr(ctx, &new_expr.callee)?);
                     Ok(Expr::NewDynamic { callee, args })
                 }
             }
@@ -5308,6 +8030,7 @@ fn lower_expr(ctx: &mut LoweringContext, expr: &ast::Expr) -> Result<Expr> {
                 let is_rest = is_rest_param(param);
                 let param_ty = get_pat_type(param, ctx);
                 let param_id = ctx.define_local(param_name.clone(), param_ty.clone());
+                record_readonly_array(ctx, param_id, pat_type_ann(param), None);
                 params.push(Param {
                     id: param_id,
                     name: param_name,
@@ -5344,6 +8067,8 @@ fn lower_expr(ctx: &mut LoweringContext, expr: &ast::Expr) -> Result<Expr> {
                 new_body.append(&mut body);
                 body = new_body;
             }
+            // Defaults apply before destructuring, so `({ a } = {}) => a` destructures the default
+            let body = with_default_params_prologue(&params, body);
 
             ctx.exit_scope(scope_mark);
 
@@ -5419,6 +8144,7 @@ fn lower_expr(ctx: &mut LoweringContext, expr: &ast::Expr) -> Result<Expr> {
                 let param_default = get_param_default(ctx, &param.pat)?;
                 let is_rest = is_rest_param(&param.pat);
                 let param_id = ctx.define_local(param_name.clone(), Type::Any);
+                record_readonly_array(ctx, param_id, pat_type_ann(&param.pat), None);
                 params.push(Param {
                     id: param_id,
                     name: param_name,
@@ -5452,6 +8178,8 @@ fn lower_expr(ctx: &mut LoweringContext, expr: &ast::Expr) -> Result<Expr> {
                 new_body.append(&mut body);
                 body = new_body;
             }
+            // Defaults apply before destructuring, so `({ a } = {}) => a` destructures the default
+            let body = with_default_params_prologue(&params, body);
 
             ctx.exit_scope(scope_mark);
 
@@ -5545,6 +8273,8 @@ fn lower_expr(ctx: &mut LoweringContext, expr: &ast::Expr) -> Result<Expr> {
                 }
                 // Member expression: this.count++ or obj.prop++ or obj[key]++
                 ast::Expr::Member(member) => {
+                    check_readonly_write(ctx, member, false);
+                    check_known_property(ctx, member);
                     let object = lower_expr(ctx, &member.obj)?;
                     match &member.prop {
                         ast::MemberProp::Ident(ident) => {
@@ -5624,6 +8354,10 @@ fn lower_expr(ctx: &mut LoweringContext, expr: &ast::Expr) -> Result<Expr> {
 
             Ok(result)
         }
+        ast::Expr::TaggedTpl(tagged) => match sql_tag_module(ctx, &tagged.tag) {
+            Some(module) => lower_sql_template(ctx, &module, &tagged.tpl),
+            None => Err(anyhow!("Tagged templates are only supported for `sql` from {}", SQL_TAG_MODULES.join(", "))),
+        },
         ast::Expr::OptChain(opt_chain) => {
             // Optional chaining: obj?.prop or obj?.[index] or obj?.method()
             // Convert to: obj == null ? undefined : obj.prop
@@ -5681,22 +8415,19 @@ fn lower_expr(ctx: &mut LoweringContext, expr: &ast::Expr) -> Result<Expr> {
                         let spread_args: Vec<CallArg> = call.args.iter().zip(args.iter())
                             .map(|(ast_arg, lowered)| {
                                 if ast_arg.spread.is_some() {
-                                    CallArg::Spread(lowered.clone())
+                                    CallArg::Spread(iterable_to_array(ctx, &ast_arg.expr, lowered.clone()))
                                 } else {
                                     CallArg::Expr(lowered.clone())
                                 }
                             })
                             .collect();
-                        Ok(Expr::CallSpread {
-                            callee: Box::new(callee_expr),
-                            args: spread_args,
-                            type_args: Vec::new(),
-                        })
+                        Ok(call_spread(Box::new(callee_expr), spread_args, Vec::new()))
                     } else {
                         Ok(Expr::Call {
                             callee: Box::new(callee_expr),
                             args,
                             type_args: Vec::new(),
+                            span: ctx.span(call.span),
                         })
                     }
                 }
@@ -5743,12 +8474,12 @@ fn lower_expr(ctx: &mut LoweringContext, expr: &ast::Expr) -> Result<Expr> {
             // For now, return a placeholder object that will be handled in property access
             match meta_prop.kind {
                 ast::MetaPropKind::ImportMeta => {
-                    // Return the file:// URL directly for import.meta.url
-                    // Since import.meta is typically accessed via .url, we generate the URL here
-                    let file_url = format!("file://{}", ctx.source_file_path);
-                    Ok(Expr::Object(vec![
-                        ("url".to_string(), Expr::String(file_url)),
-                    ]))
+                    // Bare import.meta (e.g. `const meta = import.meta`) becomes a plain object.
+                    // import.meta.resolve is only supported as a direct call.
+                    let props = ["url", "filename", "dirname"].iter()
+                        .filter_map(|name| lower_import_meta_property(ctx, name).map(|e| (name.to_string(), e)))
+                        .collect();
+                    Ok(Expr::Object(props))
                 }
                 ast::MetaPropKind::NewTarget => {
                     // new.target - not commonly used, return undefined for now
@@ -5760,7 +8491,138 @@ fn lower_expr(ctx: &mut LoweringContext, expr: &ast::Expr) -> Result<Expr> {
     }
 }
 
+/// `Symbol.dispose` / `Symbol.asyncDispose` / `Symbol.iterator` -> the property key their members are stored under
+fn well_known_symbol_key(expr: &ast::Expr) -> Option<&'static str> {
+    if let ast::Expr::Member(member) = expr {
+        if let (ast::Expr::Ident(obj), ast::MemberProp::Ident(prop)) = (member.obj.as_ref(), &member.prop) {
+            if obj.sym.as_ref() == "Symbol" {
+                return match prop.sym.as_ref() {
+                    "dispose" => Some(SYMBOL_DISPOSE_KEY),
+                    "asyncDispose" => Some(SYMBOL_ASYNC_DISPOSE_KEY),
+                    "iterator" => Some(SYMBOL_ITERATOR_KEY),
+                    _ => None,
+                };
+            }
+        }
+    }
+    None
+}
+
+/// Name of a class/object member key known at compile time (identifier, string, well-known symbol)
+fn static_prop_name(key: &ast::PropName) -> Option<String> {
+    match key {
+        ast::PropName::Ident(ident) => Some(ident.sym.to_string()),
+        ast::PropName::Str(s) => Some(s.value.as_str().unwrap_or("").to_string()),
+        ast::PropName::Computed(computed) => well_known_symbol_key(&computed.expr).map(str::to_string),
+        _ => None,
+    }
+}
+
+fn is_import_meta(expr: &ast::Expr) -> bool {
+    matches!(expr, ast::Expr::MetaProp(meta_prop) if matches!(meta_prop.kind, ast::MetaPropKind::ImportMeta))
+}
+
+/// Lower import.meta.url/filename/dirname. Values are baked in as string literals unless
+/// the module was lowered with `import_meta_exe_root`, in which case they are computed at runtime.
+fn lower_import_meta_property(ctx: &LoweringContext, name: &str) -> Option<Expr> {
+    let property = match name {
+        "url" => ImportMetaProperty::Url,
+        "filename" => ImportMetaProperty::Filename,
+        "dirname" => ImportMetaProperty::Dirname,
+        _ => return None,
+    };
+    if let Some(relative_path) = &ctx.import_meta_relative_path {
+        return Some(Expr::ImportMetaRuntime { property, relative_path: relative_path.clone() });
+    }
+    let value = match property {
+        ImportMetaProperty::Url => format!("file://{}", ctx.source_file_path),
+        ImportMetaProperty::Filename => ctx.source_file_path.clone(),
+        ImportMetaProperty::Dirname => std::path::Path::new(&ctx.source_file_path)
+            .parent()
+            .map(|p| p.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+    };
+    Some(Expr::String(value))
+}
+
+fn lower_import_meta_resolve(ctx: &LoweringContext, specifier: Expr) -> Expr {
+    match &ctx.import_meta_relative_path {
+        Some(relative_path) => Expr::ImportMetaResolve {
+            module_path: relative_path.clone(),
+            exe_relative: true,
+            specifier: Box::new(specifier),
+        },
+        None => Expr::ImportMetaResolve {
+            module_path: ctx.source_file_path.clone(),
+            exe_relative: false,
+            specifier: Box::new(specifier),
+        },
+    }
+}
+
 /// Unescape template literal strings (handle \n, \t, etc.)
+/// Database modules exporting a `sql` tag whose interpolations become bound parameters
+const SQL_TAG_MODULES: &[&str] = &["pg", "mysql2", "mysql2/promise"];
+
+/// The database module a tagged template's tag is `sql` from: imported by name,
+/// or `pg.sql` on a default or namespace import
+fn sql_tag_module(ctx: &LoweringContext, tag: &ast::Expr) -> Option<String> {
+    let (module, name) = match tag {
+        ast::Expr::Ident(ident) => {
+            let (module, imported) = ctx.lookup_native_module(ident.sym.as_ref())?;
+            (module, imported?)
+        }
+        ast::Expr::Member(member) => {
+            let (ast::Expr::Ident(obj), ast::MemberProp::Ident(prop)) = (member.obj.as_ref(), &member.prop) else {
+                return None;
+            };
+            let (module, None) = ctx.lookup_native_module(obj.sym.as_ref())? else { return None };
+            (module, prop.sym.as_ref())
+        }
+        _ => return None,
+    };
+    (name == "sql" && SQL_TAG_MODULES.contains(&module)).then(|| module.to_string())
+}
+
+/// sql`SELECT * FROM users WHERE id = ${id}` -> { text, values }: the text gets a
+/// placeholder per interpolation (`$1`, `$2`, ... for pg, `?` for mysql2) and the
+/// interpolated expressions become the values to bind. This is pg's query config shape.
+fn lower_sql_template(ctx: &mut LoweringContext, module: &str, tpl: &ast::Tpl) -> Result<Expr> {
+    let mut text = String::new();
+    let mut values = Vec::with_capacity(tpl.exprs.len());
+    for (i, quasi) in tpl.quasis.iter().enumerate() {
+        text.push_str(&unescape_template(quasi.raw.as_ref()));
+        if let Some(expr) = tpl.exprs.get(i) {
+            values.push(lower_expr(ctx, expr)?);
+            match module {
+                "pg" => text.push_str(&format!("${}", values.len())),
+                _ => text.push('?'),
+            }
+        }
+    }
+    Ok(Expr::Object(vec![
+        ("text".to_string(), Expr::String(text)),
+        ("values".to_string(), Expr::Array(values)),
+    ]))
+}
+
+/// `query({ text, values })`, as a `sql` template lowers to, passes the text and the
+/// values as separate arguments so they reach the driver's parameter binding
+fn spread_query_config(args: &mut Vec<Expr>) {
+    let [Expr::Object(fields)] = args.as_mut_slice() else { return };
+    if fields.len() != 2 {
+        return;
+    }
+    if fields[0].0 == "values" {
+        fields.swap(0, 1);
+    }
+    if fields[0].0 != "text" || fields[1].0 != "values" {
+        return;
+    }
+    let fields = std::mem::take(fields);
+    *args = fields.into_iter().map(|(_, value)| value).collect();
+}
+
 fn unescape_template(s: &str) -> String {
     let mut result = String::with_capacity(s.len());
     let mut chars = s.chars().peekable();
@@ -6078,86 +8940,1322 @@ fn generate_param_destructuring_stmts(
                             init: Some(prop_expr),
                         });
                     }
-                    ast::ObjectPatProp::Rest(_) => {
-                        // Rest pattern: {...rest} - skip for now
+                    ast::ObjectPatProp::Rest(_) => {
+                        // Rest pattern: {...rest} - skip for now
+                    }
+                }
+            }
+        }
+        _ => {}
+    }
+
+    Ok(stmts)
+}
+
+/// Check if a pattern is a destructuring pattern (array or object)
+fn is_destructuring_pattern(pat: &ast::Pat) -> bool {
+    matches!(pat, ast::Pat::Array(_) | ast::Pat::Object(_))
+}
+
+/// Detect if an expression represents a native handle instance (Big, Decimal, etc.)
+/// Returns the module name if it does.
+fn detect_native_instance_expr(expr: &ast::Expr) -> Option<&'static str> {
+    match expr {
+        // new Big(...) / new Decimal(...) / new BigNumber(...)
+        ast::Expr::New(new_expr) => {
+            if let ast::Expr::Ident(ident) = new_expr.callee.as_ref() {
+                match ident.sym.as_ref() {
+                    "Big" => Some("big.js"),
+                    "Decimal" => Some("decimal.js"),
+                    "BigNumber" => Some("bignumber.js"),
+                    "LRUCache" => Some("lru-cache"),
+                    "Command" => Some("commander"),
+                    _ => None,
+                }
+            } else {
+                None
+            }
+        }
+        // Chained method calls: new Big(...).plus(...).div(...)
+        ast::Expr::Call(call_expr) => {
+            if let ast::Callee::Expr(callee_expr) = &call_expr.callee {
+                if let ast::Expr::Member(member) = callee_expr.as_ref() {
+                    // Recursively check the object
+                    detect_native_instance_expr(&member.obj)
+                } else {
+                    None
+                }
+            } else {
+                None
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Check if a parameter pattern is a rest parameter (...args)
+fn is_rest_param(pat: &ast::Pat) -> bool {
+    matches!(pat, ast::Pat::Rest(_))
+}
+
+/// How many parameters a callee declares before its rest parameter, and whether it has one
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct ParamShape {
+    fixed: usize,
+    rest: bool,
+}
+
+/// Parameter shape of a parameter list; `None` stands for a constructor parameter property
+fn param_shape<'a>(pats: impl IntoIterator<Item = Option<&'a ast::Pat>>) -> ParamShape {
+    let mut shape = ParamShape::default();
+    for pat in pats {
+        if pat.is_some_and(is_rest_param) {
+            shape.rest = true;
+            break;
+        }
+        shape.fixed += 1;
+    }
+    shape
+}
+
+/// Extract default value from a parameter pattern (if any)
+/// For optional parameters (x?: Type), we provide Expr::Undefined as the default
+fn get_param_default(ctx: &mut LoweringContext, pat: &ast::Pat) -> Result<Option<Expr>> {
+    match pat {
+        ast::Pat::Ident(ident) => {
+            // Check if this is an optional parameter (x?: Type)
+            if ident.optional {
+                Ok(Some(Expr::Undefined))
+            } else {
+                Ok(None)
+            }
+        }
+        ast::Pat::Assign(assign) => {
+            let default_expr = lower_expr(ctx, &assign.right)?;
+            Ok(Some(default_expr))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Prepend the default-parameter prologue to a function body: for each parameter with
+/// a default, `if (param === undefined) param = default;` in declaration order. Defaults
+/// run in the callee, so they see earlier parameters and the callee's own scope and
+/// apply however the function is called (directly, through a closure or a callback).
+fn with_default_params_prologue(params: &[Param], body: Vec<Stmt>) -> Vec<Stmt> {
+    let mut stmts: Vec<Stmt> = params.iter()
+        .filter_map(|p| match &p.default {
+            // Optional parameters (`x?: T`) default to undefined, which a missing argument already is
+            None | Some(Expr::Undefined) => None,
+            Some(default) => Some(Stmt::If {
+                condition: Expr::Compare {
+                    op: CompareOp::Eq,
+                    left: Box::new(Expr::LocalGet(p.id)),
+                    right: Box::new(Expr::Undefined),
+                },
+                then_branch: vec![Stmt::Expr(Expr::LocalSet(p.id, Box::new(default.clone())))],
+                else_branch: None,
+            }),
+        })
+        .collect();
+    stmts.extend(body);
+    stmts
+}
+
+/// Export an existing module-level binding: `export { local as exported }`
+/// Local holding the value of `export default <expression>`
+const DEFAULT_EXPORT_LOCAL: &str = "__default";
+
+fn export_local_binding(ctx: &LoweringContext, module: &mut Module, local: &str, exported: &str) {
+    module.exports.push(Export::Named { local: local.to_string(), exported: exported.to_string() });
+
+    // If the local name refers to a function, add it to exported_functions
+    // so that a wrapper function is generated for cross-module calls
+    if let Some(func_id) = ctx.lookup_func(local) {
+        module.exported_functions.push((exported.to_string(), func_id));
+    }
+
+    // Module-level variables get a cross-module global, whatever their initializer
+    let is_variable = module.init.iter().any(|stmt| {
+        matches!(stmt, Stmt::Let { name, init: Some(_), .. } if name == local)
+    });
+    if is_variable && !module.exported_objects.iter().any(|name| name == exported) {
+        module.exported_objects.push(exported.to_string());
+    }
+}
+
+/// Name of a type element key (`a`, `"a"`, `[Symbol.iterator]`)
+fn type_element_key(key: &ast::Expr) -> Option<String> {
+    match key {
+        ast::Expr::Ident(id) => Some(id.sym.to_string()),
+        ast::Expr::Lit(ast::Lit::Str(s)) => Some(s.value.as_str().unwrap_or("").to_string()),
+        _ => well_known_symbol_key(key).map(str::to_string),
+    }
+}
+
+/// Key type of a mapped type, an indexed access or `Pick`/`Omit`/`Record`, keeping string
+/// and number literals
+fn extract_key_type(ts_type: &ast::TsType, ctx: Option<&LoweringContext>) -> Type {
+    match ts_type {
+        ast::TsType::TsUnionOrIntersectionType(ast::TsUnionOrIntersectionType::TsUnionType(union)) => {
+            Type::Union(union.types.iter().map(|member| extract_key_type(member, ctx)).collect())
+        }
+        ast::TsType::TsParenthesizedType(paren) => extract_key_type(&paren.type_ann, ctx),
+        _ => extract_ts_type_with_ctx(ts_type, ctx),
+    }
+}
+
+/// Evaluate `{ [K in Keys]: V }` to an object type: a homomorphic mapping when the keys
+/// are `keyof T` of a known `T`, or a mapping over literal, `string` or `number` keys.
+/// Key remapping (`as`) and mappings over unknown keys stay unevaluated.
+fn extract_mapped_type(mapped: &ast::TsMappedType, ctx: Option<&LoweringContext>) -> Option<ObjectType> {
+    if mapped.name_type.is_some() {
+        return None;
+    }
+    let edit = |modifier: Option<ast::TruePlusMinus>| match modifier {
+        None => ModifierEdit::Keep,
+        Some(ast::TruePlusMinus::True | ast::TruePlusMinus::Plus) => ModifierEdit::Add,
+        Some(ast::TruePlusMinus::Minus) => ModifierEdit::Remove,
+    };
+    let (optional, readonly) = (edit(mapped.optional), edit(mapped.readonly));
+    let keys = mapped.type_param.constraint.as_deref()?;
+    let key_param = mapped.type_param.name.sym.as_ref();
+    if let ast::TsType::TsTypeOperator(ast::TsTypeOperator { op: ast::TsTypeOperatorOp::KeyOf, type_ann: source, .. }) = keys {
+        // `T[K]` keeps each property's type; anything else replaces it
+        let value = match mapped.type_ann.as_deref() {
+            Some(ast::TsType::TsIndexedAccessType(access)) if matches!(
+                access.index_type.as_ref(),
+                ast::TsType::TsTypeRef(ast::TsTypeRef { type_name: ast::TsEntityName::Ident(ident), .. }) if &*ident.sym == key_param
+            ) => None,
+            Some(value) => Some(extract_ts_type_with_ctx(value, ctx)),
+            None => Some(Type::Any),
+        };
+        let source = ctx?.object_shape(&extract_ts_type_with_ctx(source, ctx))?;
+        return Some(source.map_properties(value.as_ref(), optional, readonly));
+    }
+    let value = mapped.type_ann.as_deref().map(|value| extract_ts_type_with_ctx(value, ctx)).unwrap_or(Type::Any);
+    ObjectType::from_keys(&extract_key_type(keys, ctx), &value, optional, readonly)
+}
+
+/// Literal type of an enum member's value
+fn enum_literal(value: &EnumValue) -> LiteralType {
+    match value {
+        EnumValue::Number(n) => LiteralType::Number(*n as f64),
+        EnumValue::String(s) => LiteralType::String(s.clone()),
+    }
+}
+
+fn literal_type(lit: &ast::TsLit) -> Option<LiteralType> {
+    match lit {
+        ast::TsLit::Str(s) => Some(LiteralType::String(s.value.as_str()?.to_string())),
+        ast::TsLit::Number(n) => Some(LiteralType::Number(n.value)),
+        ast::TsLit::Bool(b) => Some(LiteralType::Boolean(b.value)),
+        _ => None,
+    }
+}
+
+/// Properties of a type literal or interface body. Getters without a setter are readonly.
+fn extract_object_type(members: &[ast::TsTypeElement], ctx: Option<&LoweringContext>) -> ObjectType {
+    let mut object = ObjectType::default();
+    let setters: HashSet<String> = members.iter()
+        .filter_map(|member| match member {
+            ast::TsTypeElement::TsSetterSignature(setter) => type_element_key(&setter.key),
+            _ => None,
+        })
+        .collect();
+    for member in members {
+        match member {
+            ast::TsTypeElement::TsPropertySignature(prop) => {
+                let Some(name) = type_element_key(&prop.key) else { continue };
+                let ty = prop.type_ann.as_ref()
+                    .map(|ann| extract_ts_type_with_ctx(&ann.type_ann, ctx))
+                    .unwrap_or(Type::Any);
+                object.properties.insert(name, PropertyInfo { ty, optional: prop.optional, readonly: prop.readonly });
+            }
+            ast::TsTypeElement::TsGetterSignature(getter) => {
+                let Some(name) = type_element_key(&getter.key) else { continue };
+                let ty = getter.type_ann.as_ref()
+                    .map(|ann| extract_ts_type_with_ctx(&ann.type_ann, ctx))
+                    .unwrap_or(Type::Any);
+                let readonly = !setters.contains(&name);
+                object.properties.insert(name, PropertyInfo { ty, optional: false, readonly });
+            }
+            ast::TsTypeElement::TsMethodSignature(method) => {
+                let Some(name) = type_element_key(&method.key) else { continue };
+                let params = method.params.iter()
+                    .map(|p| {
+                        let (name, ty) = get_fn_param_name_and_type_with_ctx(p, ctx);
+                        (name, ty, false)
+                    })
+                    .collect();
+                let return_type = method.type_ann.as_ref()
+                    .map(|ann| extract_ts_type_with_ctx(&ann.type_ann, ctx))
+                    .unwrap_or(Type::Any);
+                let ty = Type::Function(perry_types::FunctionType {
+                    params,
+                    return_type: Box::new(return_type),
+                    is_async: false,
+                    is_generator: false,
+                });
+                object.properties.insert(name, PropertyInfo { ty, optional: method.optional, readonly: false });
+            }
+            ast::TsTypeElement::TsIndexSignature(index) => {
+                object.index_signature = index.type_ann.as_ref()
+                    .map(|ann| Box::new(extract_ts_type_with_ctx(&ann.type_ann, ctx)));
+            }
+            _ => {}
+        }
+    }
+    object
+}
+
+/// Register the declared signatures of the functions and classes a module imports from
+/// packages with typings, so references to them lower to typed `ExternFuncRef`s.
+/// Classes register their constructor.
+fn register_declared_imports(
+    ctx: &mut LoweringContext,
+    ast_module: &ast::Module,
+    declarations: &BTreeMap<String, PackageDeclarations>,
+) {
+    for item in &ast_module.body {
+        let ast::ModuleItem::ModuleDecl(ast::ModuleDecl::Import(import)) = item else { continue };
+        if import.type_only {
+            continue;
+        }
+        let Some(package) = declarations.get(import.src.value.as_str().unwrap_or("")) else { continue };
+        for spec in &import.specifiers {
+            // Extern types are looked up by the name references resolve to: the exported
+            // name for named imports, the local name for default imports
+            let (export, name) = match spec {
+                ast::ImportSpecifier::Named(named) if !named.is_type_only => {
+                    let imported = named.imported.as_ref().map(|i| match i {
+                        ast::ModuleExportName::Ident(id) => id.sym.to_string(),
+                        ast::ModuleExportName::Str(s) => s.value.as_str().unwrap_or("").to_string(),
+                    }).unwrap_or_else(|| named.local.sym.to_string());
+                    (imported.clone(), imported)
+                }
+                ast::ImportSpecifier::Default(default) => ("default".to_string(), default.local.sym.to_string()),
+                _ => continue,
+            };
+            if let Some(sig) = package.functions.get(&export) {
+                ctx.register_extern_func_types(name, sig.params.clone(), sig.return_type.clone());
+            } else if let Some(class) = package.classes.get(&export) {
+                let instance = Type::Named(name.clone());
+                ctx.register_extern_func_types(name, class.constructor.clone(), instance);
+            }
+        }
+    }
+}
+
+/// Record the shape and method return types of an interface declaration, merged with
+/// earlier declarations of the same interface
+fn collect_interface_shape(ctx: &mut LoweringContext, iface: &ast::TsInterfaceDecl) {
+    let name = iface.id.sym.to_string();
+    let extends = iface.extends.iter()
+        .filter_map(|parent| match parent.expr.as_ref() {
+            ast::Expr::Ident(ident) => Some(ident.sym.to_string()),
+            _ => None,
+        })
+        .collect();
+    let mut shape = extract_object_type(&iface.body.body, None);
+    shape.name = Some(name.clone());
+    collect_readonly_signatures(ctx, &name, &iface.body.body);
+    let methods = iface.body.body.iter()
+        .filter_map(|member| match member {
+            ast::TsTypeElement::TsMethodSignature(method) => {
+                let ty = match method.type_ann.as_ref().map(|ann| ann.type_ann.as_ref()) {
+                    Some(ast::TsType::TsThisType(_)) => Type::Named(name.clone()),
+                    Some(ty) => extract_ts_type(ty),
+                    None => Type::Any,
+                };
+                type_element_key(&method.key).map(|key| (key, ty))
+            }
+            _ => None,
+        })
+        .collect();
+    ctx.declared_methods.push((name.clone(), methods));
+    // Interfaces merge across declarations
+    if let Some((_, parents, existing)) = ctx.declared_shapes.iter_mut().find(|(n, _, _)| *n == name) {
+        parents.extend(extends);
+        existing.properties.extend(shape.properties);
+    } else {
+        ctx.declared_shapes.push((name, extends, shape));
+    }
+}
+
+/// Interfaces a `declare global { ... }` or `declare module "..." { ... }` block augments.
+/// They merge with this module's declarations of the same name; interfaces in a plain
+/// `namespace` are qualified by it and don't.
+fn augmented_interfaces(module_decl: &ast::TsModuleDecl) -> Vec<&ast::TsInterfaceDecl> {
+    let is_augmentation = module_decl.global || matches!(module_decl.id, ast::TsModuleName::Str(_));
+    let Some(ast::TsNamespaceBody::TsModuleBlock(block)) = &module_decl.body else { return Vec::new() };
+    if !is_augmentation {
+        return Vec::new();
+    }
+    block.body.iter()
+        .filter_map(|item| match item {
+            ast::ModuleItem::Stmt(ast::Stmt::Decl(ast::Decl::TsInterface(iface))) => Some(iface.as_ref()),
+            ast::ModuleItem::ModuleDecl(ast::ModuleDecl::ExportDecl(ast::ExportDecl { decl: ast::Decl::TsInterface(iface), .. })) => Some(iface.as_ref()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Warn about a non-ambient namespace that declares values: only the types of namespaces
+/// take part in lowering, so its functions, variables and classes would silently be missing
+/// Report a construct there is no lowering for. It is left out of the module, which
+/// `perry compile` refuses unless given `--permissive`.
+fn report_dropped(ctx: &mut LoweringContext, span: swc_common::Span, what: &str, help: &str) {
+    let span = ctx.span(span);
+    ctx.diagnostics.push(
+        Diagnostic::new(DiagnosticCode::DroppedConstruct, format!("{} is not supported and would be left out of the program", what))
+            .with_span(span)
+            .with_help(help)
+            .build(),
+    );
+}
+
+fn check_namespace_values(ctx: &mut LoweringContext, module_decl: &ast::TsModuleDecl) {
+    if module_decl.declare || module_decl.global {
+        return;
+    }
+    let Some(ast::TsNamespaceBody::TsModuleBlock(block)) = &module_decl.body else { return };
+    let is_type = |decl: &ast::Decl| matches!(decl, ast::Decl::TsInterface(_) | ast::Decl::TsTypeAlias(_));
+    let has_values = block.body.iter().any(|item| match item {
+        ast::ModuleItem::Stmt(ast::Stmt::Decl(decl)) => !is_type(decl),
+        ast::ModuleItem::ModuleDecl(ast::ModuleDecl::ExportDecl(export)) => !is_type(&export.decl),
+        ast::ModuleItem::Stmt(ast::Stmt::Empty(_)) => false,
+        _ => true,
+    });
+    if !has_values {
+        return;
+    }
+    let name = match &module_decl.id {
+        ast::TsModuleName::Ident(ident) => ident.sym.to_string(),
+        ast::TsModuleName::Str(s) => s.value.as_str().unwrap_or("").to_string(),
+    };
+    let span = Span::new(ctx.file_id, module_decl.span.lo.0.saturating_sub(1), module_decl.span.hi.0.saturating_sub(1));
+    ctx.diagnostics.push(
+        Diagnostic::warning(DiagnosticCode::UnsupportedFeature, format!("Values declared in namespace '{}' are not compiled", name))
+            .with_span(span)
+            .with_help("move the namespace's functions and variables to module scope, or into an exported object")
+            .build(),
+    );
+}
+
+/// Record the object shapes of top-level classes, interfaces and object type aliases
+/// (see `LoweringContext::declared_shapes`)
+fn collect_declared_shapes(ctx: &mut LoweringContext, ast_module: &ast::Module) {
+    // `as const` values first, so type aliases can refer to them with `typeof`
+    for item in &ast_module.body {
+        let var_decl = match item {
+            ast::ModuleItem::Stmt(ast::Stmt::Decl(ast::Decl::Var(var_decl))) => var_decl,
+            ast::ModuleItem::ModuleDecl(ast::ModuleDecl::ExportDecl(ast::ExportDecl { decl: ast::Decl::Var(var_decl), .. })) => var_decl,
+            _ => continue,
+        };
+        if var_decl.kind != ast::VarDeclKind::Const {
+            continue;
+        }
+        for decl in &var_decl.decls {
+            if let (ast::Pat::Ident(binding), Some(init)) = (&decl.name, decl.init.as_deref()) {
+                if let ast::Expr::TsConstAssertion(assertion) = unwrap_parens(init) {
+                    let ty = const_assertion_type(ctx, &assertion.expr);
+                    ctx.const_values.push((binding.id.sym.to_string(), ty));
+                }
+            }
+        }
+    }
+
+    // Mapped type aliases are evaluated once the shapes they map are known
+    let mut mapped_aliases = Vec::new();
+    for item in &ast_module.body {
+        let decl = match item {
+            ast::ModuleItem::Stmt(ast::Stmt::Decl(decl)) => decl,
+            ast::ModuleItem::ModuleDecl(ast::ModuleDecl::ExportDecl(export)) => &export.decl,
+            ast::ModuleItem::ModuleDecl(ast::ModuleDecl::ExportDefaultDecl(export)) => {
+                if let ast::DefaultDecl::Class(class_expr) = &export.decl {
+                    if let Some(ident) = &class_expr.ident {
+                        collect_class_shape(ctx, ident.sym.as_ref(), &class_expr.class);
+                    }
+                }
+                continue;
+            }
+            _ => continue,
+        };
+        match decl {
+            ast::Decl::Class(class_decl) => {
+                collect_class_shape(ctx, class_decl.ident.sym.as_ref(), &class_decl.class);
+            }
+            ast::Decl::TsInterface(iface) => collect_interface_shape(ctx, iface),
+            ast::Decl::TsModule(module_decl) => {
+                for iface in augmented_interfaces(module_decl) {
+                    collect_interface_shape(ctx, iface);
+                }
+            }
+            ast::Decl::TsTypeAlias(alias) => {
+                let name = alias.id.sym.to_string();
+                let (extends, mut shape) = match alias.type_ann.as_ref() {
+                    ast::TsType::TsTypeLit(lit) => {
+                        collect_readonly_signatures(ctx, &name, &lit.members);
+                        (Vec::new(), extract_object_type(&lit.members, None))
+                    }
+                    ast::TsType::TsTypeRef(type_ref) if type_ref.type_params.is_none() => match &type_ref.type_name {
+                        ast::TsEntityName::Ident(ident) => (vec![ident.sym.to_string()], ObjectType::default()),
+                        _ => continue,
+                    },
+                    ast::TsType::TsUnionOrIntersectionType(ast::TsUnionOrIntersectionType::TsUnionType(union)) => {
+                        let members = union.types.iter().map(|member| extract_ts_type_with_ctx(member, Some(&*ctx))).collect();
+                        ctx.declared_unions.push((name, members));
+                        continue;
+                    }
+                    // `A & B & { c: C }` extends its named members and declares the literal ones
+                    ast::TsType::TsUnionOrIntersectionType(ast::TsUnionOrIntersectionType::TsIntersectionType(intersection)) => {
+                        let mut extends = Vec::new();
+                        let mut shape = ObjectType::default();
+                        for member in &intersection.types {
+                            match member.as_ref() {
+                                ast::TsType::TsTypeLit(lit) => {
+                                    collect_readonly_signatures(ctx, &name, &lit.members);
+                                    shape.merge(extract_object_type(&lit.members, None));
+                                }
+                                ast::TsType::TsTypeRef(ast::TsTypeRef { type_name: ast::TsEntityName::Ident(ident), type_params: None, .. }) => {
+                                    extends.push(ident.sym.to_string());
+                                }
+                                _ => {}
+                            }
+                        }
+                        (extends, shape)
+                    }
+                    ast::TsType::TsMappedType(_) if alias.type_params.is_none() => {
+                        mapped_aliases.push((name, alias.type_ann.as_ref()));
+                        continue;
+                    }
+                    // `typeof SIZES[number]` of an `as const` array: a union of its literals
+                    ast::TsType::TsIndexedAccessType(_) if alias.type_params.is_none() => {
+                        if let Type::Union(members) = extract_ts_type_with_ctx(&alias.type_ann, Some(&*ctx)) {
+                            ctx.declared_unions.push((name, members));
+                        }
+                        continue;
+                    }
+                    ast::TsType::TsConditionalType(_) => {
+                        let type_params = alias.type_params.as_ref()
+                            .map(|tp| extract_type_params(tp))
+                            .unwrap_or_default();
+                        ctx.enter_type_param_scope(&type_params);
+                        let body = extract_ts_type_with_ctx(&alias.type_ann, Some(ctx));
+                        ctx.exit_type_param_scope();
+                        ctx.conditional_aliases.push((name, type_params.into_iter().map(|p| p.name).collect(), body));
+                        continue;
+                    }
+                    ast::TsType::TsTypeRef(ast::TsTypeRef { type_name: ast::TsEntityName::Ident(ident), .. })
+                        if alias.type_params.is_none() && mapped::MAPPED_UTILITIES.contains(&ident.sym.as_ref()) =>
+                    {
+                        mapped_aliases.push((name, alias.type_ann.as_ref()));
+                        continue;
+                    }
+                    _ => continue,
+                };
+                shape.name = Some(name.clone());
+                ctx.declared_shapes.push((name, extends, shape));
+            }
+            _ => {}
+        }
+    }
+    for (name, ty) in mapped_aliases {
+        let Some(mut shape) = ctx.object_shape(&extract_ts_type_with_ctx(ty, Some(ctx))) else { continue };
+        shape.name = Some(name.clone());
+        ctx.declared_shapes.push((name, Vec::new(), shape));
+    }
+}
+
+/// Readonly property signatures start with their `readonly` modifier (no other modifiers apply)
+fn collect_readonly_signatures(ctx: &mut LoweringContext, type_name: &str, members: &[ast::TsTypeElement]) {
+    for member in members {
+        if let ast::TsTypeElement::TsPropertySignature(prop) = member {
+            if let (true, Some(name)) = (prop.readonly, type_element_key(&prop.key)) {
+                let lo = prop.span.lo.0;
+                ctx.readonly_modifiers.push((type_name.to_string(), name, lo, lo + "readonly ".len() as u32));
+            }
+        }
+    }
+}
+
+fn collect_class_shape(ctx: &mut LoweringContext, name: &str, class: &ast::Class) {
+    let extends = match class.super_class.as_deref() {
+        Some(ast::Expr::Ident(ident)) => vec![ident.sym.to_string()],
+        _ => Vec::new(),
+    };
+    let mut shape = ObjectType { name: Some(name.to_string()), ..ObjectType::default() };
+    let mut getters = Vec::new();
+    let mut setters = HashSet::new();
+    let mut methods = Vec::new();
+    for member in &class.body {
+        if let ast::ClassMember::Method(method) = member {
+            if let (ast::MethodKind::Method, Some(method_name)) = (method.kind, static_prop_name(&method.key)) {
+                let shape = param_shape(method.function.params.iter().map(|param| Some(&param.pat)));
+                ctx.member_params.push((name.to_string(), method_name, method.is_static, shape));
+            }
+        }
+        match member {
+            ast::ClassMember::ClassProp(prop) if !prop.is_static => {
+                let Some(prop_name) = static_prop_name(&prop.key) else { continue };
+                // A readonly property initialized with a literal keeps the literal as its type
+                let literal_init = match prop.value.as_deref() {
+                    Some(value) if prop.readonly => case_literal(ctx, value).map(Type::Literal),
+                    _ => None,
+                };
+                let ty = prop.type_ann.as_ref()
+                    .map(|ann| extract_ts_type_with_ctx(&ann.type_ann, None))
+                    .or(literal_init)
+                    .unwrap_or(Type::Any);
+                // With no other modifiers the property starts with `readonly`
+                if prop.readonly && prop.decorators.is_empty() && prop.accessibility.is_none()
+                    && !prop.is_override && !prop.is_abstract && !prop.declare {
+                    let lo = prop.span.lo.0;
+                    ctx.readonly_modifiers.push((name.to_string(), prop_name.clone(), lo, lo + "readonly ".len() as u32));
+                }
+                shape.properties.insert(prop_name, PropertyInfo { ty, optional: prop.is_optional, readonly: prop.readonly });
+            }
+            ast::ClassMember::Constructor(ctor) => {
+                let shape = param_shape(ctor.params.iter().map(|param| match param {
+                    ast::ParamOrTsParamProp::Param(param) => Some(&param.pat),
+                    ast::ParamOrTsParamProp::TsParamProp(_) => None,
+                }));
+                ctx.member_params.push((name.to_string(), "constructor".to_string(), false, shape));
+                for param in &ctor.params {
+                    let ast::ParamOrTsParamProp::TsParamProp(param_prop) = param else { continue };
+                    let ast::TsParamPropParam::Ident(ident) = &param_prop.param else { continue };
+                    let prop_name = ident.id.sym.to_string();
+                    let ty = ident.type_ann.as_ref()
+                        .map(|ann| extract_ts_type_with_ctx(&ann.type_ann, None))
+                        .unwrap_or(Type::Any);
+                    if param_prop.readonly && param_prop.decorators.is_empty()
+                        && param_prop.accessibility.is_none() && !param_prop.is_override {
+                        let lo = param_prop.span.lo.0;
+                        ctx.readonly_modifiers.push((name.to_string(), prop_name.clone(), lo, lo + "readonly ".len() as u32));
+                    }
+                    shape.properties.insert(prop_name, PropertyInfo { ty, optional: false, readonly: param_prop.readonly });
+                }
+            }
+            ast::ClassMember::Method(method) if !method.is_static => {
+                let Some(prop_name) = static_prop_name(&method.key) else { continue };
+                match method.kind {
+                    ast::MethodKind::Getter => getters.push((prop_name, method)),
+                    ast::MethodKind::Setter => {
+                        setters.insert(prop_name);
                     }
+                    ast::MethodKind::Method => methods.push((prop_name, class_method_return_type(name, &method.function))),
                 }
             }
+            _ => {}
         }
-        _ => {}
     }
+    // Accessors without a setter are readonly
+    for (prop_name, getter) in getters {
+        let ty = getter.function.return_type.as_ref()
+            .map(|ann| extract_ts_type(&ann.type_ann))
+            .unwrap_or(Type::Any);
+        let readonly = !setters.contains(&prop_name);
+        shape.properties.insert(prop_name, PropertyInfo { ty, optional: false, readonly });
+    }
+    ctx.declared_shapes.push((name.to_string(), extends, shape));
+    ctx.declared_methods.push((name.to_string(), methods));
+}
 
-    Ok(stmts)
+/// Return type of a class method. `this`, whether annotated or an unannotated
+/// `return this`, resolves to the class; an unannotated `return new C()` to `C`
+fn class_method_return_type(class_name: &str, function: &ast::Function) -> Type {
+    if let Some(ann) = &function.return_type {
+        return match ann.type_ann.as_ref() {
+            ast::TsType::TsThisType(_) => Type::Named(class_name.to_string()),
+            ty => extract_ts_type(ty),
+        };
+    }
+    let returned = function.body.as_ref().and_then(|body| {
+        body.stmts.iter().find_map(|stmt| match stmt {
+            ast::Stmt::Return(ast::ReturnStmt { arg: Some(arg), .. }) => Some(unwrap_parens(arg)),
+            _ => None,
+        })
+    });
+    match returned {
+        Some(ast::Expr::This(_)) => Type::Named(class_name.to_string()),
+        Some(ast::Expr::New(new_expr)) => match new_expr.callee.as_ref() {
+            ast::Expr::Ident(ident) => Type::Named(ident.sym.to_string()),
+            _ => Type::Any,
+        },
+        _ => Type::Any,
+    }
 }
 
-/// Check if a pattern is a destructuring pattern (array or object)
-fn is_destructuring_pattern(pat: &ast::Pat) -> bool {
-    matches!(pat, ast::Pat::Array(_) | ast::Pat::Object(_))
+/// Type of an `as const` object literal: every property readonly, nested objects included
+/// Type of an `as const` expression: literals keep their literal types, array literals
+/// become tuples and the properties of object literals become readonly
+fn const_assertion_type(ctx: &LoweringContext, expr: &ast::Expr) -> Type {
+    match unwrap_parens(expr) {
+        ast::Expr::Object(obj) => Type::Object(const_object_type(ctx, obj)),
+        // Spreads and holes leave the tuple's length unknown
+        ast::Expr::Array(arr) => arr.elems.iter()
+            .map(|elem| match elem {
+                Some(ast::ExprOrSpread { spread: None, expr }) => Some(const_assertion_type(ctx, expr)),
+                _ => None,
+            })
+            .collect::<Option<Vec<_>>>()
+            .map_or(Type::Any, Type::Tuple),
+        ast::Expr::TsConstAssertion(assertion) => const_assertion_type(ctx, &assertion.expr),
+        expr => case_literal(ctx, expr).map_or(Type::Any, Type::Literal),
+    }
 }
 
-/// Detect if an expression represents a native handle instance (Big, Decimal, etc.)
-/// Returns the module name if it does.
-fn detect_native_instance_expr(expr: &ast::Expr) -> Option<&'static str> {
+fn const_object_type(ctx: &LoweringContext, obj: &ast::ObjectLit) -> ObjectType {
+    let mut object = ObjectType::default();
+    for prop in &obj.props {
+        let ast::PropOrSpread::Prop(prop) = prop else { continue };
+        let (name, ty) = match prop.as_ref() {
+            ast::Prop::KeyValue(kv) => {
+                let Some(name) = static_prop_name(&kv.key) else { continue };
+                (name, const_assertion_type(ctx, &kv.value))
+            }
+            ast::Prop::Shorthand(ident) => (ident.sym.to_string(), Type::Any),
+            _ => continue,
+        };
+        object.properties.insert(name, PropertyInfo { ty, optional: false, readonly: true });
+    }
+    object
+}
+
+fn unwrap_parens(expr: &ast::Expr) -> &ast::Expr {
     match expr {
-        // new Big(...) / new Decimal(...) / new BigNumber(...)
+        ast::Expr::Paren(paren) => unwrap_parens(&paren.expr),
+        _ => expr,
+    }
+}
+
+/// Remember the shape of a local declared without a type annotation when its initializer
+/// tells us: `new C(...)` for a class of this module, an `as const` object or array literal,
+/// or a setTimeout/setInterval timer
+fn record_inferred_shape(ctx: &mut LoweringContext, id: LocalId, ty: &Type, init: &ast::Expr) {
+    if !matches!(ty, Type::Any) {
+        return;
+    }
+    match unwrap_parens(init) {
         ast::Expr::New(new_expr) => {
-            if let ast::Expr::Ident(ident) = new_expr.callee.as_ref() {
-                match ident.sym.as_ref() {
-                    "Big" => Some("big.js"),
-                    "Decimal" => Some("decimal.js"),
-                    "BigNumber" => Some("bignumber.js"),
-                    "LRUCache" => Some("lru-cache"),
-                    "Command" => Some("commander"),
-                    _ => None,
+            if let ast::Expr::Ident(class_ident) = new_expr.callee.as_ref() {
+                let class_name = class_ident.sym.to_string();
+                if ctx.declared_shapes.iter().any(|(n, _, _)| *n == class_name) {
+                    ctx.inferred_shapes.push((id, Type::Named(class_name), None));
                 }
-            } else {
-                None
             }
         }
-        // Chained method calls: new Big(...).plus(...).div(...)
-        ast::Expr::Call(call_expr) => {
-            if let ast::Callee::Expr(callee_expr) = &call_expr.callee {
-                if let ast::Expr::Member(member) = callee_expr.as_ref() {
-                    // Recursively check the object
-                    detect_native_instance_expr(&member.obj)
-                } else {
-                    None
-                }
-            } else {
-                None
+        // `const t = setTimeout(callback, ms)`, so `t.unref()` reaches the runtime
+        ast::Expr::Call(_) if is_timer_call(ctx, init) => {
+            ctx.inferred_shapes.push((id, Type::Named("Timeout".to_string()), None));
+        }
+        ast::Expr::TsConstAssertion(assertion) => {
+            if let ast::Expr::Object(_) | ast::Expr::Array(_) = unwrap_parens(&assertion.expr) {
+                let remove = (assertion.expr.span().hi.0, assertion.span.hi.0);
+                let ty = const_assertion_type(ctx, &assertion.expr);
+                ctx.inferred_shapes.push((id, ty, Some(remove)));
+            }
+        }
+        // Object literals with a `[Symbol.iterator]` member, so for-of and spread treat them as iterable
+        ast::Expr::Object(obj) => {
+            let is_iterable = obj.props.iter().any(|prop| match prop {
+                ast::PropOrSpread::Prop(prop) => match prop.as_ref() {
+                    ast::Prop::KeyValue(kv) => static_prop_name(&kv.key).as_deref() == Some(SYMBOL_ITERATOR_KEY),
+                    ast::Prop::Method(method) => static_prop_name(&method.key).as_deref() == Some(SYMBOL_ITERATOR_KEY),
+                    _ => false,
+                },
+                ast::PropOrSpread::Spread(_) => false,
+            });
+            if is_iterable {
+                let mut shape = ObjectType::default();
+                shape.properties.insert(
+                    SYMBOL_ITERATOR_KEY.to_string(),
+                    PropertyInfo { ty: Type::Any, optional: false, readonly: false },
+                );
+                ctx.inferred_shapes.push((id, Type::Object(shape), None));
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Static type of an expression as far as readonly checks need it
+fn static_type_of(ctx: &LoweringContext, expr: &ast::Expr) -> Option<Type> {
+    match expr {
+        ast::Expr::Ident(ident) => {
+            let name = ident.sym.as_ref();
+            let id = ctx.lookup_local(name)?;
+            ctx.inferred_shapes.iter()
+                .find(|(local, _, _)| *local == id)
+                .map(|(_, ty, _)| ty.clone())
+                .or_else(|| ctx.lookup_local_type(name).cloned())
+        }
+        ast::Expr::This(_) => ctx.current_class.clone().map(Type::Named),
+        ast::Expr::Paren(paren) => static_type_of(ctx, &paren.expr),
+        ast::Expr::TsNonNull(non_null) => static_type_of(ctx, &non_null.expr),
+        ast::Expr::TsAs(ts_as) => Some(extract_ts_type_with_ctx(&ts_as.type_ann, Some(ctx))),
+        ast::Expr::TsConstAssertion(assertion) => Some(const_assertion_type(ctx, &assertion.expr)),
+        ast::Expr::Member(member) => {
+            let object_ty = static_type_of(ctx, &member.obj)?;
+            // Element of a tuple (`as const` arrays): `t[0]`
+            if let (Type::Tuple(elems), ast::MemberProp::Computed(computed)) = (&object_ty, &member.prop) {
+                let ast::Expr::Lit(ast::Lit::Num(index)) = computed.expr.as_ref() else { return None };
+                return elems.get(index.value as usize).map(|ty| match ty {
+                    Type::Literal(literal) => literal.widened(),
+                    ty => ty.clone(),
+                });
             }
+            let property = member_prop_name(&member.prop)?;
+            ctx.lookup_property(&object_ty, &property).map(|(info, _)| match info.ty {
+                Type::Literal(literal) => literal.widened(),
+                ty => ty,
+            })
         }
         _ => None,
     }
 }
 
-/// Check if a parameter pattern is a rest parameter (...args)
-fn is_rest_param(pat: &ast::Pat) -> bool {
-    matches!(pat, ast::Pat::Rest(_))
+/// Property name of `obj.name` / `obj["name"]`
+/// Functions and constructors of the global `Temporal` that perry/temporal implements,
+/// by their path below `Temporal`
+const TEMPORAL_FUNCTIONS: &[&str] = &[
+    "PlainDate",
+    "PlainDate.from",
+    "PlainDate.compare",
+    "Duration",
+    "Duration.from",
+    "Duration.compare",
+    "ZonedDateTime.from",
+    "ZonedDateTime.compare",
+    "Now.zonedDateTimeISO",
+    "Now.plainDateISO",
+    "Now.timeZoneId",
+];
+
+/// Path below the global `Temporal` of a callee like `Temporal.PlainDate.from`, when
+/// perry/temporal implements it and no local shadows `Temporal`
+fn temporal_function(ctx: &LoweringContext, callee: &ast::Expr) -> Option<String> {
+    let mut path = Vec::new();
+    let mut expr = unwrap_parens(callee);
+    while let ast::Expr::Member(member) = expr {
+        path.push(member_prop_name(&member.prop)?);
+        expr = unwrap_parens(&member.obj);
+    }
+    match expr {
+        ast::Expr::Ident(ident) if ident.sym.as_ref() == "Temporal" && ctx.lookup_local("Temporal").is_none() => {}
+        _ => return None,
+    }
+    path.reverse();
+    let path = path.join(".");
+    TEMPORAL_FUNCTIONS.contains(&path.as_str()).then_some(path)
 }
 
-/// Extract default value from a parameter pattern (if any)
-/// For optional parameters (x?: Type), we provide Expr::Undefined as the default
-fn get_param_default(ctx: &mut LoweringContext, pat: &ast::Pat) -> Result<Option<Expr>> {
+fn member_prop_name(prop: &ast::MemberProp) -> Option<String> {
+    match prop {
+        ast::MemberProp::Ident(ident) => Some(ident.sym.to_string()),
+        ast::MemberProp::Computed(computed) => match computed.expr.as_ref() {
+            ast::Expr::Lit(ast::Lit::Str(s)) => Some(s.value.as_str().unwrap_or("").to_string()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Report a write to a readonly property: `obj.p = v`, `obj.p += v`, `obj.p++` or `delete obj.p`.
+/// A class's constructor may assign its own readonly fields through `this`.
+fn check_readonly_write(ctx: &mut LoweringContext, member: &ast::MemberExpr, is_delete: bool) {
+    let is_element = match &member.prop {
+        ast::MemberProp::Computed(_) => true,
+        ast::MemberProp::Ident(ident) => ident.sym.as_ref() == "length",
+        _ => false,
+    };
+    if is_element {
+        if let Some(origin) = readonly_array_origin(ctx, &member.obj) {
+            let array = expr_path(&member.obj).unwrap_or_else(|| "array".to_string());
+            let message = match (&member.prop, is_delete) {
+                (ast::MemberProp::Ident(_), _) => format!("Cannot change the length of '{}' because it is a readonly array", array),
+                (_, true) => format!("The operand of a 'delete' operator cannot be an element of readonly array '{}'", array),
+                (_, false) => format!("Cannot assign to an element of '{}' because it is a readonly array", array),
+            };
+            report_readonly_array(ctx, member.span, &array, origin, message);
+            return;
+        }
+    }
+    let Some(property) = member_prop_name(&member.prop) else { return };
+    let Some(object_ty) = static_type_of(ctx, &member.obj) else { return };
+    let Some((info, owner)) = ctx.lookup_property(&object_ty, &property) else { return };
+    if !info.readonly {
+        return;
+    }
+    let is_this = matches!(unwrap_parens(&member.obj), ast::Expr::This(_));
+    if is_this && ctx.in_constructor && owner.is_some() && owner == ctx.current_class {
+        return;
+    }
+
+    let file_id = ctx.file_id;
+    let to_span = |lo: u32, hi: u32| Span::new(file_id, lo.saturating_sub(1), hi.saturating_sub(1));
+    let member_span = to_span(member.span.lo.0, member.span.hi.0);
+    let message = if is_delete {
+        format!("The operand of a 'delete' operator cannot be a read-only property ('{}')", property)
+    } else {
+        format!("Cannot assign to '{}' because it is a read-only property", property)
+    };
+    let mut diagnostic = Diagnostic::error(DiagnosticCode::ReadonlyAssignment, message).with_span(member_span);
+
+    // Property (at any depth) of an `as const` object: the fix is to drop the assertion
+    let mut root = unwrap_parens(&member.obj);
+    while let ast::Expr::Member(inner) = root {
+        root = unwrap_parens(&inner.obj);
+    }
+    let const_assertion = match root {
+        ast::Expr::Ident(ident) if owner.is_none() => ctx.lookup_local(ident.sym.as_ref()).and_then(|id| {
+            ctx.inferred_shapes.iter()
+                .find(|(local, _, _)| *local == id)
+                .and_then(|(_, _, remove)| *remove)
+        }),
+        _ => None,
+    };
+    if let Some((lo, hi)) = const_assertion {
+        diagnostic = diagnostic
+            .with_primary_label(member_span, "property of an `as const` object")
+            .with_help("`as const` makes every property readonly; create an updated copy with spread syntax instead")
+            .with_suggestion(Suggestion::new("remove `as const`", to_span(lo, hi), "", Applicability::MaybeIncorrect));
+    } else {
+        let declared_in = owner.as_deref().map(|name| format!(" in '{}'", name)).unwrap_or_default();
+        diagnostic = diagnostic
+            .with_primary_label(member_span, format!("'{}' is declared readonly{}", property, declared_in))
+            .with_help("create an updated copy with spread syntax, or remove `readonly` if the property is meant to change");
+        let modifier = owner.as_ref().and_then(|owner| {
+            ctx.readonly_modifiers.iter().find(|(ty, prop, _, _)| ty == owner && *prop == property)
+        });
+        if let Some((_, _, lo, hi)) = modifier {
+            diagnostic = diagnostic.with_suggestion(Suggestion::new(
+                format!("remove `readonly` from '{}'{}", property, declared_in),
+                to_span(*lo, *hi),
+                "",
+                Applicability::MaybeIncorrect,
+            ));
+        }
+    }
+    ctx.diagnostics.push(diagnostic.build());
+}
+
+/// Members every object inherits from `Object.prototype`
+const OBJECT_PROTOTYPE_MEMBERS: &[&str] = &[
+    "constructor", "hasOwnProperty", "isPrototypeOf", "propertyIsEnumerable", "toLocaleString", "toString", "valueOf",
+];
+
+/// Declared type of an expression: a local's annotation, or a property of one. Unlike
+/// `static_type_of`, shapes inferred from initializers don't count.
+fn declared_type_of(ctx: &LoweringContext, expr: &ast::Expr) -> Option<Type> {
+    match expr {
+        ast::Expr::Ident(ident) => ctx.lookup_local_type(ident.sym.as_ref()).cloned(),
+        ast::Expr::Paren(paren) => declared_type_of(ctx, &paren.expr),
+        ast::Expr::TsNonNull(non_null) => declared_type_of(ctx, &non_null.expr),
+        ast::Expr::Member(member) => {
+            let object_ty = declared_type_of(ctx, &member.obj)?;
+            let property = member_prop_name(&member.prop)?;
+            ctx.lookup_property(&object_ty, &property).map(|(info, _)| info.ty)
+        }
+        _ => None,
+    }
+}
+
+/// Report a read or write of a property that the object's declared type literal
+/// (`opts: { port: number; host?: string }`) doesn't have. Types with an index signature
+/// accept any key.
+fn check_known_property(ctx: &mut LoweringContext, member: &ast::MemberExpr) {
+    let ast::MemberProp::Ident(ident) = &member.prop else { return };
+    let property = ident.sym.as_ref();
+    if OBJECT_PROTOTYPE_MEMBERS.contains(&property) {
+        return;
+    }
+    let Some(object_ty @ Type::Object(object)) = declared_type_of(ctx, &member.obj) else { return };
+    if object.name.is_some() || object.index_signature.is_some() || object.properties.contains_key(property) {
+        return;
+    }
+    let span = Span::new(ctx.file_id, ident.span.lo.0.saturating_sub(1), ident.span.hi.0.saturating_sub(1));
+    // Reads of a member being assigned are lowered more than once
+    if ctx.diagnostics.iter().any(|d| d.code == DiagnosticCode::UnknownProperty && d.span == span) {
+        return;
+    }
+    let mut diagnostic = Diagnostic::error(
+        DiagnosticCode::UnknownProperty,
+        format!("Property '{}' does not exist on type '{}'", property, object_ty),
+    )
+    .with_span(span)
+    .with_primary_label(span, "not declared in the type literal");
+    let mut known: Vec<&str> = object.properties.keys().map(String::as_str).collect();
+    known.sort_unstable();
+    if let Some(closest) = closest_name(property, &known) {
+        diagnostic = diagnostic
+            .with_help(format!("did you mean '{}'?", closest))
+            .with_suggestion(Suggestion::new(format!("use '{}'", closest), span, closest, Applicability::MaybeIncorrect));
+    } else {
+        diagnostic = diagnostic.with_help("declare the property in the type literal, or add an index signature");
+    }
+    ctx.diagnostics.push(diagnostic.build());
+}
+
+/// Where a local's array or tuple gets its readonly-ness, which decides the fix offered
+/// for writes to it. Spans are raw BytePos.
+#[derive(Debug, Clone, Copy)]
+enum ReadonlyOrigin {
+    /// `[...] as const`; the span covers ` as const`
+    ConstAssertion(u32, u32),
+    /// A `readonly T[]` or `readonly [A, B]` annotation; the span covers `readonly `
+    Modifier(u32, u32),
+    /// A `ReadonlyArray<T>` annotation
+    ReadonlyArray,
+}
+
+/// Array methods that modify the array in place
+const MUTATING_ARRAY_METHODS: &[&str] = &["push", "pop", "shift", "unshift", "splice", "sort", "reverse", "fill", "copyWithin"];
+
+/// Type annotation of a parameter or binding pattern
+fn pat_type_ann(pat: &ast::Pat) -> Option<&ast::TsTypeAnn> {
     match pat {
-        ast::Pat::Ident(ident) => {
-            // Check if this is an optional parameter (x?: Type)
-            if ident.optional {
-                Ok(Some(Expr::Undefined))
-            } else {
-                Ok(None)
+        ast::Pat::Ident(ident) => ident.type_ann.as_deref(),
+        ast::Pat::Assign(assign) => pat_type_ann(&assign.left),
+        ast::Pat::Rest(rest) => rest.type_ann.as_deref(),
+        _ => None,
+    }
+}
+
+/// Readonly-ness of an array type annotation
+fn readonly_array_annotation(ty: &ast::TsType) -> Option<ReadonlyOrigin> {
+    match ty {
+        ast::TsType::TsTypeOperator(op) if matches!(op.op, ast::TsTypeOperatorOp::ReadOnly) => {
+            let lo = op.span.lo.0;
+            Some(ReadonlyOrigin::Modifier(lo, lo + "readonly ".len() as u32))
+        }
+        ast::TsType::TsTypeRef(ast::TsTypeRef { type_name: ast::TsEntityName::Ident(ident), .. })
+            if ident.sym.as_ref() == "ReadonlyArray" =>
+        {
+            Some(ReadonlyOrigin::ReadonlyArray)
+        }
+        ast::TsType::TsParenthesizedType(paren) => readonly_array_annotation(&paren.type_ann),
+        _ => None,
+    }
+}
+
+/// Remember that a local holds a readonly array, from its annotation or an `as const`
+/// array literal initializer (see `LoweringContext::readonly_arrays`)
+fn record_readonly_array(ctx: &mut LoweringContext, id: LocalId, type_ann: Option<&ast::TsTypeAnn>, init: Option<&ast::Expr>) {
+    let origin = match (type_ann, init.map(unwrap_parens)) {
+        (Some(ann), _) => readonly_array_annotation(&ann.type_ann),
+        (None, Some(ast::Expr::TsConstAssertion(assertion))) if matches!(unwrap_parens(&assertion.expr), ast::Expr::Array(_)) => {
+            Some(ReadonlyOrigin::ConstAssertion(assertion.expr.span().hi.0, assertion.span.hi.0))
+        }
+        _ => None,
+    };
+    if let Some(origin) = origin {
+        ctx.readonly_arrays.push((id, origin));
+    }
+}
+
+/// Readonly-ness of the array an expression evaluates to: a local recorded in
+/// `readonly_arrays`, or a tuple nested in an `as const` value (`config.sizes`)
+fn readonly_array_origin(ctx: &LoweringContext, expr: &ast::Expr) -> Option<ReadonlyOrigin> {
+    match unwrap_parens(expr) {
+        ast::Expr::Ident(ident) => {
+            let id = ctx.lookup_local(ident.sym.as_ref())?;
+            ctx.readonly_arrays.iter().rev().find(|(local, _)| *local == id).map(|(_, origin)| *origin)
+        }
+        ast::Expr::Member(member) => {
+            let Some(Type::Tuple(_)) = static_type_of(ctx, expr) else { return None };
+            let mut root = unwrap_parens(&member.obj);
+            while let ast::Expr::Member(inner) = root {
+                root = unwrap_parens(&inner.obj);
+            }
+            let ast::Expr::Ident(ident) = root else { return None };
+            let id = ctx.lookup_local(ident.sym.as_ref())?;
+            let (_, _, remove) = ctx.inferred_shapes.iter().find(|(local, _, _)| *local == id)?;
+            remove.map(|(lo, hi)| ReadonlyOrigin::ConstAssertion(lo, hi))
+        }
+        _ => None,
+    }
+}
+
+/// Report a call of a mutating method on a readonly array: `items.push(x)`
+fn check_readonly_array_call(ctx: &mut LoweringContext, callee: &ast::MemberExpr) {
+    let Some(method) = member_prop_name(&callee.prop) else { return };
+    if !MUTATING_ARRAY_METHODS.contains(&method.as_str()) {
+        return;
+    }
+    let Some(origin) = readonly_array_origin(ctx, &callee.obj) else { return };
+    let array = expr_path(&callee.obj).unwrap_or_else(|| "array".to_string());
+    let message = format!("Cannot call '{}' on '{}' because it is a readonly array", method, array);
+    report_readonly_array(ctx, callee.span, &array, origin, message);
+}
+
+fn report_readonly_array(ctx: &mut LoweringContext, span: swc_common::Span, array: &str, origin: ReadonlyOrigin, message: String) {
+    let file_id = ctx.file_id;
+    let to_span = |lo: u32, hi: u32| Span::new(file_id, lo.saturating_sub(1), hi.saturating_sub(1));
+    let member_span = to_span(span.lo.0, span.hi.0);
+    let mut diagnostic = Diagnostic::error(DiagnosticCode::ReadonlyAssignment, message).with_span(member_span);
+    diagnostic = match origin {
+        ReadonlyOrigin::ConstAssertion(lo, hi) => diagnostic
+            .with_primary_label(member_span, "element of an `as const` array")
+            .with_help("`as const` makes the array readonly; create an updated copy with spread syntax instead")
+            .with_suggestion(Suggestion::new("remove `as const`", to_span(lo, hi), "", Applicability::MaybeIncorrect)),
+        ReadonlyOrigin::Modifier(lo, hi) => diagnostic
+            .with_primary_label(member_span, format!("'{}' is declared as a readonly array", array))
+            .with_help("create an updated copy with spread syntax, or remove `readonly` if the array is meant to change")
+            .with_suggestion(Suggestion::new(
+                format!("remove `readonly` from the type of '{}'", array),
+                to_span(lo, hi),
+                "",
+                Applicability::MaybeIncorrect,
+            )),
+        ReadonlyOrigin::ReadonlyArray => diagnostic
+            .with_primary_label(member_span, format!("'{}' is declared as a ReadonlyArray", array))
+            .with_help("create an updated copy with spread syntax, or declare it as an `Array` if it is meant to change"),
+    };
+    ctx.diagnostics.push(diagnostic.build());
+}
+
+/// Built-in Node.js modules that are handled specially by the compiler
+const BUILTIN_MODULES: &[&str] = &["fs", "path", "crypto"];
+
+/// If `expr` is `require("<literal>")`, return the module source (without a "node:" prefix).
+/// Returns None when `require` is shadowed by a local binding.
+fn require_call_source(ctx: &LoweringContext, expr: &ast::Expr) -> Option<String> {
+    match expr {
+        ast::Expr::Call(call) => require_call_expr_source(ctx, call),
+        _ => None,
+    }
+}
+
+fn require_call_expr_source(ctx: &LoweringContext, call: &ast::CallExpr) -> Option<String> {
+    // Addons aren't modules to import but values loaded at runtime (see `native_addon_path`)
+    require_call_literal(ctx, call).filter(|source| !source.ends_with(".node"))
+}
+
+fn require_call_literal(ctx: &LoweringContext, call: &ast::CallExpr) -> Option<String> {
+    if let ast::Callee::Expr(callee_expr) = &call.callee {
+        if let ast::Expr::Ident(ident) = callee_expr.as_ref() {
+            if ident.sym.as_ref() == "require" && ctx.lookup_local("require").is_none() && ctx.lookup_func("require").is_none() && call.args.len() == 1 {
+                if let ast::Expr::Lit(ast::Lit::Str(s)) = &*call.args[0].expr {
+                    let source = s.value.as_str().unwrap_or("");
+                    return Some(source.strip_prefix("node:").unwrap_or(source).to_string());
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Path of an N-API addon for a `.node` import or require() specifier. Relative specifiers
+/// resolve against the importing file's directory; others are left to the runtime, which
+/// also looks next to the executable.
+fn native_addon_path(ctx: &LoweringContext, source: &str) -> Option<String> {
+    if !source.ends_with(".node") {
+        return None;
+    }
+    if source.starts_with("./") || source.starts_with("../") {
+        if let Some(dir) = std::path::Path::new(&ctx.source_file_path).parent() {
+            return Some(dir.join(source).to_string_lossy().to_string());
+        }
+    }
+    Some(source.to_string())
+}
+
+/// Bind `local` to export `imported` of a require()'d module, like `import { imported as local }`
+fn register_require_named(ctx: &mut LoweringContext, source: &str, imported: &str, local: &str) {
+    if is_native_module(source) {
+        ctx.register_native_module(local.to_string(), source.to_string(), Some(imported.to_string()));
+    } else {
+        ctx.register_imported_func(local.to_string(), imported.to_string());
+    }
+    ctx.register_require_import(source, Some(ImportSpecifier::Named {
+        imported: imported.to_string(),
+        local: local.to_string(),
+    }));
+}
+
+/// Reference export `imported` of a require()'d module (e.g. `lib.foo` after `const lib = require('./lib')`).
+/// Nothing in the module is named after the export, so the import's local name is a generated one
+/// that can't clash with the module's own bindings.
+fn require_named_import(ctx: &mut LoweringContext, source: &str, imported: &str) -> Expr {
+    ctx.register_require_import(source, Some(ImportSpecifier::Named {
+        imported: imported.to_string(),
+        local: format!("__require_{}", imported),
+    }));
+    Expr::ExternFuncRef {
+        name: imported.to_string(),
+        param_types: Vec::new(),
+        return_type: Type::Any,
+    }
+}
+
+/// Bind `name` to a require()'d module as a whole, like `import * as name`
+fn bind_require_namespace(ctx: &mut LoweringContext, name: &str, source: &str) {
+    if is_native_module(source) {
+        ctx.register_native_module(name.to_string(), source.to_string(), None);
+        ctx.register_require_import(source, Some(ImportSpecifier::Namespace { local: name.to_string() }));
+    } else {
+        ctx.register_require_namespace(name.to_string(), source.to_string());
+        ctx.register_require_import(source, None);
+    }
+}
+
+/// Handle `const name = require('...')` and `const name = require('...').prop`.
+/// Returns true if the declaration was a require() binding (no variable is emitted).
+fn lower_require_binding(ctx: &mut LoweringContext, name: &str, init: &ast::Expr) -> bool {
+    // const lib = require('./lib') - like import * as lib from './lib'
+    if let Some(source) = require_call_source(ctx, init) {
+        bind_require_namespace(ctx, name, &source);
+        return true;
+    }
+
+    // const foo = require('./lib').foo - like import { foo } from './lib'
+    if let ast::Expr::Member(member) = init {
+        if let ast::MemberProp::Ident(prop_ident) = &member.prop {
+            if let Some(source) = require_call_source(ctx, &member.obj) {
+                register_require_named(ctx, &source, prop_ident.sym.as_ref(), name);
+                return true;
+            }
+        }
+    }
+    false
+}
+
+/// Bindings of `const { a, b: c } = require(...)` as (imported, local) pairs.
+/// Returns None for patterns an import can't express (defaults, nesting, rest).
+fn require_destructure_bindings(obj_pat: &ast::ObjectPat) -> Option<Vec<(String, String)>> {
+    let mut bindings = Vec::new();
+    for prop in &obj_pat.props {
+        match prop {
+            ast::ObjectPatProp::KeyValue(kv) => {
+                let key = match &kv.key {
+                    ast::PropName::Ident(ident) => ident.sym.to_string(),
+                    ast::PropName::Str(s) => s.value.as_str().unwrap_or("").to_string(),
+                    _ => return None,
+                };
+                match kv.value.as_ref() {
+                    ast::Pat::Ident(binding) => bindings.push((key, binding.id.sym.to_string())),
+                    _ => return None,
+                }
+            }
+            ast::ObjectPatProp::Assign(assign) if assign.value.is_none() => {
+                let name = assign.key.sym.to_string();
+                bindings.push((name.clone(), name));
+            }
+            _ => return None,
+        }
+    }
+    Some(bindings)
+}
+
+/// Is `member` the CommonJS `module.exports` object?
+fn is_module_exports(ctx: &LoweringContext, member: &ast::MemberExpr) -> bool {
+    if let (ast::Expr::Ident(obj), ast::MemberProp::Ident(prop)) = (member.obj.as_ref(), &member.prop) {
+        return obj.sym.as_ref() == "module" && prop.sym.as_ref() == "exports" && ctx.lookup_local("module").is_none();
+    }
+    false
+}
+
+/// Lower a top-level CommonJS export assignment:
+/// - `exports.foo = value` / `module.exports.foo = value` exports `foo`
+/// - `module.exports = { a, b: value }` exports each property
+/// - `module.exports = value` exports `value` as the default export
+///
+/// Returns false if the assignment is not a CommonJS export.
+fn lower_commonjs_export(ctx: &mut LoweringContext, module: &mut Module, assign: &ast::AssignExpr) -> Result<bool> {
+    if assign.op != ast::AssignOp::Assign {
+        return Ok(false);
+    }
+    let member = match &assign.left {
+        ast::AssignTarget::Simple(ast::SimpleAssignTarget::Member(member)) => member,
+        _ => return Ok(false),
+    };
+
+    // module.exports = ...
+    if is_module_exports(ctx, member) {
+        match assign.right.as_ref() {
+            ast::Expr::Object(obj_lit) => {
+                for prop in &obj_lit.props {
+                    if let ast::PropOrSpread::Prop(prop) = prop {
+                        match prop.as_ref() {
+                            ast::Prop::Shorthand(ident) => {
+                                let name = ident.sym.to_string();
+                                export_commonjs_value(ctx, module, &name, &ast::Expr::Ident(ident.clone()))?;
+                            }
+                            ast::Prop::KeyValue(kv) => {
+                                let key = match &kv.key {
+                                    ast::PropName::Ident(ident) => ident.sym.to_string(),
+                                    ast::PropName::Str(s) => s.value.as_str().unwrap_or("").to_string(),
+                                    _ => continue,
+                                };
+                                export_commonjs_value(ctx, module, &key, &kv.value)?;
+                            }
+                            ast::Prop::Method(method) => {
+                                if let ast::PropName::Ident(ident) = &method.key {
+                                    let fn_expr = ast::Expr::Fn(ast::FnExpr {
+                                        ident: None,
+                                        function: method.function.clone(),
+                                    });
+                                    export_commonjs_value(ctx, module, ident.sym.as_ref(), &fn_expr)?;
+                                }
+                            }
+                            _ => {}
+                        }
+                    }
+                }
             }
+            value => export_commonjs_value(ctx, module, "default", value)?,
         }
-        ast::Pat::Assign(assign) => {
-            let default_expr = lower_expr(ctx, &assign.right)?;
-            Ok(Some(default_expr))
-        }
-        _ => Ok(None),
+        return Ok(true);
+    }
+
+    // exports.foo = ... / module.exports.foo = ...
+    let is_exports_obj = match member.obj.as_ref() {
+        ast::Expr::Ident(obj) => obj.sym.as_ref() == "exports" && ctx.lookup_local("exports").is_none(),
+        ast::Expr::Member(obj) => is_module_exports(ctx, obj),
+        _ => false,
+    };
+    if let (true, ast::MemberProp::Ident(prop)) = (is_exports_obj, &member.prop) {
+        export_commonjs_value(ctx, module, prop.sym.as_ref(), &assign.right)?;
+        return Ok(true);
     }
+    Ok(false)
 }
 
-/// Built-in Node.js modules that are handled specially by the compiler
-const BUILTIN_MODULES: &[&str] = &["fs", "path", "crypto"];
+/// Export `value` under `exported`: existing bindings are exported directly, other
+/// expressions become a module-level variable with a generated name (so it can't shadow
+/// the module's own bindings). Assigning the same export again updates that variable.
+fn export_commonjs_value(ctx: &mut LoweringContext, module: &mut Module, exported: &str, value: &ast::Expr) -> Result<()> {
+    if exported != "default" && !ctx.commonjs_exports.iter().any(|name| name == exported) {
+        ctx.commonjs_exports.push(exported.to_string());
+    }
+    if let ast::Expr::Ident(ident) = value {
+        let local = ident.sym.to_string();
+        if ctx.lookup_local(&local).is_some() || ctx.lookup_func(&local).is_some() {
+            export_local_binding(ctx, module, &local, exported);
+            return Ok(());
+        }
+    }
+
+    let init = lower_expr(ctx, value)?;
+    let local = format!("__exports_{}", exported);
+    let existing = module.exports.iter().any(|e| matches!(e, Export::Named { local: l, .. } if *l == local));
+    if let (true, Some(id)) = (existing, ctx.lookup_local(&local)) {
+        module.init.push(Stmt::Expr(Expr::LocalSet(id, Box::new(init))));
+        return Ok(());
+    }
+    let id = ctx.define_local(local.clone(), Type::Any);
+    module.init.push(Stmt::Let {
+        id,
+        name: local.clone(),
+        ty: Type::Any,
+        mutable: true,
+        init: Some(init),
+    });
+    export_local_binding(ctx, module, &local, exported);
+    Ok(())
+}
 
 /// Check if an expression is a require() call for a built-in module.
 /// Returns the module name if it is, None otherwise.
@@ -6793,8 +10891,34 @@ fn lower_var_decl_with_destructuring(
                                     base: class_name.to_string(),
                                     type_args,
                                 };
-                            } else if class_name == "URLSearchParams" {
-                                ty = Type::Named("URLSearchParams".to_string());
+                            } else if class_name == "URLSearchParams" || class_name == "DisposableStack" {
+                                ty = Type::Named(class_name.to_string());
+                            }
+                        }
+                    }
+                    // Enum member access: Color.Red is a number, Dir.Up a string
+                    if let ast::Expr::Member(member_expr) = init_expr.as_ref() {
+                        if let (ast::Expr::Ident(obj_ident), ast::MemberProp::Ident(prop_ident)) = (member_expr.obj.as_ref(), &member_expr.prop) {
+                            match ctx.lookup_enum_member(obj_ident.sym.as_ref(), prop_ident.sym.as_ref()) {
+                                Some(EnumValue::Number(_)) => ty = Type::Number,
+                                Some(EnumValue::String(_)) => ty = Type::String,
+                                None => {}
+                            }
+                        }
+                    }
+                    // stack.move() returns a new DisposableStack; stack.use(x) / stack.adopt(x, f) return x
+                    if let ast::Expr::Call(call_expr) = init_expr.as_ref() {
+                        if let ast::Callee::Expr(callee_expr) = &call_expr.callee {
+                            if let ast::Expr::Member(member_expr) = callee_expr.as_ref() {
+                                if let (ast::Expr::Ident(obj_ident), ast::MemberProp::Ident(method_ident)) = (member_expr.obj.as_ref(), &member_expr.prop) {
+                                    if is_disposable_stack_local(ctx, obj_ident.sym.as_ref()) {
+                                        match (method_ident.sym.as_ref(), call_expr.args.first()) {
+                                            ("move", _) => ty = Type::Named("DisposableStack".to_string()),
+                                            ("use" | "adopt", Some(arg)) => ty = disposable_value_type(ctx, &arg.expr),
+                                            _ => {}
+                                        }
+                                    }
+                                }
                             }
                         }
                     }
@@ -6913,6 +11037,10 @@ fn lower_var_decl_with_destructuring(
                     // Don't emit a variable declaration - the module is handled specially
                     return Ok(result);
                 }
+                // require() of a project file or package binds like an ESM import
+                if lower_require_binding(ctx, &name, init_expr) {
+                    return Ok(result);
+                }
             }
 
             // Check if this is calling toString() on URLSearchParams - returns String
@@ -7020,7 +11148,15 @@ fn lower_var_decl_with_destructuring(
             }
 
             let init = decl.init.as_ref().map(|e| lower_expr(ctx, e)).transpose()?;
+            // Track read streams so fetch() can upload them in chunks
+            if matches!(init, Some(Expr::FsCreateReadStream(_))) {
+                ctx.register_native_instance(name.clone(), "fs".to_string(), "ReadStream".to_string());
+            }
             let id = ctx.define_local(name.clone(), ty.clone());
+            if let Some(init_expr) = &decl.init {
+                record_inferred_shape(ctx, id, &ty, init_expr);
+            }
+            record_readonly_array(ctx, id, ident.type_ann.as_deref(), decl.init.as_deref());
             result.push(Stmt::Let {
                 id,
                 name,
@@ -7126,6 +11262,16 @@ fn lower_var_decl_with_destructuring(
             }
         }
         ast::Pat::Object(obj_pat) => {
+            // const { a, b: c } = require('./lib') binds like import { a, b as c } from './lib'
+            if let Some(source) = decl.init.as_ref().and_then(|e| require_call_source(ctx, e)) {
+                if let Some(bindings) = require_destructure_bindings(obj_pat) {
+                    for (imported, local) in bindings {
+                        register_require_named(ctx, &source, &imported, &local);
+                    }
+                    return Ok(result);
+                }
+            }
+
             // Object destructuring: const { a, b, c } = expr
             // Desugar to:
             //   let __tmp = expr;
@@ -7369,6 +11515,9 @@ fn collect_local_refs_expr(expr: &Expr, refs: &mut Vec<LocalId>) {
         Expr::InstanceOf { expr, .. } => {
             collect_local_refs_expr(expr, refs);
         }
+        Expr::Narrowed { value, .. } => {
+            collect_local_refs_expr(value, refs);
+        }
         Expr::In { property, object } => {
             collect_local_refs_expr(property, refs);
             collect_local_refs_expr(object, refs);
@@ -7410,6 +11559,9 @@ fn collect_local_refs_expr(expr: &Expr, refs: &mut Vec<LocalId>) {
             collect_local_refs_expr(path, refs);
             collect_local_refs_expr(content, refs);
         }
+        Expr::FsCreateReadStream(path) => {
+            collect_local_refs_expr(path, refs);
+        }
         // Path operations
         Expr::PathJoin(a, b) => {
             collect_local_refs_expr(a, refs);
@@ -7418,6 +11570,43 @@ fn collect_local_refs_expr(expr: &Expr, refs: &mut Vec<LocalId>) {
         Expr::PathDirname(path) | Expr::PathBasename(path) | Expr::PathExtname(path) | Expr::PathResolve(path) => {
             collect_local_refs_expr(path, refs);
         }
+        Expr::ProcessSetMemoryLimit { limit, policy } => {
+            collect_local_refs_expr(limit, refs);
+            if let Some(p) = policy {
+                collect_local_refs_expr(p, refs);
+            }
+        }
+        Expr::ProcessOnLowMemory(cb) => {
+            collect_local_refs_expr(cb, refs);
+        }
+        Expr::ProcessStdioWrite { data, callback, .. } => {
+            collect_local_refs_expr(data, refs);
+            if let Some(cb) = callback {
+                collect_local_refs_expr(cb, refs);
+            }
+        }
+        Expr::ProcessStdinOn { event, listener } => {
+            collect_local_refs_expr(event, refs);
+            collect_local_refs_expr(listener, refs);
+        }
+        Expr::DisposeResource { resource, .. } => {
+            collect_local_refs_expr(resource, refs);
+        }
+        Expr::DisposableStackAdopt { stack, value, on_dispose } => {
+            collect_local_refs_expr(stack, refs);
+            collect_local_refs_expr(value, refs);
+            collect_local_refs_expr(on_dispose, refs);
+        }
+        Expr::DisposableStackDefer { stack, on_dispose } => {
+            collect_local_refs_expr(stack, refs);
+            collect_local_refs_expr(on_dispose, refs);
+        }
+        Expr::DisposableStackDispose(stack) | Expr::DisposableStackMove(stack) | Expr::DisposableStackDisposed(stack) => {
+            collect_local_refs_expr(stack, refs);
+        }
+        Expr::ImportMetaResolve { specifier, .. } => {
+            collect_local_refs_expr(specifier, refs);
+        }
         // Array methods
         Expr::ArrayPush { array_id, value } => {
             refs.push(*array_id);
@@ -7506,7 +11695,8 @@ fn collect_local_refs_expr(expr: &Expr, refs: &mut Vec<LocalId>) {
             collect_local_refs_expr(map, refs);
             collect_local_refs_expr(key, refs);
         }
-        Expr::MapSize(map) | Expr::MapClear(map) => {
+        Expr::MapSize(map) | Expr::MapClear(map) | Expr::MapKeys(map) |
+        Expr::MapValues(map) | Expr::MapEntries(map) => {
             collect_local_refs_expr(map, refs);
         }
         // Set operations
@@ -7519,11 +11709,11 @@ fn collect_local_refs_expr(expr: &Expr, refs: &mut Vec<LocalId>) {
             collect_local_refs_expr(set, refs);
             collect_local_refs_expr(value, refs);
         }
-        Expr::SetSize(set) | Expr::SetClear(set) => {
+        Expr::SetSize(set) | Expr::SetClear(set) | Expr::SetValues(set) => {
             collect_local_refs_expr(set, refs);
         }
         // JSON operations
-        Expr::JsonParse(expr) | Expr::JsonStringify(expr) => {
+        Expr::JsonParse(expr) | Expr::JsonStringify(expr) | Expr::JsonCanonicalize(expr) => {
             collect_local_refs_expr(expr, refs);
         }
         // Math operations
@@ -7637,6 +11827,17 @@ fn collect_local_refs_expr(expr: &Expr, refs: &mut Vec<LocalId>) {
                 collect_local_refs_expr(opts, refs);
             }
         }
+        Expr::FetchWithOptions { url, method, body, headers, on_upload_progress, .. } => {
+            collect_local_refs_expr(url, refs);
+            collect_local_refs_expr(method, refs);
+            collect_local_refs_expr(body, refs);
+            for (_, value) in headers {
+                collect_local_refs_expr(value, refs);
+            }
+            if let Some(cb) = on_upload_progress {
+                collect_local_refs_expr(cb, refs);
+            }
+        }
         Expr::ChildProcessExec { command, options, callback } => {
             collect_local_refs_expr(command, refs);
             if let Some(opts) = options {
@@ -7718,6 +11919,7 @@ fn collect_local_refs_expr(expr: &Expr, refs: &mut Vec<LocalId>) {
         Expr::Undefined | Expr::BigInt(_) | Expr::This | Expr::FuncRef(_) |
         Expr::ClassRef(_) | Expr::ExternFuncRef { .. } | Expr::EnumMember { .. } |
         Expr::EnvGet(_) | Expr::ProcessUptime | Expr::ProcessCwd | Expr::ProcessMemoryUsage | Expr::NativeModuleRef(_) |
+        Expr::ProcessStdinRead | Expr::ProcessStdinPipe(_) |
         Expr::RegExp { .. } => {}
         Expr::ObjectKeys(obj) | Expr::ObjectValues(obj) | Expr::ObjectEntries(obj) => {
             collect_local_refs_expr(obj, refs);
@@ -7760,7 +11962,7 @@ fn collect_local_refs_expr(expr: &Expr, refs: &mut Vec<LocalId>) {
             collect_local_refs_expr(value, refs);
         }
         // JS runtime expressions
-        Expr::JsLoadModule { .. } => {}
+        Expr::JsLoadModule { .. } | Expr::NativeAddonLoad { .. } => {}
         Expr::JsGetExport { module_handle, .. } => {
             collect_local_refs_expr(module_handle, refs);
         }
@@ -7784,8 +11986,8 @@ fn collect_local_refs_expr(expr: &Expr, refs: &mut Vec<LocalId>) {
             collect_local_refs_expr(inner, refs);
         }
         // Error operations
-        Expr::ErrorNew(msg) => {
-            if let Some(m) = msg {
+        Expr::ErrorNew { message, .. } => {
+            if let Some(m) = message {
                 collect_local_refs_expr(m, refs);
             }
         }
@@ -8107,6 +12309,9 @@ fn collect_assigned_locals_expr(expr: &Expr, assigned: &mut Vec<LocalId>) {
             collect_assigned_locals_expr(path, assigned);
             collect_assigned_locals_expr(content, assigned);
         }
+        Expr::FsCreateReadStream(path) => {
+            collect_assigned_locals_expr(path, assigned);
+        }
         // Path operations
         Expr::PathJoin(a, b) => {
             collect_assigned_locals_expr(a, assigned);
@@ -8115,6 +12320,43 @@ fn collect_assigned_locals_expr(expr: &Expr, assigned: &mut Vec<LocalId>) {
         Expr::PathDirname(path) | Expr::PathBasename(path) | Expr::PathExtname(path) | Expr::PathResolve(path) => {
             collect_assigned_locals_expr(path, assigned);
         }
+        Expr::ProcessSetMemoryLimit { limit, policy } => {
+            collect_assigned_locals_expr(limit, assigned);
+            if let Some(p) = policy {
+                collect_assigned_locals_expr(p, assigned);
+            }
+        }
+        Expr::ProcessOnLowMemory(cb) => {
+            collect_assigned_locals_expr(cb, assigned);
+        }
+        Expr::ProcessStdioWrite { data, callback, .. } => {
+            collect_assigned_locals_expr(data, assigned);
+            if let Some(cb) = callback {
+                collect_assigned_locals_expr(cb, assigned);
+            }
+        }
+        Expr::ProcessStdinOn { event, listener } => {
+            collect_assigned_locals_expr(event, assigned);
+            collect_assigned_locals_expr(listener, assigned);
+        }
+        Expr::DisposeResource { resource, .. } => {
+            collect_assigned_locals_expr(resource, assigned);
+        }
+        Expr::DisposableStackAdopt { stack, value, on_dispose } => {
+            collect_assigned_locals_expr(stack, assigned);
+            collect_assigned_locals_expr(value, assigned);
+            collect_assigned_locals_expr(on_dispose, assigned);
+        }
+        Expr::DisposableStackDefer { stack, on_dispose } => {
+            collect_assigned_locals_expr(stack, assigned);
+            collect_assigned_locals_expr(on_dispose, assigned);
+        }
+        Expr::DisposableStackDispose(stack) | Expr::DisposableStackMove(stack) | Expr::DisposableStackDisposed(stack) => {
+            collect_assigned_locals_expr(stack, assigned);
+        }
+        Expr::ImportMetaResolve { specifier, .. } => {
+            collect_assigned_locals_expr(specifier, assigned);
+        }
         // Array methods - push/unshift may reassign the array pointer
         Expr::ArrayPush { array_id, value } | Expr::ArrayUnshift { array_id, value } => {
             assigned.push(*array_id); // These may reallocate the array
@@ -8199,7 +12441,8 @@ fn collect_assigned_locals_expr(expr: &Expr, assigned: &mut Vec<LocalId>) {
             collect_assigned_locals_expr(map, assigned);
             collect_assigned_locals_expr(key, assigned);
         }
-        Expr::MapSize(map) | Expr::MapClear(map) => {
+        Expr::MapSize(map) | Expr::MapClear(map) | Expr::MapKeys(map) |
+        Expr::MapValues(map) | Expr::MapEntries(map) => {
             collect_assigned_locals_expr(map, assigned);
         }
         // Set operations
@@ -8212,11 +12455,11 @@ fn collect_assigned_locals_expr(expr: &Expr, assigned: &mut Vec<LocalId>) {
             collect_assigned_locals_expr(set, assigned);
             collect_assigned_locals_expr(value, assigned);
         }
-        Expr::SetSize(set) | Expr::SetClear(set) => {
+        Expr::SetSize(set) | Expr::SetClear(set) | Expr::SetValues(set) => {
             collect_assigned_locals_expr(set, assigned);
         }
         // JSON operations
-        Expr::JsonParse(expr) | Expr::JsonStringify(expr) => {
+        Expr::JsonParse(expr) | Expr::JsonStringify(expr) | Expr::JsonCanonicalize(expr) => {
             collect_assigned_locals_expr(expr, assigned);
         }
         // Math operations
@@ -8330,6 +12573,17 @@ fn collect_assigned_locals_expr(expr: &Expr, assigned: &mut Vec<LocalId>) {
                 collect_assigned_locals_expr(opts, assigned);
             }
         }
+        Expr::FetchWithOptions { url, method, body, headers, on_upload_progress, .. } => {
+            collect_assigned_locals_expr(url, assigned);
+            collect_assigned_locals_expr(method, assigned);
+            collect_assigned_locals_expr(body, assigned);
+            for (_, value) in headers {
+                collect_assigned_locals_expr(value, assigned);
+            }
+            if let Some(cb) = on_upload_progress {
+                collect_assigned_locals_expr(cb, assigned);
+            }
+        }
         Expr::ChildProcessExec { command, options, callback } => {
             collect_assigned_locals_expr(command, assigned);
             if let Some(opts) = options {
@@ -8410,9 +12664,10 @@ fn collect_assigned_locals_expr(expr: &Expr, assigned: &mut Vec<LocalId>) {
         Expr::LocalGet(_) | Expr::GlobalGet(_) | Expr::GlobalSet(_, _) |
         Expr::FuncRef(_) | Expr::ExternFuncRef { .. } | Expr::ClassRef(_) |
         Expr::Number(_) | Expr::Integer(_) | Expr::Bool(_) | Expr::String(_) | Expr::BigInt(_) |
-        Expr::Object(_) | Expr::TypeOf(_) | Expr::InstanceOf { .. } |
+        Expr::Object(_) | Expr::TypeOf(_) | Expr::InstanceOf { .. } | Expr::Narrowed { .. } |
         Expr::EnumMember { .. } | Expr::This | Expr::Null | Expr::Undefined |
         Expr::EnvGet(_) | Expr::ProcessUptime | Expr::ProcessCwd | Expr::ProcessMemoryUsage | Expr::NativeModuleRef(_) |
+        Expr::ProcessStdinRead | Expr::ProcessStdinPipe(_) |
         Expr::RegExp { .. } => {}
         Expr::ObjectKeys(obj) | Expr::ObjectValues(obj) | Expr::ObjectEntries(obj) => {
             collect_assigned_locals_expr(obj, assigned);
@@ -8455,7 +12710,7 @@ fn collect_assigned_locals_expr(expr: &Expr, assigned: &mut Vec<LocalId>) {
             collect_assigned_locals_expr(value, assigned);
         }
         // JS runtime expressions
-        Expr::JsLoadModule { .. } => {}
+        Expr::JsLoadModule { .. } | Expr::NativeAddonLoad { .. } => {}
         Expr::JsGetExport { module_handle, .. } => {
             collect_assigned_locals_expr(module_handle, assigned);
         }
@@ -8479,8 +12734,8 @@ fn collect_assigned_locals_expr(expr: &Expr, assigned: &mut Vec<LocalId>) {
             collect_assigned_locals_expr(inner, assigned);
         }
         // Error operations
-        Expr::ErrorNew(msg) => {
-            if let Some(m) = msg {
+        Expr::ErrorNew { message, .. } => {
+            if let Some(m) = message {
                 collect_assigned_locals_expr(m, assigned);
             }
         }
@@ -8632,3 +12887,1147 @@ fn closure_uses_this(body: &[Stmt]) -> bool {
 fn is_builtin_function(name: &str) -> bool {
     matches!(name, "setTimeout" | "setInterval" | "clearTimeout" | "clearInterval" | "fetch")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lower_source(source: &str) -> Module {
+        let ast_module = perry_parser::parse_typescript(source, "test.ts").unwrap();
+        lower_module(&ast_module, "test.ts", "/tmp/test.ts").unwrap()
+    }
+
+    fn named(imported: &str, local: &str) -> ImportSpecifier {
+        ImportSpecifier::Named { imported: imported.to_string(), local: local.to_string() }
+    }
+
+    #[test]
+    fn test_require_becomes_import() {
+        let module = lower_source(
+            "const lib = require('./lib');\n\
+             const { a, b: c } = require('./other');\n\
+             require('./side');\n\
+             lib.run(a, c);\n",
+        );
+        let import = |source: &str| module.imports.iter().find(|i| i.source == source).unwrap();
+        assert_eq!(import("./lib").specifiers, vec![named("run", "run")]);
+        assert_eq!(import("./other").specifiers, vec![named("a", "a"), named("b", "c")]);
+        assert!(import("./side").specifiers.is_empty());
+        // require() bindings don't produce variables
+        assert!(module.init.iter().all(|s| !matches!(s, Stmt::Let { .. })));
+    }
+
+    #[test]
+    fn test_require_shadowed_by_local() {
+        let module = lower_source("function require(x: string) { return x; }\nconst lib = require('./lib');\n");
+        assert!(module.imports.is_empty());
+    }
+
+    #[test]
+    fn test_native_addons_load_at_runtime() {
+        let module = lower_source(
+            "import hasher, { digest } from './build/hash.node';\n\
+             const other = require('./other.node');\n\
+             console.log(hasher.version, digest('x'), other);\n",
+        );
+        assert!(module.imports.is_empty());
+        assert_eq!(module.native_addons, vec!["/tmp/./build/hash.node".to_string(), "/tmp/./other.node".to_string()]);
+        let inits: Vec<&Expr> = module.init.iter().filter_map(|s| match s {
+            Stmt::Let { init: Some(init), .. } => Some(init),
+            _ => None,
+        }).collect();
+        assert!(matches!(inits[0], Expr::NativeAddonLoad { path } if path == "/tmp/./build/hash.node"));
+        assert!(matches!(inits[1], Expr::PropertyGet { object, property } if property == "digest"
+            && matches!(object.as_ref(), Expr::NativeAddonLoad { .. })));
+        assert!(matches!(inits[2], Expr::NativeAddonLoad { path } if path == "/tmp/./other.node"));
+    }
+
+    #[test]
+    fn test_fetch_streams_read_stream_body() {
+        let module = lower_source(
+            "const file = fs.createReadStream('/tmp/video.mp4');\n\
+             fetch('https://example.com/upload', { method: 'PUT', body: file, onUploadProgress: (sent: number, total: number) => {} });\n\
+             fetch('https://example.com/echo', { method: 'POST', body: 'hello' });\n",
+        );
+        assert!(matches!(&module.init[0], Stmt::Let { init: Some(Expr::FsCreateReadStream(_)), .. }));
+        let fetches: Vec<&Expr> = module.init.iter().filter_map(|s| match s {
+            Stmt::Expr(e @ Expr::FetchWithOptions { .. }) => Some(e),
+            _ => None,
+        }).collect();
+        assert!(matches!(fetches[0], Expr::FetchWithOptions { stream_body: true, on_upload_progress: Some(_), .. }));
+        assert!(matches!(fetches[1], Expr::FetchWithOptions { stream_body: false, on_upload_progress: None, .. }));
+    }
+
+    #[test]
+    fn test_export_declare_function_is_extern() {
+        let ast_module = perry_parser::parse_typescript(
+            "export declare function add(a: number, b: number): number;\n\
+             export const sum = add(1, 2);\n",
+            "bindings.ts",
+        ).unwrap();
+        let module = lower_module(&ast_module, "bindings.ts", "/tmp/bindings.ts").unwrap();
+        assert!(module.functions.is_empty());
+        assert!(module.exported_functions.is_empty());
+        assert!(module.init.iter().any(|s| matches!(s, Stmt::Let { init: Some(Expr::Call { callee, .. }), .. }
+            if matches!(callee.as_ref(), Expr::ExternFuncRef { name, return_type: Type::Number, .. } if name == "add"))));
+    }
+
+    #[test]
+    fn test_imports_typed_from_declarations() {
+        let typings = perry_parser::parse_declarations(
+            "export declare function slugify(input: string): string;\n\
+             declare class Counter { constructor(start: number); }\n\
+             export default Counter;\n",
+            "index.d.ts",
+        ).unwrap();
+        let mut options = LowerOptions::default();
+        options.declarations.insert("slugs".to_string(), crate::declarations::collect_declarations(&typings));
+        let ast_module = perry_parser::parse_typescript(
+            "import Tally, { slugify, other } from 'slugs';\n\
+             const s = slugify('Hello World');\n\
+             const c = Tally;\n\
+             const o = other;\n",
+            "test.ts",
+        ).unwrap();
+        let module = lower_module_with_options(&ast_module, "test.ts", "/tmp/test.ts", &options).unwrap();
+        let inits: Vec<&Expr> = module.init.iter().filter_map(|s| match s {
+            Stmt::Let { init: Some(init), .. } => Some(init),
+            _ => None,
+        }).collect();
+        assert!(matches!(inits[0], Expr::Call { callee, .. } if matches!(callee.as_ref(),
+            Expr::ExternFuncRef { param_types, return_type: Type::String, .. } if *param_types == vec![Type::String])));
+        assert!(matches!(inits[1], Expr::ExternFuncRef { name, param_types, return_type: Type::Named(class), .. }
+            if name == "Tally" && class == "Tally" && *param_types == vec![Type::Number]));
+        assert!(matches!(inits[2], Expr::ExternFuncRef { return_type: Type::Any, .. }));
+    }
+
+    #[test]
+    fn test_commonjs_exports() {
+        let module = lower_source(
+            "function add(a: number, b: number) { return a + b; }\n\
+             exports.sum = add;\n\
+             module.exports.double = (x: number) => x * 2;\n",
+        );
+        let exported: Vec<&str> = module.exports.iter().filter_map(|e| match e {
+            Export::Named { exported, .. } => Some(exported.as_str()),
+            _ => None,
+        }).collect();
+        assert_eq!(exported, vec!["sum", "double", "default"]);
+        assert!(module.exported_functions.iter().any(|(name, _)| name == "sum"));
+        assert_eq!(module.exported_objects, vec!["double".to_string(), "default".to_string()]);
+
+        // module.exports as a whole is an object of the named exports
+        let Some(Stmt::Let { init: Some(Expr::Object(props)), .. }) = module.init.last() else {
+            panic!("expected the module.exports object last: {:?}", module.init.last());
+        };
+        let keys: Vec<&str> = props.iter().map(|(key, _)| key.as_str()).collect();
+        assert_eq!(keys, vec!["sum", "double"]);
+        assert!(matches!(props[0].1, Expr::FuncRef(_)));
+    }
+
+    #[test]
+    fn test_commonjs_exports_dont_shadow_locals() {
+        let module = lower_source(
+            "const count = 1;\n\
+             module.exports = { count: count + 1, total: 3 };\n\
+             exports.total = 4;\n\
+             console.log(count);\n",
+        );
+        let lets: Vec<&str> = module.init.iter().filter_map(|stmt| match stmt {
+            Stmt::Let { name, .. } => Some(name.as_str()),
+            _ => None,
+        }).collect();
+        assert_eq!(lets, vec!["count", "__exports_count", "__exports_total", "__module_exports"]);
+        // The second `exports.total` assigns the existing export variable
+        assert_eq!(module.exports.iter().filter(|e| matches!(e, Export::Named { exported, .. } if exported == "total")).count(), 1);
+        assert!(module.init.iter().any(|stmt| matches!(stmt, Stmt::Expr(Expr::LocalSet(..)))));
+    }
+
+    #[test]
+    fn test_require_imports_use_generated_locals() {
+        let module = lower_source(
+            "const lib = require('./lib');\n\
+             function helper() { return 1; }\n\
+             console.log(lib.helper(), helper(), lib);\n",
+        );
+        let import = module.imports.iter().find(|import| import.source == "./lib").unwrap();
+        assert!(import.is_require);
+        assert_eq!(import.specifiers, vec![
+            ImportSpecifier::Named { imported: "helper".to_string(), local: "__require_helper".to_string() },
+            ImportSpecifier::Named { imported: "default".to_string(), local: "__require_default".to_string() },
+        ]);
+    }
+
+    #[test]
+    fn test_exported_variables_get_export_globals() {
+        let module = lower_source(
+            "function f() { return 1; }\n\
+             export const n = 1;\n\
+             export let s = 'x';\n\
+             export const g = f;\n\
+             const t = 2;\n\
+             export { t as u };\n",
+        );
+        assert_eq!(module.exported_objects, vec!["n".to_string(), "s".to_string(), "u".to_string()]);
+        assert!(module.exported_functions.iter().any(|(name, _)| name == "g"));
+    }
+
+    #[test]
+    fn test_readonly_writes_reported() {
+        let source = "interface P { readonly x: number; y: number }\n\
+                      class A {\n\
+                        readonly id: string;\n\
+                        constructor(id: string) { this.id = id; }\n\
+                        rename(id: string) { this.id = id; }\n\
+                      }\n\
+                      const p: P = { x: 1, y: 2 };\n\
+                      p.y = 3;\n\
+                      p.x = 4;\n\
+                      const c = { n: { m: 1 } } as const;\n\
+                      c.n.m++;\n";
+        let module = lower_source(source);
+        let diagnostics = &module.diagnostics;
+        assert_eq!(diagnostics.len(), 3, "{:?}", diagnostics);
+        assert!(diagnostics.iter().all(|d| d.code == DiagnosticCode::ReadonlyAssignment));
+
+        let removed: Vec<&str> = diagnostics
+            .iter()
+            .map(|d| {
+                let span = d.suggestions[0].span;
+                &source[span.start as usize..span.end as usize]
+            })
+            .collect();
+        assert_eq!(removed, vec!["readonly ", "readonly ", " as const"]);
+        assert_eq!(&source[diagnostics[1].span.start as usize..diagnostics[1].span.end as usize], "p.x");
+    }
+
+    #[test]
+    fn test_readonly_arrays_and_const_assertions() {
+        let source = "const SIZES = ['s', 'm', 'l'] as const;\n\
+                      type Size = typeof SIZES[number];\n\
+                      function price(size: Size) {\n\
+                        switch (size) { case 's': return 1; case 'm': return 2; }\n\
+                      }\n\
+                      function total(xs: readonly number[], ys: ReadonlyArray<number>) {\n\
+                        xs.push(1);\n\
+                        ys[0] = 2;\n\
+                        return xs.length + ys.indexOf(2);\n\
+                      }\n\
+                      SIZES[0] = 'xl';\n\
+                      const config = { retries: [1, 2] } as const;\n\
+                      config.retries.length = 0;\n\
+                      const copy = [...SIZES];\n\
+                      copy.push('xl');\n";
+        let module = lower_source(source);
+        let codes: Vec<DiagnosticCode> = module.diagnostics.iter().map(|d| d.code).collect();
+        assert_eq!(
+            codes,
+            vec![
+                DiagnosticCode::NonExhaustiveSwitch,
+                DiagnosticCode::ReadonlyAssignment,
+                DiagnosticCode::ReadonlyAssignment,
+                DiagnosticCode::ReadonlyAssignment,
+                DiagnosticCode::ReadonlyAssignment,
+            ],
+            "{:?}",
+            module.diagnostics
+        );
+        // The tuple's literals make up `Size`
+        assert!(module.diagnostics[0].message.contains("\"l\""), "{}", module.diagnostics[0].message);
+        assert!(module.diagnostics[1].message.contains("'push' on 'xs'"), "{}", module.diagnostics[1].message);
+
+        let removed: Vec<&str> = module.diagnostics[1..]
+            .iter()
+            .map(|d| d.suggestions.first().map_or("", |s| &source[s.span.start as usize..s.span.end as usize]))
+            .collect();
+        assert_eq!(removed, vec!["readonly ", "", " as const", " as const"]);
+    }
+
+    #[test]
+    fn test_discriminated_union_switch() {
+        let source = "interface Circle { kind: 'circle'; readonly radius: number }\n\
+                      type Square = { kind: 'square'; size: number };\n\
+                      type Shape = Circle | Square | { kind: 'line' };\n\
+                      function area(shape: Shape) {\n\
+                        switch (shape.kind) {\n\
+                          case 'circle': shape.radius = 1; return 3;\n\
+                          case 'square': return shape.size;\n\
+                        }\n\
+                      }\n\
+                      function total(shape: Shape | null) {\n\
+                        switch (shape.kind) { case 'line': return 0; default: return 1; }\n\
+                      }\n";
+        let module = lower_source(source);
+        let codes: Vec<DiagnosticCode> = module.diagnostics.iter().map(|d| d.code).collect();
+        // The write is only recognized because `shape` is narrowed to `Circle` in its case
+        assert_eq!(codes, vec![DiagnosticCode::ReadonlyAssignment, DiagnosticCode::NonExhaustiveSwitch]);
+        let missing = &module.diagnostics[1];
+        assert!(missing.message.contains("\"line\""), "{}", missing.message);
+        assert!(!missing.message.contains("square"), "{}", missing.message);
+        assert_eq!(&source[missing.span.start as usize..missing.span.start as usize + 6], "switch");
+    }
+
+    #[test]
+    fn test_literal_and_enum_switch() {
+        let source = "type Mode = 'fast' | 'safe' | 'slow';\n\
+                      enum Color { Red, Green = 'green' }\n\
+                      function run(mode: Mode, color: Color, red: Color.Red) {\n\
+                        switch (mode) { case 'fast': return 1; case 'safe': return 2; }\n\
+                        switch (color) { case Color.Red: return 3; }\n\
+                        switch (color) { case Color.Red: case Color.Green: return 4; }\n\
+                      }\n";
+        let module = lower_source(source);
+        let run = module.functions.iter().find(|f| f.name == "run").unwrap();
+        let literal = |value: &str| Type::Literal(LiteralType::String(value.to_string()));
+        assert_eq!(run.params[0].ty, Type::Union(vec![literal("fast"), literal("safe"), literal("slow")]));
+        assert!(run.params[0].ty.is_string());
+        assert_eq!(run.params[2].ty, Type::Literal(LiteralType::Number(0.0)));
+
+        let messages: Vec<&str> = module.diagnostics.iter()
+            .filter(|d| d.code == DiagnosticCode::NonExhaustiveSwitch)
+            .map(|d| d.message.as_str())
+            .collect();
+        assert_eq!(messages, vec![
+            "Switch on 'mode' does not handle \"slow\"",
+            "Switch on 'color' does not handle \"green\"",
+        ]);
+    }
+
+    #[test]
+    fn test_temporal_calls() {
+        let module = lower_source("const day = Temporal.PlainDate.from('2024-01-15');\n\
+                                   const span = new Temporal.Duration(0, 1);\n\
+                                   function own(Temporal: any) { return Temporal.Now.timeZoneId(); }\n");
+        let methods: Vec<&str> = module.init.iter()
+            .filter_map(|s| match s {
+                Stmt::Let { init: Some(Expr::NativeMethodCall { module, method, .. }), .. } if module == "perry/temporal" => {
+                    Some(method.as_str())
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(methods, vec!["PlainDate.from", "Duration"]);
+        // A parameter named Temporal shadows the global
+        let own = module.functions.iter().find(|f| f.name == "own").unwrap();
+        assert!(!format!("{:?}", own.body).contains("perry/temporal"));
+    }
+
+    #[test]
+    fn test_intersection_members_merged() {
+        let source = "class User { name: string = ''; readonly id: number = 0 }\n\
+                      interface Stamped { readonly at: number }\n\
+                      type Audited = Stamped & { by: string } & { readonly reason: string };\n\
+                      function touch(user: User & { a: number } & { b: string }, entry: Audited) {\n\
+                        user.name = 'x'; user.a = 1; user.id = 2;\n\
+                        entry.by = 'me'; entry.at = 3; entry.reason = 'y';\n\
+                      }\n";
+        let module = lower_source(source);
+        let touch = module.functions.iter().find(|f| f.name == "touch").unwrap();
+        let Type::Intersection(members) = &touch.params[0].ty else {
+            panic!("expected an intersection, got {:?}", touch.params[0].ty);
+        };
+        // The object literal members are merged into one shape after the class
+        assert_eq!(members.len(), 2);
+        assert_eq!(members[0], Type::Named("User".into()));
+        let Type::Object(literal) = &members[1] else { panic!("{:?}", members[1]) };
+        let mut props: Vec<&String> = literal.properties.keys().collect();
+        props.sort();
+        assert_eq!(props, vec!["a", "b"]);
+
+        // Properties of every member are found, including through an intersection alias
+        let writes: Vec<&str> = module.diagnostics.iter()
+            .filter(|d| d.code == DiagnosticCode::ReadonlyAssignment)
+            .map(|d| &source[d.span.start as usize..d.span.end as usize])
+            .collect();
+        assert_eq!(writes, vec!["user.id", "entry.at", "entry.reason"]);
+    }
+
+    #[test]
+    fn test_mapped_types_evaluated() {
+        let source = "interface User { readonly id: number; name: string; email?: string }\n\
+                      type Frozen = { readonly [K in keyof User]-?: User[K] };\n\
+                      type Flags = { readonly [K in 'dark' | 'compact']: boolean };\n\
+                      function edit(patch: Partial<User>, pick: Pick<User, 'id'>, rest: Readonly<Omit<User, 'id'>>,\n\
+                                    scores: Readonly<Record<string, number>>, frozen: Frozen, flags: Flags,\n\
+                                    inline: { [K in keyof User]?: string }) {\n\
+                        patch.name = 'a'; patch.id = 1; pick.id = 2; rest.name = 'b';\n\
+                        scores.math = 3; frozen.email = 'c'; flags.dark = true; inline.name = 'd';\n\
+                      }\n";
+        let module = lower_source(source);
+        let writes: Vec<&str> = module.diagnostics.iter()
+            .filter(|d| d.code == DiagnosticCode::ReadonlyAssignment)
+            .map(|d| &source[d.span.start as usize..d.span.end as usize])
+            .collect();
+        assert_eq!(writes, vec!["patch.id", "pick.id", "rest.name", "scores.math", "frozen.email", "flags.dark"]);
+
+        // A mapped type literal is evaluated to an object type, replacing each value type
+        let edit = module.functions.iter().find(|f| f.name == "edit").unwrap();
+        let Type::Object(inline) = &edit.params[6].ty else { panic!("{:?}", edit.params[6].ty) };
+        assert_eq!(inline.properties.len(), 3);
+        assert!(inline.properties.values().all(|info| info.ty == Type::String && info.optional));
+        assert_eq!(edit.params[1].ty, Type::Generic {
+            base: "Pick".into(),
+            type_args: vec![Type::Named("User".into()), Type::Literal(LiteralType::String("id".into()))],
+        });
+    }
+
+    #[test]
+    fn test_conditional_types_evaluated() {
+        let module = lower_source(
+            "type Unwrap<T> = T extends Promise<infer U> ? U : T;\n\
+             function f(a: Unwrap<Promise<number>>, b: Unwrap<boolean>, c: Awaited<Promise<Promise<string>>>,\n\
+                        d: NonNullable<string | null>, e: Exclude<'a' | 'b' | 'c', 'a'>,\n\
+                        g: ReturnType<(x: number) => string[]>, h: Parameters<(x: number, y: string) => void>,\n\
+                        i: number extends string ? boolean : bigint) {}\n\
+             function unwrap<T>(x: T): Unwrap<T> { return x as any; }\n",
+        );
+        let f = module.functions.iter().find(|f| f.name == "f").unwrap();
+        let types: Vec<&Type> = f.params.iter().map(|p| &p.ty).collect();
+        assert_eq!(types, vec![
+            &Type::Number,
+            &Type::Boolean,
+            &Type::String,
+            &Type::String,
+            &Type::Union(vec![Type::Literal(LiteralType::String("b".into())), Type::Literal(LiteralType::String("c".into()))]),
+            &Type::Array(Box::new(Type::String)),
+            &Type::Tuple(vec![Type::Number, Type::String]),
+            &Type::BigInt,
+        ]);
+
+        // Depending on a type parameter, it waits for monomorphization
+        let unwrap = module.functions.iter().find(|f| f.name == "unwrap").unwrap();
+        assert!(matches!(unwrap.return_type, Type::Conditional(_)), "{:?}", unwrap.return_type);
+        let substitutions = [("T".to_string(), Type::Promise(Box::new(Type::String)))].into_iter().collect();
+        assert_eq!(crate::monomorph::substitute_type(&unwrap.return_type, &substitutions), Type::String);
+        let substitutions = [("T".to_string(), Type::Number)].into_iter().collect();
+        assert_eq!(crate::monomorph::substitute_type(&unwrap.return_type, &substitutions), Type::Number);
+    }
+
+    #[test]
+    fn test_keyof_and_indexed_access() {
+        let module = lower_source(
+            "interface User { id: number; name: string; tags?: string[] }\n\
+             function f(a: User[\"name\"], b: User[\"id\" | \"name\"], c: keyof User, d: [string, boolean][1],\n\
+                        e: User[\"tags\"], g: readonly number[]) {}\n\
+             function get<T, K extends keyof T>(o: T, k: K): T[K] { return o[k]; }\n",
+        );
+        let key = |name: &str| Type::Literal(LiteralType::String(name.into()));
+        let f = module.functions.iter().find(|f| f.name == "f").unwrap();
+        let types: Vec<&Type> = f.params.iter().map(|p| &p.ty).collect();
+        assert_eq!(types, vec![
+            &Type::String,
+            &Type::Union(vec![Type::Number, Type::String]),
+            &Type::Union(vec![key("id"), key("name"), key("tags")]),
+            &Type::Boolean,
+            &Type::Union(vec![Type::Array(Box::new(Type::String)), Type::Void]),
+            &Type::Array(Box::new(Type::Number)),
+        ]);
+
+        // Accessor helpers keep `T[K]` until monomorphization picks the property
+        let get = module.functions.iter().find(|f| f.name == "get").unwrap();
+        assert_eq!(get.return_type.to_string(), "T[K]");
+        let mut object = ObjectType::default();
+        object.properties.insert("port".into(), PropertyInfo { ty: Type::Number, optional: false, readonly: false });
+        let substitutions = [("T".to_string(), Type::Object(object)), ("K".to_string(), key("port"))].into_iter().collect();
+        assert_eq!(crate::monomorph::substitute_type(&get.return_type, &substitutions), Type::Number);
+    }
+
+    #[test]
+    fn test_exported_constants() {
+        let module = lower_source(
+            "export enum Dir { Up = 'up' }\n\
+             export const N = -2;\n\
+             export const CFG = { a: { b: N }, 'c': `x` } as const;\n\
+             export const MUT = { a: 1 };\n\
+             export let L = 1;\n\
+             const hidden = Dir.Up;\n\
+             export { hidden as shown };\n",
+        );
+        let names: Vec<&str> = module.exported_constants.iter().map(|(n, _)| n.as_str()).collect();
+        assert_eq!(names, vec!["Dir", "N", "CFG", "shown"]);
+        let cfg = &module.exported_constants[2].1;
+        assert_eq!(cfg.member("a").and_then(|a| a.member("b")), Some(ConstValue::Number(-2.0)));
+        assert_eq!(cfg.member("c"), Some(ConstValue::String("x".to_string())));
+        assert_eq!(module.exported_constants[3].1, ConstValue::String("up".to_string()));
+    }
+
+    #[test]
+    fn test_module_exports_object_and_default() {
+        let module = lower_source("function f() { return 1; }\nmodule.exports = { f, g: f };\n");
+        assert_eq!(module.exported_functions.iter().map(|(n, _)| n.as_str()).collect::<Vec<_>>(), vec!["f", "g"]);
+
+        let module = lower_source("function f() { return 1; }\nmodule.exports = f;\n");
+        assert_eq!(module.exported_functions.iter().map(|(n, _)| n.as_str()).collect::<Vec<_>>(), vec!["default"]);
+    }
+
+    #[test]
+    fn test_using_lowers_to_try_finally() {
+        let module = lower_source(
+            "function f(r: any) {\n\
+               using a = r;\n\
+               using b = r;\n\
+               return 1;\n\
+             }\n",
+        );
+        let body = &module.functions[0].body;
+        // try { ... } catch { record } dispose(a); rethrow; return
+        let [.., Stmt::Try { body: inner, catch: Some(_), finally: None }, Stmt::Expr(Expr::DisposeResource { is_async: false, .. }), Stmt::If { then_branch: rethrow, .. }, Stmt::If { then_branch: ret, .. }] = &body[..] else {
+            panic!("expected the disposal after the protected region, got {:?}", body);
+        };
+        assert!(matches!(&rethrow[..], [Stmt::Throw(_)]));
+        // The return inside the scope is taken after disposing
+        assert!(matches!(&ret[..], [Stmt::Return(Some(_))]));
+        assert!(!has_region_exits(inner, 0, 0));
+        // The second declaration is nested inside the first scope
+        assert!(inner.iter().any(|s| matches!(s, Stmt::Expr(Expr::DisposeResource { .. }))));
+    }
+
+    #[test]
+    fn test_await_using_awaits_dispose() {
+        let module = lower_source("async function f(r: any) {\n  await using a = r;\n}\n");
+        let disposed = module.functions[0].body.iter().any(|s| {
+            matches!(s, Stmt::Expr(Expr::Await(inner)) if matches!(**inner, Expr::DisposeResource { is_async: true, .. }))
+        });
+        assert!(disposed, "expected an awaited dispose: {:?}", module.functions[0].body);
+    }
+
+    #[test]
+    fn test_finally_runs_on_early_exits() {
+        let module = lower_source(
+            "function f(xs: number[]) {\n\
+               for (let i = 0; i < xs.length; i++) {\n\
+                 try {\n\
+                   if (xs[i] > 1) return xs[i];\n\
+                   if (xs[i] < 0) continue;\n\
+                   break;\n\
+                 } catch (e) {\n\
+                   return -1;\n\
+                 } finally {\n\
+                   console.log('cleanup');\n\
+                 }\n\
+               }\n\
+               return 0;\n\
+             }\n",
+        );
+        let Some(Stmt::For { body, .. }) = module.functions[0].body.iter().find(|s| matches!(s, Stmt::For { .. })) else {
+            panic!("expected a for loop");
+        };
+        assert!(!body.iter().any(|s| matches!(s, Stmt::Try { finally: Some(_), .. })));
+        // The finalizer runs once after both regions (body, then handler), which never
+        // jump out directly, then the exit is replayed
+        let finalizer = body.iter().position(|s| matches!(s, Stmt::Expr(_))).expect("finalizer");
+        assert!(!has_region_exits(&body[..finalizer], 0, 0));
+        let replayed: Vec<&Stmt> = body[finalizer + 1..]
+            .iter()
+            .map(|s| match s {
+                Stmt::If { then_branch, .. } => &then_branch[0],
+                other => other,
+            })
+            .collect();
+        assert!(matches!(
+            &replayed[..],
+            [Stmt::Throw(_), Stmt::Break, Stmt::Continue, Stmt::Return(Some(_)), Stmt::Return(Some(_))]
+        ));
+    }
+
+    #[test]
+    fn test_standard_streams() {
+        let module = lower_source(
+            "async function main() {\n\
+               for await (const chunk of process.stdin) {\n\
+                 process.stdout.write(chunk.toUpperCase(), 'utf8', () => {});\n\
+               }\n\
+             }\n\
+             process.stderr.write('done\\n');\n\
+             process.stdin.on('data', (chunk: string) => {});\n\
+             process.stdin.pipe(process.stdout);\n",
+        );
+        // The loop pulls chunks until read() gives null
+        let Some(Stmt::While { body, .. }) = module.functions[0].body.iter().find(|s| matches!(s, Stmt::While { .. })) else {
+            panic!("expected a chunk loop");
+        };
+        assert!(matches!(&body[0], Stmt::Let { init: Some(Expr::ProcessStdinRead), .. }));
+        assert!(matches!(&body[1], Stmt::If { condition: Expr::Compare { op: CompareOp::Eq, .. }, .. }));
+        assert!(body.iter().any(|s| matches!(s, Stmt::Expr(Expr::ProcessStdioWrite { fd: 1, callback: Some(_), .. }))));
+
+        let inits: Vec<&Expr> = module.init.iter().filter_map(|s| match s {
+            Stmt::Expr(e) => Some(e),
+            _ => None,
+        }).collect();
+        assert!(matches!(inits[0], Expr::ProcessStdioWrite { fd: 2, callback: None, .. }));
+        assert!(matches!(inits[1], Expr::ProcessStdinOn { .. }));
+        assert!(matches!(inits[2], Expr::ProcessStdinPipe(1)));
+    }
+
+    #[test]
+    fn test_timer_unref_and_introspection() {
+        let module = lower_source(
+            "const poll = setInterval(() => {}, 1000);\n\
+             poll.unref();\n\
+             setTimeout(() => {}, 50).ref().hasRef();\n\
+             const wait = setTimeout(10);\n\
+             process.getActiveTimers();\n",
+        );
+        let runtime_fn = |e: &Expr| match e {
+            Expr::Call { callee, .. } => match callee.as_ref() {
+                Expr::ExternFuncRef { name, .. } => Some(name.clone()),
+                _ => None,
+            },
+            _ => None,
+        };
+        let calls: Vec<String> = module.init.iter().filter_map(|s| match s {
+            Stmt::Expr(e) => runtime_fn(e),
+            _ => None,
+        }).collect();
+        assert_eq!(calls, ["js_timer_unref", "js_timer_has_ref", "js_timer_active_list"]);
+        // hasRef() is called on the timer returned by ref()
+        let Some(Stmt::Expr(Expr::Call { args, .. })) = module.init.iter()
+            .find(|s| matches!(s, Stmt::Expr(e) if runtime_fn(e).as_deref() == Some("js_timer_has_ref")))
+        else {
+            panic!("expected a hasRef() call");
+        };
+        assert_eq!(runtime_fn(&args[0]).as_deref(), Some("js_timer_ref"));
+    }
+
+    #[test]
+    fn test_type_guards_narrow_branches() {
+        let module = lower_source(
+            "class Point { x = 0; }\n\
+             function f(x: any, y: number | string, p: unknown) {\n\
+               if (typeof x === 'number') { return x * 2; }\n\
+               if (typeof y !== 'string') { return 0; } else { return y.length; }\n\
+               if (p instanceof Point && 'number' == typeof x) { return p.x; }\n\
+               return 1;\n\
+             }\n\
+             function g(v: any) { if (typeof v === 'number') { v = v + 1; } return v; }\n",
+        );
+        let narrowed = |branch: &[Stmt]| -> Vec<(String, Type)> {
+            branch.iter().filter_map(|s| match s {
+                Stmt::Let { name, ty, init: Some(Expr::Narrowed { value, .. }), .. } => {
+                    assert!(matches!(value.as_ref(), Expr::LocalGet(_)));
+                    Some((name.clone(), ty.clone()))
+                }
+                _ => None,
+            }).collect()
+        };
+        let ifs: Vec<(&Vec<Stmt>, &Option<Vec<Stmt>>)> = module.functions[0].body.iter().filter_map(|s| match s {
+            Stmt::If { then_branch, else_branch, .. } => Some((then_branch, else_branch)),
+            _ => None,
+        }).collect();
+        assert_eq!(narrowed(ifs[0].0), [("x".to_string(), Type::Number)]);
+        // The narrowed copy is what the branch reads
+        let (Stmt::Let { id, .. }, Stmt::Return(Some(Expr::Binary { left, .. }))) = (&ifs[0].0[0], &ifs[0].0[1]) else {
+            panic!("expected the narrowed local and the return");
+        };
+        assert!(matches!(left.as_ref(), Expr::LocalGet(read) if read == id));
+        // `!==` narrows the else branch
+        assert!(narrowed(ifs[1].0).is_empty());
+        assert_eq!(narrowed(ifs[1].1.as_ref().unwrap()), [("y".to_string(), Type::String)]);
+        assert_eq!(
+            narrowed(ifs[2].0),
+            [("p".to_string(), Type::Named("Point".to_string())), ("x".to_string(), Type::Number)]
+        );
+
+        // A branch that assigns the local keeps the original
+        let Some(Stmt::If { then_branch, .. }) = module.functions[1].body.first() else { panic!("expected an if") };
+        assert!(narrowed(then_branch).is_empty());
+    }
+
+    #[test]
+    fn test_flow_narrowing() {
+        let source = "interface Circle { kind: 'circle'; readonly radius: number }\n\
+                      type Square = { kind: 'square'; size: number };\n\
+                      type Shape = Circle | Square;\n\
+                      function f(s: string | null, n: number | string | undefined, shape: Shape | null, mode: 'a' | 'b' | number) {\n\
+                        if (s === null) { return 0; }\n\
+                        if (!n) return 1;\n\
+                        if (typeof n === 'number') return n;\n\
+                        if (mode === 'a') { return mode; }\n\
+                        if (shape === null || shape.kind !== 'circle') return 2;\n\
+                        shape.radius = 1;\n\
+                        return s.length;\n\
+                      }\n\
+                      function g(shape: Shape) { if ('size' in shape) { return shape.size; } else { shape.radius = 2; } }\n";
+        let module = lower_source(source);
+        let narrowed = |stmts: &[Stmt]| -> Vec<(String, Type)> {
+            stmts.iter().filter_map(|s| match s {
+                Stmt::Let { name, ty, init: Some(Expr::Narrowed { .. }), .. } => Some((name.clone(), ty.clone())),
+                _ => None,
+            }).collect()
+        };
+        let branches = |stmts: &[Stmt]| -> Vec<(Vec<(String, Type)>, Vec<(String, Type)>)> {
+            stmts.iter().filter_map(|s| match s {
+                Stmt::If { then_branch, else_branch, .. } => {
+                    Some((narrowed(then_branch), else_branch.as_deref().map(narrowed).unwrap_or_default()))
+                }
+                _ => None,
+            }).collect()
+        };
+        let named = |name: &str| Type::Named(name.to_string());
+
+        // Early returns narrow the rest of the body: null checks, truthiness, typeof and
+        // the discriminant, composed when one condition tests the same local twice
+        let f = &module.functions[0];
+        assert_eq!(
+            narrowed(&f.body),
+            [
+                ("s".to_string(), Type::String),
+                ("n".to_string(), Type::Union(vec![Type::Number, Type::String])),
+                ("n".to_string(), Type::String),
+                ("shape".to_string(), named("Circle")),
+            ]
+        );
+        let ifs = branches(&f.body);
+        assert_eq!(ifs[2].0, [("n".to_string(), Type::Number)]);
+        // Equality with a literal keeps the members that could hold it
+        assert_eq!(ifs[3].0, [("mode".to_string(), Type::String)]);
+
+        // `in` keeps the members that have the property, and its negation the others
+        let g = branches(&module.functions[1].body);
+        assert_eq!(g[0], (vec![("shape".to_string(), named("Square"))], vec![("shape".to_string(), named("Circle"))]));
+
+        // Both writes are checked against the narrowed `Circle`
+        let readonly = module.diagnostics.iter().filter(|d| d.code == DiagnosticCode::ReadonlyAssignment).count();
+        assert_eq!(readonly, 2);
+    }
+
+    #[test]
+    fn test_symbol_dispose_method_key() {
+        let module = lower_source("class R {\n  [Symbol.dispose]() {}\n}\n");
+        assert!(module.classes[0].methods.iter().any(|m| m.name == SYMBOL_DISPOSE_KEY));
+    }
+
+    #[test]
+    fn test_default_params_evaluated_in_callee() {
+        let module = lower_source(
+            "function greet(name: string, greeting = 'hi', times = name.length) { return greeting; }\n\
+             const f = (x?: number, y = 2) => y;\n\
+             greet('a');\n\
+             f();\n",
+        );
+        let greet = &module.functions[0];
+        // One prologue check per non-optional default, in parameter order
+        let checks: Vec<LocalId> = greet.body.iter().filter_map(|s| match s {
+            Stmt::If { condition: Expr::Compare { op: CompareOp::Eq, left, right }, then_branch, else_branch: None }
+                if matches!(right.as_ref(), Expr::Undefined) =>
+            {
+                assert!(matches!(&then_branch[..], [Stmt::Expr(Expr::LocalSet(..))]));
+                match left.as_ref() { Expr::LocalGet(id) => Some(*id), _ => None }
+            }
+            _ => None,
+        }).collect();
+        assert_eq!(checks, vec![greet.params[1].id, greet.params[2].id]);
+        assert!(matches!(greet.body.last(), Some(Stmt::Return(_))));
+
+        // Call sites pass only the arguments that were written
+        let call_arg_counts: Vec<usize> = module.init.iter().filter_map(|s| match s {
+            Stmt::Expr(Expr::Call { args, .. }) => Some(args.len()),
+            _ => None,
+        }).collect();
+        assert_eq!(call_arg_counts, vec![1, 0]);
+
+        // Closures get the same prologue; the optional `x?` needs none
+        let closure_body = module.init.iter().find_map(|s| match s {
+            Stmt::Let { init: Some(Expr::Closure { body, .. }), .. } => Some(body),
+            _ => None,
+        }).expect("closure");
+        assert_eq!(closure_body.len(), 2);
+        assert!(matches!(closure_body[0], Stmt::If { .. }));
+    }
+
+    #[test]
+    fn test_spread_and_rest_arguments() {
+        let module = lower_source(
+            "class Log {\n\
+               constructor(prefix: string, ...tags: string[]) {}\n\
+               write(level: number, ...parts: string[]) {}\n\
+             }\n\
+             function sum(...ns: number[]) { return 0; }\n\
+             const xs: string[] = ['a', 'b'];\n\
+             const log = new Log('p', 'x', 'y');\n\
+             log.write(1, ...xs);\n\
+             log.write(...[2, 'c']);\n\
+             sum(1, ...[2, 3], ...xs);\n\
+             Math.max(...xs);\n",
+        );
+        // Rest arguments of a constructor are packed into an array at the call site
+        let new_args = module.init.iter().find_map(|s| match s {
+            Stmt::Let { init: Some(Expr::New { args, .. }), .. } => Some(args),
+            _ => None,
+        }).expect("new");
+        assert!(matches!(&new_args[..], [Expr::String(_), Expr::Array(tags)] if tags.len() == 2));
+
+        let calls: Vec<&Expr> = module.init.iter().filter_map(|s| match s {
+            Stmt::Expr(e) => Some(e),
+            _ => None,
+        }).collect();
+        // A spread array local fills the rest parameter with a slice
+        assert!(matches!(calls[0], Expr::Call { args, .. }
+            if matches!(&args[..], [_, Expr::ArraySlice { .. }])));
+        // An array literal spread is flattened, then packed like written arguments
+        assert!(matches!(calls[1], Expr::Call { args, .. }
+            if matches!(&args[..], [_, Expr::Array(parts)] if parts.len() == 1)));
+        // Module functions get one spread array, unpacked by codegen
+        assert!(matches!(calls[2], Expr::CallSpread { args, .. }
+            if matches!(&args[..], [CallArg::Spread(Expr::ArraySpread(elements))] if elements.len() == 4)));
+        // Math.max over a spread of unknown length reduces the array in a closure
+        assert!(matches!(calls[3], Expr::Call { callee, .. } if matches!(callee.as_ref(), Expr::Closure { .. })));
+    }
+
+    #[test]
+    fn test_for_of_over_iterable_class() {
+        let module = lower_source(
+            "class Counter {\n\
+               n = 0;\n\
+               next() { this.n++; return { done: this.n > 3, value: this.n }; }\n\
+             }\n\
+             class Range {\n\
+               [Symbol.iterator]() { return new Counter(); }\n\
+             }\n\
+             const r = new Range();\n\
+             for (const x of r) { console.log(x); }\n",
+        );
+        assert!(module.classes[1].methods.iter().any(|m| m.name == SYMBOL_ITERATOR_KEY));
+        // The iterator comes from r[Symbol.iterator]() and is typed as the class it returns
+        let iter = module.init.iter().find_map(|s| match s {
+            Stmt::Let { name, ty, init: Some(init), .. } if name.starts_with("__iter_") => Some((ty, init)),
+            _ => None,
+        });
+        let (ty, init) = iter.expect("iterator local");
+        assert_eq!(*ty, Type::Named("Counter".to_string()));
+        assert!(matches!(init, Expr::Call { callee, .. }
+            if matches!(callee.as_ref(), Expr::PropertyGet { property, .. } if property == SYMBOL_ITERATOR_KEY)));
+        assert!(module.init.iter().any(|s| matches!(s, Stmt::While { .. })));
+    }
+
+    #[test]
+    fn test_spread_and_array_from_drain_iterables() {
+        let module = lower_source(
+            "class Range {\n\
+               i = 0;\n\
+               [Symbol.iterator]() { return this; }\n\
+               next() { this.i++; return { done: this.i > 2, value: this.i }; }\n\
+             }\n\
+             const r = new Range();\n\
+             const a = [...r];\n\
+             const b = Array.from(r, (x: number) => x * 2);\n\
+             const c = [...\"ab\"];\n",
+        );
+        let init = |local: &str| module.init.iter().find_map(|s| match s {
+            Stmt::Let { name, init: Some(init), .. } if name == local => Some(init.clone()),
+            _ => None,
+        }).unwrap();
+        let drains_range = |expr: &Expr| matches!(expr, Expr::Call { callee, .. }
+            if matches!(callee.as_ref(), Expr::Closure { params, .. } if params[0].ty == Type::Named("Range".to_string())));
+        match init("a") {
+            Expr::ArraySpread(elements) => assert!(matches!(&elements[..], [ArrayElement::Spread(e)] if drains_range(e))),
+            other => panic!("expected array spread, got {:?}", other),
+        }
+        match init("b") {
+            Expr::ArrayMap { array, .. } => assert!(matches!(array.as_ref(), Expr::ArraySpread(elements)
+                if matches!(&elements[..], [ArrayElement::Spread(e)] if drains_range(e)))),
+            other => panic!("expected Array.from with a map function, got {:?}", other),
+        }
+        match init("c") {
+            Expr::ArraySpread(elements) => assert!(matches!(&elements[..], [ArrayElement::Spread(Expr::StringSplit(..))])),
+            other => panic!("expected array spread, got {:?}", other),
+        }
+    }
+
+    fn enum_object(module: &Module, name: &str) -> Vec<(String, Expr)> {
+        module.init.iter().find_map(|s| match s {
+            Stmt::Let { name: n, init: Some(Expr::Object(props)), .. } if n == name => Some(props.clone()),
+            _ => None,
+        }).unwrap()
+    }
+
+    #[test]
+    fn test_enum_object_with_reverse_mapping() {
+        let module = lower_source("enum Color { Red, Green = 5, Blue }\nenum Dir { Up = \"UP\" }\nconst enum Hidden { A }\n");
+        let keys: Vec<String> = enum_object(&module, "Color").into_iter().map(|(k, _)| k).collect();
+        // Array-index keys come first, like property order in JS
+        assert_eq!(keys, vec!["0", "5", "6", "Red", "Green", "Blue"]);
+        assert!(matches!(&enum_object(&module, "Color")[1].1, Expr::String(s) if s == "Green"));
+        // String members have no reverse mapping
+        assert_eq!(enum_object(&module, "Dir").len(), 1);
+        // const enums are erased
+        assert!(module.init.iter().all(|s| !matches!(s, Stmt::Let { name, .. } if name == "Hidden")));
+    }
+
+    #[test]
+    fn test_enum_constant_initializers() {
+        let module = lower_source(
+            "enum Flags { None = 0, Read = 1 << 0, Write = 1 << 1, RW = Read | Write, All = Flags.RW * 2, Neg = -1, Next }\n\
+             enum Mixed { A = 1, B = \"b\" + \"ee\", C = 3 }\n",
+        );
+        let values = |name: &str| module.enums.iter().find(|e| e.name == name).unwrap().members.iter()
+            .map(|m| match &m.value {
+                EnumValue::Number(n) => n.to_string(),
+                EnumValue::String(s) => s.clone(),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(values("Flags"), vec!["0", "1", "2", "3", "6", "-1", "0"]);
+        assert_eq!(values("Mixed"), vec!["1", "bee", "3"]);
+    }
+
+    #[test]
+    fn test_fastify_routes_recorded() {
+        let module = lower_source(
+            "import Fastify, { FastifyRequest } from 'fastify';\n\
+             interface User { id: number; name: string; }\n\
+             const app = Fastify();\n\
+             app.get('/users/:id', async (request: FastifyRequest<{ Params: { id: string } }>): Promise<User> => {\n\
+               return { id: 1, name: 'a' };\n\
+             });\n\
+             app.post<{ Body: User }>('/users', { schema: { body: { type: 'object' } } }, async (request, reply) => ({}));\n\
+             function health() { return 'ok'; }\n\
+             app.get('/health', health);\n\
+             const dynamic = '/x';\n\
+             app.get(dynamic, async () => ({}));\n",
+        );
+        let routes: Vec<(&str, &str)> = module.http_routes.iter()
+            .map(|r| (r.method.as_str(), r.path.as_str()))
+            .collect();
+        assert_eq!(routes, [("GET", "/users/:id"), ("POST", "/users"), ("GET", "/health")]);
+
+        let get = &module.http_routes[0];
+        assert!(matches!(&get.request_type, Some(Type::Object(obj)) if obj.properties.contains_key("Params")));
+        assert!(matches!(&get.reply_type, Some(Type::Promise(inner)) if **inner == Type::Named("User".to_string())));
+        assert!(get.schema.is_none());
+
+        let post = &module.http_routes[1];
+        assert!(matches!(&post.schema, Some(Expr::Object(props)) if props[0].0 == "body"));
+        assert!(matches!(&post.request_type, Some(Type::Object(obj)) if obj.properties.contains_key("Body")));
+
+        assert!(module.http_routes[2].handler_func.is_some());
+    }
+
+    #[test]
+    fn test_injectable_classes_registered() {
+        let module = lower_source(
+            "import { Injectable, Controller, Get, Post, Param, Body, Inject, Scope, REQUEST, resolve } from 'perry/di';\n\
+             @Injectable()\n\
+             class UsersService { find(id: string) { return id; } }\n\
+             @Injectable({ scope: Scope.REQUEST })\n\
+             class RequestContext { constructor(@Inject(REQUEST) public request: any) {} }\n\
+             @Controller('/users/')\n\
+             export class UsersController {\n\
+               constructor(private readonly users: UsersService, private context: RequestContext) {}\n\
+               @Get(':id') findOne(@Param('id') id: string): string { return this.users.find(id); }\n\
+               @Post() create(@Body() body: any, other: number) { return body; }\n\
+               helper() { return 1; }\n\
+             }\n\
+             const service = resolve(UsersService);\n",
+        );
+        let calls: Vec<(&str, &[Expr])> = module.init.iter()
+            .filter_map(|stmt| match stmt {
+                Stmt::Expr(Expr::NativeMethodCall { module, method, args, .. }) if module == "perry/di" => Some((method.as_str(), args.as_slice())),
+                _ => None,
+            })
+            .collect();
+        let strings = |args: &[Expr]| args.iter()
+            .filter_map(|arg| match arg {
+                Expr::String(s) => Some(s.clone()),
+                Expr::Number(n) => Some(n.to_string()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(calls.len(), 5);
+        assert_eq!(strings(calls[0].1), ["UsersService", "0", ""]);
+        assert_eq!(strings(calls[1].1), ["RequestContext", "2", "REQUEST"]);
+        assert_eq!(strings(calls[2].1), ["UsersController", "0", "UsersService,RequestContext"]);
+        assert!(matches!(&calls[2].1[3], Expr::Closure { params, .. } if params.len() == 2));
+        assert_eq!(strings(calls[3].1), ["UsersController", "GET", "/users/:id"]);
+        assert_eq!(strings(calls[4].1), ["UsersController", "POST", "/users"]);
+
+        let routes: Vec<(&str, &str)> = module.http_routes.iter()
+            .map(|r| (r.method.as_str(), r.path.as_str()))
+            .collect();
+        assert_eq!(routes, [("GET", "/users/:id"), ("POST", "/users")]);
+        assert_eq!(module.http_routes[0].reply_type, Some(Type::String));
+
+        // resolve() takes the class's name as its token
+        let Some(Stmt::Let { init: Some(Expr::NativeMethodCall { method, args, .. }), .. }) = module.init.last() else {
+            panic!("expected resolve call");
+        };
+        assert_eq!((method.as_str(), strings(args)), ("resolve", vec!["UsersService".to_string()]));
+    }
+
+    #[test]
+    fn test_injectable_parameter_needs_token() {
+        let source = "import { Injectable } from 'perry/di';\n\
+                      @Injectable() class Service { constructor(config) {} }\n";
+        let ast_module = perry_parser::parse_typescript(source, "test.ts").unwrap();
+        let err = lower_module(&ast_module, "test.ts", "/tmp/test.ts").unwrap_err();
+        assert!(err.to_string().contains("Cannot inject parameter 1 of Service"));
+    }
+
+    #[test]
+    fn test_spans_point_into_source() {
+        let source = "function fail(n: number) {\n  throw new Error('bad ' + n);\n}\nfail(1);\n";
+        let ast_module = perry_parser::parse_typescript(source, "test.ts").unwrap();
+        let options = LowerOptions { file_id: Some(FileId(0)), ..Default::default() };
+        let module = lower_module_with_options(&ast_module, "test.ts", "/tmp/test.ts", &options).unwrap();
+        let text = |span: Span| &source[span.start as usize..span.end as usize];
+
+        let func = &module.functions[0];
+        assert!(text(func.span).contains("fail(n: number)") && text(func.span).ends_with('}'));
+        let Stmt::Throw(Expr::ErrorNew { span, .. }) = &func.body[0] else { panic!("expected throw") };
+        assert_eq!(text(*span), "new Error('bad ' + n)");
+        let Some(Stmt::Expr(Expr::Call { span, .. })) = module.init.last() else { panic!("expected call") };
+        assert_eq!(text(*span), "fail(1)");
+
+        // Without a file the spans are dummies
+        assert!(lower_source(source).functions[0].span.is_dummy());
+    }
+
+    #[test]
+    fn test_unknown_properties_of_type_literals() {
+        let source = "function serve(opts: { port: number; readonly host?: string; log(msg: string): void }) {\n\
+                        opts.log(opts.hots + ':' + opts.port + opts.toString());\n\
+                        opts.timeout = 30; opts.prot++; opts.host;\n\
+                      }\n\
+                      function loose(map: { [key: string]: number }, value: any) { map.anything = 1; value.whatever; }\n";
+        let module = lower_source(source);
+        let unknown: Vec<(&str, &str)> = module.diagnostics.iter()
+            .filter(|d| d.code == DiagnosticCode::UnknownProperty)
+            .map(|d| (&source[d.span.start as usize..d.span.end as usize], d.suggestions.first().map_or("", |s| s.replacement.as_str())))
+            .collect();
+        assert_eq!(unknown, vec![("hots", "host"), ("timeout", ""), ("prot", "port")]);
+
+        // Method signatures become function-typed properties
+        let serve = module.functions.iter().find(|f| f.name == "serve").unwrap();
+        let Type::Object(opts) = &serve.params[0].ty else { panic!("{:?}", serve.params[0].ty) };
+        assert!(matches!(opts.properties["log"].ty, Type::Function(_)));
+        assert!(opts.properties["host"].optional && opts.properties["host"].readonly);
+    }
+
+    #[test]
+    fn test_interface_declarations_merge() {
+        let source = "interface Options { port: number; format(x: number): string }\n\
+                      export interface Options { host?: string; format(x: string): string }\n\
+                      declare global { interface Options { verbose: boolean } }\n\
+                      declare module './plugin' { export interface Options extends Base { plugin: string } }\n\
+                      namespace Helpers { export interface Options { unrelated: number } export const x = 1; }\n\
+                      function describe(opts: Options) { return opts.host; }\n";
+        let module = lower_source(source);
+        let merged: Vec<&Interface> = module.interfaces.iter().filter(|i| i.name == "Options").collect();
+        assert_eq!(merged.len(), 1);
+        let options = merged[0];
+        let props: Vec<&str> = options.properties.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(props, vec!["port", "host", "verbose", "plugin"]);
+        assert_eq!(options.extends, vec![Type::Named("Base".into())]);
+        assert!(options.is_exported);
+        // The later declaration's overload comes first
+        assert_eq!(options.methods.len(), 2);
+        assert_eq!(options.methods[0].params[0].1, Type::String);
+
+        // A namespace's values aren't compiled, which is reported
+        let warnings: Vec<&Diagnostic> = module.diagnostics.iter()
+            .filter(|d| d.code == DiagnosticCode::UnsupportedFeature)
+            .collect();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].message.contains("'Helpers'"));
+    }
+
+    #[test]
+    fn test_sql_template_binds_interpolations() {
+        let module = lower_source(
+            "import { Pool, sql } from 'pg';\n\
+             import * as mysql from 'mysql2/promise';\n\
+             const pool = new Pool({});\n\
+             const id = 7;\n\
+             pool.query(sql`SELECT * FROM users WHERE id = ${id} AND name = ${'x'}`);\n\
+             const q = mysql.sql`DELETE FROM t WHERE id = ${id}`;\n",
+        );
+        let args = module.init.iter().find_map(|s| match s {
+            Stmt::Expr(Expr::NativeMethodCall { method, args, .. }) if method == "query" => Some(args),
+            _ => None,
+        }).expect("expected a native query call");
+        assert!(matches!(&args[0], Expr::String(text) if text == "SELECT * FROM users WHERE id = $1 AND name = $2"));
+        assert!(matches!(&args[1], Expr::Array(values) if values.len() == 2));
+        // Outside query() it stays a { text, values } object, with mysql2's placeholders
+        let fields = module.init.iter().find_map(|s| match s {
+            Stmt::Let { init: Some(Expr::Object(fields)), .. } => Some(fields),
+            _ => None,
+        }).expect("expected a query config object");
+        assert!(matches!(&fields[0], (key, Expr::String(text)) if key == "text" && text == "DELETE FROM t WHERE id = ?"));
+    }
+
+    #[test]
+    fn test_other_tagged_templates_rejected() {
+        let ast_module = perry_parser::parse_typescript("const x = tag`a${1}`;\n", "test.ts").unwrap();
+        assert!(lower_module(&ast_module, "test.ts", "/tmp/test.ts").is_err());
+    }
+
+    #[test]
+    fn test_previously_dropped_statements_lowered() {
+        let module = lower_source(
+            "export function run(n: number) {\n\
+               let i = 0;\n\
+               do { i++; } while (i < n);\n\
+               return twice(i);\n\
+               function twice(x: number) { return x * 2; }\n\
+             }\n\
+             export default run;\n",
+        );
+        assert!(module.diagnostics.is_empty());
+        let run = module.functions.iter().find(|f| f.name == "run").unwrap();
+        // The nested function is hoisted to the top of the body
+        assert!(matches!(&run.body[0], Stmt::Let { name, init: Some(Expr::Closure { .. }), .. } if name == "twice"));
+        // do-while runs the body once before the first test
+        assert!(run.body.iter().any(|s| matches!(s, Stmt::While { condition: Expr::Logical { op: LogicalOp::Or, .. }, .. })));
+        assert!(module.exports.iter().any(|e| matches!(e, Export::Named { local, exported } if local == "run" && exported == "default")));
+    }
+
+    #[test]
+    fn test_dropped_constructs_reported() {
+        let module = lower_source(
+            "class Counter {\n\
+               static { Counter.ready = true; }\n\
+               #bump() {}\n\
+             }\n\
+             function scan(rows: number[][]) {\n\
+               class Local {}\n\
+               outer: for (const row of rows) {\n\
+                 for (const x of row) { if (x < 0) break outer; }\n\
+               }\n\
+             }\n",
+        );
+        let dropped: Vec<&str> = module.diagnostics.iter()
+            .filter(|d| d.code == DiagnosticCode::DroppedConstruct)
+            .map(|d| d.message.as_str())
+            .collect();
+        assert_eq!(dropped.len(), 4);
+        assert!(dropped[0].starts_with("static initialization block"));
+        assert!(dropped[1].starts_with("private method"));
+        assert!(dropped[2].starts_with("class 'Local'"));
+        assert!(dropped[3].starts_with("label of `break outer`"));
+        assert!(module.diagnostics.iter().all(|d| d.is_error()));
+    }
+
+    #[test]
+    fn test_memoize_directive_and_decorator() {
+        let module = lower_source(
+            "function fib(n: number): number {\n\
+               'use memoize';\n\
+               return n < 2 ? n : fib(n - 1) + fib(n - 2);\n\
+             }\n\
+             function rate(code: string): number { \"use memoize(100, 60000)\"; return code.length; }\n\
+             function plain(n: number): number { return n; }\n\
+             class Repo {\n\
+               @memoize(10)\n\
+               find(id: number): number { return id; }\n\
+             }\n",
+        );
+        let memoize = |name: &str| module.functions.iter().find(|f| f.name == name).unwrap().memoize();
+        assert_eq!(memoize("fib"), Some(Memoize { max_entries: DEFAULT_MEMOIZE_ENTRIES, ttl_ms: 0 }));
+        assert_eq!(memoize("rate"), Some(Memoize { max_entries: 100, ttl_ms: 60000 }));
+        assert_eq!(memoize("plain"), None);
+        assert_eq!(module.classes[0].methods[0].memoize(), Some(Memoize { max_entries: 10, ttl_ms: 0 }));
+
+        let ast_module = perry_parser::parse_typescript("function f() { 'use memoize(big)'; }\n", "test.ts").unwrap();
+        assert!(lower_module(&ast_module, "test.ts", "/tmp/test.ts").is_err());
+    }
+}
@@ -14,7 +14,10 @@
 //!   function identity_string(x: string): string { return x; }
 
 use std::collections::{HashMap, HashSet, VecDeque};
-use perry_types::{FuncId, ObjectType, Type};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use perry_diagnostics::{Diagnostic, DiagnosticCode, Span};
+use perry_types::{indexed, FuncId, ObjectType, PropertyInfo, Type};
 use crate::ir::*;
 
 /// Key for function specialization (func_id, mangled_type_args)
@@ -40,6 +43,37 @@ pub struct MonomorphizationContext {
     /// Set of already processed specializations (to avoid duplicates)
     processed_funcs: HashSet<FuncSpecKey>,
     processed_classes: HashSet<ClassSpecKey>,
+    /// Type arguments that violate their constraints (T010), reported once per call site
+    diagnostics: Vec<Diagnostic>,
+    /// Generics exported by other modules, by the name this module's code uses for them
+    imported: HashMap<String, ImportedGeneric>,
+    /// Specialized names of imported generics, by (name used here, mangled type args)
+    imported_specialized: HashMap<ClassSpecKey, String>,
+    /// Instantiations of imported generics: defining module, generic name, type arguments
+    imported_requests: Vec<(PathBuf, String, Vec<Type>)>,
+}
+
+/// A generic function or class exported by another module
+#[derive(Debug, Clone)]
+struct ImportedGeneric {
+    /// Module that defines it
+    path: PathBuf,
+    definition: Rc<GenericDefinition>,
+}
+
+#[derive(Debug, Clone)]
+enum GenericDefinition {
+    Function(Function),
+    Class(Class),
+}
+
+impl ImportedGeneric {
+    fn name(&self) -> &str {
+        match &*self.definition {
+            GenericDefinition::Function(func) => &func.name,
+            GenericDefinition::Class(class) => &class.name,
+        }
+    }
 }
 
 /// Request to specialize a function
@@ -86,6 +120,10 @@ impl MonomorphizationContext {
             next_class_id: max_class_id + 1000,
             processed_funcs: HashSet::new(),
             processed_classes: HashSet::new(),
+            diagnostics: Vec::new(),
+            imported: HashMap::new(),
+            imported_specialized: HashMap::new(),
+            imported_requests: Vec::new(),
         }
     }
 
@@ -148,6 +186,19 @@ impl MonomorphizationContext {
 
         new_name
     }
+
+    /// Record an instantiation of the imported generic `name`; the module defining it
+    /// generates the specialization
+    fn request_imported_specialization(&mut self, name: &str, type_args: Vec<Type>) {
+        let Some(generic) = self.imported.get(name) else { return };
+        let key = (name.to_string(), mangle_type_args(&type_args));
+        if self.imported_specialized.contains_key(&key) {
+            return;
+        }
+        let specialized_name = generate_specialized_name(generic.name(), &type_args);
+        self.imported_requests.push((generic.path.clone(), generic.name().to_string(), type_args));
+        self.imported_specialized.insert(key, specialized_name);
+    }
 }
 
 /// Mangle type arguments to a string for use as a hash key
@@ -202,8 +253,17 @@ fn mangle_type(ty: &Type) -> String {
             let parts: Vec<String> = types.iter().map(|t| mangle_type(t)).collect();
             format!("union_{}", parts.join("_"))
         }
+        Type::Intersection(types) => {
+            let parts: Vec<String> = types.iter().map(|t| mangle_type(t)).collect();
+            format!("and_{}", parts.join("_"))
+        }
         Type::Object(_) => "obj".to_string(),
         Type::Function(_) => "fn".to_string(),
+        Type::Literal(lit) => mangle_type(&lit.widened()),
+        Type::Conditional(_) => "cond".to_string(),
+        Type::Infer(name) => name.clone(),
+        Type::KeyOf(inner) => format!("keyof_{}", mangle_type(inner)),
+        Type::IndexedAccess { object, index } => format!("idx_{}_{}", mangle_type(object), mangle_type(index)),
     }
 }
 
@@ -318,6 +378,9 @@ fn infer_expr_type(expr: &Expr, module: &Module) -> Option<Type> {
         // InstanceOf always returns boolean
         Expr::InstanceOf { .. } => Some(Type::Boolean),
 
+        // A narrowed value has the type its guard established
+        Expr::Narrowed { ty, .. } => Some(ty.clone()),
+
         // For other expressions, we can't easily infer the type
         _ => None,
     }
@@ -464,12 +527,15 @@ fn type_contains_type_var(ty: &Type) -> bool {
         Type::Array(elem) => type_contains_type_var(elem),
         Type::Tuple(elems) => elems.iter().any(type_contains_type_var),
         Type::Promise(inner) => type_contains_type_var(inner),
-        Type::Union(types) => types.iter().any(type_contains_type_var),
+        Type::Union(types) | Type::Intersection(types) => types.iter().any(type_contains_type_var),
         Type::Generic { type_args, .. } => type_args.iter().any(type_contains_type_var),
         Type::Function(ft) => {
             ft.params.iter().any(|(_, t, _)| type_contains_type_var(t)) ||
             type_contains_type_var(&ft.return_type)
         }
+        Type::Conditional(_) | Type::KeyOf(_) | Type::IndexedAccess { .. } => {
+            ty.contains(|t| matches!(t, Type::TypeVar(_)))
+        }
         _ => false,
     }
 }
@@ -540,11 +606,34 @@ pub fn substitute_type(ty: &Type, substitutions: &HashMap<String, Type>) -> Type
         Type::Union(types) => {
             Type::Union(types.iter().map(|t| substitute_type(t, substitutions)).collect())
         }
+        Type::Intersection(types) => {
+            Type::intersection(types.iter().map(|t| substitute_type(t, substitutions)))
+        }
+        // Conditional types and utilities (ReturnType<T>, Awaited<T>) resolve once their
+        // type arguments are known
         Type::Generic { base, type_args } => {
             Type::Generic {
                 base: base.clone(),
                 type_args: type_args.iter().map(|t| substitute_type(t, substitutions)).collect(),
             }
+            .evaluate_conditionals()
+        }
+        Type::Conditional(_) => {
+            ty.map_types(&mut |t| match t {
+                Type::TypeVar(name) => substitutions.get(name).cloned(),
+                _ => None,
+            })
+            .evaluate_conditionals()
+        }
+        // `keyof T` and `T[K]` of object literal types; named types are resolved once the
+        // module's shapes are at hand (see `evaluate_indexed_types`)
+        Type::KeyOf(_) | Type::IndexedAccess { .. } => {
+            ty.map_types(&mut |t| match t {
+                Type::TypeVar(name) => substitutions.get(name).cloned(),
+                _ => None,
+            })
+            .evaluate_conditionals()
+            .evaluate_indexed(&indexed::structural_shape)
         }
         Type::Function(func_type) => {
             Type::Function(perry_types::FunctionType {
@@ -588,6 +677,22 @@ pub enum ConstraintError {
     },
 }
 
+impl std::fmt::Display for ConstraintError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConstraintError::TypeMismatch { type_param, expected, actual } => {
+                write!(f, "'{}' does not satisfy the constraint '{}' of type parameter '{}'", actual, expected, type_param)
+            }
+            ConstraintError::MissingProperty { type_param, interface, property } => {
+                write!(f, "type parameter '{}' requires property '{}' of '{}'", type_param, property, interface)
+            }
+            ConstraintError::MissingMethod { type_param, interface, method } => {
+                write!(f, "type parameter '{}' requires method '{}' of '{}'", type_param, method, interface)
+            }
+        }
+    }
+}
+
 /// Check if a concrete type satisfies a constraint.
 /// Returns Ok(()) if satisfied, Err with details if not.
 pub fn check_constraint(
@@ -596,6 +701,12 @@ pub fn check_constraint(
     constraint: &Type,
     module: &Module,
 ) -> Result<(), ConstraintError> {
+    // A literal type argument satisfies what its primitive does
+    if let Type::Literal(literal) = concrete_type {
+        if !matches!(constraint, Type::Literal(_)) {
+            return check_constraint(type_param, &literal.widened(), constraint, module);
+        }
+    }
     match constraint {
         // Named constraint - check if concrete type is or implements the interface
         Type::Named(name) => {
@@ -642,12 +753,22 @@ pub fn check_constraint(
             })
         }
 
+        // Intersection constraint - concrete type must satisfy every member
+        Type::Intersection(members) => members.iter()
+            .try_for_each(|member| check_constraint(type_param, concrete_type, member, module)),
+
+        // Object literal constraint - the concrete type needs each required property
+        Type::Object(required) => check_object_constraint(type_param, concrete_type, constraint, required, module),
+
         // Any/Unknown - everything satisfies these
         Type::Any | Type::Unknown => Ok(()),
 
-        // Other constraints default to type equality check
+        // Other constraints (functions, tuples, literals, ...) are only checked against
+        // primitives; inference widens literal arguments, so `string` may still be `"a"`
         _ => {
-            if types_satisfy(concrete_type, constraint) {
+            let primitive = matches!(concrete_type, Type::Number | Type::Int32 | Type::String | Type::Boolean | Type::BigInt);
+            let widened_literal = matches!(constraint, Type::Literal(lit) if types_satisfy(concrete_type, &lit.widened()));
+            if types_satisfy(concrete_type, constraint) || !primitive || widened_literal {
                 Ok(())
             } else {
                 Err(ConstraintError::TypeMismatch {
@@ -660,6 +781,55 @@ pub fn check_constraint(
     }
 }
 
+/// Check a concrete type against an object literal constraint such as `{ id: string }`
+fn check_object_constraint(
+    type_param: &str,
+    concrete_type: &Type,
+    constraint: &Type,
+    required: &ObjectType,
+    module: &Module,
+) -> Result<(), ConstraintError> {
+    let shape = match concrete_type {
+        Type::Object(_) | Type::Named(_) => match module_shape(module, concrete_type, 0) {
+            Some(shape) => shape,
+            // Types declared elsewhere can't be inspected
+            None => return Ok(()),
+        },
+        // Strings and arrays only bring `length`; other primitives have no properties
+        Type::String | Type::Array(_) | Type::Tuple(_) => {
+            let mut shape = ObjectType::default();
+            let length = PropertyInfo { ty: Type::Number, optional: false, readonly: true };
+            shape.properties.insert("length".to_string(), length);
+            shape
+        }
+        Type::Number | Type::Int32 | Type::Boolean | Type::BigInt | Type::Null | Type::Void => ObjectType::default(),
+        _ => return Ok(()),
+    };
+    let mut names: Vec<&String> = required.properties.keys().collect();
+    names.sort();
+    for name in names {
+        let expected = &required.properties[name];
+        if expected.optional {
+            continue;
+        }
+        let Some(actual) = shape.property(name) else {
+            return Err(ConstraintError::MissingProperty {
+                type_param: type_param.to_string(),
+                interface: constraint.to_string(),
+                property: name.clone(),
+            });
+        };
+        if check_constraint(type_param, &actual.ty, &expected.ty, module).is_err() {
+            return Err(ConstraintError::TypeMismatch {
+                type_param: type_param.to_string(),
+                expected: constraint.clone(),
+                actual: concrete_type.clone(),
+            });
+        }
+    }
+    Ok(())
+}
+
 /// Check if a concrete type satisfies a named (interface/class) constraint
 fn check_named_constraint(
     type_param: &str,
@@ -735,6 +905,23 @@ fn check_interface_satisfaction(
                 }
             }
         }
+        Type::Number | Type::Int32 | Type::Boolean | Type::BigInt => {
+            // Other primitives have no properties to satisfy the interface with
+            if let Some(prop) = interface.properties.iter().find(|p| !p.optional) {
+                return Err(ConstraintError::MissingProperty {
+                    type_param: type_param.to_string(),
+                    interface: interface.name.clone(),
+                    property: prop.name.clone(),
+                });
+            }
+            if let Some(method) = interface.methods.first() {
+                return Err(ConstraintError::MissingMethod {
+                    type_param: type_param.to_string(),
+                    interface: interface.name.clone(),
+                    method: method.name.clone(),
+                });
+            }
+        }
         Type::Object(obj_type) => {
             // Check all required interface properties exist in object
             for prop in &interface.properties {
@@ -890,10 +1077,11 @@ fn substitute_expr(expr: &Expr, substitutions: &HashMap<String, Type>) -> Expr {
         },
 
         // Function call
-        Expr::Call { callee, args, type_args } => Expr::Call {
+        Expr::Call { callee, args, type_args, span } => Expr::Call {
             callee: Box::new(substitute_expr(callee, substitutions)),
             args: args.iter().map(|a| substitute_expr(a, substitutions)).collect(),
             type_args: type_args.iter().map(|t| substitute_type(t, substitutions)).collect(),
+            span: *span,
         },
 
         // References
@@ -967,15 +1155,20 @@ fn substitute_expr(expr: &Expr, substitutions: &HashMap<String, Type>) -> Expr {
             expr: Box::new(substitute_expr(expr, substitutions)),
             ty: ty.clone(),
         },
+        Expr::Narrowed { value, ty } => Expr::Narrowed {
+            value: Box::new(substitute_expr(value, substitutions)),
+            ty: substitute_type(ty, substitutions),
+        },
 
         // Await
         Expr::Await(inner) => Expr::Await(Box::new(substitute_expr(inner, substitutions))),
 
         // New
-        Expr::New { class_name, args, type_args } => Expr::New {
+        Expr::New { class_name, args, type_args, span } => Expr::New {
             class_name: class_name.clone(),
             args: args.iter().map(|a| substitute_expr(a, substitutions)).collect(),
             type_args: type_args.iter().map(|t| substitute_type(t, substitutions)).collect(),
+            span: *span,
         },
 
         // Class/Enum references
@@ -1015,6 +1208,8 @@ fn substitute_expr(expr: &Expr, substitutions: &HashMap<String, Type>) -> Expr {
         Expr::EnvGet(name) => Expr::EnvGet(name.clone()),
         Expr::ProcessUptime => Expr::ProcessUptime,
         Expr::ProcessMemoryUsage => Expr::ProcessMemoryUsage,
+        Expr::ProcessStdinRead => Expr::ProcessStdinRead,
+        Expr::ProcessStdinPipe(fd) => Expr::ProcessStdinPipe(*fd),
 
         // File system
         Expr::FsReadFileSync(path) => Expr::FsReadFileSync(Box::new(substitute_expr(path, substitutions))),
@@ -1025,6 +1220,7 @@ fn substitute_expr(expr: &Expr, substitutions: &HashMap<String, Type>) -> Expr {
         Expr::FsExistsSync(path) => Expr::FsExistsSync(Box::new(substitute_expr(path, substitutions))),
         Expr::FsMkdirSync(path) => Expr::FsMkdirSync(Box::new(substitute_expr(path, substitutions))),
         Expr::FsUnlinkSync(path) => Expr::FsUnlinkSync(Box::new(substitute_expr(path, substitutions))),
+        Expr::FsCreateReadStream(path) => Expr::FsCreateReadStream(Box::new(substitute_expr(path, substitutions))),
         Expr::FsAppendFileSync(path, content) => Expr::FsAppendFileSync(
             Box::new(substitute_expr(path, substitutions)),
             Box::new(substitute_expr(content, substitutions)),
@@ -1036,6 +1232,41 @@ fn substitute_expr(expr: &Expr, substitutions: &HashMap<String, Type>) -> Expr {
             Box::new(substitute_expr(b, substitutions)),
         ),
         Expr::PathDirname(path) => Expr::PathDirname(Box::new(substitute_expr(path, substitutions))),
+        Expr::ProcessSetMemoryLimit { limit, policy } => Expr::ProcessSetMemoryLimit {
+            limit: Box::new(substitute_expr(limit, substitutions)),
+            policy: policy.as_ref().map(|p| Box::new(substitute_expr(p, substitutions))),
+        },
+        Expr::ProcessOnLowMemory(cb) => Expr::ProcessOnLowMemory(Box::new(substitute_expr(cb, substitutions))),
+        Expr::ProcessStdioWrite { fd, data, callback } => Expr::ProcessStdioWrite {
+            fd: *fd,
+            data: Box::new(substitute_expr(data, substitutions)),
+            callback: callback.as_ref().map(|cb| Box::new(substitute_expr(cb, substitutions))),
+        },
+        Expr::ProcessStdinOn { event, listener } => Expr::ProcessStdinOn {
+            event: Box::new(substitute_expr(event, substitutions)),
+            listener: Box::new(substitute_expr(listener, substitutions)),
+        },
+        Expr::DisposeResource { resource, is_async } => Expr::DisposeResource {
+            resource: Box::new(substitute_expr(resource, substitutions)),
+            is_async: *is_async,
+        },
+        Expr::DisposableStackAdopt { stack, value, on_dispose } => Expr::DisposableStackAdopt {
+            stack: Box::new(substitute_expr(stack, substitutions)),
+            value: Box::new(substitute_expr(value, substitutions)),
+            on_dispose: Box::new(substitute_expr(on_dispose, substitutions)),
+        },
+        Expr::DisposableStackDefer { stack, on_dispose } => Expr::DisposableStackDefer {
+            stack: Box::new(substitute_expr(stack, substitutions)),
+            on_dispose: Box::new(substitute_expr(on_dispose, substitutions)),
+        },
+        Expr::DisposableStackDispose(stack) => Expr::DisposableStackDispose(Box::new(substitute_expr(stack, substitutions))),
+        Expr::DisposableStackMove(stack) => Expr::DisposableStackMove(Box::new(substitute_expr(stack, substitutions))),
+        Expr::DisposableStackDisposed(stack) => Expr::DisposableStackDisposed(Box::new(substitute_expr(stack, substitutions))),
+        Expr::ImportMetaResolve { module_path, exe_relative, specifier } => Expr::ImportMetaResolve {
+            module_path: module_path.clone(),
+            exe_relative: *exe_relative,
+            specifier: Box::new(substitute_expr(specifier, substitutions)),
+        },
         Expr::PathBasename(path) => Expr::PathBasename(Box::new(substitute_expr(path, substitutions))),
         Expr::PathExtname(path) => Expr::PathExtname(Box::new(substitute_expr(path, substitutions))),
         Expr::PathResolve(path) => Expr::PathResolve(Box::new(substitute_expr(path, substitutions))),
@@ -1130,6 +1361,9 @@ fn substitute_expr(expr: &Expr, substitutions: &HashMap<String, Type>) -> Expr {
         },
         Expr::MapSize(map) => Expr::MapSize(Box::new(substitute_expr(map, substitutions))),
         Expr::MapClear(map) => Expr::MapClear(Box::new(substitute_expr(map, substitutions))),
+        Expr::MapKeys(map) => Expr::MapKeys(Box::new(substitute_expr(map, substitutions))),
+        Expr::MapValues(map) => Expr::MapValues(Box::new(substitute_expr(map, substitutions))),
+        Expr::MapEntries(map) => Expr::MapEntries(Box::new(substitute_expr(map, substitutions))),
 
         // Set operations
         Expr::SetNew => Expr::SetNew,
@@ -1147,10 +1381,12 @@ fn substitute_expr(expr: &Expr, substitutions: &HashMap<String, Type>) -> Expr {
         },
         Expr::SetSize(set) => Expr::SetSize(Box::new(substitute_expr(set, substitutions))),
         Expr::SetClear(set) => Expr::SetClear(Box::new(substitute_expr(set, substitutions))),
+        Expr::SetValues(set) => Expr::SetValues(Box::new(substitute_expr(set, substitutions))),
 
         // JSON operations
         Expr::JsonParse(expr) => Expr::JsonParse(Box::new(substitute_expr(expr, substitutions))),
         Expr::JsonStringify(expr) => Expr::JsonStringify(Box::new(substitute_expr(expr, substitutions))),
+        Expr::JsonCanonicalize(expr) => Expr::JsonCanonicalize(Box::new(substitute_expr(expr, substitutions))),
 
         // Math operations
         Expr::MathFloor(expr) => Expr::MathFloor(Box::new(substitute_expr(expr, substitutions))),
@@ -1254,6 +1490,7 @@ fn substitute_expr(expr: &Expr, substitutions: &HashMap<String, Type>) -> Expr {
         Expr::IsFinite(value) => Expr::IsFinite(Box::new(substitute_expr(value, substitutions))),
         // JS Runtime expressions - pass through unchanged (no type substitution needed)
         Expr::JsLoadModule { path } => Expr::JsLoadModule { path: path.clone() },
+        Expr::NativeAddonLoad { path } => Expr::NativeAddonLoad { path: path.clone() },
         Expr::JsGetExport { module_handle, export_name } => Expr::JsGetExport {
             module_handle: Box::new(substitute_expr(module_handle, substitutions)),
             export_name: export_name.clone(),
@@ -1370,6 +1607,7 @@ pub fn specialize_function(
         is_exported: false, // Specialized versions are internal
         captures: func.captures.clone(),
         decorators: func.decorators.clone(),
+        span: func.span,
     }
 }
 
@@ -1422,6 +1660,7 @@ pub fn specialize_class(
                 is_exported: false,
                 captures: ctor.captures.clone(),
                 decorators: ctor.decorators.clone(),
+                span: ctor.span,
             }
         }),
         methods: class.methods.iter().map(|m| {
@@ -1442,6 +1681,7 @@ pub fn specialize_class(
                 is_exported: false,
                 captures: m.captures.clone(),
                 decorators: m.decorators.clone(),
+                span: m.span,
             }
         }).collect(),
         getters: class.getters.iter().map(|(name, f)| {
@@ -1456,6 +1696,7 @@ pub fn specialize_class(
                 is_exported: false,
                 captures: f.captures.clone(),
                 decorators: f.decorators.clone(),
+                span: f.span,
             })
         }).collect(),
         setters: class.setters.iter().map(|(name, f)| {
@@ -1476,6 +1717,7 @@ pub fn specialize_class(
                 is_exported: false,
                 captures: f.captures.clone(),
                 decorators: f.decorators.clone(),
+                span: f.span,
             })
         }).collect(),
         static_fields: class.static_fields.clone(),
@@ -1487,7 +1729,144 @@ pub fn specialize_class(
 /// Main monomorphization pass
 /// Processes the module and generates specialized versions of generic functions/classes
 pub fn monomorphize_module(module: &mut Module) {
+    monomorphize_with_imports(module, HashMap::new(), Vec::new());
+}
+
+/// Monomorphize all modules of a program together. Generics instantiated across module
+/// boundaries (`new Box<number>()` on a `Box` imported from another file, a call to an
+/// imported generic function) are specialized once, in the module that defines them,
+/// however many modules instantiate them; the specializations are exported and the
+/// importing call sites refer to them by name. `resolve` maps an export source (`./box`)
+/// of a module to the module's path, for following re-exports.
+pub fn monomorphize_program(
+    modules: &mut HashMap<PathBuf, Module>,
+    resolve: &dyn Fn(&Path, &str) -> Option<PathBuf>,
+) {
+    let mut paths: Vec<PathBuf> = modules.keys().cloned().collect();
+    paths.sort();
+    let mut imported: HashMap<PathBuf, HashMap<String, ImportedGeneric>> = paths.iter()
+        .map(|path| (path.clone(), imported_generics(&modules[path], modules, resolve)))
+        .collect();
+
+    // Find the instantiations of imported generics in every module first, so each
+    // defining module generates all the specializations the program needs in one pass
+    let mut requested: HashMap<PathBuf, Vec<(String, Vec<Type>)>> = HashMap::new();
+    let mut seen = HashSet::new();
+    for path in &paths {
+        if imported[path].is_empty() {
+            continue;
+        }
+        let module = &modules[path];
+        let mut ctx = MonomorphizationContext::new(module);
+        ctx.imported = imported[path].clone();
+        collect_instantiations(module, &mut ctx);
+        for (definer, name, type_args) in ctx.imported_requests {
+            if seen.insert((definer.clone(), name.clone(), mangle_type_args(&type_args))) {
+                requested.entry(definer).or_default().push((name, type_args));
+            }
+        }
+    }
+
+    for path in &paths {
+        let module = modules.get_mut(path).expect("path was collected from the map");
+        let imports = imported.remove(path).unwrap_or_default();
+        monomorphize_with_imports(module, imports, requested.remove(path).unwrap_or_default());
+    }
+}
+
+/// The generic functions and classes `module` imports from other native modules, keyed
+/// the way its code refers to them: classes by local name, functions by the exported name
+/// `ExternFuncRef` carries
+fn imported_generics(
+    module: &Module,
+    modules: &HashMap<PathBuf, Module>,
+    resolve: &dyn Fn(&Path, &str) -> Option<PathBuf>,
+) -> HashMap<String, ImportedGeneric> {
+    let mut imported = HashMap::new();
+    for import in &module.imports {
+        let (ModuleKind::NativeCompiled, Some(resolved)) = (&import.module_kind, &import.resolved_path) else {
+            continue;
+        };
+        for spec in &import.specifiers {
+            let (name, local) = match spec {
+                ImportSpecifier::Named { imported, local } => (imported.as_str(), local.as_str()),
+                ImportSpecifier::Default { local } => ("default", local.as_str()),
+                ImportSpecifier::Namespace { .. } => continue,
+            };
+            let Some(generic) = find_exported_generic(modules, Path::new(resolved), name, resolve, 0) else {
+                continue;
+            };
+            let key = match *generic.definition {
+                GenericDefinition::Function(_) => name,
+                GenericDefinition::Class(_) => local,
+            };
+            imported.insert(key.to_string(), generic);
+        }
+    }
+    imported
+}
+
+/// The generic that `path` exports as `name`, following re-exports
+fn find_exported_generic(
+    modules: &HashMap<PathBuf, Module>,
+    path: &Path,
+    name: &str,
+    resolve: &dyn Fn(&Path, &str) -> Option<PathBuf>,
+    depth: usize,
+) -> Option<ImportedGeneric> {
+    if depth > 16 {
+        return None;
+    }
+    let module = modules.get(path)?;
+    let exported_name = |local: &str| {
+        module.exports.iter().any(|export| matches!(export, Export::Named { local: l, exported } if l == local && exported == name))
+    };
+    let found = |definition| Some(ImportedGeneric { path: path.to_path_buf(), definition: Rc::new(definition) });
+    if let Some(func) = module.functions.iter()
+        .find(|f| f.is_exported && !f.type_params.is_empty() && (f.name == name || exported_name(&f.name)))
+    {
+        return found(GenericDefinition::Function(func.clone()));
+    }
+    if let Some(class) = module.classes.iter()
+        .find(|c| c.is_exported && !c.type_params.is_empty() && (c.name == name || exported_name(&c.name)))
+    {
+        return found(GenericDefinition::Class(class.clone()));
+    }
+    for export in &module.exports {
+        let (source, inner) = match export {
+            Export::ReExport { source, imported, exported } if exported == name => (source, imported.as_str()),
+            // `export *` doesn't re-export the default export
+            Export::ExportAll { source } if name != "default" => (source, name),
+            _ => continue,
+        };
+        let Some(source_path) = resolve(path, source) else { continue };
+        if let Some(generic) = find_exported_generic(modules, &source_path, inner, resolve, depth + 1) {
+            return Some(generic);
+        }
+    }
+    None
+}
+
+/// Monomorphize one module. `imported` are the generics it uses from other modules, and
+/// `requested` the instantiations of its own exported generics that other modules need.
+fn monomorphize_with_imports(
+    module: &mut Module,
+    imported: HashMap<String, ImportedGeneric>,
+    requested: Vec<(String, Vec<Type>)>,
+) {
     let mut ctx = MonomorphizationContext::new(module);
+    ctx.imported = imported;
+
+    // Other modules' instantiations of this module's generics
+    let mut exported_names = HashSet::new();
+    for (name, type_args) in requested {
+        exported_names.insert(generate_specialized_name(&name, &type_args));
+        if let Some(func) = module.functions.iter().find(|f| f.name == name && !f.type_params.is_empty()) {
+            ctx.request_func_specialization(func.id, type_args);
+        } else if module.classes.iter().any(|c| c.name == name && !c.type_params.is_empty()) {
+            ctx.request_class_specialization(&name, type_args);
+        }
+    }
 
     // First pass: collect all generic instantiations from the code
     collect_instantiations(module, &mut ctx);
@@ -1495,6 +1874,7 @@ pub fn monomorphize_module(module: &mut Module) {
     // Process work queues until empty
     let mut new_functions = Vec::new();
     let mut new_classes = Vec::new();
+    let mut specializations = Vec::new();
 
     while !ctx.func_work_queue.is_empty() || !ctx.class_work_queue.is_empty() {
         // Process function specializations
@@ -1508,14 +1888,13 @@ pub fn monomorphize_module(module: &mut Module) {
 
             // Find the original function
             if let Some(original) = module.functions.iter().find(|f| f.id == request.original_id) {
-                // Check type parameter constraints
-                if let Err(errors) = check_function_constraints(original, &request.type_args, module) {
-                    for err in errors {
-                        eprintln!("Warning: Constraint violation in function '{}': {:?}", original.name, err);
-                    }
-                    // Continue with specialization even on constraint errors (for now)
-                }
-                let specialized = specialize_function(original, &request.type_args, request.new_id);
+                let mut specialized = specialize_function(original, &request.type_args, request.new_id);
+                evaluate_indexed_types(&mut specialized, module);
+                specializations.push(Specialization {
+                    name: specialized.name.clone(),
+                    generic: original.name.clone(),
+                    type_args: request.type_args.clone(),
+                });
                 new_functions.push(specialized);
             }
         }
@@ -1531,29 +1910,124 @@ pub fn monomorphize_module(module: &mut Module) {
 
             // Find the original class
             if let Some(original) = module.classes.iter().find(|c| c.name == request.original_name) {
-                // Check type parameter constraints
-                if let Err(errors) = check_class_constraints(original, &request.type_args, module) {
-                    for err in errors {
-                        eprintln!("Warning: Constraint violation in class '{}': {:?}", original.name, err);
-                    }
-                    // Continue with specialization even on constraint errors (for now)
-                }
                 let new_id = ctx.fresh_class_id();
-                let specialized = specialize_class(original, &request.type_args, new_id);
+                let mut specialized = specialize_class(original, &request.type_args, new_id);
+                for field in specialized.fields.iter_mut().chain(specialized.static_fields.iter_mut()) {
+                    field.ty = field.ty.evaluate_indexed(&|ty| module_shape(module, ty, 0));
+                }
+                for method in specialized.constructor.iter_mut()
+                    .chain(specialized.methods.iter_mut())
+                    .chain(specialized.static_methods.iter_mut())
+                {
+                    evaluate_indexed_types(method, module);
+                }
+                specializations.push(Specialization {
+                    name: specialized.name.clone(),
+                    generic: original.name.clone(),
+                    type_args: request.type_args.clone(),
+                });
                 new_classes.push(specialized);
             }
         }
     }
 
-    // Add specialized functions and classes to the module
+    // Add specialized functions and classes to the module; the ones other modules
+    // instantiate are exported for them to link against
+    for func in &mut new_functions {
+        func.is_exported |= exported_names.contains(&func.name);
+    }
+    for class in &mut new_classes {
+        class.is_exported |= exported_names.contains(&class.name);
+    }
     module.functions.extend(new_functions);
     module.classes.extend(new_classes);
+    module.specializations.extend(specializations);
 
     // Update call sites to use specialized versions
     update_call_sites(module, &ctx);
+    import_specializations(module, &ctx);
+    module.diagnostics.append(&mut ctx.diagnostics);
+
+    // Pass undefined for omitted constructor arguments
+    pad_constructor_arguments(module);
+}
+
+/// Import the specializations of other modules' generics the call sites now refer to, so
+/// the code generator registers the specialized classes
+fn import_specializations(module: &mut Module, ctx: &MonomorphizationContext) {
+    let mut keys: Vec<&ClassSpecKey> = ctx.imported_specialized.keys().collect();
+    keys.sort();
+    for key in keys {
+        let specialized_name = &ctx.imported_specialized[key];
+        let generic = &ctx.imported[&key.0];
+        let resolved = generic.path.to_string_lossy().to_string();
+        let spec = ImportSpecifier::Named { imported: specialized_name.clone(), local: specialized_name.clone() };
+        match module.imports.iter_mut().find(|i| i.resolved_path.as_deref() == Some(resolved.as_str())) {
+            Some(import) if import.specifiers.contains(&spec) => {}
+            Some(import) => import.specifiers.push(spec),
+            None => module.imports.push(Import {
+                source: resolved.clone(),
+                specifiers: vec![spec],
+                is_native: false,
+                module_kind: ModuleKind::NativeCompiled,
+                resolved_path: Some(resolved),
+                is_require: false,
+            }),
+        }
+    }
+}
+
+/// Evaluate the `keyof` and indexed access types in a specialized function's signature
+/// over named types, which `substitute_type` can't see into
+fn evaluate_indexed_types(func: &mut Function, module: &Module) {
+    let shape = |ty: &Type| module_shape(module, ty, 0);
+    for param in &mut func.params {
+        param.ty = param.ty.evaluate_indexed(&shape);
+    }
+    func.return_type = func.return_type.evaluate_indexed(&shape);
+}
 
-    // Fill in default arguments for constructor calls
-    fill_default_arguments(module);
+/// Object shape of an object literal type or of an interface, class or object type alias
+/// declared in the module, including inherited interface properties
+fn module_shape(module: &Module, ty: &Type, depth: usize) -> Option<ObjectType> {
+    if depth > 16 {
+        return None;
+    }
+    let Type::Named(name) = ty else {
+        return indexed::structural_shape(ty);
+    };
+    if let Some(iface) = module.interfaces.iter().find(|i| i.name == *name) {
+        let mut shape = ObjectType { name: Some(name.clone()), ..ObjectType::default() };
+        for parent in &iface.extends {
+            if let Some(inherited) = module_shape(module, parent, depth + 1) {
+                shape.merge(inherited);
+            }
+        }
+        for prop in &iface.properties {
+            let info = PropertyInfo { ty: prop.ty.clone(), optional: prop.optional, readonly: prop.readonly };
+            shape.properties.insert(prop.name.clone(), info);
+        }
+        for method in &iface.methods {
+            let ty = Type::Function(perry_types::FunctionType {
+                params: method.params.clone(),
+                return_type: Box::new(method.return_type.clone()),
+                is_async: false,
+                is_generator: false,
+            });
+            shape.properties.insert(method.name.clone(), PropertyInfo { ty, optional: false, readonly: false });
+        }
+        return Some(shape);
+    }
+    if let Some(class) = module.classes.iter().find(|c| c.name == *name) {
+        let mut shape = ObjectType { name: Some(name.clone()), ..ObjectType::default() };
+        for field in &class.fields {
+            let info = PropertyInfo { ty: field.ty.clone(), optional: false, readonly: field.is_readonly };
+            shape.properties.insert(field.name.clone(), info);
+        }
+        return Some(shape);
+    }
+    let alias = module.type_aliases.iter().find(|a| a.name == *name && a.type_params.is_empty())?;
+    module_shape(module, &alias.ty, depth + 1)
 }
 
 /// Collect all generic instantiations from the module
@@ -1642,10 +2116,36 @@ fn collect_instantiations_in_stmt(stmt: &Stmt, ctx: &mut MonomorphizationContext
     }
 }
 
+/// Report the constraint violations of one generic call or `new`. Type arguments that
+/// still refer to type parameters (calls inside another generic body) are checked when
+/// the enclosing generic is specialized at its own call sites instead.
+fn report_constraint_violations(
+    ctx: &mut MonomorphizationContext,
+    generic: &str,
+    violations: Option<Vec<ConstraintError>>,
+    span: Span,
+) {
+    for violation in violations.into_iter().flatten() {
+        if let ConstraintError::TypeMismatch { actual, .. } = &violation {
+            if actual.depends_on_type_params() {
+                continue;
+            }
+        }
+        let message = format!("Type argument of '{}' violates its constraint: {}", generic, violation);
+        if ctx.diagnostics.iter().any(|d| d.span == span && d.message == message) {
+            continue;
+        }
+        let diagnostic = Diagnostic::error(DiagnosticCode::ConstraintViolation, message)
+            .with_span(span)
+            .with_help("pass a value whose type satisfies the constraint, or widen the `extends` clause");
+        ctx.diagnostics.push(diagnostic.build());
+    }
+}
+
 fn collect_instantiations_in_expr(expr: &Expr, ctx: &mut MonomorphizationContext, module: &Module) {
     match expr {
         // Check for generic function calls
-        Expr::Call { callee, args, type_args } => {
+        Expr::Call { callee, args, type_args, span } => {
             // First collect in the callee and args
             collect_instantiations_in_expr(callee, ctx, module);
             for arg in args {
@@ -1666,15 +2166,32 @@ fn collect_instantiations_in_expr(expr: &Expr, ctx: &mut MonomorphizationContext
                         };
 
                         if let Some(ta) = resolved_type_args {
+                            let violations = check_function_constraints(func, &ta, module).err();
+                            report_constraint_violations(ctx, &func.name, violations, *span);
                             ctx.request_func_specialization(*func_id, ta);
                         }
                     }
                 }
+            } else if let Expr::ExternFuncRef { name, .. } = callee.as_ref() {
+                // Generic function imported from another module
+                let imported = ctx.imported.get(name).map(|g| Rc::clone(&g.definition));
+                if let Some(GenericDefinition::Function(func)) = imported.as_deref() {
+                    let resolved_type_args = if !type_args.is_empty() {
+                        Some(type_args.clone())
+                    } else {
+                        infer_type_args(func, args, module)
+                    };
+                    if let Some(ta) = resolved_type_args.filter(|ta| !ta.iter().any(Type::depends_on_type_params)) {
+                        let violations = check_function_constraints(func, &ta, module).err();
+                        report_constraint_violations(ctx, &func.name, violations, *span);
+                        ctx.request_imported_specialization(name, ta);
+                    }
+                }
             }
         }
 
         // Check for generic class instantiation
-        Expr::New { class_name, args, type_args } => {
+        Expr::New { class_name, args, type_args, span } => {
             for arg in args {
                 collect_instantiations_in_expr(arg, ctx, module);
             }
@@ -1693,9 +2210,25 @@ fn collect_instantiations_in_expr(expr: &Expr, ctx: &mut MonomorphizationContext
                     };
 
                     if let Some(ta) = resolved_type_args {
+                        let violations = check_class_constraints(class, &ta, module).err();
+                        report_constraint_violations(ctx, class_name, violations, *span);
                         ctx.request_class_specialization(class_name, ta);
                     }
                 }
+            } else if let Some(GenericDefinition::Class(class)) = ctx.imported.get(class_name).map(|g| Rc::clone(&g.definition)).as_deref() {
+                // Generic class imported from another module
+                let resolved_type_args = if !type_args.is_empty() {
+                    Some(type_args.clone())
+                } else if let Some(ref ctor) = class.constructor {
+                    infer_type_args_for_class(class, ctor, args, module)
+                } else {
+                    None
+                };
+                if let Some(ta) = resolved_type_args.filter(|ta| !ta.iter().any(Type::depends_on_type_params)) {
+                    let violations = check_class_constraints(class, &ta, module).err();
+                    report_constraint_violations(ctx, &class.name, violations, *span);
+                    ctx.request_imported_specialization(class_name, ta);
+                }
             }
         }
 
@@ -1755,6 +2288,7 @@ fn collect_instantiations_in_expr(expr: &Expr, ctx: &mut MonomorphizationContext
         }
         Expr::TypeOf(inner) => collect_instantiations_in_expr(inner, ctx, module),
         Expr::InstanceOf { expr, .. } => collect_instantiations_in_expr(expr, ctx, module),
+        Expr::Narrowed { value, .. } => collect_instantiations_in_expr(value, ctx, module),
         Expr::Await(inner) => collect_instantiations_in_expr(inner, ctx, module),
         Expr::SuperCall(args) => {
             for arg in args {
@@ -1771,7 +2305,7 @@ fn collect_instantiations_in_expr(expr: &Expr, ctx: &mut MonomorphizationContext
             collect_instantiations_in_expr(path, ctx, module);
             collect_instantiations_in_expr(content, ctx, module);
         }
-        Expr::FsExistsSync(path) | Expr::FsMkdirSync(path) | Expr::FsUnlinkSync(path) => {
+        Expr::FsExistsSync(path) | Expr::FsMkdirSync(path) | Expr::FsUnlinkSync(path) | Expr::FsCreateReadStream(path) => {
             collect_instantiations_in_expr(path, ctx, module);
         }
         Expr::FsAppendFileSync(path, content) => {
@@ -1785,6 +2319,43 @@ fn collect_instantiations_in_expr(expr: &Expr, ctx: &mut MonomorphizationContext
         Expr::PathDirname(p) | Expr::PathBasename(p) | Expr::PathExtname(p) | Expr::PathResolve(p) => {
             collect_instantiations_in_expr(p, ctx, module);
         }
+        Expr::ProcessSetMemoryLimit { limit, policy } => {
+            collect_instantiations_in_expr(limit, ctx, module);
+            if let Some(p) = policy {
+                collect_instantiations_in_expr(p, ctx, module);
+            }
+        }
+        Expr::ProcessOnLowMemory(cb) => {
+            collect_instantiations_in_expr(cb, ctx, module);
+        }
+        Expr::ProcessStdioWrite { data, callback, .. } => {
+            collect_instantiations_in_expr(data, ctx, module);
+            if let Some(cb) = callback {
+                collect_instantiations_in_expr(cb, ctx, module);
+            }
+        }
+        Expr::ProcessStdinOn { event, listener } => {
+            collect_instantiations_in_expr(event, ctx, module);
+            collect_instantiations_in_expr(listener, ctx, module);
+        }
+        Expr::DisposeResource { resource, .. } => {
+            collect_instantiations_in_expr(resource, ctx, module);
+        }
+        Expr::DisposableStackAdopt { stack, value, on_dispose } => {
+            collect_instantiations_in_expr(stack, ctx, module);
+            collect_instantiations_in_expr(value, ctx, module);
+            collect_instantiations_in_expr(on_dispose, ctx, module);
+        }
+        Expr::DisposableStackDefer { stack, on_dispose } => {
+            collect_instantiations_in_expr(stack, ctx, module);
+            collect_instantiations_in_expr(on_dispose, ctx, module);
+        }
+        Expr::DisposableStackDispose(stack) | Expr::DisposableStackMove(stack) | Expr::DisposableStackDisposed(stack) => {
+            collect_instantiations_in_expr(stack, ctx, module);
+        }
+        Expr::ImportMetaResolve { specifier, .. } => {
+            collect_instantiations_in_expr(specifier, ctx, module);
+        }
         Expr::ArrayPush { value, .. } | Expr::ArrayUnshift { value, .. } => {
             collect_instantiations_in_expr(value, ctx, module);
         }
@@ -1825,7 +2396,8 @@ fn collect_instantiations_in_expr(expr: &Expr, ctx: &mut MonomorphizationContext
             collect_instantiations_in_expr(map, ctx, module);
             collect_instantiations_in_expr(key, ctx, module);
         }
-        Expr::MapSize(map) | Expr::MapClear(map) => {
+        Expr::MapSize(map) | Expr::MapClear(map) | Expr::MapKeys(map) |
+        Expr::MapValues(map) | Expr::MapEntries(map) => {
             collect_instantiations_in_expr(map, ctx, module);
         }
         Expr::SetNew => {}
@@ -1836,11 +2408,11 @@ fn collect_instantiations_in_expr(expr: &Expr, ctx: &mut MonomorphizationContext
             collect_instantiations_in_expr(set, ctx, module);
             collect_instantiations_in_expr(value, ctx, module);
         }
-        Expr::SetSize(set) | Expr::SetClear(set) => {
+        Expr::SetSize(set) | Expr::SetClear(set) | Expr::SetValues(set) => {
             collect_instantiations_in_expr(set, ctx, module);
         }
         // JSON operations
-        Expr::JsonParse(expr) | Expr::JsonStringify(expr) => {
+        Expr::JsonParse(expr) | Expr::JsonStringify(expr) | Expr::JsonCanonicalize(expr) => {
             collect_instantiations_in_expr(expr, ctx, module);
         }
         // Math operations
@@ -1910,6 +2482,8 @@ struct ClassInfo {
 struct InferenceLookup {
     funcs: HashMap<FuncId, FuncInfo>,
     classes: HashMap<String, ClassInfo>,
+    /// Generic functions imported from other modules, by `ExternFuncRef` name
+    imported_funcs: HashMap<String, FuncInfo>,
 }
 
 impl InferenceLookup {
@@ -1931,14 +2505,38 @@ impl InferenceLookup {
             }))
             .collect();
 
-        Self { funcs, classes }
+        Self { funcs, classes, imported_funcs: HashMap::new() }
+    }
+
+    /// Also know the generics imported from other modules
+    fn with_imported(mut self, imported: &HashMap<String, ImportedGeneric>) -> Self {
+        for (name, generic) in imported {
+            match &*generic.definition {
+                GenericDefinition::Function(f) => {
+                    self.imported_funcs.insert(name.clone(), FuncInfo {
+                        id: f.id,
+                        type_params: f.type_params.clone(),
+                        params: f.params.clone(),
+                        return_type: f.return_type.clone(),
+                    });
+                }
+                GenericDefinition::Class(c) => {
+                    self.classes.entry(name.clone()).or_insert_with(|| ClassInfo {
+                        name: c.name.clone(),
+                        type_params: c.type_params.clone(),
+                        constructor_params: c.constructor.as_ref().map(|ctor| ctor.params.clone()),
+                    });
+                }
+            }
+        }
+        self
     }
 }
 
 /// Update call sites to use specialized versions
 fn update_call_sites(module: &mut Module, ctx: &MonomorphizationContext) {
     // Build lookup table for inference (before mutating)
-    let lookup = InferenceLookup::from_module(module);
+    let lookup = InferenceLookup::from_module(module).with_imported(&ctx.imported);
 
     // Update all functions
     for func in &mut module.functions {
@@ -2030,7 +2628,7 @@ fn update_call_sites_in_stmt(stmt: &mut Stmt, ctx: &MonomorphizationContext, loo
 fn update_call_sites_in_expr(expr: &mut Expr, ctx: &MonomorphizationContext, lookup: &InferenceLookup) {
     match expr {
         // Update generic function calls to use specialized version
-        Expr::Call { callee, args, type_args } => {
+        Expr::Call { callee, args, type_args, .. } => {
             // First update the callee and args recursively
             update_call_sites_in_expr(callee, ctx, lookup);
             for arg in args.iter_mut() {
@@ -2062,11 +2660,33 @@ fn update_call_sites_in_expr(expr: &mut Expr, ctx: &MonomorphizationContext, loo
                         type_args.clear();
                     }
                 }
+            } else if let Expr::ExternFuncRef { name, param_types, return_type } = callee.as_mut() {
+                // Generic function imported from another module: call its specialization
+                if let Some(func_info) = lookup.imported_funcs.get(name.as_str()) {
+                    let resolved_type_args = if !type_args.is_empty() {
+                        Some(type_args.clone())
+                    } else {
+                        infer_type_args_from_lookup(func_info, args, lookup)
+                    };
+                    if let Some(ta) = resolved_type_args {
+                        let key = (name.clone(), mangle_type_args(&ta));
+                        if let Some(specialized_name) = ctx.imported_specialized.get(&key) {
+                            let substitutions: HashMap<String, Type> = func_info.type_params.iter()
+                                .zip(ta.iter())
+                                .map(|(param, arg)| (param.name.clone(), arg.clone()))
+                                .collect();
+                            *param_types = func_info.params.iter().map(|p| substitute_type(&p.ty, &substitutions)).collect();
+                            *return_type = substitute_type(&func_info.return_type, &substitutions);
+                            *name = specialized_name.clone();
+                            type_args.clear();
+                        }
+                    }
+                }
             }
         }
 
         // Update generic class instantiation to use specialized class
-        Expr::New { class_name, args, type_args } => {
+        Expr::New { class_name, args, type_args, .. } => {
             for arg in args.iter_mut() {
                 update_call_sites_in_expr(arg, ctx, lookup);
             }
@@ -2089,7 +2709,7 @@ fn update_call_sites_in_expr(expr: &mut Expr, ctx: &MonomorphizationContext, loo
             if let Some(ta) = resolved_type_args {
                 let mangled_args = mangle_type_args(&ta);
                 let key = (class_name.clone(), mangled_args);
-                if let Some(specialized_name) = ctx.specialized_classes.get(&key) {
+                if let Some(specialized_name) = ctx.specialized_classes.get(&key).or_else(|| ctx.imported_specialized.get(&key)) {
                     *class_name = specialized_name.clone();
                     type_args.clear();
                 }
@@ -2152,6 +2772,7 @@ fn update_call_sites_in_expr(expr: &mut Expr, ctx: &MonomorphizationContext, loo
         }
         Expr::TypeOf(inner) => update_call_sites_in_expr(inner, ctx, lookup),
         Expr::InstanceOf { expr, .. } => update_call_sites_in_expr(expr, ctx, lookup),
+        Expr::Narrowed { value, .. } => update_call_sites_in_expr(value, ctx, lookup),
         Expr::Await(inner) => update_call_sites_in_expr(inner, ctx, lookup),
         Expr::SuperCall(args) => {
             for arg in args.iter_mut() {
@@ -2176,7 +2797,7 @@ fn update_call_sites_in_expr(expr: &mut Expr, ctx: &MonomorphizationContext, loo
             update_call_sites_in_expr(path, ctx, lookup);
             update_call_sites_in_expr(content, ctx, lookup);
         }
-        Expr::FsExistsSync(path) | Expr::FsMkdirSync(path) | Expr::FsUnlinkSync(path) => {
+        Expr::FsExistsSync(path) | Expr::FsMkdirSync(path) | Expr::FsUnlinkSync(path) | Expr::FsCreateReadStream(path) => {
             update_call_sites_in_expr(path, ctx, lookup);
         }
         Expr::FsAppendFileSync(path, content) => {
@@ -2190,6 +2811,43 @@ fn update_call_sites_in_expr(expr: &mut Expr, ctx: &MonomorphizationContext, loo
         Expr::PathDirname(p) | Expr::PathBasename(p) | Expr::PathExtname(p) | Expr::PathResolve(p) => {
             update_call_sites_in_expr(p, ctx, lookup);
         }
+        Expr::ProcessSetMemoryLimit { limit, policy } => {
+            update_call_sites_in_expr(limit, ctx, lookup);
+            if let Some(p) = policy {
+                update_call_sites_in_expr(p, ctx, lookup);
+            }
+        }
+        Expr::ProcessOnLowMemory(cb) => {
+            update_call_sites_in_expr(cb, ctx, lookup);
+        }
+        Expr::ProcessStdioWrite { data, callback, .. } => {
+            update_call_sites_in_expr(data, ctx, lookup);
+            if let Some(cb) = callback {
+                update_call_sites_in_expr(cb, ctx, lookup);
+            }
+        }
+        Expr::ProcessStdinOn { event, listener } => {
+            update_call_sites_in_expr(event, ctx, lookup);
+            update_call_sites_in_expr(listener, ctx, lookup);
+        }
+        Expr::DisposeResource { resource, .. } => {
+            update_call_sites_in_expr(resource, ctx, lookup);
+        }
+        Expr::DisposableStackAdopt { stack, value, on_dispose } => {
+            update_call_sites_in_expr(stack, ctx, lookup);
+            update_call_sites_in_expr(value, ctx, lookup);
+            update_call_sites_in_expr(on_dispose, ctx, lookup);
+        }
+        Expr::DisposableStackDefer { stack, on_dispose } => {
+            update_call_sites_in_expr(stack, ctx, lookup);
+            update_call_sites_in_expr(on_dispose, ctx, lookup);
+        }
+        Expr::DisposableStackDispose(stack) | Expr::DisposableStackMove(stack) | Expr::DisposableStackDisposed(stack) => {
+            update_call_sites_in_expr(stack, ctx, lookup);
+        }
+        Expr::ImportMetaResolve { specifier, .. } => {
+            update_call_sites_in_expr(specifier, ctx, lookup);
+        }
         Expr::ArrayPush { value, .. } | Expr::ArrayUnshift { value, .. } => {
             update_call_sites_in_expr(value, ctx, lookup);
         }
@@ -2230,7 +2888,8 @@ fn update_call_sites_in_expr(expr: &mut Expr, ctx: &MonomorphizationContext, loo
             update_call_sites_in_expr(map, ctx, lookup);
             update_call_sites_in_expr(key, ctx, lookup);
         }
-        Expr::MapSize(map) | Expr::MapClear(map) => {
+        Expr::MapSize(map) | Expr::MapClear(map) | Expr::MapKeys(map) |
+        Expr::MapValues(map) | Expr::MapEntries(map) => {
             update_call_sites_in_expr(map, ctx, lookup);
         }
         Expr::SetNew => {}
@@ -2241,11 +2900,11 @@ fn update_call_sites_in_expr(expr: &mut Expr, ctx: &MonomorphizationContext, loo
             update_call_sites_in_expr(set, ctx, lookup);
             update_call_sites_in_expr(value, ctx, lookup);
         }
-        Expr::SetSize(set) | Expr::SetClear(set) => {
+        Expr::SetSize(set) | Expr::SetClear(set) | Expr::SetValues(set) => {
             update_call_sites_in_expr(set, ctx, lookup);
         }
         // JSON operations
-        Expr::JsonParse(expr) | Expr::JsonStringify(expr) => {
+        Expr::JsonParse(expr) | Expr::JsonStringify(expr) | Expr::JsonCanonicalize(expr) => {
             update_call_sites_in_expr(expr, ctx, lookup);
         }
         // Math operations
@@ -2435,6 +3094,7 @@ fn infer_expr_type_from_lookup(expr: &Expr, lookup: &InferenceLookup) -> Option<
 
         Expr::TypeOf(_) => Some(Type::String),
         Expr::InstanceOf { .. } => Some(Type::Boolean),
+        Expr::Narrowed { ty, .. } => Some(ty.clone()),
 
         Expr::Conditional { then_expr, .. } => infer_expr_type_from_lookup(then_expr, lookup),
 
@@ -2443,225 +3103,214 @@ fn infer_expr_type_from_lookup(expr: &Expr, lookup: &InferenceLookup) -> Option<
 }
 
 // ============================================================================
-// Default Argument Filling
+// Constructor Argument Padding
 // ============================================================================
 
-/// Fill in default arguments for New expressions where fewer args are provided
-/// than the constructor expects
-fn fill_default_arguments(module: &mut Module) {
-    // Build a map of class name -> constructor param defaults
-    let mut ctor_defaults: HashMap<String, Vec<Option<Expr>>> = HashMap::new();
+/// Pad `new C(...)` calls that pass fewer arguments than the constructor declares
+/// with `undefined`. Default values are applied by the constructor's own prologue
+/// (see `with_default_params_prologue` in lower.rs), so only the arity is needed here.
+fn pad_constructor_arguments(module: &mut Module) {
+    // Build a map of class name -> constructor parameter count (excluding a rest parameter)
+    let mut ctor_param_counts: HashMap<String, usize> = HashMap::new();
     for class in &module.classes {
         if let Some(ref ctor) = class.constructor {
-            let defaults: Vec<Option<Expr>> = ctor.params.iter()
-                .map(|p| p.default.clone())
-                .collect();
-            ctor_defaults.insert(class.name.clone(), defaults);
+            let count = ctor.params.iter().filter(|p| !p.is_rest).count();
+            ctor_param_counts.insert(class.name.clone(), count);
         }
     }
 
-    // Fill defaults in init statements
-    fill_defaults_in_stmts(&mut module.init, &ctor_defaults);
+    // Pad constructor calls in init statements
+    pad_ctor_args_in_stmts(&mut module.init, &ctor_param_counts);
 
-    // Fill defaults in function bodies
+    // Pad constructor calls in function bodies
     for func in &mut module.functions {
-        fill_defaults_in_stmts(&mut func.body, &ctor_defaults);
+        pad_ctor_args_in_stmts(&mut func.body, &ctor_param_counts);
     }
 
-    // Fill defaults in class methods
+    // Pad constructor calls in class methods
     for class in &mut module.classes {
         if let Some(ref mut ctor) = class.constructor {
-            fill_defaults_in_stmts(&mut ctor.body, &ctor_defaults);
+            pad_ctor_args_in_stmts(&mut ctor.body, &ctor_param_counts);
         }
         for method in &mut class.methods {
-            fill_defaults_in_stmts(&mut method.body, &ctor_defaults);
+            pad_ctor_args_in_stmts(&mut method.body, &ctor_param_counts);
         }
     }
 }
 
-fn fill_defaults_in_stmts(stmts: &mut [Stmt], ctor_defaults: &HashMap<String, Vec<Option<Expr>>>) {
+fn pad_ctor_args_in_stmts(stmts: &mut [Stmt], ctor_param_counts: &HashMap<String, usize>) {
     for stmt in stmts {
-        fill_defaults_in_stmt(stmt, ctor_defaults);
+        pad_ctor_args_in_stmt(stmt, ctor_param_counts);
     }
 }
 
-fn fill_defaults_in_stmt(stmt: &mut Stmt, ctor_defaults: &HashMap<String, Vec<Option<Expr>>>) {
+fn pad_ctor_args_in_stmt(stmt: &mut Stmt, ctor_param_counts: &HashMap<String, usize>) {
     match stmt {
         Stmt::Let { init, .. } => {
             if let Some(expr) = init {
-                fill_defaults_in_expr(expr, ctor_defaults);
+                pad_ctor_args_in_expr(expr, ctor_param_counts);
             }
         }
-        Stmt::Expr(expr) => fill_defaults_in_expr(expr, ctor_defaults),
+        Stmt::Expr(expr) => pad_ctor_args_in_expr(expr, ctor_param_counts),
         Stmt::Return(expr) => {
             if let Some(e) = expr {
-                fill_defaults_in_expr(e, ctor_defaults);
+                pad_ctor_args_in_expr(e, ctor_param_counts);
             }
         }
         Stmt::If { condition, then_branch, else_branch } => {
-            fill_defaults_in_expr(condition, ctor_defaults);
-            fill_defaults_in_stmts(then_branch, ctor_defaults);
+            pad_ctor_args_in_expr(condition, ctor_param_counts);
+            pad_ctor_args_in_stmts(then_branch, ctor_param_counts);
             if let Some(else_b) = else_branch {
-                fill_defaults_in_stmts(else_b, ctor_defaults);
+                pad_ctor_args_in_stmts(else_b, ctor_param_counts);
             }
         }
         Stmt::While { condition, body } => {
-            fill_defaults_in_expr(condition, ctor_defaults);
-            fill_defaults_in_stmts(body, ctor_defaults);
+            pad_ctor_args_in_expr(condition, ctor_param_counts);
+            pad_ctor_args_in_stmts(body, ctor_param_counts);
         }
         Stmt::For { init, condition, update, body } => {
             if let Some(init_stmt) = init {
-                fill_defaults_in_stmt(init_stmt, ctor_defaults);
+                pad_ctor_args_in_stmt(init_stmt, ctor_param_counts);
             }
             if let Some(cond) = condition {
-                fill_defaults_in_expr(cond, ctor_defaults);
+                pad_ctor_args_in_expr(cond, ctor_param_counts);
             }
             if let Some(upd) = update {
-                fill_defaults_in_expr(upd, ctor_defaults);
+                pad_ctor_args_in_expr(upd, ctor_param_counts);
             }
-            fill_defaults_in_stmts(body, ctor_defaults);
+            pad_ctor_args_in_stmts(body, ctor_param_counts);
         }
-        Stmt::Throw(expr) => fill_defaults_in_expr(expr, ctor_defaults),
+        Stmt::Throw(expr) => pad_ctor_args_in_expr(expr, ctor_param_counts),
         Stmt::Try { body, catch, finally } => {
-            fill_defaults_in_stmts(body, ctor_defaults);
+            pad_ctor_args_in_stmts(body, ctor_param_counts);
             if let Some(ref mut c) = catch {
-                fill_defaults_in_stmts(&mut c.body, ctor_defaults);
+                pad_ctor_args_in_stmts(&mut c.body, ctor_param_counts);
             }
             if let Some(f) = finally {
-                fill_defaults_in_stmts(f, ctor_defaults);
+                pad_ctor_args_in_stmts(f, ctor_param_counts);
             }
         }
         Stmt::Switch { discriminant, cases } => {
-            fill_defaults_in_expr(discriminant, ctor_defaults);
+            pad_ctor_args_in_expr(discriminant, ctor_param_counts);
             for case in cases {
-                fill_defaults_in_stmts(&mut case.body, ctor_defaults);
+                pad_ctor_args_in_stmts(&mut case.body, ctor_param_counts);
             }
         }
         Stmt::Break | Stmt::Continue => {}
     }
 }
 
-fn fill_defaults_in_expr(expr: &mut Expr, ctor_defaults: &HashMap<String, Vec<Option<Expr>>>) {
+fn pad_ctor_args_in_expr(expr: &mut Expr, ctor_param_counts: &HashMap<String, usize>) {
     match expr {
         Expr::New { class_name, args, .. } => {
             // First, recurse into the arguments
             for arg in args.iter_mut() {
-                fill_defaults_in_expr(arg, ctor_defaults);
+                pad_ctor_args_in_expr(arg, ctor_param_counts);
             }
 
-            // Check if we need to fill in defaults
-            if let Some(defaults) = ctor_defaults.get(class_name) {
-                let param_count = defaults.len();
-                let arg_count = args.len();
-
-                if arg_count < param_count {
-                    // Fill in missing arguments with defaults
-                    for i in arg_count..param_count {
-                        if let Some(ref default_expr) = defaults[i] {
-                            args.push(default_expr.clone());
-                        } else {
-                            // No default for this parameter - this is an error
-                            // For now, push a null placeholder
-                            args.push(Expr::Null);
-                        }
-                    }
+            if let Some(&param_count) = ctor_param_counts.get(class_name) {
+                while args.len() < param_count {
+                    args.push(Expr::Undefined);
                 }
             }
         }
         // Recurse into sub-expressions
         Expr::LocalSet(_, val) | Expr::GlobalSet(_, val) => {
-            fill_defaults_in_expr(val, ctor_defaults);
+            pad_ctor_args_in_expr(val, ctor_param_counts);
         }
         Expr::Binary { left, right, .. } | Expr::Compare { left, right, .. } |
         Expr::Logical { left, right, .. } => {
-            fill_defaults_in_expr(left, ctor_defaults);
-            fill_defaults_in_expr(right, ctor_defaults);
+            pad_ctor_args_in_expr(left, ctor_param_counts);
+            pad_ctor_args_in_expr(right, ctor_param_counts);
         }
         Expr::Unary { operand, .. } => {
-            fill_defaults_in_expr(operand, ctor_defaults);
+            pad_ctor_args_in_expr(operand, ctor_param_counts);
         }
         Expr::Update { .. } => {
             // Update expressions (++/--) don't contain sub-expressions
         }
         Expr::Conditional { condition, then_expr, else_expr } => {
-            fill_defaults_in_expr(condition, ctor_defaults);
-            fill_defaults_in_expr(then_expr, ctor_defaults);
-            fill_defaults_in_expr(else_expr, ctor_defaults);
+            pad_ctor_args_in_expr(condition, ctor_param_counts);
+            pad_ctor_args_in_expr(then_expr, ctor_param_counts);
+            pad_ctor_args_in_expr(else_expr, ctor_param_counts);
         }
         Expr::Call { callee, args, .. } => {
-            fill_defaults_in_expr(callee, ctor_defaults);
+            pad_ctor_args_in_expr(callee, ctor_param_counts);
             for arg in args {
-                fill_defaults_in_expr(arg, ctor_defaults);
+                pad_ctor_args_in_expr(arg, ctor_param_counts);
             }
         }
         Expr::Array(elements) => {
             for elem in elements {
-                fill_defaults_in_expr(elem, ctor_defaults);
+                pad_ctor_args_in_expr(elem, ctor_param_counts);
             }
         }
         Expr::Object(fields) => {
             for (_, val) in fields {
-                fill_defaults_in_expr(val, ctor_defaults);
+                pad_ctor_args_in_expr(val, ctor_param_counts);
             }
         }
         Expr::IndexGet { object, index } => {
-            fill_defaults_in_expr(object, ctor_defaults);
-            fill_defaults_in_expr(index, ctor_defaults);
+            pad_ctor_args_in_expr(object, ctor_param_counts);
+            pad_ctor_args_in_expr(index, ctor_param_counts);
         }
         Expr::IndexSet { object, index, value } => {
-            fill_defaults_in_expr(object, ctor_defaults);
-            fill_defaults_in_expr(index, ctor_defaults);
-            fill_defaults_in_expr(value, ctor_defaults);
+            pad_ctor_args_in_expr(object, ctor_param_counts);
+            pad_ctor_args_in_expr(index, ctor_param_counts);
+            pad_ctor_args_in_expr(value, ctor_param_counts);
         }
         Expr::PropertyGet { object, .. } => {
-            fill_defaults_in_expr(object, ctor_defaults);
+            pad_ctor_args_in_expr(object, ctor_param_counts);
         }
         Expr::PropertySet { object, value, .. } => {
-            fill_defaults_in_expr(object, ctor_defaults);
-            fill_defaults_in_expr(value, ctor_defaults);
+            pad_ctor_args_in_expr(object, ctor_param_counts);
+            pad_ctor_args_in_expr(value, ctor_param_counts);
         }
         Expr::PropertyUpdate { object, .. } => {
-            fill_defaults_in_expr(object, ctor_defaults);
+            pad_ctor_args_in_expr(object, ctor_param_counts);
         }
         Expr::Await(inner) => {
-            fill_defaults_in_expr(inner, ctor_defaults);
+            pad_ctor_args_in_expr(inner, ctor_param_counts);
         }
         Expr::TypeOf(inner) => {
-            fill_defaults_in_expr(inner, ctor_defaults);
+            pad_ctor_args_in_expr(inner, ctor_param_counts);
         }
         Expr::InstanceOf { expr, .. } => {
-            fill_defaults_in_expr(expr, ctor_defaults);
+            pad_ctor_args_in_expr(expr, ctor_param_counts);
+        }
+        Expr::Narrowed { value, .. } => {
+            pad_ctor_args_in_expr(value, ctor_param_counts);
         }
         Expr::Closure { body, .. } => {
-            fill_defaults_in_stmts(body, ctor_defaults);
+            pad_ctor_args_in_stmts(body, ctor_param_counts);
         }
         Expr::NativeMethodCall { object, args, .. } => {
             if let Some(obj) = object {
-                fill_defaults_in_expr(obj, ctor_defaults);
+                pad_ctor_args_in_expr(obj, ctor_param_counts);
             }
             for arg in args {
-                fill_defaults_in_expr(arg, ctor_defaults);
+                pad_ctor_args_in_expr(arg, ctor_param_counts);
             }
         }
         Expr::StaticMethodCall { args, .. } => {
             for arg in args {
-                fill_defaults_in_expr(arg, ctor_defaults);
+                pad_ctor_args_in_expr(arg, ctor_param_counts);
             }
         }
         Expr::SuperMethodCall { args, .. } => {
             for arg in args {
-                fill_defaults_in_expr(arg, ctor_defaults);
+                pad_ctor_args_in_expr(arg, ctor_param_counts);
             }
         }
         Expr::SuperCall(args) => {
             for arg in args {
-                fill_defaults_in_expr(arg, ctor_defaults);
+                pad_ctor_args_in_expr(arg, ctor_param_counts);
             }
         }
         Expr::JsCallMethod { object, args, .. } => {
-            fill_defaults_in_expr(object, ctor_defaults);
+            pad_ctor_args_in_expr(object, ctor_param_counts);
             for arg in args {
-                fill_defaults_in_expr(arg, ctor_defaults);
+                pad_ctor_args_in_expr(arg, ctor_param_counts);
             }
         }
         _ => {}
@@ -2671,6 +3320,7 @@ fn fill_defaults_in_expr(expr: &mut Expr, ctor_defaults: &HashMap<String, Vec<Op
 #[cfg(test)]
 mod tests {
     use super::*;
+    use perry_diagnostics::Span;
     use perry_types::TypeParam;
 
     #[test]
@@ -2731,6 +3381,7 @@ mod tests {
             is_exported: true,
             captures: vec![],
             decorators: vec![],
+            span: Span::DUMMY,
         };
 
         // Create a module with the generic function and a call to it with type args
@@ -2742,6 +3393,7 @@ mod tests {
             callee: Box::new(Expr::FuncRef(1)),
             args: vec![Expr::Number(42.0)],
             type_args: vec![Type::Number],
+            span: Span::DUMMY,
         }));
 
         // Run monomorphization
@@ -2759,6 +3411,11 @@ mod tests {
         assert!(specialized.type_params.is_empty(), "Specialized function should have no type params");
         assert_eq!(specialized.params[0].ty, Type::Number, "Param should be Number");
         assert_eq!(specialized.return_type, Type::Number, "Return type should be Number");
+
+        // Codegen names its symbol after the generic
+        assert_eq!(module.specializations.len(), 1);
+        assert_eq!(module.specializations[0].generic, "identity");
+        assert_eq!(module.specializations[0].type_args, vec![Type::Number]);
     }
 
     #[test]
@@ -2785,6 +3442,7 @@ mod tests {
             is_exported: true,
             captures: vec![],
             decorators: vec![],
+            span: Span::DUMMY,
         };
 
         let mut module = Module::new("test");
@@ -2795,6 +3453,7 @@ mod tests {
             callee: Box::new(Expr::FuncRef(1)),
             args: vec![Expr::String("hello".to_string())],
             type_args: vec![Type::String],
+            span: Span::DUMMY,
         }));
 
         // Run monomorphization
@@ -2839,6 +3498,7 @@ mod tests {
             is_exported: true,
             captures: vec![],
             decorators: vec![],
+            span: Span::DUMMY,
         };
 
         let mut module = Module::new("test");
@@ -2849,6 +3509,7 @@ mod tests {
             callee: Box::new(Expr::FuncRef(1)),
             args: vec![Expr::Number(42.0)],
             type_args: vec![], // Empty - should be inferred!
+            span: Span::DUMMY,
         }));
 
         // Run monomorphization
@@ -2906,6 +3567,7 @@ mod tests {
             is_exported: true,
             captures: vec![],
             decorators: vec![],
+            span: Span::DUMMY,
         };
 
         let mut module = Module::new("test");
@@ -2916,6 +3578,7 @@ mod tests {
             callee: Box::new(Expr::FuncRef(1)),
             args: vec![Expr::String("hello".to_string())],
             type_args: vec![], // Empty - should be inferred!
+            span: Span::DUMMY,
         }));
 
         // Run monomorphization
@@ -2930,4 +3593,129 @@ mod tests {
         assert_eq!(specialized.params[0].ty, Type::String, "Param should be String");
         assert_eq!(specialized.return_type, Type::String, "Return type should be String");
     }
+
+    #[test]
+    fn test_constraint_violation_diagnostic() {
+        // function getId<T extends { id: string }>(item: T): string
+        let mut has_id = ObjectType::default();
+        has_id.properties.insert("id".to_string(), PropertyInfo { ty: Type::String, optional: false, readonly: false });
+        let get_id = Function {
+            id: 1,
+            name: "getId".to_string(),
+            type_params: vec![TypeParam {
+                name: "T".to_string(),
+                constraint: Some(Type::Object(has_id.clone())),
+                default: None,
+            }],
+            params: vec![Param {
+                id: 0,
+                name: "item".to_string(),
+                ty: Type::TypeVar("T".to_string()),
+                default: None,
+                is_rest: false,
+            }],
+            return_type: Type::String,
+            body: vec![Stmt::Return(Some(Expr::String("".to_string())))],
+            is_async: false,
+            is_exported: false,
+            captures: vec![],
+            decorators: vec![],
+            span: Span::DUMMY,
+        };
+        let mut module = Module::new("test");
+        module.functions.push(get_id);
+        let call = |type_args: Vec<Type>, start: u32| {
+            Stmt::Expr(Expr::Call {
+                callee: Box::new(Expr::FuncRef(1)),
+                args: vec![],
+                type_args,
+                span: Span::new(perry_diagnostics::FileId(0), start, start + 8),
+            })
+        };
+        let mut user = has_id.clone();
+        user.properties.insert("name".to_string(), PropertyInfo { ty: Type::String, optional: false, readonly: false });
+        let mut wrong_id = ObjectType::default();
+        wrong_id.properties.insert("id".to_string(), PropertyInfo { ty: Type::Number, optional: false, readonly: false });
+        module.init.push(call(vec![Type::Number], 0));
+        module.init.push(call(vec![Type::Object(user)], 10));
+        module.init.push(call(vec![Type::Object(wrong_id)], 20));
+        // Violations are reported once per call site
+        module.init.push(call(vec![Type::Number], 0));
+
+        monomorphize_module(&mut module);
+
+        let violations: Vec<&Diagnostic> = module.diagnostics.iter()
+            .filter(|d| d.code == DiagnosticCode::ConstraintViolation)
+            .collect();
+        assert_eq!(violations.len(), 2);
+        assert_eq!((violations[0].span.start, violations[1].span.start), (0, 20));
+        assert!(violations[0].message.contains("requires property 'id'"), "{}", violations[0].message);
+        assert!(violations[1].message.contains("does not satisfy"), "{}", violations[1].message);
+
+        // A literal argument satisfies its primitive, and inference may widen a literal one
+        let module = Module::new("test");
+        let string = Type::String;
+        assert!(check_constraint("T", &Type::Literal(perry_types::LiteralType::String("a".into())), &string, &module).is_ok());
+        let literal = Type::Literal(perry_types::LiteralType::String("a".into()));
+        assert!(check_constraint("T", &Type::String, &literal, &module).is_ok());
+        assert!(check_constraint("T", &Type::Number, &literal, &module).is_err());
+    }
+
+    #[test]
+    fn test_monomorphize_across_modules() {
+        let lower = |source: &str, path: &str| {
+            let ast_module = perry_parser::parse_typescript(source, path).unwrap();
+            crate::lower::lower_module(&ast_module, path, path).unwrap()
+        };
+        let defining = lower(
+            "export class Box<T> { value: T; constructor(value: T) { this.value = value; } }\n\
+             export function identity<T>(x: T): T { return x; }\n",
+            "/app/box.ts",
+        );
+        let barrel = lower("export * from './box';\n", "/app/index.ts");
+        let mut user = lower(
+            "import { Box as Crate, identity } from './index';\n\
+             const a = new Crate<number>(1);\n\
+             const b = new Crate<number>(2);\n\
+             const s = identity(\"x\");\n",
+            "/app/main.ts",
+        );
+        for import in &mut user.imports {
+            import.module_kind = ModuleKind::NativeCompiled;
+            import.resolved_path = Some("/app/index.ts".to_string());
+        }
+        let mut modules: HashMap<PathBuf, Module> = HashMap::new();
+        modules.insert(PathBuf::from("/app/box.ts"), defining);
+        modules.insert(PathBuf::from("/app/index.ts"), barrel);
+        modules.insert(PathBuf::from("/app/main.ts"), user);
+        let resolve = |_: &Path, source: &str| (source == "./box").then(|| PathBuf::from("/app/box.ts"));
+
+        monomorphize_program(&mut modules, &resolve);
+
+        // Specialized once, in the defining module, and exported for the importer
+        let defining = &modules[Path::new("/app/box.ts")];
+        let boxes: Vec<&Class> = defining.classes.iter().filter(|c| c.name == "Box$num").collect();
+        assert_eq!(boxes.len(), 1);
+        assert!(boxes[0].is_exported);
+        assert!(defining.functions.iter().any(|f| f.name == "identity$str" && f.is_exported));
+
+        let user = &modules[Path::new("/app/main.ts")];
+        let inits: Vec<&Expr> = user.init.iter()
+            .filter_map(|stmt| match stmt {
+                Stmt::Let { init: Some(init), .. } => Some(init),
+                _ => None,
+            })
+            .collect();
+        assert!(matches!(inits[0], Expr::New { class_name, type_args, .. } if class_name == "Box$num" && type_args.is_empty()));
+        assert!(matches!(inits[1], Expr::New { class_name, .. } if class_name == "Box$num"));
+        match inits[2] {
+            Expr::Call { callee, .. } => assert!(
+                matches!(callee.as_ref(), Expr::ExternFuncRef { name, param_types, .. } if name == "identity$str" && *param_types == vec![Type::String])
+            ),
+            other => panic!("expected a call, got {:?}", other),
+        }
+        let spec = ImportSpecifier::Named { imported: "Box$num".to_string(), local: "Box$num".to_string() };
+        let import = user.imports.iter().find(|i| i.resolved_path.as_deref() == Some("/app/box.ts")).unwrap();
+        assert!(import.specifiers.contains(&spec));
+    }
 }
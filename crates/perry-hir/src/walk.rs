@@ -0,0 +1,752 @@
+//! Walking the expressions and statements of HIR
+//!
+//! `Visit` and `VisitMut` go through everything under the node they start from, closure
+//! bodies included. A pass overrides the methods for the nodes it looks at and calls the
+//! matching `walk_*` function to go on into their children. Every kind of expression is
+//! matched by name, so a new one has to say here what it contains before anything compiles.
+
+use crate::ir::{ArrayElement, CallArg, CatchClause, Expr, Param, Stmt, SwitchCase};
+
+/// Goes through HIR by reference
+pub trait Visit {
+    fn visit_expr(&mut self, expr: &Expr) {
+        walk_expr(self, expr);
+    }
+
+    fn visit_stmt(&mut self, stmt: &Stmt) {
+        walk_stmt(self, stmt);
+    }
+
+    fn visit_stmts(&mut self, stmts: &[Stmt]) {
+        for stmt in stmts {
+            self.visit_stmt(stmt);
+        }
+    }
+}
+
+/// Goes through HIR by mutable reference. Statements are visited as the lists they are in,
+/// so a pass can replace one statement with several.
+pub trait VisitMut {
+    fn visit_expr_mut(&mut self, expr: &mut Expr) {
+        walk_expr_mut(self, expr);
+    }
+
+    fn visit_stmt_mut(&mut self, stmt: &mut Stmt) {
+        walk_stmt_mut(self, stmt);
+    }
+
+    fn visit_stmts_mut(&mut self, stmts: &mut Vec<Stmt>) {
+        for stmt in stmts {
+            self.visit_stmt_mut(stmt);
+        }
+    }
+}
+
+/// Visit the expressions and statement lists directly under `expr`, with the same match
+/// for `&Expr` and `&mut Expr`
+macro_rules! walk_expr {
+    ($visitor:ident, $expr:ident, $visit_expr:ident, $visit_stmts:ident) => {
+        match $expr {
+            Expr::Object(props) => {
+                for (_, value) in props {
+                    $visitor.$visit_expr(value);
+                }
+            }
+            Expr::Array(exprs) | Expr::SuperCall(exprs) | Expr::MathMin(exprs) | Expr::MathMax(exprs) |
+                Expr::Sequence(exprs) => {
+                for expr in exprs {
+                    $visitor.$visit_expr(expr);
+                }
+            }
+            Expr::ArraySpread(elements) => {
+                for ArrayElement::Expr(element) | ArrayElement::Spread(element) in elements {
+                    $visitor.$visit_expr(element);
+                }
+            }
+            Expr::TypeOf(expr) | Expr::Await(expr) | Expr::EnvGetDynamic(expr) |
+                Expr::ProcessOnLowMemory(expr) | Expr::FsReadFileSync(expr) |
+                Expr::FsExistsSync(expr) | Expr::FsMkdirSync(expr) | Expr::FsUnlinkSync(expr) |
+                Expr::FsCreateReadStream(expr) | Expr::PathDirname(expr) | Expr::PathBasename(expr) |
+                Expr::PathExtname(expr) | Expr::PathResolve(expr) | Expr::JsonParse(expr) |
+                Expr::JsonStringify(expr) | Expr::JsonCanonicalize(expr) | Expr::MathFloor(expr) |
+                Expr::MathCeil(expr) | Expr::MathRound(expr) | Expr::MathAbs(expr) |
+                Expr::MathSqrt(expr) | Expr::CryptoRandomBytes(expr) | Expr::CryptoSha256(expr) |
+                Expr::CryptoMd5(expr) | Expr::BufferAllocUnsafe(expr) | Expr::BufferConcat(expr) |
+                Expr::BufferIsBuffer(expr) | Expr::BufferByteLength(expr) | Expr::BufferLength(expr) |
+                Expr::Uint8ArrayFrom(expr) | Expr::Uint8ArrayLength(expr) |
+                Expr::StringFromCharCode(expr) | Expr::MapSize(expr) | Expr::MapClear(expr) |
+                Expr::MapKeys(expr) | Expr::MapValues(expr) | Expr::MapEntries(expr) |
+                Expr::SetSize(expr) | Expr::SetClear(expr) | Expr::SetValues(expr) |
+                Expr::DateGetTime(expr) | Expr::DateToISOString(expr) | Expr::DateGetFullYear(expr) |
+                Expr::DateGetMonth(expr) | Expr::DateGetDate(expr) | Expr::DateGetHours(expr) |
+                Expr::DateGetMinutes(expr) | Expr::DateGetSeconds(expr) |
+                Expr::DateGetMilliseconds(expr) | Expr::ErrorMessage(expr) | Expr::UrlGetHref(expr) |
+                Expr::UrlGetPathname(expr) | Expr::UrlGetProtocol(expr) | Expr::UrlGetHost(expr) |
+                Expr::UrlGetHostname(expr) | Expr::UrlGetPort(expr) | Expr::UrlGetSearch(expr) |
+                Expr::UrlGetHash(expr) | Expr::UrlGetOrigin(expr) | Expr::UrlGetSearchParams(expr) |
+                Expr::UrlSearchParamsToString(expr) | Expr::DisposableStackDispose(expr) |
+                Expr::DisposableStackMove(expr) | Expr::DisposableStackDisposed(expr) |
+                Expr::Delete(expr) | Expr::ObjectKeys(expr) | Expr::ObjectValues(expr) |
+                Expr::ObjectEntries(expr) | Expr::ArrayIsArray(expr) | Expr::ParseFloat(expr) |
+                Expr::NumberCoerce(expr) | Expr::StringCoerce(expr) | Expr::IsNaN(expr) |
+                Expr::IsFinite(expr) => {
+                $visitor.$visit_expr(expr);
+            }
+            Expr::Uint8ArrayNew(expr) | Expr::DateNew(expr) | Expr::UrlSearchParamsNew(expr) => {
+                if let Some(expr) = expr {
+                    $visitor.$visit_expr(expr);
+                }
+            }
+            Expr::LocalSet(_, value) => {
+                $visitor.$visit_expr(value);
+            }
+            Expr::GlobalSet(_, value) => {
+                $visitor.$visit_expr(value);
+            }
+            Expr::Binary { left, right, .. } => {
+                $visitor.$visit_expr(left);
+                $visitor.$visit_expr(right);
+            }
+            Expr::Unary { operand, .. } => {
+                $visitor.$visit_expr(operand);
+            }
+            Expr::Compare { left, right, .. } => {
+                $visitor.$visit_expr(left);
+                $visitor.$visit_expr(right);
+            }
+            Expr::Logical { left, right, .. } => {
+                $visitor.$visit_expr(left);
+                $visitor.$visit_expr(right);
+            }
+            Expr::Call { callee, args, .. } => {
+                $visitor.$visit_expr(callee);
+                for expr in args {
+                    $visitor.$visit_expr(expr);
+                }
+            }
+            Expr::CallSpread { callee, args, .. } => {
+                $visitor.$visit_expr(callee);
+                for CallArg::Expr(arg) | CallArg::Spread(arg) in args {
+                    $visitor.$visit_expr(arg);
+                }
+            }
+            Expr::NativeMethodCall { object, args, .. } => {
+                if let Some(object) = object {
+                    $visitor.$visit_expr(object);
+                }
+                for expr in args {
+                    $visitor.$visit_expr(expr);
+                }
+            }
+            Expr::PropertyGet { object, .. } => {
+                $visitor.$visit_expr(object);
+            }
+            Expr::PropertySet { object, value, .. } => {
+                $visitor.$visit_expr(object);
+                $visitor.$visit_expr(value);
+            }
+            Expr::PropertyUpdate { object, .. } => {
+                $visitor.$visit_expr(object);
+            }
+            Expr::IndexGet { object, index } => {
+                $visitor.$visit_expr(object);
+                $visitor.$visit_expr(index);
+            }
+            Expr::IndexSet { object, index, value } => {
+                $visitor.$visit_expr(object);
+                $visitor.$visit_expr(index);
+                $visitor.$visit_expr(value);
+            }
+            Expr::IndexUpdate { object, index, .. } => {
+                $visitor.$visit_expr(object);
+                $visitor.$visit_expr(index);
+            }
+            Expr::Conditional { condition, then_expr, else_expr } => {
+                $visitor.$visit_expr(condition);
+                $visitor.$visit_expr(then_expr);
+                $visitor.$visit_expr(else_expr);
+            }
+            Expr::InstanceOf { expr, .. } => {
+                $visitor.$visit_expr(expr);
+            }
+            Expr::Narrowed { value, .. } => {
+                $visitor.$visit_expr(value);
+            }
+            Expr::In { property, object } => {
+                $visitor.$visit_expr(property);
+                $visitor.$visit_expr(object);
+            }
+            Expr::New { args, .. } => {
+                for expr in args {
+                    $visitor.$visit_expr(expr);
+                }
+            }
+            Expr::NewDynamic { callee, args } => {
+                $visitor.$visit_expr(callee);
+                for expr in args {
+                    $visitor.$visit_expr(expr);
+                }
+            }
+            Expr::StaticFieldSet { value, .. } => {
+                $visitor.$visit_expr(value);
+            }
+            Expr::StaticMethodCall { args, .. } => {
+                for expr in args {
+                    $visitor.$visit_expr(expr);
+                }
+            }
+            Expr::SuperMethodCall { args, .. } => {
+                for expr in args {
+                    $visitor.$visit_expr(expr);
+                }
+            }
+            Expr::ProcessSetMemoryLimit { limit, policy } => {
+                $visitor.$visit_expr(limit);
+                if let Some(policy) = policy {
+                    $visitor.$visit_expr(policy);
+                }
+            }
+            Expr::ProcessStdioWrite { data, callback, .. } => {
+                $visitor.$visit_expr(data);
+                if let Some(callback) = callback {
+                    $visitor.$visit_expr(callback);
+                }
+            }
+            Expr::ProcessStdinOn { event, listener } => {
+                $visitor.$visit_expr(event);
+                $visitor.$visit_expr(listener);
+            }
+            Expr::FsWriteFileSync(a, b) => {
+                $visitor.$visit_expr(a);
+                $visitor.$visit_expr(b);
+            }
+            Expr::FsAppendFileSync(a, b) => {
+                $visitor.$visit_expr(a);
+                $visitor.$visit_expr(b);
+            }
+            Expr::PathJoin(a, b) => {
+                $visitor.$visit_expr(a);
+                $visitor.$visit_expr(b);
+            }
+            Expr::MathPow(a, b) => {
+                $visitor.$visit_expr(a);
+                $visitor.$visit_expr(b);
+            }
+            Expr::BufferFrom { data, encoding } => {
+                $visitor.$visit_expr(data);
+                if let Some(encoding) = encoding {
+                    $visitor.$visit_expr(encoding);
+                }
+            }
+            Expr::BufferAlloc { size, fill } => {
+                $visitor.$visit_expr(size);
+                if let Some(fill) = fill {
+                    $visitor.$visit_expr(fill);
+                }
+            }
+            Expr::BufferToString { buffer, encoding } => {
+                $visitor.$visit_expr(buffer);
+                if let Some(encoding) = encoding {
+                    $visitor.$visit_expr(encoding);
+                }
+            }
+            Expr::BufferSlice { buffer, start, end } => {
+                $visitor.$visit_expr(buffer);
+                if let Some(start) = start {
+                    $visitor.$visit_expr(start);
+                }
+                if let Some(end) = end {
+                    $visitor.$visit_expr(end);
+                }
+            }
+            Expr::BufferCopy { source, target, target_start, source_start, source_end } => {
+                $visitor.$visit_expr(source);
+                $visitor.$visit_expr(target);
+                if let Some(target_start) = target_start {
+                    $visitor.$visit_expr(target_start);
+                }
+                if let Some(source_start) = source_start {
+                    $visitor.$visit_expr(source_start);
+                }
+                if let Some(source_end) = source_end {
+                    $visitor.$visit_expr(source_end);
+                }
+            }
+            Expr::BufferWrite { buffer, string, offset, encoding } => {
+                $visitor.$visit_expr(buffer);
+                $visitor.$visit_expr(string);
+                if let Some(offset) = offset {
+                    $visitor.$visit_expr(offset);
+                }
+                if let Some(encoding) = encoding {
+                    $visitor.$visit_expr(encoding);
+                }
+            }
+            Expr::BufferEquals { buffer, other } => {
+                $visitor.$visit_expr(buffer);
+                $visitor.$visit_expr(other);
+            }
+            Expr::BufferIndexGet { buffer, index } => {
+                $visitor.$visit_expr(buffer);
+                $visitor.$visit_expr(index);
+            }
+            Expr::BufferIndexSet { buffer, index, value } => {
+                $visitor.$visit_expr(buffer);
+                $visitor.$visit_expr(index);
+                $visitor.$visit_expr(value);
+            }
+            Expr::Uint8ArrayGet { array, index } => {
+                $visitor.$visit_expr(array);
+                $visitor.$visit_expr(index);
+            }
+            Expr::Uint8ArraySet { array, index, value } => {
+                $visitor.$visit_expr(array);
+                $visitor.$visit_expr(index);
+                $visitor.$visit_expr(value);
+            }
+            Expr::ChildProcessExecSync { command, options } => {
+                $visitor.$visit_expr(command);
+                if let Some(options) = options {
+                    $visitor.$visit_expr(options);
+                }
+            }
+            Expr::ChildProcessSpawnSync { command, args, options } => {
+                $visitor.$visit_expr(command);
+                if let Some(args) = args {
+                    $visitor.$visit_expr(args);
+                }
+                if let Some(options) = options {
+                    $visitor.$visit_expr(options);
+                }
+            }
+            Expr::ChildProcessSpawn { command, args, options } => {
+                $visitor.$visit_expr(command);
+                if let Some(args) = args {
+                    $visitor.$visit_expr(args);
+                }
+                if let Some(options) = options {
+                    $visitor.$visit_expr(options);
+                }
+            }
+            Expr::ChildProcessExec { command, options, callback } => {
+                $visitor.$visit_expr(command);
+                if let Some(options) = options {
+                    $visitor.$visit_expr(options);
+                }
+                if let Some(callback) = callback {
+                    $visitor.$visit_expr(callback);
+                }
+            }
+            Expr::FetchWithOptions { url, method, body, headers, on_upload_progress, .. } => {
+                $visitor.$visit_expr(url);
+                $visitor.$visit_expr(method);
+                $visitor.$visit_expr(body);
+                for (_, value) in headers {
+                    $visitor.$visit_expr(value);
+                }
+                if let Some(on_upload_progress) = on_upload_progress {
+                    $visitor.$visit_expr(on_upload_progress);
+                }
+            }
+            Expr::NetCreateServer { options, connection_listener } => {
+                if let Some(options) = options {
+                    $visitor.$visit_expr(options);
+                }
+                if let Some(connection_listener) = connection_listener {
+                    $visitor.$visit_expr(connection_listener);
+                }
+            }
+            Expr::NetCreateConnection { port, host, connect_listener } => {
+                $visitor.$visit_expr(port);
+                if let Some(host) = host {
+                    $visitor.$visit_expr(host);
+                }
+                if let Some(connect_listener) = connect_listener {
+                    $visitor.$visit_expr(connect_listener);
+                }
+            }
+            Expr::NetConnect { port, host, connect_listener } => {
+                $visitor.$visit_expr(port);
+                if let Some(host) = host {
+                    $visitor.$visit_expr(host);
+                }
+                if let Some(connect_listener) = connect_listener {
+                    $visitor.$visit_expr(connect_listener);
+                }
+            }
+            Expr::ArrayPush { value, .. } => {
+                $visitor.$visit_expr(value);
+            }
+            Expr::ArrayUnshift { value, .. } => {
+                $visitor.$visit_expr(value);
+            }
+            Expr::ArrayIndexOf { array, value } => {
+                $visitor.$visit_expr(array);
+                $visitor.$visit_expr(value);
+            }
+            Expr::ArrayIncludes { array, value } => {
+                $visitor.$visit_expr(array);
+                $visitor.$visit_expr(value);
+            }
+            Expr::ArraySlice { array, start, end } => {
+                $visitor.$visit_expr(array);
+                $visitor.$visit_expr(start);
+                if let Some(end) = end {
+                    $visitor.$visit_expr(end);
+                }
+            }
+            Expr::ArraySplice { start, delete_count, items, .. } => {
+                $visitor.$visit_expr(start);
+                if let Some(delete_count) = delete_count {
+                    $visitor.$visit_expr(delete_count);
+                }
+                for expr in items {
+                    $visitor.$visit_expr(expr);
+                }
+            }
+            Expr::ArrayForEach { array, callback } => {
+                $visitor.$visit_expr(array);
+                $visitor.$visit_expr(callback);
+            }
+            Expr::ArrayMap { array, callback } => {
+                $visitor.$visit_expr(array);
+                $visitor.$visit_expr(callback);
+            }
+            Expr::ArrayFilter { array, callback } => {
+                $visitor.$visit_expr(array);
+                $visitor.$visit_expr(callback);
+            }
+            Expr::ArrayFind { array, callback } => {
+                $visitor.$visit_expr(array);
+                $visitor.$visit_expr(callback);
+            }
+            Expr::ArrayFindIndex { array, callback } => {
+                $visitor.$visit_expr(array);
+                $visitor.$visit_expr(callback);
+            }
+            Expr::ArrayReduce { array, callback, initial } => {
+                $visitor.$visit_expr(array);
+                $visitor.$visit_expr(callback);
+                if let Some(initial) = initial {
+                    $visitor.$visit_expr(initial);
+                }
+            }
+            Expr::ArrayJoin { array, separator } => {
+                $visitor.$visit_expr(array);
+                if let Some(separator) = separator {
+                    $visitor.$visit_expr(separator);
+                }
+            }
+            Expr::StringSplit(a, b) => {
+                $visitor.$visit_expr(a);
+                $visitor.$visit_expr(b);
+            }
+            Expr::MapSet { map, key, value } => {
+                $visitor.$visit_expr(map);
+                $visitor.$visit_expr(key);
+                $visitor.$visit_expr(value);
+            }
+            Expr::MapGet { map, key } => {
+                $visitor.$visit_expr(map);
+                $visitor.$visit_expr(key);
+            }
+            Expr::MapHas { map, key } => {
+                $visitor.$visit_expr(map);
+                $visitor.$visit_expr(key);
+            }
+            Expr::MapDelete { map, key } => {
+                $visitor.$visit_expr(map);
+                $visitor.$visit_expr(key);
+            }
+            Expr::SetAdd { value, .. } => {
+                $visitor.$visit_expr(value);
+            }
+            Expr::SetHas { set, value } => {
+                $visitor.$visit_expr(set);
+                $visitor.$visit_expr(value);
+            }
+            Expr::SetDelete { set, value } => {
+                $visitor.$visit_expr(set);
+                $visitor.$visit_expr(value);
+            }
+            Expr::ErrorNew { message, .. } => {
+                if let Some(message) = message {
+                    $visitor.$visit_expr(message);
+                }
+            }
+            Expr::UrlNew { url, base } => {
+                $visitor.$visit_expr(url);
+                if let Some(base) = base {
+                    $visitor.$visit_expr(base);
+                }
+            }
+            Expr::UrlSearchParamsGet { params, name } => {
+                $visitor.$visit_expr(params);
+                $visitor.$visit_expr(name);
+            }
+            Expr::UrlSearchParamsHas { params, name } => {
+                $visitor.$visit_expr(params);
+                $visitor.$visit_expr(name);
+            }
+            Expr::UrlSearchParamsSet { params, name, value } => {
+                $visitor.$visit_expr(params);
+                $visitor.$visit_expr(name);
+                $visitor.$visit_expr(value);
+            }
+            Expr::UrlSearchParamsAppend { params, name, value } => {
+                $visitor.$visit_expr(params);
+                $visitor.$visit_expr(name);
+                $visitor.$visit_expr(value);
+            }
+            Expr::UrlSearchParamsDelete { params, name } => {
+                $visitor.$visit_expr(params);
+                $visitor.$visit_expr(name);
+            }
+            Expr::UrlSearchParamsGetAll { params, name } => {
+                $visitor.$visit_expr(params);
+                $visitor.$visit_expr(name);
+            }
+            Expr::DisposeResource { resource, .. } => {
+                $visitor.$visit_expr(resource);
+            }
+            Expr::DisposableStackAdopt { stack, value, on_dispose } => {
+                $visitor.$visit_expr(stack);
+                $visitor.$visit_expr(value);
+                $visitor.$visit_expr(on_dispose);
+            }
+            Expr::DisposableStackDefer { stack, on_dispose } => {
+                $visitor.$visit_expr(stack);
+                $visitor.$visit_expr(on_dispose);
+            }
+            Expr::Closure { params, body, .. } => {
+                for Param { default, .. } in params {
+                    if let Some(default) = default {
+                        $visitor.$visit_expr(default);
+                    }
+                }
+                $visitor.$visit_stmts(body);
+            }
+            Expr::RegExpTest { regex, string } => {
+                $visitor.$visit_expr(regex);
+                $visitor.$visit_expr(string);
+            }
+            Expr::StringMatch { string, regex } => {
+                $visitor.$visit_expr(string);
+                $visitor.$visit_expr(regex);
+            }
+            Expr::StringReplace { string, pattern, replacement } => {
+                $visitor.$visit_expr(string);
+                $visitor.$visit_expr(pattern);
+                $visitor.$visit_expr(replacement);
+            }
+            Expr::ParseInt { string, radix } => {
+                $visitor.$visit_expr(string);
+                if let Some(radix) = radix {
+                    $visitor.$visit_expr(radix);
+                }
+            }
+            Expr::JsGetExport { module_handle, .. } => {
+                $visitor.$visit_expr(module_handle);
+            }
+            Expr::JsCallFunction { module_handle, args, .. } => {
+                $visitor.$visit_expr(module_handle);
+                for expr in args {
+                    $visitor.$visit_expr(expr);
+                }
+            }
+            Expr::JsCallMethod { object, args, .. } => {
+                $visitor.$visit_expr(object);
+                for expr in args {
+                    $visitor.$visit_expr(expr);
+                }
+            }
+            Expr::JsGetProperty { object, .. } => {
+                $visitor.$visit_expr(object);
+            }
+            Expr::JsSetProperty { object, value, .. } => {
+                $visitor.$visit_expr(object);
+                $visitor.$visit_expr(value);
+            }
+            Expr::JsNew { module_handle, args, .. } => {
+                $visitor.$visit_expr(module_handle);
+                for expr in args {
+                    $visitor.$visit_expr(expr);
+                }
+            }
+            Expr::JsNewFromHandle { constructor, args } => {
+                $visitor.$visit_expr(constructor);
+                for expr in args {
+                    $visitor.$visit_expr(expr);
+                }
+            }
+            Expr::JsCreateCallback { closure, .. } => {
+                $visitor.$visit_expr(closure);
+            }
+            Expr::ImportMetaResolve { specifier, .. } => {
+                $visitor.$visit_expr(specifier);
+            }
+            Expr::Undefined | Expr::Null | Expr::Bool(..) | Expr::Number(..) | Expr::Integer(..) |
+                Expr::BigInt(..) | Expr::String(..) | Expr::LocalGet(..) | Expr::GlobalGet(..) |
+                Expr::Update { .. } | Expr::FuncRef(..) | Expr::ExternFuncRef { .. } |
+                Expr::NativeModuleRef(..) | Expr::ClassRef(..) | Expr::EnumMember { .. } |
+                Expr::StaticFieldGet { .. } | Expr::This | Expr::EnvGet(..) | Expr::ProcessUptime |
+                Expr::ProcessCwd | Expr::ProcessArgv | Expr::ProcessMemoryUsage |
+                Expr::ProcessStdinRead | Expr::ProcessStdinPipe(..) | Expr::MathRandom |
+                Expr::CryptoRandomUUID | Expr::OsPlatform | Expr::OsArch | Expr::OsHostname |
+                Expr::OsHomedir | Expr::OsTmpdir | Expr::OsTotalmem | Expr::OsFreemem |
+                Expr::OsUptime | Expr::OsType | Expr::OsRelease | Expr::OsCpus |
+                Expr::OsNetworkInterfaces | Expr::OsUserInfo | Expr::OsEOL | Expr::ArrayPop(..) |
+                Expr::ArrayShift(..) | Expr::MapNew | Expr::SetNew | Expr::DateNow |
+                Expr::DisposableStackNew | Expr::RegExp { .. } | Expr::NativeAddonLoad { .. } |
+                Expr::JsLoadModule { .. } | Expr::ImportMetaRuntime { .. } => {}
+        }
+    };
+}
+
+/// Visit the expressions and statements directly under `stmt`
+macro_rules! walk_stmt {
+    ($visitor:ident, $stmt:ident, $visit_expr:ident, $visit_stmt:ident, $visit_stmts:ident) => {
+        match $stmt {
+            Stmt::Let { init, .. } => {
+                if let Some(init) = init {
+                    $visitor.$visit_expr(init);
+                }
+            }
+            Stmt::Expr(expr) | Stmt::Throw(expr) => $visitor.$visit_expr(expr),
+            Stmt::Return(expr) => {
+                if let Some(expr) = expr {
+                    $visitor.$visit_expr(expr);
+                }
+            }
+            Stmt::If { condition, then_branch, else_branch } => {
+                $visitor.$visit_expr(condition);
+                $visitor.$visit_stmts(then_branch);
+                if let Some(else_branch) = else_branch {
+                    $visitor.$visit_stmts(else_branch);
+                }
+            }
+            Stmt::While { condition, body } => {
+                $visitor.$visit_expr(condition);
+                $visitor.$visit_stmts(body);
+            }
+            Stmt::For { init, condition, update, body } => {
+                if let Some(init) = init {
+                    $visitor.$visit_stmt(init);
+                }
+                if let Some(condition) = condition {
+                    $visitor.$visit_expr(condition);
+                }
+                if let Some(update) = update {
+                    $visitor.$visit_expr(update);
+                }
+                $visitor.$visit_stmts(body);
+            }
+            Stmt::Break | Stmt::Continue => {}
+            Stmt::Try { body, catch, finally } => {
+                $visitor.$visit_stmts(body);
+                if let Some(CatchClause { body, .. }) = catch {
+                    $visitor.$visit_stmts(body);
+                }
+                if let Some(finally) = finally {
+                    $visitor.$visit_stmts(finally);
+                }
+            }
+            Stmt::Switch { discriminant, cases } => {
+                $visitor.$visit_expr(discriminant);
+                for SwitchCase { test, body } in cases {
+                    if let Some(test) = test {
+                        $visitor.$visit_expr(test);
+                    }
+                    $visitor.$visit_stmts(body);
+                }
+            }
+        }
+    };
+}
+
+/// Visit the expressions and statement lists directly under `expr`
+pub fn walk_expr<V: Visit + ?Sized>(visitor: &mut V, expr: &Expr) {
+    walk_expr!(visitor, expr, visit_expr, visit_stmts)
+}
+
+/// Visit the expressions and statement lists directly under `expr`
+pub fn walk_expr_mut<V: VisitMut + ?Sized>(visitor: &mut V, expr: &mut Expr) {
+    walk_expr!(visitor, expr, visit_expr_mut, visit_stmts_mut)
+}
+
+/// Visit the expressions and statements directly under `stmt`
+pub fn walk_stmt<V: Visit + ?Sized>(visitor: &mut V, stmt: &Stmt) {
+    walk_stmt!(visitor, stmt, visit_expr, visit_stmt, visit_stmts)
+}
+
+/// Visit the expressions and statements directly under `stmt`
+pub fn walk_stmt_mut<V: VisitMut + ?Sized>(visitor: &mut V, stmt: &mut Stmt) {
+    walk_stmt!(visitor, stmt, visit_expr_mut, visit_stmt_mut, visit_stmts_mut)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use perry_diagnostics::Span;
+    use perry_types::{LocalId, Type};
+
+    /// `if (x) { run(() => { y = z; }); }`
+    fn callback_in_branch() -> Stmt {
+        let closure = Expr::Closure {
+            func_id: 1,
+            params: Vec::new(),
+            return_type: Type::Void,
+            body: vec![Stmt::Expr(Expr::LocalSet(1, Box::new(Expr::LocalGet(2))))],
+            captures: vec![1, 2],
+            mutable_captures: vec![1],
+            captures_this: false,
+            enclosing_class: None,
+            is_async: false,
+        };
+        Stmt::If {
+            condition: Expr::LocalGet(0),
+            then_branch: vec![Stmt::Expr(Expr::Call {
+                callee: Box::new(Expr::FuncRef(0)),
+                args: vec![closure],
+                type_args: Vec::new(),
+                span: Span::DUMMY,
+            })],
+            else_branch: None,
+        }
+    }
+
+    struct Reads(Vec<LocalId>);
+
+    impl Visit for Reads {
+        fn visit_expr(&mut self, expr: &Expr) {
+            if let Expr::LocalGet(id) = expr {
+                self.0.push(*id);
+            }
+            walk_expr(self, expr);
+        }
+    }
+
+    struct Inline(LocalId, f64);
+
+    impl VisitMut for Inline {
+        fn visit_expr_mut(&mut self, expr: &mut Expr) {
+            if matches!(expr, Expr::LocalGet(id) if *id == self.0) {
+                *expr = Expr::Number(self.1);
+            }
+            walk_expr_mut(self, expr);
+        }
+    }
+
+    #[test]
+    fn test_walk_reaches_closure_bodies() {
+        let mut stmt = callback_in_branch();
+        let mut reads = Reads(Vec::new());
+        reads.visit_stmt(&stmt);
+        assert_eq!(reads.0, vec![0, 2]);
+
+        Inline(2, 1.5).visit_stmt_mut(&mut stmt);
+        let mut reads = Reads(Vec::new());
+        reads.visit_stmt(&stmt);
+        assert_eq!(reads.0, vec![0]);
+    }
+}
@@ -8,11 +8,28 @@
 //! V8 objects (objects, arrays, functions) returned to native code are stored
 //! in a thread-local handle table. The native code receives a handle ID that
 //! can be used to retrieve the V8 object for subsequent operations.
+//!
+//! A handle keeps its value alive until it is released (`dispose()` from
+//! `perry/js-handles`). Handles made for the arguments of a native callback are
+//! weakened when the callback returns, unless retained: V8 may then collect the
+//! value, and its finalizer drops the entry. Each time the table passes 10,000,
+//! 100,000, ... live handles, a summary by kind is printed to stderr.
+//!
+//! ## Class Instances
+//!
+//! Instances of compiled classes go to V8 as Proxies around an object holding the native
+//! pointer and one function per method. Reads and writes of fields and accessors go to
+//! the native object, so both sides see the same state; methods call the compiled
+//! functions. A wrapper passed back to native code is the instance itself again.
 
+use perry_runtime::class_bridge;
+use perry_runtime::object::ObjectHeader;
 use perry_runtime::JSValue;
 use deno_core::v8;
 use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
+use std::ops::Range;
+use std::time::Instant;
 
 // NaN-boxing constants (must match perry-runtime/src/value.rs)
 const TAG_UNDEFINED: u64 = 0x7FFC_0000_0000_0001;
@@ -31,12 +48,40 @@ const JS_HANDLE_TAG: u64 = 0x7FFB_0000_0000_0000;
 const TAG_MASK: u64 = 0xFFFF_0000_0000_0000;
 const POINTER_MASK: u64 = 0x0000_FFFF_FFFF_FFFF;
 
+/// Property of V8 objects standing for native ones, holding the native pointer
+const NATIVE_PTR_KEY: &str = "__native_ptr__";
+
+/// How the handle table holds a V8 value
+enum Slot {
+    /// Alive until the handle is released
+    Strong(v8::Global<v8::Value>),
+    /// Alive while JavaScript references it; the entry goes once V8 collects it
+    Weak(v8::Weak<v8::Value>),
+}
+
+struct HandleEntry {
+    slot: Slot,
+    /// `object (Constructor)`, `array` or `function name`, for diagnostics
+    kind: String,
+    created: Instant,
+    /// Stays strong past the callback it was an argument of
+    retained: bool,
+}
+
 // Thread-local storage for V8 object handles
 thread_local! {
-    /// Maps handle IDs to V8 Global handles
-    static JS_OBJECT_HANDLES: RefCell<HashMap<u64, v8::Global<v8::Value>>> = RefCell::new(HashMap::new());
+    /// Maps handle IDs to V8 values
+    static JS_OBJECT_HANDLES: RefCell<HashMap<u64, HandleEntry>> = RefCell::new(HashMap::new());
     /// Counter for generating unique handle IDs
     static NEXT_HANDLE_ID: Cell<u64> = const { Cell::new(1) };
+    /// Weak handles whose values V8 collected, removed from the table on the next store
+    static COLLECTED_HANDLES: RefCell<Vec<u64>> = const { RefCell::new(Vec::new()) };
+    /// Live handle count at which the next leak warning is printed
+    static LEAK_WARNING_AT: Cell<usize> = const { Cell::new(10_000) };
+    /// Proxy handler shared by all class instance wrappers
+    static INSTANCE_HANDLER: RefCell<Option<v8::Global<v8::Object>>> = const { RefCell::new(None) };
+    /// Wrappers by native pointer, so an instance is the same object each time it crosses
+    static INSTANCE_WRAPPERS: RefCell<HashMap<usize, v8::Weak<v8::Value>>> = RefCell::new(HashMap::new());
 }
 
 /// Store a V8 value in the handle table and return a handle ID
@@ -46,17 +91,32 @@ pub fn store_js_handle(scope: &mut v8::HandleScope, value: v8::Local<v8::Value>)
         id.set(current + 1);
         current
     });
-    let global = v8::Global::new(scope, value);
-    JS_OBJECT_HANDLES.with(|handles| {
-        handles.borrow_mut().insert(handle_id, global);
+    let entry = HandleEntry {
+        slot: Slot::Strong(v8::Global::new(scope, value)),
+        kind: describe(scope, value),
+        created: Instant::now(),
+        retained: false,
+    };
+    remove_collected_handles();
+    let live = JS_OBJECT_HANDLES.with(|handles| {
+        let mut handles = handles.borrow_mut();
+        handles.insert(handle_id, entry);
+        handles.len()
     });
+    if live >= LEAK_WARNING_AT.with(Cell::get) {
+        LEAK_WARNING_AT.with(|at| at.set(live * 10));
+        warn_live_handles(live);
+    }
     handle_id
 }
 
-/// Retrieve a V8 value from the handle table
+/// Retrieve a V8 value from the handle table; None once a weak value was collected
 pub fn get_js_handle<'s>(scope: &mut v8::HandleScope<'s>, handle: u64) -> Option<v8::Local<'s, v8::Value>> {
     JS_OBJECT_HANDLES.with(|handles| {
-        handles.borrow().get(&handle).map(|g| v8::Local::new(scope, g))
+        handles.borrow().get(&handle).and_then(|entry| match &entry.slot {
+            Slot::Strong(global) => Some(v8::Local::new(scope, global)),
+            Slot::Weak(weak) => weak.to_local(scope),
+        })
     })
 }
 
@@ -67,6 +127,133 @@ pub fn release_js_handle(handle: u64) -> bool {
     })
 }
 
+/// The ID the next stored handle gets; handles stored from here on are `start..next_handle_id()`
+pub fn next_handle_id() -> u64 {
+    NEXT_HANDLE_ID.with(Cell::get)
+}
+
+/// Keep a handle strong when the callback it was made for returns. False if it is unknown.
+/// A handle that is weak already also needs [`strengthen_js_handle`].
+pub fn retain_js_handle(handle: u64) -> bool {
+    JS_OBJECT_HANDLES.with(|handles| {
+        handles.borrow_mut().get_mut(&handle).map(|entry| entry.retained = true).is_some()
+    })
+}
+
+/// Check if a handle holds its value weakly
+pub fn is_weak_js_handle(handle: u64) -> bool {
+    JS_OBJECT_HANDLES.with(|handles| {
+        matches!(handles.borrow().get(&handle), Some(HandleEntry { slot: Slot::Weak(_), .. }))
+    })
+}
+
+/// Hold a weak handle's value strongly again; false if V8 collected it already
+pub fn strengthen_js_handle(scope: &mut v8::HandleScope, handle: u64) -> bool {
+    JS_OBJECT_HANDLES.with(|handles| {
+        let mut handles = handles.borrow_mut();
+        let Some(entry) = handles.get_mut(&handle) else { return false };
+        if let Slot::Weak(weak) = &entry.slot {
+            let Some(value) = weak.to_local(scope) else { return false };
+            entry.slot = Slot::Strong(v8::Global::new(scope, value));
+        }
+        true
+    })
+}
+
+/// Hold the values of handles `ids` weakly, except retained ones; called when the native
+/// callback they were made for returns
+pub fn weaken_js_handles(scope: &mut v8::HandleScope, ids: Range<u64>) {
+    JS_OBJECT_HANDLES.with(|handles| {
+        let mut handles = handles.borrow_mut();
+        for id in ids {
+            let Some(entry) = handles.get_mut(&id) else { continue };
+            let Slot::Strong(global) = &entry.slot else { continue };
+            if entry.retained {
+                continue;
+            }
+            let value = v8::Local::new(scope, global);
+            // Finalizers run inside GC, so they only note the ID
+            let weak = v8::Weak::with_finalizer(scope, value, Box::new(move |_| {
+                COLLECTED_HANDLES.with(|collected| collected.borrow_mut().push(id));
+            }));
+            entry.slot = Slot::Weak(weak);
+        }
+    });
+}
+
+/// One line per live handle, oldest first, e.g. `#12 object (Socket) strong 5.2s`
+pub fn live_js_handles() -> Vec<String> {
+    remove_collected_handles();
+    JS_OBJECT_HANDLES.with(|handles| {
+        let handles = handles.borrow();
+        let mut ids: Vec<u64> = handles.keys().copied().collect();
+        ids.sort_unstable();
+        ids.iter()
+            .map(|id| {
+                let entry = &handles[id];
+                let hold = match entry.slot {
+                    Slot::Strong(_) => "strong",
+                    Slot::Weak(_) => "weak",
+                };
+                format!("#{} {} {} {:.1}s", id, entry.kind, hold, entry.created.elapsed().as_secs_f64())
+            })
+            .collect()
+    })
+}
+
+fn remove_collected_handles() {
+    let collected = COLLECTED_HANDLES.with(|collected| std::mem::take(&mut *collected.borrow_mut()));
+    if !collected.is_empty() {
+        JS_OBJECT_HANDLES.with(|handles| {
+            let mut handles = handles.borrow_mut();
+            for id in collected {
+                handles.remove(&id);
+            }
+        });
+    }
+}
+
+/// What a handle holds, for diagnostics
+fn describe(scope: &mut v8::HandleScope, value: v8::Local<v8::Value>) -> String {
+    if let Ok(func) = v8::Local::<v8::Function>::try_from(value) {
+        let name = func.get_name(scope).to_rust_string_lossy(scope);
+        return if name.is_empty() { "function".to_string() } else { format!("function {}", name) };
+    }
+    if value.is_array() {
+        return "array".to_string();
+    }
+    match value.to_object(scope) {
+        Some(obj) => {
+            let constructor = obj.get_constructor_name().to_rust_string_lossy(scope);
+            if constructor.is_empty() || constructor == "Object" {
+                "object".to_string()
+            } else {
+                format!("object ({})", constructor)
+            }
+        }
+        None => "value".to_string(),
+    }
+}
+
+/// Print how many handles are live, by kind; so many usually means some are never released
+fn warn_live_handles(live: usize) {
+    let mut kinds: HashMap<String, usize> = HashMap::new();
+    JS_OBJECT_HANDLES.with(|handles| {
+        for entry in handles.borrow().values() {
+            *kinds.entry(entry.kind.clone()).or_default() += 1;
+        }
+    });
+    let mut kinds: Vec<(String, usize)> = kinds.into_iter().collect();
+    kinds.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    let summary: Vec<String> = kinds.iter().take(5).map(|(kind, count)| format!("{} {}", count, kind)).collect();
+    eprintln!(
+        "Warning: native code holds {} JavaScript values ({}); release the ones it no longer \
+         needs with dispose() from perry/js-handles",
+        live,
+        summary.join(", ")
+    );
+}
+
 /// Check if a NaN-boxed value is a JS handle
 pub fn is_js_handle(value: f64) -> bool {
     let bits = value.to_bits();
@@ -215,6 +402,11 @@ pub fn v8_to_native(scope: &mut v8::HandleScope<'_>, value: v8::Local<v8::Value>
         return f64::from_bits(BIGINT_TAG | (ptr as u64 & POINTER_MASK));
     }
 
+    // Class instance wrappers are the native instance
+    if let Some(ptr) = wrapped_instance(scope, value) {
+        return f64::from_bits(POINTER_TAG | (ptr as u64 & POINTER_MASK));
+    }
+
     // For functions, always store as JS handle to preserve callability
     if value.is_function() {
         let handle_id = store_js_handle(scope, value);
@@ -285,18 +477,213 @@ fn native_object_to_v8<'s>(scope: &mut v8::HandleScope<'s>, ptr: *const u8) -> v
         return v8::null(scope).into();
     }
 
+    if let Some(obj) = unsafe { class_bridge::bridged_instance(ptr) } {
+        let cached = INSTANCE_WRAPPERS.with(|wrappers| {
+            wrappers.borrow().get(&(obj as usize)).and_then(|weak| weak.to_local(scope))
+        });
+        if let Some(wrapper) = cached {
+            return wrapper;
+        }
+        if let Some(wrapper) = instance_to_v8(scope, obj) {
+            let weak = v8::Weak::new(scope, wrapper);
+            INSTANCE_WRAPPERS.with(|wrappers| {
+                let mut wrappers = wrappers.borrow_mut();
+                // Drop the entries of collected wrappers now and then
+                if wrappers.len() >= 1024 && wrappers.len().is_power_of_two() {
+                    wrappers.retain(|_, weak| !weak.is_empty());
+                }
+                wrappers.insert(obj as usize, weak);
+            });
+            return wrapper;
+        }
+    }
+
     // For now, create a generic object
     // TODO: Properly detect array vs object and convert fields
     let obj = v8::Object::new(scope);
 
     // Store the native pointer as an external value for round-tripping
     let external = v8::External::new(scope, ptr as *mut std::ffi::c_void);
-    let key = v8::String::new(scope, "__native_ptr__").unwrap();
+    let key = v8::String::new(scope, NATIVE_PTR_KEY).unwrap();
     obj.set(scope, key.into(), external.into());
 
     obj.into()
 }
 
+/// Wrap a class instance: a Proxy whose target holds the native pointer and the methods
+fn instance_to_v8<'s>(scope: &mut v8::HandleScope<'s>, obj: *mut ObjectHeader) -> Option<v8::Local<'s, v8::Value>> {
+    let target = v8::Object::new(scope);
+    let external = v8::External::new(scope, obj as *mut std::ffi::c_void);
+    let key = v8::String::new(scope, NATIVE_PTR_KEY)?;
+    target.define_own_property(scope, key.into(), external.into(), v8::PropertyAttribute::DONT_ENUM)?;
+
+    let (_, methods) = class_bridge::member_names(obj);
+    for name in methods {
+        let name_val = v8::String::new(scope, &name)?;
+        let data = v8::Array::new(scope, 2);
+        data.set_index(scope, 0, external.into());
+        data.set_index(scope, 1, name_val.into());
+        let method = v8::Function::builder(instance_method_trampoline).data(data.into()).build(scope)?;
+        target.define_own_property(scope, name_val.into(), method.into(), v8::PropertyAttribute::DONT_ENUM)?;
+    }
+
+    let handler = instance_handler(scope)?;
+    v8::Proxy::new(scope, target, handler).map(Into::into)
+}
+
+/// The Proxy handler for class instance wrappers, created once per thread
+fn instance_handler<'s>(scope: &mut v8::HandleScope<'s>) -> Option<v8::Local<'s, v8::Object>> {
+    let cached = INSTANCE_HANDLER.with(|handler| handler.borrow().as_ref().map(|global| v8::Local::new(scope, global)));
+    if cached.is_some() {
+        return cached;
+    }
+    let handler = v8::Object::new(scope);
+    set_trap(scope, handler, "get", instance_get_trap)?;
+    set_trap(scope, handler, "set", instance_set_trap)?;
+    set_trap(scope, handler, "has", instance_has_trap)?;
+    set_trap(scope, handler, "ownKeys", instance_own_keys_trap)?;
+    set_trap(scope, handler, "getOwnPropertyDescriptor", instance_descriptor_trap)?;
+    let global = v8::Global::new(scope, handler);
+    INSTANCE_HANDLER.with(|cached| *cached.borrow_mut() = Some(global));
+    Some(handler)
+}
+
+fn set_trap(
+    scope: &mut v8::HandleScope,
+    handler: v8::Local<v8::Object>,
+    name: &str,
+    trap: impl v8::MapFnTo<v8::FunctionCallback>,
+) -> Option<()> {
+    let func = v8::Function::builder(trap).build(scope)?;
+    let key = v8::String::new(scope, name)?;
+    handler.set(scope, key.into(), func.into())?;
+    Some(())
+}
+
+/// The native pointer a wrapper's target (or another object standing for a native one) holds
+fn native_ptr_of(scope: &mut v8::HandleScope, target: v8::Local<v8::Value>) -> Option<*mut ObjectHeader> {
+    let target = v8::Local::<v8::Object>::try_from(target).ok()?;
+    let key = v8::String::new(scope, NATIVE_PTR_KEY)?;
+    let value = target.get(scope, key.into())?;
+    let external = v8::Local::<v8::External>::try_from(value).ok()?;
+    Some(external.value() as *mut ObjectHeader)
+}
+
+/// The class instance behind a wrapper
+fn wrapped_instance(scope: &mut v8::HandleScope, value: v8::Local<v8::Value>) -> Option<*mut ObjectHeader> {
+    let proxy = v8::Local::<v8::Proxy>::try_from(value).ok()?;
+    let target = proxy.get_target(scope);
+    let ptr = native_ptr_of(scope, target)?;
+    unsafe { class_bridge::bridged_instance(ptr as *const u8) }
+}
+
+/// A trap's property name, unless it is a symbol
+fn property_name(scope: &mut v8::HandleScope, property: v8::Local<v8::Value>) -> Option<String> {
+    property.is_string().then(|| property.to_rust_string_lossy(scope))
+}
+
+/// `get(target, property)`: fields and getters from the native object, then the target
+fn instance_get_trap(scope: &mut v8::HandleScope, args: v8::FunctionCallbackArguments, mut rv: v8::ReturnValue) {
+    let (target, property) = (args.get(0), args.get(1));
+    if let (Some(obj), Some(name)) = (native_ptr_of(scope, target), property_name(scope, property)) {
+        if let Some(value) = unsafe { class_bridge::get_member(obj, &name) } {
+            rv.set(native_to_v8(scope, value));
+            return;
+        }
+    }
+    // Methods, the native pointer and Object.prototype's members
+    if let Ok(target) = v8::Local::<v8::Object>::try_from(target) {
+        if let Some(value) = target.get(scope, property) {
+            rv.set(value);
+        }
+    }
+}
+
+/// `set(target, property, value)`: fields and setters in the native object, anything else
+/// on the target
+fn instance_set_trap(scope: &mut v8::HandleScope, args: v8::FunctionCallbackArguments, mut rv: v8::ReturnValue) {
+    let (target, property, value) = (args.get(0), args.get(1), args.get(2));
+    if let (Some(obj), Some(name)) = (native_ptr_of(scope, target), property_name(scope, property)) {
+        let native = v8_to_native(scope, value);
+        if unsafe { class_bridge::set_member(obj, &name, native) } {
+            rv.set_bool(true);
+            return;
+        }
+    }
+    let stored = v8::Local::<v8::Object>::try_from(target)
+        .ok()
+        .and_then(|target| target.set(scope, property, value))
+        .unwrap_or(false);
+    rv.set_bool(stored);
+}
+
+/// `has(target, property)`: fields and methods, then the target
+fn instance_has_trap(scope: &mut v8::HandleScope, args: v8::FunctionCallbackArguments, mut rv: v8::ReturnValue) {
+    let (target, property) = (args.get(0), args.get(1));
+    if let (Some(obj), Some(name)) = (native_ptr_of(scope, target), property_name(scope, property)) {
+        let (fields, methods) = class_bridge::member_names(obj);
+        if fields.contains(&name) || methods.contains(&name) {
+            rv.set_bool(true);
+            return;
+        }
+    }
+    let has = v8::Local::<v8::Object>::try_from(target)
+        .ok()
+        .and_then(|target| target.has(scope, property))
+        .unwrap_or(false);
+    rv.set_bool(has);
+}
+
+/// `ownKeys(target)`: the fields, so `Object.keys` and `JSON.stringify` see the instance's data
+fn instance_own_keys_trap(scope: &mut v8::HandleScope, args: v8::FunctionCallbackArguments, mut rv: v8::ReturnValue) {
+    let fields = native_ptr_of(scope, args.get(0))
+        .map(|obj| class_bridge::member_names(obj).0)
+        .unwrap_or_default();
+    let keys: Vec<v8::Local<v8::Value>> = fields
+        .iter()
+        .filter_map(|name| v8::String::new(scope, name).map(Into::into))
+        .collect();
+    rv.set(v8::Array::new_with_elements(scope, &keys).into());
+}
+
+/// `getOwnPropertyDescriptor(target, property)`: fields are plain, writable data properties
+fn instance_descriptor_trap(scope: &mut v8::HandleScope, args: v8::FunctionCallbackArguments, mut rv: v8::ReturnValue) {
+    let (target, property) = (args.get(0), args.get(1));
+    let Some(obj) = native_ptr_of(scope, target) else { return };
+    let Some(name) = property_name(scope, property) else { return };
+    if !class_bridge::member_names(obj).0.contains(&name) {
+        return;
+    }
+    let Some(value) = (unsafe { class_bridge::get_member(obj, &name) }) else { return };
+    let value = native_to_v8(scope, value);
+    let descriptor = v8::Object::new(scope);
+    let yes: v8::Local<v8::Value> = v8::Boolean::new(scope, true).into();
+    for (key, field) in [("value", value), ("writable", yes), ("enumerable", yes), ("configurable", yes)] {
+        if let Some(key) = v8::String::new(scope, key) {
+            descriptor.set(scope, key.into(), field);
+        }
+    }
+    rv.set(descriptor.into());
+}
+
+/// A wrapper's method: calls the compiled method with the arguments converted to native values
+fn instance_method_trampoline(scope: &mut v8::HandleScope, args: v8::FunctionCallbackArguments, mut rv: v8::ReturnValue) {
+    let Ok(data) = v8::Local::<v8::Array>::try_from(args.data()) else { return };
+    let obj = data
+        .get_index(scope, 0)
+        .and_then(|value| v8::Local::<v8::External>::try_from(value).ok())
+        .map(|external| external.value() as *mut ObjectHeader);
+    let name = data.get_index(scope, 1).map(|value| value.to_rust_string_lossy(scope));
+    let (Some(obj), Some(name)) = (obj, name) else { return };
+
+    // Arguments stay strongly held: methods commonly keep them in fields
+    let native_args: Vec<f64> = (0..args.length()).map(|i| v8_to_native(scope, args.get(i))).collect();
+    match unsafe { class_bridge::call_method(obj, &name, &native_args) } {
+        Some(result) => rv.set(native_to_v8(scope, result)),
+        None => rv.set(v8::undefined(scope).into()),
+    }
+}
+
 /// Convert a native BigInt pointer to a V8 BigInt
 fn native_bigint_to_v8<'s>(scope: &mut v8::HandleScope<'s>, ptr: *const u8) -> v8::Local<'s, v8::Value> {
     use perry_runtime::bigint::BigIntHeader;
@@ -305,46 +692,25 @@ fn native_bigint_to_v8<'s>(scope: &mut v8::HandleScope<'s>, ptr: *const u8) -> v
         return v8::BigInt::new_from_i64(scope, 0).into();
     }
 
-    let header = ptr as *const BigIntHeader;
-    let limbs = unsafe { (*header).limbs };
+    let header = unsafe { &*(ptr as *const BigIntHeader) };
+    let limbs = header.limbs();
 
-    // Check if the value fits in i64 (most common case)
-    if limbs[1] == 0 && limbs[2] == 0 && limbs[3] == 0 {
-        // Fits in a single limb - check sign
-        let val = limbs[0];
+    // Most values fit in a single limb
+    if limbs.len() <= 1 {
+        let val = limbs.first().copied().unwrap_or(0);
         if val <= i64::MAX as u64 {
-            return v8::BigInt::new_from_i64(scope, val as i64).into();
+            let val = val as i64;
+            return v8::BigInt::new_from_i64(scope, if header.is_negative() { -val } else { val }).into();
         }
-        // Value is positive but too large for i64, use u64
-        return v8::BigInt::new_from_u64(scope, val).into();
-    }
-
-    // Check if it's a negative number (two's complement: high bit set in top limb)
-    let is_negative = (limbs[3] >> 63) == 1;
-
-    if is_negative {
-        // Convert from two's complement to magnitude
-        let mut magnitude = limbs;
-        // Subtract 1 and invert
-        let mut borrow = 1u64;
-        for limb in magnitude.iter_mut() {
-            let (result, underflow) = limb.overflowing_sub(borrow);
-            *limb = !result;
-            borrow = if underflow { 1 } else { 0 };
+        if !header.is_negative() {
+            return v8::BigInt::new_from_u64(scope, val).into();
         }
-        // Find the actual word count (trim trailing zeros)
-        let word_count = magnitude.iter().rposition(|&x| x != 0).map(|i| i + 1).unwrap_or(1);
-        v8::BigInt::new_from_words(scope, true, &magnitude[..word_count])
-            .map(|bi| bi.into())
-            .unwrap_or_else(|| v8::BigInt::new_from_i64(scope, 0).into())
-    } else {
-        // Positive number with multiple limbs
-        // Find the actual word count (trim trailing zeros)
-        let word_count = limbs.iter().rposition(|&x| x != 0).map(|i| i + 1).unwrap_or(1);
-        v8::BigInt::new_from_words(scope, false, &limbs[..word_count])
-            .map(|bi| bi.into())
-            .unwrap_or_else(|| v8::BigInt::new_from_i64(scope, 0).into())
     }
+
+    // Sign-magnitude maps directly onto V8's words
+    v8::BigInt::new_from_words(scope, header.is_negative(), limbs)
+        .map(|bi| bi.into())
+        .unwrap_or_else(|| v8::BigInt::new_from_i64(scope, 0).into())
 }
 
 /// Convert a V8 object to a native object pointer
@@ -352,7 +718,7 @@ fn v8_object_to_native(scope: &mut v8::HandleScope<'_>, obj: v8::Local<v8::Objec
     use perry_runtime::{js_object_alloc, js_object_set_field};
 
     // Check if this object has a native pointer already
-    let key = v8::String::new(scope, "__native_ptr__").unwrap();
+    let key = v8::String::new(scope, NATIVE_PTR_KEY).unwrap();
     if let Some(val) = obj.get(scope, key.into()) {
         if val.is_external() {
             let external = v8::Local::<v8::External>::try_from(val).unwrap();
@@ -417,57 +783,10 @@ fn v8_array_to_native(scope: &mut v8::HandleScope<'_>, array: v8::Local<v8::Arra
 
 /// Convert a V8 BigInt to a native BigInt pointer
 fn v8_bigint_to_native(_scope: &mut v8::HandleScope<'_>, bigint: v8::Local<v8::BigInt>) -> *mut u8 {
-    use perry_runtime::bigint::BigIntHeader;
-    use std::alloc::{alloc, Layout};
-
-    // Get the word count to determine the size needed
-    let word_count = bigint.word_count();
-
-    // Allocate a BigIntHeader (4 x u64 = 256 bits)
-    let layout = Layout::new::<BigIntHeader>();
-    let ptr = unsafe { alloc(layout) as *mut BigIntHeader };
-    if ptr.is_null() {
-        panic!("Failed to allocate BigInt");
-    }
-
-    if word_count == 0 {
-        // Zero value
-        unsafe {
-            (*ptr).limbs = [0, 0, 0, 0];
-        }
-        return ptr as *mut u8;
-    }
-
-    // Get the words from V8 BigInt
-    let mut words = vec![0u64; word_count];
+    // V8 hands out the sign and the magnitude's words, the same layout as ours
+    let mut words = vec![0u64; bigint.word_count()];
     let (sign_bit, _) = bigint.to_words_array(&mut words);
-
-    // Copy words to our BigIntHeader (up to 4 limbs / 256 bits)
-    unsafe {
-        let mut limbs = [0u64; 4];
-        for (i, &word) in words.iter().enumerate().take(4) {
-            limbs[i] = word;
-        }
-
-        // Handle negative numbers (two's complement for 256 bits)
-        if sign_bit {
-            // Negate: invert all bits and add 1
-            for limb in limbs.iter_mut() {
-                *limb = !*limb;
-            }
-            // Add 1
-            let mut carry = 1u64;
-            for limb in limbs.iter_mut() {
-                let (result, overflow) = limb.overflowing_add(carry);
-                *limb = result;
-                carry = if overflow { 1 } else { 0 };
-            }
-        }
-
-        (*ptr).limbs = limbs;
-    }
-
-    ptr as *mut u8
+    perry_runtime::bigint::bigint_from_limbs(sign_bit, &words) as *mut u8
 }
 
 /// Convert a native array pointer to a V8 array
@@ -3,7 +3,8 @@
 //! These functions are called from compiled native code to interact with
 //! JavaScript modules loaded in the V8 runtime.
 
-use crate::bridge::{native_to_v8, v8_to_native, get_js_handle, store_js_handle, make_js_handle_value, is_js_handle, get_handle_id};
+use crate::bridge::{native_to_v8, v8_to_native, get_js_handle, store_js_handle, make_js_handle_value, is_js_handle, get_handle_id,
+    release_js_handle, retain_js_handle, is_weak_js_handle, strengthen_js_handle, weaken_js_handles, live_js_handles, next_handle_id};
 use crate::{ensure_runtime_initialized, get_tokio_runtime, with_runtime, JsRuntimeState, JS_RUNTIME};
 use deno_core::v8;
 use std::ffi::CStr;
@@ -37,6 +38,7 @@ pub extern "C" fn js_runtime_init() {
     perry_runtime::js_set_handle_array_length(js_handle_array_length);
     perry_runtime::js_set_handle_object_get_property(js_handle_object_get_property);
     perry_runtime::js_set_handle_to_string(js_handle_to_string);
+    perry_runtime::js_set_handle_lifecycle(js_handle_dispose, js_handle_retain, js_handle_live);
 }
 
 /// Shutdown the JavaScript runtime and release resources
@@ -588,6 +590,35 @@ pub extern "C" fn js_handle_to_string(handle: f64) -> *mut perry_runtime::string
     })
 }
 
+/// `dispose()` from perry/js-handles: release a handle's V8 value
+extern "C" fn js_handle_dispose(value: f64) -> i32 {
+    get_handle_id(value).map_or(0, |handle| release_js_handle(handle) as i32)
+}
+
+/// `retain()` from perry/js-handles: hold a handle's V8 value until it is disposed
+extern "C" fn js_handle_retain(value: f64) -> i32 {
+    let Some(handle) = get_handle_id(value) else { return 0 };
+    if !retain_js_handle(handle) {
+        return 0;
+    }
+    if !is_weak_js_handle(handle) {
+        return 1;
+    }
+    with_runtime(|state| {
+        let scope = &mut state.runtime.handle_scope();
+        strengthen_js_handle(scope, handle) as i32
+    })
+}
+
+/// `liveHandles()` from perry/js-handles: the handle table's entries as strings
+extern "C" fn js_handle_live() -> *mut perry_runtime::array::ArrayHeader {
+    let arr = perry_runtime::js_array_alloc(0);
+    live_js_handles().iter().fold(arr, |arr, line| {
+        let s = perry_runtime::js_string_from_bytes(line.as_ptr(), line.len() as u32);
+        perry_runtime::js_array_push_f64(arr, f64::from_bits(perry_runtime::JSValue::string_ptr(s).bits()))
+    })
+}
+
 /// Set a property on a JavaScript object
 /// object_ptr: NaN-boxed value containing a JS handle
 /// value: NaN-boxed value to set
@@ -766,11 +797,21 @@ pub unsafe extern "C" fn js_new_from_handle(
     })
 }
 
+/// A native callback and the V8 function wrapping it
+struct NativeCallback {
+    func_ptr: i64,
+    closure_env: i64,
+    /// Notes the callback as collected once V8 frees the function
+    _function: Option<v8::Weak<v8::Function>>,
+}
+
 // Storage for native callback function pointers and their closure environments
 thread_local! {
-    static NATIVE_CALLBACKS: std::cell::RefCell<std::collections::HashMap<u64, (i64, i64)>> =
+    static NATIVE_CALLBACKS: std::cell::RefCell<std::collections::HashMap<u64, NativeCallback>> =
         std::cell::RefCell::new(std::collections::HashMap::new());
     static NEXT_CALLBACK_ID: std::cell::Cell<u64> = const { std::cell::Cell::new(1) };
+    /// Callbacks whose V8 functions were collected, removed on the next js_create_callback
+    static COLLECTED_CALLBACKS: std::cell::RefCell<Vec<u64>> = const { std::cell::RefCell::new(Vec::new()) };
 }
 
 /// Create a V8 function that wraps a native callback
@@ -791,8 +832,13 @@ pub unsafe extern "C" fn js_create_callback(
         current
     });
 
+    let collected = COLLECTED_CALLBACKS.with(|collected| std::mem::take(&mut *collected.borrow_mut()));
     NATIVE_CALLBACKS.with(|callbacks| {
-        callbacks.borrow_mut().insert(callback_id, (func_ptr, closure_env));
+        let mut callbacks = callbacks.borrow_mut();
+        for id in collected {
+            callbacks.remove(&id);
+        }
+        callbacks.insert(callback_id, NativeCallback { func_ptr, closure_env, _function: None });
     });
 
     with_runtime(|state| {
@@ -812,6 +858,16 @@ pub unsafe extern "C" fn js_create_callback(
 
         match callback_fn {
             Some(func) => {
+                // Once the handle is disposed and JavaScript drops the function, the
+                // callback entry goes too
+                let weak = v8::Weak::with_finalizer(scope, func, Box::new(move |_| {
+                    COLLECTED_CALLBACKS.with(|collected| collected.borrow_mut().push(callback_id));
+                }));
+                NATIVE_CALLBACKS.with(|callbacks| {
+                    if let Some(callback) = callbacks.borrow_mut().get_mut(&callback_id) {
+                        callback._function = Some(weak);
+                    }
+                });
                 let handle_id = store_js_handle(scope, func.into());
                 make_js_handle_value(handle_id)
             }
@@ -846,7 +902,7 @@ fn native_callback_trampoline(
 
     // Get the function pointer and closure environment
     let (func_ptr, closure_env) = NATIVE_CALLBACKS.with(|callbacks| {
-        callbacks.borrow().get(&callback_id).copied().unwrap_or((0, 0))
+        callbacks.borrow().get(&callback_id).map_or((0, 0), |callback| (callback.func_ptr, callback.closure_env))
     });
 
     if func_ptr == 0 {
@@ -855,19 +911,22 @@ fn native_callback_trampoline(
         return;
     }
 
-    // Convert arguments to native format
+    // Convert arguments to native format; the handles made for them are weakened on return
+    let first_arg_handle = next_handle_id();
     let arg_count = args.length();
     let mut native_args: Vec<f64> = Vec::with_capacity(arg_count as usize);
     for i in 0..arg_count {
         let arg = args.get(i);
         native_args.push(v8_to_native(scope, arg));
     }
+    let arg_handles = next_handle_id() - first_arg_handle;
 
     // Call the native function
     // Function signature: fn(closure_env: i64, args_ptr: *const f64, args_len: i64) -> f64
     type CallbackFn = extern "C" fn(i64, *const f64, i64) -> f64;
     let callback: CallbackFn = unsafe { std::mem::transmute(func_ptr as *const ()) };
     let result = callback(closure_env, native_args.as_ptr(), native_args.len() as i64);
+    weaken_js_handles(scope, first_arg_handle..first_arg_handle + arg_handles);
 
     // Convert result back to V8
     let v8_result = native_to_v8(scope, result);
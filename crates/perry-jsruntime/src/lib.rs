@@ -11,9 +11,10 @@ mod bridge;
 mod interop;
 mod modules;
 mod ops;
+mod package_exports;
 
 pub use bridge::{native_to_v8, v8_to_native, store_js_handle, get_js_handle, release_js_handle,
-    is_js_handle, get_handle_id, make_js_handle_value};
+    is_js_handle, get_handle_id, make_js_handle_value, retain_js_handle, live_js_handles};
 pub use interop::{
     js_call_function, js_call_method, js_get_export, js_load_module, js_register_native_function,
     js_runtime_init, js_runtime_shutdown, js_handle_object_get_property, js_set_property,
@@ -7,6 +7,8 @@ use deno_core::error::AnyError;
 use deno_core::{ModuleLoadResponse, ModuleLoader, ModuleSource, ModuleSourceCode, ModuleSpecifier, ModuleType, RequestedModuleType, ResolutionKind};
 use std::path::{Path, PathBuf};
 
+use crate::package_exports::{read_exports, resolve_exports, ExportsResolution, IMPORT_CONDITIONS};
+
 /// Node.js-compatible module loader
 pub struct NodeModuleLoader {
     /// Base directory for module resolution
@@ -123,9 +125,8 @@ impl NodeModuleLoader {
                 // Check for package.json
                 let package_json = node_modules.join("package.json");
                 if package_json.exists() {
-                    if let Ok(entry_point) = self.resolve_package_entry(&node_modules, &package_json, subpath.as_deref()) {
-                        return Ok(entry_point);
-                    }
+                    // The first package found decides, as in Node (no fallback past "exports")
+                    return self.resolve_package_entry(&node_modules, &package_json, subpath.as_deref());
                 }
 
                 // Fall back to index.js
@@ -159,22 +160,26 @@ impl NodeModuleLoader {
         let content = std::fs::read_to_string(package_json)?;
         let pkg: serde_json::Value = serde_json::from_str(&content)?;
 
-        // If there's a subpath, resolve it directly
+        // "exports" takes precedence over every other field, for the main entry and subpaths
+        if let Some(exports) = read_exports(&content)? {
+            let export_key = subpath.map_or_else(|| ".".to_string(), |sub| format!("./{}", sub));
+            return match resolve_exports(&exports, &export_key, IMPORT_CONDITIONS) {
+                ExportsResolution::Resolved(entry) => Ok(package_dir.join(entry)),
+                ExportsResolution::NotExported => Err(anyhow!(
+                    "Package subpath '{}' is not defined by \"exports\" in {:?}",
+                    export_key,
+                    package_json
+                )),
+                ExportsResolution::Invalid(e) => Err(anyhow!("Invalid \"exports\" in {:?}: {}", package_json, e)),
+            };
+        }
+
+        // Without "exports", subpaths resolve directly
         if let Some(sub) = subpath {
             let subpath_resolved = package_dir.join(sub);
             return self.resolve_with_extensions(subpath_resolved);
         }
 
-        // Try "exports" field first (modern packages)
-        if let Some(exports) = pkg.get("exports") {
-            if let Some(entry) = resolve_exports(exports, ".") {
-                let entry_path = package_dir.join(entry);
-                if entry_path.exists() {
-                    return Ok(entry_path);
-                }
-            }
-        }
-
         // Try "module" field (ESM)
         if let Some(module) = pkg.get("module").and_then(|v| v.as_str()) {
             let module_path = package_dir.join(module);
@@ -340,32 +345,6 @@ fn parse_package_specifier(specifier: &str) -> (String, Option<String>) {
     (specifier.to_string(), None)
 }
 
-/// Resolve exports field from package.json
-fn resolve_exports(exports: &serde_json::Value, subpath: &str) -> Option<String> {
-    match exports {
-        serde_json::Value::String(s) => Some(s.clone()),
-        serde_json::Value::Object(map) => {
-            // Try the specific subpath
-            if let Some(entry) = map.get(subpath) {
-                return resolve_exports(entry, subpath);
-            }
-
-            // Try "." for main entry
-            if subpath == "." {
-                // Try common conditions
-                for condition in ["import", "module", "default", "require", "node"] {
-                    if let Some(entry) = map.get(condition) {
-                        return resolve_exports(entry, subpath);
-                    }
-                }
-            }
-
-            None
-        }
-        _ => None,
-    }
-}
-
 /// Check if code appears to be CommonJS
 fn is_commonjs(code: &str) -> bool {
     // Quick heuristics for CommonJS detection
@@ -5,10 +5,12 @@
 
 use anyhow::Result;
 use perry_diagnostics::{Diagnostic, DiagnosticCode, Diagnostics, FileId, SourceCache, Span};
-use swc_common::{input::StringInput, sync::Lrc, FileName, SourceMap};
+use swc_common::{input::StringInput, sync::Lrc, BytePos, FileName, SourceMap, DUMMY_SP};
 use swc_ecma_ast::Module;
 use swc_ecma_parser::{lexer::Lexer, Parser, Syntax, TsSyntax};
 
+mod recovery;
+
 // Re-export AST types for consumers that need to inspect the AST
 pub use swc_ecma_ast;
 
@@ -26,28 +28,20 @@ pub struct ParseResult {
     pub diagnostics: Diagnostics,
 }
 
-/// Parse TypeScript source code into an AST Module with diagnostic support.
-///
-/// This function parses TypeScript source code, adds it to the source cache,
-/// and returns both the AST and any diagnostics encountered during parsing.
-///
-/// # Arguments
-///
-/// * `source` - The TypeScript source code to parse
-/// * `filename` - The filename for error reporting
-/// * `cache` - The source cache to add the file to
-///
-/// # Returns
-///
-/// A `ParseResult` containing the AST, file ID, and any diagnostics.
-pub fn parse_typescript_with_cache(
-    source: &str,
-    filename: &str,
-    cache: &mut SourceCache,
-) -> Result<ParseResult> {
-    // Add the source to the cache
-    let file_id = cache.add_file(filename, source.to_string());
+/// Most syntax errors the recovering parser reports for one file before giving up
+const MAX_RECOVERED_ERRORS: usize = 100;
+
+/// Outcome of one SWC parse over a source text
+struct RawParse {
+    module: std::result::Result<Module, swc_ecma_parser::error::Error>,
+    /// Errors the parser recovered from by itself
+    errors: Vec<swc_ecma_parser::error::Error>,
+    /// Position of the first byte of the source in SWC spans
+    start_pos: BytePos,
+}
 
+/// `dts` parses the source as a declaration file (`.d.ts`)
+fn parse_source(source: &str, filename: &str, dts: bool) -> RawParse {
     // Create SWC source map (separate from our cache, used internally by SWC)
     let source_map: Lrc<SourceMap> = Default::default();
     let source_file = source_map.new_source_file(
@@ -59,7 +53,7 @@ pub fn parse_typescript_with_cache(
         Syntax::Typescript(TsSyntax {
             tsx: false,
             decorators: true,
-            dts: false,
+            dts,
             no_early_errors: false,
             disallow_ambiguous_jsx_like: false,
         }),
@@ -69,24 +63,57 @@ pub fn parse_typescript_with_cache(
     );
 
     let mut parser = Parser::new_from(lexer);
-    let mut diagnostics = Diagnostics::new();
+    let module = parser.parse_module();
+    RawParse {
+        module,
+        errors: parser.take_errors(),
+        start_pos: source_file.start_pos,
+    }
+}
+
+/// Span of a parse error as byte offsets into the source
+fn error_span(error: &swc_ecma_parser::error::Error, start_pos: BytePos, file_id: FileId) -> Span {
+    let span = error.span();
+    Span::new(
+        file_id,
+        span.lo.0.saturating_sub(start_pos.0),
+        span.hi.0.saturating_sub(start_pos.0),
+    )
+}
 
-    let module = parser.parse_module().map_err(|e| {
-        // Convert SWC error to our diagnostic
-        let span = Span::new(file_id, e.span().lo.0, e.span().hi.0);
-        let diag = Diagnostic::error(DiagnosticCode::ParseError, format!("{}", e.kind().msg()))
-            .with_span(span)
-            .build();
-        diagnostics.push(diag);
-        anyhow::anyhow!("Parse error: {}", e.kind().msg())
-    })?;
+/// Parse TypeScript source code into an AST Module with diagnostic support.
+///
+/// This function parses TypeScript source code, adds it to the source cache,
+/// and returns both the AST and any diagnostics encountered during parsing.
+///
+/// # Arguments
+///
+/// * `source` - The TypeScript source code to parse
+/// * `filename` - The filename for error reporting
+/// * `cache` - The source cache to add the file to
+///
+/// # Returns
+///
+/// A `ParseResult` containing the AST, file ID, and any diagnostics.
+pub fn parse_typescript_with_cache(
+    source: &str,
+    filename: &str,
+    cache: &mut SourceCache,
+) -> Result<ParseResult> {
+    // Add the source to the cache
+    let file_id = cache.add_file(filename, source.to_string());
+
+    let parsed = parse_source(source, filename, false);
+    let module = parsed
+        .module
+        .map_err(|e| anyhow::anyhow!("Parse error: {}", e.kind().msg()))?;
 
     // Collect recoverable errors as warnings
-    for error in parser.take_errors() {
-        let span = Span::new(file_id, error.span().lo.0, error.span().hi.0);
+    let mut diagnostics = Diagnostics::new();
+    for error in &parsed.errors {
         diagnostics.push(
             Diagnostic::warning(DiagnosticCode::ParseError, format!("{}", error.kind().msg()))
-                .with_span(span)
+                .with_span(error_span(error, parsed.start_pos, file_id))
                 .build(),
         );
     }
@@ -98,44 +125,99 @@ pub fn parse_typescript_with_cache(
     })
 }
 
+/// Parse TypeScript source code, recovering from syntax errors.
+///
+/// Instead of stopping at the first fatal error, the statement containing it is
+/// skipped and the rest of the file is parsed again, so every syntax error in the
+/// file ends up in the diagnostics (as errors) and the returned module holds all
+/// statements that did parse. Spans in the module still refer to the original
+/// source. Unclosed brackets at the end of the file are closed so the code before
+/// them is kept. If recovery fails altogether the module is empty.
+pub fn parse_typescript_recovering(
+    source: &str,
+    filename: &str,
+    cache: &mut SourceCache,
+) -> ParseResult {
+    let file_id = cache.add_file(filename, source.to_string());
+    let mut diagnostics = Diagnostics::new();
+    let mut text = source.to_string();
+    let mut closed_brackets = false;
+
+    for _ in 0..=MAX_RECOVERED_ERRORS {
+        let parsed = parse_source(&text, filename, false);
+        let error = match parsed.module {
+            Ok(module) => {
+                for error in &parsed.errors {
+                    diagnostics.push(
+                        Diagnostic::warning(DiagnosticCode::ParseError, format!("{}", error.kind().msg()))
+                            .with_span(error_span(error, parsed.start_pos, file_id))
+                            .build(),
+                    );
+                }
+                return ParseResult { module, file_id, diagnostics };
+            }
+            Err(error) => error,
+        };
+
+        let span = error_span(&error, parsed.start_pos, file_id);
+        // Errors in the closers appended below belong at the end of the file
+        let span = Span::new(
+            file_id,
+            span.start.min(source.len() as u32),
+            span.end.min(source.len() as u32),
+        );
+        diagnostics.push(
+            Diagnostic::error(DiagnosticCode::ParseError, format!("{}", error.kind().msg()))
+                .with_span(span)
+                .build(),
+        );
+        if diagnostics.error_count() > MAX_RECOVERED_ERRORS {
+            break;
+        }
+
+        if !recovery::blank_statement(&mut text, span.start as usize) {
+            // Nothing left to skip: the error is at the end of the file
+            let closers = recovery::closers(text.as_bytes());
+            if closed_brackets || closers.is_empty() {
+                break;
+            }
+            text.push_str(&closers);
+            closed_brackets = true;
+        }
+    }
+
+    ParseResult {
+        module: Module { span: DUMMY_SP, body: Vec::new(), shebang: None },
+        file_id,
+        diagnostics,
+    }
+}
+
 /// Parse TypeScript source code into an AST Module (legacy API).
 ///
 /// This is the original parsing function for backward compatibility.
 /// For new code, prefer `parse_typescript_with_cache` for better diagnostics.
 pub fn parse_typescript(source: &str, filename: &str) -> Result<Module> {
-    let source_map: Lrc<SourceMap> = Default::default();
-    let source_file = source_map.new_source_file(
-        Lrc::new(FileName::Custom(filename.to_string())),
-        source.to_string(),
-    );
-
-    let lexer = Lexer::new(
-        Syntax::Typescript(TsSyntax {
-            tsx: false,
-            decorators: true,
-            dts: false,
-            no_early_errors: false,
-            disallow_ambiguous_jsx_like: false,
-        }),
-        swc_ecma_ast::EsVersion::Es2022,
-        StringInput::from(&*source_file),
-        None,
-    );
-
-    let mut parser = Parser::new_from(lexer);
-
-    let module = parser
-        .parse_module()
-        .map_err(|e| anyhow::anyhow!("Parse error: {:?}", e))?;
+    let parsed = parse_source(source, filename, false);
+    let module = parsed.module.map_err(|e| anyhow::anyhow!("Parse error: {:?}", e))?;
 
     // Check for recoverable errors
-    for error in parser.take_errors() {
+    for error in parsed.errors {
         eprintln!("Parse warning: {:?}", error);
     }
 
     Ok(module)
 }
 
+/// Parse a TypeScript declaration file (`.d.ts`) into an AST Module.
+///
+/// Declaration files only carry type information, so recoverable errors are
+/// dropped rather than reported.
+pub fn parse_declarations(source: &str, filename: &str) -> Result<Module> {
+    let parsed = parse_source(source, filename, true);
+    parsed.module.map_err(|e| anyhow::anyhow!("Parse error: {}", e.kind().msg()))
+}
+
 /// Utility to convert SWC span to our span type.
 ///
 /// This is useful when processing SWC AST nodes and need to create
@@ -203,4 +285,40 @@ mod tests {
 
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_parse_recovering_reports_every_error() {
+        let source = "let a = 1;\nlet b = ;\nfunction f() {\n    let c = ) 2;\n    return 3;\n}\nlet d = 4;\n";
+        let mut cache = SourceCache::new();
+
+        let result = parse_typescript_recovering(source, "test.ts", &mut cache);
+
+        assert_eq!(result.diagnostics.error_count(), 2);
+        // `a`, `f` and `d` survive, and so does the rest of f's body
+        assert_eq!(result.module.body.len(), 3);
+        let first = result.diagnostics.iter().next().unwrap();
+        let offset = first.span.start as usize;
+        assert_eq!(&source[offset..offset + 1], ";");
+    }
+
+    #[test]
+    fn test_parse_recovering_closes_unclosed_blocks() {
+        let source = "function f() {\n    let x = 1;\n";
+        let mut cache = SourceCache::new();
+
+        let result = parse_typescript_recovering(source, "test.ts", &mut cache);
+
+        assert_eq!(result.diagnostics.error_count(), 1);
+        assert_eq!(result.module.body.len(), 1);
+    }
+
+    #[test]
+    fn test_parse_recovering_valid_source() {
+        let mut cache = SourceCache::new();
+
+        let result = parse_typescript_recovering("let x = 1;", "test.ts", &mut cache);
+
+        assert!(result.diagnostics.is_empty());
+        assert_eq!(result.module.body.len(), 1);
+    }
 }
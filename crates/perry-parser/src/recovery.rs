@@ -0,0 +1,242 @@
+//! Statement boundaries for parse error recovery
+//!
+//! When SWC gives up on a file, the recovering parser blanks out the statement holding the
+//! error and parses the file again. There is no usable AST at that point, so the statement
+//! is found from the raw text: the scanner tracks brackets while skipping strings, template
+//! literals and comments. A statement ends at `;`, at the `}` that closes its block, or at a
+//! line break followed by a line that starts with a declaration or statement keyword (the
+//! common case of a missing semicolon). Blanking replaces every byte with a space but keeps
+//! line breaks, so offsets in the re-parsed text still point into the original source.
+
+use std::ops::Range;
+
+/// Keywords that begin a new statement when they start a line
+const STATEMENT_KEYWORDS: &[&str] = &[
+    "break", "class", "const", "continue", "do", "enum", "export", "for", "function", "if",
+    "import", "interface", "let", "return", "switch", "throw", "try", "type", "var", "while",
+];
+
+/// A bracket opened and not closed yet
+struct Open {
+    byte: u8,
+    /// For `{`, where the statement currently being scanned inside it starts
+    stmt_start: usize,
+}
+
+fn is_ident_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b == b'_' || b == b'$' || b >= 0x80
+}
+
+/// If a string, template literal or comment starts at `i`, the offset just past it
+fn skip_literal(src: &[u8], i: usize) -> Option<usize> {
+    match src[i] {
+        b'"' | b'\'' => {
+            let quote = src[i];
+            let mut j = i + 1;
+            while j < src.len() && src[j] != quote && src[j] != b'\n' {
+                j += if src[j] == b'\\' { 2 } else { 1 };
+            }
+            // An unterminated string stops at the end of its line
+            Some(if j < src.len() && src[j] == quote { j + 1 } else { j.min(src.len()) })
+        }
+        b'`' => Some(skip_template(src, i + 1)),
+        b'/' if src.get(i + 1) == Some(&b'/') => {
+            Some(src[i..].iter().position(|&b| b == b'\n').map_or(src.len(), |n| i + n))
+        }
+        b'/' if src.get(i + 1) == Some(&b'*') => Some(
+            src[i + 2..]
+                .windows(2)
+                .position(|w| w == b"*/")
+                .map_or(src.len(), |n| i + 2 + n + 2),
+        ),
+        _ => None,
+    }
+}
+
+/// Offset just past the template literal whose body starts at `j`
+fn skip_template(src: &[u8], mut j: usize) -> usize {
+    while j < src.len() {
+        match src[j] {
+            b'\\' => j += 2,
+            b'`' => return j + 1,
+            b'$' if src.get(j + 1) == Some(&b'{') => {
+                j += 2;
+                let mut depth = 1;
+                while j < src.len() && depth > 0 {
+                    if let Some(end) = skip_literal(src, j) {
+                        j = end;
+                        continue;
+                    }
+                    match src[j] {
+                        b'{' => depth += 1,
+                        b'}' => depth -= 1,
+                        _ => {}
+                    }
+                    j += 1;
+                }
+            }
+            _ => j += 1,
+        }
+    }
+    src.len()
+}
+
+/// Whether the line starting at `i` begins with a statement keyword
+fn starts_statement(src: &[u8], mut i: usize) -> bool {
+    while i < src.len() && (src[i] == b' ' || src[i] == b'\t' || src[i] == b'\r') {
+        i += 1;
+    }
+    let word_end = src[i..].iter().position(|&b| !is_ident_byte(b)).map_or(src.len(), |n| i + n);
+    let Ok(word) = std::str::from_utf8(&src[i..word_end]) else {
+        return false;
+    };
+    if !STATEMENT_KEYWORDS.contains(&word) {
+        return false;
+    }
+    // `type: 1`, `if,` or `return.x` use the keyword as a name
+    let next = src[word_end..].iter().find(|&&b| b != b' ' && b != b'\t');
+    !matches!(next, Some(b':' | b',' | b'=' | b'.' | b')' | b']' | b'}' | b'?'))
+}
+
+/// Set where the next statement starts, when directly inside a block
+fn set_start(stack: &mut [Open], pos: usize) {
+    if let Some(open) = stack.last_mut() {
+        if open.byte == b'{' {
+            open.stmt_start = pos;
+        }
+    }
+}
+
+/// The byte range of the statement that contains an error at `error`
+pub(crate) fn statement_extent(src: &[u8], error: usize) -> Range<usize> {
+    let error = error.min(src.len());
+    // The file itself acts as the outermost block
+    let mut stack = vec![Open { byte: b'{', stmt_start: 0 }];
+    let mut i = 0;
+    while i < error {
+        if let Some(end) = skip_literal(src, i) {
+            i = end;
+            continue;
+        }
+        match src[i] {
+            b'{' | b'(' | b'[' => stack.push(Open { byte: src[i], stmt_start: i + 1 }),
+            b'}' | b')' | b']' => {
+                if stack.len() > 1 {
+                    stack.pop();
+                }
+                if src[i] == b'}' {
+                    set_start(&mut stack, i + 1);
+                }
+            }
+            b';' => set_start(&mut stack, i + 1),
+            b'\n' if starts_statement(src, i + 1) => set_start(&mut stack, i + 1),
+            _ => {}
+        }
+        i += 1;
+    }
+
+    // The statement belongs to the innermost block open at the error
+    let block = stack.iter().rposition(|open| open.byte == b'{').unwrap_or(0);
+    let start = stack[block].stmt_start.min(error);
+    let depth_at_error = stack.len();
+    let mut i = error;
+    while i < src.len() {
+        if let Some(end) = skip_literal(src, i) {
+            i = end;
+            continue;
+        }
+        match src[i] {
+            b'{' | b'(' | b'[' => stack.push(Open { byte: src[i], stmt_start: i + 1 }),
+            b'}' | b')' | b']' => {
+                if stack.len() == block + 1 {
+                    // Closing the enclosing block ends the statement; a stray closer at
+                    // statement level is part of what is broken
+                    if src[i] == b'}' && block > 0 {
+                        return start..i;
+                    }
+                } else {
+                    stack.pop();
+                }
+            }
+            b';' if stack.len() == block + 1 => return start..i + 1,
+            b'\n' if i > error
+                && (stack.len() == block + 1 || stack.len() == depth_at_error)
+                && starts_statement(src, i + 1) =>
+            {
+                return start..i;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    start..src.len()
+}
+
+/// Blank out the statement containing an error at `error`, keeping line breaks.
+/// Returns false when there was nothing left to blank.
+pub(crate) fn blank_statement(text: &mut String, error: usize) -> bool {
+    let Range { start, mut end } = statement_extent(text.as_bytes(), error);
+    while !text.is_char_boundary(end) {
+        end += 1;
+    }
+    let statement = &text[start..end];
+    if statement.trim().is_empty() {
+        return false;
+    }
+    let blanked: String = statement
+        .chars()
+        .flat_map(|c| {
+            let width = if c == '\n' || c == '\r' { 0 } else { c.len_utf8() };
+            std::iter::repeat(' ').take(width).chain((width == 0).then_some(c))
+        })
+        .collect();
+    text.replace_range(start..end, &blanked);
+    true
+}
+
+/// The closing brackets for every bracket left open at the end of `src`
+pub(crate) fn closers(src: &[u8]) -> String {
+    let mut open = Vec::new();
+    let mut i = 0;
+    while i < src.len() {
+        if let Some(end) = skip_literal(src, i) {
+            i = end;
+            continue;
+        }
+        match src[i] {
+            b'{' => open.push('}'),
+            b'(' => open.push(')'),
+            b'[' => open.push(']'),
+            b'}' | b')' | b']' => {
+                open.pop();
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    open.iter().rev().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn extent(src: &str, at: &str) -> String {
+        let error = src.find(at).unwrap();
+        src[statement_extent(src.as_bytes(), error)].to_string()
+    }
+
+    #[test]
+    fn test_statement_extent() {
+        assert_eq!(extent("let a = 1;\nlet b = ;\nlet c = 3;", "= ;"), "let b = ;");
+        // Missing semicolons: the neighbouring lines start statements of their own
+        assert_eq!(extent("let a = 1\nlet b = )\nlet c = 3", ")"), "let b = )");
+        // Inside a function body only the broken statement goes
+        assert_eq!(extent("function f() { let x = ; return 1; }", "= ;"), " let x = ;");
+        // The last statement of a block ends at its closing brace
+        assert_eq!(extent("if (a) { f(1,, 2) }", ",,"), " f(1,, 2) ");
+        // Brackets inside strings and comments don't count
+        assert_eq!(extent("let s = \"{\" + ; // }\nlet t = 1;", "+ ;"), "let s = \"{\" + ;");
+        assert_eq!(closers(b"function f() { g([1, `${x}`"), "])}");
+    }
+}
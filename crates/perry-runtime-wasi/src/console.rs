@@ -0,0 +1,67 @@
+//! console.log / console.error / console.warn on WASI's stdout and stderr
+
+use crate::string::{as_str, ptr};
+use crate::value::display;
+
+/// A number as console.log prints it
+fn number(value: f64) -> String {
+    if value.fract() == 0.0 && value.abs() < (i64::MAX as f64) {
+        (value as i64).to_string()
+    } else {
+        display(value)
+    }
+}
+
+/// Print a number to stdout (optimized path for known numbers)
+#[no_mangle]
+pub extern "C" fn js_console_log_number(value: f64) {
+    println!("{}", number(value));
+}
+
+/// Print a number to stderr (console.error)
+#[no_mangle]
+pub extern "C" fn js_console_error_number(value: f64) {
+    eprintln!("{}", number(value));
+}
+
+/// Print a number to stderr (console.warn)
+#[no_mangle]
+pub extern "C" fn js_console_warn_number(value: f64) {
+    eprintln!("{}", number(value));
+}
+
+/// Print a dynamic value to stdout
+#[no_mangle]
+pub extern "C" fn js_console_log_dynamic(value: f64) {
+    println!("{}", display(value));
+}
+
+/// Print a dynamic value to stderr (console.error)
+#[no_mangle]
+pub extern "C" fn js_console_error_dynamic(value: f64) {
+    eprintln!("{}", display(value));
+}
+
+/// Print a dynamic value to stderr (console.warn)
+#[no_mangle]
+pub extern "C" fn js_console_warn_dynamic(value: f64) {
+    eprintln!("{}", display(value));
+}
+
+/// Print a string to stdout
+#[no_mangle]
+pub extern "C" fn js_string_print(s: u64) {
+    println!("{}", as_str(ptr(s)));
+}
+
+/// Print a string to stderr (console.error)
+#[no_mangle]
+pub extern "C" fn js_string_error(s: u64) {
+    eprintln!("{}", as_str(ptr(s)));
+}
+
+/// Print a string to stderr (console.warn)
+#[no_mangle]
+pub extern "C" fn js_string_warn(s: u64) {
+    eprintln!("{}", as_str(ptr(s)));
+}
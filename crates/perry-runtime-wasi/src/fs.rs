@@ -0,0 +1,78 @@
+//! Synchronous file system functions on WASI
+//!
+//! Paths resolve against the directories the host preopens (`wasmtime run --dir .`); the
+//! rest of the file system isn't visible to the program.
+
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+use crate::string::{as_str, from_str, ptr};
+
+/// The path a string holds, None for a null string
+fn path<'a>(s: u64) -> Option<&'a Path> {
+    (s != 0).then(|| Path::new(as_str(ptr(s))))
+}
+
+/// Read a file's contents as a string; null on error
+#[no_mangle]
+pub extern "C" fn js_fs_read_file_sync(path_ptr: u64) -> u64 {
+    match path(path_ptr).map(fs::read_to_string) {
+        Some(Ok(content)) => from_str(&content),
+        _ => 0,
+    }
+}
+
+/// Write content to a file synchronously. Returns 1 on success, 0 on failure
+#[no_mangle]
+pub extern "C" fn js_fs_write_file_sync(path_ptr: u64, content_ptr: u64) -> i32 {
+    path(path_ptr).is_some_and(|path| fs::write(path, as_str(ptr(content_ptr))).is_ok()) as i32
+}
+
+/// Append content to a file synchronously. Returns 1 on success, 0 on failure
+#[no_mangle]
+pub extern "C" fn js_fs_append_file_sync(path_ptr: u64, content_ptr: u64) -> i32 {
+    path(path_ptr).is_some_and(|path| {
+        fs::OpenOptions::new().create(true).append(true).open(path)
+            .and_then(|mut file| file.write_all(as_str(ptr(content_ptr)).as_bytes()))
+            .is_ok()
+    }) as i32
+}
+
+/// Check if a file or directory exists
+#[no_mangle]
+pub extern "C" fn js_fs_exists_sync(path_ptr: u64) -> i32 {
+    path(path_ptr).is_some_and(Path::exists) as i32
+}
+
+/// Create a directory and its parents. Returns 1 on success, 0 on failure
+#[no_mangle]
+pub extern "C" fn js_fs_mkdir_sync(path_ptr: u64) -> i32 {
+    path(path_ptr).is_some_and(|path| fs::create_dir_all(path).is_ok()) as i32
+}
+
+/// Remove a file. Returns 1 on success, 0 on failure
+#[no_mangle]
+pub extern "C" fn js_fs_unlink_sync(path_ptr: u64) -> i32 {
+    path(path_ptr).is_some_and(|path| fs::remove_file(path).is_ok()) as i32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_append_read() {
+        let dir = std::env::temp_dir().join(format!("perry-runtime-wasi-fs-{}", std::process::id()));
+        let file = dir.join("out.txt");
+        let file_ptr = from_str(file.to_str().unwrap());
+        assert_eq!(js_fs_mkdir_sync(from_str(dir.to_str().unwrap())), 1);
+        assert_eq!(js_fs_write_file_sync(file_ptr, from_str("a")), 1);
+        assert_eq!(js_fs_append_file_sync(file_ptr, from_str("b")), 1);
+        assert_eq!(as_str(ptr(js_fs_read_file_sync(file_ptr))), "ab");
+        assert_eq!(js_fs_unlink_sync(file_ptr), 1);
+        assert_eq!(js_fs_exists_sync(file_ptr), 0);
+        assert_eq!(js_fs_read_file_sync(file_ptr), 0);
+        fs::remove_dir(dir).unwrap();
+    }
+}
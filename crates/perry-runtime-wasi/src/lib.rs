@@ -0,0 +1,27 @@
+//! Runtime Library for Perry's WebAssembly target
+//!
+//! The subset of perry-runtime a program compiled with `--target wasm32-wasip1` can use,
+//! on WASI preview 1:
+//! - JSValue representation (NaN-boxing) and coercions
+//! - Strings
+//! - Console output
+//! - Synchronous fs functions
+//! - Clocks, Math.random and process.exit
+//!
+//! Built for wasm32-wasip1, the cdylib is what `perry_codegen::wasm_link` links a
+//! program's objects into, resolving the program's runtime calls against its exports. A
+//! program calling anything else (objects, arrays, promises, the stdlib modules, ...)
+//! fails to link with the name of the missing function.
+//!
+//! Compiled code passes pointers as 64-bit integers on every target, so here they cross
+//! the ABI as `u64`s holding a 32-bit address.
+
+pub mod console;
+pub mod fs;
+pub mod math;
+pub mod process;
+pub mod string;
+pub mod value;
+
+pub use string::StringHeader;
+pub use value::JSValue;
@@ -0,0 +1,53 @@
+//! Math functions compiled code calls, and the ones WebAssembly has no instruction for
+
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Math.pow
+#[no_mangle]
+pub extern "C" fn js_math_pow(base: f64, exp: f64) -> f64 {
+    base.powf(exp)
+}
+
+/// xorshift64* state, seeded on first use from WASI's random_get (through RandomState)
+static RANDOM_STATE: AtomicU64 = AtomicU64::new(0);
+
+/// Math.random: uniform in [0, 1)
+#[no_mangle]
+pub extern "C" fn js_math_random() -> f64 {
+    let mut x = RANDOM_STATE.load(Ordering::Relaxed);
+    if x == 0 {
+        x = RandomState::new().hash_one(0u64) | 1;
+    }
+    x ^= x >> 12;
+    x ^= x << 25;
+    x ^= x >> 27;
+    RANDOM_STATE.store(x, Ordering::Relaxed);
+    // The top 53 bits of the output as a fraction
+    (x.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Fused multiply-add, which Cranelift's `fma` is and wasm has no instruction for
+#[no_mangle]
+pub extern "C" fn js_wasi_fma(a: f64, b: f64, c: f64) -> f64 {
+    a.mul_add(b, c)
+}
+
+/// `js_wasi_fma` for f32
+#[no_mangle]
+pub extern "C" fn js_wasi_fmaf(a: f32, b: f32, c: f32) -> f32 {
+    a.mul_add(b, c)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_random_range() {
+        let values: Vec<f64> = (0..1000).map(|_| js_math_random()).collect();
+        assert!(values.iter().all(|value| (0.0..1.0).contains(value)));
+        assert!(values.windows(2).any(|pair| pair[0] != pair[1]));
+    }
+}
@@ -0,0 +1,60 @@
+//! Clocks, process exit, and the lifecycle hooks main calls
+//!
+//! The hooks that set up native facilities (the stack overflow guard, the stdlib's
+//! dispatch, stdio flow control) have nothing to do under WASI, and with no timers or
+//! promises there is no event loop to run.
+
+use std::io::Write;
+use std::sync::OnceLock;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// Process start time for uptime calculation
+static PROCESS_START: OnceLock<Instant> = OnceLock::new();
+
+/// Date.now(): milliseconds since the Unix epoch
+#[no_mangle]
+pub extern "C" fn js_date_now() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as f64)
+        .unwrap_or(0.0)
+}
+
+/// process.uptime(): seconds since the process started
+#[no_mangle]
+pub extern "C" fn js_process_uptime() -> f64 {
+    PROCESS_START.get_or_init(Instant::now).elapsed().as_secs_f64()
+}
+
+/// process.exit(code)
+#[no_mangle]
+pub extern "C" fn js_process_exit(code: f64) {
+    let exit_code = if code.is_nan() || code.is_infinite() {
+        1 // Default to 1 for invalid codes
+    } else {
+        code as i32
+    };
+    js_wasi_exit(exit_code);
+}
+
+/// End the program with main's exit code; `_start` calls it after main returns
+#[no_mangle]
+pub extern "C" fn js_wasi_exit(code: i32) {
+    let _ = std::io::stdout().flush();
+    std::process::exit(code);
+}
+
+/// Main's first call: there's no guard to install, but uptime counts from here
+#[no_mangle]
+pub extern "C" fn js_stack_guard_install() {
+    PROCESS_START.get_or_init(Instant::now);
+}
+
+#[no_mangle]
+pub extern "C" fn js_stdlib_init_dispatch() {}
+
+#[no_mangle]
+pub extern "C" fn js_stdio_flow() {}
+
+#[no_mangle]
+pub extern "C" fn js_run_event_loop() {}
@@ -0,0 +1,256 @@
+//! Strings: a `StringHeader` followed by the UTF-8 bytes, as in perry-runtime
+
+use std::alloc::{alloc, Layout};
+use std::ptr;
+
+#[repr(C)]
+pub struct StringHeader {
+    /// Length in bytes (not chars - we store UTF-8)
+    pub length: u32,
+    /// Capacity (allocated space for data)
+    pub capacity: u32,
+}
+
+/// The pointer compiled code passes as `addr`
+pub(crate) fn ptr<T>(addr: u64) -> *mut T {
+    addr as usize as *mut T
+}
+
+fn string_layout(capacity: usize) -> Layout {
+    Layout::from_size_align(std::mem::size_of::<StringHeader>() + capacity, 8).expect("string too large")
+}
+
+fn string_data(s: *const StringHeader) -> *mut u8 {
+    unsafe { (s as *mut u8).add(std::mem::size_of::<StringHeader>()) }
+}
+
+/// The string's bytes; empty for a null string
+pub(crate) fn as_bytes<'a>(s: *const StringHeader) -> &'a [u8] {
+    if s.is_null() {
+        return &[];
+    }
+    unsafe { std::slice::from_raw_parts(string_data(s), (*s).length as usize) }
+}
+
+pub(crate) fn as_str<'a>(s: *const StringHeader) -> &'a str {
+    unsafe { std::str::from_utf8_unchecked(as_bytes(s)) }
+}
+
+fn with_capacity(bytes: &[u8], capacity: usize) -> u64 {
+    let capacity = capacity.max(bytes.len());
+    unsafe {
+        let s = alloc(string_layout(capacity)) as *mut StringHeader;
+        if s.is_null() {
+            panic!("Failed to allocate string");
+        }
+        (*s).length = bytes.len() as u32;
+        (*s).capacity = capacity as u32;
+        ptr::copy_nonoverlapping(bytes.as_ptr(), string_data(s), bytes.len());
+        s as usize as u64
+    }
+}
+
+pub fn from_bytes(bytes: &[u8]) -> u64 {
+    with_capacity(bytes, bytes.len())
+}
+
+pub fn from_str(s: &str) -> u64 {
+    from_bytes(s.as_bytes())
+}
+
+/// Create a string from raw bytes
+#[no_mangle]
+pub extern "C" fn js_string_from_bytes(data: u64, len: u32) -> u64 {
+    if len == 0 || data == 0 {
+        return from_bytes(&[]);
+    }
+    from_bytes(unsafe { std::slice::from_raw_parts(ptr::<u8>(data), len as usize) })
+}
+
+#[no_mangle]
+pub extern "C" fn js_string_length(s: u64) -> u32 {
+    as_bytes(ptr(s)).len() as u32
+}
+
+/// Concatenate two strings into a new one
+#[no_mangle]
+pub extern "C" fn js_string_concat(a: u64, b: u64) -> u64 {
+    let (a, b) = (as_bytes(ptr(a)), as_bytes(ptr(b)));
+    let mut bytes = Vec::with_capacity(a.len() + b.len());
+    bytes.extend_from_slice(a);
+    bytes.extend_from_slice(b);
+    from_bytes(&bytes)
+}
+
+/// Append `src` to `dest` (`+=`), in place when `dest` has room. Returns the string to
+/// use from now on.
+#[no_mangle]
+pub extern "C" fn js_string_append(dest: u64, src: u64) -> u64 {
+    let (dest_ptr, src_bytes) = (ptr::<StringHeader>(dest), as_bytes(ptr(src)));
+    if dest_ptr.is_null() {
+        return from_bytes(src_bytes);
+    }
+    if src_bytes.is_empty() {
+        return dest;
+    }
+    unsafe {
+        let len = (*dest_ptr).length as usize;
+        let total = len + src_bytes.len();
+        if total <= (*dest_ptr).capacity as usize {
+            ptr::copy_nonoverlapping(src_bytes.as_ptr(), string_data(dest_ptr).add(len), src_bytes.len());
+            (*dest_ptr).length = total as u32;
+            return dest;
+        }
+        // Room for more appends, as a loop building a string makes
+        let new = with_capacity(as_bytes(dest_ptr), total * 2);
+        let new_ptr = ptr::<StringHeader>(new);
+        ptr::copy_nonoverlapping(src_bytes.as_ptr(), string_data(new_ptr).add(len), src_bytes.len());
+        (*new_ptr).length = total as u32;
+        new
+    }
+}
+
+/// Compare two strings for equality. Returns 1 if equal, 0 if not.
+#[no_mangle]
+pub extern "C" fn js_string_equals(a: u64, b: u64) -> i32 {
+    (a == b || as_bytes(ptr(a)) == as_bytes(ptr(b))) as i32
+}
+
+/// Get character at index (empty string if out of bounds)
+#[no_mangle]
+pub extern "C" fn js_string_char_at(s: u64, index: i32) -> u64 {
+    let bytes = as_bytes(ptr(s));
+    match usize::try_from(index).ok().and_then(|index| bytes.get(index)) {
+        Some(byte) => from_bytes(std::slice::from_ref(byte)),
+        None => from_bytes(&[]),
+    }
+}
+
+/// Get the character code at index (NaN if out of bounds)
+#[no_mangle]
+pub extern "C" fn js_string_char_code_at(s: u64, index: i32) -> f64 {
+    let bytes = as_bytes(ptr(s));
+    match usize::try_from(index).ok().and_then(|index| bytes.get(index)) {
+        Some(&byte) => byte as f64,
+        None => f64::NAN,
+    }
+}
+
+/// String.fromCharCode with a single code (empty string if invalid)
+#[no_mangle]
+pub extern "C" fn js_string_from_char_code(code: i32) -> u64 {
+    match u32::try_from(code).ok().filter(|&code| code <= 0xFFFF).and_then(char::from_u32) {
+        Some(c) => from_str(c.encode_utf8(&mut [0; 4])),
+        None => from_bytes(&[]),
+    }
+}
+
+/// Get a slice of a string; negative indices count from the end
+#[no_mangle]
+pub extern "C" fn js_string_slice(s: u64, start: i32, end: i32) -> u64 {
+    let bytes = as_bytes(ptr(s));
+    let len = bytes.len() as i32;
+    let start = if start < 0 { (len + start).max(0) } else { start.min(len) };
+    let end = if end < 0 { (len + end).max(0) } else { end.min(len) };
+    if start >= end {
+        return from_bytes(&[]);
+    }
+    from_bytes(&bytes[start as usize..end as usize])
+}
+
+/// Get a substring; negative indices are 0 and the bounds are swapped if reversed
+#[no_mangle]
+pub extern "C" fn js_string_substring(s: u64, start: i32, end: i32) -> u64 {
+    let bytes = as_bytes(ptr(s));
+    let len = bytes.len() as i32;
+    let (start, end) = (start.clamp(0, len), end.clamp(0, len));
+    from_bytes(&bytes[start.min(end) as usize..start.max(end) as usize])
+}
+
+#[no_mangle]
+pub extern "C" fn js_string_repeat(s: u64, count: i32) -> u64 {
+    from_str(&as_str(ptr(s)).repeat(count.max(0) as usize))
+}
+
+/// Find index of substring (-1 if not found)
+#[no_mangle]
+pub extern "C" fn js_string_index_of(haystack: u64, needle: u64) -> i32 {
+    js_string_index_of_from(haystack, needle, 0)
+}
+
+/// Find index of substring starting from a given position (-1 if not found)
+#[no_mangle]
+pub extern "C" fn js_string_index_of_from(haystack: u64, needle: u64, from_index: i32) -> i32 {
+    if haystack == 0 || needle == 0 {
+        return -1;
+    }
+    let (haystack, needle) = (as_bytes(ptr(haystack)), as_bytes(ptr(needle)));
+    let start = from_index.max(0) as usize;
+    if start >= haystack.len() {
+        return if needle.is_empty() { haystack.len() as i32 } else { -1 };
+    }
+    if needle.is_empty() {
+        return start as i32;
+    }
+    haystack[start..].windows(needle.len())
+        .position(|window| window == needle)
+        .map_or(-1, |position| (start + position) as i32)
+}
+
+#[no_mangle]
+pub extern "C" fn js_string_starts_with(s: u64, prefix: u64) -> i32 {
+    as_bytes(ptr(s)).starts_with(as_bytes(ptr(prefix))) as i32
+}
+
+#[no_mangle]
+pub extern "C" fn js_string_ends_with(s: u64, suffix: u64) -> i32 {
+    as_bytes(ptr(s)).ends_with(as_bytes(ptr(suffix))) as i32
+}
+
+#[no_mangle]
+pub extern "C" fn js_string_to_upper_case(s: u64) -> u64 {
+    from_str(&as_str(ptr(s)).to_uppercase())
+}
+
+#[no_mangle]
+pub extern "C" fn js_string_to_lower_case(s: u64) -> u64 {
+    from_str(&as_str(ptr(s)).to_lowercase())
+}
+
+#[no_mangle]
+pub extern "C" fn js_string_trim(s: u64) -> u64 {
+    from_str(as_str(ptr(s)).trim())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(s: u64) -> &'static str {
+        as_str(ptr(s))
+    }
+
+    #[test]
+    fn test_slicing() {
+        let s = from_str("hello world");
+        assert_eq!(text(js_string_slice(s, -5, 11)), "world");
+        assert_eq!(text(js_string_substring(s, 5, 0)), "hello");
+        assert_eq!(text(js_string_char_at(s, 4)), "o");
+        assert_eq!(text(js_string_char_at(s, 40)), "");
+        assert!(js_string_char_code_at(s, -1).is_nan());
+        assert_eq!(js_string_index_of_from(s, from_str("o"), 5), 7);
+        assert_eq!(js_string_index_of(s, from_str("xyz")), -1);
+    }
+
+    #[test]
+    fn test_append_grows_in_place() {
+        let first = js_string_append(from_str("a"), from_str("bc"));
+        assert_eq!(text(first), "abc");
+        // The copy has room left, so the next append reuses it
+        let second = js_string_append(first, from_str("d"));
+        assert_eq!(second, first);
+        assert_eq!(text(second), "abcd");
+        assert_eq!(js_string_equals(second, from_str("abcd")), 1);
+        assert_eq!(text(js_string_concat(second, from_str("!"))), "abcd!");
+    }
+}
@@ -0,0 +1,307 @@
+//! JSValue representation using NaN-boxing
+//!
+//! The same encoding as perry-runtime's `value` module, which compiled code relies on:
+//! - Regular f64 values (including NaN) are stored directly
+//! - 0x7FFC: undefined, null, false and true
+//! - 0x7FFD: object pointer
+//! - 0x7FFE: int32
+//! - 0x7FFF: string pointer
+
+use crate::string::{self, StringHeader};
+
+const TAG_UNDEFINED: u64 = 0x7FFC_0000_0000_0001;
+const TAG_NULL: u64 = 0x7FFC_0000_0000_0002;
+const TAG_FALSE: u64 = 0x7FFC_0000_0000_0003;
+const TAG_TRUE: u64 = 0x7FFC_0000_0000_0004;
+
+const POINTER_TAG: u64 = 0x7FFD_0000_0000_0000;
+const POINTER_MASK: u64 = 0x0000_FFFF_FFFF_FFFF;
+const INT32_TAG: u64 = 0x7FFE_0000_0000_0000;
+const INT32_MASK: u64 = 0x0000_0000_FFFF_FFFF;
+const STRING_TAG: u64 = 0x7FFF_0000_0000_0000;
+/// JS handles only come from perry-jsruntime, but are still truthy objects
+const JS_HANDLE_TAG: u64 = 0x7FFB_0000_0000_0000;
+const TAG_MASK: u64 = 0xFFFF_0000_0000_0000;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[repr(transparent)]
+pub struct JSValue {
+    bits: u64,
+}
+
+impl JSValue {
+    pub const fn undefined() -> Self {
+        Self { bits: TAG_UNDEFINED }
+    }
+
+    pub const fn null() -> Self {
+        Self { bits: TAG_NULL }
+    }
+
+    pub const fn bool(value: bool) -> Self {
+        Self { bits: if value { TAG_TRUE } else { TAG_FALSE } }
+    }
+
+    pub const fn int32(value: i32) -> Self {
+        Self { bits: INT32_TAG | ((value as u32) as u64) }
+    }
+
+    pub fn string_ptr(ptr: *mut StringHeader) -> Self {
+        Self { bits: STRING_TAG | (ptr as u64 & POINTER_MASK) }
+    }
+
+    pub fn is_undefined(&self) -> bool {
+        self.bits == TAG_UNDEFINED
+    }
+
+    pub fn is_null(&self) -> bool {
+        self.bits == TAG_NULL
+    }
+
+    pub fn is_bool(&self) -> bool {
+        self.bits == TAG_TRUE || self.bits == TAG_FALSE
+    }
+
+    pub fn is_int32(&self) -> bool {
+        (self.bits & !INT32_MASK) == INT32_TAG
+    }
+
+    pub fn is_pointer(&self) -> bool {
+        (self.bits & !POINTER_MASK) == POINTER_TAG
+    }
+
+    pub fn is_string(&self) -> bool {
+        (self.bits & !POINTER_MASK) == STRING_TAG
+    }
+
+    pub fn as_bool(&self) -> bool {
+        self.bits == TAG_TRUE
+    }
+
+    pub fn as_int32(&self) -> i32 {
+        (self.bits & INT32_MASK) as i32
+    }
+
+    pub fn as_string_ptr(&self) -> *const StringHeader {
+        string::ptr(self.bits & POINTER_MASK)
+    }
+
+    pub fn bits(&self) -> u64 {
+        self.bits
+    }
+
+    pub fn from_bits(bits: u64) -> Self {
+        Self { bits }
+    }
+}
+
+/// Create a NaN-boxed pointer value from an i64 raw pointer
+#[no_mangle]
+pub extern "C" fn js_nanbox_pointer(ptr: u64) -> f64 {
+    f64::from_bits(POINTER_TAG | (ptr & POINTER_MASK))
+}
+
+/// Create a NaN-boxed string value from an i64 raw pointer
+#[no_mangle]
+pub extern "C" fn js_nanbox_string(ptr: u64) -> f64 {
+    f64::from_bits(STRING_TAG | (ptr & POINTER_MASK))
+}
+
+/// Extract a pointer from a NaN-boxed object or string, or raw pointer bits (bitcast
+/// from i64). 0 for anything else.
+#[no_mangle]
+pub extern "C" fn js_nanbox_get_pointer(value: f64) -> u64 {
+    let bits = value.to_bits();
+    let jsval = JSValue::from_bits(bits);
+    if jsval.is_pointer() || jsval.is_string() {
+        return bits & POINTER_MASK;
+    }
+    if bits != 0 && bits <= POINTER_MASK {
+        return bits;
+    }
+    0
+}
+
+/// Extract a string pointer from a NaN-boxed string, 0 for anything else
+#[no_mangle]
+pub extern "C" fn js_nanbox_get_string_pointer(value: f64) -> u64 {
+    let jsval = JSValue::from_bits(value.to_bits());
+    if jsval.is_string() {
+        value.to_bits() & POINTER_MASK
+    } else {
+        0
+    }
+}
+
+/// Extract a string pointer from a NaN-boxed string or a raw pointer bitcast to f64
+#[no_mangle]
+pub extern "C" fn js_get_string_pointer_unified(value: f64) -> u64 {
+    let bits = value.to_bits();
+    if JSValue::from_bits(bits).is_string() {
+        return bits & POINTER_MASK;
+    }
+    if bits >> 48 < 0x7FFC {
+        return bits;
+    }
+    0
+}
+
+/// Check if a JavaScript value is truthy. Returns 1 if truthy, 0 if falsy.
+#[no_mangle]
+pub extern "C" fn js_is_truthy(value: f64) -> i32 {
+    let bits = value.to_bits();
+    if bits == TAG_UNDEFINED || bits == TAG_NULL || bits == TAG_FALSE {
+        return 0;
+    }
+    if bits == TAG_TRUE {
+        return 1;
+    }
+    match bits & TAG_MASK {
+        // Empty string is falsy
+        STRING_TAG => return (string::js_string_length(bits & POINTER_MASK) != 0) as i32,
+        POINTER_TAG | JS_HANDLE_TAG => return 1,
+        INT32_TAG => return ((bits & INT32_MASK) as i32 != 0) as i32,
+        _ => {}
+    }
+    // Raw string pointer bits (a string literal bitcast to f64)
+    if bits > 0x1000 && bits < 0x0001_0000_0000_0000 {
+        return (string::js_string_length(bits) != 0) as i32;
+    }
+    // 0.0, -0.0 and NaN are falsy
+    (value != 0.0 && !value.is_nan()) as i32
+}
+
+/// Number(value)
+#[no_mangle]
+pub extern "C" fn js_number_coerce(value: f64) -> f64 {
+    let jsval = JSValue::from_bits(value.to_bits());
+    if jsval.is_undefined() {
+        f64::NAN
+    } else if jsval.is_null() {
+        0.0
+    } else if jsval.is_bool() {
+        if jsval.as_bool() { 1.0 } else { 0.0 }
+    } else if jsval.is_int32() {
+        jsval.as_int32() as f64
+    } else if jsval.is_string() {
+        let trimmed = string::as_str(jsval.as_string_ptr()).trim();
+        if trimmed.is_empty() {
+            0.0
+        } else {
+            trimmed.parse().unwrap_or(f64::NAN)
+        }
+    } else if jsval.is_pointer() {
+        f64::NAN
+    } else {
+        value
+    }
+}
+
+/// String(value)
+#[no_mangle]
+pub extern "C" fn js_string_coerce(value: f64) -> u64 {
+    js_jsvalue_to_string(value)
+}
+
+/// Convert any value to a string
+#[no_mangle]
+pub extern "C" fn js_jsvalue_to_string(value: f64) -> u64 {
+    let jsval = JSValue::from_bits(value.to_bits());
+    if jsval.is_string() {
+        return value.to_bits() & POINTER_MASK;
+    }
+    if jsval.is_pointer() {
+        return string::from_str("[object Object]");
+    }
+    string::from_str(&display(value))
+}
+
+/// Convert a number to a string
+#[no_mangle]
+pub extern "C" fn js_number_to_string(value: f64) -> u64 {
+    string::from_str(&number_to_string(value))
+}
+
+/// typeof value
+#[no_mangle]
+pub extern "C" fn js_value_typeof(value: f64) -> u64 {
+    let jsval = JSValue::from_bits(value.to_bits());
+    let type_str = if jsval.is_undefined() {
+        "undefined"
+    } else if jsval.is_bool() {
+        "boolean"
+    } else if jsval.is_string() {
+        "string"
+    } else if jsval.is_null() || jsval.is_pointer() {
+        // typeof null === "object"; there are no closures to tell apart here
+        "object"
+    } else {
+        "number"
+    };
+    string::from_str(type_str)
+}
+
+/// A number the way JavaScript prints it: integers without a fraction, NaN and
+/// +-Infinity by name
+pub fn number_to_string(value: f64) -> String {
+    if value.is_nan() {
+        "NaN".to_string()
+    } else if value.is_infinite() {
+        if value > 0.0 { "Infinity" } else { "-Infinity" }.to_string()
+    } else if value.fract() == 0.0 && value.abs() < 1e15 {
+        (value as i64).to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+/// Any value but an object, as console.log and String() show it
+pub fn display(value: f64) -> String {
+    let jsval = JSValue::from_bits(value.to_bits());
+    if jsval.is_undefined() {
+        "undefined".to_string()
+    } else if jsval.is_null() {
+        "null".to_string()
+    } else if jsval.is_bool() {
+        jsval.as_bool().to_string()
+    } else if jsval.is_int32() {
+        jsval.as_int32().to_string()
+    } else if jsval.is_string() {
+        string::as_str(jsval.as_string_ptr()).to_string()
+    } else if jsval.is_pointer() {
+        "[object Object]".to_string()
+    } else {
+        number_to_string(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_truthiness() {
+        let empty = string::from_str("");
+        let text = string::from_str("x");
+        assert_eq!(js_is_truthy(js_nanbox_string(empty)), 0);
+        assert_eq!(js_is_truthy(js_nanbox_string(text)), 1);
+        assert_eq!(js_is_truthy(f64::from_bits(JSValue::undefined().bits())), 0);
+        assert_eq!(js_is_truthy(f64::from_bits(JSValue::int32(0).bits())), 0);
+        assert_eq!(js_is_truthy(f64::from_bits(JSValue::bool(true).bits())), 1);
+        assert_eq!(js_is_truthy(f64::NAN), 0);
+        assert_eq!(js_is_truthy(-0.5), 1);
+    }
+
+    #[test]
+    fn test_to_string() {
+        let show = |value: f64| string::as_str(string::ptr(js_jsvalue_to_string(value))).to_string();
+        assert_eq!(show(42.0), "42");
+        assert_eq!(show(1.5), "1.5");
+        assert_eq!(show(f64::NEG_INFINITY), "-Infinity");
+        assert_eq!(show(f64::from_bits(JSValue::null().bits())), "null");
+        assert_eq!(show(f64::from_bits(JSValue::int32(-7).bits())), "-7");
+        let text = string::from_str("hi");
+        assert_eq!(js_get_string_pointer_unified(js_nanbox_string(text)), text);
+        assert_eq!(js_number_coerce(js_nanbox_string(string::from_str(" 12.5 "))), 12.5);
+    }
+}
@@ -52,20 +52,23 @@ struct Arena {
 
 impl Arena {
     fn new() -> Self {
+        // The first block is created lazily inside the thread-local initializer,
+        // so it is recorded without a limit check
+        crate::memory::record(BLOCK_SIZE);
         Arena {
             blocks: vec![ArenaBlock::new()],
             current: 0,
         }
     }
 
+    /// Try to allocate from the current block
     #[inline]
-    fn alloc(&mut self, size: usize) -> *mut u8 {
-        // Try current block first
-        if let Some(ptr) = self.blocks[self.current].alloc(size) {
-            return ptr;
-        }
+    fn try_alloc(&mut self, size: usize) -> Option<*mut u8> {
+        self.blocks[self.current].alloc(size)
+    }
 
-        // Need a new block
+    /// Start a new block and allocate from it
+    fn alloc_in_new_block(&mut self, size: usize) -> *mut u8 {
         self.blocks.push(ArenaBlock::new());
         self.current += 1;
 
@@ -82,9 +85,21 @@ thread_local! {
 /// This is very fast - just a pointer bump in the common case
 #[inline]
 pub fn arena_alloc(size: usize, _align: usize) -> *mut u8 {
+    // Try current block first
+    let ptr = ARENA.with(|arena| {
+        let arena = unsafe { &mut *arena.get() };
+        arena.try_alloc(size)
+    });
+    if let Some(ptr) = ptr {
+        return ptr;
+    }
+
+    // Need a new block. Charge it against the heap limit outside the arena borrow,
+    // since the limit check may throw or run low-memory callbacks that allocate.
+    crate::memory::charge(BLOCK_SIZE);
     ARENA.with(|arena| {
         let arena = unsafe { &mut *arena.get() };
-        arena.alloc(size)
+        arena.alloc_in_new_block(size)
     })
 }
 
@@ -34,6 +34,8 @@ pub extern "C" fn js_array_alloc(capacity: u32) -> *mut ArrayHeader {
     // Use at least MIN_ARRAY_CAPACITY to reduce reallocations for growing arrays
     let actual_capacity = capacity.max(MIN_ARRAY_CAPACITY);
     let layout = array_layout(actual_capacity as usize);
+    crate::memory::charge(layout.size());
+    crate::memory::count_allocation(crate::memory::AllocKind::Array, layout.size());
     unsafe {
         let ptr = alloc(layout) as *mut ArrayHeader;
         if ptr.is_null() {
@@ -147,6 +149,9 @@ pub extern "C" fn js_array_grow(arr: *mut ArrayHeader, min_capacity: u32) -> *mu
         let new_capacity = std::cmp::max(old_capacity * 2, min_capacity);
         let old_layout = array_layout(old_capacity as usize);
         let new_layout = array_layout(new_capacity as usize);
+        let grown = new_layout.size().saturating_sub(old_layout.size());
+        crate::memory::charge(grown);
+        crate::memory::count_growth(crate::memory::AllocKind::Array, grown);
 
         let new_ptr = realloc(arr as *mut u8, old_layout, new_layout.size()) as *mut ArrayHeader;
         if new_ptr.is_null() {
@@ -1,715 +1,430 @@
 //! BigInt runtime support for Perry
 //!
-//! Provides 256-bit integer arithmetic for cryptocurrency operations.
-//! Uses primitive_types::U256 for the underlying representation.
+//! Arbitrary-precision integers in sign-magnitude form: a `BigIntHeader` followed by the
+//! magnitude's little-endian u64 limbs. The limb arithmetic (Karatsuba multiplication,
+//! Knuth division) lives in `bignum`; this module gives it JavaScript semantics: division
+//! truncates, `%` takes the dividend's sign, bitwise operators and `>>` act on the infinite
+//! two's complement, and division by zero or a result over `MAX_BITS` throws a RangeError.
 
 use std::alloc::{alloc, Layout};
+use std::cmp::Ordering;
 
-/// BigInt is stored as a heap-allocated U256 (256-bit unsigned integer)
-/// Layout: 32 bytes (4 x u64)
+use crate::bignum;
+use crate::value::JSValue;
+
+/// Header of a heap BigInt; `len` limbs of the magnitude follow it
 #[repr(C)]
 pub struct BigIntHeader {
-    /// The 256-bit value stored as 4 x u64 in little-endian order
-    pub limbs: [u64; 4],
+    /// Number of 64-bit limbs in the magnitude (no high zero limbs; zero has none)
+    pub len: u32,
+    /// 1 when the value is negative; zero is never negative
+    pub negative: u32,
 }
 
-/// Create a BigInt from a u64 value
-#[no_mangle]
-pub extern "C" fn js_bigint_from_u64(value: u64) -> *mut BigIntHeader {
-    let layout = Layout::new::<BigIntHeader>();
+impl BigIntHeader {
+    /// The magnitude's limbs, least significant first
+    pub fn limbs(&self) -> &[u64] {
+        unsafe { std::slice::from_raw_parts((self as *const Self).add(1) as *const u64, self.len as usize) }
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.negative != 0
+    }
+}
+
+/// Largest BigInt, in bits (V8's limit)
+const MAX_BITS: u64 = 1 << 30;
+
+/// Allocate a BigInt with this sign and magnitude (normalized)
+fn alloc_bigint(negative: bool, magnitude: &[u64]) -> *mut BigIntHeader {
+    if bignum::bit_len(magnitude) > MAX_BITS {
+        throw_error("RangeError", "Maximum BigInt size exceeded");
+    }
+    let size = std::mem::size_of::<BigIntHeader>() + magnitude.len() * 8;
+    let layout = Layout::from_size_align(size, 8).expect("BigInt layout");
     unsafe {
         let ptr = alloc(layout) as *mut BigIntHeader;
         if ptr.is_null() {
             panic!("Failed to allocate BigInt");
         }
-        (*ptr).limbs = [value, 0, 0, 0];
+        (*ptr).len = magnitude.len() as u32;
+        (*ptr).negative = (negative && !magnitude.is_empty()) as u32;
+        std::ptr::copy_nonoverlapping(magnitude.as_ptr(), ptr.add(1) as *mut u64, magnitude.len());
         ptr
     }
 }
 
+/// Allocate a BigInt from a sign and little-endian limbs (high zero limbs are dropped)
+pub fn bigint_from_limbs(negative: bool, limbs: &[u64]) -> *mut BigIntHeader {
+    let len = limbs.iter().rposition(|&limb| limb != 0).map_or(0, |top| top + 1);
+    alloc_bigint(negative, &limbs[..len])
+}
+
+/// Sign and magnitude of a BigInt
+unsafe fn parts<'a>(a: *const BigIntHeader) -> (bool, &'a [u64]) {
+    ((*a).is_negative(), (*a).limbs())
+}
+
+/// Throw an error with `name` (`RangeError`, `TypeError`)
+fn throw_error(name: &str, message: &str) -> ! {
+    let msg = crate::string::js_string_from_bytes(message.as_ptr(), message.len() as u32);
+    let error = crate::error::js_error_new_with_message(msg);
+    unsafe {
+        (*error).name = crate::string::js_string_from_bytes(name.as_ptr(), name.len() as u32);
+    }
+    crate::exception::js_throw(f64::from_bits(JSValue::pointer(error as *const u8).bits()))
+}
+
+/// Decimal text of a BigInt, with a leading `-` when negative
+pub fn bigint_to_decimal(a: *const BigIntHeader) -> String {
+    let (negative, magnitude) = unsafe { parts(a) };
+    let digits = bignum::to_decimal(magnitude);
+    if negative { format!("-{}", digits) } else { digits }
+}
+
+/// Create a BigInt from a u64 value
+#[no_mangle]
+pub extern "C" fn js_bigint_from_u64(value: u64) -> *mut BigIntHeader {
+    let limbs = [value];
+    alloc_bigint(false, if value == 0 { &[] } else { &limbs })
+}
+
 /// Create a BigInt from a signed i64 value
 #[no_mangle]
 pub extern "C" fn js_bigint_from_i64(value: i64) -> *mut BigIntHeader {
-    let layout = Layout::new::<BigIntHeader>();
-    unsafe {
-        let ptr = alloc(layout) as *mut BigIntHeader;
-        if ptr.is_null() {
-            panic!("Failed to allocate BigInt");
-        }
-        if value >= 0 {
-            (*ptr).limbs = [value as u64, 0, 0, 0];
-        } else {
-            // Two's complement for negative numbers
-            (*ptr).limbs = [value as u64, u64::MAX, u64::MAX, u64::MAX];
-        }
-        ptr
-    }
+    let limbs = [value.unsigned_abs()];
+    alloc_bigint(value < 0, if value == 0 { &[] } else { &limbs })
 }
 
-/// Create a BigInt from a string (decimal or hex with 0x prefix)
+/// Create a BigInt from a string: decimal with an optional sign, or `0x`/`0o`/`0b`
+/// prefixed. Text that isn't a valid integer gives zero.
 #[no_mangle]
 pub extern "C" fn js_bigint_from_string(data: *const u8, len: u32) -> *mut BigIntHeader {
-    let layout = Layout::new::<BigIntHeader>();
-    unsafe {
-        let ptr = alloc(layout) as *mut BigIntHeader;
-        if ptr.is_null() {
-            panic!("Failed to allocate BigInt");
-        }
-
-        let bytes = std::slice::from_raw_parts(data, len as usize);
-        let s = std::str::from_utf8_unchecked(bytes);
-
-        // Parse the string
-        let (is_hex, s) = if s.starts_with("0x") || s.starts_with("0X") {
-            (true, &s[2..])
-        } else {
-            (false, s)
-        };
-
-        let mut limbs = [0u64; 4];
-
-        if is_hex {
-            // Parse hex string
-            let mut chars = s.chars().rev();
-            for limb in limbs.iter_mut() {
-                let mut value = 0u64;
-                for i in 0..16 {
-                    if let Some(c) = chars.next() {
-                        let digit = match c {
-                            '0'..='9' => c as u64 - '0' as u64,
-                            'a'..='f' => c as u64 - 'a' as u64 + 10,
-                            'A'..='F' => c as u64 - 'A' as u64 + 10,
-                            _ => continue,
-                        };
-                        value |= digit << (i * 4);
-                    } else {
-                        break;
-                    }
-                }
-                *limb = value;
-            }
-        } else {
-            // Parse decimal string using long multiplication
-            for c in s.chars() {
-                if let Some(digit) = c.to_digit(10) {
-                    // Multiply by 10 and add digit
-                    let mut carry = digit as u64;
-                    for limb in limbs.iter_mut() {
-                        let product = (*limb as u128) * 10 + carry as u128;
-                        *limb = product as u64;
-                        carry = (product >> 64) as u64;
-                    }
-                }
-            }
-        }
+    let bytes = unsafe { std::slice::from_raw_parts(data, len as usize) };
+    let text = std::str::from_utf8(bytes).unwrap_or("").trim();
+    let prefixed = |prefix: &str| {
+        let (head, rest) = text.split_at(text.len().min(2));
+        head.eq_ignore_ascii_case(prefix).then_some(rest)
+    };
+    let (negative, magnitude) = if let Some(hex) = prefixed("0x") {
+        (false, bignum::from_radix(hex, 16))
+    } else if let Some(octal) = prefixed("0o") {
+        (false, bignum::from_radix(octal, 8))
+    } else if let Some(binary) = prefixed("0b") {
+        (false, bignum::from_radix(binary, 2))
+    } else if let Some(digits) = text.strip_prefix('-') {
+        (true, bignum::from_radix(digits, 10))
+    } else {
+        (false, bignum::from_radix(text.strip_prefix('+').unwrap_or(text), 10))
+    };
+    alloc_bigint(negative, &magnitude.unwrap_or_default())
+}
 
-        (*ptr).limbs = limbs;
-        ptr
+/// Sum of two signed magnitudes
+fn signed_add(a_negative: bool, a: &[u64], b_negative: bool, b: &[u64]) -> (bool, Vec<u64>) {
+    if a_negative == b_negative {
+        return (a_negative, bignum::add(a, b));
+    }
+    match bignum::cmp(a, b) {
+        Ordering::Less => (b_negative, bignum::sub(b, a)),
+        _ => (a_negative, bignum::sub(a, b)),
     }
 }
 
 /// Add two BigInts
 #[no_mangle]
 pub extern "C" fn js_bigint_add(a: *const BigIntHeader, b: *const BigIntHeader) -> *mut BigIntHeader {
-    let layout = Layout::new::<BigIntHeader>();
-    unsafe {
-        let ptr = alloc(layout) as *mut BigIntHeader;
-        if ptr.is_null() {
-            panic!("Failed to allocate BigInt");
-        }
-
-        let a_limbs = (*a).limbs;
-        let b_limbs = (*b).limbs;
-        let mut result = [0u64; 4];
-        let mut carry = 0u64;
-
-        for i in 0..4 {
-            let sum = (a_limbs[i] as u128) + (b_limbs[i] as u128) + (carry as u128);
-            result[i] = sum as u64;
-            carry = (sum >> 64) as u64;
-        }
-
-        (*ptr).limbs = result;
-        ptr
-    }
+    let ((a_negative, a), (b_negative, b)) = unsafe { (parts(a), parts(b)) };
+    let (negative, magnitude) = signed_add(a_negative, a, b_negative, b);
+    alloc_bigint(negative, &magnitude)
 }
 
 /// Subtract two BigInts (a - b)
 #[no_mangle]
 pub extern "C" fn js_bigint_sub(a: *const BigIntHeader, b: *const BigIntHeader) -> *mut BigIntHeader {
-    let layout = Layout::new::<BigIntHeader>();
-    unsafe {
-        let ptr = alloc(layout) as *mut BigIntHeader;
-        if ptr.is_null() {
-            panic!("Failed to allocate BigInt");
-        }
-
-        let a_limbs = (*a).limbs;
-        let b_limbs = (*b).limbs;
-        let mut result = [0u64; 4];
-        let mut borrow = 0i128;
-
-        for i in 0..4 {
-            let diff = (a_limbs[i] as i128) - (b_limbs[i] as i128) - borrow;
-            if diff < 0 {
-                result[i] = (diff + (1i128 << 64)) as u64;
-                borrow = 1;
-            } else {
-                result[i] = diff as u64;
-                borrow = 0;
-            }
-        }
-
-        (*ptr).limbs = result;
-        ptr
-    }
+    let ((a_negative, a), (b_negative, b)) = unsafe { (parts(a), parts(b)) };
+    let (negative, magnitude) = signed_add(a_negative, a, !b_negative, b);
+    alloc_bigint(negative, &magnitude)
 }
 
 /// Multiply two BigInts
 #[no_mangle]
 pub extern "C" fn js_bigint_mul(a: *const BigIntHeader, b: *const BigIntHeader) -> *mut BigIntHeader {
-    let layout = Layout::new::<BigIntHeader>();
-    unsafe {
-        let ptr = alloc(layout) as *mut BigIntHeader;
-        if ptr.is_null() {
-            panic!("Failed to allocate BigInt");
-        }
-
-        let a_limbs = (*a).limbs;
-        let b_limbs = (*b).limbs;
-        let mut result = [0u64; 4];
-
-        // School multiplication (only keeping lower 256 bits)
-        for i in 0..4 {
-            let mut carry = 0u128;
-            for j in 0..(4 - i) {
-                let product = (a_limbs[i] as u128) * (b_limbs[j] as u128)
-                    + (result[i + j] as u128)
-                    + carry;
-                result[i + j] = product as u64;
-                carry = product >> 64;
-            }
-        }
-
-        (*ptr).limbs = result;
-        ptr
+    let ((a_negative, a), (b_negative, b)) = unsafe { (parts(a), parts(b)) };
+    if bignum::bit_len(a) + bignum::bit_len(b) > MAX_BITS + 1 {
+        throw_error("RangeError", "Maximum BigInt size exceeded");
     }
+    alloc_bigint(a_negative != b_negative, &bignum::mul(a, b))
 }
 
-/// Divide two BigInts (a / b)
+/// Divide two BigInts (a / b), truncating toward zero
 #[no_mangle]
 pub extern "C" fn js_bigint_div(a: *const BigIntHeader, b: *const BigIntHeader) -> *mut BigIntHeader {
-    let layout = Layout::new::<BigIntHeader>();
-    unsafe {
-        let ptr = alloc(layout) as *mut BigIntHeader;
-        if ptr.is_null() {
-            panic!("Failed to allocate BigInt");
-        }
-
-        let a_limbs = (*a).limbs;
-        let b_limbs = (*b).limbs;
-
-        // Check for division by zero
-        if b_limbs == [0, 0, 0, 0] {
-            panic!("Division by zero");
-        }
-
-        // Simple binary division
-        let mut quotient = [0u64; 4];
-        let mut remainder = [0u64; 4];
-
-        for i in (0..256).rev() {
-            // Shift remainder left by 1
-            let mut carry = 0u64;
-            for limb in remainder.iter_mut() {
-                let new_carry = *limb >> 63;
-                *limb = (*limb << 1) | carry;
-                carry = new_carry;
-            }
-
-            // Set LSB of remainder from dividend
-            let limb_idx = i / 64;
-            let bit_idx = i % 64;
-            remainder[0] |= (a_limbs[limb_idx] >> bit_idx) & 1;
-
-            // If remainder >= divisor, subtract and set quotient bit
-            if compare_limbs(&remainder, &b_limbs) >= 0 {
-                subtract_limbs(&mut remainder, &b_limbs);
-                let q_limb_idx = i / 64;
-                let q_bit_idx = i % 64;
-                quotient[q_limb_idx] |= 1u64 << q_bit_idx;
-            }
-        }
-
-        (*ptr).limbs = quotient;
-        ptr
+    let ((a_negative, a), (b_negative, b)) = unsafe { (parts(a), parts(b)) };
+    if b.is_empty() {
+        throw_error("RangeError", "Division by zero");
     }
+    alloc_bigint(a_negative != b_negative, &bignum::div_rem(a, b).0)
 }
 
-/// Modulo of two BigInts (a % b)
+/// Remainder of two BigInts (a % b), with the sign of a
 #[no_mangle]
 pub extern "C" fn js_bigint_mod(a: *const BigIntHeader, b: *const BigIntHeader) -> *mut BigIntHeader {
-    let layout = Layout::new::<BigIntHeader>();
-    unsafe {
-        let ptr = alloc(layout) as *mut BigIntHeader;
-        if ptr.is_null() {
-            panic!("Failed to allocate BigInt");
-        }
-
-        let a_limbs = (*a).limbs;
-        let b_limbs = (*b).limbs;
-
-        // Check for division by zero
-        if b_limbs == [0, 0, 0, 0] {
-            panic!("Division by zero");
-        }
-
-        // Simple binary division, return remainder
-        let mut remainder = [0u64; 4];
-
-        for i in (0..256).rev() {
-            // Shift remainder left by 1
-            let mut carry = 0u64;
-            for limb in remainder.iter_mut() {
-                let new_carry = *limb >> 63;
-                *limb = (*limb << 1) | carry;
-                carry = new_carry;
-            }
-
-            // Set LSB of remainder from dividend
-            let limb_idx = i / 64;
-            let bit_idx = i % 64;
-            remainder[0] |= (a_limbs[limb_idx] >> bit_idx) & 1;
-
-            // If remainder >= divisor, subtract
-            if compare_limbs(&remainder, &b_limbs) >= 0 {
-                subtract_limbs(&mut remainder, &b_limbs);
-            }
-        }
-
-        (*ptr).limbs = remainder;
-        ptr
+    let ((a_negative, a), (_, b)) = unsafe { (parts(a), parts(b)) };
+    if b.is_empty() {
+        throw_error("RangeError", "Division by zero");
     }
+    alloc_bigint(a_negative, &bignum::div_rem(a, b).1)
 }
 
 /// Power of two BigInts (a ** b) using binary exponentiation
-/// Note: b is interpreted as a u64 (only lower 64 bits are used)
 #[no_mangle]
 pub extern "C" fn js_bigint_pow(a: *const BigIntHeader, b: *const BigIntHeader) -> *mut BigIntHeader {
-    let layout = Layout::new::<BigIntHeader>();
-    unsafe {
-        let ptr = alloc(layout) as *mut BigIntHeader;
-        if ptr.is_null() {
-            panic!("Failed to allocate BigInt");
-        }
-
-        // Get exponent as u64 (only lower 64 bits)
-        let exp = (*b).limbs[0];
-
-        if exp == 0 {
-            // Anything to the power of 0 is 1
-            (*ptr).limbs = [1, 0, 0, 0];
-            return ptr;
+    let ((base_negative, base), (exp_negative, exp)) = unsafe { (parts(a), parts(b)) };
+    if exp_negative {
+        throw_error("RangeError", "Exponent must be non-negative");
+    }
+    if exp.is_empty() {
+        return js_bigint_from_u64(1);
+    }
+    let odd = exp[0] & 1 == 1;
+    // 0 and ±1 stay small whatever the exponent
+    if base.is_empty() || base == [1] {
+        return alloc_bigint(base_negative && odd, base);
+    }
+    if exp.len() > 1 || bignum::bit_len(base).saturating_sub(1).saturating_mul(exp[0]) > MAX_BITS {
+        throw_error("RangeError", "Maximum BigInt size exceeded");
+    }
+    let mut result = vec![1u64];
+    let mut square = base.to_vec();
+    let mut e = exp[0];
+    while e > 0 {
+        if e & 1 == 1 {
+            result = bignum::mul(&result, &square);
         }
-
-        // Binary exponentiation
-        let mut result = [1u64, 0, 0, 0];
-        let mut base = (*a).limbs;
-        let mut e = exp;
-
-        while e > 0 {
-            if e & 1 == 1 {
-                result = mul_limbs(&result, &base);
-            }
-            base = mul_limbs(&base, &base);
-            e >>= 1;
+        e >>= 1;
+        if e > 0 {
+            square = bignum::mul(&square, &square);
         }
+    }
+    alloc_bigint(base_negative && odd, &result)
+}
 
-        (*ptr).limbs = result;
-        ptr
+/// Shift amount of a shift's right operand, None when it's past any BigInt's size
+fn shift_amount(magnitude: &[u64]) -> Option<u64> {
+    match magnitude {
+        [] => Some(0),
+        [bits] if *bits <= MAX_BITS => Some(*bits),
+        _ => None,
     }
 }
 
-/// Multiply two limb arrays (helper for pow)
-fn mul_limbs(a: &[u64; 4], b: &[u64; 4]) -> [u64; 4] {
-    let mut result = [0u64; 4];
-    for i in 0..4 {
-        let mut carry = 0u128;
-        for j in 0..(4 - i) {
-            let product = (a[i] as u128) * (b[j] as u128)
-                + (result[i + j] as u128)
-                + carry;
-            result[i + j] = product as u64;
-            carry = product >> 64;
-        }
+/// `a >> bits` rounding toward negative infinity, like two's complement
+fn shift_right(negative: bool, a: &[u64], bits: u64) -> *mut BigIntHeader {
+    let mut magnitude = bignum::shr(a, bits);
+    if negative && bignum::low_bits_nonzero(a, bits) {
+        magnitude = bignum::add(&magnitude, &[1]);
     }
-    result
+    alloc_bigint(negative, &magnitude)
 }
 
-/// Left shift BigInt by b bits (a << b)
-/// Note: b is interpreted as a u64 (only lower 64 bits are used)
+/// Left shift BigInt by b bits (a << b); a negative b shifts right
 #[no_mangle]
 pub extern "C" fn js_bigint_shl(a: *const BigIntHeader, b: *const BigIntHeader) -> *mut BigIntHeader {
-    let layout = Layout::new::<BigIntHeader>();
-    unsafe {
-        let ptr = alloc(layout) as *mut BigIntHeader;
-        if ptr.is_null() {
-            panic!("Failed to allocate BigInt");
-        }
-
-        let shift = (*b).limbs[0] as usize;
-        if shift >= 256 {
-            // Shift by 256 or more bits results in zero
-            (*ptr).limbs = [0, 0, 0, 0];
-            return ptr;
-        }
-
-        let a_limbs = (*a).limbs;
-        let mut result = [0u64; 4];
-
-        // Calculate full limb shifts and bit shifts within a limb
-        let limb_shift = shift / 64;
-        let bit_shift = (shift % 64) as u32;
-
-        if bit_shift == 0 {
-            // Simple case: only limb-aligned shift
-            for i in limb_shift..4 {
-                result[i] = a_limbs[i - limb_shift];
-            }
-        } else {
-            // General case: shift across limb boundaries
-            for i in limb_shift..4 {
-                let src_idx = i - limb_shift;
-                result[i] |= a_limbs[src_idx] << bit_shift;
-                if src_idx > 0 && i > limb_shift {
-                    result[i] |= a_limbs[src_idx - 1] >> (64 - bit_shift);
-                }
-            }
-            // Handle carry from lower limb into higher position
-            if limb_shift < 4 {
-                for i in (limb_shift + 1)..4 {
-                    let src_idx = i - limb_shift - 1;
-                    result[i] |= a_limbs[src_idx] >> (64 - bit_shift);
-                }
-            }
-        }
-
-        (*ptr).limbs = result;
-        ptr
+    let ((negative, a), (shift_negative, shift)) = unsafe { (parts(a), parts(b)) };
+    match (shift_negative, shift_amount(shift)) {
+        (true, Some(bits)) => shift_right(negative, a, bits),
+        (true, None) => alloc_bigint(negative, if negative { &[1] } else { &[] }),
+        _ if a.is_empty() => alloc_bigint(false, &[]),
+        (false, Some(bits)) => alloc_bigint(negative, &bignum::shl(a, bits)),
+        (false, None) => throw_error("RangeError", "Maximum BigInt size exceeded"),
     }
 }
 
-/// Right shift BigInt by b bits (a >> b)
-/// Note: b is interpreted as a u64 (only lower 64 bits are used)
+/// Right shift BigInt by b bits (a >> b), rounding toward negative infinity; a negative
+/// b shifts left
 #[no_mangle]
 pub extern "C" fn js_bigint_shr(a: *const BigIntHeader, b: *const BigIntHeader) -> *mut BigIntHeader {
-    let layout = Layout::new::<BigIntHeader>();
-    unsafe {
-        let ptr = alloc(layout) as *mut BigIntHeader;
-        if ptr.is_null() {
-            panic!("Failed to allocate BigInt");
-        }
+    let ((negative, a), (shift_negative, shift)) = unsafe { (parts(a), parts(b)) };
+    match (shift_negative, shift_amount(shift)) {
+        (false, Some(bits)) => shift_right(negative, a, bits),
+        (false, None) => alloc_bigint(negative, if negative { &[1] } else { &[] }),
+        _ if a.is_empty() => alloc_bigint(false, &[]),
+        (true, Some(bits)) => alloc_bigint(negative, &bignum::shl(a, bits)),
+        (true, None) => throw_error("RangeError", "Maximum BigInt size exceeded"),
+    }
+}
 
-        let shift = (*b).limbs[0] as usize;
-        if shift >= 256 {
-            // Shift by 256 or more bits results in zero
-            (*ptr).limbs = [0, 0, 0, 0];
-            return ptr;
-        }
+/// `width` limbs of a signed value's two's complement
+fn to_twos_complement(negative: bool, magnitude: &[u64], width: usize) -> Vec<u64> {
+    let mut limbs = magnitude.to_vec();
+    limbs.resize(width, 0);
+    if negative {
+        negate_limbs(&mut limbs);
+    }
+    limbs
+}
 
-        let a_limbs = (*a).limbs;
-        let mut result = [0u64; 4];
-
-        // Calculate full limb shifts and bit shifts within a limb
-        let limb_shift = shift / 64;
-        let bit_shift = (shift % 64) as u32;
-
-        if bit_shift == 0 {
-            // Simple case: only limb-aligned shift
-            for i in 0..(4 - limb_shift) {
-                result[i] = a_limbs[i + limb_shift];
-            }
-        } else {
-            // General case: shift across limb boundaries
-            for i in 0..(4 - limb_shift) {
-                let src_idx = i + limb_shift;
-                result[i] |= a_limbs[src_idx] >> bit_shift;
-                if src_idx + 1 < 4 {
-                    result[i] |= a_limbs[src_idx + 1] << (64 - bit_shift);
-                }
-            }
-        }
+fn from_twos_complement(mut limbs: Vec<u64>) -> (bool, Vec<u64>) {
+    let negative = limbs.last().is_some_and(|top| top >> 63 == 1);
+    if negative {
+        negate_limbs(&mut limbs);
+    }
+    bignum::normalize(&mut limbs);
+    (negative, limbs)
+}
 
-        (*ptr).limbs = result;
-        ptr
+/// Two's complement negation in place: invert and add one
+fn negate_limbs(limbs: &mut [u64]) {
+    let mut carry = true;
+    for limb in limbs.iter_mut() {
+        let (value, overflow) = (!*limb).overflowing_add(carry as u64);
+        *limb = value;
+        carry = overflow;
     }
 }
 
+/// A bitwise operator on the two's complement of both operands
+fn bitwise(a: *const BigIntHeader, b: *const BigIntHeader, op: fn(u64, u64) -> u64) -> *mut BigIntHeader {
+    let ((a_negative, a), (b_negative, b)) = unsafe { (parts(a), parts(b)) };
+    // One spare limb holds the sign
+    let width = a.len().max(b.len()) + 1;
+    let a = to_twos_complement(a_negative, a, width);
+    let b = to_twos_complement(b_negative, b, width);
+    let (negative, magnitude) = from_twos_complement(a.iter().zip(&b).map(|(x, y)| op(*x, *y)).collect());
+    alloc_bigint(negative, &magnitude)
+}
+
 /// Bitwise AND of two BigInts (a & b)
 #[no_mangle]
 pub extern "C" fn js_bigint_and(a: *const BigIntHeader, b: *const BigIntHeader) -> *mut BigIntHeader {
-    let layout = Layout::new::<BigIntHeader>();
-    unsafe {
-        let ptr = alloc(layout) as *mut BigIntHeader;
-        if ptr.is_null() {
-            panic!("Failed to allocate BigInt");
-        }
-
-        let a_limbs = (*a).limbs;
-        let b_limbs = (*b).limbs;
-        let mut result = [0u64; 4];
-
-        for i in 0..4 {
-            result[i] = a_limbs[i] & b_limbs[i];
-        }
-
-        (*ptr).limbs = result;
-        ptr
-    }
+    bitwise(a, b, |x, y| x & y)
 }
 
 /// Bitwise OR of two BigInts (a | b)
 #[no_mangle]
 pub extern "C" fn js_bigint_or(a: *const BigIntHeader, b: *const BigIntHeader) -> *mut BigIntHeader {
-    let layout = Layout::new::<BigIntHeader>();
-    unsafe {
-        let ptr = alloc(layout) as *mut BigIntHeader;
-        if ptr.is_null() {
-            panic!("Failed to allocate BigInt");
-        }
-
-        let a_limbs = (*a).limbs;
-        let b_limbs = (*b).limbs;
-        let mut result = [0u64; 4];
-
-        for i in 0..4 {
-            result[i] = a_limbs[i] | b_limbs[i];
-        }
-
-        (*ptr).limbs = result;
-        ptr
-    }
+    bitwise(a, b, |x, y| x | y)
 }
 
 /// Bitwise XOR of two BigInts (a ^ b)
 #[no_mangle]
 pub extern "C" fn js_bigint_xor(a: *const BigIntHeader, b: *const BigIntHeader) -> *mut BigIntHeader {
-    let layout = Layout::new::<BigIntHeader>();
-    unsafe {
-        let ptr = alloc(layout) as *mut BigIntHeader;
-        if ptr.is_null() {
-            panic!("Failed to allocate BigInt");
-        }
-
-        let a_limbs = (*a).limbs;
-        let b_limbs = (*b).limbs;
-        let mut result = [0u64; 4];
-
-        for i in 0..4 {
-            result[i] = a_limbs[i] ^ b_limbs[i];
-        }
-
-        (*ptr).limbs = result;
-        ptr
-    }
+    bitwise(a, b, |x, y| x ^ y)
 }
 
 /// Compare two BigInts (-1 if a < b, 0 if equal, 1 if a > b)
 #[no_mangle]
 pub extern "C" fn js_bigint_cmp(a: *const BigIntHeader, b: *const BigIntHeader) -> i32 {
-    unsafe {
-        compare_limbs(&(*a).limbs, &(*b).limbs)
-    }
+    let ((a_negative, a), (b_negative, b)) = unsafe { (parts(a), parts(b)) };
+    let ordering = match (a_negative, b_negative) {
+        (false, true) => Ordering::Greater,
+        (true, false) => Ordering::Less,
+        (false, false) => bignum::cmp(a, b),
+        (true, true) => bignum::cmp(b, a),
+    };
+    ordering as i32
 }
 
 /// Check if two BigInts are equal
 #[no_mangle]
 pub extern "C" fn js_bigint_eq(a: *const BigIntHeader, b: *const BigIntHeader) -> bool {
-    unsafe {
-        (*a).limbs == (*b).limbs
-    }
+    unsafe { parts(a) == parts(b) }
 }
 
 /// Convert BigInt to f64 (may lose precision)
 #[no_mangle]
 pub extern "C" fn js_bigint_to_f64(a: *const BigIntHeader) -> f64 {
-    unsafe {
-        let limbs = (*a).limbs;
-        let mut result = 0.0f64;
-        let mut multiplier = 1.0f64;
-        for limb in limbs.iter() {
-            result += (*limb as f64) * multiplier;
-            multiplier *= 18446744073709551616.0; // 2^64
-        }
-        result
-    }
+    let (negative, magnitude) = unsafe { parts(a) };
+    let value = bignum::to_f64(magnitude);
+    if negative { -value } else { value }
 }
 
 /// Convert BigInt to string
 #[no_mangle]
 pub extern "C" fn js_bigint_to_string(a: *const BigIntHeader) -> *mut crate::string::StringHeader {
-    unsafe {
-        let limbs = (*a).limbs;
-
-        // Convert to decimal string
-        let mut digits = Vec::new();
-        let mut temp = limbs;
-
-        // Check if zero
-        if temp == [0, 0, 0, 0] {
-            return crate::string::js_string_from_bytes("0".as_ptr(), 1);
-        }
-
-        while temp != [0, 0, 0, 0] {
-            let mut remainder = 0u128;
-            for i in (0..4).rev() {
-                let dividend = (remainder << 64) + temp[i] as u128;
-                temp[i] = (dividend / 10) as u64;
-                remainder = dividend % 10;
-            }
-            digits.push((remainder as u8 + b'0') as char);
-        }
-
-        digits.reverse();
-        let s: String = digits.into_iter().collect();
-        crate::string::js_string_from_bytes(s.as_ptr(), s.len() as u32)
-    }
+    let s = bigint_to_decimal(a);
+    crate::string::js_string_from_bytes(s.as_ptr(), s.len() as u32)
 }
 
 /// Print BigInt to stdout (for debugging)
 #[no_mangle]
 pub extern "C" fn js_bigint_print(a: *const BigIntHeader) {
-    unsafe {
-        let limbs = (*a).limbs;
-
-        // Convert to decimal string
-        let mut digits = Vec::new();
-        let mut temp = limbs;
-
-        // Check if zero
-        if temp == [0, 0, 0, 0] {
-            println!("0n");
-            return;
-        }
-
-        while temp != [0, 0, 0, 0] {
-            let mut remainder = 0u128;
-            for i in (0..4).rev() {
-                let dividend = (remainder << 64) + temp[i] as u128;
-                temp[i] = (dividend / 10) as u64;
-                remainder = dividend % 10;
-            }
-            digits.push((remainder as u8 + b'0') as char);
-        }
-
-        digits.reverse();
-        let s: String = digits.into_iter().collect();
-        println!("{}n", s);
-    }
+    println!("{}n", bigint_to_decimal(a));
 }
 
 /// Print BigInt to stderr (console.error)
 #[no_mangle]
 pub extern "C" fn js_bigint_error(a: *const BigIntHeader) {
-    unsafe {
-        let limbs = (*a).limbs;
-
-        // Convert to decimal string
-        let mut digits = Vec::new();
-        let mut temp = limbs;
-
-        // Check if zero
-        if temp == [0, 0, 0, 0] {
-            eprintln!("0n");
-            return;
-        }
-
-        while temp != [0, 0, 0, 0] {
-            let mut remainder = 0u128;
-            for i in (0..4).rev() {
-                let dividend = (remainder << 64) + temp[i] as u128;
-                temp[i] = (dividend / 10) as u64;
-                remainder = dividend % 10;
-            }
-            digits.push((remainder as u8 + b'0') as char);
-        }
-
-        digits.reverse();
-        let s: String = digits.into_iter().collect();
-        eprintln!("{}n", s);
-    }
+    eprintln!("{}n", bigint_to_decimal(a));
 }
 
 /// Print BigInt to stderr (console.warn)
 #[no_mangle]
 pub extern "C" fn js_bigint_warn(a: *const BigIntHeader) {
-    unsafe {
-        let limbs = (*a).limbs;
-
-        // Convert to decimal string
-        let mut digits = Vec::new();
-        let mut temp = limbs;
-
-        // Check if zero
-        if temp == [0, 0, 0, 0] {
-            eprintln!("0n");
-            return;
-        }
+    eprintln!("{}n", bigint_to_decimal(a));
+}
 
-        while temp != [0, 0, 0, 0] {
-            let mut remainder = 0u128;
-            for i in (0..4).rev() {
-                let dividend = (remainder << 64) + temp[i] as u128;
-                temp[i] = (dividend / 10) as u64;
-                remainder = dividend % 10;
-            }
-            digits.push((remainder as u8 + b'0') as char);
-        }
+// ============================================================================
+// perry/bigint
+// ============================================================================
 
-        digits.reverse();
-        let s: String = digits.into_iter().collect();
-        eprintln!("{}n", s);
+/// BigInt pointer of a NaN-boxed argument, throwing a TypeError for anything else
+fn bigint_arg(value: f64, function: &str) -> *const BigIntHeader {
+    let value = JSValue::from_bits(value.to_bits());
+    if !value.is_bigint() {
+        throw_error("TypeError", &format!("{} expects BigInt arguments", function));
     }
+    value.as_bigint_ptr()
 }
 
-// Helper functions
+fn boxed_bigint(ptr: *mut BigIntHeader) -> f64 {
+    f64::from_bits(JSValue::bigint_ptr(ptr).bits())
+}
 
-fn compare_limbs(a: &[u64; 4], b: &[u64; 4]) -> i32 {
-    for i in (0..4).rev() {
-        if a[i] > b[i] {
-            return 1;
-        }
-        if a[i] < b[i] {
-            return -1;
-        }
+/// `modPow(base, exponent, modulus)`: `base ** exponent` reduced into [0, modulus)
+/// without the full power. The modulus must be positive and the exponent non-negative.
+#[no_mangle]
+pub extern "C" fn js_bigint_mod_pow(base: f64, exponent: f64, modulus: f64) -> f64 {
+    let (base, exponent, modulus) =
+        (bigint_arg(base, "modPow"), bigint_arg(exponent, "modPow"), bigint_arg(modulus, "modPow"));
+    let ((base_negative, base), (exp_negative, exp), (mod_negative, modulus)) =
+        unsafe { (parts(base), parts(exponent), parts(modulus)) };
+    if mod_negative || modulus.is_empty() {
+        throw_error("RangeError", "modPow modulus must be positive");
+    }
+    if exp_negative {
+        throw_error("RangeError", "modPow exponent must be non-negative");
     }
-    0
+    // A negative base is congruent to modulus - (|base| mod modulus)
+    let mut base = bignum::div_rem(base, modulus).1;
+    if base_negative && !base.is_empty() {
+        base = bignum::sub(modulus, &base);
+    }
+    boxed_bigint(alloc_bigint(false, &bignum::mod_pow(&base, exp, modulus)))
 }
 
-fn subtract_limbs(a: &mut [u64; 4], b: &[u64; 4]) {
-    let mut borrow = 0i128;
-    for i in 0..4 {
-        let diff = (a[i] as i128) - (b[i] as i128) - borrow;
-        if diff < 0 {
-            a[i] = (diff + (1i128 << 64)) as u64;
-            borrow = 1;
-        } else {
-            a[i] = diff as u64;
-            borrow = 0;
-        }
-    }
+/// `gcd(a, b)`: greatest common divisor of the absolute values (`gcd(0n, 0n)` is 0n)
+#[no_mangle]
+pub extern "C" fn js_bigint_gcd(a: f64, b: f64) -> f64 {
+    let (a, b) = (bigint_arg(a, "gcd"), bigint_arg(b, "gcd"));
+    let ((_, a), (_, b)) = unsafe { (parts(a), parts(b)) };
+    boxed_bigint(alloc_bigint(false, &bignum::gcd(a, b)))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn parse(s: &str) -> *mut BigIntHeader {
+        js_bigint_from_string(s.as_ptr(), s.len() as u32)
+    }
+
     #[test]
     fn test_bigint_from_u64() {
         let bi = js_bigint_from_u64(12345);
         unsafe {
-            assert_eq!((*bi).limbs[0], 12345);
-            assert_eq!((*bi).limbs[1], 0);
+            assert_eq!((*bi).limbs(), [12345]);
         }
     }
 
@@ -719,7 +434,7 @@ mod tests {
         let b = js_bigint_from_u64(200);
         let c = js_bigint_add(a, b);
         unsafe {
-            assert_eq!((*c).limbs[0], 300);
+            assert_eq!((*c).limbs()[0], 300);
         }
     }
 
@@ -729,7 +444,7 @@ mod tests {
         let b = js_bigint_from_u64(2000);
         let c = js_bigint_mul(a, b);
         unsafe {
-            assert_eq!((*c).limbs[0], 2_000_000);
+            assert_eq!((*c).limbs()[0], 2_000_000);
         }
     }
 
@@ -738,7 +453,7 @@ mod tests {
         let s = "123456789";
         let bi = js_bigint_from_string(s.as_ptr(), s.len() as u32);
         unsafe {
-            assert_eq!((*bi).limbs[0], 123456789);
+            assert_eq!((*bi).limbs()[0], 123456789);
         }
     }
 
@@ -747,8 +462,32 @@ mod tests {
         let s = "0xFFFFFFFFFFFFFFFF"; // max u64
         let bi = js_bigint_from_string(s.as_ptr(), s.len() as u32);
         unsafe {
-            assert_eq!((*bi).limbs[0], u64::MAX);
-            assert_eq!((*bi).limbs[1], 0);
+            assert_eq!((*bi).limbs(), [u64::MAX]);
         }
     }
+
+    #[test]
+    fn test_bigint_signed_semantics() {
+        let text = |bi| bigint_to_decimal(bi);
+        // Past 256 bits: 2^300 / 3
+        let big = js_bigint_shl(js_bigint_from_u64(1), js_bigint_from_u64(300));
+        assert_eq!(text(js_bigint_div(big, js_bigint_from_u64(3))).len(), 90);
+        assert_eq!(text(js_bigint_sub(js_bigint_from_u64(5), js_bigint_from_u64(7))), "-2");
+        assert_eq!(text(js_bigint_div(parse("-7"), js_bigint_from_u64(2))), "-3");
+        assert_eq!(text(js_bigint_mod(parse("-7"), js_bigint_from_u64(2))), "-1");
+        assert_eq!(text(js_bigint_shr(parse("-7"), js_bigint_from_u64(1))), "-4");
+        assert_eq!(text(js_bigint_and(parse("-6"), parse("13"))), "8");
+        assert_eq!(text(js_bigint_or(parse("-6"), parse("3"))), "-5");
+        assert_eq!(text(js_bigint_pow(parse("-3"), js_bigint_from_u64(3))), "-27");
+        assert_eq!(js_bigint_cmp(parse("-10"), parse("-9")), -1);
+    }
+
+    #[test]
+    fn test_mod_pow_and_gcd() {
+        let boxed = |s: &str| boxed_bigint(parse(s));
+        let result = |value: f64| bigint_to_decimal(JSValue::from_bits(value.to_bits()).as_bigint_ptr());
+        assert_eq!(result(js_bigint_mod_pow(boxed("4"), boxed("13"), boxed("497"))), "445");
+        assert_eq!(result(js_bigint_mod_pow(boxed("-4"), boxed("3"), boxed("5"))), "1");
+        assert_eq!(result(js_bigint_gcd(boxed("-1071"), boxed("462"))), "21");
+    }
 }
@@ -0,0 +1,429 @@
+//! Natural-number arithmetic on little-endian u64 limbs, behind BigInt
+//!
+//! Values are limb slices without high zero limbs (zero is the empty slice); every
+//! function returning a `Vec<u64>` keeps it that way. Multiplication switches from
+//! schoolbook to Karatsuba above `KARATSUBA_THRESHOLD` limbs, and division is Knuth's
+//! algorithm D.
+
+use std::cmp::Ordering;
+
+/// Operand size (limbs of the shorter factor) from which Karatsuba beats schoolbook
+const KARATSUBA_THRESHOLD: usize = 32;
+
+/// Largest power of ten in a limb, and its number of zeros
+const DECIMAL_CHUNK: u64 = 10_000_000_000_000_000_000;
+const DECIMAL_CHUNK_DIGITS: usize = 19;
+
+pub fn normalize(limbs: &mut Vec<u64>) {
+    while limbs.last() == Some(&0) {
+        limbs.pop();
+    }
+}
+
+pub fn cmp(a: &[u64], b: &[u64]) -> Ordering {
+    a.len().cmp(&b.len()).then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+/// Number of significant bits
+pub fn bit_len(a: &[u64]) -> u64 {
+    match a.last() {
+        Some(top) => a.len() as u64 * 64 - top.leading_zeros() as u64,
+        None => 0,
+    }
+}
+
+pub fn add(a: &[u64], b: &[u64]) -> Vec<u64> {
+    let (long, short) = if a.len() >= b.len() { (a, b) } else { (b, a) };
+    let mut result = long.to_vec();
+    add_into(&mut result, short);
+    normalize(&mut result);
+    result
+}
+
+/// `acc += b`, growing `acc` for a final carry
+fn add_into(acc: &mut Vec<u64>, b: &[u64]) {
+    if acc.len() < b.len() {
+        acc.resize(b.len(), 0);
+    }
+    let mut carry = false;
+    for (i, limb) in acc.iter_mut().enumerate() {
+        if i >= b.len() && !carry {
+            break;
+        }
+        let (sum, c1) = limb.overflowing_add(b.get(i).copied().unwrap_or(0));
+        let (sum, c2) = sum.overflowing_add(carry as u64);
+        *limb = sum;
+        carry = c1 || c2;
+    }
+    if carry {
+        acc.push(1);
+    }
+}
+
+/// `acc -= b` where `acc >= b`
+fn sub_into(acc: &mut [u64], b: &[u64]) {
+    let mut borrow = false;
+    for (i, limb) in acc.iter_mut().enumerate() {
+        if i >= b.len() && !borrow {
+            break;
+        }
+        let (diff, b1) = limb.overflowing_sub(b.get(i).copied().unwrap_or(0));
+        let (diff, b2) = diff.overflowing_sub(borrow as u64);
+        *limb = diff;
+        borrow = b1 || b2;
+    }
+    debug_assert!(!borrow, "bignum subtraction underflow");
+}
+
+/// `a - b` where `a >= b`
+pub fn sub(a: &[u64], b: &[u64]) -> Vec<u64> {
+    let mut result = a.to_vec();
+    sub_into(&mut result, b);
+    normalize(&mut result);
+    result
+}
+
+pub fn mul(a: &[u64], b: &[u64]) -> Vec<u64> {
+    if a.is_empty() || b.is_empty() {
+        return Vec::new();
+    }
+    let (long, short) = if a.len() >= b.len() { (a, b) } else { (b, a) };
+    let mut result = if short.len() < KARATSUBA_THRESHOLD {
+        schoolbook(long, short)
+    } else if short.len() <= long.len() / 2 {
+        // Lopsided: multiply the short factor by slices of the long one
+        let mut result = vec![0; long.len() + short.len()];
+        for (i, chunk) in long.chunks(short.len()).enumerate() {
+            let mut partial = mul(chunk, short);
+            partial.resize(result.len() - i * short.len(), 0);
+            let mut window = result[i * short.len()..].to_vec();
+            add_into(&mut window, &partial);
+            let len = result.len() - i * short.len();
+            result[i * short.len()..].copy_from_slice(&window[..len]);
+        }
+        result
+    } else {
+        karatsuba(long, short)
+    };
+    normalize(&mut result);
+    result
+}
+
+fn schoolbook(a: &[u64], b: &[u64]) -> Vec<u64> {
+    let mut result = vec![0u64; a.len() + b.len()];
+    for (i, &x) in a.iter().enumerate() {
+        if x == 0 {
+            continue;
+        }
+        let mut carry = 0u128;
+        for (j, &y) in b.iter().enumerate() {
+            let t = x as u128 * y as u128 + result[i + j] as u128 + carry;
+            result[i + j] = t as u64;
+            carry = t >> 64;
+        }
+        result[i + b.len()] = carry as u64;
+    }
+    result
+}
+
+/// (a1·B + a0)(b1·B + b0) with three half-size products: z2·B² + z1·B + z0, where
+/// z1 = (a0 + a1)(b0 + b1) - z0 - z2
+fn karatsuba(a: &[u64], b: &[u64]) -> Vec<u64> {
+    let half = a.len().max(b.len()).div_ceil(2);
+    let split = |x: &[u64]| {
+        let (low, high) = x.split_at(half.min(x.len()));
+        let mut low = low.to_vec();
+        normalize(&mut low);
+        (low, high.to_vec())
+    };
+    let (a0, a1) = split(a);
+    let (b0, b1) = split(b);
+    let z0 = mul(&a0, &b0);
+    let z2 = mul(&a1, &b1);
+    let mut z1 = mul(&add(&a0, &a1), &add(&b0, &b1));
+    sub_into(&mut z1, &z0);
+    sub_into(&mut z1, &z2);
+    normalize(&mut z1);
+
+    let mut result = vec![0u64; a.len() + b.len() + 1];
+    for (shift, part) in [(0, &z0), (half, &z1), (2 * half, &z2)] {
+        let mut window = result[shift..].to_vec();
+        add_into(&mut window, part);
+        let len = result.len() - shift;
+        result[shift..].copy_from_slice(&window[..len]);
+    }
+    result
+}
+
+/// Quotient and remainder of division by a single limb
+pub fn div_rem_small(a: &[u64], divisor: u64) -> (Vec<u64>, u64) {
+    let mut quotient = vec![0u64; a.len()];
+    let mut remainder = 0u128;
+    for (i, &limb) in a.iter().enumerate().rev() {
+        let current = (remainder << 64) | limb as u128;
+        quotient[i] = (current / divisor as u128) as u64;
+        remainder = current % divisor as u128;
+    }
+    normalize(&mut quotient);
+    (quotient, remainder as u64)
+}
+
+/// Quotient and remainder (Knuth, TAOCP vol. 2, 4.3.1, algorithm D). Panics on a zero divisor.
+pub fn div_rem(a: &[u64], b: &[u64]) -> (Vec<u64>, Vec<u64>) {
+    assert!(!b.is_empty(), "bignum division by zero");
+    if cmp(a, b) == Ordering::Less {
+        return (Vec::new(), a.to_vec());
+    }
+    if b.len() == 1 {
+        let (quotient, remainder) = div_rem_small(a, b[0]);
+        return (quotient, if remainder == 0 { Vec::new() } else { vec![remainder] });
+    }
+
+    // Normalize so the divisor's top bit is set; the dividend gains a limb for the shift
+    let shift = b[b.len() - 1].leading_zeros();
+    let divisor = shl_within(b, shift);
+    let mut rem = shl_within(a, shift);
+    rem.push(if shift == 0 { 0 } else { a[a.len() - 1] >> (64 - shift) });
+    let n = divisor.len();
+    let m = rem.len() - n;
+    let (top, second) = (divisor[n - 1] as u128, divisor[n - 2] as u128);
+    let mut quotient = vec![0u64; m];
+
+    for j in (0..m).rev() {
+        // Estimate the quotient limb from the top two limbs, then correct it (at most twice)
+        let numerator = (rem[j + n] as u128) << 64 | rem[j + n - 1] as u128;
+        let mut qhat = numerator / top;
+        let mut rhat = numerator % top;
+        while qhat >> 64 != 0 || qhat * second > (rhat << 64 | rem[j + n - 2] as u128) {
+            qhat -= 1;
+            rhat += top;
+            if rhat >> 64 != 0 {
+                break;
+            }
+        }
+
+        // rem[j..=j+n] -= qhat * divisor
+        let mut borrow = 0i128;
+        let mut carry = 0u128;
+        for i in 0..n {
+            let product = qhat * divisor[i] as u128 + carry;
+            carry = product >> 64;
+            let t = rem[i + j] as i128 - borrow - (product as u64) as i128;
+            rem[i + j] = t as u64;
+            borrow = (t < 0) as i128;
+        }
+        let t = rem[j + n] as i128 - borrow - carry as i128;
+        rem[j + n] = t as u64;
+
+        // The estimate was one too large: add the divisor back
+        if t < 0 {
+            qhat -= 1;
+            let mut carry = 0u128;
+            for i in 0..n {
+                let sum = rem[i + j] as u128 + divisor[i] as u128 + carry;
+                rem[i + j] = sum as u64;
+                carry = sum >> 64;
+            }
+            rem[j + n] = rem[j + n].wrapping_add(carry as u64);
+        }
+        quotient[j] = qhat as u64;
+    }
+
+    normalize(&mut quotient);
+    rem.truncate(n);
+    let mut remainder = shr(&rem, shift as u64);
+    normalize(&mut remainder);
+    (quotient, remainder)
+}
+
+/// `a << shift` for `shift < 64`, dropping bits shifted out of the top limb
+fn shl_within(a: &[u64], shift: u32) -> Vec<u64> {
+    if shift == 0 {
+        return a.to_vec();
+    }
+    (0..a.len())
+        .map(|i| a[i] << shift | if i > 0 { a[i - 1] >> (64 - shift) } else { 0 })
+        .collect()
+}
+
+pub fn shl(a: &[u64], bits: u64) -> Vec<u64> {
+    if a.is_empty() {
+        return Vec::new();
+    }
+    let (limbs, shift) = ((bits / 64) as usize, (bits % 64) as u32);
+    let mut result = vec![0u64; limbs];
+    result.extend(shl_within(a, shift));
+    if shift != 0 {
+        result.push(a[a.len() - 1] >> (64 - shift));
+    }
+    normalize(&mut result);
+    result
+}
+
+pub fn shr(a: &[u64], bits: u64) -> Vec<u64> {
+    let limbs = (bits / 64) as usize;
+    if limbs >= a.len() {
+        return Vec::new();
+    }
+    let shift = (bits % 64) as u32;
+    let a = &a[limbs..];
+    let mut result: Vec<u64> = if shift == 0 {
+        a.to_vec()
+    } else {
+        (0..a.len())
+            .map(|i| a[i] >> shift | a.get(i + 1).map_or(0, |next| next << (64 - shift)))
+            .collect()
+    };
+    normalize(&mut result);
+    result
+}
+
+/// Whether any of the low `bits` bits is set
+pub fn low_bits_nonzero(a: &[u64], bits: u64) -> bool {
+    let limbs = ((bits / 64) as usize).min(a.len());
+    if a[..limbs].iter().any(|&limb| limb != 0) {
+        return true;
+    }
+    let shift = bits % 64;
+    shift != 0 && a.get(limbs).is_some_and(|&limb| limb & ((1u64 << shift) - 1) != 0)
+}
+
+/// Greatest common divisor by the Euclidean algorithm (`gcd(0, 0)` is 0)
+pub fn gcd(a: &[u64], b: &[u64]) -> Vec<u64> {
+    let (mut a, mut b) = (a.to_vec(), b.to_vec());
+    while !b.is_empty() {
+        let (_, remainder) = div_rem(&a, &b);
+        a = std::mem::replace(&mut b, remainder);
+    }
+    a
+}
+
+/// `base ** exp % modulus` by left-to-right square-and-multiply, reducing after every
+/// product. Panics on a zero modulus.
+pub fn mod_pow(base: &[u64], exp: &[u64], modulus: &[u64]) -> Vec<u64> {
+    if modulus == [1] {
+        return Vec::new();
+    }
+    let base = div_rem(base, modulus).1;
+    let mut result = vec![1u64];
+    for bit in (0..bit_len(exp)).rev() {
+        result = div_rem(&mul(&result, &result), modulus).1;
+        if exp[(bit / 64) as usize] >> (bit % 64) & 1 == 1 {
+            result = div_rem(&mul(&result, &base), modulus).1;
+        }
+    }
+    result
+}
+
+/// Parse digits in `radix` (2 to 36), ignoring `_` separators. None on any other character.
+pub fn from_radix(digits: &str, radix: u32) -> Option<Vec<u64>> {
+    let mut result = Vec::new();
+    let (mut chunk, mut scale) = (0u64, 1u64);
+    for c in digits.chars().filter(|&c| c != '_') {
+        let digit = c.to_digit(radix)? as u64;
+        chunk = chunk * radix as u64 + digit;
+        scale *= radix as u64;
+        // Flush before the chunk can overflow a limb
+        if scale > u64::MAX / 36 {
+            mul_small_add(&mut result, scale, chunk);
+            (chunk, scale) = (0, 1);
+        }
+    }
+    if scale > 1 {
+        mul_small_add(&mut result, scale, chunk);
+    }
+    normalize(&mut result);
+    Some(result)
+}
+
+/// `acc = acc * factor + addend`
+fn mul_small_add(acc: &mut Vec<u64>, factor: u64, addend: u64) {
+    let mut carry = addend as u128;
+    for limb in acc.iter_mut() {
+        let t = *limb as u128 * factor as u128 + carry;
+        *limb = t as u64;
+        carry = t >> 64;
+    }
+    if carry != 0 {
+        acc.push(carry as u64);
+    }
+}
+
+pub fn to_decimal(a: &[u64]) -> String {
+    if a.is_empty() {
+        return "0".to_string();
+    }
+    let mut chunks = Vec::new();
+    let mut rest = a.to_vec();
+    while !rest.is_empty() {
+        let (quotient, chunk) = div_rem_small(&rest, DECIMAL_CHUNK);
+        chunks.push(chunk);
+        rest = quotient;
+    }
+    let mut text = chunks.pop().unwrap_or(0).to_string();
+    for chunk in chunks.iter().rev() {
+        text.push_str(&format!("{:0width$}", chunk, width = DECIMAL_CHUNK_DIGITS));
+    }
+    text
+}
+
+/// Nearest f64 (rounding the top 64 significant bits)
+pub fn to_f64(a: &[u64]) -> f64 {
+    let bits = bit_len(a);
+    if bits <= 64 {
+        return a.first().copied().unwrap_or(0) as f64;
+    }
+    let top = shr(a, bits - 64)[0];
+    let sticky = low_bits_nonzero(a, bits - 64) as u64;
+    // Keep a sticky bit so the u64 -> f64 conversion rounds correctly
+    (top | sticky) as f64 * 2f64.powi((bits - 64) as i32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Vec<u64> {
+        from_radix(text, 10).unwrap()
+    }
+
+    #[test]
+    fn test_karatsuba_matches_schoolbook() {
+        // (2^4096 - 1)^2 through Karatsuba, checked against schoolbook
+        let a = vec![u64::MAX; 64];
+        let b: Vec<u64> = (1..=50u64).map(|i| i.wrapping_mul(0x9E37_79B9_7F4A_7C15)).collect();
+        let mut expected = schoolbook(&a, &a);
+        normalize(&mut expected);
+        assert_eq!(mul(&a, &a), expected);
+        let mut expected = schoolbook(&a, &b);
+        normalize(&mut expected);
+        assert_eq!(mul(&a, &b), expected);
+        // Lopsided operands
+        let long = vec![0x1234_5678_9ABC_DEF0u64; 200];
+        let mut expected = schoolbook(&long, &b);
+        normalize(&mut expected);
+        assert_eq!(mul(&long, &b), expected);
+    }
+
+    #[test]
+    fn test_div_rem() {
+        let a: Vec<u64> = (1..=70u64).map(|i| i.wrapping_mul(0xD1B5_4A32_D192_ED03)).collect();
+        let b: Vec<u64> = (1..=23u64).map(|i| i.wrapping_mul(0x8CB9_2BA7_2F3D_8DD7)).collect();
+        let (q, r) = div_rem(&a, &b);
+        assert_eq!(cmp(&r, &b), Ordering::Less);
+        assert_eq!(add(&mul(&q, &b), &r), a);
+
+        let (q, r) = div_rem(&parse("340282366920938463463374607431768211457"), &parse("18446744073709551617"));
+        assert_eq!((to_decimal(&q), to_decimal(&r)), ("18446744073709551615".to_string(), "2".to_string()));
+    }
+
+    #[test]
+    fn test_gcd_and_mod_pow() {
+        assert_eq!(to_decimal(&gcd(&parse("1071"), &parse("462"))), "21");
+        // Fermat: 3^(p-1) = 1 mod p for the prime 2^127 - 1
+        let p = parse("170141183460469231731687303715884105727");
+        let p_minus_1 = sub(&p, &[1]);
+        assert_eq!(mod_pow(&[3], &p_minus_1, &p), vec![1]);
+        assert_eq!(to_decimal(&mod_pow(&[4], &[13], &[497])), "445");
+    }
+}
@@ -22,7 +22,7 @@ fn buffer_layout(capacity: usize) -> Layout {
 }
 
 /// Allocate a buffer with the given capacity
-fn buffer_alloc(capacity: u32) -> *mut BufferHeader {
+pub(crate) fn buffer_alloc(capacity: u32) -> *mut BufferHeader {
     let layout = buffer_layout(capacity as usize);
     unsafe {
         let ptr = alloc(layout) as *mut BufferHeader;
@@ -43,7 +43,7 @@ fn buffer_data(buf: *const BufferHeader) -> *const u8 {
 }
 
 /// Get the mutable data pointer for a buffer
-fn buffer_data_mut(buf: *mut BufferHeader) -> *mut u8 {
+pub(crate) fn buffer_data_mut(buf: *mut BufferHeader) -> *mut u8 {
     unsafe {
         (buf as *mut u8).add(std::mem::size_of::<BufferHeader>())
     }
@@ -0,0 +1,192 @@
+//! Buffer pool for network servers (`perry/buffer-pool`)
+//!
+//! Request and response bodies are short-lived and similarly sized, so a busy server spends
+//! much of its time allocating and freeing them. The pool keeps released buffers in
+//! power-of-two size classes from 256 B to 1 MiB and hands them out again; anything larger
+//! is allocated and dropped as usual. Each class keeps at most [`MAX_PER_CLASS`] buffers so
+//! a burst doesn't pin its memory for good.
+//!
+//! The HTTP and WebSocket servers use the byte-vector side ([`take`], [`give`] and
+//! [`PooledBytes`]); programs get fixed-size Buffers:
+//!
+//! ```typescript
+//! import { acquire, release } from "perry/buffer-pool";
+//! const buf = acquire(4096);   // zero-filled, buf.length === 4096
+//! // ...
+//! release(buf);                // buf must not be used afterwards
+//! ```
+
+use std::sync::Mutex;
+
+use crate::buffer::{buffer_alloc, buffer_data_mut, BufferHeader};
+
+/// Smallest size class, 256 B
+const MIN_CLASS_SHIFT: u32 = 8;
+/// Largest size class, 1 MiB
+const MAX_CLASS_SHIFT: u32 = 20;
+const CLASSES: usize = (MAX_CLASS_SHIFT - MIN_CLASS_SHIFT + 1) as usize;
+/// Buffers kept per size class; releases beyond it are dropped
+pub const MAX_PER_CLASS: usize = 64;
+
+const NO_VECS: Vec<Vec<u8>> = Vec::new();
+const NO_BUFFERS: Vec<usize> = Vec::new();
+
+/// Free byte vectors, per size class
+static VECS: Mutex<[Vec<Vec<u8>>; CLASSES]> = Mutex::new([NO_VECS; CLASSES]);
+/// Free JS Buffers (`BufferHeader` addresses), per size class
+static BUFFERS: Mutex<[Vec<usize>; CLASSES]> = Mutex::new([NO_BUFFERS; CLASSES]);
+
+/// The smallest class holding `size` bytes, if one does
+fn class_for(size: usize) -> Option<usize> {
+    let shift = size.max(1 << MIN_CLASS_SHIFT).checked_next_power_of_two()?.trailing_zeros();
+    (shift <= MAX_CLASS_SHIFT).then(|| (shift - MIN_CLASS_SHIFT) as usize)
+}
+
+/// The largest class a buffer with `capacity` bytes serves; none when it is smaller than
+/// every class or so much larger than the biggest that keeping it would waste memory
+fn class_of(capacity: usize) -> Option<usize> {
+    if capacity < 1 << MIN_CLASS_SHIFT || capacity > 2 << MAX_CLASS_SHIFT {
+        return None;
+    }
+    let shift = (usize::BITS - 1 - capacity.leading_zeros()).min(MAX_CLASS_SHIFT);
+    Some((shift - MIN_CLASS_SHIFT) as usize)
+}
+
+fn class_size(class: usize) -> usize {
+    1 << (class as u32 + MIN_CLASS_SHIFT)
+}
+
+/// An empty vector with room for at least `min_capacity` bytes, reused when one is free
+pub fn take(min_capacity: usize) -> Vec<u8> {
+    let Some(class) = class_for(min_capacity) else {
+        return Vec::with_capacity(min_capacity);
+    };
+    if let Some(vec) = VECS.lock().unwrap()[class].pop() {
+        return vec;
+    }
+    Vec::with_capacity(class_size(class))
+}
+
+/// Hand a vector back for reuse; it is dropped when its class is full or it is too small
+pub fn give(mut vec: Vec<u8>) {
+    let Some(class) = class_of(vec.capacity()) else { return };
+    vec.clear();
+    let mut vecs = VECS.lock().unwrap();
+    if vecs[class].len() < MAX_PER_CLASS {
+        vecs[class].push(vec);
+    }
+}
+
+/// Bytes that go back to the pool when dropped, for `Bytes::from_owner`
+pub struct PooledBytes(Vec<u8>);
+
+impl PooledBytes {
+    pub fn new(bytes: Vec<u8>) -> Self {
+        PooledBytes(bytes)
+    }
+}
+
+impl AsRef<[u8]> for PooledBytes {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl Drop for PooledBytes {
+    fn drop(&mut self) {
+        give(std::mem::take(&mut self.0));
+    }
+}
+
+/// Strip a NaN-box pointer tag, if any
+fn buffer_ptr(value: f64) -> *mut BufferHeader {
+    let bits = value.to_bits();
+    if bits >> 48 >= 0x7FF8 {
+        (bits & 0x0000_FFFF_FFFF_FFFF) as *mut BufferHeader
+    } else {
+        bits as *mut BufferHeader
+    }
+}
+
+/// `acquire(size)`: a zero-filled Buffer of `size` bytes, reused when one is free
+#[no_mangle]
+pub extern "C" fn js_buffer_pool_acquire(size: f64) -> f64 {
+    let size = if size.is_finite() && size > 0.0 { size as usize } else { 0 };
+    let class = class_for(size);
+    let reused = class.and_then(|class| BUFFERS.lock().unwrap()[class].pop());
+    let buf = match (reused, class) {
+        (Some(addr), _) => addr as *mut BufferHeader,
+        (None, Some(class)) => buffer_alloc(class_size(class) as u32),
+        (None, None) => buffer_alloc(size as u32),
+    };
+    unsafe {
+        (*buf).length = size as u32;
+        std::ptr::write_bytes(buffer_data_mut(buf), 0, size);
+    }
+    f64::from_bits(buf as u64)
+}
+
+/// `release(buf)`: hand a Buffer back for reuse. Buffers not from `acquire` are kept too
+/// when their capacity fits a class; the caller must not touch `buf` afterwards.
+#[no_mangle]
+pub extern "C" fn js_buffer_pool_release(buf: f64) -> f64 {
+    const TAG_UNDEFINED: u64 = 0x7FFC_0000_0000_0001;
+    let buf = buffer_ptr(buf);
+    if !buf.is_null() {
+        if let Some(class) = class_of(unsafe { (*buf).capacity } as usize) {
+            let mut buffers = BUFFERS.lock().unwrap();
+            if buffers[class].len() < MAX_PER_CLASS && !buffers[class].contains(&(buf as usize)) {
+                buffers[class].push(buf as usize);
+            }
+        }
+    }
+    f64::from_bits(TAG_UNDEFINED)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_size_classes() {
+        assert_eq!(class_for(0), Some(0));
+        assert_eq!(class_for(256), Some(0));
+        assert_eq!(class_for(257), Some(1));
+        assert_eq!(class_for(1 << 20), Some(CLASSES - 1));
+        assert_eq!(class_for((1 << 20) + 1), None);
+        assert_eq!(class_of(255), None);
+        assert_eq!(class_of(600), Some(1));
+        assert_eq!(class_of(2 << 20), Some(CLASSES - 1));
+        assert_eq!(class_of(4 << 20), None);
+    }
+
+    #[test]
+    fn test_vectors_are_reused() {
+        let mut vec = take(40_000);
+        assert_eq!(vec.capacity(), 1 << 16);
+        vec.extend_from_slice(b"body");
+        let addr = vec.as_ptr();
+        drop(PooledBytes::new(vec));
+        let again = take(50_000);
+        assert_eq!((again.as_ptr(), again.len()), (addr, 0));
+        // Too large to pool
+        assert_eq!(take(3 << 20).capacity(), 3 << 20);
+    }
+
+    #[test]
+    fn test_buffers_are_reused_zeroed() {
+        let buf = js_buffer_pool_acquire(3000.0);
+        let header = buf.to_bits() as *mut BufferHeader;
+        unsafe {
+            assert_eq!(((*header).length, (*header).capacity), (3000, 4096));
+            *(header as *mut u8).add(std::mem::size_of::<BufferHeader>()) = 7;
+        }
+        js_buffer_pool_release(buf);
+        let again = js_buffer_pool_acquire(2500.0);
+        assert_eq!(again.to_bits(), buf.to_bits());
+        unsafe {
+            assert_eq!((*header).length, 2500);
+            assert_eq!(*(header as *const u8).add(std::mem::size_of::<BufferHeader>()), 0);
+        }
+    }
+}
@@ -279,8 +279,7 @@ fn format_jsvalue(value: f64, depth: usize) -> String {
             } else {
                 // First check if this is an Error object by checking the object_type field
                 // Both ObjectHeader and ErrorHeader have object_type as the first u32 field
-                let object_type = *(ptr as *const u32);
-                if object_type == crate::error::OBJECT_TYPE_ERROR {
+                if crate::error::is_error_object(ptr as *const u8) {
                     // This is an Error object - format as "Error: <message>"
                     let error_ptr = ptr as *const crate::error::ErrorHeader;
                     let name_ptr = (*error_ptr).name;
@@ -455,8 +454,7 @@ fn format_jsvalue_for_json(value: f64, depth: usize) -> String {
                 "null".to_string()
             } else {
                 // First check if this is an Error object
-                let object_type = *(ptr as *const u32);
-                if object_type == crate::error::OBJECT_TYPE_ERROR {
+                if crate::error::is_error_object(ptr as *const u8) {
                     // Format Error as "Error: <message>"
                     let error_ptr = ptr as *const crate::error::ErrorHeader;
                     let name_ptr = (*error_ptr).name;
@@ -28,6 +28,8 @@ pub extern "C" fn js_child_process_exec_sync(
             Err(_) => return std::ptr::null_mut(),
         };
 
+        crate::permissions::check_shell_command(cmd_str);
+
         // Execute the command using shell
         #[cfg(unix)]
         let output = Command::new("sh")
@@ -81,6 +83,8 @@ pub extern "C" fn js_child_process_spawn_sync(
             Err(_) => return std::ptr::null_mut(),
         };
 
+        crate::permissions::check(crate::permissions::Capability::Run, cmd_str);
+
         // Build command
         let mut command = Command::new(cmd_str);
 
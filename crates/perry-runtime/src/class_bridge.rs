@@ -0,0 +1,169 @@
+//! Class instance members by name, for the V8 bridge
+//!
+//! Class instances keep their fields in slots and their methods in compiled functions,
+//! so neither can be found by name at runtime. When a program uses the JS runtime, each
+//! module's init registers its classes' fields (slot indices) and methods (function
+//! pointers and arities), inherited ones included. perry-jsruntime then hands instances
+//! to JavaScript as objects whose property reads, writes and method calls come back here.
+
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use crate::error::OBJECT_TYPE_REGULAR;
+use crate::object::{call_class_getter, call_class_setter, js_object_get_field, js_object_set_field, ObjectHeader};
+use crate::value::JSValue;
+
+/// Most parameters a bridged method can declare
+pub const MAX_METHOD_ARITY: u32 = 8;
+
+#[derive(Default)]
+struct ClassMembers {
+    /// Field name -> slot index
+    fields: BTreeMap<String, u32>,
+    /// Method name -> (compiled `{Class}_{method}(this, ...)`, parameter count)
+    methods: BTreeMap<String, (usize, u32)>,
+}
+
+/// Registered members by class id
+static MEMBERS: RwLock<Option<HashMap<u32, ClassMembers>>> = RwLock::new(None);
+
+unsafe fn register(class_id: u32, name_ptr: *const u8, name_len: usize, add: impl FnOnce(&mut ClassMembers, String)) {
+    if name_ptr.is_null() {
+        return;
+    }
+    let bytes = std::slice::from_raw_parts(name_ptr, name_len);
+    let Ok(name) = std::str::from_utf8(bytes) else { return };
+    let mut members = MEMBERS.write().unwrap();
+    add(members.get_or_insert_with(HashMap::new).entry(class_id).or_default(), name.to_string());
+}
+
+/// Register the slot of field `name` on instances of `class_id`
+#[no_mangle]
+pub unsafe extern "C" fn js_register_class_field(class_id: u32, name_ptr: *const u8, name_len: usize, index: u32) {
+    register(class_id, name_ptr, name_len, |members, name| {
+        members.fields.insert(name, index);
+    });
+}
+
+/// Register method `name` of `class_id`, compiled as `func(this, arg0, ..)` with `arity` parameters
+#[no_mangle]
+pub unsafe extern "C" fn js_register_class_method(class_id: u32, name_ptr: *const u8, name_len: usize, func: i64, arity: u32) {
+    if func == 0 || arity > MAX_METHOD_ARITY {
+        return;
+    }
+    register(class_id, name_ptr, name_len, |members, name| {
+        members.methods.insert(name, (func as usize, arity));
+    });
+}
+
+/// The class instance behind a pointer, if its class registered members
+pub unsafe fn bridged_instance(ptr: *const u8) -> Option<*mut ObjectHeader> {
+    let obj = ptr as *mut ObjectHeader;
+    if obj.is_null() || (*obj).object_type != OBJECT_TYPE_REGULAR || (*obj).class_id == 0 {
+        return None;
+    }
+    let members = MEMBERS.read().unwrap();
+    members.as_ref()?.contains_key(&(*obj).class_id).then_some(obj)
+}
+
+fn with_members<R>(obj: *const ObjectHeader, f: impl FnOnce(&ClassMembers) -> R) -> Option<R> {
+    let members = MEMBERS.read().unwrap();
+    members.as_ref()?.get(&unsafe { (*obj).class_id }).map(f)
+}
+
+/// Names of an instance's fields, then of its methods
+pub fn member_names(obj: *const ObjectHeader) -> (Vec<String>, Vec<String>) {
+    with_members(obj, |members| (members.fields.keys().cloned().collect(), members.methods.keys().cloned().collect()))
+        .unwrap_or_default()
+}
+
+fn field_index(obj: *const ObjectHeader, name: &str) -> Option<u32> {
+    let index = with_members(obj, |members| members.fields.get(name).copied()).flatten()?;
+    (index < unsafe { (*obj).field_count }).then_some(index)
+}
+
+/// Read field `name`, or call its getter; None if the class has neither
+pub unsafe fn get_member(obj: *mut ObjectHeader, name: &str) -> Option<f64> {
+    let value = match field_index(obj, name) {
+        Some(index) => js_object_get_field(obj, index),
+        None => call_class_getter(obj, name)?,
+    };
+    Some(f64::from_bits(value.bits()))
+}
+
+/// Write field `name`, or call its setter; false if the class has neither
+pub unsafe fn set_member(obj: *mut ObjectHeader, name: &str, value: f64) -> bool {
+    match field_index(obj, name) {
+        Some(index) => {
+            js_object_set_field(obj, index, JSValue::from_bits(value.to_bits()));
+            true
+        }
+        None => call_class_setter(obj, name, value),
+    }
+}
+
+/// Call method `name` with `args`, padded with undefined or cut to its declared parameters
+pub unsafe fn call_method(obj: *mut ObjectHeader, name: &str, args: &[f64]) -> Option<f64> {
+    let (func, arity) = with_members(obj, |members| members.methods.get(name).copied()).flatten()?;
+    let undefined = f64::from_bits(JSValue::undefined().bits());
+    let mut a = [undefined; MAX_METHOD_ARITY as usize];
+    for (slot, arg) in a.iter_mut().zip(args.iter().take(arity as usize)) {
+        *slot = *arg;
+    }
+    let this = obj as i64;
+    let f = func as *const ();
+    Some(match arity {
+        0 => std::mem::transmute::<_, extern "C" fn(i64) -> f64>(f)(this),
+        1 => std::mem::transmute::<_, extern "C" fn(i64, f64) -> f64>(f)(this, a[0]),
+        2 => std::mem::transmute::<_, extern "C" fn(i64, f64, f64) -> f64>(f)(this, a[0], a[1]),
+        3 => std::mem::transmute::<_, extern "C" fn(i64, f64, f64, f64) -> f64>(f)(this, a[0], a[1], a[2]),
+        4 => std::mem::transmute::<_, extern "C" fn(i64, f64, f64, f64, f64) -> f64>(f)(this, a[0], a[1], a[2], a[3]),
+        5 => std::mem::transmute::<_, extern "C" fn(i64, f64, f64, f64, f64, f64) -> f64>(f)(
+            this, a[0], a[1], a[2], a[3], a[4],
+        ),
+        6 => std::mem::transmute::<_, extern "C" fn(i64, f64, f64, f64, f64, f64, f64) -> f64>(f)(
+            this, a[0], a[1], a[2], a[3], a[4], a[5],
+        ),
+        7 => std::mem::transmute::<_, extern "C" fn(i64, f64, f64, f64, f64, f64, f64, f64) -> f64>(f)(
+            this, a[0], a[1], a[2], a[3], a[4], a[5], a[6],
+        ),
+        _ => std::mem::transmute::<_, extern "C" fn(i64, f64, f64, f64, f64, f64, f64, f64, f64) -> f64>(f)(
+            this, a[0], a[1], a[2], a[3], a[4], a[5], a[6], a[7],
+        ),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object::js_object_alloc;
+
+    extern "C" fn scaled(this: i64, factor: f64) -> f64 {
+        let x = js_object_get_field(this as *const ObjectHeader, 0).as_number();
+        x * factor
+    }
+
+    #[test]
+    fn test_members_by_name() {
+        let class_id = 9101;
+        unsafe {
+            js_register_class_field(class_id, b"x".as_ptr(), 1, 0);
+            js_register_class_field(class_id, b"y".as_ptr(), 1, 1);
+            js_register_class_method(class_id, b"scaled".as_ptr(), 6, scaled as usize as i64, 1);
+        }
+        let obj = js_object_alloc(class_id, 2);
+        unsafe {
+            assert_eq!(bridged_instance(obj as *const u8), Some(obj));
+            assert!(set_member(obj, "x", 4.0));
+            assert_eq!(get_member(obj, "x"), Some(4.0));
+            // Extra arguments are dropped
+            assert_eq!(call_method(obj, "scaled", &[2.5, 9.0]), Some(10.0));
+            assert_eq!(call_method(obj, "missing", &[]), None);
+            assert!(!set_member(obj, "z", 1.0));
+            assert_eq!(get_member(obj, "z"), None);
+        }
+        assert_eq!(member_names(obj), (vec!["x".to_string(), "y".to_string()], vec!["scaled".to_string()]));
+        assert!(unsafe { bridged_instance(js_object_alloc(9102, 0) as *const u8) }.is_none());
+    }
+}
@@ -4,6 +4,10 @@
 //! Layout:
 //!   - ClosureHeader at the start
 //!   - Followed by captured values (as f64 or i64 pointers)
+//!
+//! Closures whose function declares parameters record their arity. Calling one with
+//! fewer arguments passes `undefined` for the rest, so parameter defaults (evaluated
+//! inside the callee) apply to indirect calls and callbacks too.
 
 use std::alloc::{alloc, Layout};
 
@@ -20,6 +24,8 @@ pub struct ClosureHeader {
     pub capture_count: u32,
     /// Type tag: set to CLOSURE_MAGIC to identify closures at runtime
     pub type_tag: u32,
+    /// Number of declared parameters, or 0 if unknown (set by js_closure_set_arity)
+    pub arity: u32,
 }
 
 /// Allocate a closure with space for captured values
@@ -29,6 +35,7 @@ pub extern "C" fn js_closure_alloc(func_ptr: *const u8, capture_count: u32) -> *
     let captures_size = (capture_count as usize) * 8; // Each capture is 8 bytes (f64 or i64)
     let total_size = std::mem::size_of::<ClosureHeader>() + captures_size;
     let layout = Layout::from_size_align(total_size, 8).unwrap();
+    crate::memory::count_allocation(crate::memory::AllocKind::Closure, total_size);
 
     unsafe {
         let ptr = alloc(layout) as *mut ClosureHeader;
@@ -39,11 +46,41 @@ pub extern "C" fn js_closure_alloc(func_ptr: *const u8, capture_count: u32) -> *
         (*ptr).func_ptr = func_ptr;
         (*ptr).capture_count = capture_count;
         (*ptr).type_tag = CLOSURE_MAGIC;
+        (*ptr).arity = 0;
 
         ptr
     }
 }
 
+/// Record how many parameters the closure's function declares
+#[no_mangle]
+pub extern "C" fn js_closure_set_arity(closure: *mut ClosureHeader, arity: u32) {
+    unsafe {
+        (*closure).arity = arity;
+    }
+}
+
+fn undefined() -> f64 {
+    f64::from_bits(crate::value::JSValue::undefined().bits())
+}
+
+/// Call a closure that declares more parameters than `args` supplies, passing
+/// undefined for the missing ones
+unsafe fn call_padded(closure: *const ClosureHeader, args: &[f64]) -> f64 {
+    let mut a = [undefined(); 8];
+    a[..args.len()].copy_from_slice(args);
+    match (*closure).arity {
+        1 => js_closure_call1(closure, a[0]),
+        2 => js_closure_call2(closure, a[0], a[1]),
+        3 => js_closure_call3(closure, a[0], a[1], a[2]),
+        4 => js_closure_call4(closure, a[0], a[1], a[2], a[3]),
+        5 => js_closure_call5(closure, a[0], a[1], a[2], a[3], a[4]),
+        6 => js_closure_call6(closure, a[0], a[1], a[2], a[3], a[4], a[5]),
+        7 => js_closure_call7(closure, a[0], a[1], a[2], a[3], a[4], a[5], a[6]),
+        _ => js_closure_call8(closure, a[0], a[1], a[2], a[3], a[4], a[5], a[6], a[7]),
+    }
+}
+
 /// Get the function pointer from a closure
 #[no_mangle]
 pub extern "C" fn js_closure_get_func(closure: *const ClosureHeader) -> *const u8 {
@@ -90,6 +127,9 @@ pub extern "C" fn js_closure_set_capture_ptr(closure: *mut ClosureHeader, index:
 #[no_mangle]
 pub extern "C" fn js_closure_call0(closure: *const ClosureHeader) -> f64 {
     unsafe {
+        if (*closure).arity > 0 {
+            return call_padded(closure, &[]);
+        }
         let func: extern "C" fn(*const ClosureHeader) -> f64 = std::mem::transmute((*closure).func_ptr);
         func(closure)
     }
@@ -99,6 +139,9 @@ pub extern "C" fn js_closure_call0(closure: *const ClosureHeader) -> f64 {
 #[no_mangle]
 pub extern "C" fn js_closure_call1(closure: *const ClosureHeader, arg0: f64) -> f64 {
     unsafe {
+        if (*closure).arity > 1 {
+            return call_padded(closure, &[arg0]);
+        }
         let func: extern "C" fn(*const ClosureHeader, f64) -> f64 = std::mem::transmute((*closure).func_ptr);
         func(closure, arg0)
     }
@@ -108,6 +151,9 @@ pub extern "C" fn js_closure_call1(closure: *const ClosureHeader, arg0: f64) ->
 #[no_mangle]
 pub extern "C" fn js_closure_call2(closure: *const ClosureHeader, arg0: f64, arg1: f64) -> f64 {
     unsafe {
+        if (*closure).arity > 2 {
+            return call_padded(closure, &[arg0, arg1]);
+        }
         let func: extern "C" fn(*const ClosureHeader, f64, f64) -> f64 = std::mem::transmute((*closure).func_ptr);
         func(closure, arg0, arg1)
     }
@@ -117,6 +163,9 @@ pub extern "C" fn js_closure_call2(closure: *const ClosureHeader, arg0: f64, arg
 #[no_mangle]
 pub extern "C" fn js_closure_call3(closure: *const ClosureHeader, arg0: f64, arg1: f64, arg2: f64) -> f64 {
     unsafe {
+        if (*closure).arity > 3 {
+            return call_padded(closure, &[arg0, arg1, arg2]);
+        }
         let func: extern "C" fn(*const ClosureHeader, f64, f64, f64) -> f64 = std::mem::transmute((*closure).func_ptr);
         func(closure, arg0, arg1, arg2)
     }
@@ -126,6 +175,9 @@ pub extern "C" fn js_closure_call3(closure: *const ClosureHeader, arg0: f64, arg
 #[no_mangle]
 pub extern "C" fn js_closure_call4(closure: *const ClosureHeader, arg0: f64, arg1: f64, arg2: f64, arg3: f64) -> f64 {
     unsafe {
+        if (*closure).arity > 4 {
+            return call_padded(closure, &[arg0, arg1, arg2, arg3]);
+        }
         let func: extern "C" fn(*const ClosureHeader, f64, f64, f64, f64) -> f64 = std::mem::transmute((*closure).func_ptr);
         func(closure, arg0, arg1, arg2, arg3)
     }
@@ -135,6 +187,9 @@ pub extern "C" fn js_closure_call4(closure: *const ClosureHeader, arg0: f64, arg
 #[no_mangle]
 pub extern "C" fn js_closure_call5(closure: *const ClosureHeader, arg0: f64, arg1: f64, arg2: f64, arg3: f64, arg4: f64) -> f64 {
     unsafe {
+        if (*closure).arity > 5 {
+            return call_padded(closure, &[arg0, arg1, arg2, arg3, arg4]);
+        }
         let func: extern "C" fn(*const ClosureHeader, f64, f64, f64, f64, f64) -> f64 = std::mem::transmute((*closure).func_ptr);
         func(closure, arg0, arg1, arg2, arg3, arg4)
     }
@@ -144,6 +199,9 @@ pub extern "C" fn js_closure_call5(closure: *const ClosureHeader, arg0: f64, arg
 #[no_mangle]
 pub extern "C" fn js_closure_call6(closure: *const ClosureHeader, arg0: f64, arg1: f64, arg2: f64, arg3: f64, arg4: f64, arg5: f64) -> f64 {
     unsafe {
+        if (*closure).arity > 6 {
+            return call_padded(closure, &[arg0, arg1, arg2, arg3, arg4, arg5]);
+        }
         let func: extern "C" fn(*const ClosureHeader, f64, f64, f64, f64, f64, f64) -> f64 = std::mem::transmute((*closure).func_ptr);
         func(closure, arg0, arg1, arg2, arg3, arg4, arg5)
     }
@@ -153,6 +211,9 @@ pub extern "C" fn js_closure_call6(closure: *const ClosureHeader, arg0: f64, arg
 #[no_mangle]
 pub extern "C" fn js_closure_call7(closure: *const ClosureHeader, arg0: f64, arg1: f64, arg2: f64, arg3: f64, arg4: f64, arg5: f64, arg6: f64) -> f64 {
     unsafe {
+        if (*closure).arity > 7 {
+            return call_padded(closure, &[arg0, arg1, arg2, arg3, arg4, arg5, arg6]);
+        }
         let func: extern "C" fn(*const ClosureHeader, f64, f64, f64, f64, f64, f64, f64) -> f64 = std::mem::transmute((*closure).func_ptr);
         func(closure, arg0, arg1, arg2, arg3, arg4, arg5, arg6)
     }
@@ -298,4 +359,19 @@ mod tests {
         let result = js_closure_call0(closure);
         assert_eq!(result, 42.0);
     }
+
+    extern "C" fn second_arg(_closure: *const ClosureHeader, _a: f64, b: f64) -> f64 {
+        b
+    }
+
+    #[test]
+    fn test_missing_args_are_undefined() {
+        let closure = js_closure_alloc(second_arg as *const u8, 0);
+        js_closure_set_arity(closure, 2);
+        assert_eq!(js_closure_call1(closure, 1.0).to_bits(), undefined().to_bits());
+        assert_eq!(js_closure_call2(closure, 1.0, 5.0), 5.0);
+        let args = [1.0];
+        let result = unsafe { js_native_call_value(f64::from_bits(closure as u64), args.as_ptr(), 1) };
+        assert_eq!(result.to_bits(), undefined().to_bits());
+    }
 }
@@ -0,0 +1,55 @@
+//! CPU feature detection for multiversioned functions
+//!
+//! A module compiled with `// @perry-opt: multiversion` has two versions of each numeric
+//! function: one for the target's baseline and one for x86-64-v3 CPUs (AVX2, FMA, BMI1/2,
+//! LZCNT and the SSE4 rounding instructions). Calls go through a per-function slot that
+//! starts out at the baseline version; the module's init asks `js_cpu_has_x86_64_v3()` once
+//! and points the slots at the newer version when the CPU has the features.
+//!
+//! `PERRY_CPU=baseline` keeps the baseline versions, to compare or rule them out.
+
+use std::sync::OnceLock;
+
+/// Environment variable that forces the baseline versions
+const CPU_ENV: &str = "PERRY_CPU";
+
+/// Whether the x86-64-v3 versions may run: 1 when the CPU has their features
+#[no_mangle]
+pub extern "C" fn js_cpu_has_x86_64_v3() -> i32 {
+    static ENABLED: OnceLock<bool> = OnceLock::new();
+    *ENABLED.get_or_init(|| variants_enabled(std::env::var(CPU_ENV).ok().as_deref(), detect_x86_64_v3())) as i32
+}
+
+fn variants_enabled(setting: Option<&str>, detected: bool) -> bool {
+    detected && setting != Some("baseline")
+}
+
+#[cfg(target_arch = "x86_64")]
+fn detect_x86_64_v3() -> bool {
+    is_x86_feature_detected!("sse4.1")
+        && is_x86_feature_detected!("sse4.2")
+        && is_x86_feature_detected!("popcnt")
+        && is_x86_feature_detected!("avx")
+        && is_x86_feature_detected!("avx2")
+        && is_x86_feature_detected!("fma")
+        && is_x86_feature_detected!("bmi1")
+        && is_x86_feature_detected!("bmi2")
+        && is_x86_feature_detected!("lzcnt")
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+fn detect_x86_64_v3() -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_variants_enabled() {
+        assert!(variants_enabled(None, true));
+        assert!(!variants_enabled(Some("baseline"), true));
+        assert!(!variants_enabled(None, false));
+    }
+}
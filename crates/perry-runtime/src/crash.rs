@@ -0,0 +1,361 @@
+//! Crash reporting for compiled Perry programs
+//!
+//! When a program is compiled with `--crash-report`, the generated `main` calls
+//! `js_crash_install()` and every compiled function calls `js_crash_frame_enter()`
+//! in its prologue. On a fatal signal (SIGSEGV, SIGBUS, SIGILL, SIGFPE, SIGABRT) or
+//! a Rust panic inside the runtime we write a crash report containing:
+//! - the signal / panic message
+//! - the TypeScript-level shadow stack (function names)
+//! - a native backtrace, with the symbols of compiled functions demangled (see `symbols`)
+//!
+//! The report is printed to stderr, written to `$PERRY_CRASH_DIR` (defaults to the
+//! system temp dir) as `perry-crash-<pid>-<timestamp>.txt`, and optionally POSTed
+//! to `$PERRY_CRASH_ENDPOINT` (plain `http://host[:port]/path` only).
+//!
+//! The shadow stack uses "lazy pop": functions only push on entry. Each frame records
+//! the stack address at entry, and any recorded frame that is not deeper than the
+//! current one is dropped on the next push. This keeps returns and longjmp-based
+//! exception unwinding free of bookkeeping, at the cost of the innermost frames
+//! possibly including a function that had already returned when the crash happened.
+//!
+//! On Windows the signals are replaced by an unhandled-exception filter, which reports
+//! the exception code and lets the default handling terminate the process.
+//!
+//! Reporting from a signal handler is best-effort: it allocates and formats, which is
+//! not async-signal-safe, but the process is going down anyway.
+
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// Maximum number of TS frames tracked
+const MAX_FRAMES: usize = 256;
+
+// Size of the alternate signal stack (handles stack-overflow SIGSEGVs)
+#[cfg(unix)]
+const ALT_STACK_SIZE: usize = 64 * 1024;
+
+#[derive(Copy, Clone)]
+struct ShadowFrame {
+    name_ptr: *const u8,
+    name_len: usize,
+    stack_addr: usize,
+}
+
+const EMPTY_FRAME: ShadowFrame = ShadowFrame {
+    name_ptr: std::ptr::null(),
+    name_len: 0,
+    stack_addr: 0,
+};
+
+// Shadow stack storage (main thread only, like the exception handler state)
+static mut FRAMES: [ShadowFrame; MAX_FRAMES] = [EMPTY_FRAME; MAX_FRAMES];
+static mut FRAME_DEPTH: usize = 0;
+// Frames beyond MAX_FRAMES are counted but not recorded
+static mut DROPPED_FRAMES: usize = 0;
+
+static INSTALLED: AtomicBool = AtomicBool::new(false);
+static REPORTING: AtomicBool = AtomicBool::new(false);
+
+#[cfg(unix)]
+const HANDLED_SIGNALS: [libc::c_int; 5] = [
+    libc::SIGSEGV,
+    libc::SIGBUS,
+    libc::SIGILL,
+    libc::SIGFPE,
+    libc::SIGABRT,
+];
+
+/// Record entry into a compiled TS function.
+/// `name_ptr`/`name_len` point to static UTF-8 data emitted by codegen.
+#[no_mangle]
+pub extern "C" fn js_crash_frame_enter(name_ptr: *const u8, name_len: i64) {
+    let marker = 0u8;
+    let stack_addr = &marker as *const u8 as usize;
+    unsafe {
+        // Pop frames that are not deeper than us - they have already returned
+        while FRAME_DEPTH > 0 && FRAMES[FRAME_DEPTH - 1].stack_addr <= stack_addr {
+            FRAME_DEPTH -= 1;
+        }
+        if FRAME_DEPTH == 0 {
+            DROPPED_FRAMES = 0;
+        }
+        if FRAME_DEPTH < MAX_FRAMES {
+            FRAMES[FRAME_DEPTH] = ShadowFrame {
+                name_ptr,
+                name_len: name_len.max(0) as usize,
+                stack_addr,
+            };
+            FRAME_DEPTH += 1;
+        } else {
+            DROPPED_FRAMES += 1;
+        }
+    }
+}
+
+/// Install signal handlers and the panic hook. Safe to call more than once.
+#[no_mangle]
+pub extern "C" fn js_crash_install() {
+    if INSTALLED.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    let previous_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let message = if let Some(s) = info.payload().downcast_ref::<&str>() {
+            s.to_string()
+        } else if let Some(s) = info.payload().downcast_ref::<String>() {
+            s.clone()
+        } else {
+            "Box<dyn Any>".to_string()
+        };
+        let location = info
+            .location()
+            .map(|l| format!(" at {}:{}", l.file(), l.line()))
+            .unwrap_or_default();
+        report_crash(&format!("panic: {}{}", message, location));
+        previous_hook(info);
+    }));
+
+    install_fault_handlers();
+}
+
+#[cfg(unix)]
+fn install_fault_handlers() {
+    unsafe {
+        // Alternate stack so we can still report stack overflows
+        let stack = Box::leak(vec![0u8; ALT_STACK_SIZE].into_boxed_slice());
+        let ss = libc::stack_t {
+            ss_sp: stack.as_mut_ptr() as *mut libc::c_void,
+            ss_flags: 0,
+            ss_size: ALT_STACK_SIZE,
+        };
+        libc::sigaltstack(&ss, std::ptr::null_mut());
+
+        for &sig in HANDLED_SIGNALS.iter() {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = crash_signal_handler as *const () as usize;
+            action.sa_flags = libc::SA_SIGINFO | libc::SA_ONSTACK;
+            libc::sigemptyset(&mut action.sa_mask);
+            libc::sigaction(sig, &action, std::ptr::null_mut());
+        }
+    }
+}
+
+#[cfg(windows)]
+fn install_fault_handlers() {
+    unsafe {
+        SetUnhandledExceptionFilter(Some(crash_exception_filter));
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+fn install_fault_handlers() {}
+
+#[cfg(windows)]
+#[repr(C)]
+struct ExceptionPointers {
+    // EXCEPTION_RECORD, whose first field is the exception code
+    record: *const u32,
+    context: *mut std::ffi::c_void,
+}
+
+#[cfg(windows)]
+type ExceptionFilter = unsafe extern "system" fn(*const ExceptionPointers) -> i32;
+
+#[cfg(windows)]
+extern "system" {
+    fn SetUnhandledExceptionFilter(filter: Option<ExceptionFilter>) -> Option<ExceptionFilter>;
+}
+
+#[cfg(windows)]
+unsafe extern "system" fn crash_exception_filter(info: *const ExceptionPointers) -> i32 {
+    let code = if info.is_null() || (*info).record.is_null() { 0 } else { *(*info).record };
+    report_crash(&format!("exception 0x{:08X} ({})", code, exception_name(code)));
+    // EXCEPTION_CONTINUE_SEARCH: the default handling terminates the process
+    0
+}
+
+#[cfg(windows)]
+fn exception_name(code: u32) -> &'static str {
+    match code {
+        0xC000_0005 => "EXCEPTION_ACCESS_VIOLATION",
+        0xC000_001D => "EXCEPTION_ILLEGAL_INSTRUCTION",
+        0xC000_0094 => "EXCEPTION_INT_DIVIDE_BY_ZERO",
+        0xC000_00FD => "EXCEPTION_STACK_OVERFLOW",
+        0xC000_0409 => "STATUS_STACK_BUFFER_OVERRUN",
+        _ => "unknown",
+    }
+}
+
+#[cfg(unix)]
+extern "C" fn crash_signal_handler(sig: libc::c_int, _info: *mut libc::siginfo_t, _ctx: *mut libc::c_void) {
+    report_crash(&format!("signal {} ({})", sig, signal_name(sig)));
+    unsafe {
+        // Restore the default action and re-raise so the exit status is preserved
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = libc::SIG_DFL;
+        libc::sigemptyset(&mut action.sa_mask);
+        libc::sigaction(sig, &action, std::ptr::null_mut());
+        libc::raise(sig);
+    }
+}
+
+#[cfg(unix)]
+fn signal_name(sig: libc::c_int) -> &'static str {
+    match sig {
+        libc::SIGSEGV => "SIGSEGV",
+        libc::SIGBUS => "SIGBUS",
+        libc::SIGILL => "SIGILL",
+        libc::SIGFPE => "SIGFPE",
+        libc::SIGABRT => "SIGABRT",
+        _ => "unknown",
+    }
+}
+
+/// Snapshot the TS shadow stack, innermost frame first.
+pub(crate) fn shadow_stack() -> Vec<String> {
+    let mut frames = Vec::new();
+    unsafe {
+        for i in (0..FRAME_DEPTH).rev() {
+            let frame = FRAMES[i];
+            if frame.name_ptr.is_null() {
+                frames.push("<anonymous>".to_string());
+                continue;
+            }
+            let bytes = std::slice::from_raw_parts(frame.name_ptr, frame.name_len);
+            frames.push(String::from_utf8_lossy(bytes).into_owned());
+        }
+        let dropped = DROPPED_FRAMES;
+        if dropped > 0 {
+            frames.insert(0, format!("... {} deeper frames not recorded", dropped));
+        }
+    }
+    frames
+}
+
+fn format_report(reason: &str, ts_frames: &[String], native_backtrace: &str) -> String {
+    let mut report = String::new();
+    report.push_str("=== Perry crash report ===\n");
+    report.push_str(&format!("version: {}\n", env!("CARGO_PKG_VERSION")));
+    report.push_str(&format!("pid: {}\n", std::process::id()));
+    let args: Vec<String> = std::env::args().collect();
+    report.push_str(&format!("command: {}\n", args.join(" ")));
+    report.push_str(&format!("reason: {}\n", reason));
+    report.push_str("\nTypeScript stack (innermost first):\n");
+    if ts_frames.is_empty() {
+        report.push_str("    <empty>\n");
+    }
+    for frame in ts_frames {
+        report.push_str(&format!("    at {}\n", frame));
+    }
+    report.push_str("\nNative backtrace:\n");
+    report.push_str(native_backtrace);
+    if !native_backtrace.ends_with('\n') {
+        report.push('\n');
+    }
+    report
+}
+
+/// Write a crash report without installing handlers (used by the OOM abort policy)
+pub(crate) fn report(reason: &str) {
+    report_crash(reason);
+}
+
+fn report_crash(reason: &str) {
+    // Guard against crashing again while reporting
+    if REPORTING.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    let backtrace = crate::symbols::demangle_text(&std::backtrace::Backtrace::force_capture().to_string());
+    let report = format_report(reason, &shadow_stack(), &backtrace);
+
+    let _ = std::io::stderr().write_all(report.as_bytes());
+
+    let dir = std::env::var("PERRY_CRASH_DIR")
+        .map(std::path::PathBuf::from)
+        .unwrap_or_else(|_| std::env::temp_dir());
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let path = dir.join(format!("perry-crash-{}-{}.txt", std::process::id(), timestamp));
+    if std::fs::write(&path, &report).is_ok() {
+        let _ = writeln!(std::io::stderr(), "Crash report written to {}", path.display());
+    }
+
+    if let Ok(endpoint) = std::env::var("PERRY_CRASH_ENDPOINT") {
+        if let Err(e) = post_report(&endpoint, &report) {
+            let _ = writeln!(std::io::stderr(), "Failed to upload crash report: {}", e);
+        }
+    }
+}
+
+/// Split `http://host[:port]/path` into (host, port, path).
+fn parse_http_endpoint(endpoint: &str) -> Option<(String, u16, String)> {
+    let rest = endpoint.strip_prefix("http://")?;
+    let (authority, path) = match rest.find('/') {
+        Some(idx) => (&rest[..idx], &rest[idx..]),
+        None => (rest, "/"),
+    };
+    if authority.is_empty() {
+        return None;
+    }
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((h, p)) => (h, p.parse().ok()?),
+        None => (authority, 80),
+    };
+    Some((host.to_string(), port, path.to_string()))
+}
+
+fn post_report(endpoint: &str, report: &str) -> std::io::Result<()> {
+    use std::net::ToSocketAddrs;
+
+    let (host, port, path) = parse_http_endpoint(endpoint).ok_or_else(|| {
+        std::io::Error::new(std::io::ErrorKind::InvalidInput, "only http://host[:port]/path endpoints are supported")
+    })?;
+    let addr = (host.as_str(), port)
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, "endpoint did not resolve"))?;
+    let timeout = std::time::Duration::from_secs(5);
+    let mut stream = std::net::TcpStream::connect_timeout(&addr, timeout)?;
+    stream.set_write_timeout(Some(timeout))?;
+    write!(
+        stream,
+        "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        path,
+        host,
+        report.len()
+    )?;
+    stream.write_all(report.as_bytes())?;
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_http_endpoint() {
+        assert_eq!(
+            parse_http_endpoint("http://localhost:8080/crashes"),
+            Some(("localhost".to_string(), 8080, "/crashes".to_string()))
+        );
+        assert_eq!(
+            parse_http_endpoint("http://example.com"),
+            Some(("example.com".to_string(), 80, "/".to_string()))
+        );
+        assert_eq!(parse_http_endpoint("https://example.com/x"), None);
+        assert_eq!(parse_http_endpoint("http://host:notaport/"), None);
+    }
+
+    #[test]
+    fn test_format_report() {
+        let frames = vec!["inner".to_string(), "outer".to_string()];
+        let report = format_report("signal 11 (SIGSEGV)", &frames, "0: main");
+        assert!(report.contains("reason: signal 11 (SIGSEGV)"));
+        assert!(report.contains("    at inner\n    at outer\n"));
+        assert!(report.ends_with("0: main\n"));
+    }
+}
@@ -0,0 +1,239 @@
+//! Explicit resource management: `using` declarations and `DisposableStack`
+//!
+//! `Symbol.dispose` / `Symbol.asyncDispose` are lowered to the property keys
+//! `DISPOSE_KEY` / `ASYNC_DISPOSE_KEY`. Class instances with a statically known type
+//! are disposed by a direct method call from codegen; everything else (object literals,
+//! values of unknown type) goes through `js_dispose_resource`, which looks the method
+//! up among the object's named fields.
+//!
+//! A `DisposableStack` is an object with class ID `DISPOSABLE_STACK_CLASS_ID` and two
+//! fields: an array of `(value, onDispose)` pairs and the `disposed` flag. Entries run
+//! in reverse order of registration; `defer` callbacks are stored with an undefined value
+//! and called without arguments.
+
+use crate::array::{js_array_alloc, js_array_get_f64, js_array_length, js_array_push_f64, ArrayHeader};
+use crate::object::{js_object_alloc, js_object_get_field, js_object_set_field, ObjectHeader};
+use crate::string::js_string_from_bytes;
+use crate::value::JSValue;
+
+/// Property key that `[Symbol.dispose]` lowers to
+pub const DISPOSE_KEY: &str = "__symbol_dispose";
+/// Property key that `[Symbol.asyncDispose]` lowers to
+pub const ASYNC_DISPOSE_KEY: &str = "__symbol_asyncDispose";
+
+/// Special class ID for DisposableStack objects
+pub const DISPOSABLE_STACK_CLASS_ID: u32 = 0xFFFFFFFD;
+
+const FIELD_ENTRIES: u32 = 0;
+const FIELD_DISPOSED: u32 = 1;
+
+fn undefined() -> f64 {
+    f64::from_bits(JSValue::undefined().bits())
+}
+
+fn throw_error(name: &str, message: &str) -> ! {
+    let msg = js_string_from_bytes(message.as_ptr(), message.len() as u32);
+    let error = crate::error::js_error_new_with_message(msg);
+    unsafe {
+        (*error).name = js_string_from_bytes(name.as_ptr(), name.len() as u32);
+    }
+    crate::exception::js_throw(f64::from_bits(JSValue::pointer(error as *const u8).bits()))
+}
+
+/// Get the stack object behind a NaN-boxed value, or null if it is not a DisposableStack
+unsafe fn as_stack(value: f64) -> *mut ObjectHeader {
+    let jsval = JSValue::from_bits(value.to_bits());
+    if !jsval.is_pointer() {
+        return std::ptr::null_mut();
+    }
+    let obj = jsval.as_pointer::<ObjectHeader>() as *mut ObjectHeader;
+    if obj.is_null() || (*obj).object_type != crate::error::OBJECT_TYPE_REGULAR || (*obj).class_id != DISPOSABLE_STACK_CLASS_ID {
+        return std::ptr::null_mut();
+    }
+    obj
+}
+
+unsafe fn expect_stack(value: f64) -> *mut ObjectHeader {
+    let stack = as_stack(value);
+    if stack.is_null() {
+        throw_error("TypeError", "Receiver is not a DisposableStack");
+    }
+    stack
+}
+
+unsafe fn is_disposed(stack: *mut ObjectHeader) -> bool {
+    js_object_get_field(stack, FIELD_DISPOSED).to_bool()
+}
+
+unsafe fn expect_live(stack: *mut ObjectHeader) {
+    if is_disposed(stack) {
+        throw_error("ReferenceError", "DisposableStack already disposed");
+    }
+}
+
+unsafe fn entries(stack: *mut ObjectHeader) -> *mut ArrayHeader {
+    js_object_get_field(stack, FIELD_ENTRIES).as_pointer::<ArrayHeader>() as *mut ArrayHeader
+}
+
+unsafe fn push_entry(stack: *mut ObjectHeader, value: f64, on_dispose: f64) {
+    let mut arr = entries(stack);
+    arr = js_array_push_f64(arr, value);
+    arr = js_array_push_f64(arr, on_dispose);
+    js_object_set_field(stack, FIELD_ENTRIES, JSValue::array_ptr(arr));
+}
+
+fn alloc_stack(entries: *mut ArrayHeader) -> f64 {
+    let obj = js_object_alloc(DISPOSABLE_STACK_CLASS_ID, 2);
+    js_object_set_field(obj, FIELD_ENTRIES, JSValue::array_ptr(entries));
+    js_object_set_field(obj, FIELD_DISPOSED, JSValue::bool(false));
+    f64::from_bits(JSValue::pointer(obj as *const u8).bits())
+}
+
+/// Look up a closure-valued field by name on an object literal.
+/// Object literals store closures either NaN-boxed or as raw pointer bits.
+unsafe fn find_method(obj: *const ObjectHeader, key: &str) -> Option<f64> {
+    let key = js_string_from_bytes(key.as_ptr(), key.len() as u32);
+    let field = crate::object::js_object_get_field_by_name(obj, key);
+    let is_raw_pointer = field.bits() != 0 && field.bits() >> 48 == 0;
+    if field.is_pointer() || is_raw_pointer {
+        Some(f64::from_bits(field.bits()))
+    } else {
+        None
+    }
+}
+
+/// Dispose a resource whose type was not known at compile time.
+/// `null`/`undefined` are skipped; `is_async` prefers `[Symbol.asyncDispose]` and falls
+/// back to `[Symbol.dispose]`. Returns the disposer's result (a promise for async disposers).
+#[no_mangle]
+pub unsafe extern "C" fn js_dispose_resource(value: f64, is_async: i32) -> f64 {
+    let jsval = JSValue::from_bits(value.to_bits());
+    if jsval.is_undefined() || jsval.is_null() {
+        return undefined();
+    }
+
+    let stack = as_stack(value);
+    if !stack.is_null() {
+        return js_disposable_stack_dispose(value);
+    }
+
+    if jsval.is_pointer() && !jsval.is_string() {
+        let obj = jsval.as_pointer::<ObjectHeader>();
+        let method = if is_async != 0 {
+            find_method(obj, ASYNC_DISPOSE_KEY).or_else(|| find_method(obj, DISPOSE_KEY))
+        } else {
+            find_method(obj, DISPOSE_KEY)
+        };
+        if let Some(method) = method {
+            return crate::closure::js_native_call_value(method, std::ptr::null(), 0);
+        }
+    }
+
+    throw_error("TypeError", "Value is not disposable: missing [Symbol.dispose]()")
+}
+
+/// new DisposableStack()
+#[no_mangle]
+pub extern "C" fn js_disposable_stack_new() -> f64 {
+    alloc_stack(js_array_alloc(0))
+}
+
+/// stack.adopt(value, onDispose) -> value
+/// `stack.use(value)` is lowered to `adopt` with a disposer closure built at compile time.
+#[no_mangle]
+pub unsafe extern "C" fn js_disposable_stack_adopt(stack: f64, value: f64, on_dispose: f64) -> f64 {
+    let stack = expect_stack(stack);
+    expect_live(stack);
+    push_entry(stack, value, on_dispose);
+    value
+}
+
+/// stack.defer(onDispose)
+#[no_mangle]
+pub unsafe extern "C" fn js_disposable_stack_defer(stack: f64, on_dispose: f64) -> f64 {
+    let stack = expect_stack(stack);
+    expect_live(stack);
+    push_entry(stack, undefined(), on_dispose);
+    undefined()
+}
+
+/// stack.dispose() - runs all registered disposers in reverse order; no-op if already disposed
+#[no_mangle]
+pub unsafe extern "C" fn js_disposable_stack_dispose(stack: f64) -> f64 {
+    let stack = expect_stack(stack);
+    if is_disposed(stack) {
+        return undefined();
+    }
+    // Mark disposed and detach the entries first so a throwing disposer leaves the stack consistent
+    js_object_set_field(stack, FIELD_DISPOSED, JSValue::bool(true));
+    let arr = entries(stack);
+    js_object_set_field(stack, FIELD_ENTRIES, JSValue::array_ptr(js_array_alloc(0)));
+
+    let mut i = js_array_length(arr);
+    while i >= 2 {
+        i -= 2;
+        let value = js_array_get_f64(arr, i);
+        let on_dispose = js_array_get_f64(arr, i + 1);
+        if JSValue::from_bits(value.to_bits()).is_undefined() {
+            crate::closure::js_native_call_value(on_dispose, std::ptr::null(), 0);
+        } else {
+            let args = [value];
+            crate::closure::js_native_call_value(on_dispose, args.as_ptr(), 1);
+        }
+    }
+    undefined()
+}
+
+/// stack.move() - transfers all entries to a new stack and marks this one disposed
+#[no_mangle]
+pub unsafe extern "C" fn js_disposable_stack_move(stack: f64) -> f64 {
+    let stack = expect_stack(stack);
+    expect_live(stack);
+    let moved = alloc_stack(entries(stack));
+    js_object_set_field(stack, FIELD_ENTRIES, JSValue::array_ptr(js_array_alloc(0)));
+    js_object_set_field(stack, FIELD_DISPOSED, JSValue::bool(true));
+    moved
+}
+
+/// stack.disposed -> boolean
+#[no_mangle]
+pub unsafe extern "C" fn js_disposable_stack_disposed(stack: f64) -> f64 {
+    let stack = expect_stack(stack);
+    f64::from_bits(JSValue::bool(is_disposed(stack)).bits())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_move_transfers_entries() {
+        unsafe {
+            let stack = js_disposable_stack_new();
+            js_disposable_stack_adopt(stack, 1.0, undefined());
+            let moved = js_disposable_stack_move(stack);
+            assert!(JSValue::from_bits(js_disposable_stack_disposed(stack).to_bits()).as_bool());
+            assert!(!JSValue::from_bits(js_disposable_stack_disposed(moved).to_bits()).as_bool());
+            assert_eq!(js_array_length(entries(as_stack(moved))), 2);
+            assert_eq!(js_array_length(entries(as_stack(stack))), 0);
+        }
+    }
+
+    #[test]
+    fn test_dispose_resource_skips_nullish() {
+        unsafe {
+            let undef = js_dispose_resource(undefined(), 0);
+            assert!(JSValue::from_bits(undef.to_bits()).is_undefined());
+            let null = f64::from_bits(JSValue::null().bits());
+            assert!(JSValue::from_bits(js_dispose_resource(null, 1).to_bits()).is_undefined());
+        }
+    }
+
+    #[test]
+    fn test_non_stack_is_rejected() {
+        unsafe {
+            assert!(as_stack(42.0).is_null());
+            assert!(!as_stack(js_disposable_stack_new()).is_null());
+        }
+    }
+}
@@ -2,13 +2,21 @@
 //!
 //! Provides the built-in Error class and its subclasses.
 
-use crate::string::{js_string_from_bytes, StringHeader};
+use crate::string::{js_string_from_bytes, string_as_str, StringHeader};
 use std::alloc::{alloc, Layout};
 
 /// Object type tag for runtime type discrimination
 pub const OBJECT_TYPE_REGULAR: u32 = 1;
 pub const OBJECT_TYPE_ERROR: u32 = 2;
 
+/// Check whether a pointer of unknown kind points at an Error object.
+/// Arrays share the first word with `object_type` (their length), so a 2-element array
+/// would match the tag alone; an Error's padding word is 0 where an array's capacity isn't.
+pub(crate) unsafe fn is_error_object(ptr: *const u8) -> bool {
+    let header = ptr as *const ErrorHeader;
+    (*header).object_type == OBJECT_TYPE_ERROR && (*header)._padding == 0
+}
+
 /// Error object header
 #[repr(C)]
 pub struct ErrorHeader {
@@ -98,6 +106,26 @@ pub extern "C" fn js_error_get_name(error: *mut ErrorHeader) -> *mut StringHeade
     }
 }
 
+/// Fill in the stack of a new Error with where it was created:
+/// `Error: message\n    at path:line:col`
+#[no_mangle]
+pub extern "C" fn js_error_set_location(error: *mut ErrorHeader, location: *mut StringHeader) {
+    if error.is_null() || location.is_null() {
+        return;
+    }
+    unsafe {
+        let name = string_as_str((*error).name);
+        let message = string_as_str((*error).message);
+        let location = string_as_str(location);
+        let stack = if message.is_empty() {
+            format!("{}\n    at {}", name, location)
+        } else {
+            format!("{}: {}\n    at {}", name, message, location)
+        };
+        (*error).stack = js_string_from_bytes(stack.as_ptr(), stack.len() as u32);
+    }
+}
+
 /// Get the stack property of an Error
 #[no_mangle]
 pub extern "C" fn js_error_get_stack(error: *mut ErrorHeader) -> *mut StringHeader {
@@ -3,11 +3,18 @@
 //! Uses setjmp/longjmp for exception unwinding.
 //! The key insight is that setjmp must be called directly from the generated code,
 //! not from inside a Rust function (because the stack frame would be invalid when longjmp returns).
+//! On Windows the generated code calls `_setjmp(buf, NULL)`: without a frame to unwind to,
+//! the CRT's longjmp restores the registers directly instead of running SEH unwinding.
+
+use crate::error::{is_error_object, js_error_get_message, js_error_get_name, js_error_get_stack, ErrorHeader};
+use crate::string::string_as_str;
+use crate::value::{js_jsvalue_to_string, JSValue};
 
 // Platform-specific jmp_buf size (in i32 units)
 // macOS ARM64: _JBLEN = 48 (48 * 4 = 192 bytes)
 // macOS x86_64: _JBLEN = 37 (37 * 4 = 148 bytes, but aligned to 156)
 // Linux x86_64: __jmp_buf is 8 * i64 = 64 bytes
+// Windows x64: _JBLEN = 16 SETJMP_FLOAT128 = 256 bytes
 // We use a conservative size that works for all
 const JMP_BUF_SIZE: usize = 64; // 64 * i32 = 256 bytes, enough for any platform
 
@@ -67,6 +74,29 @@ pub extern "C" fn js_try_end() {
     }
 }
 
+/// How an uncaught exception is reported: an Error's stack, which says where it was
+/// created, or `name: message` when it has none; any other value as a string
+fn describe_exception(value: f64) -> String {
+    let bits = value.to_bits();
+    if JSValue::from_bits(bits).is_pointer() {
+        let ptr = (bits & 0x0000_FFFF_FFFF_FFFF) as *mut ErrorHeader;
+        if !ptr.is_null() && unsafe { is_error_object(ptr as *const u8) } {
+            let stack = string_as_str(js_error_get_stack(ptr));
+            if !stack.is_empty() {
+                return stack.to_string();
+            }
+            let name = string_as_str(js_error_get_name(ptr));
+            let message = string_as_str(js_error_get_message(ptr));
+            return if message.is_empty() { name.to_string() } else { format!("{}: {}", name, message) };
+        }
+    }
+    let text = js_jsvalue_to_string(value);
+    if text.is_null() {
+        return value.to_string();
+    }
+    string_as_str(text).to_string()
+}
+
 /// Throw an exception with the given value
 #[no_mangle]
 pub extern "C" fn js_throw(value: f64) -> ! {
@@ -79,7 +109,7 @@ pub extern "C" fn js_throw(value: f64) -> ! {
         }
 
         if TRY_DEPTH == 0 {
-            panic!("Uncaught exception: {}", value);
+            panic!("Uncaught exception: {}", describe_exception(value));
         }
 
         // Jump to the most recent try block
@@ -3,7 +3,9 @@
 use std::fs;
 use std::path::Path;
 
+use crate::permissions::{self, Capability};
 use crate::string::{js_string_from_bytes, StringHeader};
+use crate::value::JSValue;
 
 /// Read a file synchronously and return its contents as a string
 /// Returns null pointer on error
@@ -23,6 +25,8 @@ pub extern "C" fn js_fs_read_file_sync(path_ptr: *const StringHeader) -> *mut St
             Err(_) => return std::ptr::null_mut(),
         };
 
+        permissions::check(Capability::Fs, path_str);
+
         match fs::read_to_string(path_str) {
             Ok(content) => {
                 let bytes = content.as_bytes();
@@ -53,6 +57,7 @@ pub extern "C" fn js_fs_write_file_sync(
             Ok(s) => s,
             Err(_) => return 0,
         };
+        permissions::check(Capability::Fs, path_str);
 
         // Get content string
         let content_len = (*content_ptr).length as usize;
@@ -89,6 +94,7 @@ pub extern "C" fn js_fs_append_file_sync(
             Ok(s) => s,
             Err(_) => return 0,
         };
+        permissions::check(Capability::Fs, path_str);
 
         // Get content string
         let content_len = (*content_ptr).length as usize;
@@ -126,6 +132,8 @@ pub extern "C" fn js_fs_exists_sync(path_ptr: *const StringHeader) -> i32 {
             Err(_) => return 0,
         };
 
+        permissions::check(Capability::Fs, path_str);
+
         if Path::new(path_str).exists() { 1 } else { 0 }
     }
 }
@@ -148,6 +156,8 @@ pub extern "C" fn js_fs_mkdir_sync(path_ptr: *const StringHeader) -> i32 {
             Err(_) => return 0,
         };
 
+        permissions::check(Capability::Fs, path_str);
+
         match fs::create_dir_all(path_str) {
             Ok(_) => 1,
             Err(_) => 0,
@@ -173,9 +183,74 @@ pub extern "C" fn js_fs_unlink_sync(path_ptr: *const StringHeader) -> i32 {
             Err(_) => return 0,
         };
 
+        permissions::check(Capability::Fs, path_str);
+
         match fs::remove_file(path_str) {
             Ok(_) => 1,
             Err(_) => 0,
         }
     }
 }
+
+lazy_static::lazy_static! {
+    static ref READ_STREAMS: std::sync::Mutex<Vec<String>> = std::sync::Mutex::new(Vec::new());
+}
+
+/// Create a lazy read stream over a file
+/// fs.createReadStream(path) -> ReadStream
+///
+/// The file is not opened here: the stream records the path and consumers such as
+/// fetch uploads open it and read it in chunks. Returns a handle NaN-boxed with
+/// POINTER_TAG, or undefined if the path is invalid.
+#[no_mangle]
+pub extern "C" fn js_fs_create_read_stream(path_ptr: *const StringHeader) -> f64 {
+    unsafe {
+        if path_ptr.is_null() {
+            return f64::from_bits(JSValue::undefined().bits());
+        }
+
+        let len = (*path_ptr).length as usize;
+        let data_ptr = (path_ptr as *const u8).add(std::mem::size_of::<StringHeader>());
+        let path_bytes = std::slice::from_raw_parts(data_ptr, len);
+
+        let path_str = match std::str::from_utf8(path_bytes) {
+            Ok(s) => s,
+            Err(_) => return f64::from_bits(JSValue::undefined().bits()),
+        };
+
+        permissions::check(Capability::Fs, path_str);
+
+        let mut streams = READ_STREAMS.lock().unwrap();
+        streams.push(path_str.to_string());
+        f64::from_bits(JSValue::pointer(streams.len() as *const u8).bits())
+    }
+}
+
+/// Return the file path behind a value created by `js_fs_create_read_stream`,
+/// or None if the value is not a read stream
+pub fn read_stream_path(value: f64) -> Option<String> {
+    let value = JSValue::from_bits(value.to_bits());
+    if !value.is_pointer() {
+        return None;
+    }
+    let handle = value.as_pointer::<u8>() as usize;
+    if handle == 0 {
+        return None;
+    }
+    READ_STREAMS.lock().unwrap().get(handle - 1).cloned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_stream_handle_round_trip() {
+        let path = "/tmp/perry-upload.bin";
+        let path_ptr = js_string_from_bytes(path.as_ptr(), path.len() as u32);
+        let stream = js_fs_create_read_stream(path_ptr);
+        assert_eq!(read_stream_path(stream).as_deref(), Some(path));
+        assert_eq!(read_stream_path(42.0), None);
+        assert_eq!(read_stream_path(f64::from_bits(JSValue::undefined().bits())), None);
+    }
+}
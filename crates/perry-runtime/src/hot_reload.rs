@@ -0,0 +1,266 @@
+//! Hot reload agent for `perry dev`
+//!
+//! Executables built with `perry compile --hot-reload` call `js_hot_reload_start()` from
+//! main. When PERRY_HOT_RELOAD names a socket path, a thread listens on it for patches
+//! from `perry dev`, one request per connection:
+//!
+//! ```text
+//! request = <path of the patch shared object> ("\t" <function symbol>)* "\n"
+//! reply   = "ok " <functions swapped> "\n" | "error " <message> "\n"
+//! ```
+//!
+//! The patch is the recompiled object code of the edited modules, linked into a shared
+//! object. Hot-reload builds give every function and module variable default-visibility
+//! symbols that the executable exports, so the patch's references to module state bind
+//! to the running program's copies: variables, open sockets and everything reachable from
+//! them are kept. Each listed function of the executable gets its entry overwritten with
+//! a jump to the patch's version, so existing callers, closures and registered handlers
+//! run the new code from their next call on.
+//!
+//! Entries are rewritten on the main thread between tasks of the event loop
+//! (`js_promise_run_microtasks`), so no entry is being executed while it is overwritten;
+//! frames already on the stack finish in the old code. Patches stay loaded for the same
+//! reason. `perry dev` falls back to a restart whenever a patch can't be applied: the
+//! program isn't at a safe point within `APPLY_TIMEOUT`, a symbol is missing, or the
+//! platform refuses writable code pages (macOS on Apple silicon).
+
+#![cfg_attr(not(unix), allow(dead_code))]
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::time::Duration;
+
+/// Environment variable holding the socket path `perry dev` sends patches to
+pub const SOCKET_ENV: &str = "PERRY_HOT_RELOAD";
+
+/// How long a received patch waits for the main thread to reach a safe point
+const APPLY_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Bytes of the jump written over a function's entry
+#[cfg(target_arch = "x86_64")]
+const JUMP_LEN: usize = 13;
+#[cfg(target_arch = "aarch64")]
+const JUMP_LEN: usize = 16;
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+const JUMP_LEN: usize = 0;
+
+/// Entry addresses to redirect: (old function, its replacement)
+type Patch = Vec<(usize, usize)>;
+
+/// A patch waiting for the main thread, then the outcome of applying it
+struct Handoff {
+    patch: Option<Patch>,
+    result: Option<Result<usize, String>>,
+}
+
+static PENDING: AtomicBool = AtomicBool::new(false);
+static HANDOFF: Mutex<Handoff> = Mutex::new(Handoff { patch: None, result: None });
+static APPLIED: Condvar = Condvar::new();
+
+/// Start listening for patches if `perry dev` asked for it. Called by main.
+#[no_mangle]
+pub extern "C" fn js_hot_reload_start() {
+    let Some(path) = std::env::var_os(SOCKET_ENV).filter(|path| !path.is_empty()) else {
+        return;
+    };
+    #[cfg(unix)]
+    {
+        let _ = std::fs::remove_file(&path);
+        match std::os::unix::net::UnixListener::bind(&path) {
+            Ok(listener) => {
+                let _ = std::thread::Builder::new()
+                    .name("perry-hot-reload".to_string())
+                    .spawn(move || serve(listener));
+            }
+            Err(e) => eprintln!("[perry] hot reload disabled: cannot listen on {:?}: {}", path, e),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn serve(listener: std::os::unix::net::UnixListener) {
+    use std::io::{BufRead, BufReader, Write};
+
+    for stream in listener.incoming() {
+        let Ok(mut stream) = stream else { continue };
+        let mut request = String::new();
+        if BufReader::new(&stream).read_line(&mut request).is_err() {
+            continue;
+        }
+        let reply = match load(request.trim_end_matches('\n')).and_then(apply_on_main_thread) {
+            Ok(swapped) => format!("ok {}\n", swapped),
+            Err(message) => format!("error {}\n", message.replace('\n', " ")),
+        };
+        let _ = stream.write_all(reply.as_bytes());
+    }
+}
+
+/// Load the patch a request names and pair each listed function with its new version
+#[cfg(unix)]
+fn load(request: &str) -> Result<Patch, String> {
+    use std::ffi::{CStr, CString};
+
+    let mut fields = request.split('\t');
+    let path = fields.next().filter(|path| !path.is_empty()).ok_or("empty request")?;
+    if JUMP_LEN == 0 {
+        return Err(format!("hot reload is not supported on {}", std::env::consts::ARCH));
+    }
+    let c_path = CString::new(path).map_err(|_| "invalid patch path")?;
+    let handle = unsafe { libc::dlopen(c_path.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL) };
+    if handle.is_null() {
+        let error = unsafe { libc::dlerror() };
+        let message = if error.is_null() {
+            "unknown error".to_string()
+        } else {
+            unsafe { CStr::from_ptr(error) }.to_string_lossy().into_owned()
+        };
+        return Err(format!("cannot load {}: {}", path, message));
+    }
+    let patch: Result<Patch, String> = fields.map(|symbol| resolve(handle, symbol)).collect();
+    if patch.is_err() {
+        // Nothing runs the patch's code yet
+        unsafe { libc::dlclose(handle) };
+    }
+    patch
+}
+
+/// The program's function `symbol` and its version in the patch loaded at `handle`
+#[cfg(unix)]
+fn resolve(handle: *mut libc::c_void, symbol: &str) -> Result<(usize, usize), String> {
+    use std::ffi::CString;
+
+    let c_symbol = CString::new(symbol).map_err(|_| "invalid symbol name")?;
+    let old = unsafe { libc::dlsym(libc::RTLD_DEFAULT, c_symbol.as_ptr()) } as usize;
+    let new = unsafe { libc::dlsym(handle, c_symbol.as_ptr()) } as usize;
+    if old == 0 || new == 0 {
+        return Err(format!("{} is missing from the {}", symbol, if old == 0 { "program" } else { "patch" }));
+    }
+    if old == new {
+        // Already resolved to the program's copy: the patch doesn't define it
+        return Err(format!("{} is not defined by the patch", symbol));
+    }
+    if function_size(old).is_some_and(|size| size < JUMP_LEN) {
+        return Err(format!("{} is too small to patch", symbol));
+    }
+    Ok((old, new))
+}
+
+/// Hand the patch to the main thread and wait for it to be applied
+fn apply_on_main_thread(patch: Patch) -> Result<usize, String> {
+    let mut handoff = HANDOFF.lock().unwrap();
+    *handoff = Handoff { patch: Some(patch), result: None };
+    PENDING.store(true, Ordering::SeqCst);
+    let (mut handoff, _) = APPLIED
+        .wait_timeout_while(handoff, APPLY_TIMEOUT, |handoff| handoff.result.is_none())
+        .unwrap();
+    PENDING.store(false, Ordering::SeqCst);
+    match handoff.result.take() {
+        Some(result) => result,
+        None => {
+            handoff.patch = None;
+            Err("the program did not reach a safe point".to_string())
+        }
+    }
+}
+
+/// Apply a patch waiting for the main thread, if any. Called between event loop tasks.
+pub fn apply_pending() {
+    if !PENDING.load(Ordering::Relaxed) {
+        return;
+    }
+    let mut handoff = HANDOFF.lock().unwrap();
+    if let Some(patch) = handoff.patch.take() {
+        let result = patch.iter()
+            .try_for_each(|&(old, new)| unsafe { write_jump(old, new) })
+            .map(|_| patch.len());
+        handoff.result = Some(result);
+        APPLIED.notify_all();
+    }
+}
+
+/// Size of the function starting at `addr`, where the dynamic linker can tell
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+fn function_size(addr: usize) -> Option<usize> {
+    // <dlfcn.h>: have dladdr1 return the symbol's ELF symbol table entry
+    const RTLD_DL_SYMENT: libc::c_int = 1;
+    let mut info: libc::Dl_info = unsafe { std::mem::zeroed() };
+    let mut symbol: *mut libc::c_void = std::ptr::null_mut();
+    let found = unsafe { libc::dladdr1(addr as *const libc::c_void, &mut info, &mut symbol, RTLD_DL_SYMENT) };
+    if found == 0 || symbol.is_null() || info.dli_saddr as usize != addr {
+        return None;
+    }
+    Some(unsafe { (*(symbol as *const libc::Elf64_Sym)).st_size } as usize)
+}
+
+/// Size of the function starting at `addr`, where the dynamic linker can tell. Elsewhere
+/// functions are 16-byte aligned, which leaves room for the x86_64 jump.
+#[cfg(not(all(target_os = "linux", target_env = "gnu")))]
+fn function_size(_addr: usize) -> Option<usize> {
+    None
+}
+
+/// Machine code jumping to `to`, through a scratch register the calling convention
+/// doesn't pass arguments in
+fn jump_code(to: usize) -> Vec<u8> {
+    let mut code = Vec::with_capacity(JUMP_LEN);
+    if cfg!(target_arch = "x86_64") {
+        // movabs r11, to; jmp r11
+        code.extend_from_slice(&[0x49, 0xBB]);
+        code.extend_from_slice(&(to as u64).to_le_bytes());
+        code.extend_from_slice(&[0x41, 0xFF, 0xE3]);
+    } else if cfg!(target_arch = "aarch64") {
+        // ldr x16, #8; br x16; .quad to
+        code.extend_from_slice(&0x5800_0050u32.to_le_bytes());
+        code.extend_from_slice(&0xD61F_0200u32.to_le_bytes());
+        code.extend_from_slice(&(to as u64).to_le_bytes());
+    }
+    code
+}
+
+/// Overwrite the entry of the function at `from` with a jump to `to`
+#[cfg(unix)]
+unsafe fn write_jump(from: usize, to: usize) -> Result<(), String> {
+    let code = jump_code(to);
+    let page = libc::sysconf(libc::_SC_PAGESIZE) as usize;
+    let start = from & !(page - 1);
+    let len = from + code.len() - start;
+    let writable = libc::PROT_READ | libc::PROT_WRITE | libc::PROT_EXEC;
+    if libc::mprotect(start as *mut libc::c_void, len, writable) != 0 {
+        return Err(format!("cannot make code writable: {}", std::io::Error::last_os_error()));
+    }
+    std::ptr::copy_nonoverlapping(code.as_ptr(), from as *mut u8, code.len());
+    libc::mprotect(start as *mut libc::c_void, len, libc::PROT_READ | libc::PROT_EXEC);
+    #[cfg(target_arch = "aarch64")]
+    {
+        extern "C" {
+            fn __clear_cache(start: *mut libc::c_char, end: *mut libc::c_char);
+        }
+        __clear_cache(from as *mut libc::c_char, (from + code.len()) as *mut libc::c_char);
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+unsafe fn write_jump(_from: usize, _to: usize) -> Result<(), String> {
+    Err("hot reload is not supported on this platform".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_jump_code_fills_the_jump() {
+        let code = jump_code(0x1122_3344_5566_7788);
+        assert_eq!(code.len(), JUMP_LEN);
+        if cfg!(target_arch = "x86_64") {
+            assert_eq!(&code[2..10], &0x1122_3344_5566_7788u64.to_le_bytes());
+        }
+    }
+
+    #[test]
+    fn test_nothing_pending_is_a_no_op() {
+        apply_pending();
+        assert!(HANDOFF.lock().unwrap().result.is_none());
+    }
+}
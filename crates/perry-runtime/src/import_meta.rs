@@ -0,0 +1,193 @@
+//! import.meta support
+//!
+//! By default `import.meta.url`, `.filename` and `.dirname` are baked into the binary as
+//! string literals during lowering. The functions here cover the dynamic cases:
+//! - `import.meta.resolve(specifier)`
+//! - `--import-meta=exe`, where module paths are resolved relative to the executable's
+//!   directory at runtime (for binaries that ship next to their assets)
+
+use std::path::{Component, Path, PathBuf};
+
+use crate::string::{js_string_from_bytes, StringHeader};
+use crate::value::js_jsvalue_to_string;
+
+/// Node builtins that resolve to `node:<name>` URLs
+const NODE_BUILTINS: &[&str] = &[
+    "assert", "buffer", "child_process", "crypto", "dns", "events", "fs", "fs/promises",
+    "http", "https", "net", "os", "path", "process", "querystring", "readline", "stream",
+    "string_decoder", "timers", "tls", "url", "util", "worker_threads", "zlib",
+];
+
+/// Property selector passed from codegen (matches HIR ImportMetaProperty order)
+const PROP_URL: i32 = 0;
+const PROP_FILENAME: i32 = 1;
+const PROP_DIRNAME: i32 = 2;
+
+unsafe fn string_from_header(ptr: *const StringHeader) -> String {
+    if ptr.is_null() {
+        return String::new();
+    }
+    let len = (*ptr).length as usize;
+    let data_ptr = (ptr as *const u8).add(std::mem::size_of::<StringHeader>());
+    let bytes = std::slice::from_raw_parts(data_ptr, len);
+    String::from_utf8_lossy(bytes).into_owned()
+}
+
+fn string_to_js(s: &str) -> *mut StringHeader {
+    js_string_from_bytes(s.as_ptr(), s.len() as u32)
+}
+
+/// Directory containing the running executable (falls back to the cwd)
+fn exe_dir() -> PathBuf {
+    std::env::current_exe()
+        .ok()
+        .and_then(|p| p.parent().map(|d| d.to_path_buf()))
+        .or_else(|| std::env::current_dir().ok())
+        .unwrap_or_default()
+}
+
+/// Lexically normalize `.` and `..` components (does not touch the filesystem)
+fn normalize(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !out.pop() {
+                    out.push("..");
+                }
+            }
+            other => out.push(other.as_os_str()),
+        }
+    }
+    out
+}
+
+fn file_url(path: &Path) -> String {
+    format!("file://{}", path.to_string_lossy())
+}
+
+/// Absolute module path, either as baked in or relative to the executable's directory
+fn module_file(module_path: &str, exe_relative: bool) -> PathBuf {
+    if exe_relative {
+        normalize(&exe_dir().join(module_path))
+    } else {
+        PathBuf::from(module_path)
+    }
+}
+
+/// Split a bare specifier into package name and optional subpath ("@scope/pkg/x" -> ("@scope/pkg", "x"))
+fn split_package_specifier(specifier: &str) -> (&str, Option<&str>) {
+    let name_segments = if specifier.starts_with('@') { 2 } else { 1 };
+    let mut idx = 0;
+    for _ in 0..name_segments {
+        match specifier[idx..].find('/') {
+            Some(pos) => idx += pos + 1,
+            None => return (specifier, None),
+        }
+    }
+    (&specifier[..idx - 1], Some(&specifier[idx..]))
+}
+
+/// Find a package in node_modules, walking up from `dir`, and return its entry file
+fn resolve_package(dir: &Path, specifier: &str) -> Option<PathBuf> {
+    let (name, subpath) = split_package_specifier(specifier);
+    for ancestor in dir.ancestors() {
+        let pkg_dir = ancestor.join("node_modules").join(name);
+        if !pkg_dir.is_dir() {
+            continue;
+        }
+        if let Some(subpath) = subpath {
+            return Some(pkg_dir.join(subpath));
+        }
+        let main = std::fs::read_to_string(pkg_dir.join("package.json"))
+            .ok()
+            .and_then(|s| serde_json::from_str::<serde_json::Value>(&s).ok())
+            .and_then(|json| json.get("main").and_then(|m| m.as_str()).map(|m| m.to_string()))
+            .unwrap_or_else(|| "index.js".to_string());
+        return Some(normalize(&pkg_dir.join(main)));
+    }
+    None
+}
+
+/// Resolve `specifier` the way import.meta.resolve does, relative to `module_file`.
+/// Unresolvable bare specifiers are returned unchanged.
+fn resolve_specifier(module_file: &Path, specifier: &str) -> String {
+    if specifier.starts_with("node:") || specifier.contains("://") || specifier.starts_with("file:") {
+        return specifier.to_string();
+    }
+    let dir = module_file.parent().unwrap_or_else(|| Path::new(""));
+    if specifier.starts_with("./") || specifier.starts_with("../") || specifier == "." || specifier == ".." {
+        return file_url(&normalize(&dir.join(specifier)));
+    }
+    if specifier.starts_with('/') {
+        return file_url(&normalize(Path::new(specifier)));
+    }
+    if NODE_BUILTINS.contains(&specifier) {
+        return format!("node:{}", specifier);
+    }
+    match resolve_package(dir, specifier) {
+        Some(path) => file_url(&path),
+        None => specifier.to_string(),
+    }
+}
+
+/// import.meta.url / filename / dirname relative to the executable's directory
+/// `relative_path` is the source path relative to the project root.
+#[no_mangle]
+pub extern "C" fn js_import_meta_exe_path(relative_path: f64, property: i32) -> *mut StringHeader {
+    let relative = unsafe { string_from_header(js_jsvalue_to_string(relative_path)) };
+    let file = module_file(&relative, true);
+    match property {
+        PROP_URL => string_to_js(&file_url(&file)),
+        PROP_FILENAME => string_to_js(&file.to_string_lossy()),
+        PROP_DIRNAME => {
+            let dir = file.parent().map(|d| d.to_string_lossy().into_owned()).unwrap_or_default();
+            string_to_js(&dir)
+        }
+        _ => string_to_js(""),
+    }
+}
+
+/// import.meta.resolve(specifier)
+#[no_mangle]
+pub extern "C" fn js_import_meta_resolve(module_path: f64, specifier: f64, exe_relative: i32) -> *mut StringHeader {
+    let (module_path, specifier) = unsafe {
+        (
+            string_from_header(js_jsvalue_to_string(module_path)),
+            string_from_header(js_jsvalue_to_string(specifier)),
+        )
+    };
+    let file = module_file(&module_path, exe_relative != 0);
+    string_to_js(&resolve_specifier(&file, &specifier))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_relative() {
+        let module = Path::new("/app/src/main.ts");
+        assert_eq!(resolve_specifier(module, "./util.ts"), "file:///app/src/util.ts");
+        assert_eq!(resolve_specifier(module, "../assets/logo.png"), "file:///app/assets/logo.png");
+        assert_eq!(resolve_specifier(module, "/etc/hosts"), "file:///etc/hosts");
+    }
+
+    #[test]
+    fn test_resolve_builtins_and_urls() {
+        let module = Path::new("/app/main.ts");
+        assert_eq!(resolve_specifier(module, "fs"), "node:fs");
+        assert_eq!(resolve_specifier(module, "node:path"), "node:path");
+        assert_eq!(resolve_specifier(module, "https://x.dev/a.js"), "https://x.dev/a.js");
+        assert_eq!(resolve_specifier(module, "not-installed-pkg"), "not-installed-pkg");
+    }
+
+    #[test]
+    fn test_split_package_specifier() {
+        assert_eq!(split_package_specifier("lodash"), ("lodash", None));
+        assert_eq!(split_package_specifier("lodash/fp"), ("lodash", Some("fp")));
+        assert_eq!(split_package_specifier("@scope/pkg"), ("@scope/pkg", None));
+        assert_eq!(split_package_specifier("@scope/pkg/sub/x.js"), ("@scope/pkg", Some("sub/x.js")));
+    }
+}
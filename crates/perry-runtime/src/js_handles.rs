@@ -0,0 +1,92 @@
+//! Lifetime of JavaScript values held by native code (`perry/js-handles`)
+//!
+//! Objects, arrays and functions that come back from the V8 runtime are NaN-boxed JS
+//! handles: ids into perry-jsruntime's handle table, which keeps the V8 value alive.
+//! Values handed to native callbacks are held weakly once the callback returns, so V8
+//! collects them as usual; everything else stays until it is disposed:
+//!
+//! ```typescript
+//! import { dispose, retain, liveHandles } from "perry/js-handles";
+//! const server = createServer(handler);   // a JS object, held until disposed
+//! dispose(server);                        // true; `server` must not be used afterwards
+//! retain(request);                        // keep a callback argument past the callback
+//! console.log(liveHandles());             // ["#12 object (Socket) strong 5.2s", ...]
+//! ```
+//!
+//! The table lives in perry-jsruntime, which registers its functions here with
+//! [`js_set_handle_lifecycle`]. Without the JS runtime there are no handles: `dispose`
+//! returns false and `liveHandles` an empty array.
+
+use std::sync::atomic::{AtomicPtr, Ordering};
+
+use crate::array::{js_array_alloc, ArrayHeader};
+use crate::value::{is_js_handle, JSValue};
+
+type DisposeFn = extern "C" fn(f64) -> i32;
+type RetainFn = extern "C" fn(f64) -> i32;
+type LiveFn = extern "C" fn() -> *mut ArrayHeader;
+
+static HANDLE_DISPOSE: AtomicPtr<()> = AtomicPtr::new(std::ptr::null_mut());
+static HANDLE_RETAIN: AtomicPtr<()> = AtomicPtr::new(std::ptr::null_mut());
+static HANDLE_LIVE: AtomicPtr<()> = AtomicPtr::new(std::ptr::null_mut());
+
+/// Set the handle lifecycle functions (called by perry-jsruntime)
+#[no_mangle]
+pub extern "C" fn js_set_handle_lifecycle(dispose: DisposeFn, retain: RetainFn, live: LiveFn) {
+    HANDLE_DISPOSE.store(dispose as *mut (), Ordering::SeqCst);
+    HANDLE_RETAIN.store(retain as *mut (), Ordering::SeqCst);
+    HANDLE_LIVE.store(live as *mut (), Ordering::SeqCst);
+}
+
+/// `dispose(value)`: release the JS value behind a handle; true if it was held
+#[no_mangle]
+pub extern "C" fn js_handles_dispose(value: f64) -> f64 {
+    let func_ptr = HANDLE_DISPOSE.load(Ordering::SeqCst);
+    let released = is_js_handle(value) && !func_ptr.is_null() && {
+        let func: DisposeFn = unsafe { std::mem::transmute(func_ptr) };
+        func(value) != 0
+    };
+    f64::from_bits(JSValue::bool(released).bits())
+}
+
+/// `retain(value)`: hold the JS value strongly until it is disposed; returns `value`
+#[no_mangle]
+pub extern "C" fn js_handles_retain(value: f64) -> f64 {
+    let func_ptr = HANDLE_RETAIN.load(Ordering::SeqCst);
+    if is_js_handle(value) && !func_ptr.is_null() {
+        let func: RetainFn = unsafe { std::mem::transmute(func_ptr) };
+        func(value);
+    }
+    value
+}
+
+/// `liveHandles()`: one description per JS value native code holds, oldest first
+#[no_mangle]
+pub extern "C" fn js_handles_live() -> f64 {
+    let func_ptr = HANDLE_LIVE.load(Ordering::SeqCst);
+    let arr = if func_ptr.is_null() {
+        js_array_alloc(0)
+    } else {
+        let func: LiveFn = unsafe { std::mem::transmute(func_ptr) };
+        func()
+    };
+    f64::from_bits(JSValue::array_ptr(arr).bits())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::array::js_array_length;
+
+    #[test]
+    fn test_without_js_runtime() {
+        // A NaN-boxed JS handle (tag 0x7FFB) that no table knows about
+        let handle = f64::from_bits(0x7FFB_0000_0000_0007);
+        assert_eq!(js_handles_dispose(handle).to_bits(), JSValue::bool(false).bits());
+        assert_eq!(js_handles_retain(handle).to_bits(), handle.to_bits());
+        assert_eq!(js_handles_dispose(1.5).to_bits(), JSValue::bool(false).bits());
+        let live = JSValue::from_bits(js_handles_live().to_bits());
+        assert!(live.is_pointer());
+        assert_eq!(js_array_length(live.as_pointer::<ArrayHeader>()), 0);
+    }
+}
@@ -16,6 +16,7 @@ pub mod map;
 pub mod set;
 pub mod string;
 pub mod bigint;
+pub mod bignum;
 pub mod closure;
 pub mod exception;
 pub mod error;
@@ -28,13 +29,31 @@ pub mod fs;
 pub mod path;
 pub mod math;
 pub mod date;
+pub mod temporal;
 pub mod url;
 pub mod regex;
 pub mod os;
 pub mod buffer;
+pub mod buffer_pool;
 pub mod child_process;
 pub mod net;
 pub mod redis_client;
+pub mod crash;
+pub mod stack_guard;
+pub mod hot_reload;
+pub mod cpu;
+pub mod symbols;
+pub mod reflect;
+pub mod class_bridge;
+pub mod js_handles;
+pub mod memoize;
+pub mod shape;
+pub mod import_meta;
+pub mod memory;
+pub mod permissions;
+pub mod disposable;
+pub mod napi;
+pub mod stdio;
 
 pub use value::JSValue;
 pub use promise::Promise;
@@ -59,6 +78,7 @@ pub use promise::{js_promise_new, js_promise_resolve, js_promise_reject};
 pub use bigint::js_bigint_from_string;
 pub use value::{js_nanbox_get_pointer, js_nanbox_pointer, js_nanbox_string, js_get_string_pointer_unified, js_jsvalue_to_string};
 pub use value::{js_set_handle_array_get, js_set_handle_array_length, js_set_handle_object_get_property, js_set_handle_to_string};
+pub use js_handles::js_set_handle_lifecycle;
 pub use array::{js_array_push_f64};
 pub use object::js_object_set_field_by_name;
 pub use promise::{js_promise_run_microtasks, js_promise_state, js_is_promise, js_promise_value};
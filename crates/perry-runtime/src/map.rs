@@ -6,6 +6,7 @@
 
 use std::alloc::{alloc, dealloc, realloc, Layout};
 use std::ptr;
+use crate::array::{js_array_alloc, js_array_push_f64, ArrayHeader};
 use crate::string::StringHeader;
 
 /// Map header - stable address, entries allocated separately
@@ -259,3 +260,80 @@ pub extern "C" fn js_map_clear(map: *mut MapHeader) {
         (*map).size = 0;
     }
 }
+
+/// Get the keys of the map as an array (map.keys(), snapshot in insertion order)
+#[no_mangle]
+pub extern "C" fn js_map_keys(map: *const MapHeader) -> *mut ArrayHeader {
+    map_column(map, 0)
+}
+
+/// Get the values of the map as an array (map.values(), snapshot in insertion order)
+#[no_mangle]
+pub extern "C" fn js_map_values(map: *const MapHeader) -> *mut ArrayHeader {
+    map_column(map, 1)
+}
+
+/// Get the entries of the map as an array of [key, value] pairs (map.entries() and
+/// for-of over a map)
+#[no_mangle]
+pub extern "C" fn js_map_entries(map: *const MapHeader) -> *mut ArrayHeader {
+    unsafe {
+        let size = (*map).size;
+        let entries = entries_ptr(map);
+        let mut result = js_array_alloc(size);
+
+        for i in 0..size as usize {
+            let mut pair = js_array_alloc(2);
+            pair = js_array_push_f64(pair, ptr::read(entries.add(i * 2)));
+            pair = js_array_push_f64(pair, ptr::read(entries.add(i * 2 + 1)));
+            // NaN-box the pair pointer so destructuring sees an array value
+            result = js_array_push_f64(result, crate::value::js_nanbox_pointer(pair as i64));
+        }
+
+        result
+    }
+}
+
+/// Copy the keys (column 0) or values (column 1) of the map into a new array
+fn map_column(map: *const MapHeader, column: usize) -> *mut ArrayHeader {
+    unsafe {
+        let size = (*map).size;
+        let entries = entries_ptr(map);
+        let mut result = js_array_alloc(size);
+
+        for i in 0..size as usize {
+            result = js_array_push_f64(result, ptr::read(entries.add(i * 2 + column)));
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::array::{js_array_get_f64, js_array_length};
+
+    #[test]
+    fn test_map_keys_values_entries() {
+        let map = js_map_alloc(0);
+        js_map_set(map, 1.0, 10.0);
+        js_map_set(map, 2.0, 20.0);
+        js_map_set(map, 1.0, 11.0);
+
+        let keys = js_map_keys(map);
+        assert_eq!(js_array_length(keys), 2);
+        assert_eq!(js_array_get_f64(keys, 0), 1.0);
+        assert_eq!(js_array_get_f64(keys, 1), 2.0);
+
+        let values = js_map_values(map);
+        assert_eq!(js_array_get_f64(values, 0), 11.0);
+        assert_eq!(js_array_get_f64(values, 1), 20.0);
+
+        let entries = js_map_entries(map);
+        assert_eq!(js_array_length(entries), 2);
+        let pair = crate::value::js_nanbox_get_pointer(js_array_get_f64(entries, 1)) as *const ArrayHeader;
+        assert_eq!(js_array_get_f64(pair, 0), 2.0);
+        assert_eq!(js_array_get_f64(pair, 1), 20.0);
+    }
+}
@@ -0,0 +1,215 @@
+//! Result caches of memoized functions (`@memoize` / `"use memoize"`)
+//!
+//! ```typescript
+//! function route(path: string): Handler {
+//!     "use memoize(500, 60000)";   // keep up to 500 results, each for a minute
+//!     ...
+//! }
+//! class Rates {
+//!     @memoize                     // up to 1024 results, kept until dropped for room
+//!     lookup(code: string): number { ... }
+//! }
+//! ```
+//!
+//! Codegen compiles the body under its own symbol and gives the function a wrapper that
+//! looks the arguments up here, calling the body and storing its result on a miss. Each
+//! function has one cache, created on its first call in a slot the wrapper owns.
+//! Arguments are compared by value for numbers (`0` and `-0` alike), booleans and
+//! strings, and by identity for everything else (objects, arrays, functions, `this`).
+//! When the cache is full, the oldest result is dropped.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicPtr, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::string::{string_data, StringHeader};
+
+const STRING_TAG: u64 = 0x7FFF_0000_0000_0000;
+const TAG_MASK: u64 = 0xFFFF_0000_0000_0000;
+const POINTER_MASK: u64 = 0x0000_FFFF_FFFF_FFFF;
+
+/// One argument, as compared for a cache hit
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum KeyPart {
+    Bits(u64),
+    Text(Box<[u8]>),
+}
+
+struct Entry {
+    value: u64,
+    stored: Instant,
+    /// Position in `order` of this key's latest store
+    seq: u64,
+}
+
+pub struct MemoCache {
+    max_entries: usize,
+    ttl: Option<Duration>,
+    entries: HashMap<Vec<KeyPart>, Entry>,
+    /// Stores by age, oldest first; a key's earlier stores are skipped when evicting
+    order: VecDeque<(u64, Vec<KeyPart>)>,
+    next_seq: u64,
+}
+
+impl MemoCache {
+    fn new(max_entries: u32, ttl_ms: u64) -> Self {
+        MemoCache {
+            max_entries: max_entries.max(1) as usize,
+            ttl: (ttl_ms > 0).then(|| Duration::from_millis(ttl_ms)),
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+            next_seq: 0,
+        }
+    }
+
+    fn get(&mut self, key: &[KeyPart]) -> Option<u64> {
+        let entry = self.entries.get(key)?;
+        if self.ttl.is_some_and(|ttl| entry.stored.elapsed() >= ttl) {
+            self.entries.remove(key);
+            return None;
+        }
+        Some(entry.value)
+    }
+
+    fn insert(&mut self, key: Vec<KeyPart>, value: u64) {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        self.entries.insert(key.clone(), Entry { value, stored: Instant::now(), seq });
+        self.order.push_back((seq, key));
+        while self.entries.len() > self.max_entries {
+            let Some((seq, oldest)) = self.order.pop_front() else { break };
+            if self.entries.get(&oldest).is_some_and(|entry| entry.seq == seq) {
+                self.entries.remove(&oldest);
+            }
+        }
+        // Forget stores superseded since, or of expired keys
+        if self.order.len() > 2 * self.max_entries {
+            let entries = &self.entries;
+            self.order.retain(|(seq, key)| entries.get(key).is_some_and(|entry| entry.seq == *seq));
+        }
+    }
+}
+
+unsafe fn string_bytes(s: *const StringHeader) -> Box<[u8]> {
+    if s.is_null() {
+        return Box::default();
+    }
+    std::slice::from_raw_parts(string_data(s), (*s).length as usize).into()
+}
+
+/// The cache key of `argc` arguments at `args`; bit i of `string_args` marks argument i
+/// as a raw string pointer (a `string` parameter) rather than a NaN-boxed value
+unsafe fn key_of(args: *const u64, argc: u32, string_args: u64) -> Vec<KeyPart> {
+    (0..argc as usize)
+        .map(|i| {
+            let bits = *args.add(i);
+            if i < 64 && string_args & (1 << i) != 0 {
+                KeyPart::Text(string_bytes(bits as *const StringHeader))
+            } else if bits & TAG_MASK == STRING_TAG {
+                KeyPart::Text(string_bytes((bits & POINTER_MASK) as *const StringHeader))
+            } else if bits == (-0.0f64).to_bits() {
+                KeyPart::Bits(0)
+            } else {
+                KeyPart::Bits(bits)
+            }
+        })
+        .collect()
+}
+
+/// The cache in `slot`, created on first use
+unsafe fn cache_in(slot: *const AtomicPtr<Mutex<MemoCache>>, max_entries: u32, ttl_ms: u64) -> &'static Mutex<MemoCache> {
+    let slot = &*slot;
+    let existing = slot.load(Ordering::Acquire);
+    if !existing.is_null() {
+        return &*existing;
+    }
+    let fresh = Box::into_raw(Box::new(Mutex::new(MemoCache::new(max_entries, ttl_ms))));
+    match slot.compare_exchange(std::ptr::null_mut(), fresh, Ordering::AcqRel, Ordering::Acquire) {
+        Ok(_) => &*fresh,
+        Err(winner) => {
+            drop(Box::from_raw(fresh));
+            &*winner
+        }
+    }
+}
+
+/// Look the arguments up in the function's cache; on a hit, write the result's bits to
+/// `out` and return 1
+#[no_mangle]
+pub unsafe extern "C" fn js_memo_lookup(
+    slot: *const AtomicPtr<Mutex<MemoCache>>,
+    max_entries: u32,
+    ttl_ms: u64,
+    args: *const u64,
+    argc: u32,
+    string_args: u64,
+    out: *mut u64,
+) -> i32 {
+    let key = key_of(args, argc, string_args);
+    let mut cache = cache_in(slot, max_entries, ttl_ms).lock().unwrap();
+    match cache.get(&key) {
+        Some(value) => {
+            *out = value;
+            1
+        }
+        None => 0,
+    }
+}
+
+/// Store the result of calling the function with these arguments
+#[no_mangle]
+pub unsafe extern "C" fn js_memo_store(
+    slot: *const AtomicPtr<Mutex<MemoCache>>,
+    args: *const u64,
+    argc: u32,
+    string_args: u64,
+    value: u64,
+) {
+    let cache = (*slot).load(Ordering::Acquire);
+    if cache.is_null() {
+        return;
+    }
+    let key = key_of(args, argc, string_args);
+    (*cache).lock().unwrap().insert(key, value);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keys_by_value_and_identity() {
+        let slot = AtomicPtr::new(std::ptr::null_mut());
+        let a = crate::string::js_string_from_bytes(b"usd".as_ptr(), 3);
+        let b = crate::string::js_string_from_bytes(b"usd".as_ptr(), 3);
+        let mut out = 0u64;
+        let args = [a as u64, 2.0f64.to_bits()];
+        unsafe {
+            assert_eq!(js_memo_lookup(&slot, 2, 0, args.as_ptr(), 2, 0b01, &mut out), 0);
+            js_memo_store(&slot, args.as_ptr(), 2, 0b01, 42);
+            // An equal string at another address hits
+            let same = [b as u64, 2.0f64.to_bits()];
+            assert_eq!(js_memo_lookup(&slot, 2, 0, same.as_ptr(), 2, 0b01, &mut out), 1);
+            assert_eq!(out, 42);
+            // Compared as identities, the two strings differ
+            assert_eq!(js_memo_lookup(&slot, 2, 0, same.as_ptr(), 2, 0, &mut out), 0);
+
+            // -0 and 0 are one key; a third key drops the oldest
+            js_memo_store(&slot, [(-0.0f64).to_bits()].as_ptr(), 1, 0, 7);
+            assert_eq!(js_memo_lookup(&slot, 2, 0, [0.0f64.to_bits()].as_ptr(), 1, 0, &mut out), 1);
+            js_memo_store(&slot, [1.0f64.to_bits()].as_ptr(), 1, 0, 8);
+            assert_eq!(js_memo_lookup(&slot, 2, 0, args.as_ptr(), 2, 0b01, &mut out), 0);
+        }
+    }
+
+    #[test]
+    fn test_results_expire() {
+        let mut cache = MemoCache::new(8, 20);
+        let key = vec![KeyPart::Bits(1)];
+        cache.insert(key.clone(), 5);
+        assert_eq!(cache.get(&key), Some(5));
+        std::thread::sleep(Duration::from_millis(30));
+        assert_eq!(cache.get(&key), None);
+    }
+}
@@ -0,0 +1,300 @@
+//! Heap ceiling and out-of-memory policy
+//!
+//! Tracks bytes handed out by the runtime's allocation paths (arena blocks, arrays,
+//! strings) and enforces an optional ceiling. When an allocation would exceed it:
+//! - `Throw` (default): throws a catchable `RangeError` before allocating
+//! - `Abort`: writes a report to stderr (and the crash report, if installed) and aborts
+//!
+//! Low-memory callbacks registered with `process.onLowMemory(cb)` run once per limit,
+//! when usage first reaches `LOW_MEMORY_RATIO` of it (before any RangeError is thrown).
+//!
+//! Configuration, later sources win:
+//! - compile flags `--max-heap <size>` / `--oom-policy throw|abort` (via `js_memory_configure`)
+//! - env `PERRY_MAX_HEAP=<size>` (e.g. `512M`, `2G`) / `PERRY_OOM_POLICY=throw|abort`
+//! - `process.setMemoryLimit(bytes, policy?)` at runtime
+//!
+//! Arena memory is charged per 8MB block, so the effective ceiling for objects is
+//! block-granular.
+//!
+//! Separately, every object, string, array and closure allocation is counted by type
+//! (`process.allocationStats()`), along with the allocator the executable was linked
+//! against (`--allocator system|mimalloc|jemalloc`, recorded via `js_memory_set_allocator`).
+
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering};
+use std::sync::{Mutex, Once, RwLock};
+
+use crate::string::{js_string_from_bytes, string_as_str};
+use crate::value::{js_jsvalue_to_string, JSValue};
+
+/// Fraction of the limit at which low-memory callbacks fire
+const LOW_MEMORY_RATIO: f64 = 0.9;
+
+const POLICY_THROW: u8 = 0;
+const POLICY_ABORT: u8 = 1;
+
+/// Bytes currently charged
+static USED: AtomicU64 = AtomicU64::new(0);
+/// Heap ceiling in bytes (0 = unlimited)
+static LIMIT: AtomicU64 = AtomicU64::new(0);
+static POLICY: AtomicU8 = AtomicU8::new(POLICY_THROW);
+/// Set while a RangeError is being built/thrown, so its own allocations are let through
+static HANDLING_OOM: AtomicBool = AtomicBool::new(false);
+/// Set once low-memory callbacks have fired for the current limit
+static LOW_MEMORY_FIRED: AtomicBool = AtomicBool::new(false);
+static IN_LOW_MEMORY_CALLBACK: AtomicBool = AtomicBool::new(false);
+static ENV_INIT: Once = Once::new();
+
+/// Closure pointers registered via process.onLowMemory
+static LOW_MEMORY_CALLBACKS: Mutex<Vec<i64>> = Mutex::new(Vec::new());
+
+/// Kind of runtime allocation counted by `process.allocationStats()`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AllocKind {
+    Object = 0,
+    String = 1,
+    Array = 2,
+    Closure = 3,
+}
+
+/// Allocation counts and bytes by `AllocKind`, cumulative since startup
+static ALLOC_COUNTS: [AtomicU64; 4] = [const { AtomicU64::new(0) }; 4];
+static ALLOC_BYTES: [AtomicU64; 4] = [const { AtomicU64::new(0) }; 4];
+
+/// Allocator the executable was linked against (set from main; "system" if unset)
+static ALLOCATOR: RwLock<Option<String>> = RwLock::new(None);
+
+/// Parse a size like "1048576", "512K", "256M" or "2G" (powers of 1024). Shared with
+/// `perry compile --max-heap`; sizes that don't fit in 64 bits are rejected.
+pub fn parse_size(s: &str) -> Option<u64> {
+    let s = s.trim();
+    let (digits, multiplier) = match s.chars().last()? {
+        'k' | 'K' => (&s[..s.len() - 1], 1024),
+        'm' | 'M' => (&s[..s.len() - 1], 1024 * 1024),
+        'g' | 'G' => (&s[..s.len() - 1], 1024 * 1024 * 1024),
+        _ => (s, 1),
+    };
+    digits.trim().parse::<u64>().ok()?.checked_mul(multiplier)
+}
+
+fn parse_policy(s: &str) -> Option<u8> {
+    match s.trim() {
+        "throw" => Some(POLICY_THROW),
+        "abort" => Some(POLICY_ABORT),
+        _ => None,
+    }
+}
+
+fn set_limit(limit: u64) {
+    LIMIT.store(limit, Ordering::SeqCst);
+    LOW_MEMORY_FIRED.store(false, Ordering::SeqCst);
+}
+
+/// Apply PERRY_MAX_HEAP / PERRY_OOM_POLICY once, on first use
+fn init_from_env() {
+    ENV_INIT.call_once(|| {
+        if let Some(limit) = std::env::var("PERRY_MAX_HEAP").ok().and_then(|s| parse_size(&s)) {
+            set_limit(limit);
+        }
+        if let Some(policy) = std::env::var("PERRY_OOM_POLICY").ok().and_then(|s| parse_policy(&s)) {
+            POLICY.store(policy, Ordering::SeqCst);
+        }
+    });
+}
+
+/// Record memory that was allocated without a limit check (e.g. the first arena block)
+pub fn record(bytes: usize) {
+    USED.fetch_add(bytes as u64, Ordering::Relaxed);
+}
+
+/// Charge `bytes` against the heap ceiling before allocating them.
+/// Must not be called while holding runtime-internal borrows: it may throw
+/// or run low-memory callbacks that allocate.
+pub fn charge(bytes: usize) {
+    init_from_env();
+    let used = USED.fetch_add(bytes as u64, Ordering::Relaxed) + bytes as u64;
+    let limit = LIMIT.load(Ordering::Relaxed);
+    // Building the RangeError and running low-memory callbacks must not fail themselves
+    if limit == 0 || HANDLING_OOM.load(Ordering::Relaxed) || IN_LOW_MEMORY_CALLBACK.load(Ordering::Relaxed) {
+        return;
+    }
+
+    // Give callbacks a chance to run even if a single allocation jumps past the limit
+    if used as f64 >= limit as f64 * LOW_MEMORY_RATIO && !LOW_MEMORY_FIRED.swap(true, Ordering::SeqCst) {
+        run_low_memory_callbacks();
+    }
+
+    if used > limit {
+        USED.fetch_sub(bytes as u64, Ordering::Relaxed);
+        out_of_memory(bytes, used - bytes as u64, limit);
+    }
+}
+
+/// Count a new allocation of `bytes` for `process.allocationStats()`
+pub fn count_allocation(kind: AllocKind, bytes: usize) {
+    ALLOC_COUNTS[kind as usize].fetch_add(1, Ordering::Relaxed);
+    ALLOC_BYTES[kind as usize].fetch_add(bytes as u64, Ordering::Relaxed);
+}
+
+/// Count bytes added by growing an existing allocation in place
+pub fn count_growth(kind: AllocKind, bytes: usize) {
+    ALLOC_BYTES[kind as usize].fetch_add(bytes as u64, Ordering::Relaxed);
+}
+
+fn run_low_memory_callbacks() {
+    if IN_LOW_MEMORY_CALLBACK.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    // Copy the list so callbacks can register more callbacks
+    let callbacks = LOW_MEMORY_CALLBACKS.lock().map(|c| c.clone()).unwrap_or_default();
+    for callback in callbacks {
+        crate::closure::js_closure_call0(callback as *const crate::closure::ClosureHeader);
+    }
+    IN_LOW_MEMORY_CALLBACK.store(false, Ordering::SeqCst);
+}
+
+fn out_of_memory(requested: usize, used: u64, limit: u64) -> ! {
+    let message = format!(
+        "Heap limit exceeded: failed to allocate {} bytes ({} of {} bytes in use)",
+        requested, used, limit
+    );
+
+    if POLICY.load(Ordering::Relaxed) == POLICY_ABORT {
+        crate::crash::report(&format!("out of memory: {}", message));
+        std::process::abort();
+    }
+
+    // Build the RangeError with the limit suspended so its own allocations succeed
+    HANDLING_OOM.store(true, Ordering::SeqCst);
+    let msg = js_string_from_bytes(message.as_ptr(), message.len() as u32);
+    let error = crate::error::js_error_new_with_message(msg);
+    unsafe {
+        (*error).name = js_string_from_bytes(b"RangeError".as_ptr(), 10);
+    }
+    HANDLING_OOM.store(false, Ordering::SeqCst);
+    crate::exception::js_throw(f64::from_bits(JSValue::pointer(error as *const u8).bits()))
+}
+
+/// Set the heap ceiling and OOM policy from compile flags (called from main)
+/// `limit` is in bytes (0 = unlimited); `policy` is 0 = throw, 1 = abort.
+/// Environment variables still take precedence.
+#[no_mangle]
+pub extern "C" fn js_memory_configure(limit: f64, policy: i32) {
+    set_limit(limit.max(0.0) as u64);
+    POLICY.store(if policy == 1 { POLICY_ABORT } else { POLICY_THROW }, Ordering::SeqCst);
+    // Let env vars override compiled-in defaults
+    init_from_env();
+}
+
+/// process.setMemoryLimit(bytes, policy?) - policy is "throw" or "abort"
+#[no_mangle]
+pub extern "C" fn js_memory_set_limit(limit: f64, policy: f64) {
+    init_from_env();
+    set_limit(if limit.is_finite() && limit > 0.0 { limit as u64 } else { 0 });
+    let jsval = JSValue::from_bits(policy.to_bits());
+    if jsval.is_string() {
+        let policy_str = js_jsvalue_to_string(policy);
+        if let Some(p) = (!policy_str.is_null()).then(|| string_as_str(policy_str)).and_then(parse_policy) {
+            POLICY.store(p, Ordering::SeqCst);
+        }
+    }
+}
+
+/// process.onLowMemory(callback)
+#[no_mangle]
+pub extern "C" fn js_memory_on_low(callback: i64) {
+    // Accept both raw closure pointers and NaN-boxed pointers
+    let bits = callback as u64;
+    let ptr = if bits >> 48 >= 0x7FF8 { bits & 0x0000_FFFF_FFFF_FFFF } else { bits };
+    if ptr == 0 {
+        return;
+    }
+    if let Ok(mut callbacks) = LOW_MEMORY_CALLBACKS.lock() {
+        callbacks.push(ptr as i64);
+    }
+}
+
+/// Bytes currently charged against the heap ceiling
+#[no_mangle]
+pub extern "C" fn js_memory_used() -> f64 {
+    USED.load(Ordering::Relaxed) as f64
+}
+
+/// Record the allocator selected with `--allocator` (called from main)
+#[no_mangle]
+pub unsafe extern "C" fn js_memory_set_allocator(name: *const u8, len: usize) {
+    let bytes = std::slice::from_raw_parts(name, len);
+    *ALLOCATOR.write().unwrap() = Some(String::from_utf8_lossy(bytes).into_owned());
+}
+
+fn allocator_name() -> String {
+    ALLOCATOR.read().unwrap().clone().unwrap_or_else(|| "system".to_string())
+}
+
+fn boxed_string(s: &str) -> f64 {
+    f64::from_bits(JSValue::string_ptr(js_string_from_bytes(s.as_ptr(), s.len() as u32)).bits())
+}
+
+fn boxed_object(properties: &[(&str, f64)]) -> f64 {
+    let obj = crate::object::js_object_alloc(0, properties.len() as u32);
+    for (key, value) in properties {
+        let key = js_string_from_bytes(key.as_ptr(), key.len() as u32);
+        crate::object::js_object_set_field_by_name(obj, key, *value);
+    }
+    f64::from_bits(JSValue::object_ptr(obj as *mut u8).bits())
+}
+
+/// process.allocationStats(): `{ allocator, objects, strings, arrays, closures }`, each
+/// kind a `{ count, bytes }` total since startup
+#[no_mangle]
+pub extern "C" fn js_memory_allocation_stats() -> f64 {
+    // Read every counter before building the result, which allocates itself
+    let totals = [AllocKind::Object, AllocKind::String, AllocKind::Array, AllocKind::Closure].map(|kind| {
+        (
+            ALLOC_COUNTS[kind as usize].load(Ordering::Relaxed) as f64,
+            ALLOC_BYTES[kind as usize].load(Ordering::Relaxed) as f64,
+        )
+    });
+    let kinds = totals.map(|(count, bytes)| boxed_object(&[("count", count), ("bytes", bytes)]));
+    boxed_object(&[
+        ("allocator", boxed_string(&allocator_name())),
+        ("objects", kinds[0]),
+        ("strings", kinds[1]),
+        ("arrays", kinds[2]),
+        ("closures", kinds[3]),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("1048576"), Some(1048576));
+        assert_eq!(parse_size("512K"), Some(512 * 1024));
+        assert_eq!(parse_size("256m"), Some(256 * 1024 * 1024));
+        assert_eq!(parse_size("2G"), Some(2 * 1024 * 1024 * 1024));
+        assert_eq!(parse_size("lots"), None);
+        assert_eq!(parse_size(""), None);
+        assert_eq!(parse_size("99999999999G"), None);
+        assert_eq!(parse_size("18446744073709551616"), None);
+    }
+
+    #[test]
+    fn test_parse_policy() {
+        assert_eq!(parse_policy("throw"), Some(POLICY_THROW));
+        assert_eq!(parse_policy("abort"), Some(POLICY_ABORT));
+        assert_eq!(parse_policy("panic"), None);
+    }
+
+    #[test]
+    fn test_count_allocation() {
+        let count = ALLOC_COUNTS[AllocKind::Closure as usize].load(Ordering::Relaxed);
+        let bytes = ALLOC_BYTES[AllocKind::Closure as usize].load(Ordering::Relaxed);
+        count_allocation(AllocKind::Closure, 48);
+        count_growth(AllocKind::Closure, 16);
+        assert!(ALLOC_COUNTS[AllocKind::Closure as usize].load(Ordering::Relaxed) > count);
+        assert!(ALLOC_BYTES[AllocKind::Closure as usize].load(Ordering::Relaxed) >= bytes + 64);
+        assert_eq!(allocator_name(), "system");
+    }
+}
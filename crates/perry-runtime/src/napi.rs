@@ -0,0 +1,1772 @@
+//! N-API host: loading prebuilt Node.js addons (`.node` files)
+//!
+//! `require('./addon.node')` / `import addon from './addon.node'` compile to
+//! `js_napi_load`, which `dlopen`s the addon, runs its registration function with a
+//! fresh exports object and returns the exports. The `napi_*` functions below are the
+//! part of the Node-API C interface addons call back into; the executable exports them
+//! (it is linked with `--export-dynamic` when a module loads an addon).
+//!
+//! A `napi_value` is the NaN-boxed value itself (bit-inverted so that `0.0` isn't a null
+//! pointer), so values never need a handle scope and stay valid for as long as the value
+//! does. Functions an addon creates are closures of arity 8 around `napi_trampoline`;
+//! `napi_get_cb_info` reports the arguments up to the last one that isn't `undefined`,
+//! and `this` is always `undefined`.
+//!
+//! Not supported: classes (`napi_define_class`), property getters/setters, thread-safe
+//! functions and typed arrays. Async work runs synchronously when it is queued, so
+//! promise-returning addons work but block until they are done.
+
+#![allow(non_camel_case_types)]
+
+use std::collections::BTreeMap;
+use std::ffi::{c_char, c_int, c_void, CStr};
+use std::sync::Mutex;
+
+use crate::array::{js_array_alloc, js_array_get_f64, js_array_length, js_array_push_f64, js_array_set_f64, ArrayHeader};
+use crate::buffer::{js_buffer_alloc, BufferHeader};
+use crate::closure::{js_closure_alloc, js_closure_get_capture_ptr, js_closure_set_arity, js_closure_set_capture_ptr, ClosureHeader, CLOSURE_MAGIC};
+use crate::error::{js_error_new_with_message, OBJECT_TYPE_REGULAR};
+use crate::object::{js_object_alloc, js_object_delete_field, js_object_get_field, js_object_get_field_by_name, js_object_keys, js_object_set_field, js_object_set_field_by_name, ObjectHeader};
+use crate::promise::{js_promise_new, js_promise_reject, js_promise_resolve, Promise};
+use crate::string::{js_string_equals, js_string_from_bytes, StringHeader};
+use crate::value::{js_jsvalue_to_string, JSValue};
+
+pub type napi_status = c_int;
+pub type napi_valuetype = c_int;
+pub type napi_env = *mut Env;
+pub type napi_value = *mut c_void;
+pub type napi_callback_info = *mut CallbackInfo;
+pub type napi_ref = *mut Reference;
+pub type napi_deferred = *mut Promise;
+pub type napi_handle_scope = *mut c_void;
+pub type napi_async_work = *mut AsyncWork;
+pub type napi_callback = Option<unsafe extern "C" fn(napi_env, napi_callback_info) -> napi_value>;
+pub type napi_finalize = Option<unsafe extern "C" fn(napi_env, *mut c_void, *mut c_void)>;
+pub type napi_async_execute_callback = Option<unsafe extern "C" fn(napi_env, *mut c_void)>;
+pub type napi_async_complete_callback = Option<unsafe extern "C" fn(napi_env, napi_status, *mut c_void)>;
+pub type napi_addon_register_func = Option<unsafe extern "C" fn(napi_env, napi_value) -> napi_value>;
+
+pub const NAPI_OK: napi_status = 0;
+pub const NAPI_INVALID_ARG: napi_status = 1;
+pub const NAPI_OBJECT_EXPECTED: napi_status = 2;
+pub const NAPI_STRING_EXPECTED: napi_status = 3;
+pub const NAPI_NAME_EXPECTED: napi_status = 4;
+pub const NAPI_FUNCTION_EXPECTED: napi_status = 5;
+pub const NAPI_NUMBER_EXPECTED: napi_status = 6;
+pub const NAPI_BOOLEAN_EXPECTED: napi_status = 7;
+pub const NAPI_ARRAY_EXPECTED: napi_status = 8;
+pub const NAPI_GENERIC_FAILURE: napi_status = 9;
+pub const NAPI_PENDING_EXCEPTION: napi_status = 10;
+
+const NAPI_UNDEFINED: napi_valuetype = 0;
+const NAPI_NULL: napi_valuetype = 1;
+const NAPI_BOOLEAN: napi_valuetype = 2;
+const NAPI_NUMBER: napi_valuetype = 3;
+const NAPI_STRING: napi_valuetype = 4;
+const NAPI_OBJECT: napi_valuetype = 6;
+const NAPI_FUNCTION: napi_valuetype = 7;
+const NAPI_EXTERNAL: napi_valuetype = 8;
+const NAPI_BIGINT: napi_valuetype = 9;
+
+/// Highest Node-API version the host implements enough of
+const NAPI_VERSION: u32 = 8;
+
+/// `length` argument meaning "NUL-terminated"
+const NAPI_AUTO_LENGTH: usize = usize::MAX;
+
+/// Objects created for addons have room for this many properties
+const OBJECT_FIELDS: u32 = 64;
+
+/// Special class ID for objects wrapping a `napi_create_external` pointer (in field 0)
+pub const NAPI_EXTERNAL_CLASS_ID: u32 = 0xFFFFFFFC;
+
+/// Number of arguments a function created by an addon receives
+const CALLBACK_ARITY: usize = 8;
+
+/// Per-addon state behind a `napi_env`
+pub struct Env {
+    last_error: napi_extended_error_info,
+    pending_exception: Option<f64>,
+    instance_data: *mut c_void,
+}
+
+#[repr(C)]
+pub struct napi_extended_error_info {
+    error_message: *const c_char,
+    engine_reserved: *mut c_void,
+    engine_error_code: u32,
+    error_code: napi_status,
+}
+
+/// Arguments of a call into an addon function
+pub struct CallbackInfo {
+    this: f64,
+    args: Vec<f64>,
+    data: *mut c_void,
+}
+
+pub struct Reference {
+    value: f64,
+    count: u32,
+}
+
+pub struct AsyncWork {
+    execute: napi_async_execute_callback,
+    complete: napi_async_complete_callback,
+    data: *mut c_void,
+}
+
+#[repr(C)]
+pub struct napi_property_descriptor {
+    utf8name: *const c_char,
+    name: napi_value,
+    method: napi_callback,
+    getter: napi_callback,
+    setter: napi_callback,
+    value: napi_value,
+    attributes: c_int,
+    data: *mut c_void,
+}
+
+/// Legacy registration record, passed to `napi_module_register` by addons that don't
+/// export `napi_register_module_v1`
+#[repr(C)]
+pub struct napi_module {
+    nm_version: c_int,
+    nm_flags: u32,
+    nm_filename: *const c_char,
+    nm_register_func: napi_addon_register_func,
+    nm_modname: *const c_char,
+    nm_priv: *mut c_void,
+    reserved: [*mut c_void; 4],
+}
+
+#[repr(C)]
+pub struct napi_node_version {
+    major: u32,
+    minor: u32,
+    patch: u32,
+    release: *const c_char,
+}
+
+struct NodeVersion(napi_node_version);
+// Only read, and the release string is static
+unsafe impl Sync for NodeVersion {}
+
+static NODE_VERSION: NodeVersion = NodeVersion(napi_node_version {
+    major: 18,
+    minor: 0,
+    patch: 0,
+    release: c"perry".as_ptr(),
+});
+
+/// Exports of loaded addons by path (addons are loaded once, like `require`)
+static LOADED: Mutex<BTreeMap<String, u64>> = Mutex::new(BTreeMap::new());
+/// Register function from the last `napi_module_register` call
+static REGISTERED: Mutex<Option<usize>> = Mutex::new(None);
+/// `napi_wrap` pointers by object
+static WRAPPED: Mutex<BTreeMap<u64, usize>> = Mutex::new(BTreeMap::new());
+/// Object returned by `napi_get_global`
+static GLOBAL: Mutex<Option<u64>> = Mutex::new(None);
+
+fn to_napi(value: f64) -> napi_value {
+    !value.to_bits() as usize as napi_value
+}
+
+fn from_napi(value: napi_value) -> f64 {
+    f64::from_bits(!(value as usize as u64))
+}
+
+fn jsval(value: napi_value) -> JSValue {
+    JSValue::from_bits(from_napi(value).to_bits())
+}
+
+fn boxed(value: JSValue) -> f64 {
+    f64::from_bits(value.bits())
+}
+
+fn undefined() -> f64 {
+    boxed(JSValue::undefined())
+}
+
+unsafe fn status(env: napi_env, status: napi_status) -> napi_status {
+    if !env.is_null() {
+        (*env).last_error.error_code = status;
+        (*env).last_error.error_message = match status {
+            NAPI_OK => std::ptr::null(),
+            NAPI_INVALID_ARG => c"Invalid argument".as_ptr(),
+            NAPI_OBJECT_EXPECTED => c"An object was expected".as_ptr(),
+            NAPI_STRING_EXPECTED => c"A string was expected".as_ptr(),
+            NAPI_NAME_EXPECTED => c"A string or symbol was expected".as_ptr(),
+            NAPI_FUNCTION_EXPECTED => c"A function was expected".as_ptr(),
+            NAPI_NUMBER_EXPECTED => c"A number was expected".as_ptr(),
+            NAPI_BOOLEAN_EXPECTED => c"A boolean was expected".as_ptr(),
+            NAPI_ARRAY_EXPECTED => c"An array was expected".as_ptr(),
+            NAPI_PENDING_EXCEPTION => c"An exception is pending".as_ptr(),
+            _ => c"Unknown failure".as_ptr(),
+        };
+    }
+    status
+}
+
+/// Fail with `status` unless every out/in pointer is non-null
+macro_rules! check {
+    ($env:expr, $($ptr:expr),+) => {
+        if $env.is_null() {
+            return NAPI_INVALID_ARG;
+        }
+        if $($ptr.is_null())||+ {
+            return status($env, NAPI_INVALID_ARG);
+        }
+    };
+}
+
+/// Store a value in an out pointer and report success
+unsafe fn ok_value(env: napi_env, result: *mut napi_value, value: f64) -> napi_status {
+    *result = to_napi(value);
+    status(env, NAPI_OK)
+}
+
+unsafe fn c_str_bytes<'a>(ptr: *const c_char, len: usize) -> &'a [u8] {
+    if len == NAPI_AUTO_LENGTH {
+        CStr::from_ptr(ptr).to_bytes()
+    } else {
+        std::slice::from_raw_parts(ptr as *const u8, len)
+    }
+}
+
+fn new_string(bytes: &[u8]) -> f64 {
+    boxed(JSValue::string_ptr(js_string_from_bytes(bytes.as_ptr(), bytes.len() as u32)))
+}
+
+unsafe fn string_bytes<'a>(value: JSValue) -> Option<&'a [u8]> {
+    if !value.is_string() {
+        return None;
+    }
+    let ptr = value.as_string_ptr();
+    let data = (ptr as *const u8).add(std::mem::size_of::<StringHeader>());
+    Some(std::slice::from_raw_parts(data, (*ptr).length as usize))
+}
+
+fn number_of(value: JSValue) -> Option<f64> {
+    if value.is_int32() {
+        Some(value.as_int32() as f64)
+    } else if value.is_number() {
+        Some(value.as_number())
+    } else {
+        None
+    }
+}
+
+unsafe fn is_closure(value: JSValue) -> bool {
+    let ptr = value.as_pointer::<ClosureHeader>();
+    value.is_pointer() && !ptr.is_null() && (*ptr).type_tag == CLOSURE_MAGIC
+}
+
+/// The object behind a value, if it is a plain object (not a closure, array or error).
+/// Like the rest of the runtime this goes by the header's type tag, which a one-element
+/// array shares
+unsafe fn as_object(value: JSValue) -> Option<*mut ObjectHeader> {
+    if !value.is_pointer() || is_closure(value) {
+        return None;
+    }
+    let obj = value.as_pointer::<ObjectHeader>() as *mut ObjectHeader;
+    (!obj.is_null() && (*obj).object_type == OBJECT_TYPE_REGULAR).then_some(obj)
+}
+
+/// The array behind a value: any other heap value (arrays carry no type tag)
+unsafe fn as_array(value: JSValue) -> Option<*mut ArrayHeader> {
+    if !value.is_pointer() || is_closure(value) || as_object(value).is_some() {
+        return None;
+    }
+    let ptr = value.as_pointer::<ArrayHeader>() as *mut ArrayHeader;
+    (!ptr.is_null() && !crate::error::is_error_object(ptr as *const u8)).then_some(ptr)
+}
+
+fn new_object() -> f64 {
+    boxed(JSValue::object_ptr(js_object_alloc(0, OBJECT_FIELDS) as *mut u8))
+}
+
+/// Set a property, refusing to add one past the object's field slots
+unsafe fn set_property(obj: *mut ObjectHeader, key: &[u8], value: f64) -> bool {
+    let key = js_string_from_bytes(key.as_ptr(), key.len() as u32);
+    let keys = (*obj).keys_array;
+    let count = if keys.is_null() { 0 } else { js_array_length(keys) };
+    if count >= (*obj).field_count {
+        let exists = (0..count).any(|i| {
+            let stored = JSValue::from_bits(js_array_get_f64(keys, i).to_bits());
+            stored.is_string() && js_string_equals(key, stored.as_string_ptr())
+        });
+        if !exists {
+            return false;
+        }
+    }
+    js_object_set_field_by_name(obj, key, value);
+    true
+}
+
+unsafe fn get_property(obj: *mut ObjectHeader, key: &[u8]) -> f64 {
+    let key = js_string_from_bytes(key.as_ptr(), key.len() as u32);
+    boxed(js_object_get_field_by_name(obj, key))
+}
+
+unsafe fn has_property(obj: *mut ObjectHeader, key: &[u8]) -> bool {
+    let keys = (*obj).keys_array;
+    if keys.is_null() {
+        return false;
+    }
+    (0..js_array_length(keys)).any(|i| {
+        let stored = JSValue::from_bits(js_array_get_f64(keys, i).to_bits());
+        string_bytes(stored) == Some(key)
+    })
+}
+
+/// Property key of a `napi_value`: strings as they are, anything else stringified
+unsafe fn key_bytes(key: napi_value) -> Vec<u8> {
+    let value = jsval(key);
+    match string_bytes(value) {
+        Some(bytes) => bytes.to_vec(),
+        None => {
+            let s = js_jsvalue_to_string(from_napi(key));
+            string_bytes(JSValue::string_ptr(s)).unwrap_or_default().to_vec()
+        }
+    }
+}
+
+fn new_error(name: &str, message: f64) -> f64 {
+    let message = JSValue::from_bits(message.to_bits());
+    let message = if message.is_string() {
+        message.as_string_ptr() as *mut StringHeader
+    } else {
+        js_string_from_bytes(std::ptr::null(), 0)
+    };
+    let error = js_error_new_with_message(message);
+    unsafe {
+        (*error).name = js_string_from_bytes(name.as_ptr(), name.len() as u32);
+    }
+    boxed(JSValue::pointer(error as *const u8))
+}
+
+fn throw_error(message: &str) -> ! {
+    crate::exception::js_throw(new_error("Error", new_string(message.as_bytes())))
+}
+
+/// Entry point of every function an addon creates: captures are (env, callback, data)
+unsafe extern "C" fn napi_trampoline(
+    closure: *const ClosureHeader,
+    a0: f64, a1: f64, a2: f64, a3: f64, a4: f64, a5: f64, a6: f64, a7: f64,
+) -> f64 {
+    let env = js_closure_get_capture_ptr(closure, 0) as napi_env;
+    let callback: napi_callback = std::mem::transmute(js_closure_get_capture_ptr(closure, 1));
+    let data = js_closure_get_capture_ptr(closure, 2) as *mut c_void;
+    let mut args = vec![a0, a1, a2, a3, a4, a5, a6, a7];
+    // Missing arguments arrive as undefined; report the ones that were passed
+    while args.last().is_some_and(|a| JSValue::from_bits(a.to_bits()).is_undefined()) {
+        args.pop();
+    }
+    call_native(env, callback, CallbackInfo { this: undefined(), args, data })
+}
+
+/// Call into the addon and rethrow whatever it left pending
+unsafe fn call_native(env: napi_env, callback: napi_callback, mut info: CallbackInfo) -> f64 {
+    let Some(callback) = callback else { return undefined() };
+    let result = callback(env, &mut info);
+    if let Some(exception) = (*env).pending_exception.take() {
+        crate::exception::js_throw(exception);
+    }
+    if result.is_null() { undefined() } else { from_napi(result) }
+}
+
+unsafe fn new_function(env: napi_env, callback: napi_callback, data: *mut c_void) -> f64 {
+    let closure = js_closure_alloc(napi_trampoline as *const u8, 3);
+    js_closure_set_capture_ptr(closure, 0, env as i64);
+    js_closure_set_capture_ptr(closure, 1, callback.map_or(0, |f| f as usize as i64));
+    js_closure_set_capture_ptr(closure, 2, data as i64);
+    js_closure_set_arity(closure, CALLBACK_ARITY as u32);
+    boxed(JSValue::pointer(closure as *const u8))
+}
+
+/// Load an N-API addon and return its exports, throwing if it can't be loaded.
+/// A path that doesn't exist is also looked up next to the executable.
+#[no_mangle]
+pub unsafe extern "C" fn js_napi_load(path_ptr: *const u8, path_len: usize) -> f64 {
+    keep_exports();
+    let path = String::from_utf8_lossy(std::slice::from_raw_parts(path_ptr, path_len)).to_string();
+    if let Some(bits) = LOADED.lock().unwrap().get(&path) {
+        return f64::from_bits(*bits);
+    }
+    match load_addon(&path) {
+        Ok(exports) => {
+            LOADED.lock().unwrap().insert(path, exports.to_bits());
+            exports
+        }
+        Err(message) => throw_error(&message),
+    }
+}
+
+/// Candidate locations of an addon: the path itself, then the executable's directory
+fn addon_candidates(path: &str) -> Vec<std::path::PathBuf> {
+    let path = std::path::Path::new(path);
+    let mut candidates = vec![path.to_path_buf()];
+    if let (Some(name), Some(exe_dir)) = (
+        path.file_name(),
+        std::env::current_exe().ok().and_then(|exe| exe.parent().map(|dir| dir.to_path_buf())),
+    ) {
+        candidates.push(exe_dir.join(name));
+    }
+    candidates
+}
+
+#[cfg(unix)]
+unsafe fn load_addon(path: &str) -> Result<f64, String> {
+    let file = addon_candidates(path)
+        .into_iter()
+        .find(|candidate| candidate.is_file())
+        .ok_or_else(|| format!("Cannot find native addon '{}'", path))?;
+    let c_path = std::ffi::CString::new(file.to_string_lossy().as_bytes()).map_err(|e| e.to_string())?;
+
+    *REGISTERED.lock().unwrap() = None;
+    // Lazy binding: an addon that references a function the host lacks still loads and
+    // only fails if it calls it
+    let handle = libc::dlopen(c_path.as_ptr(), libc::RTLD_LAZY | libc::RTLD_LOCAL);
+    if handle.is_null() {
+        let reason = CStr::from_ptr(libc::dlerror()).to_string_lossy().to_string();
+        return Err(format!("Failed to load native addon '{}': {}", file.display(), reason));
+    }
+    let symbol = libc::dlsym(handle, c"napi_register_module_v1".as_ptr());
+    let register: napi_addon_register_func = if !symbol.is_null() {
+        Some(std::mem::transmute::<*mut c_void, unsafe extern "C" fn(napi_env, napi_value) -> napi_value>(symbol))
+    } else {
+        // Older addons register from a static constructor that ran during dlopen
+        REGISTERED.lock().unwrap().take().map(|f| {
+            std::mem::transmute::<usize, unsafe extern "C" fn(napi_env, napi_value) -> napi_value>(f)
+        })
+    };
+    let Some(register) = register else {
+        return Err(format!("'{}' is not a Node-API addon", file.display()));
+    };
+
+    let env = Box::into_raw(Box::new(Env {
+        last_error: napi_extended_error_info {
+            error_message: std::ptr::null(),
+            engine_reserved: std::ptr::null_mut(),
+            engine_error_code: 0,
+            error_code: NAPI_OK,
+        },
+        pending_exception: None,
+        instance_data: std::ptr::null_mut(),
+    }));
+    let exports = new_object();
+    let returned = register(env, to_napi(exports));
+    if let Some(exception) = (*env).pending_exception.take() {
+        crate::exception::js_throw(exception);
+    }
+    Ok(if returned.is_null() { exports } else { from_napi(returned) })
+}
+
+#[cfg(not(unix))]
+unsafe fn load_addon(path: &str) -> Result<f64, String> {
+    Err(format!("Cannot load native addon '{}': Node-API addons are only supported on Unix", path))
+}
+
+/// Reference every exported N-API function so the linker keeps them for addons to find
+fn keep_exports() {
+    std::hint::black_box([
+        napi_module_register as *const (),
+        napi_get_last_error_info as *const (),
+        napi_get_undefined as *const (),
+        napi_get_null as *const (),
+        napi_get_global as *const (),
+        napi_get_boolean as *const (),
+        napi_create_object as *const (),
+        napi_create_array as *const (),
+        napi_create_array_with_length as *const (),
+        napi_create_double as *const (),
+        napi_create_int32 as *const (),
+        napi_create_uint32 as *const (),
+        napi_create_int64 as *const (),
+        napi_create_string_utf8 as *const (),
+        napi_create_string_latin1 as *const (),
+        napi_create_string_utf16 as *const (),
+        napi_create_function as *const (),
+        napi_create_error as *const (),
+        napi_create_type_error as *const (),
+        napi_create_range_error as *const (),
+        napi_create_external as *const (),
+        napi_typeof as *const (),
+        napi_get_value_double as *const (),
+        napi_get_value_int32 as *const (),
+        napi_get_value_uint32 as *const (),
+        napi_get_value_int64 as *const (),
+        napi_get_value_bool as *const (),
+        napi_get_value_string_utf8 as *const (),
+        napi_get_value_string_utf16 as *const (),
+        napi_get_value_external as *const (),
+        napi_coerce_to_bool as *const (),
+        napi_coerce_to_number as *const (),
+        napi_coerce_to_string as *const (),
+        napi_set_property as *const (),
+        napi_get_property as *const (),
+        napi_has_property as *const (),
+        napi_delete_property as *const (),
+        napi_set_named_property as *const (),
+        napi_get_named_property as *const (),
+        napi_has_named_property as *const (),
+        napi_get_property_names as *const (),
+        napi_define_properties as *const (),
+        napi_define_class as *const (),
+        napi_set_element as *const (),
+        napi_get_element as *const (),
+        napi_has_element as *const (),
+        napi_get_array_length as *const (),
+        napi_is_array as *const (),
+        napi_is_error as *const (),
+        napi_strict_equals as *const (),
+        napi_get_cb_info as *const (),
+        napi_get_new_target as *const (),
+        napi_call_function as *const (),
+        napi_throw as *const (),
+        napi_throw_error as *const (),
+        napi_throw_type_error as *const (),
+        napi_throw_range_error as *const (),
+        napi_is_exception_pending as *const (),
+        napi_get_and_clear_last_exception as *const (),
+        napi_fatal_error as *const (),
+        napi_open_handle_scope as *const (),
+        napi_close_handle_scope as *const (),
+        napi_open_escapable_handle_scope as *const (),
+        napi_close_escapable_handle_scope as *const (),
+        napi_escape_handle as *const (),
+        napi_create_reference as *const (),
+        napi_delete_reference as *const (),
+        napi_reference_ref as *const (),
+        napi_reference_unref as *const (),
+        napi_get_reference_value as *const (),
+        napi_wrap as *const (),
+        napi_unwrap as *const (),
+        napi_remove_wrap as *const (),
+        napi_add_finalizer as *const (),
+        napi_create_buffer as *const (),
+        napi_create_buffer_copy as *const (),
+        napi_get_buffer_info as *const (),
+        napi_is_buffer as *const (),
+        napi_create_promise as *const (),
+        napi_resolve_deferred as *const (),
+        napi_reject_deferred as *const (),
+        napi_is_promise as *const (),
+        napi_create_async_work as *const (),
+        napi_queue_async_work as *const (),
+        napi_delete_async_work as *const (),
+        napi_cancel_async_work as *const (),
+        napi_set_instance_data as *const (),
+        napi_get_instance_data as *const (),
+        napi_add_env_cleanup_hook as *const (),
+        napi_remove_env_cleanup_hook as *const (),
+        napi_adjust_external_memory as *const (),
+        napi_get_version as *const (),
+        napi_get_node_version as *const (),
+    ]);
+}
+
+// ============================================================================
+// Module registration and errors
+// ============================================================================
+
+#[no_mangle]
+pub unsafe extern "C" fn napi_module_register(module: *mut napi_module) {
+    if let Some(register) = module.as_ref().and_then(|m| m.nm_register_func) {
+        *REGISTERED.lock().unwrap() = Some(register as usize);
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn napi_get_last_error_info(env: napi_env, result: *mut *const napi_extended_error_info) -> napi_status {
+    check!(env, result);
+    *result = &(*env).last_error;
+    NAPI_OK
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn napi_fatal_error(location: *const c_char, location_len: usize, message: *const c_char, message_len: usize) -> ! {
+    let location = if location.is_null() { &[][..] } else { c_str_bytes(location, location_len) };
+    let message = if message.is_null() { &[][..] } else { c_str_bytes(message, message_len) };
+    eprintln!(
+        "FATAL ERROR: {} {}",
+        String::from_utf8_lossy(location),
+        String::from_utf8_lossy(message)
+    );
+    std::process::abort();
+}
+
+// ============================================================================
+// Creating values
+// ============================================================================
+
+#[no_mangle]
+pub unsafe extern "C" fn napi_get_undefined(env: napi_env, result: *mut napi_value) -> napi_status {
+    check!(env, result);
+    ok_value(env, result, undefined())
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn napi_get_null(env: napi_env, result: *mut napi_value) -> napi_status {
+    check!(env, result);
+    ok_value(env, result, boxed(JSValue::null()))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn napi_get_global(env: napi_env, result: *mut napi_value) -> napi_status {
+    check!(env, result);
+    let global = *GLOBAL.lock().unwrap().get_or_insert_with(|| new_object().to_bits());
+    ok_value(env, result, f64::from_bits(global))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn napi_get_boolean(env: napi_env, value: bool, result: *mut napi_value) -> napi_status {
+    check!(env, result);
+    ok_value(env, result, boxed(JSValue::bool(value)))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn napi_create_object(env: napi_env, result: *mut napi_value) -> napi_status {
+    check!(env, result);
+    ok_value(env, result, new_object())
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn napi_create_array(env: napi_env, result: *mut napi_value) -> napi_status {
+    napi_create_array_with_length(env, 0, result)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn napi_create_array_with_length(env: napi_env, length: usize, result: *mut napi_value) -> napi_status {
+    check!(env, result);
+    // Arrays can't move once the addon holds them, so reserve room to grow into
+    let arr = js_array_alloc((length as u32).max(16));
+    for _ in 0..length {
+        js_array_push_f64(arr, undefined());
+    }
+    ok_value(env, result, boxed(JSValue::array_ptr(arr)))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn napi_create_double(env: napi_env, value: f64, result: *mut napi_value) -> napi_status {
+    check!(env, result);
+    ok_value(env, result, boxed(JSValue::number(value)))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn napi_create_int32(env: napi_env, value: i32, result: *mut napi_value) -> napi_status {
+    napi_create_double(env, value as f64, result)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn napi_create_uint32(env: napi_env, value: u32, result: *mut napi_value) -> napi_status {
+    napi_create_double(env, value as f64, result)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn napi_create_int64(env: napi_env, value: i64, result: *mut napi_value) -> napi_status {
+    napi_create_double(env, value as f64, result)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn napi_create_string_utf8(env: napi_env, s: *const c_char, length: usize, result: *mut napi_value) -> napi_status {
+    check!(env, result);
+    if s.is_null() && length != 0 {
+        return status(env, NAPI_INVALID_ARG);
+    }
+    let bytes = if s.is_null() { &[][..] } else { c_str_bytes(s, length) };
+    // Invalid sequences become U+FFFD, as in Node
+    ok_value(env, result, new_string(String::from_utf8_lossy(bytes).as_bytes()))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn napi_create_string_latin1(env: napi_env, s: *const c_char, length: usize, result: *mut napi_value) -> napi_status {
+    check!(env, result);
+    if s.is_null() && length != 0 {
+        return status(env, NAPI_INVALID_ARG);
+    }
+    let bytes = if s.is_null() { &[][..] } else { c_str_bytes(s, length) };
+    let text: String = bytes.iter().map(|&b| b as char).collect();
+    ok_value(env, result, new_string(text.as_bytes()))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn napi_create_string_utf16(env: napi_env, s: *const u16, length: usize, result: *mut napi_value) -> napi_status {
+    check!(env, result);
+    if s.is_null() && length != 0 {
+        return status(env, NAPI_INVALID_ARG);
+    }
+    let units = if s.is_null() {
+        &[][..]
+    } else if length == NAPI_AUTO_LENGTH {
+        let mut len = 0;
+        while *s.add(len) != 0 {
+            len += 1;
+        }
+        std::slice::from_raw_parts(s, len)
+    } else {
+        std::slice::from_raw_parts(s, length)
+    };
+    ok_value(env, result, new_string(String::from_utf16_lossy(units).as_bytes()))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn napi_create_function(
+    env: napi_env,
+    _utf8name: *const c_char,
+    _length: usize,
+    cb: napi_callback,
+    data: *mut c_void,
+    result: *mut napi_value,
+) -> napi_status {
+    check!(env, result);
+    if cb.is_none() {
+        return status(env, NAPI_INVALID_ARG);
+    }
+    ok_value(env, result, new_function(env, cb, data))
+}
+
+unsafe fn create_error(env: napi_env, name: &str, msg: napi_value, result: *mut napi_value) -> napi_status {
+    check!(env, msg, result);
+    if !jsval(msg).is_string() {
+        return status(env, NAPI_STRING_EXPECTED);
+    }
+    ok_value(env, result, new_error(name, from_napi(msg)))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn napi_create_error(env: napi_env, _code: napi_value, msg: napi_value, result: *mut napi_value) -> napi_status {
+    create_error(env, "Error", msg, result)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn napi_create_type_error(env: napi_env, _code: napi_value, msg: napi_value, result: *mut napi_value) -> napi_status {
+    create_error(env, "TypeError", msg, result)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn napi_create_range_error(env: napi_env, _code: napi_value, msg: napi_value, result: *mut napi_value) -> napi_status {
+    create_error(env, "RangeError", msg, result)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn napi_create_external(
+    env: napi_env,
+    data: *mut c_void,
+    _finalize_cb: napi_finalize,
+    _finalize_hint: *mut c_void,
+    result: *mut napi_value,
+) -> napi_status {
+    check!(env, result);
+    let obj = js_object_alloc(NAPI_EXTERNAL_CLASS_ID, 1);
+    js_object_set_field(obj, 0, JSValue::from_bits(data as u64));
+    ok_value(env, result, boxed(JSValue::object_ptr(obj as *mut u8)))
+}
+
+// ============================================================================
+// Reading values
+// ============================================================================
+
+unsafe fn external_data(value: JSValue) -> Option<*mut c_void> {
+    let obj = as_object(value)?;
+    ((*obj).class_id == NAPI_EXTERNAL_CLASS_ID).then(|| js_object_get_field(obj, 0).bits() as *mut c_void)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn napi_typeof(env: napi_env, value: napi_value, result: *mut napi_valuetype) -> napi_status {
+    check!(env, value, result);
+    let v = jsval(value);
+    *result = if v.is_undefined() {
+        NAPI_UNDEFINED
+    } else if v.is_null() {
+        NAPI_NULL
+    } else if v.is_bool() {
+        NAPI_BOOLEAN
+    } else if v.is_string() {
+        NAPI_STRING
+    } else if v.is_bigint() {
+        NAPI_BIGINT
+    } else if is_closure(v) {
+        NAPI_FUNCTION
+    } else if external_data(v).is_some() {
+        NAPI_EXTERNAL
+    } else if v.is_pointer() {
+        NAPI_OBJECT
+    } else {
+        NAPI_NUMBER
+    };
+    status(env, NAPI_OK)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn napi_get_value_double(env: napi_env, value: napi_value, result: *mut f64) -> napi_status {
+    check!(env, value, result);
+    match number_of(jsval(value)) {
+        Some(n) => {
+            *result = n;
+            status(env, NAPI_OK)
+        }
+        None => status(env, NAPI_NUMBER_EXPECTED),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn napi_get_value_int32(env: napi_env, value: napi_value, result: *mut i32) -> napi_status {
+    check!(env, value, result);
+    match number_of(jsval(value)) {
+        Some(n) => {
+            // ToInt32: non-finite values are 0, the rest wrap modulo 2^32
+            *result = if n.is_finite() { n as i64 as i32 } else { 0 };
+            status(env, NAPI_OK)
+        }
+        None => status(env, NAPI_NUMBER_EXPECTED),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn napi_get_value_uint32(env: napi_env, value: napi_value, result: *mut u32) -> napi_status {
+    check!(env, value, result);
+    match number_of(jsval(value)) {
+        Some(n) => {
+            *result = if n.is_finite() { n as i64 as u32 } else { 0 };
+            status(env, NAPI_OK)
+        }
+        None => status(env, NAPI_NUMBER_EXPECTED),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn napi_get_value_int64(env: napi_env, value: napi_value, result: *mut i64) -> napi_status {
+    check!(env, value, result);
+    match number_of(jsval(value)) {
+        Some(n) => {
+            *result = if n.is_finite() { n as i64 } else { 0 };
+            status(env, NAPI_OK)
+        }
+        None => status(env, NAPI_NUMBER_EXPECTED),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn napi_get_value_bool(env: napi_env, value: napi_value, result: *mut bool) -> napi_status {
+    check!(env, value, result);
+    let v = jsval(value);
+    if !v.is_bool() {
+        return status(env, NAPI_BOOLEAN_EXPECTED);
+    }
+    *result = v.as_bool();
+    status(env, NAPI_OK)
+}
+
+/// Copy a string into `buf` (NUL-terminated, truncated at a character boundary), or
+/// report its length in bytes when `buf` is null
+#[no_mangle]
+pub unsafe extern "C" fn napi_get_value_string_utf8(
+    env: napi_env,
+    value: napi_value,
+    buf: *mut c_char,
+    bufsize: usize,
+    result: *mut usize,
+) -> napi_status {
+    check!(env, value);
+    let Some(bytes) = string_bytes(jsval(value)) else {
+        return status(env, NAPI_STRING_EXPECTED);
+    };
+    if buf.is_null() {
+        if result.is_null() {
+            return status(env, NAPI_INVALID_ARG);
+        }
+        *result = bytes.len();
+        return status(env, NAPI_OK);
+    }
+    let mut copied = 0;
+    if bufsize > 0 {
+        copied = bytes.len().min(bufsize - 1);
+        while copied > 0 && copied < bytes.len() && (bytes[copied] & 0xC0) == 0x80 {
+            copied -= 1;
+        }
+        std::ptr::copy_nonoverlapping(bytes.as_ptr(), buf as *mut u8, copied);
+        *buf.add(copied) = 0;
+    }
+    if !result.is_null() {
+        *result = copied;
+    }
+    status(env, NAPI_OK)
+}
+
+/// Like `napi_get_value_string_utf8`, in UTF-16 code units
+#[no_mangle]
+pub unsafe extern "C" fn napi_get_value_string_utf16(
+    env: napi_env,
+    value: napi_value,
+    buf: *mut u16,
+    bufsize: usize,
+    result: *mut usize,
+) -> napi_status {
+    check!(env, value);
+    let Some(bytes) = string_bytes(jsval(value)) else {
+        return status(env, NAPI_STRING_EXPECTED);
+    };
+    let units: Vec<u16> = String::from_utf8_lossy(bytes).encode_utf16().collect();
+    if buf.is_null() {
+        if result.is_null() {
+            return status(env, NAPI_INVALID_ARG);
+        }
+        *result = units.len();
+        return status(env, NAPI_OK);
+    }
+    let mut copied = 0;
+    if bufsize > 0 {
+        copied = units.len().min(bufsize - 1);
+        std::ptr::copy_nonoverlapping(units.as_ptr(), buf, copied);
+        *buf.add(copied) = 0;
+    }
+    if !result.is_null() {
+        *result = copied;
+    }
+    status(env, NAPI_OK)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn napi_get_value_external(env: napi_env, value: napi_value, result: *mut *mut c_void) -> napi_status {
+    check!(env, value, result);
+    match external_data(jsval(value)) {
+        Some(data) => {
+            *result = data;
+            status(env, NAPI_OK)
+        }
+        None => status(env, NAPI_INVALID_ARG),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn napi_coerce_to_bool(env: napi_env, value: napi_value, result: *mut napi_value) -> napi_status {
+    check!(env, value, result);
+    ok_value(env, result, boxed(JSValue::bool(jsval(value).to_bool())))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn napi_coerce_to_number(env: napi_env, value: napi_value, result: *mut napi_value) -> napi_status {
+    check!(env, value, result);
+    ok_value(env, result, boxed(JSValue::number(jsval(value).to_number())))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn napi_coerce_to_string(env: napi_env, value: napi_value, result: *mut napi_value) -> napi_status {
+    check!(env, value, result);
+    let s = js_jsvalue_to_string(from_napi(value));
+    ok_value(env, result, boxed(JSValue::string_ptr(s)))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn napi_strict_equals(env: napi_env, lhs: napi_value, rhs: napi_value, result: *mut bool) -> napi_status {
+    check!(env, lhs, rhs, result);
+    let (a, b) = (jsval(lhs), jsval(rhs));
+    *result = match (number_of(a), number_of(b)) {
+        (Some(x), Some(y)) => x == y,
+        _ if a.is_string() && b.is_string() => js_string_equals(a.as_string_ptr(), b.as_string_ptr()),
+        _ => a.bits() == b.bits(),
+    };
+    status(env, NAPI_OK)
+}
+
+// ============================================================================
+// Objects and arrays
+// ============================================================================
+
+#[no_mangle]
+pub unsafe extern "C" fn napi_set_property(env: napi_env, object: napi_value, key: napi_value, value: napi_value) -> napi_status {
+    check!(env, object, key, value);
+    let Some(obj) = as_object(jsval(object)) else {
+        return status(env, NAPI_OBJECT_EXPECTED);
+    };
+    if !set_property(obj, &key_bytes(key), from_napi(value)) {
+        return status(env, NAPI_GENERIC_FAILURE);
+    }
+    status(env, NAPI_OK)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn napi_get_property(env: napi_env, object: napi_value, key: napi_value, result: *mut napi_value) -> napi_status {
+    check!(env, object, key, result);
+    let Some(obj) = as_object(jsval(object)) else {
+        return status(env, NAPI_OBJECT_EXPECTED);
+    };
+    ok_value(env, result, get_property(obj, &key_bytes(key)))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn napi_has_property(env: napi_env, object: napi_value, key: napi_value, result: *mut bool) -> napi_status {
+    check!(env, object, key, result);
+    let Some(obj) = as_object(jsval(object)) else {
+        return status(env, NAPI_OBJECT_EXPECTED);
+    };
+    *result = has_property(obj, &key_bytes(key));
+    status(env, NAPI_OK)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn napi_delete_property(env: napi_env, object: napi_value, key: napi_value, result: *mut bool) -> napi_status {
+    check!(env, object, key);
+    let Some(obj) = as_object(jsval(object)) else {
+        return status(env, NAPI_OBJECT_EXPECTED);
+    };
+    let key = key_bytes(key);
+    let deleted = js_object_delete_field(obj, js_string_from_bytes(key.as_ptr(), key.len() as u32)) != 0;
+    if !result.is_null() {
+        *result = deleted;
+    }
+    status(env, NAPI_OK)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn napi_set_named_property(env: napi_env, object: napi_value, utf8name: *const c_char, value: napi_value) -> napi_status {
+    check!(env, object, utf8name, value);
+    let Some(obj) = as_object(jsval(object)) else {
+        return status(env, NAPI_OBJECT_EXPECTED);
+    };
+    if !set_property(obj, CStr::from_ptr(utf8name).to_bytes(), from_napi(value)) {
+        return status(env, NAPI_GENERIC_FAILURE);
+    }
+    status(env, NAPI_OK)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn napi_get_named_property(env: napi_env, object: napi_value, utf8name: *const c_char, result: *mut napi_value) -> napi_status {
+    check!(env, object, utf8name, result);
+    let Some(obj) = as_object(jsval(object)) else {
+        return status(env, NAPI_OBJECT_EXPECTED);
+    };
+    ok_value(env, result, get_property(obj, CStr::from_ptr(utf8name).to_bytes()))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn napi_has_named_property(env: napi_env, object: napi_value, utf8name: *const c_char, result: *mut bool) -> napi_status {
+    check!(env, object, utf8name, result);
+    let Some(obj) = as_object(jsval(object)) else {
+        return status(env, NAPI_OBJECT_EXPECTED);
+    };
+    *result = has_property(obj, CStr::from_ptr(utf8name).to_bytes());
+    status(env, NAPI_OK)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn napi_get_property_names(env: napi_env, object: napi_value, result: *mut napi_value) -> napi_status {
+    check!(env, object, result);
+    let Some(obj) = as_object(jsval(object)) else {
+        return status(env, NAPI_OBJECT_EXPECTED);
+    };
+    ok_value(env, result, boxed(JSValue::array_ptr(js_object_keys(obj))))
+}
+
+/// Define methods and values; accessor descriptors (getter/setter) are not supported
+#[no_mangle]
+pub unsafe extern "C" fn napi_define_properties(
+    env: napi_env,
+    object: napi_value,
+    property_count: usize,
+    properties: *const napi_property_descriptor,
+) -> napi_status {
+    check!(env, object);
+    if property_count > 0 && properties.is_null() {
+        return status(env, NAPI_INVALID_ARG);
+    }
+    let Some(obj) = as_object(jsval(object)) else {
+        return status(env, NAPI_OBJECT_EXPECTED);
+    };
+    for i in 0..property_count {
+        let descriptor = &*properties.add(i);
+        let key = if !descriptor.utf8name.is_null() {
+            CStr::from_ptr(descriptor.utf8name).to_bytes().to_vec()
+        } else if !descriptor.name.is_null() {
+            key_bytes(descriptor.name)
+        } else {
+            return status(env, NAPI_NAME_EXPECTED);
+        };
+        let value = if descriptor.method.is_some() {
+            new_function(env, descriptor.method, descriptor.data)
+        } else if descriptor.getter.is_some() || descriptor.setter.is_some() {
+            return status(env, NAPI_GENERIC_FAILURE);
+        } else if !descriptor.value.is_null() {
+            from_napi(descriptor.value)
+        } else {
+            undefined()
+        };
+        if !set_property(obj, &key, value) {
+            return status(env, NAPI_GENERIC_FAILURE);
+        }
+    }
+    status(env, NAPI_OK)
+}
+
+/// Classes aren't supported (compiled code can't `new` an addon constructor)
+#[no_mangle]
+pub unsafe extern "C" fn napi_define_class(
+    env: napi_env,
+    _utf8name: *const c_char,
+    _length: usize,
+    _constructor: napi_callback,
+    _data: *mut c_void,
+    _property_count: usize,
+    _properties: *const napi_property_descriptor,
+    _result: *mut napi_value,
+) -> napi_status {
+    status(env, NAPI_GENERIC_FAILURE)
+}
+
+/// Set an array element. Arrays can't be reallocated under the addon, so an element past
+/// the array's capacity fails
+#[no_mangle]
+pub unsafe extern "C" fn napi_set_element(env: napi_env, object: napi_value, index: u32, value: napi_value) -> napi_status {
+    check!(env, object, value);
+    let Some(arr) = as_array(jsval(object)) else {
+        return status(env, NAPI_ARRAY_EXPECTED);
+    };
+    if index < js_array_length(arr) {
+        js_array_set_f64(arr, index, from_napi(value));
+    } else if index < (*arr).capacity {
+        while js_array_length(arr) < index {
+            js_array_push_f64(arr, undefined());
+        }
+        js_array_push_f64(arr, from_napi(value));
+    } else {
+        return status(env, NAPI_GENERIC_FAILURE);
+    }
+    status(env, NAPI_OK)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn napi_get_element(env: napi_env, object: napi_value, index: u32, result: *mut napi_value) -> napi_status {
+    check!(env, object, result);
+    let Some(arr) = as_array(jsval(object)) else {
+        return status(env, NAPI_ARRAY_EXPECTED);
+    };
+    let value = if index < js_array_length(arr) { js_array_get_f64(arr, index) } else { undefined() };
+    ok_value(env, result, value)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn napi_has_element(env: napi_env, object: napi_value, index: u32, result: *mut bool) -> napi_status {
+    check!(env, object, result);
+    let Some(arr) = as_array(jsval(object)) else {
+        return status(env, NAPI_ARRAY_EXPECTED);
+    };
+    *result = index < js_array_length(arr);
+    status(env, NAPI_OK)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn napi_get_array_length(env: napi_env, value: napi_value, result: *mut u32) -> napi_status {
+    check!(env, value, result);
+    let Some(arr) = as_array(jsval(value)) else {
+        return status(env, NAPI_ARRAY_EXPECTED);
+    };
+    *result = js_array_length(arr);
+    status(env, NAPI_OK)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn napi_is_array(env: napi_env, value: napi_value, result: *mut bool) -> napi_status {
+    check!(env, value, result);
+    *result = as_array(jsval(value)).is_some();
+    status(env, NAPI_OK)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn napi_is_error(env: napi_env, value: napi_value, result: *mut bool) -> napi_status {
+    check!(env, value, result);
+    let v = jsval(value);
+    *result = v.is_pointer() && !is_closure(v) && crate::error::is_error_object(v.as_pointer::<u8>());
+    status(env, NAPI_OK)
+}
+
+// ============================================================================
+// Functions
+// ============================================================================
+
+#[no_mangle]
+pub unsafe extern "C" fn napi_get_cb_info(
+    env: napi_env,
+    cbinfo: napi_callback_info,
+    argc: *mut usize,
+    argv: *mut napi_value,
+    this_arg: *mut napi_value,
+    data: *mut *mut c_void,
+) -> napi_status {
+    check!(env, cbinfo);
+    let info = &*cbinfo;
+    if !argc.is_null() {
+        if !argv.is_null() {
+            for i in 0..*argc {
+                *argv.add(i) = to_napi(info.args.get(i).copied().unwrap_or_else(undefined));
+            }
+        }
+        *argc = info.args.len();
+    }
+    if !this_arg.is_null() {
+        *this_arg = to_napi(info.this);
+    }
+    if !data.is_null() {
+        *data = info.data;
+    }
+    status(env, NAPI_OK)
+}
+
+/// Addon functions are never constructors, so there is no `new.target`
+#[no_mangle]
+pub unsafe extern "C" fn napi_get_new_target(env: napi_env, cbinfo: napi_callback_info, result: *mut napi_value) -> napi_status {
+    check!(env, cbinfo, result);
+    *result = std::ptr::null_mut();
+    status(env, NAPI_OK)
+}
+
+/// Call a function value. An exception it throws propagates through the addon's frames
+#[no_mangle]
+pub unsafe extern "C" fn napi_call_function(
+    env: napi_env,
+    _recv: napi_value,
+    func: napi_value,
+    argc: usize,
+    argv: *const napi_value,
+    result: *mut napi_value,
+) -> napi_status {
+    check!(env, func);
+    if argc > 0 && argv.is_null() {
+        return status(env, NAPI_INVALID_ARG);
+    }
+    if !is_closure(jsval(func)) {
+        return status(env, NAPI_FUNCTION_EXPECTED);
+    }
+    let args: Vec<f64> = (0..argc).map(|i| from_napi(*argv.add(i))).collect();
+    let value = crate::closure::js_native_call_value(from_napi(func), args.as_ptr(), args.len());
+    if !result.is_null() {
+        *result = to_napi(value);
+    }
+    status(env, NAPI_OK)
+}
+
+// ============================================================================
+// Exceptions
+// ============================================================================
+
+#[no_mangle]
+pub unsafe extern "C" fn napi_throw(env: napi_env, error: napi_value) -> napi_status {
+    check!(env, error);
+    (*env).pending_exception = Some(from_napi(error));
+    status(env, NAPI_OK)
+}
+
+unsafe fn throw_new(env: napi_env, name: &str, msg: *const c_char) -> napi_status {
+    check!(env, msg);
+    let message = new_string(CStr::from_ptr(msg).to_bytes());
+    (*env).pending_exception = Some(new_error(name, message));
+    status(env, NAPI_OK)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn napi_throw_error(env: napi_env, _code: *const c_char, msg: *const c_char) -> napi_status {
+    throw_new(env, "Error", msg)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn napi_throw_type_error(env: napi_env, _code: *const c_char, msg: *const c_char) -> napi_status {
+    throw_new(env, "TypeError", msg)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn napi_throw_range_error(env: napi_env, _code: *const c_char, msg: *const c_char) -> napi_status {
+    throw_new(env, "RangeError", msg)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn napi_is_exception_pending(env: napi_env, result: *mut bool) -> napi_status {
+    check!(env, result);
+    *result = (*env).pending_exception.is_some();
+    status(env, NAPI_OK)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn napi_get_and_clear_last_exception(env: napi_env, result: *mut napi_value) -> napi_status {
+    check!(env, result);
+    let exception = (*env).pending_exception.take().unwrap_or_else(undefined);
+    ok_value(env, result, exception)
+}
+
+// ============================================================================
+// Lifetime: handle scopes, references, wrapping
+// ============================================================================
+// Values are never collected, so scopes are no-ops and references only count.
+
+#[no_mangle]
+pub unsafe extern "C" fn napi_open_handle_scope(env: napi_env, result: *mut napi_handle_scope) -> napi_status {
+    check!(env, result);
+    *result = std::ptr::NonNull::dangling().as_ptr();
+    status(env, NAPI_OK)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn napi_close_handle_scope(env: napi_env, scope: napi_handle_scope) -> napi_status {
+    check!(env, scope);
+    status(env, NAPI_OK)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn napi_open_escapable_handle_scope(env: napi_env, result: *mut napi_handle_scope) -> napi_status {
+    napi_open_handle_scope(env, result)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn napi_close_escapable_handle_scope(env: napi_env, scope: napi_handle_scope) -> napi_status {
+    napi_close_handle_scope(env, scope)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn napi_escape_handle(env: napi_env, scope: napi_handle_scope, escapee: napi_value, result: *mut napi_value) -> napi_status {
+    check!(env, scope, escapee, result);
+    *result = escapee;
+    status(env, NAPI_OK)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn napi_create_reference(env: napi_env, value: napi_value, initial_refcount: u32, result: *mut napi_ref) -> napi_status {
+    check!(env, value, result);
+    *result = Box::into_raw(Box::new(Reference { value: from_napi(value), count: initial_refcount }));
+    status(env, NAPI_OK)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn napi_delete_reference(env: napi_env, reference: napi_ref) -> napi_status {
+    check!(env, reference);
+    drop(Box::from_raw(reference));
+    status(env, NAPI_OK)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn napi_reference_ref(env: napi_env, reference: napi_ref, result: *mut u32) -> napi_status {
+    check!(env, reference);
+    (*reference).count += 1;
+    if !result.is_null() {
+        *result = (*reference).count;
+    }
+    status(env, NAPI_OK)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn napi_reference_unref(env: napi_env, reference: napi_ref, result: *mut u32) -> napi_status {
+    check!(env, reference);
+    if (*reference).count == 0 {
+        return status(env, NAPI_GENERIC_FAILURE);
+    }
+    (*reference).count -= 1;
+    if !result.is_null() {
+        *result = (*reference).count;
+    }
+    status(env, NAPI_OK)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn napi_get_reference_value(env: napi_env, reference: napi_ref, result: *mut napi_value) -> napi_status {
+    check!(env, reference, result);
+    ok_value(env, result, (*reference).value)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn napi_wrap(
+    env: napi_env,
+    js_object: napi_value,
+    native_object: *mut c_void,
+    _finalize_cb: napi_finalize,
+    _finalize_hint: *mut c_void,
+    result: *mut napi_ref,
+) -> napi_status {
+    check!(env, js_object);
+    if as_object(jsval(js_object)).is_none() {
+        return status(env, NAPI_OBJECT_EXPECTED);
+    }
+    let key = from_napi(js_object).to_bits();
+    let mut wrapped = WRAPPED.lock().unwrap();
+    if wrapped.contains_key(&key) {
+        return status(env, NAPI_INVALID_ARG);
+    }
+    wrapped.insert(key, native_object as usize);
+    drop(wrapped);
+    if !result.is_null() {
+        return napi_create_reference(env, js_object, 0, result);
+    }
+    status(env, NAPI_OK)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn napi_unwrap(env: napi_env, js_object: napi_value, result: *mut *mut c_void) -> napi_status {
+    check!(env, js_object, result);
+    match WRAPPED.lock().unwrap().get(&from_napi(js_object).to_bits()) {
+        Some(native) => {
+            *result = *native as *mut c_void;
+            status(env, NAPI_OK)
+        }
+        None => status(env, NAPI_INVALID_ARG),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn napi_remove_wrap(env: napi_env, js_object: napi_value, result: *mut *mut c_void) -> napi_status {
+    check!(env, js_object);
+    match WRAPPED.lock().unwrap().remove(&from_napi(js_object).to_bits()) {
+        Some(native) => {
+            if !result.is_null() {
+                *result = native as *mut c_void;
+            }
+            status(env, NAPI_OK)
+        }
+        None => status(env, NAPI_INVALID_ARG),
+    }
+}
+
+/// Finalizers never run: nothing is collected
+#[no_mangle]
+pub unsafe extern "C" fn napi_add_finalizer(
+    env: napi_env,
+    js_object: napi_value,
+    _finalize_data: *mut c_void,
+    _finalize_cb: napi_finalize,
+    _finalize_hint: *mut c_void,
+    result: *mut napi_ref,
+) -> napi_status {
+    check!(env, js_object);
+    if !result.is_null() {
+        return napi_create_reference(env, js_object, 0, result);
+    }
+    status(env, NAPI_OK)
+}
+
+// ============================================================================
+// Buffers
+// ============================================================================
+
+#[no_mangle]
+pub unsafe extern "C" fn napi_create_buffer(env: napi_env, size: usize, data: *mut *mut c_void, result: *mut napi_value) -> napi_status {
+    check!(env, result);
+    let buffer = js_buffer_alloc(size as i32, 0);
+    if !data.is_null() {
+        *data = (buffer as *mut u8).add(std::mem::size_of::<BufferHeader>()) as *mut c_void;
+    }
+    ok_value(env, result, boxed(JSValue::pointer(buffer as *const u8)))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn napi_create_buffer_copy(
+    env: napi_env,
+    length: usize,
+    data: *const c_void,
+    result_data: *mut *mut c_void,
+    result: *mut napi_value,
+) -> napi_status {
+    check!(env, result);
+    if length > 0 && data.is_null() {
+        return status(env, NAPI_INVALID_ARG);
+    }
+    let mut copy: *mut c_void = std::ptr::null_mut();
+    let created = napi_create_buffer(env, length, &mut copy, result);
+    if created != NAPI_OK {
+        return created;
+    }
+    if length > 0 {
+        std::ptr::copy_nonoverlapping(data as *const u8, copy as *mut u8, length);
+    }
+    if !result_data.is_null() {
+        *result_data = copy;
+    }
+    status(env, NAPI_OK)
+}
+
+/// Buffers carry no type tag: any heap value that isn't a closure, object or error is
+/// taken to be one (as `Buffer.isBuffer` does)
+#[no_mangle]
+pub unsafe extern "C" fn napi_get_buffer_info(env: napi_env, value: napi_value, data: *mut *mut c_void, length: *mut usize) -> napi_status {
+    check!(env, value);
+    let Some(buffer) = as_array(jsval(value)).map(|ptr| ptr as *mut BufferHeader) else {
+        return status(env, NAPI_INVALID_ARG);
+    };
+    if !data.is_null() {
+        *data = (buffer as *mut u8).add(std::mem::size_of::<BufferHeader>()) as *mut c_void;
+    }
+    if !length.is_null() {
+        *length = (*buffer).length as usize;
+    }
+    status(env, NAPI_OK)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn napi_is_buffer(env: napi_env, value: napi_value, result: *mut bool) -> napi_status {
+    check!(env, value, result);
+    *result = as_array(jsval(value)).is_some();
+    status(env, NAPI_OK)
+}
+
+// ============================================================================
+// Promises and async work
+// ============================================================================
+
+#[no_mangle]
+pub unsafe extern "C" fn napi_create_promise(env: napi_env, deferred: *mut napi_deferred, promise: *mut napi_value) -> napi_status {
+    check!(env, deferred, promise);
+    let p = js_promise_new();
+    *deferred = p;
+    ok_value(env, promise, boxed(JSValue::pointer(p as *const u8)))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn napi_resolve_deferred(env: napi_env, deferred: napi_deferred, resolution: napi_value) -> napi_status {
+    check!(env, deferred, resolution);
+    js_promise_resolve(deferred, from_napi(resolution));
+    status(env, NAPI_OK)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn napi_reject_deferred(env: napi_env, deferred: napi_deferred, rejection: napi_value) -> napi_status {
+    check!(env, deferred, rejection);
+    js_promise_reject(deferred, from_napi(rejection));
+    status(env, NAPI_OK)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn napi_is_promise(env: napi_env, value: napi_value, is_promise: *mut bool) -> napi_status {
+    check!(env, value, is_promise);
+    let v = jsval(value);
+    *is_promise = v.is_pointer()
+        && !is_closure(v)
+        && as_object(v).is_none()
+        && crate::promise::js_is_promise(v.as_pointer::<Promise>() as *mut Promise) != 0;
+    status(env, NAPI_OK)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn napi_create_async_work(
+    env: napi_env,
+    _async_resource: napi_value,
+    _async_resource_name: napi_value,
+    execute: napi_async_execute_callback,
+    complete: napi_async_complete_callback,
+    data: *mut c_void,
+    result: *mut napi_async_work,
+) -> napi_status {
+    check!(env, result);
+    if execute.is_none() {
+        return status(env, NAPI_INVALID_ARG);
+    }
+    *result = Box::into_raw(Box::new(AsyncWork { execute, complete, data }));
+    status(env, NAPI_OK)
+}
+
+/// Run the work right away on the calling thread, then its completion
+#[no_mangle]
+pub unsafe extern "C" fn napi_queue_async_work(env: napi_env, work: napi_async_work) -> napi_status {
+    check!(env, work);
+    let (execute, complete, data) = ((*work).execute, (*work).complete, (*work).data);
+    if let Some(execute) = execute {
+        execute(env, data);
+    }
+    if let Some(complete) = complete {
+        complete(env, NAPI_OK, data);
+        if let Some(exception) = (*env).pending_exception.take() {
+            crate::exception::js_throw(exception);
+        }
+    }
+    status(env, NAPI_OK)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn napi_delete_async_work(env: napi_env, work: napi_async_work) -> napi_status {
+    check!(env, work);
+    drop(Box::from_raw(work));
+    status(env, NAPI_OK)
+}
+
+/// Queued work has already run
+#[no_mangle]
+pub unsafe extern "C" fn napi_cancel_async_work(env: napi_env, work: napi_async_work) -> napi_status {
+    check!(env, work);
+    status(env, NAPI_GENERIC_FAILURE)
+}
+
+// ============================================================================
+// Environment
+// ============================================================================
+
+#[no_mangle]
+pub unsafe extern "C" fn napi_set_instance_data(env: napi_env, data: *mut c_void, _finalize_cb: napi_finalize, _finalize_hint: *mut c_void) -> napi_status {
+    check!(env, env);
+    (*env).instance_data = data;
+    status(env, NAPI_OK)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn napi_get_instance_data(env: napi_env, data: *mut *mut c_void) -> napi_status {
+    check!(env, data);
+    *data = (*env).instance_data;
+    status(env, NAPI_OK)
+}
+
+/// Cleanup hooks never run: addons stay loaded until the process exits
+#[no_mangle]
+pub unsafe extern "C" fn napi_add_env_cleanup_hook(env: napi_env, _fun: Option<unsafe extern "C" fn(*mut c_void)>, _arg: *mut c_void) -> napi_status {
+    check!(env, env);
+    status(env, NAPI_OK)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn napi_remove_env_cleanup_hook(env: napi_env, _fun: Option<unsafe extern "C" fn(*mut c_void)>, _arg: *mut c_void) -> napi_status {
+    check!(env, env);
+    status(env, NAPI_OK)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn napi_adjust_external_memory(env: napi_env, _change_in_bytes: i64, adjusted_value: *mut i64) -> napi_status {
+    check!(env, adjusted_value);
+    *adjusted_value = 0;
+    status(env, NAPI_OK)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn napi_get_version(env: napi_env, result: *mut u32) -> napi_status {
+    check!(env, result);
+    *result = NAPI_VERSION;
+    status(env, NAPI_OK)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn napi_get_node_version(env: napi_env, version: *mut *const napi_node_version) -> napi_status {
+    check!(env, version);
+    *version = &NODE_VERSION.0;
+    status(env, NAPI_OK)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_env() -> napi_env {
+        Box::into_raw(Box::new(Env {
+            last_error: napi_extended_error_info {
+                error_message: std::ptr::null(),
+                engine_reserved: std::ptr::null_mut(),
+                engine_error_code: 0,
+                error_code: NAPI_OK,
+            },
+            pending_exception: None,
+            instance_data: std::ptr::null_mut(),
+        }))
+    }
+
+    unsafe extern "C" fn add(env: napi_env, info: napi_callback_info) -> napi_value {
+        let mut argc = 2;
+        let mut argv = [std::ptr::null_mut(); 2];
+        napi_get_cb_info(env, info, &mut argc, argv.as_mut_ptr(), std::ptr::null_mut(), std::ptr::null_mut());
+        let (mut a, mut b) = (0.0, 0.0);
+        napi_get_value_double(env, argv[0], &mut a);
+        if napi_get_value_double(env, argv[1], &mut b) != NAPI_OK {
+            napi_throw_type_error(env, std::ptr::null(), c"b must be a number".as_ptr());
+            return std::ptr::null_mut();
+        }
+        let mut result = std::ptr::null_mut();
+        napi_create_double(env, a + b + argc as f64 * 100.0, &mut result);
+        result
+    }
+
+    #[test]
+    fn test_values_round_trip() {
+        unsafe {
+            let env = test_env();
+            let mut value = std::ptr::null_mut();
+            napi_create_double(env, 0.0, &mut value);
+            assert!(!value.is_null());
+            let mut kind = 0;
+            napi_typeof(env, value, &mut kind);
+            assert_eq!(kind, NAPI_NUMBER);
+
+            napi_create_string_utf8(env, c"héllo".as_ptr(), NAPI_AUTO_LENGTH, &mut value);
+            let mut len = 0;
+            napi_get_value_string_utf8(env, value, std::ptr::null_mut(), 0, &mut len);
+            assert_eq!(len, 6);
+            // Truncation keeps whole characters
+            let mut buf = [0 as c_char; 3];
+            napi_get_value_string_utf8(env, value, buf.as_mut_ptr(), buf.len(), &mut len);
+            assert_eq!(len, 1);
+            assert_eq!(CStr::from_ptr(buf.as_ptr()).to_bytes(), b"h");
+
+            let mut flag = false;
+            assert_eq!(napi_get_value_bool(env, value, &mut flag), NAPI_BOOLEAN_EXPECTED);
+            let mut info = std::ptr::null();
+            napi_get_last_error_info(env, &mut info);
+            assert_eq!((*info).error_code, NAPI_BOOLEAN_EXPECTED);
+        }
+    }
+
+    #[test]
+    fn test_objects_and_arrays() {
+        unsafe {
+            let env = test_env();
+            let (mut obj, mut value, mut out) = (std::ptr::null_mut(), std::ptr::null_mut(), std::ptr::null_mut());
+            napi_create_object(env, &mut obj);
+            napi_create_int32(env, 7, &mut value);
+            assert_eq!(napi_set_named_property(env, obj, c"seven".as_ptr(), value), NAPI_OK);
+            napi_get_named_property(env, obj, c"seven".as_ptr(), &mut out);
+            let mut n = 0;
+            napi_get_value_int32(env, out, &mut n);
+            assert_eq!(n, 7);
+            let mut has = false;
+            napi_has_named_property(env, obj, c"eight".as_ptr(), &mut has);
+            assert!(!has);
+
+            let mut arr = std::ptr::null_mut();
+            napi_create_array_with_length(env, 2, &mut arr);
+            napi_set_element(env, arr, 3, value);
+            let mut len = 0;
+            napi_get_array_length(env, arr, &mut len);
+            assert_eq!(len, 4);
+            napi_get_element(env, arr, 1, &mut out);
+            let mut kind = 0;
+            napi_typeof(env, out, &mut kind);
+            assert_eq!(kind, NAPI_UNDEFINED);
+            let mut is_array = false;
+            napi_is_array(env, obj, &mut is_array);
+            assert!(!is_array);
+        }
+    }
+
+    #[test]
+    fn test_functions_called_from_compiled_code() {
+        unsafe {
+            let env = test_env();
+            let mut func = std::ptr::null_mut();
+            napi_create_function(env, c"add".as_ptr(), NAPI_AUTO_LENGTH, Some(add), std::ptr::null_mut(), &mut func);
+            let mut kind = 0;
+            napi_typeof(env, func, &mut kind);
+            assert_eq!(kind, NAPI_FUNCTION);
+
+            let args = [1.5, 2.0];
+            let result = crate::closure::js_native_call_value(from_napi(func), args.as_ptr(), args.len());
+            assert_eq!(result, 203.5);
+
+            // napi_call_function goes the other way
+            let (mut a, mut b, mut out) = (std::ptr::null_mut(), std::ptr::null_mut(), std::ptr::null_mut());
+            napi_create_double(env, 1.0, &mut a);
+            napi_create_double(env, 2.0, &mut b);
+            napi_call_function(env, std::ptr::null_mut(), func, 2, [a, b].as_ptr(), &mut out);
+            assert_eq!(from_napi(out), 203.0);
+
+            // An exception left pending by the addon is not lost
+            napi_throw_error(env, std::ptr::null(), c"boom".as_ptr());
+            let mut pending = false;
+            napi_is_exception_pending(env, &mut pending);
+            assert!(pending);
+            let mut error = std::ptr::null_mut();
+            napi_get_and_clear_last_exception(env, &mut error);
+            let mut is_error = false;
+            napi_is_error(env, error, &mut is_error);
+            assert!(is_error);
+        }
+    }
+}
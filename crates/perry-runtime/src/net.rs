@@ -10,6 +10,7 @@ use crate::string::{js_string_from_bytes, StringHeader};
 use crate::object::ObjectHeader;
 use crate::buffer::BufferHeader;
 use crate::closure::ClosureHeader;
+use crate::permissions::{self, Capability};
 
 // Global handle registry for servers and sockets
 static NEXT_HANDLE: AtomicU64 = AtomicU64::new(1);
@@ -66,6 +67,7 @@ pub extern "C" fn js_net_server_listen(
     };
 
     let addr = format!("{}:{}", host, port);
+    permissions::check(Capability::Net, &addr);
     match TcpListener::bind(&addr) {
         Ok(listener) => {
             let address = listener.local_addr().unwrap_or_else(|_| addr.parse().unwrap());
@@ -160,6 +162,7 @@ pub extern "C" fn js_net_create_connection(
     };
 
     let addr = format!("{}:{}", host, port);
+    permissions::check(Capability::Net, &addr);
     match TcpStream::connect(&addr) {
         Ok(stream) => {
             let remote_address = stream.peer_addr().ok();
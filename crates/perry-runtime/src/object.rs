@@ -12,6 +12,7 @@ use crate::arena::arena_alloc;
 use std::alloc::{alloc, dealloc, Layout};
 use std::ptr;
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::RwLock;
 
 /// Global class registry mapping class_id -> parent_class_id for inheritance chain lookups
@@ -66,6 +67,105 @@ fn get_parent_class_id(class_id: u32) -> Option<u32> {
     registry.as_ref().and_then(|r| r.get(&class_id).copied())
 }
 
+/// Class accessor registry: class_id -> property name -> getter/setter function pointer.
+/// Filled by each module's init so property access on values whose class is only known
+/// at runtime still invokes `get x()` / `set x(v)`.
+static CLASS_GETTERS: RwLock<Option<HashMap<u32, HashMap<String, usize>>>> = RwLock::new(None);
+static CLASS_SETTERS: RwLock<Option<HashMap<u32, HashMap<String, usize>>>> = RwLock::new(None);
+
+/// Set once any accessor is registered, so programs without accessors skip the lookup
+static HAS_CLASS_ACCESSORS: AtomicBool = AtomicBool::new(false);
+
+/// Compiled getter: `{Class}_get_{prop}(this) -> value`
+type ClassGetterFn = extern "C" fn(this: i64) -> f64;
+/// Compiled setter: `{Class}_set_{prop}(this, value)`
+type ClassSetterFn = extern "C" fn(this: i64, value: f64) -> f64;
+
+unsafe fn register_class_accessor(
+    registry: &RwLock<Option<HashMap<u32, HashMap<String, usize>>>>,
+    class_id: u32,
+    name_ptr: *const u8,
+    name_len: usize,
+    func: i64,
+) {
+    if name_ptr.is_null() || func == 0 {
+        return;
+    }
+    let bytes = std::slice::from_raw_parts(name_ptr, name_len);
+    let Ok(name) = std::str::from_utf8(bytes) else {
+        return;
+    };
+    let mut registry = registry.write().unwrap();
+    registry
+        .get_or_insert_with(HashMap::new)
+        .entry(class_id)
+        .or_default()
+        .insert(name.to_string(), func as usize);
+    HAS_CLASS_ACCESSORS.store(true, Ordering::Release);
+}
+
+/// Register the getter for `property` on instances of `class_id` (and its subclasses)
+#[no_mangle]
+pub unsafe extern "C" fn js_register_class_getter(class_id: u32, name_ptr: *const u8, name_len: usize, getter: i64) {
+    register_class_accessor(&CLASS_GETTERS, class_id, name_ptr, name_len, getter);
+}
+
+/// Register the setter for `property` on instances of `class_id` (and its subclasses)
+#[no_mangle]
+pub unsafe extern "C" fn js_register_class_setter(class_id: u32, name_ptr: *const u8, name_len: usize, setter: i64) {
+    register_class_accessor(&CLASS_SETTERS, class_id, name_ptr, name_len, setter);
+}
+
+/// Find the accessor for `property` on a class instance, walking up the inheritance chain
+unsafe fn find_class_accessor(
+    registry: &RwLock<Option<HashMap<u32, HashMap<String, usize>>>>,
+    obj: *const ObjectHeader,
+    property: &str,
+) -> Option<usize> {
+    if !HAS_CLASS_ACCESSORS.load(Ordering::Acquire) || obj.is_null() {
+        return None;
+    }
+    if (*obj).object_type != crate::error::OBJECT_TYPE_REGULAR || (*obj).class_id == 0 {
+        return None;
+    }
+    let registry = registry.read().unwrap();
+    let classes = registry.as_ref()?;
+    let mut class_id = (*obj).class_id;
+    let mut parent_hint = (*obj).parent_class_id;
+    // Bounded walk guards against a malformed (cyclic) registry
+    for _ in 0..64 {
+        if let Some(func) = classes.get(&class_id).and_then(|props| props.get(property)) {
+            return Some(*func);
+        }
+        let parent = if parent_hint != 0 {
+            parent_hint
+        } else {
+            get_parent_class_id(class_id)?
+        };
+        parent_hint = 0;
+        if parent == 0 {
+            return None;
+        }
+        class_id = parent;
+    }
+    None
+}
+
+/// Call the getter for `property` on a class instance, if its class has one
+pub(crate) unsafe fn call_class_getter(obj: *const ObjectHeader, property: &str) -> Option<JSValue> {
+    let getter = find_class_accessor(&CLASS_GETTERS, obj, property)?;
+    let getter: ClassGetterFn = std::mem::transmute(getter);
+    Some(JSValue::from_bits(getter(obj as i64).to_bits()))
+}
+
+/// Call the setter for `property` on a class instance; false if its class has none
+pub(crate) unsafe fn call_class_setter(obj: *mut ObjectHeader, property: &str, value: f64) -> bool {
+    let Some(setter) = find_class_accessor(&CLASS_SETTERS, obj, property) else { return false };
+    let setter: ClassSetterFn = std::mem::transmute(setter);
+    setter(obj as i64, value);
+    true
+}
+
 /// Object header - precedes the fields in memory
 #[repr(C)]
 pub struct ObjectHeader {
@@ -109,6 +209,7 @@ pub extern "C" fn js_object_alloc_with_parent(class_id: u32, parent_class_id: u3
     }
 
     let layout = object_layout(field_count as usize);
+    crate::memory::count_allocation(crate::memory::AllocKind::Object, layout.size());
     unsafe {
         let ptr = alloc(layout) as *mut ObjectHeader;
         if ptr.is_null() {
@@ -142,6 +243,7 @@ pub extern "C" fn js_object_alloc_fast(class_id: u32, field_count: u32) -> *mut
     let total_size = header_size + fields_size;
 
     let ptr = arena_alloc(total_size, 8) as *mut ObjectHeader;
+    crate::memory::count_allocation(crate::memory::AllocKind::Object, total_size);
 
     unsafe {
         // Initialize header only - fields left uninitialized for constructor to fill
@@ -168,6 +270,7 @@ pub extern "C" fn js_object_alloc_fast_with_parent(class_id: u32, parent_class_i
     let total_size = header_size + fields_size;
 
     let ptr = arena_alloc(total_size, 8) as *mut ObjectHeader;
+    crate::memory::count_allocation(crate::memory::AllocKind::Object, total_size);
 
     unsafe {
         (*ptr).object_type = crate::error::OBJECT_TYPE_REGULAR;
@@ -249,16 +352,17 @@ pub extern "C" fn js_object_set_field_by_index(obj: *mut ObjectHeader, _key: *co
 }
 
 /// Set the keys array for an object (used for Object.keys() support)
-/// The keys_array should be an array of string pointers
+/// The keys_array should be an array of string pointers; objects with the same keys share
+/// one array, their shape (see `crate::shape`)
 #[no_mangle]
 pub extern "C" fn js_object_set_keys(obj: *mut ObjectHeader, keys_array: *mut ArrayHeader) {
     unsafe {
-        (*obj).keys_array = keys_array;
+        (*obj).keys_array = crate::shape::js_object_shape_of(keys_array);
     }
 }
 
 /// Get the keys of an object as an array of strings
-/// Returns the stored keys array, or an empty array if no keys were stored
+/// Returns a copy of the stored keys array, or an empty array if no keys were stored
 #[no_mangle]
 pub extern "C" fn js_object_keys(obj: *const ObjectHeader) -> *mut ArrayHeader {
     unsafe {
@@ -267,7 +371,8 @@ pub extern "C" fn js_object_keys(obj: *const ObjectHeader) -> *mut ArrayHeader {
             // Return an empty array if no keys are stored
             crate::array::js_array_alloc(0)
         } else {
-            keys
+            // A copy: the keys array may be a shape other objects share
+            crate::shape::own_keys(keys)
         }
     }
 }
@@ -350,7 +455,7 @@ pub extern "C" fn js_object_has_property(obj: f64, key: f64) -> f64 {
         return 0.0;
     }
 
-    let key_str = key_val.as_pointer::<crate::StringHeader>();
+    let key_str = key_val.as_string_ptr();
 
     unsafe {
         let keys = (*obj_ptr).keys_array;
@@ -364,7 +469,7 @@ pub extern "C" fn js_object_has_property(obj: f64, key: f64) -> f64 {
             let stored_key_val = crate::array::js_array_get(keys, i as u32);
             // Keys are stored as string pointers (NaN-boxed)
             if stored_key_val.is_string() {
-                let stored_key = stored_key_val.as_pointer::<crate::StringHeader>();
+                let stored_key = stored_key_val.as_string_ptr();
                 if crate::string::js_string_equals(key_str, stored_key) {
                     // Found the property
                     return 1.0;
@@ -385,6 +490,15 @@ pub extern "C" fn js_object_get_field_by_name(obj: *const ObjectHeader, key: *co
         return JSValue::undefined();
     }
     unsafe {
+        // Class instances route accessor properties through their compiled getter
+        if !key.is_null() {
+            let property = crate::string::string_as_str(key);
+            if let Some(getter) = find_class_accessor(&CLASS_GETTERS, obj, property) {
+                let getter: ClassGetterFn = std::mem::transmute(getter);
+                return JSValue::from_bits(getter(obj as i64).to_bits());
+            }
+        }
+
         let keys = (*obj).keys_array;
         if keys.is_null() {
             return JSValue::undefined();
@@ -396,7 +510,7 @@ pub extern "C" fn js_object_get_field_by_name(obj: *const ObjectHeader, key: *co
             let key_val = crate::array::js_array_get(keys, i as u32);
             // Keys are stored as string pointers (NaN-boxed)
             if key_val.is_string() {
-                let stored_key = key_val.as_pointer::<crate::StringHeader>();
+                let stored_key = key_val.as_string_ptr();
                 if crate::string::js_string_equals(key, stored_key) {
                     // Found it - return the field at this index
                     return js_object_get_field(obj, i as u32);
@@ -417,6 +531,41 @@ pub extern "C" fn js_object_get_field_by_name_f64(obj: *const ObjectHeader, key:
     f64::from_bits(value.bits())
 }
 
+/// Inline cache of one `obj.name` read site, filled by the slow path.
+///
+/// The compiled fast path hits when the object is a plain object (`object_type` 1,
+/// `class_id` 0, read as one u64) whose keys array is `shape`; it then loads field
+/// `index` directly. Only shared shapes are cached (see `crate::shape`), since they are
+/// never changed or freed.
+#[repr(C)]
+pub struct PropertyCache {
+    /// The shape's keys array; 1 while empty, which no keys array can be
+    pub shape: u64,
+    pub index: u64,
+}
+
+/// Read `name` from a plain object, filling `cache` when the key is found.
+/// None when `obj` is not a plain object, so the caller takes the generic path.
+pub unsafe fn get_field_cached(obj: *const ObjectHeader, name: &[u8], cache: *mut PropertyCache) -> Option<JSValue> {
+    if (*obj).object_type != crate::error::OBJECT_TYPE_REGULAR || (*obj).class_id != 0 {
+        return None;
+    }
+    let keys = (*obj).keys_array;
+    if keys.is_null() {
+        return Some(JSValue::undefined());
+    }
+    for i in 0..crate::array::js_array_length(keys) {
+        let key_val = crate::array::js_array_get(keys, i);
+        if key_val.is_string() && crate::string::string_as_str(key_val.as_string_ptr()).as_bytes() == name {
+            if !cache.is_null() && crate::shape::is_shape(keys) {
+                *cache = PropertyCache { shape: keys as u64, index: i as u64 };
+            }
+            return Some(js_object_get_field(obj, i));
+        }
+    }
+    Some(JSValue::undefined())
+}
+
 /// Set a field value by its string key name (dynamic property access)
 /// This searches the keys array for a match and sets the corresponding value.
 /// If the key doesn't exist, it adds it to the object.
@@ -426,14 +575,23 @@ pub extern "C" fn js_object_set_field_by_name(obj: *mut ObjectHeader, key: *cons
         return;
     }
     unsafe {
+        if !key.is_null() {
+            let property = crate::string::string_as_str(key);
+            if let Some(setter) = find_class_accessor(&CLASS_SETTERS, obj, property) {
+                let setter: ClassSetterFn = std::mem::transmute(setter);
+                setter(obj as i64, value);
+                return;
+            }
+        }
+
         let keys = (*obj).keys_array;
 
-        // If no keys array exists, create one
+        // If no keys array exists, move to the shape with just this key
         if keys.is_null() {
-            // Create a new keys array with the key
-            let new_keys = crate::array::js_array_alloc(4);
-            crate::array::js_array_push(new_keys, JSValue::string_ptr(key as *mut _));
-            (*obj).keys_array = new_keys;
+            (*obj).keys_array = match crate::shape::shape_with_key(keys, key) {
+                Some(shape) => shape,
+                None => crate::array::js_array_push(crate::array::js_array_alloc(4), JSValue::string_ptr(key as *mut _)),
+            };
 
             // Reallocate fields to hold at least one value
             // Note: We assume the object has enough field slots pre-allocated
@@ -447,7 +605,7 @@ pub extern "C" fn js_object_set_field_by_name(obj: *mut ObjectHeader, key: *cons
             let key_val = crate::array::js_array_get(keys, i as u32);
             // Keys are stored as string pointers (NaN-boxed)
             if key_val.is_string() {
-                let stored_key = key_val.as_pointer::<crate::StringHeader>();
+                let stored_key = key_val.as_string_ptr();
                 if crate::string::js_string_equals(key, stored_key) {
                     // Found it - update the field
                     js_object_set_field(obj, i as u32, JSValue::from_bits(value.to_bits()));
@@ -457,8 +615,15 @@ pub extern "C" fn js_object_set_field_by_name(obj: *mut ObjectHeader, key: *cons
         }
 
         // Key not found - add it to the object
-        // First, add the key to the keys array
-        crate::array::js_array_push(keys, JSValue::string_ptr(key as *mut _));
+        // First, move to the shape with the key added. Objects with their own keys array
+        // extend it in place (which may move it); ones leaving the shape tree get a copy
+        (*obj).keys_array = if !crate::shape::is_shape(keys) {
+            crate::array::js_array_push(keys, JSValue::string_ptr(key as *mut _))
+        } else if let Some(shape) = crate::shape::shape_with_key(keys, key) {
+            shape
+        } else {
+            crate::array::js_array_push(crate::shape::own_keys(keys), JSValue::string_ptr(key as *mut _))
+        };
 
         // Set the field at the new index
         let new_index = key_count as u32;
@@ -485,7 +650,7 @@ pub extern "C" fn js_object_delete_field(obj: *mut ObjectHeader, key: *const cra
             let key_val = crate::array::js_array_get(keys, i as u32);
             // Keys are stored as string pointers (NaN-boxed)
             if key_val.is_string() {
-                let stored_key = key_val.as_pointer::<crate::StringHeader>();
+                let stored_key = key_val.as_string_ptr();
                 if crate::string::js_string_equals(key, stored_key) {
                     // Found it - set the field to undefined
                     js_object_set_field(obj, i as u32, JSValue::undefined());
@@ -508,7 +673,7 @@ pub extern "C" fn js_object_delete_dynamic(obj: *mut ObjectHeader, key: f64) ->
 
     // If the key is a string, use js_object_delete_field
     if key_val.is_string() {
-        let key_str = key_val.as_pointer::<crate::StringHeader>();
+        let key_str = key_val.as_string_ptr();
         return js_object_delete_field(obj, key_str);
     }
 
@@ -697,7 +862,7 @@ pub unsafe extern "C" fn js_native_call_method(
             for i in 0..key_count {
                 let key_val = crate::array::js_array_get(keys, i as u32);
                 if key_val.is_string() {
-                    let stored_key = key_val.as_pointer::<crate::StringHeader>();
+                    let stored_key = key_val.as_string_ptr();
                     if crate::string::js_string_equals(method_key, stored_key) {
                         // Found the method - get it and call it if it's a closure
                         let field_val = js_object_get_field(obj as *mut _, i as u32);
@@ -803,4 +968,74 @@ mod tests {
 
         js_object_free(obj);
     }
+
+    extern "C" fn double_first_field(this: i64) -> f64 {
+        js_object_get_field_f64(this as *const ObjectHeader, 0) * 2.0
+    }
+
+    extern "C" fn halve_into_first_field(this: i64, value: f64) -> f64 {
+        js_object_set_field_f64(this as *mut ObjectHeader, 0, value / 2.0);
+        value
+    }
+
+    #[test]
+    fn test_class_accessors_dispatch_by_name() {
+        let name = b"doubled";
+        unsafe {
+            js_register_class_getter(9001, name.as_ptr(), name.len(), double_first_field as usize as i64);
+            js_register_class_setter(9001, name.as_ptr(), name.len(), halve_into_first_field as usize as i64);
+        }
+        let key = crate::string::js_string_from_bytes(name.as_ptr(), name.len() as u32);
+
+        // Subclass instances find the accessor through the parent chain
+        let obj = js_object_alloc_with_parent(9002, 9001, 1);
+        js_object_set_field(obj, 0, JSValue::number(21.0));
+        assert_eq!(js_object_get_field_by_name(obj, key).as_number(), 42.0);
+
+        js_object_set_field_by_name(obj, key, 10.0);
+        assert_eq!(js_object_get_field(obj, 0).as_number(), 5.0);
+        assert_eq!(js_object_get_field_by_name(obj, key).as_number(), 10.0);
+
+        // Unrelated classes are unaffected
+        let other = js_object_alloc(9003, 1);
+        assert!(js_object_get_field_by_name(other, key).is_undefined());
+
+        js_object_free(obj);
+        js_object_free(other);
+    }
+
+    #[test]
+    fn test_objects_with_same_keys_share_a_shape() {
+        let make = |x: f64, y: f64| {
+            let obj = js_object_alloc(0, 4);
+            for (name, value) in [(&b"x"[..], x), (&b"y"[..], y)] {
+                let key = crate::string::js_string_from_bytes(name.as_ptr(), name.len() as u32);
+                js_object_set_field_by_name(obj, key, value);
+            }
+            obj
+        };
+        let (a, b) = (make(1.0, 2.0), make(3.0, 4.0));
+        unsafe {
+            assert_eq!((*a).keys_array, (*b).keys_array);
+            let mut cache = PropertyCache { shape: 1, index: 0 };
+            assert_eq!(get_field_cached(a, b"y", &mut cache).map(|v| v.as_number()), Some(2.0));
+            assert_eq!((cache.shape, cache.index), ((*b).keys_array as u64, 1));
+            assert_eq!(get_field_cached(b, b"y", &mut cache).map(|v| v.as_number()), Some(4.0));
+            assert!(get_field_cached(b, b"z", &mut cache).unwrap().is_undefined());
+            // Class instances take the generic path
+            assert!(get_field_cached(js_object_alloc(9004, 1), b"y", &mut cache).is_none());
+
+            // Adding a key moves the object to another shape, leaving the shared one alone
+            let z = crate::string::js_string_from_bytes(b"z".as_ptr(), 1);
+            js_object_set_field_by_name(b, z, 5.0);
+            assert_ne!((*a).keys_array, (*b).keys_array);
+            assert_eq!(crate::array::js_array_length((*a).keys_array), 2);
+
+            // Object.keys() hands out a copy
+            let keys = js_object_keys(a);
+            assert_ne!(keys, (*a).keys_array);
+            crate::array::js_array_push(keys, JSValue::string_ptr(z));
+            assert_eq!(crate::array::js_array_length((*a).keys_array), 2);
+        }
+    }
 }
@@ -0,0 +1,271 @@
+//! Capability permissions compiled into the binary
+//!
+//! A `[permissions]` table in perry.toml restricts what the program may reach:
+//! - `allow-net`: hosts for outgoing connections and listening (`api.example.com`,
+//!   `localhost:8080`, `*.internal`)
+//! - `allow-fs`: files and directories the program may read or write; relative paths are
+//!   resolved against the working directory
+//! - `allow-env`: environment variables (`PORT`, `APP_*`)
+//! - `allow-run`: programs child_process may start, by name through PATH (`git`) or by
+//!   path (`/usr/bin/convert`)
+//!
+//! `"*"` allows everything of a kind, and a kind the table leaves out allows nothing. The
+//! compiler passes the table to `js_permissions_configure`, which the entry module calls
+//! before any module initializes. Unlike the heap ceiling, no environment variable or API
+//! widens it at run time: the first policy installed is the one the process keeps.
+//! Without the table nothing is restricted.
+//!
+//! A denied operation throws a `PermissionDenied` error naming the resource.
+
+use std::path::{Component, Path, PathBuf};
+use std::sync::OnceLock;
+
+use crate::string::js_string_from_bytes;
+use crate::value::JSValue;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Capability {
+    Net,
+    Fs,
+    Env,
+    Run,
+}
+
+impl Capability {
+    fn key(&self) -> &'static str {
+        match self {
+            Capability::Net => "allow-net",
+            Capability::Fs => "allow-fs",
+            Capability::Env => "allow-env",
+            Capability::Run => "allow-run",
+        }
+    }
+
+    fn from_key(key: &str) -> Option<Self> {
+        match key {
+            "allow-net" => Some(Capability::Net),
+            "allow-fs" => Some(Capability::Fs),
+            "allow-env" => Some(Capability::Env),
+            "allow-run" => Some(Capability::Run),
+            _ => None,
+        }
+    }
+}
+
+/// What each capability allows
+#[derive(Debug, Default, PartialEq)]
+pub struct Policy {
+    allowed: Vec<(Capability, String)>,
+}
+
+impl Policy {
+    /// One `<allow-key> <entry>` per line, as the compiler serializes the perry.toml table
+    pub fn parse(text: &str) -> Self {
+        let allowed = text
+            .lines()
+            .filter_map(|line| line.split_once(' '))
+            .filter_map(|(key, entry)| Some((Capability::from_key(key)?, entry.to_string())))
+            .collect();
+        Policy { allowed }
+    }
+
+    fn entries(&self, capability: Capability) -> impl Iterator<Item = &str> {
+        self.allowed.iter().filter(move |(c, _)| *c == capability).map(|(_, entry)| entry.as_str())
+    }
+
+    pub fn allows(&self, capability: Capability, resource: &str) -> bool {
+        let mut entries = self.entries(capability);
+        match capability {
+            Capability::Net => entries.any(|entry| host_matches(entry, resource)),
+            Capability::Env => entries.any(|entry| match entry.strip_suffix('*') {
+                Some(prefix) => resource.starts_with(prefix),
+                None => entry == resource,
+            }),
+            Capability::Fs => {
+                let Some(path) = resolve(Path::new(resource)) else { return false };
+                entries.any(|entry| entry == "*" || resolve(Path::new(entry)).is_some_and(|allowed| path.starts_with(allowed)))
+            }
+            // A bare name only allows starting the program by that name, through PATH; a
+            // program started by path needs its path listed
+            Capability::Run => entries.any(|entry| entry == "*" || entry == resource),
+        }
+    }
+}
+
+static POLICY: OnceLock<Policy> = OnceLock::new();
+
+/// `host`, `host:port`, `*.domain` or `*` against `host:port`
+fn host_matches(entry: &str, resource: &str) -> bool {
+    if entry == "*" {
+        return true;
+    }
+    let (host, port) = split_host_port(resource);
+    let (entry_host, entry_port) = split_host_port(entry);
+    if entry_port.is_some() && entry_port != port {
+        return false;
+    }
+    match entry_host.strip_prefix("*.") {
+        Some(domain) => host.len() > domain.len() + 1
+            && host.to_ascii_lowercase().ends_with(&format!(".{}", domain.to_ascii_lowercase())),
+        None => host.eq_ignore_ascii_case(entry_host),
+    }
+}
+
+/// Split `host[:port]`, including bracketed IPv6 addresses (`[::1]:80`)
+fn split_host_port(address: &str) -> (&str, Option<&str>) {
+    if let Some(rest) = address.strip_prefix('[') {
+        return match rest.split_once(']') {
+            Some((host, port)) => (host, port.strip_prefix(':')),
+            None => (address, None),
+        };
+    }
+    match address.rsplit_once(':') {
+        Some((host, port)) if !host.contains(':') => (host, Some(port)),
+        _ => (address, None),
+    }
+}
+
+/// `host:port` a URL connects to, with the scheme's default port
+pub fn url_address(url: &str) -> Option<String> {
+    let (scheme, rest) = url.split_once("://")?;
+    let authority = rest.split(['/', '?', '#']).next()?;
+    let authority = authority.rsplit_once('@').map_or(authority, |(_, host)| host);
+    if split_host_port(authority).1.is_some() {
+        return Some(authority.to_string());
+    }
+    let port = match scheme.to_ascii_lowercase().as_str() {
+        "https" | "wss" => 443,
+        _ => 80,
+    };
+    Some(format!("{}:{}", authority, port))
+}
+
+/// Absolute form of `path` with symlinks followed through the part that exists, so a link
+/// can't lead out of an allowed directory. None when `..` follows a part that doesn't
+/// exist (the OS would reject the path, but it can't be checked lexically either).
+fn resolve(path: &Path) -> Option<PathBuf> {
+    let absolute = match std::env::current_dir() {
+        Ok(cwd) => cwd.join(path),
+        Err(_) => path.to_path_buf(),
+    };
+    for existing in absolute.ancestors() {
+        if let Ok(canonical) = existing.canonicalize() {
+            let rest = absolute.strip_prefix(existing).ok()?;
+            if rest.components().any(|c| matches!(c, Component::ParentDir)) {
+                return None;
+            }
+            return Some(canonical.join(rest));
+        }
+    }
+    None
+}
+
+/// Whether the compiled-in policy allows using `resource`
+pub fn allowed(capability: Capability, resource: &str) -> bool {
+    match POLICY.get() {
+        Some(policy) => policy.allows(capability, resource),
+        None => true,
+    }
+}
+
+/// The error message for a denied operation, or None when it is allowed
+pub fn denial(capability: Capability, resource: &str) -> Option<String> {
+    if allowed(capability, resource) {
+        return None;
+    }
+    Some(format!(
+        "Permission denied: '{}' is not covered by the `{}` permissions compiled into this program",
+        resource,
+        capability.key()
+    ))
+}
+
+/// Throw a PermissionDenied error unless the policy allows using `resource`
+pub fn check(capability: Capability, resource: &str) {
+    if let Some(message) = denial(capability, resource) {
+        let msg = js_string_from_bytes(message.as_ptr(), message.len() as u32);
+        let error = crate::error::js_error_new_with_message(msg);
+        unsafe {
+            (*error).name = js_string_from_bytes(b"PermissionDenied".as_ptr(), 16);
+        }
+        crate::exception::js_throw(f64::from_bits(JSValue::pointer(error as *const u8).bits()))
+    }
+}
+
+/// A shell command line runs whatever it says, so under a policy only a plain
+/// `program args...` line can be checked; anything with shell syntax needs `allow-run = ["*"]`
+pub fn check_shell_command(command: &str) {
+    let program = command.split_whitespace().next().unwrap_or("");
+    let shell_syntax = command.contains(|c: char| ";|&$`<>(){}\n\\*?~".contains(c));
+    if shell_syntax {
+        check(Capability::Run, command);
+    } else {
+        check(Capability::Run, program);
+    }
+}
+
+/// Install the compiled-in policy (called from main). `policy` holds the serialized
+/// `[permissions]` table; only the first call takes effect.
+#[no_mangle]
+pub extern "C" fn js_permissions_configure(policy: *const u8, len: i64) {
+    if policy.is_null() || len < 0 {
+        return;
+    }
+    let bytes = unsafe { std::slice::from_raw_parts(policy, len as usize) };
+    let _ = POLICY.set(Policy::parse(&String::from_utf8_lossy(bytes)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_net_and_env() {
+        let policy = Policy::parse(
+            "allow-net api.example.com\nallow-net localhost:8080\nallow-net *.internal\nallow-env PORT\nallow-env APP_*\n",
+        );
+        assert!(policy.allows(Capability::Net, "api.example.com:443"));
+        assert!(policy.allows(Capability::Net, "LOCALHOST:8080"));
+        assert!(!policy.allows(Capability::Net, "localhost:5432"));
+        assert!(policy.allows(Capability::Net, "db.internal:5432"));
+        assert!(!policy.allows(Capability::Net, "internal:5432"));
+        assert!(!policy.allows(Capability::Net, "evil.com:443"));
+        assert!(policy.allows(Capability::Env, "APP_SECRET"));
+        assert!(!policy.allows(Capability::Env, "HOME"));
+        // A kind the table leaves out allows nothing
+        assert!(!policy.allows(Capability::Run, "git"));
+
+        assert_eq!(url_address("https://user@api.example.com/v1?x=1").as_deref(), Some("api.example.com:443"));
+        assert_eq!(url_address("http://[::1]:3000/").as_deref(), Some("[::1]:3000"));
+        assert_eq!(split_host_port("[::1]:3000"), ("::1", Some("3000")));
+        assert_eq!(url_address("not a url"), None);
+    }
+
+    #[test]
+    fn test_fs_and_run() {
+        let root = std::env::temp_dir().join(format!("perry-permissions-test-{}", std::process::id()));
+        let data = root.join("data");
+        std::fs::create_dir_all(&data).unwrap();
+        let policy = Policy::parse(&format!("allow-fs {}\nallow-run git\nallow-run /opt/tool\n", data.display()));
+        assert!(policy.allows(Capability::Fs, &data.join("users.json").to_string_lossy()));
+        assert!(policy.allows(Capability::Fs, &data.join("new/nested.txt").to_string_lossy()));
+        assert!(!policy.allows(Capability::Fs, &root.join("secrets").to_string_lossy()));
+        assert!(!policy.allows(Capability::Fs, &data.join("../secrets").to_string_lossy()));
+        assert!(!policy.allows(Capability::Fs, &data.join("missing/../../secrets").to_string_lossy()));
+        #[cfg(unix)]
+        {
+            // A link inside the allowed directory doesn't extend it
+            std::os::unix::fs::symlink(&root, data.join("escape")).unwrap();
+            assert!(!policy.allows(Capability::Fs, &data.join("escape/secrets").to_string_lossy()));
+        }
+
+        assert!(policy.allows(Capability::Run, "git"));
+        // A bare entry doesn't allow a program of that name elsewhere
+        assert!(!policy.allows(Capability::Run, "/usr/bin/git"));
+        assert!(!policy.allows(Capability::Run, "/tmp/x/git"));
+        assert!(!policy.allows(Capability::Run, "./git"));
+        assert!(!policy.allows(Capability::Run, "/opt/other/tool"));
+        assert!(policy.allows(Capability::Run, "/opt/tool"));
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -292,6 +292,8 @@ pub extern "C" fn js_promise_finally(
 /// Process all pending promise callbacks (run microtasks)
 #[no_mangle]
 pub extern "C" fn js_promise_run_microtasks() -> i32 {
+    // Swap in functions `perry dev` recompiled, between tasks (see hot_reload)
+    crate::hot_reload::apply_pending();
     let mut ran = 0;
 
     // First, tick timers to resolve any expired timer promises
@@ -0,0 +1,253 @@
+//! Runtime type reflection (perry/reflect)
+//!
+//! Each module's init registers the name, parent and declared fields of its classes, with
+//! the fields' static types as the compiler printed them. `typeOf`, `className` and `fields`
+//! read that metadata back, so a serializer can walk class instances without a hand-written
+//! schema. Plain objects have no declared types; they are described by their keys and the
+//! runtime kind of each value.
+//!
+//! Like the rest of the runtime, objects are told apart from arrays by the header's type
+//! tag, which a one-element array shares; such an array only reads as a class instance if
+//! its capacity happens to be a registered class id.
+
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use crate::array::{js_array_alloc, js_array_length, js_array_push_f64, ArrayHeader};
+use crate::closure::{ClosureHeader, CLOSURE_MAGIC};
+use crate::error::{is_error_object, OBJECT_TYPE_REGULAR};
+use crate::object::{js_object_alloc, js_object_get_field, js_object_keys, js_object_set_field_by_name, ObjectHeader};
+use crate::string::js_string_from_bytes;
+use crate::value::JSValue;
+
+/// A declared instance field
+#[derive(Debug, Clone, PartialEq)]
+struct FieldInfo {
+    name: String,
+    /// Static type, as written by the compiler (`number`, `string[]`, `Point`, ...)
+    ty: String,
+    readonly: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct ClassInfo {
+    name: String,
+    /// Class id of the parent class (0 if none)
+    parent: u32,
+    /// Own fields, in declaration order
+    fields: Vec<FieldInfo>,
+}
+
+/// Registered classes by class id
+static CLASSES: RwLock<Option<HashMap<u32, ClassInfo>>> = RwLock::new(None);
+
+/// Inheritance depth past which a parent chain is assumed to be cyclic
+const MAX_CLASS_DEPTH: usize = 64;
+
+/// Parse class metadata: a `name\tparent_id` line, then a `name\ttype\treadonly` line per
+/// field (`readonly` is 0 or 1)
+fn parse_metadata(text: &str) -> Option<ClassInfo> {
+    let mut lines = text.lines();
+    let (name, parent) = lines.next()?.split_once('\t')?;
+    let fields = lines
+        .map(|line| {
+            let mut parts = line.split('\t');
+            Some(FieldInfo {
+                name: parts.next()?.to_string(),
+                ty: parts.next()?.to_string(),
+                readonly: parts.next()? == "1",
+            })
+        })
+        .collect::<Option<Vec<_>>>()?;
+    Some(ClassInfo { name: name.to_string(), parent: parent.parse().ok()?, fields })
+}
+
+/// Register a class's metadata (emitted by the compiler into each module's init)
+#[no_mangle]
+pub unsafe extern "C" fn js_register_class_metadata(class_id: u32, data: *const u8, len: usize) {
+    let bytes = std::slice::from_raw_parts(data, len);
+    let Some(info) = std::str::from_utf8(bytes).ok().and_then(parse_metadata) else { return };
+    let mut classes = CLASSES.write().unwrap();
+    classes.get_or_insert_with(HashMap::new).insert(class_id, info);
+}
+
+fn class_info(class_id: u32) -> Option<ClassInfo> {
+    CLASSES.read().unwrap().as_ref()?.get(&class_id).cloned()
+}
+
+/// Declared fields of a class, its ancestors' first
+fn class_fields(class_id: u32) -> Vec<FieldInfo> {
+    let mut chain = Vec::new();
+    let mut id = class_id;
+    while let Some(info) = class_info(id) {
+        id = info.parent;
+        chain.push(info);
+        if id == 0 || chain.len() >= MAX_CLASS_DEPTH {
+            break;
+        }
+    }
+    chain.into_iter().rev().flat_map(|info| info.fields).collect()
+}
+
+unsafe fn is_closure(value: JSValue) -> bool {
+    let ptr = value.as_pointer::<ClosureHeader>();
+    value.is_pointer() && !ptr.is_null() && (*ptr).type_tag == CLOSURE_MAGIC
+}
+
+/// The plain object or class instance behind a value
+unsafe fn as_object(value: JSValue) -> Option<*mut ObjectHeader> {
+    if !value.is_pointer() || is_closure(value) {
+        return None;
+    }
+    let obj = value.as_pointer::<ObjectHeader>() as *mut ObjectHeader;
+    (!obj.is_null() && (*obj).object_type == OBJECT_TYPE_REGULAR).then_some(obj)
+}
+
+/// Class id of a value, if it is an instance of a registered class
+unsafe fn instance_class(value: JSValue) -> Option<u32> {
+    let obj = as_object(value)?;
+    let class_id = (*obj).class_id;
+    (class_id != 0 && class_info(class_id).is_some()).then_some(class_id)
+}
+
+/// Kind of a value: `typeof` with `null`, `array`, `error` and `class` told apart
+unsafe fn kind_of(value: JSValue) -> &'static str {
+    if value.is_undefined() {
+        "undefined"
+    } else if value.is_null() {
+        "null"
+    } else if value.is_bool() {
+        "boolean"
+    } else if value.is_string() {
+        "string"
+    } else if value.is_bigint() {
+        "bigint"
+    } else if !value.is_pointer() {
+        "number"
+    } else if is_closure(value) {
+        "function"
+    } else if instance_class(value).is_some() {
+        "class"
+    } else if as_object(value).is_some() {
+        "object"
+    } else if is_error_object(value.as_pointer::<u8>()) {
+        "error"
+    } else {
+        "array"
+    }
+}
+
+fn boxed(value: JSValue) -> f64 {
+    f64::from_bits(value.bits())
+}
+
+fn string(s: &str) -> f64 {
+    boxed(JSValue::string_ptr(js_string_from_bytes(s.as_ptr(), s.len() as u32)))
+}
+
+/// A plain object with these properties
+fn object(properties: &[(&str, f64)]) -> f64 {
+    let obj = js_object_alloc(0, properties.len() as u32);
+    for (key, value) in properties {
+        let key = js_string_from_bytes(key.as_ptr(), key.len() as u32);
+        js_object_set_field_by_name(obj, key, *value);
+    }
+    boxed(JSValue::object_ptr(obj as *mut u8))
+}
+
+fn array(values: impl IntoIterator<Item = f64>) -> f64 {
+    let mut arr = js_array_alloc(0);
+    for value in values {
+        arr = js_array_push_f64(arr, value);
+    }
+    boxed(JSValue::array_ptr(arr))
+}
+
+/// `{ name, type, readonly }` for each field of a class instance or plain object
+unsafe fn field_list(value: JSValue) -> Vec<f64> {
+    let field = |name: &str, ty: &str, readonly: bool| {
+        object(&[("name", string(name)), ("type", string(ty)), ("readonly", boxed(JSValue::bool(readonly)))])
+    };
+    if let Some(class_id) = instance_class(value) {
+        return class_fields(class_id).iter().map(|f| field(&f.name, &f.ty, f.readonly)).collect();
+    }
+    let Some(obj) = as_object(value) else { return Vec::new() };
+    let keys: *mut ArrayHeader = js_object_keys(obj);
+    (0..js_array_length(keys))
+        .filter_map(|i| {
+            let key = JSValue::from_bits(crate::array::js_array_get_f64(keys, i).to_bits());
+            if !key.is_string() {
+                return None;
+            }
+            let name = crate::string::string_as_str(key.as_string_ptr()).to_string();
+            // A nested class instance is described by its class name
+            let element = js_object_get_field(obj, i);
+            let ty = match instance_class(element).and_then(class_info) {
+                Some(info) => info.name,
+                None => kind_of(element).to_string(),
+            };
+            Some(field(&name, &ty, false))
+        })
+        .collect()
+}
+
+/// `typeOf(value)`: `{ kind }`, plus `length` for arrays, `name` for class instances and
+/// `fields` for class instances and plain objects
+#[no_mangle]
+pub extern "C" fn js_reflect_type_of(value: f64) -> f64 {
+    let value = JSValue::from_bits(value.to_bits());
+    unsafe {
+        let kind = kind_of(value);
+        match kind {
+            "class" => {
+                let name = instance_class(value).and_then(class_info).map(|info| info.name).unwrap_or_default();
+                let fields = array(field_list(value));
+                object(&[("kind", string(kind)), ("name", string(&name)), ("fields", fields)])
+            }
+            "object" => object(&[("kind", string(kind)), ("fields", array(field_list(value)))]),
+            "array" => {
+                let length = js_array_length(value.as_pointer::<ArrayHeader>());
+                object(&[("kind", string(kind)), ("length", length as f64)])
+            }
+            _ => object(&[("kind", string(kind))]),
+        }
+    }
+}
+
+/// `className(value)`: the class of an instance, or undefined
+#[no_mangle]
+pub extern "C" fn js_reflect_class_name(value: f64) -> f64 {
+    let value = JSValue::from_bits(value.to_bits());
+    match unsafe { instance_class(value) }.and_then(class_info) {
+        Some(info) => string(&info.name),
+        None => boxed(JSValue::undefined()),
+    }
+}
+
+/// `fields(value)`: the `{ name, type, readonly }` fields of a class instance (declared,
+/// inherited ones first) or plain object (its keys); an empty array for anything else
+#[no_mangle]
+pub extern "C" fn js_reflect_fields(value: f64) -> f64 {
+    let value = JSValue::from_bits(value.to_bits());
+    array(unsafe { field_list(value) })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_metadata_and_inherited_fields() {
+        let base = "Shape\t0\nid\tstring\t1\n";
+        let point = "Point\t9001\nx\tnumber\t0\ny\tnumber\t0\n";
+        unsafe {
+            js_register_class_metadata(9001, base.as_ptr(), base.len());
+            js_register_class_metadata(9002, point.as_ptr(), point.len());
+        }
+        let info = class_info(9002).unwrap();
+        assert_eq!((info.name.as_str(), info.parent), ("Point", 9001));
+        let names: Vec<(String, bool)> = class_fields(9002).into_iter().map(|f| (f.name, f.readonly)).collect();
+        assert_eq!(names, vec![("id".to_string(), true), ("x".to_string(), false), ("y".to_string(), false)]);
+        assert_eq!(parse_metadata("Broken\tnot-a-number\n"), None);
+    }
+}
@@ -6,6 +6,7 @@
 
 use std::alloc::{alloc, realloc, Layout};
 use std::ptr;
+use crate::array::{js_array_alloc, js_array_push_f64, ArrayHeader};
 use crate::string::StringHeader;
 
 /// Set header - stable address, elements allocated separately
@@ -227,3 +228,19 @@ pub extern "C" fn js_set_clear(set: *mut SetHeader) {
         (*set).size = 0;
     }
 }
+
+/// Get the values of the set as an array (set.values() and for-of over a set)
+#[no_mangle]
+pub extern "C" fn js_set_values(set: *const SetHeader) -> *mut ArrayHeader {
+    unsafe {
+        let size = (*set).size;
+        let elements = elements_ptr(set);
+        let mut result = js_array_alloc(size);
+
+        for i in 0..size as usize {
+            result = js_array_push_f64(result, ptr::read(elements.add(i)));
+        }
+
+        result
+    }
+}
@@ -0,0 +1,179 @@
+//! Shapes (hidden classes) of plain objects
+//!
+//! A plain object's `keys_array` names its fields in slot order. Objects with the same keys
+//! in the same order share one keys array, their shape, so the layout is known from a
+//! pointer: a property read site caches the shape it last saw and the slot it found the key
+//! in, and then reads other objects of that shape with a single load.
+//!
+//! Shapes form a tree rooted at the empty shape: adding key `k` to an object of shape `S`
+//! moves it to `S`'s transition for `k`, created on first use. Object literal sites look
+//! their shape up once and reuse it; objects the runtime builds get theirs through
+//! `js_object_set_keys`. Shape arrays are never changed or freed, which is what makes a
+//! pointer comparison enough. Their addresses are also kept in a lock-free table (see
+//! [`is_shape`]), so telling a shape from an object's own keys array doesn't take the tree's
+//! lock, and the arrays' headers stay as they are.
+//!
+//! Objects used as dictionaries would grow a shape per key, so an object with
+//! [`MAX_SHAPE_KEYS`] keys, or any object once [`MAX_SHAPES`] exist, gets its own keys
+//! array instead, which it then extends in place.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use crate::array::{js_array_alloc, js_array_get, js_array_length, js_array_push, ArrayHeader};
+use crate::string::{js_string_intern, string_data, StringHeader};
+use crate::value::JSValue;
+
+/// Most keys an object can have and still share a shape
+pub const MAX_SHAPE_KEYS: u32 = 64;
+/// Most shapes the program creates
+pub const MAX_SHAPES: usize = 1 << 16;
+/// Slots of the shape address table: twice the most shapes, so probes stay short
+const SHAPE_SLOTS: usize = 2 * MAX_SHAPES;
+
+#[derive(Default)]
+struct ShapeTree {
+    /// (shape, interned key) -> the shape with the key appended; the empty shape is 0
+    transitions: HashMap<(usize, usize), usize>,
+    /// Shapes created so far
+    count: usize,
+}
+
+static SHAPES: Mutex<Option<ShapeTree>> = Mutex::new(None);
+
+/// Addresses of the shapes, open addressed with linear probing; 0 is an empty slot. Only
+/// written with `SHAPES` locked, and never cleared
+static SHAPE_TABLE: [AtomicUsize; SHAPE_SLOTS] = [const { AtomicUsize::new(0) }; SHAPE_SLOTS];
+
+/// The slots `keys` is looked for in, in order
+fn probe(keys: usize) -> impl Iterator<Item = &'static AtomicUsize> {
+    let start = (keys >> 3).wrapping_mul(0x9E37_79B9_7F4A_7C15) % SHAPE_SLOTS;
+    (0..SHAPE_SLOTS).map(move |i| &SHAPE_TABLE[(start + i) % SHAPE_SLOTS])
+}
+
+/// Whether `keys` is a shared shape (and so must not be changed)
+pub fn is_shape(keys: *const ArrayHeader) -> bool {
+    if keys.is_null() {
+        return false;
+    }
+    for slot in probe(keys as usize) {
+        match slot.load(Ordering::Acquire) {
+            0 => return false,
+            shape if shape == keys as usize => return true,
+            _ => {}
+        }
+    }
+    false
+}
+
+/// The shape of an object of shape `shape` (null for none) after adding `key`; None when
+/// the object should have its own keys array
+pub fn shape_with_key(shape: *const ArrayHeader, key: *const StringHeader) -> Option<*mut ArrayHeader> {
+    if key.is_null() {
+        return None;
+    }
+    let key_count = if shape.is_null() { 0 } else { js_array_length(shape) };
+    if key_count >= MAX_SHAPE_KEYS {
+        return None;
+    }
+    let key = unsafe { js_string_intern(string_data(key), (*key).length) };
+    let mut tree = SHAPES.lock().unwrap();
+    let tree = tree.get_or_insert_with(ShapeTree::default);
+    if let Some(&child) = tree.transitions.get(&(shape as usize, key as usize)) {
+        return Some(child as *mut ArrayHeader);
+    }
+    if tree.count >= MAX_SHAPES {
+        return None;
+    }
+    let mut child = js_array_alloc(key_count + 1);
+    for i in 0..key_count {
+        child = js_array_push(child, js_array_get(shape, i));
+    }
+    child = js_array_push(child, JSValue::string_ptr(key));
+    // Recorded before other threads can find it through the tree. There are at most
+    // MAX_SHAPES, so a free slot is always found
+    if let Some(slot) = probe(child as usize).find(|slot| slot.load(Ordering::Relaxed) == 0) {
+        slot.store(child as usize, Ordering::Release);
+    }
+    tree.transitions.insert((shape as usize, key as usize), child as usize);
+    tree.count += 1;
+    Some(child)
+}
+
+/// The shape with the keys of `keys`, in order; `keys` itself when they can't share one
+/// (too many, a non-string key, or the shape limit reached)
+#[no_mangle]
+pub extern "C" fn js_object_shape_of(keys: *mut ArrayHeader) -> *mut ArrayHeader {
+    if keys.is_null() || is_shape(keys) {
+        return keys;
+    }
+    let mut shape: *mut ArrayHeader = std::ptr::null_mut();
+    for i in 0..js_array_length(keys) {
+        let key = js_array_get(keys, i);
+        if !key.is_string() {
+            return keys;
+        }
+        match shape_with_key(shape, key.as_string_ptr()) {
+            Some(next) => shape = next,
+            None => return keys,
+        }
+    }
+    if shape.is_null() {
+        keys
+    } else {
+        shape
+    }
+}
+
+/// An unshared copy of `keys` with room for one more key
+pub fn own_keys(keys: *const ArrayHeader) -> *mut ArrayHeader {
+    let count = js_array_length(keys);
+    let mut copy = js_array_alloc(count + 1);
+    for i in 0..count {
+        copy = js_array_push(copy, js_array_get(keys, i));
+    }
+    copy
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::string::js_string_from_bytes;
+
+    fn key(name: &str) -> *mut StringHeader {
+        js_string_from_bytes(name.as_ptr(), name.len() as u32)
+    }
+
+    #[test]
+    fn test_same_keys_share_a_shape() {
+        let x = shape_with_key(std::ptr::null(), key("shape_x")).unwrap();
+        let xy = shape_with_key(x, key("shape_y")).unwrap();
+        // Other strings with the same contents take the same transitions
+        assert_eq!(shape_with_key(std::ptr::null(), key("shape_x")), Some(x));
+        assert_eq!(shape_with_key(x, key("shape_y")), Some(xy));
+        assert!(is_shape(xy) && js_array_length(xy) == 2 && js_array_length(x) == 1);
+
+        let mut keys = js_array_alloc(2);
+        keys = js_array_push(keys, JSValue::string_ptr(key("shape_x")));
+        keys = js_array_push(keys, JSValue::string_ptr(key("shape_y")));
+        assert!(!is_shape(keys));
+        assert_eq!(js_object_shape_of(keys), xy);
+
+        // Key order matters
+        let y = shape_with_key(std::ptr::null(), key("shape_y")).unwrap();
+        assert_ne!(shape_with_key(y, key("shape_x")), Some(xy));
+    }
+
+    #[test]
+    fn test_dictionaries_get_their_own_keys() {
+        let mut shape: *mut ArrayHeader = std::ptr::null_mut();
+        for i in 0..MAX_SHAPE_KEYS {
+            shape = shape_with_key(shape, key(&format!("dict_{}", i))).unwrap();
+        }
+        assert_eq!(shape_with_key(shape, key("one_too_many")), None);
+        let own = own_keys(shape);
+        assert!(!is_shape(own));
+        assert_eq!(js_array_length(own), MAX_SHAPE_KEYS);
+    }
+}
@@ -0,0 +1,234 @@
+//! Stack overflow detection
+//!
+//! The generated `main` calls `js_stack_guard_install()` before running any module code.
+//! It finds the main thread's stack, maps a guard region below it where the OS doesn't
+//! already keep one, and installs a SIGSEGV/SIGBUS handler on an alternate signal stack.
+//! A fault inside the stack's reserved range can only be the stack running into its
+//! guard, so the handler turns it into a `RangeError: Maximum call stack size exceeded`
+//! thrown like any other exception: `try`/`catch` around a deep recursion catches it, and
+//! an uncaught one is reported instead of the process dying with a bare segfault.
+//!
+//! The error's stack lists the innermost TypeScript functions from the crash shadow
+//! stack, which compiled functions only record when built with `--crash-report`.
+//!
+//! Other faults are passed on: the handler restores the previous action (the crash
+//! reporter's, or the default) and returns, so the faulting instruction runs again under
+//! it. Like `crash`, throwing from a signal handler allocates, which is not
+//! async-signal-safe; a stack overflow inside the allocator itself is not recoverable.
+//!
+//! Windows has no signals to hook: installing is a no-op there, and an overflow ends the
+//! process with `EXCEPTION_STACK_OVERFLOW`, reported by `crash` under `--crash-report`.
+
+#![cfg_attr(not(unix), allow(dead_code))]
+
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use crate::error::js_error_new_with_message;
+use crate::string::js_string_from_bytes;
+use crate::value::JSValue;
+
+/// Size of the alternate signal stack the handler runs on while building the error
+const ALT_STACK_SIZE: usize = 256 * 1024;
+
+/// Size of the guard region mapped below the stack
+const GUARD_SIZE: usize = 64 * 1024;
+
+/// Frames listed in the error's stack, as in Node (`Error.stackTraceLimit`)
+const STACK_TRACE_LIMIT: usize = 10;
+
+const MESSAGE: &str = "Maximum call stack size exceeded";
+
+static INSTALLED: AtomicBool = AtomicBool::new(false);
+// Main thread stack range, lowest address first (0 when unknown)
+static STACK_LOW: AtomicUsize = AtomicUsize::new(0);
+static STACK_HIGH: AtomicUsize = AtomicUsize::new(0);
+
+#[cfg(unix)]
+const HANDLED_SIGNALS: [libc::c_int; 2] = [libc::SIGSEGV, libc::SIGBUS];
+
+// Actions in place before ours, restored for faults that aren't stack overflows
+#[cfg(unix)]
+static mut PREVIOUS_ACTIONS: [Option<libc::sigaction>; 2] = [None, None];
+
+/// Install the guard region and the overflow handler. Safe to call more than once.
+#[no_mangle]
+pub extern "C" fn js_stack_guard_install() {
+    if !cfg!(unix) || INSTALLED.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    let Some((low, high)) = (unsafe { main_stack_bounds() }) else { return };
+    unsafe { map_guard(low) };
+    STACK_LOW.store(low, Ordering::SeqCst);
+    STACK_HIGH.store(high, Ordering::SeqCst);
+    install_signal_handlers();
+}
+
+#[cfg(not(unix))]
+fn install_signal_handlers() {}
+
+#[cfg(unix)]
+fn install_signal_handlers() {
+    unsafe {
+        // Replaces the crash reporter's smaller alternate stack, if it installed one
+        let stack = Box::leak(vec![0u8; ALT_STACK_SIZE].into_boxed_slice());
+        let ss = libc::stack_t {
+            ss_sp: stack.as_mut_ptr() as *mut libc::c_void,
+            ss_flags: 0,
+            ss_size: ALT_STACK_SIZE,
+        };
+        libc::sigaltstack(&ss, std::ptr::null_mut());
+
+        for (i, &sig) in HANDLED_SIGNALS.iter().enumerate() {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = overflow_signal_handler as *const () as usize;
+            // NODEFER: the handler leaves by longjmp, which wouldn't unblock the signal
+            action.sa_flags = libc::SA_SIGINFO | libc::SA_ONSTACK | libc::SA_NODEFER;
+            libc::sigemptyset(&mut action.sa_mask);
+            let mut previous: libc::sigaction = std::mem::zeroed();
+            if libc::sigaction(sig, &action, &mut previous) == 0 {
+                PREVIOUS_ACTIONS[i] = Some(previous);
+            }
+        }
+    }
+}
+
+/// Lowest and highest address of the calling (main) thread's stack
+#[cfg(target_os = "linux")]
+unsafe fn main_stack_bounds() -> Option<(usize, usize)> {
+    let mut attr: libc::pthread_attr_t = std::mem::zeroed();
+    if libc::pthread_getattr_np(libc::pthread_self(), &mut attr) != 0 {
+        return None;
+    }
+    let mut addr: *mut libc::c_void = std::ptr::null_mut();
+    let mut size: libc::size_t = 0;
+    let ok = libc::pthread_attr_getstack(&attr, &mut addr, &mut size) == 0;
+    libc::pthread_attr_destroy(&mut attr);
+    (ok && !addr.is_null()).then(|| (addr as usize, addr as usize + size))
+}
+
+#[cfg(target_os = "macos")]
+unsafe fn main_stack_bounds() -> Option<(usize, usize)> {
+    let thread = libc::pthread_self();
+    let high = libc::pthread_get_stackaddr_np(thread) as usize;
+    let size = libc::pthread_get_stacksize_np(thread);
+    (high != 0 && size != 0).then(|| (high - size, high))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+unsafe fn main_stack_bounds() -> Option<(usize, usize)> {
+    None
+}
+
+/// Reserve `GUARD_SIZE` inaccessible bytes at the bottom of the main thread's stack.
+/// Linux grows the main stack on demand up to its rlimit, leaving the range unmapped;
+/// the mapping stops growth there even when the rlimit is larger than the address space
+/// left below. macOS already guards its stacks.
+#[cfg(target_os = "linux")]
+unsafe fn map_guard(low: usize) {
+    let guard = libc::mmap(
+        low as *mut libc::c_void,
+        GUARD_SIZE,
+        libc::PROT_NONE,
+        libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | libc::MAP_NORESERVE | libc::MAP_FIXED_NOREPLACE,
+        -1,
+        0,
+    );
+    // Older kernels treat FIXED_NOREPLACE as a hint; don't keep a guard somewhere else
+    if guard != libc::MAP_FAILED && guard as usize != low {
+        libc::munmap(guard, GUARD_SIZE);
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+unsafe fn map_guard(_low: usize) {}
+
+/// Whether a fault at `addr` is the stack running into its guard: the address lies in the
+/// stack's reserved range or the guard just below it, where nothing else is mapped
+fn is_stack_fault(addr: usize, low: usize, high: usize) -> bool {
+    low != 0 && addr >= low.saturating_sub(GUARD_SIZE) && addr < high
+}
+
+#[cfg(target_os = "linux")]
+unsafe fn fault_address(info: *const libc::siginfo_t) -> usize {
+    (*info).si_addr() as usize
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+unsafe fn fault_address(info: *const libc::siginfo_t) -> usize {
+    (*info).si_addr as usize
+}
+
+#[cfg(unix)]
+extern "C" fn overflow_signal_handler(sig: libc::c_int, info: *mut libc::siginfo_t, _ctx: *mut libc::c_void) {
+    let addr = unsafe { fault_address(info) };
+    if !is_stack_fault(addr, STACK_LOW.load(Ordering::SeqCst), STACK_HIGH.load(Ordering::SeqCst)) {
+        unsafe { restore_previous(sig) };
+        return;
+    }
+    throw_range_error();
+}
+
+/// Put back the action that was in place before ours; returning from the handler then
+/// re-runs the faulting instruction under it
+#[cfg(unix)]
+unsafe fn restore_previous(sig: libc::c_int) {
+    let index = HANDLED_SIGNALS.iter().position(|&s| s == sig);
+    let action: libc::sigaction = match index.and_then(|i| PREVIOUS_ACTIONS[i]) {
+        Some(previous) => previous,
+        None => {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = libc::SIG_DFL;
+            libc::sigemptyset(&mut action.sa_mask);
+            action
+        }
+    };
+    libc::sigaction(sig, &action, std::ptr::null_mut());
+}
+
+/// `RangeError: Maximum call stack size exceeded` followed by the innermost frames
+fn overflow_stack_text(frames: &[String]) -> String {
+    let mut text = format!("RangeError: {}", MESSAGE);
+    for frame in frames.iter().take(STACK_TRACE_LIMIT) {
+        text.push_str("\n    at ");
+        text.push_str(frame);
+    }
+    text
+}
+
+/// Throw the overflow as a RangeError; `js_throw` longjmps to the innermost `try`,
+/// off the alternate stack and back onto the unwound program stack
+fn throw_range_error() -> ! {
+    let message = js_string_from_bytes(MESSAGE.as_ptr(), MESSAGE.len() as u32);
+    let error = js_error_new_with_message(message);
+    let stack = overflow_stack_text(&crate::crash::shadow_stack());
+    unsafe {
+        (*error).name = js_string_from_bytes(b"RangeError".as_ptr(), 10);
+        (*error).stack = js_string_from_bytes(stack.as_ptr(), stack.len() as u32);
+    }
+    crate::exception::js_throw(f64::from_bits(JSValue::pointer(error as *const u8).bits()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_stack_fault() {
+        let (low, high) = (0x7000_0000, 0x7080_0000);
+        assert!(is_stack_fault(low + 16, low, high));
+        assert!(is_stack_fault(low - 8, low, high));
+        assert!(!is_stack_fault(high, low, high));
+        assert!(!is_stack_fault(0, low, high));
+        // Bounds unknown: nothing counts as an overflow
+        assert!(!is_stack_fault(16, 0, 0));
+    }
+
+    #[test]
+    fn test_overflow_stack_text() {
+        let frames: Vec<String> = (0..12).map(|i| format!("depth{}", i)).collect();
+        let text = overflow_stack_text(&frames);
+        assert!(text.starts_with("RangeError: Maximum call stack size exceeded\n    at depth0\n"));
+        assert_eq!(text.lines().count(), 1 + STACK_TRACE_LIMIT);
+        assert_eq!(overflow_stack_text(&[]), "RangeError: Maximum call stack size exceeded");
+    }
+}
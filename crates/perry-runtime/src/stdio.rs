@@ -0,0 +1,225 @@
+//! Standard streams - process.stdin, process.stdout and process.stderr
+//!
+//! Writes go through the same `std::io` handles as console.log and are flushed before
+//! `write()` returns, so manual writes and logged lines come out in program order and
+//! nothing is left in a buffer when the process exits. A write blocks until the pipe has
+//! taken the data, which is where backpressure comes from: the writer never runs ahead of
+//! the reader on the other end, `write()` returns true, and its callback runs right after.
+//!
+//! stdin is read a chunk at a time as data arrives. Chunks are UTF-8 strings that never
+//! split a character; `for await` loops and `read()` pull chunks on demand, while 'data'
+//! listeners and `pipe()` destinations are fed once the top-level code has finished.
+
+use std::io::{ErrorKind, Read, Write};
+use std::sync::Mutex;
+
+use crate::closure::{js_closure_call0, js_closure_call1, ClosureHeader};
+use crate::error::js_error_new_with_message;
+use crate::string::{js_string_from_bytes, StringHeader};
+use crate::value::{js_jsvalue_to_string, JSValue};
+
+/// Most bytes handed out in one chunk (Node's default highWaterMark for stdin)
+const CHUNK_SIZE: usize = 64 * 1024;
+
+struct StdinState {
+    /// Bytes read but not handed out yet: the start of a character cut off by a read
+    pending: Vec<u8>,
+    ended: bool,
+}
+
+static STDIN: Mutex<StdinState> = Mutex::new(StdinState { pending: Vec::new(), ended: false });
+
+/// 'data' and 'end' listeners on process.stdin
+static DATA_LISTENERS: Mutex<Vec<i64>> = Mutex::new(Vec::new());
+static END_LISTENERS: Mutex<Vec<i64>> = Mutex::new(Vec::new());
+
+/// File descriptors process.stdin is piped to
+static PIPE_TARGETS: Mutex<Vec<i32>> = Mutex::new(Vec::new());
+
+/// Length of the prefix of `bytes` that ends on a character boundary
+fn complete_len(bytes: &[u8]) -> usize {
+    let len = bytes.len();
+    for back in 1..=len.min(3) {
+        let byte = bytes[len - back];
+        if byte & 0xC0 == 0x80 {
+            continue; // continuation byte
+        }
+        let width = if byte >= 0xF0 { 4 } else if byte >= 0xE0 { 3 } else if byte >= 0xC0 { 2 } else { 1 };
+        return if width > back { len - back } else { len };
+    }
+    len
+}
+
+/// The next chunk of stdin, or None once it is exhausted
+fn read_chunk() -> Option<Vec<u8>> {
+    let mut state = STDIN.lock().unwrap_or_else(|e| e.into_inner());
+    if state.ended {
+        return None;
+    }
+    let mut buf = vec![0u8; CHUNK_SIZE];
+    loop {
+        let n = match std::io::stdin().lock().read(&mut buf) {
+            Ok(n) => n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(_) => 0,
+        };
+        if n == 0 {
+            state.ended = true;
+            // A truncated character at the very end is passed on as it is
+            return if state.pending.is_empty() { None } else { Some(std::mem::take(&mut state.pending)) };
+        }
+        state.pending.extend_from_slice(&buf[..n]);
+        let complete = complete_len(&state.pending);
+        if complete > 0 {
+            let rest = state.pending.split_off(complete);
+            return Some(std::mem::replace(&mut state.pending, rest));
+        }
+    }
+}
+
+fn chunk_to_value(chunk: &[u8]) -> f64 {
+    let text = String::from_utf8_lossy(chunk);
+    let ptr = js_string_from_bytes(text.as_ptr(), text.len() as u32);
+    f64::from_bits(JSValue::string_ptr(ptr).bits())
+}
+
+/// Write to stdout (fd 1) or stderr (fd 2) and flush
+fn write_fd(fd: i32, bytes: &[u8]) -> bool {
+    let result = if fd == 2 {
+        let mut err = std::io::stderr().lock();
+        err.write_all(bytes).and_then(|_| err.flush())
+    } else {
+        let mut out = std::io::stdout().lock();
+        out.write_all(bytes).and_then(|_| out.flush())
+    };
+    result.is_ok()
+}
+
+/// Closure pointer from a raw or NaN-boxed callback value (0 for none)
+fn closure_ptr(callback: i64) -> i64 {
+    let bits = callback as u64;
+    if bits >> 48 >= 0x7FF8 {
+        let jsval = JSValue::from_bits(bits);
+        if !jsval.is_pointer() {
+            return 0;
+        }
+        (bits & 0x0000_FFFF_FFFF_FFFF) as i64
+    } else {
+        callback
+    }
+}
+
+/// process.stdout.write(data, callback?) / process.stderr.write(data, callback?) -> boolean
+#[no_mangle]
+pub extern "C" fn js_stdio_write(fd: i32, data: f64, callback: i64) -> f64 {
+    let ok = unsafe {
+        let ptr: *const StringHeader = js_jsvalue_to_string(data);
+        if ptr.is_null() {
+            true
+        } else {
+            let len = (*ptr).length as usize;
+            let data_ptr = (ptr as *const u8).add(std::mem::size_of::<StringHeader>());
+            write_fd(fd, std::slice::from_raw_parts(data_ptr, len))
+        }
+    };
+    let callback = closure_ptr(callback);
+    if callback != 0 {
+        let err = if ok {
+            JSValue::null()
+        } else {
+            let message = if fd == 2 { "write to stderr failed" } else { "write to stdout failed" };
+            let message = js_string_from_bytes(message.as_ptr(), message.len() as u32);
+            JSValue::pointer(js_error_new_with_message(message) as *const u8)
+        };
+        js_closure_call1(callback as *const ClosureHeader, f64::from_bits(err.bits()));
+    }
+    f64::from_bits(JSValue::bool(ok).bits())
+}
+
+/// process.stdin.read() -> the next chunk as a string, or null at the end of input
+#[no_mangle]
+pub extern "C" fn js_stdin_read() -> f64 {
+    match read_chunk() {
+        Some(chunk) => chunk_to_value(&chunk),
+        None => f64::from_bits(JSValue::null().bits()),
+    }
+}
+
+/// process.stdin.on('data' | 'end', listener)
+#[no_mangle]
+pub extern "C" fn js_stdin_on(event: f64, listener: i64) {
+    let listener = closure_ptr(listener);
+    if listener == 0 {
+        return;
+    }
+    let name = unsafe {
+        let ptr: *const StringHeader = js_jsvalue_to_string(event);
+        if ptr.is_null() {
+            return;
+        }
+        let len = (*ptr).length as usize;
+        let data_ptr = (ptr as *const u8).add(std::mem::size_of::<StringHeader>());
+        std::slice::from_raw_parts(data_ptr, len).to_vec()
+    };
+    let listeners = match name.as_slice() {
+        b"data" => &DATA_LISTENERS,
+        b"end" | b"close" => &END_LISTENERS,
+        _ => return,
+    };
+    listeners.lock().unwrap_or_else(|e| e.into_inner()).push(listener);
+}
+
+/// process.stdin.pipe(process.stdout | process.stderr)
+#[no_mangle]
+pub extern "C" fn js_stdin_pipe(fd: i32) {
+    PIPE_TARGETS.lock().unwrap_or_else(|e| e.into_inner()).push(fd);
+}
+
+/// Feed stdin to its 'data' listeners and pipe destinations until it ends, then run the
+/// 'end' listeners. Called by main once the top-level code has run; does nothing when
+/// stdin was never put into flowing mode.
+#[no_mangle]
+pub extern "C" fn js_stdio_flow() {
+    let flowing = !DATA_LISTENERS.lock().unwrap_or_else(|e| e.into_inner()).is_empty()
+        || !END_LISTENERS.lock().unwrap_or_else(|e| e.into_inner()).is_empty()
+        || !PIPE_TARGETS.lock().unwrap_or_else(|e| e.into_inner()).is_empty();
+    if !flowing {
+        return;
+    }
+    while let Some(chunk) = read_chunk() {
+        // Copy the lists: listeners may add listeners of their own
+        let targets = PIPE_TARGETS.lock().unwrap_or_else(|e| e.into_inner()).clone();
+        for fd in targets {
+            write_fd(fd, &chunk);
+        }
+        let listeners = DATA_LISTENERS.lock().unwrap_or_else(|e| e.into_inner()).clone();
+        if !listeners.is_empty() {
+            let value = chunk_to_value(&chunk);
+            for listener in listeners {
+                js_closure_call1(listener as *const ClosureHeader, value);
+            }
+        }
+    }
+    let listeners = std::mem::take(&mut *END_LISTENERS.lock().unwrap_or_else(|e| e.into_inner()));
+    for listener in listeners {
+        js_closure_call0(listener as *const ClosureHeader);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chunks_end_on_character_boundaries() {
+        assert_eq!(complete_len(b"abc"), 3);
+        assert_eq!(complete_len(b""), 0);
+        let text = "a\u{e9}\u{20ac}\u{1f600}".as_bytes();
+        assert_eq!(complete_len(text), text.len());
+        // Cut inside the 4-byte emoji, the 3-byte euro sign and the 2-byte e-acute
+        assert_eq!(complete_len(&text[..text.len() - 1]), 6);
+        assert_eq!(complete_len(&text[..text.len() - 3]), 6);
+        assert_eq!(complete_len(&text[..5]), 3);
+        assert_eq!(complete_len(&text[..2]), 1);
+    }
+}
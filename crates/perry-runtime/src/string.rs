@@ -37,6 +37,8 @@ pub extern "C" fn js_string_from_bytes(data: *const u8, len: u32) -> *mut String
 pub extern "C" fn js_string_from_bytes_with_capacity(data: *const u8, len: u32, capacity: u32) -> *mut StringHeader {
     let capacity = capacity.max(len); // Ensure capacity >= len
     let layout = string_layout(capacity as usize);
+    crate::memory::charge(layout.size());
+    crate::memory::count_allocation(crate::memory::AllocKind::String, layout.size());
 
     unsafe {
         let ptr = alloc(layout) as *mut StringHeader;
@@ -57,6 +59,33 @@ pub extern "C" fn js_string_from_bytes_with_capacity(data: *const u8, len: u32,
     }
 }
 
+/// Interned strings: content -> string, and the set of their addresses. They are never
+/// freed or grown in place.
+static INTERNED: std::sync::Mutex<Option<(std::collections::HashMap<Box<[u8]>, usize>, std::collections::HashSet<usize>)>> =
+    std::sync::Mutex::new(None);
+
+/// The one string with these bytes, created on first use. Object keys are interned so
+/// that objects with the same keys share key pointers, which property inline caches
+/// compare.
+#[no_mangle]
+pub extern "C" fn js_string_intern(data: *const u8, len: u32) -> *mut StringHeader {
+    let bytes: &[u8] = if data.is_null() || len == 0 { &[] } else { unsafe { slice::from_raw_parts(data, len as usize) } };
+    let mut interned = INTERNED.lock().unwrap();
+    let (by_content, addresses) = interned.get_or_insert_with(Default::default);
+    if let Some(&ptr) = by_content.get(bytes) {
+        return ptr as *mut StringHeader;
+    }
+    let ptr = js_string_from_bytes(bytes.as_ptr(), len);
+    by_content.insert(bytes.into(), ptr as usize);
+    addresses.insert(ptr as usize);
+    ptr
+}
+
+fn is_interned(s: *const StringHeader) -> bool {
+    let interned = INTERNED.lock().unwrap();
+    interned.as_ref().is_some_and(|(_, addresses)| addresses.contains(&(s as usize)))
+}
+
 /// Append a string to another string in-place if possible
 /// Returns the (possibly reallocated) string pointer
 /// This is the key optimization for `str = str + x` patterns
@@ -97,10 +126,18 @@ pub extern "C" fn js_string_append(dest: *mut StringHeader, src: *const StringHe
             return dest;
         }
 
+        // Interned strings are shared by every object with that key: copy, don't grow
+        if is_interned(dest) {
+            return js_string_concat(dest, src);
+        }
+
         // Need to reallocate - use 2x growth strategy
         let new_cap = (new_len * 2).max(32); // At least 32 bytes, or 2x needed
         let old_layout = string_layout(dest_cap as usize);
         let new_layout = string_layout(new_cap as usize);
+        let grown = new_layout.size().saturating_sub(old_layout.size());
+        crate::memory::charge(grown);
+        crate::memory::count_growth(crate::memory::AllocKind::String, grown);
 
         let new_ptr = realloc(dest as *mut u8, old_layout, new_layout.size()) as *mut StringHeader;
         if new_ptr.is_null() {
@@ -141,14 +178,14 @@ pub extern "C" fn js_string_length(s: *const StringHeader) -> u32 {
 }
 
 /// Get the data pointer for a string
-fn string_data(s: *const StringHeader) -> *const u8 {
+pub(crate) fn string_data(s: *const StringHeader) -> *const u8 {
     unsafe {
         (s as *const u8).add(std::mem::size_of::<StringHeader>())
     }
 }
 
 /// Get string as a Rust &str (for internal use)
-fn string_as_str<'a>(s: *const StringHeader) -> &'a str {
+pub(crate) fn string_as_str<'a>(s: *const StringHeader) -> &'a str {
     unsafe {
         let len = (*s).length as usize;
         let cap = (*s).capacity as usize;
@@ -168,6 +205,8 @@ pub extern "C" fn js_string_concat(a: *const StringHeader, b: *const StringHeade
 
     let total_size = std::mem::size_of::<StringHeader>() + total_len as usize;
     let layout = Layout::from_size_align(total_size, 8).unwrap();
+    crate::memory::charge(layout.size());
+    crate::memory::count_allocation(crate::memory::AllocKind::String, layout.size());
 
     unsafe {
         let ptr = alloc(layout) as *mut StringHeader;
@@ -361,6 +400,29 @@ pub extern "C" fn js_string_equals(a: *const StringHeader, b: *const StringHeade
     true
 }
 
+/// Tag of a string for `switch` dispatch over string literal cases: FNV-1a over its bytes.
+/// Codegen computes the same tag for each `case` literal at compile time, so tags agree
+/// across modules without a registry; a switch jumps on the tag and confirms the match with
+/// `js_string_equals_bytes`. 0 for a null pointer (not a string).
+#[no_mangle]
+pub extern "C" fn js_string_switch_tag(s: *const StringHeader) -> u64 {
+    if s.is_null() {
+        return 0;
+    }
+    let len = unsafe { (*s).length } as usize;
+    let bytes = unsafe { std::slice::from_raw_parts(string_data(s), len) };
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3))
+}
+
+/// Whether a string holds exactly the `len` bytes at `bytes`
+#[no_mangle]
+pub extern "C" fn js_string_equals_bytes(s: *const StringHeader, bytes: *const u8, len: u32) -> bool {
+    if s.is_null() || unsafe { (*s).length } != len {
+        return false;
+    }
+    len == 0 || unsafe { std::slice::from_raw_parts(string_data(s), len as usize) == std::slice::from_raw_parts(bytes, len as usize) }
+}
+
 /// Check if a string starts with a prefix
 #[no_mangle]
 pub extern "C" fn js_string_starts_with(s: *const StringHeader, prefix: *const StringHeader) -> i32 {
@@ -664,6 +726,157 @@ pub extern "C" fn js_string_repeat(s: *const StringHeader, count: i32) -> *mut S
     ret
 }
 
+// Locale-sensitive methods: localeCompare, toLocaleUpperCase and toLocaleLowerCase.
+// Collation and the language-specific case rules (Turkish dotted and dotless i,
+// Lithuanian, ...) come from ICU4X's compiled data. toUpperCase and toLowerCase need no
+// locale: Rust's full Unicode mapping is what JavaScript specifies for them.
+
+/// The host's locale, used when a method is given none: LC_ALL or LANG (`de_DE.UTF-8` is
+/// `de-DE`), or the root locale when neither names one
+fn default_locale() -> &'static icu_locale_core::Locale {
+    static DEFAULT: std::sync::OnceLock<icu_locale_core::Locale> = std::sync::OnceLock::new();
+    DEFAULT.get_or_init(|| {
+        ["LC_ALL", "LANG"]
+            .iter()
+            .filter_map(|var| std::env::var(var).ok())
+            .find(|value| !value.is_empty())
+            .filter(|value| value != "C" && value != "POSIX")
+            .and_then(|value| {
+                let tag = value.split(['.', '@']).next().unwrap_or_default().replace('_', "-");
+                icu_locale_core::Locale::try_from_str(&tag).ok()
+            })
+            .unwrap_or(icu_locale_core::Locale::UNKNOWN)
+    })
+}
+
+/// The locale a `locales` argument names: a BCP 47 tag, or the default for undefined and
+/// anything that isn't a valid tag (JavaScript throws a RangeError for those)
+fn requested_locale(locales: f64) -> icu_locale_core::Locale {
+    let value = crate::value::JSValue::from_bits(locales.to_bits());
+    if value.is_string() {
+        if let Ok(locale) = icu_locale_core::Locale::try_from_str(string_as_str(value.as_string_ptr())) {
+            return locale;
+        }
+    }
+    default_locale().clone()
+}
+
+/// Convert string to uppercase with the case rules of a locale
+/// str.toLocaleUpperCase(locales)
+#[no_mangle]
+pub extern "C" fn js_string_to_locale_upper_case(s: *const StringHeader, locales: f64) -> *mut StringHeader {
+    if s.is_null() {
+        return js_string_from_bytes(ptr::null(), 0);
+    }
+    let locale = requested_locale(locales);
+    let upper = icu_casemap::CaseMapper::new().uppercase_to_string(string_as_str(s), &locale.id);
+    js_string_from_str(&upper)
+}
+
+/// Convert string to lowercase with the case rules of a locale
+/// str.toLocaleLowerCase(locales)
+#[no_mangle]
+pub extern "C" fn js_string_to_locale_lower_case(s: *const StringHeader, locales: f64) -> *mut StringHeader {
+    if s.is_null() {
+        return js_string_from_bytes(ptr::null(), 0);
+    }
+    let locale = requested_locale(locales);
+    let lower = icu_casemap::CaseMapper::new().lowercase_to_string(string_as_str(s), &locale.id);
+    js_string_from_str(&lower)
+}
+
+/// What differences between letters count (`Intl.Collator`'s `sensitivity`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Sensitivity {
+    /// a = á = A
+    Base,
+    /// a ≠ á, a = A
+    Accent,
+    /// a = á, a ≠ A
+    Case,
+    /// a ≠ á ≠ A
+    Variant,
+}
+
+thread_local! {
+    /// Collators by locale tag, sensitivity and numeric ordering, built on first use
+    static COLLATORS: std::cell::RefCell<std::collections::HashMap<(String, Sensitivity, bool), std::rc::Rc<icu_collator::CollatorBorrowed<'static>>>> =
+        std::cell::RefCell::new(std::collections::HashMap::new());
+}
+
+fn collator(
+    mut locale: icu_locale_core::Locale,
+    sensitivity: Sensitivity,
+    numeric: bool,
+) -> Option<std::rc::Rc<icu_collator::CollatorBorrowed<'static>>> {
+    use icu_collator::options::{CaseLevel, CollatorOptions, Strength};
+    use icu_locale_core::extensions::unicode::{key, value};
+
+    let cache_key = (locale.to_string(), sensitivity, numeric);
+    if let Some(collator) = COLLATORS.with(|collators| collators.borrow().get(&cache_key).cloned()) {
+        return Some(collator);
+    }
+    if numeric {
+        // "item2" before "item10": the -u-kn-true keyword of the locale
+        locale.extensions.unicode.keywords.set(key!("kn"), value!("true"));
+    }
+    let mut options = CollatorOptions::default();
+    options.strength = Some(match sensitivity {
+        Sensitivity::Base | Sensitivity::Case => Strength::Primary,
+        Sensitivity::Accent => Strength::Secondary,
+        Sensitivity::Variant => Strength::Tertiary,
+    });
+    if sensitivity == Sensitivity::Case {
+        options.case_level = Some(CaseLevel::On);
+    }
+    let collator = std::rc::Rc::new(icu_collator::Collator::try_new((&locale).into(), options).ok()?);
+    COLLATORS.with(|collators| collators.borrow_mut().insert(cache_key, collator.clone()));
+    Some(collator)
+}
+
+/// Compare two strings in the sort order of a locale: negative, 0 or positive like
+/// str.localeCompare(that, locales, options). Of the options, `sensitivity` and
+/// `numeric` are supported.
+#[no_mangle]
+pub extern "C" fn js_string_locale_compare(s: *const StringHeader, that: *const StringHeader, locales: f64, options: f64) -> f64 {
+    let a = if s.is_null() { "" } else { string_as_str(s) };
+    let b = if that.is_null() { "" } else { string_as_str(that) };
+
+    let mut sensitivity = Sensitivity::Variant;
+    let mut numeric = false;
+    let options = crate::value::JSValue::from_bits(options.to_bits());
+    // Object literals arrive NaN-boxed or as raw pointer bits
+    let is_raw_pointer = options.bits() > 0x10000 && options.bits() >> 48 == 0;
+    let obj = if options.is_pointer() || is_raw_pointer {
+        (options.bits() & 0x0000_FFFF_FFFF_FFFF) as *const crate::object::ObjectHeader
+    } else {
+        ptr::null()
+    };
+    if !obj.is_null() {
+        unsafe {
+            if let Some(name) = crate::object::get_field_cached(obj, b"sensitivity", ptr::null_mut()) {
+                if name.is_string() {
+                    sensitivity = match string_as_str(name.as_string_ptr()) {
+                        "base" => Sensitivity::Base,
+                        "accent" => Sensitivity::Accent,
+                        "case" => Sensitivity::Case,
+                        _ => Sensitivity::Variant,
+                    };
+                }
+            }
+            if let Some(flag) = crate::object::get_field_cached(obj, b"numeric", ptr::null_mut()) {
+                numeric = flag.is_bool() && flag.as_bool();
+            }
+        }
+    }
+
+    match collator(requested_locale(locales), sensitivity, numeric) {
+        Some(collator) => collator.compare(a, b) as i32 as f64,
+        // No collation data for the locale: code point order
+        None => a.cmp(b) as i32 as f64,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -684,6 +897,17 @@ mod tests {
         assert_eq!(string_as_str(c), "hello world");
     }
 
+    #[test]
+    fn test_string_switch_tag() {
+        let s = js_string_from_bytes(b"ADD_TODO".as_ptr(), 8);
+        // FNV-1a, as codegen tags `case "ADD_TODO":`
+        assert_eq!(js_string_switch_tag(s), 0xb407_2b53_0f0d_fca9);
+        assert_ne!(js_string_switch_tag(s), js_string_switch_tag(js_string_from_bytes(b"ADD_TODOS".as_ptr(), 9)));
+        assert!(js_string_equals_bytes(s, b"ADD_TODO".as_ptr(), 8));
+        assert!(!js_string_equals_bytes(s, b"ADD_TOD0".as_ptr(), 8));
+        assert!(!js_string_equals_bytes(std::ptr::null(), b"".as_ptr(), 0));
+    }
+
     #[test]
     fn test_string_slice() {
         let s = js_string_from_bytes(b"hello world".as_ptr(), 11);
@@ -730,4 +954,36 @@ mod tests {
             assert_eq!(string_as_str(ptr2), "c");
         }
     }
+
+    fn js_str(s: &str) -> *mut StringHeader {
+        js_string_from_str(s)
+    }
+
+    fn locale(tag: &str) -> f64 {
+        crate::value::js_nanbox_string(js_str(tag) as i64)
+    }
+
+    #[test]
+    fn test_locale_case_mapping() {
+        let undefined = f64::from_bits(crate::value::JSValue::undefined().bits());
+        assert_eq!(string_as_str(js_string_to_upper_case(js_str("straße"))), "STRASSE");
+        assert_eq!(string_as_str(js_string_to_locale_upper_case(js_str("istanbul"), locale("tr"))), "İSTANBUL");
+        assert_eq!(string_as_str(js_string_to_locale_lower_case(js_str("DIYARBAKIR"), locale("tr"))), "dıyarbakır");
+        assert_eq!(string_as_str(js_string_to_locale_upper_case(js_str("istanbul"), locale("en"))), "ISTANBUL");
+        assert_eq!(string_as_str(js_string_to_locale_lower_case(js_str("ΟΔΟΣ"), undefined)), "οδος");
+    }
+
+    #[test]
+    fn test_locale_compare() {
+        let undefined = f64::from_bits(crate::value::JSValue::undefined().bits());
+        let compare = |a: &str, b: &str, tag: &str| js_string_locale_compare(js_str(a), js_str(b), locale(tag), undefined);
+        // Letters sort by alphabet, not by code point
+        assert!(compare("a", "B", "en") < 0);
+        assert!(compare("résumé", "resume", "en") > 0);
+        assert!(compare("resume", "rèsume", "en") < 0);
+        // ä is a variant of a in German, a letter after z in Swedish
+        assert!(compare("ä", "z", "de") < 0);
+        assert!(compare("ä", "z", "sv") > 0);
+        assert_eq!(compare("straße", "straße", "de"), 0.0);
+    }
 }
@@ -0,0 +1,338 @@
+//! Symbol names of compiled TypeScript functions
+//!
+//! Codegen gives module-local functions (non-exported functions, closures, monomorphized
+//! generics, and class members other modules don't link against) mangled names that say
+//! where they come from, so perf, objdump and crash backtraces can be mapped back to the
+//! source:
+//!
+//! ```text
+//! symbol    = "_P" kind component+ [ "I" component+ ] "E"
+//! component = <decimal byte length> <escaped name>
+//! ```
+//!
+//! The first component is the module (its path relative to the project root) and the
+//! rest name the function inside it. The `I` group holds the type arguments of a
+//! monomorphized generic, which belong to the first name (the function or class). Bytes
+//! other than ASCII letters, digits and `_` are escaped as `$` and two hex digits, so a
+//! symbol is a plain identifier; so is a leading digit, which would read as part of the
+//! length. Kinds:
+//!
+//! - `F` function, `N` its integer-only specialization, `B` / `V` its baseline and x86-64-v3
+//!   versions under `// @perry-opt: multiversion` (path: function)
+//! - `U` the uncached body of a memoized function or method (path: function, or class and method)
+//! - `K` constructor, `M` method, `S` static method, `G` getter, `T` setter (path: class, member)
+//! - `C` closure (path: the function or class member it is defined in, if known, then its id)
+//! - `D` module variable, `Y` static field: data that `--hot-reload` builds export so a patch
+//!   shares the running program's (path: variable and its index among the module's variables
+//!   of that name, or class and field)
+//!
+//! `_PM14src$2Fapp$2Ets5Cache3getI6stringE` demangles to `src/app.ts::Cache<string>#get`.
+//! `perry demangle` does this for any text, and crash reports for their native backtrace.
+
+use std::fmt;
+
+/// What a mangled symbol names
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolKind {
+    Function,
+    /// The i64 version of an integer-only function
+    IntegerFunction,
+    /// The baseline version of a multiversioned function
+    BaselineFunction,
+    /// The x86-64-v3 version of a multiversioned function
+    CpuVariant,
+    /// The body of a memoized function, behind its caching wrapper
+    MemoizedBody,
+    Constructor,
+    Method,
+    StaticMethod,
+    Getter,
+    Setter,
+    Closure,
+    /// A module-level variable in a hot-reload build
+    ModuleVariable,
+    /// A class's static field in a hot-reload build
+    StaticField,
+}
+
+impl SymbolKind {
+    fn code(self) -> u8 {
+        match self {
+            SymbolKind::Function => b'F',
+            SymbolKind::IntegerFunction => b'N',
+            SymbolKind::BaselineFunction => b'B',
+            SymbolKind::CpuVariant => b'V',
+            SymbolKind::MemoizedBody => b'U',
+            SymbolKind::Constructor => b'K',
+            SymbolKind::Method => b'M',
+            SymbolKind::StaticMethod => b'S',
+            SymbolKind::Getter => b'G',
+            SymbolKind::Setter => b'T',
+            SymbolKind::Closure => b'C',
+            SymbolKind::ModuleVariable => b'D',
+            SymbolKind::StaticField => b'Y',
+        }
+    }
+
+    fn from_code(code: u8) -> Option<Self> {
+        Some(match code {
+            b'F' => SymbolKind::Function,
+            b'N' => SymbolKind::IntegerFunction,
+            b'B' => SymbolKind::BaselineFunction,
+            b'V' => SymbolKind::CpuVariant,
+            b'U' => SymbolKind::MemoizedBody,
+            b'K' => SymbolKind::Constructor,
+            b'M' => SymbolKind::Method,
+            b'S' => SymbolKind::StaticMethod,
+            b'G' => SymbolKind::Getter,
+            b'T' => SymbolKind::Setter,
+            b'C' => SymbolKind::Closure,
+            b'D' => SymbolKind::ModuleVariable,
+            b'Y' => SymbolKind::StaticField,
+            _ => return None,
+        })
+    }
+}
+
+/// A compiled function's identity, as encoded in its symbol name
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    pub kind: SymbolKind,
+    /// Module path relative to the project root
+    pub module: String,
+    /// Names inside the module (see the module docs for each kind)
+    pub path: Vec<String>,
+    /// Type arguments of a monomorphized function or class, as TypeScript
+    pub type_args: Vec<String>,
+}
+
+impl Symbol {
+    pub fn new(kind: SymbolKind, module: impl Into<String>, path: Vec<String>) -> Self {
+        Self { kind, module: module.into(), path, type_args: Vec::new() }
+    }
+
+    pub fn with_type_args(mut self, type_args: Vec<String>) -> Self {
+        self.type_args = type_args;
+        self
+    }
+
+    pub fn mangle(&self) -> String {
+        let mut symbol = String::from("_P");
+        symbol.push(self.kind.code() as char);
+        push_component(&mut symbol, &self.module);
+        for name in &self.path {
+            push_component(&mut symbol, name);
+        }
+        if !self.type_args.is_empty() {
+            symbol.push('I');
+            for arg in &self.type_args {
+                push_component(&mut symbol, arg);
+            }
+        }
+        symbol.push('E');
+        symbol
+    }
+
+    /// Decode a whole mangled symbol. Mach-O's extra leading underscore is accepted.
+    pub fn demangle(symbol: &str) -> Option<Self> {
+        let start = if symbol.starts_with("__P") { 1 } else { 0 };
+        match parse(symbol.as_bytes(), start)? {
+            (parsed, end) if end == symbol.len() => Some(parsed),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}::", self.module)?;
+        let (owner, index) = match (self.kind, self.path.split_last()) {
+            (SymbolKind::Closure | SymbolKind::ModuleVariable, Some((id, owner))) => (owner, Some(id)),
+            _ => (self.path.as_slice(), None),
+        };
+        if let Some((name, members)) = owner.split_first() {
+            f.write_str(name)?;
+            if !self.type_args.is_empty() {
+                write!(f, "<{}>", self.type_args.join(", "))?;
+            }
+            for member in members {
+                let separator = match self.kind {
+                    SymbolKind::StaticMethod | SymbolKind::StaticField => ".",
+                    _ => "#",
+                };
+                write!(f, "{}{}", separator, member)?;
+            }
+        }
+        match self.kind {
+            SymbolKind::IntegerFunction => f.write_str(" [i64]"),
+            SymbolKind::BaselineFunction => f.write_str(" [baseline]"),
+            SymbolKind::CpuVariant => f.write_str(" [x86-64-v3]"),
+            SymbolKind::MemoizedBody => f.write_str(" [uncached]"),
+            SymbolKind::Constructor => f.write_str("#constructor"),
+            SymbolKind::Getter => f.write_str(" [get]"),
+            SymbolKind::Setter => f.write_str(" [set]"),
+            SymbolKind::ModuleVariable => write!(f, " [var #{}]", index.map(String::as_str).unwrap_or("?")),
+            SymbolKind::StaticField => f.write_str(" [static]"),
+            SymbolKind::Closure => {
+                let separator = if owner.is_empty() { "" } else { "::" };
+                write!(f, "{}{{closure#{}}}", separator, index.map(String::as_str).unwrap_or("?"))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Replace every mangled symbol in `text` with its demangled form (a `c++filt` for perry)
+pub fn demangle_text(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut out = String::with_capacity(text.len());
+    let mut copied = 0;
+    let mut i = 0;
+    while i + 1 < bytes.len() {
+        let boundary = i == 0 || !(bytes[i - 1].is_ascii_alphanumeric() || bytes[i - 1] == b'$');
+        if boundary && bytes[i] == b'_' {
+            // Mach-O symbols carry one more leading underscore
+            let start = if bytes[i + 1] == b'_' { i + 1 } else { i };
+            if let Some((symbol, end)) = parse(bytes, start) {
+                out.push_str(&text[copied..i]);
+                out.push_str(&symbol.to_string());
+                copied = end;
+                i = end;
+                continue;
+            }
+        }
+        i += 1;
+    }
+    out.push_str(&text[copied..]);
+    out
+}
+
+fn push_component(symbol: &mut String, name: &str) {
+    let mut escaped = String::with_capacity(name.len());
+    for (i, byte) in name.bytes().enumerate() {
+        if byte.is_ascii_alphabetic() || byte == b'_' || (byte.is_ascii_digit() && i > 0) {
+            escaped.push(byte as char);
+        } else {
+            escaped.push_str(&format!("${:02X}", byte));
+        }
+    }
+    symbol.push_str(&escaped.len().to_string());
+    symbol.push_str(&escaped);
+}
+
+/// Parse a symbol starting at `start`; returns it and the index just past its `E`
+fn parse(bytes: &[u8], start: usize) -> Option<(Symbol, usize)> {
+    if bytes.get(start..start + 2)? != b"_P" {
+        return None;
+    }
+    let kind = SymbolKind::from_code(*bytes.get(start + 2)?)?;
+    let mut pos = start + 3;
+    let mut names = Vec::new();
+    let mut type_args = Vec::new();
+    let mut in_type_args = false;
+    loop {
+        match *bytes.get(pos)? {
+            b'E' => break,
+            b'I' if !in_type_args && names.len() >= 2 => {
+                in_type_args = true;
+                pos += 1;
+            }
+            b'0'..=b'9' => {
+                let (name, next) = parse_component(bytes, pos)?;
+                if in_type_args {
+                    type_args.push(name);
+                } else {
+                    names.push(name);
+                }
+                pos = next;
+            }
+            _ => return None,
+        }
+    }
+    if names.len() < 2 || (in_type_args && type_args.is_empty()) {
+        return None;
+    }
+    let module = names.remove(0);
+    Some((Symbol { kind, module, path: names, type_args }, pos + 1))
+}
+
+fn parse_component(bytes: &[u8], start: usize) -> Option<(String, usize)> {
+    let digits = bytes[start..].iter().take_while(|b| b.is_ascii_digit()).count();
+    let len: usize = std::str::from_utf8(&bytes[start..start + digits]).ok()?.parse().ok()?;
+    let begin = start + digits;
+    let escaped = bytes.get(begin..begin + len)?;
+    let mut name = Vec::with_capacity(len);
+    let mut i = 0;
+    while i < escaped.len() {
+        if escaped[i] == b'$' {
+            let hex = std::str::from_utf8(escaped.get(i + 1..i + 3)?).ok()?;
+            name.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else if escaped[i].is_ascii_alphanumeric() || escaped[i] == b'_' {
+            name.push(escaped[i]);
+            i += 1;
+        } else {
+            return None;
+        }
+    }
+    Some((String::from_utf8(name).ok()?, begin + len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn path(names: &[&str]) -> Vec<String> {
+        names.iter().map(|n| n.to_string()).collect()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let method = Symbol::new(SymbolKind::Method, "src/app.ts", path(&["Cache", "get"]))
+            .with_type_args(vec!["string".into()]);
+        let mangled = method.mangle();
+        assert_eq!(mangled, "_PM14src$2Fapp$2Ets5Cache3getI6stringE");
+        assert_eq!(Symbol::demangle(&mangled), Some(method.clone()));
+        assert_eq!(Symbol::demangle(&format!("_{}", mangled)), Some(method.clone()));
+        assert_eq!(method.to_string(), "src/app.ts::Cache<string>#get");
+
+        let pair = Symbol::new(SymbolKind::Function, "util.ts", path(&["pair"]))
+            .with_type_args(vec!["number".into(), "Map<string, number[]>".into()]);
+        assert_eq!(Symbol::demangle(&pair.mangle()), Some(pair.clone()));
+        assert_eq!(pair.to_string(), "util.ts::pair<number, Map<string, number[]>>");
+
+        let closure = Symbol::new(SymbolKind::Closure, "main.ts", path(&["Server", "listen", "12"]));
+        assert_eq!(closure.to_string(), "main.ts::Server#listen::{closure#12}");
+        assert_eq!(Symbol::demangle(&closure.mangle()), Some(closure.clone()));
+        let top_level = Symbol::new(SymbolKind::Closure, "main.ts", path(&["3"]));
+        assert_eq!(top_level.to_string(), "main.ts::{closure#3}");
+        assert_eq!(Symbol::new(SymbolKind::Getter, "m.ts", path(&["A", "size"])).to_string(), "m.ts::A#size [get]");
+        assert_eq!(Symbol::new(SymbolKind::StaticMethod, "m.ts", path(&["A", "of"])).to_string(), "m.ts::A.of");
+        let variant = Symbol::new(SymbolKind::CpuVariant, "m.ts", path(&["dot"])).mangle();
+        assert_eq!(Symbol::demangle(&variant).unwrap().to_string(), "m.ts::dot [x86-64-v3]");
+        let body = Symbol::new(SymbolKind::MemoizedBody, "m.ts", path(&["Rates", "lookup"])).mangle();
+        assert_eq!(Symbol::demangle(&body).unwrap().to_string(), "m.ts::Rates#lookup [uncached]");
+        let var = Symbol::new(SymbolKind::ModuleVariable, "m.ts", path(&["count", "0"])).mangle();
+        assert_eq!(Symbol::demangle(&var).unwrap().to_string(), "m.ts::count [var #0]");
+        let field = Symbol::new(SymbolKind::StaticField, "m.ts", path(&["Pool", "size"])).mangle();
+        assert_eq!(Symbol::demangle(&field).unwrap().to_string(), "m.ts::Pool.size [static]");
+
+        // Truncated or foreign symbols don't demangle
+        assert_eq!(Symbol::demangle(&mangled[..mangled.len() - 1]), None);
+        assert_eq!(Symbol::demangle("_ZN4core3fmt5write17h0123456789abcdefE"), None);
+        assert_eq!(Symbol::demangle("_PF9main$2EtsE"), None);
+    }
+
+    #[test]
+    fn test_demangle_text() {
+        let fib = Symbol::new(SymbolKind::IntegerFunction, "main.ts", path(&["fib"])).mangle();
+        let ctor = Symbol::new(SymbolKind::Constructor, "main.ts", path(&["Point"])).mangle();
+        let text = format!("  42.10%  app  [.] {}\n   3: 0x1000 - _{}\n  js_array_push _P\n", fib, ctor);
+        assert_eq!(
+            demangle_text(&text),
+            "  42.10%  app  [.] main.ts::fib [i64]\n   3: 0x1000 - main.ts::Point#constructor\n  js_array_push _P\n"
+        );
+        // Only whole symbols are replaced
+        assert_eq!(demangle_text(&format!("x{}", fib)), format!("x{}", fib));
+    }
+}
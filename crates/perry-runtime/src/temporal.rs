@@ -0,0 +1,1306 @@
+//! Temporal subset: ISO calendar dates, durations and zoned date-times
+//!
+//! The arithmetic behind `Temporal.PlainDate`, `Temporal.Duration` and
+//! `Temporal.ZonedDateTime`; perry-stdlib's `temporal` module wraps these values in
+//! handles. Only the ISO 8601 calendar is supported, and times are kept to the nanosecond.
+//!
+//! Time zones are read from TZif files: first from the table `perry compile --embed-tzdata`
+//! compiles into the executable (see `js_temporal_set_tzdata`), then from `$TZDIR` or
+//! /usr/share/zoneinfo. Instants after a zone's last transition follow the POSIX TZ rule
+//! in the file's footer.
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const NS_PER_SECOND: i128 = 1_000_000_000;
+const NS_PER_DAY: i128 = 86_400 * NS_PER_SECOND;
+
+/// Nanoseconds in each unit from hours down, in `Duration` field order
+const TIME_UNIT_NS: [i128; 6] = [3_600 * NS_PER_SECOND, 60 * NS_PER_SECOND, NS_PER_SECOND, 1_000_000, 1_000, 1];
+
+/// Largest year Temporal represents, in either direction
+const MAX_YEAR: i64 = 275_760;
+
+/// Epoch nanoseconds Temporal represents, in either direction (100 million days)
+const MAX_EPOCH_NS: i128 = 100_000_000 * NS_PER_DAY;
+
+/// Magic of the table `pack_tzdata` writes. Each zone follows as a little-endian u16 name
+/// length, the name, a u32 data length and the zone's TZif file.
+const TZDATA_MAGIC: &[u8; 4] = b"PTZ1";
+
+/// A Temporal operation failed; surfaced to JavaScript as a RangeError with this message
+#[derive(Debug, Clone, PartialEq)]
+pub struct TemporalError(pub String);
+
+pub type Result<T> = std::result::Result<T, TemporalError>;
+
+fn range_error<T>(message: impl Into<String>) -> Result<T> {
+    Err(TemporalError(message.into()))
+}
+
+// ============================================================================
+// Calendar
+// ============================================================================
+
+pub fn is_leap_year(year: i32) -> bool {
+    year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+pub fn days_in_month(year: i32, month: u8) -> u8 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Days since 1970-01-01 of a proleptic Gregorian date
+pub fn days_from_civil(year: i32, month: u8, day: u8) -> i64 {
+    let y = year as i64 - if month <= 2 { 1 } else { 0 };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let m = month as i64;
+    let doy = (153 * (if m > 2 { m - 3 } else { m + 9 }) + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// Date of a day count since 1970-01-01
+pub fn civil_from_days(days: i64) -> (i32, u8, u8) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year as i32, month as u8, day as u8)
+}
+
+/// Units of `until`/`since`, `total` and `largestUnit`, largest first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Unit {
+    Years,
+    Months,
+    Weeks,
+    Days,
+    Hours,
+    Minutes,
+    Seconds,
+    Milliseconds,
+    Microseconds,
+    Nanoseconds,
+}
+
+impl Unit {
+    /// A unit name, singular or plural (`"day"`, `"days"`)
+    pub fn parse(name: &str) -> Result<Unit> {
+        Ok(match name.strip_suffix('s').unwrap_or(name) {
+            "year" => Unit::Years,
+            "month" => Unit::Months,
+            "week" => Unit::Weeks,
+            "day" => Unit::Days,
+            "hour" => Unit::Hours,
+            "minute" => Unit::Minutes,
+            "second" => Unit::Seconds,
+            "millisecond" => Unit::Milliseconds,
+            "microsecond" => Unit::Microseconds,
+            "nanosecond" => Unit::Nanoseconds,
+            _ => return range_error(format!("Invalid unit: {}", name)),
+        })
+    }
+
+    fn is_calendar(self) -> bool {
+        self <= Unit::Weeks
+    }
+
+    /// Length in nanoseconds of a day or smaller unit
+    fn nanoseconds(self) -> i128 {
+        match self {
+            Unit::Days => NS_PER_DAY,
+            _ => TIME_UNIT_NS[self as usize - Unit::Hours as usize],
+        }
+    }
+}
+
+/// A calendar date without a time or time zone
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct PlainDate {
+    pub year: i32,
+    pub month: u8,
+    pub day: u8,
+}
+
+impl PlainDate {
+    /// The date, rejecting a month or day out of range
+    pub fn new(year: i64, month: i64, day: i64) -> Result<PlainDate> {
+        if !(1..=12).contains(&month) || year.abs() > MAX_YEAR {
+            return range_error(format!("Invalid date {}-{}-{}", year, month, day));
+        }
+        if day < 1 || day > days_in_month(year as i32, month as u8) as i64 {
+            return range_error(format!("Invalid date {}-{}-{}", year, month, day));
+        }
+        Ok(PlainDate { year: year as i32, month: month as u8, day: day as u8 })
+    }
+
+    /// The date with the month and day clamped into range (Temporal's `overflow: "constrain"`)
+    pub fn constrained(year: i64, month: i64, day: i64) -> Result<PlainDate> {
+        if month < 1 || day < 1 {
+            return range_error(format!("Invalid date {}-{}-{}", year, month, day));
+        }
+        let month = month.min(12);
+        if year.abs() > MAX_YEAR {
+            return range_error(format!("Invalid date {}-{}-{}", year, month, day));
+        }
+        let day = day.min(days_in_month(year as i32, month as u8) as i64);
+        PlainDate::new(year, month, day)
+    }
+
+    pub fn from_epoch_days(days: i64) -> Result<PlainDate> {
+        let (year, month, day) = civil_from_days(days);
+        PlainDate::new(year as i64, month as i64, day as i64)
+    }
+
+    /// Days since 1970-01-01
+    pub fn epoch_days(&self) -> i64 {
+        days_from_civil(self.year, self.month, self.day)
+    }
+
+    /// `2024-01-15`, or the date part of a longer ISO string (`2024-01-15T10:00[UTC]`)
+    pub fn parse(text: &str) -> Result<PlainDate> {
+        let mut cursor = Cursor::new(text);
+        let date = cursor.date().ok_or_else(|| invalid_string(text))?;
+        match cursor.peek() {
+            None | Some(b'T' | b't' | b' ' | b'[' | b'Z' | b'+' | b'-') => Ok(date),
+            _ => Err(invalid_string(text)),
+        }
+    }
+
+    /// ISO weekday: 1 is Monday, 7 is Sunday
+    pub fn day_of_week(&self) -> u8 {
+        ((self.epoch_days() + 3).rem_euclid(7) + 1) as u8
+    }
+
+    pub fn day_of_year(&self) -> u16 {
+        (self.epoch_days() - days_from_civil(self.year, 1, 1) + 1) as u16
+    }
+
+    pub fn days_in_month(&self) -> u8 {
+        days_in_month(self.year, self.month)
+    }
+
+    pub fn days_in_year(&self) -> u16 {
+        if is_leap_year(self.year) { 366 } else { 365 }
+    }
+
+    pub fn in_leap_year(&self) -> bool {
+        is_leap_year(self.year)
+    }
+
+    /// This date `months` months later, with the day clamped to the new month's length
+    fn add_months(&self, months: i64) -> Result<PlainDate> {
+        let total = self.year as i64 * 12 + self.month as i64 - 1 + months;
+        PlainDate::constrained(total.div_euclid(12), total.rem_euclid(12) + 1, self.day as i64)
+    }
+
+    /// Years and months first, clamping the day, then weeks and days. Time units count
+    /// only as far as they make whole days.
+    pub fn add(&self, duration: &Duration) -> Result<PlainDate> {
+        let moved = self.add_months(duration.years * 12 + duration.months)?;
+        let days = duration.weeks * 7 + duration.days + (duration.time_nanoseconds() / NS_PER_DAY) as i64;
+        PlainDate::from_epoch_days(moved.epoch_days() + days)
+    }
+
+    /// Duration from this date to `other` in units up to `largest` (days by default)
+    pub fn until(&self, other: &PlainDate, largest: Unit) -> Result<Duration> {
+        let mut result = Duration::default();
+        match largest {
+            Unit::Years | Unit::Months => {
+                let sign = if other < self { -1 } else { 1 };
+                let mut months = (other.year as i64 - self.year as i64) * 12 + other.month as i64 - self.month as i64;
+                // Back off while adding the months overshoots `other`
+                let mut moved = self.add_months(months)?;
+                while moved.cmp(other) == (if sign > 0 { Ordering::Greater } else { Ordering::Less }) {
+                    months -= sign;
+                    moved = self.add_months(months)?;
+                }
+                result.days = other.epoch_days() - moved.epoch_days();
+                if largest == Unit::Years {
+                    result.years = months / 12;
+                    result.months = months % 12;
+                } else {
+                    result.months = months;
+                }
+            }
+            Unit::Weeks => {
+                let days = other.epoch_days() - self.epoch_days();
+                result.weeks = days / 7;
+                result.days = days % 7;
+            }
+            _ => result.days = other.epoch_days() - self.epoch_days(),
+        }
+        Ok(result)
+    }
+}
+
+impl std::fmt::Display for PlainDate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if (0..=9999).contains(&self.year) {
+            write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+        } else {
+            let sign = if self.year < 0 { '-' } else { '+' };
+            write!(f, "{}{:06}-{:02}-{:02}", sign, self.year.unsigned_abs(), self.month, self.day)
+        }
+    }
+}
+
+// ============================================================================
+// Duration
+// ============================================================================
+
+/// An amount of time in calendar and clock units. All non-zero fields share one sign.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Duration {
+    pub years: i64,
+    pub months: i64,
+    pub weeks: i64,
+    pub days: i64,
+    pub hours: i64,
+    pub minutes: i64,
+    pub seconds: i64,
+    pub milliseconds: i64,
+    pub microseconds: i64,
+    pub nanoseconds: i64,
+}
+
+/// Names of the `Duration` fields, in order
+pub const DURATION_FIELDS: [&str; 10] = [
+    "years", "months", "weeks", "days", "hours", "minutes", "seconds", "milliseconds", "microseconds", "nanoseconds",
+];
+
+impl Duration {
+    /// The duration with these fields (in `DURATION_FIELDS` order), rejecting mixed signs
+    pub fn from_fields(fields: [i64; 10]) -> Result<Duration> {
+        let positive = fields.iter().any(|field| *field > 0);
+        let negative = fields.iter().any(|field| *field < 0);
+        if positive && negative {
+            return range_error("Mixed-sign values not allowed as duration fields");
+        }
+        let [years, months, weeks, days, hours, minutes, seconds, milliseconds, microseconds, nanoseconds] = fields;
+        Ok(Duration { years, months, weeks, days, hours, minutes, seconds, milliseconds, microseconds, nanoseconds })
+    }
+
+    pub fn fields(&self) -> [i64; 10] {
+        [
+            self.years, self.months, self.weeks, self.days, self.hours, self.minutes, self.seconds,
+            self.milliseconds, self.microseconds, self.nanoseconds,
+        ]
+    }
+
+    pub fn sign(&self) -> i8 {
+        self.fields().iter().find(|field| **field != 0).map_or(0, |field| field.signum() as i8)
+    }
+
+    pub fn negated(&self) -> Duration {
+        let mut fields = self.fields();
+        fields.iter_mut().for_each(|field| *field = -*field);
+        Duration::from_fields(fields).unwrap_or_default()
+    }
+
+    pub fn abs(&self) -> Duration {
+        if self.sign() < 0 { self.negated() } else { *self }
+    }
+
+    fn has_calendar_units(&self) -> bool {
+        self.years != 0 || self.months != 0 || self.weeks != 0
+    }
+
+    /// Hours and smaller units in nanoseconds
+    pub fn time_nanoseconds(&self) -> i128 {
+        self.fields()[4..].iter().zip(TIME_UNIT_NS).map(|(field, ns)| *field as i128 * ns).sum()
+    }
+
+    /// Days and smaller units in nanoseconds, with days counted as 24 hours
+    fn day_time_nanoseconds(&self) -> Result<i128> {
+        if self.has_calendar_units() {
+            return range_error("A relativeTo date is required for years, months and weeks");
+        }
+        Ok(self.days as i128 * NS_PER_DAY + self.time_nanoseconds())
+    }
+
+    /// `nanoseconds` balanced into days (24 hours) and smaller units, up to `largest`
+    pub fn from_nanoseconds(nanoseconds: i128, largest: Unit) -> Duration {
+        let sign = nanoseconds.signum() as i64;
+        let mut rest = nanoseconds.abs();
+        let mut fields = [0i64; 10];
+        for (index, unit) in DURATION_UNITS.iter().enumerate().skip(Unit::Days as usize) {
+            if *unit < largest {
+                continue;
+            }
+            let length = unit.nanoseconds();
+            fields[index] = (rest / length) as i64 * sign;
+            rest %= length;
+        }
+        Duration::from_fields(fields).unwrap_or_default()
+    }
+
+    /// Sum of two durations without calendar units, balanced up to the larger of their
+    /// largest units
+    pub fn add(&self, other: &Duration) -> Result<Duration> {
+        let total = self.day_time_nanoseconds()? + other.day_time_nanoseconds()?;
+        Ok(Duration::from_nanoseconds(total, self.largest_unit().min(other.largest_unit())))
+    }
+
+    /// Largest unit with a non-zero field (nanoseconds for a blank duration)
+    pub fn largest_unit(&self) -> Unit {
+        let index = self.fields().iter().position(|field| *field != 0).unwrap_or(9);
+        DURATION_UNITS[index]
+    }
+
+    /// The duration as a number of `unit`s. Days count as 24 hours.
+    pub fn total(&self, unit: Unit) -> Result<f64> {
+        if unit.is_calendar() {
+            return range_error("A relativeTo date is required for years, months and weeks");
+        }
+        Ok(self.day_time_nanoseconds()? as f64 / unit.nanoseconds() as f64)
+    }
+
+    /// Compare by length, with days counted as 24 hours
+    pub fn compare(&self, other: &Duration) -> Result<Ordering> {
+        Ok(self.day_time_nanoseconds()?.cmp(&other.day_time_nanoseconds()?))
+    }
+
+    /// `P1Y2M3W4DT5H6M7.008S`, with an optional sign. A fraction is allowed on the last
+    /// hours, minutes or seconds field.
+    pub fn parse(text: &str) -> Result<Duration> {
+        let invalid = || invalid_string(text);
+        let mut cursor = Cursor::new(text);
+        let sign: i64 = if cursor.eat(b'-') { -1 } else { cursor.eat(b'+'); 1 };
+        if !cursor.eat_ignore_case(b'P') {
+            return Err(invalid());
+        }
+        let mut fields = [0i64; 10];
+        let mut time_fraction: i128 = 0;
+        let mut in_time = false;
+        let mut last_index = None;
+        let mut seen_fraction = false;
+        while let Some(next) = cursor.peek() {
+            if next.eq_ignore_ascii_case(&b'T') {
+                if in_time {
+                    return Err(invalid());
+                }
+                cursor.at += 1;
+                in_time = true;
+                continue;
+            }
+            if seen_fraction {
+                return Err(invalid());
+            }
+            let value = cursor.number().ok_or_else(invalid)?;
+            let fraction = cursor.fraction();
+            let designator = cursor.next().ok_or_else(invalid)?.to_ascii_uppercase();
+            let index = match (in_time, designator) {
+                (false, b'Y') => 0,
+                (false, b'M') => 1,
+                (false, b'W') => 2,
+                (false, b'D') => 3,
+                (true, b'H') => 4,
+                (true, b'M') => 5,
+                (true, b'S') => 6,
+                _ => return Err(invalid()),
+            };
+            if last_index.is_some_and(|last| index <= last) {
+                return Err(invalid());
+            }
+            if let Some((digits, scale)) = fraction {
+                if index < 4 {
+                    return Err(invalid());
+                }
+                time_fraction = digits as i128 * TIME_UNIT_NS[index - 4] / scale as i128;
+                seen_fraction = true;
+            }
+            fields[index] = value;
+            last_index = Some(index);
+        }
+        if last_index.is_none() || (in_time && last_index < Some(4)) {
+            return Err(invalid());
+        }
+        // Spread a fraction over the units below the one it was written on
+        let mut rest = time_fraction;
+        for (offset, ns) in TIME_UNIT_NS.iter().enumerate() {
+            if rest == 0 {
+                break;
+            }
+            let index = offset + 4;
+            if last_index.is_some_and(|last| index <= last) {
+                continue;
+            }
+            fields[index] += (rest / ns) as i64;
+            rest %= ns;
+        }
+        fields.iter_mut().for_each(|field| *field *= sign);
+        Duration::from_fields(fields)
+    }
+}
+
+/// Units of the `Duration` fields, in order
+const DURATION_UNITS: [Unit; 10] = [
+    Unit::Years, Unit::Months, Unit::Weeks, Unit::Days, Unit::Hours, Unit::Minutes, Unit::Seconds,
+    Unit::Milliseconds, Unit::Microseconds, Unit::Nanoseconds,
+];
+
+impl std::fmt::Display for Duration {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let d = self.abs();
+        if self.sign() < 0 {
+            write!(f, "-")?;
+        }
+        write!(f, "P")?;
+        for (value, designator) in [(d.years, 'Y'), (d.months, 'M'), (d.weeks, 'W'), (d.days, 'D')] {
+            if value != 0 {
+                write!(f, "{}{}", value, designator)?;
+            }
+        }
+        let subsecond = d.milliseconds as i128 * 1_000_000 + d.microseconds as i128 * 1_000 + d.nanoseconds as i128;
+        let seconds = d.seconds as i128 + subsecond / NS_PER_SECOND;
+        let fraction = subsecond % NS_PER_SECOND;
+        let blank = self.sign() == 0;
+        if d.hours != 0 || d.minutes != 0 || seconds != 0 || fraction != 0 || blank {
+            write!(f, "T")?;
+            if d.hours != 0 {
+                write!(f, "{}H", d.hours)?;
+            }
+            if d.minutes != 0 {
+                write!(f, "{}M", d.minutes)?;
+            }
+            if seconds != 0 || fraction != 0 || blank {
+                write!(f, "{}{}S", seconds, format_fraction(fraction as i64))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `.5`, `.123456789` or nothing for a nanosecond fraction of a second
+fn format_fraction(nanoseconds: i64) -> String {
+    if nanoseconds == 0 {
+        return String::new();
+    }
+    let digits = format!("{:09}", nanoseconds);
+    format!(".{}", digits.trim_end_matches('0'))
+}
+
+/// `HH:MM:SS` with a fraction when the time has one
+fn format_time(ns_of_day: i64) -> String {
+    let seconds = ns_of_day / NS_PER_SECOND as i64;
+    format!(
+        "{:02}:{:02}:{:02}{}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60,
+        format_fraction(ns_of_day % NS_PER_SECOND as i64)
+    )
+}
+
+/// `+05:30`, or `+05:30:15` when the offset has seconds
+pub fn format_offset(offset_seconds: i32) -> String {
+    let sign = if offset_seconds < 0 { '-' } else { '+' };
+    let abs = offset_seconds.unsigned_abs();
+    let text = format!("{}{:02}:{:02}", sign, abs / 3600, abs / 60 % 60);
+    if abs % 60 == 0 { text } else { format!("{}:{:02}", text, abs % 60) }
+}
+
+// ============================================================================
+// ISO 8601 parsing
+// ============================================================================
+
+fn invalid_string(text: &str) -> TemporalError {
+    TemporalError(format!("Invalid ISO 8601 string: {}", text))
+}
+
+struct Cursor<'a> {
+    text: &'a [u8],
+    at: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(text: &'a str) -> Cursor<'a> {
+        Cursor { text: text.as_bytes(), at: 0 }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.text.get(self.at).copied()
+    }
+
+    fn next(&mut self) -> Option<u8> {
+        let byte = self.peek()?;
+        self.at += 1;
+        Some(byte)
+    }
+
+    fn eat(&mut self, byte: u8) -> bool {
+        let matched = self.peek() == Some(byte);
+        if matched {
+            self.at += 1;
+        }
+        matched
+    }
+
+    fn eat_ignore_case(&mut self, byte: u8) -> bool {
+        let matched = self.peek().is_some_and(|next| next.eq_ignore_ascii_case(&byte));
+        if matched {
+            self.at += 1;
+        }
+        matched
+    }
+
+    /// Exactly `count` digits
+    fn digits(&mut self, count: usize) -> Option<i64> {
+        let digits = self.text.get(self.at..self.at + count)?;
+        if !digits.iter().all(u8::is_ascii_digit) {
+            return None;
+        }
+        self.at += count;
+        Some(digits.iter().fold(0, |value, digit| value * 10 + (digit - b'0') as i64))
+    }
+
+    /// One or more digits
+    fn number(&mut self) -> Option<i64> {
+        let start = self.at;
+        while self.peek().is_some_and(|byte| byte.is_ascii_digit()) {
+            self.at += 1;
+        }
+        std::str::from_utf8(&self.text[start..self.at]).ok()?.parse().ok()
+    }
+
+    /// `.digits` or `,digits` (up to nine), as the digits and their scale
+    fn fraction(&mut self) -> Option<(i64, i64)> {
+        if !matches!(self.peek(), Some(b'.' | b',')) {
+            return None;
+        }
+        let start = self.at;
+        self.at += 1;
+        let (mut digits, mut scale) = (0i64, 1i64);
+        while let Some(byte) = self.peek().filter(u8::is_ascii_digit) {
+            if scale >= NS_PER_SECOND as i64 {
+                self.at = start;
+                return None;
+            }
+            digits = digits * 10 + (byte - b'0') as i64;
+            scale *= 10;
+            self.at += 1;
+        }
+        if scale == 1 {
+            self.at = start;
+            return None;
+        }
+        Some((digits, scale))
+    }
+
+    /// `YYYY-MM-DD`, `YYYYMMDD` or with a six-digit signed year (`+012024-01-15`)
+    fn date(&mut self) -> Option<PlainDate> {
+        let year = match self.peek()? {
+            b'+' | b'-' => {
+                let sign = if self.next()? == b'-' { -1 } else { 1 };
+                sign * self.digits(6)?
+            }
+            _ => self.digits(4)?,
+        };
+        let extended = self.eat(b'-');
+        let month = self.digits(2)?;
+        if extended && !self.eat(b'-') {
+            return None;
+        }
+        let day = self.digits(2)?;
+        PlainDate::new(year, month, day).ok()
+    }
+
+    /// `HH[:MM[:SS[.fraction]]]` as nanoseconds of the day
+    fn time(&mut self) -> Option<i64> {
+        let hour = self.digits(2)?;
+        let mut minute = 0;
+        let mut second = 0;
+        let mut fraction = 0;
+        let extended = self.eat(b':');
+        if let Some(value) = self.digits(2) {
+            minute = value;
+            if (!extended || self.eat(b':')) && self.peek().is_some_and(|byte| byte.is_ascii_digit()) {
+                second = self.digits(2)?.min(59);
+                if let Some((digits, scale)) = self.fraction() {
+                    fraction = digits * (NS_PER_SECOND as i64 / scale);
+                }
+            }
+        } else if extended {
+            return None;
+        }
+        if hour > 23 || minute > 59 {
+            return None;
+        }
+        Some(((hour * 60 + minute) * 60 + second) * NS_PER_SECOND as i64 + fraction)
+    }
+
+    /// `[+-]HH[:MM[:SS]]` in seconds
+    fn offset(&mut self) -> Option<i32> {
+        let sign = match self.peek()? {
+            b'+' => 1,
+            b'-' => -1,
+            _ => return None,
+        };
+        self.at += 1;
+        let hours = self.digits(2)?;
+        let extended = self.eat(b':');
+        let minutes = self.digits(2).unwrap_or(0);
+        let seconds = if !extended || self.eat(b':') { self.digits(2).unwrap_or(0) } else { 0 };
+        if hours > 23 || minutes > 59 || seconds > 59 {
+            return None;
+        }
+        Some(sign * (hours * 3600 + minutes * 60 + seconds) as i32)
+    }
+
+    /// The time zone of `[Zone/Name]` annotations, skipping `[key=value]` ones (a calendar
+    /// other than iso8601 is an error)
+    fn annotations(&mut self) -> Option<Option<String>> {
+        let mut zone = None;
+        while self.eat(b'[') {
+            self.eat(b'!');
+            let start = self.at;
+            while self.peek().is_some_and(|byte| byte != b']') {
+                self.at += 1;
+            }
+            let content = std::str::from_utf8(&self.text[start..self.at]).ok()?;
+            if !self.eat(b']') {
+                return None;
+            }
+            match content.split_once('=') {
+                Some(("u-ca", calendar)) if calendar != "iso8601" => return None,
+                Some(_) => {}
+                None if zone.is_none() => zone = Some(content.to_string()),
+                None => return None,
+            }
+        }
+        Some(zone)
+    }
+}
+
+/// Parse a single offset string such as `+05:30` or `-0800`
+pub fn parse_offset(text: &str) -> Option<i32> {
+    let mut cursor = Cursor::new(text);
+    let offset = cursor.offset()?;
+    cursor.peek().is_none().then_some(offset)
+}
+
+// ============================================================================
+// Time zones
+// ============================================================================
+
+/// A time zone: UTC, a fixed offset, or a zone from the tz database
+#[derive(Debug, Clone)]
+pub enum TimeZone {
+    Utc,
+    Fixed(i32),
+    Named(Arc<Zone>),
+}
+
+/// A tz database zone: its transitions and the rule that continues them
+#[derive(Debug)]
+pub struct Zone {
+    name: String,
+    /// Transition instants (epoch seconds), ascending
+    transitions: Vec<i64>,
+    /// Offset in effect from each transition on
+    offsets: Vec<i32>,
+    /// Offset before the first transition
+    initial: i32,
+    /// POSIX TZ rule for instants after the last transition
+    rule: Option<PosixRule>,
+}
+
+impl TimeZone {
+    /// `UTC`, an offset (`+05:30`) or a tz database name (`America/New_York`)
+    pub fn parse(id: &str) -> Result<TimeZone> {
+        if id.eq_ignore_ascii_case("UTC") || id.eq_ignore_ascii_case("Etc/UTC") || id.eq_ignore_ascii_case("Z") {
+            return Ok(TimeZone::Utc);
+        }
+        if let Some(offset) = parse_offset(id) {
+            return Ok(TimeZone::Fixed(offset));
+        }
+        match load_zone(id) {
+            Some(zone) => Ok(TimeZone::Named(zone)),
+            None => range_error(format!("Unknown time zone: {}", id)),
+        }
+    }
+
+    /// The zone `$TZ` names, else the one /etc/localtime links to, else UTC
+    pub fn system() -> TimeZone {
+        if let Ok(tz) = std::env::var("TZ") {
+            if let Ok(zone) = TimeZone::parse(tz.trim_start_matches(':')) {
+                return zone;
+            }
+        }
+        if let Ok(target) = std::fs::read_link("/etc/localtime") {
+            let target = target.to_string_lossy().into_owned();
+            if let Some(zone) = target.split_once("zoneinfo/").and_then(|(_, id)| TimeZone::parse(id).ok()) {
+                return zone;
+            }
+        }
+        TimeZone::Utc
+    }
+
+    pub fn id(&self) -> String {
+        match self {
+            TimeZone::Utc => "UTC".to_string(),
+            TimeZone::Fixed(offset) => format_offset(*offset),
+            TimeZone::Named(zone) => zone.name.clone(),
+        }
+    }
+
+    /// UTC offset in seconds at an instant
+    pub fn offset_at(&self, epoch_seconds: i64) -> i32 {
+        match self {
+            TimeZone::Utc => 0,
+            TimeZone::Fixed(offset) => *offset,
+            TimeZone::Named(zone) => zone.offset_at(epoch_seconds),
+        }
+    }
+
+    /// Exact time (epoch nanoseconds) of a wall-clock time (nanoseconds since the local
+    /// epoch), by Temporal's "compatible" disambiguation: the earlier instant when the
+    /// wall time repeats, and in a gap the wall time shifted forward by the gap's length
+    pub fn to_exact(&self, wall_ns: i128) -> i128 {
+        let local = wall_ns.div_euclid(NS_PER_SECOND) as i64;
+        let before = self.offset_at(local - 86_400);
+        let after = self.offset_at(local + 86_400);
+        let valid = [before, after].into_iter()
+            .filter(|offset| self.offset_at(local - *offset as i64) == *offset)
+            .max();
+        wall_ns - valid.unwrap_or(before) as i128 * NS_PER_SECOND
+    }
+}
+
+impl PartialEq for TimeZone {
+    fn eq(&self, other: &TimeZone) -> bool {
+        self.id() == other.id()
+    }
+}
+
+impl Zone {
+    fn offset_at(&self, epoch_seconds: i64) -> i32 {
+        let index = self.transitions.partition_point(|transition| *transition <= epoch_seconds);
+        match &self.rule {
+            Some(rule) if index == self.transitions.len() => rule.offset_at(epoch_seconds),
+            _ if index == 0 => self.initial,
+            _ => self.offsets[index - 1],
+        }
+    }
+}
+
+/// A POSIX TZ rule such as `EST5EDT,M3.2.0,M11.1.0`
+#[derive(Debug, Clone, PartialEq)]
+struct PosixRule {
+    /// Standard time's UTC offset in seconds (east positive, unlike the POSIX string)
+    std_offset: i32,
+    dst: Option<DstRule>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct DstRule {
+    offset: i32,
+    /// Local standard time at which daylight saving time starts
+    start: (RuleDate, i32),
+    /// Local daylight time at which it ends
+    end: (RuleDate, i32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum RuleDate {
+    /// `Jn`: day 1-365, never counting February 29
+    Julian(u16),
+    /// `n`: zero-based day of the year, counting February 29
+    DayOfYear(u16),
+    /// `Mm.w.d`: weekday d (0 is Sunday) of week w (5 is the last) of month m
+    MonthWeekDay(u8, u8, u8),
+}
+
+impl RuleDate {
+    /// Days since 1970-01-01 of this date in `year`
+    fn epoch_days(self, year: i32) -> i64 {
+        let jan1 = days_from_civil(year, 1, 1);
+        match self {
+            RuleDate::Julian(day) => {
+                let leap_shift = (is_leap_year(year) && day >= 60) as i64;
+                jan1 + day as i64 - 1 + leap_shift
+            }
+            RuleDate::DayOfYear(day) => jan1 + day as i64,
+            RuleDate::MonthWeekDay(month, week, weekday) => {
+                let first = days_from_civil(year, month, 1);
+                // 1970-01-01 was a Thursday (4)
+                let first_weekday = (first + 4).rem_euclid(7);
+                let mut day = first + (weekday as i64 - first_weekday).rem_euclid(7) + (week as i64 - 1) * 7;
+                while day >= first + days_in_month(year, month) as i64 {
+                    day -= 7;
+                }
+                day
+            }
+        }
+    }
+}
+
+impl PosixRule {
+    fn parse(text: &str) -> Option<PosixRule> {
+        let mut cursor = Cursor::new(text);
+        posix_name(&mut cursor)?;
+        let std_offset = -posix_time(&mut cursor)?;
+        if cursor.peek().is_none() {
+            return Some(PosixRule { std_offset, dst: None });
+        }
+        posix_name(&mut cursor)?;
+        let offset = match cursor.peek() {
+            Some(b',') | None => std_offset + 3600,
+            _ => -posix_time(&mut cursor)?,
+        };
+        // Without dates, the US rules apply
+        let (start, end) = if cursor.eat(b',') {
+            let start = posix_rule_date(&mut cursor)?;
+            if !cursor.eat(b',') {
+                return None;
+            }
+            (start, posix_rule_date(&mut cursor)?)
+        } else {
+            ((RuleDate::MonthWeekDay(3, 2, 0), 7200), (RuleDate::MonthWeekDay(11, 1, 0), 7200))
+        };
+        cursor.peek().is_none().then_some(PosixRule { std_offset, dst: Some(DstRule { offset, start, end }) })
+    }
+
+    fn offset_at(&self, epoch_seconds: i64) -> i32 {
+        let Some(dst) = &self.dst else { return self.std_offset };
+        let local_days = (epoch_seconds + self.std_offset as i64).div_euclid(86_400);
+        let (year, _, _) = civil_from_days(local_days);
+        let start = dst.start.0.epoch_days(year) * 86_400 + dst.start.1 as i64 - self.std_offset as i64;
+        let end = dst.end.0.epoch_days(year) * 86_400 + dst.end.1 as i64 - dst.offset as i64;
+        let in_dst = if start < end {
+            start <= epoch_seconds && epoch_seconds < end
+        } else {
+            // Southern hemisphere: daylight time spans the new year
+            !(end <= epoch_seconds && epoch_seconds < start)
+        };
+        if in_dst { dst.offset } else { self.std_offset }
+    }
+}
+
+/// A zone abbreviation: letters, or anything between `<` and `>`
+fn posix_name(cursor: &mut Cursor) -> Option<()> {
+    let start = cursor.at;
+    if cursor.eat(b'<') {
+        while cursor.next()? != b'>' {}
+        return Some(());
+    }
+    while cursor.peek().is_some_and(|byte| byte.is_ascii_alphabetic()) {
+        cursor.at += 1;
+    }
+    (cursor.at - start >= 3).then_some(())
+}
+
+/// `[+-]h[h][:mm[:ss]]` in seconds, hours up to 167
+fn posix_time(cursor: &mut Cursor) -> Option<i32> {
+    let sign = if cursor.eat(b'-') { -1 } else { cursor.eat(b'+'); 1 };
+    let hours = cursor.number()?;
+    let minutes = if cursor.eat(b':') { cursor.digits(2)? } else { 0 };
+    let seconds = if cursor.eat(b':') { cursor.digits(2)? } else { 0 };
+    (hours <= 167).then_some(sign * (hours * 3600 + minutes * 60 + seconds) as i32)
+}
+
+/// `Jn`, `n` or `Mm.w.d`, with an optional `/time` (02:00 by default)
+fn posix_rule_date(cursor: &mut Cursor) -> Option<(RuleDate, i32)> {
+    let date = if cursor.eat(b'J') {
+        RuleDate::Julian(cursor.number()?.clamp(1, 365) as u16)
+    } else if cursor.eat(b'M') {
+        let month = cursor.number()?;
+        cursor.eat(b'.').then_some(())?;
+        let week = cursor.number()?;
+        cursor.eat(b'.').then_some(())?;
+        let weekday = cursor.number()?;
+        if !(1..=12).contains(&month) || !(1..=5).contains(&week) || weekday > 6 {
+            return None;
+        }
+        RuleDate::MonthWeekDay(month as u8, week as u8, weekday as u8)
+    } else {
+        RuleDate::DayOfYear(cursor.number()?.min(365) as u16)
+    };
+    let time = if cursor.eat(b'/') { posix_time(cursor)? } else { 7200 };
+    Some((date, time))
+}
+
+/// Parse a TZif file (RFC 8536), using the 64-bit data of version 2 and later files
+fn parse_tzif(name: &str, data: &[u8]) -> Option<Zone> {
+    let counts = |at: usize| -> Option<[usize; 6]> {
+        if data.get(at..at + 4)? != b"TZif" {
+            return None;
+        }
+        let mut counts = [0usize; 6];
+        for (i, count) in counts.iter_mut().enumerate() {
+            let bytes = data.get(at + 20 + i * 4..at + 24 + i * 4)?;
+            *count = u32::from_be_bytes(bytes.try_into().ok()?) as usize;
+        }
+        Some(counts)
+    };
+    // isutcnt, isstdcnt, leapcnt, timecnt, typecnt, charcnt
+    let block_len = |c: [usize; 6], time_size: usize| {
+        c[3] * time_size + c[3] + c[4] * 6 + c[5] + c[2] * (time_size + 4) + c[1] + c[0]
+    };
+    let mut header = 0;
+    let mut c = counts(header)?;
+    let mut time_size = 4;
+    let version = *data.get(4)?;
+    if version >= b'2' {
+        header = 44 + block_len(c, 4);
+        c = counts(header)?;
+        time_size = 8;
+    }
+    let [_, _, _, time_count, type_count, _] = c;
+    let mut at = header + 44;
+    let mut transitions = Vec::with_capacity(time_count);
+    for _ in 0..time_count {
+        let bytes = data.get(at..at + time_size)?;
+        transitions.push(if time_size == 8 {
+            i64::from_be_bytes(bytes.try_into().ok()?)
+        } else {
+            i32::from_be_bytes(bytes.try_into().ok()?) as i64
+        });
+        at += time_size;
+    }
+    let indices = data.get(at..at + time_count)?.to_vec();
+    at += time_count;
+    let mut type_offsets = Vec::with_capacity(type_count);
+    for _ in 0..type_count {
+        type_offsets.push(i32::from_be_bytes(data.get(at..at + 4)?.try_into().ok()?));
+        at += 6;
+    }
+    let offsets = indices.iter().map(|index| type_offsets.get(*index as usize).copied()).collect::<Option<Vec<_>>>()?;
+    // The footer follows the data block: "\n<POSIX TZ string>\n"
+    let rule = if version >= b'2' {
+        let footer = data.get(header + 44 + block_len(c, 8)..)?;
+        let footer = std::str::from_utf8(footer).ok()?.trim_matches('\n');
+        (!footer.is_empty()).then(|| PosixRule::parse(footer)).flatten()
+    } else {
+        None
+    };
+    Some(Zone {
+        name: name.to_string(),
+        transitions,
+        offsets,
+        initial: *type_offsets.first()?,
+        rule,
+    })
+}
+
+static EMBEDDED_TZDATA: OnceLock<&'static [u8]> = OnceLock::new();
+static ZONES: OnceLock<Mutex<HashMap<String, Option<Arc<Zone>>>>> = OnceLock::new();
+
+/// Register the time zone table `perry compile --embed-tzdata` compiled into the
+/// executable. Called by the entry module's main before any user code runs.
+#[no_mangle]
+pub unsafe extern "C" fn js_temporal_set_tzdata(data: *const u8, len: i64) {
+    if !data.is_null() && len > 0 {
+        let _ = EMBEDDED_TZDATA.set(std::slice::from_raw_parts(data, len as usize));
+    }
+}
+
+/// A zone's TZif file from a table `pack_tzdata` wrote
+fn table_zone<'a>(table: &'a [u8], name: &str) -> Option<&'a [u8]> {
+    let mut rest = table.strip_prefix(TZDATA_MAGIC)?;
+    while rest.len() >= 2 {
+        let name_len = u16::from_le_bytes([rest[0], rest[1]]) as usize;
+        let entry_name = rest.get(2..2 + name_len)?;
+        let data_len = u32::from_le_bytes(rest.get(2 + name_len..6 + name_len)?.try_into().ok()?) as usize;
+        let data = rest.get(6 + name_len..6 + name_len + data_len)?;
+        if entry_name == name.as_bytes() {
+            return Some(data);
+        }
+        rest = &rest[6 + name_len + data_len..];
+    }
+    None
+}
+
+/// Load (and cache) a tz database zone from the embedded table or the zoneinfo directory
+fn load_zone(name: &str) -> Option<Arc<Zone>> {
+    let valid = !name.is_empty()
+        && !name.starts_with('/')
+        && !name.split('/').any(|part| part.is_empty() || part == "." || part == "..")
+        && name.bytes().all(|byte| byte.is_ascii_alphanumeric() || b"/_-+".contains(&byte));
+    if !valid {
+        return None;
+    }
+    let zones = ZONES.get_or_init(|| Mutex::new(HashMap::new()));
+    let mut zones = zones.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    zones.entry(name.to_string())
+        .or_insert_with(|| {
+            let embedded = EMBEDDED_TZDATA.get().and_then(|table| table_zone(table, name));
+            let data = match embedded {
+                Some(data) => data.to_vec(),
+                None => std::fs::read(Path::new(&zoneinfo_dir()).join(name)).ok()?,
+            };
+            parse_tzif(name, &data).map(Arc::new)
+        })
+        .clone()
+}
+
+/// `$TZDIR`, else /usr/share/zoneinfo
+pub fn zoneinfo_dir() -> String {
+    std::env::var("TZDIR").unwrap_or_else(|_| "/usr/share/zoneinfo".to_string())
+}
+
+/// Pack TZif files from a zoneinfo directory into the table `js_temporal_set_tzdata` reads:
+/// the named `zones`, or every zone when `zones` is empty (skipping the `posix/` and
+/// `right/` copies)
+pub fn pack_tzdata(dir: &Path, zones: &[String]) -> io::Result<Vec<u8>> {
+    let mut names = zones.to_vec();
+    if names.is_empty() {
+        collect_zone_names(dir, "", &mut names)?;
+        names.sort();
+    }
+    let mut table = TZDATA_MAGIC.to_vec();
+    for name in &names {
+        let data = std::fs::read(dir.join(name))
+            .map_err(|e| io::Error::new(e.kind(), format!("time zone {}: {}", name, e)))?;
+        if !data.starts_with(b"TZif") || parse_tzif(name, &data).is_none() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{} is not a TZif file", name)));
+        }
+        table.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        table.extend_from_slice(name.as_bytes());
+        table.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        table.extend_from_slice(&data);
+    }
+    Ok(table)
+}
+
+fn collect_zone_names(dir: &Path, prefix: &str, names: &mut Vec<String>) -> io::Result<()> {
+    for entry in std::fs::read_dir(dir.join(prefix))? {
+        let entry = entry?;
+        let file_name = entry.file_name().to_string_lossy().into_owned();
+        let name = if prefix.is_empty() { file_name.clone() } else { format!("{}/{}", prefix, file_name) };
+        if entry.file_type()?.is_dir() {
+            if prefix.is_empty() && matches!(file_name.as_str(), "posix" | "right") {
+                continue;
+            }
+            collect_zone_names(dir, &name, names)?;
+        } else if std::fs::read(entry.path()).is_ok_and(|data| data.starts_with(b"TZif")) {
+            names.push(name);
+        }
+    }
+    Ok(())
+}
+
+// ============================================================================
+// Zoned date-times
+// ============================================================================
+
+/// An exact time in a time zone
+#[derive(Debug, Clone, PartialEq)]
+pub struct ZonedDateTime {
+    pub epoch_ns: i128,
+    pub zone: TimeZone,
+}
+
+/// Current time in epoch nanoseconds
+pub fn now_epoch_ns() -> i128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_nanos() as i128)
+        .unwrap_or(0)
+}
+
+impl ZonedDateTime {
+    pub fn new(epoch_ns: i128, zone: TimeZone) -> Result<ZonedDateTime> {
+        if epoch_ns.abs() > MAX_EPOCH_NS {
+            return range_error("Date-time outside the representable range");
+        }
+        Ok(ZonedDateTime { epoch_ns, zone })
+    }
+
+    /// The date and time of day in `zone`, resolved with "compatible" disambiguation
+    pub fn from_wall(date: &PlainDate, ns_of_day: i64, zone: TimeZone) -> Result<ZonedDateTime> {
+        let wall = date.epoch_days() as i128 * NS_PER_DAY + ns_of_day as i128;
+        ZonedDateTime::new(zone.to_exact(wall), zone)
+    }
+
+    /// `2024-03-10T02:30:00-05:00[America/New_York]`. The time zone annotation is required;
+    /// an offset must be one the zone uses at that wall time, and `Z` takes the exact time.
+    pub fn parse(text: &str) -> Result<ZonedDateTime> {
+        let invalid = || invalid_string(text);
+        let mut cursor = Cursor::new(text);
+        let date = cursor.date().ok_or_else(invalid)?;
+        let ns_of_day = if cursor.eat_ignore_case(b'T') || cursor.eat(b' ') { cursor.time().ok_or_else(invalid)? } else { 0 };
+        enum Offset { None, Utc, Seconds(i32) }
+        let offset = if cursor.eat_ignore_case(b'Z') {
+            Offset::Utc
+        } else if matches!(cursor.peek(), Some(b'+' | b'-')) {
+            Offset::Seconds(cursor.offset().ok_or_else(invalid)?)
+        } else {
+            Offset::None
+        };
+        let zone_id = cursor.annotations().ok_or_else(invalid)?;
+        if cursor.peek().is_some() {
+            return Err(invalid());
+        }
+        let zone = TimeZone::parse(&zone_id.ok_or_else(|| TemporalError(format!("Missing time zone annotation: {}", text)))?)?;
+        let wall = date.epoch_days() as i128 * NS_PER_DAY + ns_of_day as i128;
+        let epoch_ns = match offset {
+            Offset::None => zone.to_exact(wall),
+            Offset::Utc => wall,
+            Offset::Seconds(offset) => {
+                let exact = wall - offset as i128 * NS_PER_SECOND;
+                if zone.offset_at(exact.div_euclid(NS_PER_SECOND) as i64) != offset {
+                    return range_error(format!("Offset {} is invalid for {} in {}", format_offset(offset), date, zone.id()));
+                }
+                exact
+            }
+        };
+        ZonedDateTime::new(epoch_ns, zone)
+    }
+
+    pub fn offset_seconds(&self) -> i32 {
+        self.zone.offset_at(self.epoch_ns.div_euclid(NS_PER_SECOND) as i64)
+    }
+
+    /// The wall-clock date and nanoseconds of the day
+    pub fn wall(&self) -> (PlainDate, i64) {
+        let wall = self.epoch_ns + self.offset_seconds() as i128 * NS_PER_SECOND;
+        let (year, month, day) = civil_from_days(wall.div_euclid(NS_PER_DAY) as i64);
+        (PlainDate { year, month, day }, wall.rem_euclid(NS_PER_DAY) as i64)
+    }
+
+    /// Calendar units move the wall-clock date (keeping the time of day), then time
+    /// units move the exact time
+    pub fn add(&self, duration: &Duration) -> Result<ZonedDateTime> {
+        let mut epoch_ns = self.epoch_ns;
+        if duration.years != 0 || duration.months != 0 || duration.weeks != 0 || duration.days != 0 {
+            let (date, ns_of_day) = self.wall();
+            let calendar = Duration { years: duration.years, months: duration.months, weeks: duration.weeks, days: duration.days, ..Duration::default() };
+            epoch_ns = ZonedDateTime::from_wall(&date.add(&calendar)?, ns_of_day, self.zone.clone())?.epoch_ns;
+        }
+        ZonedDateTime::new(epoch_ns + duration.time_nanoseconds(), self.zone.clone())
+    }
+
+    /// Duration to `other` in units up to `largest` (hours by default). Days and larger
+    /// units count calendar days in this date-time's zone, so a day across a DST change
+    /// is still one day.
+    pub fn until(&self, other: &ZonedDateTime, largest: Unit) -> Result<Duration> {
+        if largest > Unit::Days {
+            return Ok(Duration::from_nanoseconds(other.epoch_ns - self.epoch_ns, largest));
+        }
+        let sign = if other.epoch_ns < self.epoch_ns { -1 } else { 1 };
+        let other_here = ZonedDateTime { epoch_ns: other.epoch_ns, zone: self.zone.clone() };
+        let (start_date, _) = self.wall();
+        let (mut end_date, _) = other_here.wall();
+        // Step the end date back a day while the calendar part overshoots `other`
+        loop {
+            let calendar = start_date.until(&end_date, largest)?;
+            let moved = self.add(&calendar)?;
+            let rest = other.epoch_ns - moved.epoch_ns;
+            if rest == 0 || rest.signum() as i64 == sign {
+                let time = Duration::from_nanoseconds(rest, Unit::Hours);
+                let mut fields = calendar.fields();
+                fields[4..].copy_from_slice(&time.fields()[4..]);
+                return Duration::from_fields(fields);
+            }
+            end_date = PlainDate::from_epoch_days(end_date.epoch_days() - sign)?;
+        }
+    }
+
+    pub fn with_time_zone(&self, zone: TimeZone) -> ZonedDateTime {
+        ZonedDateTime { epoch_ns: self.epoch_ns, zone }
+    }
+
+    pub fn start_of_day(&self) -> Result<ZonedDateTime> {
+        ZonedDateTime::from_wall(&self.wall().0, 0, self.zone.clone())
+    }
+
+    /// Length of this date-time's day in hours: 23 or 25 across a DST change
+    pub fn hours_in_day(&self) -> Result<f64> {
+        let (date, _) = self.wall();
+        let start = ZonedDateTime::from_wall(&date, 0, self.zone.clone())?;
+        let next = ZonedDateTime::from_wall(&PlainDate::from_epoch_days(date.epoch_days() + 1)?, 0, self.zone.clone())?;
+        Ok((next.epoch_ns - start.epoch_ns) as f64 / TIME_UNIT_NS[0] as f64)
+    }
+}
+
+impl std::fmt::Display for ZonedDateTime {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (date, ns_of_day) = self.wall();
+        write!(f, "{}T{}{}[{}]", date, format_time(ns_of_day), format_offset(self.offset_seconds()), self.zone.id())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(text: &str) -> PlainDate {
+        PlainDate::parse(text).unwrap()
+    }
+
+    #[test]
+    fn test_plain_date_arithmetic() {
+        let jan31 = date("2024-01-31");
+        let month = Duration { months: 1, ..Duration::default() };
+        assert_eq!(jan31.add(&month).unwrap().to_string(), "2024-02-29");
+        assert_eq!(jan31.day_of_week(), 3);
+        assert_eq!(date("2024-12-31").day_of_year(), 366);
+
+        let until = date("2024-01-15").until(&date("2025-03-20"), Unit::Years).unwrap();
+        assert_eq!((until.years, until.months, until.days), (1, 2, 5));
+        let back = date("2024-03-31").until(&date("2024-02-29"), Unit::Months).unwrap();
+        assert_eq!((back.months, back.days), (-1, 0));
+        assert!(PlainDate::parse("2023-02-29").is_err());
+    }
+
+    #[test]
+    fn test_duration_parse_and_format() {
+        let duration = Duration::parse("P1Y2M3W4DT5H6M7.008S").unwrap();
+        assert_eq!(duration.fields(), [1, 2, 3, 4, 5, 6, 7, 8, 0, 0]);
+        assert_eq!(duration.to_string(), "P1Y2M3W4DT5H6M7.008S");
+        assert_eq!(Duration::parse("-PT1.5H").unwrap().to_string(), "-PT1H30M");
+        assert_eq!(Duration::default().to_string(), "PT0S");
+        assert!(Duration::parse("P1H").is_err());
+        assert!(Duration::from_fields([0, 0, 0, 1, -1, 0, 0, 0, 0, 0]).is_err());
+
+        let sum = Duration::parse("PT23H").unwrap().add(&Duration::parse("PT2H").unwrap()).unwrap();
+        assert_eq!(sum.to_string(), "PT25H");
+        assert_eq!(Duration::parse("P1DT12H").unwrap().total(Unit::Days), Ok(1.5));
+    }
+
+    #[test]
+    fn test_dst_transitions() {
+        let rule = PosixRule::parse("EST5EDT,M3.2.0,M11.1.0").unwrap();
+        let zone = TimeZone::Named(Arc::new(Zone {
+            name: "America/New_York".to_string(),
+            transitions: Vec::new(),
+            offsets: Vec::new(),
+            initial: -5 * 3600,
+            rule: Some(rule),
+        }));
+
+        // 02:30 doesn't exist on 2024-03-10: compatible disambiguation moves it to 03:30 EDT
+        let gap = ZonedDateTime::from_wall(&date("2024-03-10"), 9_000 * NS_PER_SECOND as i64, zone.clone()).unwrap();
+        assert_eq!(gap.to_string(), "2024-03-10T03:30:00-04:00[America/New_York]");
+        assert_eq!(gap.hours_in_day().unwrap(), 23.0);
+
+        // 01:30 happens twice on 2024-11-03: the earlier one is EDT
+        let fold = ZonedDateTime::from_wall(&date("2024-11-03"), 5_400 * NS_PER_SECOND as i64, zone.clone()).unwrap();
+        assert_eq!(fold.offset_seconds(), -4 * 3600);
+
+        // A day later is the same wall time, 23 hours of exact time
+        let before = ZonedDateTime::from_wall(&date("2024-03-09"), 12 * 3_600 * NS_PER_SECOND as i64, zone).unwrap();
+        let after = before.add(&Duration { days: 1, ..Duration::default() }).unwrap();
+        assert_eq!(after.to_string(), "2024-03-10T12:00:00-04:00[America/New_York]");
+        assert_eq!(before.until(&after, Unit::Hours).unwrap().hours, 23);
+        assert_eq!(before.until(&after, Unit::Days).unwrap().fields(), [0, 0, 0, 1, 0, 0, 0, 0, 0, 0]);
+    }
+}
@@ -3,6 +3,7 @@
 //! Provides a simple timer queue that integrates with the Promise runtime.
 
 use std::cell::RefCell;
+use std::sync::atomic::{AtomicPtr, Ordering};
 use std::time::{Duration, Instant};
 use crate::promise::{Promise, js_promise_new, js_promise_resolve};
 
@@ -147,45 +148,71 @@ pub extern "C" fn js_sleep_ms(ms: f64) {
     }
 }
 
+thread_local! {
+    /// Next ID for a callback or interval timer (shared so any ID can be passed to
+    /// clearTimeout, clearInterval, unref and ref)
+    static NEXT_TIMER_ID: RefCell<i64> = RefCell::new(1);
+}
+
+fn next_timer_id() -> i64 {
+    NEXT_TIMER_ID.with(|id_cell| {
+        let mut id = id_cell.borrow_mut();
+        let current = *id;
+        *id += 1;
+        current
+    })
+}
+
 /// JS-style setTimeout that takes a callback function and delay
 /// The callback is a closure pointer that will be called with no arguments
-/// Returns a timer ID (currently always 0)
+/// Returns a timer ID for clearTimeout and unref()/ref()
 #[no_mangle]
 pub extern "C" fn js_set_timeout_callback(callback: i64, delay_ms: f64) -> i64 {
-    use crate::closure::js_closure_call0;
-
     ensure_initialized();
 
-    // Schedule the callback to be called after the delay
-    // For now, we create a timer and store the callback
-    // The callback will be invoked when the timer fires
+    let delay = delay_ms.max(0.0) as u64;
+    let deadline = Instant::now() + Duration::from_millis(delay);
+    let id = next_timer_id();
 
-    let delay = Duration::from_millis(delay_ms.max(0.0) as u64);
-    let deadline = Instant::now() + delay;
-
-    // Store callback in a special timer structure
     CALLBACK_TIMERS.with(|q| {
         q.borrow_mut().push(CallbackTimer {
+            id,
             deadline,
+            delay_ms: delay,
             callback,
+            refed: true,
         });
     });
 
-    0 // timer ID (not really used currently)
+    id
 }
 
 /// A scheduled timer with a callback
 struct CallbackTimer {
+    /// Unique ID for this timer
+    id: i64,
     /// When this timer should fire
     deadline: Instant,
+    /// The delay it was scheduled with, for introspection
+    delay_ms: u64,
     /// The closure pointer to call
     callback: i64,
+    /// Whether this timer keeps the process alive (false after unref())
+    refed: bool,
 }
 
 thread_local! {
     static CALLBACK_TIMERS: RefCell<Vec<CallbackTimer>> = RefCell::new(Vec::new());
 }
 
+/// Clear a callback timer by ID before it fires
+/// Interval IDs are accepted as well, as in Node
+#[no_mangle]
+pub extern "C" fn clearTimeout(timer_id: i64) {
+    CALLBACK_TIMERS.with(|q| q.borrow_mut().retain(|t| t.id != timer_id));
+    clearInterval(timer_id);
+}
+
 /// Process any expired callback timers
 /// Returns the number of callbacks that were called
 #[no_mangle]
@@ -193,29 +220,28 @@ pub extern "C" fn js_callback_timer_tick() -> i32 {
     use crate::closure::js_closure_call0;
 
     let now = Instant::now();
+    // Timers scheduled by the callbacks below wait for the next tick, even with a 0 delay
+    let last_id = NEXT_TIMER_ID.with(|id| *id.borrow());
     let mut fired = 0;
 
-    // Collect expired timers
-    let expired: Vec<CallbackTimer> = CALLBACK_TIMERS.with(|q| {
-        let mut queue = q.borrow_mut();
-        let mut expired = Vec::new();
-        let mut i = 0;
-        while i < queue.len() {
-            if queue[i].deadline <= now {
-                expired.push(queue.remove(i));
-            } else {
-                i += 1;
-            }
-        }
-        expired
-    });
+    // Fire one timer at a time, earliest deadline first, so a callback can still
+    // clear a timer that expired in the same tick
+    loop {
+        let next = CALLBACK_TIMERS.with(|q| {
+            let mut queue = q.borrow_mut();
+            let index = queue
+                .iter()
+                .enumerate()
+                .filter(|(_, t)| t.deadline <= now && t.id < last_id)
+                .min_by_key(|(_, t)| (t.deadline, t.id))
+                .map(|(i, _)| i)?;
+            Some(queue.remove(index).callback)
+        });
+        let Some(callback) = next else { break };
 
-    // Call the callbacks
-    for timer in expired {
-        // Call the closure with no arguments
         // The closure pointer is an i64 (pointer to ClosureHeader)
         unsafe {
-            js_closure_call0(timer.callback as *const crate::closure::ClosureHeader);
+            js_closure_call0(callback as *const crate::closure::ClosureHeader);
         }
         fired += 1;
     }
@@ -245,13 +271,13 @@ struct IntervalTimer {
     next_deadline: Instant,
     /// Whether this interval has been cleared
     cleared: bool,
+    /// Whether this interval keeps the process alive (false after unref())
+    refed: bool,
 }
 
 thread_local! {
     /// Active interval timers
     static INTERVAL_TIMERS: RefCell<Vec<IntervalTimer>> = RefCell::new(Vec::new());
-    /// Next interval ID to assign
-    static NEXT_INTERVAL_ID: RefCell<i64> = RefCell::new(1);
 }
 
 /// JS-style setInterval that takes a callback function and interval
@@ -261,15 +287,10 @@ thread_local! {
 pub extern "C" fn setInterval(callback: i64, interval_ms: f64) -> i64 {
     ensure_initialized();
 
-    let interval = interval_ms.max(0.0) as u64;
+    // A zero interval would fire on every tick; Node uses 1ms as the minimum as well
+    let interval = (interval_ms.max(0.0) as u64).max(1);
     let next_deadline = Instant::now() + Duration::from_millis(interval);
-
-    let id = NEXT_INTERVAL_ID.with(|id_cell| {
-        let mut id = id_cell.borrow_mut();
-        let current = *id;
-        *id += 1;
-        current
-    });
+    let id = next_timer_id();
 
     INTERVAL_TIMERS.with(|timers| {
         timers.borrow_mut().push(IntervalTimer {
@@ -278,6 +299,7 @@ pub extern "C" fn setInterval(callback: i64, interval_ms: f64) -> i64 {
             interval_ms: interval,
             next_deadline,
             cleared: false,
+            refed: true,
         });
     });
 
@@ -301,6 +323,20 @@ pub extern "C" fn clearInterval(interval_id: i64) {
         // Also remove any already-cleared intervals to prevent memory growth
         timers.retain(|t| !t.cleared);
     });
+    CALLBACK_TIMERS.with(|q| q.borrow_mut().retain(|t| t.id != interval_id));
+}
+
+/// The deadline after `deadline` on an interval's schedule, given that it fired at `now`.
+/// Deadlines advance by whole periods from the first one, so time spent in callbacks or
+/// waiting for a busy loop doesn't push every later tick back; ticks that were missed
+/// entirely are skipped rather than fired in a burst.
+fn next_interval_deadline(deadline: Instant, period: Duration, now: Instant) -> Instant {
+    let next = deadline + period;
+    if next > now {
+        return next;
+    }
+    let missed = ((now - next).as_nanos() / period.as_nanos()) as u32 + 1;
+    next + period * missed
 }
 
 /// Process any expired interval timers
@@ -312,27 +348,35 @@ pub extern "C" fn js_interval_timer_tick() -> i32 {
     let now = Instant::now();
     let mut fired = 0;
 
-    // Collect callbacks to call and update deadlines
-    let callbacks_to_call: Vec<i64> = INTERVAL_TIMERS.with(|timers| {
+    // Collect the due intervals and move them to their next deadline
+    let due: Vec<i64> = INTERVAL_TIMERS.with(|timers| {
         let mut timers = timers.borrow_mut();
-        let mut callbacks = Vec::new();
+        let mut due = Vec::new();
 
         for timer in timers.iter_mut() {
             if !timer.cleared && timer.next_deadline <= now {
-                callbacks.push(timer.callback);
-                // Schedule the next firing
-                timer.next_deadline = now + Duration::from_millis(timer.interval_ms);
+                due.push(timer.id);
+                timer.next_deadline = next_interval_deadline(
+                    timer.next_deadline,
+                    Duration::from_millis(timer.interval_ms),
+                    now,
+                );
             }
         }
 
         // Clean up any cleared timers
         timers.retain(|t| !t.cleared);
 
-        callbacks
+        due
     });
 
-    // Call the callbacks outside of the borrow
-    for callback in callbacks_to_call {
+    // Call the callbacks outside of the borrow, skipping intervals that an earlier
+    // callback in this tick has cleared
+    for id in due {
+        let callback = INTERVAL_TIMERS.with(|timers| {
+            timers.borrow().iter().find(|t| t.id == id && !t.cleared).map(|t| t.callback)
+        });
+        let Some(callback) = callback else { continue };
         unsafe {
             js_closure_call0(callback as *const crate::closure::ClosureHeader);
         }
@@ -371,3 +415,262 @@ pub extern "C" fn js_interval_timer_next_deadline() -> f64 {
             .unwrap_or(-1.0)
     })
 }
+
+// ============================================================================
+// unref() / ref() and the event loop
+// ============================================================================
+
+/// Set whether the timer with this ID keeps the process alive
+/// Returns false when no such timer is active
+fn set_timer_ref(timer_id: i64, refed: bool) -> bool {
+    let found = CALLBACK_TIMERS.with(|q| {
+        q.borrow_mut().iter_mut().find(|t| t.id == timer_id).map(|t| t.refed = refed).is_some()
+    });
+    found
+        || INTERVAL_TIMERS.with(|timers| {
+            timers
+                .borrow_mut()
+                .iter_mut()
+                .find(|t| t.id == timer_id && !t.cleared)
+                .map(|t| t.refed = refed)
+                .is_some()
+        })
+}
+
+/// timer.unref(): the timer still fires, but no longer keeps the process alive
+/// Returns the timer so calls can be chained
+#[no_mangle]
+pub extern "C" fn js_timer_unref(timer_id: i64) -> i64 {
+    set_timer_ref(timer_id, false);
+    timer_id
+}
+
+/// timer.ref(): undo unref()
+/// Returns the timer so calls can be chained
+#[no_mangle]
+pub extern "C" fn js_timer_ref(timer_id: i64) -> i64 {
+    set_timer_ref(timer_id, true);
+    timer_id
+}
+
+/// timer.hasRef() -> boolean
+#[no_mangle]
+pub extern "C" fn js_timer_has_ref(timer_id: i64) -> f64 {
+    let refed = CALLBACK_TIMERS.with(|q| q.borrow().iter().any(|t| t.id == timer_id && t.refed))
+        || INTERVAL_TIMERS.with(|timers| {
+            timers.borrow().iter().any(|t| t.id == timer_id && !t.cleared && t.refed)
+        });
+    f64::from_bits(crate::value::JSValue::bool(refed).bits())
+}
+
+/// Whether any timer still keeps the process alive. Promise timers (`await setTimeout(ms)`)
+/// can't be unref'd and always count.
+fn has_refed_timers() -> bool {
+    TIMER_QUEUE.with(|q| !q.borrow().is_empty())
+        || CALLBACK_TIMERS.with(|q| q.borrow().iter().any(|t| t.refed))
+        || INTERVAL_TIMERS.with(|timers| timers.borrow().iter().any(|t| !t.cleared && t.refed))
+}
+
+/// The earliest deadline of any active timer, ref'd or not
+fn next_timer_deadline() -> Option<Instant> {
+    let promise_timers = TIMER_QUEUE.with(|q| q.borrow().iter().map(|t| t.deadline).min());
+    let callback_timers = CALLBACK_TIMERS.with(|q| q.borrow().iter().map(|t| t.deadline).min());
+    let intervals = INTERVAL_TIMERS.with(|timers| {
+        timers.borrow().iter().filter(|t| !t.cleared).map(|t| t.next_deadline).min()
+    });
+    [promise_timers, callback_timers, intervals].into_iter().flatten().min()
+}
+
+/// Run timers until none is left that keeps the process alive
+/// Called by main once the top-level code has run. unref'd timers fire while the loop
+/// is running for other timers, but don't hold it open on their own.
+#[no_mangle]
+pub extern "C" fn js_run_event_loop() {
+    loop {
+        crate::promise::js_promise_run_microtasks();
+        if !has_refed_timers() {
+            break;
+        }
+        if let Some(deadline) = next_timer_deadline() {
+            let now = Instant::now();
+            if deadline > now {
+                std::thread::sleep(deadline - now);
+            }
+        }
+    }
+}
+
+// ============================================================================
+// Host run loops
+// ============================================================================
+//
+// perry/ui hands the main thread to the platform's run loop (AppKit's NSApplication
+// run), so nothing else drives promises and timers. The host pumps them from a run loop
+// timer with js_event_loop_pump, and registers a wait function so an `await` inside an
+// event handler keeps dispatching UI events instead of sleeping.
+
+type PendingWorkFn = extern "C" fn() -> i32;
+type AwaitWaitFn = extern "C" fn(f64);
+
+static PENDING_WORK: AtomicPtr<()> = AtomicPtr::new(std::ptr::null_mut());
+static AWAIT_WAIT: AtomicPtr<()> = AtomicPtr::new(std::ptr::null_mut());
+
+/// Set the function that settles promises for work completed on other threads
+/// (called by perry-stdlib with js_stdlib_process_pending)
+#[no_mangle]
+pub extern "C" fn js_set_pending_work_processor(func: PendingWorkFn) {
+    PENDING_WORK.store(func as *mut (), Ordering::SeqCst);
+}
+
+/// Set how `await` waits while its promise is pending (called by perry/ui while its run
+/// loop owns the main thread)
+#[no_mangle]
+pub extern "C" fn js_set_await_wait(func: AwaitWaitFn) {
+    AWAIT_WAIT.store(func as *mut (), Ordering::SeqCst);
+}
+
+/// Run whatever is ready: due timers, microtasks and work completed on other threads.
+/// Returns the number of callbacks and resolutions run
+#[no_mangle]
+pub extern "C" fn js_event_loop_pump() -> i32 {
+    let mut ran = crate::promise::js_promise_run_microtasks();
+    let pending = PENDING_WORK.load(Ordering::SeqCst);
+    if !pending.is_null() {
+        let process: PendingWorkFn = unsafe { std::mem::transmute(pending) };
+        let settled = process();
+        if settled > 0 {
+            // Continuations of the promises that just settled
+            ran += settled + crate::promise::js_promise_run_microtasks();
+        }
+    }
+    ran
+}
+
+/// Wait up to `ms` for an `await`'s promise: in the host run loop if one registered,
+/// otherwise by sleeping
+#[no_mangle]
+pub extern "C" fn js_await_wait(ms: f64) {
+    let wait = AWAIT_WAIT.load(Ordering::SeqCst);
+    if wait.is_null() {
+        js_sleep_ms(ms);
+    } else {
+        let wait: AwaitWaitFn = unsafe { std::mem::transmute(wait) };
+        wait(ms);
+    }
+}
+
+// ============================================================================
+// Introspection
+// ============================================================================
+
+/// Milliseconds until `deadline`, 0 once it has passed
+fn remaining_ms(deadline: Instant, now: Instant) -> f64 {
+    deadline.saturating_duration_since(now).as_secs_f64() * 1000.0
+}
+
+/// One `{ id, type, delay, remaining, ref }` entry for process.getActiveTimers()
+fn timer_info(id: i64, kind: &str, delay_ms: u64, remaining: f64, refed: bool) -> f64 {
+    use crate::object::{js_object_alloc, js_object_set_field_by_name};
+    use crate::string::js_string_from_bytes;
+    use crate::value::JSValue;
+
+    let field = |name: &str| js_string_from_bytes(name.as_ptr(), name.len() as u32);
+    let kind = js_string_from_bytes(kind.as_ptr(), kind.len() as u32);
+    let obj = js_object_alloc(0, 5);
+    // The ID keeps the bit pattern of the handle setTimeout/setInterval returned, so
+    // entries compare equal to the handles held by the program
+    js_object_set_field_by_name(obj, field("id"), f64::from_bits(id as u64));
+    js_object_set_field_by_name(obj, field("type"), f64::from_bits(JSValue::string_ptr(kind).bits()));
+    js_object_set_field_by_name(obj, field("delay"), delay_ms as f64);
+    js_object_set_field_by_name(obj, field("remaining"), remaining);
+    js_object_set_field_by_name(obj, field("ref"), f64::from_bits(JSValue::bool(refed).bits()));
+    f64::from_bits(JSValue::pointer(obj as *const u8).bits())
+}
+
+/// process.getActiveTimers() -> the pending setTimeout and setInterval timers, soonest
+/// first, as `{ id, type: "Timeout" | "Interval", delay, remaining, ref }` objects.
+/// Meant for finding out what keeps a process from exiting.
+#[no_mangle]
+pub extern "C" fn js_timer_active_list() -> f64 {
+    use crate::array::{js_array_alloc, js_array_push_f64};
+    use crate::value::JSValue;
+
+    let now = Instant::now();
+    let mut entries: Vec<(Instant, f64)> = Vec::new();
+    CALLBACK_TIMERS.with(|q| {
+        for t in q.borrow().iter() {
+            let info = timer_info(t.id, "Timeout", t.delay_ms, remaining_ms(t.deadline, now), t.refed);
+            entries.push((t.deadline, info));
+        }
+    });
+    INTERVAL_TIMERS.with(|timers| {
+        for t in timers.borrow().iter().filter(|t| !t.cleared) {
+            let remaining = remaining_ms(t.next_deadline, now);
+            let info = timer_info(t.id, "Interval", t.interval_ms, remaining, t.refed);
+            entries.push((t.next_deadline, info));
+        }
+    });
+    entries.sort_by_key(|(deadline, _)| *deadline);
+
+    let mut arr = js_array_alloc(entries.len() as u32);
+    for (_, info) in entries {
+        arr = js_array_push_f64(arr, info);
+    }
+    f64::from_bits(JSValue::pointer(arr as *const u8).bits())
+}
+
+/// process.getActiveResourcesInfo() -> "Timeout" for every timer that keeps the process
+/// alive, as in Node
+#[no_mangle]
+pub extern "C" fn js_process_active_resources() -> f64 {
+    use crate::array::{js_array_alloc, js_array_push_f64};
+    use crate::string::js_string_from_bytes;
+    use crate::value::JSValue;
+
+    let count = TIMER_QUEUE.with(|q| q.borrow().len())
+        + CALLBACK_TIMERS.with(|q| q.borrow().iter().filter(|t| t.refed).count())
+        + INTERVAL_TIMERS.with(|timers| timers.borrow().iter().filter(|t| !t.cleared && t.refed).count());
+
+    let mut arr = js_array_alloc(count as u32);
+    for _ in 0..count {
+        let name = js_string_from_bytes("Timeout".as_ptr(), 7);
+        arr = js_array_push_f64(arr, f64::from_bits(JSValue::string_ptr(name).bits()));
+    }
+    f64::from_bits(JSValue::pointer(arr as *const u8).bits())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_interval_deadlines_stay_on_schedule() {
+        let start = Instant::now();
+        let period = Duration::from_millis(10);
+        // On time, or a little late: the next tick is one period after the previous deadline
+        assert_eq!(next_interval_deadline(start, period, start), start + period);
+        assert_eq!(next_interval_deadline(start, period, start + Duration::from_millis(4)), start + period);
+        // Late by several periods: the missed ticks are skipped
+        assert_eq!(
+            next_interval_deadline(start, period, start + Duration::from_millis(35)),
+            start + Duration::from_millis(40)
+        );
+        assert_eq!(
+            next_interval_deadline(start, period, start + Duration::from_millis(20)),
+            start + Duration::from_millis(30)
+        );
+    }
+
+    #[test]
+    fn test_pump_runs_pending_work_processor() {
+        use std::sync::atomic::AtomicI32;
+        static CALLS: AtomicI32 = AtomicI32::new(0);
+        extern "C" fn process() -> i32 {
+            CALLS.fetch_add(1, Ordering::SeqCst);
+            2
+        }
+        js_set_pending_work_processor(process);
+        assert!(js_event_loop_pump() >= 2);
+        assert_eq!(CALLS.load(Ordering::SeqCst), 1);
+    }
+}
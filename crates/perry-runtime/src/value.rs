@@ -753,6 +753,18 @@ pub extern "C" fn js_dynamic_array_get(value: f64, index: i32) -> f64 {
         return f64::from_bits(TAG_UNDEFINED);
     }
 
+    // Object literal indexed with a number (e.g. an enum's reverse mapping `Color[0]`):
+    // look the index up as a property name. Anonymous objects have object_type 1 and
+    // class_id 0 where an array has its length and capacity, which can't be 1 and 0.
+    unsafe {
+        let obj = ptr as *const crate::object::ObjectHeader;
+        if (*obj).object_type == crate::error::OBJECT_TYPE_REGULAR && (*obj).class_id == 0 {
+            let key = index.to_string();
+            let key_ptr = crate::string::js_string_from_bytes(key.as_ptr(), key.len() as u32);
+            return f64::from_bits(crate::object::js_object_get_field_by_name(obj, key_ptr).bits());
+        }
+    }
+
     // Call the native array get function
     let result_bits = crate::array::js_array_get_jsvalue(ptr as *const crate::array::ArrayHeader, index as u32);
     f64::from_bits(result_bits)
@@ -966,6 +978,30 @@ pub unsafe extern "C" fn js_dynamic_object_get_property(
     )
 }
 
+/// `obj.name` from a compiled read site with an inline cache: the slow path when the
+/// inlined shape check misses. Plain objects are looked up without allocating the key
+/// and fill `cache`; everything else goes through `js_dynamic_object_get_property`.
+#[no_mangle]
+pub unsafe extern "C" fn js_dynamic_object_get_property_ic(
+    obj_value: f64,
+    property_name_ptr: *const i8,
+    property_name_len: usize,
+    cache: *mut crate::object::PropertyCache,
+) -> f64 {
+    let bits = obj_value.to_bits();
+    if (bits & TAG_MASK) == POINTER_TAG && !property_name_ptr.is_null() && property_name_len > 0 {
+        let ptr = bits & POINTER_MASK;
+        if ptr >= 0x100000 {
+            let name = std::slice::from_raw_parts(property_name_ptr as *const u8, property_name_len);
+            let obj = ptr as *const crate::object::ObjectHeader;
+            if let Some(value) = crate::object::get_field_cached(obj, name, cache) {
+                return f64::from_bits(value.bits());
+            }
+        }
+    }
+    js_dynamic_object_get_property(obj_value, property_name_ptr, property_name_len)
+}
+
 /// Dynamic Object.keys() that handles both regular objects and Error objects.
 /// Takes a raw pointer (extracted from NaN-boxed value) and returns array of keys.
 #[no_mangle]
@@ -1080,4 +1116,24 @@ mod tests {
         assert!(JSValue::number(-1.0).to_bool());
         assert!(!JSValue::number(f64::NAN).to_bool());
     }
+
+    #[test]
+    fn test_dynamic_index_on_object_uses_property_name() {
+        // Enum objects carry reverse mappings under numeric keys: { "0": "Red", Red: 0 }
+        let obj = crate::object::js_object_alloc(0, 2);
+        let key = crate::string::js_string_from_bytes(b"0".as_ptr(), 1);
+        let name = crate::string::js_string_from_bytes(b"Red".as_ptr(), 3);
+        crate::object::js_object_set_field_by_name(obj, key, f64::from_bits(JSValue::string_ptr(name).bits()));
+
+        let obj_val = f64::from_bits(JSValue::pointer(obj as *const u8).bits());
+        let found = JSValue::from_bits(js_dynamic_array_get(obj_val, 0).to_bits());
+        assert!(found.is_string());
+        assert!(JSValue::from_bits(js_dynamic_array_get(obj_val, 1).to_bits()).is_undefined());
+
+        // Arrays are still indexed by position
+        let arr = crate::array::js_array_alloc(2);
+        crate::array::js_array_push_f64(arr, 7.0);
+        let arr_val = f64::from_bits(JSValue::pointer(arr as *const u8).bits());
+        assert_eq!(js_dynamic_array_get(arr_val, 0), 7.0);
+    }
 }
@@ -3,8 +3,11 @@
 //! Native implementation of the 'axios' npm package using reqwest.
 //! Provides HTTP client functionality with a promise-based API.
 
+use std::collections::HashMap;
+
 use perry_runtime::{js_promise_new, js_string_from_bytes, JSValue, ObjectHeader, Promise, StringHeader};
 use crate::common::{register_handle, get_handle, spawn_for_promise, Handle};
+use crate::http_cache;
 
 /// Helper to extract string from StringHeader pointer
 unsafe fn string_from_header(ptr: *const StringHeader) -> Option<String> {
@@ -41,6 +44,17 @@ pub unsafe extern "C" fn js_axios_get(url_ptr: *const StringHeader) -> *mut Prom
     };
 
     spawn_for_promise(promise as *mut u8, async move {
+        if http_cache::is_enabled() {
+            let cached = http_cache::get(&url, &HashMap::new()).await.map_err(|e| format!("Request failed: {}", e))?;
+            let handle = register_handle(AxiosResponseHandle {
+                status: cached.status,
+                status_text: cached.status_text,
+                data: String::from_utf8_lossy(&cached.body).into_owned(),
+                headers: cached.headers,
+            });
+            return Ok(handle as u64);
+        }
+
         let client = reqwest::Client::new();
         match client.get(&url).send().await {
             Ok(response) => {
@@ -103,6 +117,7 @@ pub unsafe extern "C" fn js_axios_post(
         {
             Ok(response) => {
                 let status = response.status().as_u16();
+                http_cache::invalidate(&url, status).await;
                 let status_text = response.status().canonical_reason().unwrap_or("").to_string();
                 let headers: Vec<(String, String)> = response
                     .headers()
@@ -161,6 +176,7 @@ pub unsafe extern "C" fn js_axios_put(
         {
             Ok(response) => {
                 let status = response.status().as_u16();
+                http_cache::invalidate(&url, status).await;
                 let status_text = response.status().canonical_reason().unwrap_or("").to_string();
                 let headers: Vec<(String, String)> = response
                     .headers()
@@ -208,6 +224,7 @@ pub unsafe extern "C" fn js_axios_delete(url_ptr: *const StringHeader) -> *mut P
         match client.delete(&url).send().await {
             Ok(response) => {
                 let status = response.status().as_u16();
+                http_cache::invalidate(&url, status).await;
                 let status_text = response.status().canonical_reason().unwrap_or("").to_string();
                 let headers: Vec<(String, String)> = response
                     .headers()
@@ -266,6 +283,7 @@ pub unsafe extern "C" fn js_axios_patch(
         {
             Ok(response) => {
                 let status = response.status().as_u16();
+                http_cache::invalidate(&url, status).await;
                 let status_text = response.status().canonical_reason().unwrap_or("").to_string();
                 let headers: Vec<(String, String)> = response
                     .headers()
@@ -117,7 +117,17 @@ where
 pub extern "C" fn js_stdlib_process_pending() -> i32 {
     let mut count = 0i32;
 
-    // Process simple resolutions first
+    // Report fetch upload progress before settling promises, so the last
+    // onUploadProgress call runs before the response is delivered
+    #[cfg(feature = "http-client")]
+    {
+        extern "C" {
+            fn js_fetch_process_pending() -> i32;
+        }
+        count += unsafe { js_fetch_process_pending() };
+    }
+
+    // Process simple resolutions
     {
         let mut pending = PENDING_RESOLUTIONS.lock().unwrap();
         count += pending.len() as i32;
@@ -41,6 +41,52 @@ pub unsafe extern "C" fn js_handle_method_dispatch(
         return dispatch_fastify_context(handle, method_name, args);
     }
 
+    // Try perry/concurrency task group and cancel scope dispatch
+    if with_handle::<crate::concurrency::TaskGroup, bool, _>(handle, |_| true).unwrap_or(false) {
+        return crate::concurrency::dispatch_task_group(handle, method_name, args);
+    }
+    if with_handle::<crate::concurrency::CancelScope, bool, _>(handle, |_| true).unwrap_or(false) {
+        return crate::concurrency::dispatch_cancel_scope(handle, method_name, args);
+    }
+
+    // Try perry/streams emitter stream dispatch
+    if with_handle::<crate::streams::EmitterStream, bool, _>(handle, |_| true).unwrap_or(false) {
+        return crate::streams::dispatch_emitter_stream(handle, method_name);
+    }
+
+    // Try Temporal value dispatch (PlainDate, Duration, ZonedDateTime)
+    if crate::temporal::is_temporal(handle) {
+        return crate::temporal::dispatch_temporal(handle, method_name, args);
+    }
+
+    // Try openid-client configuration and token set dispatch
+    #[cfg(feature = "oidc")]
+    if crate::oidc::is_oidc_handle(handle) {
+        return crate::oidc::dispatch_oidc(handle, method_name);
+    }
+
+    // Try perry/eventlog log dispatch
+    if crate::eventlog::is_event_log(handle) {
+        return crate::eventlog::dispatch_event_log(handle, method_name, args);
+    }
+
+    // Try minisearch index dispatch
+    if crate::minisearch::is_search_index(handle) {
+        return crate::minisearch::dispatch_search_index(handle, method_name, args);
+    }
+
+    // Try perry/wasm plugin dispatch
+    #[cfg(feature = "wasm-plugins")]
+    if crate::wasm::is_plugin(handle) {
+        return crate::wasm::dispatch_plugin(handle, method_name, args);
+    }
+
+    // Try level database, iterator and chained batch dispatch
+    #[cfg(feature = "kv-store")]
+    if crate::level::is_level_handle(handle) {
+        return crate::level::dispatch_level(handle, method_name, args);
+    }
+
     // Unknown handle type - return undefined
     f64::from_bits(0x7FF8_0000_0000_0001)
 }
@@ -48,6 +94,13 @@ pub unsafe extern "C" fn js_handle_method_dispatch(
 /// Dispatch method calls on Fastify app handles
 unsafe fn dispatch_fastify_app(handle: i64, method: &str, args: &[f64]) -> f64 {
     match method {
+        // Shorthands with a route options object: get(path, { schema }, handler)
+        "get" | "post" | "put" | "delete" | "patch" | "head" | "options" | "all" if args.len() >= 3 => {
+            let path = args[0].to_bits() as i64;
+            let handler = args[2].to_bits() as i64;
+            let result = crate::fastify::app::register_route_with_opts(handle, &method.to_uppercase(), path, args[1], handler);
+            if result { 1.0 } else { 0.0 }
+        }
         "get" if args.len() >= 2 => {
             let path = args[0].to_bits() as i64;
             let handler = args[1].to_bits() as i64;
@@ -246,12 +299,58 @@ pub unsafe extern "C" fn js_handle_property_dispatch(
         };
     }
 
+    // Try perry/concurrency task group and cancel scope properties
+    if with_handle::<crate::concurrency::TaskGroup, bool, _>(handle, |_| true).unwrap_or(false)
+        || with_handle::<crate::concurrency::CancelScope, bool, _>(handle, |_| true).unwrap_or(false)
+    {
+        return crate::concurrency::handle_property(handle, property_name);
+    }
+
+    // Try perry/streams emitter stream properties
+    if with_handle::<crate::streams::EmitterStream, bool, _>(handle, |_| true).unwrap_or(false) {
+        return crate::streams::emitter_stream_property(handle, property_name);
+    }
+
+    // Try Temporal value properties
+    if crate::temporal::is_temporal(handle) {
+        return crate::temporal::temporal_property(handle, property_name);
+    }
+
+    // Try openid-client token set properties
+    #[cfg(feature = "oidc")]
+    if crate::oidc::is_oidc_handle(handle) {
+        return crate::oidc::oidc_property(handle, property_name);
+    }
+
+    // Try perry/eventlog log properties
+    if crate::eventlog::is_event_log(handle) {
+        return crate::eventlog::event_log_property(handle, property_name);
+    }
+
+    // Try minisearch index properties
+    if crate::minisearch::is_search_index(handle) {
+        return crate::minisearch::search_index_property(handle, property_name);
+    }
+
+    // Try perry/wasm plugin properties
+    #[cfg(feature = "wasm-plugins")]
+    if crate::wasm::is_plugin(handle) {
+        return crate::wasm::plugin_property(handle, property_name);
+    }
+
+    // Try level database and chained batch properties
+    #[cfg(feature = "kv-store")]
+    if crate::level::is_level_handle(handle) {
+        return crate::level::level_property(handle, property_name);
+    }
+
     // Unknown handle type - return undefined
     f64::from_bits(0x7FFC_0000_0000_0001)
 }
 
 /// Initialize the handle method and property dispatch systems.
-/// This registers our dispatch functions with perry-runtime.
+/// This registers our dispatch functions with perry-runtime, along with the
+/// pending-work processor host run loops (perry/ui) pump async completions with.
 /// Must be called before any user code runs.
 #[no_mangle]
 pub unsafe extern "C" fn js_stdlib_init_dispatch() {
@@ -265,4 +364,5 @@ pub unsafe extern "C" fn js_stdlib_init_dispatch() {
     }
     js_register_handle_method_dispatch(js_handle_method_dispatch);
     js_register_handle_property_dispatch(js_handle_property_dispatch);
+    perry_runtime::timer::js_set_pending_work_processor(crate::common::js_stdlib_process_pending);
 }
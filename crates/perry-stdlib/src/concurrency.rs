@@ -0,0 +1,446 @@
+//! Structured concurrency for `perry/concurrency`: task groups and cancellation scopes
+//!
+//! `taskGroup(body, options?)` calls `body(group)`; `group.spawn(fn)` starts `fn(signal)`
+//! right away. The group's promise resolves with the spawned tasks' results in spawn order
+//! once the body and every task have settled. The first failure aborts `group.signal` and,
+//! after the remaining tasks have settled, rejects the group with that error, so no task
+//! outlives its group.
+//!
+//! `cancelScope(body, options?)` calls `body(scope)` and settles with its result, unless the
+//! scope is cancelled first by `scope.cancel()`, its `timeout` or its parent `signal`. Then
+//! `scope.signal` is aborted and the scope rejects immediately. The body keeps running
+//! until it notices the aborted signal.
+//!
+//! Signals are the runtime's `AbortController` signals. Tasks are expected to be async
+//! functions; a synchronous result that is a heap value is taken for a promise, as in
+//! `Promise.all`. A parent `signal` is polled every `SIGNAL_POLL_MS` while the group or
+//! scope is running, since AbortSignal has no listeners.
+
+use perry_runtime::closure::{js_closure_alloc, js_closure_get_capture_ptr, js_closure_set_capture_ptr};
+use perry_runtime::promise::js_promise_then;
+use perry_runtime::timer::{clearTimeout, js_set_timeout_callback, setInterval};
+use perry_runtime::url::{js_abort_controller_abort, js_abort_controller_new, js_abort_controller_signal};
+use perry_runtime::{
+    js_array_alloc, js_array_push_f64, js_closure_call1, js_nanbox_get_pointer, js_object_get_field_by_name,
+    js_promise_new, js_promise_reject, js_promise_resolve, js_string_from_bytes, ClosureHeader, JSValue,
+    ObjectHeader, Promise,
+};
+
+use crate::common::handle::{get_handle_mut, register_handle, take_handle, with_handle, Handle};
+
+const TAG_UNDEFINED: u64 = 0x7FFC_0000_0000_0001;
+
+/// How often a parent `signal` is checked for abort
+const SIGNAL_POLL_MS: f64 = 10.0;
+
+/// Result slot of the body, whose value isn't part of the group's results
+const BODY: i64 = -1;
+
+/// Signature of the native closures watching a promise
+pub(crate) type SettleFn = unsafe extern "C" fn(*const ClosureHeader, f64) -> f64;
+
+pub struct TaskGroup {
+    /// AbortController whose signal is passed to every task
+    controller: i64,
+    /// Promise returned by `taskGroup`
+    promise: i64,
+    /// Results by spawn order, undefined until the task fulfills
+    results: Vec<f64>,
+    /// The body and tasks that haven't settled
+    pending: usize,
+    first_error: Option<f64>,
+    /// Timeout and parent-signal poll timers
+    timers: Vec<i64>,
+}
+
+pub struct CancelScope {
+    controller: i64,
+    /// Promise returned by `cancelScope`
+    promise: i64,
+    timers: Vec<i64>,
+}
+
+pub(crate) fn undefined() -> f64 {
+    f64::from_bits(TAG_UNDEFINED)
+}
+
+pub(crate) fn boxed_pointer(ptr: i64) -> f64 {
+    f64::from_bits(JSValue::pointer(ptr as *const u8).bits())
+}
+
+/// An `Error` with `message`, NaN-boxed
+pub(crate) unsafe fn error_value(message: &str) -> f64 {
+    let text = js_string_from_bytes(message.as_ptr(), message.len() as u32);
+    boxed_pointer(perry_runtime::error::js_error_new_with_message(text) as i64)
+}
+
+/// The reason a group or scope is cancelled with: the given value, or a "Cancelled" error
+unsafe fn cancel_reason(reason: Option<f64>) -> f64 {
+    match reason {
+        Some(reason) if !JSValue::from_bits(reason.to_bits()).is_undefined() => reason,
+        _ => error_value("Cancelled"),
+    }
+}
+
+/// `timeout` and `signal` from an options object
+unsafe fn read_options(options: f64) -> (Option<f64>, Option<i64>) {
+    let value = JSValue::from_bits(options.to_bits());
+    if !value.is_pointer() {
+        return (None, None);
+    }
+    let obj = value.as_pointer::<ObjectHeader>();
+    let field = |name: &str| js_object_get_field_by_name(obj, js_string_from_bytes(name.as_ptr(), name.len() as u32));
+    let timeout = field("timeout");
+    let timeout = timeout.is_number().then(|| timeout.as_number()).filter(|ms| *ms >= 0.0);
+    let signal = field("signal");
+    let signal = signal.is_pointer().then(|| signal.as_pointer::<u8>() as i64);
+    (timeout, signal)
+}
+
+/// Whether an AbortSignal object has been aborted
+unsafe fn signal_aborted(signal: i64) -> bool {
+    let key = "aborted";
+    let aborted = js_object_get_field_by_name(
+        signal as *const ObjectHeader,
+        js_string_from_bytes(key.as_ptr(), key.len() as u32),
+    );
+    aborted.to_bool()
+}
+
+/// A native closure `(value) => callback(handle, slot, value)`
+pub(crate) unsafe fn handler(callback: *const u8, handle: Handle, slot: i64) -> *mut ClosureHeader {
+    let closure = js_closure_alloc(callback, 2);
+    js_closure_set_capture_ptr(closure, 0, handle);
+    js_closure_set_capture_ptr(closure, 1, slot);
+    closure
+}
+
+pub(crate) unsafe fn captures(closure: *const ClosureHeader) -> (Handle, i64) {
+    (js_closure_get_capture_ptr(closure, 0), js_closure_get_capture_ptr(closure, 1))
+}
+
+/// Call `on_fulfilled` / `on_rejected` when `result` settles (right away when it isn't a promise)
+pub(crate) unsafe fn watch(result: f64, handle: Handle, slot: i64, on_fulfilled: SettleFn, on_rejected: SettleFn) {
+    let value = JSValue::from_bits(result.to_bits());
+    if value.is_pointer() {
+        let promise = js_nanbox_get_pointer(result) as *mut Promise;
+        js_promise_then(
+            promise,
+            handler(on_fulfilled as *const u8, handle, slot),
+            handler(on_rejected as *const u8, handle, slot),
+        );
+    } else {
+        on_fulfilled(handler(on_fulfilled as *const u8, handle, slot), result);
+    }
+}
+
+/// Timers that cancel `handle` after `timeout` ms or once `parent` is aborted
+unsafe fn start_timers(handle: Handle, timeout: Option<f64>, parent: Option<i64>, on_timeout: *const u8, on_poll: *const u8) -> Vec<i64> {
+    let mut timers = Vec::new();
+    if let Some(ms) = timeout {
+        timers.push(js_set_timeout_callback(handler(on_timeout, handle, ms as i64) as i64, ms));
+    }
+    if let Some(signal) = parent {
+        timers.push(setInterval(handler(on_poll, handle, signal) as i64, SIGNAL_POLL_MS));
+    }
+    timers
+}
+
+fn clear_timers(timers: &[i64]) {
+    for &timer in timers {
+        clearTimeout(timer);
+    }
+}
+
+// ============================================================================
+// Task groups
+// ============================================================================
+
+/// `taskGroup(body, { timeout, signal }?)`: run `body(group)`; resolves with the spawned
+/// tasks' results once everything has settled
+#[no_mangle]
+pub unsafe extern "C" fn js_task_group(body: f64, options: f64) -> f64 {
+    let controller = js_abort_controller_new() as i64;
+    let promise = js_promise_new();
+    let handle = register_handle(TaskGroup {
+        controller,
+        promise: promise as i64,
+        results: Vec::new(),
+        pending: 1,
+        first_error: None,
+        timers: Vec::new(),
+    });
+
+    let (timeout, parent) = read_options(options);
+    if parent.is_some_and(|signal| signal_aborted(signal)) {
+        group_cancel(handle, error_value("Cancelled"));
+    }
+    let timers = start_timers(handle, timeout, parent, group_timed_out as *const u8, group_poll_parent as *const u8);
+    if let Some(group) = get_handle_mut::<TaskGroup>(handle) {
+        group.timers = timers;
+    }
+
+    let body = js_nanbox_get_pointer(body) as *const ClosureHeader;
+    if body.is_null() {
+        group_child_rejected_with(handle, error_value("taskGroup() expects a function"));
+    } else {
+        let result = js_closure_call1(body, boxed_pointer(handle));
+        watch(result, handle, BODY, group_child_fulfilled as SettleFn, group_child_rejected as SettleFn);
+    }
+    boxed_pointer(promise as i64)
+}
+
+/// `group.spawn(fn)`: start `fn(group.signal)`. Once the group is cancelled, tasks are no
+/// longer started and their results stay undefined.
+unsafe fn group_spawn(handle: Handle, task: f64) -> f64 {
+    let Some(group) = get_handle_mut::<TaskGroup>(handle) else {
+        perry_runtime::exception::js_throw(error_value("Cannot spawn into a task group that has finished"));
+    };
+    let slot = group.results.len() as i64;
+    group.results.push(undefined());
+    if group.first_error.is_some() {
+        return undefined();
+    }
+    group.pending += 1;
+    let signal = js_abort_controller_signal(group.controller as *mut ObjectHeader) as i64;
+
+    let task = js_nanbox_get_pointer(task) as *const ClosureHeader;
+    if task.is_null() {
+        group_child_rejected_with(handle, error_value("group.spawn() expects a function"));
+    } else {
+        let result = js_closure_call1(task, boxed_pointer(signal));
+        watch(result, handle, slot, group_child_fulfilled as SettleFn, group_child_rejected as SettleFn);
+    }
+    undefined()
+}
+
+/// Abort the group's signal; the group rejects with `reason` once its tasks have settled
+unsafe fn group_cancel(handle: Handle, reason: f64) {
+    let Some(group) = get_handle_mut::<TaskGroup>(handle) else { return };
+    if group.first_error.is_some() {
+        return;
+    }
+    group.first_error = Some(reason);
+    js_abort_controller_abort(group.controller as *mut ObjectHeader);
+}
+
+unsafe extern "C" fn group_child_fulfilled(closure: *const ClosureHeader, value: f64) -> f64 {
+    let (handle, slot) = captures(closure);
+    if let Some(group) = get_handle_mut::<TaskGroup>(handle) {
+        if slot != BODY {
+            group.results[slot as usize] = value;
+        }
+        group.pending -= 1;
+    }
+    group_finish_if_done(handle);
+    undefined()
+}
+
+unsafe extern "C" fn group_child_rejected(closure: *const ClosureHeader, reason: f64) -> f64 {
+    group_child_rejected_with(captures(closure).0, reason);
+    undefined()
+}
+
+unsafe fn group_child_rejected_with(handle: Handle, reason: f64) {
+    group_cancel(handle, reason);
+    if let Some(group) = get_handle_mut::<TaskGroup>(handle) {
+        group.pending -= 1;
+    }
+    group_finish_if_done(handle);
+}
+
+/// Settle the group's promise once nothing is pending
+unsafe fn group_finish_if_done(handle: Handle) {
+    if with_handle::<TaskGroup, bool, _>(handle, |group| group.pending == 0) != Some(true) {
+        return;
+    }
+    let Some(group) = take_handle::<TaskGroup>(handle) else { return };
+    clear_timers(&group.timers);
+    let promise = group.promise as *mut Promise;
+    match group.first_error {
+        Some(reason) => js_promise_reject(promise, reason),
+        None => {
+            let mut results = js_array_alloc(group.results.len() as u32);
+            for value in group.results {
+                results = js_array_push_f64(results, value);
+            }
+            js_promise_resolve(promise, boxed_pointer(results as i64));
+        }
+    }
+}
+
+unsafe extern "C" fn group_timed_out(closure: *const ClosureHeader) -> f64 {
+    let (handle, ms) = captures(closure);
+    group_cancel(handle, error_value(&format!("Task group timed out after {}ms", ms)));
+    undefined()
+}
+
+unsafe extern "C" fn group_poll_parent(closure: *const ClosureHeader) -> f64 {
+    let (handle, signal) = captures(closure);
+    if signal_aborted(signal) {
+        group_cancel(handle, error_value("Cancelled"));
+    }
+    undefined()
+}
+
+// ============================================================================
+// Cancellation scopes
+// ============================================================================
+
+/// `cancelScope(body, { timeout, signal }?)`: settles with `body(scope)`'s result, or
+/// rejects as soon as the scope is cancelled
+#[no_mangle]
+pub unsafe extern "C" fn js_cancel_scope(body: f64, options: f64) -> f64 {
+    let controller = js_abort_controller_new() as i64;
+    let promise = js_promise_new();
+    let handle = register_handle(CancelScope { controller, promise: promise as i64, timers: Vec::new() });
+
+    let (timeout, parent) = read_options(options);
+    if parent.is_some_and(|signal| signal_aborted(signal)) {
+        scope_cancel(handle, error_value("Cancelled"));
+        return boxed_pointer(promise as i64);
+    }
+    let timers = start_timers(handle, timeout, parent, scope_timed_out as *const u8, scope_poll_parent as *const u8);
+    if let Some(scope) = get_handle_mut::<CancelScope>(handle) {
+        scope.timers = timers;
+    }
+
+    let body = js_nanbox_get_pointer(body) as *const ClosureHeader;
+    if body.is_null() {
+        scope_settle(handle, Err(error_value("cancelScope() expects a function")));
+    } else {
+        let result = js_closure_call1(body, boxed_pointer(handle));
+        watch(result, handle, BODY, scope_body_fulfilled as SettleFn, scope_body_rejected as SettleFn);
+    }
+    boxed_pointer(promise as i64)
+}
+
+/// Abort the scope's signal and reject it with `reason`
+unsafe fn scope_cancel(handle: Handle, reason: f64) {
+    if let Some(controller) = with_handle::<CancelScope, i64, _>(handle, |scope| scope.controller) {
+        js_abort_controller_abort(controller as *mut ObjectHeader);
+        scope_settle(handle, Err(reason));
+    }
+}
+
+/// Settle the scope's promise; later outcomes (a body finishing after cancellation) are ignored
+unsafe fn scope_settle(handle: Handle, outcome: Result<f64, f64>) {
+    let Some(scope) = take_handle::<CancelScope>(handle) else { return };
+    clear_timers(&scope.timers);
+    let promise = scope.promise as *mut Promise;
+    match outcome {
+        Ok(value) => js_promise_resolve(promise, value),
+        Err(reason) => js_promise_reject(promise, reason),
+    }
+}
+
+unsafe extern "C" fn scope_body_fulfilled(closure: *const ClosureHeader, value: f64) -> f64 {
+    scope_settle(captures(closure).0, Ok(value));
+    undefined()
+}
+
+unsafe extern "C" fn scope_body_rejected(closure: *const ClosureHeader, reason: f64) -> f64 {
+    scope_settle(captures(closure).0, Err(reason));
+    undefined()
+}
+
+unsafe extern "C" fn scope_timed_out(closure: *const ClosureHeader) -> f64 {
+    let (handle, ms) = captures(closure);
+    scope_cancel(handle, error_value(&format!("Cancel scope timed out after {}ms", ms)));
+    undefined()
+}
+
+unsafe extern "C" fn scope_poll_parent(closure: *const ClosureHeader) -> f64 {
+    let (handle, signal) = captures(closure);
+    if signal_aborted(signal) {
+        scope_cancel(handle, error_value("Cancelled"));
+    }
+    undefined()
+}
+
+// ============================================================================
+// Handle dispatch
+// ============================================================================
+
+/// `group.spawn(fn)` / `group.cancel(reason?)`
+pub(crate) unsafe fn dispatch_task_group(handle: Handle, method: &str, args: &[f64]) -> f64 {
+    match method {
+        "spawn" => group_spawn(handle, args.first().copied().unwrap_or_else(undefined)),
+        "cancel" => {
+            group_cancel(handle, cancel_reason(args.first().copied()));
+            undefined()
+        }
+        _ => undefined(),
+    }
+}
+
+/// `scope.cancel(reason?)`
+pub(crate) unsafe fn dispatch_cancel_scope(handle: Handle, method: &str, args: &[f64]) -> f64 {
+    match method {
+        "cancel" => {
+            scope_cancel(handle, cancel_reason(args.first().copied()));
+            undefined()
+        }
+        _ => undefined(),
+    }
+}
+
+/// `signal` and `cancelled` of a task group or cancel scope
+pub(crate) unsafe fn handle_property(handle: Handle, property: &str) -> f64 {
+    let Some(controller) = with_handle::<TaskGroup, i64, _>(handle, |group| group.controller)
+        .or_else(|| with_handle::<CancelScope, i64, _>(handle, |scope| scope.controller))
+    else {
+        return undefined();
+    };
+    let signal = js_abort_controller_signal(controller as *mut ObjectHeader) as i64;
+    match property {
+        "signal" => boxed_pointer(signal),
+        "cancelled" => f64::from_bits(JSValue::bool(signal_aborted(signal)).bits()),
+        _ => undefined(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use perry_runtime::promise::{js_promise_state, js_promise_value};
+    use perry_runtime::{js_array_get_jsvalue, js_array_length, ArrayHeader};
+
+    unsafe extern "C" fn task(closure: *const ClosureHeader, _signal: f64) -> f64 {
+        js_closure_get_capture_ptr(closure, 0) as f64
+    }
+
+    /// Body that spawns tasks returning 1 and 2, cancelling the group first when `cancel` is set
+    unsafe extern "C" fn body(closure: *const ClosureHeader, group: f64) -> f64 {
+        let handle = js_nanbox_get_pointer(group);
+        if js_closure_get_capture_ptr(closure, 0) != 0 {
+            dispatch_task_group(handle, "cancel", &[]);
+        }
+        for value in [1, 2] {
+            let closure = js_closure_alloc(task as *const u8, 1);
+            js_closure_set_capture_ptr(closure, 0, value);
+            dispatch_task_group(handle, "spawn", &[boxed_pointer(closure as i64)]);
+        }
+        undefined()
+    }
+
+    unsafe fn run_group(cancel: bool) -> *mut Promise {
+        let closure = js_closure_alloc(body as *const u8, 1);
+        js_closure_set_capture_ptr(closure, 0, cancel as i64);
+        js_nanbox_get_pointer(js_task_group(boxed_pointer(closure as i64), undefined())) as *mut Promise
+    }
+
+    #[test]
+    fn test_task_group_settles_after_children() {
+        unsafe {
+            let promise = run_group(false);
+            assert_eq!(js_promise_state(promise), 1);
+            let results = js_nanbox_get_pointer(js_promise_value(promise)) as *const ArrayHeader;
+            assert_eq!(js_array_length(results), 2);
+            assert_eq!(JSValue::from_bits(js_array_get_jsvalue(results, 1)).as_number(), 2.0);
+
+            // Once cancelled, spawned tasks don't run and the group rejects
+            assert_eq!(js_promise_state(run_group(true)), 2);
+        }
+    }
+}
@@ -0,0 +1,350 @@
+//! Dependency injection container for `perry/di`
+//!
+//! The compiler registers every class decorated with `@Injectable()` or `@Controller()` at
+//! module init, passing the tokens of its constructor parameters and a generated factory
+//! closure `(d0, d1) => new C(d0, d1)`. Controller methods decorated with `@Get()`, `@Post()`
+//! and so on are registered as routes with a handler closure
+//! `(controller, request, reply) => controller.method(...)`.
+//!
+//! Scopes follow NestJS: a DEFAULT provider is a singleton created on first use, a TRANSIENT
+//! provider is created for every injection and a REQUEST provider once per HTTP request. A
+//! provider that depends on a request-scoped provider is request-scoped itself.
+//! `registerControllers(app)` mounts every controller route on a Fastify app. Each request
+//! resolves its controller in a fresh request scope, where the `REQUEST` token is the Fastify
+//! request.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use once_cell::sync::Lazy;
+use perry_runtime::closure::{
+    js_closure_call4, js_closure_call5, js_closure_call6, js_closure_call7, js_closure_call8,
+};
+use perry_runtime::{
+    js_closure_call0, js_closure_call1, js_closure_call2, js_closure_call3,
+    js_get_string_pointer_unified, js_nanbox_get_pointer, js_string_from_bytes, ClosureHeader,
+    JSValue, StringHeader,
+};
+
+const TAG_UNDEFINED: u64 = 0x7FFC_0000_0000_0001;
+
+/// Token of the current HTTP request
+const REQUEST_TOKEN: &str = "REQUEST";
+
+/// Provider lifetime, numbered like NestJS's `Scope` enum
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Scope {
+    Default,
+    Transient,
+    Request,
+}
+
+impl Scope {
+    fn from_number(value: f64) -> Self {
+        match value as i64 {
+            1 => Scope::Transient,
+            2 => Scope::Request,
+            _ => Scope::Default,
+        }
+    }
+}
+
+enum Provider {
+    /// An injectable class
+    Class {
+        scope: Scope,
+        /// Tokens of the constructor parameters
+        deps: Vec<String>,
+        /// Closure taking the resolved dependencies and returning the instance
+        factory: i64,
+        /// The singleton, once created
+        instance: Option<f64>,
+    },
+    /// A value from `provideValue(token, value)`
+    Value(f64),
+}
+
+/// A controller method mounted by `registerControllers`
+struct Route {
+    controller: String,
+    method: String,
+    path: String,
+    /// Closure `(controller, request, reply)` calling the method
+    handler: i64,
+}
+
+#[derive(Default)]
+struct Container {
+    providers: HashMap<String, Provider>,
+    routes: Vec<Route>,
+}
+
+impl Container {
+    /// Check that `token` and everything it depends on can be resolved
+    fn check(&self, token: &str, path: &mut Vec<String>) -> Result<(), String> {
+        if token == REQUEST_TOKEN {
+            return Ok(());
+        }
+        if path.iter().any(|t| t == token) {
+            return Err(format!("Circular dependency: {} -> {}", path.join(" -> "), token));
+        }
+        match self.providers.get(token) {
+            None => Err(missing_provider(token, path)),
+            Some(Provider::Value(_)) => Ok(()),
+            Some(Provider::Class { deps, .. }) => {
+                path.push(token.to_string());
+                for dep in deps {
+                    self.check(dep, path)?;
+                }
+                path.pop();
+                Ok(())
+            }
+        }
+    }
+}
+
+static CONTAINER: Lazy<Mutex<Container>> = Lazy::new(|| Mutex::new(Container::default()));
+
+/// Instances belonging to one HTTP request
+struct RequestScope {
+    request: f64,
+    instances: HashMap<String, f64>,
+}
+
+fn missing_provider(token: &str, path: &[String]) -> String {
+    match path.last() {
+        Some(dependent) => format!(
+            "{} depends on {}, which is not @Injectable() and has no provideValue()",
+            dependent, token
+        ),
+        None => format!("No provider for {}", token),
+    }
+}
+
+/// Resolve `token`. The flag says whether the instance is tied to the current request.
+unsafe fn resolve(
+    token: &str,
+    mut scope: Option<&mut RequestScope>,
+    path: &mut Vec<String>,
+) -> Result<(f64, bool), String> {
+    if token == REQUEST_TOKEN {
+        return match scope {
+            Some(scope) => Ok((scope.request, true)),
+            None => Err("REQUEST can only be injected while handling a request".to_string()),
+        };
+    }
+    if path.iter().any(|t| t == token) {
+        return Err(format!("Circular dependency: {} -> {}", path.join(" -> "), token));
+    }
+
+    // Copy what's needed out of the container: factories run user code, which may resolve
+    let (provider_scope, deps, factory) = {
+        let container = CONTAINER.lock().unwrap();
+        match container.providers.get(token) {
+            None => return Err(missing_provider(token, path)),
+            Some(Provider::Value(value)) => return Ok((*value, false)),
+            Some(Provider::Class { instance: Some(instance), .. }) => return Ok((*instance, false)),
+            Some(Provider::Class { scope, deps, factory, .. }) => (*scope, deps.clone(), *factory),
+        }
+    };
+    if let Some(existing) = scope.as_ref().and_then(|scope| scope.instances.get(token)) {
+        return Ok((*existing, true));
+    }
+
+    path.push(token.to_string());
+    let mut args = Vec::with_capacity(deps.len());
+    let mut per_request = provider_scope == Scope::Request;
+    for dep in &deps {
+        let (value, dep_per_request) = resolve(dep, scope.as_deref_mut(), path)?;
+        args.push(value);
+        per_request |= dep_per_request;
+    }
+    path.pop();
+
+    if per_request && scope.is_none() {
+        return Err(format!(
+            "{} is request-scoped and can only be resolved while handling a request",
+            token
+        ));
+    }
+    let instance = call_factory(token, factory, &args)?;
+    match (provider_scope, scope) {
+        (Scope::Transient, _) => {}
+        (_, Some(scope)) if per_request => {
+            scope.instances.insert(token.to_string(), instance);
+        }
+        _ => {
+            let mut container = CONTAINER.lock().unwrap();
+            if let Some(Provider::Class { instance: slot, .. }) = container.providers.get_mut(token) {
+                *slot = Some(instance);
+            }
+        }
+    }
+    Ok((instance, per_request))
+}
+
+unsafe fn call_factory(token: &str, factory: i64, args: &[f64]) -> Result<f64, String> {
+    let closure = factory as *const ClosureHeader;
+    Ok(match *args {
+        [] => js_closure_call0(closure),
+        [a] => js_closure_call1(closure, a),
+        [a, b] => js_closure_call2(closure, a, b),
+        [a, b, c] => js_closure_call3(closure, a, b, c),
+        [a, b, c, d] => js_closure_call4(closure, a, b, c, d),
+        [a, b, c, d, e] => js_closure_call5(closure, a, b, c, d, e),
+        [a, b, c, d, e, f] => js_closure_call6(closure, a, b, c, d, e, f),
+        [a, b, c, d, e, f, g] => js_closure_call7(closure, a, b, c, d, e, f, g),
+        [a, b, c, d, e, f, g, h] => js_closure_call8(closure, a, b, c, d, e, f, g, h),
+        _ => return Err(format!("{} has more than 8 constructor dependencies", token)),
+    })
+}
+
+/// Throw a JS Error with `message`
+unsafe fn throw_error(message: &str) -> ! {
+    let text = js_string_from_bytes(message.as_ptr(), message.len() as u32);
+    let error = perry_runtime::error::js_error_new_with_message(text);
+    perry_runtime::exception::js_throw(f64::from_bits(JSValue::pointer(error as *const u8).bits()))
+}
+
+unsafe fn string_arg(value: f64) -> Option<String> {
+    let ptr = js_get_string_pointer_unified(value) as *const StringHeader;
+    if ptr.is_null() {
+        return None;
+    }
+    let len = (*ptr).length as usize;
+    let data = (ptr as *const u8).add(std::mem::size_of::<StringHeader>());
+    Some(String::from_utf8_lossy(std::slice::from_raw_parts(data, len)).into_owned())
+}
+
+/// Register an injectable class: `deps` is the comma-separated tokens of its constructor
+/// parameters and `factory` the closure building an instance from them
+#[no_mangle]
+pub unsafe extern "C" fn js_di_provide(name: f64, scope: f64, deps: f64, factory: f64) -> f64 {
+    if let Some(name) = string_arg(name) {
+        let deps = string_arg(deps).unwrap_or_default();
+        let provider = Provider::Class {
+            scope: Scope::from_number(scope),
+            deps: deps.split(',').filter(|dep| !dep.is_empty()).map(str::to_string).collect(),
+            factory: js_nanbox_get_pointer(factory),
+            instance: None,
+        };
+        CONTAINER.lock().unwrap().providers.insert(name, provider);
+    }
+    f64::from_bits(TAG_UNDEFINED)
+}
+
+/// `provideValue(token, value)`: inject `value` wherever `@Inject(token)` asks for it
+#[no_mangle]
+pub unsafe extern "C" fn js_di_provide_value(token: f64, value: f64) -> f64 {
+    if let Some(token) = string_arg(token) {
+        CONTAINER.lock().unwrap().providers.insert(token, Provider::Value(value));
+    }
+    f64::from_bits(TAG_UNDEFINED)
+}
+
+/// Register a controller method as a route
+#[no_mangle]
+pub unsafe extern "C" fn js_di_route(controller: f64, method: f64, path: f64, handler: f64) -> f64 {
+    if let (Some(controller), Some(method), Some(path)) =
+        (string_arg(controller), string_arg(method), string_arg(path))
+    {
+        let route = Route { controller, method, path, handler: js_nanbox_get_pointer(handler) };
+        CONTAINER.lock().unwrap().routes.push(route);
+    }
+    f64::from_bits(TAG_UNDEFINED)
+}
+
+/// `resolve(UsersService)`: the instance of a provider outside of a request
+#[no_mangle]
+pub unsafe extern "C" fn js_di_resolve(token: f64) -> f64 {
+    let Some(token) = string_arg(token) else {
+        throw_error("resolve() expects an injectable class or a token");
+    };
+    match resolve(&token, None, &mut Vec::new()) {
+        Ok((instance, _)) => instance,
+        Err(message) => throw_error(&message),
+    }
+}
+
+/// Handler mounted for a controller route; its capture is the route's index
+#[cfg(feature = "http-server")]
+unsafe extern "C" fn controller_route_handler(closure: *const ClosureHeader, request: f64, reply: f64) -> f64 {
+    let index = perry_runtime::closure::js_closure_get_capture_ptr(closure, 0) as usize;
+    let (controller, handler) = {
+        let container = CONTAINER.lock().unwrap();
+        let route = &container.routes[index];
+        (route.controller.clone(), route.handler)
+    };
+    let mut scope = RequestScope { request, instances: HashMap::new() };
+    match resolve(&controller, Some(&mut scope), &mut Vec::new()) {
+        Ok((instance, _)) => js_closure_call3(handler as *const ClosureHeader, instance, request, reply),
+        Err(message) => throw_error(&message),
+    }
+}
+
+/// `registerControllers(app)`: add every controller route to a Fastify app. Fails when a
+/// controller's dependencies can't be resolved, before any request arrives.
+#[cfg(feature = "http-server")]
+#[no_mangle]
+pub unsafe extern "C" fn js_di_register_controllers(app: f64) -> f64 {
+    use crate::fastify::FastifyApp;
+
+    let checked = {
+        let container = CONTAINER.lock().unwrap();
+        container.routes.iter()
+            .try_for_each(|route| container.check(&route.controller, &mut Vec::new()))
+            .map(|()| container.routes.iter().map(|route| (route.method.clone(), route.path.clone())).collect::<Vec<_>>())
+    };
+    let routes = match checked {
+        Ok(routes) => routes,
+        Err(message) => throw_error(&message),
+    };
+
+    let Some(app) = crate::common::get_handle_mut::<FastifyApp>(js_nanbox_get_pointer(app)) else {
+        throw_error("registerControllers() expects a Fastify app");
+    };
+    for (index, (method, path)) in routes.into_iter().enumerate() {
+        let closure = perry_runtime::closure::js_closure_alloc(controller_route_handler as *const u8, 1);
+        perry_runtime::closure::js_closure_set_capture_ptr(closure, 0, index as i64);
+        if method == "ALL" {
+            for method in ["GET", "POST", "PUT", "DELETE", "PATCH", "HEAD", "OPTIONS"] {
+                app.add_route(method, &path, closure as i64);
+            }
+        } else {
+            app.add_route(&method, &path, closure as i64);
+        }
+    }
+    f64::from_bits(TAG_UNDEFINED)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn class(deps: &[&str]) -> Provider {
+        Provider::Class {
+            scope: Scope::Default,
+            deps: deps.iter().map(|dep| dep.to_string()).collect(),
+            factory: 0,
+            instance: None,
+        }
+    }
+
+    #[test]
+    fn test_check_dependencies() {
+        let mut container = Container::default();
+        container.providers.insert("Db".to_string(), Provider::Value(0.0));
+        container.providers.insert("Repo".to_string(), class(&["Db", "REQUEST"]));
+        container.providers.insert("Ctrl".to_string(), class(&["Repo", "Mailer"]));
+        container.providers.insert("A".to_string(), class(&["B"]));
+        container.providers.insert("B".to_string(), class(&["A"]));
+
+        assert!(container.check("Repo", &mut Vec::new()).is_ok());
+        assert_eq!(
+            container.check("Ctrl", &mut Vec::new()).unwrap_err(),
+            "Ctrl depends on Mailer, which is not @Injectable() and has no provideValue()"
+        );
+        assert_eq!(container.check("A", &mut Vec::new()).unwrap_err(), "Circular dependency: A -> B -> A");
+        assert_eq!(Scope::from_number(2.0), Scope::Request);
+    }
+}
@@ -3,6 +3,7 @@
 //! Provides formatUnits, parseUnits, parseEther, formatEther, getAddress, and other ethers utilities.
 
 use perry_runtime::{js_string_from_bytes, js_bigint_from_string, BigIntHeader, StringHeader};
+use perry_runtime::bigint::bigint_to_decimal;
 
 /// getAddress(address: string) -> string
 /// Returns the checksummed address (EIP-55 format).
@@ -217,19 +218,15 @@ pub extern "C" fn js_ethers_format_units(bigint_ptr: *const BigIntHeader, decima
         return js_string_from_bytes(s.as_ptr(), s.len() as u32);
     }
 
-    unsafe {
-        // Read the BigInt value - fixed 256-bit (4 limbs)
-        let bigint = &*bigint_ptr;
-        let limbs = &bigint.limbs;
-
-        // Convert to big integer string (always positive in current impl)
-        let value_str = limbs_to_string(limbs);
+    let value_str = bigint_to_decimal(bigint_ptr);
 
-        // Format with decimals
-        let formatted = format_with_decimals(&value_str, decimals as usize);
+    // Format the magnitude with decimals, keeping the sign in front
+    let formatted = match value_str.strip_prefix('-') {
+        Some(magnitude) => format!("-{}", format_with_decimals(magnitude, decimals as usize)),
+        None => format_with_decimals(&value_str, decimals as usize),
+    };
 
-        js_string_from_bytes(formatted.as_ptr(), formatted.len() as u32)
-    }
+    js_string_from_bytes(formatted.as_ptr(), formatted.len() as u32)
 }
 
 /// parseUnits(value: string, decimals: number) -> bigint
@@ -263,46 +260,6 @@ pub extern "C" fn js_ethers_parse_units(str_ptr: *const StringHeader, decimals:
     }
 }
 
-/// Convert limbs (little-endian u64 array, fixed 4 limbs) to decimal string
-fn limbs_to_string(limbs: &[u64; 4]) -> String {
-    if limbs.iter().all(|&x| x == 0) {
-        return "0".to_string();
-    }
-
-    // For U256, we can use repeated division by 10
-    // Work with a copy of the limbs
-    let mut work = *limbs;
-    let mut digits = Vec::with_capacity(78); // max digits for U256
-
-    while !is_zero(&work) {
-        let remainder = div_by_10(&mut work);
-        digits.push((b'0' + remainder) as char);
-    }
-
-    // Reverse to get correct order
-    digits.reverse();
-    digits.into_iter().collect()
-}
-
-/// Check if U256 (4 limbs) is zero
-fn is_zero(limbs: &[u64; 4]) -> bool {
-    limbs[0] == 0 && limbs[1] == 0 && limbs[2] == 0 && limbs[3] == 0
-}
-
-/// Divide U256 (4 limbs in little-endian) by 10, return remainder
-fn div_by_10(limbs: &mut [u64; 4]) -> u8 {
-    let mut remainder: u128 = 0;
-
-    // Process from most significant to least significant limb
-    for i in (0..4).rev() {
-        let current = (remainder << 64) | (limbs[i] as u128);
-        limbs[i] = (current / 10) as u64;
-        remainder = current % 10;
-    }
-
-    remainder as u8
-}
-
 /// Format a number string with decimal places
 fn format_with_decimals(value: &str, decimals: usize) -> String {
     if decimals == 0 {
@@ -0,0 +1,671 @@
+//! Append-only event log for `perry/eventlog`
+//!
+//! `openLog(dir, options?)` opens (or creates) a log in `dir`: a sequence of records, each
+//! a string with an offset that is assigned on append and never changes. It is meant as a
+//! small durable queue or event store for a single process, where embedding a database
+//! only to append rows and read them back in order is more than needed.
+//!
+//! The log is split into segment files named after their first offset
+//! (`00000000000000000000.log`); appends go to the last one, which is sealed and a new one
+//! started once it holds `segmentBytes`. A record is `[offset u64][len u32][crc32 u32]`
+//! followed by its UTF-8 bytes, little-endian, the CRC covering offset, length and data.
+//! Opening a log scans every segment: a torn or corrupt record at the end of the last
+//! segment (a crash mid-append) is cut off, anywhere else it is an error.
+//!
+//! Options:
+//! - `segmentBytes`: size at which a segment is sealed (64 MiB by default)
+//! - `fsync`: `"always"` syncs each append before it returns (the default), `"interval"`
+//!   syncs on the first append or `sync()` at least `fsyncIntervalMs` (1000) after the last
+//!   sync, `"never"` leaves it to the OS
+//!
+//! `log.append(data)` and `log.appendBatch(records)` return the (first) new offset,
+//! `log.read(offset)` a record's data or `undefined`, and `log.readFrom(offset, limit?)`
+//! up to `limit` records from `offset` on as `{ offset, data }` objects; a consumer keeps
+//! its own position and passes the last offset it handled plus one. `log.firstOffset` and
+//! `log.nextOffset` bound the readable offsets.
+//!
+//! Sealed segments can be reclaimed: `log.compact((data, offset) => keep)` rewrites them
+//! without the records the hook rejects, and `log.deleteBefore(offset)` drops the segments
+//! whose records all come before `offset`; both return the number of records removed and
+//! leave the remaining offsets as they were. `log.sync()` forces a sync and `log.close()`
+//! syncs and closes the log.
+
+use std::collections::HashSet;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use perry_runtime::closure::CLOSURE_MAGIC;
+use perry_runtime::permissions::{self, Capability};
+use perry_runtime::{
+    js_array_alloc, js_array_get_jsvalue, js_array_is_array, js_array_length, js_array_push_f64, js_closure_call2,
+    js_get_string_pointer_unified, js_nanbox_get_pointer, js_object_alloc, js_object_get_field_by_name,
+    js_object_set_field_by_name, js_string_from_bytes, ArrayHeader, ClosureHeader, JSValue, ObjectHeader,
+    StringHeader,
+};
+
+use crate::common::handle::{get_handle, get_handle_mut, register_handle, take_handle, with_handle, Handle};
+use crate::concurrency::{boxed_pointer, error_value, undefined};
+
+/// Segment size unless `segmentBytes` says otherwise (64 MiB)
+const DEFAULT_SEGMENT_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Time between syncs under `fsync: "interval"` unless `fsyncIntervalMs` says otherwise
+const DEFAULT_FSYNC_INTERVAL: Duration = Duration::from_millis(1000);
+
+/// Offset, length and CRC in front of each record
+const HEADER_LEN: usize = 16;
+
+/// Records larger than this are treated as corrupt when scanning
+const MAX_RECORD_LEN: u32 = 1 << 30;
+
+const HANDLE_LIMIT: i64 = 0x100000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Fsync {
+    Always,
+    Interval(Duration),
+    Never,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct LogOptions {
+    segment_bytes: u64,
+    fsync: Fsync,
+}
+
+impl Default for LogOptions {
+    fn default() -> Self {
+        LogOptions { segment_bytes: DEFAULT_SEGMENT_BYTES, fsync: Fsync::Always }
+    }
+}
+
+/// CRC-32 (IEEE) lookup table
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 { 0xEDB8_8320 ^ (crc >> 1) } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+fn crc32(parts: &[&[u8]]) -> u32 {
+    let mut crc = !0u32;
+    for part in parts {
+        for &byte in *part {
+            crc = CRC_TABLE[((crc ^ byte as u32) & 0xFF) as usize] ^ (crc >> 8);
+        }
+    }
+    !crc
+}
+
+fn encode(offset: u64, data: &[u8]) -> Vec<u8> {
+    let offset_bytes = offset.to_le_bytes();
+    let len_bytes = (data.len() as u32).to_le_bytes();
+    let mut record = Vec::with_capacity(HEADER_LEN + data.len());
+    record.extend_from_slice(&offset_bytes);
+    record.extend_from_slice(&len_bytes);
+    record.extend_from_slice(&crc32(&[&offset_bytes, &len_bytes, data]).to_le_bytes());
+    record.extend_from_slice(data);
+    record
+}
+
+/// The record at `pos` in `bytes` as `(offset, data)`, or None if it is torn or corrupt
+fn decode(bytes: &[u8], pos: usize) -> Option<(u64, &[u8])> {
+    let header = bytes.get(pos..pos + HEADER_LEN)?;
+    let len = u32::from_le_bytes(header[8..12].try_into().unwrap());
+    if len > MAX_RECORD_LEN {
+        return None;
+    }
+    let data = bytes.get(pos + HEADER_LEN..pos + HEADER_LEN + len as usize)?;
+    let crc = u32::from_le_bytes(header[12..16].try_into().unwrap());
+    (crc32(&[&header[..12], data]) == crc).then(|| (u64::from_le_bytes(header[..8].try_into().unwrap()), data))
+}
+
+struct Segment {
+    /// Offset the segment was started at, and its file name
+    base: u64,
+    path: PathBuf,
+    size: u64,
+    /// `(offset, position)` of each record, by offset
+    index: Vec<(u64, u64)>,
+}
+
+impl Segment {
+    fn path_for(dir: &Path, base: u64) -> PathBuf {
+        dir.join(format!("{:020}.log", base))
+    }
+
+    /// Index the segment's records; a bad record is cut off when `repair` is set, an error otherwise
+    fn scan(dir: &Path, base: u64, repair: bool) -> io::Result<Segment> {
+        let path = Segment::path_for(dir, base);
+        let bytes = fs::read(&path)?;
+        let mut index = Vec::new();
+        let mut pos = 0;
+        let mut next = base;
+        while pos < bytes.len() {
+            match decode(&bytes, pos) {
+                Some((offset, data)) if offset >= next => {
+                    index.push((offset, pos as u64));
+                    next = offset + 1;
+                    pos += HEADER_LEN + data.len();
+                }
+                _ if repair => {
+                    OpenOptions::new().write(true).open(&path)?.set_len(pos as u64)?;
+                    break;
+                }
+                _ => {
+                    let message = format!("corrupt record at byte {} of {}", pos, path.display());
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+                }
+            }
+        }
+        Ok(Segment { base, path, size: pos as u64, index })
+    }
+
+    fn read_at(&self, file: &mut File, pos: u64) -> io::Result<Vec<u8>> {
+        let mut header = [0u8; HEADER_LEN];
+        file.seek(SeekFrom::Start(pos))?;
+        file.read_exact(&mut header)?;
+        let len = u32::from_le_bytes(header[8..12].try_into().unwrap()) as usize;
+        let mut data = vec![0u8; len];
+        file.read_exact(&mut data)?;
+        Ok(data)
+    }
+
+    /// Up to `limit` records with offsets from `from` on
+    fn read_from(&self, from: u64, limit: usize) -> io::Result<Vec<(u64, Vec<u8>)>> {
+        let start = self.index.partition_point(|&(offset, _)| offset < from);
+        let entries = &self.index[start..self.index.len().min(start.saturating_add(limit))];
+        if entries.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut file = File::open(&self.path)?;
+        entries.iter().map(|&(offset, pos)| Ok((offset, self.read_at(&mut file, pos)?))).collect()
+    }
+}
+
+pub struct EventLog {
+    dir: PathBuf,
+    options: LogOptions,
+    /// By base offset; the last is the one appended to
+    segments: Vec<Segment>,
+    active: File,
+    next_offset: u64,
+    last_sync: Instant,
+    unsynced: bool,
+}
+
+impl EventLog {
+    fn open(dir: &Path, options: LogOptions) -> io::Result<EventLog> {
+        fs::create_dir_all(dir)?;
+        let mut bases = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let name = entry?.file_name();
+            let name = name.to_string_lossy();
+            if let Some(base) = name.strip_suffix(".log").and_then(|stem| stem.parse::<u64>().ok()) {
+                bases.push(base);
+            }
+        }
+        bases.sort_unstable();
+        if bases.is_empty() {
+            File::create(Segment::path_for(dir, 0))?;
+            bases.push(0);
+        }
+        let last = bases.len() - 1;
+        let segments = bases
+            .iter()
+            .enumerate()
+            .map(|(i, &base)| Segment::scan(dir, base, i == last))
+            .collect::<io::Result<Vec<_>>>()?;
+        let tail = &segments[last];
+        let next_offset = tail.index.last().map_or(tail.base, |&(offset, _)| offset + 1);
+        let active = OpenOptions::new().append(true).open(&tail.path)?;
+        Ok(EventLog {
+            dir: dir.to_path_buf(),
+            options,
+            segments,
+            active,
+            next_offset,
+            last_sync: Instant::now(),
+            unsynced: false,
+        })
+    }
+
+    fn first_offset(&self) -> u64 {
+        self.segments.iter().find_map(|segment| segment.index.first()).map_or(self.next_offset, |&(offset, _)| offset)
+    }
+
+    /// Append records, returning the first one's offset
+    fn append<'a>(&mut self, records: impl IntoIterator<Item = &'a [u8]>) -> io::Result<u64> {
+        let first = self.next_offset;
+        for data in records {
+            if data.len() > MAX_RECORD_LEN as usize {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "record is larger than 1 GiB"));
+            }
+            if self.tail().size >= self.options.segment_bytes && !self.tail().index.is_empty() {
+                self.roll()?;
+            }
+            let record = encode(self.next_offset, data);
+            self.active.write_all(&record)?;
+            let offset = self.next_offset;
+            let tail = self.segments.last_mut().unwrap();
+            tail.index.push((offset, tail.size));
+            tail.size += record.len() as u64;
+            self.next_offset += 1;
+            self.unsynced = true;
+        }
+        match self.options.fsync {
+            Fsync::Always => self.sync()?,
+            Fsync::Interval(interval) if self.last_sync.elapsed() >= interval => self.sync()?,
+            _ => {}
+        }
+        Ok(first)
+    }
+
+    fn tail(&self) -> &Segment {
+        self.segments.last().unwrap()
+    }
+
+    /// Seal the active segment and start a new one at the next offset
+    fn roll(&mut self) -> io::Result<()> {
+        if self.options.fsync != Fsync::Never {
+            self.active.sync_data()?;
+        }
+        let path = Segment::path_for(&self.dir, self.next_offset);
+        self.active = OpenOptions::new().create(true).append(true).open(&path)?;
+        self.segments.push(Segment { base: self.next_offset, path, size: 0, index: Vec::new() });
+        Ok(())
+    }
+
+    fn sync(&mut self) -> io::Result<()> {
+        if self.unsynced {
+            self.active.sync_data()?;
+            self.unsynced = false;
+        }
+        self.last_sync = Instant::now();
+        Ok(())
+    }
+
+    /// The segment that holds `offset`, if any
+    fn segment_of(&self, offset: u64) -> Option<&Segment> {
+        let i = self.segments.partition_point(|segment| segment.base <= offset);
+        i.checked_sub(1).map(|i| &self.segments[i])
+    }
+
+    fn read(&self, offset: u64) -> io::Result<Option<Vec<u8>>> {
+        let Some(segment) = self.segment_of(offset) else { return Ok(None) };
+        match segment.index.binary_search_by_key(&offset, |&(offset, _)| offset) {
+            Ok(i) => segment.read_at(&mut File::open(&segment.path)?, segment.index[i].1).map(Some),
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// Up to `limit` records with offsets from `from` on
+    fn read_from(&self, from: u64, limit: usize) -> io::Result<Vec<(u64, Vec<u8>)>> {
+        let mut records = Vec::new();
+        let start = self.segments.partition_point(|segment| segment.base <= from).saturating_sub(1);
+        for segment in &self.segments[start..] {
+            if records.len() >= limit {
+                break;
+            }
+            records.extend(segment.read_from(from, limit - records.len())?);
+        }
+        Ok(records)
+    }
+
+    /// Base offsets of the sealed segments
+    fn sealed(&self) -> Vec<u64> {
+        self.segments[..self.segments.len() - 1].iter().map(|segment| segment.base).collect()
+    }
+
+    fn segment_records(&self, base: u64) -> io::Result<Vec<(u64, Vec<u8>)>> {
+        match self.segments.iter().find(|segment| segment.base == base) {
+            Some(segment) => segment.read_from(0, usize::MAX),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Rewrite the sealed segment at `base` with only the records in `keep`; an emptied
+    /// segment is deleted. Returns the number of records removed.
+    fn retain_in(&mut self, base: u64, keep: &HashSet<u64>) -> io::Result<usize> {
+        let sealed = self.segments.len() - 1;
+        let Some(i) = self.segments[..sealed].iter().position(|segment| segment.base == base) else { return Ok(0) };
+        let records = self.segments[i].read_from(0, usize::MAX)?;
+        let kept: Vec<_> = records.iter().filter(|(offset, _)| keep.contains(offset)).collect();
+        let removed = records.len() - kept.len();
+        if removed == 0 {
+            return Ok(0);
+        }
+        let path = self.segments[i].path.clone();
+        if kept.is_empty() {
+            fs::remove_file(&path)?;
+            self.segments.remove(i);
+            return Ok(removed);
+        }
+        // Write the survivors beside the segment, then swap it in
+        let staging = path.with_extension("log.compact");
+        let mut file = File::create(&staging)?;
+        let mut index = Vec::with_capacity(kept.len());
+        let mut size = 0u64;
+        for (offset, data) in kept {
+            let record = encode(*offset, data);
+            file.write_all(&record)?;
+            index.push((*offset, size));
+            size += record.len() as u64;
+        }
+        file.sync_all()?;
+        fs::rename(&staging, &path)?;
+        self.segments[i].index = index;
+        self.segments[i].size = size;
+        Ok(removed)
+    }
+
+    /// Remove the records of sealed segments that `keep(data, offset)` rejects
+    #[cfg(test)]
+    fn compact(&mut self, mut keep: impl FnMut(&[u8], u64) -> bool) -> io::Result<usize> {
+        let mut removed = 0;
+        for base in self.sealed() {
+            let records = self.segment_records(base)?;
+            let keep: HashSet<u64> =
+                records.iter().filter(|(offset, data)| keep(data, *offset)).map(|(offset, _)| *offset).collect();
+            removed += self.retain_in(base, &keep)?;
+        }
+        Ok(removed)
+    }
+
+    /// Delete the sealed segments whose records all come before `offset`
+    fn delete_before(&mut self, offset: u64) -> io::Result<usize> {
+        let mut removed = 0;
+        while self.segments.len() > 1 && self.segments[1].base <= offset {
+            let segment = self.segments.remove(0);
+            fs::remove_file(&segment.path)?;
+            removed += segment.index.len();
+        }
+        Ok(removed)
+    }
+}
+
+unsafe fn key(name: &str) -> *mut StringHeader {
+    js_string_from_bytes(name.as_ptr(), name.len() as u32)
+}
+
+fn string_value(bytes: &[u8]) -> f64 {
+    unsafe { f64::from_bits(JSValue::string_ptr(js_string_from_bytes(bytes.as_ptr(), bytes.len() as u32)).bits()) }
+}
+
+unsafe fn throw_error(message: &str) -> ! {
+    perry_runtime::exception::js_throw(error_value(message))
+}
+
+unsafe fn io_error(method: &str, e: io::Error) -> ! {
+    throw_error(&format!("event log {}: {}", method, e))
+}
+
+unsafe fn string_arg(value: f64) -> Option<String> {
+    let ptr = js_get_string_pointer_unified(value) as *const StringHeader;
+    if ptr.is_null() {
+        return None;
+    }
+    let len = (*ptr).length as usize;
+    let data = (ptr as *const u8).add(std::mem::size_of::<StringHeader>());
+    Some(String::from_utf8_lossy(std::slice::from_raw_parts(data, len)).into_owned())
+}
+
+/// A non-negative integral offset or count
+fn index_arg(value: f64) -> Option<u64> {
+    let js = JSValue::from_bits(value.to_bits());
+    let n = if js.is_int32() {
+        js.as_int32() as f64
+    } else if js.is_number() {
+        value
+    } else {
+        return None;
+    };
+    (n >= 0.0 && n.fract() == 0.0).then_some(n as u64)
+}
+
+unsafe fn closure_arg(value: f64) -> Option<*const ClosureHeader> {
+    let ptr = js_nanbox_get_pointer(value);
+    (ptr >= HANDLE_LIMIT && (*(ptr as *const ClosureHeader)).type_tag == CLOSURE_MAGIC)
+        .then_some(ptr as *const ClosureHeader)
+}
+
+unsafe fn read_options(options: f64) -> LogOptions {
+    let mut result = LogOptions::default();
+    let value = JSValue::from_bits(options.to_bits());
+    if !value.is_pointer() {
+        return result;
+    }
+    let obj = value.as_pointer::<ObjectHeader>();
+    let field = |name: &str| f64::from_bits(js_object_get_field_by_name(obj, key(name)).bits());
+    if let Some(bytes) = index_arg(field("segmentBytes")).filter(|bytes| *bytes > 0) {
+        result.segment_bytes = bytes;
+    }
+    let interval = index_arg(field("fsyncIntervalMs")).map_or(DEFAULT_FSYNC_INTERVAL, Duration::from_millis);
+    result.fsync = match string_arg(field("fsync")).as_deref() {
+        None | Some("always") => Fsync::Always,
+        Some("interval") => Fsync::Interval(interval),
+        Some("never") => Fsync::Never,
+        Some(other) => throw_error(&format!("unknown fsync policy '{}' (expected always, interval or never)", other)),
+    };
+    result
+}
+
+/// `openLog(dir, { segmentBytes, fsync, fsyncIntervalMs }?)`
+#[no_mangle]
+pub unsafe extern "C" fn js_eventlog_open(dir: f64, options: f64) -> f64 {
+    let Some(dir) = string_arg(dir) else { throw_error("openLog() expects a directory path") };
+    permissions::check(Capability::Fs, &dir);
+    match EventLog::open(Path::new(&dir), read_options(options)) {
+        Ok(log) => boxed_pointer(register_handle(log)),
+        Err(e) => throw_error(&format!("cannot open log {}: {}", dir, e)),
+    }
+}
+
+// ============================================================================
+// Handle dispatch
+// ============================================================================
+
+unsafe fn array_of(values: impl IntoIterator<Item = f64>) -> f64 {
+    let mut arr = js_array_alloc(0);
+    for value in values {
+        arr = js_array_push_f64(arr, value);
+    }
+    boxed_pointer(arr as i64)
+}
+
+pub(crate) fn is_event_log(handle: Handle) -> bool {
+    with_handle::<EventLog, bool, _>(handle, |_| true).unwrap_or(false)
+}
+
+/// `log.append`, `appendBatch`, `read`, `readFrom`, `compact`, `deleteBefore`, `sync`, `close`
+pub(crate) unsafe fn dispatch_event_log(handle: Handle, method: &str, args: &[f64]) -> f64 {
+    let arg = |i: usize| args.get(i).copied().unwrap_or_else(undefined);
+    if method == "close" {
+        if let Some(mut log) = take_handle::<EventLog>(handle) {
+            log.sync().unwrap_or_else(|e| io_error(method, e));
+        }
+        return undefined();
+    }
+    if method == "compact" {
+        return compact(handle, arg(0));
+    }
+    let Some(log) = get_handle_mut::<EventLog>(handle) else { throw_error("event log is closed") };
+    match method {
+        "append" => {
+            let Some(data) = string_arg(arg(0)) else { throw_error("append() expects a string") };
+            log.append([data.as_bytes()]).unwrap_or_else(|e| io_error(method, e)) as f64
+        }
+        "appendBatch" => {
+            let records = arg(0);
+            if js_array_is_array(records) == 0.0 {
+                throw_error("appendBatch() expects an array of strings");
+            }
+            let array = js_nanbox_get_pointer(records) as *const ArrayHeader;
+            let records = (0..js_array_length(array))
+                .map(|i| string_arg(f64::from_bits(js_array_get_jsvalue(array, i))))
+                .collect::<Option<Vec<_>>>()
+                .unwrap_or_else(|| throw_error("appendBatch() expects an array of strings"));
+            log.append(records.iter().map(|data| data.as_bytes())).unwrap_or_else(|e| io_error(method, e)) as f64
+        }
+        "read" => {
+            let Some(offset) = index_arg(arg(0)) else { return undefined() };
+            match log.read(offset).unwrap_or_else(|e| io_error(method, e)) {
+                Some(data) => string_value(&data),
+                None => undefined(),
+            }
+        }
+        "readFrom" => {
+            let from = index_arg(arg(0)).unwrap_or(0);
+            let limit = index_arg(arg(1)).map_or(usize::MAX, |limit| limit as usize);
+            let records = log.read_from(from, limit).unwrap_or_else(|e| io_error(method, e));
+            let entries = records.into_iter().map(|(offset, data)| {
+                let obj = js_object_alloc(0, 2);
+                js_object_set_field_by_name(obj, key("offset"), offset as f64);
+                js_object_set_field_by_name(obj, key("data"), string_value(&data));
+                boxed_pointer(obj as i64)
+            });
+            array_of(entries.collect::<Vec<_>>())
+        }
+        "deleteBefore" => {
+            let Some(offset) = index_arg(arg(0)) else { throw_error("deleteBefore() expects an offset") };
+            log.delete_before(offset).unwrap_or_else(|e| io_error(method, e)) as f64
+        }
+        "sync" => {
+            log.sync().unwrap_or_else(|e| io_error(method, e));
+            undefined()
+        }
+        _ => undefined(),
+    }
+}
+
+/// `log.compact(keep)`: the hook runs with no borrow of the log held, so it may append
+/// to it; segments are rewritten one at a time
+unsafe fn compact(handle: Handle, keep: f64) -> f64 {
+    let Some(keep) = closure_arg(keep) else { throw_error("compact() expects a function (data, offset) => boolean") };
+    let Some(log) = get_handle::<EventLog>(handle) else { throw_error("event log is closed") };
+    let mut removed = 0;
+    for base in log.sealed() {
+        let Some(log) = get_handle::<EventLog>(handle) else { break };
+        let records = log.segment_records(base).unwrap_or_else(|e| io_error("compact", e));
+        let kept = records
+            .iter()
+            .filter(|(offset, data)| {
+                let verdict = js_closure_call2(keep, string_value(data), *offset as f64);
+                perry_runtime::value::js_is_truthy(verdict) != 0
+            })
+            .map(|(offset, _)| *offset)
+            .collect::<HashSet<_>>();
+        let Some(log) = get_handle_mut::<EventLog>(handle) else { break };
+        removed += log.retain_in(base, &kept).unwrap_or_else(|e| io_error("compact", e));
+    }
+    removed as f64
+}
+
+/// `log.firstOffset`, `log.nextOffset`
+pub(crate) fn event_log_property(handle: Handle, property: &str) -> f64 {
+    let value = match property {
+        "firstOffset" => with_handle::<EventLog, u64, _>(handle, |log| log.first_offset()),
+        "nextOffset" => with_handle::<EventLog, u64, _>(handle, |log| log.next_offset),
+        _ => None,
+    };
+    value.map_or_else(undefined, |offset| offset as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_log(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("perry-eventlog-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn small() -> LogOptions {
+        LogOptions { segment_bytes: 64, fsync: Fsync::Never }
+    }
+
+    #[test]
+    fn test_append_read_and_reopen() {
+        let dir = temp_log("reopen");
+        let mut log = EventLog::open(&dir, small()).unwrap();
+        assert_eq!(log.append([b"first".as_slice()]).unwrap(), 0);
+        let batch: Vec<String> = (1..10).map(|i| format!("event {}", i)).collect();
+        assert_eq!(log.append(batch.iter().map(|s| s.as_bytes())).unwrap(), 1);
+        assert!(log.segments.len() > 1, "64-byte segments should roll");
+        assert_eq!(log.read(0).unwrap().as_deref(), Some(b"first".as_slice()));
+        assert_eq!(log.read(7).unwrap().as_deref(), Some(b"event 7".as_slice()));
+        assert_eq!(log.read(10).unwrap(), None);
+        let page = log.read_from(4, 3).unwrap();
+        assert_eq!(page.iter().map(|(offset, _)| *offset).collect::<Vec<_>>(), vec![4, 5, 6]);
+        drop(log);
+
+        let mut log = EventLog::open(&dir, small()).unwrap();
+        assert_eq!((log.first_offset(), log.next_offset), (0, 10));
+        assert_eq!(log.append([b"after".as_slice()]).unwrap(), 10);
+        assert_eq!(log.read_from(9, usize::MAX).unwrap().len(), 2);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_torn_tail_is_cut_off() {
+        let dir = temp_log("torn");
+        let mut log = EventLog::open(&dir, LogOptions::default()).unwrap();
+        log.append([b"kept".as_slice(), b"torn".as_slice()]).unwrap();
+        let path = log.tail().path.clone();
+        drop(log);
+        let len = fs::metadata(&path).unwrap().len();
+        OpenOptions::new().write(true).open(&path).unwrap().set_len(len - 2).unwrap();
+
+        let mut log = EventLog::open(&dir, LogOptions::default()).unwrap();
+        assert_eq!(log.next_offset, 1);
+        assert_eq!(log.append([b"again".as_slice()]).unwrap(), 1);
+        assert_eq!(log.read(1).unwrap().as_deref(), Some(b"again".as_slice()));
+        drop(log);
+
+        // Corruption before the last segment is not repaired
+        let mut bytes = fs::read(&path).unwrap();
+        bytes[HEADER_LEN] ^= 0xFF;
+        fs::write(&path, bytes).unwrap();
+        fs::write(Segment::path_for(&dir, 2), b"").unwrap();
+        assert!(EventLog::open(&dir, LogOptions::default()).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_compact_and_delete_keep_offsets() {
+        let dir = temp_log("compact");
+        let mut log = EventLog::open(&dir, small()).unwrap();
+        let records: Vec<String> = (0..12).map(|i| format!("record {}", i)).collect();
+        log.append(records.iter().map(|s| s.as_bytes())).unwrap();
+
+        let removed = log.compact(|_, offset| offset % 2 == 0).unwrap();
+        assert!(removed > 0);
+        // Only sealed segments are compacted; odd offsets past them remain
+        assert_eq!(log.read(1).unwrap(), None);
+        assert_eq!(log.read(4).unwrap().as_deref(), Some(b"record 4".as_slice()));
+        assert_eq!(log.read(11).unwrap().as_deref(), Some(b"record 11".as_slice()));
+        drop(log);
+
+        let mut log = EventLog::open(&dir, small()).unwrap();
+        assert_eq!(log.read(3).unwrap(), None);
+        assert_eq!(log.read_from(0, 2).unwrap().iter().map(|(offset, _)| *offset).collect::<Vec<_>>(), vec![0, 2]);
+        let before = log.segments.len();
+        assert!(log.delete_before(11).unwrap() > 0);
+        assert!(log.segments.len() < before && log.first_offset() > 0);
+        assert_eq!(log.read(11).unwrap().as_deref(), Some(b"record 11".as_slice()));
+        assert_eq!(log.next_offset, 12);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,10 +1,12 @@
 //! Fastify application creation and route registration
 
+use std::sync::Arc;
+
 use perry_runtime::{js_string_from_bytes, StringHeader, JSValue};
 
 use crate::common::{get_handle, get_handle_mut, register_handle, Handle};
-use super::{FastifyApp, FastifyConfig, ClosurePtr};
-use super::context::string_from_nanboxed;
+use super::{FastifyApp, FastifyConfig, ClosurePtr, RouteSchema};
+use super::context::{jsvalue_to_json_string, string_from_nanboxed};
 
 /// Helper to extract string from StringHeader pointer
 unsafe fn string_from_header(ptr: *const StringHeader) -> Option<String> {
@@ -140,6 +142,53 @@ pub unsafe extern "C" fn js_fastify_route(app_handle: Handle, method: i64, path:
     register_route(app_handle, &method_str, path, handler)
 }
 
+/// Register a route with an options object:
+/// `app.get(path, { schema }, handler)` and the other method shorthands
+#[no_mangle]
+pub unsafe extern "C" fn js_fastify_route_with_opts(app_handle: Handle, method: i64, path: i64, opts: f64, handler: i64) -> bool {
+    let method_str = match string_from_nanboxed(method) {
+        Some(m) => m.to_uppercase(),
+        None => return false,
+    };
+    register_route_with_opts(app_handle, &method_str, path, opts, handler)
+}
+
+/// Register a route, reading its request schemas from `opts.schema`.
+/// `ALL` registers the route for every method.
+pub(crate) unsafe fn register_route_with_opts(app_handle: Handle, method: &str, path: i64, opts: f64, handler: i64) -> bool {
+    let path_str = match string_from_nanboxed(path) {
+        Some(p) => p,
+        None => return false,
+    };
+
+    let mut schema = None;
+    let jsv = JSValue::from_bits(opts.to_bits());
+    if jsv.is_pointer() {
+        let ptr = jsv.as_pointer::<perry_runtime::ObjectHeader>();
+        let schema_key = js_string_from_bytes(b"schema".as_ptr(), 6);
+        let schema_val = perry_runtime::js_object_get_field_by_name_f64(ptr, schema_key);
+        if JSValue::from_bits(schema_val.to_bits()).is_pointer() {
+            let json = jsvalue_to_json_string(schema_val);
+            schema = serde_json::from_str(&json)
+                .ok()
+                .and_then(|value| RouteSchema::from_json(&value))
+                .map(Arc::new);
+        }
+    }
+
+    if let Some(app) = get_handle_mut::<FastifyApp>(app_handle) {
+        if method == "ALL" {
+            for method in ["GET", "POST", "PUT", "DELETE", "PATCH", "HEAD", "OPTIONS"] {
+                app.add_route_with_schema(method, &path_str, handler, schema.clone());
+            }
+        } else {
+            app.add_route_with_schema(method, &path_str, handler, schema);
+        }
+        return true;
+    }
+    false
+}
+
 /// Internal helper to register a route
 unsafe fn register_route(app_handle: Handle, method: &str, path: i64, handler: i64) -> bool {
     let path_str = match string_from_nanboxed(path) {
@@ -118,13 +118,7 @@ impl FastifyContext {
 
     /// Get all query parameters as a map
     pub fn get_query_params(&self) -> HashMap<String, String> {
-        let mut params = HashMap::new();
-        for pair in self.query_string.split('&') {
-            if let Some((key, value)) = pair.split_once('=') {
-                params.insert(key.to_string(), urlencoding_decode(value));
-            }
-        }
-        params
+        parse_query_string(&self.query_string)
     }
 
     /// Get body as string
@@ -143,6 +137,17 @@ impl FastifyContext {
     }
 }
 
+/// Parse a query string (without leading ?) into a map
+pub(crate) fn parse_query_string(query_string: &str) -> HashMap<String, String> {
+    let mut params = HashMap::new();
+    for pair in query_string.split('&') {
+        if let Some((key, value)) = pair.split_once('=') {
+            params.insert(key.to_string(), urlencoding_decode(value));
+        }
+    }
+    params
+}
+
 /// Simple URL decoding
 fn urlencoding_decode(s: &str) -> String {
     let mut result = String::with_capacity(s.len());
@@ -474,7 +479,7 @@ unsafe fn jsvalue_to_response_body(value: f64) -> Vec<u8> {
 }
 
 /// Convert a JSValue to a JSON string
-unsafe fn jsvalue_to_json_string(value: f64) -> String {
+pub(crate) unsafe fn jsvalue_to_json_string(value: f64) -> String {
     let jsv = JSValue::from_bits(value.to_bits());
 
     if jsv.is_undefined() {
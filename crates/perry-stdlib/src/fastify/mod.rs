@@ -27,13 +27,16 @@ pub mod router;
 pub mod context;
 pub mod app;
 pub mod server;
+pub mod schema;
 
 pub use router::*;
 pub use context::*;
 pub use app::*;
 pub use server::*;
+pub use schema::RouteSchema;
 
 use std::collections::HashMap;
+use std::sync::Arc;
 use std::sync::atomic::{AtomicU64, Ordering};
 
 /// Closure pointer type (matches perry-runtime)
@@ -48,6 +51,8 @@ pub struct Route {
     pub pattern: RoutePattern,
     /// Handler closure pointer
     pub handler: ClosurePtr,
+    /// Request schemas checked before the handler runs
+    pub schema: Option<Arc<RouteSchema>>,
 }
 
 /// Lifecycle hooks for request processing
@@ -134,6 +139,11 @@ impl FastifyApp {
 
     /// Add a route
     pub fn add_route(&mut self, method: &str, path: &str, handler: ClosurePtr) {
+        self.add_route_with_schema(method, path, handler, None);
+    }
+
+    /// Add a route whose requests are validated against `schema`
+    pub fn add_route_with_schema(&mut self, method: &str, path: &str, handler: ClosurePtr, schema: Option<Arc<RouteSchema>>) {
         let full_path = if self.prefix.is_empty() {
             path.to_string()
         } else {
@@ -144,6 +154,7 @@ impl FastifyApp {
             method: method.to_uppercase(),
             pattern: RoutePattern::parse(&full_path),
             handler,
+            schema,
         });
     }
 
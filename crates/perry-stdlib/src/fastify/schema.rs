@@ -0,0 +1,421 @@
+//! Route schema validation
+//!
+//! Routes registered with an options object, e.g.
+//! `app.post('/users', { schema: { body: {...} } }, handler)`, have the request checked
+//! against the JSON Schemas under `body`, `querystring`, `params` and `headers` before the
+//! handler runs. A request that fails gets a 400 reply in Fastify's error format and never
+//! reaches TypeScript. Querystring, params and headers arrive as strings, so values are
+//! coerced to the `number` / `integer` / `boolean` type their schema asks for first, as
+//! Fastify does.
+//!
+//! Supported keywords: type (including type arrays), properties, required,
+//! additionalProperties, items, enum, const, minimum, maximum, exclusiveMinimum,
+//! exclusiveMaximum, multipleOf, minLength, maxLength, minItems, maxItems, uniqueItems,
+//! minProperties, maxProperties, anyOf, oneOf, allOf, not and nullable. Other keywords
+//! (format, pattern, $ref) are accepted and not checked.
+
+use std::collections::HashMap;
+
+use serde_json::{Map, Value};
+
+/// Schemas attached to a route
+#[derive(Debug, Clone, Default)]
+pub struct RouteSchema {
+    pub body: Option<Value>,
+    pub querystring: Option<Value>,
+    pub params: Option<Value>,
+    pub headers: Option<Value>,
+    /// Response schemas by status code; kept for documentation, not enforced
+    pub response: Option<Value>,
+}
+
+impl RouteSchema {
+    /// Read the `schema` object of a route's options. Returns None when it holds no schemas.
+    pub fn from_json(schema: &Value) -> Option<Self> {
+        let obj = schema.as_object()?;
+        let part = |name: &str| obj.get(name).filter(|v| v.is_object()).cloned();
+        let route_schema = Self {
+            body: part("body"),
+            querystring: part("querystring").or_else(|| part("query")),
+            params: part("params"),
+            headers: part("headers"),
+            response: part("response"),
+        };
+        if route_schema.body.is_none()
+            && route_schema.querystring.is_none()
+            && route_schema.params.is_none()
+            && route_schema.headers.is_none()
+            && route_schema.response.is_none()
+        {
+            return None;
+        }
+        Some(route_schema)
+    }
+
+    /// Check a request against the route's schemas, in Fastify's order
+    /// (params, body, querystring, headers). Returns the first failure's message.
+    pub fn validate_request(
+        &self,
+        params: &HashMap<String, String>,
+        query: &HashMap<String, String>,
+        headers: &HashMap<String, String>,
+        body: Option<&[u8]>,
+    ) -> Result<(), String> {
+        if let Some(schema) = &self.params {
+            validate(schema, &coerce_strings(schema, params), "params")?;
+        }
+        if let Some(schema) = &self.body {
+            let value = match body {
+                Some(bytes) => serde_json::from_slice(bytes)
+                    .map_err(|_| "body must be valid JSON".to_string())?,
+                None => Value::Null,
+            };
+            validate(schema, &value, "body")?;
+        }
+        if let Some(schema) = &self.querystring {
+            validate(schema, &coerce_strings(schema, query), "querystring")?;
+        }
+        if let Some(schema) = &self.headers {
+            // Header names are matched case-insensitively
+            let lowered = lowercase_properties(schema);
+            validate(&lowered, &coerce_strings(&lowered, headers), "headers")?;
+        }
+        Ok(())
+    }
+}
+
+/// Fastify's reply body for a failed validation
+pub fn validation_error_body(message: &str) -> String {
+    serde_json::json!({
+        "statusCode": 400,
+        "code": "FST_ERR_VALIDATION",
+        "error": "Bad Request",
+        "message": message,
+    })
+    .to_string()
+}
+
+/// Build an object from string values, converting each to the type its property schema asks for
+fn coerce_strings(schema: &Value, values: &HashMap<String, String>) -> Value {
+    let properties = schema.get("properties").and_then(Value::as_object);
+    let mut obj = Map::new();
+    for (key, raw) in values {
+        let value = match properties.and_then(|p| p.get(key)) {
+            Some(prop) => coerce_string(prop, raw),
+            None => Value::String(raw.clone()),
+        };
+        obj.insert(key.clone(), value);
+    }
+    Value::Object(obj)
+}
+
+fn coerce_string(schema: &Value, raw: &str) -> Value {
+    for ty in schema_types(schema) {
+        match ty {
+            "number" => {
+                if let Some(n) = raw.trim().parse::<f64>().ok().and_then(serde_json::Number::from_f64) {
+                    return Value::Number(n);
+                }
+            }
+            "integer" => {
+                if let Ok(n) = raw.trim().parse::<i64>() {
+                    return Value::Number(n.into());
+                }
+            }
+            "boolean" => match raw {
+                "true" => return Value::Bool(true),
+                "false" => return Value::Bool(false),
+                _ => {}
+            },
+            "null" if raw.is_empty() => return Value::Null,
+            "array" => {
+                let items = schema.get("items").cloned().unwrap_or(Value::Null);
+                return Value::Array(raw.split(',').map(|part| coerce_string(&items, part)).collect());
+            }
+            _ => {}
+        }
+    }
+    Value::String(raw.to_string())
+}
+
+fn lowercase_properties(schema: &Value) -> Value {
+    let mut lowered = schema.clone();
+    if let Some(props) = lowered.get_mut("properties").and_then(Value::as_object_mut) {
+        let entries = std::mem::take(props);
+        for (key, value) in entries {
+            props.insert(key.to_lowercase(), value);
+        }
+    }
+    if let Some(required) = lowered.get_mut("required").and_then(Value::as_array_mut) {
+        for name in required.iter_mut() {
+            if let Some(s) = name.as_str() {
+                *name = Value::String(s.to_lowercase());
+            }
+        }
+    }
+    lowered
+}
+
+/// The `type` keyword as a list (empty when absent)
+fn schema_types(schema: &Value) -> Vec<&str> {
+    let mut types: Vec<&str> = match schema.get("type") {
+        Some(Value::String(ty)) => vec![ty.as_str()],
+        Some(Value::Array(tys)) => tys.iter().filter_map(Value::as_str).collect(),
+        _ => Vec::new(),
+    };
+    if !types.is_empty() && schema.get("nullable") == Some(&Value::Bool(true)) {
+        types.push("null");
+    }
+    types
+}
+
+fn type_matches(ty: &str, value: &Value) -> bool {
+    match ty {
+        "null" => value.is_null(),
+        "boolean" => value.is_boolean(),
+        "number" => value.is_number(),
+        "integer" => value.as_f64().map_or(false, |n| n.fract() == 0.0),
+        "string" => value.is_string(),
+        "array" => value.is_array(),
+        "object" => value.is_object(),
+        _ => true,
+    }
+}
+
+fn number_keyword(schema: &Value, name: &str) -> Option<f64> {
+    schema.get(name).and_then(Value::as_f64)
+}
+
+fn count_keyword(schema: &Value, name: &str) -> Option<usize> {
+    schema.get(name).and_then(Value::as_u64).map(|n| n as usize)
+}
+
+/// Validate `value` against a JSON Schema. `path` names the value in error messages,
+/// e.g. `body/address/city`.
+pub fn validate(schema: &Value, value: &Value, path: &str) -> Result<(), String> {
+    let schema = match schema {
+        Value::Bool(true) => return Ok(()),
+        Value::Bool(false) => return Err(format!("{} must NOT be valid", path)),
+        Value::Object(_) => schema,
+        _ => return Ok(()),
+    };
+
+    let types = schema_types(schema);
+    if !types.is_empty() && !types.iter().any(|ty| type_matches(ty, value)) {
+        return Err(format!("{} must be {}", path, types.join(",")));
+    }
+
+    if let Some(Value::Array(allowed)) = schema.get("enum") {
+        if !allowed.contains(value) {
+            return Err(format!("{} must be equal to one of the allowed values", path));
+        }
+    }
+    if let Some(expected) = schema.get("const") {
+        if expected != value {
+            return Err(format!("{} must be equal to constant", path));
+        }
+    }
+
+    match value {
+        Value::Number(n) => {
+            let n = n.as_f64().unwrap_or(f64::NAN);
+            if let Some(min) = number_keyword(schema, "minimum") {
+                if n < min {
+                    return Err(format!("{} must be >= {}", path, min));
+                }
+            }
+            if let Some(max) = number_keyword(schema, "maximum") {
+                if n > max {
+                    return Err(format!("{} must be <= {}", path, max));
+                }
+            }
+            if let Some(min) = number_keyword(schema, "exclusiveMinimum") {
+                if n <= min {
+                    return Err(format!("{} must be > {}", path, min));
+                }
+            }
+            if let Some(max) = number_keyword(schema, "exclusiveMaximum") {
+                if n >= max {
+                    return Err(format!("{} must be < {}", path, max));
+                }
+            }
+            if let Some(step) = number_keyword(schema, "multipleOf") {
+                if step > 0.0 && (n / step).fract() != 0.0 {
+                    return Err(format!("{} must be multiple of {}", path, step));
+                }
+            }
+        }
+        Value::String(s) => {
+            let len = s.chars().count();
+            if let Some(min) = count_keyword(schema, "minLength") {
+                if len < min {
+                    return Err(format!("{} must NOT have fewer than {} characters", path, min));
+                }
+            }
+            if let Some(max) = count_keyword(schema, "maxLength") {
+                if len > max {
+                    return Err(format!("{} must NOT have more than {} characters", path, max));
+                }
+            }
+        }
+        Value::Array(items) => {
+            if let Some(min) = count_keyword(schema, "minItems") {
+                if items.len() < min {
+                    return Err(format!("{} must NOT have fewer than {} items", path, min));
+                }
+            }
+            if let Some(max) = count_keyword(schema, "maxItems") {
+                if items.len() > max {
+                    return Err(format!("{} must NOT have more than {} items", path, max));
+                }
+            }
+            if schema.get("uniqueItems") == Some(&Value::Bool(true)) {
+                for (i, item) in items.iter().enumerate() {
+                    if items[..i].contains(item) {
+                        return Err(format!("{} must NOT have duplicate items", path));
+                    }
+                }
+            }
+            if let Some(item_schema) = schema.get("items") {
+                for (i, item) in items.iter().enumerate() {
+                    validate(item_schema, item, &format!("{}/{}", path, i))?;
+                }
+            }
+        }
+        Value::Object(obj) => {
+            if let Some(Value::Array(required)) = schema.get("required") {
+                for name in required.iter().filter_map(Value::as_str) {
+                    if !obj.contains_key(name) {
+                        return Err(format!("{} must have required property '{}'", path, name));
+                    }
+                }
+            }
+            if let Some(min) = count_keyword(schema, "minProperties") {
+                if obj.len() < min {
+                    return Err(format!("{} must NOT have fewer than {} properties", path, min));
+                }
+            }
+            if let Some(max) = count_keyword(schema, "maxProperties") {
+                if obj.len() > max {
+                    return Err(format!("{} must NOT have more than {} properties", path, max));
+                }
+            }
+            let properties = schema.get("properties").and_then(Value::as_object);
+            let additional = schema.get("additionalProperties");
+            for (key, prop_value) in obj {
+                let prop_path = format!("{}/{}", path, key);
+                match properties.and_then(|p| p.get(key)) {
+                    Some(prop_schema) => validate(prop_schema, prop_value, &prop_path)?,
+                    None => match additional {
+                        Some(Value::Bool(false)) => {
+                            return Err(format!("{} must NOT have additional properties", path));
+                        }
+                        Some(extra) => validate(extra, prop_value, &prop_path)?,
+                        None => {}
+                    },
+                }
+            }
+        }
+        _ => {}
+    }
+
+    if let Some(Value::Array(all)) = schema.get("allOf") {
+        for sub in all {
+            validate(sub, value, path)?;
+        }
+    }
+    if let Some(Value::Array(any)) = schema.get("anyOf") {
+        if !any.iter().any(|sub| validate(sub, value, path).is_ok()) {
+            return Err(format!("{} must match a schema in anyOf", path));
+        }
+    }
+    if let Some(Value::Array(one)) = schema.get("oneOf") {
+        let matching = one.iter().filter(|sub| validate(sub, value, path).is_ok()).count();
+        if matching != 1 {
+            return Err(format!("{} must match exactly one schema in oneOf", path));
+        }
+    }
+    if let Some(not) = schema.get("not") {
+        if validate(not, value, path).is_ok() {
+            return Err(format!("{} must NOT be valid", path));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_validate_body() {
+        let schema = json!({
+            "type": "object",
+            "required": ["name"],
+            "additionalProperties": false,
+            "properties": {
+                "name": { "type": "string", "minLength": 1 },
+                "age": { "type": "integer", "minimum": 0 },
+                "tags": { "type": "array", "items": { "type": "string" }, "maxItems": 2 }
+            }
+        });
+        assert!(validate(&schema, &json!({ "name": "Ada", "age": 36 }), "body").is_ok());
+        assert_eq!(
+            validate(&schema, &json!({ "age": 36 }), "body"),
+            Err("body must have required property 'name'".to_string())
+        );
+        assert_eq!(
+            validate(&schema, &json!({ "name": "Ada", "age": -1 }), "body"),
+            Err("body/age must be >= 0".to_string())
+        );
+        assert_eq!(
+            validate(&schema, &json!({ "name": "Ada", "age": 1.5 }), "body"),
+            Err("body/age must be integer".to_string())
+        );
+        assert_eq!(
+            validate(&schema, &json!({ "name": "Ada", "tags": ["a", 1] }), "body"),
+            Err("body/tags/1 must be string".to_string())
+        );
+        assert_eq!(
+            validate(&schema, &json!({ "name": "Ada", "extra": true }), "body"),
+            Err("body must NOT have additional properties".to_string())
+        );
+        assert_eq!(validate(&schema, &Value::Null, "body"), Err("body must be object".to_string()));
+    }
+
+    #[test]
+    fn test_validate_request_coerces_strings() {
+        let schema = RouteSchema::from_json(&json!({
+            "params": { "type": "object", "properties": { "id": { "type": "integer" } } },
+            "querystring": {
+                "type": "object",
+                "properties": { "limit": { "type": "number", "maximum": 100 }, "full": { "type": "boolean" } }
+            },
+            "headers": { "type": "object", "required": ["X-Api-Key"] }
+        }))
+        .unwrap();
+
+        let params: HashMap<String, String> = [("id".to_string(), "42".to_string())].into_iter().collect();
+        let mut query: HashMap<String, String> = [("limit".to_string(), "10".to_string())].into_iter().collect();
+        query.insert("full".to_string(), "true".to_string());
+        let headers: HashMap<String, String> = [("x-api-key".to_string(), "k".to_string())].into_iter().collect();
+        assert!(schema.validate_request(&params, &query, &headers, None).is_ok());
+
+        let bad_id: HashMap<String, String> = [("id".to_string(), "abc".to_string())].into_iter().collect();
+        assert_eq!(
+            schema.validate_request(&bad_id, &query, &headers, None),
+            Err("params/id must be integer".to_string())
+        );
+        query.insert("limit".to_string(), "500".to_string());
+        assert_eq!(
+            schema.validate_request(&params, &query, &headers, None),
+            Err("querystring/limit must be <= 100".to_string())
+        );
+        query.insert("limit".to_string(), "5".to_string());
+        assert_eq!(
+            schema.validate_request(&params, &query, &HashMap::new(), None),
+            Err("headers must have required property 'x-api-key'".to_string())
+        );
+    }
+}
@@ -6,19 +6,44 @@ use bytes::Bytes;
 use http_body_util::{BodyExt, Full};
 use hyper::server::conn::http1;
 use hyper::service::service_fn;
-use hyper::{body::Incoming, Request, Response, StatusCode};
+use hyper::body::{Body, Incoming};
+use hyper::{Request, Response, StatusCode};
 use hyper_util::rt::TokioIo;
 use std::collections::HashMap;
 use std::net::SocketAddr;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use tokio::net::TcpListener;
 use tokio::sync::mpsc;
 
+use perry_runtime::buffer_pool::{self, PooledBytes};
 use perry_runtime::{js_string_from_bytes, StringHeader, JSValue};
 
 use crate::common::{get_handle, get_handle_mut, register_handle, Handle, RUNTIME};
 use super::{FastifyApp, FastifyContext, ClosurePtr};
-use super::context::string_from_header;
+use super::context::{parse_query_string, string_from_header};
+use super::schema::validation_error_body;
+
+/// OpenAPI document compiled into the binary (`perry compile --openapi <file> --docs`),
+/// served by every app at /docs/json with a browsable page at /docs
+static DOCS_DOCUMENT: Mutex<Option<String>> = Mutex::new(None);
+
+/// Page that renders /docs/json with Swagger UI
+const DOCS_PAGE: &str = r##"<!DOCTYPE html>
+<html lang="en">
+<head>
+  <meta charset="utf-8">
+  <title>API documentation</title>
+  <link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@5/swagger-ui.css">
+</head>
+<body>
+  <div id="swagger-ui"></div>
+  <script src="https://unpkg.com/swagger-ui-dist@5/swagger-ui-bundle.js"></script>
+  <script>
+    window.onload = () => { window.ui = SwaggerUIBundle({ url: "/docs/json", dom_id: "#swagger-ui" }); };
+  </script>
+</body>
+</html>
+"##;
 
 /// Server handle for managing the running server
 pub struct FastifyServerHandle {
@@ -158,6 +183,32 @@ pub unsafe extern "C" fn js_fastify_listen(app_handle: Handle, opts: f64, callba
     event_loop(app_handle, &mut request_rx);
 }
 
+/// Install the OpenAPI document served at /docs. Called from main before any module runs.
+#[no_mangle]
+pub unsafe extern "C" fn js_fastify_set_docs(document: *const u8, len: i64) {
+    if document.is_null() || len <= 0 {
+        return;
+    }
+    let bytes = std::slice::from_raw_parts(document, len as usize);
+    *DOCS_DOCUMENT.lock().unwrap_or_else(|e| e.into_inner()) = Some(String::from_utf8_lossy(bytes).into_owned());
+}
+
+/// The docs page or document for a GET that matched no route, if docs are compiled in.
+/// Routes the app registers itself at these paths take precedence.
+fn docs_response(path: &str) -> Option<Response<Full<Bytes>>> {
+    let document = DOCS_DOCUMENT.lock().unwrap_or_else(|e| e.into_inner()).clone()?;
+    let (content_type, body) = match path.trim_end_matches('/') {
+        "/docs" => ("text/html; charset=utf-8", DOCS_PAGE.to_string()),
+        "/docs/json" => ("application/json; charset=utf-8", document),
+        _ => return None,
+    };
+    Some(Response::builder()
+        .status(StatusCode::OK)
+        .header("content-type", content_type)
+        .body(Full::new(Bytes::from(body)))
+        .unwrap())
+}
+
 /// Handle incoming HTTP request - match route and forward to TypeScript
 async fn handle_request(
     req: Request<Incoming>,
@@ -171,6 +222,8 @@ async fn handle_request(
         Some(q) => format!("{}?{}", uri.path(), q),
         None => uri.path().to_string(),
     };
+    let path_only = uri.path().to_string();
+    let query_string = uri.query().unwrap_or("").to_string();
 
     // Extract headers
     let mut headers = HashMap::new();
@@ -180,27 +233,36 @@ async fn handle_request(
         }
     }
 
-    // Read body
-    let body = match req.collect().await {
-        Ok(collected) => {
-            let bytes = collected.to_bytes();
-            if bytes.is_empty() {
-                None
-            } else {
-                Some(bytes.to_vec())
-            }
+    // Read the body into a pooled buffer; the event loop hands it back once the handler is done
+    let mut incoming = req.into_body();
+    let size_hint = incoming.size_hint().upper().unwrap_or(0) as usize;
+    let mut body = buffer_pool::take(size_hint);
+    while let Some(frame) = incoming.frame().await {
+        let Ok(frame) = frame else {
+            body.clear();
+            break;
+        };
+        if let Some(data) = frame.data_ref() {
+            body.extend_from_slice(data);
         }
-        Err(_) => None,
+    }
+    let body = if body.is_empty() {
+        buffer_pool::give(body);
+        None
+    } else {
+        Some(body)
     };
 
     // Match route
     let mut matched_params = HashMap::new();
+    let mut matched_schema = None;
     let mut found_route = false;
 
     for route in routes.iter() {
         if route.method == method {
             if let Some(params) = route.pattern.match_path(&path) {
                 matched_params = params;
+                matched_schema = route.schema.clone();
                 found_route = true;
                 break;
             }
@@ -208,6 +270,14 @@ async fn handle_request(
     }
 
     if !found_route {
+        if let Some(body) = body {
+            buffer_pool::give(body);
+        }
+        if method == "GET" {
+            if let Some(response) = docs_response(&path_only) {
+                return Ok(response);
+            }
+        }
         // Return 404
         return Ok(Response::builder()
             .status(StatusCode::NOT_FOUND)
@@ -216,6 +286,21 @@ async fn handle_request(
             .unwrap());
     }
 
+    // Reject requests that don't match the route's schemas before they reach the handler
+    if let Some(schema) = matched_schema {
+        let query = parse_query_string(&query_string);
+        if let Err(message) = schema.validate_request(&matched_params, &query, &headers, body.as_deref()) {
+            if let Some(body) = body {
+                buffer_pool::give(body);
+            }
+            return Ok(Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .header("content-type", "application/json; charset=utf-8")
+                .body(Full::new(Bytes::from(validation_error_body(&message))))
+                .unwrap());
+        }
+    }
+
     // Create oneshot channel for response
     let (response_tx, response_rx) = tokio::sync::oneshot::channel::<FastifyResponse>();
 
@@ -246,7 +331,9 @@ async fn handle_request(
                 response = response.header(name, value);
             }
 
-            Ok(response.body(Full::new(Bytes::from(fastify_response.body))).unwrap())
+            // The body goes back to the pool once hyper has written it
+            let body = Bytes::from_owner(PooledBytes::new(fastify_response.body));
+            Ok(response.body(Full::new(body)).unwrap())
         }
         Err(_) => {
             Ok(Response::builder()
@@ -279,14 +366,14 @@ fn event_loop(app_handle: Handle, request_rx: &mut mpsc::Receiver<FastifyPending
             ).await
         });
 
-        if let Ok(Some(pending)) = result {
+        if let Ok(Some(mut pending)) = result {
             // Create context
             let ctx = FastifyContext::new(
                 0, // request_id
                 pending.method.clone(),
                 pending.path.clone(),
                 pending.headers.clone(),
-                pending.body.clone(),
+                pending.body.take(),
                 pending.params.clone(),
             );
             let ctx_handle = unsafe { register_handle(ctx) };
@@ -338,11 +425,15 @@ fn event_loop(app_handle: Handle, request_rx: &mut mpsc::Receiver<FastifyPending
                 }
 
                 // Get response from context
-                if let Some(ctx) = unsafe { get_handle::<FastifyContext>(ctx_handle) } {
+                if let Some(ctx) = get_handle_mut::<FastifyContext>(ctx_handle) {
+                    // The handler is done with the request body; its buffer goes back to the pool
+                    if let Some(body) = ctx.body.take() {
+                        buffer_pool::give(body);
+                    }
                     let response = FastifyResponse {
                         status: ctx.status_code,
                         headers: ctx.response_headers.clone(),
-                        body: ctx.response_body.clone().unwrap_or_else(|| {
+                        body: ctx.response_body.take().unwrap_or_else(|| {
                             // If no explicit body, use handler return value
                             build_response_body(final_result)
                         }),
@@ -448,6 +539,7 @@ mod tests {
             method: "GET".to_string(),
             pattern: super::super::RoutePattern::parse("/"),
             handler: 1,
+            schema: None,
         });
 
         // Add GET /users/:id route
@@ -455,6 +547,7 @@ mod tests {
             method: "GET".to_string(),
             pattern: super::super::RoutePattern::parse("/users/:id"),
             handler: 2,
+            schema: None,
         });
 
         // Add POST /users route
@@ -462,6 +555,7 @@ mod tests {
             method: "POST".to_string(),
             pattern: super::super::RoutePattern::parse("/users"),
             handler: 3,
+            schema: None,
         });
 
         let routes = Arc::new(routes);
@@ -4,18 +4,22 @@
 //! Provides fetch() function for making HTTP requests.
 
 use perry_runtime::{
-    js_array_alloc, js_array_push, js_object_alloc, js_object_set_field, js_object_set_keys,
-    js_string_from_bytes, JSValue, StringHeader,
+    js_array_alloc, js_array_push, js_closure_call2, js_object_alloc, js_object_set_field,
+    js_object_set_keys, js_string_from_bytes, ClosureHeader, JSValue, StringHeader,
 };
+use perry_runtime::permissions::{self, Capability};
 use std::collections::HashMap;
 use std::sync::Mutex;
+use tokio::io::{AsyncRead, AsyncReadExt};
 
 use crate::common::async_bridge::{queue_promise_resolution, spawn};
+use crate::http_cache;
 
 // Response handle storage
 lazy_static::lazy_static! {
     static ref FETCH_RESPONSES: Mutex<HashMap<usize, FetchResponse>> = Mutex::new(HashMap::new());
     static ref NEXT_RESPONSE_ID: Mutex<usize> = Mutex::new(1);
+    static ref UPLOAD_PROGRESS: Mutex<Vec<PendingUploadProgress>> = Mutex::new(Vec::new());
 }
 
 struct FetchResponse {
@@ -36,6 +40,98 @@ unsafe fn string_from_header(ptr: *const StringHeader) -> Option<String> {
     std::str::from_utf8(bytes).ok().map(|s| s.to_string())
 }
 
+/// Reject the fetch promise unless the compiled-in permissions allow connecting to `url`
+fn reject_denied(url: &str, promise_ptr: usize) -> bool {
+    let address = permissions::url_address(url).unwrap_or_else(|| url.to_string());
+    let Some(err_msg) = permissions::denial(Capability::Net, &address) else { return false };
+    let err_str = js_string_from_bytes(err_msg.as_ptr(), err_msg.len() as u32);
+    let err_bits = JSValue::pointer(err_str as *const u8).bits();
+    queue_promise_resolution(promise_ptr, false, err_bits);
+    true
+}
+
+/// Build a request for `method` with the custom headers applied
+fn build_request(method: &str, url: &str, custom_headers: &HashMap<String, String>) -> reqwest::RequestBuilder {
+    let client = reqwest::Client::new();
+    let mut request = match method.to_uppercase().as_str() {
+        "POST" => client.post(url),
+        "PUT" => client.put(url),
+        "DELETE" => client.delete(url),
+        "PATCH" => client.patch(url),
+        "HEAD" => client.head(url),
+        _ => client.get(url), // Default to GET
+    };
+
+    // Add custom headers
+    for (key, value) in custom_headers {
+        request = request.header(key.as_str(), value.as_str());
+    }
+    request
+}
+
+/// Send the request, store the response and settle the fetch promise with its handle.
+/// Returns the response status, or None if the request failed.
+async fn send_and_store(request: reqwest::RequestBuilder, promise_ptr: usize) -> Option<u16> {
+    match request.send().await {
+        Ok(response) => {
+            let status = response.status().as_u16();
+            let status_text = response.status().canonical_reason().unwrap_or("").to_string();
+
+            let mut headers = HashMap::new();
+            for (key, value) in response.headers() {
+                if let Ok(v) = value.to_str() {
+                    headers.insert(key.to_string(), v.to_string());
+                }
+            }
+
+            let body = response.bytes().await.unwrap_or_default().to_vec();
+            store_response(FetchResponse { status, status_text, headers, body }, promise_ptr);
+            Some(status)
+        }
+        Err(e) => {
+            reject_fetch(&e.to_string(), promise_ptr);
+            None
+        }
+    }
+}
+
+/// Store the response and settle the fetch promise with its handle
+fn store_response(response: FetchResponse, promise_ptr: usize) {
+    let mut id_guard = NEXT_RESPONSE_ID.lock().unwrap();
+    let response_id = *id_guard;
+    *id_guard += 1;
+    drop(id_guard);
+
+    FETCH_RESPONSES.lock().unwrap().insert(response_id, response);
+
+    // Return response handle
+    let result_bits = (response_id as f64).to_bits();
+    queue_promise_resolution(promise_ptr, true, result_bits);
+}
+
+fn reject_fetch(error: &str, promise_ptr: usize) {
+    let err_msg = format!("Fetch error: {}", error);
+    let err_str = js_string_from_bytes(err_msg.as_ptr(), err_msg.len() as u32);
+    let err_bits = JSValue::pointer(err_str as *const u8).bits();
+    queue_promise_resolution(promise_ptr, false, err_bits);
+}
+
+/// GET `url` through the HTTP cache (`perry/http-cache`) and settle the fetch promise
+async fn send_cached(url: &str, custom_headers: &HashMap<String, String>, promise_ptr: usize) {
+    match http_cache::get(url, custom_headers).await {
+        Ok(cached) => {
+            let response = FetchResponse {
+                status: cached.status,
+                status_text: cached.status_text,
+                headers: cached.headers.into_iter().collect(),
+                body: cached.body,
+            };
+            store_response(response, promise_ptr);
+        }
+        Err(e) => reject_fetch(&e, promise_ptr),
+    }
+}
+
 /// Perform a GET request
 /// fetch(url) -> Promise<Response>
 #[no_mangle]
@@ -53,46 +149,15 @@ pub unsafe extern "C" fn js_fetch_get(url_ptr: *const StringHeader) -> *mut perr
             return promise;
         }
     };
+    if reject_denied(&url, promise_ptr) {
+        return promise;
+    }
 
     spawn(async move {
-        let client = reqwest::Client::new();
-        match client.get(&url).send().await {
-            Ok(response) => {
-                let status = response.status().as_u16();
-                let status_text = response.status().canonical_reason().unwrap_or("").to_string();
-
-                let mut headers = HashMap::new();
-                for (key, value) in response.headers() {
-                    if let Ok(v) = value.to_str() {
-                        headers.insert(key.to_string(), v.to_string());
-                    }
-                }
-
-                let body = response.bytes().await.unwrap_or_default().to_vec();
-
-                // Store response
-                let mut id_guard = NEXT_RESPONSE_ID.lock().unwrap();
-                let response_id = *id_guard;
-                *id_guard += 1;
-                drop(id_guard);
-
-                FETCH_RESPONSES.lock().unwrap().insert(response_id, FetchResponse {
-                    status,
-                    status_text,
-                    headers,
-                    body,
-                });
-
-                // Return response handle
-                let result_bits = (response_id as f64).to_bits();
-                queue_promise_resolution(promise_ptr, true, result_bits);
-            }
-            Err(e) => {
-                let err_msg = format!("Fetch error: {}", e);
-                let err_str = js_string_from_bytes(err_msg.as_ptr(), err_msg.len() as u32);
-                let err_bits = JSValue::pointer(err_str as *const u8).bits();
-                queue_promise_resolution(promise_ptr, false, err_bits);
-            }
+        if http_cache::is_enabled() {
+            send_cached(&url, &HashMap::new(), promise_ptr).await;
+        } else {
+            send_and_store(reqwest::Client::new().get(&url), promise_ptr).await;
         }
     });
 
@@ -120,6 +185,9 @@ pub unsafe extern "C" fn js_fetch_post(
             return promise;
         }
     };
+    if reject_denied(&url, promise_ptr) {
+        return promise;
+    }
 
     let body = string_from_header(body_ptr).unwrap_or_default();
     let content_type = string_from_header(content_type_ptr).unwrap_or_else(|| "application/json".to_string());
@@ -135,6 +203,7 @@ pub unsafe extern "C" fn js_fetch_post(
         {
             Ok(response) => {
                 let status = response.status().as_u16();
+                http_cache::invalidate(&url, status).await;
                 let status_text = response.status().canonical_reason().unwrap_or("").to_string();
 
                 let mut headers = HashMap::new();
@@ -197,6 +266,9 @@ pub unsafe extern "C" fn js_fetch_with_options(
             return promise;
         }
     };
+    if reject_denied(&url, promise_ptr) {
+        return promise;
+    }
 
     let method = string_from_header(method_ptr).unwrap_or_else(|| "GET".to_string());
     let body = string_from_header(body_ptr);
@@ -206,69 +278,165 @@ pub unsafe extern "C" fn js_fetch_with_options(
     let custom_headers: HashMap<String, String> = serde_json::from_str(&headers_json).unwrap_or_default();
 
     spawn(async move {
-        let client = reqwest::Client::new();
-        let mut request = match method.to_uppercase().as_str() {
-            "POST" => client.post(&url),
-            "PUT" => client.put(&url),
-            "DELETE" => client.delete(&url),
-            "PATCH" => client.patch(&url),
-            "HEAD" => client.head(&url),
-            _ => client.get(&url), // Default to GET
-        };
-
-        // Add custom headers
-        for (key, value) in &custom_headers {
-            request = request.header(key.as_str(), value.as_str());
+        if method.eq_ignore_ascii_case("GET") && body.is_none() && http_cache::is_enabled() {
+            send_cached(&url, &custom_headers, promise_ptr).await;
+            return;
         }
 
+        let mut request = build_request(&method, &url, &custom_headers);
+
         // Add body if present
         if let Some(b) = body {
             request = request.body(b);
         }
 
-        match request.send().await {
-            Ok(response) => {
-                let status = response.status().as_u16();
-                let status_text = response.status().canonical_reason().unwrap_or("").to_string();
+        let status = send_and_store(request, promise_ptr).await;
+        // A change made through an unsafe method makes the cached copy out of date
+        if let Some(status) = status.filter(|_| !matches!(method.to_uppercase().as_str(), "GET" | "HEAD")) {
+            http_cache::invalidate(&url, status).await;
+        }
+    });
 
-                let mut headers = HashMap::new();
-                for (key, value) in response.headers() {
-                    if let Ok(v) = value.to_str() {
-                        headers.insert(key.to_string(), v.to_string());
-                    }
+    promise
+}
+
+/// Chunk size for streamed request bodies
+const UPLOAD_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Upload progress waiting to be reported on the main thread
+struct PendingUploadProgress {
+    callback: i64,
+    sent: u64,
+    total: u64,
+}
+
+/// Wrap `reader` in a chunked request body that queues a progress event per chunk
+fn upload_body(
+    reader: Box<dyn AsyncRead + Send + Unpin>,
+    total: u64,
+    on_progress: i64,
+) -> reqwest::Body {
+    let chunks = futures_util::stream::unfold((reader, 0u64), move |(mut reader, sent)| async move {
+        let mut chunk = vec![0u8; UPLOAD_CHUNK_SIZE];
+        match reader.read(&mut chunk).await {
+            Ok(0) => None,
+            Ok(n) => {
+                chunk.truncate(n);
+                let sent = sent + n as u64;
+                if on_progress != 0 {
+                    UPLOAD_PROGRESS.lock().unwrap().push(PendingUploadProgress {
+                        callback: on_progress,
+                        sent,
+                        total,
+                    });
                 }
+                Some((Ok::<_, std::io::Error>(chunk), (reader, sent)))
+            }
+            Err(e) => Some((Err(e), (reader, sent))),
+        }
+    });
+    reqwest::Body::wrap_stream(chunks)
+}
 
-                let body = response.bytes().await.unwrap_or_default().to_vec();
+/// Perform a fetch request whose body is streamed in chunks
+/// fetch(url, { method, body: fs.createReadStream(path), headers, onUploadProgress }) -> Promise<Response>
+///
+/// `body` is either a read stream, which is read from disk while uploading so
+/// multi-GB files never sit in memory, or a string. `on_progress` is a closure
+/// called as onUploadProgress(sentBytes, totalBytes) after each chunk, or 0.
+#[no_mangle]
+pub unsafe extern "C" fn js_fetch_upload_stream(
+    url_ptr: *const StringHeader,
+    method_ptr: *const StringHeader,
+    body: f64,
+    headers_json_ptr: *const StringHeader,
+    on_progress: i64,
+) -> *mut perry_runtime::Promise {
+    let promise = perry_runtime::js_promise_new();
+    let promise_ptr = promise as usize;
 
-                // Store response
-                let mut id_guard = NEXT_RESPONSE_ID.lock().unwrap();
-                let response_id = *id_guard;
-                *id_guard += 1;
-                drop(id_guard);
+    let url = match string_from_header(url_ptr) {
+        Some(u) => u,
+        None => {
+            let err_msg = "Invalid URL";
+            let err_str = js_string_from_bytes(err_msg.as_ptr(), err_msg.len() as u32);
+            let err_bits = JSValue::pointer(err_str as *const u8).bits();
+            queue_promise_resolution(promise_ptr, false, err_bits);
+            return promise;
+        }
+    };
+    if reject_denied(&url, promise_ptr) {
+        return promise;
+    }
 
-                FETCH_RESPONSES.lock().unwrap().insert(response_id, FetchResponse {
-                    status,
-                    status_text,
-                    headers,
-                    body,
-                });
+    let method = string_from_header(method_ptr).unwrap_or_else(|| "PUT".to_string());
+    let headers_json = string_from_header(headers_json_ptr).unwrap_or_else(|| "{}".to_string());
+    let custom_headers: HashMap<String, String> = serde_json::from_str(&headers_json).unwrap_or_default();
 
-                // Return response handle
-                let result_bits = (response_id as f64).to_bits();
-                queue_promise_resolution(promise_ptr, true, result_bits);
+    // Strings are copied out here; stream files are opened on the worker
+    let stream_path = perry_runtime::fs::read_stream_path(body);
+    let bytes = if stream_path.is_none() {
+        let body_ptr = perry_runtime::js_get_string_pointer_unified(body) as *const StringHeader;
+        string_from_header(body_ptr).unwrap_or_default().into_bytes()
+    } else {
+        Vec::new()
+    };
+
+    spawn(async move {
+        let (reader, total) = match stream_path {
+            Some(path) => {
+                let opened = match tokio::fs::File::open(&path).await {
+                    Ok(file) => file.metadata().await.map(|meta| (file, meta.len())),
+                    Err(e) => Err(e),
+                };
+                match opened {
+                    Ok((file, len)) => (Box::new(file) as Box<dyn AsyncRead + Send + Unpin>, len),
+                    Err(e) => {
+                        let err_msg = format!("Fetch error: cannot read {}: {}", path, e);
+                        let err_str = js_string_from_bytes(err_msg.as_ptr(), err_msg.len() as u32);
+                        let err_bits = JSValue::pointer(err_str as *const u8).bits();
+                        queue_promise_resolution(promise_ptr, false, err_bits);
+                        return;
+                    }
+                }
             }
-            Err(e) => {
-                let err_msg = format!("Fetch error: {}", e);
-                let err_str = js_string_from_bytes(err_msg.as_ptr(), err_msg.len() as u32);
-                let err_bits = JSValue::pointer(err_str as *const u8).bits();
-                queue_promise_resolution(promise_ptr, false, err_bits);
+            None => {
+                let len = bytes.len() as u64;
+                (Box::new(std::io::Cursor::new(bytes)) as Box<dyn AsyncRead + Send + Unpin>, len)
             }
+        };
+
+        let mut request = build_request(&method, &url, &custom_headers);
+        // Send a fixed length rather than chunked encoding; object stores require it
+        if !custom_headers.keys().any(|k| k.eq_ignore_ascii_case("content-length")) {
+            request = request.header(reqwest::header::CONTENT_LENGTH, total);
+        }
+        request = request.body(upload_body(reader, total, on_progress));
+
+        if let Some(status) = send_and_store(request, promise_ptr).await {
+            http_cache::invalidate(&url, status).await;
         }
     });
 
     promise
 }
 
+/// Run queued onUploadProgress callbacks on the main thread
+/// Returns the number of callbacks invoked.
+#[no_mangle]
+pub extern "C" fn js_fetch_process_pending() -> i32 {
+    let events: Vec<PendingUploadProgress> = {
+        let mut guard = UPLOAD_PROGRESS.lock().unwrap();
+        guard.drain(..).collect()
+    };
+
+    for event in &events {
+        let closure = event.callback as *const ClosureHeader;
+        js_closure_call2(closure, event.sent as f64, event.total as f64);
+    }
+    events.len() as i32
+}
+
 /// Get response status code
 /// response.status -> number
 #[no_mangle]
@@ -339,7 +507,7 @@ pub unsafe extern "C" fn js_fetch_response_text(handle: i64) -> *mut perry_runti
 }
 
 /// Convert serde_json::Value to JSValue
-unsafe fn json_value_to_jsvalue(value: &serde_json::Value) -> JSValue {
+pub(crate) unsafe fn json_value_to_jsvalue(value: &serde_json::Value) -> JSValue {
     match value {
         serde_json::Value::Null => JSValue::null(),
         serde_json::Value::Bool(b) => JSValue::bool(*b),
@@ -438,6 +606,9 @@ pub unsafe extern "C" fn js_fetch_text(url_ptr: *const StringHeader) -> *mut per
             return promise;
         }
     };
+    if reject_denied(&url, promise_ptr) {
+        return promise;
+    }
 
     spawn(async move {
         let client = reqwest::Client::new();
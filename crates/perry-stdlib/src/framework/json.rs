@@ -1,6 +1,7 @@
 //! JSON handling
 //!
-//! Provides JSON.parse() and JSON.stringify() functionality.
+//! Provides JSON.parse() and JSON.stringify() functionality, plus JSON.canonicalize()
+//! for the RFC 8785 (JCS) form that signatures and hashes are computed over.
 
 use perry_runtime::{
     js_array_alloc, js_array_push, js_object_alloc, js_object_set_field,
@@ -413,6 +414,101 @@ pub unsafe extern "C" fn js_json_stringify(value: f64, type_hint: u32) -> *mut S
     js_string_from_bytes(buf.as_ptr(), buf.len() as u32)
 }
 
+/// Write `value` in its RFC 8785 canonical form: object members sorted by the UTF-16
+/// code units of their names, no whitespace, numbers as ECMAScript prints them and
+/// strings with only the escapes JSON requires
+fn write_canonical(buf: &mut String, value: &serde_json::Value) {
+    match value {
+        serde_json::Value::Null => buf.push_str("null"),
+        serde_json::Value::Bool(b) => buf.push_str(if *b { "true" } else { "false" }),
+        serde_json::Value::Number(n) => write_canonical_number(buf, n.as_f64().unwrap_or(0.0)),
+        serde_json::Value::String(s) => buf.push_str(&serde_json::to_string(s).unwrap_or_default()),
+        serde_json::Value::Array(items) => {
+            buf.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    buf.push(',');
+                }
+                write_canonical(buf, item);
+            }
+            buf.push(']');
+        }
+        serde_json::Value::Object(members) => {
+            let mut keys: Vec<&String> = members.keys().collect();
+            keys.sort_by(|a, b| a.encode_utf16().cmp(b.encode_utf16()));
+            buf.push('{');
+            for (i, key) in keys.into_iter().enumerate() {
+                if i > 0 {
+                    buf.push(',');
+                }
+                buf.push_str(&serde_json::to_string(key).unwrap_or_default());
+                buf.push(':');
+                write_canonical(buf, &members[key]);
+            }
+            buf.push('}');
+        }
+    }
+}
+
+/// Number.prototype.toString(): the shortest digits that round-trip, in plain notation
+/// for decimal exponents from -7 to 20 and exponential notation otherwise
+fn write_canonical_number(buf: &mut String, value: f64) {
+    if !value.is_finite() {
+        // JSON has no NaN or Infinity; JSON.stringify writes them as null too
+        buf.push_str("null");
+        return;
+    }
+    if value == 0.0 {
+        buf.push('0');
+        return;
+    }
+    if value < 0.0 {
+        buf.push('-');
+    }
+    // `{:e}` gives the shortest round-trip digits, e.g. "1.2345e-7"
+    let formatted = format!("{:e}", value.abs());
+    let (mantissa, exponent) = formatted.split_once('e').unwrap_or((&formatted, "0"));
+    let digits: String = mantissa.chars().filter(|c| *c != '.').collect();
+    let k = digits.len() as i32;
+    let n = exponent.parse::<i32>().unwrap_or(0) + 1;
+    if k <= n && n <= 21 {
+        buf.push_str(&digits);
+        buf.push_str(&"0".repeat((n - k) as usize));
+    } else if 0 < n && n <= 21 {
+        buf.push_str(&digits[..n as usize]);
+        buf.push('.');
+        buf.push_str(&digits[n as usize..]);
+    } else if -6 < n && n <= 0 {
+        buf.push_str("0.");
+        buf.push_str(&"0".repeat(-n as usize));
+        buf.push_str(&digits);
+    } else {
+        buf.push_str(&digits[..1]);
+        if k > 1 {
+            buf.push('.');
+            buf.push_str(&digits[1..]);
+        }
+        let _ = write!(buf, "e{}{}", if n > 0 { "+" } else { "-" }, (n - 1).abs());
+    }
+}
+
+/// JSON.canonicalize(value) -> string
+///
+/// Stable stringify per RFC 8785: the same value always gives the same bytes, whatever
+/// order its properties were added in. Takes the same arguments as js_json_stringify.
+#[no_mangle]
+pub unsafe extern "C" fn js_json_canonicalize(value: f64, type_hint: u32) -> *mut StringHeader {
+    let mut json = String::with_capacity(256);
+    stringify_value(value, type_hint, &mut json);
+    let mut buf = String::with_capacity(json.len());
+    match serde_json::from_str::<serde_json::Value>(&json) {
+        Ok(parsed) => write_canonical(&mut buf, &parsed),
+        // Only a value stringify couldn't represent gets here; keep its output
+        Err(_) => buf = json,
+    }
+    js_string_from_bytes(buf.as_ptr(), buf.len() as u32)
+}
+
 /// Check if a string is valid JSON
 #[no_mangle]
 pub unsafe extern "C" fn js_json_is_valid(text_ptr: *const StringHeader) -> bool {
@@ -508,3 +604,42 @@ pub unsafe extern "C" fn js_json_get_bool(
 
     false
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn canonical(json: &str) -> String {
+        let mut buf = String::new();
+        write_canonical(&mut buf, &serde_json::from_str(json).unwrap());
+        buf
+    }
+
+    #[test]
+    fn test_canonicalize() {
+        assert_eq!(canonical(r#"{ "b": [1, {"z": true, "a": null}], "a": "x" }"#), r#"{"a":"x","b":[1,{"a":null,"z":true}]}"#);
+        // Names sort by UTF-16 code units: U+1F600 (a surrogate pair) before U+FB33
+        assert_eq!(canonical("{\"\u{fb33}\": 1, \"\u{1f600}\": 2, \"\\r\": 3}"), "{\"\\r\":3,\"\u{1f600}\":2,\"\u{fb33}\":1}");
+        assert_eq!(canonical(r#""\u0001\u00e9\/""#), r#""\u0001é/""#);
+    }
+
+    #[test]
+    fn test_canonical_numbers() {
+        let number = |value: f64| {
+            let mut buf = String::new();
+            write_canonical_number(&mut buf, value);
+            buf
+        };
+        assert_eq!(number(0.0), "0");
+        assert_eq!(number(-0.0), "0");
+        assert_eq!(number(333333333.3333333), "333333333.3333333");
+        assert_eq!(number(1e21), "1e+21");
+        assert_eq!(number(1e20), "100000000000000000000");
+        assert_eq!(number(1e-7), "1e-7");
+        assert_eq!(number(0.000001), "0.000001");
+        assert_eq!(number(-1.5e-10), "-1.5e-10");
+        assert_eq!(number(4.5), "4.5");
+        assert_eq!(number(9007199254740992.0), "9007199254740992");
+        assert_eq!(number(f64::NAN), "null");
+    }
+}
@@ -0,0 +1,489 @@
+//! Geo and spatial utilities (perry/geo)
+//!
+//! Turf-style helpers for points on the earth, computed natively:
+//!
+//! ```typescript
+//! import { distance, bearing, destination, pointInPolygon, bbox, geohashEncode } from 'perry/geo';
+//! distance([-0.1278, 51.5074], [2.3522, 48.8566], { units: 'kilometers' });  // 343.5...
+//! pointInPolygon([1, 1], [[[0, 0], [4, 0], [4, 4], [0, 4], [0, 0]]]);        // true
+//! geohashEncode([-0.1278, 51.5074], 7);                                     // "gcpvj0d"
+//! ```
+//!
+//! Positions are `[longitude, latitude]` as in GeoJSON, and a point can also be given as a
+//! Point geometry or a Feature with one. Polygons are arrays of rings (the first the outer
+//! boundary, the rest holes), Polygon or MultiPolygon geometries or Features with one.
+//! `bbox` takes any GeoJSON value or nested coordinate array and returns
+//! `[minLon, minLat, maxLon, maxLat]`.
+//!
+//! Distances are great-circle distances (haversine) on a sphere of radius 6371008.8 m,
+//! in `units` of kilometers (the default), meters, miles, nauticalmiles, feet, yards,
+//! degrees or radians. Bearings are in degrees from north, between -180 and 180. A point on
+//! a polygon's boundary is inside it unless `ignoreBoundary` is set.
+//!
+//! Geohashes are base32 strings of up to 12 characters: `geohashEncode(point, precision)`
+//! (9 by default), `geohashDecode(hash)` gives the position of the cell's center,
+//! `geohashBounds(hash)` its bounding box and `geohashNeighbors(hash)` the 8 cells around
+//! it, in the order north, north-east, east, south-east, south, south-west, west, north-west.
+
+use perry_runtime::{
+    js_array_alloc, js_array_get_jsvalue, js_array_is_array, js_array_length, js_array_push_f64,
+    js_get_string_pointer_unified, js_nanbox_get_pointer, js_object_get_field_by_name, js_string_from_bytes, ArrayHeader,
+    JSValue, ObjectHeader, StringHeader,
+};
+
+use crate::concurrency::{boxed_pointer, error_value, undefined};
+
+/// Mean earth radius in meters, as turf uses
+const EARTH_RADIUS: f64 = 6_371_008.8;
+
+const BASE32: &[u8; 32] = b"0123456789bcdefghjkmnpqrstuvwxyz";
+
+const MAX_PRECISION: usize = 12;
+
+const DEFAULT_PRECISION: usize = 9;
+
+type Position = [f64; 2];
+
+type Ring = Vec<Position>;
+
+/// Length of one radian of arc in `units`
+fn units_per_radian(units: &str) -> Option<f64> {
+    Some(match units {
+        "kilometers" | "kilometres" => EARTH_RADIUS / 1000.0,
+        "meters" | "metres" => EARTH_RADIUS,
+        "miles" => EARTH_RADIUS / 1609.344,
+        "nauticalmiles" => EARTH_RADIUS / 1852.0,
+        "feet" => EARTH_RADIUS * 3.28084,
+        "yards" => EARTH_RADIUS * 1.0936,
+        "degrees" => 180.0 / std::f64::consts::PI,
+        "radians" => 1.0,
+        _ => return None,
+    })
+}
+
+/// Angle between two positions as seen from the earth's center, in radians
+fn haversine(from: Position, to: Position) -> f64 {
+    let (lat1, lat2) = (from[1].to_radians(), to[1].to_radians());
+    let d_lat = lat2 - lat1;
+    let d_lon = (to[0] - from[0]).to_radians();
+    let a = (d_lat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (d_lon / 2.0).sin().powi(2);
+    2.0 * a.sqrt().atan2((1.0 - a).sqrt())
+}
+
+/// Initial bearing of the great circle from `from` to `to`, in degrees
+fn initial_bearing(from: Position, to: Position) -> f64 {
+    let (lat1, lat2) = (from[1].to_radians(), to[1].to_radians());
+    let d_lon = (to[0] - from[0]).to_radians();
+    let y = d_lon.sin() * lat2.cos();
+    let x = lat1.cos() * lat2.sin() - lat1.sin() * lat2.cos() * d_lon.cos();
+    y.atan2(x).to_degrees()
+}
+
+/// The position `angle` radians from `origin` along `bearing` degrees
+fn travel(origin: Position, angle: f64, bearing: f64) -> Position {
+    let (lat1, lon1) = (origin[1].to_radians(), origin[0].to_radians());
+    let bearing = bearing.to_radians();
+    let lat2 = (lat1.sin() * angle.cos() + lat1.cos() * angle.sin() * bearing.cos()).asin();
+    let lon2 = lon1 + (bearing.sin() * angle.sin() * lat1.cos()).atan2(angle.cos() - lat1.sin() * lat2.sin());
+    [lon2.to_degrees(), lat2.to_degrees()]
+}
+
+/// Whether `point` is inside `ring` (even-odd rule), or None if it is on its boundary
+fn ring_contains(point: Position, ring: &[Position]) -> Option<bool> {
+    let [x, y] = point;
+    let mut inside = false;
+    for (i, &[x1, y1]) in ring.iter().enumerate() {
+        let [x2, y2] = ring[(i + 1) % ring.len()];
+        let cross = (x - x1) * (y2 - y1) - (y - y1) * (x2 - x1);
+        if cross == 0.0 && (x1.min(x2)..=x1.max(x2)).contains(&x) && (y1.min(y2)..=y1.max(y2)).contains(&y) {
+            return None;
+        }
+        if (y1 > y) != (y2 > y) && x < (x2 - x1) * (y - y1) / (y2 - y1) + x1 {
+            inside = !inside;
+        }
+    }
+    Some(inside)
+}
+
+/// Whether `point` is in any of `polygons`: inside the outer ring and outside the holes
+fn polygons_contain(point: Position, polygons: &[Vec<Ring>], ignore_boundary: bool) -> bool {
+    polygons.iter().any(|rings| {
+        let Some((outer, holes)) = rings.split_first() else { return false };
+        match ring_contains(point, outer) {
+            None => !ignore_boundary,
+            Some(false) => false,
+            Some(true) => holes.iter().all(|hole| match ring_contains(point, hole) {
+                None => !ignore_boundary,
+                Some(inside) => !inside,
+            }),
+        }
+    })
+}
+
+fn geohash_encode(point: Position, precision: usize) -> String {
+    let (mut lon, mut lat) = ((-180.0, 180.0), (-90.0, 90.0));
+    let mut hash = String::with_capacity(precision);
+    let mut even = true;
+    for _ in 0..precision {
+        let mut index = 0;
+        for _ in 0..5 {
+            let (range, value) = if even { (&mut lon, point[0]) } else { (&mut lat, point[1]) };
+            let mid = (range.0 + range.1) / 2.0;
+            index <<= 1;
+            if value >= mid {
+                index |= 1;
+                range.0 = mid;
+            } else {
+                range.1 = mid;
+            }
+            even = !even;
+        }
+        hash.push(BASE32[index] as char);
+    }
+    hash
+}
+
+/// `[minLon, minLat, maxLon, maxLat]` of a geohash's cell; None if it isn't a geohash
+fn geohash_bounds(hash: &str) -> Option<[f64; 4]> {
+    if hash.is_empty() || hash.len() > MAX_PRECISION {
+        return None;
+    }
+    let (mut lon, mut lat) = ((-180.0, 180.0), (-90.0, 90.0));
+    let mut even = true;
+    for c in hash.bytes() {
+        let index = BASE32.iter().position(|&b| b == c.to_ascii_lowercase())?;
+        for bit in (0..5).rev() {
+            let range: &mut (f64, f64) = if even { &mut lon } else { &mut lat };
+            let mid = (range.0 + range.1) / 2.0;
+            if index >> bit & 1 == 1 {
+                range.0 = mid;
+            } else {
+                range.1 = mid;
+            }
+            even = !even;
+        }
+    }
+    Some([lon.0, lat.0, lon.1, lat.1])
+}
+
+/// The cells around a geohash's: north, north-east, east, ..., north-west
+fn geohash_neighbors(hash: &str) -> Option<Vec<String>> {
+    let [min_lon, min_lat, max_lon, max_lat] = geohash_bounds(hash)?;
+    let (width, height) = (max_lon - min_lon, max_lat - min_lat);
+    let center = [(min_lon + max_lon) / 2.0, (min_lat + max_lat) / 2.0];
+    let steps = [(0.0, 1.0), (1.0, 1.0), (1.0, 0.0), (1.0, -1.0), (0.0, -1.0), (-1.0, -1.0), (-1.0, 0.0), (-1.0, 1.0)];
+    let neighbors = steps.iter().map(|(dx, dy)| {
+        let lon = (center[0] + dx * width + 180.0).rem_euclid(360.0) - 180.0;
+        let lat = (center[1] + dy * height).clamp(-90.0 + height / 2.0, 90.0 - height / 2.0);
+        geohash_encode([lon, lat], hash.len())
+    });
+    Some(neighbors.collect())
+}
+
+// ============================================================================
+// Value conversion
+// ============================================================================
+
+unsafe fn key(name: &str) -> *mut StringHeader {
+    js_string_from_bytes(name.as_ptr(), name.len() as u32)
+}
+
+unsafe fn throw_error(message: &str) -> ! {
+    perry_runtime::exception::js_throw(error_value(message))
+}
+
+/// `value` NaN-boxed: objects and arrays can arrive as raw pointers
+fn boxed(value: f64) -> f64 {
+    let bits = value.to_bits();
+    if bits >> 48 == 0 && bits >= 0x100000 {
+        boxed_pointer(bits as i64)
+    } else {
+        value
+    }
+}
+
+fn number(value: f64) -> Option<f64> {
+    let (js, bits) = (JSValue::from_bits(value.to_bits()), value.to_bits());
+    if js.is_int32() {
+        Some(js.as_int32() as f64)
+    } else if js.is_number() && !(bits >> 48 == 0 && bits >= 0x100000) {
+        Some(value)
+    } else {
+        None
+    }
+}
+
+unsafe fn string_arg(value: f64) -> Option<String> {
+    if !JSValue::from_bits(value.to_bits()).is_string() {
+        return None;
+    }
+    let ptr = js_get_string_pointer_unified(value) as *const StringHeader;
+    if ptr.is_null() {
+        return None;
+    }
+    let data = (ptr as *const u8).add(std::mem::size_of::<StringHeader>());
+    Some(String::from_utf8_lossy(std::slice::from_raw_parts(data, (*ptr).length as usize)).into_owned())
+}
+
+unsafe fn items(value: f64) -> Option<Vec<f64>> {
+    let value = boxed(value);
+    if js_array_is_array(value) == 0.0 {
+        return None;
+    }
+    let array = js_nanbox_get_pointer(value) as *const ArrayHeader;
+    Some((0..js_array_length(array)).map(|i| f64::from_bits(js_array_get_jsvalue(array, i))).collect())
+}
+
+unsafe fn field(object: f64, name: &str) -> f64 {
+    let value = JSValue::from_bits(boxed(object).to_bits());
+    if !value.is_pointer() || js_array_is_array(boxed(object)) != 0.0 {
+        return undefined();
+    }
+    f64::from_bits(js_object_get_field_by_name(value.as_pointer::<ObjectHeader>(), key(name)).bits())
+}
+
+/// The geometry of a Feature, or the value itself
+unsafe fn geometry(value: f64) -> f64 {
+    match string_arg(field(value, "type")).as_deref() {
+        Some("Feature") => field(value, "geometry"),
+        _ => value,
+    }
+}
+
+unsafe fn position_of(value: f64) -> Option<Position> {
+    let coords = match items(value) {
+        Some(coords) => coords,
+        None => {
+            let geometry = geometry(value);
+            if string_arg(field(geometry, "type")).as_deref() != Some("Point") {
+                return None;
+            }
+            items(field(geometry, "coordinates"))?
+        }
+    };
+    Some([number(*coords.first()?)?, number(*coords.get(1)?)?])
+}
+
+unsafe fn point_arg(value: f64, function: &str) -> Position {
+    position_of(value).unwrap_or_else(|| throw_error(&format!("{}: expected a [longitude, latitude] position or a Point", function)))
+}
+
+unsafe fn ring_of(value: f64) -> Option<Ring> {
+    items(value)?.into_iter().map(|item| position_of(item)).collect()
+}
+
+unsafe fn rings_of(value: f64) -> Option<Vec<Ring>> {
+    items(value)?.into_iter().map(|item| ring_of(item)).collect()
+}
+
+/// The polygons of an array of rings, a Polygon, a MultiPolygon or a Feature with one
+unsafe fn polygons_of(value: f64) -> Option<Vec<Vec<Ring>>> {
+    if items(value).is_some() {
+        return Some(vec![rings_of(value)?]);
+    }
+    let geometry = geometry(value);
+    let coordinates = field(geometry, "coordinates");
+    match string_arg(field(geometry, "type")).as_deref()? {
+        "Polygon" => Some(vec![rings_of(coordinates)?]),
+        "MultiPolygon" => items(coordinates)?.into_iter().map(|polygon| rings_of(polygon)).collect(),
+        _ => None,
+    }
+}
+
+/// Extend `bounds` with every position in a GeoJSON value or coordinate array
+unsafe fn extend_bounds(value: f64, bounds: &mut [f64; 4]) {
+    if let Some(values) = items(value) {
+        match values.first().and_then(|first| number(*first)) {
+            Some(lon) => {
+                if let Some(lat) = values.get(1).and_then(|lat| number(*lat)) {
+                    *bounds = [bounds[0].min(lon), bounds[1].min(lat), bounds[2].max(lon), bounds[3].max(lat)];
+                }
+            }
+            None => values.into_iter().for_each(|item| extend_bounds(item, bounds)),
+        }
+        return;
+    }
+    for name in ["coordinates", "geometry", "geometries", "features"] {
+        let inner = field(value, name);
+        if JSValue::from_bits(inner.to_bits()).is_pointer() {
+            extend_bounds(inner, bounds);
+        }
+    }
+}
+
+unsafe fn units_option(options: f64, function: &str) -> f64 {
+    match string_arg(field(options, "units")) {
+        Some(units) => units_per_radian(&units).unwrap_or_else(|| throw_error(&format!("{}: unknown units \"{}\"", function, units))),
+        None => EARTH_RADIUS / 1000.0,
+    }
+}
+
+unsafe fn numbers(values: impl IntoIterator<Item = f64>) -> f64 {
+    let mut arr = js_array_alloc(0);
+    for value in values {
+        arr = js_array_push_f64(arr, value);
+    }
+    boxed_pointer(arr as i64)
+}
+
+fn string_value(text: &str) -> f64 {
+    unsafe { f64::from_bits(JSValue::string_ptr(key(text)).bits()) }
+}
+
+unsafe fn hash_arg(hash: f64, function: &str) -> String {
+    string_arg(hash).unwrap_or_else(|| throw_error(&format!("{}: expected a geohash string", function)))
+}
+
+unsafe fn invalid_hash(hash: &str) -> ! {
+    throw_error(&format!("Invalid geohash \"{}\"", hash))
+}
+
+// ============================================================================
+// perry/geo functions
+// ============================================================================
+
+/// `distance(from, to, { units }?)`
+#[no_mangle]
+pub unsafe extern "C" fn js_geo_distance(from: f64, to: f64, options: f64) -> f64 {
+    let (from, to) = (point_arg(from, "distance"), point_arg(to, "distance"));
+    haversine(from, to) * units_option(options, "distance")
+}
+
+/// `bearing(from, to)`
+#[no_mangle]
+pub unsafe extern "C" fn js_geo_bearing(from: f64, to: f64) -> f64 {
+    initial_bearing(point_arg(from, "bearing"), point_arg(to, "bearing"))
+}
+
+/// `destination(origin, distance, bearing, { units }?)` -> `[longitude, latitude]`
+#[no_mangle]
+pub unsafe extern "C" fn js_geo_destination(origin: f64, distance: f64, bearing: f64, options: f64) -> f64 {
+    let origin = point_arg(origin, "destination");
+    let (Some(distance), Some(bearing)) = (number(distance), number(bearing)) else {
+        throw_error("destination: distance and bearing must be numbers")
+    };
+    let [lon, lat] = travel(origin, distance / units_option(options, "destination"), bearing);
+    numbers([lon, lat])
+}
+
+/// `pointInPolygon(point, polygon, { ignoreBoundary }?)`
+#[no_mangle]
+pub unsafe extern "C" fn js_geo_point_in_polygon(point: f64, polygon: f64, options: f64) -> f64 {
+    let point = point_arg(point, "pointInPolygon");
+    let Some(polygons) = polygons_of(polygon) else {
+        throw_error("pointInPolygon: expected an array of rings, a Polygon or a MultiPolygon")
+    };
+    let ignore_boundary = perry_runtime::value::js_is_truthy(field(options, "ignoreBoundary")) != 0;
+    f64::from_bits(JSValue::bool(polygons_contain(point, &polygons, ignore_boundary)).bits())
+}
+
+/// `bbox(geojson)` -> `[minLon, minLat, maxLon, maxLat]`
+#[no_mangle]
+pub unsafe extern "C" fn js_geo_bbox(geojson: f64) -> f64 {
+    let mut bounds = [f64::INFINITY, f64::INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY];
+    extend_bounds(geojson, &mut bounds);
+    if bounds[0] > bounds[2] {
+        throw_error("bbox: no coordinates");
+    }
+    numbers(bounds)
+}
+
+/// `geohashEncode(point, precision?)`
+#[no_mangle]
+pub unsafe extern "C" fn js_geo_geohash_encode(point: f64, precision: f64) -> f64 {
+    let point = point_arg(point, "geohashEncode");
+    let precision = number(precision).map_or(DEFAULT_PRECISION, |p| p as usize);
+    if !(1..=MAX_PRECISION).contains(&precision) {
+        throw_error("geohashEncode: precision must be between 1 and 12");
+    }
+    string_value(&geohash_encode(point, precision))
+}
+
+/// `geohashDecode(hash)` -> center of the cell as `[longitude, latitude]`
+#[no_mangle]
+pub unsafe extern "C" fn js_geo_geohash_decode(hash: f64) -> f64 {
+    let hash = hash_arg(hash, "geohashDecode");
+    let Some([min_lon, min_lat, max_lon, max_lat]) = geohash_bounds(&hash) else { invalid_hash(&hash) };
+    numbers([(min_lon + max_lon) / 2.0, (min_lat + max_lat) / 2.0])
+}
+
+/// `geohashBounds(hash)` -> `[minLon, minLat, maxLon, maxLat]`
+#[no_mangle]
+pub unsafe extern "C" fn js_geo_geohash_bounds(hash: f64) -> f64 {
+    let hash = hash_arg(hash, "geohashBounds");
+    let Some(bounds) = geohash_bounds(&hash) else { invalid_hash(&hash) };
+    numbers(bounds)
+}
+
+/// `geohashNeighbors(hash)` -> `[n, ne, e, se, s, sw, w, nw]`
+#[no_mangle]
+pub unsafe extern "C" fn js_geo_geohash_neighbors(hash: f64) -> f64 {
+    let hash = hash_arg(hash, "geohashNeighbors");
+    let Some(neighbors) = geohash_neighbors(&hash) else { invalid_hash(&hash) };
+    let mut arr = js_array_alloc(0);
+    for neighbor in &neighbors {
+        arr = js_array_push_f64(arr, string_value(neighbor));
+    }
+    boxed_pointer(arr as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LONDON: Position = [-0.1278, 51.5074];
+    const PARIS: Position = [2.3522, 48.8566];
+
+    #[test]
+    fn test_distance_bearing_destination() {
+        let km = haversine(LONDON, PARIS) * units_per_radian("kilometers").unwrap();
+        assert!((km - 343.56).abs() < 0.1, "{}", km);
+        let miles = haversine(LONDON, PARIS) * units_per_radian("miles").unwrap();
+        assert!((miles - km / 1.609344).abs() < 1e-9);
+        assert_eq!(haversine(PARIS, PARIS), 0.0);
+
+        let bearing = initial_bearing(LONDON, PARIS);
+        assert!((bearing - 148.1).abs() < 0.1, "{}", bearing);
+        assert!((initial_bearing([0.0, 0.0], [-1.0, 0.0]) + 90.0).abs() < 1e-9);
+
+        let there = travel(LONDON, haversine(LONDON, PARIS), bearing);
+        assert!((there[0] - PARIS[0]).abs() < 1e-6 && (there[1] - PARIS[1]).abs() < 1e-6, "{:?}", there);
+    }
+
+    #[test]
+    fn test_point_in_polygon() {
+        let square = vec![vec![[0.0, 0.0], [4.0, 0.0], [4.0, 4.0], [0.0, 4.0], [0.0, 0.0]]];
+        let hole = vec![[1.0, 1.0], [3.0, 1.0], [3.0, 3.0], [1.0, 3.0], [1.0, 1.0]];
+        let with_hole = [vec![square[0].clone(), hole]];
+        let triangle = vec![vec![[10.0, 10.0], [12.0, 10.0], [11.0, 12.0]]];
+        let square = [square];
+        assert!(polygons_contain([0.5, 2.0], &square, false));
+        assert!(!polygons_contain([5.0, 2.0], &square, false));
+        assert!(polygons_contain([4.0, 2.0], &square, false));
+        assert!(!polygons_contain([4.0, 2.0], &square, true));
+        assert!(!polygons_contain([2.0, 2.0], &with_hole, false));
+        assert!(polygons_contain([0.5, 0.5], &with_hole, false));
+        let [square] = square;
+        assert!(polygons_contain([11.0, 11.0], &[square, triangle], false));
+    }
+
+    #[test]
+    fn test_geohash() {
+        assert_eq!(geohash_encode([-5.6, 42.6], 5), "ezs42");
+        assert_eq!(geohash_encode([10.40744, 57.64911], 11), "u4pruydqqvj");
+        assert_eq!(geohash_encode([-0.1278, 51.5074], 7), "gcpvj0d");
+        let [min_lon, min_lat, max_lon, max_lat] = geohash_bounds("ezs42").unwrap();
+        assert!(min_lon <= -5.6 && -5.6 <= max_lon && min_lat <= 42.6 && 42.6 <= max_lat);
+        assert_eq!(geohash_bounds("EZS42"), geohash_bounds("ezs42"));
+        assert_eq!(geohash_bounds("ezs4a"), None);
+        assert_eq!(geohash_bounds(""), None);
+
+        assert_eq!(
+            geohash_neighbors("dqcjq").unwrap(),
+            vec!["dqcjw", "dqcjx", "dqcjr", "dqcjp", "dqcjn", "dqcjj", "dqcjm", "dqcjt"]
+        );
+        // East of the antimeridian wraps around
+        let east = &geohash_neighbors(&geohash_encode([179.99, 0.0], 4)).unwrap()[2];
+        assert!(geohash_bounds(east).unwrap()[0] < -179.0);
+    }
+}
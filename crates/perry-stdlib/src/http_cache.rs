@@ -0,0 +1,697 @@
+//! HTTP client cache for fetch and axios (`perry/http-cache`)
+//!
+//! Off until `configure(options?)` turns it on. GET requests made with `fetch()` or
+//! `axios.get()` then go through a private cache that follows RFC 9111:
+//! - a response is stored unless either side says `no-store`, and only if it has a
+//!   freshness lifetime (`max-age`, `Expires`, or 10% of its `Last-Modified` age, up to a
+//!   day) or a validator (`ETag`, `Last-Modified`)
+//! - a fresh response is served without touching the network, with an `age` header
+//! - a stale one is revalidated with `If-None-Match` / `If-Modified-Since`; a 304 refreshes
+//!   the stored copy's headers and the stored body is served
+//! - within `stale-while-revalidate` the stale copy is served at once and revalidated in
+//!   the background; within `stale-if-error` it is served when the upstream can't be reached
+//! - `no-cache`, `must-revalidate` and a request's own `Cache-Control` are honoured, and
+//!   `Vary` keeps responses for different request header values apart (`Vary: *` isn't stored)
+//! - a successful POST, PUT, PATCH or DELETE drops the stored copy of its URL
+//!
+//! Options pick the storage: `{ store: "memory", maxEntries }` (the default; the oldest
+//! entry is evicted past `maxEntries`), `{ store: "disk", path }` (one file per URL under
+//! `path`, which goes through the `fs` permission policy) or `{ store: "redis", url }`
+//! (keys under `perry:http-cache:`, expiring once they can no longer be served).
+//! `disable()` turns the cache off and `clear()` drops everything stored.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use perry_runtime::permissions::{self, Capability};
+use perry_runtime::{js_get_string_pointer_unified, js_object_get_field_by_name, js_string_from_bytes, JSValue, ObjectHeader, StringHeader};
+use serde::{Deserialize, Serialize};
+
+use crate::common::async_bridge::spawn;
+use crate::concurrency::{error_value, undefined};
+
+/// Entries the memory store keeps unless `maxEntries` says otherwise
+const DEFAULT_MAX_ENTRIES: usize = 1000;
+
+/// Upper bound on a heuristic freshness lifetime (RFC 9111 §4.2.2 suggests 10% of the
+/// time since `Last-Modified`)
+const MAX_HEURISTIC_LIFETIME: u64 = 24 * 60 * 60;
+
+/// How long Redis keeps an entry that can be revalidated after it goes stale
+const VALIDATOR_TTL: u64 = 24 * 60 * 60;
+
+#[cfg(feature = "database-redis")]
+const REDIS_PREFIX: &str = "perry:http-cache:";
+
+/// Status codes that are cacheable by default (RFC 9110 §15.1)
+const CACHEABLE_STATUSES: [u16; 10] = [200, 203, 204, 300, 301, 404, 405, 410, 414, 501];
+
+/// The directives of a `Cache-Control` header this cache acts on
+#[derive(Debug, Default, Clone, PartialEq)]
+struct CacheControl {
+    max_age: Option<u64>,
+    no_store: bool,
+    no_cache: bool,
+    must_revalidate: bool,
+    stale_while_revalidate: Option<u64>,
+    stale_if_error: Option<u64>,
+}
+
+impl CacheControl {
+    fn parse(value: &str) -> CacheControl {
+        let mut cc = CacheControl::default();
+        for directive in value.split(',') {
+            let (name, argument) = match directive.split_once('=') {
+                Some((name, argument)) => (name.trim(), Some(argument.trim().trim_matches('"'))),
+                None => (directive.trim(), None),
+            };
+            let seconds = argument.and_then(|a| a.parse::<u64>().ok());
+            match name.to_ascii_lowercase().as_str() {
+                "max-age" => cc.max_age = seconds,
+                "no-store" => cc.no_store = true,
+                // `no-cache="field"` is treated as plain `no-cache`
+                "no-cache" => cc.no_cache = true,
+                "must-revalidate" | "proxy-revalidate" => cc.must_revalidate = true,
+                "stale-while-revalidate" => cc.stale_while_revalidate = seconds,
+                "stale-if-error" => cc.stale_if_error = seconds,
+                _ => {}
+            }
+        }
+        cc
+    }
+}
+
+/// Case-insensitive header lookup
+fn header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers.iter().find(|(key, _)| key.eq_ignore_ascii_case(name)).map(|(_, value)| value.as_str())
+}
+
+fn cache_control(headers: &[(String, String)]) -> CacheControl {
+    header(headers, "cache-control").map(CacheControl::parse).unwrap_or_default()
+}
+
+/// Seconds since the epoch of an HTTP date (`Sun, 06 Nov 1994 08:49:37 GMT`)
+fn parse_http_date(value: &str) -> Option<u64> {
+    let date = chrono::DateTime::parse_from_rfc2822(value.trim()).ok()?;
+    u64::try_from(date.timestamp()).ok()
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+/// A stored response, with what is needed to judge its freshness and match `Vary`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct CachedResponse {
+    pub status: u16,
+    pub status_text: String,
+    pub headers: Vec<(String, String)>,
+    /// Written after the JSON metadata by `encode`, so it stays binary
+    #[serde(skip)]
+    pub body: Vec<u8>,
+    /// When the response was received (or last revalidated), in seconds since the epoch
+    stored_at: u64,
+    /// Values the request had for the headers the response varies on
+    vary: Vec<(String, Option<String>)>,
+}
+
+/// How a stored response may be used for a request
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Freshness {
+    Fresh,
+    /// Stale, but may be served while it is revalidated in the background
+    StaleWhileRevalidate,
+    Stale,
+}
+
+impl CachedResponse {
+    fn new(status: u16, status_text: String, headers: Vec<(String, String)>, body: Vec<u8>, request: &[(String, String)]) -> CachedResponse {
+        let vary = header(&headers, "vary")
+            .map(|value| {
+                value
+                    .split(',')
+                    .map(|name| name.trim().to_ascii_lowercase())
+                    .filter(|name| !name.is_empty())
+                    .map(|name| {
+                        let value = header(request, &name).map(str::to_string);
+                        (name, value)
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        CachedResponse { status, status_text, headers, body, stored_at: now(), vary }
+    }
+
+    /// Freshness lifetime in seconds: `max-age`, then `Expires`, then the heuristic
+    fn lifetime(&self) -> Option<u64> {
+        if let Some(max_age) = cache_control(&self.headers).max_age {
+            return Some(max_age);
+        }
+        let date = header(&self.headers, "date").and_then(parse_http_date).unwrap_or(self.stored_at);
+        if let Some(expires) = header(&self.headers, "expires") {
+            // An invalid date (often `0`) means already expired
+            return Some(parse_http_date(expires).map(|e| e.saturating_sub(date)).unwrap_or(0));
+        }
+        let last_modified = header(&self.headers, "last-modified").and_then(parse_http_date)?;
+        Some((date.saturating_sub(last_modified) / 10).min(MAX_HEURISTIC_LIFETIME))
+    }
+
+    /// Current age: the upstream's `Age` plus the time spent here
+    fn age(&self, now: u64) -> u64 {
+        let upstream = header(&self.headers, "age").and_then(|a| a.trim().parse::<u64>().ok()).unwrap_or(0);
+        upstream + now.saturating_sub(self.stored_at)
+    }
+
+    fn has_validator(&self) -> bool {
+        header(&self.headers, "etag").is_some() || header(&self.headers, "last-modified").is_some()
+    }
+
+    /// Whether the response may be stored for a request with these headers
+    fn is_storable(&self, request: &[(String, String)]) -> bool {
+        let cc = cache_control(&self.headers);
+        CACHEABLE_STATUSES.contains(&self.status)
+            && !cc.no_store
+            && !cache_control(request).no_store
+            && header(&self.headers, "vary").map(|v| v.trim() != "*").unwrap_or(true)
+            && (self.lifetime().is_some() || self.has_validator())
+    }
+
+    /// Whether the request sends the same values for the headers the response varies on
+    fn matches(&self, request: &[(String, String)]) -> bool {
+        self.vary.iter().all(|(name, value)| header(request, name) == value.as_deref())
+    }
+
+    fn freshness(&self, request: &[(String, String)], now: u64) -> Freshness {
+        let cc = cache_control(&self.headers);
+        let request_cc = cache_control(request);
+        let age = self.age(now);
+        if cc.no_cache || request_cc.no_cache || request_cc.max_age.map(|max| age > max).unwrap_or(false) {
+            return Freshness::Stale;
+        }
+        let lifetime = self.lifetime().unwrap_or(0);
+        if age < lifetime {
+            return Freshness::Fresh;
+        }
+        match cc.stale_while_revalidate {
+            Some(window) if !cc.must_revalidate && age < lifetime + window => Freshness::StaleWhileRevalidate,
+            _ => Freshness::Stale,
+        }
+    }
+
+    /// Whether a stale copy may stand in for an upstream that can't be reached
+    fn usable_on_error(&self, now: u64) -> bool {
+        let cc = cache_control(&self.headers);
+        match cc.stale_if_error {
+            Some(window) => !cc.must_revalidate && self.age(now) < self.lifetime().unwrap_or(0) + window,
+            None => false,
+        }
+    }
+
+    /// How long the entry is worth keeping: as long as it can be served, or a day past that
+    /// if it can be revalidated
+    fn time_to_live(&self) -> u64 {
+        let cc = cache_control(&self.headers);
+        let extra = cc.stale_while_revalidate.unwrap_or(0).max(cc.stale_if_error.unwrap_or(0));
+        let ttl = self.lifetime().unwrap_or(0) + extra;
+        if self.has_validator() {
+            ttl.max(VALIDATOR_TTL)
+        } else {
+            ttl
+        }
+    }
+
+    /// Fold in the headers of a 304 answering a revalidation (RFC 9111 §4.3.4)
+    fn refresh(&mut self, headers: Vec<(String, String)>) {
+        for (name, value) in headers {
+            if name.eq_ignore_ascii_case("content-length") {
+                continue;
+            }
+            self.headers.retain(|(key, _)| !key.eq_ignore_ascii_case(&name));
+            self.headers.push((name, value));
+        }
+        self.stored_at = now();
+    }
+
+    /// The response as handed to the caller, with its current `age`
+    fn served(mut self) -> CachedResponse {
+        let age = self.age(now());
+        self.headers.retain(|(key, _)| !key.eq_ignore_ascii_case("age"));
+        self.headers.push(("age".to_string(), age.to_string()));
+        self
+    }
+
+    /// JSON metadata, a newline, then the raw body
+    fn encode(&self) -> Vec<u8> {
+        let mut bytes = serde_json::to_vec(self).unwrap_or_default();
+        bytes.push(b'\n');
+        bytes.extend_from_slice(&self.body);
+        bytes
+    }
+
+    fn decode(bytes: &[u8]) -> Option<CachedResponse> {
+        let split = bytes.iter().position(|&b| b == b'\n')?;
+        let mut entry: CachedResponse = serde_json::from_slice(&bytes[..split]).ok()?;
+        entry.body = bytes[split + 1..].to_vec();
+        Some(entry)
+    }
+}
+
+// ============================================================================
+// Storage
+// ============================================================================
+
+/// Entries in memory, the oldest evicted first
+struct MemoryStore {
+    entries: HashMap<String, CachedResponse>,
+    order: VecDeque<String>,
+    max_entries: usize,
+}
+
+enum Store {
+    Memory(Mutex<MemoryStore>),
+    /// A directory holding one file per URL
+    Disk(PathBuf),
+    #[cfg(feature = "database-redis")]
+    Redis {
+        client: redis::Client,
+        connection: tokio::sync::OnceCell<redis::aio::ConnectionManager>,
+    },
+}
+
+/// File name for a URL: its 64-bit FNV-1a hash, which stays stable across builds
+fn disk_file_name(url: &str) -> String {
+    let hash = url.bytes().fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100_0000_01b3));
+    format!("{:016x}.entry", hash)
+}
+
+impl Store {
+    #[cfg(feature = "database-redis")]
+    async fn redis(
+        client: &redis::Client,
+        connection: &tokio::sync::OnceCell<redis::aio::ConnectionManager>,
+    ) -> Option<redis::aio::ConnectionManager> {
+        connection.get_or_try_init(|| client.get_connection_manager()).await.ok().cloned()
+    }
+
+    async fn load(&self, url: &str) -> Option<CachedResponse> {
+        match self {
+            Store::Memory(memory) => memory.lock().unwrap().entries.get(url).cloned(),
+            Store::Disk(dir) => {
+                let bytes = tokio::fs::read(dir.join(disk_file_name(url))).await.ok()?;
+                CachedResponse::decode(&bytes)
+            }
+            #[cfg(feature = "database-redis")]
+            Store::Redis { client, connection } => {
+                let mut conn = Store::redis(client, connection).await?;
+                let bytes: Option<Vec<u8>> =
+                    redis::cmd("GET").arg(format!("{}{}", REDIS_PREFIX, url)).query_async(&mut conn).await.ok()?;
+                CachedResponse::decode(&bytes?)
+            }
+        }
+    }
+
+    async fn save(&self, url: &str, entry: &CachedResponse) {
+        match self {
+            Store::Memory(memory) => {
+                let mut memory = memory.lock().unwrap();
+                if memory.entries.insert(url.to_string(), entry.clone()).is_none() {
+                    memory.order.push_back(url.to_string());
+                }
+                while memory.entries.len() > memory.max_entries {
+                    let Some(oldest) = memory.order.pop_front() else { break };
+                    memory.entries.remove(&oldest);
+                }
+            }
+            Store::Disk(dir) => {
+                // Write beside the entry and rename, so a reader never sees half a file
+                let path = dir.join(disk_file_name(url));
+                let partial = path.with_extension("partial");
+                if tokio::fs::write(&partial, entry.encode()).await.is_ok() {
+                    let _ = tokio::fs::rename(&partial, &path).await;
+                }
+            }
+            #[cfg(feature = "database-redis")]
+            Store::Redis { client, connection } => {
+                let ttl = entry.time_to_live();
+                if ttl == 0 {
+                    return;
+                }
+                let Some(mut conn) = Store::redis(client, connection).await else { return };
+                let _: redis::RedisResult<()> = redis::cmd("SET")
+                    .arg(format!("{}{}", REDIS_PREFIX, url))
+                    .arg(entry.encode())
+                    .arg("EX")
+                    .arg(ttl)
+                    .query_async(&mut conn)
+                    .await;
+            }
+        }
+    }
+
+    async fn remove(&self, url: &str) {
+        match self {
+            Store::Memory(memory) => {
+                let mut memory = memory.lock().unwrap();
+                if memory.entries.remove(url).is_some() {
+                    memory.order.retain(|key| key != url);
+                }
+            }
+            Store::Disk(dir) => {
+                let _ = tokio::fs::remove_file(dir.join(disk_file_name(url))).await;
+            }
+            #[cfg(feature = "database-redis")]
+            Store::Redis { client, connection } => {
+                let Some(mut conn) = Store::redis(client, connection).await else { return };
+                let _: redis::RedisResult<()> =
+                    redis::cmd("DEL").arg(format!("{}{}", REDIS_PREFIX, url)).query_async(&mut conn).await;
+            }
+        }
+    }
+
+    async fn clear(&self) {
+        match self {
+            Store::Memory(memory) => {
+                let mut memory = memory.lock().unwrap();
+                memory.entries.clear();
+                memory.order.clear();
+            }
+            Store::Disk(dir) => {
+                let Ok(mut files) = tokio::fs::read_dir(dir).await else { return };
+                while let Ok(Some(file)) = files.next_entry().await {
+                    if file.path().extension().map(|e| e == "entry").unwrap_or(false) {
+                        let _ = tokio::fs::remove_file(file.path()).await;
+                    }
+                }
+            }
+            #[cfg(feature = "database-redis")]
+            Store::Redis { client, connection } => {
+                let Some(mut conn) = Store::redis(client, connection).await else { return };
+                let keys: Vec<String> = redis::cmd("KEYS")
+                    .arg(format!("{}*", REDIS_PREFIX))
+                    .query_async(&mut conn)
+                    .await
+                    .unwrap_or_default();
+                if !keys.is_empty() {
+                    let _: redis::RedisResult<()> = redis::cmd("DEL").arg(keys).query_async(&mut conn).await;
+                }
+            }
+        }
+    }
+}
+
+/// The configured store, or None while the cache is off
+static CACHE: RwLock<Option<Arc<Store>>> = RwLock::new(None);
+
+lazy_static::lazy_static! {
+    static ref CLIENT: reqwest::Client = reqwest::Client::new();
+    /// URLs with a background revalidation in flight
+    static ref REVALIDATING: Mutex<HashSet<String>> = Mutex::new(HashSet::new());
+}
+
+fn current_store() -> Option<Arc<Store>> {
+    CACHE.read().unwrap().clone()
+}
+
+/// Whether `configure()` has turned the cache on
+pub(crate) fn is_enabled() -> bool {
+    CACHE.read().unwrap().is_some()
+}
+
+// ============================================================================
+// Requests
+// ============================================================================
+
+/// Send a GET, adding the conditional headers for `stale` when revalidating it
+async fn send(url: &str, request: &[(String, String)], stale: Option<&CachedResponse>) -> Result<reqwest::Response, String> {
+    let mut builder = CLIENT.get(url);
+    for (name, value) in request {
+        builder = builder.header(name.as_str(), value.as_str());
+    }
+    if let Some(stale) = stale {
+        if let Some(etag) = header(&stale.headers, "etag") {
+            builder = builder.header("If-None-Match", etag);
+        }
+        if let Some(last_modified) = header(&stale.headers, "last-modified") {
+            builder = builder.header("If-Modified-Since", last_modified);
+        }
+    }
+    builder.send().await.map_err(|e| e.to_string())
+}
+
+fn response_headers(response: &reqwest::Response) -> Vec<(String, String)> {
+    response
+        .headers()
+        .iter()
+        .filter_map(|(key, value)| Some((key.to_string(), value.to_str().ok()?.to_string())))
+        .collect()
+}
+
+/// Fetch from upstream, revalidating `stale` if given, and store what can be stored
+async fn fetch_upstream(store: &Store, url: &str, request: &[(String, String)], stale: Option<CachedResponse>) -> Result<CachedResponse, String> {
+    let response = match send(url, request, stale.as_ref()).await {
+        Ok(response) => response,
+        Err(e) => match stale {
+            Some(stale) if stale.usable_on_error(now()) => return Ok(stale),
+            _ => return Err(e),
+        },
+    };
+    let status = response.status().as_u16();
+    let headers = response_headers(&response);
+    if let (304, Some(mut stale)) = (status, stale) {
+        stale.refresh(headers);
+        store.save(url, &stale).await;
+        return Ok(stale);
+    }
+    let status_text = response.status().canonical_reason().unwrap_or("").to_string();
+    let body = response.bytes().await.map_err(|e| e.to_string())?.to_vec();
+    let entry = CachedResponse::new(status, status_text, headers, body, request);
+    if entry.is_storable(request) {
+        store.save(url, &entry).await;
+    } else {
+        store.remove(url).await;
+    }
+    Ok(entry)
+}
+
+/// GET `url` through the cache, or straight from upstream while it is off
+pub(crate) async fn get(url: &str, request: &HashMap<String, String>) -> Result<CachedResponse, String> {
+    let request: Vec<(String, String)> = request.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
+    let Some(store) = current_store() else {
+        let response = send(url, &request, None).await?;
+        let status = response.status().as_u16();
+        let status_text = response.status().canonical_reason().unwrap_or("").to_string();
+        let headers = response_headers(&response);
+        let body = response.bytes().await.map_err(|e| e.to_string())?.to_vec();
+        return Ok(CachedResponse::new(status, status_text, headers, body, &request));
+    };
+    if cache_control(&request).no_store {
+        return fetch_upstream(&store, url, &request, None).await;
+    }
+    let stored = store.load(url).await.filter(|entry| entry.matches(&request));
+    let Some(stored) = stored else {
+        return fetch_upstream(&store, url, &request, None).await;
+    };
+    match stored.freshness(&request, now()) {
+        Freshness::Fresh => Ok(stored.served()),
+        Freshness::StaleWhileRevalidate => {
+            if REVALIDATING.lock().unwrap().insert(url.to_string()) {
+                let (url, request, stale) = (url.to_string(), request.clone(), stored.clone());
+                spawn(async move {
+                    let _ = fetch_upstream(&store, &url, &request, Some(stale)).await;
+                    REVALIDATING.lock().unwrap().remove(&url);
+                });
+            }
+            Ok(stored.served())
+        }
+        Freshness::Stale => fetch_upstream(&store, url, &request, Some(stored)).await.map(CachedResponse::served),
+    }
+}
+
+/// Drop the stored copy of `url` after a successful unsafe request to it (RFC 9111 §4.4)
+pub(crate) async fn invalidate(url: &str, status: u16) {
+    if !(200..400).contains(&status) {
+        return;
+    }
+    if let Some(store) = current_store() {
+        store.remove(url).await;
+    }
+}
+
+// ============================================================================
+// perry/http-cache
+// ============================================================================
+
+unsafe fn key(name: &str) -> *mut StringHeader {
+    js_string_from_bytes(name.as_ptr(), name.len() as u32)
+}
+
+unsafe fn throw_error(message: &str) -> ! {
+    perry_runtime::exception::js_throw(error_value(message))
+}
+
+unsafe fn string_field(obj: *const ObjectHeader, name: &str) -> Option<String> {
+    let value = js_object_get_field_by_name(obj, key(name));
+    if !value.is_string() {
+        return None;
+    }
+    let ptr = js_get_string_pointer_unified(f64::from_bits(value.bits())) as *const StringHeader;
+    if ptr.is_null() {
+        return None;
+    }
+    let len = (*ptr).length as usize;
+    let data = (ptr as *const u8).add(std::mem::size_of::<StringHeader>());
+    Some(String::from_utf8_lossy(std::slice::from_raw_parts(data, len)).into_owned())
+}
+
+/// Build the store `{ store, path, url, maxEntries }` asks for
+unsafe fn open_store(options: f64) -> Result<Store, String> {
+    let value = JSValue::from_bits(options.to_bits());
+    let obj = if value.is_pointer() { value.as_pointer::<ObjectHeader>() } else { std::ptr::null() };
+    let field = |name: &str| if obj.is_null() { None } else { string_field(obj, name) };
+    match field("store").as_deref().unwrap_or("memory") {
+        "memory" => {
+            let max_entries = if obj.is_null() {
+                JSValue::undefined()
+            } else {
+                JSValue::from_bits(js_object_get_field_by_name(obj, key("maxEntries")).bits())
+            };
+            let max_entries = if max_entries.is_number() && max_entries.as_number() >= 1.0 {
+                max_entries.as_number() as usize
+            } else {
+                DEFAULT_MAX_ENTRIES
+            };
+            Ok(Store::Memory(Mutex::new(MemoryStore { entries: HashMap::new(), order: VecDeque::new(), max_entries })))
+        }
+        "disk" => {
+            let path = field("path").ok_or("the disk store needs a `path`")?;
+            permissions::check(Capability::Fs, &path);
+            std::fs::create_dir_all(&path).map_err(|e| format!("cannot create {}: {}", path, e))?;
+            Ok(Store::Disk(PathBuf::from(path)))
+        }
+        #[cfg(feature = "database-redis")]
+        "redis" => {
+            let url = field("url").unwrap_or_else(|| "redis://127.0.0.1/".to_string());
+            let client = redis::Client::open(url.as_str()).map_err(|e| format!("invalid Redis URL {}: {}", url, e))?;
+            Ok(Store::Redis { client, connection: tokio::sync::OnceCell::new() })
+        }
+        #[cfg(not(feature = "database-redis"))]
+        "redis" => Err("the redis store needs perry-stdlib's `database-redis` feature".to_string()),
+        other => Err(format!("unknown store '{}' (expected memory, disk or redis)", other)),
+    }
+}
+
+/// `configure({ store, path, url, maxEntries }?)`: turn the cache on, replacing any
+/// store configured before
+#[no_mangle]
+pub unsafe extern "C" fn js_http_cache_configure(options: f64) -> f64 {
+    match open_store(options) {
+        Ok(store) => *CACHE.write().unwrap() = Some(Arc::new(store)),
+        Err(message) => throw_error(&format!("http-cache: {}", message)),
+    }
+    undefined()
+}
+
+/// `disable()`: send requests straight upstream again; stored entries are kept
+#[no_mangle]
+pub extern "C" fn js_http_cache_disable() -> f64 {
+    *CACHE.write().unwrap() = None;
+    undefined()
+}
+
+/// `clear()`: drop every stored response (in the background for Redis)
+#[no_mangle]
+pub extern "C" fn js_http_cache_clear() -> f64 {
+    let Some(store) = current_store() else { return undefined() };
+    #[cfg(feature = "database-redis")]
+    if matches!(*store, Store::Redis { .. }) {
+        spawn(async move { store.clear().await });
+        return undefined();
+    }
+    crate::common::async_bridge::block_on(store.clear());
+    undefined()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    fn entry(response: &[(&str, &str)], stored_at: u64) -> CachedResponse {
+        let mut entry = CachedResponse::new(200, "OK".to_string(), headers(response), b"body".to_vec(), &[]);
+        entry.stored_at = stored_at;
+        entry
+    }
+
+    #[test]
+    fn test_parse_cache_control() {
+        let cc = CacheControl::parse("public, Max-Age=60, stale-while-revalidate=\"30\", must-revalidate");
+        assert_eq!(cc.max_age, Some(60));
+        assert_eq!(cc.stale_while_revalidate, Some(30));
+        assert!(cc.must_revalidate && !cc.no_store && !cc.no_cache);
+        assert!(CacheControl::parse("no-cache=\"set-cookie\", no-store").no_cache);
+        assert_eq!(CacheControl::parse("max-age=soon").max_age, None);
+    }
+
+    #[test]
+    fn test_freshness() {
+        let now = 1_000_000;
+        let swr = entry(&[("cache-control", "max-age=60, stale-while-revalidate=30")], now - 10);
+        assert_eq!(swr.freshness(&[], now), Freshness::Fresh);
+        assert_eq!(swr.freshness(&[], now + 70), Freshness::StaleWhileRevalidate);
+        assert_eq!(swr.freshness(&[], now + 90), Freshness::Stale);
+        // The request can insist on a fresher or revalidated copy
+        assert_eq!(swr.freshness(&headers(&[("Cache-Control", "max-age=5")]), now), Freshness::Stale);
+        assert_eq!(swr.freshness(&headers(&[("Cache-Control", "no-cache")]), now), Freshness::Stale);
+
+        let upstream_age = entry(&[("cache-control", "max-age=60"), ("age", "55")], now - 10);
+        assert_eq!(upstream_age.freshness(&[], now), Freshness::Stale);
+
+        let expires = entry(&[("date", "Sun, 06 Nov 1994 08:49:37 GMT"), ("expires", "Sun, 06 Nov 1994 08:50:37 GMT")], now);
+        assert_eq!(expires.lifetime(), Some(60));
+        assert_eq!(entry(&[("expires", "0")], now).lifetime(), Some(0));
+        let heuristic = entry(&[("date", "Sun, 06 Nov 1994 08:49:37 GMT"), ("last-modified", "Sun, 06 Nov 1994 08:32:57 GMT")], now);
+        assert_eq!(heuristic.lifetime(), Some(100));
+
+        let on_error = entry(&[("cache-control", "max-age=10, stale-if-error=60")], now - 30);
+        assert!(on_error.usable_on_error(now));
+        assert!(!on_error.usable_on_error(now + 60));
+    }
+
+    #[test]
+    fn test_storable_and_vary() {
+        assert!(entry(&[("cache-control", "max-age=60")], 0).is_storable(&[]));
+        assert!(entry(&[("etag", "\"v1\"")], 0).is_storable(&[]));
+        assert!(!entry(&[], 0).is_storable(&[]));
+        assert!(!entry(&[("cache-control", "max-age=60, no-store")], 0).is_storable(&[]));
+        assert!(!entry(&[("cache-control", "max-age=60"), ("vary", "*")], 0).is_storable(&[]));
+        assert!(!entry(&[("cache-control", "max-age=60")], 0).is_storable(&headers(&[("cache-control", "no-store")])));
+
+        let request = headers(&[("Accept-Language", "de")]);
+        let varied = CachedResponse::new(200, "OK".to_string(), headers(&[("Vary", "accept-language")]), Vec::new(), &request);
+        assert!(varied.matches(&headers(&[("accept-language", "de")])));
+        assert!(!varied.matches(&headers(&[("accept-language", "fr")])));
+        assert!(!varied.matches(&[]));
+    }
+
+    #[test]
+    fn test_refresh_and_encoding() {
+        let mut stale = entry(&[("etag", "\"v1\""), ("cache-control", "max-age=1"), ("content-length", "4")], 0);
+        stale.refresh(headers(&[("Cache-Control", "max-age=60"), ("content-length", "0")]));
+        assert_eq!(header(&stale.headers, "cache-control"), Some("max-age=60"));
+        assert_eq!(header(&stale.headers, "content-length"), Some("4"));
+        assert_eq!(stale.freshness(&[], now()), Freshness::Fresh);
+
+        let mut binary = stale.clone();
+        binary.body = vec![0, b'\n', 255];
+        let decoded = CachedResponse::decode(&binary.encode()).unwrap();
+        assert_eq!(decoded.body, binary.body);
+        assert_eq!(decoded.headers, binary.headers);
+        assert_eq!(disk_file_name("https://example.com/"), disk_file_name("https://example.com/"));
+        assert_ne!(disk_file_name("https://example.com/a"), disk_file_name("https://example.com/b"));
+    }
+}
@@ -0,0 +1,569 @@
+//! Embedded key-value store (level compatible)
+//!
+//! Native implementation of the 'level' npm package's API on sled, an embedded ordered
+//! store: `new Level(location, { keyEncoding, valueEncoding }?)` opens (or creates) a
+//! database directory. Encodings are `"utf8"` (the default) and `"json"`; keys are ordered
+//! by their encoded bytes, as in LevelDB.
+//!
+//! Every operation returns a promise, the same as abstract-level:
+//! - `db.get(key)` gives the value or `undefined`, `db.getMany(keys)` an array of them
+//! - `db.put(key, value)`, `db.del(key)` and `db.batch([{ type: "put" | "del", key, value }])`,
+//!   the batch applied atomically; `db.batch()` with no operations returns a chained batch
+//!   (`put`, `del`, `clear`, `write`, `close`, `length`)
+//! - writes take `{ sync: true }` to flush to disk before resolving; otherwise sled flushes
+//!   in the background
+//! - `db.clear(range?)` deletes a range, `db.close()` flushes and closes, `db.open()` reopens
+//!
+//! `db.iterator(range?)`, `db.keys(range?)` and `db.values(range?)` walk keys in order (or
+//! in reverse with `reverse: true`) within `gt`/`gte`/`lt`/`lte`, up to `limit` entries:
+//! `await it.next()` gives `[key, value]` (a key or value for `keys()` and `values()`) or
+//! `undefined` once exhausted, `it.nextv(n)` up to `n` of them, `it.all()` the rest.
+//! Iterators read the live database, not a snapshot: entries written after an iterator was
+//! created are seen if they are still ahead of it.
+
+use std::ops::Bound;
+
+use perry_runtime::permissions::{self, Capability};
+use perry_runtime::promise::{js_promise_rejected, js_promise_resolved};
+use perry_runtime::{
+    js_array_alloc, js_array_get_jsvalue, js_array_is_array, js_array_length, js_array_push_f64,
+    js_get_string_pointer_unified, js_jsvalue_to_string, js_nanbox_get_pointer, js_object_get_field_by_name,
+    js_string_from_bytes, ArrayHeader, JSValue, ObjectHeader, StringHeader,
+};
+use sled::IVec;
+
+use crate::common::handle::{get_handle, get_handle_mut, register_handle, with_handle, Handle};
+use crate::concurrency::{boxed_pointer, error_value, undefined};
+use crate::framework::json::{js_json_parse, js_json_stringify};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Encoding {
+    Utf8,
+    Json,
+}
+
+impl Encoding {
+    fn parse(name: &str) -> Option<Encoding> {
+        match name {
+            "utf8" | "utf-8" => Some(Encoding::Utf8),
+            "json" => Some(Encoding::Json),
+            _ => None,
+        }
+    }
+}
+
+pub struct LevelDb {
+    location: String,
+    /// None once closed
+    db: Option<sled::Db>,
+    key_encoding: Encoding,
+    value_encoding: Encoding,
+}
+
+/// What an iterator yields
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Yields {
+    Entries,
+    Keys,
+    Values,
+}
+
+/// Key bounds of an iterator or `clear()`, with its direction and limit
+#[derive(Debug, Clone)]
+struct Range {
+    lower: Bound<Vec<u8>>,
+    upper: Bound<Vec<u8>>,
+    reverse: bool,
+    /// Entries left to yield
+    remaining: usize,
+}
+
+impl Default for Range {
+    fn default() -> Self {
+        Range { lower: Bound::Unbounded, upper: Bound::Unbounded, reverse: false, remaining: usize::MAX }
+    }
+}
+
+impl Range {
+    /// Whether no key can lie between the bounds (sled rejects such ranges)
+    fn is_empty(&self) -> bool {
+        match (&self.lower, &self.upper) {
+            (Bound::Included(lo), Bound::Included(hi)) => lo > hi,
+            (Bound::Included(lo) | Bound::Excluded(lo), Bound::Included(hi) | Bound::Excluded(hi)) => lo >= hi,
+            _ => false,
+        }
+    }
+
+    /// Up to `n` entries from the front of the range, which then starts after the last of them
+    fn take(&mut self, tree: &sled::Tree, n: usize) -> sled::Result<Vec<(IVec, IVec)>> {
+        let n = n.min(self.remaining);
+        if n == 0 || self.is_empty() {
+            return Ok(Vec::new());
+        }
+        let entries = tree.range::<Vec<u8>, _>((self.lower.clone(), self.upper.clone()));
+        let entries: Vec<_> = if self.reverse {
+            entries.rev().take(n).collect::<sled::Result<_>>()?
+        } else {
+            entries.take(n).collect::<sled::Result<_>>()?
+        };
+        if let Some((last, _)) = entries.last() {
+            let after = Bound::Excluded(last.to_vec());
+            if self.reverse {
+                self.upper = after;
+            } else {
+                self.lower = after;
+            }
+        }
+        self.remaining -= entries.len();
+        if entries.len() < n {
+            self.remaining = 0;
+        }
+        Ok(entries)
+    }
+}
+
+pub struct LevelIterator {
+    db: Handle,
+    range: Range,
+    yields: Yields,
+}
+
+enum BatchOp {
+    Put(Vec<u8>, Vec<u8>),
+    Del(Vec<u8>),
+}
+
+/// A chained batch from `db.batch()`
+pub struct LevelBatch {
+    db: Handle,
+    ops: Vec<BatchOp>,
+}
+
+impl LevelDb {
+    fn open(location: &str, key_encoding: Encoding, value_encoding: Encoding) -> sled::Result<LevelDb> {
+        Ok(LevelDb { location: location.to_string(), db: Some(sled::open(location)?), key_encoding, value_encoding })
+    }
+
+    fn tree(&self) -> Result<&sled::Db, String> {
+        self.db.as_ref().ok_or_else(|| "Database is not open".to_string())
+    }
+
+    fn apply(&self, ops: Vec<BatchOp>, sync: bool) -> Result<(), String> {
+        let tree = self.tree()?;
+        let mut batch = sled::Batch::default();
+        for op in ops {
+            match op {
+                BatchOp::Put(key, value) => batch.insert(key, value),
+                BatchOp::Del(key) => batch.remove(key),
+            }
+        }
+        tree.apply_batch(batch).map_err(|e| e.to_string())?;
+        if sync {
+            tree.flush().map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+
+    /// Delete the keys in `range`, returning how many there were
+    fn clear(&self, mut range: Range) -> Result<usize, String> {
+        let tree = self.tree()?;
+        let mut cleared = 0;
+        loop {
+            let entries = range.take(tree, 1024).map_err(|e| e.to_string())?;
+            if entries.is_empty() {
+                return Ok(cleared);
+            }
+            cleared += entries.len();
+            let mut batch = sled::Batch::default();
+            for (key, _) in entries {
+                batch.remove(key);
+            }
+            tree.apply_batch(batch).map_err(|e| e.to_string())?;
+        }
+    }
+}
+
+// ============================================================================
+// Value conversion
+// ============================================================================
+
+unsafe fn key(name: &str) -> *mut StringHeader {
+    js_string_from_bytes(name.as_ptr(), name.len() as u32)
+}
+
+unsafe fn string_bytes(ptr: *const StringHeader) -> Vec<u8> {
+    if ptr.is_null() {
+        return Vec::new();
+    }
+    let data = (ptr as *const u8).add(std::mem::size_of::<StringHeader>());
+    std::slice::from_raw_parts(data, (*ptr).length as usize).to_vec()
+}
+
+unsafe fn string_arg(value: f64) -> Option<String> {
+    let ptr = js_get_string_pointer_unified(value) as *const StringHeader;
+    (!ptr.is_null()).then(|| String::from_utf8_lossy(&string_bytes(ptr)).into_owned())
+}
+
+fn is_nullish(value: f64) -> bool {
+    let js = JSValue::from_bits(value.to_bits());
+    js.is_undefined() || js.is_null()
+}
+
+/// The stored bytes of a key or value
+unsafe fn encode(value: f64, encoding: Encoding) -> Vec<u8> {
+    match encoding {
+        Encoding::Utf8 if JSValue::from_bits(value.to_bits()).is_string() => {
+            string_bytes(js_get_string_pointer_unified(value) as *const StringHeader)
+        }
+        Encoding::Utf8 => string_bytes(js_jsvalue_to_string(value)),
+        Encoding::Json => string_bytes(js_json_stringify(value, 0)),
+    }
+}
+
+unsafe fn decode(bytes: &[u8], encoding: Encoding) -> f64 {
+    let text = js_string_from_bytes(bytes.as_ptr(), bytes.len() as u32);
+    match encoding {
+        Encoding::Utf8 => f64::from_bits(JSValue::string_ptr(text).bits()),
+        Encoding::Json => f64::from_bits(js_json_parse(text).bits()),
+    }
+}
+
+unsafe fn array_of(values: impl IntoIterator<Item = f64>) -> f64 {
+    let mut arr = js_array_alloc(0);
+    for value in values {
+        arr = js_array_push_f64(arr, value);
+    }
+    boxed_pointer(arr as i64)
+}
+
+unsafe fn array_arg(value: f64) -> Option<Vec<f64>> {
+    if js_array_is_array(value) == 0.0 {
+        return None;
+    }
+    let array = js_nanbox_get_pointer(value) as *const ArrayHeader;
+    Some((0..js_array_length(array)).map(|i| f64::from_bits(js_array_get_jsvalue(array, i))).collect())
+}
+
+/// A field of an options object, or undefined when `options` isn't one
+unsafe fn field(options: f64, name: &str) -> f64 {
+    let value = JSValue::from_bits(options.to_bits());
+    if !value.is_pointer() {
+        return undefined();
+    }
+    f64::from_bits(js_object_get_field_by_name(value.as_pointer::<ObjectHeader>(), key(name)).bits())
+}
+
+unsafe fn flag(options: f64, name: &str) -> bool {
+    let value = JSValue::from_bits(field(options, name).to_bits());
+    value.is_bool() && value.as_bool()
+}
+
+/// `gt`, `gte`, `lt`, `lte`, `reverse` and `limit` (-1 for none)
+unsafe fn read_range(options: f64, key_encoding: Encoding) -> Range {
+    let mut range = Range::default();
+    let bound = |name: &str| {
+        let value = field(options, name);
+        (!is_nullish(value)).then(|| encode(value, key_encoding))
+    };
+    if let Some(gte) = bound("gte") {
+        range.lower = Bound::Included(gte);
+    }
+    if let Some(gt) = bound("gt") {
+        range.lower = Bound::Excluded(gt);
+    }
+    if let Some(lte) = bound("lte") {
+        range.upper = Bound::Included(lte);
+    }
+    if let Some(lt) = bound("lt") {
+        range.upper = Bound::Excluded(lt);
+    }
+    range.reverse = flag(options, "reverse");
+    let limit = JSValue::from_bits(field(options, "limit").to_bits());
+    if limit.is_number() && limit.as_number() >= 0.0 {
+        range.remaining = limit.as_number() as usize;
+    } else if limit.is_int32() && limit.as_int32() >= 0 {
+        range.remaining = limit.as_int32() as usize;
+    }
+    range
+}
+
+unsafe fn throw_error(message: &str) -> ! {
+    perry_runtime::exception::js_throw(error_value(message))
+}
+
+fn resolved(value: f64) -> f64 {
+    boxed_pointer(js_promise_resolved(value) as i64)
+}
+
+unsafe fn rejected(message: &str) -> f64 {
+    boxed_pointer(js_promise_rejected(error_value(message)) as i64)
+}
+
+/// `new Level(location, { keyEncoding, valueEncoding }?)`
+#[no_mangle]
+pub unsafe extern "C" fn js_level_open(location: f64, options: f64) -> f64 {
+    let Some(location) = string_arg(location) else { throw_error("new Level() expects a location") };
+    permissions::check(Capability::Fs, &location);
+    let encoding = |name: &str| {
+        let Some(name) = string_arg(field(options, name)) else { return Encoding::Utf8 };
+        Encoding::parse(&name).unwrap_or_else(|| throw_error(&format!("Encoding '{}' is not supported", name)))
+    };
+    let (key_encoding, value_encoding) = (encoding("keyEncoding"), encoding("valueEncoding"));
+    match LevelDb::open(&location, key_encoding, value_encoding) {
+        Ok(db) => boxed_pointer(register_handle(db)),
+        Err(e) => throw_error(&format!("cannot open {}: {}", location, e)),
+    }
+}
+
+// ============================================================================
+// Handle dispatch
+// ============================================================================
+
+pub(crate) fn is_level_handle(handle: Handle) -> bool {
+    with_handle::<LevelDb, bool, _>(handle, |_| true).unwrap_or(false)
+        || with_handle::<LevelIterator, bool, _>(handle, |_| true).unwrap_or(false)
+        || with_handle::<LevelBatch, bool, _>(handle, |_| true).unwrap_or(false)
+}
+
+pub(crate) unsafe fn dispatch_level(handle: Handle, method: &str, args: &[f64]) -> f64 {
+    let arg = |i: usize| args.get(i).copied().unwrap_or_else(undefined);
+    if get_handle::<LevelIterator>(handle).is_some() {
+        return dispatch_iterator(handle, method, arg(0));
+    }
+    if get_handle::<LevelBatch>(handle).is_some() {
+        return dispatch_batch(handle, method, arg(0), arg(1));
+    }
+    let Some(db) = get_handle_mut::<LevelDb>(handle) else { return undefined() };
+    let (keys, values) = (db.key_encoding, db.value_encoding);
+    let result = match method {
+        "get" => {
+            if is_nullish(arg(0)) {
+                return rejected("Key cannot be null or undefined");
+            }
+            let key = encode(arg(0), keys);
+            db.tree()
+                .and_then(|tree| tree.get(key).map_err(|e| e.to_string()))
+                .map(|value| value.map_or_else(undefined, |value| decode(&value, values)))
+        }
+        "getMany" => {
+            let Some(requested) = array_arg(arg(0)) else { return rejected("getMany() expects an array of keys") };
+            db.tree().and_then(|tree| {
+                let found = requested
+                    .iter()
+                    .map(|key| tree.get(encode(*key, keys)).map(|value| value.map_or_else(undefined, |v| decode(&v, values))))
+                    .collect::<sled::Result<Vec<_>>>()
+                    .map_err(|e| e.to_string())?;
+                Ok(array_of(found))
+            })
+        }
+        "put" => {
+            if is_nullish(arg(0)) || is_nullish(arg(1)) {
+                return rejected("Key and value cannot be null or undefined");
+            }
+            let op = BatchOp::Put(encode(arg(0), keys), encode(arg(1), values));
+            db.apply(vec![op], flag(arg(2), "sync")).map(|_| undefined())
+        }
+        "del" => {
+            if is_nullish(arg(0)) {
+                return rejected("Key cannot be null or undefined");
+            }
+            db.apply(vec![BatchOp::Del(encode(arg(0), keys))], flag(arg(1), "sync")).map(|_| undefined())
+        }
+        "batch" if is_nullish(arg(0)) => {
+            if db.db.is_none() {
+                return rejected("Database is not open");
+            }
+            return boxed_pointer(register_handle(LevelBatch { db: handle, ops: Vec::new() }));
+        }
+        "batch" => {
+            let Some(operations) = array_arg(arg(0)) else { return rejected("batch() expects an array of operations") };
+            let mut ops = Vec::with_capacity(operations.len());
+            for operation in operations {
+                let key = field(operation, "key");
+                if is_nullish(key) {
+                    return rejected("Key cannot be null or undefined");
+                }
+                match string_arg(field(operation, "type")).as_deref() {
+                    Some("put") if !is_nullish(field(operation, "value")) => {
+                        ops.push(BatchOp::Put(encode(key, keys), encode(field(operation, "value"), values)))
+                    }
+                    Some("put") => return rejected("Value cannot be null or undefined"),
+                    Some("del") => ops.push(BatchOp::Del(encode(key, keys))),
+                    _ => return rejected("A batch operation's type must be 'put' or 'del'"),
+                }
+            }
+            db.apply(ops, flag(arg(1), "sync")).map(|_| undefined())
+        }
+        "iterator" | "keys" | "values" => {
+            if db.db.is_none() {
+                return rejected("Database is not open");
+            }
+            let yields = match method {
+                "keys" => Yields::Keys,
+                "values" => Yields::Values,
+                _ => Yields::Entries,
+            };
+            return boxed_pointer(register_handle(LevelIterator { db: handle, range: read_range(arg(0), keys), yields }));
+        }
+        "clear" => db.clear(read_range(arg(0), keys)).map(|_| undefined()),
+        "open" if db.db.is_some() => Ok(undefined()),
+        "open" => sled::open(&db.location).map(|tree| db.db = Some(tree)).map(|_| undefined()).map_err(|e| e.to_string()),
+        "close" => match db.db.take() {
+            Some(tree) => tree.flush().map(|_| undefined()).map_err(|e| e.to_string()),
+            None => Ok(undefined()),
+        },
+        _ => return undefined(),
+    };
+    match result {
+        Ok(value) => resolved(value),
+        Err(message) => rejected(&message),
+    }
+}
+
+/// `it.next()`, `it.nextv(n)`, `it.all()`, `it.close()`
+unsafe fn dispatch_iterator(handle: Handle, method: &str, size: f64) -> f64 {
+    let Some(it) = get_handle_mut::<LevelIterator>(handle) else { return undefined() };
+    let count = match method {
+        "next" => 1,
+        "nextv" => {
+            let size = JSValue::from_bits(size.to_bits());
+            if size.is_int32() {
+                size.as_int32().max(1) as usize
+            } else if size.is_number() {
+                size.as_number().max(1.0) as usize
+            } else {
+                return rejected("nextv() expects a size");
+            }
+        }
+        "all" => usize::MAX,
+        "close" => {
+            it.range.remaining = 0;
+            return resolved(undefined());
+        }
+        _ => return undefined(),
+    };
+    let Some(db) = get_handle::<LevelDb>(it.db) else { return rejected("Database is not open") };
+    let entries = match db.tree() {
+        Ok(tree) => it.range.take(tree, count).map_err(|e| e.to_string()),
+        Err(message) => Err(message),
+    };
+    let entries = match entries {
+        Ok(entries) => entries,
+        Err(message) => return rejected(&message),
+    };
+    let mut items = entries.iter().map(|(key, value)| match it.yields {
+        Yields::Keys => decode(key, db.key_encoding),
+        Yields::Values => decode(value, db.value_encoding),
+        Yields::Entries => array_of([decode(key, db.key_encoding), decode(value, db.value_encoding)]),
+    });
+    if method == "next" {
+        resolved(items.next().unwrap_or_else(undefined))
+    } else {
+        resolved(array_of(items.collect::<Vec<_>>()))
+    }
+}
+
+/// `batch.put(key, value)`, `batch.del(key)`, `batch.clear()` (chainable), `batch.write()`, `batch.close()`
+unsafe fn dispatch_batch(handle: Handle, method: &str, first: f64, second: f64) -> f64 {
+    let Some(batch) = get_handle_mut::<LevelBatch>(handle) else { return undefined() };
+    let Some(db) = get_handle::<LevelDb>(batch.db) else { return rejected("Database is not open") };
+    let this = boxed_pointer(handle);
+    match method {
+        "put" => {
+            if is_nullish(first) || is_nullish(second) {
+                throw_error("Key and value cannot be null or undefined");
+            }
+            batch.ops.push(BatchOp::Put(encode(first, db.key_encoding), encode(second, db.value_encoding)));
+            this
+        }
+        "del" => {
+            if is_nullish(first) {
+                throw_error("Key cannot be null or undefined");
+            }
+            batch.ops.push(BatchOp::Del(encode(first, db.key_encoding)));
+            this
+        }
+        "clear" => {
+            batch.ops.clear();
+            this
+        }
+        "write" => match db.apply(std::mem::take(&mut batch.ops), flag(first, "sync")) {
+            Ok(()) => resolved(undefined()),
+            Err(message) => rejected(&message),
+        },
+        "close" => {
+            batch.ops.clear();
+            resolved(undefined())
+        }
+        _ => undefined(),
+    }
+}
+
+/// `db.status`, `db.location`, `batch.length`
+pub(crate) unsafe fn level_property(handle: Handle, property: &str) -> f64 {
+    if let Some(batch) = get_handle::<LevelBatch>(handle) {
+        return if property == "length" { batch.ops.len() as f64 } else { undefined() };
+    }
+    let Some(db) = get_handle::<LevelDb>(handle) else { return undefined() };
+    let text = match property {
+        "status" if db.db.is_some() => "open",
+        "status" => "closed",
+        "location" => &db.location,
+        _ => return undefined(),
+    };
+    f64::from_bits(JSValue::string_ptr(key(text)).bits())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_db(name: &str) -> sled::Db {
+        sled::Config::new().temporary(true).path(std::env::temp_dir().join(format!("perry-level-{}", name))).open().unwrap()
+    }
+
+    fn keys(entries: Vec<(IVec, IVec)>) -> Vec<String> {
+        entries.iter().map(|(key, _)| String::from_utf8_lossy(key).into_owned()).collect()
+    }
+
+    #[test]
+    fn test_range_walks_in_steps() {
+        let db = temp_db("range");
+        for key in ["a", "b", "c", "d", "e"] {
+            db.insert(key, key).unwrap();
+        }
+        let mut range = Range { lower: Bound::Excluded(b"a".to_vec()), upper: Bound::Included(b"d".to_vec()), ..Range::default() };
+        assert_eq!(keys(range.take(&db, 2).unwrap()), vec!["b", "c"]);
+        // A key written ahead of the cursor is seen
+        db.insert("cc", "cc").unwrap();
+        assert_eq!(keys(range.take(&db, 5).unwrap()), vec!["cc", "d"]);
+        assert!(range.take(&db, 5).unwrap().is_empty());
+
+        let mut reverse = Range { reverse: true, remaining: 3, ..Range::default() };
+        assert_eq!(keys(reverse.take(&db, 2).unwrap()), vec!["e", "d"]);
+        assert_eq!(keys(reverse.take(&db, 2).unwrap()), vec!["cc"]);
+        assert_eq!(reverse.remaining, 0);
+
+        let empty = Range { lower: Bound::Included(b"d".to_vec()), upper: Bound::Excluded(b"d".to_vec()), ..Range::default() };
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn test_batches_and_clear() {
+        let dir = std::env::temp_dir().join(format!("perry-level-batch-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let mut level = LevelDb::open(dir.to_str().unwrap(), Encoding::Utf8, Encoding::Utf8).unwrap();
+        let ops = (0..5).map(|i| BatchOp::Put(format!("k{}", i).into_bytes(), b"v".to_vec())).chain([BatchOp::Del(b"k2".to_vec())]);
+        level.apply(ops.collect(), true).unwrap();
+        let tree = level.tree().unwrap().clone();
+        assert_eq!(tree.len(), 4);
+        let range = Range { lower: Bound::Included(b"k1".to_vec()), ..Range::default() };
+        assert_eq!(level.clear(range).unwrap(), 3);
+        assert_eq!(keys(Range::default().take(&tree, 10).unwrap()), vec!["k0"]);
+
+        level.db = None;
+        assert!(level.apply(vec![BatchOp::Del(b"k0".to_vec())], false).is_err());
+        drop(tree);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -10,6 +10,9 @@
 //! - `database` - All databases (postgres, mysql, sqlite, redis, mongodb)
 //! - `crypto` - Cryptographic functions
 //! - `compression` - zlib compression
+//! - `oidc` - OAuth 2.0 / OpenID Connect client (openid-client)
+//! - `wasm-plugins` - WebAssembly plugin host (perry/wasm)
+//! - `kv-store` - Embedded key-value store (level)
 //! - `full` - Everything (default)
 
 // Core modules - always available
@@ -25,6 +28,13 @@ pub mod commander;
 pub mod decimal;
 pub mod exponential_backoff;
 pub mod async_local_storage;
+pub mod di;
+pub mod concurrency;
+pub mod streams;
+pub mod temporal;
+pub mod eventlog;
+pub mod minisearch;
+pub mod geo;
 
 // Re-export core
 pub use common::*;
@@ -39,6 +49,13 @@ pub use commander::*;
 pub use decimal::*;
 pub use exponential_backoff::*;
 pub use async_local_storage::*;
+pub use di::*;
+pub use concurrency::*;
+pub use streams::*;
+pub use temporal::*;
+pub use eventlog::*;
+pub use minisearch::*;
+pub use geo::*;
 
 // === HTTP Server ===
 #[cfg(feature = "http-server")]
@@ -63,6 +80,11 @@ pub mod axios;
 #[cfg(feature = "http-client")]
 pub use axios::*;
 
+#[cfg(feature = "http-client")]
+pub mod http_cache;
+#[cfg(feature = "http-client")]
+pub use http_cache::*;
+
 // === WebSocket ===
 #[cfg(feature = "websocket")]
 pub mod ws;
@@ -178,3 +200,21 @@ pub use uuid::*;
 pub mod nanoid;
 #[cfg(feature = "ids")]
 pub use nanoid::*;
+
+// === OAuth 2.0 / OpenID Connect ===
+#[cfg(feature = "oidc")]
+pub mod oidc;
+#[cfg(feature = "oidc")]
+pub use oidc::*;
+
+// === WebAssembly Plugins ===
+#[cfg(feature = "wasm-plugins")]
+pub mod wasm;
+#[cfg(feature = "wasm-plugins")]
+pub use wasm::*;
+
+// === Embedded Key-Value Store ===
+#[cfg(feature = "kv-store")]
+pub mod level;
+#[cfg(feature = "kv-store")]
+pub use level::*;
@@ -0,0 +1,687 @@
+//! Full-text search (minisearch compatible)
+//!
+//! Native implementation of the 'minisearch' npm package: an in-memory inverted index over
+//! the `fields` of plain documents, ranked with BM25+ the same way minisearch ranks them.
+//!
+//! ```typescript
+//! import MiniSearch from 'minisearch';
+//! const index = new MiniSearch({ fields: ['title', 'text'], storeFields: ['title'] });
+//! index.addAll(docs);
+//! index.search('zen art', { prefix: true, fuzzy: 0.2, boost: { title: 2 } });
+//! // [{ id, score, terms: ['zen', 'art'], match: { zen: ['title'], ... }, title }, ...]
+//! ```
+//!
+//! Text is split on anything that isn't a letter or digit and lowercased. Each query term
+//! matches the indexed term equal to it and, with `prefix`, the terms it begins, and with
+//! `fuzzy`, terms within an edit distance (a number of edits, or below 1 a fraction of the
+//! term's length, at most 6). Prefix and fuzzy matches count for less than exact ones.
+//! `combineWith: "AND"` keeps only documents matching every query term; the default
+//! `"OR"` adds up their scores. Options given as `searchOptions` to the constructor apply
+//! to every search unless the search overrides them.
+//!
+//! Documents are added with `add`/`addAll`, removed by `remove(document)` or
+//! `discard(id)`, and replaced with `replace`; `has(id)`, `documentCount` and `termCount`
+//! describe the index. `toJSON()` (a string) and `toBuffer()` save it, and
+//! `MiniSearch.loadJSON(json, options)` / `MiniSearch.loadBuffer(buffer, options)` load it
+//! back without re-indexing; the saved fields win over those in `options`.
+
+use std::collections::{BTreeMap, HashMap};
+
+use perry_runtime::{
+    js_array_alloc, js_array_get_jsvalue, js_array_is_array, js_array_length, js_array_push_f64,
+    js_get_string_pointer_unified, js_jsvalue_to_string, js_nanbox_get_pointer, js_object_alloc,
+    js_object_get_field_by_name, js_object_set_field_by_name, js_string_from_bytes, ArrayHeader, BufferHeader,
+    JSValue, ObjectHeader, StringHeader,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as Json;
+
+use crate::common::handle::{get_handle, get_handle_mut, register_handle, with_handle, Handle};
+use crate::concurrency::{boxed_pointer, error_value, undefined};
+
+/// BM25+ term frequency saturation, length normalization and lower bound (minisearch's defaults)
+const BM25_K: f64 = 1.2;
+const BM25_B: f64 = 0.7;
+const BM25_D: f64 = 0.5;
+
+/// Weight of a prefix or fuzzy match relative to an exact one
+const PREFIX_WEIGHT: f64 = 0.375;
+const FUZZY_WEIGHT: f64 = 0.45;
+
+/// Most edits a fuzzy match may take
+const MAX_FUZZY: usize = 6;
+
+/// Fraction of a term's length `fuzzy: true` allows
+const DEFAULT_FUZZY: f64 = 0.2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+enum Combine {
+    #[default]
+    Or,
+    And,
+}
+
+#[derive(Debug, Clone, Default)]
+struct SearchOptions {
+    /// Fields to search; all of them when None
+    fields: Option<Vec<String>>,
+    prefix: bool,
+    /// Edits allowed, or below 1 the fraction of the term's length
+    fuzzy: Option<f64>,
+    boost: HashMap<String, f64>,
+    combine: Combine,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Document {
+    id: Json,
+    /// Terms in each field
+    lengths: Vec<u32>,
+    /// Distinct terms of each field, for removing the document
+    terms: Vec<Vec<String>>,
+    stored: BTreeMap<String, Json>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Hit {
+    id: Json,
+    score: f64,
+    /// Matched indexed terms, in the order first matched
+    terms: Vec<String>,
+    /// Fields each matched term was found in
+    matches: BTreeMap<String, Vec<String>>,
+    stored: BTreeMap<String, Json>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchIndex {
+    fields: Vec<String>,
+    store_fields: Vec<String>,
+    id_field: String,
+    /// By short id; None once removed
+    documents: Vec<Option<Document>>,
+    /// Short id of each document id, keyed by the id's JSON
+    ids: HashMap<String, u32>,
+    /// Term -> for each field, the documents containing it and how often
+    postings: BTreeMap<String, Vec<BTreeMap<u32, u32>>>,
+    /// Terms in each field over all documents
+    total_lengths: Vec<u64>,
+    #[serde(skip)]
+    defaults: SearchOptions,
+}
+
+fn tokenize(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric()).filter(|word| !word.is_empty()).map(|word| word.to_lowercase())
+}
+
+/// Levenshtein distance of `a` and `b` if it is at most `max`
+fn edit_distance(a: &[char], b: &[char], max: usize) -> Option<usize> {
+    if a.len().abs_diff(b.len()) > max {
+        return None;
+    }
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitute = previous[j] + usize::from(ca != cb);
+            current[j + 1] = substitute.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        if current.iter().all(|&d| d > max) {
+            return None;
+        }
+        previous = current;
+    }
+    previous.last().copied().filter(|&d| d <= max)
+}
+
+fn bm25(frequency: u32, matching: usize, total: usize, length: u32, average_length: f64) -> f64 {
+    let idf = (1.0 + (total as f64 - matching as f64 + 0.5) / (matching as f64 + 0.5)).ln();
+    let frequency = frequency as f64;
+    let normalized = BM25_K * (1.0 - BM25_B + BM25_B * length as f64 / average_length.max(1.0));
+    idf * (BM25_D + frequency * (BM25_K + 1.0) / (frequency + normalized))
+}
+
+impl SearchIndex {
+    fn new(fields: Vec<String>, store_fields: Vec<String>, id_field: String) -> SearchIndex {
+        let field_count = fields.len();
+        SearchIndex {
+            fields,
+            store_fields,
+            id_field,
+            documents: Vec::new(),
+            ids: HashMap::new(),
+            postings: BTreeMap::new(),
+            total_lengths: vec![0; field_count],
+            defaults: SearchOptions::default(),
+        }
+    }
+
+    fn document_count(&self) -> usize {
+        self.ids.len()
+    }
+
+    fn has(&self, id: &Json) -> bool {
+        self.ids.contains_key(&id.to_string())
+    }
+
+    /// Index a document given its id, the text of each field and its stored fields
+    fn add(&mut self, id: Json, texts: &[Option<String>], stored: BTreeMap<String, Json>) -> Result<(), String> {
+        if self.has(&id) {
+            return Err(format!("MiniSearch: duplicate ID {}", id));
+        }
+        let short_id = self.documents.len() as u32;
+        let mut lengths = vec![0; self.fields.len()];
+        let mut terms = vec![Vec::new(); self.fields.len()];
+        for (field, text) in texts.iter().enumerate() {
+            let Some(text) = text else { continue };
+            let mut counts: BTreeMap<String, u32> = BTreeMap::new();
+            for term in tokenize(text) {
+                *counts.entry(term).or_default() += 1;
+                lengths[field] += 1;
+            }
+            self.total_lengths[field] += lengths[field] as u64;
+            for (term, count) in counts {
+                let postings = self.postings.entry(term.clone()).or_insert_with(|| vec![BTreeMap::new(); texts.len()]);
+                postings[field].insert(short_id, count);
+                terms[field].push(term);
+            }
+        }
+        self.ids.insert(id.to_string(), short_id);
+        self.documents.push(Some(Document { id, lengths, terms, stored }));
+        Ok(())
+    }
+
+    /// Remove the document with `id`; false if there is none
+    fn discard(&mut self, id: &Json) -> bool {
+        let Some(short_id) = self.ids.remove(&id.to_string()) else { return false };
+        let Some(document) = self.documents[short_id as usize].take() else { return false };
+        for (field, terms) in document.terms.iter().enumerate() {
+            self.total_lengths[field] -= document.lengths[field] as u64;
+            for term in terms {
+                let Some(postings) = self.postings.get_mut(term) else { continue };
+                postings[field].remove(&short_id);
+                if postings.iter().all(|docs| docs.is_empty()) {
+                    self.postings.remove(term);
+                }
+            }
+        }
+        true
+    }
+
+    fn clear(&mut self) {
+        *self = SearchIndex { defaults: self.defaults.clone(), ..SearchIndex::new(self.fields.clone(), self.store_fields.clone(), self.id_field.clone()) };
+    }
+
+    /// Indexed terms a query term matches, with the weight of each match
+    fn expand(&self, query: &str, options: &SearchOptions) -> Vec<(String, f64)> {
+        let mut matches = Vec::new();
+        if self.postings.contains_key(query) {
+            matches.push((query.to_string(), 1.0));
+        }
+        let query_len = query.chars().count() as f64;
+        if options.prefix {
+            for (term, _) in self.postings.range(query.to_string()..).take_while(|(term, _)| term.starts_with(query)) {
+                if term != query {
+                    matches.push((term.clone(), PREFIX_WEIGHT * query_len / term.chars().count() as f64));
+                }
+            }
+        }
+        if let Some(fuzzy) = options.fuzzy {
+            let max = if fuzzy < 1.0 { (query_len * fuzzy).round() as usize } else { fuzzy as usize }.min(MAX_FUZZY);
+            if max > 0 {
+                let query_chars: Vec<char> = query.chars().collect();
+                for term in self.postings.keys() {
+                    if matches.iter().any(|(matched, _)| matched == term) {
+                        continue;
+                    }
+                    let term_chars: Vec<char> = term.chars().collect();
+                    if let Some(distance) = edit_distance(&query_chars, &term_chars, max).filter(|&d| d > 0) {
+                        matches.push((term.clone(), FUZZY_WEIGHT * query_len / (query_len + distance as f64)));
+                    }
+                }
+            }
+        }
+        matches
+    }
+
+    fn search(&self, query: &str, options: &SearchOptions) -> Vec<Hit> {
+        let total = self.document_count();
+        let fields: Vec<usize> = match &options.fields {
+            Some(names) => names.iter().filter_map(|name| self.fields.iter().position(|field| field == name)).collect(),
+            None => (0..self.fields.len()).collect(),
+        };
+        let mut combined: Option<BTreeMap<u32, Hit>> = None;
+        for query_term in tokenize(query) {
+            let mut hits: BTreeMap<u32, Hit> = BTreeMap::new();
+            for (term, weight) in self.expand(&query_term, options) {
+                let postings = &self.postings[&term];
+                for &field in &fields {
+                    let field_name = &self.fields[field];
+                    let boost = options.boost.get(field_name).copied().unwrap_or(1.0);
+                    let average = self.total_lengths[field] as f64 / total.max(1) as f64;
+                    for (&short_id, &frequency) in &postings[field] {
+                        let Some(document) = &self.documents[short_id as usize] else { continue };
+                        let score = weight
+                            * boost
+                            * bm25(frequency, postings[field].len(), total, document.lengths[field], average);
+                        let hit = hits.entry(short_id).or_insert_with(|| Hit {
+                            id: document.id.clone(),
+                            score: 0.0,
+                            terms: Vec::new(),
+                            matches: BTreeMap::new(),
+                            stored: document.stored.clone(),
+                        });
+                        hit.score += score;
+                        if !hit.terms.contains(&term) {
+                            hit.terms.push(term.clone());
+                        }
+                        let found_in = hit.matches.entry(term.clone()).or_default();
+                        if !found_in.contains(field_name) {
+                            found_in.push(field_name.clone());
+                        }
+                    }
+                }
+            }
+            combined = Some(match combined {
+                None => hits,
+                Some(mut so_far) => match options.combine {
+                    Combine::Or => {
+                        for (short_id, hit) in hits {
+                            match so_far.get_mut(&short_id) {
+                                Some(existing) => existing.merge(hit),
+                                None => {
+                                    so_far.insert(short_id, hit);
+                                }
+                            }
+                        }
+                        so_far
+                    }
+                    Combine::And => {
+                        let mut both = BTreeMap::new();
+                        for (short_id, hit) in hits {
+                            if let Some(mut existing) = so_far.remove(&short_id) {
+                                existing.merge(hit);
+                                both.insert(short_id, existing);
+                            }
+                        }
+                        both
+                    }
+                },
+            });
+        }
+        let mut results: Vec<Hit> = combined.unwrap_or_default().into_values().collect();
+        results.sort_by(|a, b| b.score.total_cmp(&a.score));
+        results
+    }
+}
+
+impl Hit {
+    fn merge(&mut self, other: Hit) {
+        self.score += other.score;
+        for term in other.terms {
+            if !self.terms.contains(&term) {
+                self.terms.push(term);
+            }
+        }
+        for (term, fields) in other.matches {
+            let found_in = self.matches.entry(term).or_default();
+            for field in fields {
+                if !found_in.contains(&field) {
+                    found_in.push(field);
+                }
+            }
+        }
+    }
+}
+
+// ============================================================================
+// Value conversion
+// ============================================================================
+
+unsafe fn key(name: &str) -> *mut StringHeader {
+    js_string_from_bytes(name.as_ptr(), name.len() as u32)
+}
+
+fn string_value(text: &str) -> f64 {
+    unsafe { f64::from_bits(JSValue::string_ptr(key(text)).bits()) }
+}
+
+unsafe fn throw_error(message: &str) -> ! {
+    perry_runtime::exception::js_throw(error_value(message))
+}
+
+unsafe fn string_of(ptr: *const StringHeader) -> String {
+    if ptr.is_null() {
+        return String::new();
+    }
+    let data = (ptr as *const u8).add(std::mem::size_of::<StringHeader>());
+    String::from_utf8_lossy(std::slice::from_raw_parts(data, (*ptr).length as usize)).into_owned()
+}
+
+unsafe fn string_arg(value: f64) -> Option<String> {
+    JSValue::from_bits(value.to_bits()).is_string().then(|| string_of(js_get_string_pointer_unified(value) as *const StringHeader))
+}
+
+unsafe fn field(object: f64, name: &str) -> f64 {
+    let value = JSValue::from_bits(object.to_bits());
+    if !value.is_pointer() {
+        return undefined();
+    }
+    f64::from_bits(js_object_get_field_by_name(value.as_pointer::<ObjectHeader>(), key(name)).bits())
+}
+
+unsafe fn array_arg(value: f64) -> Option<Vec<f64>> {
+    if js_array_is_array(value) == 0.0 {
+        return None;
+    }
+    let array = js_nanbox_get_pointer(value) as *const ArrayHeader;
+    Some((0..js_array_length(array)).map(|i| f64::from_bits(js_array_get_jsvalue(array, i))).collect())
+}
+
+unsafe fn strings_arg(value: f64) -> Option<Vec<String>> {
+    array_arg(value).map(|items| items.into_iter().filter_map(|item| string_arg(item)).collect())
+}
+
+/// A document id or stored field as JSON; objects and arrays are stored as their string form
+unsafe fn to_json(value: f64) -> Json {
+    let js = JSValue::from_bits(value.to_bits());
+    if js.is_undefined() || js.is_null() {
+        Json::Null
+    } else if js.is_bool() {
+        Json::Bool(js.as_bool())
+    } else if js.is_int32() {
+        Json::from(js.as_int32())
+    } else if js.is_number() {
+        serde_json::Number::from_f64(value).map_or(Json::Null, Json::Number)
+    } else if js.is_string() {
+        Json::String(string_of(js.as_string_ptr()))
+    } else {
+        Json::String(string_of(js_jsvalue_to_string(value)))
+    }
+}
+
+fn from_json(value: &Json) -> f64 {
+    match value {
+        Json::Bool(b) => f64::from_bits(JSValue::bool(*b).bits()),
+        Json::Number(n) => n.as_f64().unwrap_or(f64::NAN),
+        Json::String(s) => string_value(s),
+        _ => f64::from_bits(JSValue::null().bits()),
+    }
+}
+
+unsafe fn array_of(values: impl IntoIterator<Item = f64>) -> f64 {
+    let mut arr = js_array_alloc(0);
+    for value in values {
+        arr = js_array_push_f64(arr, value);
+    }
+    boxed_pointer(arr as i64)
+}
+
+/// `fields`, `prefix`, `fuzzy`, `boost` and `combineWith` of search options, over `base`
+unsafe fn read_search_options(options: f64, base: &SearchOptions, index_fields: &[String]) -> SearchOptions {
+    let mut result = base.clone();
+    if !JSValue::from_bits(options.to_bits()).is_pointer() {
+        return result;
+    }
+    if let Some(fields) = strings_arg(field(options, "fields")) {
+        result.fields = Some(fields);
+    }
+    let prefix = JSValue::from_bits(field(options, "prefix").to_bits());
+    if prefix.is_bool() {
+        result.prefix = prefix.as_bool();
+    }
+    let fuzzy = JSValue::from_bits(field(options, "fuzzy").to_bits());
+    if fuzzy.is_bool() {
+        result.fuzzy = fuzzy.as_bool().then_some(DEFAULT_FUZZY);
+    } else if fuzzy.is_int32() {
+        result.fuzzy = Some(fuzzy.as_int32() as f64).filter(|f| *f > 0.0);
+    } else if fuzzy.is_number() {
+        result.fuzzy = Some(fuzzy.as_number()).filter(|f| *f > 0.0);
+    }
+    let boost = field(options, "boost");
+    for name in index_fields {
+        let factor = JSValue::from_bits(field(boost, name).to_bits());
+        if factor.is_int32() {
+            result.boost.insert(name.clone(), factor.as_int32() as f64);
+        } else if factor.is_number() {
+            result.boost.insert(name.clone(), factor.as_number());
+        }
+    }
+    match string_arg(field(options, "combineWith")).map(|mode| mode.to_ascii_uppercase()).as_deref() {
+        Some("AND") => result.combine = Combine::And,
+        Some("OR") => result.combine = Combine::Or,
+        Some(other) => throw_error(&format!("MiniSearch: invalid combination operator: {}", other)),
+        None => {}
+    }
+    result
+}
+
+/// `new MiniSearch({ fields, storeFields?, idField?, searchOptions? })`
+#[no_mangle]
+pub unsafe extern "C" fn js_minisearch_new(options: f64) -> f64 {
+    let Some(fields) = strings_arg(field(options, "fields")) else {
+        throw_error("MiniSearch: option \"fields\" must be provided")
+    };
+    let store_fields = strings_arg(field(options, "storeFields")).unwrap_or_default();
+    let id_field = string_arg(field(options, "idField")).unwrap_or_else(|| "id".to_string());
+    let mut index = SearchIndex::new(fields, store_fields, id_field);
+    index.defaults = read_search_options(field(options, "searchOptions"), &SearchOptions::default(), &index.fields);
+    boxed_pointer(register_handle(index))
+}
+
+unsafe fn load(json: &[u8], options: f64) -> f64 {
+    let mut index: SearchIndex =
+        serde_json::from_slice(json).unwrap_or_else(|e| throw_error(&format!("MiniSearch: cannot load index: {}", e)));
+    index.defaults = read_search_options(field(options, "searchOptions"), &SearchOptions::default(), &index.fields);
+    boxed_pointer(register_handle(index))
+}
+
+/// `MiniSearch.loadJSON(json, options)`
+#[no_mangle]
+pub unsafe extern "C" fn js_minisearch_load_json(json: f64, options: f64) -> f64 {
+    let Some(json) = string_arg(json) else { throw_error("MiniSearch.loadJSON() expects a string") };
+    load(json.as_bytes(), options)
+}
+
+/// `MiniSearch.loadBuffer(buffer, options)`
+#[no_mangle]
+pub unsafe extern "C" fn js_minisearch_load_buffer(buffer: f64, options: f64) -> f64 {
+    if let Some(json) = string_arg(buffer) {
+        return load(json.as_bytes(), options);
+    }
+    let bits = buffer.to_bits();
+    let ptr = if bits >> 48 >= 0x7FF8 { bits & 0x0000_FFFF_FFFF_FFFF } else { bits } as *const BufferHeader;
+    if ptr.is_null() {
+        throw_error("MiniSearch.loadBuffer() expects a Buffer");
+    }
+    let data = (ptr as *const u8).add(std::mem::size_of::<BufferHeader>());
+    load(std::slice::from_raw_parts(data, (*ptr).length as usize), options)
+}
+
+// ============================================================================
+// Handle dispatch
+// ============================================================================
+
+pub(crate) fn is_search_index(handle: Handle) -> bool {
+    with_handle::<SearchIndex, bool, _>(handle, |_| true).unwrap_or(false)
+}
+
+/// The id and field texts of a document, as `add` takes them
+unsafe fn read_document(index: &SearchIndex, document: f64) -> (Json, Vec<Option<String>>, BTreeMap<String, Json>) {
+    let id = to_json(field(document, &index.id_field));
+    if id.is_null() {
+        throw_error(&format!("MiniSearch: document does not have ID field \"{}\"", index.id_field));
+    }
+    let texts = index
+        .fields
+        .iter()
+        .map(|name| {
+            let value = field(document, name);
+            let js = JSValue::from_bits(value.to_bits());
+            (!js.is_undefined() && !js.is_null()).then(|| match string_arg(value) {
+                Some(text) => text,
+                None => string_of(js_jsvalue_to_string(value)),
+            })
+        })
+        .collect();
+    let stored = index.store_fields.iter().map(|name| (name.clone(), to_json(field(document, name)))).collect();
+    (id, texts, stored)
+}
+
+unsafe fn hit_value(hit: Hit) -> f64 {
+    let obj = js_object_alloc(0, 4 + hit.stored.len() as u32);
+    js_object_set_field_by_name(obj, key("id"), from_json(&hit.id));
+    js_object_set_field_by_name(obj, key("score"), hit.score);
+    js_object_set_field_by_name(obj, key("terms"), array_of(hit.terms.iter().map(|term| string_value(term)).collect::<Vec<_>>()));
+    let matches = js_object_alloc(0, hit.matches.len() as u32);
+    for (term, fields) in &hit.matches {
+        js_object_set_field_by_name(matches, key(term), array_of(fields.iter().map(|f| string_value(f)).collect::<Vec<_>>()));
+    }
+    js_object_set_field_by_name(obj, key("match"), boxed_pointer(matches as i64));
+    for (name, value) in &hit.stored {
+        js_object_set_field_by_name(obj, key(name), from_json(value));
+    }
+    boxed_pointer(obj as i64)
+}
+
+/// `add`, `addAll`, `remove`, `discard`, `replace`, `has`, `removeAll`, `search`, `toJSON`, `toBuffer`
+pub(crate) unsafe fn dispatch_search_index(handle: Handle, method: &str, args: &[f64]) -> f64 {
+    let arg = |i: usize| args.get(i).copied().unwrap_or_else(undefined);
+    let Some(index) = get_handle_mut::<SearchIndex>(handle) else { return undefined() };
+    match method {
+        "add" => {
+            let (id, texts, stored) = read_document(index, arg(0));
+            index.add(id, &texts, stored).unwrap_or_else(|e| throw_error(&e));
+            undefined()
+        }
+        "addAll" => {
+            let Some(documents) = array_arg(arg(0)) else { throw_error("MiniSearch: addAll() expects an array") };
+            for document in documents {
+                let (id, texts, stored) = read_document(index, document);
+                index.add(id, &texts, stored).unwrap_or_else(|e| throw_error(&e));
+            }
+            undefined()
+        }
+        "remove" | "discard" => {
+            let id = if method == "remove" { to_json(field(arg(0), &index.id_field)) } else { to_json(arg(0)) };
+            if !index.discard(&id) {
+                throw_error(&format!("MiniSearch: cannot {} document with ID {}: it is not in the index", method, id));
+            }
+            undefined()
+        }
+        "replace" => {
+            let (id, texts, stored) = read_document(index, arg(0));
+            if !index.discard(&id) {
+                throw_error(&format!("MiniSearch: cannot replace document with ID {}: it is not in the index", id));
+            }
+            index.add(id, &texts, stored).unwrap_or_else(|e| throw_error(&e));
+            undefined()
+        }
+        "has" => f64::from_bits(JSValue::bool(index.has(&to_json(arg(0)))).bits()),
+        "removeAll" => {
+            index.clear();
+            undefined()
+        }
+        "search" => {
+            let query = string_arg(arg(0)).unwrap_or_default();
+            let options = read_search_options(arg(1), &index.defaults, &index.fields);
+            let hits = index.search(&query, &options);
+            array_of(hits.into_iter().map(|hit| hit_value(hit)).collect::<Vec<_>>())
+        }
+        "toJSON" => string_value(&serde_json::to_string(&*index).unwrap_or_default()),
+        "toBuffer" => {
+            let json = serde_json::to_vec(&*index).unwrap_or_default();
+            let buffer = perry_runtime::buffer::js_buffer_alloc_unsafe(json.len() as i32);
+            let data = (buffer as *mut u8).add(std::mem::size_of::<BufferHeader>());
+            std::ptr::copy_nonoverlapping(json.as_ptr(), data, json.len());
+            boxed_pointer(buffer as i64)
+        }
+        _ => undefined(),
+    }
+}
+
+/// `index.documentCount`, `index.termCount`
+pub(crate) fn search_index_property(handle: Handle, property: &str) -> f64 {
+    let Some(index) = get_handle::<SearchIndex>(handle) else { return undefined() };
+    match property {
+        "documentCount" => index.document_count() as f64,
+        "termCount" => index.postings.len() as f64,
+        _ => undefined(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index() -> SearchIndex {
+        let mut index = SearchIndex::new(vec!["title".into(), "text".into()], vec!["title".into()], "id".into());
+        let docs = [
+            (1, "Moby Dick", "Call me Ishmael. Some years ago, never mind how long precisely"),
+            (2, "Zen and the Art of Motorcycle Maintenance", "I can see by my watch, without taking my hand from the motorcycle"),
+            (3, "Neuromancer", "The sky above the port was the color of television, tuned to a dead channel"),
+            (4, "Zen and the Art of Archery", "At first sight it must seem intolerably degrading for Zen"),
+        ];
+        for (id, title, text) in docs {
+            let stored = BTreeMap::from([("title".to_string(), Json::from(title))]);
+            index.add(Json::from(id), &[Some(title.into()), Some(text.into())], stored).unwrap();
+        }
+        index
+    }
+
+    fn ids(hits: &[Hit]) -> Vec<i64> {
+        hits.iter().map(|hit| hit.id.as_i64().unwrap()).collect()
+    }
+
+    #[test]
+    fn test_ranking_and_combination() {
+        let index = index();
+        let options = SearchOptions::default();
+        // "zen" is in both titles and one text: the document with more of it ranks first
+        let hits = index.search("zen", &options);
+        assert_eq!(ids(&hits), vec![4, 2]);
+        assert_eq!(hits[0].matches["zen"], vec!["title", "text"]);
+        assert_eq!(hits[0].stored["title"], "Zen and the Art of Archery");
+
+        assert_eq!(ids(&index.search("zen motorcycle", &options)), vec![2, 4]);
+        let and = SearchOptions { combine: Combine::And, ..SearchOptions::default() };
+        assert_eq!(ids(&index.search("zen archery", &and)), vec![4]);
+
+        let boosted = SearchOptions { boost: HashMap::from([("text".to_string(), 10.0)]), ..SearchOptions::default() };
+        assert_eq!(ids(&index.search("zen motorcycle", &boosted)), vec![2, 4]);
+        let titles = SearchOptions { fields: Some(vec!["title".into()]), ..SearchOptions::default() };
+        assert!(index.search("ishmael", &titles).is_empty());
+    }
+
+    #[test]
+    fn test_prefix_and_fuzzy() {
+        let index = index();
+        assert!(index.search("moto", &SearchOptions::default()).is_empty());
+        let prefix = SearchOptions { prefix: true, ..SearchOptions::default() };
+        let hits = index.search("moto", &prefix);
+        assert_eq!((ids(&hits), hits[0].terms.clone()), (vec![2], vec!["motorcycle".to_string()]));
+
+        let fuzzy = SearchOptions { fuzzy: Some(0.2), ..SearchOptions::default() };
+        assert_eq!(ids(&index.search("neuromancr", &fuzzy)), vec![3]);
+        assert!(index.search("nuromncr", &fuzzy).is_empty());
+        assert_eq!(ids(&index.search("nuromncr", &SearchOptions { fuzzy: Some(3.0), ..SearchOptions::default() })), vec![3]);
+        assert_eq!(edit_distance(&['k', 'i', 't', 't', 'e', 'n'], &['s', 'i', 't', 't', 'i', 'n', 'g'], 3), Some(3));
+        assert_eq!(edit_distance(&['a'], &['a', 'b', 'c'], 1), None);
+    }
+
+    #[test]
+    fn test_remove_and_reload() {
+        let mut index = index();
+        assert!(index.discard(&Json::from(2)));
+        assert!(!index.discard(&Json::from(2)));
+        assert_eq!(index.document_count(), 3);
+        assert_eq!(ids(&index.search("zen", &SearchOptions::default())), vec![4]);
+        assert!(!index.postings.contains_key("motorcycle"));
+
+        let saved = serde_json::to_string(&index).unwrap();
+        let loaded: SearchIndex = serde_json::from_str(&saved).unwrap();
+        let options = SearchOptions { prefix: true, ..SearchOptions::default() };
+        assert_eq!(loaded.search("neuro dick", &options), index.search("neuro dick", &options));
+        assert!(loaded.has(&Json::from(3)) && !loaded.has(&Json::from(2)));
+    }
+}
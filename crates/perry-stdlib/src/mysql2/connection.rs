@@ -7,7 +7,7 @@ use sqlx::mysql::MySqlConnection;
 use sqlx::Connection;
 
 use crate::common::{register_handle, get_handle_mut, Handle};
-use super::pool::MysqlPoolConnectionHandle;
+use super::pool::{extract_params_from_jsvalue, js_mysql2_pool_connection_execute, MysqlPoolConnectionHandle, ParamValue};
 use super::result::RawQueryResult;
 use super::types::parse_mysql_config;
 
@@ -189,16 +189,80 @@ pub unsafe extern "C" fn js_mysql2_connection_query(
 
 /// connection.execute(sql, params) -> Promise<[rows, fields]>
 ///
-/// Executes a prepared statement with parameters.
+/// Executes a prepared statement with parameters. Also used for
+/// `connection.query(sql, values)`, which binds its values the same way.
 #[no_mangle]
 pub unsafe extern "C" fn js_mysql2_connection_execute(
     conn_handle: Handle,
     sql_ptr: *const u8,
-    _params: JSValue, // TODO: Parse parameters array
+    params: JSValue,
 ) -> *mut Promise {
-    // For now, just call query without params
-    // TODO: Implement parameter binding
-    js_mysql2_connection_query(conn_handle, sql_ptr)
+    // Pool connections bind through their own execute
+    if get_handle_mut::<MysqlConnectionHandle>(conn_handle).is_none() {
+        return js_mysql2_pool_connection_execute(conn_handle, sql_ptr, params);
+    }
+
+    let promise = js_promise_new();
+
+    // Extract the SQL string
+    let sql = if sql_ptr.is_null() {
+        String::new()
+    } else {
+        let header = sql_ptr as *const perry_runtime::StringHeader;
+        let len = (*header).length as usize;
+        let data_ptr = sql_ptr.add(std::mem::size_of::<perry_runtime::StringHeader>());
+        let bytes = std::slice::from_raw_parts(data_ptr, len);
+        String::from_utf8_lossy(bytes).to_string()
+    };
+
+    // Extract parameters from the JSValue array
+    let param_values = extract_params_from_jsvalue(params);
+
+    crate::common::spawn_for_promise_deferred(
+        promise as *mut u8,
+        async move {
+            use tokio::time::timeout;
+
+            if let Some(wrapper) = get_handle_mut::<MysqlConnectionHandle>(conn_handle) {
+                if let Some(conn) = wrapper.connection.as_mut() {
+                    // Build the query with parameter bindings
+                    let mut query = sqlx::query(&sql);
+
+                    for param in &param_values {
+                        query = match param {
+                            ParamValue::Null => query.bind(Option::<String>::None),
+                            ParamValue::String(s) => query.bind(s.clone()),
+                            ParamValue::Number(n) => query.bind(*n),
+                            ParamValue::Int(i) => query.bind(*i),
+                            ParamValue::Bool(b) => query.bind(*b),
+                        };
+                    }
+
+                    let query_future = query.fetch_all(conn);
+                    match timeout(Duration::from_secs(DEFAULT_QUERY_TIMEOUT_SECS), query_future).await {
+                        Ok(Ok(rows)) => {
+                            let raw_result = RawQueryResult::from_mysql_rows(rows);
+                            Ok(raw_result)
+                        }
+                        Ok(Err(e)) => Err(format!("Query failed: {}", e)),
+                        Err(_) => Err(format!(
+                            "Query timed out after {} seconds (MySQL server may be unavailable)",
+                            DEFAULT_QUERY_TIMEOUT_SECS
+                        )),
+                    }
+                } else {
+                    Err("Connection already closed".to_string())
+                }
+            } else {
+                Err("Invalid connection handle".to_string())
+            }
+        },
+        |raw_result: RawQueryResult| {
+            raw_result.to_jsvalue().bits()
+        },
+    );
+
+    promise
 }
 
 /// connection.beginTransaction() -> Promise<void>
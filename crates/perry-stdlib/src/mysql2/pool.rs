@@ -164,6 +164,7 @@ pub unsafe extern "C" fn js_mysql2_pool_query(
 /// pool.execute(sql, params) -> Promise<[rows, fields]>
 ///
 /// Executes a prepared statement with parameters using a connection from the pool.
+/// Also used for `pool.query(sql, values)`, which binds its values the same way.
 #[no_mangle]
 pub unsafe extern "C" fn js_mysql2_pool_execute(
     pool_handle: Handle,
@@ -236,7 +237,7 @@ pub unsafe extern "C" fn js_mysql2_pool_execute(
 
 /// Enum to hold different parameter value types
 #[derive(Clone, Debug)]
-enum ParamValue {
+pub(crate) enum ParamValue {
     Null,
     String(String),
     Number(f64),
@@ -245,7 +246,7 @@ enum ParamValue {
 }
 
 /// Extract parameter values from a JSValue array
-unsafe fn extract_params_from_jsvalue(params: JSValue) -> Vec<ParamValue> {
+pub(crate) unsafe fn extract_params_from_jsvalue(params: JSValue) -> Vec<ParamValue> {
     let mut result = Vec::new();
 
     let bits = params.bits();
@@ -420,6 +421,7 @@ pub unsafe extern "C" fn js_mysql2_pool_connection_query(
 /// poolConnection.execute(sql, params) -> Promise<[rows, fields]>
 ///
 /// Execute a prepared statement with parameters on the pool connection.
+/// Also used for `poolConnection.query(sql, values)`.
 #[no_mangle]
 pub unsafe extern "C" fn js_mysql2_pool_connection_execute(
     conn_handle: Handle,
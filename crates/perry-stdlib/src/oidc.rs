@@ -0,0 +1,629 @@
+//! OAuth 2.0 / OpenID Connect client (openid-client compatible)
+//!
+//! Native implementation of the functional API of the 'openid-client' npm package (v6):
+//! - `discovery(server, clientId, clientSecret?)` fetches `/.well-known/openid-configuration`
+//!   (or the given `.well-known` URL), checks its `issuer` and resolves to a configuration
+//!   whose `serverMetadata()` returns the document
+//! - `randomPKCECodeVerifier()`, `calculatePKCECodeChallenge(verifier)` (S256),
+//!   `randomState()` and `randomNonce()`
+//! - `buildAuthorizationUrl(config, parameters)` returns a URL for the authorization endpoint
+//!   with `client_id`, `response_type=code` (unless given) and the parameters
+//! - `authorizationCodeGrant(config, currentUrl, { pkceCodeVerifier, expectedState,
+//!   expectedNonce })` checks the callback's `state`, `iss` and `error` and redeems its
+//!   `code`; the redirect URI sent is `currentUrl` without its query
+//! - `clientCredentialsGrant(config, parameters?)` and `refreshTokenGrant(config,
+//!   refreshToken, parameters?)`
+//! - `fetchUserInfo(config, accessToken, expectedSubject)` checks the response's `sub`
+//!
+//! Grants resolve to a token set: its properties are the token endpoint response's fields
+//! (`access_token`, `refresh_token`, `id_token`, `expires_in`, ...), `claims()` returns the
+//! ID token's claims and `expiresIn()` the seconds left. An ID token is verified before the
+//! promise resolves: its signature against the server's JWKS (fetched once, and again when
+//! a token names an unknown key) or the client secret for HS* algorithms, and its `iss`,
+//! `aud`, `exp` (with 60 seconds of leeway) and, for the authorization code flow, `nonce`.
+//!
+//! The client authenticates with `client_secret_post` when it has a secret, and as a public
+//! client otherwise. Every request goes through the `net` permission policy. Failures
+//! reject with an Error; OAuth errors read `error: error_description`.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine as _;
+use jsonwebtoken::jwk::{Jwk, JwkSet};
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use perry_runtime::permissions::{self, Capability};
+use perry_runtime::{
+    js_array_get, js_array_length, js_get_string_pointer_unified, js_object_get_field, js_object_get_field_by_name,
+    js_object_keys, js_promise_new, js_string_from_bytes, JSValue, ObjectHeader, StringHeader,
+};
+use rand::RngCore;
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+
+use crate::common::async_bridge::{queue_deferred_resolution, spawn};
+use crate::common::handle::{register_handle, with_handle, Handle};
+use crate::concurrency::{boxed_pointer, error_value, undefined};
+use crate::fetch::json_value_to_jsvalue;
+
+/// Seconds of clock skew tolerated when checking an ID token's `exp`
+const CLOCK_TOLERANCE: u64 = 60;
+
+/// Claims every ID token must carry (OpenID Connect Core §2)
+const REQUIRED_CLAIMS: [&str; 5] = ["iss", "sub", "aud", "exp", "iat"];
+
+lazy_static::lazy_static! {
+    static ref CLIENT: reqwest::Client = reqwest::Client::new();
+}
+
+/// A discovered authorization server and the client registered with it
+#[derive(Clone)]
+pub(crate) struct Configuration {
+    metadata: Map<String, Value>,
+    client_id: String,
+    client_secret: Option<String>,
+    /// The server's signing keys, shared by clones and fetched on first use
+    jwks: Arc<Mutex<Option<JwkSet>>>,
+}
+
+/// A token endpoint response, with the claims of its validated ID token
+pub(crate) struct TokenSet {
+    response: Map<String, Value>,
+    claims: Option<Value>,
+    /// Seconds since the epoch when the response arrived
+    received_at: u64,
+}
+
+fn now() -> u64 {
+    std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+/// `len` random bytes, base64url-encoded
+fn random_base64url(len: usize) -> String {
+    let mut bytes = vec![0u8; len];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// The S256 PKCE challenge for a verifier (RFC 7636 §4.2)
+fn pkce_challenge(verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()))
+}
+
+/// The discovery document URL and the issuer it must name
+fn discovery_urls(server: &str) -> (String, String) {
+    match server.split_once("/.well-known/") {
+        Some((issuer, _)) => (server.to_string(), issuer.to_string()),
+        None => {
+            let issuer = server.trim_end_matches('/');
+            (format!("{}/.well-known/openid-configuration", issuer), issuer.to_string())
+        }
+    }
+}
+
+fn check_net(url: &str) -> Result<(), String> {
+    let address = permissions::url_address(url).unwrap_or_else(|| url.to_string());
+    match permissions::denial(Capability::Net, &address) {
+        Some(message) => Err(message),
+        None => Ok(()),
+    }
+}
+
+/// `error: error_description` of an OAuth error response, if it is one
+fn oauth_error(body: &Value) -> Option<String> {
+    let error = body.get("error")?.as_str()?;
+    Some(match body.get("error_description").and_then(Value::as_str) {
+        Some(description) => format!("{}: {}", error, description),
+        None => error.to_string(),
+    })
+}
+
+async fn get_json(url: &str, bearer: Option<&str>) -> Result<Value, String> {
+    check_net(url)?;
+    let mut request = CLIENT.get(url).header("Accept", "application/json");
+    if let Some(token) = bearer {
+        request = request.bearer_auth(token);
+    }
+    let response = request.send().await.map_err(|e| format!("{}: {}", url, e))?;
+    let status = response.status();
+    let body: Value = response.json().await.map_err(|e| format!("{}: invalid JSON response: {}", url, e))?;
+    if let Some(error) = oauth_error(&body) {
+        return Err(error);
+    }
+    if !status.is_success() {
+        return Err(format!("{} responded with {}", url, status));
+    }
+    Ok(body)
+}
+
+/// The authorization code in the redirect back from the authorization endpoint, and the
+/// redirect URI to redeem it with. Checks `state` and, if present, `iss` (RFC 9207).
+fn callback_code(current_url: &str, issuer: &str, expected_state: Option<&str>) -> Result<(String, String), String> {
+    let mut url = reqwest::Url::parse(current_url).map_err(|e| format!("invalid callback URL {}: {}", current_url, e))?;
+    let params: HashMap<String, String> = url.query_pairs().into_owned().collect();
+    let param = |name: &str| params.get(name).map(String::as_str);
+    if let Some(error) = param("error") {
+        return Err(match param("error_description") {
+            Some(description) => format!("{}: {}", error, description),
+            None => error.to_string(),
+        });
+    }
+    if param("state") != expected_state {
+        return Err("the callback's state does not match expectedState".to_string());
+    }
+    if param("iss").map(|iss| iss != issuer).unwrap_or(false) {
+        return Err(format!("the callback's iss does not match the issuer {}", issuer));
+    }
+    let code = param("code").ok_or("the callback URL has no code")?.to_string();
+    url.set_query(None);
+    url.set_fragment(None);
+    Ok((code, url.to_string()))
+}
+
+/// The key a token's header names, or the only key when it names none
+fn select_key<'a>(keys: &'a JwkSet, kid: Option<&str>) -> Option<&'a Jwk> {
+    match kid {
+        Some(kid) => keys.find(kid),
+        None if keys.keys.len() == 1 => keys.keys.first(),
+        None => None,
+    }
+}
+
+fn check_nonce(claims: &Value, expected: Option<&str>) -> Result<(), String> {
+    match (claims.get("nonce").and_then(Value::as_str), expected) {
+        (Some(nonce), Some(expected)) if nonce == expected => Ok(()),
+        (None, None) => Ok(()),
+        (_, Some(_)) => Err("the ID token's nonce does not match expectedNonce".to_string()),
+        (Some(_), None) => Err("the ID token has a nonce, but no expectedNonce was given".to_string()),
+    }
+}
+
+impl Configuration {
+    async fn discover(server: String, client_id: String, client_secret: Option<String>) -> Result<Configuration, String> {
+        let (url, expected_issuer) = discovery_urls(&server);
+        let Value::Object(metadata) = get_json(&url, None).await? else {
+            return Err(format!("{} did not return a JSON object", url));
+        };
+        let issuer = metadata.get("issuer").and_then(Value::as_str).ok_or_else(|| format!("{} names no issuer", url))?;
+        if issuer.trim_end_matches('/') != expected_issuer {
+            return Err(format!("{} names the issuer {}, expected {}", url, issuer, expected_issuer));
+        }
+        Ok(Configuration { metadata, client_id, client_secret, jwks: Arc::new(Mutex::new(None)) })
+    }
+
+    fn issuer(&self) -> &str {
+        self.metadata.get("issuer").and_then(Value::as_str).unwrap_or("")
+    }
+
+    fn endpoint(&self, name: &str) -> Result<String, String> {
+        self.metadata.get(name).and_then(Value::as_str).map(str::to_string).ok_or_else(|| format!("the server metadata has no {}", name))
+    }
+
+    fn authorization_url(&self, parameters: &[(String, String)]) -> Result<String, String> {
+        let endpoint = self.endpoint("authorization_endpoint")?;
+        let mut url = reqwest::Url::parse(&endpoint).map_err(|e| format!("invalid authorization_endpoint {}: {}", endpoint, e))?;
+        {
+            let mut query = url.query_pairs_mut();
+            query.append_pair("client_id", &self.client_id);
+            if !parameters.iter().any(|(name, _)| name == "response_type") {
+                query.append_pair("response_type", "code");
+            }
+            for (name, value) in parameters {
+                query.append_pair(name, value);
+            }
+        }
+        Ok(url.to_string())
+    }
+
+    /// POST a grant to the token endpoint, authenticating with `client_secret_post`
+    async fn grant(&self, mut params: Vec<(String, String)>) -> Result<Map<String, Value>, String> {
+        let endpoint = self.endpoint("token_endpoint")?;
+        check_net(&endpoint)?;
+        params.push(("client_id".to_string(), self.client_id.clone()));
+        if let Some(secret) = &self.client_secret {
+            params.push(("client_secret".to_string(), secret.clone()));
+        }
+        let response = CLIENT
+            .post(&endpoint)
+            .header("Accept", "application/json")
+            .form(&params)
+            .send()
+            .await
+            .map_err(|e| format!("{}: {}", endpoint, e))?;
+        let status = response.status();
+        let body: Value = response.json().await.map_err(|e| format!("{}: invalid JSON response: {}", endpoint, e))?;
+        if let Some(error) = oauth_error(&body) {
+            return Err(error);
+        }
+        match body {
+            Value::Object(response) if status.is_success() && response.contains_key("access_token") => Ok(response),
+            _ if !status.is_success() => Err(format!("{} responded with {}", endpoint, status)),
+            _ => Err("the token endpoint response has no access_token".to_string()),
+        }
+    }
+
+    /// The server's signing keys, fetched again when `refresh` is set
+    async fn signing_keys(&self, refresh: bool) -> Result<JwkSet, String> {
+        if !refresh {
+            if let Some(keys) = self.jwks.lock().unwrap().clone() {
+                return Ok(keys);
+            }
+        }
+        let uri = self.endpoint("jwks_uri")?;
+        let keys: JwkSet = serde_json::from_value(get_json(&uri, None).await?).map_err(|e| format!("invalid JWKS from {}: {}", uri, e))?;
+        *self.jwks.lock().unwrap() = Some(keys.clone());
+        Ok(keys)
+    }
+
+    /// Verify an ID token's signature, `iss`, `aud` and `exp`, returning its claims
+    async fn validate_id_token(&self, token: &str) -> Result<Value, String> {
+        let header = jsonwebtoken::decode_header(token).map_err(|e| format!("invalid ID token: {}", e))?;
+        let key = match header.alg {
+            Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512 => {
+                let secret = self.client_secret.as_deref().ok_or("the ID token is signed with a client secret, but none was configured")?;
+                DecodingKey::from_secret(secret.as_bytes())
+            }
+            _ => {
+                let mut keys = self.signing_keys(false).await?;
+                if select_key(&keys, header.kid.as_deref()).is_none() {
+                    // The server may have rotated its keys since they were fetched
+                    keys = self.signing_keys(true).await?;
+                }
+                let jwk = select_key(&keys, header.kid.as_deref()).ok_or("no key in the server's JWKS matches the ID token")?;
+                DecodingKey::from_jwk(jwk).map_err(|e| format!("unusable key in the server's JWKS: {}", e))?
+            }
+        };
+        let mut validation = Validation::new(header.alg);
+        validation.leeway = CLOCK_TOLERANCE;
+        validation.set_audience(&[&self.client_id]);
+        validation.set_issuer(&[self.issuer()]);
+        validation.set_required_spec_claims(&REQUIRED_CLAIMS);
+        jsonwebtoken::decode::<Value>(token, &key, &validation)
+            .map(|data| data.claims)
+            .map_err(|e| format!("ID token validation failed: {}", e))
+    }
+
+    /// Redeem a grant and validate the ID token in the response, if any
+    async fn token_set(&self, params: Vec<(String, String)>, expected_nonce: Option<Option<&str>>) -> Result<TokenSet, String> {
+        let response = self.grant(params).await?;
+        let claims = match response.get("id_token").and_then(Value::as_str) {
+            Some(id_token) => {
+                let claims = self.validate_id_token(id_token).await?;
+                if let Some(expected) = expected_nonce {
+                    check_nonce(&claims, expected)?;
+                }
+                Some(claims)
+            }
+            None => None,
+        };
+        Ok(TokenSet { response, claims, received_at: now() })
+    }
+}
+
+// ============================================================================
+// Argument conversion
+// ============================================================================
+
+unsafe fn key(name: &str) -> *mut StringHeader {
+    js_string_from_bytes(name.as_ptr(), name.len() as u32)
+}
+
+fn string_value(text: &str) -> f64 {
+    unsafe { f64::from_bits(JSValue::string_ptr(key(text)).bits()) }
+}
+
+fn json_value(value: &Value) -> f64 {
+    unsafe { f64::from_bits(json_value_to_jsvalue(value).bits()) }
+}
+
+unsafe fn string_arg(value: f64) -> Option<String> {
+    if !JSValue::from_bits(value.to_bits()).is_string() {
+        return None;
+    }
+    let ptr = js_get_string_pointer_unified(value) as *const StringHeader;
+    if ptr.is_null() {
+        return None;
+    }
+    let len = (*ptr).length as usize;
+    let data = (ptr as *const u8).add(std::mem::size_of::<StringHeader>());
+    Some(String::from_utf8_lossy(std::slice::from_raw_parts(data, len)).into_owned())
+}
+
+/// A URL given as a string or a URL object (its `href`)
+unsafe fn url_arg(value: f64) -> Option<String> {
+    let js = JSValue::from_bits(value.to_bits());
+    if js.is_pointer() {
+        let href = js_object_get_field_by_name(js.as_pointer::<ObjectHeader>(), key("href"));
+        return string_arg(f64::from_bits(href.bits()));
+    }
+    string_arg(value)
+}
+
+unsafe fn object_arg(value: f64) -> Option<*const ObjectHeader> {
+    let js = JSValue::from_bits(value.to_bits());
+    (js.is_pointer() && !js.as_pointer::<ObjectHeader>().is_null()).then(|| js.as_pointer::<ObjectHeader>())
+}
+
+unsafe fn string_field(obj: Option<*const ObjectHeader>, name: &str) -> Option<String> {
+    let value = js_object_get_field_by_name(obj?, key(name));
+    string_arg(f64::from_bits(value.bits()))
+}
+
+/// The string and number properties of a parameters object, as form or query pairs
+unsafe fn string_pairs(value: f64) -> Vec<(String, String)> {
+    let Some(obj) = object_arg(value) else { return Vec::new() };
+    let keys = js_object_keys(obj);
+    (0..js_array_length(keys))
+        .filter_map(|i| {
+            let name = string_arg(f64::from_bits(js_array_get(keys, i).bits()))?;
+            let field = js_object_get_field(obj, i);
+            let value = if field.is_number() {
+                let n = field.as_number();
+                if n.fract() == 0.0 { format!("{}", n as i64) } else { n.to_string() }
+            } else {
+                string_arg(f64::from_bits(field.bits()))?
+            };
+            Some((name, value))
+        })
+        .collect()
+}
+
+unsafe fn configuration_arg(value: f64) -> Option<Configuration> {
+    let js = JSValue::from_bits(value.to_bits());
+    if !js.is_pointer() {
+        return None;
+    }
+    with_handle::<Configuration, _, _>(js.as_pointer::<u8>() as Handle, Configuration::clone)
+}
+
+/// Run `future` on the async runtime and settle a promise with `convert(result)` on the
+/// main thread, or reject it with an Error
+unsafe fn spawn_promise<T, F, C>(future: F, convert: C) -> f64
+where
+    T: Send + 'static,
+    F: Future<Output = Result<T, String>> + Send + 'static,
+    C: FnOnce(T) -> f64 + Send + 'static,
+{
+    let promise = js_promise_new();
+    let promise_ptr = promise as usize;
+    spawn(async move {
+        match future.await {
+            Ok(value) => queue_deferred_resolution(promise_ptr, true, move || convert(value).to_bits()),
+            Err(message) => queue_deferred_resolution(promise_ptr, false, move || unsafe { error_value(&message) }.to_bits()),
+        }
+    });
+    boxed_pointer(promise as i64)
+}
+
+unsafe fn rejected(message: &str) -> f64 {
+    let message = message.to_string();
+    spawn_promise(async move { Err::<(), _>(message) }, |_| undefined())
+}
+
+fn token_set_value(tokens: TokenSet) -> f64 {
+    boxed_pointer(register_handle(tokens))
+}
+
+// ============================================================================
+// openid-client
+// ============================================================================
+
+/// `discovery(server, clientId, clientSecret?)` -> Promise<Configuration>
+#[no_mangle]
+pub unsafe extern "C" fn js_oidc_discovery(server: f64, client_id: f64, client_secret: f64) -> f64 {
+    let (Some(server), Some(client_id)) = (url_arg(server), string_arg(client_id)) else {
+        return rejected("discovery() expects a server URL and a client id");
+    };
+    let client_secret = string_arg(client_secret);
+    spawn_promise(Configuration::discover(server, client_id, client_secret), |config| boxed_pointer(register_handle(config)))
+}
+
+/// `randomPKCECodeVerifier()`: 32 random bytes, base64url-encoded (43 characters)
+#[no_mangle]
+pub extern "C" fn js_oidc_random_pkce_code_verifier() -> f64 {
+    string_value(&random_base64url(32))
+}
+
+/// `calculatePKCECodeChallenge(verifier)` -> Promise<string>
+#[no_mangle]
+pub unsafe extern "C" fn js_oidc_calculate_pkce_code_challenge(verifier: f64) -> f64 {
+    let Some(verifier) = string_arg(verifier) else {
+        return rejected("calculatePKCECodeChallenge() expects a code verifier");
+    };
+    spawn_promise(async move { Ok(pkce_challenge(&verifier)) }, |challenge| string_value(&challenge))
+}
+
+/// `randomState()`
+#[no_mangle]
+pub extern "C" fn js_oidc_random_state() -> f64 {
+    string_value(&random_base64url(32))
+}
+
+/// `randomNonce()`
+#[no_mangle]
+pub extern "C" fn js_oidc_random_nonce() -> f64 {
+    string_value(&random_base64url(32))
+}
+
+/// `buildAuthorizationUrl(config, parameters)` -> URL
+#[no_mangle]
+pub unsafe extern "C" fn js_oidc_build_authorization_url(config: f64, parameters: f64) -> f64 {
+    let Some(config) = configuration_arg(config) else {
+        perry_runtime::exception::js_throw(error_value("buildAuthorizationUrl() expects a configuration from discovery()"))
+    };
+    match config.authorization_url(&string_pairs(parameters)) {
+        Ok(url) => {
+            let url = perry_runtime::url::js_url_new(key(&url));
+            f64::from_bits(JSValue::object_ptr(url as *mut u8).bits())
+        }
+        Err(message) => perry_runtime::exception::js_throw(error_value(&message)),
+    }
+}
+
+/// `authorizationCodeGrant(config, currentUrl, { pkceCodeVerifier, expectedState,
+/// expectedNonce }, parameters?)` -> Promise<TokenSet>
+#[no_mangle]
+pub unsafe extern "C" fn js_oidc_authorization_code_grant(config: f64, current_url: f64, checks: f64, parameters: f64) -> f64 {
+    let (Some(config), Some(current_url)) = (configuration_arg(config), url_arg(current_url)) else {
+        return rejected("authorizationCodeGrant() expects a configuration and the callback URL");
+    };
+    let checks = object_arg(checks);
+    let verifier = string_field(checks, "pkceCodeVerifier");
+    let expected_state = string_field(checks, "expectedState");
+    let expected_nonce = string_field(checks, "expectedNonce");
+    let (code, redirect_uri) = match callback_code(&current_url, config.issuer(), expected_state.as_deref()) {
+        Ok(callback) => callback,
+        Err(message) => return rejected(&message),
+    };
+    let mut params = vec![
+        ("grant_type".to_string(), "authorization_code".to_string()),
+        ("code".to_string(), code),
+        ("redirect_uri".to_string(), redirect_uri),
+    ];
+    if let Some(verifier) = verifier {
+        params.push(("code_verifier".to_string(), verifier));
+    }
+    params.extend(string_pairs(parameters));
+    spawn_promise(
+        async move { config.token_set(params, Some(expected_nonce.as_deref())).await },
+        token_set_value,
+    )
+}
+
+/// `clientCredentialsGrant(config, parameters?)` -> Promise<TokenSet>
+#[no_mangle]
+pub unsafe extern "C" fn js_oidc_client_credentials_grant(config: f64, parameters: f64) -> f64 {
+    let Some(config) = configuration_arg(config) else {
+        return rejected("clientCredentialsGrant() expects a configuration from discovery()");
+    };
+    let mut params = vec![("grant_type".to_string(), "client_credentials".to_string())];
+    params.extend(string_pairs(parameters));
+    spawn_promise(async move { config.token_set(params, None).await }, token_set_value)
+}
+
+/// `refreshTokenGrant(config, refreshToken, parameters?)` -> Promise<TokenSet>
+#[no_mangle]
+pub unsafe extern "C" fn js_oidc_refresh_token_grant(config: f64, refresh_token: f64, parameters: f64) -> f64 {
+    let (Some(config), Some(refresh_token)) = (configuration_arg(config), string_arg(refresh_token)) else {
+        return rejected("refreshTokenGrant() expects a configuration and a refresh token");
+    };
+    let mut params = vec![
+        ("grant_type".to_string(), "refresh_token".to_string()),
+        ("refresh_token".to_string(), refresh_token),
+    ];
+    params.extend(string_pairs(parameters));
+    spawn_promise(async move { config.token_set(params, None).await }, token_set_value)
+}
+
+/// `fetchUserInfo(config, accessToken, expectedSubject)` -> Promise<object>; an undefined
+/// `expectedSubject` skips the `sub` check
+#[no_mangle]
+pub unsafe extern "C" fn js_oidc_fetch_user_info(config: f64, access_token: f64, expected_subject: f64) -> f64 {
+    let (Some(config), Some(access_token)) = (configuration_arg(config), string_arg(access_token)) else {
+        return rejected("fetchUserInfo() expects a configuration and an access token");
+    };
+    let expected_subject = string_arg(expected_subject);
+    spawn_promise(
+        async move {
+            let endpoint = config.endpoint("userinfo_endpoint")?;
+            let info = get_json(&endpoint, Some(&access_token)).await?;
+            let subject = info.get("sub").and_then(Value::as_str);
+            match expected_subject {
+                Some(expected) if subject != Some(expected.as_str()) => {
+                    Err("the UserInfo response's sub does not match expectedSubject".to_string())
+                }
+                _ => Ok(info),
+            }
+        },
+        |info| json_value(&info),
+    )
+}
+
+// ============================================================================
+// Handle dispatch
+// ============================================================================
+
+pub(crate) fn is_oidc_handle(handle: Handle) -> bool {
+    with_handle::<Configuration, bool, _>(handle, |_| true).unwrap_or(false)
+        || with_handle::<TokenSet, bool, _>(handle, |_| true).unwrap_or(false)
+}
+
+/// `config.serverMetadata()`, `tokens.claims()` and `tokens.expiresIn()`
+pub(crate) fn dispatch_oidc(handle: Handle, method: &str) -> f64 {
+    if let Some(metadata) = with_handle::<Configuration, _, _>(handle, |config| config.metadata.clone()) {
+        return match method {
+            "serverMetadata" => json_value(&Value::Object(metadata)),
+            _ => undefined(),
+        };
+    }
+    with_handle::<TokenSet, _, _>(handle, |tokens| match method {
+        "claims" => tokens.claims.as_ref().map(json_value).unwrap_or_else(undefined),
+        "expiresIn" => match tokens.response.get("expires_in").and_then(Value::as_u64) {
+            Some(expires_in) => (tokens.received_at + expires_in).saturating_sub(now()) as f64,
+            None => undefined(),
+        },
+        _ => undefined(),
+    })
+    .unwrap_or_else(undefined)
+}
+
+/// Token set properties: the fields of the token endpoint response
+pub(crate) fn oidc_property(handle: Handle, property: &str) -> f64 {
+    with_handle::<TokenSet, _, _>(handle, |tokens| tokens.response.get(property).map(json_value))
+        .flatten()
+        .unwrap_or_else(undefined)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pkce_and_discovery_urls() {
+        // RFC 7636 Appendix B
+        assert_eq!(pkce_challenge("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk"), "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM");
+        assert_eq!(random_base64url(32).len(), 43);
+        assert_eq!(
+            discovery_urls("https://id.example.com/"),
+            ("https://id.example.com/.well-known/openid-configuration".to_string(), "https://id.example.com".to_string())
+        );
+        assert_eq!(
+            discovery_urls("https://id.example.com/realms/a/.well-known/openid-configuration").1,
+            "https://id.example.com/realms/a"
+        );
+    }
+
+    #[test]
+    fn test_callback_code() {
+        let issuer = "https://id.example.com";
+        let (code, redirect) =
+            callback_code("https://app.example.com/cb?code=abc&state=xyz&iss=https%3A%2F%2Fid.example.com", issuer, Some("xyz")).unwrap();
+        assert_eq!((code.as_str(), redirect.as_str()), ("abc", "https://app.example.com/cb"));
+
+        assert!(callback_code("https://app.example.com/cb?code=abc&state=other", issuer, Some("xyz")).is_err());
+        assert!(callback_code("https://app.example.com/cb?code=abc&state=xyz&iss=https://evil.example", issuer, Some("xyz")).is_err());
+        let denied = callback_code("https://app.example.com/cb?error=access_denied&error_description=No", issuer, None);
+        assert_eq!(denied.unwrap_err(), "access_denied: No");
+    }
+
+    #[test]
+    fn test_authorization_url_and_nonce() {
+        let mut metadata = Map::new();
+        metadata.insert("issuer".to_string(), Value::from("https://id.example.com"));
+        metadata.insert("authorization_endpoint".to_string(), Value::from("https://id.example.com/authorize"));
+        let config = Configuration { metadata, client_id: "app".to_string(), client_secret: None, jwks: Arc::default() };
+        let url = config
+            .authorization_url(&[("scope".to_string(), "openid email".to_string()), ("code_challenge_method".to_string(), "S256".to_string())])
+            .unwrap();
+        assert_eq!(url, "https://id.example.com/authorize?client_id=app&response_type=code&scope=openid+email&code_challenge_method=S256");
+        assert!(config.endpoint("token_endpoint").is_err());
+
+        let claims = serde_json::json!({ "sub": "1", "nonce": "n-1" });
+        assert!(check_nonce(&claims, Some("n-1")).is_ok());
+        assert!(check_nonce(&claims, Some("n-2")).is_err());
+        assert!(check_nonce(&claims, None).is_err());
+        assert!(check_nonce(&serde_json::json!({ "sub": "1" }), None).is_ok());
+    }
+}
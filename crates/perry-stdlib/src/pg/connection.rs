@@ -5,6 +5,7 @@ use sqlx::postgres::PgConnection;
 use sqlx::{Connection, Row};
 
 use crate::common::{register_handle, Handle};
+use crate::mysql2::pool::{extract_params_from_jsvalue, ParamValue};
 use super::result::rows_to_pg_result;
 use super::types::{parse_pg_config, PgConfig};
 
@@ -139,14 +140,72 @@ pub unsafe extern "C" fn js_pg_client_query(
 
 /// client.query(sql, params) -> Promise<Result>
 ///
-/// Executes a parameterized query.
+/// Executes a parameterized query, binding `params` to `$1`, `$2`, ...
+/// A `sql` tagged template passes its interpolations here.
 #[no_mangle]
 pub unsafe extern "C" fn js_pg_client_query_params(
     client_handle: Handle,
     sql_ptr: *const u8,
-    _params: JSValue, // TODO: Parse parameters array
+    params: JSValue,
 ) -> *mut Promise {
-    // For now, just call query without params
-    // TODO: Implement parameter binding (PostgreSQL uses $1, $2, etc.)
-    js_pg_client_query(client_handle, sql_ptr)
+    let promise = js_promise_new();
+
+    // Extract the SQL string
+    let sql = if sql_ptr.is_null() {
+        String::new()
+    } else {
+        let header = sql_ptr as *const perry_runtime::StringHeader;
+        let len = (*header).length as usize;
+        let data_ptr = sql_ptr.add(std::mem::size_of::<perry_runtime::StringHeader>());
+        let bytes = std::slice::from_raw_parts(data_ptr, len);
+        String::from_utf8_lossy(bytes).to_string()
+    };
+
+    // Determine command type from SQL
+    let command = sql.trim().split_whitespace().next()
+        .unwrap_or("SELECT").to_uppercase();
+
+    // Extract parameters from the JSValue array
+    let param_values = extract_params_from_jsvalue(params);
+
+    crate::common::spawn_for_promise(promise as *mut u8, async move {
+        use crate::common::get_handle_mut;
+
+        if let Some(wrapper) = get_handle_mut::<PgConnectionHandle>(client_handle) {
+            if let Some(conn) = wrapper.connection.as_mut() {
+                // Build the query with parameter bindings
+                let mut query = sqlx::query(&sql);
+
+                for param in &param_values {
+                    query = match param {
+                        ParamValue::Null => query.bind(Option::<String>::None),
+                        ParamValue::String(s) => query.bind(s.clone()),
+                        ParamValue::Number(n) => query.bind(*n),
+                        ParamValue::Int(i) => query.bind(*i),
+                        ParamValue::Bool(b) => query.bind(*b),
+                    };
+                }
+
+                match query.fetch_all(conn).await {
+                    Ok(rows) => {
+                        let columns: Vec<_> = if !rows.is_empty() {
+                            rows[0].columns().to_vec()
+                        } else {
+                            Vec::new()
+                        };
+
+                        let result = rows_to_pg_result(rows, &columns, &command);
+                        Ok(result.bits())
+                    }
+                    Err(e) => Err(format!("Query failed: {}", e)),
+                }
+            } else {
+                Err("Connection already closed".to_string())
+            }
+        } else {
+            Err("Invalid client handle".to_string())
+        }
+    });
+
+    promise
 }
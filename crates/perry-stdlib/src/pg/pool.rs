@@ -5,6 +5,7 @@ use sqlx::postgres::{PgPool, PgPoolOptions};
 use sqlx::Row;
 
 use crate::common::{register_handle, Handle};
+use crate::mysql2::pool::{extract_params_from_jsvalue, ParamValue};
 use super::result::rows_to_pg_result;
 use super::types::parse_pg_config;
 
@@ -108,6 +109,77 @@ pub unsafe extern "C" fn js_pg_pool_query(
     promise
 }
 
+/// pool.query(sql, params) -> Promise<Result>
+///
+/// Executes a parameterized query on the pool, binding `params` to `$1`, `$2`, ...
+#[no_mangle]
+pub unsafe extern "C" fn js_pg_pool_query_params(
+    pool_handle: Handle,
+    sql_ptr: *const u8,
+    params: JSValue,
+) -> *mut Promise {
+    let promise = js_promise_new();
+
+    // Extract the SQL string
+    let sql = if sql_ptr.is_null() {
+        String::new()
+    } else {
+        let header = sql_ptr as *const perry_runtime::StringHeader;
+        let len = (*header).length as usize;
+        let data_ptr = sql_ptr.add(std::mem::size_of::<perry_runtime::StringHeader>());
+        let bytes = std::slice::from_raw_parts(data_ptr, len);
+        String::from_utf8_lossy(bytes).to_string()
+    };
+
+    // Determine command type from SQL
+    let command = sql.trim().split_whitespace().next()
+        .unwrap_or("SELECT").to_uppercase();
+
+    // Extract parameters from the JSValue array
+    let param_values = extract_params_from_jsvalue(params);
+
+    crate::common::spawn_for_promise(promise as *mut u8, async move {
+        use crate::common::get_handle;
+
+        if let Some(wrapper) = get_handle::<PgPoolHandle>(pool_handle) {
+            if let Some(pool) = wrapper.pool.as_ref() {
+                // Build the query with parameter bindings
+                let mut query = sqlx::query(&sql);
+
+                for param in &param_values {
+                    query = match param {
+                        ParamValue::Null => query.bind(Option::<String>::None),
+                        ParamValue::String(s) => query.bind(s.clone()),
+                        ParamValue::Number(n) => query.bind(*n),
+                        ParamValue::Int(i) => query.bind(*i),
+                        ParamValue::Bool(b) => query.bind(*b),
+                    };
+                }
+
+                match query.fetch_all(pool).await {
+                    Ok(rows) => {
+                        let columns: Vec<_> = if !rows.is_empty() {
+                            rows[0].columns().to_vec()
+                        } else {
+                            Vec::new()
+                        };
+
+                        let result = rows_to_pg_result(rows, &columns, &command);
+                        Ok(result.bits())
+                    }
+                    Err(e) => Err(format!("Query failed: {}", e)),
+                }
+            } else {
+                Err("Pool already closed".to_string())
+            }
+        } else {
+            Err("Invalid pool handle".to_string())
+        }
+    });
+
+    promise
+}
+
 /// pool.end() -> Promise<void>
 ///
 /// Closes all connections in the pool.
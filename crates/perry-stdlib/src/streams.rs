@@ -0,0 +1,536 @@
+//! Emitter-to-stream adapter and pipelines for `perry/streams`
+//!
+//! `fromEmitter(emitter, event?, options?)` listens for an EventEmitter's `event` (`"data"` by
+//! default), `end` and `error` events and buffers the values for an async iterator:
+//! `await stream.next()` gives `{ value, done }` like an async generator, `stream.return()`
+//! detaches it from the emitter and `stream.toArray()` collects every value up to `end`.
+//! Values buffered before an `error` are still delivered; the error rejects the `next()` after
+//! them.
+//!
+//! An emitter can't be paused, so `highWaterMark` (16 by default) is enforced by `policy`:
+//! `"drop"` ignores new values while the buffer is full, `"latest"` discards the oldest
+//! buffered value to make room, and `"block"` (the default) keeps every value but calls
+//! `options.pause()` once the buffer is full and `options.resume()` once it has drained to
+//! half, for sources that can stop producing.
+//!
+//! `pipeline(source, ...stages)` feeds each value of a stream, an EventEmitter or an array
+//! through the stages in order, awaiting every stage before pulling the next value. A stage
+//! returning `undefined` drops the value. The first error from the source or a stage stops the
+//! pipeline, detaches the source and rejects the pipeline with it.
+
+use std::collections::VecDeque;
+
+use perry_runtime::closure::{js_closure_set_arity, CLOSURE_MAGIC};
+use perry_runtime::promise::js_promise_resolved;
+use perry_runtime::{
+    js_array_alloc, js_array_get_jsvalue, js_array_is_array, js_array_length, js_array_push_f64, js_closure_call0,
+    js_closure_call1, js_get_string_pointer_unified, js_nanbox_get_pointer, js_object_alloc,
+    js_object_get_field_by_name, js_object_set_field_by_name, js_promise_new, js_promise_reject, js_promise_resolve,
+    js_string_from_bytes, ArrayHeader, ClosureHeader, JSValue, ObjectHeader, Promise, StringHeader,
+};
+
+use crate::common::handle::{get_handle_mut, register_handle, take_handle, with_handle, Handle};
+use crate::concurrency::{boxed_pointer, captures, error_value, handler, undefined, watch, SettleFn};
+use crate::events::{js_event_emitter_on, js_event_emitter_remove_listener, EventEmitterHandle};
+
+/// Handles are small integers; anything above is a heap pointer
+const HANDLE_LIMIT: i64 = 0x100000;
+
+const DEFAULT_HIGH_WATER_MARK: usize = 16;
+
+/// What a full buffer does with the next value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Policy {
+    /// Ignore it
+    Drop,
+    /// Discard the oldest buffered value
+    Latest,
+    /// Keep it, and ask the source to pause
+    Block,
+}
+
+impl Policy {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "drop" => Some(Policy::Drop),
+            "latest" => Some(Policy::Latest),
+            "block" => Some(Policy::Block),
+            _ => None,
+        }
+    }
+}
+
+/// Values waiting for `next()`, bounded by the high-water mark according to the policy
+struct Buffer {
+    values: VecDeque<f64>,
+    high_water_mark: usize,
+    policy: Policy,
+    /// Values lost to `drop` and `latest`
+    dropped: usize,
+    /// Whether a blocking buffer has asked its source to pause
+    paused: bool,
+}
+
+impl Buffer {
+    fn new(high_water_mark: usize, policy: Policy) -> Self {
+        Buffer { values: VecDeque::new(), high_water_mark: high_water_mark.max(1), policy, dropped: 0, paused: false }
+    }
+
+    /// Buffer `value`; true when the source should pause
+    fn push(&mut self, value: f64) -> bool {
+        if self.values.len() >= self.high_water_mark {
+            match self.policy {
+                Policy::Drop => {
+                    self.dropped += 1;
+                    return false;
+                }
+                Policy::Latest => {
+                    self.values.pop_front();
+                    self.dropped += 1;
+                }
+                Policy::Block => {}
+            }
+        }
+        self.values.push_back(value);
+        if self.policy == Policy::Block && !self.paused && self.values.len() >= self.high_water_mark {
+            self.paused = true;
+            return true;
+        }
+        false
+    }
+
+    /// The oldest value, and whether the source should resume
+    fn shift(&mut self) -> Option<(f64, bool)> {
+        let value = self.values.pop_front()?;
+        let resume = self.paused && self.values.len() <= self.high_water_mark / 2;
+        if resume {
+            self.paused = false;
+        }
+        Some((value, resume))
+    }
+}
+
+pub struct EmitterStream {
+    /// EventEmitter handle the listeners are registered on
+    emitter: Handle,
+    /// (event, listener closure) pairs, removed again when the stream ends or is closed
+    listeners: Vec<(String, i64)>,
+    buffer: Buffer,
+    /// Promises of `next()` calls waiting for a value, oldest first
+    waiters: VecDeque<i64>,
+    /// Reason of an `error` event, delivered once the buffer is empty
+    error: Option<f64>,
+    ended: bool,
+    /// `pause` and `resume` closures from the options, 0 when not given
+    pause: i64,
+    resume: i64,
+}
+
+struct StreamOptions {
+    high_water_mark: usize,
+    policy: Policy,
+    pause: i64,
+    resume: i64,
+}
+
+impl Default for StreamOptions {
+    fn default() -> Self {
+        StreamOptions { high_water_mark: DEFAULT_HIGH_WATER_MARK, policy: Policy::Block, pause: 0, resume: 0 }
+    }
+}
+
+pub struct Pipeline {
+    source: Source,
+    /// Stage closures, in order
+    stages: Vec<i64>,
+    /// Values that made it through every stage, kept for `toArray()`
+    collected: Option<Vec<f64>>,
+    /// Promise returned by `pipeline` / `toArray`
+    promise: i64,
+}
+
+enum Source {
+    Stream(Handle),
+    Values(VecDeque<f64>),
+}
+
+unsafe fn throw_error(message: &str) -> ! {
+    perry_runtime::exception::js_throw(error_value(message))
+}
+
+unsafe fn string_arg(value: f64) -> Option<String> {
+    let ptr = js_get_string_pointer_unified(value) as *const StringHeader;
+    if ptr.is_null() {
+        return None;
+    }
+    let len = (*ptr).length as usize;
+    let data = (ptr as *const u8).add(std::mem::size_of::<StringHeader>());
+    Some(String::from_utf8_lossy(std::slice::from_raw_parts(data, len)).into_owned())
+}
+
+unsafe fn key(name: &str) -> *mut StringHeader {
+    js_string_from_bytes(name.as_ptr(), name.len() as u32)
+}
+
+/// Pointer of a closure value, None for anything else
+unsafe fn closure_arg(value: f64) -> Option<i64> {
+    let ptr = js_nanbox_get_pointer(value);
+    (ptr >= HANDLE_LIMIT && (*(ptr as *const ClosureHeader)).type_tag == CLOSURE_MAGIC).then_some(ptr)
+}
+
+/// `{ value, done }`, NaN-boxed
+unsafe fn iter_result(value: f64, done: bool) -> f64 {
+    let obj = js_object_alloc(0, 2);
+    js_object_set_field_by_name(obj, key("value"), value);
+    js_object_set_field_by_name(obj, key("done"), f64::from_bits(JSValue::bool(done).bits()));
+    boxed_pointer(obj as i64)
+}
+
+unsafe fn read_options(options: f64) -> StreamOptions {
+    let mut result = StreamOptions::default();
+    let value = JSValue::from_bits(options.to_bits());
+    if !value.is_pointer() {
+        return result;
+    }
+    let obj = value.as_pointer::<ObjectHeader>();
+    let field = |name: &str| f64::from_bits(js_object_get_field_by_name(obj, key(name)).bits());
+    let high_water_mark = JSValue::from_bits(field("highWaterMark").to_bits());
+    if high_water_mark.is_number() && high_water_mark.as_number() >= 1.0 {
+        result.high_water_mark = high_water_mark.as_number() as usize;
+    }
+    if let Some(policy) = string_arg(field("policy")) {
+        result.policy = Policy::from_name(&policy)
+            .unwrap_or_else(|| throw_error(&format!("Unknown policy \"{}\", expected drop, latest or block", policy)));
+    }
+    result.pause = closure_arg(field("pause")).unwrap_or(0);
+    result.resume = closure_arg(field("resume")).unwrap_or(0);
+    result
+}
+
+// ============================================================================
+// Emitter streams
+// ============================================================================
+
+/// `fromEmitter(emitter, event = "data", { highWaterMark, policy, pause, resume }?)`
+#[no_mangle]
+pub unsafe extern "C" fn js_streams_from_emitter(emitter: f64, event: f64, options: f64) -> f64 {
+    let emitter = js_nanbox_get_pointer(emitter);
+    if with_handle::<EventEmitterHandle, bool, _>(emitter, |_| true) != Some(true) {
+        throw_error("fromEmitter() expects an EventEmitter");
+    }
+    let event = string_arg(event).unwrap_or_else(|| "data".to_string());
+    boxed_pointer(from_emitter(emitter, &event, read_options(options)))
+}
+
+unsafe fn from_emitter(emitter: Handle, event: &str, options: StreamOptions) -> Handle {
+    let handle = register_handle(EmitterStream {
+        emitter,
+        listeners: Vec::new(),
+        buffer: Buffer::new(options.high_water_mark, options.policy),
+        waiters: VecDeque::new(),
+        error: None,
+        ended: false,
+        pause: options.pause,
+        resume: options.resume,
+    });
+    let mut listeners = Vec::new();
+    for (name, listener) in [(event, stream_on_data as SettleFn), ("end", stream_on_end), ("error", stream_on_error)] {
+        let closure = handler(listener as *const u8, handle, 0);
+        js_closure_set_arity(closure, 1);
+        js_event_emitter_on(emitter, key(name), closure as i64);
+        listeners.push((name.to_string(), closure as i64));
+    }
+    if let Some(stream) = get_handle_mut::<EmitterStream>(handle) {
+        stream.listeners = listeners;
+    }
+    handle
+}
+
+unsafe extern "C" fn stream_on_data(closure: *const ClosureHeader, value: f64) -> f64 {
+    let Some(stream) = get_handle_mut::<EmitterStream>(captures(closure).0) else { return undefined() };
+    if stream.ended || stream.error.is_some() {
+        return undefined();
+    }
+    if let Some(waiter) = stream.waiters.pop_front() {
+        js_promise_resolve(waiter as *mut Promise, iter_result(value, false));
+    } else if stream.buffer.push(value) && stream.pause != 0 {
+        js_closure_call0(stream.pause as *const ClosureHeader);
+    }
+    undefined()
+}
+
+unsafe extern "C" fn stream_on_end(closure: *const ClosureHeader, _value: f64) -> f64 {
+    let handle = captures(closure).0;
+    if let Some(stream) = get_handle_mut::<EmitterStream>(handle) {
+        stream.ended = true;
+        // Waiters only queue up while the buffer is empty
+        for waiter in stream.waiters.drain(..) {
+            js_promise_resolve(waiter as *mut Promise, iter_result(undefined(), true));
+        }
+    }
+    stream_detach(handle);
+    undefined()
+}
+
+unsafe extern "C" fn stream_on_error(closure: *const ClosureHeader, reason: f64) -> f64 {
+    let handle = captures(closure).0;
+    if let Some(stream) = get_handle_mut::<EmitterStream>(handle) {
+        if stream.waiters.is_empty() {
+            stream.error = Some(reason);
+        } else {
+            stream.ended = true;
+            for waiter in stream.waiters.drain(..) {
+                js_promise_reject(waiter as *mut Promise, reason);
+            }
+        }
+    }
+    stream_detach(handle);
+    undefined()
+}
+
+/// Remove the stream's listeners from its emitter
+unsafe fn stream_detach(handle: Handle) {
+    let Some(stream) = get_handle_mut::<EmitterStream>(handle) else { return };
+    let emitter = stream.emitter;
+    for (name, listener) in std::mem::take(&mut stream.listeners) {
+        js_event_emitter_remove_listener(emitter, key(&name), listener);
+    }
+}
+
+/// `stream.next()`: a promise of the next `{ value, done }`
+unsafe fn stream_next(handle: Handle) -> *mut Promise {
+    let promise = js_promise_new();
+    let Some(stream) = get_handle_mut::<EmitterStream>(handle) else {
+        js_promise_resolve(promise, iter_result(undefined(), true));
+        return promise;
+    };
+    if let Some((value, resume)) = stream.buffer.shift() {
+        js_promise_resolve(promise, iter_result(value, false));
+        if resume && stream.resume != 0 {
+            js_closure_call0(stream.resume as *const ClosureHeader);
+        }
+    } else if let Some(reason) = stream.error.take() {
+        stream.ended = true;
+        js_promise_reject(promise, reason);
+    } else if stream.ended {
+        js_promise_resolve(promise, iter_result(undefined(), true));
+    } else {
+        stream.waiters.push_back(promise as i64);
+    }
+    promise
+}
+
+/// `stream.return()`: stop listening and discard buffered values
+unsafe fn stream_close(handle: Handle) -> *mut Promise {
+    stream_detach(handle);
+    if let Some(stream) = get_handle_mut::<EmitterStream>(handle) {
+        stream.ended = true;
+        stream.error = None;
+        stream.buffer.values.clear();
+        for waiter in stream.waiters.drain(..) {
+            js_promise_resolve(waiter as *mut Promise, iter_result(undefined(), true));
+        }
+    }
+    js_promise_resolved(iter_result(undefined(), true))
+}
+
+// ============================================================================
+// Pipelines
+// ============================================================================
+
+/// `pipeline(source, ...stages)`: resolves once every value has gone through the stages
+#[no_mangle]
+pub unsafe extern "C" fn js_streams_pipeline(
+    source: f64,
+    s1: f64,
+    s2: f64,
+    s3: f64,
+    s4: f64,
+    s5: f64,
+    s6: f64,
+    s7: f64,
+) -> f64 {
+    let promise = js_promise_new();
+    let mut stages = Vec::new();
+    for stage in [s1, s2, s3, s4, s5, s6, s7] {
+        if JSValue::from_bits(stage.to_bits()).is_undefined() {
+            continue;
+        }
+        match closure_arg(stage) {
+            Some(closure) => stages.push(closure),
+            None => {
+                js_promise_reject(promise, error_value("pipeline() stages must be functions"));
+                return boxed_pointer(promise as i64);
+            }
+        }
+    }
+    match pipeline_source(source) {
+        Some(source) => start_pipeline(source, stages, None, promise),
+        None => js_promise_reject(promise, error_value("pipeline() expects a stream, an EventEmitter or an array")),
+    }
+    boxed_pointer(promise as i64)
+}
+
+unsafe fn pipeline_source(value: f64) -> Option<Source> {
+    let ptr = js_nanbox_get_pointer(value);
+    if ptr > 0 && ptr < HANDLE_LIMIT {
+        if with_handle::<EmitterStream, bool, _>(ptr, |_| true) == Some(true) {
+            return Some(Source::Stream(ptr));
+        }
+        if with_handle::<EventEmitterHandle, bool, _>(ptr, |_| true) == Some(true) {
+            return Some(Source::Stream(from_emitter(ptr, "data", StreamOptions::default())));
+        }
+        return None;
+    }
+    if js_array_is_array(value) == 0.0 {
+        return None;
+    }
+    let array = ptr as *const ArrayHeader;
+    let values = (0..js_array_length(array)).map(|i| f64::from_bits(js_array_get_jsvalue(array, i))).collect();
+    Some(Source::Values(values))
+}
+
+unsafe fn start_pipeline(source: Source, stages: Vec<i64>, collected: Option<Vec<f64>>, promise: *mut Promise) {
+    let handle = register_handle(Pipeline { source, stages, collected, promise: promise as i64 });
+    pipeline_pull(handle);
+}
+
+/// Ask the source for the next value
+unsafe fn pipeline_pull(handle: Handle) {
+    let Some(pipeline) = get_handle_mut::<Pipeline>(handle) else { return };
+    let step = match &mut pipeline.source {
+        Source::Stream(stream) => stream_next(*stream),
+        Source::Values(values) => js_promise_resolved(match values.pop_front() {
+            Some(value) => iter_result(value, false),
+            None => iter_result(undefined(), true),
+        }),
+    };
+    watch(boxed_pointer(step as i64), handle, 0, pipeline_pulled, pipeline_failed);
+}
+
+unsafe extern "C" fn pipeline_pulled(closure: *const ClosureHeader, step: f64) -> f64 {
+    let handle = captures(closure).0;
+    let step = js_nanbox_get_pointer(step) as *const ObjectHeader;
+    if js_object_get_field_by_name(step, key("done")).to_bool() {
+        pipeline_finish(handle, Ok(()));
+    } else {
+        let value = f64::from_bits(js_object_get_field_by_name(step, key("value")).bits());
+        pipeline_run(handle, 0, value);
+    }
+    undefined()
+}
+
+/// Run `value` through the stages from `stage` on, then pull the next value
+unsafe fn pipeline_run(handle: Handle, stage: usize, value: f64) {
+    let Some(pipeline) = get_handle_mut::<Pipeline>(handle) else { return };
+    let Some(&closure) = pipeline.stages.get(stage) else {
+        if let Some(collected) = &mut pipeline.collected {
+            collected.push(value);
+        }
+        pipeline_pull(handle);
+        return;
+    };
+    let result = js_closure_call1(closure as *const ClosureHeader, value);
+    watch(result, handle, stage as i64 + 1, pipeline_stage_done, pipeline_failed);
+}
+
+unsafe extern "C" fn pipeline_stage_done(closure: *const ClosureHeader, value: f64) -> f64 {
+    let (handle, next_stage) = captures(closure);
+    if JSValue::from_bits(value.to_bits()).is_undefined() {
+        pipeline_pull(handle);
+    } else {
+        pipeline_run(handle, next_stage as usize, value);
+    }
+    undefined()
+}
+
+unsafe extern "C" fn pipeline_failed(closure: *const ClosureHeader, reason: f64) -> f64 {
+    pipeline_finish(captures(closure).0, Err(reason));
+    undefined()
+}
+
+/// Detach the source and settle the pipeline's promise
+unsafe fn pipeline_finish(handle: Handle, outcome: Result<(), f64>) {
+    let Some(pipeline) = take_handle::<Pipeline>(handle) else { return };
+    if let Source::Stream(stream) = pipeline.source {
+        stream_close(stream);
+    }
+    let promise = pipeline.promise as *mut Promise;
+    match outcome {
+        Ok(()) => {
+            let result = match pipeline.collected {
+                Some(values) => {
+                    let mut array = js_array_alloc(values.len() as u32);
+                    for value in values {
+                        array = js_array_push_f64(array, value);
+                    }
+                    boxed_pointer(array as i64)
+                }
+                None => undefined(),
+            };
+            js_promise_resolve(promise, result);
+        }
+        Err(reason) => js_promise_reject(promise, reason),
+    }
+}
+
+// ============================================================================
+// Handle dispatch
+// ============================================================================
+
+/// `stream.next()`, `stream.return()` and `stream.toArray()`
+pub(crate) unsafe fn dispatch_emitter_stream(handle: Handle, method: &str) -> f64 {
+    let promise = match method {
+        "next" => stream_next(handle),
+        "return" => stream_close(handle),
+        "toArray" => {
+            let promise = js_promise_new();
+            start_pipeline(Source::Stream(handle), Vec::new(), Some(Vec::new()), promise);
+            promise
+        }
+        _ => return undefined(),
+    };
+    boxed_pointer(promise as i64)
+}
+
+/// `stream.buffered` and `stream.dropped`
+pub(crate) unsafe fn emitter_stream_property(handle: Handle, property: &str) -> f64 {
+    with_handle::<EmitterStream, f64, _>(handle, |stream| match property {
+        "buffered" => stream.buffer.values.len() as f64,
+        "dropped" => stream.buffer.dropped as f64,
+        _ => undefined(),
+    })
+    .unwrap_or_else(undefined)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn drain(buffer: &mut Buffer) -> Vec<f64> {
+        std::iter::from_fn(|| buffer.shift().map(|(value, _)| value)).collect()
+    }
+
+    #[test]
+    fn test_buffer_policies() {
+        let mut buffer = Buffer::new(2, Policy::Drop);
+        for value in [1.0, 2.0, 3.0] {
+            assert!(!buffer.push(value));
+        }
+        assert_eq!((drain(&mut buffer), buffer.dropped), (vec![1.0, 2.0], 1));
+
+        let mut buffer = Buffer::new(2, Policy::Latest);
+        for value in [1.0, 2.0, 3.0] {
+            buffer.push(value);
+        }
+        assert_eq!((drain(&mut buffer), buffer.dropped), (vec![2.0, 3.0], 1));
+
+        // Blocking keeps every value; pause once full, resume once drained to half
+        let mut buffer = Buffer::new(4, Policy::Block);
+        let pauses: Vec<bool> = (1..=5).map(|value| buffer.push(value as f64)).collect();
+        assert_eq!(pauses, [false, false, false, true, false]);
+        let resumes: Vec<bool> = std::iter::from_fn(|| buffer.shift().map(|(_, resume)| resume)).collect();
+        assert_eq!(resumes, [false, false, true, false, false]);
+        assert_eq!(buffer.dropped, 0);
+    }
+}
@@ -0,0 +1,443 @@
+//! `Temporal.PlainDate`, `Temporal.Duration`, `Temporal.ZonedDateTime` and `Temporal.Now`
+//!
+//! Values are handles around perry-runtime's `temporal` types. Arguments follow the
+//! proposal's conversions: a Temporal value of the right kind, an ISO 8601 string or a
+//! property bag (`{ year, month, day }`, `{ hours: 2 }`). Invalid dates, strings and unknown
+//! time zones throw a RangeError, arguments of the wrong kind a TypeError.
+//!
+//! Only the ISO calendar is supported, and `epochNanoseconds` (a BigInt) is not exposed.
+
+use std::cmp::Ordering;
+
+use perry_runtime::error::js_error_new_with_message;
+use perry_runtime::temporal::{
+    format_offset, now_epoch_ns, Duration, PlainDate, TemporalError, TimeZone, Unit, ZonedDateTime, DURATION_FIELDS,
+};
+use perry_runtime::{js_get_string_pointer_unified, js_object_get_field_by_name, js_string_from_bytes, JSValue, ObjectHeader, StringHeader};
+
+use crate::common::handle::{register_handle, with_handle, Handle};
+use crate::concurrency::{boxed_pointer, undefined};
+
+/// Handles are small integers; anything above is a heap pointer
+const HANDLE_LIMIT: i64 = 0x100000;
+
+/// A JavaScript argument, by what Temporal's conversions care about
+enum Arg {
+    Str(String),
+    Handle(Handle),
+    Object(*const ObjectHeader),
+    Number(f64),
+    Missing,
+}
+
+unsafe fn classify(value: f64) -> Arg {
+    let js = JSValue::from_bits(value.to_bits());
+    if js.is_string() {
+        let ptr = js_get_string_pointer_unified(value) as *const StringHeader;
+        if ptr.is_null() {
+            return Arg::Missing;
+        }
+        let len = (*ptr).length as usize;
+        let data = (ptr as *const u8).add(std::mem::size_of::<StringHeader>());
+        return Arg::Str(String::from_utf8_lossy(std::slice::from_raw_parts(data, len)).into_owned());
+    }
+    if js.is_pointer() {
+        let ptr = js.as_pointer::<ObjectHeader>() as i64;
+        return if ptr > 0 && ptr < HANDLE_LIMIT { Arg::Handle(ptr) } else { Arg::Object(ptr as *const ObjectHeader) };
+    }
+    if js.is_int32() {
+        return Arg::Number(js.as_int32() as f64);
+    }
+    if js.is_number() {
+        return Arg::Number(value);
+    }
+    Arg::Missing
+}
+
+fn arg(args: &[f64], index: usize) -> f64 {
+    args.get(index).copied().unwrap_or_else(undefined)
+}
+
+unsafe fn key(name: &str) -> *mut StringHeader {
+    js_string_from_bytes(name.as_ptr(), name.len() as u32)
+}
+
+fn string_value(text: &str) -> f64 {
+    unsafe { f64::from_bits(JSValue::string_ptr(key(text)).bits()) }
+}
+
+fn bool_value(value: bool) -> f64 {
+    f64::from_bits(JSValue::bool(value).bits())
+}
+
+unsafe fn field(obj: *const ObjectHeader, name: &str) -> f64 {
+    f64::from_bits(js_object_get_field_by_name(obj, key(name)).bits())
+}
+
+/// Throw an error with `name` (`RangeError`, `TypeError`)
+unsafe fn throw_error(name: &str, message: &str) -> ! {
+    let error = js_error_new_with_message(key(message));
+    (*error).name = key(name);
+    perry_runtime::exception::js_throw(boxed_pointer(error as i64))
+}
+
+unsafe fn check<T>(result: Result<T, TemporalError>) -> T {
+    result.unwrap_or_else(|TemporalError(message)| throw_error("RangeError", &message))
+}
+
+/// An integral number, or None for `undefined`
+unsafe fn integer_arg(value: f64, name: &str) -> Option<i64> {
+    match classify(value) {
+        Arg::Missing => None,
+        Arg::Number(number) if number.is_finite() && number.fract() == 0.0 => Some(number as i64),
+        Arg::Number(_) => throw_error("RangeError", &format!("{} must be an integer", name)),
+        _ => throw_error("TypeError", &format!("{} must be a number", name)),
+    }
+}
+
+fn is_plain_date(handle: Handle) -> bool {
+    with_handle::<PlainDate, bool, _>(handle, |_| true).unwrap_or(false)
+}
+
+fn is_duration(handle: Handle) -> bool {
+    with_handle::<Duration, bool, _>(handle, |_| true).unwrap_or(false)
+}
+
+fn is_zoned_date_time(handle: Handle) -> bool {
+    with_handle::<ZonedDateTime, bool, _>(handle, |_| true).unwrap_or(false)
+}
+
+/// A PlainDate, a ZonedDateTime's date, an ISO string or `{ year, month, day }`
+unsafe fn plain_date_arg(value: f64) -> PlainDate {
+    match classify(value) {
+        Arg::Handle(handle) if is_plain_date(handle) => with_handle::<PlainDate, _, _>(handle, |date| *date).unwrap(),
+        Arg::Handle(handle) if is_zoned_date_time(handle) => {
+            with_handle::<ZonedDateTime, _, _>(handle, |zoned| zoned.wall().0).unwrap()
+        }
+        Arg::Str(text) => check(PlainDate::parse(&text)),
+        Arg::Object(obj) => {
+            let part = |name: &str| {
+                integer_arg(field(obj, name), name)
+                    .unwrap_or_else(|| throw_error("TypeError", &format!("{} is required", name)))
+            };
+            check(PlainDate::constrained(part("year"), part("month"), part("day")))
+        }
+        _ => throw_error("TypeError", "Expected a Temporal.PlainDate, string or { year, month, day }"),
+    }
+}
+
+/// A Duration, an ISO string (`PT1H30M`) or a property bag (`{ hours: 1, minutes: 30 }`)
+unsafe fn duration_arg(value: f64) -> Duration {
+    match classify(value) {
+        Arg::Handle(handle) if is_duration(handle) => with_handle::<Duration, _, _>(handle, |duration| *duration).unwrap(),
+        Arg::Str(text) => check(Duration::parse(&text)),
+        Arg::Object(obj) => {
+            let mut fields = [0i64; 10];
+            let mut any = false;
+            for (slot, name) in fields.iter_mut().zip(DURATION_FIELDS) {
+                if let Some(value) = integer_arg(field(obj, name), name) {
+                    *slot = value;
+                    any = true;
+                }
+            }
+            if !any {
+                throw_error("TypeError", "A duration needs at least one of its fields");
+            }
+            check(Duration::from_fields(fields))
+        }
+        _ => throw_error("TypeError", "Expected a Temporal.Duration, string or duration object"),
+    }
+}
+
+/// A ZonedDateTime or an ISO string with a time zone annotation
+unsafe fn zoned_date_time_arg(value: f64) -> ZonedDateTime {
+    match classify(value) {
+        Arg::Handle(handle) if is_zoned_date_time(handle) => {
+            with_handle::<ZonedDateTime, _, _>(handle, |zoned| zoned.clone()).unwrap()
+        }
+        Arg::Str(text) => check(ZonedDateTime::parse(&text)),
+        _ => throw_error("TypeError", "Expected a Temporal.ZonedDateTime or string"),
+    }
+}
+
+/// A time zone id, a ZonedDateTime's zone, or the system zone when omitted
+unsafe fn time_zone_arg(value: f64) -> TimeZone {
+    match classify(value) {
+        Arg::Missing => TimeZone::system(),
+        Arg::Str(id) => check(TimeZone::parse(&id)),
+        Arg::Handle(handle) if is_zoned_date_time(handle) => {
+            with_handle::<ZonedDateTime, _, _>(handle, |zoned| zoned.zone.clone()).unwrap()
+        }
+        _ => throw_error("TypeError", "Expected a time zone id"),
+    }
+}
+
+/// A unit given directly (`"days"`) or as `options[name]`, else `default`
+unsafe fn unit_arg(value: f64, name: &str, default: Unit) -> Unit {
+    let value = match classify(value) {
+        Arg::Object(obj) => classify(field(obj, name)),
+        other => other,
+    };
+    match value {
+        Arg::Missing => default,
+        Arg::Str(unit) => check(Unit::parse(&unit)),
+        _ => throw_error("TypeError", &format!("{} must be a string", name)),
+    }
+}
+
+fn ordering_value(ordering: Ordering) -> f64 {
+    ordering as i8 as f64
+}
+
+fn plain_date_value(date: PlainDate) -> f64 {
+    boxed_pointer(register_handle(date))
+}
+
+fn duration_value(duration: Duration) -> f64 {
+    boxed_pointer(register_handle(duration))
+}
+
+fn zoned_date_time_value(zoned: ZonedDateTime) -> f64 {
+    boxed_pointer(register_handle(zoned))
+}
+
+// ============================================================================
+// Constructors and statics
+// ============================================================================
+
+/// `new Temporal.PlainDate(year, month, day)`
+#[no_mangle]
+pub unsafe extern "C" fn js_temporal_plain_date_new(year: f64, month: f64, day: f64) -> f64 {
+    let part = |value: f64, name: &str| integer_arg(value, name).unwrap_or_else(|| throw_error("RangeError", &format!("{} is required", name)));
+    plain_date_value(check(PlainDate::new(part(year, "year"), part(month, "month"), part(day, "day"))))
+}
+
+/// `Temporal.PlainDate.from(value)`
+#[no_mangle]
+pub unsafe extern "C" fn js_temporal_plain_date_from(value: f64) -> f64 {
+    plain_date_value(plain_date_arg(value))
+}
+
+/// `Temporal.PlainDate.compare(a, b)`
+#[no_mangle]
+pub unsafe extern "C" fn js_temporal_plain_date_compare(a: f64, b: f64) -> f64 {
+    ordering_value(plain_date_arg(a).cmp(&plain_date_arg(b)))
+}
+
+/// `new Temporal.Duration(years, months, ..., nanoseconds)`; omitted fields are zero
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+pub unsafe extern "C" fn js_temporal_duration_new(
+    years: f64,
+    months: f64,
+    weeks: f64,
+    days: f64,
+    hours: f64,
+    minutes: f64,
+    seconds: f64,
+    milliseconds: f64,
+    microseconds: f64,
+    nanoseconds: f64,
+) -> f64 {
+    let values = [years, months, weeks, days, hours, minutes, seconds, milliseconds, microseconds, nanoseconds];
+    let mut fields = [0i64; 10];
+    for ((slot, value), name) in fields.iter_mut().zip(values).zip(DURATION_FIELDS) {
+        *slot = integer_arg(value, name).unwrap_or(0);
+    }
+    duration_value(check(Duration::from_fields(fields)))
+}
+
+/// `Temporal.Duration.from(value)`
+#[no_mangle]
+pub unsafe extern "C" fn js_temporal_duration_from(value: f64) -> f64 {
+    duration_value(duration_arg(value))
+}
+
+/// `Temporal.Duration.compare(a, b)`: by length, with days counted as 24 hours
+#[no_mangle]
+pub unsafe extern "C" fn js_temporal_duration_compare(a: f64, b: f64) -> f64 {
+    ordering_value(check(duration_arg(a).compare(&duration_arg(b))))
+}
+
+/// `Temporal.ZonedDateTime.from(value)`
+#[no_mangle]
+pub unsafe extern "C" fn js_temporal_zoned_date_time_from(value: f64) -> f64 {
+    zoned_date_time_value(zoned_date_time_arg(value))
+}
+
+/// `Temporal.ZonedDateTime.compare(a, b)`: by exact time
+#[no_mangle]
+pub unsafe extern "C" fn js_temporal_zoned_date_time_compare(a: f64, b: f64) -> f64 {
+    ordering_value(zoned_date_time_arg(a).epoch_ns.cmp(&zoned_date_time_arg(b).epoch_ns))
+}
+
+/// `Temporal.Now.zonedDateTimeISO(timeZone?)`
+#[no_mangle]
+pub unsafe extern "C" fn js_temporal_now_zoned_date_time_iso(time_zone: f64) -> f64 {
+    zoned_date_time_value(check(ZonedDateTime::new(now_epoch_ns(), time_zone_arg(time_zone))))
+}
+
+/// `Temporal.Now.plainDateISO(timeZone?)`
+#[no_mangle]
+pub unsafe extern "C" fn js_temporal_now_plain_date_iso(time_zone: f64) -> f64 {
+    let now = check(ZonedDateTime::new(now_epoch_ns(), time_zone_arg(time_zone)));
+    plain_date_value(now.wall().0)
+}
+
+/// `Temporal.Now.timeZoneId()`
+#[no_mangle]
+pub unsafe extern "C" fn js_temporal_now_time_zone_id() -> f64 {
+    string_value(&TimeZone::system().id())
+}
+
+// ============================================================================
+// Handle dispatch
+// ============================================================================
+
+/// Dispatch a method call on a PlainDate, Duration or ZonedDateTime handle
+pub(crate) unsafe fn dispatch_temporal(handle: Handle, method: &str, args: &[f64]) -> f64 {
+    if let Some(date) = with_handle::<PlainDate, _, _>(handle, |date| *date) {
+        return plain_date_method(date, method, args);
+    }
+    if let Some(duration) = with_handle::<Duration, _, _>(handle, |duration| *duration) {
+        return duration_method(duration, method, args);
+    }
+    match with_handle::<ZonedDateTime, _, _>(handle, |zoned| zoned.clone()) {
+        Some(zoned) => zoned_date_time_method(zoned, method, args),
+        None => undefined(),
+    }
+}
+
+unsafe fn plain_date_method(date: PlainDate, method: &str, args: &[f64]) -> f64 {
+    match method {
+        "add" => plain_date_value(check(date.add(&duration_arg(arg(args, 0))))),
+        "subtract" => plain_date_value(check(date.add(&duration_arg(arg(args, 0)).negated()))),
+        "until" | "since" => {
+            let largest = unit_arg(arg(args, 1), "largestUnit", Unit::Days);
+            if largest > Unit::Days {
+                throw_error("RangeError", "largestUnit of a PlainDate difference must be days or larger");
+            }
+            let other = plain_date_arg(arg(args, 0));
+            let duration = check(date.until(&other, largest));
+            duration_value(if method == "since" { duration.negated() } else { duration })
+        }
+        "equals" => bool_value(date == plain_date_arg(arg(args, 0))),
+        "with" => {
+            let Arg::Object(obj) = classify(arg(args, 0)) else {
+                throw_error("TypeError", "with() expects an object of fields")
+            };
+            let part = |name: &str, current: i64| integer_arg(field(obj, name), name).unwrap_or(current);
+            let year = part("year", date.year as i64);
+            let month = part("month", date.month as i64);
+            plain_date_value(check(PlainDate::constrained(year, month, part("day", date.day as i64))))
+        }
+        "toZonedDateTime" => {
+            let zone = match classify(arg(args, 0)) {
+                Arg::Object(obj) => time_zone_arg(field(obj, "timeZone")),
+                _ => time_zone_arg(arg(args, 0)),
+            };
+            zoned_date_time_value(check(ZonedDateTime::from_wall(&date, 0, zone)))
+        }
+        "toString" | "toJSON" => string_value(&date.to_string()),
+        _ => undefined(),
+    }
+}
+
+unsafe fn duration_method(duration: Duration, method: &str, args: &[f64]) -> f64 {
+    match method {
+        "negated" => duration_value(duration.negated()),
+        "abs" => duration_value(duration.abs()),
+        "add" => duration_value(check(duration.add(&duration_arg(arg(args, 0))))),
+        "subtract" => duration_value(check(duration.add(&duration_arg(arg(args, 0)).negated()))),
+        "total" => {
+            if matches!(classify(arg(args, 0)), Arg::Missing) {
+                throw_error("RangeError", "total() requires a unit");
+            }
+            check(duration.total(unit_arg(arg(args, 0), "unit", Unit::Nanoseconds)))
+        }
+        "toString" | "toJSON" => string_value(&duration.to_string()),
+        _ => undefined(),
+    }
+}
+
+unsafe fn zoned_date_time_method(zoned: ZonedDateTime, method: &str, args: &[f64]) -> f64 {
+    match method {
+        "add" => zoned_date_time_value(check(zoned.add(&duration_arg(arg(args, 0))))),
+        "subtract" => zoned_date_time_value(check(zoned.add(&duration_arg(arg(args, 0)).negated()))),
+        "until" | "since" => {
+            let other = zoned_date_time_arg(arg(args, 0));
+            let largest = unit_arg(arg(args, 1), "largestUnit", Unit::Hours);
+            let duration = check(zoned.until(&other, largest));
+            duration_value(if method == "since" { duration.negated() } else { duration })
+        }
+        "equals" => {
+            let other = zoned_date_time_arg(arg(args, 0));
+            bool_value(zoned.epoch_ns == other.epoch_ns && zoned.zone == other.zone)
+        }
+        "withTimeZone" => zoned_date_time_value(zoned.with_time_zone(time_zone_arg(arg(args, 0)))),
+        "toPlainDate" => plain_date_value(zoned.wall().0),
+        "startOfDay" => zoned_date_time_value(check(zoned.start_of_day())),
+        "toString" | "toJSON" => string_value(&zoned.to_string()),
+        _ => undefined(),
+    }
+}
+
+/// Property access on a PlainDate, Duration or ZonedDateTime handle
+pub(crate) unsafe fn temporal_property(handle: Handle, property: &str) -> f64 {
+    if let Some(date) = with_handle::<PlainDate, _, _>(handle, |date| *date) {
+        return plain_date_property(&date, property).unwrap_or_else(undefined);
+    }
+    if let Some(duration) = with_handle::<Duration, _, _>(handle, |duration| *duration) {
+        return match property {
+            "sign" => duration.sign() as f64,
+            "blank" => bool_value(duration.sign() == 0),
+            _ => DURATION_FIELDS
+                .iter()
+                .position(|name| *name == property)
+                .map_or_else(undefined, |index| duration.fields()[index] as f64),
+        };
+    }
+    let Some(zoned) = with_handle::<ZonedDateTime, _, _>(handle, |zoned| zoned.clone()) else {
+        return undefined();
+    };
+    let (date, ns_of_day) = zoned.wall();
+    let seconds = ns_of_day / 1_000_000_000;
+    let subsecond = ns_of_day % 1_000_000_000;
+    match property {
+        "hour" => (seconds / 3600) as f64,
+        "minute" => (seconds / 60 % 60) as f64,
+        "second" => (seconds % 60) as f64,
+        "millisecond" => (subsecond / 1_000_000) as f64,
+        "microsecond" => (subsecond / 1_000 % 1_000) as f64,
+        "nanosecond" => (subsecond % 1_000) as f64,
+        "timeZoneId" => string_value(&zoned.zone.id()),
+        "offset" => string_value(&format_offset(zoned.offset_seconds())),
+        "offsetNanoseconds" => zoned.offset_seconds() as f64 * 1e9,
+        "epochMilliseconds" => zoned.epoch_ns.div_euclid(1_000_000) as f64,
+        "hoursInDay" => check(zoned.hours_in_day()),
+        _ => plain_date_property(&date, property).unwrap_or_else(undefined),
+    }
+}
+
+fn plain_date_property(date: &PlainDate, property: &str) -> Option<f64> {
+    Some(match property {
+        "year" => date.year as f64,
+        "month" => date.month as f64,
+        "day" => date.day as f64,
+        "monthCode" => string_value(&format!("M{:02}", date.month)),
+        "dayOfWeek" => date.day_of_week() as f64,
+        "dayOfYear" => date.day_of_year() as f64,
+        "daysInWeek" => 7.0,
+        "daysInMonth" => date.days_in_month() as f64,
+        "daysInYear" => date.days_in_year() as f64,
+        "monthsInYear" => 12.0,
+        "inLeapYear" => bool_value(date.in_leap_year()),
+        "calendarId" => string_value("iso8601"),
+        _ => return None,
+    })
+}
+
+/// Whether a handle is a Temporal value
+pub(crate) fn is_temporal(handle: Handle) -> bool {
+    is_plain_date(handle) || is_duration(handle) || is_zoned_date_time(handle)
+}
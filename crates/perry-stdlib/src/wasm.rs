@@ -0,0 +1,382 @@
+//! WebAssembly plugin host for `perry/wasm`
+//!
+//! `loadPlugin(path, options?)` compiles a `.wasm` file with wasmi, an interpreter, so a
+//! plugin runs inside the program without a JIT or the V8 fallback and can only reach what
+//! it is given: nothing at all by default, or WASI's stdout, stderr, args and clock with
+//! `{ wasi: true }` (no files, sockets or environment). Reading the file goes through the
+//! `fs` permission policy.
+//!
+//! Limits come from the options and apply to every call:
+//! - `fuel`: instructions a single call may execute (wasmi fuel units); running out traps
+//! - `maxMemoryPages`: 64 KiB pages a plugin's linear memory may grow to (256 = 16 MiB by default)
+//!
+//! `plugin.call(name, ...args)` converts each argument to the export's declared parameter
+//! type: `i32`, `f32` and `f64` take numbers, `i64` takes a BigInt or an integral number.
+//! A single result comes back as a number (a BigInt for `i64`), several as an array, none
+//! as `undefined`, the same as JavaScript's WebAssembly API.
+//!
+//! `plugin.callString(name, input)` passes text, extism-style, through the plugin's memory.
+//! The plugin exports `memory`, `alloc(len: i32) -> i32` and the function as
+//! `(ptr: i32, len: i32) -> i64`; the host writes the UTF-8 input at `alloc(len)` and the
+//! function returns its output as `(out_ptr << 32) | out_len`. JSON payloads go through
+//! `JSON.stringify` / `JSON.parse` on the caller's side.
+//!
+//! `plugin.exports()` lists the exported functions with their signatures, `plugin.fuelUsed`
+//! is the fuel the last call consumed, and `plugin.close()` frees the instance. Traps,
+//! exhausted fuel and refused memory growth throw an Error naming the export.
+
+use perry_runtime::bigint::js_bigint_from_i64;
+use perry_runtime::permissions::{self, Capability};
+use perry_runtime::{
+    js_array_alloc, js_array_push_f64, js_get_string_pointer_unified, js_object_alloc, js_object_get_field_by_name,
+    js_object_set_field_by_name, js_string_from_bytes, JSValue, ObjectHeader, StringHeader,
+};
+use wasmi::{Config, Engine, Func, Instance, Linker, Module, Store, StoreLimits, StoreLimitsBuilder, Val, ValType};
+use wasmi_wasi::{WasiCtx, WasiCtxBuilder};
+
+use crate::common::handle::{drop_handle, get_handle_mut, register_handle, with_handle, Handle};
+use crate::concurrency::{boxed_pointer, error_value, undefined};
+
+/// Fuel a call may use unless `fuel` says otherwise
+const DEFAULT_FUEL: u64 = 100_000_000;
+
+/// Memory pages a plugin may grow to unless `maxMemoryPages` says otherwise (16 MiB)
+const DEFAULT_MAX_MEMORY_PAGES: u32 = 256;
+
+const PAGE_SIZE: usize = 64 * 1024;
+
+/// Per-plugin resource limits
+#[derive(Debug, Clone, Copy)]
+struct Limits {
+    fuel: u64,
+    max_memory_pages: u32,
+    wasi: bool,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits { fuel: DEFAULT_FUEL, max_memory_pages: DEFAULT_MAX_MEMORY_PAGES, wasi: false }
+    }
+}
+
+/// Store data: the memory limiter and, for WASI plugins, their context
+struct HostState {
+    limits: StoreLimits,
+    wasi: Option<WasiCtx>,
+}
+
+pub struct Plugin {
+    store: Store<HostState>,
+    instance: Instance,
+    fuel: u64,
+    /// Fuel the last call consumed
+    fuel_used: u64,
+}
+
+impl Plugin {
+    /// Compile and instantiate a module under `limits`
+    fn instantiate(wasm: &[u8], limits: Limits) -> Result<Plugin, String> {
+        let mut config = Config::default();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config);
+        let module = Module::new(&engine, wasm).map_err(|e| format!("invalid WebAssembly module: {}", e))?;
+
+        let memory_limit = StoreLimitsBuilder::new()
+            .memory_size(limits.max_memory_pages as usize * PAGE_SIZE)
+            .instances(1)
+            .build();
+        let wasi = if limits.wasi {
+            let ctx = WasiCtxBuilder::new()
+                .inherit_stdout()
+                .inherit_stderr()
+                .args(&["plugin".to_string()])
+                .map_err(|e| e.to_string())?
+                .build();
+            Some(ctx)
+        } else {
+            None
+        };
+        let mut store = Store::new(&engine, HostState { limits: memory_limit, wasi });
+        store.limiter(|state| &mut state.limits);
+
+        let mut linker = <Linker<HostState>>::new(&engine);
+        if limits.wasi {
+            wasmi_wasi::add_to_linker(&mut linker, |state: &mut HostState| {
+                state.wasi.as_mut().expect("WASI plugin without a WASI context")
+            })
+            .map_err(|e| e.to_string())?;
+        }
+        // Start functions and initializers run on the first call's budget
+        store.set_fuel(limits.fuel).map_err(|e| e.to_string())?;
+        let instance = linker
+            .instantiate(&mut store, &module)
+            .and_then(|pre| pre.start(&mut store))
+            .map_err(|e| format!("cannot instantiate plugin: {}", e))?;
+        Ok(Plugin { store, instance, fuel: limits.fuel, fuel_used: 0 })
+    }
+
+    fn func(&self, name: &str) -> Result<Func, String> {
+        self.instance.get_func(&self.store, name).ok_or_else(|| format!("plugin has no exported function '{}'", name))
+    }
+
+    /// Call an export with a fresh fuel budget
+    fn invoke(&mut self, name: &str, func: Func, params: &[Val]) -> Result<Vec<Val>, String> {
+        let ty = func.ty(&self.store);
+        let mut results: Vec<Val> = ty.results().iter().map(|ty| Val::default(*ty)).collect();
+        self.store.set_fuel(self.fuel).map_err(|e| e.to_string())?;
+        let outcome = func.call(&mut self.store, params, &mut results);
+        self.fuel_used = self.fuel - self.store.get_fuel().unwrap_or(0);
+        outcome.map_err(|e| format!("plugin function '{}' failed: {}", name, e))?;
+        Ok(results)
+    }
+
+    /// Pass `input` through the plugin's memory to an extism-style `(ptr, len) -> i64` export
+    fn call_bytes(&mut self, name: &str, input: &[u8]) -> Result<Vec<u8>, String> {
+        let alloc = self.func("alloc")?;
+        let func = self.func(name)?;
+        let memory = self.instance.get_memory(&self.store, "memory").ok_or("plugin exports no memory")?;
+        let len = i32::try_from(input.len()).map_err(|_| "input is too large".to_string())?;
+        let ptr = match self.invoke("alloc", alloc, &[Val::I32(len)])?.as_slice() {
+            [Val::I32(ptr)] => *ptr as u32 as usize,
+            _ => return Err("plugin's alloc must return an i32 pointer".to_string()),
+        };
+        memory.write(&mut self.store, ptr, input).map_err(|e| format!("cannot write plugin input: {}", e))?;
+        let packed = match self.invoke(name, func, &[Val::I32(ptr as i32), Val::I32(len)])?.as_slice() {
+            [Val::I64(packed)] => *packed as u64,
+            _ => return Err(format!("plugin function '{}' must return an i64 (ptr << 32 | len)", name)),
+        };
+        let (out_ptr, out_len) = ((packed >> 32) as usize, (packed & 0xFFFF_FFFF) as usize);
+        let mut output = vec![0u8; out_len];
+        memory.read(&self.store, out_ptr, &mut output).map_err(|e| format!("cannot read plugin output: {}", e))?;
+        Ok(output)
+    }
+
+    /// `(name, params, results)` of every exported function
+    fn signatures(&self) -> Vec<(String, Vec<ValType>, Vec<ValType>)> {
+        self.instance
+            .exports(&self.store)
+            .filter_map(|export| {
+                let name = export.name().to_string();
+                let func = export.into_func()?;
+                let ty = func.ty(&self.store);
+                Some((name, ty.params().to_vec(), ty.results().to_vec()))
+            })
+            .collect()
+    }
+}
+
+fn type_name(ty: ValType) -> &'static str {
+    match ty {
+        ValType::I32 => "i32",
+        ValType::I64 => "i64",
+        ValType::F32 => "f32",
+        ValType::F64 => "f64",
+        ValType::FuncRef => "funcref",
+        ValType::ExternRef => "externref",
+    }
+}
+
+unsafe fn key(name: &str) -> *mut StringHeader {
+    js_string_from_bytes(name.as_ptr(), name.len() as u32)
+}
+
+fn string_value(text: &str) -> f64 {
+    unsafe { f64::from_bits(JSValue::string_ptr(key(text)).bits()) }
+}
+
+unsafe fn throw_error(message: &str) -> ! {
+    perry_runtime::exception::js_throw(error_value(message))
+}
+
+unsafe fn string_arg(value: f64) -> Option<String> {
+    let ptr = js_get_string_pointer_unified(value) as *const StringHeader;
+    if ptr.is_null() {
+        return None;
+    }
+    let len = (*ptr).length as usize;
+    let data = (ptr as *const u8).add(std::mem::size_of::<StringHeader>());
+    Some(String::from_utf8_lossy(std::slice::from_raw_parts(data, len)).into_owned())
+}
+
+unsafe fn read_limits(options: f64) -> Limits {
+    let mut limits = Limits::default();
+    let value = JSValue::from_bits(options.to_bits());
+    if !value.is_pointer() {
+        return limits;
+    }
+    let obj = value.as_pointer::<ObjectHeader>();
+    let field = |name: &str| JSValue::from_bits(js_object_get_field_by_name(obj, key(name)).bits());
+    let positive = |value: JSValue| (value.is_number() && value.as_number() >= 1.0).then(|| value.as_number());
+    if let Some(fuel) = positive(field("fuel")) {
+        limits.fuel = fuel as u64;
+    }
+    if let Some(pages) = positive(field("maxMemoryPages")) {
+        limits.max_memory_pages = pages.min(65536.0) as u32;
+    }
+    limits.wasi = field("wasi").is_bool() && field("wasi").as_bool();
+    limits
+}
+
+/// `loadPlugin(path, { fuel, maxMemoryPages, wasi }?)`
+#[no_mangle]
+pub unsafe extern "C" fn js_wasm_load_plugin(path: f64, options: f64) -> f64 {
+    let Some(path) = string_arg(path) else { throw_error("loadPlugin() expects a path to a .wasm file") };
+    permissions::check(Capability::Fs, &path);
+    let wasm = std::fs::read(&path).unwrap_or_else(|e| throw_error(&format!("cannot read plugin {}: {}", path, e)));
+    match Plugin::instantiate(&wasm, read_limits(options)) {
+        Ok(plugin) => boxed_pointer(register_handle(plugin)),
+        Err(message) => throw_error(&format!("{}: {}", path, message)),
+    }
+}
+
+// ============================================================================
+// Value conversion
+// ============================================================================
+
+/// Convert a JavaScript argument to a parameter of type `ty`
+unsafe fn to_wasm(value: f64, ty: ValType) -> Result<Val, String> {
+    let js = JSValue::from_bits(value.to_bits());
+    let number = if js.is_int32() {
+        Some(js.as_int32() as f64)
+    } else if js.is_number() {
+        Some(value)
+    } else {
+        None
+    };
+    match (ty, number) {
+        (ValType::I32, Some(n)) => Ok(Val::I32(n as i64 as i32)),
+        (ValType::F32, Some(n)) => Ok(Val::F32((n as f32).into())),
+        (ValType::F64, Some(n)) => Ok(Val::F64(n.into())),
+        (ValType::I64, Some(n)) if n.fract() == 0.0 => Ok(Val::I64(n as i64)),
+        // BigInt.asIntN(64)
+        (ValType::I64, None) if js.is_bigint() => {
+            let bigint = &*js.as_bigint_ptr();
+            let low = bigint.limbs().first().copied().unwrap_or(0) as i64;
+            Ok(Val::I64(if bigint.is_negative() { low.wrapping_neg() } else { low }))
+        }
+        (ValType::I64, _) => Err("an i64 parameter takes a BigInt or an integer".to_string()),
+        (ValType::I32 | ValType::F32 | ValType::F64, None) => Err(format!("a {} parameter takes a number", type_name(ty))),
+        _ => Err(format!("{} parameters are not supported", type_name(ty))),
+    }
+}
+
+fn from_wasm(value: &Val) -> f64 {
+    match value {
+        Val::I32(n) => *n as f64,
+        Val::I64(n) => f64::from_bits(JSValue::bigint_ptr(js_bigint_from_i64(*n)).bits()),
+        Val::F32(n) => f32::from(*n) as f64,
+        Val::F64(n) => f64::from(*n),
+        _ => undefined(),
+    }
+}
+
+unsafe fn array_of(values: impl IntoIterator<Item = f64>) -> f64 {
+    let mut arr = js_array_alloc(0);
+    for value in values {
+        arr = js_array_push_f64(arr, value);
+    }
+    boxed_pointer(arr as i64)
+}
+
+// ============================================================================
+// Handle dispatch
+// ============================================================================
+
+pub(crate) fn is_plugin(handle: Handle) -> bool {
+    with_handle::<Plugin, bool, _>(handle, |_| true).unwrap_or(false)
+}
+
+/// `plugin.call(name, ...args)`, `plugin.callString(name, input)`, `plugin.exports()`, `plugin.close()`
+pub(crate) unsafe fn dispatch_plugin(handle: Handle, method: &str, args: &[f64]) -> f64 {
+    if method == "close" {
+        drop_handle(handle);
+        return undefined();
+    }
+    let Some(plugin) = get_handle_mut::<Plugin>(handle) else { throw_error("plugin is closed") };
+    match method {
+        "call" => {
+            let name = args.first().and_then(|name| string_arg(*name)).unwrap_or_default();
+            let func = plugin.func(&name).unwrap_or_else(|e| throw_error(&e));
+            let ty = func.ty(&plugin.store);
+            if args.len() - 1 != ty.params().len() {
+                throw_error(&format!("'{}' takes {} arguments, got {}", name, ty.params().len(), args.len() - 1));
+            }
+            let params = args[1..]
+                .iter()
+                .zip(ty.params())
+                .enumerate()
+                .map(|(i, (value, ty))| {
+                    to_wasm(*value, *ty).unwrap_or_else(|e| throw_error(&format!("argument {} of '{}': {}", i + 1, name, e)))
+                })
+                .collect::<Vec<_>>();
+            let results = plugin.invoke(&name, func, &params).unwrap_or_else(|e| throw_error(&e));
+            match results.as_slice() {
+                [] => undefined(),
+                [single] => from_wasm(single),
+                several => array_of(several.iter().map(from_wasm)),
+            }
+        }
+        "callString" => {
+            let name = args.first().and_then(|name| string_arg(*name)).unwrap_or_default();
+            let input = args.get(1).and_then(|input| string_arg(*input)).unwrap_or_default();
+            let output = plugin.call_bytes(&name, input.as_bytes()).unwrap_or_else(|e| throw_error(&e));
+            string_value(&String::from_utf8_lossy(&output))
+        }
+        "exports" => {
+            let types = |types: &[ValType]| array_of(types.iter().map(|ty| string_value(type_name(*ty))));
+            let entries = plugin.signatures().into_iter().map(|(name, params, results)| {
+                let obj = js_object_alloc(0, 3);
+                js_object_set_field_by_name(obj, key("name"), string_value(&name));
+                js_object_set_field_by_name(obj, key("params"), types(&params));
+                js_object_set_field_by_name(obj, key("results"), types(&results));
+                boxed_pointer(obj as i64)
+            });
+            array_of(entries.collect::<Vec<_>>())
+        }
+        _ => undefined(),
+    }
+}
+
+/// `plugin.fuelUsed`
+pub(crate) fn plugin_property(handle: Handle, property: &str) -> f64 {
+    match property {
+        "fuelUsed" => with_handle::<Plugin, f64, _>(handle, |plugin| plugin.fuel_used as f64).unwrap_or_else(undefined),
+        _ => undefined(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `add(i32, i32) -> i32` and `spin()`, an endless loop
+    const MODULE: &[u8] = &[
+        0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // header
+        0x01, 0x0a, 0x02, 0x60, 0x02, 0x7f, 0x7f, 0x01, 0x7f, 0x60, 0x00, 0x00, // types
+        0x03, 0x03, 0x02, 0x00, 0x01, // functions
+        0x07, 0x0e, 0x02, 0x03, b'a', b'd', b'd', 0x00, 0x00, 0x04, b's', b'p', b'i', b'n', 0x00, 0x01, // exports
+        0x0a, 0x11, 0x02, 0x07, 0x00, 0x20, 0x00, 0x20, 0x01, 0x6a, 0x0b, 0x07, 0x00, 0x03, 0x40, 0x0c, 0x00, 0x0b,
+        0x0b, // code
+    ];
+
+    #[test]
+    fn test_call_and_fuel_limit() {
+        let limits = Limits { fuel: 10_000, ..Limits::default() };
+        let mut plugin = Plugin::instantiate(MODULE, limits).unwrap();
+        let add = plugin.func("add").unwrap();
+        let results = plugin.invoke("add", add, &[Val::I32(2), Val::I32(40)]).unwrap();
+        assert!(matches!(results.as_slice(), [Val::I32(42)]));
+        assert!(plugin.fuel_used > 0 && plugin.fuel_used < 10_000);
+
+        // The budget is per call: running out traps this call only
+        let spin = plugin.func("spin").unwrap();
+        let error = plugin.invoke("spin", spin, &[]).unwrap_err();
+        assert!(error.contains("'spin'"), "{}", error);
+        assert!(plugin.invoke("add", add, &[Val::I32(1), Val::I32(1)]).is_ok());
+
+        let names: Vec<String> = plugin.signatures().into_iter().map(|(name, _, _)| name).collect();
+        assert_eq!(names, vec!["add", "spin"]);
+        assert!(plugin.func("missing").is_err());
+        assert!(Plugin::instantiate(b"not wasm", Limits::default()).is_err());
+    }
+}
@@ -3,6 +3,7 @@
 //! Native implementation of the 'ws' npm package using tokio-tungstenite.
 //! Provides WebSocket client and server functionality.
 
+use perry_runtime::buffer_pool;
 use perry_runtime::{js_string_from_bytes, JSValue, StringHeader, ClosureHeader, js_closure_call0, js_closure_call1};
 use futures_util::{SinkExt, StreamExt};
 use std::collections::HashMap;
@@ -78,6 +79,36 @@ unsafe fn string_from_header(ptr: *const StringHeader) -> Option<String> {
     std::str::from_utf8(bytes).ok().map(|s| s.to_string())
 }
 
+/// Copy an outgoing message into a pooled buffer; received messages refill the pool
+unsafe fn pooled_string_from_header(ptr: *const StringHeader) -> Option<String> {
+    if ptr.is_null() {
+        return None;
+    }
+    let len = (*ptr).length as usize;
+    let data_ptr = (ptr as *const u8).add(std::mem::size_of::<StringHeader>());
+    let mut bytes = buffer_pool::take(len);
+    bytes.extend_from_slice(std::slice::from_raw_parts(data_ptr, len));
+    String::from_utf8(bytes)
+        .map_err(|err| buffer_pool::give(err.into_bytes()))
+        .ok()
+}
+
+/// Text of a binary frame: the frame's own bytes when they are UTF-8, a lossy copy otherwise
+fn binary_text(data: Vec<u8>) -> String {
+    String::from_utf8(data).unwrap_or_else(|err| {
+        let text = String::from_utf8_lossy(err.as_bytes()).into_owned();
+        buffer_pool::give(err.into_bytes());
+        text
+    })
+}
+
+/// Copy a received message into a JS string and hand its buffer to the pool
+fn js_string_from_message(message: String) -> *mut StringHeader {
+    let str_ptr = js_string_from_bytes(message.as_ptr(), message.len() as u32);
+    buffer_pool::give(message.into_bytes());
+    str_ptr
+}
+
 /// Create a new WebSocket connection
 /// new WebSocket(url) -> Promise<WebSocket>
 #[no_mangle]
@@ -221,7 +252,7 @@ pub unsafe extern "C" fn js_ws_connect(url_ptr: *const StringHeader) -> *mut per
 #[no_mangle]
 pub unsafe extern "C" fn js_ws_send(handle: i64, message_ptr: *const StringHeader) {
     let ws_id = handle as usize;
-    let message = match string_from_header(message_ptr) {
+    let message = match pooled_string_from_header(message_ptr) {
         Some(m) => m,
         None => return,
     };
@@ -290,7 +321,7 @@ pub extern "C" fn js_ws_receive(handle: i64) -> *mut StringHeader {
                 std::ptr::null_mut()
             } else {
                 let msg = conn.messages.remove(0);
-                js_string_from_bytes(msg.as_ptr(), msg.len() as u32)
+                js_string_from_message(msg)
             }
         }
         None => std::ptr::null_mut(),
@@ -316,7 +347,7 @@ pub unsafe extern "C" fn js_ws_wait_for_message(handle: i64, timeout_ms: f64) ->
                 if let Some(conn) = guard.get_mut(&ws_id) {
                     if !conn.messages.is_empty() {
                         let msg = conn.messages.remove(0);
-                        let result_str = js_string_from_bytes(msg.as_ptr(), msg.len() as u32);
+                        let result_str = js_string_from_message(msg);
                         let result_bits = JSValue::pointer(result_str as *const u8).bits();
                         queue_promise_resolution(promise_ptr, true, result_bits);
                         return;
@@ -551,7 +582,7 @@ pub unsafe extern "C" fn js_ws_server_new(opts_f64: f64) -> Handle {
                                                 }
                                                 Ok(Message::Binary(data)) => {
                                                     // Convert binary to string representation for now
-                                                    let text = String::from_utf8_lossy(&data).to_string();
+                                                    let text = binary_text(data);
                                                     let has_listeners = WS_CLIENT_LISTENERS.lock().unwrap()
                                                         .get(&ws_id_recv)
                                                         .map(|l| l.listeners.get("message").map(|v| !v.is_empty()).unwrap_or(false))
@@ -687,7 +718,7 @@ pub unsafe extern "C" fn js_ws_process_pending() -> i32 {
                 };
 
                 // Create string on main thread and NaN-box with STRING_TAG
-                let msg_str = js_string_from_bytes(message.as_ptr(), message.len() as u32);
+                let msg_str = js_string_from_message(message);
                 let msg_f64 = f64::from_bits(
                     0x7FFF_0000_0000_0000u64 | (msg_str as u64 & 0x0000_FFFF_FFFF_FFFF)
                 );
@@ -0,0 +1,183 @@
+//! Whole-Program Dead Code Elimination for Perry HIR
+//!
+//! Before codegen, the driver drops the functions no code can reach, so they are never
+//! compiled. Every module's top-level statements run (the entry module's as `main`, the
+//! others' as module inits called from it), so they are the roots, together with class
+//! members, which are kept whole. A function is reachable when a reachable body refers
+//! to it: directly (`FuncRef`), through an import (`ExternFuncRef`, by exported name) or
+//! as a decorator. Exported symbols are named after the function alone, so names are
+//! matched in every module; a module imported as a namespace keeps all its exports.
+//!
+//! Bodies are searched with `perry_hir::walk`, which goes through every kind of
+//! expression, including callbacks handed to native modules. The linker then drops the
+//! runtime and stdlib code nothing calls (`--gc-sections`, `-dead_strip`).
+//!
+//! Objects written for another linker (`--no-link`) keep every export as a root too: the
+//! code they are linked with can call any of them.
+
+use perry_hir::walk::{walk_expr, Visit};
+use perry_hir::{Class, Decorator, Export, Expr, Function, ImportSpecifier, Module, ModuleKind, Param};
+use perry_types::FuncId;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+
+/// Functions and names a piece of HIR refers to
+#[derive(Default)]
+struct References {
+    funcs: Vec<FuncId>,
+    names: Vec<String>,
+}
+
+impl References {
+    fn of_function(func: &Function) -> Self {
+        let mut refs = References::default();
+        refs.function(func);
+        refs
+    }
+
+    /// The references in a module's top-level code: its statements, global initializers
+    /// and classes
+    fn of_module(module: &Module) -> Self {
+        let mut refs = References::default();
+        refs.visit_stmts(&module.init);
+        for init in module.globals.iter().filter_map(|global| global.init.as_ref()) {
+            refs.visit_expr(init);
+        }
+        for class in &module.classes {
+            refs.class(class);
+        }
+        refs
+    }
+
+    fn function(&mut self, func: &Function) {
+        self.decorators(&func.decorators);
+        for Param { default, .. } in &func.params {
+            if let Some(default) = default {
+                self.visit_expr(default);
+            }
+        }
+        self.visit_stmts(&func.body);
+    }
+
+    fn decorators(&mut self, decorators: &[Decorator]) {
+        for decorator in decorators {
+            self.names.push(decorator.name.clone());
+            for arg in &decorator.args {
+                self.visit_expr(arg);
+            }
+        }
+    }
+
+    /// Class members are kept whole
+    fn class(&mut self, class: &Class) {
+        for init in class.fields.iter().chain(&class.static_fields).filter_map(|field| field.init.as_ref()) {
+            self.visit_expr(init);
+        }
+        let methods = class.constructor.iter()
+            .chain(&class.methods)
+            .chain(&class.static_methods)
+            .chain(class.getters.iter().chain(&class.setters).map(|(_, func)| func));
+        for func in methods {
+            self.function(func);
+        }
+    }
+}
+
+impl Visit for References {
+    fn visit_expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::FuncRef(id) => self.funcs.push(*id),
+            Expr::ExternFuncRef { name, .. } => self.names.push(name.clone()),
+            _ => {}
+        }
+        walk_expr(self, expr);
+    }
+}
+
+/// Remove the functions of `modules` that no module's top-level code or classes can
+/// reach, nor with `keep_exports` any module's exports. Returns how many were removed.
+pub fn eliminate_dead_functions(modules: &mut HashMap<PathBuf, Module>, keep_exports: bool) -> usize {
+    let mut paths: Vec<PathBuf> = modules.keys().cloned().collect();
+    paths.sort();
+
+    // Functions by every name they can be referred to by: their own, and the names
+    // they are exported under
+    let mut by_name: HashMap<&str, Vec<(usize, FuncId)>> = HashMap::new();
+    for (index, path) in paths.iter().enumerate() {
+        let module = &modules[path];
+        for func in &module.functions {
+            by_name.entry(func.name.as_str()).or_default().push((index, func.id));
+        }
+        for (exported, id) in &module.exported_functions {
+            by_name.entry(exported.as_str()).or_default().push((index, *id));
+        }
+        for export in &module.exports {
+            if let Export::Named { local, exported } = export {
+                if let Some(func) = module.functions.iter().find(|f| &f.name == local) {
+                    by_name.entry(exported.as_str()).or_default().push((index, func.id));
+                }
+            }
+        }
+    }
+
+    // Modules imported as a namespace: their exports are reached through property reads
+    let namespaced: HashSet<&str> = modules.values()
+        .flat_map(|module| &module.imports)
+        .filter(|import| import.module_kind == ModuleKind::NativeCompiled)
+        .filter(|import| import.specifiers.iter().any(|s| matches!(s, ImportSpecifier::Namespace { .. })))
+        .filter_map(|import| import.resolved_path.as_deref())
+        .collect();
+
+    let mut reachable: HashSet<(usize, FuncId)> = HashSet::new();
+    let mut pending: Vec<(usize, References)> = Vec::new();
+    for (index, path) in paths.iter().enumerate() {
+        let module = &modules[path];
+        pending.push((index, References::of_module(module)));
+        if keep_exports || namespaced.contains(path.to_string_lossy().as_ref()) {
+            let named = module.exports.iter().filter_map(|export| match export {
+                Export::Named { local, .. } => module.functions.iter().find(|f| &f.name == local).map(|f| f.id),
+                _ => None,
+            });
+            let exported = module.functions.iter().filter(|f| f.is_exported).map(|f| f.id)
+                .chain(module.exported_functions.iter().map(|(_, id)| *id))
+                .chain(named);
+            pending.push((index, References { funcs: exported.collect(), names: Vec::new() }));
+        }
+    }
+
+    let functions: Vec<HashMap<FuncId, &Function>> = paths.iter()
+        .map(|path| modules[path].functions.iter().map(|f| (f.id, f)).collect())
+        .collect();
+    while let Some((index, refs)) = pending.pop() {
+        let named = refs.names.iter()
+            .flat_map(|name| by_name.get(name.as_str()).into_iter().flatten().copied());
+        let direct = refs.funcs.iter().map(|id| (index, *id));
+        for (index, id) in direct.chain(named).collect::<Vec<_>>() {
+            let Some(func) = functions[index].get(&id) else { continue };
+            if reachable.insert((index, id)) {
+                pending.push((index, References::of_function(func)));
+            }
+        }
+    }
+
+    let mut removed = 0;
+    for (index, path) in paths.iter().enumerate() {
+        let module = modules.get_mut(path).expect("path was collected from the map");
+        let dead: HashSet<FuncId> = module.functions.iter()
+            .map(|f| f.id)
+            .filter(|id| !reachable.contains(&(index, *id)))
+            .collect();
+        if dead.is_empty() {
+            continue;
+        }
+        let dead_names: HashSet<String> = module.functions.iter()
+            .filter(|f| dead.contains(&f.id))
+            .map(|f| f.name.clone())
+            .collect();
+        module.functions.retain(|f| !dead.contains(&f.id));
+        module.exported_functions.retain(|(_, id)| !dead.contains(id));
+        module.specializations.retain(|s| !dead_names.contains(&s.name));
+        removed += dead.len();
+    }
+    removed
+}
@@ -0,0 +1,410 @@
+//! Dead Statement Elimination for Perry HIR
+//!
+//! Removes the code of a module that can never run or whose result nothing reads, so it
+//! is neither compiled nor seen by the passes after this one:
+//! - statements after a `return`, `throw`, `break` or `continue`, or after an `if` whose
+//!   branches both end in one
+//! - the branch an `if` or `?:` never takes, and `while` and `for` loops that never run,
+//!   when the condition is a literal, a `const` bound to one, or `!` of either
+//! - `let` and `const` bindings nothing refers to whose initializer has no side effects
+//!   (a literal, a local, a function or class reference, a closure, or an object or array
+//!   literal of those), and expression statements of that kind
+//!
+//! Module-level bindings are kept, as other modules can import them; the bindings of the
+//! module's functions, methods and closures are not. A binding declared after a `return`
+//! that is still referred to (a `var` read before its declaration) is kept too.
+//!
+//! References to locals are counted in the whole module with `perry_hir::walk`, as in
+//! scalar replacement. Removing code uncounts the references in it, and statement lists
+//! are pruned from the end, so bindings only used by each other go together.
+
+use perry_hir::walk::{walk_expr, walk_expr_mut, walk_stmt, Visit, VisitMut};
+use perry_hir::{Expr, Function, Module, Stmt, UnaryOp};
+use perry_types::LocalId;
+use std::collections::{HashMap, HashSet};
+
+/// References to each local in the module
+#[derive(Default)]
+struct Uses {
+    /// References other than the declaration
+    counts: HashMap<LocalId, usize>,
+    /// Locals assigned after their declaration
+    written: HashSet<LocalId>,
+}
+
+impl Uses {
+    /// A visitor counting the references it visits, or with `forget`, uncounting them
+    fn count(&mut self, forget: bool) -> Counter<'_> {
+        Counter { uses: self, forget }
+    }
+
+    fn used(&self, id: LocalId) -> bool {
+        self.counts.get(&id).is_some_and(|count| *count > 0)
+    }
+}
+
+struct Counter<'a> {
+    uses: &'a mut Uses,
+    forget: bool,
+}
+
+impl Counter<'_> {
+    fn note(&mut self, id: LocalId, write: bool) {
+        if self.forget {
+            if let Some(count) = self.uses.counts.get_mut(&id) {
+                *count = count.saturating_sub(1);
+            }
+        } else {
+            *self.uses.counts.entry(id).or_default() += 1;
+            if write {
+                self.uses.written.insert(id);
+            }
+        }
+    }
+}
+
+impl Visit for Counter<'_> {
+    fn visit_expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::LocalGet(id) => self.note(*id, false),
+            Expr::LocalSet(id, _)
+            | Expr::Update { id, .. }
+            | Expr::ArrayPop(id)
+            | Expr::ArrayShift(id)
+            | Expr::ArrayPush { array_id: id, .. }
+            | Expr::ArrayUnshift { array_id: id, .. }
+            | Expr::ArraySplice { array_id: id, .. }
+            | Expr::SetAdd { set_id: id, .. } => self.note(*id, true),
+            Expr::Closure { captures, mutable_captures, .. } => {
+                for id in captures.iter().chain(mutable_captures) {
+                    self.note(*id, false);
+                }
+            }
+            _ => {}
+        }
+        walk_expr(self, expr);
+    }
+}
+
+/// The functions and methods of `module`
+fn functions(module: &Module) -> impl Iterator<Item = &Function> {
+    let methods = module.classes.iter().flat_map(|class| {
+        class.constructor.iter()
+            .chain(&class.methods)
+            .chain(&class.static_methods)
+            .chain(class.getters.iter().chain(&class.setters).map(|(_, func)| func))
+    });
+    module.functions.iter().chain(methods)
+}
+
+/// Visit the initializers, parameter defaults, decorator arguments and bodies of `module`
+fn visit_module(visitor: &mut impl Visit, module: &Module) {
+    for func in functions(module) {
+        for default in func.params.iter().filter_map(|param| param.default.as_ref()) {
+            visitor.visit_expr(default);
+        }
+        for arg in func.decorators.iter().flat_map(|decorator| &decorator.args) {
+            visitor.visit_expr(arg);
+        }
+        visitor.visit_stmts(&func.body);
+    }
+    let fields = module.classes.iter().flat_map(|class| class.fields.iter().chain(&class.static_fields));
+    let inits = fields.filter_map(|field| field.init.as_ref())
+        .chain(module.globals.iter().filter_map(|global| global.init.as_ref()));
+    for init in inits {
+        visitor.visit_expr(init);
+    }
+    visitor.visit_stmts(&module.init);
+}
+
+/// Whether a condition is always true or always false, if it is either
+fn truthiness(expr: &Expr, consts: &HashMap<LocalId, bool>) -> Option<bool> {
+    match expr {
+        Expr::Undefined | Expr::Null => Some(false),
+        Expr::Bool(b) => Some(*b),
+        Expr::Integer(n) => Some(*n != 0),
+        Expr::Number(n) => Some(*n != 0.0 && !n.is_nan()),
+        Expr::String(s) => Some(!s.is_empty()),
+        Expr::LocalGet(id) => consts.get(id).copied(),
+        Expr::Unary { op: UnaryOp::Not, operand } => truthiness(operand, consts).map(|b| !b),
+        Expr::Narrowed { value, .. } => truthiness(value, consts),
+        _ => None,
+    }
+}
+
+/// Whether evaluating an expression can have no effect other than producing its value
+fn is_pure(expr: &Expr) -> bool {
+    match expr {
+        Expr::Undefined
+        | Expr::Null
+        | Expr::Bool(_)
+        | Expr::Number(_)
+        | Expr::Integer(_)
+        | Expr::BigInt(_)
+        | Expr::String(_)
+        | Expr::LocalGet(_)
+        | Expr::GlobalGet(_)
+        | Expr::FuncRef(_)
+        | Expr::ExternFuncRef { .. }
+        | Expr::ClassRef(_)
+        | Expr::Closure { .. } => true,
+        Expr::Object(props) => props.iter().all(|(_, value)| is_pure(value)),
+        Expr::Array(items) => items.iter().all(is_pure),
+        Expr::TypeOf(value) | Expr::Narrowed { value, .. } => is_pure(value),
+        Expr::Conditional { condition, then_expr, else_expr } => {
+            is_pure(condition) && is_pure(then_expr) && is_pure(else_expr)
+        }
+        _ => false,
+    }
+}
+
+/// Whether control never continues past a statement to the next one
+fn exits(stmt: &Stmt) -> bool {
+    match stmt {
+        Stmt::Break | Stmt::Continue | Stmt::Return(_) | Stmt::Throw(_) => true,
+        Stmt::If { then_branch, else_branch: Some(else_branch), .. } => {
+            then_branch.iter().any(exits) && else_branch.iter().any(exits)
+        }
+        _ => false,
+    }
+}
+
+/// `const` bindings initialized with a literal condition, and whether it is true
+struct Consts<'a> {
+    written: &'a HashSet<LocalId>,
+    consts: HashMap<LocalId, bool>,
+}
+
+impl Visit for Consts<'_> {
+    fn visit_stmt(&mut self, stmt: &Stmt) {
+        if let Stmt::Let { id, mutable: false, init: Some(init), .. } = stmt {
+            if let (Some(truthy), false) = (truthiness(init, &HashMap::new()), self.written.contains(id)) {
+                self.consts.insert(*id, truthy);
+            }
+        }
+        walk_stmt(self, stmt);
+    }
+}
+
+/// Locals declared anywhere in a statement
+#[derive(Default)]
+struct Declared(Vec<LocalId>);
+
+impl Visit for Declared {
+    fn visit_stmt(&mut self, stmt: &Stmt) {
+        if let Stmt::Let { id, .. } = stmt {
+            self.0.push(*id);
+        }
+        walk_stmt(self, stmt);
+    }
+}
+
+struct Pass {
+    uses: Uses,
+    consts: HashMap<LocalId, bool>,
+    removed: usize,
+}
+
+impl Pass {
+    /// Prune a statement list; `locals` if its bindings belong to a function or closure
+    fn stmts(&mut self, stmts: &mut Vec<Stmt>, locals: bool) {
+        let mut reachable = Vec::with_capacity(stmts.len());
+        let mut unreachable = Vec::new();
+        for stmt in stmts.drain(..) {
+            if reachable.last().is_some_and(exits) {
+                unreachable.push(stmt);
+            } else {
+                reachable.extend(self.stmt(stmt, locals));
+            }
+        }
+
+        // Unreachable statements declaring a binding something still refers to stay, and
+        // so then do those their references keep alive. Module-level bindings always stay.
+        let declares: Vec<Vec<LocalId>> = unreachable.iter()
+            .map(|stmt| {
+                let mut declared = Declared::default();
+                declared.visit_stmt(stmt);
+                declared.0
+            })
+            .collect();
+        let mut kept: Vec<bool> = declares.iter().map(|ids| !locals && !ids.is_empty()).collect();
+        for (stmt, kept) in unreachable.iter().zip(&kept) {
+            if !kept {
+                self.uses.count(true).visit_stmt(stmt);
+            }
+        }
+        loop {
+            let mut changed = false;
+            for ((stmt, ids), kept) in unreachable.iter().zip(&declares).zip(kept.iter_mut()) {
+                if !*kept && ids.iter().any(|id| self.uses.used(*id)) {
+                    self.uses.count(false).visit_stmt(stmt);
+                    *kept = true;
+                    changed = true;
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+        for (stmt, kept) in unreachable.into_iter().zip(kept) {
+            if kept {
+                reachable.push(stmt);
+            } else {
+                self.removed += 1;
+            }
+        }
+
+        let mut live = Vec::with_capacity(reachable.len());
+        for stmt in reachable.into_iter().rev() {
+            if self.is_dead(&stmt, locals) {
+                self.uses.count(true).visit_stmt(&stmt);
+                self.removed += 1;
+            } else {
+                live.push(stmt);
+            }
+        }
+        live.reverse();
+        *stmts = live;
+    }
+
+    /// An unused binding or an expression statement without effects
+    fn is_dead(&self, stmt: &Stmt, locals: bool) -> bool {
+        match stmt {
+            Stmt::Let { id, init, .. } => locals && !self.uses.used(*id) && init.as_ref().is_none_or(is_pure),
+            Stmt::Expr(expr) => is_pure(expr),
+            _ => false,
+        }
+    }
+
+    /// The statements a statement becomes
+    fn stmt(&mut self, mut stmt: Stmt, locals: bool) -> Vec<Stmt> {
+        match &mut stmt {
+            Stmt::If { condition, then_branch, else_branch } => {
+                self.visit_expr_mut(condition);
+                if let Some(taken) = truthiness(condition, &self.consts) {
+                    self.uses.count(true).visit_expr(condition);
+                    let else_branch = else_branch.take().unwrap_or_default();
+                    let then_branch = std::mem::take(then_branch);
+                    let (mut branch, untaken) = if taken { (then_branch, else_branch) } else { (else_branch, then_branch) };
+                    self.uses.count(true).visit_stmts(&untaken);
+                    self.stmts(&mut branch, locals);
+                    self.removed += 1;
+                    return branch;
+                }
+                self.stmts(then_branch, locals);
+                if let Some(else_branch) = else_branch {
+                    self.stmts(else_branch, locals);
+                }
+            }
+            Stmt::While { condition, body } => {
+                self.visit_expr_mut(condition);
+                if truthiness(condition, &self.consts) == Some(false) {
+                    self.uses.count(true).visit_stmt(&stmt);
+                    self.removed += 1;
+                    return Vec::new();
+                }
+                self.stmts(body, locals);
+            }
+            Stmt::For { init, condition, update, body } => {
+                if let Some(init) = init {
+                    self.simple_stmt(init);
+                }
+                for expr in condition.iter_mut().chain(update.iter_mut()) {
+                    self.visit_expr_mut(expr);
+                }
+                if condition.as_ref().and_then(|c| truthiness(c, &self.consts)) == Some(false) {
+                    // The initializer still runs once
+                    let init = init.take();
+                    self.uses.count(true).visit_stmt(&stmt);
+                    self.removed += 1;
+                    return init.map(|init| *init).into_iter().collect();
+                }
+                self.stmts(body, locals);
+            }
+            Stmt::Try { body, catch, finally } => {
+                self.stmts(body, locals);
+                if let Some(catch) = catch {
+                    self.stmts(&mut catch.body, locals);
+                }
+                if let Some(finally) = finally {
+                    self.stmts(finally, locals);
+                }
+            }
+            Stmt::Switch { discriminant, cases } => {
+                self.visit_expr_mut(discriminant);
+                for case in cases {
+                    if let Some(test) = &mut case.test {
+                        self.visit_expr_mut(test);
+                    }
+                    self.stmts(&mut case.body, locals);
+                }
+            }
+            _ => self.simple_stmt(&mut stmt),
+        }
+        vec![stmt]
+    }
+
+    /// Prune the expressions of a statement without statements of its own
+    fn simple_stmt(&mut self, stmt: &mut Stmt) {
+        match stmt {
+            Stmt::Let { init: Some(expr), .. } | Stmt::Expr(expr) | Stmt::Return(Some(expr)) | Stmt::Throw(expr) => {
+                self.visit_expr_mut(expr)
+            }
+            _ => {}
+        }
+    }
+
+    /// Take the branch a `?:` with a constant condition always takes
+    fn constant_branch(&mut self, expr: &mut Expr) -> Option<Expr> {
+        let Expr::Conditional { condition, then_expr, else_expr } = expr else { return None };
+        let (taken, untaken) = if truthiness(condition, &self.consts)? { (then_expr, else_expr) } else { (else_expr, then_expr) };
+        self.uses.count(true).visit_expr(condition);
+        self.uses.count(true).visit_expr(untaken);
+        self.removed += 1;
+        Some(std::mem::replace(&mut **taken, Expr::Undefined))
+    }
+}
+
+/// Prunes the closures and conditional expressions in an expression
+impl VisitMut for Pass {
+    fn visit_expr_mut(&mut self, expr: &mut Expr) {
+        while let Some(taken) = self.constant_branch(expr) {
+            *expr = taken;
+        }
+        walk_expr_mut(self, expr);
+    }
+
+    // Only a closure's body is reached from an expression
+    fn visit_stmts_mut(&mut self, stmts: &mut Vec<Stmt>) {
+        self.stmts(stmts, true);
+    }
+}
+
+/// Remove the unreachable statements, untaken constant branches and unused side-effect
+/// free bindings of `module`. Returns how many statements and branches were removed.
+pub fn eliminate_dead_statements(module: &mut Module) -> usize {
+    let mut uses = Uses::default();
+    let mut counter = uses.count(false);
+    visit_module(&mut counter, module);
+    // Functions capture module-level locals
+    for id in functions(module).flat_map(|func| &func.captures) {
+        counter.note(*id, false);
+    }
+    let mut consts = Consts { written: &uses.written, consts: HashMap::new() };
+    visit_module(&mut consts, module);
+    let consts = consts.consts;
+
+    let mut bodies: Vec<(&mut Vec<Stmt>, bool)> = vec![(&mut module.init, false)];
+    bodies.extend(module.functions.iter_mut().map(|f| (&mut f.body, true)));
+    for class in &mut module.classes {
+        bodies.extend(class.constructor.iter_mut().map(|f| (&mut f.body, true)));
+        bodies.extend(class.methods.iter_mut().chain(&mut class.static_methods).map(|f| (&mut f.body, true)));
+        bodies.extend(class.getters.iter_mut().chain(&mut class.setters).map(|(_, f)| (&mut f.body, true)));
+    }
+
+    let mut pass = Pass { uses, consts, removed: 0 };
+    for (body, locals) in bodies {
+        pass.stmts(body, locals);
+    }
+    pass.removed
+}
@@ -0,0 +1,432 @@
+//! Scalar Replacement of Non-Escaping Objects and Arrays for Perry HIR
+//!
+//! An object or array literal bound to a local, whose every use reads or writes one of its
+//! fields by a constant key (`opts.retries`, `opts["delay"]`, `pair[1]`, `pair.length`),
+//! can't be observed by anything else, so it doesn't need to exist: the binding is replaced
+//! by one local per field. `const opts = { retries: 3, delay: 10 }` becomes
+//! `const opts$retries = 3; const opts$delay = 10;`, and the options objects and tuples a
+//! function builds and takes apart itself are never allocated.
+//!
+//! Any other use lets the value escape, and the literal is kept: passing, returning,
+//! storing or comparing it, capturing it in a closure, reassigning the binding, reading a
+//! key it doesn't have, calling a method through it (the object would be `this`) or
+//! deleting from it. Uses are searched in the whole module with `perry_hir::walk`, as in
+//! dead code elimination, so no kind of expression is missed.
+//!
+//! Closures are not replaced: calling one needs its environment. Neither are module-level
+//! bindings, which the module's functions can read. The pass runs once generics are
+//! specialized and before local type inference, which then types the field locals from
+//! their initializers.
+
+use perry_hir::walk::{walk_expr, walk_expr_mut, walk_stmt, Visit, VisitMut};
+use perry_hir::{BinaryOp, CatchClause, Expr, Function, Module, Stmt, UpdateOp};
+use perry_types::{LocalId, Type};
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+
+/// Most fields a replaced literal can have
+const MAX_FIELDS: usize = 16;
+
+/// Fields of a literal, in the order they are initialized
+#[derive(Clone, PartialEq)]
+enum Layout {
+    Object(Vec<String>),
+    Array(usize),
+}
+
+/// What a constant key names in a literal
+#[derive(Clone, Copy)]
+enum Slot {
+    Field(usize),
+    /// The length of an array
+    Length,
+}
+
+/// What an access does with the field it names
+#[derive(Clone, Copy, PartialEq)]
+enum Access {
+    Read,
+    Write,
+    /// `++` (true) or `--` (false)
+    Step(bool),
+    /// A compound update other than ++/--
+    Update,
+}
+
+impl Access {
+    fn update(op: &BinaryOp) -> Self {
+        match op {
+            BinaryOp::Add => Access::Step(true),
+            BinaryOp::Sub => Access::Step(false),
+            _ => Access::Update,
+        }
+    }
+}
+
+impl Layout {
+    /// The layout of a literal that can be replaced
+    fn of(init: &Expr) -> Option<Self> {
+        match init {
+            Expr::Object(props) => {
+                let keys: Vec<String> = props.iter().map(|(key, _)| key.clone()).collect();
+                let distinct: HashSet<&String> = keys.iter().collect();
+                let replaceable = distinct.len() == keys.len()
+                    && keys.len() <= MAX_FIELDS
+                    && !keys.iter().any(|key| key == "__proto__");
+                replaceable.then_some(Layout::Object(keys))
+            }
+            Expr::Array(elements) => (elements.len() <= MAX_FIELDS).then_some(Layout::Array(elements.len())),
+            _ => None,
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            Layout::Object(keys) => keys.len(),
+            Layout::Array(len) => *len,
+        }
+    }
+
+    fn slot(&self, key: &str) -> Option<Slot> {
+        match self {
+            Layout::Object(keys) => keys.iter().position(|k| k == key).map(Slot::Field),
+            Layout::Array(_) if key == "length" => Some(Slot::Length),
+            Layout::Array(len) => key.parse::<usize>().ok().filter(|index| index < len).map(Slot::Field),
+        }
+    }
+
+    /// Name of the local replacing field `index` of the literal bound to `name`
+    fn field_name(&self, name: &str, index: usize) -> String {
+        match self {
+            Layout::Object(keys) => format!("{}${}", name, keys[index]),
+            Layout::Array(_) => format!("{}${}", name, index),
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Candidate {
+    layout: Layout,
+    escaped: bool,
+    /// Fields assigned after initialization
+    written: HashSet<usize>,
+    /// Locals replacing the fields
+    fields: Vec<LocalId>,
+}
+
+/// A constant index as a key: `o["key"]` or `a[0]`
+fn constant_key(index: &Expr) -> Option<Cow<'_, str>> {
+    let n = match index {
+        Expr::String(key) => return Some(Cow::Borrowed(key)),
+        Expr::Number(n) => *n,
+        Expr::Integer(n) => *n as f64,
+        _ => return None,
+    };
+    (n >= 0.0 && n.fract() == 0.0).then(|| Cow::Owned((n as u64).to_string()))
+}
+
+/// The local, key and kind of an access to a field of a local by a constant key:
+/// `o.key`, `o["key"]` or `a[0]`
+fn field_access(expr: &Expr) -> Option<(LocalId, Cow<'_, str>, Access)> {
+    let (object, key, access) = match expr {
+        Expr::PropertyGet { object, property } => (object, Cow::Borrowed(property.as_str()), Access::Read),
+        Expr::PropertySet { object, property, .. } => (object, Cow::Borrowed(property.as_str()), Access::Write),
+        Expr::PropertyUpdate { object, property, op, .. } => (object, Cow::Borrowed(property.as_str()), Access::update(op)),
+        Expr::IndexGet { object, index } => (object, constant_key(index)?, Access::Read),
+        Expr::IndexSet { object, index, .. } => (object, constant_key(index)?, Access::Write),
+        Expr::IndexUpdate { object, index, op, .. } => (object, constant_key(index)?, Access::update(op)),
+        _ => return None,
+    };
+    match **object {
+        Expr::LocalGet(id) => Some((id, key, access)),
+        _ => None,
+    }
+}
+
+/// The candidate, slot and kind of an access to a candidate by a constant key; None for
+/// any other expression, and for accesses the literal can't be replaced under (writing an
+/// array's length, a compound update other than ++/--)
+fn access_slot(candidates: &HashMap<LocalId, Candidate>, expr: &Expr) -> Option<(LocalId, Slot, Access)> {
+    let (id, key, access) = field_access(expr)?;
+    let slot = candidates.get(&id)?.layout.slot(&key)?;
+    let allowed = match slot {
+        Slot::Length => access == Access::Read,
+        Slot::Field(_) => access != Access::Update,
+    };
+    allowed.then_some((id, slot, access))
+}
+
+/// Literals bound by the `let`/`const` statements of a body, including those of nested
+/// blocks and closures. Only statement lists are searched: a `for` loop's declaration
+/// can't be replaced by several
+#[derive(Default)]
+struct Literals(Vec<(LocalId, Layout)>);
+
+impl Visit for Literals {
+    fn visit_stmts(&mut self, stmts: &[Stmt]) {
+        for stmt in stmts {
+            if let Stmt::Let { id, init: Some(init), .. } = stmt {
+                if let Some(layout) = Layout::of(init) {
+                    self.0.push((*id, layout));
+                }
+            }
+            self.visit_stmt(stmt);
+        }
+    }
+}
+
+/// Uses of the candidates in the module
+struct Uses {
+    candidates: HashMap<LocalId, Candidate>,
+    /// Largest local id declared in the module
+    max_local: LocalId,
+}
+
+impl Uses {
+    fn declared(&mut self, id: LocalId) {
+        self.max_local = self.max_local.max(id);
+    }
+
+    fn escape(&mut self, id: LocalId) {
+        self.declared(id);
+        if let Some(candidate) = self.candidates.get_mut(&id) {
+            candidate.escaped = true;
+        }
+    }
+
+    fn module(&mut self, module: &Module) {
+        let methods = module.classes.iter().flat_map(|class| {
+            class.constructor.iter()
+                .chain(&class.methods)
+                .chain(&class.static_methods)
+                .chain(class.getters.iter().chain(&class.setters).map(|(_, func)| func))
+        });
+        for func in module.functions.iter().chain(methods) {
+            self.function(func);
+        }
+        let fields = module.classes.iter().flat_map(|class| class.fields.iter().chain(&class.static_fields));
+        let inits = fields.filter_map(|field| field.init.as_ref())
+            .chain(module.globals.iter().filter_map(|global| global.init.as_ref()));
+        for init in inits {
+            self.visit_expr(init);
+        }
+        self.visit_stmts(&module.init);
+    }
+
+    fn function(&mut self, func: &Function) {
+        for id in &func.captures {
+            self.escape(*id);
+        }
+        for param in &func.params {
+            self.escape(param.id);
+            if let Some(default) = &param.default {
+                self.visit_expr(default);
+            }
+        }
+        for arg in func.decorators.iter().flat_map(|decorator| &decorator.args) {
+            self.visit_expr(arg);
+        }
+        self.visit_stmts(&func.body);
+    }
+}
+
+impl Visit for Uses {
+    fn visit_stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            // The declaration itself isn't a use
+            Stmt::Let { id, .. } => self.declared(*id),
+            Stmt::Try { catch: Some(CatchClause { param: Some((id, _)), .. }), .. } => self.declared(*id),
+            _ => {}
+        }
+        walk_stmt(self, stmt);
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        if let Some((id, slot, access)) = access_slot(&self.candidates, expr) {
+            if let (Slot::Field(index), false) = (slot, access == Access::Read) {
+                if let Some(candidate) = self.candidates.get_mut(&id) {
+                    candidate.written.insert(index);
+                }
+            }
+            // Everything but the object
+            match expr {
+                Expr::PropertySet { value, .. } => self.visit_expr(value),
+                Expr::IndexGet { index, .. } | Expr::IndexUpdate { index, .. } => self.visit_expr(index),
+                Expr::IndexSet { index, value, .. } => {
+                    self.visit_expr(index);
+                    self.visit_expr(value);
+                }
+                _ => {}
+            }
+            return;
+        }
+        match expr {
+            Expr::Call { callee, .. } | Expr::CallSpread { callee, .. } | Expr::NewDynamic { callee, .. } => {
+                // A method called through the literal gets it as `this`
+                if let Some((id, ..)) = field_access(callee) {
+                    self.escape(id);
+                }
+            }
+            Expr::Delete(target) => {
+                if let Some((id, ..)) = field_access(target) {
+                    self.escape(id);
+                }
+            }
+            Expr::LocalGet(id)
+            | Expr::LocalSet(id, _)
+            | Expr::Update { id, .. }
+            | Expr::ArrayPop(id)
+            | Expr::ArrayShift(id)
+            | Expr::ArrayPush { array_id: id, .. }
+            | Expr::ArrayUnshift { array_id: id, .. }
+            | Expr::ArraySplice { array_id: id, .. }
+            | Expr::SetAdd { set_id: id, .. } => self.escape(*id),
+            Expr::Closure { params, captures, mutable_captures, .. } => {
+                for id in params.iter().map(|param| &param.id).chain(captures).chain(mutable_captures) {
+                    self.escape(*id);
+                }
+            }
+            _ => {}
+        }
+        walk_expr(self, expr);
+    }
+}
+
+/// Replaces the literals of a body that don't escape, and the accesses to them
+struct Rewrite<'a> {
+    replaced: &'a HashMap<LocalId, Candidate>,
+}
+
+impl Rewrite<'_> {
+    /// The locals replacing the literal a statement declares, if it declares a replaced one
+    fn field_decls(&self, stmt: &mut Stmt) -> Option<Vec<Stmt>> {
+        let Stmt::Let { id, name, init: Some(init), .. } = stmt else { return None };
+        let candidate = self.replaced.get(id)?;
+        let values: Vec<Expr> = match (init, &candidate.layout) {
+            (Expr::Object(props), Layout::Object(keys)) if props.len() == keys.len() => {
+                std::mem::take(props).into_iter().map(|(_, value)| value).collect()
+            }
+            (Expr::Array(elements), Layout::Array(len)) if elements.len() == *len => std::mem::take(elements),
+            _ => return None,
+        };
+        let decls = values.into_iter().enumerate().map(|(index, value)| Stmt::Let {
+            id: candidate.fields[index],
+            name: candidate.layout.field_name(name, index),
+            ty: Type::Any,
+            mutable: candidate.written.contains(&index),
+            init: Some(value),
+        });
+        Some(decls.collect())
+    }
+
+    /// The expression replacing an access to a replaced literal
+    fn replace_access(&self, expr: &mut Expr) -> Option<Expr> {
+        let (id, slot, access) = access_slot(self.replaced, expr)?;
+        let candidate = &self.replaced[&id];
+        let index = match slot {
+            Slot::Length => return Some(Expr::Number(candidate.layout.len() as f64)),
+            Slot::Field(index) => index,
+        };
+        let local = candidate.fields[index];
+        Some(match (access, expr) {
+            (Access::Read, _) => Expr::LocalGet(local),
+            (Access::Write, Expr::PropertySet { value, .. } | Expr::IndexSet { value, .. }) => {
+                Expr::LocalSet(local, std::mem::replace(value, Box::new(Expr::Undefined)))
+            }
+            (Access::Step(increment), Expr::PropertyUpdate { prefix, .. } | Expr::IndexUpdate { prefix, .. }) => {
+                let op = if increment { UpdateOp::Increment } else { UpdateOp::Decrement };
+                Expr::Update { id: local, op, prefix: *prefix }
+            }
+            _ => return None,
+        })
+    }
+}
+
+impl VisitMut for Rewrite<'_> {
+    fn visit_stmts_mut(&mut self, stmts: &mut Vec<Stmt>) {
+        for stmt in stmts.iter_mut() {
+            self.visit_stmt_mut(stmt);
+        }
+        let mut rewritten = Vec::with_capacity(stmts.len());
+        for mut stmt in stmts.drain(..) {
+            match self.field_decls(&mut stmt) {
+                Some(decls) => rewritten.extend(decls),
+                None => rewritten.push(stmt),
+            }
+        }
+        *stmts = rewritten;
+    }
+
+    fn visit_expr_mut(&mut self, expr: &mut Expr) {
+        walk_expr_mut(self, expr);
+        if let Some(replacement) = self.replace_access(expr) {
+            *expr = replacement;
+        }
+    }
+}
+
+/// The bodies of `module`'s functions and methods
+fn bodies(module: &mut Module) -> Vec<&mut Vec<Stmt>> {
+    let mut bodies: Vec<&mut Vec<Stmt>> = module.functions.iter_mut().map(|f| &mut f.body).collect();
+    for class in &mut module.classes {
+        bodies.extend(class.constructor.iter_mut().map(|f| &mut f.body));
+        bodies.extend(class.methods.iter_mut().chain(&mut class.static_methods).map(|f| &mut f.body));
+        bodies.extend(class.getters.iter_mut().chain(&mut class.setters).map(|(_, f)| &mut f.body));
+    }
+    bodies
+}
+
+/// Replace the object and array literals of `module`'s functions and methods that never
+/// escape with a local per field. Returns how many were replaced.
+pub fn scalar_replace_allocations(module: &mut Module) -> usize {
+    // Literals of every body; specializations of a generic function share the ids of its
+    // body, and with them its literals
+    let mut candidates: HashMap<LocalId, Candidate> = HashMap::new();
+    let mut declared = Vec::new();
+    for body in bodies(module) {
+        let mut literals = Literals::default();
+        literals.visit_stmts(body);
+        for (id, layout) in &literals.0 {
+            let candidate = candidates.entry(*id).or_insert_with(|| Candidate {
+                layout: layout.clone(),
+                escaped: false,
+                written: HashSet::new(),
+                fields: Vec::new(),
+            });
+            if candidate.layout != *layout {
+                candidate.escaped = true;
+            }
+        }
+        declared.push(literals.0);
+    }
+    if candidates.is_empty() {
+        return 0;
+    }
+
+    let mut uses = Uses { candidates, max_local: 0 };
+    uses.module(module);
+    let mut replaced: Vec<(LocalId, Candidate)> = uses.candidates.into_iter()
+        .filter(|(_, candidate)| !candidate.escaped)
+        .collect();
+    replaced.sort_by_key(|(id, _)| *id);
+    let mut next_local = uses.max_local + 1;
+    for (_, candidate) in &mut replaced {
+        candidate.fields = (0..candidate.layout.len() as LocalId).map(|i| next_local + i).collect();
+        next_local += candidate.layout.len() as LocalId;
+    }
+    let replaced: HashMap<LocalId, Candidate> = replaced.into_iter().collect();
+
+    let mut count = 0;
+    for (body, literals) in bodies(module).into_iter().zip(declared) {
+        // Only the literals a body declares are replaced in it
+        let here: HashMap<LocalId, Candidate> = literals.iter()
+            .filter_map(|(id, _)| Some((*id, replaced.get(id)?.clone())))
+            .collect();
+        if here.is_empty() {
+            continue;
+        }
+        Rewrite { replaced: &here }.visit_stmts_mut(body);
+        count += here.len();
+    }
+    count
+}
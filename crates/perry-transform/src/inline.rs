@@ -76,13 +76,16 @@ pub fn inline_functions(module: &mut Module) {
         inline_calls_in_stmts(&mut func.body, &func_candidates, &method_candidates, &class_names, &mut local_types, &mut local_id);
     }
 
-    // Phase 6: Inline calls in class method bodies
+    // Phase 6: Inline calls in constructors, methods and accessors
     for class in &mut module.classes {
-        for method in &mut class.methods {
-            // Skip if this method is itself a candidate (avoid recursion)
-            if method_candidates.contains_key(&(class.name.clone(), method.name.clone())) {
-                continue;
-            }
+        let class_name = &class.name;
+        let methods = class.methods.iter_mut()
+            // Skip methods that are themselves candidates (avoid recursion)
+            .filter(|method| !method_candidates.contains_key(&(class_name.clone(), method.name.clone())));
+        let others = class.constructor.iter_mut()
+            .chain(&mut class.static_methods)
+            .chain(class.getters.iter_mut().chain(&mut class.setters).map(|(_, f)| f));
+        for method in methods.chain(others) {
             let mut local_id = find_max_local_id(&method.body) + 1;
             let mut local_types: HashMap<LocalId, String> = HashMap::new();
             for param in &method.params {
@@ -96,12 +99,23 @@ pub fn inline_functions(module: &mut Module) {
 }
 
 /// Check if a function is suitable for inlining
-fn is_inlinable(func: &Function) -> bool {
+pub(crate) fn is_inlinable(func: &Function) -> bool {
     // Don't inline async functions
     if func.is_async {
         return false;
     }
 
+    // Don't inline memoized functions: the cache sits in their compiled wrapper
+    if func.memoize().is_some() {
+        return false;
+    }
+
+    // Don't inline functions with default parameters: their prologue runs in the callee,
+    // and a call that omits an argument would leave the parameter unbound once inlined
+    if func.params.iter().any(|p| p.default.is_some()) {
+        return false;
+    }
+
     // Don't inline functions with captures (closures)
     if !func.captures.is_empty() {
         return false;
@@ -268,6 +282,9 @@ fn find_max_local_id(stmts: &[Stmt]) -> LocalId {
             Expr::PropertyGet { object, .. } | Expr::PropertySet { object, .. } => {
                 check_expr(object, max_id);
             }
+            Expr::Narrowed { value, .. } => {
+                check_expr(value, max_id);
+            }
             _ => {}
         }
     }
@@ -821,7 +838,7 @@ fn substitute_locals(expr: &mut Expr, param_map: &HashMap<LocalId, Expr>, next_l
             substitute_locals(object, param_map, next_local_id);
             substitute_locals(value, param_map, next_local_id);
         }
-        Expr::TypeOf(inner) => {
+        Expr::TypeOf(inner) | Expr::Narrowed { value: inner, .. } => {
             substitute_locals(inner, param_map, next_local_id);
         }
         // Set operations
@@ -832,7 +849,7 @@ fn substitute_locals(expr: &mut Expr, param_map: &HashMap<LocalId, Expr>, next_l
         Expr::SetAdd { value, .. } => {
             substitute_locals(value, param_map, next_local_id);
         }
-        Expr::SetSize(set) | Expr::SetClear(set) => {
+        Expr::SetSize(set) | Expr::SetClear(set) | Expr::SetValues(set) => {
             substitute_locals(set, param_map, next_local_id);
         }
         // Map operations
@@ -845,7 +862,8 @@ fn substitute_locals(expr: &mut Expr, param_map: &HashMap<LocalId, Expr>, next_l
             substitute_locals(key, param_map, next_local_id);
             substitute_locals(value, param_map, next_local_id);
         }
-        Expr::MapSize(map) | Expr::MapClear(map) => {
+        Expr::MapSize(map) | Expr::MapClear(map) | Expr::MapKeys(map) |
+        Expr::MapValues(map) | Expr::MapEntries(map) => {
             substitute_locals(map, param_map, next_local_id);
         }
         // Array operations
@@ -903,7 +921,11 @@ fn substitute_locals(expr: &mut Expr, param_map: &HashMap<LocalId, Expr>, next_l
             }
         }
         // JSON operations
-        Expr::JsonStringify(inner) | Expr::JsonParse(inner) => {
+        Expr::JsonStringify(inner) | Expr::JsonCanonicalize(inner) | Expr::JsonParse(inner) => {
+            substitute_locals(inner, param_map, next_local_id);
+        }
+        // Object static methods
+        Expr::ObjectKeys(inner) | Expr::ObjectValues(inner) | Expr::ObjectEntries(inner) => {
             substitute_locals(inner, param_map, next_local_id);
         }
         // Math operations
@@ -1009,7 +1031,7 @@ fn substitute_this(expr: &mut Expr, obj_id: LocalId) {
         Expr::LocalSet(_, value) => {
             substitute_this(value, obj_id);
         }
-        Expr::TypeOf(inner) => {
+        Expr::TypeOf(inner) | Expr::Narrowed { value: inner, .. } => {
             substitute_this(inner, obj_id);
         }
         _ => {}
@@ -3,11 +3,20 @@
 //! This crate contains transformation passes that run on the HIR:
 //! - Closure conversion
 //! - Async/await lowering
-//! - Optimization passes (function inlining)
+//! - Optimization passes (function inlining, cross-module inlining, dead code elimination,
+//!   dead statement elimination, scalar replacement of non-escaping objects and arrays)
 
 pub mod closure;
+pub mod dead_code;
+pub mod dead_statements;
+pub mod escape;
 pub mod inline;
+pub mod whole_program;
 
 // Re-export main transformation functions
 pub use closure::convert_closures;
+pub use dead_code::eliminate_dead_functions;
+pub use dead_statements::eliminate_dead_statements;
+pub use escape::scalar_replace_allocations;
 pub use inline::inline_functions;
+pub use whole_program::inline_across_modules;
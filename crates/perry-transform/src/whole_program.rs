@@ -0,0 +1,363 @@
+//! Whole-Program Optimization for Perry HIR
+//!
+//! With `perry compile --whole-program`, the driver collects the HIR of every native
+//! module before running the inliner. This pass then copies small, self-contained
+//! exported functions into each module that imports them, so calls like
+//! `clamp(x)` from `./math` inline the same way calls to local helpers do. Calls that
+//! still aren't inlined go back to the exporter's symbol and the copies are dropped, so
+//! no function is compiled twice.
+//!
+//! Calls name an imported function by its exported name alone (`ExternFuncRef`), so a
+//! name a module imports from two modules can't be told apart, and neither is copied.
+//! The exporter's own definition stays for the inliner to decide on; once every call to
+//! it is inlined, whole-program dead code elimination (`dead_code.rs`) drops it.
+
+use perry_hir::walk::{walk_expr, walk_expr_mut, Visit, VisitMut};
+use perry_hir::{Expr, Function, ImportSpecifier, Module, ModuleKind, Stmt};
+use perry_types::{FuncId, LocalId};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::PathBuf;
+
+use crate::inline::{inline_functions, is_inlinable};
+
+/// Inline small exported functions across module boundaries, then run the regular
+/// inliner on every module. Modules marked `// @perry-opt: no-inline` are left alone.
+pub fn inline_across_modules(modules: &mut HashMap<PathBuf, Module>) {
+    // Exported functions that can be compiled in another module: key is
+    // (resolved_path, function_name)
+    let mut exported: HashMap<(String, String), Function> = HashMap::new();
+    for (path, module) in modules.iter() {
+        let path_str = path.to_string_lossy().to_string();
+        for func in &module.functions {
+            if func.is_exported && is_inlinable(func) && is_self_contained(func) {
+                exported.insert((path_str.clone(), func.name.clone()), func.clone());
+            }
+        }
+    }
+
+    for module in modules.values_mut().filter(|module| module.opt.inline) {
+        let copies = import_candidates(module, &exported);
+        inline_functions(module);
+        if !copies.is_empty() {
+            drop_copies(module, &copies);
+        }
+    }
+}
+
+/// Add private copies of the imported candidates to `module` and point direct calls at
+/// them. Returns copy id -> the callee expression the calls had before.
+fn import_candidates(module: &mut Module, exported: &HashMap<(String, String), Function>) -> HashMap<FuncId, Expr> {
+    // Modules each name is imported from; None for one that isn't compiled natively
+    let mut sources: BTreeMap<&str, HashSet<Option<&str>>> = BTreeMap::new();
+    for import in &module.imports {
+        let native = import.module_kind == ModuleKind::NativeCompiled;
+        let source = import.resolved_path.as_deref().filter(|_| native);
+        for spec in &import.specifiers {
+            if let ImportSpecifier::Named { imported, .. } = spec {
+                sources.entry(imported.as_str()).or_default().insert(source);
+            }
+        }
+    }
+    let mut by_name: Vec<(String, Function)> = Vec::new();
+    for (name, paths) in sources {
+        let mut paths = paths.into_iter();
+        let (Some(Some(path)), None) = (paths.next(), paths.next()) else { continue };
+        if let Some(func) = exported.get(&(path.to_string(), name.to_string())) {
+            by_name.push((name.to_string(), func.clone()));
+        }
+    }
+    if by_name.is_empty() {
+        return HashMap::new();
+    }
+
+    let mut targets: HashMap<String, FuncId> = HashMap::new();
+    let mut copies: HashMap<FuncId, Expr> = HashMap::new();
+    for (next_id, (name, func)) in (max_func_id(module) + 1..).zip(by_name) {
+        let mut copy = func.clone();
+        copy.id = next_id;
+        copy.is_exported = false;
+        copies.insert(next_id, Expr::ExternFuncRef {
+            name: name.clone(),
+            param_types: func.params.iter().map(|p| p.ty.clone()).collect(),
+            return_type: func.return_type.clone(),
+        });
+        targets.insert(name, next_id);
+        module.functions.push(copy);
+    }
+
+    retarget_calls(module, &mut |callee| match callee {
+        Expr::ExternFuncRef { name, .. } => targets.get(name).map(|id| Expr::FuncRef(*id)),
+        _ => None,
+    });
+    copies
+}
+
+/// Remove the copies again, sending calls the inliner left alone back to the exporter
+fn drop_copies(module: &mut Module, copies: &HashMap<FuncId, Expr>) {
+    module.functions.retain(|f| !copies.contains_key(&f.id));
+    retarget_calls(module, &mut |callee| match callee {
+        Expr::FuncRef(id) => copies.get(id).cloned(),
+        _ => None,
+    });
+}
+
+/// The functions, constructors, methods and accessors of `module`
+fn functions(module: &Module) -> impl Iterator<Item = &Function> {
+    let methods = module.classes.iter().flat_map(|class| {
+        class.constructor.iter()
+            .chain(&class.methods)
+            .chain(&class.static_methods)
+            .chain(class.getters.iter().chain(&class.setters).map(|(_, f)| f))
+    });
+    module.functions.iter().chain(methods)
+}
+
+fn functions_mut(module: &mut Module) -> impl Iterator<Item = &mut Function> {
+    let methods = module.classes.iter_mut().flat_map(|class| {
+        class.constructor.iter_mut()
+            .chain(&mut class.methods)
+            .chain(&mut class.static_methods)
+            .chain(class.getters.iter_mut().chain(&mut class.setters).map(|(_, f)| f))
+    });
+    module.functions.iter_mut().chain(methods)
+}
+
+/// Largest function id in `module`; closures have ids of their own
+#[derive(Default)]
+struct MaxFuncId(FuncId);
+
+impl Visit for MaxFuncId {
+    fn visit_expr(&mut self, expr: &Expr) {
+        if let Expr::Closure { func_id, .. } = expr {
+            self.0 = self.0.max(*func_id);
+        }
+        walk_expr(self, expr);
+    }
+}
+
+fn max_func_id(module: &Module) -> FuncId {
+    let mut max = MaxFuncId::default();
+    for func in functions(module) {
+        max.0 = max.0.max(func.id);
+        for default in func.params.iter().filter_map(|p| p.default.as_ref()) {
+            max.visit_expr(default);
+        }
+        max.visit_stmts(&func.body);
+    }
+    let fields = module.classes.iter().flat_map(|class| class.fields.iter().chain(&class.static_fields));
+    let inits = fields.filter_map(|field| field.init.as_ref())
+        .chain(module.globals.iter().filter_map(|global| global.init.as_ref()));
+    for init in inits {
+        max.visit_expr(init);
+    }
+    max.visit_stmts(&module.init);
+    max.0
+}
+
+/// A function whose body only uses its own parameters and locals and expressions that
+/// mean the same in any module (no module variables, imports, classes or closures)
+fn is_self_contained(func: &Function) -> bool {
+    fn expr_ok(expr: &Expr, locals: &HashSet<LocalId>) -> bool {
+        match expr {
+            Expr::Undefined | Expr::Null | Expr::Bool(_) | Expr::Number(_) |
+            Expr::Integer(_) | Expr::String(_) => true,
+            Expr::LocalGet(id) | Expr::Update { id, .. } => locals.contains(id),
+            Expr::LocalSet(id, value) => locals.contains(id) && expr_ok(value, locals),
+            Expr::Binary { left, right, .. } | Expr::Compare { left, right, .. } |
+            Expr::Logical { left, right, .. } | Expr::IndexGet { object: left, index: right } |
+            Expr::MathPow(left, right) => expr_ok(left, locals) && expr_ok(right, locals),
+            Expr::Unary { operand: inner, .. } | Expr::PropertyGet { object: inner, .. } |
+            Expr::TypeOf(inner) | Expr::MathFloor(inner) | Expr::MathCeil(inner) |
+            Expr::MathRound(inner) | Expr::MathAbs(inner) | Expr::MathSqrt(inner) => expr_ok(inner, locals),
+            Expr::Conditional { condition, then_expr, else_expr } => {
+                expr_ok(condition, locals) && expr_ok(then_expr, locals) && expr_ok(else_expr, locals)
+            }
+            Expr::Array(elements) | Expr::MathMin(elements) | Expr::MathMax(elements) => {
+                elements.iter().all(|e| expr_ok(e, locals))
+            }
+            Expr::Object(properties) => properties.iter().all(|(_, v)| expr_ok(v, locals)),
+            _ => false,
+        }
+    }
+
+    fn stmts_ok(stmts: &[Stmt], locals: &mut HashSet<LocalId>) -> bool {
+        stmts.iter().all(|stmt| match stmt {
+            Stmt::Let { id, init, .. } => {
+                let ok = init.as_ref().map_or(true, |e| expr_ok(e, locals));
+                locals.insert(*id);
+                ok
+            }
+            Stmt::Expr(e) | Stmt::Return(Some(e)) => expr_ok(e, locals),
+            Stmt::Return(None) => true,
+            Stmt::If { condition, then_branch, else_branch } => {
+                expr_ok(condition, locals)
+                    && stmts_ok(then_branch, locals)
+                    && else_branch.as_ref().map_or(true, |b| stmts_ok(b, locals))
+            }
+            _ => false,
+        })
+    }
+
+    let mut locals: HashSet<LocalId> = func.params.iter().map(|p| p.id).collect();
+    func.params.iter().all(|p| p.default.is_none() && !p.is_rest) && stmts_ok(&func.body, &mut locals)
+}
+
+/// Replaces the callee of every direct call for which `map` returns a new one
+struct Retarget<'a> {
+    map: &'a mut dyn FnMut(&Expr) -> Option<Expr>,
+}
+
+impl VisitMut for Retarget<'_> {
+    fn visit_expr_mut(&mut self, expr: &mut Expr) {
+        if let Expr::Call { callee, .. } = expr {
+            if let Some(new_callee) = (self.map)(callee) {
+                **callee = new_callee;
+            }
+        }
+        walk_expr_mut(self, expr);
+    }
+}
+
+/// Replace the callee of every direct call in `module` for which `map` returns a new
+/// callee: in top-level code, functions, constructors, methods, accessors, closures and
+/// initializers
+fn retarget_calls(module: &mut Module, map: &mut dyn FnMut(&Expr) -> Option<Expr>) {
+    let mut retarget = Retarget { map };
+    retarget.visit_stmts_mut(&mut module.init);
+    for func in functions_mut(module) {
+        for default in func.params.iter_mut().filter_map(|p| p.default.as_mut()) {
+            retarget.visit_expr_mut(default);
+        }
+        retarget.visit_stmts_mut(&mut func.body);
+    }
+    let fields = module.classes.iter_mut().flat_map(|class| class.fields.iter_mut().chain(&mut class.static_fields));
+    let inits = fields.filter_map(|field| field.init.as_mut())
+        .chain(module.globals.iter_mut().filter_map(|global| global.init.as_mut()));
+    for init in inits {
+        retarget.visit_expr_mut(init);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use perry_hir::{BinaryOp, Import, Param};
+    use perry_types::Type;
+
+    fn func(id: FuncId, name: &str, body: Vec<Stmt>) -> Function {
+        Function {
+            id,
+            name: name.to_string(),
+            type_params: Vec::new(),
+            params: vec![Param { id: 0, name: "v".to_string(), ty: Type::Number, default: None, is_rest: false }],
+            return_type: Type::Number,
+            body,
+            is_async: false,
+            is_exported: true,
+            captures: Vec::new(),
+            decorators: Vec::new(),
+            span: Default::default(),
+        }
+    }
+
+    /// A module exporting `double(v) { return v * factor }`
+    fn exporter(factor: f64) -> Module {
+        let mut module = Module::new("exporter");
+        let product = Expr::Binary {
+            op: BinaryOp::Mul,
+            left: Box::new(Expr::LocalGet(0)),
+            right: Box::new(Expr::Number(factor)),
+        };
+        module.functions.push(func(0, "double", vec![Stmt::Return(Some(product))]));
+        module
+    }
+
+    fn import(path: &str, imported: &str, local: &str) -> Import {
+        Import {
+            source: path.to_string(),
+            specifiers: vec![ImportSpecifier::Named { imported: imported.to_string(), local: local.to_string() }],
+            is_native: false,
+            module_kind: ModuleKind::NativeCompiled,
+            resolved_path: Some(path.to_string()),
+            is_require: false,
+        }
+    }
+
+    /// `const x = double(1)`
+    fn call_double() -> Stmt {
+        let call = Expr::Call {
+            callee: Box::new(Expr::ExternFuncRef { name: "double".to_string(), param_types: vec![Type::Number], return_type: Type::Number }),
+            args: vec![Expr::Number(1.0)],
+            type_args: Vec::new(),
+            span: Default::default(),
+        };
+        Stmt::Let { id: 1, name: "x".to_string(), ty: Type::Number, mutable: false, init: Some(call) }
+    }
+
+    fn init_value(modules: &HashMap<PathBuf, Module>) -> &Expr {
+        match &modules[&PathBuf::from("/main.ts")].init[0] {
+            Stmt::Let { init: Some(init), .. } => init,
+            other => panic!("unexpected statement {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_inlines_imported_helper() {
+        let mut main = Module::new("main");
+        main.imports.push(import("/a.ts", "double", "double"));
+        main.init.push(call_double());
+        let mut modules = HashMap::from([
+            (PathBuf::from("/main.ts"), main),
+            (PathBuf::from("/a.ts"), exporter(2.0)),
+        ]);
+        inline_across_modules(&mut modules);
+        assert!(matches!(init_value(&modules), Expr::Binary { right, .. } if matches!(**right, Expr::Number(n) if n == 2.0)));
+        assert!(modules[&PathBuf::from("/main.ts")].functions.is_empty());
+    }
+
+    #[test]
+    fn test_same_name_from_two_modules_is_not_copied() {
+        // import { double } from "./a"; import { double as triple } from "./b";
+        // Both calls would be ExternFuncRef "double", so neither can be inlined
+        let mut main = Module::new("main");
+        main.imports.push(import("/a.ts", "double", "double"));
+        main.imports.push(import("/b.ts", "double", "triple"));
+        main.init.push(call_double());
+        let mut modules = HashMap::from([
+            (PathBuf::from("/main.ts"), main),
+            (PathBuf::from("/a.ts"), exporter(2.0)),
+            (PathBuf::from("/b.ts"), exporter(3.0)),
+        ]);
+        inline_across_modules(&mut modules);
+        let Expr::Call { callee, .. } = init_value(&modules) else { panic!("call was inlined") };
+        assert!(matches!(**callee, Expr::ExternFuncRef { ref name, .. } if name == "double"));
+        assert!(modules[&PathBuf::from("/main.ts")].functions.is_empty());
+    }
+
+    #[test]
+    fn test_copies_get_ids_above_closures() {
+        let mut main = Module::new("main");
+        main.imports.push(import("/a.ts", "double", "double"));
+        let closure = Expr::Closure {
+            func_id: 7,
+            params: Vec::new(),
+            return_type: Type::Void,
+            body: Vec::new(),
+            captures: Vec::new(),
+            mutable_captures: Vec::new(),
+            captures_this: false,
+            enclosing_class: None,
+            is_async: false,
+        };
+        main.init.push(Stmt::Let { id: 2, name: "f".to_string(), ty: Type::Any, mutable: false, init: Some(closure) });
+        main.init.push(call_double());
+        assert_eq!(max_func_id(&main), 7);
+
+        let exported = HashMap::from([(("/a.ts".to_string(), "double".to_string()), exporter(2.0).functions.remove(0))]);
+        let copies = import_candidates(&mut main, &exported);
+        assert_eq!(copies.keys().copied().collect::<Vec<_>>(), vec![8]);
+        assert_eq!(main.functions[0].id, 8);
+        let Stmt::Let { init: Some(Expr::Call { callee, .. }), .. } = &main.init[1] else { panic!() };
+        assert!(matches!(**callee, Expr::FuncRef(8)));
+    }
+}
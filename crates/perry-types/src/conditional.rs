@@ -0,0 +1,275 @@
+//! Conditional type evaluation
+//!
+//! `T extends U ? X : Y` is `X` when `T` is assignable to `U` and `Y` otherwise, and
+//! `infer R` in `U` binds `R` to the part of `T` it matches, for use in `X`. A conditional
+//! whose check type is a bare type parameter distributes over unions: with `T = A | B` it
+//! is evaluated for `A` and for `B` and the results are unioned.
+//!
+//! A conditional is evaluated once its check and extends types no longer refer to type
+//! parameters: while lowering when it is written against concrete types, otherwise when
+//! monomorphization substitutes a generic's type arguments. The standard utilities
+//! defined as conditionals (`ReturnType`, `Parameters`, `Awaited`, `NonNullable`,
+//! `Exclude`, `Extract`) are evaluated directly.
+//!
+//! Assignability is structural and approximate: named types match only themselves, and
+//! `any` takes the true branch (TypeScript would union both).
+
+use crate::{ConditionalType, Type};
+use std::collections::HashMap;
+
+/// Standard library utility types that are conditional types
+pub const CONDITIONAL_UTILITIES: &[&str] = &["ReturnType", "Parameters", "Awaited", "NonNullable", "Exclude", "Extract"];
+
+/// Name lowering gives a function type's rest parameter (`(...args: A) => R`)
+const REST_PARAM: &str = "_rest";
+
+impl ConditionalType {
+    /// The branch the conditional picks, with its `infer` variables bound. None while
+    /// the check or extends type still refers to type parameters.
+    pub fn evaluate(&self) -> Option<Type> {
+        let unresolved = |ty: &Type| matches!(ty, Type::TypeVar(_) | Type::Conditional(_));
+        if self.check.contains(|ty| unresolved(ty) || matches!(ty, Type::Infer(_))) || self.extends.contains(unresolved) {
+            return None;
+        }
+        match &self.check {
+            Type::Union(members) if self.distributive.is_some() => {
+                Some(union_of(members.iter().map(|member| self.pick(member))))
+            }
+            // `never` is the empty union
+            Type::Never if self.distributive.is_some() => Some(Type::Never),
+            check => Some(self.pick(check)),
+        }
+    }
+
+    fn pick(&self, check: &Type) -> Type {
+        let mut bindings = HashMap::new();
+        let branch = if matches(check, &self.extends, &mut bindings) { &self.true_type } else { &self.false_type };
+        if let Some(param) = &self.distributive {
+            bindings.insert(param.clone(), check.clone());
+        }
+        // A variable the match didn't reach (`any extends Promise<infer R>`) is unknown
+        branch
+            .map_types(&mut |ty| match ty {
+                Type::Infer(name) => Some(bindings.get(name).cloned().unwrap_or(Type::Unknown)),
+                _ => None,
+            })
+            .evaluate_conditionals()
+    }
+}
+
+impl Type {
+    /// Evaluate the conditional types and conditional utilities inside this type that
+    /// no longer depend on type parameters
+    pub fn evaluate_conditionals(&self) -> Type {
+        self.map_types(&mut |ty| match ty {
+            Type::Conditional(cond) => {
+                let cond = ConditionalType {
+                    check: cond.check.evaluate_conditionals(),
+                    extends: cond.extends.evaluate_conditionals(),
+                    ..(**cond).clone()
+                };
+                Some(cond.evaluate().unwrap_or_else(|| Type::Conditional(Box::new(cond))))
+            }
+            Type::Generic { base, type_args } if CONDITIONAL_UTILITIES.contains(&base.as_str()) => {
+                let type_args: Vec<Type> = type_args.iter().map(Type::evaluate_conditionals).collect();
+                Some(evaluate_utility(base, &type_args).unwrap_or_else(|| Type::Generic { base: base.clone(), type_args }))
+            }
+            _ => None,
+        })
+    }
+}
+
+/// Whether `source` is assignable to `target`
+pub fn is_assignable(source: &Type, target: &Type) -> bool {
+    matches(source, target, &mut HashMap::new())
+}
+
+/// Evaluate a standard conditional utility type. None when `base` isn't one, its type
+/// arguments still refer to type parameters, or they have the wrong shape.
+pub fn evaluate_utility(base: &str, type_args: &[Type]) -> Option<Type> {
+    if type_args.iter().any(|arg| arg.contains(|ty| matches!(ty, Type::TypeVar(_) | Type::Conditional(_)))) {
+        return None;
+    }
+    match (base, type_args) {
+        ("ReturnType", [Type::Function(func)]) => Some(match func.return_type.as_ref() {
+            ret if func.is_async && !matches!(ret, Type::Promise(_)) => Type::Promise(Box::new(ret.clone())),
+            ret => ret.clone(),
+        }),
+        ("Parameters", [Type::Function(func)]) => Some(Type::Tuple(func.params.iter().map(|(_, ty, _)| ty.clone()).collect())),
+        ("ReturnType" | "Parameters", [Type::Any]) => Some(Type::Any),
+        ("Awaited", [ty]) => Some(awaited(ty)),
+        ("NonNullable", [ty]) => Some(union_of(members(ty).filter(|m| !matches!(m, Type::Null | Type::Void)).cloned())),
+        ("Exclude", [ty, excluded]) => Some(union_of(members(ty).filter(|m| !is_assignable(m, excluded)).cloned())),
+        ("Extract", [ty, extracted]) => Some(union_of(members(ty).filter(|m| is_assignable(m, extracted)).cloned())),
+        _ => None,
+    }
+}
+
+/// The type a promise resolves to, unwrapping nested promises
+fn awaited(ty: &Type) -> Type {
+    match ty {
+        Type::Promise(inner) => awaited(inner),
+        Type::Generic { base, type_args } if base == "Promise" && type_args.len() == 1 => awaited(&type_args[0]),
+        Type::Union(members) => union_of(members.iter().map(awaited)),
+        ty => ty.clone(),
+    }
+}
+
+fn members(ty: &Type) -> impl Iterator<Item = &Type> {
+    match ty {
+        Type::Union(members) => members.iter(),
+        ty => std::slice::from_ref(ty).iter(),
+    }
+}
+
+/// Union of `types`, without `never` and duplicates. One member is returned as is, none is `never`.
+pub(crate) fn union_of(types: impl IntoIterator<Item = Type>) -> Type {
+    let mut union: Vec<Type> = Vec::new();
+    for ty in types {
+        for member in members(&ty) {
+            if *member != Type::Never && !union.contains(member) {
+                union.push(member.clone());
+            }
+        }
+    }
+    match union.len() {
+        0 => Type::Never,
+        1 => union.pop().unwrap(),
+        _ => Type::Union(union),
+    }
+}
+
+/// Assignability of `source` to `target`, binding the `infer` variables in `target`
+fn matches(source: &Type, target: &Type, bindings: &mut HashMap<String, Type>) -> bool {
+    match (source, target) {
+        (_, Type::Infer(name)) => {
+            // A variable matched in several places (covariantly) gets the union
+            let bound = match bindings.remove(name) {
+                Some(existing) => union_of([existing, source.clone()]),
+                None => source.clone(),
+            };
+            bindings.insert(name.clone(), bound);
+            true
+        }
+        (_, Type::Any | Type::Unknown) | (Type::Never | Type::Any, _) => true,
+        (Type::Union(members), _) => members.iter().all(|member| matches(member, target, bindings)),
+        (_, Type::Union(members)) => members.iter().any(|member| {
+            let mut attempt = bindings.clone();
+            let matched = matches(source, member, &mut attempt);
+            if matched {
+                *bindings = attempt;
+            }
+            matched
+        }),
+        (_, Type::Intersection(members)) => members.iter().all(|member| matches(source, member, bindings)),
+        (Type::Intersection(members), _) => members.iter().any(|member| matches(member, target, bindings)),
+        (Type::Literal(lit), _) if lit.widened() == *target => true,
+        (Type::Number | Type::Int32, Type::Number | Type::Int32) => true,
+        (Type::Array(elem), Type::Array(target_elem)) | (Type::Promise(elem), Type::Promise(target_elem)) => {
+            matches(elem, target_elem, bindings)
+        }
+        (Type::Tuple(elems), Type::Array(target_elem)) => elems.iter().all(|elem| matches(elem, target_elem, bindings)),
+        (Type::Tuple(elems), Type::Tuple(target_elems)) => {
+            elems.len() == target_elems.len() && elems.iter().zip(target_elems).all(|(e, t)| matches(e, t, bindings))
+        }
+        (Type::Generic { base, type_args }, Type::Generic { base: target_base, type_args: target_args }) => {
+            base == target_base
+                && type_args.len() == target_args.len()
+                && type_args.iter().zip(target_args).all(|(a, t)| matches(a, t, bindings))
+        }
+        (Type::Function(func), Type::Function(target_func)) => {
+            let params_match = match target_func.params.as_slice() {
+                // `(...args: P) => R` takes the parameter list as a tuple
+                [(name, rest, _)] if name == REST_PARAM => {
+                    let params = Type::Tuple(func.params.iter().map(|(_, ty, _)| ty.clone()).collect());
+                    matches(&params, rest, bindings)
+                }
+                // A function may ignore trailing parameters; parameter types are compared loosely
+                target_params => func.params.len() <= target_params.len()
+                    && func.params.iter().zip(target_params).all(|((_, p, _), (_, t, _))| matches(p, t, bindings)),
+            };
+            params_match && matches(&func.return_type, &target_func.return_type, bindings)
+        }
+        (Type::Object(obj), Type::Object(target_obj)) => target_obj.properties.iter().all(|(name, info)| {
+            match obj.property(name) {
+                Some(property) => matches(&property.ty, &info.ty, bindings),
+                None => info.optional,
+            }
+        }),
+        (source, target) => source == target,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FunctionType, LiteralType};
+
+    /// `T extends ... ? ... : ...`, where the branches refer to `T` as `Infer("T")`
+    fn conditional(extends: Type, true_type: Type, false_type: Type) -> Type {
+        Type::Conditional(Box::new(ConditionalType {
+            check: Type::TypeVar("T".into()),
+            extends,
+            true_type,
+            false_type,
+            distributive: Some("T".into()),
+        }))
+    }
+
+    fn substitute(ty: &Type, name: &str, with: Type) -> Type {
+        ty.map_types(&mut |t| match t {
+            Type::TypeVar(n) if n == name => Some(with.clone()),
+            _ => None,
+        })
+        .evaluate_conditionals()
+    }
+
+    #[test]
+    fn test_infer_and_distribution() {
+        // type Unwrap<T> = T extends Promise<infer U> ? U : T
+        let unwrap = conditional(Type::Promise(Box::new(Type::Infer("U".into()))), Type::Infer("U".into()), Type::Infer("T".into()));
+        assert_eq!(unwrap.evaluate_conditionals(), unwrap, "unevaluated while T is unknown");
+        assert_eq!(substitute(&unwrap, "T", Type::Promise(Box::new(Type::String))), Type::String);
+        assert_eq!(substitute(&unwrap, "T", Type::Number), Type::Number);
+        // Distributes over a union substituted for T
+        let union = Type::Union(vec![Type::Promise(Box::new(Type::Boolean)), Type::Null]);
+        assert_eq!(substitute(&unwrap, "T", union), Type::Union(vec![Type::Boolean, Type::Null]));
+
+        // type ElementOf<T> = T extends (infer E)[] ? E : never
+        let element_of = conditional(Type::Array(Box::new(Type::Infer("E".into()))), Type::Infer("E".into()), Type::Never);
+        assert_eq!(substitute(&element_of, "T", Type::Array(Box::new(Type::String))), Type::String);
+        assert_eq!(substitute(&element_of, "T", Type::String), Type::Never);
+    }
+
+    #[test]
+    fn test_utilities() {
+        let handler = Type::Function(FunctionType {
+            params: vec![("id".into(), Type::Number, false), ("name".into(), Type::String, true)],
+            return_type: Box::new(Type::Promise(Box::new(Type::Boolean))),
+            is_async: false,
+            is_generator: false,
+        });
+        let return_type = evaluate_utility("ReturnType", &[handler.clone()]).unwrap();
+        assert_eq!(return_type, Type::Promise(Box::new(Type::Boolean)));
+        assert_eq!(evaluate_utility("Awaited", &[return_type]), Some(Type::Boolean));
+        assert_eq!(evaluate_utility("Parameters", &[handler]), Some(Type::Tuple(vec![Type::Number, Type::String])));
+
+        let maybe = Type::Union(vec![Type::String, Type::Null, Type::Void]);
+        assert_eq!(evaluate_utility("NonNullable", &[maybe]), Some(Type::String));
+        let events = Type::Union(vec![
+            Type::Literal(LiteralType::String("click".into())),
+            Type::Literal(LiteralType::String("key".into())),
+            Type::Number,
+        ]);
+        assert_eq!(
+            evaluate_utility("Exclude", &[events.clone(), Type::String]),
+            Some(Type::Number)
+        );
+        assert_eq!(
+            evaluate_utility("Extract", &[events, Type::Literal(LiteralType::String("key".into()))]),
+            Some(Type::Literal(LiteralType::String("key".into())))
+        );
+        // Still generic: left for monomorphization
+        assert_eq!(evaluate_utility("Awaited", &[Type::TypeVar("T".into())]), None);
+    }
+}
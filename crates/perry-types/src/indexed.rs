@@ -0,0 +1,183 @@
+//! `keyof` and indexed access type evaluation
+//!
+//! `keyof T` is the union of `T`'s property names as string literals (`string | number`
+//! for a string index signature, `number` for arrays and tuples). `T[K]` is the type of
+//! property `K` of `T`, distributed over unions in either position: `User["id"]`,
+//! `Tuple[0]`, `Item[]` indexed by `number`. An optional property reads as `T | undefined`.
+//!
+//! Like conditional types, these are evaluated while lowering when written against
+//! concrete types and after monomorphization otherwise, so accessor helpers such as
+//! `function get<T, K extends keyof T>(o: T, k: K): T[K]` end up with precise types.
+//! Evaluation needs object shapes, so callers pass a resolver for named types; without
+//! one only object literal types are known. A key that is just `string` or `number`
+//! (what call-site inference gives a string argument) reads as the union of all
+//! property types.
+
+use crate::conditional::union_of;
+use crate::{LiteralType, ObjectType, Type};
+
+/// Resolves a type to the object type it describes, following named types
+pub type ShapeResolver<'a> = &'a dyn Fn(&Type) -> Option<ObjectType>;
+
+impl ObjectType {
+    /// `keyof` this type, with the property names in sorted order
+    pub fn keys(&self) -> Type {
+        let mut names: Vec<&String> = self.properties.keys().collect();
+        names.sort();
+        let mut keys: Vec<Type> = names.into_iter().map(|name| Type::Literal(LiteralType::String(name.clone()))).collect();
+        // Numeric keys of a string index signature are strings too
+        if self.index_signature.is_some() {
+            keys.extend([Type::String, Type::Number]);
+        }
+        union_of(keys)
+    }
+}
+
+/// Only object literal types are known
+pub fn structural_shape(ty: &Type) -> Option<ObjectType> {
+    match ty {
+        Type::Object(obj) => Some(obj.clone()),
+        _ => None,
+    }
+}
+
+/// `keyof ty`. None while `ty` is unknown or depends on type parameters.
+pub fn key_of(ty: &Type, shape: ShapeResolver) -> Option<Type> {
+    match ty {
+        Type::Any => Some(Type::Union(vec![Type::String, Type::Number, Type::Symbol])),
+        Type::Array(_) | Type::Tuple(_) => Some(Type::Number),
+        ty => Some(shape(ty)?.keys()),
+    }
+}
+
+/// `object[index]`. None while either depends on type parameters, or the property is
+/// unknown.
+pub fn index(object: &Type, index_type: &Type, shape: ShapeResolver) -> Option<Type> {
+    if let Type::Union(keys) = index_type {
+        return keys.iter().map(|key| index(object, key, shape)).collect::<Option<Vec<_>>>().map(union_of);
+    }
+    if let Type::Union(objects) = object {
+        return objects.iter().map(|obj| index(obj, index_type, shape)).collect::<Option<Vec<_>>>().map(union_of);
+    }
+    let length = matches!(index_type, Type::Literal(LiteralType::String(key)) if key == "length");
+    match (object, index_type) {
+        (Type::Any, _) => Some(Type::Any),
+        (Type::Array(_) | Type::Tuple(_) | Type::String, _) if length => Some(Type::Number),
+        (Type::Array(elem), Type::Number | Type::Int32 | Type::Literal(LiteralType::Number(_))) => Some((**elem).clone()),
+        (Type::Tuple(elems), Type::Literal(LiteralType::Number(n))) if *n >= 0.0 && n.fract() == 0.0 => {
+            elems.get(*n as usize).cloned()
+        }
+        (Type::Tuple(elems), Type::Number | Type::Int32) => Some(union_of(elems.iter().cloned())),
+        (Type::String, Type::Number | Type::Int32 | Type::Literal(LiteralType::Number(_))) => Some(Type::String),
+        (object, _) => {
+            let obj = shape(object)?;
+            let property = |key: &str| {
+                let info = obj.property(key)?;
+                Some(if info.optional { union_of([info.ty, Type::Void]) } else { info.ty })
+            };
+            match index_type {
+                Type::Literal(LiteralType::String(key)) => property(key),
+                Type::Literal(LiteralType::Number(n)) => property(&n.to_string()),
+                Type::String | Type::Number | Type::Int32 => match &obj.index_signature {
+                    Some(value) => Some((**value).clone()),
+                    None if !obj.properties.is_empty() => {
+                        let mut names: Vec<&String> = obj.properties.keys().collect();
+                        names.sort();
+                        Some(union_of(names.into_iter().map(|name| obj.properties[name].ty.clone())))
+                    }
+                    None => None,
+                },
+                _ => None,
+            }
+        }
+    }
+}
+
+impl Type {
+    /// Evaluate the `keyof` and indexed access types inside this type that no longer
+    /// depend on type parameters
+    pub fn evaluate_indexed(&self, shape: ShapeResolver) -> Type {
+        self.map_types(&mut |ty| match ty {
+            Type::KeyOf(inner) => {
+                let inner = inner.evaluate_indexed(shape);
+                Some(key_of(&inner, shape).unwrap_or_else(|| Type::KeyOf(Box::new(inner))))
+            }
+            Type::IndexedAccess { object, index: index_type } => {
+                let object = object.evaluate_indexed(shape);
+                let index_type = index_type.evaluate_indexed(shape);
+                Some(index(&object, &index_type, shape).unwrap_or_else(|| Type::IndexedAccess {
+                    object: Box::new(object),
+                    index: Box::new(index_type),
+                }))
+            }
+            _ => None,
+        })
+    }
+
+    /// Whether the type still refers to type parameters, directly or through a
+    /// conditional, `keyof` or indexed access type that couldn't be evaluated yet
+    pub fn depends_on_type_params(&self) -> bool {
+        self.contains(|ty| matches!(ty, Type::TypeVar(_) | Type::Conditional(_) | Type::KeyOf(_) | Type::IndexedAccess { .. }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PropertyInfo;
+
+    fn key(name: &str) -> Type {
+        Type::Literal(LiteralType::String(name.into()))
+    }
+
+    fn user() -> Type {
+        let mut user = ObjectType::default();
+        user.properties.insert("id".into(), PropertyInfo { ty: Type::Number, optional: false, readonly: true });
+        user.properties.insert("email".into(), PropertyInfo { ty: Type::String, optional: true, readonly: false });
+        Type::Object(user)
+    }
+
+    #[test]
+    fn test_key_of() {
+        let shape: ShapeResolver = &structural_shape;
+        assert_eq!(key_of(&user(), shape), Some(Type::Union(vec![key("email"), key("id")])));
+        assert_eq!(key_of(&Type::Array(Box::new(Type::String)), shape), Some(Type::Number));
+        assert_eq!(key_of(&Type::TypeVar("T".into()), shape), None);
+        let named = |ty: &Type| match ty {
+            Type::Named(name) if name == "User" => structural_shape(&user()),
+            _ => None,
+        };
+        assert_eq!(key_of(&Type::Named("User".into()), &named), key_of(&user(), shape));
+    }
+
+    #[test]
+    fn test_index() {
+        let shape: ShapeResolver = &structural_shape;
+        assert_eq!(index(&user(), &key("id"), shape), Some(Type::Number));
+        assert_eq!(index(&user(), &key("email"), shape), Some(Type::Union(vec![Type::String, Type::Void])));
+        assert_eq!(index(&user(), &Type::Union(vec![key("id"), key("id")]), shape), Some(Type::Number));
+        assert_eq!(index(&user(), &Type::String, shape), Some(Type::Union(vec![Type::String, Type::Number])));
+        assert_eq!(index(&user(), &key("missing"), shape), None);
+
+        let tuple = Type::Tuple(vec![Type::String, Type::Boolean]);
+        assert_eq!(index(&tuple, &Type::Literal(LiteralType::Number(1.0)), shape), Some(Type::Boolean));
+        assert_eq!(index(&tuple, &key("length"), shape), Some(Type::Number));
+        assert_eq!(index(&Type::Array(Box::new(Type::BigInt)), &Type::Number, shape), Some(Type::BigInt));
+
+        // `T[K]` waits for its type arguments, then evaluates
+        let deferred = Type::IndexedAccess {
+            object: Box::new(Type::TypeVar("T".into())),
+            index: Box::new(Type::TypeVar("K".into())),
+        };
+        assert!(deferred.depends_on_type_params());
+        assert_eq!(deferred.evaluate_indexed(shape), deferred);
+        let substituted = deferred.map_types(&mut |ty| match ty {
+            Type::TypeVar(name) if name == "T" => Some(user()),
+            Type::TypeVar(_) => Some(key("id")),
+            _ => None,
+        });
+        assert_eq!(substituted.evaluate_indexed(shape), Type::Number);
+        assert_eq!(Type::KeyOf(Box::new(user())).evaluate_indexed(shape).to_string(), "\"email\" | \"id\"");
+        assert_eq!(deferred.to_string(), "T[K]");
+    }
+}
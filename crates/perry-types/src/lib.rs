@@ -3,8 +3,13 @@
 //! Defines the type representations used throughout the compiler,
 //! from parsing through code generation.
 
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
+pub mod conditional;
+pub mod indexed;
+pub mod mapped;
+
 /// Unique identifier for types
 pub type TypeId = u32;
 
@@ -18,7 +23,7 @@ pub type LocalId = u32;
 pub type GlobalId = u32;
 
 /// Core type representation
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Type {
     /// Void type (undefined in JS terms)
     Void,
@@ -46,6 +51,12 @@ pub enum Type {
     Function(FunctionType),
     /// Union type (e.g., string | number)
     Union(Vec<Type>),
+    /// Intersection type (e.g., Timestamped & { id: string }). Built with
+    /// `Type::intersection`, so object literal members are already merged into one
+    Intersection(Vec<Type>),
+    /// Literal type (e.g., "circle", 42, true). Only kept where it matters, such as the
+    /// tag property of discriminated union members; elsewhere literals widen to their base type
+    Literal(LiteralType),
     /// Promise type
     Promise(Box<Type>),
     /// Any type (boxed value, escape hatch)
@@ -67,10 +78,115 @@ pub enum Type {
         /// Concrete type arguments
         type_args: Vec<Type>,
     },
+    /// Conditional type (`T extends U ? X : Y`) that depends on type parameters. Evaluated
+    /// once they are substituted (see `conditional`)
+    Conditional(Box<ConditionalType>),
+    /// Type variable bound by `infer R` in a conditional type's `extends` clause, and the
+    /// references to it in the true branch
+    Infer(String),
+    /// `keyof T` of a type that depends on type parameters (see `indexed`)
+    KeyOf(Box<Type>),
+    /// Indexed access `T[K]` that depends on type parameters (see `indexed`)
+    IndexedAccess {
+        object: Box<Type>,
+        index: Box<Type>,
+    },
+}
+
+/// Value of a literal type
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum LiteralType {
+    String(String),
+    Number(f64),
+    Boolean(bool),
+}
+
+impl LiteralType {
+    /// The primitive type this literal belongs to
+    pub fn widened(&self) -> Type {
+        match self {
+            LiteralType::String(_) => Type::String,
+            LiteralType::Number(_) => Type::Number,
+            LiteralType::Boolean(_) => Type::Boolean,
+        }
+    }
+}
+
+impl std::fmt::Display for LiteralType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LiteralType::String(s) => write!(f, "{:?}", s),
+            LiteralType::Number(n) => write!(f, "{}", n),
+            LiteralType::Boolean(b) => write!(f, "{}", b),
+        }
+    }
+}
+
+/// A union of object types told apart by one property (the tag) that has a distinct
+/// literal type in every member, e.g.
+/// `{ kind: "circle"; radius: number } | { kind: "square"; size: number }`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DiscriminatedUnion {
+    /// Name of the tag property
+    pub tag: String,
+    /// Members in declaration order
+    pub variants: Vec<UnionVariant>,
+}
+
+/// One member of a discriminated union
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UnionVariant {
+    /// Literal type of the tag property in this member
+    pub tag: LiteralType,
+    /// The member type (a named class/interface or an object type)
+    pub ty: Type,
+}
+
+impl DiscriminatedUnion {
+    /// Model union members, each paired with the literal type of its `tag` property.
+    /// Returns None unless every member has a literal tag and no two tags are equal.
+    pub fn new(tag: impl Into<String>, members: impl IntoIterator<Item = (Type, Option<LiteralType>)>) -> Option<Self> {
+        let mut variants: Vec<UnionVariant> = Vec::new();
+        for (ty, literal) in members {
+            let literal = literal?;
+            if variants.iter().any(|v| v.tag == literal) {
+                return None;
+            }
+            variants.push(UnionVariant { tag: literal, ty });
+        }
+        if variants.is_empty() {
+            return None;
+        }
+        Some(Self { tag: tag.into(), variants })
+    }
+
+    /// The member whose tag is `literal`
+    pub fn variant(&self, literal: &LiteralType) -> Option<&UnionVariant> {
+        self.variants.iter().find(|v| v.tag == *literal)
+    }
+
+    /// Type of a value known to carry one of the `literals` tags: the single member, the
+    /// union of the members, or None when no member matches
+    pub fn narrow(&self, literals: &[LiteralType]) -> Option<Type> {
+        let mut members: Vec<Type> = self.variants.iter()
+            .filter(|v| literals.contains(&v.tag))
+            .map(|v| v.ty.clone())
+            .collect();
+        match members.len() {
+            0 => None,
+            1 => members.pop(),
+            _ => Some(Type::Union(members)),
+        }
+    }
+
+    /// Members whose tags are not among `handled`
+    pub fn unhandled(&self, handled: &[LiteralType]) -> Vec<&UnionVariant> {
+        self.variants.iter().filter(|v| !handled.contains(&v.tag)).collect()
+    }
 }
 
 /// Type parameter definition (used in generic functions/classes)
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct TypeParam {
     /// Name of the type parameter (e.g., "T", "K", "V")
     pub name: String,
@@ -81,7 +197,7 @@ pub struct TypeParam {
 }
 
 /// Object type with property information
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ObjectType {
     /// Optional name (for classes/interfaces)
     pub name: Option<String>,
@@ -92,7 +208,7 @@ pub struct ObjectType {
 }
 
 /// Property information including mutability
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct PropertyInfo {
     pub ty: Type,
     pub optional: bool,
@@ -100,7 +216,7 @@ pub struct PropertyInfo {
 }
 
 /// Function type information
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct FunctionType {
     /// Parameter types with names
     pub params: Vec<(String, Type, bool)>, // (name, type, optional)
@@ -112,6 +228,19 @@ pub struct FunctionType {
     pub is_generator: bool,
 }
 
+/// Conditional type `check extends extends ? true_type : false_type`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConditionalType {
+    pub check: Type,
+    pub extends: Type,
+    pub true_type: Type,
+    pub false_type: Type,
+    /// The type parameter the check type was, when it was a bare one: the conditional
+    /// distributes over the members of a union substituted for it, and the branches
+    /// refer to the member at hand as `Infer` of this name
+    pub distributive: Option<String>,
+}
+
 impl Type {
     /// Check if this type is a primitive (number, string, boolean, etc.)
     pub fn is_primitive(&self) -> bool {
@@ -125,13 +254,205 @@ impl Type {
                 | Type::BigInt
                 | Type::String
                 | Type::Symbol
+                | Type::Literal(_)
         )
     }
 
+    /// Check if every value of this type is a string: `string`, a string literal, or a
+    /// union of those (`"fast" | "safe"`)
+    pub fn is_string(&self) -> bool {
+        match self {
+            Type::String | Type::Literal(LiteralType::String(_)) => true,
+            Type::Union(members) => !members.is_empty() && members.iter().all(Type::is_string),
+            _ => false,
+        }
+    }
+
     /// Check if this type could be undefined/null
     pub fn is_nullable(&self) -> bool {
         matches!(self, Type::Void | Type::Null | Type::Any | Type::Unknown)
     }
+
+    /// Intersection of `members`: nested intersections are flattened, `unknown` and
+    /// repeated members dropped, and object literal members merged into a single object
+    /// type (a later member's property wins, as it must be assignable to both). A single
+    /// remaining member is returned as is.
+    pub fn intersection(members: impl IntoIterator<Item = Type>) -> Type {
+        let mut merged: Vec<Type> = Vec::new();
+        let mut object: Option<ObjectType> = None;
+        let mut pending = members.into_iter().collect::<Vec<_>>();
+        pending.reverse();
+        while let Some(member) = pending.pop() {
+            match member {
+                Type::Intersection(inner) => pending.extend(inner.into_iter().rev()),
+                Type::Unknown => {}
+                Type::Object(obj) if obj.name.is_none() => match &mut object {
+                    Some(existing) => existing.merge(obj),
+                    None => object = Some(obj),
+                },
+                member if !merged.contains(&member) => merged.push(member),
+                _ => {}
+            }
+        }
+        merged.extend(object.map(Type::Object));
+        match merged.len() {
+            0 => Type::Unknown,
+            1 => merged.pop().unwrap(),
+            _ => Type::Intersection(merged),
+        }
+    }
+
+    /// Rebuild the type top-down: `f` replaces a type by returning Some, otherwise the
+    /// types inside it are mapped
+    pub fn map_types<F: FnMut(&Type) -> Option<Type>>(&self, f: &mut F) -> Type {
+        if let Some(replaced) = f(self) {
+            return replaced;
+        }
+        let map_all = |types: &[Type], f: &mut F| types.iter().map(|ty| ty.map_types(f)).collect::<Vec<_>>();
+        match self {
+            Type::Array(elem) => Type::Array(Box::new(elem.map_types(f))),
+            Type::Promise(inner) => Type::Promise(Box::new(inner.map_types(f))),
+            Type::Tuple(elems) => Type::Tuple(map_all(elems, f)),
+            Type::Union(members) => Type::Union(map_all(members, f)),
+            Type::Intersection(members) => Type::intersection(map_all(members, f)),
+            Type::Generic { base, type_args } => Type::Generic { base: base.clone(), type_args: map_all(type_args, f) },
+            Type::Object(obj) => Type::Object(ObjectType {
+                name: obj.name.clone(),
+                properties: obj.properties.iter()
+                    .map(|(name, info)| (name.clone(), PropertyInfo { ty: info.ty.map_types(f), ..info.clone() }))
+                    .collect(),
+                index_signature: obj.index_signature.as_ref().map(|index| Box::new(index.map_types(f))),
+            }),
+            Type::Function(func) => Type::Function(FunctionType {
+                params: func.params.iter().map(|(name, ty, optional)| (name.clone(), ty.map_types(f), *optional)).collect(),
+                return_type: Box::new(func.return_type.map_types(f)),
+                ..func.clone()
+            }),
+            Type::Conditional(cond) => Type::Conditional(Box::new(ConditionalType {
+                check: cond.check.map_types(f),
+                extends: cond.extends.map_types(f),
+                true_type: cond.true_type.map_types(f),
+                false_type: cond.false_type.map_types(f),
+                distributive: cond.distributive.clone(),
+            })),
+            Type::KeyOf(inner) => Type::KeyOf(Box::new(inner.map_types(f))),
+            Type::IndexedAccess { object, index } => Type::IndexedAccess {
+                object: Box::new(object.map_types(f)),
+                index: Box::new(index.map_types(f)),
+            },
+            _ => self.clone(),
+        }
+    }
+
+    /// Whether the type or any type inside it matches `pred`
+    pub fn contains(&self, pred: impl Fn(&Type) -> bool) -> bool {
+        let mut found = false;
+        self.map_types(&mut |ty| {
+            found |= pred(ty);
+            found.then(|| ty.clone())
+        });
+        found
+    }
+}
+
+/// TypeScript spelling of the type, e.g. `Map<string, number[]>` or `{ id: number; tag?: string }`
+impl std::fmt::Display for Type {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fn join(f: &mut std::fmt::Formatter<'_>, types: &[Type], separator: &str) -> std::fmt::Result {
+            for (i, ty) in types.iter().enumerate() {
+                if i > 0 {
+                    f.write_str(separator)?;
+                }
+                write!(f, "{}", ty)?;
+            }
+            Ok(())
+        }
+        match self {
+            Type::Void => f.write_str("void"),
+            Type::Null => f.write_str("null"),
+            Type::Boolean => f.write_str("boolean"),
+            Type::Number | Type::Int32 => f.write_str("number"),
+            Type::BigInt => f.write_str("bigint"),
+            Type::String => f.write_str("string"),
+            Type::Symbol => f.write_str("symbol"),
+            Type::Array(elem) => match elem.as_ref() {
+                Type::Union(_) | Type::Intersection(_) | Type::Function(_) => write!(f, "({})[]", elem),
+                _ => write!(f, "{}[]", elem),
+            },
+            Type::Tuple(elems) => {
+                f.write_str("[")?;
+                join(f, elems, ", ")?;
+                f.write_str("]")
+            }
+            Type::Object(obj) => {
+                let mut props: Vec<_> = obj.properties.iter().collect();
+                props.sort_by(|a, b| a.0.cmp(b.0));
+                let mut members: Vec<String> = props.into_iter()
+                    .map(|(name, info)| format!(
+                        "{}{}{}: {}",
+                        if info.readonly { "readonly " } else { "" },
+                        name,
+                        if info.optional { "?" } else { "" },
+                        info.ty
+                    ))
+                    .collect();
+                if let Some(index) = &obj.index_signature {
+                    members.push(format!("[key: string]: {}", index));
+                }
+                if members.is_empty() {
+                    f.write_str("{}")
+                } else {
+                    write!(f, "{{ {} }}", members.join("; "))
+                }
+            }
+            Type::Function(func) => {
+                let params: Vec<String> = func.params.iter()
+                    .map(|(name, ty, optional)| format!("{}{}: {}", name, if *optional { "?" } else { "" }, ty))
+                    .collect();
+                write!(f, "({}) => {}", params.join(", "), func.return_type)
+            }
+            Type::Union(members) => join(f, members, " | "),
+            Type::Intersection(members) => join(f, members, " & "),
+            Type::Literal(lit) => write!(f, "{}", lit),
+            Type::Promise(inner) => write!(f, "Promise<{}>", inner),
+            Type::Any => f.write_str("any"),
+            Type::Unknown => f.write_str("unknown"),
+            Type::Never => f.write_str("never"),
+            Type::Named(name) | Type::TypeVar(name) => f.write_str(name),
+            Type::Generic { base, type_args } => {
+                write!(f, "{}<", base)?;
+                join(f, type_args, ", ")?;
+                f.write_str(">")
+            }
+            Type::Conditional(cond) => {
+                // Variables are declared with `infer` in the extends clause
+                let extends = cond.extends.map_types(&mut |ty| match ty {
+                    Type::Infer(name) => Some(Type::Named(format!("infer {}", name))),
+                    _ => None,
+                });
+                write!(f, "{} extends {} ? {} : {}", cond.check, extends, cond.true_type, cond.false_type)
+            }
+            Type::Infer(name) => f.write_str(name),
+            Type::KeyOf(inner) => match inner.as_ref() {
+                Type::Union(_) | Type::Intersection(_) | Type::Function(_) => write!(f, "keyof ({})", inner),
+                _ => write!(f, "keyof {}", inner),
+            },
+            Type::IndexedAccess { object, index } => match object.as_ref() {
+                Type::Union(_) | Type::Intersection(_) | Type::Function(_) | Type::KeyOf(_) => write!(f, "({})[{}]", object, index),
+                _ => write!(f, "{}[{}]", object, index),
+            },
+        }
+    }
+}
+
+impl ObjectType {
+    /// Add `other`'s properties and index signature; its entries replace same-named ones
+    pub fn merge(&mut self, other: ObjectType) {
+        self.properties.extend(other.properties);
+        if other.index_signature.is_some() {
+            self.index_signature = other.index_signature;
+        }
+    }
 }
 
 impl Default for ObjectType {
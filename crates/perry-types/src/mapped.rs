@@ -0,0 +1,185 @@
+//! Mapped type evaluation
+//!
+//! A mapped type builds an object type from a set of keys. Homomorphic mappings copy the
+//! properties of a known object type, optionally replacing their value type and changing
+//! their `?` and `readonly` modifiers: `{ [K in keyof T]?: T[K] }` and the `Partial`,
+//! `Required`, `Readonly`, `Pick` and `Omit` utilities. Mappings over a key type give every
+//! key the same value type: literal keys become properties and `string` or `number` an
+//! index signature, as in `{ [K in "a" | "b"]: V }` and `Record<K, V>`.
+//!
+//! Evaluation needs the source's properties, so callers resolve named types to a single
+//! `ObjectType` first (following `extends` and intersections) and pass it in.
+
+use crate::{LiteralType, ObjectType, PropertyInfo, Type};
+
+/// Standard library utility types that are mapped types
+pub const MAPPED_UTILITIES: &[&str] = &["Partial", "Required", "Readonly", "Pick", "Omit", "Record"];
+
+/// How a mapped type changes a property modifier: `?`/`+?` adds it, `-?` removes it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ModifierEdit {
+    /// Keep the source property's modifier
+    #[default]
+    Keep,
+    Add,
+    Remove,
+}
+
+impl ModifierEdit {
+    pub fn apply(self, current: bool) -> bool {
+        match self {
+            ModifierEdit::Keep => current,
+            ModifierEdit::Add => true,
+            ModifierEdit::Remove => false,
+        }
+    }
+}
+
+impl ObjectType {
+    /// Homomorphic mapping of this type's properties. `value` replaces every property's
+    /// type (`{ [K in keyof T]: boolean }`); None keeps it (`T[K]`).
+    pub fn map_properties(&self, value: Option<&Type>, optional: ModifierEdit, readonly: ModifierEdit) -> ObjectType {
+        let properties = self.properties.iter()
+            .map(|(name, info)| {
+                let info = PropertyInfo {
+                    ty: value.cloned().unwrap_or_else(|| info.ty.clone()),
+                    optional: optional.apply(info.optional),
+                    readonly: readonly.apply(info.readonly),
+                };
+                (name.clone(), info)
+            })
+            .collect();
+        let index_signature = match value {
+            Some(value) => self.index_signature.as_ref().map(|_| Box::new(value.clone())),
+            None => self.index_signature.clone(),
+        };
+        ObjectType { name: None, properties, index_signature }
+    }
+
+    /// Mapping over a key type, `{ [K in keys]: value }`. None unless the keys are string or
+    /// number literals, `string`, `number`, or a union of those.
+    pub fn from_keys(keys: &Type, value: &Type, optional: ModifierEdit, readonly: ModifierEdit) -> Option<ObjectType> {
+        let info = PropertyInfo { ty: value.clone(), optional: optional.apply(false), readonly: readonly.apply(false) };
+        let mut object = ObjectType::default();
+        add_keys(&mut object, keys, &info)?;
+        Some(object)
+    }
+
+    /// A property by name, falling back to the index signature
+    pub fn property(&self, name: &str) -> Option<PropertyInfo> {
+        self.properties.get(name).cloned().or_else(|| {
+            let ty = self.index_signature.as_deref()?.clone();
+            Some(PropertyInfo { ty, optional: false, readonly: false })
+        })
+    }
+}
+
+fn add_keys(object: &mut ObjectType, keys: &Type, info: &PropertyInfo) -> Option<()> {
+    match keys {
+        Type::Literal(LiteralType::String(_) | LiteralType::Number(_)) => {
+            object.properties.insert(literal_key(keys)?, info.clone());
+        }
+        Type::String | Type::Number | Type::Int32 => object.index_signature = Some(Box::new(info.ty.clone())),
+        Type::Union(members) => {
+            for member in members {
+                add_keys(object, member, info)?;
+            }
+        }
+        _ => return None,
+    }
+    Some(())
+}
+
+fn literal_key(key: &Type) -> Option<String> {
+    match key {
+        Type::Literal(LiteralType::String(key)) => Some(key.clone()),
+        Type::Literal(LiteralType::Number(key)) => Some(key.to_string()),
+        _ => None,
+    }
+}
+
+/// Keys named by a `Pick`/`Omit` key type: a literal or a union of literals
+fn literal_keys(keys: &Type) -> Option<Vec<String>> {
+    match keys {
+        Type::Union(members) => members.iter().map(literal_key).collect(),
+        key => Some(vec![literal_key(key)?]),
+    }
+}
+
+/// Evaluate a standard mapped utility type. `source` is the object type of the first type
+/// argument, which all but `Record` need. None when `base` isn't a mapped utility or its
+/// arguments can't be evaluated.
+pub fn evaluate_utility(base: &str, type_args: &[Type], source: Option<&ObjectType>) -> Option<ObjectType> {
+    use ModifierEdit::{Add, Keep, Remove};
+    match (base, type_args) {
+        ("Partial", [_]) => Some(source?.map_properties(None, Add, Keep)),
+        ("Required", [_]) => Some(source?.map_properties(None, Remove, Keep)),
+        ("Readonly", [_]) => Some(source?.map_properties(None, Keep, Add)),
+        ("Pick" | "Omit", [_, keys]) => {
+            let keys = literal_keys(keys)?;
+            let keep = base == "Pick";
+            let mut object = source?.map_properties(None, Keep, Keep);
+            object.properties.retain(|name, _| keys.contains(name) == keep);
+            if keep {
+                object.index_signature = None;
+            }
+            Some(object)
+        }
+        ("Record", [keys, value]) => ObjectType::from_keys(keys, value, Keep, Keep),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn prop(ty: Type, optional: bool, readonly: bool) -> PropertyInfo {
+        PropertyInfo { ty, optional, readonly }
+    }
+
+    fn user() -> ObjectType {
+        let mut user = ObjectType { name: Some("User".into()), ..ObjectType::default() };
+        user.properties.insert("id".into(), prop(Type::Number, false, true));
+        user.properties.insert("email".into(), prop(Type::String, true, false));
+        user
+    }
+
+    #[test]
+    fn test_homomorphic_utilities() {
+        let args = [Type::Named("User".into())];
+        let partial = evaluate_utility("Partial", &args, Some(&user())).unwrap();
+        assert_eq!(partial.name, None);
+        assert_eq!(partial.properties["id"], prop(Type::Number, true, true));
+        let required = evaluate_utility("Required", &args, Some(&user())).unwrap();
+        assert_eq!(required.properties["email"], prop(Type::String, false, false));
+        let frozen = evaluate_utility("Readonly", &args, Some(&user())).unwrap();
+        assert!(frozen.properties.values().all(|info| info.readonly));
+
+        let email = Type::Literal(LiteralType::String("email".into()));
+        let picked = evaluate_utility("Pick", &[args[0].clone(), email.clone()], Some(&user())).unwrap();
+        assert_eq!(picked.properties.keys().collect::<Vec<_>>(), vec!["email"]);
+        let omitted = evaluate_utility("Omit", &[args[0].clone(), email], Some(&user())).unwrap();
+        assert_eq!(omitted.properties.keys().collect::<Vec<_>>(), vec!["id"]);
+        // The source has to be known, and Pick/Omit keys have to be literals
+        assert!(evaluate_utility("Partial", &args, None).is_none());
+        assert!(evaluate_utility("Pick", &[args[0].clone(), Type::String], Some(&user())).is_none());
+    }
+
+    #[test]
+    fn test_key_mappings() {
+        let record = evaluate_utility("Record", &[Type::String, Type::Number], None).unwrap();
+        assert!(record.properties.is_empty());
+        assert_eq!(record.property("anything"), Some(prop(Type::Number, false, false)));
+
+        let keys = Type::Union(vec![
+            Type::Literal(LiteralType::String("theme".into())),
+            Type::Literal(LiteralType::Number(2.0)),
+        ]);
+        let settings = ObjectType::from_keys(&keys, &Type::Boolean, ModifierEdit::Add, ModifierEdit::Keep).unwrap();
+        assert_eq!(settings.properties["theme"], prop(Type::Boolean, true, false));
+        assert!(settings.properties.contains_key("2"));
+        assert_eq!(settings.property("other"), None);
+        assert!(ObjectType::from_keys(&Type::Boolean, &Type::Any, ModifierEdit::Keep, ModifierEdit::Keep).is_none());
+    }
+}
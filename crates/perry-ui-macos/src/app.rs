@@ -1,18 +1,42 @@
 use objc2::rc::Retained;
 use objc2::MainThreadOnly;
 use objc2_app_kit::{
-    NSApplication, NSApplicationActivationPolicy, NSBackingStoreType, NSWindow,
+    NSApplication, NSApplicationActivationPolicy, NSBackingStoreType, NSEventMask, NSWindow,
     NSWindowStyleMask,
 };
 use objc2_core_foundation::{CGPoint, CGSize, CGRect};
-use objc2_foundation::{NSString, MainThreadMarker};
+use objc2_foundation::{NSDate, NSDefaultRunLoopMode, NSString, MainThreadMarker};
 
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
+use std::ffi::c_void;
 
 use crate::widgets;
 
 thread_local! {
     static APPS: RefCell<Vec<AppEntry>> = RefCell::new(Vec::new());
+    /// Set while the run loop timer is pumping, so a nested fire (from a handler that
+    /// awaits) doesn't pump again
+    static PUMPING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// How often the run loop pumps promises, timers and async stdlib work (60Hz)
+const PUMP_INTERVAL_SECS: f64 = 1.0 / 60.0;
+
+#[link(name = "CoreFoundation", kind = "framework")]
+extern "C" {
+    static kCFRunLoopCommonModes: *const c_void;
+    fn CFRunLoopGetMain() -> *mut c_void;
+    fn CFAbsoluteTimeGetCurrent() -> f64;
+    fn CFRunLoopTimerCreate(
+        allocator: *const c_void,
+        fire_date: f64,
+        interval: f64,
+        flags: u64,
+        order: i64,
+        callout: extern "C" fn(*mut c_void, *mut c_void),
+        context: *mut c_void,
+    ) -> *mut c_void;
+    fn CFRunLoopAddTimer(run_loop: *mut c_void, timer: *mut c_void, mode: *const c_void);
 }
 
 struct AppEntry {
@@ -100,5 +124,53 @@ pub fn app_run(_app_handle: i64) {
     #[allow(deprecated)]
     app.activateIgnoringOtherApps(true);
 
+    // NSApplication owns the main thread from here on: pump the async runtime from its run
+    // loop, and keep dispatching UI events while compiled code blocks in an await
+    unsafe {
+        let timer = CFRunLoopTimerCreate(
+            std::ptr::null(),
+            CFAbsoluteTimeGetCurrent() + PUMP_INTERVAL_SECS,
+            PUMP_INTERVAL_SECS,
+            0,
+            0,
+            pump_timer_fired,
+            std::ptr::null_mut(),
+        );
+        CFRunLoopAddTimer(CFRunLoopGetMain(), timer, kCFRunLoopCommonModes);
+    }
+    perry_runtime::timer::js_set_await_wait(ui_await_wait);
+
     app.run();
 }
+
+/// Run loop timer callout: apply state set off the main thread, then run microtasks,
+/// expired timers and completed stdlib async work
+extern "C" fn pump_timer_fired(_timer: *mut c_void, _info: *mut c_void) {
+    if PUMPING.with(|p| p.replace(true)) {
+        return;
+    }
+    crate::state::apply_pending_sets();
+    perry_runtime::timer::js_event_loop_pump();
+    PUMPING.with(|p| p.set(false));
+}
+
+/// Await hook: dispatch UI events for up to `ms` instead of sleeping, so the window stays
+/// responsive while a handler awaits
+extern "C" fn ui_await_wait(ms: f64) {
+    let Some(mtm) = MainThreadMarker::new() else {
+        perry_runtime::timer::js_sleep_ms(ms);
+        return;
+    };
+    let app = NSApplication::sharedApplication(mtm);
+    let until = NSDate::dateWithTimeIntervalSinceNow(ms / 1000.0);
+    unsafe {
+        while let Some(event) = app.nextEventMatchingMask_untilDate_inMode_dequeue(
+            NSEventMask::Any,
+            Some(&until),
+            NSDefaultRunLoopMode,
+            true,
+        ) {
+            app.sendEvent(&event);
+        }
+    }
+}
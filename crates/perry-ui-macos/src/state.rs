@@ -1,4 +1,7 @@
 use std::cell::RefCell;
+use std::sync::Mutex;
+
+use objc2_foundation::MainThreadMarker;
 
 /// Callback invoked when state changes: (state_id)
 type RebuildFn = Box<dyn Fn(i64)>;
@@ -7,6 +10,9 @@ struct StateEntry {
     value: f64,
 }
 
+/// State sets made off the main thread, as (handle, value bits); applied by `apply_pending_sets`
+static PENDING_SETS: Mutex<Vec<(i64, u64)>> = Mutex::new(Vec::new());
+
 thread_local! {
     static STATES: RefCell<Vec<StateEntry>> = RefCell::new(Vec::new());
     static REBUILD_CB: RefCell<Option<RebuildFn>> = RefCell::new(None);
@@ -35,7 +41,12 @@ pub fn state_get(handle: i64) -> f64 {
 }
 
 /// Set a new value on a state cell and trigger re-render.
+/// Off the main thread the set is queued and applied by the app's run loop.
 pub fn state_set(handle: i64, value: f64) {
+    if MainThreadMarker::new().is_none() {
+        PENDING_SETS.lock().unwrap().push((handle, value.to_bits()));
+        return;
+    }
     STATES.with(|s| {
         let mut states = s.borrow_mut();
         let idx = (handle - 1) as usize;
@@ -57,3 +68,11 @@ pub fn set_rebuild_callback(cb: RebuildFn) {
         *rc.borrow_mut() = Some(cb);
     });
 }
+
+/// Apply the state sets queued from other threads, in order. Main thread only.
+pub fn apply_pending_sets() {
+    let pending = std::mem::take(&mut *PENDING_SETS.lock().unwrap());
+    for (handle, bits) in pending {
+        state_set(handle, f64::from_bits(bits));
+    }
+}
@@ -30,14 +30,15 @@ define_class!(
         #[unsafe(method(buttonPressed:))]
         fn button_pressed(&self, _sender: &AnyObject) {
             let key = self.ivars().callback_key.get();
-            BUTTON_CALLBACKS.with(|cbs| {
-                if let Some(&closure_f64) = cbs.borrow().get(&key) {
-                    let closure_i64 = closure_f64.to_bits() as i64;
-                    unsafe {
-                        js_closure_call0(closure_i64);
-                    }
+            // Copy the closure out first: an async handler that awaits dispatches UI
+            // events (and so other buttons' handlers) before it returns
+            let closure_f64 = BUTTON_CALLBACKS.with(|cbs| cbs.borrow().get(&key).copied());
+            if let Some(closure_f64) = closure_f64 {
+                let closure_i64 = closure_f64.to_bits() as i64;
+                unsafe {
+                    js_closure_call0(closure_i64);
                 }
-            });
+            }
         }
     }
 );
@@ -0,0 +1,494 @@
+//! Bindgen command - expose a Rust crate's C functions to TypeScript
+//!
+//! `perry bindgen <crate>` scans the crate's sources for `#[no_mangle] pub extern "C"`
+//! functions and writes two files:
+//!
+//! - a TypeScript module of `export declare function`s (`<crate>/bindings.ts`)
+//! - a Rust glue module (`<crate>/src/perry_bindings.rs`) with a `__wrapper_<name>` per
+//!   function. perry calls a declared function through that symbol with NaN-boxed `f64`
+//!   arguments (after an unused closure pointer); the wrapper unboxes them, calls the
+//!   function and boxes its result.
+//!
+//! The crate builds as a `staticlib` with the glue module included (`mod perry_bindings;`),
+//! and perry.toml's `[build] native_libs` links the library into the program.
+//!
+//! Supported parameter and return types: the integer and float primitives (`number`),
+//! `bool` (`boolean`), `*const StringHeader`/`*mut StringHeader` (perry strings, `string`),
+//! and `()` as a return type (`void`). Functions with other types are reported and skipped.
+
+use anyhow::{anyhow, Result};
+use clap::Args;
+use console::style;
+use std::fs;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+use crate::OutputFormat;
+
+#[derive(Args, Debug)]
+pub struct BindgenArgs {
+    /// Crate directory (containing Cargo.toml)
+    #[arg(default_value = ".")]
+    pub crate_dir: PathBuf,
+
+    /// TypeScript module to write (default: <crate>/bindings.ts)
+    #[arg(long, value_name = "PATH")]
+    pub ts_out: Option<PathBuf>,
+
+    /// Rust glue module to write (default: <crate>/src/perry_bindings.rs)
+    #[arg(long, value_name = "PATH")]
+    pub glue_out: Option<PathBuf>,
+}
+
+/// How a value crosses between TypeScript and the native function
+#[derive(Debug, Clone, PartialEq)]
+pub enum AbiType {
+    /// An integer or float primitive, converted with `as`
+    Number(String),
+    Bool,
+    /// A perry string (`*const StringHeader`)
+    Str,
+    /// `()` (return types only)
+    Void,
+}
+
+impl AbiType {
+    fn parse(ty: &str) -> Option<Self> {
+        let ty = ty.split_whitespace().collect::<Vec<_>>().join(" ");
+        match ty.as_str() {
+            "f64" | "f32" | "i8" | "i16" | "i32" | "i64" | "isize" | "u8" | "u16" | "u32" | "u64" | "usize" => {
+                Some(AbiType::Number(ty))
+            }
+            "bool" => Some(AbiType::Bool),
+            "()" => Some(AbiType::Void),
+            _ => {
+                let pointee = ty.strip_prefix("*const ").or_else(|| ty.strip_prefix("*mut "))?;
+                (pointee.rsplit("::").next() == Some("StringHeader")).then_some(AbiType::Str)
+            }
+        }
+    }
+
+    fn typescript(&self) -> &'static str {
+        match self {
+            AbiType::Number(_) => "number",
+            AbiType::Bool => "boolean",
+            AbiType::Str => "string",
+            AbiType::Void => "void",
+        }
+    }
+
+    /// The type in the glue's `extern` block (which can't name `StringHeader`)
+    fn rust(&self) -> &str {
+        match self {
+            AbiType::Number(ty) => ty,
+            AbiType::Bool => "bool",
+            AbiType::Str => "*mut c_void",
+            AbiType::Void => "()",
+        }
+    }
+}
+
+/// A `#[no_mangle] pub extern "C"` function of the crate
+#[derive(Debug, Clone, PartialEq)]
+pub struct NativeFunction {
+    pub name: String,
+    pub params: Vec<(String, AbiType)>,
+    pub return_type: AbiType,
+    /// `///` doc comment lines, carried over as JSDoc
+    pub docs: Vec<String>,
+}
+
+/// Exported functions of one source file, and (name, reason) for the ones that can't be bound
+pub fn scan_source(source: &str) -> (Vec<NativeFunction>, Vec<(String, String)>) {
+    let mut functions = Vec::new();
+    let mut skipped = Vec::new();
+    let lines: Vec<&str> = source.lines().collect();
+    let mut docs = Vec::new();
+    let mut no_mangle = false;
+    let mut i = 0;
+    while i < lines.len() {
+        let line = lines[i].trim();
+        i += 1;
+        if let Some(doc) = line.strip_prefix("///") {
+            docs.push(doc.trim().to_string());
+            continue;
+        }
+        if line.starts_with("#[") {
+            no_mangle |= line.starts_with("#[no_mangle]") || line.starts_with("#[unsafe(no_mangle)]");
+            continue;
+        }
+        if line.is_empty() || line.starts_with("//") {
+            continue;
+        }
+        if no_mangle && line.starts_with("pub") && line.contains("extern \"C\" fn ") {
+            // The signature may span lines; it ends at the body
+            let mut signature = line.to_string();
+            while !signature.contains('{') && !signature.contains(';') && i < lines.len() {
+                signature.push(' ');
+                signature.push_str(lines[i].trim());
+                i += 1;
+            }
+            match parse_signature(&signature) {
+                Ok((name, _, _)) if name.starts_with("__wrapper_") => {}
+                Ok((name, params, return_type)) => {
+                    functions.push(NativeFunction { name, params, return_type, docs: std::mem::take(&mut docs) });
+                }
+                Err((name, reason)) => skipped.push((name, reason)),
+            }
+        }
+        docs.clear();
+        no_mangle = false;
+    }
+    (functions, skipped)
+}
+
+type Signature = (String, Vec<(String, AbiType)>, AbiType);
+
+fn parse_signature(signature: &str) -> Result<Signature, (String, String)> {
+    let rest = &signature[signature.find("extern \"C\" fn ").unwrap_or(0) + "extern \"C\" fn ".len()..];
+    let open = rest.find('(').ok_or_else(|| (rest.trim().to_string(), "unparsable signature".to_string()))?;
+    let name = rest[..open].trim().to_string();
+    if name.contains('<') {
+        return Err((name, "generic functions can't be called from TypeScript".to_string()));
+    }
+    let close = matching_paren(rest, open).ok_or_else(|| (name.clone(), "unparsable signature".to_string()))?;
+
+    let mut params = Vec::new();
+    for (index, param) in split_top_level(&rest[open + 1..close]).into_iter().enumerate() {
+        let (pattern, ty) = param.split_once(':')
+            .ok_or_else(|| (name.clone(), format!("unparsable parameter `{}`", param)))?;
+        let ty = match AbiType::parse(ty) {
+            Some(AbiType::Void) | None => {
+                return Err((name, format!("unsupported parameter type `{}`", ty.trim())));
+            }
+            Some(ty) => ty,
+        };
+        params.push((param_name(pattern, index), ty));
+    }
+
+    let after = rest[close + 1..].trim();
+    let return_type = match after.strip_prefix("->") {
+        Some(ret) => {
+            let end = ret.find(['{', ';']).unwrap_or(ret.len());
+            let ret = ret[..end].split(" where ").next().unwrap_or("").trim();
+            AbiType::parse(ret).ok_or_else(|| (name.clone(), format!("unsupported return type `{}`", ret)))?
+        }
+        None => AbiType::Void,
+    };
+    Ok((name, params, return_type))
+}
+
+fn matching_paren(s: &str, open: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in s.char_indices().skip_while(|&(i, _)| i < open) {
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Split a parameter list at the commas outside brackets
+fn split_top_level(params: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let (mut depth, mut start) = (0i32, 0);
+    for (i, c) in params.char_indices() {
+        match c {
+            '(' | '[' | '<' => depth += 1,
+            ')' | ']' | '>' => depth -= 1,
+            ',' if depth == 0 => {
+                parts.push(params[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(params[start..].trim());
+    parts.retain(|part| !part.is_empty());
+    parts
+}
+
+/// Plain identifiers keep their name (renamed if JavaScript reserves it); `_` and
+/// destructuring patterns become `argN`
+fn param_name(pattern: &str, index: usize) -> String {
+    let pattern = pattern.trim();
+    let name = pattern.strip_prefix("mut ").unwrap_or(pattern).trim();
+    let name = name.strip_prefix("r#").unwrap_or(name);
+    if name == "_" || !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+        return format!("arg{}", index);
+    }
+    const JS_RESERVED: &[&str] = &[
+        "arguments", "case", "catch", "class", "default", "delete", "do", "eval", "export", "extends",
+        "finally", "function", "import", "instanceof", "new", "null", "switch", "this", "throw", "try",
+        "typeof", "var", "void", "with", "yield",
+    ];
+    if JS_RESERVED.contains(&name) {
+        format!("{}_", name)
+    } else {
+        name.to_string()
+    }
+}
+
+/// The `export declare function`s for the functions
+pub fn generate_typescript(crate_name: &str, functions: &[NativeFunction]) -> String {
+    let mut out = format!("// Generated by `perry bindgen` from the {} crate. Do not edit.\n", crate_name);
+    for function in functions {
+        out.push('\n');
+        if !function.docs.is_empty() {
+            out.push_str("/**\n");
+            for line in &function.docs {
+                out.push_str(&format!(" * {}\n", line).replace(" * \n", " *\n"));
+            }
+            out.push_str(" */\n");
+        }
+        let params: Vec<String> = function.params.iter()
+            .map(|(name, ty)| format!("{}: {}", name, ty.typescript()))
+            .collect();
+        out.push_str(&format!(
+            "export declare function {}({}): {};\n",
+            function.name,
+            params.join(", "),
+            function.return_type.typescript()
+        ));
+    }
+    out
+}
+
+/// The `__wrapper_<name>` functions perry's calls link against. Edition 2024 crates need
+/// `unsafe extern` blocks and `#[unsafe(no_mangle)]`.
+pub fn generate_glue(crate_name: &str, functions: &[NativeFunction], edition_2024: bool) -> String {
+    let (extern_block, no_mangle) = if edition_2024 {
+        ("unsafe extern", "#[unsafe(no_mangle)]")
+    } else {
+        ("extern", "#[no_mangle]")
+    };
+    let mut out = format!(
+        "//! Generated by `perry bindgen` from the {} crate. Do not edit.\n\
+         //!\n\
+         //! perry calls `declare function`s as `__wrapper_<name>(closure, args...) -> f64` with\n\
+         //! NaN-boxed arguments; each wrapper unboxes them and calls the function.\n\
+         #![allow(dead_code)]\n\
+         \n\
+         use std::ffi::c_void;\n\
+         \n\
+         const TAG_UNDEFINED: u64 = 0x7FFC_0000_0000_0001;\n\
+         const TAG_FALSE: u64 = 0x7FFC_0000_0000_0003;\n\
+         const TAG_TRUE: u64 = 0x7FFC_0000_0000_0004;\n\
+         \n\
+         {} \"C\" {{\n\
+         \x20   // perry runtime\n\
+         \x20   fn js_is_truthy(value: f64) -> i32;\n\
+         \x20   fn js_nanbox_get_pointer(value: f64) -> i64;\n\
+         \x20   fn js_nanbox_string(ptr: i64) -> f64;\n\
+         \n\
+         \x20   // {}\n",
+        crate_name, extern_block, crate_name
+    );
+    for function in functions {
+        let params: Vec<String> = function.params.iter()
+            .map(|(name, ty)| format!("{}: {}", name, ty.rust()))
+            .collect();
+        let ret = match &function.return_type {
+            AbiType::Void => String::new(),
+            ty => format!(" -> {}", ty.rust()),
+        };
+        out.push_str(&format!("    fn {}({}){};\n", function.name, params.join(", "), ret));
+    }
+    out.push_str("}\n");
+
+    for function in functions {
+        let params: Vec<String> = function.params.iter().map(|(name, _)| format!(", {}: f64", name)).collect();
+        let args: Vec<String> = function.params.iter()
+            .map(|(name, ty)| match ty {
+                AbiType::Number(ty) => format!("{} as {}", name, ty),
+                AbiType::Bool => format!("js_is_truthy({}) != 0", name),
+                AbiType::Str => format!("js_nanbox_get_pointer({}) as *mut c_void", name),
+                AbiType::Void => unreachable!("void parameters are rejected when scanning"),
+            })
+            .collect();
+        let call = format!("unsafe {{ {}({}) }}", function.name, args.join(", "));
+        let body = match &function.return_type {
+            AbiType::Number(_) => format!("    let result = {};\n    result as f64\n", call),
+            AbiType::Bool => format!("    let result = {};\n    f64::from_bits(if result {{ TAG_TRUE }} else {{ TAG_FALSE }})\n", call),
+            AbiType::Str => format!("    let result = {};\n    unsafe {{ js_nanbox_string(result as i64) }}\n", call),
+            AbiType::Void => format!("    {};\n    f64::from_bits(TAG_UNDEFINED)\n", call),
+        };
+        out.push_str(&format!(
+            "\n{}\npub extern \"C\" fn __wrapper_{}(_closure: i64{}) -> f64 {{\n{}}}\n",
+            no_mangle,
+            function.name,
+            params.concat(),
+            body
+        ));
+    }
+    out
+}
+
+/// The crate's library name (`[lib] name`, or the package name with `-` as `_`), whether
+/// it builds a staticlib, and whether it's edition 2024
+fn read_manifest(crate_dir: &Path) -> Result<(String, bool, bool)> {
+    let path = crate_dir.join("Cargo.toml");
+    let text = fs::read_to_string(&path).map_err(|e| anyhow!("Failed to read {}: {}", path.display(), e))?;
+    let manifest: toml::Table = toml::from_str(&text).map_err(|e| anyhow!("Invalid {}: {}", path.display(), e))?;
+    let lib = manifest.get("lib").and_then(|lib| lib.as_table());
+    let name = lib.and_then(|lib| lib.get("name")).and_then(|name| name.as_str())
+        .or_else(|| manifest.get("package")?.get("name")?.as_str())
+        .ok_or_else(|| anyhow!("{} has no package name", path.display()))?
+        .replace('-', "_");
+    let staticlib = lib.and_then(|lib| lib.get("crate-type")).and_then(|types| types.as_array())
+        .is_some_and(|types| types.iter().any(|ty| ty.as_str() == Some("staticlib")));
+    let edition = manifest.get("package").and_then(|package| package.get("edition")).and_then(|e| e.as_str());
+    Ok((name, staticlib, edition == Some("2024")))
+}
+
+pub fn run(args: BindgenArgs, format: OutputFormat, use_color: bool) -> Result<()> {
+    let (crate_name, staticlib, edition_2024) = read_manifest(&args.crate_dir)?;
+    let ts_out = args.ts_out.clone().unwrap_or_else(|| args.crate_dir.join("bindings.ts"));
+    let glue_out = args.glue_out.clone().unwrap_or_else(|| args.crate_dir.join("src/perry_bindings.rs"));
+    let glue_canonical = glue_out.canonicalize().ok();
+
+    let mut sources: Vec<PathBuf> = WalkDir::new(args.crate_dir.join("src"))
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.into_path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "rs"))
+        .filter(|path| glue_canonical.is_none() || path.canonicalize().ok() != glue_canonical)
+        .collect();
+    sources.sort();
+
+    let mut functions: Vec<NativeFunction> = Vec::new();
+    let mut skipped = Vec::new();
+    for path in &sources {
+        let source = fs::read_to_string(path).map_err(|e| anyhow!("Failed to read {}: {}", path.display(), e))?;
+        let (found, not_bound) = scan_source(&source);
+        for function in found {
+            if functions.iter().any(|f| f.name == function.name) {
+                skipped.push((function.name, format!("defined again in {}", path.display())));
+            } else {
+                functions.push(function);
+            }
+        }
+        skipped.extend(not_bound);
+    }
+    if functions.is_empty() {
+        return Err(anyhow!("No `#[no_mangle] pub extern \"C\"` functions found in {}", args.crate_dir.join("src").display()));
+    }
+
+    for (path, contents) in [(&ts_out, generate_typescript(&crate_name, &functions)), (&glue_out, generate_glue(&crate_name, &functions, edition_2024))] {
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, contents).map_err(|e| anyhow!("Failed to write {}: {}", path.display(), e))?;
+    }
+    let library = args.crate_dir.join("target/release").join(format!("lib{}.a", crate_name));
+
+    match format {
+        OutputFormat::Text => {
+            let names: Vec<&str> = functions.iter().map(|f| f.name.as_str()).collect();
+            println!("Bound {} function(s): {}", functions.len(), names.join(", "));
+            for (name, reason) in &skipped {
+                let warning = format!("Skipped {}: {}", name, reason);
+                if use_color {
+                    println!("{}", style(warning).yellow());
+                } else {
+                    println!("{}", warning);
+                }
+            }
+            println!("Wrote {} and {}", ts_out.display(), glue_out.display());
+            println!();
+            println!("Next steps:");
+            if !staticlib {
+                println!("  - set `crate-type = [\"staticlib\"]` under [lib] in Cargo.toml");
+            }
+            println!("  - add `mod perry_bindings;` to the crate root (once)");
+            println!("  - build the crate: cargo build --release");
+            println!("  - list {} under [build] native_libs in perry.toml", library.display());
+            println!("  - import the functions from {}", ts_out.display());
+        }
+        OutputFormat::Json => {
+            let output = serde_json::json!({
+                "crate": crate_name,
+                "functions": functions.iter().map(|f| &f.name).collect::<Vec<_>>(),
+                "skipped": skipped.iter().map(|(name, reason)| serde_json::json!({ "name": name, "reason": reason })).collect::<Vec<_>>(),
+                "typescript": ts_out,
+                "glue": glue_out,
+                "library": library,
+                "staticlib": staticlib,
+            });
+            println!("{}", serde_json::to_string_pretty(&output)?);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = r#"
+use perry_runtime::string::StringHeader;
+
+/// Distance between two points, in meters.
+#[no_mangle]
+pub extern "C" fn geo_distance(lat1: f64, lon1: f64,
+                               lat2: f64, lon2: f64) -> f64 {
+    0.0
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn geo_name(code: i32, _: bool) -> *mut StringHeader {
+    std::ptr::null_mut()
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn geo_reset(default: *const perry_runtime::StringHeader) {}
+
+#[no_mangle]
+pub extern "C" fn geo_points(points: *const f64, len: usize) -> u32 { 0 }
+
+pub extern "C" fn not_exported(x: f64) -> f64 { x }
+"#;
+
+    #[test]
+    fn test_scan_source() {
+        let (functions, skipped) = scan_source(SOURCE);
+        assert_eq!(functions.len(), 3);
+        assert_eq!(functions[0].name, "geo_distance");
+        assert_eq!(functions[0].params.len(), 4);
+        assert_eq!(functions[0].docs, vec!["Distance between two points, in meters."]);
+        assert_eq!(functions[1].params, vec![("code".to_string(), AbiType::Number("i32".to_string())), ("arg1".to_string(), AbiType::Bool)]);
+        assert_eq!(functions[1].return_type, AbiType::Str);
+        assert_eq!(functions[2].params, vec![("default_".to_string(), AbiType::Str)]);
+        assert_eq!(functions[2].return_type, AbiType::Void);
+        assert_eq!(skipped, vec![("geo_points".to_string(), "unsupported parameter type `*const f64`".to_string())]);
+    }
+
+    #[test]
+    fn test_generate_bindings() {
+        let (functions, _) = scan_source(SOURCE);
+        let ts = generate_typescript("geo", &functions);
+        assert!(ts.contains("/**\n * Distance between two points, in meters.\n */\n\
+            export declare function geo_distance(lat1: number, lon1: number, lat2: number, lon2: number): number;\n"));
+        assert!(ts.contains("export declare function geo_name(code: number, arg1: boolean): string;\n"));
+        assert!(ts.contains("export declare function geo_reset(default_: string): void;\n"));
+
+        let glue = generate_glue("geo", &functions, false);
+        assert!(glue.contains("    fn geo_name(code: i32, arg1: bool) -> *mut c_void;\n"));
+        assert!(glue.contains("pub extern \"C\" fn __wrapper_geo_name(_closure: i64, code: f64, arg1: f64) -> f64 {\n\
+            \x20   let result = unsafe { geo_name(code as i32, js_is_truthy(arg1) != 0) };\n\
+            \x20   unsafe { js_nanbox_string(result as i64) }\n}\n"));
+        assert!(glue.contains("    unsafe { geo_reset(js_nanbox_get_pointer(default_) as *mut c_void) };\n\
+            \x20   f64::from_bits(TAG_UNDEFINED)\n"));
+
+        let glue = generate_glue("geo", &functions, true);
+        assert!(glue.contains("unsafe extern \"C\" {\n"));
+        assert!(glue.contains("#[unsafe(no_mangle)]\npub extern \"C\" fn __wrapper_geo_distance("));
+    }
+}
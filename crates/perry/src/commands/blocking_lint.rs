@@ -0,0 +1,654 @@
+//! Blocking work inside HTTP handlers (`perry check --perf`)
+//!
+//! F002: a server runs every request on one event loop, so a handler that blocks stalls
+//! all the others. Handlers are found syntactically: functions passed to
+//! `app.get/post/put/delete/patch/head/options/all(path, ...)`, the `handler` and hook
+//! properties of `app.route({...})`, `app.addHook(name, fn)`, `app.use(fn)`,
+//! `createServer(fn)`, and controller methods decorated with `@Get()`, `@Post()`, ...
+//! A handler may also be named, when it refers to a function declared at the top of
+//! the module.
+//!
+//! Inside a handler two things are reported: calls to known synchronous APIs (`fs.*Sync`,
+//! `execSync`, `pbkdf2Sync`, `zlib.*Sync`, `bcrypt.hashSync`, ...), and loops over data
+//! the handler awaited that run another loop in their body without awaiting anything,
+//! which grows with the square of the result size. `fs` calls in async handlers come with
+//! a rewrite to `fs.promises`, which is never applied automatically.
+
+use perry_diagnostics::{Applicability, Diagnostic, DiagnosticCode, FileId, Span, Suggestion};
+use perry_parser::swc_ecma_ast::*;
+use perry_parser::Spanned;
+use std::collections::{HashMap, HashSet};
+
+/// Synchronous calls and the asynchronous API to use instead
+const BLOCKING_CALLS: &[(&str, &str)] = &[
+    ("readFileSync", "fs.promises.readFile"),
+    ("writeFileSync", "fs.promises.writeFile"),
+    ("appendFileSync", "fs.promises.appendFile"),
+    ("readdirSync", "fs.promises.readdir"),
+    ("statSync", "fs.promises.stat"),
+    ("lstatSync", "fs.promises.lstat"),
+    ("existsSync", "fs.promises.access"),
+    ("accessSync", "fs.promises.access"),
+    ("mkdirSync", "fs.promises.mkdir"),
+    ("rmSync", "fs.promises.rm"),
+    ("rmdirSync", "fs.promises.rmdir"),
+    ("unlinkSync", "fs.promises.unlink"),
+    ("renameSync", "fs.promises.rename"),
+    ("copyFileSync", "fs.promises.copyFile"),
+    ("realpathSync", "fs.promises.realpath"),
+    ("execSync", "child_process.exec"),
+    ("execFileSync", "child_process.execFile"),
+    ("spawnSync", "child_process.spawn"),
+    ("pbkdf2Sync", "crypto.pbkdf2"),
+    ("scryptSync", "crypto.scrypt"),
+    ("generateKeyPairSync", "crypto.generateKeyPair"),
+    ("hashSync", "bcrypt.hash"),
+    ("compareSync", "bcrypt.compare"),
+    ("genSaltSync", "bcrypt.genSalt"),
+    ("gzipSync", "zlib.gzip"),
+    ("gunzipSync", "zlib.gunzip"),
+    ("deflateSync", "zlib.deflate"),
+    ("inflateSync", "zlib.inflate"),
+    ("brotliCompressSync", "zlib.brotliCompress"),
+    ("brotliDecompressSync", "zlib.brotliDecompress"),
+];
+
+/// Route registration methods taking `(path, ...handlers)`
+const ROUTE_METHODS: &[&str] = &["get", "post", "put", "delete", "patch", "head", "options", "all"];
+
+/// Decorators marking a controller method as a route
+const ROUTE_DECORATORS: &[&str] = &["Get", "Post", "Put", "Delete", "Patch", "Head", "Options", "All"];
+
+/// `route({...})` properties holding per-request functions
+const ROUTE_HOOKS: &[&str] = &[
+    "onRequest", "preParsing", "preValidation", "preHandler", "preSerialization", "onSend",
+    "onResponse", "onError", "onTimeout",
+];
+
+/// Array methods that loop over the array
+const ITERATION_METHODS: &[&str] = &[
+    "forEach", "map", "filter", "find", "findIndex", "some", "every", "reduce", "includes",
+    "indexOf", "flatMap", "sort",
+];
+
+/// A function that runs for each request
+#[derive(Clone, Copy)]
+enum Handler<'a> {
+    Function(&'a Function),
+    Arrow(&'a ArrowExpr),
+}
+
+impl<'a> Handler<'a> {
+    /// Where the function is, to tell handlers apart
+    fn key(&self) -> (u32, u32) {
+        let span = match self {
+            Handler::Function(f) => f.span,
+            Handler::Arrow(a) => a.span,
+        };
+        (span.lo.0, span.hi.0)
+    }
+
+    fn is_async(&self) -> bool {
+        match self {
+            Handler::Function(f) => f.is_async,
+            Handler::Arrow(a) => a.is_async,
+        }
+    }
+
+    fn walk(&self, visit: &mut dyn FnMut(Node<'a>)) {
+        match self {
+            Handler::Function(f) => {
+                for stmt in f.body.iter().flat_map(|body| &body.stmts) {
+                    walk_stmt(stmt, visit);
+                }
+            }
+            Handler::Arrow(a) => match &*a.body {
+                BlockStmtOrExpr::BlockStmt(block) => block.stmts.iter().for_each(|stmt| walk_stmt(stmt, visit)),
+                BlockStmtOrExpr::Expr(body) => walk_expr(body, visit),
+            },
+        }
+    }
+}
+
+/// Blocking-call lints for one module
+pub struct BlockingLinter<'a> {
+    file_id: FileId,
+    source: &'a str,
+    /// Functions declared at the top of the module, for handlers passed by name
+    functions: HashMap<String, Handler<'a>>,
+    /// Handlers already checked, by span: a named function can be registered twice
+    checked: HashSet<(u32, u32)>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> BlockingLinter<'a> {
+    /// Report blocking work in the module's HTTP handlers
+    pub fn check(module: &'a Module, file_id: FileId, source: &'a str) -> Vec<Diagnostic> {
+        let mut linter = BlockingLinter {
+            file_id,
+            source,
+            functions: HashMap::new(),
+            checked: HashSet::new(),
+            diagnostics: Vec::new(),
+        };
+        for item in &module.body {
+            let decl = match item {
+                ModuleItem::Stmt(Stmt::Decl(decl)) => decl,
+                ModuleItem::ModuleDecl(ModuleDecl::ExportDecl(export)) => &export.decl,
+                _ => continue,
+            };
+            linter.declare(decl);
+        }
+
+        let mut handlers = Vec::new();
+        for item in &module.body {
+            walk_module_item(item, &mut |node| match node {
+                Node::Expr(Expr::Call(call)) => handlers.extend(linter.registered_handlers(call)),
+                Node::Class(class) => handlers.extend(controller_routes(class).map(|h| (h, "handler"))),
+                _ => {}
+            });
+        }
+        for (handler, kind) in handlers {
+            linter.check_handler(handler, kind);
+        }
+        linter.diagnostics
+    }
+
+    fn declare(&mut self, decl: &'a Decl) {
+        match decl {
+            Decl::Fn(f) => {
+                self.functions.insert(f.ident.sym.to_string(), Handler::Function(&f.function));
+            }
+            Decl::Var(var) => {
+                for declarator in &var.decls {
+                    let (Pat::Ident(binding), Some(init)) = (&declarator.name, &declarator.init) else { continue };
+                    if let Some(handler) = as_function(init) {
+                        self.functions.insert(binding.id.sym.to_string(), handler);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// An inline function, or a top-level function passed by name
+    fn handler(&self, expr: &'a Expr) -> Option<Handler<'a>> {
+        match strip_parens(expr) {
+            Expr::Ident(ident) => self.functions.get(ident.sym.as_ref()).copied(),
+            expr => as_function(expr),
+        }
+    }
+
+    /// Handlers a call registers, with what kind of per-request function each is
+    fn registered_handlers(&self, call: &'a CallExpr) -> Vec<(Handler<'a>, &'static str)> {
+        let Callee::Expr(callee) = &call.callee else { return Vec::new() };
+        let method = match strip_parens(callee) {
+            Expr::Ident(ident) => ident.sym.as_ref(),
+            Expr::Member(member) => match &member.prop {
+                MemberProp::Ident(prop) => prop.sym.as_ref(),
+                _ => return Vec::new(),
+            },
+            _ => return Vec::new(),
+        };
+        let handlers = |args: &'a [ExprOrSpread], kind| {
+            args.iter().filter_map(|arg| self.handler(&arg.expr)).map(|h| (h, kind)).collect::<Vec<_>>()
+        };
+        match method {
+            _ if ROUTE_METHODS.contains(&method) => match call.args.first().map(|arg| strip_parens(&arg.expr)) {
+                // A path first tells routes apart from `map.get(key)` and friends
+                Some(Expr::Lit(Lit::Str(_)) | Expr::Tpl(_)) => handlers(&call.args[1..], "handler"),
+                _ => Vec::new(),
+            },
+            "route" => match call.args.first().map(|arg| strip_parens(&arg.expr)) {
+                Some(Expr::Object(options)) => options.props.iter().filter_map(|prop| {
+                    let PropOrSpread::Prop(prop) = prop else { return None };
+                    let (key, handler) = match &**prop {
+                        Prop::KeyValue(kv) => (&kv.key, self.handler(&kv.value)?),
+                        Prop::Method(m) => (&m.key, Handler::Function(&m.function)),
+                        _ => return None,
+                    };
+                    let PropName::Ident(key) = key else { return None };
+                    match key.sym.as_ref() {
+                        "handler" => Some((handler, "handler")),
+                        key if ROUTE_HOOKS.contains(&key) => Some((handler, "hook")),
+                        _ => None,
+                    }
+                }).collect(),
+                _ => Vec::new(),
+            },
+            "addHook" if call.args.len() >= 2 => handlers(&call.args[1..], "hook"),
+            "use" => handlers(&call.args, "middleware"),
+            "createServer" => handlers(&call.args, "handler"),
+            _ => Vec::new(),
+        }
+    }
+
+    fn check_handler(&mut self, handler: Handler<'a>, kind: &'static str) {
+        if !self.checked.insert(handler.key()) {
+            return;
+        }
+        // Locals holding awaited results, e.g. rows from a query
+        let mut awaited = HashSet::new();
+        let mut found = Vec::new();
+        handler.walk(&mut |node| match node {
+            Node::Stmt(Stmt::Decl(Decl::Var(var))) => {
+                for declarator in &var.decls {
+                    if let (Pat::Ident(binding), Some(Expr::Await(_))) =
+                        (&declarator.name, declarator.init.as_deref().map(strip_parens))
+                    {
+                        awaited.insert(binding.id.sym.to_string());
+                    }
+                }
+            }
+            Node::Stmt(Stmt::ForOf(for_of)) if !for_of.is_await && is_awaited(&for_of.right, &awaited) => {
+                if runs_nested_loop(|visit| walk_stmt(&for_of.body, visit)) {
+                    found.push(Found::Loop(self.span(for_of)));
+                }
+            }
+            Node::Expr(Expr::Call(call)) => {
+                if let Some(blocking) = blocking_call(call) {
+                    found.push(Found::Call(call, blocking));
+                } else if let Some(callback) = iteration_callback(call, &awaited) {
+                    if runs_nested_loop(|visit| callback.walk(visit)) {
+                        found.push(Found::Loop(self.span(call)));
+                    }
+                }
+            }
+            _ => {}
+        });
+
+        for found in found {
+            let diagnostic = match found {
+                Found::Call(call, (name, alternative)) => {
+                    let mut diagnostic = Diagnostic::new(
+                        DiagnosticCode::BlockingInHandler,
+                        format!("`{}` blocks the event loop inside an HTTP {}", name, kind),
+                    )
+                    .with_span(self.span(call))
+                    .with_primary_label(self.span(call), "no other request is served until this returns")
+                    .with_help(format!("use the asynchronous `{}` and await it", alternative));
+                    if handler.is_async() {
+                        if let Some(suggestion) = self.fs_promises_suggestion(call, name, alternative) {
+                            diagnostic = diagnostic.with_suggestion(suggestion);
+                        }
+                    }
+                    diagnostic
+                }
+                Found::Loop(span) => Diagnostic::new(
+                    DiagnosticCode::BlockingInHandler,
+                    format!("nested loop over awaited data inside an HTTP {} never yields", kind),
+                )
+                .with_span(span)
+                .with_primary_label(span, "runs to completion before any other request is served")
+                .with_help("the work grows with the square of the result size; index the data in a Map first, or process it in chunks with `await new Promise(setImmediate)` between them"),
+            };
+            self.diagnostics.push(diagnostic.build());
+        }
+    }
+
+    /// `fs.readFileSync(path)` -> `(await fs.promises.readFile(path))`
+    fn fs_promises_suggestion(&self, call: &CallExpr, name: &str, alternative: &str) -> Option<Suggestion> {
+        // `access` throws where `existsSync` returns false
+        if name == "existsSync" || !alternative.starts_with("fs.promises.") {
+            return None;
+        }
+        let Callee::Expr(callee) = &call.callee else { return None };
+        let Expr::Member(member) = strip_parens(callee) else { return None };
+        let Expr::Ident(module) = strip_parens(&member.obj) else { return None };
+        let args = match (call.args.first(), call.args.last()) {
+            (Some(first), Some(last)) => self.source.get(
+                first.span().lo.0.saturating_sub(1) as usize..last.span().hi.0.saturating_sub(1) as usize,
+            )?,
+            _ => "",
+        };
+        let method = alternative.rsplit('.').next()?;
+        Some(Suggestion::new(
+            format!("await `{}.promises.{}` instead", module.sym, method),
+            self.span(call),
+            format!("(await {}.promises.{}({}))", module.sym, method, args),
+            Applicability::MaybeIncorrect,
+        ))
+    }
+
+    fn span(&self, node: &impl Spanned) -> Span {
+        let span = node.span();
+        Span::new(self.file_id, span.lo.0.saturating_sub(1), span.hi.0.saturating_sub(1))
+    }
+}
+
+/// Something reported inside a handler
+enum Found<'a> {
+    Call(&'a CallExpr, (&'static str, &'static str)),
+    Loop(Span),
+}
+
+fn as_function(expr: &Expr) -> Option<Handler<'_>> {
+    match strip_parens(expr) {
+        Expr::Arrow(arrow) => Some(Handler::Arrow(arrow)),
+        Expr::Fn(f) => Some(Handler::Function(&f.function)),
+        _ => None,
+    }
+}
+
+/// Methods of a controller class decorated as routes
+fn controller_routes(class: &Class) -> impl Iterator<Item = Handler<'_>> {
+    class.body.iter().filter_map(|member| {
+        let ClassMember::Method(method) = member else { return None };
+        method.function.decorators.iter().any(|decorator| match &*decorator.expr {
+            Expr::Call(call) => matches!(&call.callee, Callee::Expr(callee)
+                if matches!(&**callee, Expr::Ident(name) if ROUTE_DECORATORS.contains(&name.sym.as_ref()))),
+            _ => false,
+        })
+        .then_some(Handler::Function(&method.function))
+    })
+}
+
+/// The blocking API a call uses, with its asynchronous alternative
+fn blocking_call(call: &CallExpr) -> Option<(&'static str, &'static str)> {
+    let Callee::Expr(callee) = &call.callee else { return None };
+    let name = match strip_parens(callee) {
+        Expr::Ident(ident) => ident.sym.as_ref(),
+        Expr::Member(member) => match &member.prop {
+            MemberProp::Ident(prop) => prop.sym.as_ref(),
+            _ => return None,
+        },
+        _ => return None,
+    };
+    BLOCKING_CALLS.iter().find(|(blocking, _)| *blocking == name).copied()
+}
+
+fn is_awaited(expr: &Expr, awaited: &HashSet<String>) -> bool {
+    match strip_parens(expr) {
+        Expr::Await(_) => true,
+        Expr::Ident(ident) => awaited.contains(ident.sym.as_ref()),
+        _ => false,
+    }
+}
+
+/// The callback of `rows.forEach(...)`, `rows.map(...)`, ... over awaited data
+fn iteration_callback<'a>(call: &'a CallExpr, awaited: &HashSet<String>) -> Option<Handler<'a>> {
+    let Callee::Expr(callee) = &call.callee else { return None };
+    let Expr::Member(member) = strip_parens(callee) else { return None };
+    let MemberProp::Ident(method) = &member.prop else { return None };
+    if !ITERATION_METHODS.contains(&method.sym.as_ref()) || !is_awaited(&member.obj, awaited) {
+        return None;
+    }
+    as_function(&call.args.first()?.expr)
+}
+
+/// Whether a loop body runs another loop and never awaits
+fn runs_nested_loop<'a>(walk: impl FnOnce(&mut dyn FnMut(Node<'a>))) -> bool {
+    let (mut loops, mut awaits) = (false, false);
+    walk(&mut |node| match node {
+        Node::Stmt(Stmt::For(_) | Stmt::ForOf(_) | Stmt::ForIn(_) | Stmt::While(_) | Stmt::DoWhile(_)) => loops = true,
+        Node::Expr(Expr::Await(_)) => awaits = true,
+        Node::Expr(Expr::Call(call)) => {
+            if let Callee::Expr(callee) = &call.callee {
+                if let Expr::Member(MemberExpr { prop: MemberProp::Ident(method), .. }) = strip_parens(callee) {
+                    loops |= ITERATION_METHODS.contains(&method.sym.as_ref());
+                }
+            }
+        }
+        _ => {}
+    });
+    loops && !awaits
+}
+
+pub(super) fn strip_parens(expr: &Expr) -> &Expr {
+    match expr {
+        Expr::Paren(p) => strip_parens(&p.expr),
+        _ => expr,
+    }
+}
+
+// ---- Walking ----
+
+/// A node reached while walking code, in source order (nested functions included)
+#[derive(Clone, Copy)]
+pub(super) enum Node<'a> {
+    Stmt(&'a Stmt),
+    Expr(&'a Expr),
+    Class(&'a Class),
+}
+
+pub(super) fn walk_module_item<'a>(item: &'a ModuleItem, visit: &mut dyn FnMut(Node<'a>)) {
+    match item {
+        ModuleItem::Stmt(stmt) => walk_stmt(stmt, visit),
+        ModuleItem::ModuleDecl(ModuleDecl::ExportDecl(export)) => walk_decl(&export.decl, visit),
+        ModuleItem::ModuleDecl(ModuleDecl::ExportDefaultDecl(export)) => match &export.decl {
+            DefaultDecl::Fn(f) => walk_function(&f.function, visit),
+            DefaultDecl::Class(c) => walk_class(&c.class, visit),
+            DefaultDecl::TsInterfaceDecl(_) => {}
+        },
+        ModuleItem::ModuleDecl(ModuleDecl::ExportDefaultExpr(export)) => walk_expr(&export.expr, visit),
+        _ => {}
+    }
+}
+
+fn walk_stmt<'a>(stmt: &'a Stmt, visit: &mut dyn FnMut(Node<'a>)) {
+    visit(Node::Stmt(stmt));
+    match stmt {
+        Stmt::Decl(decl) => walk_decl(decl, visit),
+        Stmt::Expr(s) => walk_expr(&s.expr, visit),
+        Stmt::Block(block) => block.stmts.iter().for_each(|s| walk_stmt(s, visit)),
+        Stmt::Return(s) => s.arg.iter().for_each(|arg| walk_expr(arg, visit)),
+        Stmt::Throw(s) => walk_expr(&s.arg, visit),
+        Stmt::If(s) => {
+            walk_expr(&s.test, visit);
+            walk_stmt(&s.cons, visit);
+            s.alt.iter().for_each(|alt| walk_stmt(alt, visit));
+        }
+        Stmt::While(s) => {
+            walk_expr(&s.test, visit);
+            walk_stmt(&s.body, visit);
+        }
+        Stmt::DoWhile(s) => {
+            walk_stmt(&s.body, visit);
+            walk_expr(&s.test, visit);
+        }
+        Stmt::For(s) => {
+            match &s.init {
+                Some(VarDeclOrExpr::VarDecl(var)) => walk_var(var, visit),
+                Some(VarDeclOrExpr::Expr(init)) => walk_expr(init, visit),
+                None => {}
+            }
+            s.test.iter().for_each(|test| walk_expr(test, visit));
+            s.update.iter().for_each(|update| walk_expr(update, visit));
+            walk_stmt(&s.body, visit);
+        }
+        Stmt::ForIn(s) => {
+            walk_expr(&s.right, visit);
+            walk_stmt(&s.body, visit);
+        }
+        Stmt::ForOf(s) => {
+            walk_expr(&s.right, visit);
+            walk_stmt(&s.body, visit);
+        }
+        Stmt::Labeled(s) => walk_stmt(&s.body, visit),
+        Stmt::Switch(s) => {
+            walk_expr(&s.discriminant, visit);
+            for case in &s.cases {
+                case.test.iter().for_each(|test| walk_expr(test, visit));
+                case.cons.iter().for_each(|s| walk_stmt(s, visit));
+            }
+        }
+        Stmt::Try(s) => {
+            s.block.stmts.iter().for_each(|s| walk_stmt(s, visit));
+            if let Some(handler) = &s.handler {
+                handler.body.stmts.iter().for_each(|s| walk_stmt(s, visit));
+            }
+            if let Some(finalizer) = &s.finalizer {
+                finalizer.stmts.iter().for_each(|s| walk_stmt(s, visit));
+            }
+        }
+        _ => {}
+    }
+}
+
+fn walk_decl<'a>(decl: &'a Decl, visit: &mut dyn FnMut(Node<'a>)) {
+    match decl {
+        Decl::Var(var) => walk_var(var, visit),
+        Decl::Fn(f) => walk_function(&f.function, visit),
+        Decl::Class(c) => walk_class(&c.class, visit),
+        _ => {}
+    }
+}
+
+fn walk_var<'a>(var: &'a VarDecl, visit: &mut dyn FnMut(Node<'a>)) {
+    for declarator in &var.decls {
+        declarator.init.iter().for_each(|init| walk_expr(init, visit));
+    }
+}
+
+fn walk_function<'a>(function: &'a Function, visit: &mut dyn FnMut(Node<'a>)) {
+    for stmt in function.body.iter().flat_map(|body| &body.stmts) {
+        walk_stmt(stmt, visit);
+    }
+}
+
+fn walk_class<'a>(class: &'a Class, visit: &mut dyn FnMut(Node<'a>)) {
+    visit(Node::Class(class));
+    for member in &class.body {
+        match member {
+            ClassMember::Method(method) => walk_function(&method.function, visit),
+            ClassMember::PrivateMethod(method) => walk_function(&method.function, visit),
+            ClassMember::Constructor(ctor) => {
+                for stmt in ctor.body.iter().flat_map(|body| &body.stmts) {
+                    walk_stmt(stmt, visit);
+                }
+            }
+            ClassMember::ClassProp(prop) => prop.value.iter().for_each(|value| walk_expr(value, visit)),
+            ClassMember::StaticBlock(block) => block.body.stmts.iter().for_each(|s| walk_stmt(s, visit)),
+            _ => {}
+        }
+    }
+}
+
+fn walk_expr<'a>(expr: &'a Expr, visit: &mut dyn FnMut(Node<'a>)) {
+    visit(Node::Expr(expr));
+    match expr {
+        Expr::Call(call) => {
+            if let Callee::Expr(callee) = &call.callee {
+                walk_expr(callee, visit);
+            }
+            call.args.iter().for_each(|arg| walk_expr(&arg.expr, visit));
+        }
+        Expr::New(new) => {
+            walk_expr(&new.callee, visit);
+            new.args.iter().flatten().for_each(|arg| walk_expr(&arg.expr, visit));
+        }
+        Expr::Member(m) => {
+            walk_expr(&m.obj, visit);
+            if let MemberProp::Computed(c) = &m.prop {
+                walk_expr(&c.expr, visit);
+            }
+        }
+        Expr::OptChain(chain) => match &*chain.base {
+            OptChainBase::Member(m) => walk_expr(&m.obj, visit),
+            OptChainBase::Call(call) => {
+                walk_expr(&call.callee, visit);
+                call.args.iter().for_each(|arg| walk_expr(&arg.expr, visit));
+            }
+        },
+        Expr::Arrow(arrow) => match &*arrow.body {
+            BlockStmtOrExpr::BlockStmt(block) => block.stmts.iter().for_each(|s| walk_stmt(s, visit)),
+            BlockStmtOrExpr::Expr(body) => walk_expr(body, visit),
+        },
+        Expr::Fn(f) => walk_function(&f.function, visit),
+        Expr::Class(c) => walk_class(&c.class, visit),
+        Expr::Paren(p) => walk_expr(&p.expr, visit),
+        Expr::Await(a) => walk_expr(&a.arg, visit),
+        Expr::Assign(a) => walk_expr(&a.right, visit),
+        Expr::Bin(b) => {
+            walk_expr(&b.left, visit);
+            walk_expr(&b.right, visit);
+        }
+        Expr::Unary(u) => walk_expr(&u.arg, visit),
+        Expr::Update(u) => walk_expr(&u.arg, visit),
+        Expr::Cond(c) => {
+            walk_expr(&c.test, visit);
+            walk_expr(&c.cons, visit);
+            walk_expr(&c.alt, visit);
+        }
+        Expr::Seq(seq) => seq.exprs.iter().for_each(|e| walk_expr(e, visit)),
+        Expr::Tpl(tpl) => tpl.exprs.iter().for_each(|e| walk_expr(e, visit)),
+        Expr::TaggedTpl(tagged) => tagged.tpl.exprs.iter().for_each(|e| walk_expr(e, visit)),
+        Expr::Array(arr) => arr.elems.iter().flatten().for_each(|elem| walk_expr(&elem.expr, visit)),
+        Expr::Object(obj) => {
+            for prop in &obj.props {
+                match prop {
+                    PropOrSpread::Spread(spread) => walk_expr(&spread.expr, visit),
+                    PropOrSpread::Prop(prop) => match &**prop {
+                        Prop::KeyValue(kv) => walk_expr(&kv.value, visit),
+                        Prop::Method(m) => walk_function(&m.function, visit),
+                        Prop::Getter(g) => g.body.iter().flat_map(|b| &b.stmts).for_each(|s| walk_stmt(s, visit)),
+                        Prop::Setter(setter) => setter.body.iter().flat_map(|b| &b.stmts).for_each(|s| walk_stmt(s, visit)),
+                        _ => {}
+                    },
+                }
+            }
+        }
+        Expr::TsAs(a) => walk_expr(&a.expr, visit),
+        Expr::TsNonNull(n) => walk_expr(&n.expr, visit),
+        Expr::TsSatisfies(s) => walk_expr(&s.expr, visit),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use perry_diagnostics::SourceCache;
+
+    fn lint(source: &str) -> Vec<Diagnostic> {
+        let mut cache = SourceCache::new();
+        let result = perry_parser::parse_typescript_with_cache(source, "test.ts", &mut cache)
+            .expect("Parse failed");
+        BlockingLinter::check(&result.module, result.file_id, source)
+    }
+
+    fn flagged<'s>(source: &'s str, diagnostic: &Diagnostic) -> &'s str {
+        &source[diagnostic.span.start as usize..diagnostic.span.end as usize]
+    }
+
+    #[test]
+    fn test_blocking_calls_in_routes_and_hooks() {
+        let source = "import fs from 'fs';\nconst app = fastify();\napp.get('/file', async (request, reply) => {\n  const text = fs.readFileSync('data.txt', 'utf8');\n  return text.toUpperCase();\n});\napp.addHook('onRequest', function (request, reply, done) { execSync('sync'); done(); });";
+        let diags = lint(source);
+        assert_eq!(diags.len(), 2);
+        assert_eq!(diags[0].code, DiagnosticCode::BlockingInHandler);
+        assert_eq!(flagged(source, &diags[0]), "fs.readFileSync('data.txt', 'utf8')");
+        let suggestion = &diags[0].suggestions[0];
+        assert_eq!(suggestion.applicability, Applicability::MaybeIncorrect);
+        assert_eq!(suggestion.replacement, "(await fs.promises.readFile('data.txt', 'utf8'))");
+        // The hook isn't async, so it only gets help
+        assert!(diags[1].message.contains("`execSync`") && diags[1].message.contains("hook"));
+        assert!(diags[1].suggestions.is_empty());
+    }
+
+    #[test]
+    fn test_named_handlers_and_controllers() {
+        let source = "function hashPassword(req, res) { res.send(bcrypt.hashSync(req.body.pw, 10)); }\napp.post('/signup', hashPassword);\napp.post('/register', hashPassword);\nclass Files {\n  @Get(':name')\n  read(@Param('name') name: string) { return readFileSync(name); }\n  helper() { return readFileSync('x'); }\n}";
+        let diags = lint(source);
+        // A handler registered twice is reported once; undecorated methods aren't handlers
+        assert_eq!(diags.len(), 2);
+        assert_eq!(flagged(source, &diags[0]), "bcrypt.hashSync(req.body.pw, 10)");
+        assert_eq!(flagged(source, &diags[1]), "readFileSync(name)");
+    }
+
+    #[test]
+    fn test_nested_loop_over_awaited_rows() {
+        let source = "app.get('/report', async () => {\n  const users = await db.users();\n  const orders = await db.orders();\n  for (const user of users) {\n    user.total = orders.filter(o => o.userId === user.id).length;\n  }\n  for (const user of users) { await notify(user); }\n  return users;\n});";
+        let diags = lint(source);
+        assert_eq!(diags.len(), 1);
+        assert!(flagged(source, &diags[0]).starts_with("for (const user of users) {\n"));
+    }
+
+    #[test]
+    fn test_code_outside_handlers_ignored() {
+        // Startup code may block, and `map.get(key, ...)` isn't a route
+        assert!(lint("const config = readFileSync('config.json');\nconst app = fastify();").is_empty());
+        assert!(lint("cache.get(key, () => readFileSync(key));").is_empty());
+        assert!(lint("app.get('/', async () => { return await fs.promises.readFile('x'); });").is_empty());
+    }
+}
@@ -0,0 +1,201 @@
+//! Build graph of a program (`perry compile --emit-build-graph`)
+//!
+//! Lists every file the build read and how each module is compiled, so build systems
+//! wrapping perry (Bazel, Nx, make) know what to hash for caching and what invalidates
+//! a build. Paths are relative to the project root when they're inside it.
+//!
+//! ```json
+//! {
+//!   "version": 1,
+//!   "project_root": "/home/me/app",
+//!   "entry": "src/main.ts",
+//!   "inputs": ["perry.toml", "src/db.ts", "src/main.ts", "tsconfig.json"],
+//!   "modules": [{
+//!     "path": "src/main.ts",
+//!     "name": "src/main.ts",
+//!     "kind": "NativeCompiled",
+//!     "imports": [
+//!       { "specifier": "./db", "kind": "NativeCompiled", "path": "src/db.ts" },
+//!       { "specifier": "mysql2/promise", "kind": "NativeRust", "path": null }
+//!     ]
+//!   }],
+//!   "features": { "js_runtime": false, "ui": false, "napi": false, "native_modules": ["mysql2/promise"], "native_addons": [] }
+//! }
+//! ```
+//!
+//! Module kinds: `NativeCompiled` (TypeScript compiled by perry), `NativeRust` (a module
+//! perry's stdlib implements), `V8` (JavaScript run by the V8 runtime, whose own imports
+//! V8 resolves at run time) and `Declaration` (a `.d.ts` file, read for types only).
+//! An import perry couldn't resolve has kind and path `null`.
+
+use perry_hir::{Export, ModuleKind};
+use serde_json::{json, Value};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+use super::compile::CompilationContext;
+
+/// Format version, bumped when fields change meaning
+const VERSION: u32 = 1;
+
+/// Build the graph of the modules collected into `ctx`. `resolve` maps a re-export
+/// source to its file like imports are resolved; `config_files` are the tsconfig.json
+/// and perry.toml the build read.
+pub fn generate(
+    ctx: &CompilationContext,
+    entry: &Path,
+    config_files: &[PathBuf],
+    resolve: impl Fn(&str, &Path) -> Option<(PathBuf, ModuleKind)>,
+) -> Value {
+    let relative = |path: &Path| path.strip_prefix(&ctx.project_root).unwrap_or(path).display().to_string();
+    let mut inputs: BTreeSet<String> = config_files.iter().map(|path| relative(path)).collect();
+    let mut native_modules = BTreeSet::new();
+    let mut native_addons = BTreeSet::new();
+    let mut modules = Vec::new();
+
+    let mut native: Vec<_> = ctx.native_modules.iter().collect();
+    native.sort_by(|a, b| a.0.cmp(b.0));
+    for (path, module) in native {
+        inputs.insert(relative(path));
+        let mut imports: Vec<Value> = module.imports.iter()
+            .map(|import| {
+                if import.is_native {
+                    native_modules.insert(import.source.clone());
+                    return json!({ "specifier": import.source, "kind": "NativeRust", "path": null });
+                }
+                match &import.resolved_path {
+                    Some(resolved) => {
+                        let resolved = Path::new(resolved);
+                        json!({
+                            "specifier": import.source,
+                            "kind": kind_name(import.module_kind, resolved),
+                            "path": relative(resolved),
+                        })
+                    }
+                    None => json!({ "specifier": import.source, "kind": null, "path": null }),
+                }
+            })
+            .collect();
+        // `export ... from` reads the source module like an import does
+        for export in &module.exports {
+            let source = match export {
+                Export::ReExport { source, .. } | Export::ExportAll { source } => source,
+                Export::Named { .. } => continue,
+            };
+            let import = match resolve(source, path) {
+                Some((resolved, kind)) => json!({
+                    "specifier": source,
+                    "kind": kind_name(kind, &resolved),
+                    "path": relative(&resolved),
+                    "reexport": true,
+                }),
+                None => json!({ "specifier": source, "kind": null, "path": null, "reexport": true }),
+            };
+            imports.push(import);
+        }
+        native_addons.extend(module.native_addons.iter().cloned());
+        modules.push(json!({
+            "path": relative(path),
+            "name": module.name,
+            "kind": "NativeCompiled",
+            "imports": imports,
+        }));
+    }
+
+    let mut js: Vec<_> = ctx.js_modules.values().collect();
+    js.sort_by(|a, b| a.path.cmp(&b.path));
+    for module in js {
+        inputs.insert(relative(&module.path));
+        modules.push(json!({
+            "path": relative(&module.path),
+            "kind": "V8",
+            "imports": [],
+        }));
+    }
+
+    json!({
+        "version": VERSION,
+        "project_root": ctx.project_root.display().to_string(),
+        "entry": relative(entry),
+        "inputs": inputs,
+        "modules": modules,
+        "features": {
+            "js_runtime": ctx.needs_js_runtime,
+            "ui": ctx.needs_ui,
+            "napi": ctx.needs_napi,
+            "native_modules": native_modules,
+            "native_addons": native_addons,
+        },
+    })
+}
+
+fn kind_name(kind: ModuleKind, path: &Path) -> &'static str {
+    match kind {
+        ModuleKind::NativeCompiled => "NativeCompiled",
+        ModuleKind::NativeRust => "NativeRust",
+        ModuleKind::Interpreted if path.to_string_lossy().ends_with(".d.ts") => "Declaration",
+        ModuleKind::Interpreted => "V8",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::compile::JsModule;
+    use perry_hir::{Import, Module as HirModule};
+
+    fn import(source: &str, kind: ModuleKind, resolved: Option<&str>) -> Import {
+        Import {
+            source: source.to_string(),
+            specifiers: Vec::new(),
+            is_native: kind == ModuleKind::NativeRust,
+            module_kind: kind,
+            resolved_path: resolved.map(str::to_string),
+            is_require: false,
+        }
+    }
+
+    #[test]
+    fn test_graph_lists_modules_and_features() {
+        let root = PathBuf::from("/app");
+        let mut ctx = CompilationContext::new(root.clone());
+        let mut main = HirModule::new("src/main.ts");
+        main.imports = vec![
+            import("./db", ModuleKind::NativeCompiled, Some("/app/src/db.ts")),
+            import("mysql2/promise", ModuleKind::NativeRust, None),
+            import("left-pad", ModuleKind::Interpreted, Some("/app/node_modules/left-pad/index.js")),
+            import("./missing", ModuleKind::NativeCompiled, None),
+        ];
+        main.exports = vec![Export::ExportAll { source: "./db".to_string() }];
+        ctx.native_modules.insert(root.join("src/main.ts"), main);
+        ctx.native_modules.insert(root.join("src/db.ts"), HirModule::new("src/db.ts"));
+        let pad = root.join("node_modules/left-pad/index.js");
+        ctx.js_modules.insert(pad.display().to_string(), JsModule {
+            path: pad.clone(),
+            source: String::new(),
+            specifier: pad.display().to_string(),
+        });
+        ctx.needs_js_runtime = true;
+
+        let graph = generate(&ctx, &root.join("src/main.ts"), &[root.join("tsconfig.json")], |source, _| {
+            (source == "./db").then(|| (root.join("src/db.ts"), ModuleKind::NativeCompiled))
+        });
+        assert_eq!(graph["entry"], "src/main.ts");
+        assert_eq!(
+            graph["inputs"],
+            json!(["node_modules/left-pad/index.js", "src/db.ts", "src/main.ts", "tsconfig.json"])
+        );
+        let modules = graph["modules"].as_array().unwrap();
+        assert_eq!(modules.iter().map(|m| m["path"].as_str().unwrap()).collect::<Vec<_>>(),
+            vec!["src/db.ts", "src/main.ts", "node_modules/left-pad/index.js"]);
+        let imports = modules[1]["imports"].as_array().unwrap();
+        assert_eq!(imports[0], json!({ "specifier": "./db", "kind": "NativeCompiled", "path": "src/db.ts" }));
+        assert_eq!(imports[1]["kind"], "NativeRust");
+        assert_eq!(imports[2]["kind"], "V8");
+        assert_eq!(imports[3]["kind"], Value::Null);
+        assert_eq!(imports[4]["reexport"], true);
+        assert_eq!(modules[2]["kind"], "V8");
+        assert_eq!(graph["features"]["js_runtime"], true);
+        assert_eq!(graph["features"]["native_modules"], json!(["mysql2/promise"]));
+    }
+}
@@ -15,8 +15,15 @@ use super::deps::{
     check_node_builtin_imports, compatibility_to_diagnostics, scan_project_file_for_issues,
     unresolved_imports_to_diagnostics, CompatibilityIssue, DependencyResolver, IssueKind,
 };
+use super::coverage::{CoverageReport, FileCoverage};
 use super::fix_applier::FixApplier;
 use super::fixer::{Confidence, Fixer};
+use super::blocking_lint::BlockingLinter;
+use super::perf_lint::PerfLinter;
+use super::sql_lint::SqlLinter;
+use super::structural::{Strictness, StructuralChecker};
+use super::telemetry;
+use super::tsconfig::CompilerOptions;
 use crate::OutputFormat;
 
 #[derive(Args, Debug)]
@@ -52,6 +59,19 @@ pub struct CheckArgs {
     /// Include medium-confidence fixes (inferred types)
     #[arg(long)]
     pub fix_unsafe: bool,
+
+    /// Run performance lints (sequential awaits in loops, blocking calls in HTTP handlers)
+    #[arg(long)]
+    pub perf: bool,
+
+    /// Report per file which constructs and imports are native, partial, V8-fallback or unsupported
+    #[arg(long)]
+    pub coverage: bool,
+
+    /// tsconfig.json whose strictness flags (`strict`, `noImplicitAny`, `strictNullChecks`)
+    /// apply. Defaults to the nearest tsconfig.json at or above the input.
+    #[arg(long, value_name = "PATH")]
+    pub tsconfig: Option<PathBuf>,
 }
 
 /// Collect all TypeScript files in a directory
@@ -134,10 +154,37 @@ pub fn run(args: CheckArgs, format: OutputFormat, use_color: bool, verbose: u8)
 
     let mut source_cache = SourceCache::new();
     let mut all_diagnostics = Diagnostics::new();
+
+    let compiler_options = match &args.tsconfig {
+        Some(config) => Some(CompilerOptions::load(config)?),
+        None => CompilerOptions::find(&project_root)?,
+    };
+    let strictness = match &compiler_options {
+        Some(options) => Strictness {
+            strict_null_checks: options.strict_null_checks(),
+            no_implicit_any: options.no_implicit_any(),
+        },
+        None => Strictness::default(),
+    };
+    if let Some(options) = &compiler_options {
+        if matches!(format, OutputFormat::Text) && verbose > 0 {
+            println!("Using compiler options from {}", options.config_path.display());
+        }
+        for (option, note) in &options.unsupported {
+            all_diagnostics.push(
+                Diagnostic::warning(
+                    DiagnosticCode::UnsupportedFeature,
+                    format!("tsconfig option '{}' in {}: {}", option, options.config_path.display(), note),
+                )
+                .build(),
+            );
+        }
+    }
     let mut checked_files = 0;
     let mut visited = HashSet::new();
     let mut dep_resolver = DependencyResolver::new(project_root.clone());
     let mut fix_applier = FixApplier::new();
+    let mut coverage = CoverageReport::new();
     let min_confidence = if args.fix_unsafe {
         Confidence::Medium
     } else {
@@ -167,23 +214,38 @@ pub fn run(args: CheckArgs, format: OutputFormat, use_color: bool, verbose: u8)
 
         let filename = canonical.to_string_lossy().to_string();
 
-        // Parse with diagnostics
-        let parse_result =
-            match perry_parser::parse_typescript_with_cache(&source, &filename, &mut source_cache)
-            {
-                Ok(result) => result,
-                Err(e) => {
-                    if verbose > 0 {
-                        eprintln!("Parse error in {}: {}", canonical.display(), e);
-                    }
-                    continue;
-                }
-            };
+        // Parse with diagnostics. Broken statements are skipped so every syntax error in
+        // the file is reported and the checks below still see the rest of it.
+        let parse_result = perry_parser::parse_typescript_recovering(&source, &filename, &mut source_cache);
+        // Fixes are only applied to files that parse cleanly
+        let fixable = !parse_result.diagnostics.has_errors();
 
         all_diagnostics.extend(parse_result.diagnostics.into_iter());
 
+        if args.coverage {
+            coverage.add(FileCoverage::analyze(&canonical, &parse_result.module));
+        }
+
+        // Check object literals against the interfaces they are assigned to
+        all_diagnostics.extend(StructuralChecker::check_with_strictness(&parse_result.module, parse_result.file_id, &source, strictness));
+
+        // SQL built from strings and passed to query(); its `sql` rewrite is only suggested
+        all_diagnostics.extend(SqlLinter::check(&parse_result.module, parse_result.file_id, &source));
+
+        // Opt-in performance lints; their machine-applicable rewrites go through --fix
+        if args.perf {
+            let mut perf_diagnostics = PerfLinter::check(&parse_result.module, parse_result.file_id, &source);
+            perf_diagnostics.extend(BlockingLinter::check(&parse_result.module, parse_result.file_id, &source));
+            if fixable && (args.fix || args.fix_dry_run) {
+                for suggestion in perf_diagnostics.iter().flat_map(|d| &d.suggestions) {
+                    fix_applier.add_suggestion(suggestion, &canonical, &source);
+                }
+            }
+            all_diagnostics.extend(perf_diagnostics);
+        }
+
         // Run fixer analysis if --fix or --fix-dry-run is enabled
-        if args.fix || args.fix_dry_run {
+        if fixable && (args.fix || args.fix_dry_run) {
             let fixable_issues = Fixer::analyze(&parse_result.module, parse_result.file_id, &source);
             for issue in &fixable_issues {
                 fix_applier.add_issue(issue, &canonical, &source, min_confidence);
@@ -226,9 +288,16 @@ pub fn run(args: CheckArgs, format: OutputFormat, use_color: bool, verbose: u8)
         }
 
         // Try to lower to HIR to catch more errors
-        match perry_hir::lower_module(&parse_result.module, &filename, &filename) {
-            Ok(_hir_module) => {
-                // Successfully lowered
+        let lower_options = perry_hir::LowerOptions {
+            file_id: Some(parse_result.file_id),
+            ..Default::default()
+        };
+        match perry_hir::lower_module_with_options(&parse_result.module, &filename, &filename, &lower_options) {
+            Ok(mut hir_module) => {
+                // Lowering diagnostics (e.g. writes to readonly properties), then generic
+                // constraint violations found while specializing
+                perry_hir::monomorphize_module(&mut hir_module);
+                all_diagnostics.extend(hir_module.diagnostics);
             }
             Err(e) => {
                 all_diagnostics.push(
@@ -297,6 +366,9 @@ pub fn run(args: CheckArgs, format: OutputFormat, use_color: bool, verbose: u8)
         }
     }
 
+    telemetry::set("files", checked_files);
+    telemetry::diagnostics(all_diagnostics.iter());
+
     // Emit diagnostics
     let stderr = std::io::stderr();
 
@@ -380,6 +452,11 @@ pub fn run(args: CheckArgs, format: OutputFormat, use_color: bool, verbose: u8)
                 println!("Note: Run with --check-deps to verify dependencies and guarantee compilation.");
             }
 
+            if args.coverage {
+                println!();
+                print!("{}", coverage.to_text(&project_root));
+            }
+
             // Handle fix output
             if args.fix_dry_run && fix_applier.pending_fixes() > 0 {
                 println!();
@@ -444,6 +521,10 @@ pub fn run(args: CheckArgs, format: OutputFormat, use_color: bool, verbose: u8)
                 (0, 0)
             };
 
+            if args.coverage {
+                println!("{}", serde_json::to_string(&coverage.to_json(&project_root))?);
+            }
+
             let summary = serde_json::json!({
                 "type": "summary",
                 "success": errors == 0 && (!args.strict || warnings == 0),
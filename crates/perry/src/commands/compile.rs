@@ -1,15 +1,37 @@
 //! Compile command - compiles TypeScript to native executable
 
 use anyhow::{anyhow, Result};
-use clap::Args;
-use perry_hir::{Module as HirModule, ModuleKind};
-use perry_transform::inline_functions;
-use std::collections::{HashMap, HashSet};
+use clap::{Args, ValueEnum};
+use rayon::prelude::*;
+use perry_diagnostics::{DiagnosticCode, FileId, SourceCache};
+use perry_codegen::wasm::WasmObject;
+use perry_hir::{Module as HirModule, ModuleKind, OptLevel, OptSettings, PackageDeclarations};
+use perry_transform::{
+    eliminate_dead_functions, eliminate_dead_statements, inline_across_modules, inline_functions, scalar_replace_allocations,
+};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::sync::Arc;
+use std::time::Instant;
 
 use crate::OutputFormat;
+use super::build_graph;
+use super::dts;
+use super::hir_cache::HirCache;
+use super::hot_reload;
+use super::js_audit::{self, AdvisoryDb, Finding, Severity};
+use super::remote_cache::{RemoteCache, REMOTE_CACHE_ENV};
+use super::module_graph::ModuleGraph;
+use super::object_cache::ObjectCache;
+use super::openapi::{self, DocumentInfo};
+use super::progress;
+use super::package_exports::{read_exports, resolve_exports, ExportsResolution, IMPORT_CONDITIONS, REQUIRE_CONDITIONS};
+use super::project_config::{BuildConfig, Permissions};
+use super::target::{Os, Target};
+use super::telemetry;
+use super::tsconfig::PathAliases;
 
 #[derive(Args, Debug)]
 pub struct CompileArgs {
@@ -37,6 +59,254 @@ pub struct CompileArgs {
     /// WARNING: This significantly increases binary size (~10-15MB).
     #[arg(long)]
     pub enable_js_runtime: bool,
+
+    /// Install crash handlers that write a report (TS stack + native backtrace) on
+    /// panic or fatal signal. Set PERRY_CRASH_DIR to choose where reports are written
+    /// and PERRY_CRASH_ENDPOINT to POST them to an http:// URL.
+    #[arg(long)]
+    pub crash_report: bool,
+
+    /// Emit DWARF debug info (function names, TypeScript line tables, parameters and
+    /// locals) for debuggers and profilers, keeping frame pointers. On macOS the
+    /// executable's debug info is collected into a .dSYM bundle with dsymutil.
+    #[arg(short = 'g', long)]
+    pub debug: bool,
+
+    /// How import.meta.url/filename/dirname/resolve locate the module: `source` bakes in
+    /// the absolute source path; `exe` resolves the project-relative path against the
+    /// executable's directory at runtime.
+    #[arg(long, value_enum, default_value_t = ImportMetaMode::Source)]
+    pub import_meta: ImportMetaMode,
+
+    /// Heap ceiling for the runtime allocator, e.g. `512M` or `2G` (K/M/G are powers
+    /// of 1024). PERRY_MAX_HEAP overrides it at run time.
+    #[arg(long, value_name = "SIZE", value_parser = parse_heap_size)]
+    pub max_heap: Option<u64>,
+
+    /// What happens when the heap ceiling is hit: `throw` a catchable RangeError or
+    /// `abort` with a report. PERRY_OOM_POLICY overrides it at run time.
+    #[arg(long, value_enum, default_value_t = OomPolicy::Throw)]
+    pub oom_policy: OomPolicy,
+
+    /// Memory allocator linked into the executable in place of the C library's malloc.
+    /// A static library is taken from PERRY_MIMALLOC_LIB / PERRY_JEMALLOC_LIB or the usual
+    /// library directories, else the shared one is linked with -lmimalloc / -ljemalloc
+    #[arg(long, value_enum, default_value_t = Allocator::System)]
+    pub allocator: Allocator,
+
+    /// tsconfig.json providing `baseUrl` / `paths` import aliases. Defaults to the
+    /// nearest tsconfig.json at or above the input file.
+    #[arg(long, value_name = "PATH")]
+    pub tsconfig: Option<PathBuf>,
+
+    /// Optimize across modules: collect the HIR of every module before running the
+    /// inliner, so small exported functions inline into the modules that call them
+    #[arg(long)]
+    pub whole_program: bool,
+
+    /// Compile every function and statement, even those the program can't reach, and link
+    /// the runtime and stdlib without dropping unused sections
+    #[arg(long)]
+    pub no_dce: bool,
+
+    /// Write an OpenAPI 3.1 document describing the program's Fastify routes, built
+    /// from their schema options and TypeScript types
+    #[arg(long, value_name = "PATH")]
+    pub openapi: Option<PathBuf>,
+
+    /// Compile the OpenAPI document into the executable: Fastify apps serve it at
+    /// /docs/json with a browsable page at /docs
+    #[arg(long)]
+    pub docs: bool,
+
+    /// Compile time zone data into the executable so Temporal resolves these zones
+    /// (comma-separated, e.g. `--embed-tzdata=America/New_York,Europe/Berlin`; every zone
+    /// when none are given) without a zoneinfo directory on the target. Read from $TZDIR,
+    /// else /usr/share/zoneinfo
+    #[arg(long, value_name = "ZONES", num_args = 0.., value_delimiter = ',', require_equals = true)]
+    pub embed_tzdata: Option<Vec<String>>,
+
+    /// Parse, lower and compile every module even if .perry-cache holds its HIR or
+    /// object code from an earlier build of the same source
+    #[arg(long)]
+    pub no_cache: bool,
+
+    /// Share cached HIR through a remote content-addressed store
+    /// (`http://host[:port]/path`). Defaults to PERRY_REMOTE_CACHE, else perry.toml
+    /// `[build] remote_cache`
+    #[arg(long, value_name = "URL")]
+    pub remote_cache: Option<String>,
+
+    /// Download from the remote cache without uploading newly lowered modules
+    #[arg(long)]
+    pub remote_cache_read_only: bool,
+
+    /// Cranelift optimization level for modules without a `// @perry-opt:` directive.
+    /// Defaults to perry.toml `[build] opt_level`, else speed
+    #[arg(long, value_enum)]
+    pub opt_level: Option<OptLevelArg>,
+
+    /// Run the Cranelift IR verifier on every function: slower, but catches codegen bugs
+    #[arg(long)]
+    pub verify_ir: bool,
+
+    /// Set any other Cranelift setting, shared or for the host ISA, e.g.
+    /// `has_avx2=false` or `regalloc_checker=true`. Repeatable; applied after
+    /// perry.toml `[build.cranelift]`
+    #[arg(long = "cranelift-flag", value_name = "NAME=VALUE", value_parser = parse_cranelift_flag)]
+    pub cranelift_flags: Vec<(String, String)>,
+
+    /// Cross-compile for another platform, e.g. `x86_64-unknown-linux-gnu` or
+    /// `aarch64-unknown-linux-musl`. Links against runtime libraries built for that
+    /// target (`target/<triple>/release`) with PERRY_LINKER, else a cross gcc, zig or clang.
+    /// `wasm32-wasip1` writes a WebAssembly module linked into perry-runtime-wasi
+    #[arg(long, value_name = "TRIPLE")]
+    pub target: Option<String>,
+
+    /// Threads generating code, for several modules and a large module's functions at
+    /// once. Defaults to RAYON_NUM_THREADS, else the number of CPU cores
+    #[arg(short, long, value_name = "N")]
+    pub jobs: Option<usize>,
+
+    /// Write the resolved module graph as JSON: every input file, each module's imports
+    /// and kind, and the runtime features the program needs, for build systems that
+    /// cache around perry
+    #[arg(long, value_name = "PATH")]
+    pub emit_build_graph: Option<PathBuf>,
+
+    /// Write a .d.ts declaring the input module's exports with the types perry compiled
+    /// against, to ship alongside the object files (`--no-link`) for projects and editors
+    /// that type-check against them
+    #[arg(long, value_name = "PATH")]
+    pub emit_dts: Option<PathBuf>,
+
+    /// Build for `perry dev`'s hot reload and write the manifest it compares builds by to
+    /// PATH: functions and module variables get exported symbols a patch binds to, object
+    /// files are kept, and unreachable functions are compiled too
+    #[arg(long, value_name = "PATH")]
+    pub hot_reload: Option<PathBuf>,
+
+    /// Audit the JavaScript modules routed to the V8 runtime: install scripts, eval and
+    /// new Function, and known advisories with --audit-db. Defaults to perry.toml
+    /// `[build] audit`
+    #[arg(long)]
+    pub audit: bool,
+
+    /// Advisory database for the audit (implies --audit): JSON keyed by package name, in
+    /// the format of npm's bulk advisory endpoint. Defaults to perry.toml `[build] audit_db`
+    #[arg(long, value_name = "PATH")]
+    pub audit_db: Option<PathBuf>,
+
+    /// Fail the build on audit findings of this severity or higher. Defaults to
+    /// perry.toml `[build] audit_fail_on`, else high
+    #[arg(long, value_enum, value_name = "SEVERITY")]
+    pub audit_fail_on: Option<Severity>,
+
+    /// Build even when statements, declarations or class members can't be compiled:
+    /// they are left out of the executable and listed in a summary instead of failing
+    #[arg(long)]
+    pub permissive: bool,
+
+    /// Report progress as JSON lines for editors and build tools: `json` writes them to
+    /// stderr, `unix:PATH` to a Unix socket listening at PATH
+    #[arg(long, value_name = "CHANNEL", value_parser = progress::parse_sink)]
+    pub progress: Option<progress::Sink>,
+}
+
+/// Where import.meta paths point in the compiled binary
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum ImportMetaMode {
+    /// Absolute source paths baked in at compile time
+    #[default]
+    Source,
+    /// Paths relative to the executable's directory, computed at runtime
+    Exe,
+}
+
+/// Behavior when an allocation would exceed --max-heap
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum OomPolicy {
+    /// Throw a RangeError that TypeScript code can catch
+    #[default]
+    Throw,
+    /// Write a report to stderr and abort the process
+    Abort,
+}
+
+/// Memory allocator linked into the executable (`--allocator`)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Allocator {
+    /// The C library's malloc
+    #[default]
+    System,
+    /// Microsoft's mimalloc
+    Mimalloc,
+    /// jemalloc
+    Jemalloc,
+}
+
+impl Allocator {
+    fn name(self) -> &'static str {
+        match self {
+            Allocator::System => "system",
+            Allocator::Mimalloc => "mimalloc",
+            Allocator::Jemalloc => "jemalloc",
+        }
+    }
+}
+
+/// Cranelift optimization level (`--opt-level`)
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum OptLevelArg {
+    /// Fastest compile, no optimization or inlining
+    None,
+    /// Fastest code
+    Speed,
+    /// Fast code, preferring smaller size
+    Size,
+}
+
+impl From<OptLevelArg> for OptLevel {
+    fn from(level: OptLevelArg) -> Self {
+        match level {
+            OptLevelArg::None => OptLevel::None,
+            OptLevelArg::Speed => OptLevel::Speed,
+            OptLevelArg::Size => OptLevel::Size,
+        }
+    }
+}
+
+/// Parse a --cranelift-flag value like "has_avx2=false"
+fn parse_cranelift_flag(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((name, value)) if !name.trim().is_empty() => Ok((name.trim().to_string(), value.trim().to_string())),
+        _ => Err(format!("invalid Cranelift setting '{}' (expected NAME=VALUE)", s)),
+    }
+}
+
+/// Parse a --max-heap value like "1048576", "512K", "256M" or "2G", the same way the
+/// runtime reads PERRY_MAX_HEAP
+fn parse_heap_size(s: &str) -> Result<u64, String> {
+    perry_runtime::memory::parse_size(s)
+        .ok_or_else(|| format!("invalid heap size '{}' (expected e.g. 512M or 2G)", s.trim()))
+}
+
+/// Title and version for the OpenAPI document: the project's package.json `name` and
+/// `version`, falling back to the input file name and 1.0.0
+fn document_info(project_root: &Path, input: &Path) -> DocumentInfo {
+    let package: Option<serde_json::Value> = fs::read_to_string(project_root.join("package.json"))
+        .ok()
+        .and_then(|text| serde_json::from_str(&text).ok());
+    let field = |name: &str| package.as_ref()
+        .and_then(|p| p.get(name))
+        .and_then(|v| v.as_str())
+        .map(str::to_string);
+    DocumentInfo {
+        title: field("name").unwrap_or_else(|| {
+            input.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_else(|| "api".to_string())
+        }),
+        version: field("version").unwrap_or_else(|| "1.0.0".to_string()),
+    }
 }
 
 /// Information about a JavaScript module that will be interpreted at runtime
@@ -63,8 +333,39 @@ pub struct CompilationContext {
     pub needs_js_runtime: bool,
     /// Whether perry/ui module is imported (needs UI library linking)
     pub needs_ui: bool,
+    /// Whether a module loads an N-API addon (the executable must export the N-API symbols)
+    pub needs_napi: bool,
     /// Project root (where we start looking for node_modules)
     pub project_root: PathBuf,
+    /// Options passed to HIR lowering for every module
+    pub lower_options: perry_hir::LowerOptions,
+    /// Import aliases from tsconfig.json (`baseUrl` / `paths`)
+    pub path_aliases: Option<PathAliases>,
+    /// Defer inlining until every module is collected (`--whole-program`)
+    pub whole_program: bool,
+    /// Sources of the native modules, for the spans HIR nodes carry into codegen
+    pub source_cache: SourceCache,
+    /// Lowered HIR from earlier builds (`None` with `--no-cache`)
+    pub hir_cache: Option<HirCache>,
+    /// Optimization settings for modules without `// @perry-opt:` directives
+    pub default_opt: OptSettings,
+}
+
+/// A native module on its way through codegen
+struct CodegenJob<'a> {
+    path: &'a PathBuf,
+    hir_module: &'a HirModule,
+    is_entry: bool,
+    /// Classes (with the local name they're imported under) and function param counts
+    /// from other native modules
+    imported_classes: Vec<(&'a perry_hir::Class, String)>,
+    imported_param_counts: Vec<(String, usize)>,
+    /// Object cache key, if caching is on
+    cache_key: Option<u64>,
+    /// Object code reused from the cache
+    cached: Option<Vec<u8>>,
+    /// Machine code of the module's functions, when its object code has to be generated
+    functions: Option<Arc<perry_codegen::FunctionCache>>,
 }
 
 impl CompilationContext {
@@ -75,109 +376,123 @@ impl CompilationContext {
             import_map: HashMap::new(),
             needs_js_runtime: false,
             needs_ui: false,
+            needs_napi: false,
             project_root,
+            lower_options: perry_hir::LowerOptions::default(),
+            path_aliases: None,
+            whole_program: false,
+            source_cache: SourceCache::new(),
+            hir_cache: None,
+            default_opt: OptSettings::default(),
         }
     }
 }
 
-/// Find the runtime library for linking
-fn find_runtime_library() -> Result<PathBuf> {
-    let candidates = [
-        PathBuf::from("target/release/libperry_runtime.a"),
-        PathBuf::from("target/debug/libperry_runtime.a"),
-        std::env::current_exe()
-            .ok()
-            .and_then(|p| p.parent().map(|p| p.join("libperry_runtime.a")))
-            .unwrap_or_default(),
-        PathBuf::from("/usr/local/lib/libperry_runtime.a"),
-    ];
+/// Find the runtime library for linking: perry-runtime, or perry-runtime-wasi's module
+/// for WebAssembly
+fn find_runtime_library(target: &Target) -> Result<PathBuf> {
+    let (library, package) = if target.is_wasm() {
+        (target.static_library("perry_runtime_wasi"), "perry-runtime-wasi")
+    } else {
+        (target.static_library("perry_runtime"), "perry-runtime")
+    };
+    if let Some(path) = target.library_candidates(&library).into_iter().find(|path| path.exists()) {
+        return Ok(path);
+    }
 
-    for path in &candidates {
-        if path.exists() {
-            return Ok(path.clone());
-        }
+    match &target.triple {
+        None => Err(anyhow!(
+            "Could not find {}. Build it with: cargo build --release -p {}",
+            library, package
+        )),
+        Some(triple) => Err(anyhow!(
+            "Could not find {} for {}. Build it with: cargo build --release --target {} -p {}",
+            library, triple, triple, package
+        )),
     }
+}
 
-    Err(anyhow!(
-        "Could not find libperry_runtime.a. Build it with: cargo build --release -p perry-runtime"
-    ))
+/// Link the modules' WebAssembly objects into the WASI runtime's module (see
+/// `perry_codegen::wasm_link`)
+fn link_wasm(runtime: &Path, obj_paths: &[PathBuf], exe_path: &Path) -> Result<()> {
+    let runtime = fs::read(runtime)
+        .map_err(|e| anyhow!("Failed to read {}: {}", runtime.display(), e))?;
+    let objects = obj_paths.iter()
+        .map(|path| WasmObject::from_bytes(&fs::read(path)?).map_err(|e| anyhow!("{}: {}", path.display(), e)))
+        .collect::<Result<Vec<_>>>()?;
+    let module = perry_codegen::wasm_link::link(&runtime, &objects)
+        .map_err(|e| anyhow!("Linking failed: {}", e))?;
+    fs::write(exe_path, module)
+        .map_err(|e| anyhow!("Failed to write {}: {}", exe_path.display(), e))
 }
 
-/// Find the stdlib library for linking (optional - only needed for native modules)
-fn find_stdlib_library() -> Option<PathBuf> {
-    let candidates = [
-        PathBuf::from("target/release/libperry_stdlib.a"),
-        PathBuf::from("target/debug/libperry_stdlib.a"),
-        std::env::current_exe()
-            .ok()
-            .and_then(|p| p.parent().map(|p| p.join("libperry_stdlib.a")))
-            .unwrap_or_default(),
-        PathBuf::from("/usr/local/lib/libperry_stdlib.a"),
+/// Import libraries of the Windows DLLs the runtime and the Rust standard library call
+/// into, and the dynamic C runtime Rust's MSVC target builds against
+const WINDOWS_SYSTEM_LIBS: &[&str] = &[
+    "kernel32.lib", "advapi32.lib", "bcrypt.lib", "ntdll.lib", "userenv.lib", "ws2_32.lib",
+    "secur32.lib", "crypt32.lib", "ole32.lib", "oleaut32.lib", "psapi.lib", "pdh.lib",
+    "iphlpapi.lib", "powrprof.lib", "netapi32.lib", "msvcrt.lib",
+];
+
+/// MSVC-style linker arguments for a Windows executable
+fn windows_link_args(exe_path: &Path) -> Vec<String> {
+    let mut args = vec![
+        format!("/OUT:{}", exe_path.display()),
+        "/NOLOGO".to_string(),
+        "/SUBSYSTEM:CONSOLE".to_string(),
+        // The 8 MiB main stack Linux and macOS give, rather than Windows' 1 MiB
+        "/STACK:8388608".to_string(),
     ];
+    args.extend(WINDOWS_SYSTEM_LIBS.iter().map(|lib| lib.to_string()));
+    args
+}
 
-    for path in &candidates {
-        if path.exists() {
-            return Some(path.clone());
-        }
+/// Linker arguments that replace malloc with the `--allocator` library. The runtime
+/// allocates through Rust's System allocator, i.e. malloc, so every runtime allocation
+/// goes to it. A static archive linked ahead of libc overrides malloc outright; the
+/// shared library is the fallback when none is found.
+fn allocator_link_args(allocator: Allocator, target: &Target) -> Vec<String> {
+    let (env_var, lib) = match allocator {
+        Allocator::System => return Vec::new(),
+        Allocator::Mimalloc => ("PERRY_MIMALLOC_LIB", "mimalloc"),
+        Allocator::Jemalloc => ("PERRY_JEMALLOC_LIB", "jemalloc"),
+    };
+    let archive = format!("lib{}.a", lib);
+    let mut candidates = vec![std::env::var_os(env_var).map(PathBuf::from).unwrap_or_default()];
+    // The system library directories hold builds for the host
+    if target.is_host() {
+        candidates.extend([
+            PathBuf::from("/usr/local/lib").join(&archive),
+            PathBuf::from("/opt/homebrew/lib").join(&archive),
+            PathBuf::from("/usr/lib").join(&archive),
+            PathBuf::from("/usr/lib/x86_64-linux-gnu").join(&archive),
+            PathBuf::from("/usr/lib/aarch64-linux-gnu").join(&archive),
+        ]);
+    }
+    let mut args = match candidates.iter().find(|path| path.is_file()) {
+        Some(path) => vec![path.display().to_string()],
+        None => vec![format!("-l{}", lib)],
+    };
+    // Both allocators use threads for their per-thread heaps
+    if target.os == Os::Linux {
+        args.push("-lpthread".to_string());
     }
+    args
+}
 
-    None
+/// Find the stdlib library for linking (optional - only needed for native modules)
+fn find_stdlib_library(target: &Target) -> Option<PathBuf> {
+    target.library_candidates(&target.static_library("perry_stdlib")).into_iter().find(|path| path.exists())
 }
 
 /// Find the V8 jsruntime library for linking (optional - only needed for JS module support)
-fn find_jsruntime_library() -> Option<PathBuf> {
-    let candidates = [
-        PathBuf::from("target/release/libperry_jsruntime.a"),
-        PathBuf::from("target/debug/libperry_jsruntime.a"),
-        std::env::current_exe()
-            .ok()
-            .and_then(|p| p.parent().map(|p| p.join("libperry_jsruntime.a")))
-            .unwrap_or_default(),
-        PathBuf::from("/usr/local/lib/libperry_jsruntime.a"),
-    ];
-
-    for path in &candidates {
-        if path.exists() {
-            return Some(path.clone());
-        }
-    }
-
-    None
+fn find_jsruntime_library(target: &Target) -> Option<PathBuf> {
+    target.library_candidates(&target.static_library("perry_jsruntime")).into_iter().find(|path| path.exists())
 }
 
 /// Find the UI library for linking (optional - only needed when perry/ui is imported)
-fn find_ui_library() -> Option<PathBuf> {
-    let candidates = [
-        PathBuf::from("target/release/libperry_ui_macos.a"),
-        PathBuf::from("target/debug/libperry_ui_macos.a"),
-        std::env::current_exe()
-            .ok()
-            .and_then(|p| p.parent().map(|p| p.join("libperry_ui_macos.a")))
-            .unwrap_or_default(),
-        PathBuf::from("/usr/local/lib/libperry_ui_macos.a"),
-    ];
-
-    for path in &candidates {
-        if path.exists() {
-            return Some(path.clone());
-        }
-    }
-
-    None
-}
-
-/// Find node_modules directory starting from a given path
-fn find_node_modules(start: &Path) -> Option<PathBuf> {
-    let mut current = start.to_path_buf();
-    loop {
-        let node_modules = current.join("node_modules");
-        if node_modules.is_dir() {
-            return Some(node_modules);
-        }
-        if !current.pop() {
-            return None;
-        }
-    }
+fn find_ui_library(target: &Target) -> Option<PathBuf> {
+    target.library_candidates(&target.static_library("perry_ui_macos")).into_iter().find(|path| path.exists())
 }
 
 /// Parse a package specifier into (package_name, subpath)
@@ -292,37 +607,47 @@ fn resolve_with_extensions(base: &Path) -> Option<PathBuf> {
     None
 }
 
-/// Resolve package.json entry point
-fn resolve_package_entry(package_dir: &Path, subpath: Option<&str>) -> Option<PathBuf> {
+/// Resolve package.json entry point. Packages with an "exports" field only expose
+/// what it maps; Err explains why the specifier was rejected.
+fn resolve_package_entry(package_dir: &Path, subpath: Option<&str>, conditions: &[&str]) -> Result<Option<PathBuf>, String> {
     let package_json = package_dir.join("package.json");
     if !package_json.exists() {
         // Fall back to index.js
-        return resolve_with_extensions(&package_dir.join("index"));
+        let base = subpath.map_or_else(|| package_dir.join("index"), |sub| package_dir.join(sub));
+        return Ok(resolve_with_extensions(&base));
     }
 
-    let content = fs::read_to_string(&package_json).ok()?;
-    let pkg: serde_json::Value = serde_json::from_str(&content).ok()?;
-
-    // Try "exports" field first (modern packages), for both main and subpaths
-    let export_key = if let Some(sub) = subpath {
-        format!("./{}", sub)
-    } else {
-        ".".to_string()
-    };
-
-    if let Some(exports) = pkg.get("exports") {
-        if let Some(entry) = resolve_exports(exports, &export_key) {
-            let entry_path = package_dir.join(&entry);
-            if entry_path.exists() {
-                return Some(entry_path);
+    let content = fs::read_to_string(&package_json).map_err(|e| format!("Failed to read {}: {}", package_json.display(), e))?;
+    let pkg: serde_json::Value = serde_json::from_str(&content).map_err(|e| format!("Failed to parse {}: {}", package_json.display(), e))?;
+
+    // "exports" takes precedence over every other field, for the main entry and subpaths
+    let exports = read_exports(&content).map_err(|e| format!("Failed to parse {}: {}", package_json.display(), e))?;
+    if let Some(exports) = exports {
+        let export_key = subpath.map_or_else(|| ".".to_string(), |sub| format!("./{}", sub));
+        return match resolve_exports(&exports, &export_key, conditions) {
+            ExportsResolution::Resolved(entry) => {
+                let entry_path = package_dir.join(&entry);
+                if entry_path.is_file() {
+                    Ok(Some(entry_path))
+                } else {
+                    Err(format!(
+                        "\"exports\" in {} maps '{}' to '{}', which does not exist",
+                        package_json.display(), export_key, entry
+                    ))
+                }
             }
-        }
+            ExportsResolution::NotExported => Err(format!(
+                "Package subpath '{}' is not defined by \"exports\" in {} (conditions: {}, default)",
+                export_key, package_json.display(), conditions.join(", ")
+            )),
+            ExportsResolution::Invalid(e) => Err(format!("Invalid \"exports\" in {}: {}", package_json.display(), e)),
+        };
     }
 
-    // If there's a subpath and exports didn't match, resolve it directly
+    // Without "exports", subpaths resolve directly
     if let Some(sub) = subpath {
         let subpath_resolved = package_dir.join(sub);
-        return resolve_with_extensions(&subpath_resolved);
+        return Ok(resolve_with_extensions(&subpath_resolved));
     }
 
     // Try "types" or "typings" field for TypeScript
@@ -332,7 +657,7 @@ fn resolve_package_entry(package_dir: &Path, subpath: Option<&str>) -> Option<Pa
             let types_file = package_dir.join(types_path);
             let ts_file = types_file.with_extension("ts");
             if ts_file.exists() {
-                return Some(ts_file);
+                return Ok(Some(ts_file));
             }
         }
     }
@@ -341,42 +666,28 @@ fn resolve_package_entry(package_dir: &Path, subpath: Option<&str>) -> Option<Pa
     if let Some(module) = pkg.get("module").and_then(|v| v.as_str()) {
         let module_path = package_dir.join(module);
         if module_path.exists() {
-            return Some(module_path);
+            return Ok(Some(module_path));
         }
     }
 
     // Try "main" field (CommonJS)
     if let Some(main) = pkg.get("main").and_then(|v| v.as_str()) {
         let main_path = package_dir.join(main);
-        return resolve_with_extensions(&main_path);
+        return Ok(resolve_with_extensions(&main_path));
     }
 
     // Fall back to index files
-    resolve_with_extensions(&package_dir.join("index"))
+    Ok(resolve_with_extensions(&package_dir.join("index")))
 }
 
-/// Resolve exports field from package.json
-fn resolve_exports(exports: &serde_json::Value, subpath: &str) -> Option<String> {
-    match exports {
-        serde_json::Value::String(s) => Some(s.clone()),
-        serde_json::Value::Object(map) => {
-            // Try the specific subpath first
-            if let Some(entry) = map.get(subpath) {
-                return resolve_exports(entry, subpath);
-            }
-
-            // Try common conditions (for both main entry and subpath entries)
-            // This handles the case where we've matched a subpath and now need to resolve the conditions
-            for condition in ["import", "module", "default", "require", "node"] {
-                if let Some(entry) = map.get(condition) {
-                    return resolve_exports(entry, subpath);
-                }
-            }
-
-            None
-        }
-        _ => None,
-    }
+/// Find the directory of a package in the nearest node_modules that contains it,
+/// searching from the importer, then from the project root
+fn find_package_dir(package_name: &str, importer_path: &Path, project_root: &Path) -> Option<PathBuf> {
+    let search_paths = [importer_path.parent(), Some(project_root)];
+    search_paths.iter().flatten()
+        .flat_map(|start| start.ancestors())
+        .map(|dir| dir.join("node_modules").join(package_name))
+        .find(|dir| dir.is_dir())
 }
 
 /// Determine if a file is a JavaScript file (not TypeScript)
@@ -393,6 +704,54 @@ fn is_declaration_file(path: &Path) -> bool {
     path.to_string_lossy().ends_with(".d.ts")
 }
 
+/// Typings of the project's dependencies, keyed by package name. Packages without
+/// typings, or whose declaration file doesn't parse, stay untyped.
+fn load_package_declarations(project_root: &Path) -> BTreeMap<String, PackageDeclarations> {
+    let manifest = project_root.join("package.json");
+    let package: Option<serde_json::Value> = fs::read_to_string(&manifest)
+        .ok()
+        .and_then(|text| serde_json::from_str(&text).ok());
+    let names: BTreeSet<&str> = ["dependencies", "devDependencies", "peerDependencies", "optionalDependencies"]
+        .iter()
+        .filter_map(|field| package.as_ref()?.get(field)?.as_object())
+        .flat_map(|deps| deps.keys().map(String::as_str))
+        .filter(|name| !name.starts_with("@types/"))
+        .collect();
+
+    let mut declarations = BTreeMap::new();
+    for name in names {
+        // The package's own typings win over @types/<package> (@types/scope__pkg for @scope/pkg)
+        let types_package = format!("@types/{}", name.strip_prefix('@').map_or_else(|| name.to_string(), |s| s.replacen('/', "__", 1)));
+        let found = [name, types_package.as_str()].iter()
+            .filter_map(|package| find_package_dir(package, &manifest, project_root))
+            .filter_map(|dir| package_typings(&dir))
+            .filter_map(|path| {
+                let source = fs::read_to_string(&path).ok()?;
+                let module = perry_parser::parse_declarations(&source, &path.to_string_lossy()).ok()?;
+                Some(perry_hir::collect_declarations(&module))
+            })
+            .find(|decls| !decls.is_empty());
+        if let Some(decls) = found {
+            declarations.insert(name.to_string(), decls);
+        }
+    }
+    declarations
+}
+
+/// Declaration file of a package: its package.json `types` (or `typings`) entry,
+/// otherwise `index.d.ts`
+fn package_typings(package_dir: &Path) -> Option<PathBuf> {
+    let package: Option<serde_json::Value> = fs::read_to_string(package_dir.join("package.json"))
+        .ok()
+        .and_then(|text| serde_json::from_str(&text).ok());
+    let entry = package.as_ref()
+        .and_then(|p| p.get("types").or_else(|| p.get("typings")))
+        .and_then(|v| v.as_str())
+        .unwrap_or("index.d.ts");
+    let path = package_dir.join(entry);
+    path.is_file().then_some(path)
+}
+
 /// Determine if a file is a TypeScript file (but not a declaration file)
 fn is_ts_file(path: &Path) -> bool {
     if is_declaration_file(path) {
@@ -405,11 +764,23 @@ fn is_ts_file(path: &Path) -> bool {
     }
 }
 
+/// Module kind for a project file: JS files are interpreted, everything else compiled
+fn local_module(path: &Path) -> Option<(PathBuf, ModuleKind)> {
+    let kind = if is_js_file(path) {
+        ModuleKind::Interpreted
+    } else {
+        ModuleKind::NativeCompiled
+    };
+    Some((path.canonicalize().ok()?, kind))
+}
+
 /// Resolve an import specifier to a file path
 fn resolve_import(
     import_source: &str,
     importer_path: &Path,
     project_root: &Path,
+    path_aliases: Option<&PathAliases>,
+    conditions: &[&str],
 ) -> Option<(PathBuf, ModuleKind)> {
     // Check if it's a native Rust stdlib module
     if perry_hir::is_native_module(import_source) {
@@ -420,52 +791,79 @@ fn resolve_import(
     if import_source.starts_with("./") || import_source.starts_with("../") {
         let parent = importer_path.parent()?;
         let resolved = parent.join(import_source);
-        if let Some(path) = resolve_with_extensions(&resolved) {
-            let kind = if is_js_file(&path) {
-                ModuleKind::Interpreted
-            } else {
-                ModuleKind::NativeCompiled
-            };
-            return Some((path.canonicalize().ok()?, kind));
-        }
-        return None;
+        return resolve_with_extensions(&resolved).and_then(|path| local_module(&path));
     }
 
     // Handle absolute paths
     if import_source.starts_with('/') {
         let resolved = PathBuf::from(import_source);
-        if let Some(path) = resolve_with_extensions(&resolved) {
-            let kind = if is_js_file(&path) {
-                ModuleKind::Interpreted
-            } else {
-                ModuleKind::NativeCompiled
-            };
-            return Some((path.canonicalize().ok()?, kind));
+        return resolve_with_extensions(&resolved).and_then(|path| local_module(&path));
+    }
+
+    // tsconfig `paths` aliases, then `baseUrl`; like tsc, fall through to node_modules
+    // when none of the substitutions exist
+    if let Some(aliases) = path_aliases {
+        if let Some(alias) = aliases.match_paths(import_source) {
+            if let Some(path) = alias.candidates.iter().find_map(|c| resolve_with_extensions(c)) {
+                return local_module(&path);
+            }
+        }
+        if let Some(path) = aliases.base_url_candidate(import_source).and_then(|c| resolve_with_extensions(&c)) {
+            return local_module(&path);
         }
-        return None;
     }
 
     // Handle node_modules (bare specifiers)
     let (package_name, subpath) = parse_package_specifier(import_source);
+    let package_dir = find_package_dir(&package_name, importer_path, project_root)?;
+    let entry = resolve_package_entry(&package_dir, subpath.as_deref(), conditions).ok()??;
+    // For node_modules packages, always treat as Interpreted
+    // Even .ts files in node_modules are library source code,
+    // not user code to be compiled. V8 will handle them at runtime.
+    Some((entry.canonicalize().ok()?, ModuleKind::Interpreted))
+}
 
-    // Find node_modules starting from importer, then project root
-    let search_paths = [importer_path.parent(), Some(project_root)];
-
-    for start in search_paths.iter().flatten() {
-        if let Some(node_modules) = find_node_modules(start) {
-            let package_dir = node_modules.join(&package_name);
-            if package_dir.is_dir() {
-                if let Some(entry) = resolve_package_entry(&package_dir, subpath.as_deref()) {
-                    // For node_modules packages, always treat as Interpreted
-                    // Even .ts files in node_modules are library source code,
-                    // not user code to be compiled. V8 will handle them at runtime.
-                    return Some((entry.canonicalize().ok()?, ModuleKind::Interpreted));
+/// `  file:line:column: message [code]` for each lowering diagnostic with `code`, sorted
+fn diagnostic_locations(ctx: &CompilationContext, code: DiagnosticCode) -> Vec<String> {
+    let mut locations = Vec::new();
+    for (path, hir_module) in &ctx.native_modules {
+        for diagnostic in hir_module.diagnostics.iter().filter(|d| d.code == code) {
+            let location = match ctx.source_cache.get_file(diagnostic.span.file_id) {
+                Some(file) => {
+                    let (line, column) = file.line_column(diagnostic.span.start);
+                    format!("{}:{}:{}", file.path.display(), line, column)
                 }
-            }
+                None => path.display().to_string(),
+            };
+            locations.push(format!("  {}: {} [{}]", location, diagnostic.message, diagnostic.code));
         }
     }
+    locations.sort();
+    locations
+}
 
-    None
+/// Parse a source file into `cache`, reporting every syntax error in it rather than just the first
+fn parse_module_source(
+    source: &str,
+    path: &Path,
+    cache: &mut SourceCache,
+) -> Result<(perry_parser::swc_ecma_ast::Module, FileId)> {
+    let result = perry_parser::parse_typescript_recovering(source, &path.to_string_lossy(), cache);
+    let file = cache.get_file(result.file_id);
+    let mut errors = Vec::new();
+    for diagnostic in result.diagnostics.iter() {
+        let (line, column) = file.map_or((0, 0), |f| f.line_column(diagnostic.span.start));
+        let location = format!("{}:{}:{}: {}", path.display(), line, column, diagnostic.message);
+        if diagnostic.is_error() {
+            errors.push(format!("  {}", location));
+        } else {
+            eprintln!("Parse warning: {}", location);
+        }
+    }
+    if errors.is_empty() {
+        return Ok((result.module, result.file_id));
+    }
+    Err(anyhow!("Parse error: {} syntax error(s)\n{}", errors.len(), errors.join("\n")))
 }
 
 /// Collect all modules to compile (transitive closure of imports)
@@ -538,12 +936,52 @@ fn collect_modules(
         .map(|s| s.to_string())
         .unwrap_or_else(|| filename.to_string());
 
-    let ast_module = perry_parser::parse_typescript(&source, filename)?;
+    // Registering the source first gives the file the id parsing would, so the cache key
+    // can include it before deciding whether to parse at all
+    let file_id = ctx.source_cache.add_file(&canonical, source.clone());
     let source_file_path = canonical.to_string_lossy().to_string();
-    let mut hir_module = perry_hir::lower_module(&ast_module, &module_name, &source_file_path)?;
+    let lower_options = perry_hir::LowerOptions { file_id: Some(file_id), ..ctx.lower_options.clone() };
+    let cache_key = HirCache::key(&source, &module_name, &source_file_path, &lower_options);
+    progress::module_started(&module_name);
+    let cached = ctx.hir_cache.as_mut().and_then(|cache| {
+        let module = cache.load(&source_file_path, cache_key);
+        telemetry::cache(module.is_some());
+        module
+    });
+    let from_cache = cached.is_some();
+    let mut hir_module = match cached {
+        Some(module) => module,
+        None => {
+            let (ast_module, _) = parse_module_source(&source, &canonical, &mut ctx.source_cache)?;
+            let module = perry_hir::lower_module_with_options(&ast_module, &module_name, &source_file_path, &lower_options)?;
+            if let Some(cache) = &ctx.hir_cache {
+                cache.store(&source_file_path, cache_key, &module);
+            }
+            module
+        }
+    };
 
-    // Apply function inlining optimization
-    inline_functions(&mut hir_module);
+    hir_module.opt = OptSettings::from_source(&source, ctx.default_opt)
+        .map_err(|e| anyhow!("{}: {}", canonical.display(), e))?;
+
+    // Apply function inlining optimization (whole-program mode inlines once all modules
+    // are collected); `// @perry-opt: no-inline` turns it off for the module
+    if !ctx.whole_program && hir_module.opt.inline {
+        inline_functions(&mut hir_module);
+    }
+    progress::module_finished(&module_name, from_cache);
+
+    for addon in &hir_module.native_addons {
+        ctx.needs_napi = true;
+        if let OutputFormat::Text = format {
+            // Bare specifiers are resolved next to the executable at runtime
+            if Path::new(addon).is_absolute() && !Path::new(addon).exists() {
+                println!("  Warning: native addon {} does not exist (imported from {})", addon, filename);
+            } else {
+                println!("  Native addon: {}", addon);
+            }
+        }
+    }
 
     // Process imports and update their resolved paths and module kinds
     for import in &mut hir_module.imports {
@@ -555,7 +993,8 @@ fn collect_modules(
             continue;
         }
 
-        if let Some((resolved_path, kind)) = resolve_import(&import.source, &canonical, &ctx.project_root) {
+        let conditions = if import.is_require { REQUIRE_CONDITIONS } else { IMPORT_CONDITIONS };
+        if let Some((resolved_path, kind)) = resolve_import(&import.source, &canonical, &ctx.project_root, ctx.path_aliases.as_ref(), conditions) {
             import.resolved_path = Some(resolved_path.to_string_lossy().to_string());
             import.module_kind = kind;
 
@@ -593,6 +1032,38 @@ fn collect_modules(
                 }
             }
         } else {
+            // An import matching a tsconfig `paths` alias points into the project, so a
+            // missing target is an error rather than a possibly-builtin module
+            if let Some(aliases) = &ctx.path_aliases {
+                if let Some(alias) = aliases.match_paths(&import.source) {
+                    let tried: Vec<String> = alias.candidates.iter()
+                        .map(|c| format!("  {}", c.display()))
+                        .collect();
+                    return Err(anyhow!(
+                        "Cannot resolve '{}' imported from {}: path alias '{}' in {} maps it to\n{}\nbut none of these exist (tried .ts, .tsx, .mts, .js, .mjs, .cjs, .json and index files)",
+                        import.source,
+                        canonical.display(),
+                        alias.pattern,
+                        aliases.config_path.display(),
+                        tried.join("\n")
+                    ));
+                }
+            }
+            // An installed package whose package.json rejects the specifier (e.g. a subpath
+            // missing from "exports") fails like it does in Node
+            if !import.source.starts_with('.') && !import.source.starts_with('/') {
+                let (package_name, subpath) = parse_package_specifier(&import.source);
+                if let Some(package_dir) = find_package_dir(&package_name, &canonical, &ctx.project_root) {
+                    if let Err(reason) = resolve_package_entry(&package_dir, subpath.as_deref(), conditions) {
+                        return Err(anyhow!(
+                            "Cannot resolve '{}' imported from {}: {}",
+                            import.source,
+                            canonical.display(),
+                            reason
+                        ));
+                    }
+                }
+            }
             // Could not resolve - might be a Node.js builtin or missing module
             // For now, treat unresolved non-native imports as errors
             if !import.is_native {
@@ -614,7 +1085,7 @@ fn collect_modules(
             perry_hir::Export::Named { .. } => None,
         };
         if let Some(src) = source {
-            if let Some((resolved_path, kind)) = resolve_import(src, &canonical, &ctx.project_root) {
+            if let Some((resolved_path, kind)) = resolve_import(src, &canonical, &ctx.project_root, ctx.path_aliases.as_ref(), IMPORT_CONDITIONS) {
                 match kind {
                     ModuleKind::NativeCompiled => {
                         collect_modules(&resolved_path, ctx, visited, enable_js_runtime, format)?;
@@ -635,6 +1106,61 @@ fn collect_modules(
 }
 
 /// Generate a JS bundle file containing all JS modules
+/// Compile-time constants exported by each native module, keyed by (resolved_path, export_name),
+/// including the ones a module re-exports (`export * from`, `export { X } from`)
+fn collect_exported_constants(
+    native_modules: &HashMap<PathBuf, HirModule>,
+    project_root: &Path,
+    path_aliases: Option<&PathAliases>,
+) -> HashMap<(String, String), perry_hir::ConstValue> {
+    let mut constants: HashMap<(String, String), perry_hir::ConstValue> = HashMap::new();
+    for (path, hir_module) in native_modules {
+        let path_str = path.to_string_lossy().to_string();
+        for (name, value) in &hir_module.exported_constants {
+            constants.insert((path_str.clone(), name.clone()), value.clone());
+        }
+    }
+
+    // Iterate until no new entries are added (for chained re-exports)
+    loop {
+        let mut new_entries = Vec::new();
+        for (path, hir_module) in native_modules {
+            let path_str = path.to_string_lossy().to_string();
+            for export in &hir_module.exports {
+                let (source, names) = match export {
+                    perry_hir::Export::ExportAll { source } => (source, None),
+                    perry_hir::Export::ReExport { source, imported, exported } => (source, Some((imported, exported))),
+                    perry_hir::Export::Named { .. } => continue,
+                };
+                let Some((resolved_source, _)) = resolve_import(source, path, project_root, path_aliases, IMPORT_CONDITIONS) else {
+                    continue;
+                };
+                let source_str = resolved_source.to_string_lossy().to_string();
+                for ((src_path, name), value) in &constants {
+                    if *src_path != source_str {
+                        continue;
+                    }
+                    let exported = match names {
+                        // `export *` doesn't re-export the default export
+                        None if name != "default" => name,
+                        Some((imported, exported)) if imported == name => exported,
+                        _ => continue,
+                    };
+                    let key = (path_str.clone(), exported.clone());
+                    if !constants.contains_key(&key) {
+                        new_entries.push((key, value.clone()));
+                    }
+                }
+            }
+        }
+        if new_entries.is_empty() {
+            break;
+        }
+        constants.extend(new_entries);
+    }
+    constants
+}
+
 fn generate_js_bundle(ctx: &CompilationContext, output_dir: &Path) -> Result<PathBuf> {
     let bundle_path = output_dir.join("__perry_js_bundle.js");
 
@@ -662,6 +1188,9 @@ fn generate_js_bundle(ctx: &CompilationContext, output_dir: &Path) -> Result<Pat
 }
 
 pub fn run(args: CompileArgs, format: OutputFormat, _use_color: bool, _verbose: u8) -> Result<()> {
+    if let Some(sink) = &args.progress {
+        progress::begin(sink);
+    }
     match format {
         OutputFormat::Text => println!("Collecting modules..."),
         OutputFormat::Json => {}
@@ -674,9 +1203,104 @@ pub fn run(args: CompileArgs, format: OutputFormat, _use_color: bool, _verbose:
         .unwrap_or_else(|_| PathBuf::from("."));
 
     let mut ctx = CompilationContext::new(project_root);
+    ctx.whole_program = args.whole_program;
+    ctx.path_aliases = match &args.tsconfig {
+        Some(config) => PathAliases::load(config)?,
+        None => PathAliases::find(&ctx.project_root)?,
+    };
+    if let (Some(aliases), OutputFormat::Text) = (&ctx.path_aliases, format) {
+        println!("Using import aliases from {}", aliases.config_path.display());
+    }
+    // Build settings: perry.toml `[build]`, overridden by the command line
+    let build_config = BuildConfig::find(&ctx.project_root)?;
+    if let (Some(config), OutputFormat::Text) = (&build_config, format) {
+        println!("Using build settings from {}", config.config_path.display());
+    }
+    // Files besides the sources whose changes affect the build
+    let config_files: Vec<PathBuf> = ctx.path_aliases.iter().map(|aliases| aliases.config_path.clone())
+        .chain(build_config.iter().map(|config| config.config_path.clone()))
+        .collect();
+    let build_config = build_config.unwrap_or_default();
+    if !args.no_cache {
+        let mut cache = HirCache::new(&ctx.project_root);
+        let remote_url = args.remote_cache.clone()
+            .or_else(|| std::env::var(REMOTE_CACHE_ENV).ok().filter(|url| !url.is_empty()))
+            .or_else(|| build_config.remote_cache.clone());
+        if let Some(url) = remote_url {
+            let read_only = args.remote_cache_read_only || build_config.remote_cache_upload == Some(false);
+            cache.remote = Some(RemoteCache::new(&url, read_only).map_err(|e| anyhow!(e))?);
+        }
+        ctx.hir_cache = Some(cache);
+    }
+    let opt_level = args.opt_level.map(OptLevel::from).or(build_config.opt_level).unwrap_or_default();
+    ctx.default_opt = OptSettings::with_level(opt_level);
+    let target = Target::from_arg(args.target.as_deref())?;
+    if target.os == Os::Windows && args.allocator != Allocator::System {
+        return Err(anyhow!(
+            "--allocator {} is not supported on Windows; the MSVC C runtime's allocator is used",
+            args.allocator.name()
+        ));
+    }
+    if target.os == Os::Windows && args.hot_reload.is_some() {
+        return Err(anyhow!("--hot-reload is not supported on Windows"));
+    }
+    if target.is_wasm() {
+        if args.allocator != Allocator::System {
+            return Err(anyhow!("--allocator {} is not supported for WebAssembly", args.allocator.name()));
+        }
+        if args.hot_reload.is_some() {
+            return Err(anyhow!("--hot-reload is not supported for WebAssembly"));
+        }
+        if args.debug {
+            return Err(anyhow!("--debug is not supported for WebAssembly: perry emits no DWARF for it"));
+        }
+        if args.enable_js_runtime {
+            return Err(anyhow!("--enable-js-runtime is not supported for WebAssembly: V8 doesn't run on WASI"));
+        }
+    }
+    let mut cranelift = perry_codegen::CraneliftSettings {
+        verifier: args.verify_ir || build_config.verifier.unwrap_or(false),
+        flags: build_config.cranelift_flags,
+        target: target.triple.clone(),
+    };
+    // Locals on the stack are described relative to the frame pointer, and profilers
+    // walk frame pointers
+    if args.debug {
+        cranelift.flags.push(("preserve_frame_pointers".to_string(), "true".to_string()));
+    }
+    cranelift.flags.extend(args.cranelift_flags.iter().cloned());
+    if args.import_meta == ImportMetaMode::Exe {
+        ctx.lower_options.import_meta_exe_root = Some(ctx.project_root.to_string_lossy().to_string());
+    }
+    // Calls into packages run by the JS runtime are typed from the packages' .d.ts files
+    if args.enable_js_runtime {
+        ctx.lower_options.declarations = load_package_declarations(&ctx.project_root);
+    }
     let mut visited = HashSet::new();
 
+    telemetry::set("whole_program", args.whole_program);
+    telemetry::set("js_runtime", args.enable_js_runtime);
+    let collect_started = Instant::now();
+    progress::phase("collect");
     collect_modules(&args.input, &mut ctx, &mut visited, args.enable_js_runtime, format)?;
+    telemetry::phase("collect", collect_started);
+    telemetry::set("modules", serde_json::json!({
+        "native": ctx.native_modules.len(),
+        "js": ctx.js_modules.len(),
+    }));
+    telemetry::diagnostics(ctx.native_modules.values().flat_map(|m| &m.diagnostics));
+    // Anything lowering left out would make the binary quietly behave differently
+    let dropped = diagnostic_locations(&ctx, DiagnosticCode::DroppedConstruct);
+    if !dropped.is_empty() {
+        if !args.permissive {
+            return Err(anyhow!(
+                "Unsupported: {} construct(s) can't be compiled (--permissive builds without them)\n{}",
+                dropped.len(),
+                dropped.join("\n")
+            ));
+        }
+        eprintln!("Warning: {} construct(s) left out of the executable:\n{}", dropped.len(), dropped.join("\n"));
+    }
 
     let total_modules = ctx.native_modules.len() + ctx.js_modules.len();
     match format {
@@ -686,10 +1310,119 @@ pub fn run(args: CompileArgs, format: OutputFormat, _use_color: bool, _verbose:
                 ctx.native_modules.len(),
                 ctx.js_modules.len()
             );
+            if let Some(cache) = ctx.hir_cache.as_ref().filter(|cache| cache.hits > 0) {
+                println!("Reused cached HIR for {} of {} native module(s)",
+                    cache.hits, cache.hits + cache.misses);
+            }
+            if let Some(remote) = ctx.hir_cache.as_ref().and_then(|cache| cache.remote.as_ref()) {
+                println!("Remote cache: {} downloaded, {} uploaded", remote.hits.get(), remote.uploads.get());
+            }
         }
         OutputFormat::Json => {}
     }
 
+    // JavaScript modules skip native compilation and its checks; look at them before they are bundled
+    let audit_findings = if args.audit || args.audit_db.is_some() || build_config.audit == Some(true) {
+        let db = args.audit_db.clone().or_else(|| build_config.audit_db.clone())
+            .map(|path| AdvisoryDb::load(&path))
+            .transpose()?;
+        let findings = js_audit::audit(&ctx.js_modules, db.as_ref(), &ctx.project_root);
+        let fail_on = args.audit_fail_on.or(build_config.audit_fail_on).unwrap_or(Severity::High);
+        if let OutputFormat::Text = format {
+            println!("Audited {} JavaScript module(s): {} finding(s)", ctx.js_modules.len(), findings.len());
+            for finding in findings.iter().filter(|finding| finding.severity < fail_on) {
+                println!("  {}", finding);
+            }
+        }
+        let failing: Vec<String> = findings.iter()
+            .filter(|finding| finding.severity >= fail_on)
+            .map(|finding| format!("  {}", finding))
+            .collect();
+        if !failing.is_empty() {
+            return Err(anyhow!("Audit found {} issue(s) at or above {} severity:\n{}",
+                failing.len(), fail_on.as_str(), failing.join("\n")));
+        }
+        Some(findings)
+    } else {
+        None
+    };
+
+    // Capabilities the program is limited to, compiled into the entry module
+    let permissions = build_config.permissions.as_ref().map(Permissions::serialize);
+    if let (Some(_), OutputFormat::Text) = (&permissions, format) {
+        println!("Restricting the program to the perry.toml [permissions]");
+    }
+
+    // Describe the imports and routes before any transform rewrites the HIR they were recorded from
+    if let Some(path) = &args.emit_build_graph {
+        let entry = args.input.canonicalize().unwrap_or_else(|_| args.input.clone());
+        let graph = build_graph::generate(&ctx, &entry, &config_files, |source, importer| {
+            resolve_import(source, importer, &ctx.project_root, ctx.path_aliases.as_ref(), IMPORT_CONDITIONS)
+        });
+        fs::write(path, serde_json::to_string_pretty(&graph)? + "\n")
+            .map_err(|e| anyhow!("Failed to write {}: {}", path.display(), e))?;
+        if let OutputFormat::Text = format {
+            println!("Wrote build graph to {}", path.display());
+        }
+    }
+
+    if let Some(path) = &args.emit_dts {
+        let entry = args.input.canonicalize().unwrap_or_else(|_| args.input.clone());
+        let mut module = ctx.native_modules.get(&entry)
+            .ok_or_else(|| anyhow!("{} was not compiled natively, so it has no declarations", args.input.display()))?
+            .clone();
+        // Un-annotated exported bindings take their initializers' types
+        perry_hir::infer_local_types(&mut module);
+        fs::write(path, dts::generate(&module))
+            .map_err(|e| anyhow!("Failed to write {}: {}", path.display(), e))?;
+        if let OutputFormat::Text = format {
+            println!("Wrote declarations to {}", path.display());
+        }
+    }
+
+    let docs_document = if args.openapi.is_some() || args.docs {
+        let document = openapi::generate(&ctx.native_modules, &document_info(&ctx.project_root, &args.input));
+        if let Some(path) = &args.openapi {
+            fs::write(path, serde_json::to_string_pretty(&document)? + "\n")
+                .map_err(|e| anyhow!("Failed to write {}: {}", path.display(), e))?;
+            if let OutputFormat::Text = format {
+                println!("Wrote OpenAPI document to {}", path.display());
+            }
+        }
+        args.docs.then(|| document.to_string())
+    } else {
+        None
+    };
+
+    let tzdata = match &args.embed_tzdata {
+        Some(zones) => {
+            let dir = PathBuf::from(perry_runtime::temporal::zoneinfo_dir());
+            let table = perry_runtime::temporal::pack_tzdata(&dir, zones)
+                .map_err(|e| anyhow!("Failed to embed time zone data from {}: {}", dir.display(), e))?;
+            Some(table)
+        }
+        None => None,
+    };
+
+    // perry-runtime-wasi has none of the libraries these link in
+    if target.is_wasm() {
+        if ctx.needs_js_runtime {
+            return Err(anyhow!("JavaScript modules can't be compiled for WebAssembly: V8 doesn't run on WASI"));
+        }
+        if ctx.needs_ui {
+            return Err(anyhow!("perry/ui is not supported for WebAssembly"));
+        }
+        if ctx.needs_napi {
+            return Err(anyhow!("N-API addons are not supported for WebAssembly"));
+        }
+        if !build_config.native_libs.is_empty() {
+            return Err(anyhow!("perry.toml native_libs are not supported for WebAssembly"));
+        }
+    }
+
+    let transform_started = Instant::now();
+    progress::phase("transform");
+
     // Transform JS imports into runtime calls
     if ctx.needs_js_runtime {
         for (_, hir_module) in ctx.native_modules.iter_mut() {
@@ -725,11 +1458,67 @@ pub fn run(args: CompileArgs, format: OutputFormat, _use_color: bool, _verbose:
         }
     }
 
-    // Run monomorphization pass on all native modules
-    for (_, hir_module) in ctx.native_modules.iter_mut() {
-        perry_hir::monomorphize_module(hir_module);
+    // Fold imported enums and constants into literals. Modules are lowered before the
+    // modules they import, so this can only run once all of them are collected.
+    let exported_constants = collect_exported_constants(&ctx.native_modules, &ctx.project_root, ctx.path_aliases.as_ref());
+    if !exported_constants.is_empty() {
+        for (_, hir_module) in ctx.native_modules.iter_mut() {
+            perry_hir::fold_imported_constants(hir_module, &exported_constants);
+        }
     }
 
+    if ctx.whole_program {
+        inline_across_modules(&mut ctx.native_modules);
+    }
+
+    // Monomorphize all native modules together, so a generic instantiated in another
+    // module than the one defining it is specialized too (once, in the defining module)
+    let (project_root, path_aliases) = (&ctx.project_root, ctx.path_aliases.as_ref());
+    let resolve = |importer: &Path, source: &str| {
+        resolve_import(source, importer, project_root, path_aliases, IMPORT_CONDITIONS).map(|(path, _)| path)
+    };
+    perry_hir::monomorphize_program(&mut ctx.native_modules, &resolve);
+    // A type argument that violates its constraint would specialize into code that reads
+    // properties the value doesn't have
+    let violations = diagnostic_locations(&ctx, DiagnosticCode::ConstraintViolation);
+    if !violations.is_empty() {
+        return Err(anyhow!("Type error: {} constraint violation(s)\n{}", violations.len(), violations.join("\n")));
+    }
+    // Statements that can't run, untaken constant branches and unused bindings go before
+    // the passes below look at them
+    let mut dead_statements = 0;
+    for hir_module in ctx.native_modules.values_mut() {
+        if !args.no_dce && hir_module.opt.level != OptLevel::None {
+            dead_statements += eliminate_dead_statements(hir_module);
+        }
+    }
+    telemetry::set("dead_statements", dead_statements);
+    // Objects and arrays that never leave their function become a local per field, which
+    // inference below then types like any other binding
+    let mut scalar_replaced = 0;
+    for hir_module in ctx.native_modules.values_mut() {
+        if hir_module.opt.level != OptLevel::None {
+            scalar_replaced += scalar_replace_allocations(hir_module);
+        }
+    }
+    telemetry::set("scalar_replaced", scalar_replaced);
+    // With every generic specialized, un-annotated bindings can take their initializers' types
+    for hir_module in ctx.native_modules.values_mut() {
+        perry_hir::infer_local_types(hir_module);
+    }
+    // Skip functions nothing reaches from the modules' top-level code. JavaScript modules
+    // can call any export, and a hot reload patch any function, so those programs keep everything.
+    // An object left for another linker keeps its exports for the code it's linked with
+    let dead_code_elimination = !args.no_dce && !ctx.needs_js_runtime && args.hot_reload.is_none();
+    if dead_code_elimination {
+        let removed = eliminate_dead_functions(&mut ctx.native_modules, args.no_link);
+        telemetry::set("dead_functions", removed);
+        if let (OutputFormat::Text, true) = (format, removed > 0) {
+            println!("Skipping {} unreachable function(s)", removed);
+        }
+    }
+    telemetry::phase("transform", transform_started);
+
     if args.print_hir {
         for (path, hir_module) in &ctx.native_modules {
             println!("\n=== HIR (after monomorphization): {} ===", path.display());
@@ -781,84 +1570,35 @@ pub fn run(args: CompileArgs, format: OutputFormat, _use_color: bool, _verbose:
     // Get canonical path of entry module
     let entry_path = args.input.canonicalize().unwrap_or_else(|_| args.input.clone());
 
-    // Collect non-entry module names for init function calls
-    // Topologically sort by import dependencies so that if module A imports from module B,
-    // module B is initialized first. This ensures module-level variables (e.g., Maps) are
-    // allocated before other modules try to use them via imported functions.
+    // Collect non-entry module names for init function calls, in ESM evaluation order:
+    // if module A imports from module B, B is initialized first so that its module-level
+    // variables (e.g., Maps) exist before A uses them. Import cycles are broken at the
+    // import that closes them; bindings read across the cycle go through export globals,
+    // which stay `undefined` until the exporting module has initialized them.
     let non_entry_module_names: Vec<String> = {
-        // Build path->name mapping and dependency graph
-        let mut path_to_name: HashMap<PathBuf, String> = HashMap::new();
-        let mut name_to_path: HashMap<String, PathBuf> = HashMap::new();
-        let mut deps: HashMap<PathBuf, Vec<PathBuf>> = HashMap::new();
-
+        let mut graph = ModuleGraph::new();
         for (path, hir_module) in &ctx.native_modules {
-            if *path == entry_path {
-                continue;
-            }
-            path_to_name.insert(path.clone(), hir_module.name.clone());
-            name_to_path.insert(hir_module.name.clone(), path.clone());
-
-            let mut module_deps = Vec::new();
-            for import in &hir_module.imports {
-                if let Some(ref resolved) = import.resolved_path {
-                    let resolved_path = PathBuf::from(resolved);
-                    if resolved_path != entry_path && ctx.native_modules.contains_key(&resolved_path) {
-                        module_deps.push(resolved_path);
-                    }
-                }
-            }
-            deps.insert(path.clone(), module_deps);
+            let imports = hir_module.imports.iter()
+                .filter(|import| import.module_kind == ModuleKind::NativeCompiled)
+                .filter_map(|import| import.resolved_path.as_ref().map(PathBuf::from))
+                .collect();
+            graph.add_module(path.clone(), imports);
         }
 
-        // Topological sort using Kahn's algorithm
-        let mut in_degree: HashMap<PathBuf, usize> = HashMap::new();
-        for (path, _) in &path_to_name {
-            in_degree.insert(path.clone(), 0);
-        }
-        for (_, module_deps) in &deps {
-            for dep in module_deps {
-                if let Some(count) = in_degree.get_mut(dep) {
-                    *count += 1;
-                }
+        if let OutputFormat::Text = format {
+            for cycle in graph.cycles() {
+                let members: Vec<String> = cycle.iter()
+                    .map(|p| p.strip_prefix(&ctx.project_root).unwrap_or(p).display().to_string())
+                    .collect();
+                println!("Note: circular import between {}", members.join(", "));
             }
         }
 
-        let mut queue: Vec<PathBuf> = in_degree.iter()
-            .filter(|(_, &count)| count == 0)
-            .map(|(path, _)| path.clone())
-            .collect();
-        queue.sort(); // deterministic order for modules with same in-degree
-
-        let mut sorted = Vec::new();
-        while let Some(path) = queue.pop() {
-            if let Some(name) = path_to_name.get(&path) {
-                sorted.push(name.clone());
-            }
-            if let Some(module_deps) = deps.get(&path) {
-                for dep in module_deps {
-                    if let Some(count) = in_degree.get_mut(dep) {
-                        *count -= 1;
-                        if *count == 0 {
-                            queue.push(dep.clone());
-                            queue.sort();
-                        }
-                    }
-                }
-            }
-        }
-
-        // Add any remaining modules not reached by the sort (cycle protection)
-        for (path, name) in &path_to_name {
-            if !sorted.contains(name) {
-                sorted.push(name.clone());
-            }
-        }
-
-        // Reverse: dependencies should be initialized first (they have no dependents)
-        // Kahn's gives us "leaves first" (no incoming edges = no one depends on them)
-        // We want "roots first" (modules that others depend on)
-        sorted.reverse();
-        sorted
+        graph.init_order(&entry_path)
+            .into_iter()
+            .filter(|path| *path != entry_path)
+            .filter_map(|path| ctx.native_modules.get(&path).map(|m| m.name.clone()))
+            .collect()
     };
 
     // Build a map of all exported classes from all modules
@@ -900,7 +1640,7 @@ pub fn run(args: CompileArgs, format: OutputFormat, _use_color: bool, _verbose:
             for export in &hir_module.exports {
                 if let perry_hir::Export::ExportAll { source } = export {
                     // Resolve the source path relative to this module
-                    if let Some((resolved_source, _)) = resolve_import(source, path, &ctx.project_root) {
+                    if let Some((resolved_source, _)) = resolve_import(source, path, &ctx.project_root, ctx.path_aliases.as_ref(), IMPORT_CONDITIONS) {
                         let source_path_str = resolved_source.to_string_lossy().to_string();
 
                         // Find all classes exported from the source module and add them
@@ -927,31 +1667,21 @@ pub fn run(args: CompileArgs, format: OutputFormat, _use_color: bool, _verbose:
         }
     }
 
-    // Compile native modules
+    // Compile native modules, reusing the object code of modules whose codegen inputs are unchanged
+    let codegen_started = Instant::now();
+    progress::phase("codegen");
+    let sources = Arc::new(std::mem::take(&mut ctx.source_cache));
+    let mut object_cache = (!args.no_cache).then(|| ObjectCache::new(&ctx.project_root));
+    let mut js_specifiers: Vec<&String> = ctx.js_modules.keys().collect();
+    js_specifiers.sort();
+    let mut jobs: Vec<CodegenJob> = Vec::with_capacity(ctx.native_modules.len());
     for (path, hir_module) in &ctx.native_modules {
-        let mut compiler = perry_codegen::Compiler::new()?;
-
         // Check if this is the entry module
         let is_entry = path == &entry_path;
-        compiler.set_is_entry_module(is_entry);
-
-        // For entry module, add init function calls for all other native modules
-        if is_entry {
-            for module_name in &non_entry_module_names {
-                compiler.add_native_module_init(module_name.clone());
-            }
-        }
-
-        // If we need JS runtime, tell the compiler to generate init code
-        if ctx.needs_js_runtime {
-            compiler.set_needs_js_runtime(true);
-            // Pass JS module paths for loading
-            for (specifier, _module) in &ctx.js_modules {
-                compiler.add_js_module(specifier.clone());
-            }
-        }
 
-        // Register imported classes from other native modules
+        // Classes and function param counts imported from other native modules
+        let mut imported_classes: Vec<(&perry_hir::Class, String)> = Vec::new();
+        let mut imported_param_counts: Vec<(String, usize)> = Vec::new();
         for import in &hir_module.imports {
             // Only process imports from other native TypeScript modules
             if import.module_kind != perry_hir::ModuleKind::NativeCompiled {
@@ -973,28 +1703,149 @@ pub fn run(args: CompileArgs, format: OutputFormat, _use_color: bool, _verbose:
                 // Check if this import is a class from another module
                 let key = (resolved_path.clone(), exported_name.clone());
                 if let Some(class) = exported_classes.get(&key) {
-                    // Register this class as an import in the current compiler
-                    // Pass the local_name as an alias so the class can be found when used with that name
-                    compiler.register_imported_class(class, Some(&local_name))?;
+                    // The local_name is an alias so the class can be found when used with that name
+                    imported_classes.push((*class, local_name));
                 }
 
                 // Check if this import is a function from another module
-                // Register its param count so wrapper signatures are consistent
+                // Its param count keeps wrapper signatures consistent
                 if let Some(&param_count) = exported_func_param_counts.get(&key) {
-                    compiler.register_imported_func_param_count(exported_name, param_count);
+                    imported_param_counts.push((exported_name, param_count));
                 }
             }
         }
 
-        let object_code = compiler.compile_module(hir_module)
-            .map_err(|e| anyhow::anyhow!("Error compiling module '{}' ({}): {}", hir_module.name, path.display(), e))?;
+        // Everything besides the HIR that the object code depends on
+        let cache_key = object_cache.as_ref().and_then(|_| {
+            let entry_inputs = is_entry.then(|| (
+                args.allocator.name(),
+                &docs_document,
+                &tzdata,
+                &permissions,
+                &non_entry_module_names,
+            ));
+            let imports: Vec<String> = imported_classes.iter()
+                .map(|(class, alias)| format!("{} {:?}", alias, class))
+                .collect();
+            ObjectCache::key(hir_module, &(
+                fs::read(path).ok(),
+                path,
+                (args.crash_report, args.debug, args.max_heap, args.oom_policy == OomPolicy::Abort, args.hot_reload.is_some()),
+                (cranelift.verifier, &cranelift.flags, &cranelift.target),
+                (ctx.needs_js_runtime, &js_specifiers),
+                entry_inputs,
+                (imports, &imported_param_counts),
+            ))
+        });
+        let cached = match (object_cache.as_mut(), cache_key) {
+            (Some(cache), Some(key)) => cache.load(path, key),
+            _ => None,
+        };
+        // Debug builds compile every function for its debug info, and WebAssembly isn't
+        // machine code
+        let functions = object_cache.as_ref()
+            .filter(|_| cached.is_none() && !args.debug && !target.is_wasm())
+            .map(|cache| Arc::new(cache.load_functions(path)));
+        jobs.push(CodegenJob { path, hir_module, is_entry, imported_classes, imported_param_counts, cache_key, cached, functions });
+    }
+    progress::modules(jobs.len());
+
+    // Generate code for the modules that missed the cache in parallel, each with its own
+    // Compiler (and so its own Cranelift module) on a rayon worker. A module's functions
+    // are compiled in shards on the same pool (see perry_codegen's shards)
+    let compile_job = |job: &CodegenJob| -> Result<Vec<u8>> {
+        let (path, hir_module, is_entry) = (job.path, job.hir_module, job.is_entry);
+        let mut compiler = perry_codegen::Compiler::with_settings(hir_module.opt.level, &cranelift)?;
+        compiler.set_sources(sources.clone());
+        compiler.set_is_entry_module(is_entry);
+        compiler.set_crash_reporting(args.crash_report);
+        compiler.set_debug_info(args.debug);
+        compiler.set_multiversion(hir_module.opt.multiversion)?;
+        compiler.set_memory_limit(args.max_heap, args.oom_policy == OomPolicy::Abort);
+        compiler.set_hot_reload(args.hot_reload.is_some());
+        if let Some(functions) = &job.functions {
+            compiler.set_function_cache(functions.clone());
+        }
+        if is_entry {
+            if args.allocator != Allocator::System {
+                compiler.set_allocator(Some(args.allocator.name().to_string()));
+            }
+            compiler.set_docs_document(docs_document.clone());
+            compiler.set_tzdata(tzdata.clone());
+            compiler.set_permissions(permissions.clone());
+        }
+
+        // For entry module, add init function calls for all other native modules
+        if is_entry {
+            for module_name in &non_entry_module_names {
+                compiler.add_native_module_init(module_name.clone());
+            }
+        }
+
+        // If we need JS runtime, tell the compiler to generate init code
+        if ctx.needs_js_runtime {
+            compiler.set_needs_js_runtime(true);
+            // Pass JS module paths for loading
+            for specifier in &js_specifiers {
+                compiler.add_js_module((*specifier).clone());
+            }
+        }
+
+        // Register imported classes and function param counts from other native modules
+        for (class, local_name) in &job.imported_classes {
+            compiler.register_imported_class(class, Some(local_name.as_str()))?;
+        }
+        for (func_name, param_count) in &job.imported_param_counts {
+            compiler.register_imported_func_param_count(func_name.clone(), *param_count);
+        }
+
+        compiler.compile_module(hir_module)
+            .map_err(|e| anyhow::anyhow!("Error compiling module '{}' ({}): {}", hir_module.name, path.display(), e))
+    };
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(args.jobs.unwrap_or(0))
+        .build()
+        .map_err(|e| anyhow::anyhow!("Failed to start codegen threads: {}", e))?;
+    // (object code, whether it was just generated)
+    let compiled: Vec<Result<(Vec<u8>, bool)>> = pool.install(|| {
+        jobs.par_iter_mut()
+            .map(|job| match job.cached.take() {
+                Some(object_code) => {
+                    progress::module_finished(&job.hir_module.name, true);
+                    Ok((object_code, false))
+                }
+                None => {
+                    progress::module_started(&job.hir_module.name);
+                    compile_job(job).map(|object_code| {
+                        progress::module_finished(&job.hir_module.name, false);
+                        (object_code, true)
+                    })
+                }
+            })
+            .collect()
+    });
+
+    // Write the object files in module order, so their names don't depend on scheduling
+    let mut hot_reload_modules = Vec::new();
+    let (mut function_hits, mut function_misses) = (0, 0);
+    for (job, compiled) in jobs.iter().zip(compiled) {
+        let path = job.path;
+        let (object_code, generated) = compiled?;
+        if let (Some(cache), Some(key), true) = (&object_cache, job.cache_key, generated) {
+            cache.store(path, key, &object_code);
+        }
+        if let (Some(cache), Some(functions)) = (&object_cache, &job.functions) {
+            cache.store_functions(path, functions);
+            function_hits += functions.hits();
+            function_misses += functions.misses();
+        }
 
         // Generate a unique object file name to handle files with same basename in different directories
         // e.g., routes/auth.ts -> routes_auth.o, middleware/auth.ts -> middleware_auth.o
         let obj_name = {
             // Try to get a unique name by including parent directory
             let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("module");
-            let expected_obj_name = format!("{}.o", stem);
+            let expected_obj_name = format!("{}.{}", stem, target.object_extension());
             if let Some(parent) = path.parent().and_then(|p| p.file_name()).and_then(|s| s.to_str()) {
                 // Check if there might be a conflict (another file with same stem exists)
                 let simple_obj = PathBuf::from(&expected_obj_name);
@@ -1010,15 +1861,31 @@ pub fn run(args: CompileArgs, format: OutputFormat, _use_color: bool, _verbose:
                 stem.to_string()
             }
         };
-        let obj_path = PathBuf::from(format!("{}.o", obj_name));
+        let obj_path = PathBuf::from(format!("{}.{}", obj_name, target.object_extension()));
 
         fs::write(&obj_path, &object_code)?;
         match format {
             OutputFormat::Text => println!("Wrote object file: {}", obj_path.display()),
             OutputFormat::Json => {}
         }
+        if args.hot_reload.is_some() {
+            let object = std::env::current_dir()?.join(&obj_path);
+            hot_reload_modules.push(hot_reload::ModuleEntry::new(job.hir_module, object, &object_code)?);
+        }
         obj_paths.push(obj_path);
     }
+    if let Some(path) = &args.hot_reload {
+        hot_reload::Manifest { modules: hot_reload_modules }.write(path)?;
+    }
+    telemetry::phase("codegen", codegen_started);
+    if let (Some(cache), OutputFormat::Text) = (object_cache.as_ref().filter(|cache| cache.hits > 0), format) {
+        println!("Reused cached object code for {} of {} native module(s)",
+            cache.hits, cache.hits + cache.misses);
+    }
+    if function_hits > 0 && matches!(format, OutputFormat::Text) {
+        println!("Reused cached machine code for {} of {} function(s) in recompiled modules",
+            function_hits, function_hits + function_misses);
+    }
 
     // Generate JS bundle if needed
     let _js_bundle_path = if ctx.needs_js_runtime && !ctx.js_modules.is_empty() {
@@ -1037,7 +1904,7 @@ pub fn run(args: CompileArgs, format: OutputFormat, _use_color: bool, _verbose:
         .file_stem()
         .and_then(|s| s.to_str())
         .unwrap_or("output");
-    let exe_path = args.output.unwrap_or_else(|| PathBuf::from(stem));
+    let exe_path = args.output.unwrap_or_else(|| PathBuf::from(format!("{}{}", stem, target.exe_suffix())));
 
     if args.no_link {
         return Ok(());
@@ -1048,134 +1915,205 @@ pub fn run(args: CompileArgs, format: OutputFormat, _use_color: bool, _verbose:
         OutputFormat::Json => {}
     }
 
-    let runtime_lib = find_runtime_library()?;
-    let stdlib_lib = find_stdlib_library();
-    let jsruntime_lib = if ctx.needs_js_runtime || args.enable_js_runtime {
-        match find_jsruntime_library() {
-            Some(lib) => {
-                match format {
-                    OutputFormat::Text => println!("Using V8 JavaScript runtime for JS module support"),
-                    OutputFormat::Json => {}
+    let runtime_lib = find_runtime_library(&target)?;
+    if target.is_wasm() {
+        // WebAssembly needs no system linker: perry merges the objects into the runtime
+        let link_started = Instant::now();
+        progress::phase("link");
+        link_wasm(&runtime_lib, &obj_paths, &exe_path)?;
+        telemetry::phase("link", link_started);
+    } else {
+        let stdlib_lib = find_stdlib_library(&target);
+        let jsruntime_lib = if ctx.needs_js_runtime || args.enable_js_runtime {
+            match find_jsruntime_library(&target) {
+                Some(lib) => {
+                    match format {
+                        OutputFormat::Text => println!("Using V8 JavaScript runtime for JS module support"),
+                        OutputFormat::Json => {}
+                    }
+                    Some(lib)
                 }
-                Some(lib)
-            }
-            None => {
-                if ctx.needs_js_runtime {
-                    return Err(anyhow!(
-                        "JavaScript modules found but libperry_jsruntime.a not found. Build it with: cargo build --release -p perry-jsruntime"
-                    ));
+                None => {
+                    if ctx.needs_js_runtime {
+                        return Err(anyhow!(
+                            "JavaScript modules found but {} not found. Build it with: cargo build --release -p perry-jsruntime",
+                            target.static_library("perry_jsruntime")
+                        ));
+                    }
+                    None
                 }
-                None
             }
+        } else {
+            None
+        };
+
+        let mut cmd = target.linker();
+        for obj_path in &obj_paths {
+            cmd.arg(obj_path);
         }
-    } else {
-        None
-    };
 
-    let mut cmd = Command::new("cc");
-    for obj_path in &obj_paths {
-        cmd.arg(obj_path);
-    }
-
-    // Link libraries carefully to avoid duplicate symbols.
-    // All three libraries (runtime, stdlib, jsruntime) contain perry-runtime symbols
-    // because Rust staticlib embeds all dependencies.
-    //
-    // To avoid duplicates:
-    // - If jsruntime is used: link only jsruntime + stdlib (jsruntime has runtime)
-    // - If only stdlib: link only stdlib (it has runtime)
-    // - If neither: link only runtime
-    //
-    // Note: When both jsruntime and stdlib are needed, they both contain runtime,
-    // so we use -Wl,-allow_sub_type_mismatches to ignore the duplicates.
-
-
-    // Link libraries - avoid duplicates by linking only one library with runtime symbols.
-    // jsruntime now includes stdlib, which includes runtime.
-    // So we only need to link ONE of: jsruntime, stdlib, or runtime.
-    if let Some(ref jsruntime) = jsruntime_lib {
-        // jsruntime includes stdlib and runtime - link only jsruntime
-        cmd.arg(jsruntime);
-    } else if let Some(ref stdlib) = stdlib_lib {
-        // stdlib includes runtime - link only stdlib
-        cmd.arg(stdlib);
-    } else {
-        // No stdlib or jsruntime - link runtime directly
-        cmd.arg(&runtime_lib);
-    }
+        // perry.toml native_libs (`perry bindgen` crates) call into the runtime, so they go
+        // before the runtime library
+        for lib in &build_config.native_libs {
+            if !lib.is_file() {
+                return Err(anyhow!(
+                    "Native library {} not found (build its crate with `cargo build --release`)",
+                    lib.display()
+                ));
+            }
+            cmd.arg(lib);
+        }
 
-    cmd.arg("-o")
-        .arg(&exe_path)
-        .arg("-lc");
+        // Link libraries carefully to avoid duplicate symbols.
+        // All three libraries (runtime, stdlib, jsruntime) contain perry-runtime symbols
+        // because Rust staticlib embeds all dependencies.
+        //
+        // To avoid duplicates:
+        // - If jsruntime is used: link only jsruntime + stdlib (jsruntime has runtime)
+        // - If only stdlib: link only stdlib (it has runtime)
+        // - If neither: link only runtime
+        //
+        // Note: When both jsruntime and stdlib are needed, they both contain runtime,
+        // so we use -Wl,-allow_sub_type_mismatches to ignore the duplicates.
+
+
+        // Link libraries - avoid duplicates by linking only one library with runtime symbols.
+        // jsruntime now includes stdlib, which includes runtime.
+        // So we only need to link ONE of: jsruntime, stdlib, or runtime.
+        if let Some(ref jsruntime) = jsruntime_lib {
+            // jsruntime includes stdlib and runtime - link only jsruntime
+            cmd.arg(jsruntime);
+        } else if let Some(ref stdlib) = stdlib_lib {
+            // stdlib includes runtime - link only stdlib
+            cmd.arg(stdlib);
+        } else {
+            // No stdlib or jsruntime - link runtime directly
+            cmd.arg(&runtime_lib);
+        }
 
-    // On macOS, we need additional frameworks for the runtime (sysinfo, etc.) and V8
-    #[cfg(target_os = "macos")]
-    {
-        // Always link CoreFoundation and related frameworks since perry-runtime uses sysinfo
-        cmd.arg("-framework").arg("Security")
-           .arg("-framework").arg("CoreFoundation")
-           .arg("-framework").arg("SystemConfiguration")
-           .arg("-framework").arg("IOKit")
-           .arg("-liconv")
-           .arg("-lresolv");
+        // Drop the runtime and stdlib functions nothing calls. Addons look symbols up at
+        // runtime, so the executable keeps them all when it loads any
+        let gc_sections = dead_code_elimination && !ctx.needs_napi;
+        if target.os == Os::Windows {
+            cmd.args(windows_link_args(&exe_path));
+            if gc_sections {
+                cmd.arg("/OPT:REF");
+            }
+        } else {
+            // --allocator: after the runtime (which calls malloc) and before libc
+            cmd.args(allocator_link_args(args.allocator, &target));
+
+            cmd.arg("-o")
+                .arg(&exe_path)
+                .arg("-lc");
+            if gc_sections {
+                cmd.arg(if target.os == Os::MacOs { "-Wl,-dead_strip" } else { "-Wl,--gc-sections" });
+            }
+        }
 
-        // V8 requires additional C++ runtime
-        if jsruntime_lib.is_some() {
-            cmd.arg("-lc++");
+        // On macOS, we need additional frameworks for the runtime (sysinfo, etc.) and V8
+        if target.os == Os::MacOs {
+            // Always link CoreFoundation and related frameworks since perry-runtime uses sysinfo
+            cmd.arg("-framework").arg("Security")
+               .arg("-framework").arg("CoreFoundation")
+               .arg("-framework").arg("SystemConfiguration")
+               .arg("-framework").arg("IOKit")
+               .arg("-liconv")
+               .arg("-lresolv");
+
+            // V8 requires additional C++ runtime
+            if jsruntime_lib.is_some() {
+                cmd.arg("-lc++");
+            }
         }
-    }
 
-    // On Linux, link against pthread and dl for V8
-    #[cfg(target_os = "linux")]
-    {
-        if jsruntime_lib.is_some() {
-            cmd.arg("-lpthread")
-               .arg("-ldl")
-               .arg("-lstdc++");
+        // On Linux, link against pthread and dl for V8
+        if target.os == Os::Linux {
+            if jsruntime_lib.is_some() {
+                cmd.arg("-lpthread")
+                   .arg("-ldl")
+                   .arg("-lstdc++");
+            }
+        }
+
+        // N-API addons are dlopen()ed at runtime and resolve napi_* against the executable, as
+        // hot reload patches resolve the program's functions and module variables
+        if ctx.needs_napi || args.hot_reload.is_some() {
+            match target.os {
+                Os::Linux => cmd.arg("-Wl,--export-dynamic").arg("-ldl"),
+                Os::MacOs => cmd.arg("-Wl,-export_dynamic"),
+                // The runtime can't load addons there (see napi.rs); WASI never gets here
+                Os::Windows | Os::Wasi => &mut cmd,
+            };
         }
-    }
 
-    // Link perry/ui library and platform frameworks if needed
-    if ctx.needs_ui {
-        if let Some(ui_lib) = find_ui_library() {
-            cmd.arg(&ui_lib);
+        // Link perry/ui library and platform frameworks if needed
+        if ctx.needs_ui {
+            if let Some(ui_lib) = find_ui_library(&target) {
+                cmd.arg(&ui_lib);
 
-            #[cfg(target_os = "macos")]
-            {
-                cmd.arg("-framework").arg("AppKit");
-            }
+                if target.os == Os::MacOs {
+                    cmd.arg("-framework").arg("AppKit");
+                }
 
-            match format {
-                OutputFormat::Text => println!("Linking perry/ui (native UI)"),
-                OutputFormat::Json => {}
+                match format {
+                    OutputFormat::Text => println!("Linking perry/ui (native UI)"),
+                    OutputFormat::Json => {}
+                }
+            } else {
+                return Err(anyhow!(
+                    "perry/ui imported but {} not found. Build with: cargo build --release -p perry-ui-macos",
+                    target.static_library("perry_ui_macos")
+                ));
             }
-        } else {
-            return Err(anyhow!(
-                "perry/ui imported but libperry_ui_macos.a not found. Build with: cargo build --release -p perry-ui-macos"
-            ));
         }
-    }
-
-    let status = cmd.status()?;
 
-    if !status.success() {
-        return Err(anyhow!("Linking failed"));
+        let link_started = Instant::now();
+        progress::phase("link");
+        let status = cmd.status().map_err(|e| match &target.triple {
+            Some(triple) => anyhow!(
+                "Could not run the linker for {} ({}). Install a cross toolchain, or set {} to a linker command such as `zig cc -target ...`",
+                triple, e, super::target::LINKER_ENV
+            ),
+            None => e.into(),
+        })?;
+        telemetry::phase("link", link_started);
+
+        if !status.success() {
+            return Err(anyhow!("Linking failed"));
+        }
     }
 
     match format {
         OutputFormat::Text => println!("Wrote executable: {}", exe_path.display()),
         OutputFormat::Json => {
+            // Modules the executable was built from; `perry run` checks them before reusing it
+            let js_paths = ctx.js_modules.values().map(|module| &module.path);
+            let mut inputs: Vec<_> = ctx.native_modules.keys().chain(js_paths).map(|path| path.to_string_lossy()).collect();
+            inputs.sort();
             let result = serde_json::json!({
                 "success": true,
                 "output": exe_path.to_string_lossy(),
                 "native_modules": ctx.native_modules.len(),
                 "js_modules": ctx.js_modules.len(),
+                "inputs": inputs,
+                "audit": audit_findings.map(|findings| findings.iter().map(Finding::to_json).collect::<Vec<_>>()),
             });
             println!("{}", serde_json::to_string(&result)?);
         }
     }
 
-    if !args.keep_intermediates {
+    // The linker leaves debug info in the object files on macOS; gather it before they go
+    #[cfg(target_os = "macos")]
+    if args.debug && target.os == Os::MacOs {
+        let dsym = Command::new("dsymutil").arg(&exe_path).status();
+        if !matches!(dsym, Ok(status) if status.success()) {
+            eprintln!("Warning: dsymutil failed; no .dSYM bundle was written");
+        }
+    }
+
+    // perry dev links hot reload patches from the object files
+    if !args.keep_intermediates && args.hot_reload.is_none() {
         for obj_path in &obj_paths {
             let _ = fs::remove_file(obj_path);
         }
@@ -0,0 +1,810 @@
+//! Support coverage report (`perry check --coverage`)
+//!
+//! Counts, per file, the language constructs and imports that matter for native
+//! compilation and how far each is supported: compiled natively, partially supported,
+//! run by the V8 fallback (`--enable-js-runtime`), or not supported. Constructs every
+//! program uses (identifiers, literals, calls, operators) are not counted, so the
+//! percentages describe the parts of a file that can actually block a migration.
+
+use perry_parser::swc_ecma_ast::*;
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use super::deps::is_node_builtin;
+
+/// How far Perry supports a construct or import
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SupportLevel {
+    /// Compiled to native code
+    Native,
+    /// Compiled, but only common forms behave as in Node (e.g. basic decorators)
+    Partial,
+    /// Runs in the embedded V8 runtime (needs `--enable-js-runtime`)
+    JsFallback,
+    /// Rejected or silently dropped by the compiler
+    Unsupported,
+}
+
+impl SupportLevel {
+    const ALL: [SupportLevel; 4] = [
+        SupportLevel::Native,
+        SupportLevel::Partial,
+        SupportLevel::JsFallback,
+        SupportLevel::Unsupported,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SupportLevel::Native => "native",
+            SupportLevel::Partial => "partial",
+            SupportLevel::JsFallback => "v8-fallback",
+            SupportLevel::Unsupported => "unsupported",
+        }
+    }
+}
+
+/// Support level of an import specifier
+pub fn classify_import(source: &str) -> SupportLevel {
+    if perry_hir::is_native_module(source) {
+        SupportLevel::Native
+    } else if source.starts_with('.') || source.starts_with('/') {
+        // Project files: TypeScript is compiled, plain JavaScript goes to V8
+        let is_js = Path::new(source)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map_or(false, |ext| matches!(ext, "js" | "mjs" | "cjs"));
+        if is_js {
+            SupportLevel::JsFallback
+        } else {
+            SupportLevel::Native
+        }
+    } else if is_node_builtin(source) {
+        SupportLevel::Unsupported
+    } else {
+        SupportLevel::JsFallback
+    }
+}
+
+/// Construct and import counts of one file
+#[derive(Debug, Clone)]
+pub struct FileCoverage {
+    pub path: PathBuf,
+    /// Construct name -> (support level, occurrences)
+    pub constructs: BTreeMap<&'static str, (SupportLevel, usize)>,
+    /// Import specifiers in source order (each counted once)
+    pub imports: Vec<(String, SupportLevel)>,
+}
+
+impl FileCoverage {
+    /// Walk a parsed module and count its constructs and imports
+    pub fn analyze(path: &Path, module: &Module) -> Self {
+        let mut coverage = FileCoverage {
+            path: path.to_path_buf(),
+            constructs: BTreeMap::new(),
+            imports: Vec::new(),
+        };
+        for item in &module.body {
+            match item {
+                ModuleItem::Stmt(stmt) => coverage.visit_stmt(stmt),
+                ModuleItem::ModuleDecl(decl) => coverage.visit_module_decl(decl),
+            }
+        }
+        coverage
+    }
+
+    /// Number of counted items (construct occurrences and imports) at each level
+    pub fn counts(&self) -> [usize; 4] {
+        let mut counts = [0; 4];
+        for (level, count) in self.constructs.values() {
+            counts[*level as usize] += count;
+        }
+        for (_, level) in &self.imports {
+            counts[*level as usize] += 1;
+        }
+        counts
+    }
+
+    fn record(&mut self, construct: &'static str, level: SupportLevel) {
+        self.constructs.entry(construct).or_insert((level, 0)).1 += 1;
+    }
+
+    fn record_import(&mut self, source: &str) {
+        if !self.imports.iter().any(|(s, _)| s == source) {
+            self.imports.push((source.to_string(), classify_import(source)));
+        }
+    }
+
+    // ---- Walking ----
+
+    fn visit_module_decl(&mut self, decl: &ModuleDecl) {
+        match decl {
+            ModuleDecl::Import(import) => {
+                if !import.type_only {
+                    self.record_import(import.src.value.as_str().unwrap_or(""));
+                }
+            }
+            ModuleDecl::ExportDecl(export) => self.visit_decl(&export.decl),
+            ModuleDecl::ExportNamed(export) => {
+                if let Some(src) = &export.src {
+                    self.record_import(src.value.as_str().unwrap_or(""));
+                }
+            }
+            ModuleDecl::ExportAll(export) => self.record_import(export.src.value.as_str().unwrap_or("")),
+            ModuleDecl::ExportDefaultDecl(export) => match &export.decl {
+                DefaultDecl::Fn(f) => self.visit_function(&f.function),
+                DefaultDecl::Class(c) => self.visit_class(&c.class),
+                DefaultDecl::TsInterfaceDecl(_) => {}
+            },
+            ModuleDecl::ExportDefaultExpr(export) => self.visit_expr(&export.expr),
+            _ => {}
+        }
+    }
+
+    fn visit_stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Block(block) => self.visit_stmts(&block.stmts),
+            Stmt::Decl(decl) => self.visit_decl(decl),
+            Stmt::Expr(s) => self.visit_expr(&s.expr),
+            Stmt::Return(s) => {
+                if let Some(arg) = &s.arg {
+                    self.visit_expr(arg);
+                }
+            }
+            Stmt::Throw(s) => self.visit_expr(&s.arg),
+            Stmt::If(s) => {
+                self.visit_expr(&s.test);
+                self.visit_stmt(&s.cons);
+                if let Some(alt) = &s.alt {
+                    self.visit_stmt(alt);
+                }
+            }
+            Stmt::While(s) => {
+                self.visit_expr(&s.test);
+                self.visit_stmt(&s.body);
+            }
+            Stmt::DoWhile(s) => {
+                self.record("do-while loop", SupportLevel::Unsupported);
+                self.visit_stmt(&s.body);
+                self.visit_expr(&s.test);
+            }
+            Stmt::For(s) => {
+                match &s.init {
+                    Some(VarDeclOrExpr::VarDecl(var)) => self.visit_var_decl(var),
+                    Some(VarDeclOrExpr::Expr(expr)) => self.visit_expr(expr),
+                    None => {}
+                }
+                for expr in s.test.iter().chain(s.update.iter()) {
+                    self.visit_expr(expr);
+                }
+                self.visit_stmt(&s.body);
+            }
+            Stmt::ForIn(s) => {
+                self.record("for-in loop", SupportLevel::Native);
+                self.visit_for_head(&s.left);
+                self.visit_expr(&s.right);
+                self.visit_stmt(&s.body);
+            }
+            Stmt::ForOf(s) => {
+                if s.is_await {
+                    self.record("for-await-of loop", SupportLevel::Unsupported);
+                } else {
+                    self.record("for-of loop", SupportLevel::Native);
+                }
+                self.visit_for_head(&s.left);
+                self.visit_expr(&s.right);
+                self.visit_stmt(&s.body);
+            }
+            Stmt::Switch(s) => {
+                self.record("switch", SupportLevel::Native);
+                self.visit_expr(&s.discriminant);
+                for case in &s.cases {
+                    if let Some(test) = &case.test {
+                        self.visit_expr(test);
+                    }
+                    self.visit_stmts(&case.cons);
+                }
+            }
+            Stmt::Try(s) => {
+                self.record("try/catch/finally", SupportLevel::Native);
+                self.visit_stmts(&s.block.stmts);
+                if let Some(handler) = &s.handler {
+                    if let Some(param) = &handler.param {
+                        self.visit_pat(param);
+                    }
+                    self.visit_stmts(&handler.body.stmts);
+                }
+                if let Some(finalizer) = &s.finalizer {
+                    self.visit_stmts(&finalizer.stmts);
+                }
+            }
+            Stmt::Labeled(s) => {
+                self.record("labeled statement", SupportLevel::Unsupported);
+                self.visit_stmt(&s.body);
+            }
+            Stmt::With(s) => {
+                self.record("with statement", SupportLevel::Unsupported);
+                self.visit_expr(&s.obj);
+                self.visit_stmt(&s.body);
+            }
+            _ => {}
+        }
+    }
+
+    fn visit_stmts(&mut self, stmts: &[Stmt]) {
+        for stmt in stmts {
+            self.visit_stmt(stmt);
+        }
+    }
+
+    fn visit_for_head(&mut self, head: &ForHead) {
+        match head {
+            ForHead::VarDecl(var) => self.visit_var_decl(var),
+            ForHead::UsingDecl(using) => {
+                self.record("using in a loop head", SupportLevel::Unsupported);
+                for decl in &using.decls {
+                    self.visit_pat(&decl.name);
+                }
+            }
+            ForHead::Pat(pat) => self.visit_pat(pat),
+        }
+    }
+
+    fn visit_decl(&mut self, decl: &Decl) {
+        match decl {
+            Decl::Var(var) => self.visit_var_decl(var),
+            Decl::Using(using) => {
+                self.record("using declaration", SupportLevel::Native);
+                for declarator in &using.decls {
+                    self.visit_declarator(declarator);
+                }
+            }
+            Decl::Fn(f) => self.visit_function(&f.function),
+            Decl::Class(c) => self.visit_class(&c.class),
+            Decl::TsEnum(_) => self.record("enum", SupportLevel::Native),
+            Decl::TsModule(_) => self.record("namespace", SupportLevel::Unsupported),
+            Decl::TsInterface(_) | Decl::TsTypeAlias(_) => {}
+        }
+    }
+
+    fn visit_var_decl(&mut self, var: &VarDecl) {
+        for declarator in &var.decls {
+            self.visit_declarator(declarator);
+        }
+    }
+
+    fn visit_declarator(&mut self, declarator: &VarDeclarator) {
+        self.visit_pat(&declarator.name);
+        if let Some(init) = &declarator.init {
+            // const x = require('pkg') is an import
+            if let Some(source) = require_source(init) {
+                self.record_import(source);
+                return;
+            }
+            self.visit_expr(init);
+        }
+    }
+
+    fn visit_function(&mut self, function: &Function) {
+        if function.is_generator {
+            self.record("generator function", SupportLevel::Unsupported);
+        } else if function.is_async {
+            self.record("async function", SupportLevel::Native);
+        } else {
+            self.record("function", SupportLevel::Native);
+        }
+        for decorator in function.params.iter().flat_map(|p| &p.decorators) {
+            self.visit_decorator(decorator);
+        }
+        for param in &function.params {
+            self.visit_pat(&param.pat);
+        }
+        if let Some(ann) = &function.return_type {
+            self.visit_type(&ann.type_ann);
+        }
+        if let Some(body) = &function.body {
+            self.visit_stmts(&body.stmts);
+        }
+    }
+
+    fn visit_class(&mut self, class: &Class) {
+        self.record("class", SupportLevel::Native);
+        for decorator in &class.decorators {
+            self.visit_decorator(decorator);
+        }
+        if let Some(super_class) = &class.super_class {
+            self.visit_expr(super_class);
+        }
+        for member in &class.body {
+            match member {
+                ClassMember::Constructor(ctor) => {
+                    for param in &ctor.params {
+                        match param {
+                            ParamOrTsParamProp::Param(param) => self.visit_pat(&param.pat),
+                            ParamOrTsParamProp::TsParamProp(prop) => {
+                                if let TsParamPropParam::Ident(ident) = &prop.param {
+                                    self.visit_binding_type(ident);
+                                }
+                            }
+                        }
+                    }
+                    if let Some(body) = &ctor.body {
+                        self.visit_stmts(&body.stmts);
+                    }
+                }
+                ClassMember::Method(method) => {
+                    for decorator in &method.function.decorators {
+                        self.visit_decorator(decorator);
+                    }
+                    match method.kind {
+                        MethodKind::Getter | MethodKind::Setter => {
+                            self.record("getter/setter", SupportLevel::Native);
+                            if let Some(body) = &method.function.body {
+                                self.visit_stmts(&body.stmts);
+                            }
+                        }
+                        MethodKind::Method => self.visit_function(&method.function),
+                    }
+                }
+                ClassMember::PrivateMethod(method) => {
+                    self.record("private member (#name)", SupportLevel::Native);
+                    self.visit_function(&method.function);
+                }
+                ClassMember::ClassProp(prop) => {
+                    for decorator in &prop.decorators {
+                        self.visit_decorator(decorator);
+                    }
+                    if let Some(ann) = &prop.type_ann {
+                        self.visit_type(&ann.type_ann);
+                    }
+                    if let Some(value) = &prop.value {
+                        self.visit_expr(value);
+                    }
+                }
+                ClassMember::PrivateProp(prop) => {
+                    self.record("private member (#name)", SupportLevel::Native);
+                    if let Some(value) = &prop.value {
+                        self.visit_expr(value);
+                    }
+                }
+                ClassMember::StaticBlock(block) => {
+                    self.record("static block", SupportLevel::Unsupported);
+                    self.visit_stmts(&block.body.stmts);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn visit_decorator(&mut self, decorator: &Decorator) {
+        self.record("decorator", SupportLevel::Partial);
+        self.visit_expr(&decorator.expr);
+    }
+
+    fn visit_pat(&mut self, pat: &Pat) {
+        match pat {
+            Pat::Ident(binding) => self.visit_binding_type(binding),
+            Pat::Array(arr) => {
+                self.record("destructuring", SupportLevel::Native);
+                for elem in arr.elems.iter().flatten() {
+                    self.visit_pat(elem);
+                }
+            }
+            Pat::Object(obj) => {
+                self.record("destructuring", SupportLevel::Native);
+                for prop in &obj.props {
+                    match prop {
+                        ObjectPatProp::KeyValue(kv) => self.visit_pat(&kv.value),
+                        ObjectPatProp::Assign(assign) => {
+                            if let Some(value) = &assign.value {
+                                self.visit_expr(value);
+                            }
+                        }
+                        ObjectPatProp::Rest(rest) => self.visit_pat(&rest.arg),
+                    }
+                }
+            }
+            Pat::Rest(rest) => {
+                self.record("rest parameter/element", SupportLevel::Native);
+                self.visit_pat(&rest.arg);
+            }
+            Pat::Assign(assign) => {
+                self.record("default value", SupportLevel::Native);
+                self.visit_pat(&assign.left);
+                self.visit_expr(&assign.right);
+            }
+            Pat::Expr(expr) => self.visit_expr(expr),
+            Pat::Invalid(_) => {}
+        }
+    }
+
+    fn visit_binding_type(&mut self, binding: &BindingIdent) {
+        if let Some(ann) = &binding.type_ann {
+            self.visit_type(&ann.type_ann);
+        }
+    }
+
+    /// `any` compiles, but values of unknown type take the slow dynamic paths
+    fn visit_type(&mut self, ty: &TsType) {
+        match ty {
+            TsType::TsKeywordType(keyword) if keyword.kind == TsKeywordTypeKind::TsAnyKeyword => {
+                self.record("any type", SupportLevel::Partial);
+            }
+            TsType::TsArrayType(arr) => self.visit_type(&arr.elem_type),
+            TsType::TsParenthesizedType(p) => self.visit_type(&p.type_ann),
+            TsType::TsTypeOperator(op) => self.visit_type(&op.type_ann),
+            TsType::TsUnionOrIntersectionType(TsUnionOrIntersectionType::TsUnionType(u)) => {
+                for member in &u.types {
+                    self.visit_type(member);
+                }
+            }
+            TsType::TsTypeRef(r) => {
+                for param in r.type_params.iter().flat_map(|p| &p.params) {
+                    self.visit_type(param);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Arrow(arrow) => {
+                self.record(if arrow.is_async { "async arrow function" } else { "arrow function" }, SupportLevel::Native);
+                for param in &arrow.params {
+                    self.visit_pat(param);
+                }
+                match &*arrow.body {
+                    BlockStmtOrExpr::BlockStmt(block) => self.visit_stmts(&block.stmts),
+                    BlockStmtOrExpr::Expr(body) => self.visit_expr(body),
+                }
+            }
+            Expr::Fn(f) => self.visit_function(&f.function),
+            Expr::Class(c) => {
+                self.record("class expression", SupportLevel::Unsupported);
+                for member in &c.class.body {
+                    if let ClassMember::Method(method) = member {
+                        if let Some(body) = &method.function.body {
+                            self.visit_stmts(&body.stmts);
+                        }
+                    }
+                }
+            }
+            Expr::Call(call) => {
+                match &call.callee {
+                    Callee::Import(_) => self.record("dynamic import()", SupportLevel::Unsupported),
+                    Callee::Expr(callee) => {
+                        if let Some(source) = require_source(expr) {
+                            self.record_import(source);
+                            return;
+                        }
+                        if matches!(&**callee, Expr::Ident(ident) if ident.sym.as_ref() == "eval") {
+                            self.record("eval()", SupportLevel::Unsupported);
+                        }
+                        self.visit_expr(callee);
+                    }
+                    Callee::Super(_) => {}
+                }
+                for arg in &call.args {
+                    self.visit_arg(arg);
+                }
+            }
+            Expr::New(new) => {
+                if matches!(&*new.callee, Expr::Ident(ident) if ident.sym.as_ref() == "Function") {
+                    self.record("new Function()", SupportLevel::Unsupported);
+                }
+                self.visit_expr(&new.callee);
+                for arg in new.args.iter().flatten() {
+                    self.visit_arg(arg);
+                }
+            }
+            Expr::Await(a) => {
+                self.record("await", SupportLevel::Native);
+                self.visit_expr(&a.arg);
+            }
+            Expr::Yield(y) => {
+                self.record("yield", SupportLevel::Unsupported);
+                if let Some(arg) = &y.arg {
+                    self.visit_expr(arg);
+                }
+            }
+            Expr::Tpl(tpl) => {
+                self.record("template literal", SupportLevel::Native);
+                for e in &tpl.exprs {
+                    self.visit_expr(e);
+                }
+            }
+            Expr::TaggedTpl(tagged) => {
+                self.record("tagged template", SupportLevel::Unsupported);
+                for e in &tagged.tpl.exprs {
+                    self.visit_expr(e);
+                }
+            }
+            Expr::OptChain(chain) => {
+                self.record("optional chaining", SupportLevel::Native);
+                match &*chain.base {
+                    OptChainBase::Member(member) => self.visit_expr(&member.obj),
+                    OptChainBase::Call(call) => {
+                        self.visit_expr(&call.callee);
+                        for arg in &call.args {
+                            self.visit_arg(arg);
+                        }
+                    }
+                }
+            }
+            Expr::Lit(Lit::Regex(_)) => self.record("regular expression", SupportLevel::Partial),
+            Expr::Bin(bin) => {
+                if bin.op == BinaryOp::NullishCoalescing {
+                    self.record("nullish coalescing", SupportLevel::Native);
+                }
+                self.visit_expr(&bin.left);
+                self.visit_expr(&bin.right);
+            }
+            Expr::Unary(unary) => {
+                match unary.op {
+                    UnaryOp::Void => self.record("void operator", SupportLevel::Unsupported),
+                    UnaryOp::Delete => self.record("delete operator", SupportLevel::Partial),
+                    _ => {}
+                }
+                self.visit_expr(&unary.arg);
+            }
+            Expr::Update(update) => self.visit_expr(&update.arg),
+            Expr::Assign(assign) => {
+                if let AssignTarget::Pat(_) = &assign.left {
+                    self.record("destructuring", SupportLevel::Native);
+                }
+                self.visit_expr(&assign.right);
+            }
+            Expr::Member(member) => {
+                if let MemberProp::PrivateName(_) = &member.prop {
+                    self.record("private member (#name)", SupportLevel::Native);
+                }
+                self.visit_expr(&member.obj);
+                if let MemberProp::Computed(computed) = &member.prop {
+                    self.visit_expr(&computed.expr);
+                }
+            }
+            Expr::Cond(c) => {
+                self.visit_expr(&c.test);
+                self.visit_expr(&c.cons);
+                self.visit_expr(&c.alt);
+            }
+            Expr::Seq(seq) => {
+                for e in &seq.exprs {
+                    self.visit_expr(e);
+                }
+            }
+            Expr::Paren(p) => self.visit_expr(&p.expr),
+            Expr::Array(arr) => {
+                for elem in arr.elems.iter().flatten() {
+                    if elem.spread.is_some() {
+                        self.record("spread", SupportLevel::Native);
+                    }
+                    self.visit_expr(&elem.expr);
+                }
+            }
+            Expr::Object(obj) => {
+                for prop in &obj.props {
+                    match prop {
+                        PropOrSpread::Spread(spread) => {
+                            self.record("spread", SupportLevel::Native);
+                            self.visit_expr(&spread.expr);
+                        }
+                        PropOrSpread::Prop(prop) => match &**prop {
+                            Prop::KeyValue(kv) => self.visit_expr(&kv.value),
+                            Prop::Method(m) => self.visit_function(&m.function),
+                            Prop::Getter(getter) => {
+                                self.record("getter/setter", SupportLevel::Native);
+                                if let Some(body) = &getter.body {
+                                    self.visit_stmts(&body.stmts);
+                                }
+                            }
+                            Prop::Setter(setter) => {
+                                self.record("getter/setter", SupportLevel::Native);
+                                if let Some(body) = &setter.body {
+                                    self.visit_stmts(&body.stmts);
+                                }
+                            }
+                            _ => {}
+                        },
+                    }
+                }
+            }
+            Expr::TsAs(a) => {
+                self.visit_type(&a.type_ann);
+                self.visit_expr(&a.expr);
+            }
+            Expr::TsNonNull(n) => self.visit_expr(&n.expr),
+            Expr::TsSatisfies(s) => self.visit_expr(&s.expr),
+            Expr::TsConstAssertion(c) => self.visit_expr(&c.expr),
+            Expr::TsTypeAssertion(t) => self.visit_expr(&t.expr),
+            _ => {}
+        }
+    }
+
+    fn visit_arg(&mut self, arg: &ExprOrSpread) {
+        if arg.spread.is_some() {
+            self.record("spread", SupportLevel::Native);
+        }
+        self.visit_expr(&arg.expr);
+    }
+}
+
+/// `require('x')` with a string literal argument
+fn require_source(expr: &Expr) -> Option<&str> {
+    let Expr::Call(call) = expr else { return None };
+    let Callee::Expr(callee) = &call.callee else { return None };
+    let Expr::Ident(ident) = &**callee else { return None };
+    if ident.sym.as_ref() != "require" || call.args.len() != 1 {
+        return None;
+    }
+    match &*call.args[0].expr {
+        Expr::Lit(Lit::Str(s)) => s.value.as_str(),
+        _ => None,
+    }
+}
+
+fn percent(count: usize, total: usize) -> f64 {
+    if total == 0 {
+        return 0.0;
+    }
+    (count as f64 * 1000.0 / total as f64).round() / 10.0
+}
+
+fn counts_json(counts: [usize; 4]) -> serde_json::Value {
+    let total: usize = counts.iter().sum();
+    let mut levels = serde_json::Map::new();
+    levels.insert("total".to_string(), json!(total));
+    for level in SupportLevel::ALL {
+        let count = counts[level as usize];
+        levels.insert(level.as_str().to_string(), json!({ "count": count, "percent": percent(count, total) }));
+    }
+    serde_json::Value::Object(levels)
+}
+
+/// Coverage of all checked files
+#[derive(Debug, Default)]
+pub struct CoverageReport {
+    pub files: Vec<FileCoverage>,
+}
+
+impl CoverageReport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, file: FileCoverage) {
+        self.files.push(file);
+    }
+
+    /// Item counts at each level over all files
+    pub fn totals(&self) -> [usize; 4] {
+        let mut totals = [0; 4];
+        for file in &self.files {
+            for (total, count) in totals.iter_mut().zip(file.counts()) {
+                *total += count;
+            }
+        }
+        totals
+    }
+
+    /// Machine-readable report (one JSON object, `"type": "coverage"`)
+    pub fn to_json(&self, project_root: &Path) -> serde_json::Value {
+        let files: Vec<_> = self.files.iter().map(|file| {
+            let constructs: Vec<_> = file.constructs.iter()
+                .map(|(name, (level, count))| json!({ "construct": name, "support": level.as_str(), "count": count }))
+                .collect();
+            let imports: Vec<_> = file.imports.iter()
+                .map(|(source, level)| json!({ "source": source, "support": level.as_str() }))
+                .collect();
+            json!({
+                "file": file.path.strip_prefix(project_root).unwrap_or(&file.path).to_string_lossy(),
+                "summary": counts_json(file.counts()),
+                "constructs": constructs,
+                "imports": imports,
+            })
+        }).collect();
+        json!({
+            "type": "coverage",
+            "files": files,
+            "summary": counts_json(self.totals()),
+        })
+    }
+
+    /// Human-readable report: one line per file plus everything that is not native
+    pub fn to_text(&self, project_root: &Path) -> String {
+        let mut out = String::from("Coverage report\n");
+        for file in &self.files {
+            let counts = file.counts();
+            let total: usize = counts.iter().sum();
+            out.push_str(&format!(
+                "  {}: {:.1}% native ({} native, {} partial, {} v8-fallback, {} unsupported)\n",
+                file.path.strip_prefix(project_root).unwrap_or(&file.path).display(),
+                percent(counts[SupportLevel::Native as usize], total),
+                counts[0], counts[1], counts[2], counts[3],
+            ));
+            for level in &SupportLevel::ALL[1..] {
+                for (name, (construct_level, count)) in &file.constructs {
+                    if construct_level == level {
+                        out.push_str(&format!("      {}: {} (x{})\n", level.as_str(), name, count));
+                    }
+                }
+                for (source, import_level) in &file.imports {
+                    if import_level == level {
+                        out.push_str(&format!("      {}: import '{}'\n", level.as_str(), source));
+                    }
+                }
+            }
+        }
+        let totals = self.totals();
+        let total: usize = totals.iter().sum();
+        out.push_str(&format!(
+            "Total: {:.1}% native, {:.1}% partial, {:.1}% v8-fallback, {:.1}% unsupported ({} item(s) in {} file(s))\n",
+            percent(totals[0], total),
+            percent(totals[1], total),
+            percent(totals[2], total),
+            percent(totals[3], total),
+            total,
+            self.files.len(),
+        ));
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use perry_diagnostics::SourceCache;
+
+    fn analyze(source: &str) -> FileCoverage {
+        let mut cache = SourceCache::new();
+        let result = perry_parser::parse_typescript_with_cache(source, "test.ts", &mut cache)
+            .expect("Parse failed");
+        FileCoverage::analyze(Path::new("test.ts"), &result.module)
+    }
+
+    #[test]
+    fn test_imports_classified() {
+        assert_eq!(classify_import("pg"), SupportLevel::Native);
+        assert_eq!(classify_import("node:fs"), SupportLevel::Native);
+        assert_eq!(classify_import("./util"), SupportLevel::Native);
+        assert_eq!(classify_import("./legacy.js"), SupportLevel::JsFallback);
+        assert_eq!(classify_import("lodash"), SupportLevel::JsFallback);
+        assert_eq!(classify_import("dns"), SupportLevel::Unsupported);
+    }
+
+    #[test]
+    fn test_constructs_counted() {
+        let coverage = analyze(
+            "import _ from 'lodash';\n\
+             const { a, b } = require('./cfg');\n\
+             function* gen() { yield 1; }\n\
+             async function main(x: any) {\n\
+               for (const v of [1, 2]) { await f(v); }\n\
+               do { x--; } while (x > 0);\n\
+             }\n",
+        );
+        assert_eq!(coverage.constructs["generator function"], (SupportLevel::Unsupported, 1));
+        assert_eq!(coverage.constructs["yield"], (SupportLevel::Unsupported, 1));
+        assert_eq!(coverage.constructs["do-while loop"], (SupportLevel::Unsupported, 1));
+        assert_eq!(coverage.constructs["any type"], (SupportLevel::Partial, 1));
+        assert_eq!(coverage.constructs["for-of loop"], (SupportLevel::Native, 1));
+        assert_eq!(coverage.imports, vec![
+            ("lodash".to_string(), SupportLevel::JsFallback),
+            ("./cfg".to_string(), SupportLevel::Native),
+        ]);
+        // async function, for-of, await, destructuring + ./cfg are native
+        assert_eq!(coverage.counts(), [5, 1, 1, 3]);
+    }
+
+    #[test]
+    fn test_report_json_percentages() {
+        let mut report = CoverageReport::new();
+        report.add(analyze("import _ from 'lodash';\nconst f = () => 1;\n"));
+        let json = report.to_json(Path::new("."));
+        assert_eq!(json["summary"]["total"], 2);
+        assert_eq!(json["summary"]["native"]["percent"], 50.0);
+        assert_eq!(json["summary"]["v8-fallback"]["count"], 1);
+        assert_eq!(json["files"][0]["imports"][0]["support"], "v8-fallback");
+    }
+}
@@ -0,0 +1,60 @@
+//! Demangle command - turn perry symbol names back into TypeScript names
+//!
+//! `perry demangle <symbol>...` decodes the given symbols; without arguments it copies
+//! stdin to stdout with every symbol in it demangled, so it works as a filter for tools
+//! that print symbol names: `perf report --stdio | perry demangle`.
+
+use anyhow::Result;
+use clap::Args;
+use perry_runtime::symbols::{demangle_text, Symbol};
+use std::io::{self, BufRead, Write};
+
+use crate::OutputFormat;
+
+#[derive(Args, Debug)]
+pub struct DemangleArgs {
+    /// Symbols to demangle (reads stdin when omitted)
+    pub symbols: Vec<String>,
+}
+
+pub fn run(args: DemangleArgs, format: OutputFormat, use_color: bool) -> Result<()> {
+    if args.symbols.is_empty() {
+        let stdout = io::stdout();
+        let mut out = stdout.lock();
+        for line in io::stdin().lock().lines() {
+            writeln!(out, "{}", demangle_text(&line?))?;
+        }
+        return Ok(());
+    }
+
+    match format {
+        OutputFormat::Text => {
+            for symbol in &args.symbols {
+                match Symbol::demangle(symbol) {
+                    Some(demangled) if use_color => println!("{}", console::style(demangled).cyan()),
+                    Some(demangled) => println!("{}", demangled),
+                    // Not a perry symbol: print it unchanged, like c++filt
+                    None => println!("{}", symbol),
+                }
+            }
+        }
+        OutputFormat::Json => {
+            let output: Vec<_> = args.symbols.iter()
+                .map(|symbol| match Symbol::demangle(symbol) {
+                    Some(demangled) => serde_json::json!({
+                        "symbol": symbol,
+                        "demangled": demangled.to_string(),
+                        "kind": format!("{:?}", demangled.kind),
+                        "module": demangled.module,
+                        "path": demangled.path,
+                        "type_args": demangled.type_args,
+                    }),
+                    None => serde_json::json!({ "symbol": symbol, "demangled": null }),
+                })
+                .collect();
+            println!("{}", serde_json::to_string_pretty(&output)?);
+        }
+    }
+
+    Ok(())
+}
@@ -205,7 +205,7 @@ impl DependencyResolver {
 }
 
 /// Check if an import is a Node.js built-in module
-fn is_node_builtin(name: &str) -> bool {
+pub fn is_node_builtin(name: &str) -> bool {
     let builtins = [
         "assert", "buffer", "child_process", "cluster", "console", "constants",
         "crypto", "dgram", "dns", "domain", "events", "fs", "http", "https",
@@ -327,7 +327,7 @@ fn extract_version(content: &str) -> Option<String> {
 }
 
 /// Scan source code for compatibility issues using pattern matching
-fn scan_source_for_issues(path: &Path, source: &str) -> Vec<CompatibilityIssue> {
+pub(super) fn scan_source_for_issues(path: &Path, source: &str) -> Vec<CompatibilityIssue> {
     let mut issues = Vec::new();
 
     for (line_num, line) in source.lines().enumerate() {
@@ -0,0 +1,342 @@
+//! Dev command - rebuild a program whenever one of its sources changes and swap the
+//! recompiled functions into the running process
+//!
+//! `perry dev server.ts` compiles the program, starts it, and watches every file the build
+//! read: the `inputs` of its build graph (see `build_graph`), so a newly imported module is
+//! watched from the next build on. When one changes, perry recompiles (with the HIR and
+//! object caches only the edited modules are lowered and compiled again). If the edits
+//! stay inside function bodies, the recompiled modules are linked into a shared object
+//! that the running process loads as a patch: calls to their functions run the new code
+//! from then on, while module variables, open connections and other in-memory state are
+//! kept (see `hot_reload` and perry_runtime's `hot_reload`). Any other edit, such as a new
+//! function or module variable, a changed signature, class layout or top-level statement,
+//! replaces the running process with the new build, as does a patch the process can't
+//! apply; on Windows every reload is a restart. An edit that doesn't compile leaves the
+//! running process alone, so the server stays up while the error is fixed.
+//!
+//! Files are polled rather than watched with OS notifications.
+
+use anyhow::{anyhow, Result};
+use clap::Args;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command};
+use std::time::{Duration, Instant, SystemTime};
+
+use super::hir_cache::CACHE_DIR;
+use super::hot_reload::Manifest;
+use super::target::Target;
+use crate::OutputFormat;
+
+/// How long to wait after a change for the editor to finish writing related files
+const SETTLE: Duration = Duration::from_millis(100);
+
+/// Whether builds can take patches: the agent in the program listens on a Unix socket
+/// and loads them with dlopen
+const HOT_RELOAD: bool = cfg!(unix);
+
+/// How long to wait for the program to apply a patch; it applies patches between event
+/// loop tasks and gives up after two seconds
+const REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Args, Debug)]
+pub struct DevArgs {
+    /// Entry point of the program
+    pub entry: PathBuf,
+
+    /// Embed V8 for JavaScript module support
+    #[arg(long)]
+    pub enable_js_runtime: bool,
+
+    /// How often to check the sources for changes, in milliseconds
+    #[arg(long, default_value_t = 300, value_name = "MS")]
+    pub poll_interval: u64,
+
+    /// Arguments passed through to the program
+    #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+    pub args: Vec<String>,
+}
+
+/// Modification times of the watched files; a file that can't be read has none
+#[derive(Debug)]
+struct Snapshot(Vec<(PathBuf, Option<SystemTime>)>);
+
+impl Snapshot {
+    fn take(paths: &[PathBuf]) -> Self {
+        Snapshot(paths.iter().map(|path| (path.clone(), modified(path))).collect())
+    }
+
+    /// Files whose modification time differs from the snapshot's
+    fn changed(&self) -> Vec<&Path> {
+        self.0.iter()
+            .filter(|(path, mtime)| modified(path) != *mtime)
+            .map(|(path, _)| path.as_path())
+            .collect()
+    }
+
+    /// Files written after `time`, e.g. edited while the build that read them ran
+    fn modified_since(&self, time: SystemTime) -> Vec<&Path> {
+        self.0.iter()
+            .filter(|(_, mtime)| mtime.is_some_and(|mtime| mtime > time))
+            .map(|(path, _)| path.as_path())
+            .collect()
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+/// The files a build read, from its build graph
+fn watched_files(graph: &serde_json::Value) -> Option<Vec<PathBuf>> {
+    let root = PathBuf::from(graph["project_root"].as_str()?);
+    let inputs = graph["inputs"].as_array()?;
+    // Inputs inside the project are relative to it; joining leaves absolute ones as they are
+    Some(inputs.iter().filter_map(|input| input.as_str()).map(|input| root.join(input)).collect())
+}
+
+/// Compile the program into `exe` by invoking this perry binary's `compile` command,
+/// returning the files the build read. Compiler output is only shown when it fails.
+/// With a `manifest` path the build is one the running program can take patches from.
+fn build(args: &DevArgs, entry: &Path, dir: &Path, exe: &Path, manifest: Option<&Path>) -> Result<Vec<PathBuf>> {
+    let graph_path = dir.join("build-graph.json");
+    let mut command = Command::new(std::env::current_exe()?);
+    command
+        .arg("compile")
+        .arg(entry)
+        .arg("-o")
+        .arg(exe)
+        .arg("--emit-build-graph")
+        .arg(&graph_path)
+        .args(["--format", "json"])
+        // Object files are written to the working directory
+        .current_dir(dir);
+    if args.enable_js_runtime {
+        command.arg("--enable-js-runtime");
+    }
+    if let Some(manifest) = manifest {
+        command.arg("--hot-reload").arg(manifest);
+    }
+    let output = command.output()?;
+    if !output.status.success() {
+        eprint!("{}", String::from_utf8_lossy(&output.stdout));
+        eprint!("{}", String::from_utf8_lossy(&output.stderr));
+        return Err(anyhow!("Failed to compile {}", entry.display()));
+    }
+    let graph: serde_json::Value = serde_json::from_slice(&fs::read(&graph_path)?)?;
+    watched_files(&graph).ok_or_else(|| anyhow!("Malformed build graph {}", graph_path.display()))
+}
+
+fn stop(child: &mut Child) {
+    let _ = child.kill();
+    let _ = child.wait();
+}
+
+/// Stop the running process, if any, and start the build at `next_exe` in its place
+fn restart(args: &DevArgs, exe: &Path, next_exe: &Path, socket: &Path, child: &mut Option<Child>) -> Result<Child> {
+    if let Some(mut old) = child.take() {
+        stop(&mut old);
+    }
+    fs::rename(next_exe, exe).map_err(|e| anyhow!("Failed to replace {}: {}", exe.display(), e))?;
+    Command::new(exe)
+        .args(&args.args)
+        .env(perry_runtime::hot_reload::SOCKET_ENV, socket)
+        .spawn()
+        .map_err(|e| anyhow!("Failed to start {}: {}", exe.display(), e))
+}
+
+/// Link the modules of `next` that changed since `running` into the shared object
+/// `patch` and have the program listening on `socket` load it. Returns how many functions
+/// were swapped, else why the program has to restart instead.
+fn swap(running: &Manifest, next: &Manifest, patch: &Path, socket: &Path) -> Result<usize, String> {
+    let modules = running.patch(next)?;
+    if modules.is_empty() {
+        return Ok(0);
+    }
+    let mut link = Target::from_arg(None).map_err(|e| e.to_string())?.linker();
+    link.arg("-shared").arg("-o").arg(patch);
+    // The patch binds to the program's functions and module variables when it is loaded
+    if cfg!(target_os = "macos") {
+        link.args(["-undefined", "dynamic_lookup"]);
+    }
+    link.args(modules.iter().map(|module| &module.object));
+    let output = link.output().map_err(|e| format!("cannot link a patch: {}", e))?;
+    if !output.status.success() {
+        return Err(format!("cannot link a patch: {}", String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    let mut request = patch.display().to_string();
+    for function in modules.iter().flat_map(|module| &module.functions) {
+        request.push('\t');
+        request.push_str(function);
+    }
+    request.push('\n');
+    send(socket, &request)
+}
+
+/// Send a request to the program's hot reload agent and wait for its reply
+#[cfg(unix)]
+fn send(socket: &Path, request: &str) -> Result<usize, String> {
+    use std::io::{BufRead, BufReader, Write};
+
+    let mut stream = std::os::unix::net::UnixStream::connect(socket)
+        .map_err(|e| format!("cannot reach the program: {}", e))?;
+    let mut reply = String::new();
+    stream.set_read_timeout(Some(REPLY_TIMEOUT))
+        .and_then(|_| stream.write_all(request.as_bytes()))
+        .and_then(|_| BufReader::new(&stream).read_line(&mut reply))
+        .map_err(|e| format!("the program didn't answer: {}", e))?;
+    parse_reply(&reply)
+}
+
+#[cfg(not(unix))]
+fn send(_socket: &Path, _request: &str) -> Result<usize, String> {
+    Err("hot reload is not supported on this platform".to_string())
+}
+
+/// The number of functions swapped, from an `ok N` reply, or the agent's error
+fn parse_reply(reply: &str) -> Result<usize, String> {
+    let reply = reply.trim_end();
+    if let Some(message) = reply.strip_prefix("error ") {
+        return Err(message.to_string());
+    }
+    reply.strip_prefix("ok ")
+        .and_then(|swapped| swapped.parse().ok())
+        .ok_or_else(|| format!("unexpected reply from the program: {:?}", reply))
+}
+
+pub fn run(args: DevArgs, format: OutputFormat, _use_color: bool, _verbose: u8) -> Result<()> {
+    let entry = args.entry
+        .canonicalize()
+        .map_err(|e| anyhow!("Failed to canonicalize {}: {}", args.entry.display(), e))?;
+    let project_root = entry.parent().unwrap_or(Path::new(".")).to_path_buf();
+    let dir = project_root.join(CACHE_DIR).join("dev");
+    fs::create_dir_all(&dir).map_err(|e| anyhow!("Failed to create {}: {}", dir.display(), e))?;
+    let stem = entry.file_stem().and_then(|s| s.to_str()).unwrap_or("app");
+    let exe = dir.join(format!("{}{}", stem, std::env::consts::EXE_SUFFIX));
+    let next_exe = dir.join(format!("{}-next{}", stem, std::env::consts::EXE_SUFFIX));
+    let manifest_path = dir.join("hot-reload.json");
+    let socket = dir.join("hot-reload.sock");
+    let poll_interval = Duration::from_millis(args.poll_interval.max(10));
+    let report = |message: String| {
+        if let OutputFormat::Text = format {
+            eprintln!("[perry dev] {}", message);
+        }
+    };
+
+    let mut watched = vec![entry.clone()];
+    let mut child: Option<Child> = None;
+    // Manifest of the code the running process executes, and the patches it has loaded
+    let mut running: Option<Manifest> = None;
+    let mut patches: Vec<PathBuf> = Vec::new();
+    loop {
+        let build_started = SystemTime::now();
+        let started = Instant::now();
+        match build(&args, &entry, &dir, &next_exe, HOT_RELOAD.then_some(manifest_path.as_path())) {
+            Ok(inputs) => {
+                watched = inputs;
+                let next = HOT_RELOAD.then(|| Manifest::read(&manifest_path).ok()).flatten();
+                let swapped = match (&child, &running, &next) {
+                    (Some(process), Some(running), Some(next)) => {
+                        let patch = dir.join(format!("patch-{}.so", patches.len() + 1));
+                        patches.push(patch.clone());
+                        swap(running, next, &patch, &socket)
+                            .map(|swapped| (process.id(), swapped))
+                            .map_err(|reason| report(format!("can't hot swap: {}; restarting", reason)))
+                            .ok()
+                    }
+                    _ => None,
+                };
+                running = next;
+                if let Some((pid, swapped)) = swapped {
+                    report(format!("built in {:.1}s, swapped {} function(s) into {} (pid {})",
+                        started.elapsed().as_secs_f64(), swapped, entry.display(), pid));
+                } else {
+                    match restart(&args, &exe, &next_exe, &socket, &mut child) {
+                        Ok(spawned) => {
+                            report(format!("built in {:.1}s, started {} (pid {})",
+                                started.elapsed().as_secs_f64(), entry.display(), spawned.id()));
+                            child = Some(spawned);
+                        }
+                        Err(e) => report(format!("{}; waiting for changes", e)),
+                    }
+                    // Only the stopped process had them loaded
+                    for patch in patches.drain(..) {
+                        let _ = fs::remove_file(patch);
+                    }
+                }
+            }
+            Err(e) if child.is_some() => report(format!("{}; the previous build keeps running", e)),
+            Err(e) => report(e.to_string()),
+        }
+
+        let snapshot = Snapshot::take(&watched);
+        let mut changed: Vec<PathBuf> = snapshot.modified_since(build_started).into_iter().map(Path::to_path_buf).collect();
+        while changed.is_empty() {
+            std::thread::sleep(poll_interval);
+            if let Some(status) = child.as_mut().and_then(|c| c.try_wait().ok().flatten()) {
+                report(format!("{} exited ({}); waiting for changes", entry.display(), status));
+                child = None;
+            }
+            changed = snapshot.changed().into_iter().map(Path::to_path_buf).collect();
+        }
+        std::thread::sleep(SETTLE);
+        let names: Vec<String> = changed.iter()
+            .map(|path| path.strip_prefix(&project_root).unwrap_or(path).display().to_string())
+            .collect();
+        report(format!("{} changed, rebuilding", names.join(", ")));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::UNIX_EPOCH;
+
+    #[test]
+    fn test_snapshot_detects_changes() {
+        let dir = std::env::temp_dir().join(format!("perry-dev-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let main = dir.join("main.ts");
+        let routes = dir.join("routes.ts");
+        fs::write(&main, "import './routes';\n").unwrap();
+        fs::write(&routes, "export {};\n").unwrap();
+        let long_ago = UNIX_EPOCH + Duration::from_secs(1_000_000);
+        for path in [&main, &routes] {
+            fs::File::options().write(true).open(path).unwrap().set_modified(long_ago).unwrap();
+        }
+
+        let snapshot = Snapshot::take(&[main.clone(), routes.clone(), dir.join("missing.ts")]);
+        assert!(snapshot.changed().is_empty());
+        assert!(snapshot.modified_since(long_ago).is_empty());
+
+        fs::File::options().write(true).open(&routes).unwrap().set_modified(SystemTime::now()).unwrap();
+        assert_eq!(snapshot.changed(), vec![routes.as_path()]);
+        // A file that appears (or disappears) counts as a change too
+        fs::write(dir.join("missing.ts"), "").unwrap();
+        assert_eq!(snapshot.changed().len(), 2);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_parse_reply() {
+        assert_eq!(parse_reply("ok 12\n"), Ok(12));
+        assert_eq!(parse_reply("error the program did not reach a safe point\n"),
+            Err("the program did not reach a safe point".to_string()));
+        assert!(parse_reply("").is_err());
+        assert!(parse_reply("ok many\n").is_err());
+    }
+
+    #[test]
+    fn test_watched_files_from_build_graph() {
+        let graph = serde_json::json!({
+            "project_root": "/app",
+            "inputs": ["perry.toml", "src/main.ts", "/shared/lib.ts"],
+        });
+        assert_eq!(
+            watched_files(&graph).unwrap(),
+            vec![PathBuf::from("/app/perry.toml"), PathBuf::from("/app/src/main.ts"), PathBuf::from("/shared/lib.ts")]
+        );
+        assert!(watched_files(&serde_json::json!({})).is_none());
+    }
+}
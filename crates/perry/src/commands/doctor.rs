@@ -7,6 +7,7 @@ use std::path::PathBuf;
 use std::process::Command;
 
 use crate::OutputFormat;
+use super::project_config::BuildConfig;
 
 #[derive(Args, Debug)]
 pub struct DoctorArgs {
@@ -96,10 +97,17 @@ fn check_runtime_library() -> CheckResult {
 fn check_project_config() -> CheckResult {
     let config_path = PathBuf::from("perry.toml");
     if config_path.exists() {
-        CheckResult {
-            name: "project config (perry.toml)".to_string(),
-            status: CheckStatus::Ok,
-            details: Some("found".to_string()),
+        match BuildConfig::load(&config_path) {
+            Ok(_) => CheckResult {
+                name: "project config (perry.toml)".to_string(),
+                status: CheckStatus::Ok,
+                details: Some("found".to_string()),
+            },
+            Err(e) => CheckResult {
+                name: "project config (perry.toml)".to_string(),
+                status: CheckStatus::Error,
+                details: Some(e.to_string()),
+            },
         }
     } else {
         CheckResult {
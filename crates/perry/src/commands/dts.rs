@@ -0,0 +1,257 @@
+//! TypeScript declarations for the exports of a compiled module (`perry compile --emit-dts`)
+//!
+//! Written from the entry module's HIR, so the declared types are the ones perry compiled
+//! against: exported functions, classes, interfaces, type aliases, enums and variables.
+//! Re-exports (`export { a } from './b'`, `export * from './b'`) are kept as re-exports of
+//! the source module. Exports perry doesn't lower a declaration for (such as
+//! `export default <expression>`) are declared as `any`.
+
+use perry_hir::{Class, EnumValue, Export, Function, Interface, Module as HirModule, Param, Stmt};
+use perry_types::{Type, TypeParam};
+
+/// The `.d.ts` text for `module`'s exports
+pub fn generate(module: &HirModule) -> String {
+    let mut out = format!("// Generated by perry from {}\n", module.name);
+    let mut declared: Vec<&str> = Vec::new();
+    for export in &module.exports {
+        match export {
+            Export::Named { local, exported } => {
+                // A name exported twice (`export { a, a as b }`) is declared once
+                if !declared.contains(&local.as_str()) {
+                    declared.push(local);
+                    let declaration = declare(module, local);
+                    match declaration {
+                        Some(declaration) if local == exported => {
+                            out.push_str(&format!("export {}\n", declaration));
+                            continue;
+                        }
+                        Some(declaration) => out.push_str(&format!("{}\n", declaration)),
+                        None if local == exported => {
+                            out.push_str(&format!("export declare const {}: any;\n", exported));
+                            continue;
+                        }
+                        None => out.push_str(&format!("declare const {}: any;\n", local)),
+                    }
+                }
+                if exported == "default" {
+                    out.push_str(&format!("export default {};\n", local));
+                } else {
+                    out.push_str(&format!("export {{ {} as {} }};\n", local, exported));
+                }
+            }
+            Export::ReExport { source, imported, exported } if imported == exported => {
+                out.push_str(&format!("export {{ {} }} from {:?};\n", imported, source));
+            }
+            Export::ReExport { source, imported, exported } => {
+                out.push_str(&format!("export {{ {} as {} }} from {:?};\n", imported, exported, source));
+            }
+            Export::ExportAll { source } => out.push_str(&format!("export * from {:?};\n", source)),
+        }
+    }
+    out
+}
+
+/// Declaration of the module-level binding `name`, without `export`
+fn declare(module: &HirModule, name: &str) -> Option<String> {
+    if let Some(func) = module.functions.iter().find(|f| f.name == name) {
+        return Some(format!("declare function {}{}({}): {};", name, type_params(&func.type_params), params(&func.params), return_type(func)));
+    }
+    if let Some(class) = module.classes.iter().find(|c| c.name == name) {
+        return Some(declare_class(class));
+    }
+    if let Some(iface) = module.interfaces.iter().find(|i| i.name == name) {
+        return Some(declare_interface(iface));
+    }
+    if let Some(alias) = module.type_aliases.iter().find(|a| a.name == name) {
+        return Some(format!("type {}{} = {};", name, type_params(&alias.type_params), alias.ty));
+    }
+    if let Some(en) = module.enums.iter().find(|e| e.name == name) {
+        let members: Vec<String> = en.members.iter()
+            .map(|member| match &member.value {
+                EnumValue::Number(n) => format!("    {} = {},", member.name, n),
+                EnumValue::String(s) => format!("    {} = {:?},", member.name, s),
+            })
+            .collect();
+        return Some(format!("declare enum {} {{\n{}\n}}", name, members.join("\n")));
+    }
+    module.init.iter().find_map(|stmt| match stmt {
+        Stmt::Let { name: let_name, ty, mutable, .. } if let_name == name => {
+            Some(format!("declare {} {}: {};", if *mutable { "let" } else { "const" }, name, ty))
+        }
+        _ => None,
+    })
+}
+
+fn declare_class(class: &Class) -> String {
+    let mut heading = format!("declare class {}{}", class.name, type_params(&class.type_params));
+    if let Some(parent) = class.extends_name.as_ref().or(class.native_extends.as_ref().map(|(_, name)| name)) {
+        heading.push_str(&format!(" extends {}", parent));
+    }
+    let mut members = Vec::new();
+    // Like tsc: `private` members are declared without their type, `#fields` as one `#private`
+    if class.fields.iter().chain(&class.static_fields).any(|f| f.name.starts_with('#')) {
+        members.push("#private;".to_string());
+    }
+    for (fields, prefix) in [(&class.static_fields, "static "), (&class.fields, "")] {
+        for field in fields {
+            if field.name.starts_with('#') {
+                continue;
+            }
+            if field.is_private {
+                members.push(format!("{}private {};", prefix, field.name));
+            } else {
+                let readonly = if field.is_readonly { "readonly " } else { "" };
+                members.push(format!("{}{}{}: {};", prefix, readonly, field.name, field.ty));
+            }
+        }
+    }
+    if let Some(ctor) = &class.constructor {
+        members.push(format!("constructor({});", params(&ctor.params)));
+    }
+    for (name, getter) in &class.getters {
+        members.push(format!("get {}(): {};", name, getter.return_type));
+    }
+    for (name, setter) in &class.setters {
+        members.push(format!("set {}({});", name, params(&setter.params)));
+    }
+    for (methods, prefix) in [(&class.static_methods, "static "), (&class.methods, "")] {
+        for method in methods {
+            members.push(format!(
+                "{}{}{}({}): {};",
+                prefix,
+                method.name,
+                type_params(&method.type_params),
+                params(&method.params),
+                return_type(method)
+            ));
+        }
+    }
+    declare_body(&heading, &members)
+}
+
+fn declare_interface(iface: &Interface) -> String {
+    let mut heading = format!("interface {}{}", iface.name, type_params(&iface.type_params));
+    if !iface.extends.is_empty() {
+        let parents: Vec<String> = iface.extends.iter().map(|t| t.to_string()).collect();
+        heading.push_str(&format!(" extends {}", parents.join(", ")));
+    }
+    let mut members: Vec<String> = iface.properties.iter()
+        .map(|prop| format!(
+            "{}{}{}: {};",
+            if prop.readonly { "readonly " } else { "" },
+            prop.name,
+            if prop.optional { "?" } else { "" },
+            prop.ty
+        ))
+        .collect();
+    for method in &iface.methods {
+        let params: Vec<String> = method.params.iter()
+            .map(|(name, ty, optional)| format!("{}{}: {}", name, if *optional { "?" } else { "" }, ty))
+            .collect();
+        members.push(format!("{}{}({}): {};", method.name, type_params(&method.type_params), params.join(", "), method.return_type));
+    }
+    declare_body(&heading, &members)
+}
+
+fn declare_body(heading: &str, members: &[String]) -> String {
+    if members.is_empty() {
+        return format!("{} {{}}", heading);
+    }
+    let body: Vec<String> = members.iter().map(|m| format!("    {}", m)).collect();
+    format!("{} {{\n{}\n}}", heading, body.join("\n"))
+}
+
+fn type_params(params: &[TypeParam]) -> String {
+    if params.is_empty() {
+        return String::new();
+    }
+    let params: Vec<String> = params.iter()
+        .map(|p| {
+            let mut param = p.name.clone();
+            if let Some(constraint) = &p.constraint {
+                param.push_str(&format!(" extends {}", constraint));
+            }
+            if let Some(default) = &p.default {
+                param.push_str(&format!(" = {}", default));
+            }
+            param
+        })
+        .collect();
+    format!("<{}>", params.join(", "))
+}
+
+/// Parameters with defaults (and `x?: T`) are optional to callers
+fn params(params: &[Param]) -> String {
+    params.iter()
+        .map(|p| if p.is_rest {
+            format!("...{}: {}", p.name, p.ty)
+        } else {
+            format!("{}{}: {}", p.name, if p.default.is_some() { "?" } else { "" }, p.ty)
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Async functions without a `Promise` annotation still return one
+fn return_type(func: &Function) -> String {
+    match &func.return_type {
+        Type::Promise(_) => func.return_type.to_string(),
+        ty if func.is_async => format!("Promise<{}>", ty),
+        ty => ty.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn declarations(source: &str) -> String {
+        let ast_module = perry_parser::parse_typescript(source, "lib.ts").unwrap();
+        let mut module = perry_hir::lower_module(&ast_module, "lib.ts", "/tmp/lib.ts").unwrap();
+        perry_hir::infer_local_types(&mut module);
+        generate(&module)
+    }
+
+    #[test]
+    fn test_declares_exports() {
+        let dts = declarations(
+            "export interface Point { readonly x: number; y?: number; }\n\
+             export type Id = string | number;\n\
+             export enum Color { Red, Green = 'green' }\n\
+             export const ORIGIN = 0;\n\
+             export function scale<T extends Point>(p: T, by: number = 2, ...rest: number[]): T { return p; }\n\
+             export async function load(id: string) { return 1; }\n\
+             function helper(): void {}\n\
+             export { helper as assist };\n\
+             export * from './shapes';\n",
+        );
+        assert!(dts.contains("export interface Point {\n    readonly x: number;\n    y?: number;\n}\n"));
+        assert!(dts.contains("export type Id = string | number;\n"));
+        assert!(dts.contains("export declare enum Color {\n    Red = 0,\n    Green = \"green\",\n}\n"));
+        assert!(dts.contains("export declare const ORIGIN: number;\n"));
+        assert!(dts.contains("export declare function scale<T extends Point>(p: T, by?: number, ...rest: number[]): T;\n"));
+        assert!(dts.contains("export declare function load(id: string): Promise<any>;\n"));
+        assert!(dts.contains("declare function helper(): void;\nexport { helper as assist };\n"));
+        assert!(dts.contains("export * from \"./shapes\";\n"));
+    }
+
+    #[test]
+    fn test_declares_classes() {
+        let dts = declarations(
+            "export class Counter {\n\
+               private count: number = 0;\n\
+               #secret = 1;\n\
+               readonly label: string;\n\
+               static instances: number = 0;\n\
+               constructor(label: string) { this.label = label; }\n\
+               get value(): number { return this.count; }\n\
+               increment(by: number): number { this.count += by; return this.count; }\n\
+               static create(): Counter { return new Counter('c'); }\n\
+             }\n",
+        );
+        assert!(dts.contains(
+            "export declare class Counter {\n    #private;\n    static instances: number;\n    private count;\n    readonly label: string;\n    \
+             constructor(label: string);\n    get value(): number;\n    static create(): Counter;\n    increment(by: number): number;\n}\n"
+        ));
+    }
+}
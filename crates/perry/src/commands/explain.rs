@@ -63,6 +63,54 @@ const ERROR_EXPLANATIONS: &[ErrorExplanation] = &[
         suggestion: Some("Add an explicit type annotation to the parameter or variable."),
         related: &["T002", "T003"],
     },
+    ErrorExplanation {
+        code: "T006",
+        title: "Missing Property",
+        description: "An object literal assigned to an interface or object type is missing a property the type requires. Native code reads the property at a fixed slot, so it must exist.",
+        example: Some("interface User { name: string; email: string }\nconst u: User = { name: \"a\" };  // 'email' is missing"),
+        suggestion: Some("Add the property, or mark it optional in the type (email?: string)."),
+        related: &["T001", "T007"],
+    },
+    ErrorExplanation {
+        code: "T007",
+        title: "Excess Property",
+        description: "An object literal assigned to an interface or object type has a property the type does not declare. This is usually a typo in the property name.",
+        example: Some("interface User { name: string }\nconst u: User = { nmae: \"a\" };  // typo of 'name'"),
+        suggestion: Some("Fix the property name, or declare the property in the type."),
+        related: &["T001", "T006"],
+    },
+    ErrorExplanation {
+        code: "T008",
+        title: "Readonly Assignment",
+        description: "A property declared `readonly` (in a class, interface or object type, a getter without a setter, or any property of an `as const` object) is assigned, incremented or deleted. Readonly properties may only be set by a class's own constructor.",
+        example: Some("interface Point { readonly x: number }\nconst p: Point = { x: 1 };\np.x = 2;  // 'x' is readonly"),
+        suggestion: Some("Create an updated copy ({ ...p, x: 2 }), or remove the readonly modifier if the property is meant to change."),
+        related: &["T001"],
+    },
+    ErrorExplanation {
+        code: "T009",
+        title: "Non-Exhaustive Switch",
+        description: "A `switch` on the tag property of a discriminated union (a union whose members all declare the same property with distinct literal types) has no case for some members and no `default`. Values of the missing members fall through the switch silently.",
+        example: Some("type Shape = { kind: 'circle'; r: number } | { kind: 'square'; size: number };\nswitch (shape.kind) {\n  case 'circle': return Math.PI * shape.r ** 2;\n}  // 'square' is not handled"),
+        suggestion: Some("Add a case for each missing tag, or a `default` clause if falling through is intended."),
+        related: &["T001"],
+    },
+    ErrorExplanation {
+        code: "T010",
+        title: "Constraint Violation",
+        description: "A generic function or class is called or instantiated with a type argument, explicit or inferred from the arguments, that does not satisfy the type parameter's `extends` constraint. The specialized code would read properties the value doesn't have.",
+        example: Some("function getId<T extends { id: string }>(item: T): string { return item.id; }\ngetId(42);  // 'number' has no property 'id'"),
+        suggestion: Some("Pass a value whose type satisfies the constraint, or widen the constraint."),
+        related: &["T001", "T006"],
+    },
+    ErrorExplanation {
+        code: "T011",
+        title: "Unknown Property",
+        description: "A property is read, written or called on a value whose declared type is an object type literal, such as an options parameter, and the literal doesn't declare it. This is usually a misspelled option that would silently read `undefined`.",
+        example: Some("function listen(opts: { port: number; host?: string }) {\n  return opts.hots;  // 'hots' does not exist\n}"),
+        suggestion: Some("Fix the property name, or declare the property in the type (optional with `?` if callers may omit it)."),
+        related: &["T007"],
+    },
     // Unsupported features
     ErrorExplanation {
         code: "U001",
@@ -88,6 +136,14 @@ const ERROR_EXPLANATIONS: &[ErrorExplanation] = &[
         suggestion: Some("Check the documentation for supported features and possible workarounds."),
         related: &["U001", "U002"],
     },
+    ErrorExplanation {
+        code: "U010",
+        title: "Dropped Construct",
+        description: "The compiler has no lowering for this statement, declaration or class member, so it would be left out of the binary and the program would behave differently than under Node. Compilation stops on it; `perry compile --permissive` builds anyway, reporting every dropped construct in a summary.",
+        example: Some("export default { port: 3000 };"),
+        suggestion: Some("const config = { port: 3000 };\nexport default config;"),
+        related: &["U006"],
+    },
     // Dynamic code
     ErrorExplanation {
         code: "D001",
@@ -146,6 +202,32 @@ const mod = plugins[name];"#),
         suggestion: Some("Use strict equality (=== or !==) for predictable behavior."),
         related: &["C001"],
     },
+    // Performance lints
+    ErrorExplanation {
+        code: "F001",
+        title: "Await In Loop",
+        description: "An `await` inside a loop over an array makes each iteration wait for the previous one, so independent requests run one at a time. Reported by `perry check --perf`. When the loop body only declares constants and awaits, `--fix` rewrites the loop to run the iterations concurrently.",
+        example: Some("for (const id of ids) {\n  const user = await fetchUser(id);  // one request at a time\n}"),
+        suggestion: Some("await Promise.all(ids.map(async (id) => {\n  const user = await fetchUser(id);\n}));"),
+        related: &["F002"],
+    },
+    ErrorExplanation {
+        code: "F002",
+        title: "Blocking Call In Handler",
+        description: "A server runs all requests on one event loop, so synchronous work inside an HTTP handler, hook or middleware stalls every other request until it finishes. Reported by `perry check --perf` for known blocking APIs (`readFileSync` and the other `fs.*Sync` functions, `execSync`, `pbkdf2Sync`, `scryptSync`, `zlib.*Sync`, `bcrypt.hashSync`, ...) and for nested loops over awaited data that never await. Startup code outside handlers is not reported.",
+        example: Some("app.get('/config', async () => {\n  return JSON.parse(fs.readFileSync('config.json', 'utf8'));  // blocks every request\n});"),
+        suggestion: Some("app.get('/config', async () => {\n  return JSON.parse(await fs.promises.readFile('config.json', 'utf8'));\n});"),
+        related: &["F001"],
+    },
+    // Security lints
+    ErrorExplanation {
+        code: "S001",
+        title: "SQL Built From Strings",
+        description: "SQL text assembled with `+` or template interpolation lets a value change the statement itself, which is how SQL injection happens. `perry check` reports such text when it is passed to `query()`, `execute()` or `prepare()`, directly or through a variable. Text built only from literals is not reported.",
+        example: Some("await pool.query(`SELECT * FROM users WHERE name = '${name}'`);"),
+        suggestion: Some("import { sql } from 'pg';\n\nawait pool.query(sql`SELECT * FROM users WHERE name = ${name}`);  // or pool.query('... WHERE name = $1', [name])"),
+        related: &[],
+    },
     // Resolution errors
     ErrorExplanation {
         code: "R001",
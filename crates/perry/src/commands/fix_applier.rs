@@ -4,7 +4,7 @@
 //! either showing a diff (dry-run) or modifying the files in place.
 
 use crate::commands::fixer::{Confidence, FixableIssue};
-use perry_diagnostics::FileId;
+use perry_diagnostics::{Applicability, FileId, Suggestion};
 use std::collections::HashMap;
 use std::fs;
 use std::path::{Path, PathBuf};
@@ -77,6 +77,29 @@ impl FixApplier {
             .or_insert_with(|| source.to_string());
     }
 
+    /// Add a diagnostic's suggestion as an edit; only machine-applicable suggestions are applied
+    pub fn add_suggestion(&mut self, suggestion: &Suggestion, file_path: &Path, source: &str) {
+        if suggestion.applicability != Applicability::MachineApplicable {
+            return;
+        }
+
+        // Diagnostic spans are 0-based, edits use SWC's 1-based positions
+        let edit = TextEdit {
+            start: suggestion.span.start + 1,
+            end: suggestion.span.end + 1,
+            replacement: suggestion.replacement.clone(),
+        };
+
+        self.edits_by_file
+            .entry(file_path.to_path_buf())
+            .or_default()
+            .push(edit);
+
+        self.file_contents
+            .entry(file_path.to_path_buf())
+            .or_insert_with(|| source.to_string());
+    }
+
     /// Get the number of pending fixes
     pub fn pending_fixes(&self) -> usize {
         self.edits_by_file.values().map(|v| v.len()).sum()
@@ -0,0 +1,139 @@
+//! On-disk cache of lowered HIR, so unchanged modules skip parsing and lowering
+//!
+//! Each source file gets one entry under `<project>/.perry-cache/hir/`, named after a hash
+//! of its path and holding the key it was written under plus the module's HIR (before
+//! inlining, which depends on the rest of the program). The key hashes the perry version,
+//! the source text, the module name and path, and the lowering options; the options carry
+//! the file's id in the source cache, so a module whose spans would resolve differently is
+//! a miss too. Only the newest version of a file is kept, which is what iterative builds
+//! want: a changed file overwrites its entry instead of piling up stale ones.
+//!
+//! Reading and writing are best-effort. A missing, unreadable or outdated entry is a miss
+//! and a failed write is ignored; the cache never fails a build. Parse warnings are only
+//! printed when a file is actually parsed.
+//!
+//! With a [`RemoteCache`] attached, a local miss asks the remote for the same key, a remote
+//! hit is written to the local cache, and freshly lowered modules are uploaded, so CI
+//! machines and developers share lowering work.
+
+use perry_hir::{LowerOptions, Module as HirModule};
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+
+use super::remote_cache::RemoteCache;
+
+/// Directory under the project root holding perry's build caches
+pub const CACHE_DIR: &str = ".perry-cache";
+
+#[derive(Debug)]
+pub struct HirCache {
+    dir: PathBuf,
+    pub hits: usize,
+    pub misses: usize,
+    pub remote: Option<RemoteCache>,
+}
+
+impl HirCache {
+    pub fn new(project_root: &Path) -> Self {
+        Self { dir: project_root.join(CACHE_DIR).join("hir"), hits: 0, misses: 0, remote: None }
+    }
+
+    /// Key for a module's lowered HIR; any input that changes lowering changes the key
+    pub fn key(source: &str, module_name: &str, source_path: &str, options: &LowerOptions) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        env!("CARGO_PKG_VERSION").hash(&mut hasher);
+        source.hash(&mut hasher);
+        module_name.hash(&mut hasher);
+        source_path.hash(&mut hasher);
+        format!("{:?}", options).hash(&mut hasher);
+        hasher.finish()
+    }
+
+    fn entry_path(&self, source_path: &str) -> PathBuf {
+        let mut hasher = DefaultHasher::new();
+        source_path.hash(&mut hasher);
+        self.dir.join(format!("{:016x}.bin", hasher.finish()))
+    }
+
+    /// The cached HIR for `source_path` if it was stored under `key`
+    pub fn load(&mut self, source_path: &str, key: u64) -> Option<HirModule> {
+        let module = fs::read(self.entry_path(source_path))
+            .ok()
+            .and_then(|bytes| Self::decode(&bytes, key))
+            .or_else(|| {
+                let bytes = self.remote.as_ref()?.get(key)?;
+                let module = Self::decode(&bytes, key)?;
+                self.write_local(source_path, &bytes);
+                Some(module)
+            });
+        if module.is_some() {
+            self.hits += 1;
+        } else {
+            self.misses += 1;
+        }
+        module
+    }
+
+    fn decode(bytes: &[u8], key: u64) -> Option<HirModule> {
+        bincode::deserialize::<(u64, HirModule)>(bytes)
+            .ok()
+            .filter(|(stored, _)| *stored == key)
+            .map(|(_, module)| module)
+    }
+
+    /// Replace the entry for `source_path`, and upload it to the remote cache
+    pub fn store(&self, source_path: &str, key: u64, module: &HirModule) {
+        let Ok(bytes) = bincode::serialize(&(key, module)) else {
+            return;
+        };
+        self.write_local(source_path, &bytes);
+        if let Some(remote) = &self.remote {
+            remote.put(key, &bytes);
+        }
+    }
+
+    fn write_local(&self, source_path: &str, bytes: &[u8]) {
+        let path = self.entry_path(source_path);
+        // Write then rename, so a concurrent build never reads half an entry
+        let tmp = path.with_extension(format!("tmp{}", std::process::id()));
+        let written = fs::create_dir_all(&self.dir)
+            .and_then(|_| fs::write(&tmp, bytes))
+            .and_then(|_| fs::rename(&tmp, &path));
+        if written.is_err() {
+            let _ = fs::remove_file(&tmp);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip_and_invalidation() {
+        let root = std::env::temp_dir().join(format!("perry-hir-cache-{}", std::process::id()));
+        let mut cache = HirCache::new(&root);
+        let options = LowerOptions::default();
+        let source = "export const answer: number = 42;\nexport function twice(x: number) { return x * 2; }";
+        let ast = perry_parser::parse_typescript(source, "main.ts").unwrap();
+        let module = perry_hir::lower_module_with_options(&ast, "main", "/src/main.ts", &options).unwrap();
+
+        let key = HirCache::key(source, "main", "/src/main.ts", &options);
+        assert!(cache.load("/src/main.ts", key).is_none());
+        cache.store("/src/main.ts", key, &module);
+        let cached = cache.load("/src/main.ts", key).expect("entry was stored");
+        assert_eq!(format!("{:?}", cached), format!("{:?}", module));
+        assert_eq!((cache.hits, cache.misses), (1, 1));
+
+        // Editing the file, or lowering it differently, misses
+        let edited = HirCache::key("export const answer = 43;", "main", "/src/main.ts", &options);
+        assert!(cache.load("/src/main.ts", edited).is_none());
+        let exe_root = LowerOptions { import_meta_exe_root: Some("/src".into()), ..LowerOptions::default() };
+        assert_ne!(HirCache::key(source, "main", "/src/main.ts", &exe_root), key);
+        assert!(cache.load("/src/other.ts", key).is_none());
+
+        let _ = fs::remove_dir_all(&root);
+    }
+}
@@ -0,0 +1,215 @@
+//! Hot reload manifests - `perry compile --hot-reload <PATH>`, read by `perry dev`
+//!
+//! A hot-reload build (see `perry_codegen::hot_reload`) keeps its object files and writes
+//! a manifest of its native modules: each one's object file, hashes of its object code and
+//! of its interface, and the symbols the object code defines. `perry dev` compares the
+//! manifests of the running build and the next one to decide whether the program can take
+//! the modules whose object code changed as a patch. That holds when the edits stay inside
+//! function bodies; a module added or removed, a function, module variable or class member
+//! added or renamed, a signature, class layout or top-level statement changed all need a
+//! restart, and [`Manifest::patch`] says which one it was.
+
+use anyhow::{anyhow, Result};
+use perry_hir::{Class, Function, Module as HirModule};
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+
+/// The native modules of a hot-reload build
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Manifest {
+    pub modules: Vec<ModuleEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModuleEntry {
+    pub name: String,
+    /// Absolute path of the module's object file
+    pub object: PathBuf,
+    pub object_hash: u64,
+    /// Hash of what other code relies on (see `interface_hash`)
+    pub interface_hash: u64,
+    /// Functions a patch can swap
+    pub functions: Vec<String>,
+    /// Module variables and other writable globals, which a patch binds to
+    pub data: Vec<String>,
+    /// Hash of the startup code, which a patch can't rerun
+    pub startup_hash: u64,
+}
+
+impl ModuleEntry {
+    pub fn new(module: &HirModule, object: PathBuf, object_code: &[u8]) -> Result<Self> {
+        let symbols = perry_codegen::hot_reload::read_symbols(object_code)?;
+        let mut hasher = DefaultHasher::new();
+        object_code.hash(&mut hasher);
+        Ok(ModuleEntry {
+            name: module.name.clone(),
+            object,
+            object_hash: hasher.finish(),
+            interface_hash: interface_hash(module),
+            functions: symbols.functions,
+            data: symbols.data,
+            startup_hash: symbols.startup_hash,
+        })
+    }
+}
+
+/// The parts of a function other code calls it by: everything but its body
+fn signature(function: &Function) -> String {
+    let params: Vec<String> = function.params.iter()
+        .map(|param| format!("{}{}: {:?}{}", if param.is_rest { "..." } else { "" }, param.name, param.ty,
+            if param.default.is_some() { " = _" } else { "" }))
+        .collect();
+    let decorators: Vec<&str> = function.decorators.iter().map(|decorator| decorator.name.as_str()).collect();
+    format!("{:?} {}{}({}) -> {:?} {}", decorators, if function.is_async { "async " } else { "" },
+        function.name, params.join(", "), function.return_type, function.is_exported)
+}
+
+fn class_layout(class: &Class) -> String {
+    let fields = |fields: &[perry_hir::ClassField]| -> Vec<String> {
+        fields.iter()
+            .map(|field| format!("{}: {:?} {} {}", field.name, field.ty, field.is_private, field.is_readonly))
+            .collect()
+    };
+    let accessors = |accessors: &[(String, Function)]| -> Vec<String> {
+        accessors.iter().map(|(name, function)| format!("{} {}", name, signature(function))).collect()
+    };
+    format!(
+        "{} {} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {}",
+        class.name,
+        class.type_params.len(),
+        class.extends_name,
+        class.native_extends,
+        fields(&class.fields),
+        class.constructor.as_ref().map(signature),
+        class.methods.iter().map(signature).collect::<Vec<_>>(),
+        accessors(&class.getters),
+        accessors(&class.setters),
+        fields(&class.static_fields),
+        class.static_methods.iter().map(signature).collect::<Vec<_>>(),
+        class.is_exported,
+    )
+}
+
+/// Hash of what a module's code relies on beyond function bodies: its imports and
+/// exports, function signatures, class layouts, enums and globals. Ids and spans are
+/// left out, since edits elsewhere in the module shift them.
+pub fn interface_hash(module: &HirModule) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    for import in &module.imports {
+        (&import.source, format!("{:?} {:?}", import.specifiers, import.module_kind), &import.resolved_path)
+            .hash(&mut hasher);
+    }
+    format!("{:?}", module.exports).hash(&mut hasher);
+    for function in &module.functions {
+        signature(function).hash(&mut hasher);
+    }
+    for class in &module.classes {
+        class_layout(class).hash(&mut hasher);
+    }
+    format!("{:?}", module.enums.iter().map(|e| (&e.name, &e.members, e.is_exported)).collect::<Vec<_>>())
+        .hash(&mut hasher);
+    for global in &module.globals {
+        (&global.name, format!("{:?}", global.ty), global.mutable).hash(&mut hasher);
+    }
+    (&module.exported_native_instances, &module.exported_objects).hash(&mut hasher);
+    module.exported_functions.iter().map(|(name, _)| name).for_each(|name| name.hash(&mut hasher));
+    format!("{:?} {:?} {:?}", module.exported_constants, module.native_addons, module.opt).hash(&mut hasher);
+    hasher.finish()
+}
+
+impl Manifest {
+    pub fn read(path: &Path) -> Result<Self> {
+        let bytes = fs::read(path).map_err(|e| anyhow!("Failed to read {}: {}", path.display(), e))?;
+        serde_json::from_slice(&bytes).map_err(|e| anyhow!("Malformed hot reload manifest {}: {}", path.display(), e))
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)? + "\n")
+            .map_err(|e| anyhow!("Failed to write {}: {}", path.display(), e))
+    }
+
+    /// The modules of `next` whose object code differs from this build's, if a program
+    /// running this build can take them as a patch; otherwise why it has to restart
+    pub fn patch<'a>(&self, next: &'a Manifest) -> Result<Vec<&'a ModuleEntry>, String> {
+        if self.modules.len() != next.modules.len() {
+            return Err("modules were added or removed".to_string());
+        }
+        let mut changed = Vec::new();
+        for module in &next.modules {
+            let running = self.modules.iter()
+                .find(|running| running.name == module.name)
+                .ok_or_else(|| format!("{} is a new module", module.name))?;
+            if running.object_hash == module.object_hash {
+                continue;
+            }
+            if running.interface_hash != module.interface_hash {
+                return Err(format!("{} changed a signature, class layout, import or export", module.name));
+            }
+            if running.startup_hash != module.startup_hash {
+                return Err(format!("{} changed its top-level code", module.name));
+            }
+            if running.functions != module.functions || running.data != module.data {
+                return Err(format!("{} added, removed or renamed a function or variable", module.name));
+            }
+            changed.push(module);
+        }
+        Ok(changed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str, object_hash: u64) -> ModuleEntry {
+        ModuleEntry {
+            name: name.to_string(),
+            object: PathBuf::from(format!("/app/.perry-cache/dev/{}.o", name)),
+            object_hash,
+            interface_hash: 1,
+            functions: vec!["handler".to_string()],
+            data: vec!["count".to_string()],
+            startup_hash: 2,
+        }
+    }
+
+    #[test]
+    fn test_patch_takes_changed_function_bodies() {
+        let running = Manifest { modules: vec![entry("main", 1), entry("routes", 1)] };
+        let next = Manifest { modules: vec![entry("main", 1), entry("routes", 2)] };
+        let patch = running.patch(&next).unwrap();
+        assert_eq!(patch.iter().map(|module| module.name.as_str()).collect::<Vec<_>>(), ["routes"]);
+        assert!(running.patch(&running).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_patch_refuses_other_changes() {
+        let running = Manifest { modules: vec![entry("main", 1), entry("routes", 1)] };
+        let changed = |change: fn(&mut ModuleEntry)| {
+            let mut next = running.clone();
+            next.modules[1].object_hash = 2;
+            change(&mut next.modules[1]);
+            running.patch(&next).unwrap_err()
+        };
+        assert!(changed(|module| module.interface_hash = 3).contains("signature"));
+        assert!(changed(|module| module.startup_hash = 3).contains("top-level"));
+        assert!(changed(|module| module.functions.push("helper".to_string())).contains("function"));
+        assert!(changed(|module| module.data.clear()).contains("variable"));
+        assert!(changed(|module| module.name = "db".to_string()).contains("new module"));
+
+        let fewer = Manifest { modules: vec![entry("main", 1)] };
+        assert!(running.patch(&fewer).is_err());
+    }
+
+    #[test]
+    fn test_manifest_round_trip() {
+        let path = std::env::temp_dir().join(format!("perry-hot-reload-test-{}.json", std::process::id()));
+        let manifest = Manifest { modules: vec![entry("main", u64::MAX)] };
+        manifest.write(&path).unwrap();
+        assert_eq!(Manifest::read(&path).unwrap(), manifest);
+        fs::remove_file(&path).unwrap();
+    }
+}
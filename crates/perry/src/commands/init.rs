@@ -36,12 +36,19 @@ entry = "src/main.ts"
 
 [build]
 out_dir = "dist"
-opt_level = 2
+# "speed", "size" or "none" (fastest compile); `perry compile --opt-level` overrides it
+opt_level = "speed"
+# Run the Cranelift IR verifier on every function (slower, catches codegen bugs)
+verifier = false
+
+# Other Cranelift settings, shared or for the host CPU, e.g. has_avx2 = false
+# [build.cranelift]
 "#;
 
 const DEFAULT_GITIGNORE: &str = r#"# Perry build outputs
 dist/
 *.o
+.perry-cache/
 
 # Node modules (if using for type checking)
 node_modules/
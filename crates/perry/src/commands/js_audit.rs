@@ -0,0 +1,390 @@
+//! Audit of the JavaScript modules routed to the V8 runtime
+//!
+//! Modules perry can't compile natively run as they are in the embedded V8, outside the
+//! checks native code goes through. `perry compile --audit` looks at them before they are
+//! bundled into the executable:
+//!
+//! - the version of each package is checked against an advisory database (`--audit-db`):
+//!   a JSON object keyed by package name, in the format npm's bulk advisory endpoint
+//!   returns (`{"lodash": [{"id": ..., "title": ..., "severity": "high",
+//!   "vulnerable_versions": "<4.17.21", "url": ...}]}`). perry doesn't fetch it, so
+//!   builds stay offline and reproducible;
+//! - packages with `preinstall`, `install` or `postinstall` scripts are flagged, since
+//!   they ran arbitrary code when the dependency was installed;
+//! - `eval()` and `new Function()` in the bundled sources are flagged.
+//!
+//! Every finding has a severity; the build fails on findings at or above
+//! `--audit-fail-on` (high by default) and only reports the rest.
+
+use anyhow::{anyhow, Result};
+use clap::ValueEnum;
+use serde_json::json;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::compile::JsModule;
+use super::deps::{scan_source_for_issues, IssueKind};
+
+/// Severity of installing a package that runs code at install time
+const INSTALL_SCRIPT_SEVERITY: Severity = Severity::Moderate;
+/// Severity of code built from strings at run time
+const DYNAMIC_CODE_SEVERITY: Severity = Severity::Moderate;
+
+/// npm's advisory severities, in increasing order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, ValueEnum)]
+pub enum Severity {
+    Low,
+    Moderate,
+    High,
+    Critical,
+}
+
+impl Severity {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "low" | "info" => Some(Severity::Low),
+            "moderate" | "medium" => Some(Severity::Moderate),
+            "high" => Some(Severity::High),
+            "critical" => Some(Severity::Critical),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Low => "low",
+            Severity::Moderate => "moderate",
+            Severity::High => "high",
+            Severity::Critical => "critical",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FindingKind {
+    /// The installed version is affected by a known advisory
+    Advisory { id: String, title: String, url: Option<String> },
+    /// package.json runs a script at install time
+    InstallScript { script: String, command: String },
+    /// `eval()` or `new Function()`
+    DynamicCode { file: PathBuf, line: Option<u32> },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Finding {
+    /// Package name, or the module's path for JavaScript outside node_modules
+    pub package: String,
+    pub version: Option<String>,
+    pub severity: Severity,
+    pub kind: FindingKind,
+}
+
+impl Finding {
+    pub fn to_json(&self) -> serde_json::Value {
+        let mut finding = json!({
+            "package": self.package,
+            "version": self.version,
+            "severity": self.severity.as_str(),
+        });
+        let details = match &self.kind {
+            FindingKind::Advisory { id, title, url } => json!({ "kind": "advisory", "id": id, "title": title, "url": url }),
+            FindingKind::InstallScript { script, command } => json!({ "kind": "install_script", "script": script, "command": command }),
+            FindingKind::DynamicCode { file, line } => json!({ "kind": "dynamic_code", "file": file.to_string_lossy(), "line": line }),
+        };
+        if let (Some(finding), serde_json::Value::Object(details)) = (finding.as_object_mut(), details) {
+            finding.extend(details);
+        }
+        finding
+    }
+}
+
+impl std::fmt::Display for Finding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[{}] {}", self.severity.as_str(), self.package)?;
+        if let Some(version) = &self.version {
+            write!(f, "@{}", version)?;
+        }
+        match &self.kind {
+            FindingKind::Advisory { id, title, url } => {
+                write!(f, ": {} ({})", title, id)?;
+                if let Some(url) = url {
+                    write!(f, " {}", url)?;
+                }
+                Ok(())
+            }
+            FindingKind::InstallScript { script, command } => write!(f, ": `{}` script runs `{}`", script, command),
+            FindingKind::DynamicCode { file, line } => {
+                write!(f, ": eval or new Function in {}", file.display())?;
+                if let Some(line) = line {
+                    write!(f, ":{}", line)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Advisory {
+    id: String,
+    title: String,
+    severity: Severity,
+    vulnerable_versions: String,
+    url: Option<String>,
+}
+
+/// Known advisories by package name
+#[derive(Debug, Default)]
+pub struct AdvisoryDb(HashMap<String, Vec<Advisory>>);
+
+impl AdvisoryDb {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path).map_err(|e| anyhow!("Failed to read {}: {}", path.display(), e))?;
+        Self::parse(&text).map_err(|e| anyhow!("Invalid advisory database {}: {}", path.display(), e))
+    }
+
+    fn parse(text: &str) -> Result<Self, String> {
+        let json: serde_json::Value = serde_json::from_str(text).map_err(|e| e.to_string())?;
+        let packages = json.as_object().ok_or("expected an object keyed by package name")?;
+        let mut db = HashMap::new();
+        for (package, advisories) in packages {
+            let advisories = advisories.as_array().ok_or_else(|| format!("`{}` must be an array", package))?;
+            let parsed = advisories.iter().map(|advisory| {
+                let field = |name: &str| advisory[name].as_str().map(str::to_string);
+                let id = match &advisory["id"] {
+                    serde_json::Value::Number(n) => n.to_string(),
+                    serde_json::Value::String(s) => s.clone(),
+                    _ => return Err(format!("advisory for `{}` has no id", package)),
+                };
+                let severity = field("severity").and_then(|s| Severity::parse(&s))
+                    .ok_or_else(|| format!("advisory {} has no known severity", id))?;
+                let vulnerable_versions = field("vulnerable_versions")
+                    .ok_or_else(|| format!("advisory {} has no vulnerable_versions", id))?;
+                Ok(Advisory { title: field("title").unwrap_or_else(|| id.clone()), id, severity, vulnerable_versions, url: field("url") })
+            });
+            db.insert(package.clone(), parsed.collect::<Result<Vec<_>, String>>()?);
+        }
+        Ok(AdvisoryDb(db))
+    }
+}
+
+/// A package in node_modules that some of the modules belong to
+#[derive(Debug)]
+struct Package {
+    name: String,
+    version: Option<String>,
+    install_scripts: Vec<(String, String)>,
+}
+
+/// The package directory a module in node_modules belongs to: the nearest directory with a
+/// package.json, without leaving the package (`node_modules/<name>` or
+/// `node_modules/@scope/<name>`)
+fn package_dir(path: &Path) -> Option<&Path> {
+    for dir in path.ancestors().skip(1) {
+        if dir.join("package.json").is_file() {
+            return Some(dir);
+        }
+        let parent = dir.parent()?;
+        let top_level = parent.file_name().is_some_and(|name| name == "node_modules")
+            || (parent.file_name().is_some_and(|name| name.to_string_lossy().starts_with('@'))
+                && parent.parent().and_then(Path::file_name).is_some_and(|name| name == "node_modules"));
+        if top_level {
+            return None;
+        }
+    }
+    None
+}
+
+fn read_package(dir: &Path) -> Option<Package> {
+    let json: serde_json::Value = serde_json::from_str(&fs::read_to_string(dir.join("package.json")).ok()?).ok()?;
+    let name = json["name"].as_str().map(str::to_string)
+        .unwrap_or_else(|| dir.file_name().unwrap_or_default().to_string_lossy().to_string());
+    let install_scripts = ["preinstall", "install", "postinstall"].iter()
+        .filter_map(|script| Some((script.to_string(), json["scripts"][script].as_str()?.to_string())))
+        .collect();
+    Some(Package { name, version: json["version"].as_str().map(str::to_string), install_scripts })
+}
+
+/// Audit the JavaScript modules, most severe findings first
+pub fn audit(modules: &HashMap<String, JsModule>, db: Option<&AdvisoryDb>, project_root: &Path) -> Vec<Finding> {
+    // Modules grouped by package, in a stable order
+    let mut packages: BTreeMap<PathBuf, Vec<&JsModule>> = BTreeMap::new();
+    let mut loose: Vec<&JsModule> = Vec::new();
+    for module in modules.values() {
+        let in_node_modules = module.path.components().any(|c| c.as_os_str() == "node_modules");
+        match package_dir(&module.path).filter(|_| in_node_modules) {
+            Some(dir) => packages.entry(dir.to_path_buf()).or_default().push(module),
+            None => loose.push(module),
+        }
+    }
+
+    let mut findings = Vec::new();
+    for (dir, modules) in &packages {
+        let Some(package) = read_package(dir) else { continue };
+        let finding = |severity, kind| Finding { package: package.name.clone(), version: package.version.clone(), severity, kind };
+        if let (Some(db), Some(version)) = (db, &package.version) {
+            for advisory in db.0.get(&package.name).into_iter().flatten() {
+                if version_matches(&advisory.vulnerable_versions, version) {
+                    findings.push(finding(advisory.severity, FindingKind::Advisory {
+                        id: advisory.id.clone(),
+                        title: advisory.title.clone(),
+                        url: advisory.url.clone(),
+                    }));
+                }
+            }
+        }
+        for (script, command) in &package.install_scripts {
+            findings.push(finding(INSTALL_SCRIPT_SEVERITY, FindingKind::InstallScript { script: script.clone(), command: command.clone() }));
+        }
+        for module in modules {
+            findings.extend(dynamic_code(module, dir).map(|kind| finding(DYNAMIC_CODE_SEVERITY, kind)));
+        }
+    }
+    for module in loose {
+        let package = module.path.strip_prefix(project_root).unwrap_or(&module.path).display().to_string();
+        findings.extend(dynamic_code(module, project_root).map(|kind| Finding {
+            package: package.clone(),
+            version: None,
+            severity: DYNAMIC_CODE_SEVERITY,
+            kind,
+        }));
+    }
+    findings.sort_by(|a, b| b.severity.cmp(&a.severity).then_with(|| a.package.cmp(&b.package)));
+    findings
+}
+
+/// `eval()` / `new Function()` in a module, with its path relative to `base`
+fn dynamic_code<'a>(module: &'a JsModule, base: &'a Path) -> impl Iterator<Item = FindingKind> + 'a {
+    scan_source_for_issues(&module.path, &module.source)
+        .into_iter()
+        .filter(|issue| issue.kind == IssueKind::DynamicCode)
+        .map(move |issue| FindingKind::DynamicCode {
+            file: issue.file.strip_prefix(base).map(Path::to_path_buf).unwrap_or(issue.file),
+            line: issue.line,
+        })
+}
+
+/// `major.minor.patch`, ignoring prerelease and build tags; missing parts are zero
+fn parse_version(version: &str) -> Option<(u64, u64, u64)> {
+    let version = version.trim().trim_start_matches(['v', '=']);
+    let core = version.split(['-', '+']).next()?;
+    let mut parts = core.split('.').map(|part| match part {
+        "x" | "X" | "*" => Some(0),
+        part => part.parse().ok(),
+    });
+    let major = parts.next()??;
+    let minor = parts.next().unwrap_or(Some(0))?;
+    let patch = parts.next().unwrap_or(Some(0))?;
+    Some((major, minor, patch))
+}
+
+/// Whether `version` is in an npm range: `||`-separated sets of comparators (`<`, `<=`,
+/// `>`, `>=`, `=`, `^`, `~`), hyphen ranges and `*`. An unparsable range matches nothing.
+pub fn version_matches(range: &str, version: &str) -> bool {
+    let Some(version) = parse_version(version) else { return false };
+    range.split("||").any(|set| {
+        let set = set.trim();
+        if let Some((low, high)) = set.split_once(" - ") {
+            return matches!((parse_version(low), parse_version(high)), (Some(low), Some(high)) if low <= version && version <= high);
+        }
+        let mut comparators = set.split_whitespace().peekable();
+        comparators.peek().is_some() && comparators.all(|comparator| satisfies(comparator, version))
+    })
+}
+
+fn satisfies(comparator: &str, version: (u64, u64, u64)) -> bool {
+    if comparator == "*" || comparator == "x" {
+        return true;
+    }
+    let (op, bound) = [">=", "<=", ">", "<", "=", "^", "~"]
+        .iter()
+        .find_map(|op| comparator.strip_prefix(op).map(|rest| (*op, rest)))
+        .unwrap_or(("=", comparator));
+    let Some(bound) = parse_version(bound) else { return false };
+    let (major, minor, _) = bound;
+    match op {
+        ">=" => version >= bound,
+        "<=" => version <= bound,
+        ">" => version > bound,
+        "<" => version < bound,
+        // `^1.2.3` allows changes that don't modify the leftmost non-zero part
+        "^" if major > 0 => version >= bound && version < (major + 1, 0, 0),
+        "^" => version >= bound && version < (0, minor + 1, 0),
+        "~" => version >= bound && version < (major, minor + 1, 0),
+        _ => version == bound,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_version_ranges() {
+        assert!(version_matches("<4.17.21", "4.17.20"));
+        assert!(!version_matches("<4.17.21", "4.17.21"));
+        assert!(version_matches(">=2.0.0 <2.3.1 || >=3.0.0-beta <3.0.2", "3.0.1"));
+        assert!(!version_matches(">=2.0.0 <2.3.1", "1.9.9"));
+        assert!(version_matches("^1.2.0", "1.9.0"));
+        assert!(!version_matches("^0.2.0", "0.3.0"));
+        assert!(version_matches("~1.2.0", "1.2.9"));
+        assert!(version_matches("1.0.0 - 1.4.x", "1.3.7"));
+        assert!(version_matches("*", "0.0.1"));
+        assert!(version_matches("<2", "v1.5.0-rc.1"));
+        assert!(!version_matches("", "1.0.0"));
+        assert!(!version_matches("<=1.0.0", "not-a-version"));
+    }
+
+    #[test]
+    fn test_audit_packages() {
+        let root = std::env::temp_dir().join(format!("perry-audit-test-{}", std::process::id()));
+        let lodash = root.join("node_modules/lodash");
+        let scoped = root.join("node_modules/@acme/telemetry");
+        fs::create_dir_all(lodash.join("fp")).unwrap();
+        fs::create_dir_all(&scoped).unwrap();
+        fs::write(lodash.join("package.json"), r#"{"name": "lodash", "version": "4.17.20"}"#).unwrap();
+        fs::write(
+            scoped.join("package.json"),
+            r#"{"name": "@acme/telemetry", "version": "1.0.0", "scripts": {"postinstall": "node setup.js", "test": "jest"}}"#,
+        )
+        .unwrap();
+
+        let module = |path: PathBuf, source: &str| {
+            let specifier = path.to_string_lossy().to_string();
+            (specifier.clone(), JsModule { path, source: source.to_string(), specifier })
+        };
+        let modules: HashMap<String, JsModule> = [
+            module(lodash.join("fp/template.js"), "module.exports = function (s) {\n  return new Function('o', s);\n};\n"),
+            module(scoped.join("index.js"), "export const send = () => {};\n"),
+            module(root.join("legacy.js"), "// eval( is fine in a comment\nexport const x = 1;\n"),
+        ]
+        .into_iter()
+        .collect();
+        let db = AdvisoryDb::parse(
+            r#"{"lodash": [
+                {"id": 1523, "title": "Prototype Pollution", "severity": "high", "vulnerable_versions": "<4.17.21", "url": "https://example.com/1523"},
+                {"id": "GHSA-old", "severity": "critical", "vulnerable_versions": "<4.0.0"}
+            ]}"#,
+        )
+        .unwrap();
+
+        let findings = audit(&modules, Some(&db), &root);
+        let summary: Vec<String> = findings.iter().map(|f| f.to_string()).collect();
+        assert_eq!(
+            summary,
+            [
+                "[high] lodash@4.17.20: Prototype Pollution (1523) https://example.com/1523",
+                "[moderate] @acme/telemetry@1.0.0: `postinstall` script runs `node setup.js`",
+                "[moderate] lodash@4.17.20: eval or new Function in fp/template.js:2",
+            ]
+        );
+        assert_eq!(findings[1].to_json()["kind"], "install_script");
+        assert_eq!(findings[2].to_json()["line"], 2);
+        // Without a database only the package contents are checked
+        assert_eq!(audit(&modules, None, &root).len(), 2);
+
+        assert!(AdvisoryDb::parse(r#"{"x": [{"id": 1, "severity": "urgent", "vulnerable_versions": "*"}]}"#).is_err());
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
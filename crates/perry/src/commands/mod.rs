@@ -1,10 +1,35 @@
 //! CLI command implementations
 
+pub mod bindgen;
+pub mod blocking_lint;
+pub mod build_graph;
 pub mod check;
 pub mod compile;
+pub mod coverage;
+pub mod demangle;
 pub mod deps;
+pub mod dev;
 pub mod doctor;
+pub mod dts;
 pub mod explain;
 pub mod fix_applier;
 pub mod fixer;
+pub mod hir_cache;
+pub mod hot_reload;
 pub mod init;
+pub mod js_audit;
+pub mod module_graph;
+pub mod node;
+pub mod object_cache;
+pub mod openapi;
+pub mod package_exports;
+pub mod perf_lint;
+pub mod progress;
+pub mod project_config;
+pub mod remote_cache;
+pub mod run;
+pub mod sql_lint;
+pub mod structural;
+pub mod target;
+pub mod telemetry;
+pub mod tsconfig;
@@ -0,0 +1,190 @@
+//! Import graph of the natively compiled modules
+//!
+//! Decides the order in which module initializers run and reports import cycles.
+//! The order follows ESM evaluation: a depth-first walk from the entry module in
+//! import declaration order, where each module runs after the modules it imports.
+//! An import that leads back to a module still being visited closes a cycle and is
+//! skipped, so the module that was reached first in the cycle initializes last and
+//! the others observe its bindings through their (live) export globals.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+/// Modules and their imports, in declaration order
+#[derive(Debug, Default)]
+pub struct ModuleGraph {
+    edges: BTreeMap<PathBuf, Vec<PathBuf>>,
+}
+
+impl ModuleGraph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a module with the modules it imports. Duplicates and imports of
+    /// modules outside the graph are ignored when walking.
+    pub fn add_module(&mut self, path: PathBuf, imports: Vec<PathBuf>) {
+        self.edges.insert(path, imports);
+    }
+
+    fn imports_of(&self, path: &Path) -> impl Iterator<Item = &PathBuf> {
+        self.edges
+            .get(path)
+            .into_iter()
+            .flatten()
+            .filter(move |dep| self.edges.contains_key(*dep))
+    }
+
+    /// Initialization order: every module after its imports, `entry` last.
+    /// Modules not reachable from `entry` follow in path order.
+    pub fn init_order(&self, entry: &Path) -> Vec<PathBuf> {
+        let mut order = Vec::new();
+        let mut visited = HashSet::new();
+        let roots = std::iter::once(entry).chain(self.edges.keys().map(|p| p.as_path()));
+        for root in roots {
+            if !self.edges.contains_key(root) || visited.contains(root) {
+                continue;
+            }
+            // Iterative post-order DFS so deep import chains can't overflow the stack
+            visited.insert(root.to_path_buf());
+            let mut stack: Vec<(&Path, usize)> = vec![(root, 0)];
+            while let Some((path, next)) = stack.pop() {
+                match self.imports_of(path).nth(next) {
+                    Some(dep) => {
+                        stack.push((path, next + 1));
+                        if visited.insert(dep.clone()) {
+                            stack.push((dep, 0));
+                        }
+                    }
+                    None => order.push(path.to_path_buf()),
+                }
+            }
+        }
+        // The entry may itself be imported by a module reached through a cycle;
+        // it still has to run last because it is `main`.
+        if let Some(pos) = order.iter().position(|p| p == entry) {
+            let entry = order.remove(pos);
+            order.push(entry);
+        }
+        order
+    }
+
+    /// Import cycles: strongly connected components with more than one module, or a
+    /// module importing itself. Members are sorted by path.
+    pub fn cycles(&self) -> Vec<Vec<PathBuf>> {
+        // Tarjan's algorithm
+        struct State<'g> {
+            index: HashMap<&'g Path, usize>,
+            lowlink: HashMap<&'g Path, usize>,
+            on_stack: HashSet<&'g Path>,
+            stack: Vec<&'g Path>,
+            components: Vec<Vec<PathBuf>>,
+        }
+
+        fn connect<'g>(graph: &'g ModuleGraph, node: &'g Path, state: &mut State<'g>) {
+            let index = state.index.len();
+            state.index.insert(node, index);
+            state.lowlink.insert(node, index);
+            state.stack.push(node);
+            state.on_stack.insert(node);
+
+            for dep in graph.imports_of(node) {
+                let dep = dep.as_path();
+                if !state.index.contains_key(dep) {
+                    connect(graph, dep, state);
+                    let low = state.lowlink[node].min(state.lowlink[dep]);
+                    state.lowlink.insert(node, low);
+                } else if state.on_stack.contains(dep) {
+                    let low = state.lowlink[node].min(state.index[dep]);
+                    state.lowlink.insert(node, low);
+                }
+            }
+
+            if state.lowlink[node] == state.index[node] {
+                let mut component = Vec::new();
+                while let Some(member) = state.stack.pop() {
+                    state.on_stack.remove(member);
+                    component.push(member.to_path_buf());
+                    if member == node {
+                        break;
+                    }
+                }
+                let self_import = component.len() == 1 && graph.imports_of(node).any(|d| d == node);
+                if component.len() > 1 || self_import {
+                    component.sort();
+                    state.components.push(component);
+                }
+            }
+        }
+
+        let mut state = State {
+            index: HashMap::new(),
+            lowlink: HashMap::new(),
+            on_stack: HashSet::new(),
+            stack: Vec::new(),
+            components: Vec::new(),
+        };
+        for node in self.edges.keys() {
+            if !state.index.contains_key(node.as_path()) {
+                connect(self, node, &mut state);
+            }
+        }
+        state.components.sort();
+        state.components
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn graph(edges: &[(&str, &[&str])]) -> ModuleGraph {
+        let mut graph = ModuleGraph::new();
+        for (path, imports) in edges {
+            graph.add_module(PathBuf::from(path), imports.iter().map(PathBuf::from).collect());
+        }
+        graph
+    }
+
+    fn paths(names: &[&str]) -> Vec<PathBuf> {
+        names.iter().map(PathBuf::from).collect()
+    }
+
+    #[test]
+    fn test_init_order_dependencies_first() {
+        let g = graph(&[
+            ("main", &["a", "c"]),
+            ("a", &["b"]),
+            ("b", &[]),
+            ("c", &["b", "node_modules/x"]),
+        ]);
+        assert_eq!(g.init_order(Path::new("main")), paths(&["b", "a", "c", "main"]));
+        assert!(g.cycles().is_empty());
+    }
+
+    #[test]
+    fn test_init_order_with_cycle() {
+        // main imports a, a imports b, b imports a: b runs first (as in ESM) and
+        // sees a's bindings only once a has initialized
+        let g = graph(&[("main", &["a", "b"]), ("a", &["b"]), ("b", &["a"])]);
+        assert_eq!(g.init_order(Path::new("main")), paths(&["b", "a", "main"]));
+        assert_eq!(g.cycles(), vec![paths(&["a", "b"])]);
+
+        let g = graph(&[("main", &["b"]), ("a", &["b"]), ("b", &["a"])]);
+        assert_eq!(g.init_order(Path::new("main")), paths(&["a", "b", "main"]));
+    }
+
+    #[test]
+    fn test_entry_in_cycle_runs_last() {
+        let g = graph(&[("main", &["a"]), ("a", &["main"]), ("z", &[])]);
+        assert_eq!(g.init_order(Path::new("main")), paths(&["a", "z", "main"]));
+        assert_eq!(g.cycles(), vec![paths(&["a", "main"])]);
+    }
+
+    #[test]
+    fn test_self_import_is_a_cycle() {
+        let g = graph(&[("main", &["main"])]);
+        assert_eq!(g.init_order(Path::new("main")), paths(&["main"]));
+        assert_eq!(g.cycles(), vec![paths(&["main"])]);
+    }
+}
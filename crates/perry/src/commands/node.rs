@@ -0,0 +1,375 @@
+//! Node command - a `node` compatible front end for package.json scripts
+//!
+//! `perry node <file> [args]` accepts the node command line that npm scripts commonly use
+//! (`-e`/`-p`, `--env-file`, `--test`, `--max-old-space-size`, loader flags for TS runners)
+//! so `"start": "node dist/index.js"` can become `"start": "perry node src/index.ts"`.
+//! Scripts are compiled with the JS runtime enabled: TypeScript compiles natively and
+//! plain JavaScript (the entry file or anything it imports) runs on V8. Binaries are
+//! cached like `perry run`'s, so a script is recompiled whenever it or any TypeScript or
+//! JavaScript module it imports changes; node has no flag to force that.
+
+use anyhow::{anyhow, Result};
+use clap::Args;
+use serde_json::json;
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use super::run::{cached_compile, exec, scratch_dir};
+use crate::OutputFormat;
+
+#[derive(Args, Debug)]
+pub struct NodeArgs {
+    /// node command line: [node flags] [script] [script args]
+    #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+    pub args: Vec<String>,
+}
+
+/// Compile flags for everything `perry node` runs
+const COMPILE_FLAGS: &[&str] = &["--enable-js-runtime"];
+
+/// Loaders that only exist to run TypeScript on node; perry compiles TS itself
+const TS_LOADERS: &[&str] = &[
+    "ts-node/register",
+    "ts-node/esm",
+    "tsx",
+    "tsx/cjs",
+    "tsx/esm",
+    "esbuild-register",
+    "@swc-node/register",
+    "@swc/register",
+];
+
+/// node flags that don't change what a compiled program does
+const IGNORED_FLAGS: &[&str] = &[
+    "--enable-source-maps",
+    "--no-warnings",
+    "--no-deprecation",
+    "--trace-warnings",
+    "--trace-deprecation",
+    "--trace-uncaught",
+    "--preserve-symlinks",
+    "--preserve-symlinks-main",
+];
+
+/// A node command line, parsed into what perry needs
+#[derive(Debug, Default, PartialEq)]
+struct NodeInvocation {
+    /// Entry script; None with `-e`/`-p` and `--test`
+    script: Option<PathBuf>,
+    /// Code passed with `-e`/`-p`
+    eval: Option<String>,
+    /// `-p`: print the result of `eval`
+    print: bool,
+    /// `--env-file` files, in order
+    env_files: Vec<PathBuf>,
+    /// `--test`: run test files instead of a script
+    test: bool,
+    /// `--max-old-space-size`, in MiB
+    max_heap_mb: Option<u64>,
+    /// Script arguments, or the files and directories to test
+    args: Vec<String>,
+}
+
+/// Split `--flag=value` / `--flag value` / `-f value`
+fn flag_value(flag: &str, inline: Option<&str>, rest: &mut std::slice::Iter<'_, String>) -> Result<String> {
+    match inline {
+        Some(value) => Ok(value.to_string()),
+        None => rest.next().cloned().ok_or_else(|| anyhow!("{} requires an argument", flag)),
+    }
+}
+
+fn parse_invocation(args: &[String]) -> Result<NodeInvocation> {
+    let mut invocation = NodeInvocation::default();
+    let mut rest = args.iter();
+    while let Some(arg) = rest.next() {
+        if arg == "--" {
+            invocation.args.extend(rest.by_ref().cloned());
+            break;
+        }
+        if !arg.starts_with('-') {
+            // The first positional ends node's own flags: it is the script (or, with
+            // --test, the first file to test) and everything after it belongs to the script
+            if invocation.test || invocation.eval.is_some() {
+                invocation.args.push(arg.clone());
+                invocation.args.extend(rest.by_ref().cloned());
+            } else {
+                invocation.script = Some(PathBuf::from(arg));
+                invocation.args.extend(rest.by_ref().cloned());
+            }
+            break;
+        }
+        let (flag, inline) = match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => (flag, Some(value)),
+            _ => (arg.as_str(), None),
+        };
+        match flag {
+            "-e" | "--eval" => invocation.eval = Some(flag_value(flag, inline, &mut rest)?),
+            "-p" | "--print" => {
+                invocation.eval = Some(flag_value(flag, inline, &mut rest)?);
+                invocation.print = true;
+            }
+            "--env-file" => invocation.env_files.push(PathBuf::from(flag_value(flag, inline, &mut rest)?)),
+            "--test" => invocation.test = true,
+            "--max-old-space-size" => {
+                let value = flag_value(flag, inline, &mut rest)?;
+                let mb = value.parse().map_err(|_| anyhow!("Invalid --max-old-space-size: {}", value))?;
+                invocation.max_heap_mb = Some(mb);
+            }
+            "-r" | "--require" | "--loader" | "--experimental-loader" | "--import" => {
+                let module = flag_value(flag, inline, &mut rest)?;
+                if module == "dotenv/config" {
+                    invocation.env_files.push(PathBuf::from(".env"));
+                } else if !TS_LOADERS.contains(&module.as_str()) {
+                    return Err(anyhow!("perry node: `{} {}` is not supported", flag, module));
+                }
+            }
+            _ if IGNORED_FLAGS.contains(&flag) || flag.starts_with("--experimental-") => {}
+            _ => return Err(anyhow!("perry node: unsupported node option {}", arg)),
+        }
+    }
+    Ok(invocation)
+}
+
+/// Variables of a dotenv file: `KEY=value` lines with optional `export`, `#` comments,
+/// and single-, double- (with `\n` escapes) or un-quoted values
+fn parse_env_file(contents: &str) -> Vec<(String, String)> {
+    let mut vars = Vec::new();
+    for line in contents.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let Some((key, value)) = line.split_once('=') else { continue };
+        let key = key.trim();
+        if key.is_empty() {
+            continue;
+        }
+        let value = value.trim();
+        let value = if let Some(quoted) = value.strip_prefix('"').and_then(|v| v.rsplit_once('"')) {
+            quoted.0.replace("\\n", "\n")
+        } else if let Some(quoted) = value.strip_prefix('\'').and_then(|v| v.rsplit_once('\'')) {
+            quoted.0.to_string()
+        } else {
+            // Unquoted values end at an inline comment
+            value.split(" #").next().unwrap_or("").trim_end().to_string()
+        };
+        vars.push((key.to_string(), value));
+    }
+    vars
+}
+
+/// Load `--env-file`s into the environment the program inherits. As in node, variables
+/// already set take precedence over the files
+fn load_env_files(files: &[PathBuf]) -> Result<()> {
+    for file in files {
+        let contents = fs::read_to_string(file)
+            .map_err(|e| anyhow!("{}: {}", file.display(), e))?;
+        for (key, value) in parse_env_file(&contents) {
+            if std::env::var_os(&key).is_none() {
+                std::env::set_var(key, value);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Write `-e`/`-p` code to a file so it compiles like any other script
+fn eval_script(code: &str, print: bool) -> Result<PathBuf> {
+    let source = if print {
+        // Evaluated by V8, so `-p "let x = 1; x"` prints the last statement's value like node
+        format!("console.log(eval({}));\n", serde_json::to_string(code)?)
+    } else {
+        format!("{}\n", code)
+    };
+    let mut hasher = DefaultHasher::new();
+    source.hash(&mut hasher);
+    let path = scratch_dir()?.join(format!("eval-{:016x}.js", hasher.finish()));
+    if !path.is_file() {
+        fs::write(&path, source)
+            .map_err(|e| anyhow!("Failed to write {}: {}", path.display(), e))?;
+    }
+    Ok(path)
+}
+
+/// node's default test file patterns, extended to TypeScript: `*.test.*`, `*-test.*`,
+/// `*_test.*`, `test-*.*`, `test.*`, and any script under a `test` directory
+fn is_test_file(relative: &Path) -> bool {
+    let is_script = matches!(
+        relative.extension().and_then(|e| e.to_str()),
+        Some("js" | "mjs" | "cjs" | "ts" | "mts" | "cts")
+    );
+    if !is_script {
+        return false;
+    }
+    let name = relative.file_name().and_then(|n| n.to_str()).unwrap_or("");
+    let stem = name.split('.').next().unwrap_or("");
+    let in_test_dir = relative
+        .parent()
+        .map(|dir| dir.components().any(|c| c.as_os_str() == "test"))
+        .unwrap_or(false);
+    in_test_dir
+        || name.contains(".test.")
+        || stem.ends_with("-test")
+        || stem.ends_with("_test")
+        || stem.starts_with("test-")
+        || stem == "test"
+}
+
+/// Test files under `root`, skipping node_modules and hidden directories
+fn discover_tests(root: &Path) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = walkdir::WalkDir::new(root)
+        .into_iter()
+        .filter_entry(|entry| {
+            let name = entry.file_name().to_string_lossy();
+            entry.depth() == 0 || !(name == "node_modules" || name.starts_with('.'))
+        })
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_file())
+        .map(|entry| entry.into_path())
+        .filter(|path| is_test_file(path.strip_prefix(root).unwrap_or(path)))
+        .collect();
+    files.sort();
+    files
+}
+
+/// `--test`: compile and run each test file as its own program. A file passes when it
+/// exits with status 0 (a failed `node:assert` throws, so the process exits non-zero)
+fn run_tests(paths: &[String], format: OutputFormat, verbose: u8) -> Result<()> {
+    let mut files = if paths.is_empty() { discover_tests(Path::new(".")) } else { Vec::new() };
+    for path in paths.iter().map(PathBuf::from) {
+        if path.is_dir() {
+            files.extend(discover_tests(&path));
+        } else {
+            files.push(path);
+        }
+    }
+
+    let mut results = Vec::new();
+    for file in &files {
+        let outcome = cached_compile(file, false, COMPILE_FLAGS, format, verbose)
+            .and_then(|exe| Ok(Command::new(exe).status()?));
+        let (ok, error) = match outcome {
+            Ok(status) => (status.success(), (!status.success()).then(|| format!("exited with {}", status))),
+            Err(e) => (false, Some(e.to_string())),
+        };
+        if let OutputFormat::Text = format {
+            if results.is_empty() {
+                println!("TAP version 13");
+            }
+            let status = if ok { "ok" } else { "not ok" };
+            println!("{} {} - {}", status, results.len() + 1, file.display());
+            if let Some(error) = &error {
+                println!("  ---\n  error: {:?}\n  ...", error);
+            }
+        }
+        results.push((file, ok, error));
+    }
+
+    let passed = results.iter().filter(|(_, ok, _)| *ok).count();
+    let failed = results.len() - passed;
+    match format {
+        OutputFormat::Text => {
+            println!("1..{}", results.len());
+            println!("# tests {}", results.len());
+            println!("# pass {}", passed);
+            println!("# fail {}", failed);
+        }
+        OutputFormat::Json => {
+            let tests: Vec<_> = results
+                .iter()
+                .map(|(file, ok, error)| json!({ "file": file.display().to_string(), "ok": ok, "error": error }))
+                .collect();
+            let report = json!({ "tests": tests, "pass": passed, "fail": failed });
+            println!("{}", serde_json::to_string_pretty(&report)?);
+        }
+    }
+    super::telemetry::set("tests", json!({ "pass": passed, "fail": failed }));
+    if failed > 0 {
+        super::telemetry::finish(Some(&anyhow!("{} of {} test file(s) failed", failed, results.len())));
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+pub fn run(args: NodeArgs, format: OutputFormat, _use_color: bool, verbose: u8) -> Result<()> {
+    let invocation = parse_invocation(&args.args)?;
+    load_env_files(&invocation.env_files)?;
+    if let Some(mb) = invocation.max_heap_mb {
+        // An explicit PERRY_MAX_HEAP wins, like the compile-time --max-heap default it overrides
+        if std::env::var_os("PERRY_MAX_HEAP").is_none() {
+            std::env::set_var("PERRY_MAX_HEAP", format!("{}M", mb));
+        }
+    }
+
+    if invocation.test {
+        return run_tests(&invocation.args, format, verbose);
+    }
+    let script = match (&invocation.eval, &invocation.script) {
+        (Some(code), _) => eval_script(code, invocation.print)?,
+        (None, Some(script)) => script.clone(),
+        (None, None) => return Err(anyhow!("perry node: no script given (the REPL is not supported)")),
+    };
+    let exe = cached_compile(&script, false, COMPILE_FLAGS, format, verbose)?;
+    exec(&exe, &invocation.args)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> NodeInvocation {
+        let args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
+        parse_invocation(&args).unwrap()
+    }
+
+    #[test]
+    fn test_parses_node_command_lines() {
+        let invocation = parse(&["--env-file=.env", "--enable-source-maps", "-r", "ts-node/register", "src/main.ts", "--port", "80"]);
+        assert_eq!(invocation.script, Some(PathBuf::from("src/main.ts")));
+        assert_eq!(invocation.env_files, vec![PathBuf::from(".env")]);
+        assert_eq!(invocation.args, vec!["--port", "80"]);
+
+        let invocation = parse(&["-p", "1 + 1", "x"]);
+        assert_eq!(invocation.eval.as_deref(), Some("1 + 1"));
+        assert!(invocation.print);
+        assert_eq!(invocation.args, vec!["x"]);
+
+        let invocation = parse(&["--test", "--max-old-space-size=4096", "test/", "a.test.ts"]);
+        assert!(invocation.test);
+        assert_eq!(invocation.max_heap_mb, Some(4096));
+        assert_eq!(invocation.args, vec!["test/", "a.test.ts"]);
+
+        let args = vec!["--inspect".to_string(), "a.js".to_string()];
+        assert!(parse_invocation(&args).is_err());
+    }
+
+    #[test]
+    fn test_parses_env_files() {
+        let vars = parse_env_file(
+            "# comment\nexport PORT=8080\nNAME=\"perry\\nnode\"\nRAW='a # b'\nURL=http://x # inline\n\nbroken\n",
+        );
+        assert_eq!(
+            vars,
+            vec![
+                ("PORT".to_string(), "8080".to_string()),
+                ("NAME".to_string(), "perry\nnode".to_string()),
+                ("RAW".to_string(), "a # b".to_string()),
+                ("URL".to_string(), "http://x".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_recognizes_test_files() {
+        for path in ["a.test.ts", "src/b-test.js", "c_test.mjs", "test-d.cts", "test.js", "test/unit/e.ts"] {
+            assert!(is_test_file(Path::new(path)), "{}", path);
+        }
+        for path in ["src/main.ts", "testing.ts", "test/fixture.json", "latest.ts"] {
+            assert!(!is_test_file(Path::new(path)), "{}", path);
+        }
+    }
+}
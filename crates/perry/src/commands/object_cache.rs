@@ -0,0 +1,168 @@
+//! On-disk cache of compiled object code, so unchanged modules skip Cranelift codegen
+//!
+//! Each module compiles to one object file, and each gets one entry under
+//! `<project>/.perry-cache/obj/`, named after a hash of its path and holding the key it was
+//! written under plus the object bytes. The key hashes the module's final HIR (after
+//! inlining and the other whole-program transforms, so an edit that changes what another
+//! module inlines invalidates that module too), the compiler binary, and every other input
+//! the caller passes: the module's source text, for line numbers, and the settings and
+//! cross-module facts codegen reads (imported classes, entry-module options, Cranelift flags).
+//! An unchanged module's cached object is written out and linked as is.
+//!
+//! A module that changed still reuses the machine code of its unchanged functions: next to
+//! its object, each entry keeps a [`FunctionCache`] of the module's functions, keyed by
+//! their Cranelift IR, that the module's next codegen loads. Only functions whose IR
+//! differs go through Cranelift again.
+//!
+//! Like [`HirCache`](super::hir_cache::HirCache), reading and writing are best-effort: a
+//! missing, unreadable or outdated entry is a miss, a failed write is ignored, and only the
+//! newest object for each module is kept.
+
+use perry_codegen::FunctionCache;
+use perry_hir::Module as HirModule;
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+
+use super::hir_cache::CACHE_DIR;
+
+#[derive(Debug)]
+pub struct ObjectCache {
+    dir: PathBuf,
+    pub hits: usize,
+    pub misses: usize,
+}
+
+/// Identifies the running compiler: its version, plus the executable's size and
+/// modification time, so a rebuilt perry of the same version doesn't reuse old objects
+fn compiler_identity() -> (String, Option<(u64, Option<std::time::SystemTime>)>) {
+    let binary = std::env::current_exe()
+        .and_then(fs::metadata)
+        .ok()
+        .map(|meta| (meta.len(), meta.modified().ok()));
+    (env!("CARGO_PKG_VERSION").to_string(), binary)
+}
+
+impl ObjectCache {
+    pub fn new(project_root: &Path) -> Self {
+        Self { dir: project_root.join(CACHE_DIR).join("obj"), hits: 0, misses: 0 }
+    }
+
+    /// Key for a module's object code: its HIR, the compiler, and the other codegen
+    /// `inputs`. None if the HIR can't be serialized, which disables caching for it.
+    pub fn key(module: &HirModule, inputs: &impl Hash) -> Option<u64> {
+        let hir = bincode::serialize(module).ok()?;
+        let mut hasher = DefaultHasher::new();
+        compiler_identity().hash(&mut hasher);
+        hir.hash(&mut hasher);
+        inputs.hash(&mut hasher);
+        Some(hasher.finish())
+    }
+
+    fn entry_path(&self, module_path: &Path) -> PathBuf {
+        let mut hasher = DefaultHasher::new();
+        module_path.hash(&mut hasher);
+        self.dir.join(format!("{:016x}.bin", hasher.finish()))
+    }
+
+    fn functions_path(&self, module_path: &Path) -> PathBuf {
+        self.entry_path(module_path).with_extension("fn.bin")
+    }
+
+    /// Hash of the running compiler, which the machine code of cached functions depends on
+    fn compiler_key() -> u64 {
+        let mut hasher = DefaultHasher::new();
+        compiler_identity().hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// The cached object code for `module_path` if it was stored under `key`
+    pub fn load(&mut self, module_path: &Path, key: u64) -> Option<Vec<u8>> {
+        let object = fs::read(self.entry_path(module_path))
+            .ok()
+            .and_then(|bytes| bincode::deserialize::<(u64, Vec<u8>)>(&bytes).ok())
+            .filter(|(stored, _)| *stored == key)
+            .map(|(_, object)| object);
+        if object.is_some() {
+            self.hits += 1;
+        } else {
+            self.misses += 1;
+        }
+        object
+    }
+
+    /// Replace the entry for `module_path`
+    pub fn store(&self, module_path: &Path, key: u64, object: &[u8]) {
+        if let Ok(bytes) = bincode::serialize(&(key, object)) {
+            self.write(&self.entry_path(module_path), &bytes);
+        }
+    }
+
+    /// The machine code of `module_path`'s functions from its last codegen by this compiler
+    pub fn load_functions(&self, module_path: &Path) -> FunctionCache {
+        fs::read(self.functions_path(module_path))
+            .ok()
+            .and_then(|bytes| bincode::deserialize::<(u64, Vec<u8>)>(&bytes).ok())
+            .filter(|(compiler, _)| *compiler == Self::compiler_key())
+            .map_or_else(FunctionCache::default, |(_, functions)| FunctionCache::from_bytes(&functions))
+    }
+
+    /// Replace the functions cached for `module_path`
+    pub fn store_functions(&self, module_path: &Path, functions: &FunctionCache) {
+        if let Ok(bytes) = bincode::serialize(&(Self::compiler_key(), functions.to_bytes())) {
+            self.write(&self.functions_path(module_path), &bytes);
+        }
+    }
+
+    fn write(&self, path: &Path, bytes: &[u8]) {
+        // Write then rename, so a concurrent build never reads half an entry
+        let tmp = path.with_extension(format!("tmp{}", std::process::id()));
+        let written = fs::create_dir_all(&self.dir)
+            .and_then(|_| fs::write(&tmp, bytes))
+            .and_then(|_| fs::rename(&tmp, path));
+        if written.is_err() {
+            let _ = fs::remove_file(&tmp);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip_and_invalidation() {
+        let root = std::env::temp_dir().join(format!("perry-object-cache-{}", std::process::id()));
+        let mut cache = ObjectCache::new(&root);
+        let lower = |source: &str| {
+            let ast = perry_parser::parse_typescript(source, "main.ts").unwrap();
+            perry_hir::lower_module_with_options(&ast, "main", "/src/main.ts", &Default::default()).unwrap()
+        };
+        let module = lower("export function twice(x: number) { return x * 2; }");
+        let path = Path::new("/src/main.ts");
+
+        let key = ObjectCache::key(&module, &("settings", false)).unwrap();
+        assert_eq!(ObjectCache::key(&module, &("settings", false)), Some(key));
+        assert!(cache.load(path, key).is_none());
+        cache.store(path, key, b"\x7fELF object");
+        assert_eq!(cache.load(path, key).as_deref(), Some(&b"\x7fELF object"[..]));
+        assert_eq!((cache.hits, cache.misses), (1, 1));
+
+        // Changing the HIR or any other input misses
+        let edited = lower("export function twice(x: number) { return x * 3; }");
+        assert_ne!(ObjectCache::key(&edited, &("settings", false)), Some(key));
+        assert_ne!(ObjectCache::key(&module, &("settings", true)), Some(key));
+        assert!(cache.load(Path::new("/src/other.ts"), key).is_none());
+
+        // Functions are kept beside the object; a missing or foreign entry is an empty cache
+        assert_eq!(cache.load_functions(path).to_bytes(), FunctionCache::default().to_bytes());
+        fs::write(cache.functions_path(path), b"not an entry").unwrap();
+        assert_eq!(cache.load_functions(path).to_bytes(), FunctionCache::default().to_bytes());
+        cache.store_functions(path, &FunctionCache::default());
+        assert!(cache.functions_path(path).exists());
+        assert_eq!(cache.load(path, key).as_deref(), Some(&b"\x7fELF object"[..]));
+
+        let _ = fs::remove_dir_all(&root);
+    }
+}
@@ -0,0 +1,478 @@
+//! OpenAPI 3.1 document for the Fastify routes of a program (`perry compile --openapi`)
+//!
+//! Routes come from `Module::http_routes`. A route is described by its schema options
+//! where it has them (`schema.body`, `querystring`, `params`, `headers`, `response`,
+//! plus `summary`, `description`, `tags` and `operationId`), and otherwise by its
+//! TypeScript types: the `{ Body, Querystring, Params, Headers, Reply }` route type
+//! argument or `FastifyRequest<...>` handler parameter, and the handler's return type.
+//! Interfaces, type aliases and classes named by those types become
+//! `components.schemas` entries. Path parameters `:id` are written as `{id}`.
+
+use perry_hir::{Expr, HttpRoute, Module as HirModule, Stmt, UnaryOp};
+use perry_types::{LiteralType, ObjectType, Type};
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::PathBuf;
+
+/// Methods an `app.all()` route is documented under
+const ALL_METHODS: [&str; 7] = ["get", "post", "put", "delete", "patch", "head", "options"];
+
+/// `info` section of the document
+#[derive(Debug, Clone)]
+pub struct DocumentInfo {
+    pub title: String,
+    pub version: String,
+}
+
+/// Build the OpenAPI document for every route registered in `modules`
+pub fn generate(modules: &HashMap<PathBuf, HirModule>, info: &DocumentInfo) -> Value {
+    let mut ordered: Vec<(&PathBuf, &HirModule)> = modules.iter().collect();
+    ordered.sort_by(|a, b| a.0.cmp(b.0));
+    let mut types = TypeResolver::new(ordered.iter().map(|(_, m)| *m).collect());
+
+    let mut paths: BTreeMap<String, Map<String, Value>> = BTreeMap::new();
+    for (_, module) in &ordered {
+        for route in &module.http_routes {
+            let operation = describe_route(route, module, &mut types);
+            let entry = paths.entry(openapi_path(&route.path)).or_default();
+            if route.method == "ALL" {
+                for method in ALL_METHODS {
+                    entry.insert(method.to_string(), operation.clone());
+                }
+            } else {
+                entry.insert(route.method.to_lowercase(), operation);
+            }
+        }
+    }
+
+    let mut document = json!({
+        "openapi": "3.1.0",
+        "info": { "title": info.title, "version": info.version },
+        "paths": paths,
+    });
+    if !types.components.is_empty() {
+        document["components"] = json!({ "schemas": types.components });
+    }
+    document
+}
+
+/// `/users/:id` -> `/users/{id}`
+fn openapi_path(path: &str) -> String {
+    let segments: Vec<String> = path
+        .split('/')
+        .map(|segment| match segment.strip_prefix(':') {
+            Some(name) => format!("{{{}}}", name),
+            None if segment == "*" => "{*}".to_string(),
+            None => segment.to_string(),
+        })
+        .collect();
+    let joined = segments.join("/");
+    if joined.is_empty() { "/".to_string() } else { joined }
+}
+
+/// Names of the path parameters in a route path
+fn path_params(path: &str) -> Vec<String> {
+    path.split('/')
+        .filter_map(|segment| match segment.strip_prefix(':') {
+            Some(name) => Some(name.to_string()),
+            None if segment == "*" => Some("*".to_string()),
+            None => None,
+        })
+        .collect()
+}
+
+fn describe_route(route: &HttpRoute, module: &HirModule, types: &mut TypeResolver) -> Value {
+    let schema = route.schema.as_ref().and_then(|expr| expr_to_json(expr, module));
+    let schema_part = |name: &str| schema.as_ref().and_then(|s| s.get(name)).filter(|v| v.is_object());
+
+    // Types from the route generic / FastifyRequest parameter, or a named handler's signature
+    let (mut request_type, mut reply_type) = (route.request_type.clone(), route.reply_type.clone());
+    if let Some(handler) = route.handler_func.and_then(|id| module.functions.iter().find(|f| f.id == id)) {
+        if request_type.is_none() {
+            if let Some(Type::Generic { base, type_args }) = handler.params.first().map(|p| &p.ty) {
+                if base.ends_with("FastifyRequest") {
+                    request_type = type_args.first().cloned();
+                }
+            }
+        }
+        reply_type = Some(handler.return_type.clone());
+    }
+    let request_members = request_type.map(|ty| types.members(&ty)).unwrap_or_default();
+
+    let mut operation = Map::new();
+    if let Some(schema) = &schema {
+        for key in ["operationId", "summary", "description", "tags", "deprecated"] {
+            if let Some(value) = schema.get(key) {
+                operation.insert(key.to_string(), value.clone());
+            }
+        }
+    }
+
+    let mut parameters = Vec::new();
+    let params_schema = schema_part("params").cloned().or_else(|| {
+        request_members.get("Params").map(|(ty, _)| types.inline_schema(ty))
+    });
+    for name in path_params(&route.path) {
+        let param_schema = params_schema.as_ref()
+            .and_then(|s| s.get("properties"))
+            .and_then(|p| p.get(&name))
+            .cloned()
+            .unwrap_or_else(|| json!({ "type": "string" }));
+        parameters.push(json!({ "name": name, "in": "path", "required": true, "schema": param_schema }));
+    }
+    for (location, schema_key, type_key) in [("query", "querystring", "Querystring"), ("header", "headers", "Headers")] {
+        let part = schema_part(schema_key).or_else(|| if schema_key == "querystring" { schema_part("query") } else { None }).cloned();
+        let part = part.or_else(|| request_members.get(type_key).map(|(ty, _)| types.inline_schema(ty)));
+        let Some(part) = part else { continue };
+        let required: HashSet<&str> = part.get("required")
+            .and_then(Value::as_array)
+            .map(|names| names.iter().filter_map(Value::as_str).collect())
+            .unwrap_or_default();
+        if let Some(properties) = part.get("properties").and_then(Value::as_object) {
+            for (name, prop_schema) in properties {
+                parameters.push(json!({
+                    "name": name,
+                    "in": location,
+                    "required": required.contains(name.as_str()),
+                    "schema": prop_schema,
+                }));
+            }
+        }
+    }
+    if !parameters.is_empty() {
+        operation.insert("parameters".to_string(), Value::Array(parameters));
+    }
+
+    let body_schema = schema_part("body").cloned()
+        .or_else(|| request_members.get("Body").map(|(ty, _)| types.schema(ty)));
+    if let Some(body_schema) = body_schema {
+        operation.insert("requestBody".to_string(), json!({
+            "required": true,
+            "content": { "application/json": { "schema": body_schema } },
+        }));
+    }
+
+    let mut responses = Map::new();
+    if let Some(Value::Object(by_status)) = schema_part("response") {
+        for (status, response_schema) in by_status {
+            responses.insert(status.clone(), json_response(Some(response_schema.clone())));
+        }
+    } else {
+        let reply = request_members.get("Reply").map(|(ty, _)| ty.clone()).or(reply_type);
+        let content = reply.as_ref()
+            .map(unwrap_promise)
+            .filter(|ty| !matches!(ty, Type::Any | Type::Unknown | Type::Void | Type::Never))
+            .map(|ty| types.schema(ty));
+        responses.insert("200".to_string(), json_response(content));
+    }
+    operation.insert("responses".to_string(), Value::Object(responses));
+
+    Value::Object(operation)
+}
+
+fn json_response(schema: Option<Value>) -> Value {
+    let description = schema.as_ref()
+        .and_then(|s| s.get("description"))
+        .cloned()
+        .unwrap_or_else(|| json!("Default Response"));
+    match schema {
+        Some(schema) => json!({ "description": description, "content": { "application/json": { "schema": schema } } }),
+        None => json!({ "description": description }),
+    }
+}
+
+fn unwrap_promise(ty: &Type) -> &Type {
+    match ty {
+        Type::Promise(inner) => unwrap_promise(inner),
+        Type::Generic { base, type_args } if base == "Promise" && type_args.len() == 1 => unwrap_promise(&type_args[0]),
+        other => other,
+    }
+}
+
+/// A literal HIR expression as JSON. Identifiers are followed to the module-level
+/// `const` that initializes them; anything else computed at run time yields None.
+fn expr_to_json(expr: &Expr, module: &HirModule) -> Option<Value> {
+    Some(match expr {
+        Expr::Null => Value::Null,
+        Expr::Bool(b) => Value::Bool(*b),
+        Expr::Integer(n) => json!(n),
+        Expr::Number(n) => json!(n),
+        Expr::String(s) => Value::String(s.clone()),
+        Expr::Unary { op: UnaryOp::Neg, operand } => match operand.as_ref() {
+            Expr::Integer(n) => json!(-n),
+            Expr::Number(n) => json!(-n),
+            _ => return None,
+        },
+        Expr::Array(items) => Value::Array(items.iter().filter_map(|item| expr_to_json(item, module)).collect()),
+        Expr::Object(props) => Value::Object(
+            props.iter()
+                .filter_map(|(key, value)| expr_to_json(value, module).map(|v| (key.clone(), v)))
+                .collect(),
+        ),
+        Expr::LocalGet(id) => {
+            let init = module.init.iter().find_map(|stmt| match stmt {
+                Stmt::Let { id: let_id, mutable: false, init: Some(init), .. } if let_id == id => Some(init),
+                _ => None,
+            })?;
+            return expr_to_json(init, module);
+        }
+        _ => return None,
+    })
+}
+
+/// A named type the document can describe
+enum NamedType<'a> {
+    Interface(&'a perry_hir::Interface),
+    Alias(&'a perry_hir::TypeAlias),
+    Class(&'a perry_hir::Class),
+}
+
+/// Turns TypeScript types into JSON Schemas, collecting named types as components
+struct TypeResolver<'a> {
+    modules: Vec<&'a HirModule>,
+    components: BTreeMap<String, Value>,
+    /// Named types whose component is being built (guards recursive types)
+    pending: HashSet<String>,
+}
+
+impl<'a> TypeResolver<'a> {
+    fn new(modules: Vec<&'a HirModule>) -> Self {
+        Self { modules, components: BTreeMap::new(), pending: HashSet::new() }
+    }
+
+    fn lookup(&self, name: &str) -> Option<NamedType<'a>> {
+        for &module in &self.modules {
+            if let Some(interface) = module.interfaces.iter().find(|i| i.name == name) {
+                return Some(NamedType::Interface(interface));
+            }
+            if let Some(alias) = module.type_aliases.iter().find(|a| a.name == name && a.type_params.is_empty()) {
+                return Some(NamedType::Alias(alias));
+            }
+            if let Some(class) = module.classes.iter().find(|c| c.name == name && c.type_params.is_empty()) {
+                return Some(NamedType::Class(class));
+            }
+        }
+        None
+    }
+
+    /// Members of an object-like type: (type, optional) by name
+    fn members(&self, ty: &Type) -> HashMap<String, (Type, bool)> {
+        match ty {
+            Type::Object(obj) => obj.properties.iter()
+                .map(|(name, prop)| (name.clone(), (prop.ty.clone(), prop.optional)))
+                .collect(),
+            Type::Named(name) => match self.lookup(name) {
+                Some(NamedType::Interface(interface)) => {
+                    let mut members: HashMap<String, (Type, bool)> = HashMap::new();
+                    for parent in &interface.extends {
+                        members.extend(self.members(parent));
+                    }
+                    for prop in &interface.properties {
+                        members.insert(prop.name.clone(), (prop.ty.clone(), prop.optional));
+                    }
+                    members
+                }
+                Some(NamedType::Alias(alias)) => self.members(&alias.ty),
+                Some(NamedType::Class(class)) => class.fields.iter()
+                    .filter(|f| !f.is_private)
+                    .map(|f| (f.name.clone(), (f.ty.clone(), false)))
+                    .collect(),
+                None => HashMap::new(),
+            },
+            _ => HashMap::new(),
+        }
+    }
+
+    /// Schema with the top level spelled out rather than referenced, for types whose
+    /// properties become individual parameters
+    fn inline_schema(&mut self, ty: &Type) -> Value {
+        let members = self.members(ty);
+        if members.is_empty() {
+            return self.schema(ty);
+        }
+        let mut ordered: Vec<(String, (Type, bool))> = members.into_iter().collect();
+        ordered.sort_by(|a, b| a.0.cmp(&b.0));
+        self.object_schema(ordered.iter().map(|(name, (ty, optional))| (name.as_str(), ty, *optional)), None)
+    }
+
+    fn object_schema<'t>(&mut self, props: impl Iterator<Item = (&'t str, &'t Type, bool)>, index: Option<&Type>) -> Value {
+        let mut properties = Map::new();
+        let mut required = Vec::new();
+        for (name, ty, optional) in props {
+            properties.insert(name.to_string(), self.schema(ty));
+            if !optional {
+                required.push(Value::String(name.to_string()));
+            }
+        }
+        let mut schema = json!({ "type": "object", "properties": properties });
+        if !required.is_empty() {
+            schema["required"] = Value::Array(required);
+        }
+        if let Some(index) = index {
+            schema["additionalProperties"] = self.schema(index);
+        }
+        schema
+    }
+
+    fn object_type_schema(&mut self, obj: &ObjectType) -> Value {
+        let mut props: Vec<(&String, &perry_types::PropertyInfo)> = obj.properties.iter().collect();
+        props.sort_by(|a, b| a.0.cmp(b.0));
+        self.object_schema(props.into_iter().map(|(name, prop)| (name.as_str(), &prop.ty, prop.optional)), obj.index_signature.as_deref())
+    }
+
+    fn schema(&mut self, ty: &Type) -> Value {
+        match ty {
+            Type::Null => json!({ "type": "null" }),
+            Type::Boolean => json!({ "type": "boolean" }),
+            Type::Number => json!({ "type": "number" }),
+            Type::Int32 | Type::BigInt => json!({ "type": "integer" }),
+            Type::String => json!({ "type": "string" }),
+            Type::Array(elem) => json!({ "type": "array", "items": self.schema(elem) }),
+            Type::Tuple(elems) => {
+                let items: Vec<Value> = elems.iter().map(|e| self.schema(e)).collect();
+                json!({ "type": "array", "prefixItems": items, "minItems": elems.len(), "maxItems": elems.len() })
+            }
+            Type::Object(obj) => self.object_type_schema(obj),
+            Type::Literal(lit) => json!({ "const": literal_json(lit) }),
+            Type::Union(members) => {
+                let members: Vec<&Type> = members.iter().filter(|m| !matches!(m, Type::Void)).collect();
+                if members.len() == 1 {
+                    return self.schema(members[0]);
+                }
+                if !members.is_empty() && members.iter().all(|m| matches!(m, Type::Literal(_))) {
+                    let values: Vec<Value> = members.iter()
+                        .filter_map(|m| match m { Type::Literal(lit) => Some(literal_json(lit)), _ => None })
+                        .collect();
+                    return json!({ "enum": values });
+                }
+                let any_of: Vec<Value> = members.iter().map(|m| self.schema(m)).collect();
+                json!({ "anyOf": any_of })
+            }
+            Type::Intersection(members) => {
+                let all_of: Vec<Value> = members.iter().map(|m| self.schema(m)).collect();
+                json!({ "allOf": all_of })
+            }
+            Type::Promise(inner) => self.schema(inner),
+            Type::Named(name) => self.named_schema(name),
+            Type::Generic { base, type_args } => match (base.as_str(), type_args.as_slice()) {
+                ("Array" | "ReadonlyArray" | "Set", [elem]) => json!({ "type": "array", "items": self.schema(elem) }),
+                ("Record" | "Map", [_, value]) => json!({ "type": "object", "additionalProperties": self.schema(value) }),
+                ("Promise", [inner]) => self.schema(inner),
+                ("Partial", [inner]) => {
+                    let mut schema = self.inline_schema(inner);
+                    if let Some(obj) = schema.as_object_mut() {
+                        obj.remove("required");
+                    }
+                    schema
+                }
+                _ => json!({}),
+            },
+            _ => json!({}),
+        }
+    }
+
+    /// `$ref` to the component for a named type, building the component on first use
+    fn named_schema(&mut self, name: &str) -> Value {
+        if name == "Date" {
+            return json!({ "type": "string", "format": "date-time" });
+        }
+        let reference = json!({ "$ref": format!("#/components/schemas/{}", name) });
+        if self.components.contains_key(name) || self.pending.contains(name) {
+            return reference;
+        }
+        let Some(named) = self.lookup(name) else {
+            return json!({});
+        };
+        self.pending.insert(name.to_string());
+        let schema = match named {
+            NamedType::Interface(interface) => {
+                let own = self.object_schema(
+                    interface.properties.iter().map(|p| (p.name.as_str(), &p.ty, p.optional)),
+                    None,
+                );
+                if interface.extends.is_empty() {
+                    own
+                } else {
+                    let mut all_of: Vec<Value> = interface.extends.iter().map(|parent| self.schema(parent)).collect();
+                    all_of.push(own);
+                    json!({ "allOf": all_of })
+                }
+            }
+            NamedType::Alias(alias) => self.schema(&alias.ty),
+            NamedType::Class(class) => {
+                let fields: Vec<&perry_hir::ClassField> = class.fields.iter().filter(|f| !f.is_private).collect();
+                self.object_schema(fields.into_iter().map(|f| (f.name.as_str(), &f.ty, false)), None)
+            }
+        };
+        self.pending.remove(name);
+        self.components.insert(name.to_string(), schema);
+        reference
+    }
+}
+
+fn literal_json(lit: &LiteralType) -> Value {
+    match lit {
+        LiteralType::String(s) => json!(s),
+        LiteralType::Number(n) => json!(n),
+        LiteralType::Boolean(b) => json!(b),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn document(source: &str) -> Value {
+        let ast_module = perry_parser::parse_typescript(source, "app.ts").unwrap();
+        let module = perry_hir::lower_module(&ast_module, "app.ts", "/tmp/app.ts").unwrap();
+        let modules: HashMap<PathBuf, HirModule> = [(PathBuf::from("/tmp/app.ts"), module)].into_iter().collect();
+        generate(&modules, &DocumentInfo { title: "app".to_string(), version: "1.0.0".to_string() })
+    }
+
+    #[test]
+    fn test_routes_from_schemas_and_types() {
+        let doc = document(
+            "import Fastify, { FastifyRequest } from 'fastify';\n\
+             interface User { id: number; name: string; email?: string; }\n\
+             const createUser = { body: { type: 'object', required: ['name'], properties: { name: { type: 'string' } } } };\n\
+             const app = Fastify();\n\
+             app.get('/users/:id', async (request: FastifyRequest<{ Params: { id: string } }>): Promise<User> => {\n\
+               return { id: 1, name: 'a' };\n\
+             });\n\
+             app.post('/users', { schema: createUser }, async (request, reply) => ({}));\n\
+             app.get<{ Querystring: { limit: number; q?: string } }>('/search', async () => []);\n",
+        );
+        assert_eq!(doc["openapi"], "3.1.0");
+
+        let get_user = &doc["paths"]["/users/{id}"]["get"];
+        assert_eq!(get_user["parameters"][0], json!({ "name": "id", "in": "path", "required": true, "schema": { "type": "string" } }));
+        assert_eq!(
+            get_user["responses"]["200"]["content"]["application/json"]["schema"],
+            json!({ "$ref": "#/components/schemas/User" })
+        );
+        assert_eq!(
+            doc["components"]["schemas"]["User"],
+            json!({
+                "type": "object",
+                "properties": { "id": { "type": "number" }, "name": { "type": "string" }, "email": { "type": "string" } },
+                "required": ["id", "name"],
+            })
+        );
+
+        let create = &doc["paths"]["/users"]["post"];
+        assert_eq!(create["requestBody"]["content"]["application/json"]["schema"]["required"], json!(["name"]));
+        assert_eq!(create["responses"]["200"], json!({ "description": "Default Response" }));
+
+        let search = &doc["paths"]["/search"]["get"]["parameters"];
+        assert_eq!(search[0], json!({ "name": "limit", "in": "query", "required": true, "schema": { "type": "number" } }));
+        assert_eq!(search[1]["name"], "q");
+        assert_eq!(search[1]["required"], false);
+    }
+
+    #[test]
+    fn test_openapi_path() {
+        assert_eq!(openapi_path("/users/:id/posts/:postId"), "/users/{id}/posts/{postId}");
+        assert_eq!(openapi_path("/"), "/");
+        assert_eq!(path_params("/files/*"), vec!["*"]);
+    }
+}
@@ -0,0 +1,300 @@
+//! package.json `exports` resolution (Node's PACKAGE_EXPORTS_RESOLVE)
+//!
+//! Handles the string / array / condition-object shorthands for the main entry,
+//! subpath keys (`"./feature"`), subpath patterns (`"./features/*.js"`, the key
+//! with the longest prefix wins), nested conditions matched in key order against
+//! the active conditions plus `"default"`, array fallbacks, and `null` targets
+//! that block a subpath.
+//!
+//! Mirrored in perry-jsruntime/src/package_exports.rs for the V8 module loader.
+
+use serde::de::{Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::Deserialize;
+use std::fmt;
+
+/// Conditions for `import` / `export ... from`
+pub const IMPORT_CONDITIONS: &[&str] = &["node", "import"];
+/// Conditions for `require()`
+pub const REQUIRE_CONDITIONS: &[&str] = &["node", "require"];
+
+/// An `exports` value. Object key order is kept: conditions are tried in the
+/// order the package lists them.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExportsValue {
+    Null,
+    Target(String),
+    Array(Vec<ExportsValue>),
+    Map(Vec<(String, ExportsValue)>),
+    /// Numbers and booleans (always invalid)
+    Other,
+}
+
+/// Result of looking up a subpath in an `exports` map
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExportsResolution {
+    /// Package-relative path of the entry file (`./dist/index.js`)
+    Resolved(String),
+    /// The subpath isn't exported, is blocked by `null`, or no condition matched
+    NotExported,
+    /// The `exports` field is malformed
+    Invalid(String),
+}
+
+/// Read the `exports` field of a package.json. Ok(None) when there is none.
+pub fn read_exports(package_json: &str) -> Result<Option<ExportsValue>, serde_json::Error> {
+    #[derive(Deserialize)]
+    struct Manifest {
+        #[serde(default)]
+        exports: Option<ExportsValue>,
+    }
+    Ok(serde_json::from_str::<Manifest>(package_json)?.exports)
+}
+
+/// Resolve `subpath` (`"."` or `"./name"`) through an `exports` value
+pub fn resolve_exports(exports: &ExportsValue, subpath: &str, conditions: &[&str]) -> ExportsResolution {
+    let entries = match exports {
+        ExportsValue::Map(entries) if entries.iter().any(|(k, _)| k.starts_with('.')) => {
+            if !entries.iter().all(|(k, _)| k.starts_with('.')) {
+                return ExportsResolution::Invalid(
+                    "\"exports\" cannot mix subpath keys (\"./...\") with conditions".to_string(),
+                );
+            }
+            entries
+        }
+        // Shorthand: the whole value is the main entry
+        _ => {
+            if subpath != "." {
+                return ExportsResolution::NotExported;
+            }
+            return target_to_resolution(resolve_target(exports, None, conditions));
+        }
+    };
+
+    if !subpath.contains('*') {
+        if let Some((_, target)) = entries.iter().find(|(k, _)| k == subpath) {
+            return target_to_resolution(resolve_target(target, None, conditions));
+        }
+    }
+
+    // Subpath patterns: longest prefix before `*` first, then the longest key
+    let mut best: Option<(&str, &ExportsValue, &str)> = None;
+    for (key, target) in entries {
+        let Some((prefix, suffix)) = key.split_once('*') else {
+            continue;
+        };
+        if suffix.contains('*') || subpath == prefix || subpath.len() < key.len() - 1 {
+            continue;
+        }
+        let Some(matched) = subpath.strip_prefix(prefix).and_then(|rest| rest.strip_suffix(suffix)) else {
+            continue;
+        };
+        let better = match best {
+            None => true,
+            Some((best_key, _, _)) => pattern_key_compare(key, best_key),
+        };
+        if better {
+            best = Some((key, target, matched));
+        }
+    }
+    match best {
+        Some((_, target, matched)) => target_to_resolution(resolve_target(target, Some(matched), conditions)),
+        None => ExportsResolution::NotExported,
+    }
+}
+
+/// Whether pattern key `a` takes precedence over `b`
+fn pattern_key_compare(a: &str, b: &str) -> bool {
+    let prefix_a = a.find('*').unwrap_or(a.len());
+    let prefix_b = b.find('*').unwrap_or(b.len());
+    prefix_a > prefix_b || (prefix_a == prefix_b && a.len() > b.len())
+}
+
+enum TargetResult {
+    Found(String),
+    /// `null`, or an empty array
+    Blocked,
+    /// No condition matched
+    Unmatched,
+}
+
+fn target_to_resolution(result: Result<TargetResult, String>) -> ExportsResolution {
+    match result {
+        Ok(TargetResult::Found(path)) => ExportsResolution::Resolved(path),
+        Ok(TargetResult::Blocked) | Ok(TargetResult::Unmatched) => ExportsResolution::NotExported,
+        Err(e) => ExportsResolution::Invalid(e),
+    }
+}
+
+fn resolve_target(target: &ExportsValue, pattern_match: Option<&str>, conditions: &[&str]) -> Result<TargetResult, String> {
+    match target {
+        ExportsValue::Target(path) => {
+            if !path.starts_with("./") {
+                return Err(format!("invalid target \"{}\": targets must start with \"./\"", path));
+            }
+            if has_invalid_segment(&path[2..]) {
+                return Err(format!("invalid target \"{}\"", path));
+            }
+            match pattern_match {
+                Some(matched) => {
+                    if has_invalid_segment(matched) {
+                        return Err(format!("invalid subpath \"{}\" for pattern target \"{}\"", matched, path));
+                    }
+                    Ok(TargetResult::Found(path.replace('*', matched)))
+                }
+                None => Ok(TargetResult::Found(path.clone())),
+            }
+        }
+        ExportsValue::Map(entries) => {
+            for (condition, value) in entries {
+                if condition == "default" || conditions.contains(&condition.as_str()) {
+                    match resolve_target(value, pattern_match, conditions)? {
+                        TargetResult::Unmatched => continue,
+                        result => return Ok(result),
+                    }
+                }
+            }
+            Ok(TargetResult::Unmatched)
+        }
+        ExportsValue::Array(items) => {
+            let mut last_error = None;
+            for item in items {
+                match resolve_target(item, pattern_match, conditions) {
+                    Ok(TargetResult::Unmatched) => continue,
+                    Ok(result) => return Ok(result),
+                    Err(e) => last_error = Some(e),
+                }
+            }
+            match last_error {
+                Some(e) => Err(e),
+                None => Ok(TargetResult::Blocked),
+            }
+        }
+        ExportsValue::Null => Ok(TargetResult::Blocked),
+        ExportsValue::Other => Err("invalid target: expected a string, array, object or null".to_string()),
+    }
+}
+
+/// `.`, `..` and `node_modules` segments may not escape the package
+fn has_invalid_segment(path: &str) -> bool {
+    path.split(['/', '\\'])
+        .any(|segment| segment == "." || segment == ".." || segment.eq_ignore_ascii_case("node_modules"))
+}
+
+impl<'de> Deserialize<'de> for ExportsValue {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ExportsVisitor;
+
+        impl<'de> Visitor<'de> for ExportsVisitor {
+            type Value = ExportsValue;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a package.json exports value")
+            }
+
+            fn visit_str<E>(self, v: &str) -> Result<ExportsValue, E> {
+                Ok(ExportsValue::Target(v.to_string()))
+            }
+
+            fn visit_unit<E>(self) -> Result<ExportsValue, E> {
+                Ok(ExportsValue::Null)
+            }
+
+            fn visit_none<E>(self) -> Result<ExportsValue, E> {
+                Ok(ExportsValue::Null)
+            }
+
+            fn visit_bool<E>(self, _: bool) -> Result<ExportsValue, E> {
+                Ok(ExportsValue::Other)
+            }
+
+            fn visit_i64<E>(self, _: i64) -> Result<ExportsValue, E> {
+                Ok(ExportsValue::Other)
+            }
+
+            fn visit_u64<E>(self, _: u64) -> Result<ExportsValue, E> {
+                Ok(ExportsValue::Other)
+            }
+
+            fn visit_f64<E>(self, _: f64) -> Result<ExportsValue, E> {
+                Ok(ExportsValue::Other)
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<ExportsValue, A::Error> {
+                let mut items = Vec::new();
+                while let Some(item) = seq.next_element()? {
+                    items.push(item);
+                }
+                Ok(ExportsValue::Array(items))
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<ExportsValue, A::Error> {
+                let mut entries = Vec::new();
+                while let Some((key, value)) = map.next_entry::<String, ExportsValue>()? {
+                    entries.push((key, value));
+                }
+                Ok(ExportsValue::Map(entries))
+            }
+        }
+
+        deserializer.deserialize_any(ExportsVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolve(exports: &str, subpath: &str, conditions: &[&str]) -> ExportsResolution {
+        let exports = read_exports(&format!("{{ \"exports\": {} }}", exports)).unwrap().unwrap();
+        resolve_exports(&exports, subpath, conditions)
+    }
+
+    fn resolved(path: &str) -> ExportsResolution {
+        ExportsResolution::Resolved(path.to_string())
+    }
+
+    #[test]
+    fn test_main_entry_shorthands() {
+        assert_eq!(resolve(r#""./index.js""#, ".", IMPORT_CONDITIONS), resolved("./index.js"));
+        assert_eq!(resolve(r#""./index.js""#, "./other", IMPORT_CONDITIONS), ExportsResolution::NotExported);
+        let conditional = r#"{ "require": "./index.cjs", "import": "./index.mjs" }"#;
+        assert_eq!(resolve(conditional, ".", IMPORT_CONDITIONS), resolved("./index.mjs"));
+        assert_eq!(resolve(conditional, ".", REQUIRE_CONDITIONS), resolved("./index.cjs"));
+    }
+
+    #[test]
+    fn test_conditions_follow_key_order() {
+        // "node" is listed before "import", so it wins even for imports
+        let exports = r#"{ ".": { "node": { "import": "./node.mjs", "require": "./node.cjs" }, "import": "./browser.mjs", "default": "./fallback.js" } }"#;
+        assert_eq!(resolve(exports, ".", IMPORT_CONDITIONS), resolved("./node.mjs"));
+        assert_eq!(resolve(exports, ".", REQUIRE_CONDITIONS), resolved("./node.cjs"));
+        assert_eq!(resolve(exports, ".", &[]), resolved("./fallback.js"));
+        assert_eq!(resolve(r#"{ "browser": "./b.js" }"#, ".", IMPORT_CONDITIONS), ExportsResolution::NotExported);
+    }
+
+    #[test]
+    fn test_subpaths_and_patterns() {
+        let exports = r#"{
+            ".": "./index.js",
+            "./utils": { "import": "./esm/utils.js", "require": "./cjs/utils.js" },
+            "./features/*": "./dist/features/*.js",
+            "./features/internal/*": null,
+            "./icons/*.svg": "./assets/*.svg",
+            "./package.json": "./package.json"
+        }"#;
+        assert_eq!(resolve(exports, "./utils", REQUIRE_CONDITIONS), resolved("./cjs/utils.js"));
+        assert_eq!(resolve(exports, "./features/a/b", IMPORT_CONDITIONS), resolved("./dist/features/a/b.js"));
+        assert_eq!(resolve(exports, "./features/internal/x", IMPORT_CONDITIONS), ExportsResolution::NotExported);
+        assert_eq!(resolve(exports, "./icons/home.svg", IMPORT_CONDITIONS), resolved("./assets/home.svg"));
+        assert_eq!(resolve(exports, "./icons/home.png", IMPORT_CONDITIONS), ExportsResolution::NotExported);
+        assert_eq!(resolve(exports, "./dist/index.js", IMPORT_CONDITIONS), ExportsResolution::NotExported);
+    }
+
+    #[test]
+    fn test_array_fallbacks_and_invalid_targets() {
+        assert_eq!(resolve(r#"["bad", "./ok.js"]"#, ".", IMPORT_CONDITIONS), resolved("./ok.js"));
+        assert!(matches!(resolve(r#""../escape.js""#, ".", IMPORT_CONDITIONS), ExportsResolution::Invalid(_)));
+        assert!(matches!(resolve(r#"{ "./*": "./lib/*" }"#, "./../x", IMPORT_CONDITIONS), ExportsResolution::Invalid(_)));
+        assert!(matches!(resolve(r#"{ ".": "./a.js", "import": "./b.js" }"#, ".", IMPORT_CONDITIONS), ExportsResolution::Invalid(_)));
+    }
+}
@@ -0,0 +1,544 @@
+//! Opt-in performance lints (`perry check --perf`)
+//!
+//! F001: an `await` inside a loop over an array makes every iteration wait for the
+//! previous one. When a `for...of` body only declares constants and awaits (no
+//! assignments, no other calls, no control flow), the iterations are independent and
+//! the loop is rewritten to `await Promise.all(items.map(async (item) => { ... }))`.
+//! Indexed loops (`for (let i = 0; i < items.length; i++)`) are reported without a fix.
+//!
+//! Arrays are recognized syntactically: array literals, variables and parameters
+//! annotated as `T[]` / `Array<T>` or initialized with an array, and the results of
+//! array methods, `Array.from` and `Object.keys/values/entries`.
+
+use perry_diagnostics::{
+    Applicability, Diagnostic, DiagnosticBuilder, DiagnosticCode, FileId, Span, Suggestion,
+};
+use perry_parser::swc_ecma_ast::*;
+use perry_parser::Spanned;
+use std::collections::HashMap;
+
+/// Array methods that return a new array
+const ARRAY_RETURNING_METHODS: &[&str] = &[
+    "map", "filter", "slice", "concat", "flat", "flatMap", "reverse", "sort", "toSorted",
+    "toReversed", "toSpliced", "with", "fill", "splice",
+];
+
+/// Performance lints for one module
+pub struct PerfLinter<'a> {
+    file_id: FileId,
+    source: &'a str,
+    /// Variables and parameters in scope and whether they are known to hold an array,
+    /// innermost scope last
+    scopes: Vec<HashMap<String, bool>>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> PerfLinter<'a> {
+    /// Run the performance lints over a module
+    pub fn check(module: &Module, file_id: FileId, source: &'a str) -> Vec<Diagnostic> {
+        let mut linter = PerfLinter {
+            file_id,
+            source,
+            scopes: vec![HashMap::new()],
+            diagnostics: Vec::new(),
+        };
+        for item in &module.body {
+            match item {
+                ModuleItem::Stmt(stmt) => linter.check_stmt(stmt),
+                ModuleItem::ModuleDecl(ModuleDecl::ExportDecl(export)) => linter.check_decl(&export.decl),
+                ModuleItem::ModuleDecl(ModuleDecl::ExportDefaultDecl(export)) => match &export.decl {
+                    DefaultDecl::Fn(f) => linter.check_function(&f.function),
+                    DefaultDecl::Class(c) => linter.check_class(&c.class),
+                    DefaultDecl::TsInterfaceDecl(_) => {}
+                },
+                ModuleItem::ModuleDecl(ModuleDecl::ExportDefaultExpr(export)) => linter.check_expr(&export.expr),
+                _ => {}
+            }
+        }
+        linter.diagnostics
+    }
+
+    // ---- Walking ----
+
+    fn check_stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Decl(decl) => self.check_decl(decl),
+            Stmt::Expr(expr_stmt) => self.check_expr(&expr_stmt.expr),
+            Stmt::Block(block) => self.check_block(&block.stmts),
+            Stmt::Return(ret) => {
+                if let Some(arg) = &ret.arg {
+                    self.check_expr(arg);
+                }
+            }
+            Stmt::If(if_stmt) => {
+                self.check_expr(&if_stmt.test);
+                self.check_stmt(&if_stmt.cons);
+                if let Some(alt) = &if_stmt.alt {
+                    self.check_stmt(alt);
+                }
+            }
+            Stmt::While(s) => self.check_stmt(&s.body),
+            Stmt::DoWhile(s) => self.check_stmt(&s.body),
+            Stmt::For(s) => {
+                self.scopes.push(HashMap::new());
+                if let Some(VarDeclOrExpr::VarDecl(var)) = &s.init {
+                    self.check_var_decl(var);
+                }
+                self.check_indexed_loop(s);
+                self.check_stmt(&s.body);
+                self.scopes.pop();
+            }
+            Stmt::ForIn(s) => self.check_stmt(&s.body),
+            Stmt::ForOf(s) => {
+                self.check_expr(&s.right);
+                self.check_for_of(s);
+                self.check_stmt(&s.body);
+            }
+            Stmt::Labeled(s) => self.check_stmt(&s.body),
+            Stmt::Switch(s) => {
+                for case in &s.cases {
+                    self.check_block(&case.cons);
+                }
+            }
+            Stmt::Try(s) => {
+                self.check_block(&s.block.stmts);
+                if let Some(handler) = &s.handler {
+                    self.check_block(&handler.body.stmts);
+                }
+                if let Some(finalizer) = &s.finalizer {
+                    self.check_block(&finalizer.stmts);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn check_block(&mut self, stmts: &[Stmt]) {
+        self.scopes.push(HashMap::new());
+        for stmt in stmts {
+            self.check_stmt(stmt);
+        }
+        self.scopes.pop();
+    }
+
+    fn check_decl(&mut self, decl: &Decl) {
+        match decl {
+            Decl::Var(var) => self.check_var_decl(var),
+            Decl::Fn(f) => self.check_function(&f.function),
+            Decl::Class(c) => self.check_class(&c.class),
+            _ => {}
+        }
+    }
+
+    fn check_var_decl(&mut self, var: &VarDecl) {
+        for declarator in &var.decls {
+            if let Some(init) = &declarator.init {
+                self.check_expr(init);
+            }
+            let Pat::Ident(binding) = &declarator.name else { continue };
+            let annotated = binding.type_ann.as_ref().map_or(false, |ann| is_array_type(&ann.type_ann));
+            let initialized = binding.type_ann.is_none()
+                && declarator.init.as_ref().map_or(false, |init| self.is_array_expr(init));
+            self.declare(binding.id.sym.to_string(), annotated || initialized);
+        }
+    }
+
+    fn check_function(&mut self, function: &Function) {
+        self.scopes.push(HashMap::new());
+        for param in &function.params {
+            self.check_param(&param.pat);
+        }
+        if let Some(body) = &function.body {
+            self.check_block(&body.stmts);
+        }
+        self.scopes.pop();
+    }
+
+    fn check_arrow(&mut self, arrow: &ArrowExpr) {
+        self.scopes.push(HashMap::new());
+        for pat in &arrow.params {
+            self.check_param(pat);
+        }
+        match &*arrow.body {
+            BlockStmtOrExpr::BlockStmt(block) => self.check_block(&block.stmts),
+            BlockStmtOrExpr::Expr(body) => self.check_expr(body),
+        }
+        self.scopes.pop();
+    }
+
+    fn check_param(&mut self, pat: &Pat) {
+        let binding = match pat {
+            Pat::Assign(assign) => match &*assign.left {
+                Pat::Ident(binding) => Some(binding),
+                _ => None,
+            },
+            Pat::Ident(binding) => Some(binding),
+            _ => None,
+        };
+        if let Some(binding) = binding {
+            let is_array = binding.type_ann.as_ref().map_or(false, |ann| is_array_type(&ann.type_ann));
+            self.declare(binding.id.sym.to_string(), is_array);
+        }
+    }
+
+    fn check_class(&mut self, class: &Class) {
+        for member in &class.body {
+            match member {
+                ClassMember::Method(method) => self.check_function(&method.function),
+                ClassMember::PrivateMethod(method) => self.check_function(&method.function),
+                ClassMember::Constructor(ctor) => {
+                    if let Some(body) = &ctor.body {
+                        self.check_block(&body.stmts);
+                    }
+                }
+                ClassMember::StaticBlock(block) => self.check_block(&block.body.stmts),
+                _ => {}
+            }
+        }
+    }
+
+    /// Only function bodies can hold loops, so expressions are walked for nested functions
+    fn check_expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Arrow(arrow) => self.check_arrow(arrow),
+            Expr::Fn(f) => self.check_function(&f.function),
+            Expr::Class(c) => self.check_class(&c.class),
+            Expr::Call(call) => {
+                if let Callee::Expr(callee) = &call.callee {
+                    self.check_expr(callee);
+                }
+                for arg in &call.args {
+                    self.check_expr(&arg.expr);
+                }
+            }
+            Expr::New(new) => {
+                for arg in new.args.iter().flatten() {
+                    self.check_expr(&arg.expr);
+                }
+            }
+            Expr::Assign(assign) => self.check_expr(&assign.right),
+            Expr::Paren(p) => self.check_expr(&p.expr),
+            Expr::Await(a) => self.check_expr(&a.arg),
+            Expr::Member(m) => self.check_expr(&m.obj),
+            Expr::Cond(c) => {
+                self.check_expr(&c.cons);
+                self.check_expr(&c.alt);
+            }
+            Expr::Array(arr) => {
+                for elem in arr.elems.iter().flatten() {
+                    self.check_expr(&elem.expr);
+                }
+            }
+            Expr::Object(obj) => {
+                for prop in &obj.props {
+                    if let PropOrSpread::Prop(prop) = prop {
+                        match &**prop {
+                            Prop::KeyValue(kv) => self.check_expr(&kv.value),
+                            Prop::Method(m) => self.check_function(&m.function),
+                            _ => {}
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // ---- Lints ----
+
+    /// `for (const x of items) { ... await ... }`
+    fn check_for_of(&mut self, for_of: &ForOfStmt) {
+        if for_of.is_await || !self.is_array_expr(&for_of.right) {
+            return;
+        }
+        let Some(await_expr) = find_await_in_stmt(&for_of.body) else { return };
+        let mut diagnostic = self.await_in_loop_diagnostic(await_expr);
+        if let Some(suggestion) = self.promise_all_suggestion(for_of) {
+            diagnostic = diagnostic.with_suggestion(suggestion);
+        }
+        self.diagnostics.push(diagnostic.build());
+    }
+
+    /// `for (let i = 0; i < items.length; i++) { ... await ... }`
+    fn check_indexed_loop(&mut self, for_stmt: &ForStmt) {
+        let bounded_by_array = match for_stmt.test.as_deref().map(strip_parens) {
+            Some(Expr::Bin(bin)) if matches!(bin.op, BinaryOp::Lt | BinaryOp::LtEq) => match strip_parens(&bin.right) {
+                Expr::Member(member) => matches!(&member.prop, MemberProp::Ident(prop) if prop.sym.as_ref() == "length")
+                    && self.is_array_expr(&member.obj),
+                _ => false,
+            },
+            _ => false,
+        };
+        if !bounded_by_array {
+            return;
+        }
+        if let Some(await_expr) = find_await_in_stmt(&for_stmt.body) {
+            let diagnostic = self.await_in_loop_diagnostic(await_expr);
+            self.diagnostics.push(diagnostic.build());
+        }
+    }
+
+    fn await_in_loop_diagnostic(&self, await_expr: &AwaitExpr) -> DiagnosticBuilder {
+        Diagnostic::new(DiagnosticCode::AwaitInLoop, "`await` inside a loop over an array runs the iterations one at a time")
+            .with_span(self.span(await_expr))
+            .with_primary_label(self.span(await_expr), "each iteration waits for the previous one to finish")
+            .with_help("if the iterations don't depend on each other, start them together and wait for all of them with Promise.all")
+    }
+
+    /// The `Promise.all` rewrite, when the loop body has no effects besides its awaits
+    fn promise_all_suggestion(&self, for_of: &ForOfStmt) -> Option<Suggestion> {
+        let ForHead::VarDecl(var) = &for_of.left else { return None };
+        if var.kind == VarDeclKind::Var || var.decls.len() != 1 {
+            return None;
+        }
+        let stmts: Vec<&Stmt> = match &*for_of.body {
+            Stmt::Block(block) => block.stmts.iter().collect(),
+            stmt => vec![stmt],
+        };
+        if !stmts.iter().all(|stmt| is_independent_stmt(stmt)) {
+            return None;
+        }
+
+        let binding = self.text(&var.decls[0].name)?;
+        let items = self.text(&*for_of.right)?;
+        let items = match strip_parens(&for_of.right) {
+            Expr::Ident(_) | Expr::Member(_) | Expr::Call(_) | Expr::Array(_) => items.to_string(),
+            _ => format!("({})", items),
+        };
+        let body = match &*for_of.body {
+            Stmt::Block(_) => self.text(&*for_of.body)?.to_string(),
+            stmt => format!("{{ {} }}", self.text(stmt)?),
+        };
+        let replacement = format!("await Promise.all({}.map(async ({}) => {}));", items, binding, body);
+        Some(Suggestion::new(
+            "run the iterations concurrently with Promise.all",
+            self.span(for_of),
+            replacement,
+            Applicability::MachineApplicable,
+        ))
+    }
+
+    // ---- Arrays ----
+
+    fn declare(&mut self, name: String, is_array: bool) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name, is_array);
+        }
+    }
+
+    fn is_array_name(&self, name: &str) -> bool {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name).copied()).unwrap_or(false)
+    }
+
+    fn is_array_expr(&self, expr: &Expr) -> bool {
+        match strip_parens(expr) {
+            Expr::Array(_) => true,
+            Expr::Ident(ident) => self.is_array_name(ident.sym.as_ref()),
+            Expr::TsAs(ts_as) => is_array_type(&ts_as.type_ann),
+            Expr::TsNonNull(non_null) => self.is_array_expr(&non_null.expr),
+            Expr::Call(call) => {
+                let Callee::Expr(callee) = &call.callee else { return false };
+                let Expr::Member(member) = &**callee else { return false };
+                let MemberProp::Ident(method) = &member.prop else { return false };
+                let method = method.sym.as_ref();
+                match strip_parens(&member.obj) {
+                    Expr::Ident(obj) if obj.sym.as_ref() == "Array" => method == "from" || method == "of",
+                    Expr::Ident(obj) if obj.sym.as_ref() == "Object" => matches!(method, "keys" | "values" | "entries"),
+                    obj => ARRAY_RETURNING_METHODS.contains(&method) && self.is_array_expr(obj),
+                }
+            }
+            _ => false,
+        }
+    }
+
+    // ---- Source ----
+
+    fn text(&self, node: &impl Spanned) -> Option<&'a str> {
+        let span = node.span();
+        self.source.get(span.lo.0.saturating_sub(1) as usize..span.hi.0.saturating_sub(1) as usize)
+    }
+
+    fn span(&self, node: &impl Spanned) -> Span {
+        let span = node.span();
+        Span::new(self.file_id, span.lo.0.saturating_sub(1), span.hi.0.saturating_sub(1))
+    }
+}
+
+/// `T[]`, `readonly T[]`, `Array<T>`, `ReadonlyArray<T>`
+fn is_array_type(ty: &TsType) -> bool {
+    match ty {
+        TsType::TsArrayType(_) => true,
+        TsType::TsTypeOperator(op) => is_array_type(&op.type_ann),
+        TsType::TsParenthesizedType(p) => is_array_type(&p.type_ann),
+        TsType::TsTypeRef(r) => matches!(&r.type_name, TsEntityName::Ident(ident)
+            if matches!(ident.sym.as_ref(), "Array" | "ReadonlyArray")),
+        _ => false,
+    }
+}
+
+fn strip_parens(expr: &Expr) -> &Expr {
+    match expr {
+        Expr::Paren(p) => strip_parens(&p.expr),
+        _ => expr,
+    }
+}
+
+/// First `await` that runs as part of a statement (nested functions are not searched)
+fn find_await_in_stmt(stmt: &Stmt) -> Option<&AwaitExpr> {
+    match stmt {
+        Stmt::Expr(s) => find_await(&s.expr),
+        Stmt::Decl(Decl::Var(var)) => var.decls.iter().filter_map(|d| d.init.as_deref()).find_map(find_await),
+        Stmt::Block(block) => block.stmts.iter().find_map(find_await_in_stmt),
+        Stmt::Return(ret) => ret.arg.as_deref().and_then(find_await),
+        Stmt::Throw(s) => find_await(&s.arg),
+        Stmt::If(s) => find_await(&s.test)
+            .or_else(|| find_await_in_stmt(&s.cons))
+            .or_else(|| s.alt.as_deref().and_then(find_await_in_stmt)),
+        Stmt::While(s) => find_await(&s.test).or_else(|| find_await_in_stmt(&s.body)),
+        Stmt::DoWhile(s) => find_await_in_stmt(&s.body).or_else(|| find_await(&s.test)),
+        Stmt::For(s) => find_await_in_stmt(&s.body),
+        Stmt::ForOf(s) => find_await_in_stmt(&s.body),
+        Stmt::ForIn(s) => find_await_in_stmt(&s.body),
+        Stmt::Labeled(s) => find_await_in_stmt(&s.body),
+        Stmt::Try(s) => s.block.stmts.iter().find_map(find_await_in_stmt)
+            .or_else(|| s.handler.as_ref().and_then(|h| h.body.stmts.iter().find_map(find_await_in_stmt)))
+            .or_else(|| s.finalizer.as_ref().and_then(|f| f.stmts.iter().find_map(find_await_in_stmt))),
+        Stmt::Switch(s) => find_await(&s.discriminant)
+            .or_else(|| s.cases.iter().flat_map(|c| c.cons.iter()).find_map(find_await_in_stmt)),
+        _ => None,
+    }
+}
+
+fn find_await(expr: &Expr) -> Option<&AwaitExpr> {
+    match expr {
+        Expr::Await(a) => Some(a),
+        Expr::Paren(p) => find_await(&p.expr),
+        Expr::Assign(a) => find_await(&a.right),
+        Expr::Bin(b) => find_await(&b.left).or_else(|| find_await(&b.right)),
+        Expr::Unary(u) => find_await(&u.arg),
+        Expr::Cond(c) => find_await(&c.test).or_else(|| find_await(&c.cons)).or_else(|| find_await(&c.alt)),
+        Expr::Seq(seq) => seq.exprs.iter().find_map(|e| find_await(e)),
+        Expr::Member(m) => find_await(&m.obj),
+        Expr::Call(call) => {
+            let callee = match &call.callee {
+                Callee::Expr(callee) => find_await(callee),
+                _ => None,
+            };
+            callee.or_else(|| call.args.iter().find_map(|arg| find_await(&arg.expr)))
+        }
+        Expr::New(new) => new.args.iter().flatten().find_map(|arg| find_await(&arg.expr)),
+        Expr::Array(arr) => arr.elems.iter().flatten().find_map(|elem| find_await(&elem.expr)),
+        Expr::Tpl(tpl) => tpl.exprs.iter().find_map(|e| find_await(e)),
+        Expr::TsAs(a) => find_await(&a.expr),
+        Expr::TsNonNull(n) => find_await(&n.expr),
+        _ => None,
+    }
+}
+
+/// A statement that can run concurrently with other iterations: constant declarations
+/// and awaited expressions, with no calls or writes outside the awaited operations
+fn is_independent_stmt(stmt: &Stmt) -> bool {
+    match stmt {
+        Stmt::Decl(Decl::Var(var)) if var.kind != VarDeclKind::Var => var.decls.iter()
+            .all(|d| d.init.as_deref().map_or(true, |init| is_side_effect_free(init, false))),
+        Stmt::Expr(s) => match strip_parens(&s.expr) {
+            Expr::Await(a) => is_side_effect_free(&a.arg, true),
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// Whether evaluating `expr` has no effects; calls are accepted only as (part of) the
+/// operand of an `await`, which is the work being made concurrent
+fn is_side_effect_free(expr: &Expr, calls_allowed: bool) -> bool {
+    let pure = |e: &Expr| is_side_effect_free(e, calls_allowed);
+    match expr {
+        Expr::Ident(_) | Expr::Lit(_) | Expr::This(_) | Expr::Arrow(_) | Expr::Fn(_) => true,
+        Expr::Await(a) => is_side_effect_free(&a.arg, true),
+        Expr::Paren(p) => pure(&p.expr),
+        Expr::Member(m) => pure(&m.obj) && match &m.prop {
+            MemberProp::Computed(c) => pure(&c.expr),
+            _ => true,
+        },
+        Expr::Tpl(tpl) => tpl.exprs.iter().all(|e| pure(e)),
+        Expr::Bin(b) => pure(&b.left) && pure(&b.right),
+        Expr::Unary(u) => u.op != UnaryOp::Delete && pure(&u.arg),
+        Expr::Cond(c) => pure(&c.test) && pure(&c.cons) && pure(&c.alt),
+        Expr::TsAs(a) => pure(&a.expr),
+        Expr::TsNonNull(n) => pure(&n.expr),
+        Expr::Array(arr) => arr.elems.iter().flatten().all(|elem| pure(&elem.expr)),
+        Expr::Object(obj) => obj.props.iter().all(|prop| match prop {
+            PropOrSpread::Spread(spread) => pure(&spread.expr),
+            PropOrSpread::Prop(prop) => match &**prop {
+                Prop::Shorthand(_) => true,
+                Prop::KeyValue(kv) => pure(&kv.value),
+                _ => false,
+            },
+        }),
+        Expr::Call(call) => calls_allowed
+            && match &call.callee {
+                Callee::Expr(callee) => pure(callee),
+                _ => false,
+            }
+            && call.args.iter().all(|arg| pure(&arg.expr)),
+        Expr::New(new) => calls_allowed
+            && pure(&new.callee)
+            && new.args.iter().flatten().all(|arg| pure(&arg.expr)),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use perry_diagnostics::SourceCache;
+
+    fn lint(source: &str) -> Vec<Diagnostic> {
+        let mut cache = SourceCache::new();
+        let result = perry_parser::parse_typescript_with_cache(source, "test.ts", &mut cache)
+            .expect("Parse failed");
+        PerfLinter::check(&result.module, result.file_id, source)
+    }
+
+    #[test]
+    fn test_independent_loop_gets_promise_all() {
+        let source = "async function load(ids: string[]) {\n  for (const id of ids) {\n    const res = await fetch(id);\n    await save(res);\n  }\n}";
+        let diags = lint(source);
+        assert_eq!(diags.len(), 1);
+        assert_eq!(diags[0].code, DiagnosticCode::AwaitInLoop);
+        assert_eq!(&source[diags[0].span.start as usize..diags[0].span.end as usize], "await fetch(id)");
+        let suggestion = &diags[0].suggestions[0];
+        assert_eq!(suggestion.applicability, Applicability::MachineApplicable);
+        assert_eq!(
+            suggestion.replacement,
+            "await Promise.all(ids.map(async (id) => {\n    const res = await fetch(id);\n    await save(res);\n  }));"
+        );
+    }
+
+    #[test]
+    fn test_dependent_loop_has_no_fix() {
+        // Pushing to an outer array and logging depend on iteration order
+        let diags = lint("async function f() {\n  const out = [];\n  for (const x of [1, 2]) {\n    out.push(await g(x));\n  }\n}");
+        assert_eq!(diags.len(), 1);
+        assert!(diags[0].suggestions.is_empty());
+        let diags = lint("async function f(xs: number[]) {\n  let total = 0;\n  for (const x of xs) { total += await g(x); }\n}");
+        assert_eq!(diags.len(), 1);
+        assert!(diags[0].suggestions.is_empty());
+    }
+
+    #[test]
+    fn test_indexed_loop_reported_without_fix() {
+        let diags = lint("async function f(xs: Array<number>) {\n  for (let i = 0; i < xs.length; i++) { await g(xs[i]); }\n}");
+        assert_eq!(diags.len(), 1);
+        assert!(diags[0].suggestions.is_empty());
+    }
+
+    #[test]
+    fn test_non_arrays_and_nested_functions_ignored() {
+        // Unknown iterable, `for await`, and an await inside a callback are not flagged
+        assert!(lint("async function f(it: any) { for (const x of it) { await g(x); } }").is_empty());
+        assert!(lint("async function f(xs: string[]) { for await (const x of xs) { await g(x); } }").is_empty());
+        assert!(lint("function f(xs: string[]) { for (const x of xs) { h(async () => { await g(x); }); } }").is_empty());
+    }
+}
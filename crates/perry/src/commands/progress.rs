@@ -0,0 +1,311 @@
+//! Machine-readable compile progress - `perry compile --progress <json|unix:PATH>`
+//!
+//! Editors and build dashboards follow long builds through one JSON object per line,
+//! written to stderr (`json`) or to a Unix socket the tool listens on (`unix:PATH`).
+//! Every event carries `event`, `time_ms` (since the build started) and `percent`:
+//!
+//! - `started` with the event `schema` version, always first
+//! - `phase_started` / `phase_finished` (with `duration_ms`) for `collect`, `transform`,
+//!   `codegen` and `link`
+//! - `module_started` / `module_finished` with the module's project-relative name, whether
+//!   it came from a cache, and `finished`/`total` module counts once the phase knows how many modules it has
+//! - `done` with `success` and the first line of the error, always last
+//!
+//! Phases have fixed shares of the percentage; within a phase whose module count is known
+//! (codegen) it advances per finished module. Progress never fails the build: a socket
+//! that can't be reached is reported once and the build carries on, and write errors are
+//! ignored.
+
+use serde_json::{json, Value};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// Bumped whenever an event field changes meaning or is removed
+const SCHEMA_VERSION: u32 = 1;
+
+/// Phases in build order with the share of the overall percentage each one covers
+const PHASES: [(&str, f64); 4] = [("collect", 20.0), ("transform", 10.0), ("codegen", 55.0), ("link", 15.0)];
+
+static REPORTER: Mutex<Option<Reporter>> = Mutex::new(None);
+
+/// Where events go
+#[derive(Debug, Clone, PartialEq)]
+pub enum Sink {
+    Stderr,
+    Unix(PathBuf),
+}
+
+/// Parse a --progress value: `json` (stderr) or `unix:PATH`
+pub fn parse_sink(s: &str) -> Result<Sink, String> {
+    match s.trim() {
+        "json" => Ok(Sink::Stderr),
+        s => match s.strip_prefix("unix:") {
+            Some(path) if !path.is_empty() => Ok(Sink::Unix(PathBuf::from(path))),
+            _ => Err(format!("invalid progress channel '{}' (expected json or unix:PATH)", s)),
+        },
+    }
+}
+
+struct Reporter {
+    out: Box<dyn Write + Send>,
+    started: Instant,
+    /// Current phase, when it started and how many of its modules are done out of how many
+    phase: Option<(&'static str, Instant)>,
+    modules_done: usize,
+    modules_total: Option<usize>,
+}
+
+/// Start reporting progress to `sink`
+pub fn begin(sink: &Sink) {
+    let out: Box<dyn Write + Send> = match sink {
+        Sink::Stderr => Box::new(std::io::stderr()),
+        Sink::Unix(path) => match connect(path) {
+            Ok(stream) => stream,
+            Err(e) => {
+                eprintln!("Warning: could not connect to progress socket {}: {}", path.display(), e);
+                return;
+            }
+        },
+    };
+    let mut reporter = Reporter::new(out);
+    reporter.emit(json!({ "event": "started", "schema": SCHEMA_VERSION }));
+    *REPORTER.lock().unwrap() = Some(reporter);
+}
+
+#[cfg(unix)]
+fn connect(path: &std::path::Path) -> std::io::Result<Box<dyn Write + Send>> {
+    Ok(Box::new(std::os::unix::net::UnixStream::connect(path)?))
+}
+
+#[cfg(not(unix))]
+fn connect(_path: &std::path::Path) -> std::io::Result<Box<dyn Write + Send>> {
+    Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "Unix sockets are not available on this platform"))
+}
+
+fn with_reporter(f: impl FnOnce(&mut Reporter)) {
+    if let Some(reporter) = REPORTER.lock().unwrap().as_mut() {
+        f(reporter);
+    }
+}
+
+/// Enter phase `name`, finishing the one before it
+pub fn phase(name: &'static str) {
+    with_reporter(|reporter| reporter.start_phase(name));
+}
+
+/// Announce how many modules the current phase will process
+pub fn modules(total: usize) {
+    with_reporter(|reporter| reporter.modules_total = Some(total));
+}
+
+/// Record that the current phase began work on `module`
+pub fn module_started(module: &str) {
+    with_reporter(|reporter| {
+        let event = reporter.module_event("module_started", module);
+        reporter.emit(event);
+    });
+}
+
+/// Record that the current phase is done with `module`
+pub fn module_finished(module: &str, cached: bool) {
+    with_reporter(|reporter| {
+        reporter.modules_done += 1;
+        let mut event = reporter.module_event("module_finished", module);
+        event["cached"] = json!(cached);
+        reporter.emit(event);
+    });
+}
+
+/// Finish the current phase, write the `done` event and stop reporting. Does nothing
+/// unless `begin` connected a sink.
+pub fn finish(error: Option<&anyhow::Error>) {
+    let Some(mut reporter) = REPORTER.lock().unwrap().take() else {
+        return;
+    };
+    let error = error.map(|e| e.to_string());
+    reporter.end(error.as_deref());
+}
+
+impl Reporter {
+    fn new(out: Box<dyn Write + Send>) -> Self {
+        Reporter { out, started: Instant::now(), phase: None, modules_done: 0, modules_total: None }
+    }
+
+    fn start_phase(&mut self, name: &'static str) {
+        self.finish_phase();
+        self.phase = Some((name, Instant::now()));
+        self.modules_done = 0;
+        self.modules_total = None;
+        let percent = self.percent();
+        self.emit(json!({ "event": "phase_started", "phase": name, "percent": percent }));
+    }
+
+    fn finish_phase(&mut self) {
+        let Some((name, started)) = self.phase.take() else {
+            return;
+        };
+        let percent = phase_end(name);
+        self.emit(json!({
+            "event": "phase_finished",
+            "phase": name,
+            "duration_ms": round_ms(started.elapsed().as_secs_f64() * 1000.0),
+            "percent": percent,
+        }));
+    }
+
+    fn end(&mut self, error: Option<&str>) {
+        if error.is_none() {
+            self.finish_phase();
+        }
+        let phase = self.phase.map(|(name, _)| name);
+        let percent = if error.is_none() { 100.0 } else { self.percent() };
+        self.emit(json!({
+            "event": "done",
+            "success": error.is_none(),
+            "error": error.map(|e| e.lines().next().unwrap_or("").to_string()),
+            "phase": phase,
+            "duration_ms": round_ms(self.started.elapsed().as_secs_f64() * 1000.0),
+            "percent": percent,
+        }));
+    }
+
+    fn module_event(&self, event: &str, module: &str) -> Value {
+        let mut value = json!({
+            "event": event,
+            "phase": self.phase.map(|(name, _)| name),
+            "module": module,
+            "percent": self.percent(),
+        });
+        if let Some(total) = self.modules_total {
+            value["finished"] = json!(self.modules_done);
+            value["total"] = json!(total);
+        }
+        value
+    }
+
+    /// Overall percentage: the phases before the current one, plus the current phase's
+    /// share scaled by its finished modules when their number is known
+    fn percent(&self) -> f64 {
+        let Some((name, _)) = self.phase else {
+            return 0.0;
+        };
+        let start = phase_end(name) - phase_weight(name);
+        let fraction = match self.modules_total {
+            Some(total) if total > 0 => self.modules_done.min(total) as f64 / total as f64,
+            _ => 0.0,
+        };
+        round_percent(start + phase_weight(name) * fraction)
+    }
+
+    fn emit(&mut self, mut event: Value) {
+        event["time_ms"] = json!(round_ms(self.started.elapsed().as_secs_f64() * 1000.0));
+        // One write per event keeps lines whole when other output shares the stream
+        let line = format!("{}\n", event);
+        let _ = self.out.write_all(line.as_bytes()).and_then(|_| self.out.flush());
+    }
+}
+
+fn phase_weight(name: &str) -> f64 {
+    PHASES.iter().find(|(phase, _)| *phase == name).map_or(0.0, |(_, weight)| *weight)
+}
+
+/// Percentage reached once phase `name` is complete
+fn phase_end(name: &str) -> f64 {
+    let index = PHASES.iter().position(|(phase, _)| *phase == name).unwrap_or(PHASES.len() - 1);
+    round_percent(PHASES[..=index].iter().map(|(_, weight)| weight).sum())
+}
+
+fn round_ms(ms: f64) -> f64 {
+    (ms * 10.0).round() / 10.0
+}
+
+fn round_percent(percent: f64) -> f64 {
+    (percent * 10.0).round() / 10.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    /// Shared buffer standing in for stderr
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(data);
+            Ok(data.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Buffer {
+        fn events(&self) -> Vec<Value> {
+            let content = String::from_utf8(self.0.lock().unwrap().clone()).unwrap();
+            content.lines().map(|line| serde_json::from_str(line).unwrap()).collect()
+        }
+    }
+
+    #[test]
+    fn test_parse_sink() {
+        assert_eq!(parse_sink("json"), Ok(Sink::Stderr));
+        assert_eq!(parse_sink("unix:/tmp/perry.sock"), Ok(Sink::Unix(PathBuf::from("/tmp/perry.sock"))));
+        assert!(parse_sink("unix:").is_err());
+        assert!(parse_sink("text").is_err());
+    }
+
+    #[test]
+    fn test_phase_and_module_events() {
+        let buffer = Buffer::default();
+        let mut reporter = Reporter::new(Box::new(buffer.clone()));
+        reporter.start_phase("collect");
+        reporter.start_phase("transform");
+        reporter.start_phase("codegen");
+        reporter.modules_total = Some(4);
+        let event = reporter.module_event("module_started", "main.ts");
+        reporter.emit(event);
+        reporter.modules_done = 1;
+        assert_eq!(reporter.percent(), 43.8);
+        reporter.end(None);
+
+        let events = buffer.events();
+        let kinds: Vec<&str> = events.iter().map(|e| e["event"].as_str().unwrap()).collect();
+        assert_eq!(kinds, [
+            "phase_started", "phase_finished", "phase_started", "phase_finished",
+            "phase_started", "module_started", "phase_finished", "done",
+        ]);
+        assert_eq!(events[1]["phase"], "collect");
+        assert_eq!(events[1]["percent"], 20.0);
+        assert_eq!(events[4]["percent"], 30.0);
+        assert_eq!(events[5]["module"], "main.ts");
+        assert_eq!(events[5]["finished"], 0);
+        assert_eq!(events[5]["total"], 4);
+        assert_eq!(events[6]["percent"], 85.0);
+        assert_eq!(events[7]["success"], true);
+        assert_eq!(events[7]["percent"], 100.0);
+        assert!(events.iter().all(|e| e["time_ms"].is_number()));
+    }
+
+    #[test]
+    fn test_failed_build_keeps_phase() {
+        let buffer = Buffer::default();
+        let mut reporter = Reporter::new(Box::new(buffer.clone()));
+        reporter.start_phase("collect");
+        reporter.start_phase("transform");
+        reporter.start_phase("codegen");
+        reporter.start_phase("link");
+        reporter.end(Some("Linking failed\nld: symbol not found"));
+
+        let done = buffer.events().pop().unwrap();
+        assert_eq!(done["success"], false);
+        assert_eq!(done["error"], "Linking failed");
+        assert_eq!(done["phase"], "link");
+        assert_eq!(done["percent"], 85.0);
+    }
+}
@@ -0,0 +1,280 @@
+//! perry.toml `[build]` and `[permissions]` settings
+//!
+//! `perry compile` reads the nearest perry.toml at or above the input file; its flags
+//! override what the file says. Keys the compiler doesn't use (`out_dir`) are ignored.
+//!
+//! ```toml
+//! [build]
+//! opt_level = "speed"   # "none", "speed" or "size" (0-3, "s" and "z" work too)
+//! verifier = false      # run the Cranelift IR verifier on every function
+//! remote_cache = "http://cache.internal:8080/perry"  # shared HIR cache (PERRY_REMOTE_CACHE overrides)
+//! remote_cache_upload = false  # only download from the remote cache
+//! audit = true         # audit the JavaScript modules routed to V8
+//! audit_db = "advisories.json"  # advisory database, relative to perry.toml
+//! audit_fail_on = "moderate"  # fail on audit findings this severe (default high)
+//! native_libs = ["native/target/release/libgeo.a"]  # `perry bindgen` crates to link in
+//!
+//! [build.cranelift]     # any other Cranelift setting, shared or for the host ISA
+//! has_avx2 = false
+//! regalloc_checker = true
+//!
+//! [permissions]         # compiled in: the program may only reach what is listed
+//! allow-net = ["api.example.com", "localhost:8080", "*.internal"]
+//! allow-fs = ["./data", "/tmp"]
+//! allow-env = ["PORT", "APP_*"]
+//! allow-run = ["git"]
+//! ```
+
+use anyhow::{anyhow, Result};
+use perry_hir::OptLevel;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::js_audit::Severity;
+
+/// Build settings from a perry.toml
+#[derive(Debug, Clone, Default)]
+pub struct BuildConfig {
+    /// The perry.toml the settings were loaded from
+    pub config_path: PathBuf,
+    pub opt_level: Option<OptLevel>,
+    pub verifier: Option<bool>,
+    /// URL of the remote HIR cache
+    pub remote_cache: Option<String>,
+    pub remote_cache_upload: Option<bool>,
+    pub audit: Option<bool>,
+    /// Advisory database for the audit, resolved against the perry.toml directory
+    pub audit_db: Option<PathBuf>,
+    pub audit_fail_on: Option<Severity>,
+    /// Static libraries linked into the executable, resolved against the perry.toml directory
+    pub native_libs: Vec<PathBuf>,
+    /// `[build.cranelift]` entries
+    pub cranelift_flags: Vec<(String, String)>,
+    /// `[permissions]`; None leaves the program unrestricted
+    pub permissions: Option<Permissions>,
+}
+
+/// Capabilities the compiled program is limited to. A kind left out allows nothing.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Permissions {
+    pub allow_net: Vec<String>,
+    pub allow_fs: Vec<String>,
+    pub allow_env: Vec<String>,
+    pub allow_run: Vec<String>,
+}
+
+impl Permissions {
+    const KINDS: [&'static str; 4] = ["allow-net", "allow-fs", "allow-env", "allow-run"];
+
+    fn parse(table: &toml::Table) -> Result<Self, String> {
+        let mut permissions = Permissions::default();
+        for (key, value) in table {
+            let entries = match key.as_str() {
+                "allow-net" => &mut permissions.allow_net,
+                "allow-fs" => &mut permissions.allow_fs,
+                "allow-env" => &mut permissions.allow_env,
+                "allow-run" => &mut permissions.allow_run,
+                // A misspelled key would silently deny (or, read loosely, allow) too much
+                _ => return Err(format!("unknown permission `{}` (expected {})", key, Self::KINDS.join(", "))),
+            };
+            let list = value.as_array().ok_or_else(|| format!("`permissions.{}` must be an array of strings", key))?;
+            for entry in list {
+                match entry.as_str() {
+                    Some(entry) if !entry.is_empty() && !entry.contains('\n') => entries.push(entry.to_string()),
+                    _ => return Err(format!("`permissions.{}` entries must be non-empty single-line strings", key)),
+                }
+            }
+        }
+        Ok(permissions)
+    }
+
+    /// The form the runtime reads (`js_permissions_configure`): a header line, then one
+    /// `<kind> <entry>` line per entry
+    pub fn serialize(&self) -> String {
+        let mut policy = String::from("perry-permissions 1\n");
+        let kinds = [&self.allow_net, &self.allow_fs, &self.allow_env, &self.allow_run];
+        for (kind, entries) in Self::KINDS.iter().zip(kinds) {
+            for entry in entries {
+                policy.push_str(&format!("{} {}\n", kind, entry));
+            }
+        }
+        policy
+    }
+}
+
+impl BuildConfig {
+    /// Find the nearest perry.toml at or above `start` and load it
+    pub fn find(start: &Path) -> Result<Option<Self>> {
+        let start = start.canonicalize().unwrap_or_else(|_| start.to_path_buf());
+        for dir in start.ancestors() {
+            let candidate = dir.join("perry.toml");
+            if candidate.is_file() {
+                return Self::load(&candidate).map(Some);
+            }
+        }
+        Ok(None)
+    }
+
+    pub fn load(config_path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(config_path)
+            .map_err(|e| anyhow!("Failed to read {}: {}", config_path.display(), e))?;
+        Self::parse(&text, config_path.to_path_buf())
+            .map_err(|e| anyhow!("Invalid {}: {}", config_path.display(), e))
+    }
+
+    fn parse(text: &str, config_path: PathBuf) -> Result<Self, String> {
+        let file: toml::Table = toml::from_str(text).map_err(|e| e.to_string())?;
+        let mut config = BuildConfig { config_path, ..Default::default() };
+        if let Some(permissions) = file.get("permissions") {
+            let permissions = permissions.as_table().ok_or("`permissions` must be a table")?;
+            config.permissions = Some(Permissions::parse(permissions)?);
+        }
+        let Some(build) = file.get("build") else { return Ok(config) };
+        let build = build.as_table().ok_or("`build` must be a table")?;
+
+        config.opt_level = match build.get("opt_level") {
+            None => None,
+            Some(toml::Value::String(level)) => Some(parse_opt_level(level)?),
+            Some(toml::Value::Integer(level)) => Some(parse_opt_level(&level.to_string())?),
+            Some(_) => return Err("`build.opt_level` must be a string or a number".into()),
+        };
+        config.verifier = match build.get("verifier") {
+            None => None,
+            Some(value) => Some(value.as_bool().ok_or("`build.verifier` must be true or false")?),
+        };
+        config.remote_cache = match build.get("remote_cache") {
+            None => None,
+            Some(value) => Some(value.as_str().ok_or("`build.remote_cache` must be a URL string")?.to_string()),
+        };
+        config.remote_cache_upload = match build.get("remote_cache_upload") {
+            None => None,
+            Some(value) => Some(value.as_bool().ok_or("`build.remote_cache_upload` must be true or false")?),
+        };
+        config.audit = match build.get("audit") {
+            None => None,
+            Some(value) => Some(value.as_bool().ok_or("`build.audit` must be true or false")?),
+        };
+        config.audit_db = match build.get("audit_db") {
+            None => None,
+            Some(value) => {
+                let path = value.as_str().ok_or("`build.audit_db` must be a path string")?;
+                Some(config.config_path.parent().unwrap_or(Path::new(".")).join(path))
+            }
+        };
+        config.audit_fail_on = match build.get("audit_fail_on") {
+            None => None,
+            Some(value) => {
+                let severity = value.as_str().ok_or("`build.audit_fail_on` must be a string")?;
+                Some(Severity::parse(severity).ok_or_else(|| {
+                    format!("unknown audit_fail_on `{}` (expected low, moderate, high or critical)", severity)
+                })?)
+            }
+        };
+        if let Some(libs) = build.get("native_libs") {
+            let libs = libs.as_array().ok_or("`build.native_libs` must be an array of paths")?;
+            for lib in libs {
+                let lib = lib.as_str().ok_or("`build.native_libs` entries must be path strings")?;
+                config.native_libs.push(config.config_path.parent().unwrap_or(Path::new(".")).join(lib));
+            }
+        }
+        if let Some(cranelift) = build.get("cranelift") {
+            let cranelift = cranelift.as_table().ok_or("`build.cranelift` must be a table")?;
+            for (name, value) in cranelift {
+                let value = match value {
+                    toml::Value::String(s) => s.clone(),
+                    toml::Value::Boolean(b) => b.to_string(),
+                    toml::Value::Integer(n) => n.to_string(),
+                    _ => return Err(format!("`build.cranelift.{}` must be a string, boolean or number", name)),
+                };
+                config.cranelift_flags.push((name.clone(), value));
+            }
+        }
+        Ok(config)
+    }
+}
+
+/// `none`/`0`, `speed`/`1`-`3`, or `size`/`s`/`z`
+pub fn parse_opt_level(level: &str) -> Result<OptLevel, String> {
+    match level {
+        "none" | "0" => Ok(OptLevel::None),
+        "speed" | "1" | "2" | "3" => Ok(OptLevel::Speed),
+        "size" | "s" | "z" => Ok(OptLevel::Size),
+        other => Err(format!("unknown opt_level `{}` (expected none, speed or size)", other)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_section() {
+        let config = BuildConfig::parse(
+            "[project]\nname = \"app\"\n\n[build]\nout_dir = \"dist\"\nopt_level = 2\nverifier = true\nremote_cache = \"http://cache:8080/perry\"\nremote_cache_upload = false\n\n[build.cranelift]\nhas_avx2 = false\nregalloc_checker = true\n",
+            PathBuf::from("perry.toml"),
+        )
+        .unwrap();
+        assert_eq!(config.opt_level, Some(OptLevel::Speed));
+        assert_eq!(config.verifier, Some(true));
+        assert_eq!(config.remote_cache.as_deref(), Some("http://cache:8080/perry"));
+        assert_eq!(config.remote_cache_upload, Some(false));
+        assert_eq!(
+            config.cranelift_flags,
+            vec![("has_avx2".to_string(), "false".to_string()), ("regalloc_checker".to_string(), "true".to_string())]
+        );
+
+        let config = BuildConfig::parse("[project]\nname = \"app\"\n", PathBuf::from("perry.toml")).unwrap();
+        assert_eq!((config.opt_level, config.verifier), (None, None));
+        assert!(BuildConfig::parse("[build]\nopt_level = \"fastest\"\n", PathBuf::from("perry.toml"))
+            .unwrap_err()
+            .contains("`fastest`"));
+    }
+
+    #[test]
+    fn test_permissions() {
+        let config = BuildConfig::parse(
+            "[permissions]\nallow-net = [\"api.example.com\", \"*.internal\"]\nallow-env = [\"PORT\"]\n",
+            PathBuf::from("perry.toml"),
+        )
+        .unwrap();
+        let permissions = config.permissions.unwrap();
+        assert_eq!(permissions.allow_net, vec!["api.example.com", "*.internal"]);
+        assert!(permissions.allow_fs.is_empty());
+        assert_eq!(
+            permissions.serialize(),
+            "perry-permissions 1\nallow-net api.example.com\nallow-net *.internal\nallow-env PORT\n"
+        );
+
+        assert!(BuildConfig::parse("[build]\n", PathBuf::from("perry.toml")).unwrap().permissions.is_none());
+        assert!(BuildConfig::parse("[permissions]\nallow-network = [\"*\"]\n", PathBuf::from("perry.toml"))
+            .unwrap_err()
+            .contains("`allow-network`"));
+        assert!(BuildConfig::parse("[permissions]\nallow-fs = \"/tmp\"\n", PathBuf::from("perry.toml")).is_err());
+    }
+
+    #[test]
+    fn test_audit_settings() {
+        let config = BuildConfig::parse(
+            "[build]\naudit = true\naudit_db = \"security/advisories.json\"\naudit_fail_on = \"moderate\"\n",
+            PathBuf::from("/app/perry.toml"),
+        )
+        .unwrap();
+        assert_eq!(config.audit, Some(true));
+        assert_eq!(config.audit_db, Some(PathBuf::from("/app/security/advisories.json")));
+        assert_eq!(config.audit_fail_on, Some(Severity::Moderate));
+        assert!(BuildConfig::parse("[build]\naudit_fail_on = \"severe\"\n", PathBuf::from("perry.toml"))
+            .unwrap_err()
+            .contains("`severe`"));
+    }
+
+    #[test]
+    fn test_native_libs() {
+        let config = BuildConfig::parse(
+            "[build]\nnative_libs = [\"native/target/release/libgeo.a\"]\n",
+            PathBuf::from("/app/perry.toml"),
+        )
+        .unwrap();
+        assert_eq!(config.native_libs, vec![PathBuf::from("/app/native/target/release/libgeo.a")]);
+        assert!(BuildConfig::parse("[build]\nnative_libs = \"libgeo.a\"\n", PathBuf::from("perry.toml")).is_err());
+    }
+}
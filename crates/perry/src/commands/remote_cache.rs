@@ -0,0 +1,256 @@
+//! Remote backend for the HIR cache, shared between machines
+//!
+//! A content-addressed store over plain HTTP: an entry is fetched with
+//! `GET <url>/hir/<perry version>/<key>` and uploaded with `PUT` to the same path, where
+//! `<key>` is the HIR cache key (a hash of the source, module name and path, and lowering
+//! options). Any server that stores PUT bodies and serves them back works: bazel-remote,
+//! nginx with WebDAV, or an S3/GCS bucket behind a proxy that signs requests. Like
+//! telemetry, only `http://host[:port]/path` URLs are supported; put TLS in the proxy.
+//!
+//! The key includes the absolute source path, because lowering bakes it into the HIR
+//! (import.meta, relative file reads), so machines share entries when they build from the
+//! same checkout path, e.g. CI runners with a fixed workspace and dev containers.
+//!
+//! The remote is an accelerator, never a dependency: any error counts as a miss, and
+//! after the first connection failure the rest of the build stops asking. Entries are
+//! trusted, so only point perry at a cache that only your builds can write.
+
+use std::cell::Cell;
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use super::telemetry::parse_http_endpoint;
+
+/// Environment variable naming the remote cache, overriding perry.toml `[build] remote_cache`
+pub const REMOTE_CACHE_ENV: &str = "PERRY_REMOTE_CACHE";
+
+/// Keep an unreachable cache from noticeably slowing down builds
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+const IO_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug)]
+pub struct RemoteCache {
+    host: String,
+    port: u16,
+    /// URL path without the trailing slash
+    prefix: String,
+    /// Only download; builds that can't be trusted to populate the cache set this
+    pub read_only: bool,
+    /// Set after a connection fails, so one dead server costs one timeout per build
+    unavailable: Cell<bool>,
+    pub hits: Cell<usize>,
+    pub uploads: Cell<usize>,
+}
+
+impl RemoteCache {
+    pub fn new(url: &str, read_only: bool) -> Result<Self, String> {
+        let (host, port, path) = parse_http_endpoint(url)
+            .ok_or_else(|| format!("remote cache `{}` must be an http://host[:port]/path URL", url))?;
+        Ok(Self {
+            host,
+            port,
+            prefix: path.trim_end_matches('/').to_string(),
+            read_only,
+            unavailable: Cell::new(false),
+            hits: Cell::new(0),
+            uploads: Cell::new(0),
+        })
+    }
+
+    fn entry_path(&self, key: u64) -> String {
+        format!("{}/hir/{}/{:016x}", self.prefix, env!("CARGO_PKG_VERSION"), key)
+    }
+
+    /// The entry stored under `key`, if the server has it
+    pub fn get(&self, key: u64) -> Option<Vec<u8>> {
+        if self.unavailable.get() {
+            return None;
+        }
+        match self.request("GET", &self.entry_path(key), &[]) {
+            Ok((200, body)) => {
+                self.hits.set(self.hits.get() + 1);
+                Some(body)
+            }
+            Ok(_) => None,
+            Err(e) => {
+                self.fail(e);
+                None
+            }
+        }
+    }
+
+    /// Upload the entry for `key` unless the cache is read-only
+    pub fn put(&self, key: u64, bytes: &[u8]) {
+        if self.read_only || self.unavailable.get() {
+            return;
+        }
+        match self.request("PUT", &self.entry_path(key), bytes) {
+            Ok((status, _)) if (200..300).contains(&status) => self.uploads.set(self.uploads.get() + 1),
+            Ok((status, _)) => log::debug!("remote cache rejected upload with HTTP {}", status),
+            Err(e) => self.fail(e),
+        }
+    }
+
+    fn fail(&self, error: io::Error) {
+        log::debug!("remote cache {}:{} unavailable: {}", self.host, self.port, error);
+        self.unavailable.set(true);
+    }
+
+    /// Send one request and return the status code and body
+    fn request(&self, method: &str, path: &str, body: &[u8]) -> io::Result<(u16, Vec<u8>)> {
+        let addr = (self.host.as_str(), self.port)
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "remote cache host did not resolve"))?;
+        let mut stream = TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT)?;
+        stream.set_read_timeout(Some(IO_TIMEOUT))?;
+        stream.set_write_timeout(Some(IO_TIMEOUT))?;
+        write!(
+            stream,
+            "{} {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/octet-stream\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            method,
+            path,
+            self.host,
+            body.len()
+        )?;
+        stream.write_all(body)?;
+        stream.flush()?;
+
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response)?;
+        parse_response(&response)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "malformed HTTP response"))
+    }
+}
+
+/// Split a complete HTTP/1.1 response into status code and body
+fn parse_response(response: &[u8]) -> Option<(u16, Vec<u8>)> {
+    let header_end = response.windows(4).position(|w| w == b"\r\n\r\n")?;
+    let head = std::str::from_utf8(&response[..header_end]).ok()?;
+    let body = &response[header_end + 4..];
+    let mut lines = head.split("\r\n");
+    let status = lines.next()?.split(' ').nth(1)?.parse().ok()?;
+
+    let mut content_length = None;
+    let mut chunked = false;
+    for line in lines {
+        let Some((name, value)) = line.split_once(':') else { continue };
+        let value = value.trim();
+        if name.eq_ignore_ascii_case("content-length") {
+            content_length = Some(value.parse::<usize>().ok()?);
+        } else if name.eq_ignore_ascii_case("transfer-encoding") && value.eq_ignore_ascii_case("chunked") {
+            chunked = true;
+        }
+    }
+    let body = if chunked {
+        decode_chunked(body)?
+    } else if let Some(length) = content_length {
+        // A truncated body is a failed download, not a shorter entry
+        body.get(..length)?.to_vec()
+    } else {
+        body.to_vec()
+    };
+    Some((status, body))
+}
+
+fn decode_chunked(mut body: &[u8]) -> Option<Vec<u8>> {
+    let mut decoded = Vec::new();
+    loop {
+        let line_end = body.windows(2).position(|w| w == b"\r\n")?;
+        let size_line = std::str::from_utf8(&body[..line_end]).ok()?;
+        let size = usize::from_str_radix(size_line.split(';').next()?.trim(), 16).ok()?;
+        body = &body[line_end + 2..];
+        if size == 0 {
+            return Some(decoded);
+        }
+        decoded.extend_from_slice(body.get(..size)?);
+        body = body.get(size + 2..)?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::io::BufRead;
+    use std::net::TcpListener;
+
+    /// A minimal content-addressed store: PUT saves the body, GET returns it or 404
+    fn serve(listener: TcpListener, requests: usize) {
+        let mut store: HashMap<String, Vec<u8>> = HashMap::new();
+        for stream in listener.incoming().take(requests) {
+            let mut reader = io::BufReader::new(stream.unwrap());
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            let mut parts = request_line.split_whitespace();
+            let (method, path) = (parts.next().unwrap().to_string(), parts.next().unwrap().to_string());
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line == "\r\n" {
+                    break;
+                }
+                if let Some(value) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+                    length = value.trim().parse().unwrap();
+                }
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            let response = match (method.as_str(), store.get(&path)) {
+                ("PUT", _) => {
+                    store.insert(path, body);
+                    b"HTTP/1.1 201 Created\r\nContent-Length: 0\r\n\r\n".to_vec()
+                }
+                ("GET", Some(entry)) => {
+                    let mut response = format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n", entry.len()).into_bytes();
+                    response.extend_from_slice(entry);
+                    response
+                }
+                _ => b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n".to_vec(),
+            };
+            reader.get_mut().write_all(&response).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_get_and_put() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/cache/", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || serve(listener, 3));
+
+        let cache = RemoteCache::new(&url, false).unwrap();
+        assert_eq!(cache.get(7), None);
+        cache.put(7, b"entry");
+        assert_eq!(cache.get(7), Some(b"entry".to_vec()));
+        assert_eq!((cache.hits.get(), cache.uploads.get()), (1, 1));
+        server.join().unwrap();
+
+        // A read-only cache never uploads, and a dead server disables the remote
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/cache", listener.local_addr().unwrap());
+        drop(listener);
+        let cache = RemoteCache::new(&url, true).unwrap();
+        cache.put(7, b"entry");
+        assert_eq!(cache.uploads.get(), 0);
+        assert_eq!(cache.get(7), None);
+        assert!(cache.unavailable.get());
+
+        assert!(RemoteCache::new("https://cache.example.com", false).is_err());
+    }
+
+    #[test]
+    fn test_parse_response() {
+        assert_eq!(
+            parse_response(b"HTTP/1.1 200 OK\r\nContent-Length: 3\r\n\r\nabc"),
+            Some((200, b"abc".to_vec()))
+        );
+        assert_eq!(
+            parse_response(b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n2\r\nab\r\n1\r\nc\r\n0\r\n\r\n"),
+            Some((200, b"abc".to_vec()))
+        );
+        assert_eq!(parse_response(b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nabc"), None);
+        assert_eq!(parse_response(b"HTTP/1.1 404 Not Found\r\n\r\n"), Some((404, Vec::new())));
+    }
+}
@@ -0,0 +1,314 @@
+//! Run command - compile a script and execute it in one step
+//!
+//! This backs `#!/usr/bin/env perry` scripts: the kernel invokes `perry <script> <args>`,
+//! which `main` rewrites to `perry run <script> -- <args>`. The compiled binary is kept
+//! in a per-user cache keyed by a hash of the script's contents, its path and the perry
+//! version, so only the first run of a given script pays for compilation.
+//!
+//! Next to each binary, a `.inputs` file lists every module it was compiled from (as
+//! `perry compile --format json` reports them) with a hash of its contents. The binary
+//! is only reused while all of them are unchanged, so editing a module the script
+//! imports recompiles it too.
+
+use anyhow::{anyhow, Result};
+use clap::Args;
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Instant;
+
+use super::telemetry;
+use crate::OutputFormat;
+
+#[derive(Args, Debug)]
+pub struct RunArgs {
+    /// Script to compile and run
+    pub script: PathBuf,
+
+    /// Recompile the script even if a cached binary exists
+    #[arg(long)]
+    pub rebuild: bool,
+
+    /// Arguments passed through to the script
+    #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+    pub args: Vec<String>,
+}
+
+/// Whether `path` is a script meant to be run by perry: its first line is a
+/// shebang that names perry (`#!/usr/bin/env perry`, `#!/usr/local/bin/perry`, ...)
+pub fn has_perry_shebang(path: &Path) -> bool {
+    if !path.is_file() {
+        return false;
+    }
+    let Ok(source) = fs::read(path) else {
+        return false;
+    };
+    let first_line = source.split(|&b| b == b'\n').next().unwrap_or(&[]);
+    first_line.starts_with(b"#!") && String::from_utf8_lossy(first_line).contains("perry")
+}
+
+/// Directory holding compiled scripts: PERRY_CACHE_DIR if set, otherwise the
+/// platform's user cache directory
+fn cache_dir() -> Result<PathBuf> {
+    if let Some(dir) = std::env::var_os("PERRY_CACHE_DIR") {
+        return Ok(PathBuf::from(dir).join("scripts"));
+    }
+    let base = if cfg!(target_os = "macos") {
+        std::env::var_os("HOME").map(|home| PathBuf::from(home).join("Library/Caches"))
+    } else if cfg!(windows) {
+        std::env::var_os("LOCALAPPDATA").map(PathBuf::from)
+    } else {
+        std::env::var_os("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+    };
+    base.map(|dir| dir.join("perry").join("scripts"))
+        .ok_or_else(|| anyhow!("Could not determine a cache directory; set PERRY_CACHE_DIR"))
+}
+
+/// Cache key for a script: changes whenever its contents, location, compile flags or
+/// the compiler do
+fn script_hash(canonical: &Path, source: &[u8], compile_flags: &[&str]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    env!("CARGO_PKG_VERSION").hash(&mut hasher);
+    canonical.hash(&mut hasher);
+    source.hash(&mut hasher);
+    compile_flags.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Hash of a module's contents, as recorded in a binary's `.inputs` file
+fn content_hash(source: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    source.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// The `.inputs` file listing the modules a cached binary was compiled from
+fn inputs_file(exe: &Path) -> PathBuf {
+    exe.with_extension("inputs")
+}
+
+/// Modules listed in the JSON report `perry compile --format json` prints last
+fn reported_inputs(stdout: &[u8]) -> Vec<PathBuf> {
+    let report = String::from_utf8_lossy(stdout)
+        .lines()
+        .rev()
+        .find_map(|line| serde_json::from_str::<serde_json::Value>(line).ok());
+    report
+        .as_ref()
+        .and_then(|report| report.get("inputs"))
+        .and_then(|inputs| inputs.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|input| input.as_str().map(PathBuf::from))
+        .collect()
+}
+
+/// Write the `.inputs` file for a binary: one `<hash> <path>` line per module
+fn record_inputs(inputs_file: &Path, inputs: &[PathBuf]) -> Result<()> {
+    let mut listing = String::new();
+    for input in inputs {
+        let source = fs::read(input).map_err(|e| anyhow!("Failed to read {}: {}", input.display(), e))?;
+        listing.push_str(&format!("{:016x} {}\n", content_hash(&source), input.display()));
+    }
+    fs::write(inputs_file, listing)?;
+    Ok(())
+}
+
+/// Whether every module listed in a binary's `.inputs` file still has the contents it
+/// was compiled from. A binary without one is stale.
+fn inputs_unchanged(inputs_file: &Path) -> bool {
+    let Ok(listing) = fs::read_to_string(inputs_file) else {
+        return false;
+    };
+    listing.lines().all(|line| {
+        let Some((hash, path)) = line.split_once(' ') else {
+            return false;
+        };
+        fs::read(path).is_ok_and(|source| format!("{:016x}", content_hash(&source)) == hash)
+    })
+}
+
+/// Path of the cached binary for a script
+fn cached_binary(cache_dir: &Path, canonical: &Path, source: &[u8], compile_flags: &[&str]) -> PathBuf {
+    let stem = canonical
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("script");
+    let hash = script_hash(canonical, source, compile_flags);
+    let name = format!("{}-{:016x}{}", stem, hash, std::env::consts::EXE_SUFFIX);
+    cache_dir.join(name)
+}
+
+/// Compile `script` into `exe` by invoking this perry binary's `compile` command, and
+/// record the modules it was compiled from. Compiler output is captured so it doesn't
+/// mix with the script's own output, and only shown when compilation fails.
+fn compile_script(script: &Path, exe: &Path, cache_dir: &Path, compile_flags: &[&str]) -> Result<()> {
+    // Link to a temporary name and rename into place, so a concurrent run of the
+    // same script never executes a half-written binary
+    let tmp = exe.with_extension(format!("tmp{}", std::process::id()));
+    let output = Command::new(std::env::current_exe()?)
+        .arg("compile")
+        .arg(script)
+        .arg("-o")
+        .arg(&tmp)
+        .args(["--format", "json"])
+        .args(compile_flags)
+        // Object files are written to the working directory
+        .current_dir(cache_dir)
+        .output()?;
+
+    if !output.status.success() {
+        let _ = fs::remove_file(&tmp);
+        eprint!("{}", String::from_utf8_lossy(&output.stdout));
+        eprint!("{}", String::from_utf8_lossy(&output.stderr));
+        return Err(anyhow!("Failed to compile {}", script.display()));
+    }
+    let mut inputs = reported_inputs(&output.stdout);
+    if inputs.is_empty() {
+        inputs.push(script.to_path_buf());
+    }
+    let tmp_inputs = inputs_file(&tmp);
+    record_inputs(&tmp_inputs, &inputs)?;
+    fs::rename(&tmp_inputs, inputs_file(exe))?;
+    fs::rename(&tmp, exe)?;
+    Ok(())
+}
+
+#[cfg(unix)]
+pub(crate) fn exec(exe: &Path, args: &[String]) -> Result<()> {
+    use std::os::unix::process::CommandExt;
+    super::telemetry::finish(None);
+    // Replace this process so the script owns the terminal, signals and exit status
+    let err = Command::new(exe).args(args).exec();
+    Err(anyhow!("Failed to execute {}: {}", exe.display(), err))
+}
+
+#[cfg(not(unix))]
+pub(crate) fn exec(exe: &Path, args: &[String]) -> Result<()> {
+    super::telemetry::finish(None);
+    let status = Command::new(exe).args(args).status()?;
+    std::process::exit(status.code().unwrap_or(1));
+}
+
+/// Compile a script unless an up-to-date binary is cached, returning the binary's path.
+/// `compile_flags` are passed to `perry compile` and are part of the cache key; a cached
+/// binary is up to date while none of the modules it was compiled from changed.
+pub(crate) fn cached_compile(
+    script: &Path,
+    rebuild: bool,
+    compile_flags: &[&str],
+    format: OutputFormat,
+    verbose: u8,
+) -> Result<PathBuf> {
+    let canonical = script
+        .canonicalize()
+        .map_err(|e| anyhow!("Failed to canonicalize {}: {}", script.display(), e))?;
+    let source = fs::read(&canonical)
+        .map_err(|e| anyhow!("Failed to read {}: {}", canonical.display(), e))?;
+
+    let cache_dir = cache_dir()?;
+    fs::create_dir_all(&cache_dir)
+        .map_err(|e| anyhow!("Failed to create {}: {}", cache_dir.display(), e))?;
+    let exe = cached_binary(&cache_dir, &canonical, &source, compile_flags);
+
+    let cached = exe.is_file() && !rebuild && inputs_unchanged(&inputs_file(&exe));
+    telemetry::cache(cached);
+    if !cached {
+        let started = Instant::now();
+        compile_script(&canonical, &exe, &cache_dir, compile_flags)?;
+        telemetry::phase("compile", started);
+    }
+    if verbose > 0 {
+        if let OutputFormat::Text = format {
+            let state = if cached { "cached" } else { "compiled" };
+            eprintln!("perry: {} {} -> {}", state, canonical.display(), exe.display());
+        }
+    }
+    Ok(exe)
+}
+
+/// Directory for files perry writes on a script's behalf (e.g. `perry node -e` code)
+pub(crate) fn scratch_dir() -> Result<PathBuf> {
+    let dir = cache_dir()?.join("scratch");
+    fs::create_dir_all(&dir)
+        .map_err(|e| anyhow!("Failed to create {}: {}", dir.display(), e))?;
+    Ok(dir)
+}
+
+pub fn run(args: RunArgs, format: OutputFormat, _use_color: bool, verbose: u8) -> Result<()> {
+    let exe = cached_compile(&args.script, args.rebuild, &[], format, verbose)?;
+    exec(&exe, &args.args)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detects_perry_shebang() {
+        let dir = std::env::temp_dir().join(format!("perry-run-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let script = dir.join("hello");
+        fs::write(&script, "#!/usr/bin/env perry\nconsole.log('hi');\n").unwrap();
+        assert!(has_perry_shebang(&script));
+
+        let node_script = dir.join("hello.js");
+        fs::write(&node_script, "#!/usr/bin/env node\nconsole.log('hi');\n").unwrap();
+        assert!(!has_perry_shebang(&node_script));
+
+        let plain = dir.join("plain.ts");
+        fs::write(&plain, "// perry\nconsole.log('hi');\n").unwrap();
+        assert!(!has_perry_shebang(&plain));
+
+        assert!(!has_perry_shebang(&dir.join("missing.ts")));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_cache_key_tracks_contents_and_path() {
+        let dir = Path::new("/cache");
+        let a = cached_binary(dir, Path::new("/scripts/tool.ts"), b"console.log(1)", &[]);
+        let b = cached_binary(dir, Path::new("/scripts/tool.ts"), b"console.log(2)", &[]);
+        let c = cached_binary(dir, Path::new("/other/tool.ts"), b"console.log(1)", &[]);
+        let d = cached_binary(dir, Path::new("/scripts/tool.ts"), b"console.log(1)", &["--enable-js-runtime"]);
+        assert_ne!(a, b);
+        assert_ne!(a, c);
+        assert_ne!(a, d);
+        assert_eq!(a, cached_binary(dir, Path::new("/scripts/tool.ts"), b"console.log(1)", &[]));
+        assert!(a.file_name().unwrap().to_str().unwrap().starts_with("tool-"));
+    }
+
+    #[test]
+    fn test_cached_binary_tracks_imported_modules() {
+        let dir = std::env::temp_dir().join(format!("perry-run-inputs-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let main = dir.join("main.ts");
+        let util = dir.join("util.ts");
+        fs::write(&main, "import { f } from './util';\nconsole.log(f());\n").unwrap();
+        fs::write(&util, "export const f = () => 1;\n").unwrap();
+
+        let report = serde_json::json!({ "success": true, "inputs": [main, util] });
+        let stdout = format!("warning: something\n{}\n", report);
+        let inputs = reported_inputs(stdout.as_bytes());
+        assert_eq!(inputs, vec![main.clone(), util.clone()]);
+
+        let listing = dir.join("main.inputs");
+        assert!(!inputs_unchanged(&listing));
+        record_inputs(&listing, &inputs).unwrap();
+        assert!(inputs_unchanged(&listing));
+
+        // Editing an imported module makes the binary stale, as does deleting it
+        fs::write(&util, "export const f = () => 2;\n").unwrap();
+        assert!(!inputs_unchanged(&listing));
+        record_inputs(&listing, &inputs).unwrap();
+        assert!(inputs_unchanged(&listing));
+        fs::remove_file(&util).unwrap();
+        assert!(!inputs_unchanged(&listing));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
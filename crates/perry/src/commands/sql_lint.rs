@@ -0,0 +1,187 @@
+//! SQL built from strings (`perry check`)
+//!
+//! S001: SQL text assembled with `+` or template interpolation and passed to `query()`,
+//! `execute()` or `prepare()` lets a value rewrite the statement itself, which is how
+//! injection happens. Text counts as SQL when it starts with a statement keyword, and is
+//! reported when passed directly or through a variable initialized with it. Text built
+//! only from literals is fine. An interpolating template passed straight to `query()` or
+//! `execute()` comes with a rewrite to the driver's `sql` tag, which binds the
+//! interpolations as parameters; it is never applied automatically because the tag has to
+//! be imported first.
+
+use perry_diagnostics::{Applicability, Diagnostic, DiagnosticCode, FileId, Span, Suggestion};
+use perry_parser::swc_ecma_ast::*;
+use perry_parser::Spanned;
+use std::collections::HashSet;
+
+use super::blocking_lint::{strip_parens, walk_module_item, Node};
+
+/// Methods running the SQL text passed as their first argument
+const QUERY_METHODS: &[&str] = &["query", "execute", "prepare"];
+
+/// Keywords a SQL statement starts with
+const SQL_KEYWORDS: &[&str] = &[
+    "SELECT", "INSERT", "UPDATE", "DELETE", "WITH", "REPLACE", "MERGE", "UPSERT", "CALL",
+    "CREATE", "ALTER", "DROP", "TRUNCATE",
+];
+
+/// SQL-concatenation lint for one module
+pub struct SqlLinter;
+
+impl SqlLinter {
+    /// Report SQL built from strings that reaches a query method
+    pub fn check(module: &Module, file_id: FileId, source: &str) -> Vec<Diagnostic> {
+        // Variables initialized with built SQL, for `const text = ...; db.query(text)`
+        let mut built = HashSet::new();
+        for item in &module.body {
+            walk_module_item(item, &mut |node| {
+                let Node::Stmt(Stmt::Decl(Decl::Var(var))) = node else { return };
+                for declarator in &var.decls {
+                    if let (Pat::Ident(binding), Some(init)) = (&declarator.name, &declarator.init) {
+                        if is_built_sql(init) {
+                            built.insert(binding.id.sym.to_string());
+                        }
+                    }
+                }
+            });
+        }
+
+        let mut diagnostics = Vec::new();
+        for item in &module.body {
+            walk_module_item(item, &mut |node| {
+                let Node::Expr(Expr::Call(call)) = node else { return };
+                let Some(method) = query_method(call) else { return };
+                let Some(arg) = call.args.first().filter(|arg| arg.spread.is_none()) else { return };
+                let text = strip_parens(&arg.expr);
+                let label = match text {
+                    Expr::Ident(ident) if built.contains(ident.sym.as_ref()) => {
+                        format!("`{}` is built by splicing values into SQL", ident.sym)
+                    }
+                    _ if is_built_sql(text) => "values are spliced into the statement text".to_string(),
+                    _ => return,
+                };
+                let span = span(file_id, text);
+                let mut diagnostic = Diagnostic::new(
+                    DiagnosticCode::SqlConcatenation,
+                    format!("SQL passed to `{}()` is built from strings", method),
+                )
+                .with_span(span)
+                .with_primary_label(span, label)
+                .with_help("pass the values as bound parameters instead, e.g. `query(text, [id])`, or tag the template with the driver's `sql` so its interpolations are bound");
+                // The `sql` tag feeds query() and execute(); prepare() binds later
+                if matches!(text, Expr::Tpl(_)) && method != "prepare" {
+                    if let Some(tpl) = source.get(span.start as usize..span.end as usize) {
+                        // A bound value needs no quotes: '${name}' -> ${name}
+                        let tpl = tpl.replace("'${", "${").replace("}'", "}");
+                        diagnostic = diagnostic.with_suggestion(Suggestion::new(
+                            "tag the template with `sql` (imported from pg or mysql2) to bind its interpolations",
+                            span,
+                            format!("sql{}", tpl),
+                            Applicability::MaybeIncorrect,
+                        ));
+                    }
+                }
+                diagnostics.push(diagnostic.build());
+            });
+        }
+        diagnostics
+    }
+}
+
+/// `db.query(...)`, `pool.execute(...)`, `db.prepare(...)`
+fn query_method(call: &CallExpr) -> Option<&str> {
+    let Callee::Expr(callee) = &call.callee else { return None };
+    let Expr::Member(member) = strip_parens(callee) else { return None };
+    let MemberProp::Ident(method) = &member.prop else { return None };
+    QUERY_METHODS.contains(&method.sym.as_ref()).then(|| method.sym.as_ref())
+}
+
+/// SQL text with a non-literal value spliced in: `'SELECT ... ' + id` or `` `SELECT ... ${id}` ``
+fn is_built_sql(expr: &Expr) -> bool {
+    let mut operands = Vec::new();
+    concatenated(expr, &mut operands);
+    let leading = match operands.first().map(|e| strip_parens(e)) {
+        Some(Expr::Lit(Lit::Str(s))) => s.value.as_str().unwrap_or(""),
+        Some(Expr::Tpl(tpl)) => tpl.quasis.first().map(|q| q.raw.as_ref()).unwrap_or(""),
+        _ => return false,
+    };
+    starts_statement(leading) && !operands.iter().all(|e| is_literal(e))
+}
+
+/// The operands of `a + b + c`, or the expression itself
+fn concatenated<'a>(expr: &'a Expr, operands: &mut Vec<&'a Expr>) {
+    match strip_parens(expr) {
+        Expr::Bin(bin) if bin.op == BinaryOp::Add => {
+            concatenated(&bin.left, operands);
+            concatenated(&bin.right, operands);
+        }
+        expr => operands.push(expr),
+    }
+}
+
+fn is_literal(expr: &Expr) -> bool {
+    match strip_parens(expr) {
+        Expr::Lit(_) => true,
+        Expr::Tpl(tpl) => tpl.exprs.iter().all(|e| is_literal(e)),
+        _ => false,
+    }
+}
+
+fn starts_statement(text: &str) -> bool {
+    let word = text.trim_start().split(|c: char| !c.is_ascii_alphabetic()).next().unwrap_or("");
+    SQL_KEYWORDS.iter().any(|keyword| keyword.eq_ignore_ascii_case(word))
+}
+
+fn span(file_id: FileId, node: &impl Spanned) -> Span {
+    let span = node.span();
+    Span::new(file_id, span.lo.0.saturating_sub(1), span.hi.0.saturating_sub(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use perry_diagnostics::SourceCache;
+
+    fn lint(source: &str) -> Vec<Diagnostic> {
+        let mut cache = SourceCache::new();
+        let result = perry_parser::parse_typescript_with_cache(source, "test.ts", &mut cache)
+            .expect("Parse failed");
+        SqlLinter::check(&result.module, result.file_id, source)
+    }
+
+    fn flagged<'s>(source: &'s str, diagnostic: &Diagnostic) -> &'s str {
+        &source[diagnostic.span.start as usize..diagnostic.span.end as usize]
+    }
+
+    #[test]
+    fn test_concatenated_and_interpolated_sql() {
+        let source = "async function find(id: string, name: string) {\n  await pool.query('SELECT * FROM users WHERE id = ' + id);\n  await conn.execute(`DELETE FROM users WHERE name = '${name}'`);\n}";
+        let diags = lint(source);
+        assert_eq!(diags.len(), 2);
+        assert_eq!(diags[0].code, DiagnosticCode::SqlConcatenation);
+        assert_eq!(flagged(source, &diags[0]), "'SELECT * FROM users WHERE id = ' + id");
+        assert!(diags[0].suggestions.is_empty());
+        // An interpolating template gets the `sql` tag, for review, without the quotes
+        let suggestion = &diags[1].suggestions[0];
+        assert_eq!(suggestion.applicability, Applicability::MaybeIncorrect);
+        assert_eq!(suggestion.replacement, "sql`DELETE FROM users WHERE name = ${name}`");
+    }
+
+    #[test]
+    fn test_sql_built_in_a_variable() {
+        let source = "let text = 'UPDATE t SET x = 1 WHERE id = ' + req.params.id;\ndb.prepare(text).run();";
+        let diags = lint(source);
+        assert_eq!(diags.len(), 1);
+        assert_eq!(flagged(source, &diags[0]), "text");
+        assert!(diags[0].labels[0].message.contains("`text`"));
+    }
+
+    #[test]
+    fn test_safe_queries_ignored() {
+        // Bound parameters, the `sql` tag, literal-only text and non-SQL strings
+        assert!(lint("pool.query('SELECT * FROM users WHERE id = $1', [id]);").is_empty());
+        assert!(lint("pool.query(sql`SELECT * FROM users WHERE id = ${id}`);").is_empty());
+        assert!(lint("pool.query('SELECT * FROM ' + 'users');").is_empty());
+        assert!(lint("router.query(`/users/${id}`);").is_empty());
+    }
+}
@@ -0,0 +1,1216 @@
+//! Structural checking of object literals against interfaces
+//!
+//! Object literals are checked against the interface / object type they are
+//! assigned to: typed variable and class property initializers, assignments to
+//! typed variables, parameter defaults, `satisfies`, returns from functions with
+//! a declared return type, and arguments to locally declared functions. Nested
+//! literals and array literals are checked against the property / element type.
+//!
+//! Reported: required properties that are missing (T006), properties the type
+//! does not declare (T007), and property values whose literal type cannot match
+//! the declared type (T001). Anything that can't be decided syntactically
+//! (identifiers, calls, imported or generic types) is accepted.
+//!
+//! `Strictness` comes from tsconfig.json: without `strictNullChecks`, `null` and
+//! `undefined` values that don't match are warnings rather than errors, and with
+//! `noImplicitAny` unannotated parameters that aren't contextually typed (a callback
+//! passed to a call, a function assigned to a typed binding) are errors (T004).
+
+use perry_diagnostics::{closest_name, Diagnostic, DiagnosticCode, FileId, Span};
+use perry_parser::swc_ecma_ast::*;
+use perry_parser::Spanned;
+use std::collections::{HashMap, HashSet};
+
+/// Limit for following type aliases / nested types (guards against recursive aliases)
+const MAX_TYPE_DEPTH: usize = 16;
+
+/// The tsconfig.json flags that change what the checker reports
+#[derive(Debug, Clone, Copy)]
+pub struct Strictness {
+    pub strict_null_checks: bool,
+    pub no_implicit_any: bool,
+}
+
+/// Without a tsconfig.json: `null` mismatches are errors, implicit `any` isn't reported
+impl Default for Strictness {
+    fn default() -> Self {
+        Self { strict_null_checks: true, no_implicit_any: false }
+    }
+}
+
+/// Checker for object literal assignments in one module
+pub struct StructuralChecker<'a> {
+    file_id: FileId,
+    source: &'a str,
+    strictness: Strictness,
+    /// Set while checking an expression whose type comes from its context (call
+    /// arguments, typed initializers), so function parameters there aren't implicitly `any`
+    contextual: bool,
+    /// Interface declarations by name (several when merged)
+    interfaces: HashMap<String, Vec<TsInterfaceDecl>>,
+    /// Type aliases by name
+    aliases: HashMap<String, TsType>,
+    /// Parameter types of module-level function declarations
+    functions: HashMap<String, Vec<Option<TsType>>>,
+    /// Declared types of variables and parameters, innermost scope last
+    scopes: Vec<HashMap<String, TsType>>,
+    /// Declared return type of each enclosing function (None when unannotated)
+    return_types: Vec<Option<TsType>>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+/// Properties of an object type, flattened across `extends` and merged declarations
+struct ObjectShape {
+    /// Type name for messages (interface/alias name or the type's source text)
+    name: String,
+    props: Vec<PropShape>,
+    has_index_signature: bool,
+    /// False when some members are unknown (unresolved base interface, computed keys),
+    /// so extra properties can't be reported
+    complete: bool,
+}
+
+struct PropShape {
+    name: String,
+    optional: bool,
+    /// None for methods/accessors and unannotated properties
+    ty: Option<TsType>,
+    span: Span,
+}
+
+/// What can be told about a value from its syntax alone
+#[derive(Debug, Clone, PartialEq)]
+enum ValueKind {
+    Number(Option<f64>),
+    String(Option<String>),
+    Boolean(Option<bool>),
+    BigInt,
+    Null,
+    Undefined,
+    Array,
+    Object,
+    Function,
+}
+
+impl ValueKind {
+    fn describe(&self) -> String {
+        match self {
+            ValueKind::Number(Some(n)) => format_number(*n),
+            ValueKind::Number(None) => "number".to_string(),
+            ValueKind::String(Some(s)) => format!("\"{}\"", s),
+            ValueKind::String(None) => "string".to_string(),
+            ValueKind::Boolean(Some(b)) => b.to_string(),
+            ValueKind::Boolean(None) => "boolean".to_string(),
+            ValueKind::BigInt => "bigint".to_string(),
+            ValueKind::Null => "null".to_string(),
+            ValueKind::Undefined => "undefined".to_string(),
+            ValueKind::Array => "array".to_string(),
+            ValueKind::Object => "object".to_string(),
+            ValueKind::Function => "function".to_string(),
+        }
+    }
+}
+
+impl<'a> StructuralChecker<'a> {
+    /// Check every object literal assignment in a module
+    pub fn check(module: &Module, file_id: FileId, source: &'a str) -> Vec<Diagnostic> {
+        Self::check_with_strictness(module, file_id, source, Strictness::default())
+    }
+
+    /// Check a module with the strictness flags of its tsconfig.json
+    pub fn check_with_strictness(module: &Module, file_id: FileId, source: &'a str, strictness: Strictness) -> Vec<Diagnostic> {
+        let mut checker = StructuralChecker {
+            file_id,
+            source,
+            strictness,
+            contextual: false,
+            interfaces: HashMap::new(),
+            aliases: HashMap::new(),
+            functions: HashMap::new(),
+            scopes: vec![HashMap::new()],
+            return_types: Vec::new(),
+            diagnostics: Vec::new(),
+        };
+
+        // First pass: module-level types and function signatures
+        for item in &module.body {
+            match item {
+                ModuleItem::Stmt(Stmt::Decl(decl)) => checker.collect_decl(decl),
+                ModuleItem::ModuleDecl(ModuleDecl::ExportDecl(export)) => checker.collect_decl(&export.decl),
+                ModuleItem::ModuleDecl(ModuleDecl::ExportDefaultDecl(export)) => {
+                    if let DefaultDecl::TsInterfaceDecl(iface) = &export.decl {
+                        checker.collect_decl(&Decl::TsInterface(iface.clone()));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        // Second pass: check assignments
+        for item in &module.body {
+            match item {
+                ModuleItem::Stmt(stmt) => checker.check_stmt(stmt),
+                ModuleItem::ModuleDecl(ModuleDecl::ExportDecl(export)) => checker.check_decl(&export.decl),
+                ModuleItem::ModuleDecl(ModuleDecl::ExportDefaultDecl(export)) => match &export.decl {
+                    DefaultDecl::Fn(f) => checker.check_function(&f.function),
+                    DefaultDecl::Class(c) => checker.check_class(&c.class),
+                    DefaultDecl::TsInterfaceDecl(_) => {}
+                },
+                ModuleItem::ModuleDecl(ModuleDecl::ExportDefaultExpr(export)) => checker.check_expr(&export.expr),
+                _ => {}
+            }
+        }
+
+        checker.diagnostics
+    }
+
+    fn collect_decl(&mut self, decl: &Decl) {
+        match decl {
+            Decl::TsInterface(iface) => {
+                self.interfaces.entry(iface.id.sym.to_string()).or_default().push((**iface).clone());
+            }
+            Decl::TsTypeAlias(alias) => {
+                self.aliases.insert(alias.id.sym.to_string(), (*alias.type_ann).clone());
+            }
+            Decl::Fn(f) => {
+                let mut params = Vec::new();
+                for param in &f.function.params {
+                    match &param.pat {
+                        Pat::Rest(_) => break,
+                        pat => params.push(pat_type(pat)),
+                    }
+                }
+                self.functions.insert(f.ident.sym.to_string(), params);
+            }
+            _ => {}
+        }
+    }
+
+    // ---- Walking ----
+
+    fn check_stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Decl(decl) => self.check_decl(decl),
+            Stmt::Expr(expr_stmt) => self.check_expr(&expr_stmt.expr),
+            Stmt::Block(block) => self.check_block(&block.stmts),
+            Stmt::Return(ret) => {
+                if let Some(arg) = &ret.arg {
+                    let return_type = self.return_types.last().cloned().flatten();
+                    if let Some(ty) = &return_type {
+                        self.check_against(arg, ty, None);
+                    }
+                    self.check_expr_in_context(arg, return_type.is_some());
+                }
+            }
+            Stmt::If(if_stmt) => {
+                self.check_expr(&if_stmt.test);
+                self.check_stmt(&if_stmt.cons);
+                if let Some(alt) = &if_stmt.alt {
+                    self.check_stmt(alt);
+                }
+            }
+            Stmt::While(s) => {
+                self.check_expr(&s.test);
+                self.check_stmt(&s.body);
+            }
+            Stmt::DoWhile(s) => {
+                self.check_stmt(&s.body);
+                self.check_expr(&s.test);
+            }
+            Stmt::For(s) => {
+                self.scopes.push(HashMap::new());
+                match &s.init {
+                    Some(VarDeclOrExpr::VarDecl(var)) => self.check_var_decl(var),
+                    Some(VarDeclOrExpr::Expr(expr)) => self.check_expr(expr),
+                    None => {}
+                }
+                if let Some(test) = &s.test {
+                    self.check_expr(test);
+                }
+                if let Some(update) = &s.update {
+                    self.check_expr(update);
+                }
+                self.check_stmt(&s.body);
+                self.scopes.pop();
+            }
+            Stmt::ForIn(s) => {
+                self.check_expr(&s.right);
+                self.check_stmt(&s.body);
+            }
+            Stmt::ForOf(s) => {
+                self.check_expr(&s.right);
+                self.check_stmt(&s.body);
+            }
+            Stmt::Labeled(s) => self.check_stmt(&s.body),
+            Stmt::Switch(s) => {
+                self.check_expr(&s.discriminant);
+                for case in &s.cases {
+                    if let Some(test) = &case.test {
+                        self.check_expr(test);
+                    }
+                    self.check_block(&case.cons);
+                }
+            }
+            Stmt::Throw(s) => self.check_expr(&s.arg),
+            Stmt::Try(s) => {
+                self.check_block(&s.block.stmts);
+                if let Some(handler) = &s.handler {
+                    self.check_block(&handler.body.stmts);
+                }
+                if let Some(finalizer) = &s.finalizer {
+                    self.check_block(&finalizer.stmts);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn check_block(&mut self, stmts: &[Stmt]) {
+        self.scopes.push(HashMap::new());
+        for stmt in stmts {
+            self.check_stmt(stmt);
+        }
+        self.scopes.pop();
+    }
+
+    fn check_decl(&mut self, decl: &Decl) {
+        match decl {
+            Decl::Var(var) => self.check_var_decl(var),
+            Decl::Fn(f) => self.check_function(&f.function),
+            Decl::Class(c) => self.check_class(&c.class),
+            _ => {}
+        }
+    }
+
+    fn check_var_decl(&mut self, var: &VarDecl) {
+        for declarator in &var.decls {
+            let declared = pat_type(&declarator.name);
+            if let Some(init) = &declarator.init {
+                if let Some(ty) = &declared {
+                    self.check_against(init, ty, None);
+                }
+                self.check_expr_in_context(init, declared.is_some());
+            }
+            if let (Pat::Ident(binding), Some(ty)) = (&declarator.name, declared) {
+                self.declare(binding.id.sym.to_string(), ty);
+            }
+        }
+    }
+
+    fn check_function(&mut self, function: &Function) {
+        let return_type = function.return_type.as_ref()
+            .and_then(|ann| return_value_type(&ann.type_ann, function.is_async));
+        let contextual = std::mem::replace(&mut self.contextual, false);
+        self.scopes.push(HashMap::new());
+        for param in &function.params {
+            if !contextual {
+                self.check_implicit_any(&param.pat);
+            }
+            self.check_param(&param.pat);
+        }
+        self.return_types.push(return_type);
+        if let Some(body) = &function.body {
+            self.check_block(&body.stmts);
+        }
+        self.return_types.pop();
+        self.scopes.pop();
+        self.contextual = contextual;
+    }
+
+    fn check_arrow(&mut self, arrow: &ArrowExpr) {
+        let return_type = arrow.return_type.as_ref()
+            .and_then(|ann| return_value_type(&ann.type_ann, arrow.is_async));
+        let contextual = std::mem::replace(&mut self.contextual, false);
+        self.scopes.push(HashMap::new());
+        for pat in &arrow.params {
+            if !contextual {
+                self.check_implicit_any(pat);
+            }
+            self.check_param(pat);
+        }
+        match &*arrow.body {
+            BlockStmtOrExpr::BlockStmt(block) => {
+                self.return_types.push(return_type);
+                self.check_block(&block.stmts);
+                self.return_types.pop();
+            }
+            BlockStmtOrExpr::Expr(body) => {
+                if let Some(ty) = &return_type {
+                    self.check_against(body, ty, None);
+                }
+                self.return_types.push(None);
+                self.check_expr(body);
+                self.return_types.pop();
+            }
+        }
+        self.scopes.pop();
+        self.contextual = contextual;
+    }
+
+    /// With `noImplicitAny`, report a parameter that has neither a type nor a default
+    fn check_implicit_any(&mut self, pat: &Pat) {
+        if !self.strictness.no_implicit_any {
+            return;
+        }
+        let name = match pat {
+            Pat::Ident(binding) if binding.type_ann.is_none() => binding.id.sym.to_string(),
+            Pat::Rest(rest) if rest.type_ann.is_none() => match &*rest.arg {
+                Pat::Ident(binding) => binding.id.sym.to_string(),
+                _ => "(rest)".to_string(),
+            },
+            Pat::Object(obj) if obj.type_ann.is_none() => "(destructured)".to_string(),
+            Pat::Array(arr) if arr.type_ann.is_none() => "(destructured)".to_string(),
+            _ => return,
+        };
+        self.diagnostics.push(
+            Diagnostic::error(
+                DiagnosticCode::ImplicitAny,
+                format!("Parameter '{}' implicitly has an 'any' type", name),
+            )
+            .with_span(self.span(pat))
+            .build(),
+        );
+    }
+
+    /// Record a parameter's type and check its default value
+    fn check_param(&mut self, pat: &Pat) {
+        if let Pat::Assign(assign) = pat {
+            let declared = pat_type(&assign.left);
+            if let Some(ty) = &declared {
+                self.check_against(&assign.right, ty, None);
+            }
+            self.check_expr_in_context(&assign.right, declared.is_some());
+        }
+        let binding = match pat {
+            Pat::Assign(assign) => match &*assign.left {
+                Pat::Ident(binding) => Some(binding),
+                _ => None,
+            },
+            Pat::Ident(binding) => Some(binding),
+            _ => None,
+        };
+        if let (Some(binding), Some(ty)) = (binding, pat_type(pat)) {
+            self.declare(binding.id.sym.to_string(), ty);
+        }
+    }
+
+    fn check_class(&mut self, class: &Class) {
+        for member in &class.body {
+            match member {
+                ClassMember::ClassProp(prop) => {
+                    if let Some(value) = &prop.value {
+                        if let Some(ann) = &prop.type_ann {
+                            self.check_against(value, &ann.type_ann, None);
+                        }
+                        self.check_expr_in_context(value, prop.type_ann.is_some());
+                    }
+                }
+                ClassMember::PrivateProp(prop) => {
+                    if let Some(value) = &prop.value {
+                        if let Some(ann) = &prop.type_ann {
+                            self.check_against(value, &ann.type_ann, None);
+                        }
+                        self.check_expr_in_context(value, prop.type_ann.is_some());
+                    }
+                }
+                ClassMember::Method(method) => self.check_function(&method.function),
+                ClassMember::PrivateMethod(method) => self.check_function(&method.function),
+                ClassMember::Constructor(ctor) => {
+                    self.scopes.push(HashMap::new());
+                    for param in &ctor.params {
+                        if let ParamOrTsParamProp::Param(param) = param {
+                            self.check_param(&param.pat);
+                        }
+                    }
+                    self.return_types.push(None);
+                    if let Some(body) = &ctor.body {
+                        self.check_block(&body.stmts);
+                    }
+                    self.return_types.pop();
+                    self.scopes.pop();
+                }
+                ClassMember::StaticBlock(block) => {
+                    self.return_types.push(None);
+                    self.check_block(&block.body.stmts);
+                    self.return_types.pop();
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Check an expression, typed by its context when `contextual` (functions in it,
+    /// including inside object and array literals, take their parameter types from it)
+    fn check_expr_in_context(&mut self, expr: &Expr, contextual: bool) {
+        let outer = std::mem::replace(&mut self.contextual, contextual);
+        self.check_expr(expr);
+        self.contextual = outer;
+    }
+
+    fn check_expr(&mut self, expr: &Expr) {
+        // Only functions directly inside (or nested in literals inside) a contextual
+        // expression inherit its types
+        let contextual = match expr {
+            Expr::Arrow(_) | Expr::Fn(_) | Expr::Object(_) | Expr::Array(_) | Expr::Paren(_) | Expr::Cond(_) => self.contextual,
+            Expr::TsAs(_) | Expr::TsSatisfies(_) | Expr::TsTypeAssertion(_) | Expr::TsConstAssertion(_) => true,
+            _ => false,
+        };
+        let outer = std::mem::replace(&mut self.contextual, contextual);
+        self.check_expr_kind(expr);
+        self.contextual = outer;
+    }
+
+    fn check_expr_kind(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Assign(assign) => {
+                // Assignments to members and typed variables give the value a type
+                let mut contextual = !matches!(assign.left, AssignTarget::Simple(SimpleAssignTarget::Ident(_)));
+                if assign.op == AssignOp::Assign {
+                    if let AssignTarget::Simple(SimpleAssignTarget::Ident(binding)) = &assign.left {
+                        if let Some(ty) = self.lookup(&binding.id.sym) {
+                            self.check_against(&assign.right, &ty, None);
+                            contextual = true;
+                        }
+                    }
+                }
+                self.check_expr_in_context(&assign.right, contextual);
+            }
+            Expr::Call(call) => {
+                if let Callee::Expr(callee) = &call.callee {
+                    if let Expr::Ident(ident) = &**callee {
+                        // Skip calls to shadowed names
+                        let params = if self.lookup(&ident.sym).is_none() {
+                            self.functions.get(ident.sym.as_ref()).cloned()
+                        } else {
+                            None
+                        };
+                        if let Some(params) = params {
+                            for (arg, param) in call.args.iter().zip(params.iter()) {
+                                if arg.spread.is_some() {
+                                    break;
+                                }
+                                if let Some(ty) = param {
+                                    self.check_against(&arg.expr, ty, None);
+                                }
+                            }
+                        }
+                    }
+                    self.check_expr(callee);
+                }
+                for arg in &call.args {
+                    self.check_expr_in_context(&arg.expr, true);
+                }
+            }
+            Expr::New(new) => {
+                self.check_expr(&new.callee);
+                for arg in new.args.iter().flatten() {
+                    self.check_expr_in_context(&arg.expr, true);
+                }
+            }
+            Expr::Arrow(arrow) => self.check_arrow(arrow),
+            Expr::Fn(f) => self.check_function(&f.function),
+            Expr::Class(c) => self.check_class(&c.class),
+            Expr::TsSatisfies(s) => {
+                self.check_against(&s.expr, &s.type_ann, None);
+                self.check_expr(&s.expr);
+            }
+            Expr::Paren(p) => self.check_expr(&p.expr),
+            Expr::Seq(seq) => {
+                for e in &seq.exprs {
+                    self.check_expr(e);
+                }
+            }
+            Expr::Cond(c) => {
+                self.check_expr(&c.test);
+                self.check_expr(&c.cons);
+                self.check_expr(&c.alt);
+            }
+            Expr::Bin(b) => {
+                self.check_expr(&b.left);
+                self.check_expr(&b.right);
+            }
+            Expr::Unary(u) => self.check_expr(&u.arg),
+            Expr::Await(a) => self.check_expr(&a.arg),
+            Expr::Member(m) => self.check_expr(&m.obj),
+            Expr::Array(arr) => {
+                for elem in arr.elems.iter().flatten() {
+                    self.check_expr(&elem.expr);
+                }
+            }
+            Expr::Object(obj) => {
+                for prop in &obj.props {
+                    match prop {
+                        PropOrSpread::Spread(spread) => self.check_expr(&spread.expr),
+                        PropOrSpread::Prop(prop) => match &**prop {
+                            Prop::KeyValue(kv) => self.check_expr(&kv.value),
+                            Prop::Method(m) => self.check_function(&m.function),
+                            _ => {}
+                        },
+                    }
+                }
+            }
+            Expr::Tpl(tpl) => {
+                for e in &tpl.exprs {
+                    self.check_expr(e);
+                }
+            }
+            Expr::TsAs(a) => self.check_expr(&a.expr),
+            Expr::TsNonNull(n) => self.check_expr(&n.expr),
+            Expr::TsConstAssertion(c) => self.check_expr(&c.expr),
+            Expr::TsTypeAssertion(t) => self.check_expr(&t.expr),
+            _ => {}
+        }
+    }
+
+    fn declare(&mut self, name: String, ty: TsType) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name, ty);
+        }
+    }
+
+    fn lookup(&self, name: &str) -> Option<TsType> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name).cloned())
+    }
+
+    // ---- Checking ----
+
+    /// Check a value against its declared type. `context` names the property being
+    /// checked, for messages.
+    fn check_against(&mut self, expr: &Expr, ty: &TsType, context: Option<(&str, &str)>) {
+        self.check_against_depth(expr, ty, context, 0);
+    }
+
+    fn check_against_depth(&mut self, expr: &Expr, ty: &TsType, context: Option<(&str, &str)>, depth: usize) {
+        if depth > MAX_TYPE_DEPTH {
+            return;
+        }
+        let expr = strip_parens(expr);
+        let ty = &self.resolve_alias(ty, 0).clone();
+
+        if let Some(kind) = value_kind(expr) {
+            if self.accepts(ty, &kind, 0) == Some(false) {
+                let type_text = self.type_text(ty);
+                let message = match context {
+                    Some((prop, owner)) => format!(
+                        "Type '{}' is not assignable to type '{}' of property '{}' in '{}'",
+                        kind.describe(), type_text, prop, owner
+                    ),
+                    None => format!("Type '{}' is not assignable to type '{}'", kind.describe(), type_text),
+                };
+                // Without strictNullChecks tsc accepts it; perry still stores the field
+                // unboxed, so it's worth a warning
+                let builder = if !self.strictness.strict_null_checks && matches!(kind, ValueKind::Null | ValueKind::Undefined) {
+                    Diagnostic::warning(DiagnosticCode::TypeMismatch, message)
+                } else {
+                    Diagnostic::error(DiagnosticCode::TypeMismatch, message)
+                };
+                self.diagnostics.push(builder.with_span(self.span(expr)).build());
+                return;
+            }
+        }
+
+        match expr {
+            Expr::Object(obj) => {
+                if let Some(shape) = self.object_target(ty) {
+                    self.check_object(obj, &shape, depth);
+                }
+            }
+            Expr::Array(arr) => {
+                if let Some(elem_ty) = self.element_type(ty) {
+                    for elem in arr.elems.iter().flatten() {
+                        if elem.spread.is_none() {
+                            self.check_against_depth(&elem.expr, &elem_ty, None, depth + 1);
+                        }
+                    }
+                }
+            }
+            Expr::Cond(cond) => {
+                self.check_against_depth(&cond.cons, ty, context, depth + 1);
+                self.check_against_depth(&cond.alt, ty, context, depth + 1);
+            }
+            _ => {}
+        }
+    }
+
+    fn check_object(&mut self, obj: &ObjectLit, shape: &ObjectShape, depth: usize) {
+        // Spreads and computed keys may supply any property
+        let mut open = false;
+        let mut seen = HashSet::new();
+
+        for prop in &obj.props {
+            let prop = match prop {
+                PropOrSpread::Spread(_) => {
+                    open = true;
+                    continue;
+                }
+                PropOrSpread::Prop(prop) => prop,
+            };
+            let (key, key_span, value) = match &**prop {
+                Prop::KeyValue(kv) => match prop_name(&kv.key) {
+                    Some(name) => (name, self.span(&kv.key), Some(&*kv.value)),
+                    None => {
+                        open = true;
+                        continue;
+                    }
+                },
+                Prop::Shorthand(ident) => (ident.sym.to_string(), self.span(ident), None),
+                Prop::Method(m) => match prop_name(&m.key) {
+                    Some(name) => (name, self.span(&m.key), None),
+                    None => {
+                        open = true;
+                        continue;
+                    }
+                },
+                Prop::Getter(g) => match prop_name(&g.key) {
+                    Some(name) => (name, self.span(&g.key), None),
+                    None => {
+                        open = true;
+                        continue;
+                    }
+                },
+                Prop::Setter(s) => match prop_name(&s.key) {
+                    Some(name) => (name, self.span(&s.key), None),
+                    None => {
+                        open = true;
+                        continue;
+                    }
+                },
+                Prop::Assign(_) => continue,
+            };
+
+            match shape.props.iter().find(|p| p.name == key) {
+                Some(declared) => {
+                    if let (Some(value), Some(ty)) = (value, &declared.ty) {
+                        let optional_undefined = declared.optional
+                            && value_kind(value) == Some(ValueKind::Undefined);
+                        if !optional_undefined {
+                            self.check_against_depth(value, ty, Some((&key, &shape.name)), depth + 1);
+                        }
+                    }
+                }
+                None if shape.complete && !shape.has_index_signature => {
+                    let mut builder = Diagnostic::error(
+                        DiagnosticCode::ExcessProperty,
+                        format!("Object literal may only specify known properties, and '{}' does not exist in type '{}'", key, shape.name),
+                    )
+                    .with_span(key_span);
+                    let unused: Vec<&str> = shape.props.iter()
+                        .map(|p| p.name.as_str())
+                        .filter(|name| !obj_has_key(obj, name))
+                        .collect();
+                    if let Some(suggestion) = closest_name(&key, &unused) {
+                        builder = builder.suggest_replace(
+                            key_span,
+                            suggestion,
+                            format!("did you mean '{}'?", suggestion),
+                        );
+                    }
+                    self.diagnostics.push(builder.build());
+                }
+                None => {}
+            }
+            seen.insert(key);
+        }
+
+        if open {
+            return;
+        }
+        let missing: Vec<&PropShape> = shape.props.iter()
+            .filter(|p| !p.optional && !seen.contains(&p.name))
+            .collect();
+        if missing.is_empty() {
+            return;
+        }
+        let names: Vec<String> = missing.iter().map(|p| format!("'{}'", p.name)).collect();
+        let message = if missing.len() == 1 {
+            format!("Property {} is missing but required in type '{}'", names[0], shape.name)
+        } else {
+            format!("Properties {} are missing but required in type '{}'", names.join(", "), shape.name)
+        };
+        let mut builder = Diagnostic::error(DiagnosticCode::MissingProperty, message)
+            .with_span(self.span(obj));
+        for prop in missing {
+            builder = builder.with_label(prop.span, format!("'{}' is declared here", prop.name));
+        }
+        self.diagnostics.push(builder.build());
+    }
+
+    /// Whether a value of `kind` can have type `ty`; None when it can't be decided
+    fn accepts(&self, ty: &TsType, kind: &ValueKind, depth: usize) -> Option<bool> {
+        if depth > MAX_TYPE_DEPTH {
+            return None;
+        }
+        match ty {
+            TsType::TsKeywordType(kw) => {
+                use TsKeywordTypeKind::*;
+                match kw.kind {
+                    TsAnyKeyword | TsUnknownKeyword => Some(true),
+                    TsNumberKeyword => Some(matches!(kind, ValueKind::Number(_))),
+                    TsStringKeyword => Some(matches!(kind, ValueKind::String(_))),
+                    TsBooleanKeyword => Some(matches!(kind, ValueKind::Boolean(_))),
+                    TsBigIntKeyword => Some(matches!(kind, ValueKind::BigInt)),
+                    TsNullKeyword => Some(matches!(kind, ValueKind::Null)),
+                    TsUndefinedKeyword | TsVoidKeyword => Some(matches!(kind, ValueKind::Undefined)),
+                    TsObjectKeyword => Some(matches!(kind, ValueKind::Object | ValueKind::Array | ValueKind::Function)),
+                    TsNeverKeyword => Some(false),
+                    _ => None,
+                }
+            }
+            TsType::TsLitType(lit) => match (&lit.lit, kind) {
+                (TsLit::Number(n), ValueKind::Number(Some(v))) => Some(n.value == *v),
+                (TsLit::Str(s), ValueKind::String(Some(v))) => Some(s.value.as_str() == Some(v.as_str())),
+                (TsLit::Bool(b), ValueKind::Boolean(Some(v))) => Some(b.value == *v),
+                (TsLit::Number(_), ValueKind::Number(None))
+                | (TsLit::Str(_), ValueKind::String(None))
+                | (TsLit::Bool(_), ValueKind::Boolean(None))
+                | (TsLit::Tpl(_), ValueKind::String(_))
+                | (TsLit::BigInt(_), ValueKind::BigInt) => None,
+                _ => Some(false),
+            },
+            TsType::TsUnionOrIntersectionType(TsUnionOrIntersectionType::TsUnionType(union)) => {
+                let mut undecided = false;
+                for member in &union.types {
+                    match self.accepts(self.resolve_alias(member, 0), kind, depth + 1) {
+                        Some(true) => return Some(true),
+                        Some(false) => {}
+                        None => undecided = true,
+                    }
+                }
+                if undecided { None } else { Some(false) }
+            }
+            TsType::TsParenthesizedType(p) => self.accepts(&p.type_ann, kind, depth + 1),
+            TsType::TsTypeOperator(op) if op.op == TsTypeOperatorOp::ReadOnly => {
+                self.accepts(&op.type_ann, kind, depth + 1)
+            }
+            TsType::TsArrayType(_) | TsType::TsTupleType(_) => Some(matches!(kind, ValueKind::Array)),
+            TsType::TsFnOrConstructorType(_) => match kind {
+                ValueKind::Function => Some(true),
+                ValueKind::Object => None,
+                _ => Some(false),
+            },
+            TsType::TsTypeLit(_) => object_accepts(kind),
+            TsType::TsTypeRef(r) => {
+                let name = type_ref_name(r)?;
+                if let Some(alias) = self.aliases.get(name) {
+                    return self.accepts(alias, kind, depth + 1);
+                }
+                if self.interfaces.contains_key(name) {
+                    return object_accepts(kind);
+                }
+                match name {
+                    "Array" | "ReadonlyArray" => Some(matches!(kind, ValueKind::Array)),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// The object type an object literal must match, if it is a single known one.
+    /// For unions like `T | null | undefined`, the only object-like member is used.
+    fn object_target(&self, ty: &TsType) -> Option<ObjectShape> {
+        let ty = self.resolve_alias(ty, 0);
+        if let TsType::TsUnionOrIntersectionType(TsUnionOrIntersectionType::TsUnionType(union)) = ty {
+            let mut object_members = union.types.iter()
+                .map(|t| self.resolve_alias(t, 0))
+                .filter(|t| self.accepts(t, &ValueKind::Object, 0) != Some(false));
+            let first = object_members.next()?;
+            if object_members.next().is_some() {
+                return None;
+            }
+            return self.shape_of(first, &mut Vec::new());
+        }
+        self.shape_of(ty, &mut Vec::new())
+    }
+
+    /// Element type for array literals assigned to `T[]` / `Array<T>` / `readonly T[]`
+    fn element_type(&self, ty: &TsType) -> Option<TsType> {
+        match self.resolve_alias(ty, 0) {
+            TsType::TsArrayType(arr) => Some((*arr.elem_type).clone()),
+            TsType::TsParenthesizedType(p) => self.element_type(&p.type_ann),
+            TsType::TsTypeOperator(op) if op.op == TsTypeOperatorOp::ReadOnly => self.element_type(&op.type_ann),
+            TsType::TsTypeRef(r) if matches!(type_ref_name(r), Some("Array" | "ReadonlyArray")) => {
+                let args = r.type_params.as_ref()?;
+                Some((*args.params.first()?.clone()).clone())
+            }
+            _ => None,
+        }
+    }
+
+    /// Properties of an object type: interfaces (with `extends` and merging), type
+    /// literals, aliases of those, and intersections of them
+    fn shape_of(&self, ty: &TsType, visiting: &mut Vec<String>) -> Option<ObjectShape> {
+        match self.resolve_alias(ty, 0) {
+            TsType::TsTypeLit(lit) => {
+                let mut shape = ObjectShape {
+                    name: self.type_text(ty),
+                    props: Vec::new(),
+                    has_index_signature: false,
+                    complete: true,
+                };
+                add_members(&mut shape, &lit.members, self.file_id);
+                Some(shape)
+            }
+            TsType::TsParenthesizedType(p) => self.shape_of(&p.type_ann, visiting),
+            TsType::TsTypeRef(r) => {
+                let name = type_ref_name(r)?;
+                self.interface_shape(name, visiting)
+            }
+            TsType::TsUnionOrIntersectionType(TsUnionOrIntersectionType::TsIntersectionType(inter)) => {
+                let mut shape = ObjectShape {
+                    name: self.type_text(ty),
+                    props: Vec::new(),
+                    has_index_signature: false,
+                    complete: true,
+                };
+                for part in &inter.types {
+                    let part = self.shape_of(part, visiting)?;
+                    merge_shape(&mut shape, part);
+                }
+                Some(shape)
+            }
+            _ => None,
+        }
+    }
+
+    fn interface_shape(&self, name: &str, visiting: &mut Vec<String>) -> Option<ObjectShape> {
+        let decls = self.interfaces.get(name)?;
+        if visiting.iter().any(|v| v == name) {
+            return None;
+        }
+        visiting.push(name.to_string());
+
+        let mut shape = ObjectShape {
+            name: name.to_string(),
+            props: Vec::new(),
+            has_index_signature: false,
+            complete: true,
+        };
+        for decl in decls {
+            for base in &decl.extends {
+                let base_shape = match &*base.expr {
+                    Expr::Ident(ident) => self.shape_of_name(&ident.sym, visiting),
+                    _ => None,
+                };
+                match base_shape {
+                    Some(base_shape) => merge_shape(&mut shape, base_shape),
+                    None => shape.complete = false,
+                }
+            }
+        }
+        for decl in decls {
+            add_members(&mut shape, &decl.body.body, self.file_id);
+        }
+
+        visiting.pop();
+        Some(shape)
+    }
+
+    fn shape_of_name(&self, name: &str, visiting: &mut Vec<String>) -> Option<ObjectShape> {
+        if let Some(alias) = self.aliases.get(name) {
+            return self.shape_of(alias, visiting);
+        }
+        self.interface_shape(name, visiting)
+    }
+
+    /// Follow type aliases (`type Id = string | number`) to the aliased type
+    fn resolve_alias<'t>(&'t self, ty: &'t TsType, depth: usize) -> &'t TsType {
+        if depth > MAX_TYPE_DEPTH {
+            return ty;
+        }
+        match ty {
+            TsType::TsTypeRef(r) if r.type_params.is_none() => {
+                match type_ref_name(r).and_then(|name| self.aliases.get(name)) {
+                    Some(alias) => self.resolve_alias(alias, depth + 1),
+                    None => ty,
+                }
+            }
+            TsType::TsParenthesizedType(p) => self.resolve_alias(&p.type_ann, depth + 1),
+            _ => ty,
+        }
+    }
+
+    fn type_text(&self, ty: &TsType) -> String {
+        let span = ty.span();
+        let (start, end) = (span.lo.0.saturating_sub(1) as usize, span.hi.0.saturating_sub(1) as usize);
+        self.source.get(start..end)
+            .map(|s| s.split_whitespace().collect::<Vec<_>>().join(" "))
+            .unwrap_or_else(|| "unknown".to_string())
+    }
+
+    fn span(&self, node: &impl Spanned) -> Span {
+        diagnostic_span(self.file_id, node)
+    }
+}
+
+fn object_accepts(kind: &ValueKind) -> Option<bool> {
+    match kind {
+        ValueKind::Object => Some(true),
+        ValueKind::Array | ValueKind::Function => None,
+        _ => Some(false),
+    }
+}
+
+/// Convert an SWC node span to a diagnostic span
+/// Note: SWC BytePos starts at 1, diagnostic offsets at 0
+fn diagnostic_span(file_id: FileId, node: &impl Spanned) -> Span {
+    let span = node.span();
+    Span::new(file_id, span.lo.0.saturating_sub(1), span.hi.0.saturating_sub(1))
+}
+
+fn add_members(shape: &mut ObjectShape, members: &[TsTypeElement], file_id: FileId) {
+    for member in members {
+        let (key, computed, optional, ty, span) = match member {
+            TsTypeElement::TsPropertySignature(p) => (
+                &p.key,
+                p.computed,
+                p.optional,
+                p.type_ann.as_ref().map(|ann| (*ann.type_ann).clone()),
+                diagnostic_span(file_id, p),
+            ),
+            TsTypeElement::TsMethodSignature(m) => (&m.key, m.computed, m.optional, None, diagnostic_span(file_id, m)),
+            TsTypeElement::TsGetterSignature(g) => (&g.key, g.computed, false, None, diagnostic_span(file_id, g)),
+            TsTypeElement::TsSetterSignature(s) => (&s.key, s.computed, false, None, diagnostic_span(file_id, s)),
+            TsTypeElement::TsIndexSignature(_) => {
+                shape.has_index_signature = true;
+                continue;
+            }
+            _ => continue,
+        };
+        let name = match (computed, &**key) {
+            (false, Expr::Ident(ident)) => ident.sym.to_string(),
+            (_, Expr::Lit(Lit::Str(s))) => s.value.as_str().unwrap_or("").to_string(),
+            (_, Expr::Lit(Lit::Num(n))) => format_number(n.value),
+            _ => {
+                shape.complete = false;
+                continue;
+            }
+        };
+        let prop = PropShape { name, optional, ty, span };
+        match shape.props.iter_mut().find(|p| p.name == prop.name) {
+            Some(existing) => *existing = prop,
+            None => shape.props.push(prop),
+        }
+    }
+}
+
+fn merge_shape(shape: &mut ObjectShape, other: ObjectShape) {
+    shape.has_index_signature |= other.has_index_signature;
+    shape.complete &= other.complete;
+    for prop in other.props {
+        match shape.props.iter_mut().find(|p| p.name == prop.name) {
+            Some(existing) => *existing = prop,
+            None => shape.props.push(prop),
+        }
+    }
+}
+
+/// Declared type of a binding pattern (`x: T`, `x: T = default`)
+fn pat_type(pat: &Pat) -> Option<TsType> {
+    match pat {
+        Pat::Ident(binding) => binding.type_ann.as_ref().map(|ann| (*ann.type_ann).clone()),
+        Pat::Assign(assign) => pat_type(&assign.left),
+        Pat::Object(obj) => obj.type_ann.as_ref().map(|ann| (*ann.type_ann).clone()),
+        Pat::Array(arr) => arr.type_ann.as_ref().map(|ann| (*ann.type_ann).clone()),
+        _ => None,
+    }
+}
+
+/// Type checked against `return` values: `Promise<T>` of an async function is `T`
+fn return_value_type(ty: &TsType, is_async: bool) -> Option<TsType> {
+    if is_async {
+        if let TsType::TsTypeRef(r) = ty {
+            if type_ref_name(r) == Some("Promise") {
+                return r.type_params.as_ref()
+                    .and_then(|args| args.params.first())
+                    .map(|t| (**t).clone());
+            }
+        }
+        return None;
+    }
+    Some(ty.clone())
+}
+
+fn type_ref_name(r: &TsTypeRef) -> Option<&str> {
+    match &r.type_name {
+        TsEntityName::Ident(ident) => Some(ident.sym.as_ref()),
+        _ => None,
+    }
+}
+
+fn strip_parens(expr: &Expr) -> &Expr {
+    match expr {
+        Expr::Paren(p) => strip_parens(&p.expr),
+        _ => expr,
+    }
+}
+
+/// Literal type of an expression, if syntax alone determines it
+fn value_kind(expr: &Expr) -> Option<ValueKind> {
+    match strip_parens(expr) {
+        Expr::Lit(Lit::Num(n)) => Some(ValueKind::Number(Some(n.value))),
+        Expr::Lit(Lit::Str(s)) => Some(ValueKind::String(s.value.as_str().map(str::to_string))),
+        Expr::Lit(Lit::Bool(b)) => Some(ValueKind::Boolean(Some(b.value))),
+        Expr::Lit(Lit::Null(_)) => Some(ValueKind::Null),
+        Expr::Lit(Lit::BigInt(_)) => Some(ValueKind::BigInt),
+        Expr::Tpl(_) => Some(ValueKind::String(None)),
+        Expr::Ident(ident) if &*ident.sym == "undefined" => Some(ValueKind::Undefined),
+        Expr::Array(_) => Some(ValueKind::Array),
+        Expr::Object(_) => Some(ValueKind::Object),
+        Expr::Arrow(_) | Expr::Fn(_) => Some(ValueKind::Function),
+        Expr::Unary(u) => match (u.op, value_kind(&u.arg)) {
+            (UnaryOp::Minus, Some(ValueKind::Number(Some(n)))) => Some(ValueKind::Number(Some(-n))),
+            (UnaryOp::Minus | UnaryOp::Plus | UnaryOp::Tilde, _) => Some(ValueKind::Number(None)),
+            (UnaryOp::Bang, _) => Some(ValueKind::Boolean(None)),
+            (UnaryOp::TypeOf, _) => Some(ValueKind::String(None)),
+            (UnaryOp::Void, _) => Some(ValueKind::Undefined),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn prop_name(key: &PropName) -> Option<String> {
+    match key {
+        PropName::Ident(ident) => Some(ident.sym.to_string()),
+        PropName::Str(s) => s.value.as_str().map(str::to_string),
+        PropName::Num(n) => Some(format_number(n.value)),
+        _ => None,
+    }
+}
+
+fn obj_has_key(obj: &ObjectLit, name: &str) -> bool {
+    obj.props.iter().any(|prop| match prop {
+        PropOrSpread::Prop(prop) => match &**prop {
+            Prop::KeyValue(kv) => prop_name(&kv.key).as_deref() == Some(name),
+            Prop::Shorthand(ident) => &*ident.sym == name,
+            Prop::Method(m) => prop_name(&m.key).as_deref() == Some(name),
+            Prop::Getter(g) => prop_name(&g.key).as_deref() == Some(name),
+            Prop::Setter(s) => prop_name(&s.key).as_deref() == Some(name),
+            Prop::Assign(_) => false,
+        },
+        PropOrSpread::Spread(_) => false,
+    })
+}
+
+fn format_number(n: f64) -> String {
+    if n.fract() == 0.0 && n.abs() < 1e15 {
+        format!("{}", n as i64)
+    } else {
+        format!("{}", n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use perry_diagnostics::SourceCache;
+
+    fn check_code(source: &str) -> Vec<Diagnostic> {
+        let mut cache = SourceCache::new();
+        let result = perry_parser::parse_typescript_with_cache(source, "test.ts", &mut cache)
+            .expect("Parse failed");
+        StructuralChecker::check(&result.module, result.file_id, source)
+    }
+
+    fn spanned_text<'s>(source: &'s str, diag: &Diagnostic) -> &'s str {
+        &source[diag.span.start as usize..diag.span.end as usize]
+    }
+
+    fn check_strict(source: &str, strictness: Strictness) -> Vec<Diagnostic> {
+        let mut cache = SourceCache::new();
+        let result = perry_parser::parse_typescript_with_cache(source, "test.ts", &mut cache)
+            .expect("Parse failed");
+        StructuralChecker::check_with_strictness(&result.module, result.file_id, source, strictness)
+    }
+
+    #[test]
+    fn test_strictness_flags() {
+        let source = r#"
+            interface User { name: string }
+            const u: User = { name: null };
+            function greet(user, greeting = "hi", ...rest) { return [1].map((n) => n); }
+            const handler: (e: number) => void = (e) => {};
+            const loose = (x) => x;
+        "#;
+        let strict = check_strict(source, Strictness { strict_null_checks: true, no_implicit_any: true });
+        let texts: Vec<(&str, DiagnosticCode)> = strict.iter().map(|d| (spanned_text(source, d), d.code)).collect();
+        assert_eq!(texts, vec![
+            ("null", DiagnosticCode::TypeMismatch),
+            ("user", DiagnosticCode::ImplicitAny),
+            ("...rest", DiagnosticCode::ImplicitAny),
+            ("x", DiagnosticCode::ImplicitAny),
+        ]);
+        assert!(strict[0].is_error());
+
+        let loose = check_strict(source, Strictness { strict_null_checks: false, no_implicit_any: false });
+        assert_eq!(loose.len(), 1);
+        assert_eq!(loose[0].severity, perry_diagnostics::Severity::Warning);
+    }
+
+    #[test]
+    fn test_excess_property_with_suggestion() {
+        let source = "interface User { name: string; age: number }\nconst u: User = { nmae: \"a\", age: 1 };";
+        let diags = check_code(source);
+        assert_eq!(diags.len(), 2);
+        assert_eq!(diags[0].code, DiagnosticCode::ExcessProperty);
+        assert_eq!(spanned_text(source, &diags[0]), "nmae");
+        assert_eq!(diags[0].suggestions[0].replacement, "name");
+        assert_eq!(diags[1].code, DiagnosticCode::MissingProperty);
+        assert_eq!(spanned_text(source, &diags[1]), "{ nmae: \"a\", age: 1 }");
+        assert_eq!(diags[1].labels.len(), 1);
+    }
+
+    #[test]
+    fn test_mistyped_and_nested_properties() {
+        let source = r#"
+            type Id = string | number;
+            interface Address { city: string; zip?: string }
+            interface User { id: Id; age: number; address: Address; tags: string[] }
+            const u: User = { id: true, age: "x", address: { city: 1 }, tags: ["a", 2] };
+        "#;
+        let diags = check_code(source);
+        let texts: Vec<&str> = diags.iter().map(|d| spanned_text(source, d)).collect();
+        assert_eq!(texts, vec!["true", "\"x\"", "1", "2"]);
+        assert!(diags.iter().all(|d| d.code == DiagnosticCode::TypeMismatch));
+        assert!(diags[1].message.contains("property 'age' in 'User'"));
+    }
+
+    #[test]
+    fn test_extends_returns_assignments_and_calls() {
+        let source = r#"
+            interface Base { id: number }
+            interface Item extends Base { label: string }
+            function make(): Item { return { label: "a" }; }
+            async function load(): Promise<Item> { return { id: 1, label: "a", extra: 1 }; }
+            function save(item: Item | null) {}
+            save({ id: 1 });
+            let cur: Item = { id: 1, label: "a" };
+            cur = { id: 2 };
+        "#;
+        let codes: Vec<DiagnosticCode> = check_code(source).iter().map(|d| d.code).collect();
+        assert_eq!(codes, vec![
+            DiagnosticCode::MissingProperty,
+            DiagnosticCode::ExcessProperty,
+            DiagnosticCode::MissingProperty,
+            DiagnosticCode::MissingProperty,
+        ]);
+    }
+
+    #[test]
+    fn test_undecidable_cases_pass() {
+        let source = r#"
+            import { Remote } from "./remote";
+            interface Local extends Remote { a: number }
+            interface Dict { [key: string]: number }
+            interface Opt { a?: number; b(): void }
+            const base = { a: 1 };
+            const l: Local = { a: 1, fromRemote: true };
+            const d: Dict = { x: 1, y: 2 };
+            const o: Opt = { ...base };
+            const p: Opt = { a: undefined, b() {} };
+            const q: Opt = { a: compute(), b: handler };
+        "#;
+        assert!(check_code(source).is_empty());
+    }
+}
@@ -0,0 +1,316 @@
+//! Target platform of a build (`perry compile --target <triple>`)
+//!
+//! Without `--target` perry compiles for the machine it runs on, with that CPU's features.
+//! With it, Cranelift generates code for the named triple, the runtime and stdlib static
+//! libraries are taken from a per-target directory (they have to be built for the target:
+//! `cargo build --release --target <triple> -p perry-runtime -p perry-stdlib`), and the
+//! objects are linked by a cross-linker:
+//!
+//! 1. `PERRY_LINKER`, a command with optional arguments (e.g. `zig cc -target x86_64-linux-gnu`)
+//! 2. the GNU cross toolchain's `<arch>-linux-gnu-gcc` (or `-musl-gcc`) on PATH
+//! 3. `zig cc -target ...` when zig is on PATH
+//! 4. `clang --target=<triple>`, which needs a sysroot for the target
+//!
+//! Windows (`x86_64-pc-windows-msvc`) objects are COFF and are linked by `PERRY_LINKER`,
+//! then `lld-link` (which cross-links given the MSVC and Windows SDK libraries on `LIB`),
+//! then `link.exe`. Its static libraries are named `perry_runtime.lib` rather than
+//! `libperry_runtime.a`.
+//!
+//! WebAssembly (`wasm32-wasip1`, or by its older name `wasm32-wasi`) needs no linker:
+//! perry translates the Cranelift IR to WebAssembly itself (`perry_codegen::wasm`) and
+//! links the objects into `perry_runtime_wasi.wasm`, the subset of the runtime that runs
+//! on WASI (`cargo build --release --target wasm32-wasip1 -p perry-runtime-wasi`). The
+//! output is a `.wasm` command for WASI hosts such as wasmtime.
+
+use anyhow::{anyhow, Result};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Environment variable naming the linker command for cross builds
+pub const LINKER_ENV: &str = "PERRY_LINKER";
+
+/// Triples `--target` accepts
+pub const SUPPORTED_TARGETS: &[&str] = &[
+    "x86_64-unknown-linux-gnu",
+    "aarch64-unknown-linux-gnu",
+    "x86_64-unknown-linux-musl",
+    "aarch64-unknown-linux-musl",
+    "x86_64-apple-darwin",
+    "aarch64-apple-darwin",
+    "x86_64-pc-windows-msvc",
+    "wasm32-wasip1",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Os {
+    Linux,
+    MacOs,
+    Windows,
+    /// WebAssembly on WASI preview 1
+    Wasi,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Target {
+    /// The `--target` triple; None when building for the host
+    pub triple: Option<String>,
+    pub arch: &'static str,
+    pub os: Os,
+    /// C library: `gnu` or `musl` on Linux, `msvc` on Windows; empty for macOS and WASI
+    pub env: &'static str,
+}
+
+impl Target {
+    /// The machine perry runs on
+    pub fn host() -> Self {
+        Target {
+            triple: None,
+            arch: if cfg!(target_arch = "aarch64") { "aarch64" } else { "x86_64" },
+            os: if cfg!(target_os = "macos") {
+                Os::MacOs
+            } else if cfg!(windows) {
+                Os::Windows
+            } else {
+                Os::Linux
+            },
+            env: if cfg!(target_os = "macos") {
+                ""
+            } else if cfg!(windows) {
+                "msvc"
+            } else if cfg!(target_env = "musl") {
+                "musl"
+            } else {
+                "gnu"
+            },
+        }
+    }
+
+    /// A `--target` triple, one of [`SUPPORTED_TARGETS`]. `x86_64-linux-gnu` style
+    /// triples without the vendor, `x86_64-windows` and `wasm32-wasi` are accepted too.
+    pub fn parse(triple: &str) -> Result<Self> {
+        let canonical = match triple.split('-').collect::<Vec<_>>().as_slice() {
+            [arch, "linux", env] => format!("{}-unknown-linux-{}", arch, env),
+            [arch, "macos"] | [arch, "darwin"] => format!("{}-apple-darwin", arch),
+            [arch, "windows"] | [arch, "windows", "msvc"] => format!("{}-pc-windows-msvc", arch),
+            [arch, "wasi"] => format!("{}-wasip1", arch),
+            _ => triple.to_string(),
+        };
+        if !SUPPORTED_TARGETS.contains(&canonical.as_str()) {
+            return Err(anyhow!(
+                "Unsupported target `{}`; perry can build for {}",
+                triple,
+                SUPPORTED_TARGETS.join(", ")
+            ));
+        }
+        let arch = if canonical.starts_with("aarch64") {
+            "aarch64"
+        } else if canonical.starts_with("wasm32") {
+            "wasm32"
+        } else {
+            "x86_64"
+        };
+        let (os, env) = if canonical.ends_with("wasip1") {
+            (Os::Wasi, "")
+        } else if canonical.ends_with("darwin") {
+            (Os::MacOs, "")
+        } else if canonical.ends_with("windows-msvc") {
+            (Os::Windows, "msvc")
+        } else if canonical.ends_with("musl") {
+            (Os::Linux, "musl")
+        } else {
+            (Os::Linux, "gnu")
+        };
+        Ok(Target { triple: Some(canonical), arch, os, env })
+    }
+
+    /// The host, or `--target` when given
+    pub fn from_arg(triple: Option<&str>) -> Result<Self> {
+        match triple {
+            None => Ok(Self::host()),
+            Some(triple) => {
+                let target = Self::parse(triple)?;
+                // Naming the host keeps its CPU features and native linker
+                let host = Self::host();
+                Ok(if (target.arch, target.os, target.env) == (host.arch, host.os, host.env) { host } else { target })
+            }
+        }
+    }
+
+    pub fn is_host(&self) -> bool {
+        self.triple.is_none()
+    }
+
+    pub fn is_wasm(&self) -> bool {
+        self.os == Os::Wasi
+    }
+
+    /// File name of the static library cargo builds for crate `name` on this target (for
+    /// WASI, the WebAssembly module of a cdylib)
+    pub fn static_library(&self, name: &str) -> String {
+        match self.os {
+            Os::Windows => format!("{}.lib", name),
+            Os::Linux | Os::MacOs => format!("lib{}.a", name),
+            Os::Wasi => format!("{}.wasm", name),
+        }
+    }
+
+    /// Extension of the object files handed to the linker
+    pub fn object_extension(&self) -> &'static str {
+        if self.os == Os::Windows { "obj" } else { "o" }
+    }
+
+    /// Suffix of the linked executable
+    pub fn exe_suffix(&self) -> &'static str {
+        match self.os {
+            Os::Windows => ".exe",
+            Os::Wasi => ".wasm",
+            Os::Linux | Os::MacOs => "",
+        }
+    }
+
+    /// Where a static library built for this target may be, most specific first
+    pub fn library_candidates(&self, file_name: &str) -> Vec<PathBuf> {
+        let exe_dir = std::env::current_exe().ok().and_then(|p| p.parent().map(Path::to_path_buf));
+        match &self.triple {
+            None => vec![
+                PathBuf::from("target/release").join(file_name),
+                PathBuf::from("target/debug").join(file_name),
+                exe_dir.map(|dir| dir.join(file_name)).unwrap_or_default(),
+                PathBuf::from("/usr/local/lib").join(file_name),
+            ],
+            // Cargo's `--target` output, then per-target directories next to perry
+            Some(triple) => vec![
+                PathBuf::from("target").join(triple).join("release").join(file_name),
+                PathBuf::from("target").join(triple).join("debug").join(file_name),
+                exe_dir.map(|dir| dir.join(triple).join(file_name)).unwrap_or_default(),
+                PathBuf::from("/usr/local/lib/perry").join(triple).join(file_name),
+            ],
+        }
+    }
+
+    /// The command that links the objects into an executable
+    pub fn linker(&self) -> Command {
+        if self.os == Os::Windows {
+            return self.msvc_linker();
+        }
+        let Some(triple) = &self.triple else { return Command::new("cc") };
+        if let Some(cmd) = linker_from_env() {
+            return cmd;
+        }
+        if self.os == Os::Linux {
+            let gcc = format!("{}-linux-{}-gcc", self.arch, self.env);
+            if on_path(&gcc) {
+                return Command::new(gcc);
+            }
+        }
+        if on_path("zig") {
+            let mut cmd = Command::new("zig");
+            cmd.arg("cc").arg("-target").arg(self.zig_target());
+            return cmd;
+        }
+        let mut cmd = Command::new("clang");
+        cmd.arg(format!("--target={}", triple));
+        cmd
+    }
+
+    /// An MSVC-style linker, taking `/OUT:` and `.lib` names; used for host Windows builds too
+    fn msvc_linker(&self) -> Command {
+        if let Some(cmd) = linker_from_env() {
+            return cmd;
+        }
+        if on_path("lld-link") || !cfg!(windows) {
+            return Command::new("lld-link");
+        }
+        Command::new("link.exe")
+    }
+
+    /// The target as zig names it, e.g. `aarch64-linux-musl`
+    fn zig_target(&self) -> String {
+        match self.os {
+            Os::Linux => format!("{}-linux-{}", self.arch, self.env),
+            Os::MacOs => format!("{}-macos", self.arch),
+            Os::Windows => format!("{}-windows-msvc", self.arch),
+            Os::Wasi => format!("{}-wasi", self.arch),
+        }
+    }
+}
+
+/// `PERRY_LINKER` split into the program and its arguments
+fn linker_from_env() -> Option<Command> {
+    let command = std::env::var(LINKER_ENV).ok().filter(|c| !c.trim().is_empty())?;
+    let mut words = command.split_whitespace();
+    let mut cmd = Command::new(words.next()?);
+    cmd.args(words);
+    Some(cmd)
+}
+
+fn on_path(program: &str) -> bool {
+    std::env::var_os("PATH")
+        .map(|paths| {
+            let exe = format!("{}{}", program, std::env::consts::EXE_SUFFIX);
+            std::env::split_paths(&paths).any(|dir| dir.join(program).is_file() || dir.join(&exe).is_file())
+        })
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_triples() {
+        let target = Target::parse("aarch64-unknown-linux-gnu").unwrap();
+        assert_eq!((target.arch, target.os, target.env), ("aarch64", Os::Linux, "gnu"));
+        assert_eq!(Target::parse("x86_64-linux-musl").unwrap().triple.as_deref(), Some("x86_64-unknown-linux-musl"));
+        assert_eq!(Target::parse("aarch64-macos").unwrap().os, Os::MacOs);
+        let error = Target::parse("riscv64gc-unknown-linux-gnu").unwrap_err().to_string();
+        assert!(error.contains("x86_64-unknown-linux-gnu"));
+    }
+
+    #[test]
+    fn test_host_triple_is_native() {
+        let host = Target::host();
+        let triple = match host.os {
+            Os::Linux => format!("{}-unknown-linux-{}", host.arch, host.env),
+            Os::MacOs => format!("{}-apple-darwin", host.arch),
+            Os::Windows => format!("{}-pc-windows-msvc", host.arch),
+            Os::Wasi => unreachable!("perry doesn't run on WASI"),
+        };
+        assert!(Target::from_arg(Some(&triple)).unwrap().is_host());
+        assert!(Target::from_arg(None).unwrap().is_host());
+    }
+
+    #[test]
+    fn test_cross_libraries_are_per_target() {
+        let target = Target::parse("x86_64-unknown-linux-gnu").unwrap();
+        let candidates = target.library_candidates("libperry_runtime.a");
+        assert_eq!(candidates[0], PathBuf::from("target/x86_64-unknown-linux-gnu/release/libperry_runtime.a"));
+        assert!(candidates.iter().all(|path| path.to_string_lossy().contains("x86_64-unknown-linux-gnu")));
+    }
+
+    #[test]
+    fn test_windows_target() {
+        let target = Target::parse("x86_64-windows").unwrap();
+        assert_eq!(target.triple.as_deref(), Some("x86_64-pc-windows-msvc"));
+        assert_eq!((target.os, target.env), (Os::Windows, "msvc"));
+        assert_eq!(target.static_library("perry_runtime"), "perry_runtime.lib");
+        assert_eq!((target.object_extension(), target.exe_suffix()), ("obj", ".exe"));
+        let candidates = target.library_candidates(&target.static_library("perry_runtime"));
+        assert_eq!(candidates[0], PathBuf::from("target/x86_64-pc-windows-msvc/release/perry_runtime.lib"));
+        assert!(Target::parse("aarch64-windows").is_err());
+        let linux = Target::parse("x86_64-linux-gnu").unwrap();
+        assert_eq!(linux.static_library("perry_runtime"), "libperry_runtime.a");
+    }
+
+    #[test]
+    fn test_wasi_target() {
+        let target = Target::parse("wasm32-wasi").unwrap();
+        assert_eq!(target.triple.as_deref(), Some("wasm32-wasip1"));
+        assert_eq!((target.arch, target.os, target.env), ("wasm32", Os::Wasi, ""));
+        assert!(target.is_wasm() && !Target::from_arg(Some("wasm32-wasip1")).unwrap().is_host());
+        assert_eq!(target.exe_suffix(), ".wasm");
+        let candidates = target.library_candidates(&target.static_library("perry_runtime_wasi"));
+        assert_eq!(candidates[0], PathBuf::from("target/wasm32-wasip1/release/perry_runtime_wasi.wasm"));
+        assert!(Target::parse("wasm64-wasi").is_err());
+    }
+}
@@ -0,0 +1,330 @@
+//! Opt-in build telemetry - one JSON event per perry invocation
+//!
+//! Nothing is recorded unless `PERRY_TELEMETRY` is set, either to a file path (events are
+//! appended as JSON lines) or to a plain `http://host[:port]/path` endpoint (each event is
+//! POSTed as one JSON document). An event describes the session, never the code:
+//! command, perry version, OS, project name, success and the first line of the error,
+//! total and per-phase durations, module counts, diagnostic counts by code and compile
+//! cache hits/misses. `PERRY_TELEMETRY_PROJECT` overrides the project name, which
+//! otherwise comes from package.json or the working directory.
+//!
+//! Commands record into a process-wide session that `main` opens before dispatch and
+//! flushes afterwards; everything is a no-op when telemetry is off. Failing to write an
+//! event never fails the command (it's reported with -v).
+
+use perry_diagnostics::Diagnostic;
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// Bumped whenever a field changes meaning or is removed
+const SCHEMA_VERSION: u32 = 1;
+
+static SESSION: Mutex<Option<Session>> = Mutex::new(None);
+
+/// Where events go
+#[derive(Debug, Clone, PartialEq)]
+enum Sink {
+    File(PathBuf),
+    Http(String),
+}
+
+struct Session {
+    sink: Sink,
+    command: &'static str,
+    started: Instant,
+    verbose: bool,
+    /// Phase name -> milliseconds, in the order phases finished
+    phases: Vec<(&'static str, f64)>,
+    /// Command-specific fields (module counts, flags, ...)
+    fields: Map<String, Value>,
+    /// Diagnostic code -> count
+    diagnostics: BTreeMap<&'static str, usize>,
+    cache_hits: usize,
+    cache_misses: usize,
+}
+
+/// Parse `PERRY_TELEMETRY`; unset, empty, `0`, `off` and `false` all disable telemetry
+fn sink_from_env(value: Option<&str>) -> Option<Sink> {
+    let value = value?.trim();
+    if value.is_empty() || matches!(value.to_ascii_lowercase().as_str(), "0" | "off" | "false") {
+        return None;
+    }
+    if value.starts_with("http://") {
+        Some(Sink::Http(value.to_string()))
+    } else {
+        Some(Sink::File(PathBuf::from(value)))
+    }
+}
+
+/// Start recording a session for `command` if telemetry is enabled
+pub fn begin(command: &'static str, verbose: u8) {
+    let Some(sink) = sink_from_env(std::env::var("PERRY_TELEMETRY").ok().as_deref()) else {
+        return;
+    };
+    *SESSION.lock().unwrap() = Some(Session {
+        sink,
+        command,
+        started: Instant::now(),
+        verbose: verbose > 0,
+        phases: Vec::new(),
+        fields: Map::new(),
+        diagnostics: BTreeMap::new(),
+        cache_hits: 0,
+        cache_misses: 0,
+    });
+}
+
+fn with_session(f: impl FnOnce(&mut Session)) {
+    if let Some(session) = SESSION.lock().unwrap().as_mut() {
+        f(session);
+    }
+}
+
+/// Record that `name` ran from `started` until now
+pub fn phase(name: &'static str, started: Instant) {
+    let ms = started.elapsed().as_secs_f64() * 1000.0;
+    with_session(|session| session.phases.push((name, ms)));
+}
+
+/// Record a command-specific field
+pub fn set(key: &str, value: impl Into<Value>) {
+    let value = value.into();
+    with_session(|session| {
+        session.fields.insert(key.to_string(), value);
+    });
+}
+
+/// Count diagnostics by code
+pub fn diagnostics<'a>(diagnostics: impl IntoIterator<Item = &'a Diagnostic>) {
+    with_session(|session| {
+        for diagnostic in diagnostics {
+            *session.diagnostics.entry(diagnostic.code.as_str()).or_default() += 1;
+        }
+    });
+}
+
+/// Record a compile cache lookup
+pub fn cache(hit: bool) {
+    with_session(|session| {
+        if hit {
+            session.cache_hits += 1;
+        } else {
+            session.cache_misses += 1;
+        }
+    });
+}
+
+/// Write the session's event and end it. Safe to call more than once (only the first
+/// call writes), so commands that replace or exit the process can flush early.
+pub fn finish(error: Option<&anyhow::Error>) {
+    let Some(session) = SESSION.lock().unwrap().take() else {
+        return;
+    };
+    let error = error.map(|e| e.to_string());
+    let event = session.event(error.as_deref(), project_name());
+    if let Err(e) = emit(&session.sink, &event) {
+        if session.verbose {
+            eprintln!("perry: failed to record telemetry: {}", e);
+        }
+    }
+}
+
+impl Session {
+    fn event(&self, error: Option<&str>, project: Option<String>) -> Value {
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let phases: Map<String, Value> = self
+            .phases
+            .iter()
+            .map(|(name, ms)| (name.to_string(), json!(round_ms(*ms))))
+            .collect();
+
+        let mut event = json!({
+            "schema": SCHEMA_VERSION,
+            "command": self.command,
+            "perry_version": env!("CARGO_PKG_VERSION"),
+            "timestamp": timestamp,
+            "os": std::env::consts::OS,
+            "arch": std::env::consts::ARCH,
+            "ci": std::env::var_os("CI").is_some(),
+            "project": project,
+            "success": error.is_none(),
+            "error": error.map(|e| e.lines().next().unwrap_or("").chars().take(300).collect::<String>()),
+            "duration_ms": round_ms(self.started.elapsed().as_secs_f64() * 1000.0),
+            "phases": phases,
+            "diagnostics": self.diagnostics,
+        });
+        let lookups = self.cache_hits + self.cache_misses;
+        if lookups > 0 {
+            event["cache"] = json!({
+                "hits": self.cache_hits,
+                "misses": self.cache_misses,
+                "hit_rate": self.cache_hits as f64 / lookups as f64,
+            });
+        }
+        for (key, value) in &self.fields {
+            event[key.as_str()] = value.clone();
+        }
+        event
+    }
+}
+
+fn round_ms(ms: f64) -> f64 {
+    (ms * 10.0).round() / 10.0
+}
+
+/// Name used to group events by repository
+fn project_name() -> Option<String> {
+    if let Ok(name) = std::env::var("PERRY_TELEMETRY_PROJECT") {
+        return Some(name);
+    }
+    let cwd = std::env::current_dir().ok()?;
+    let package_name = std::fs::read_to_string(cwd.join("package.json"))
+        .ok()
+        .and_then(|content| serde_json::from_str::<Value>(&content).ok())
+        .and_then(|pkg| pkg.get("name").and_then(|n| n.as_str()).map(str::to_string));
+    package_name.or_else(|| cwd.file_name().map(|n| n.to_string_lossy().to_string()))
+}
+
+fn emit(sink: &Sink, event: &Value) -> std::io::Result<()> {
+    let line = serde_json::to_string(event)?;
+    match sink {
+        Sink::File(path) => {
+            // One write per event keeps lines from concurrent builds intact
+            let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+            file.write_all(format!("{}\n", line).as_bytes())
+        }
+        Sink::Http(endpoint) => post_event(endpoint, &line),
+    }
+}
+
+/// Split `http://host[:port]/path` into (host, port, path)
+pub(super) fn parse_http_endpoint(endpoint: &str) -> Option<(String, u16, String)> {
+    let rest = endpoint.strip_prefix("http://")?;
+    let (authority, path) = match rest.find('/') {
+        Some(idx) => (&rest[..idx], &rest[idx..]),
+        None => (rest, "/"),
+    };
+    if authority.is_empty() {
+        return None;
+    }
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((h, p)) => (h, p.parse().ok()?),
+        None => (authority, 80),
+    };
+    Some((host.to_string(), port, path.to_string()))
+}
+
+fn post_event(endpoint: &str, body: &str) -> std::io::Result<()> {
+    use std::net::ToSocketAddrs;
+
+    let (host, port, path) = parse_http_endpoint(endpoint).ok_or_else(|| {
+        std::io::Error::new(std::io::ErrorKind::InvalidInput, "only http://host[:port]/path endpoints are supported")
+    })?;
+    let addr = (host.as_str(), port)
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, "endpoint did not resolve"))?;
+    // Keep an unreachable collector from noticeably slowing down builds
+    let timeout = std::time::Duration::from_secs(2);
+    let mut stream = std::net::TcpStream::connect_timeout(&addr, timeout)?;
+    stream.set_write_timeout(Some(timeout))?;
+    write!(
+        stream,
+        "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        path,
+        host,
+        body.len()
+    )?;
+    stream.write_all(body.as_bytes())?;
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use perry_diagnostics::DiagnosticCode;
+
+    fn new_session(sink: Sink) -> Session {
+        Session {
+            sink,
+            command: "compile",
+            started: Instant::now(),
+            verbose: false,
+            phases: Vec::new(),
+            fields: Map::new(),
+            diagnostics: BTreeMap::new(),
+            cache_hits: 0,
+            cache_misses: 0,
+        }
+    }
+
+    #[test]
+    fn test_sink_from_env() {
+        assert_eq!(sink_from_env(None), None);
+        assert_eq!(sink_from_env(Some("")), None);
+        assert_eq!(sink_from_env(Some("off")), None);
+        assert_eq!(sink_from_env(Some("0")), None);
+        assert_eq!(
+            sink_from_env(Some("http://collector:9000/events")),
+            Some(Sink::Http("http://collector:9000/events".to_string()))
+        );
+        assert_eq!(
+            sink_from_env(Some("/var/log/perry.jsonl")),
+            Some(Sink::File(PathBuf::from("/var/log/perry.jsonl")))
+        );
+        assert_eq!(
+            parse_http_endpoint("http://collector:9000/events"),
+            Some(("collector".to_string(), 9000, "/events".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_event_fields() {
+        let mut session = new_session(Sink::File(PathBuf::from("unused")));
+        session.phases.push(("codegen", 12.34));
+        session.fields.insert("modules".to_string(), json!({ "native": 3, "js": 0 }));
+        let diags = [
+            Diagnostic::error(DiagnosticCode::UnsupportedFeature, "a").build(),
+            Diagnostic::error(DiagnosticCode::UnsupportedFeature, "b").build(),
+        ];
+        for diag in &diags {
+            *session.diagnostics.entry(diag.code.as_str()).or_default() += 1;
+        }
+        session.cache_hits = 3;
+        session.cache_misses = 1;
+
+        let event = session.event(Some("Linking failed\nmore detail"), Some("app".to_string()));
+        assert_eq!(event["command"], "compile");
+        assert_eq!(event["project"], "app");
+        assert_eq!(event["success"], false);
+        assert_eq!(event["error"], "Linking failed");
+        assert_eq!(event["phases"]["codegen"], 12.3);
+        assert_eq!(event["modules"]["native"], 3);
+        assert_eq!(event["diagnostics"][DiagnosticCode::UnsupportedFeature.as_str()], 2);
+        assert_eq!(event["cache"]["hit_rate"], 0.75);
+
+        let ok = new_session(Sink::File(PathBuf::from("unused"))).event(None, None);
+        assert_eq!(ok["success"], true);
+        assert!(ok.get("cache").is_none());
+    }
+
+    #[test]
+    fn test_file_sink_appends_lines() {
+        let path = std::env::temp_dir().join(format!("perry-telemetry-test-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let sink = Sink::File(path.clone());
+        emit(&sink, &json!({ "n": 1 })).unwrap();
+        emit(&sink, &json!({ "n": 2 })).unwrap();
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(content, "{\"n\":1}\n{\"n\":2}\n");
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,417 @@
+//! tsconfig.json `compilerOptions` support
+//!
+//! Loads the nearest tsconfig.json (following `extends`). `baseUrl` / `paths` map
+//! import specifiers to candidate file paths the way tsc does: the `paths` pattern with
+//! the longest prefix before `*` wins (exact patterns first), and each of its
+//! substitutions is tried in order. Without a match, bare specifiers are looked up
+//! relative to `baseUrl`. The compile command resolves the candidates with its usual
+//! extension rules.
+//!
+//! The strictness flags (`strict`, `noImplicitAny`, `strictNullChecks`) set the severity
+//! of `perry check`'s type diagnostics. Options perry doesn't honor are listed so
+//! `perry check` can report them.
+
+use anyhow::{anyhow, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Path aliases from a tsconfig.json
+#[derive(Debug, Clone)]
+pub struct PathAliases {
+    /// The tsconfig.json the aliases were loaded from
+    pub config_path: PathBuf,
+    /// Absolute `baseUrl`, if set
+    base_url: Option<PathBuf>,
+    /// `paths` entries in declaration order: (pattern, substitutions)
+    paths: Vec<(String, Vec<String>)>,
+    /// Directory `paths` substitutions are relative to (`baseUrl`, or the directory of
+    /// the tsconfig that declared `paths`)
+    paths_base: PathBuf,
+}
+
+/// A `paths` pattern matching a specifier, with the files it maps to
+#[derive(Debug, Clone)]
+pub struct AliasMatch {
+    pub pattern: String,
+    pub candidates: Vec<PathBuf>,
+}
+
+impl PathAliases {
+    /// Find the nearest tsconfig.json at or above `start` and load its aliases.
+    /// Returns None when there is no tsconfig or it declares neither `baseUrl` nor `paths`.
+    pub fn find(start: &Path) -> Result<Option<Self>> {
+        let start = start.canonicalize().unwrap_or_else(|_| start.to_path_buf());
+        for dir in start.ancestors() {
+            let candidate = dir.join("tsconfig.json");
+            if candidate.is_file() {
+                return Self::load(&candidate);
+            }
+        }
+        Ok(None)
+    }
+
+    /// Load aliases from a specific tsconfig file
+    pub fn load(config_path: &Path) -> Result<Option<Self>> {
+        let config_path = config_path
+            .canonicalize()
+            .map_err(|e| anyhow!("Failed to read {}: {}", config_path.display(), e))?;
+        let mut options = MergedOptions::default();
+        load_config(&config_path, &mut options, &mut Vec::new())?;
+
+        if options.base_url.is_none() && options.paths.is_none() {
+            return Ok(None);
+        }
+        let (paths, paths_dir) = options.paths.unwrap_or_else(|| (Vec::new(), PathBuf::new()));
+        let paths_base = options.base_url.clone().unwrap_or(paths_dir);
+        Ok(Some(Self {
+            config_path,
+            base_url: options.base_url,
+            paths,
+            paths_base,
+        }))
+    }
+
+    /// Match a specifier against `paths`. Exact patterns win over wildcards; among
+    /// wildcards the one with the longest prefix wins.
+    pub fn match_paths(&self, specifier: &str) -> Option<AliasMatch> {
+        if let Some((pattern, targets)) = self.paths.iter().find(|(p, _)| !p.contains('*') && p == specifier) {
+            return Some(AliasMatch {
+                pattern: pattern.clone(),
+                candidates: targets.iter().map(|t| self.paths_base.join(t)).collect(),
+            });
+        }
+
+        let (pattern, targets, matched) = self.paths.iter()
+            .filter_map(|(pattern, targets)| {
+                let (prefix, suffix) = pattern.split_once('*')?;
+                let matched = specifier.strip_prefix(prefix)?.strip_suffix(suffix)?;
+                Some((pattern, targets, matched, prefix.len()))
+            })
+            .max_by_key(|(_, _, _, prefix_len)| *prefix_len)
+            .map(|(pattern, targets, matched, _)| (pattern, targets, matched))?;
+
+        Some(AliasMatch {
+            pattern: pattern.clone(),
+            candidates: targets.iter().map(|t| self.paths_base.join(t.replacen('*', matched, 1))).collect(),
+        })
+    }
+
+    /// Candidate path for a bare specifier relative to `baseUrl`
+    pub fn base_url_candidate(&self, specifier: &str) -> Option<PathBuf> {
+        self.base_url.as_ref().map(|base| base.join(specifier))
+    }
+}
+
+/// Options that change what perry compiles or checks. Everything else in
+/// `compilerOptions` that isn't in `NO_EFFECT_OPTIONS` is reported as unsupported.
+const HONORED_OPTIONS: &[&str] = &["baseUrl", "paths", "strict", "noImplicitAny", "strictNullChecks", "target", "lib"];
+
+/// Options that only affect tsc's output, editor tooling or module interop perry
+/// handles on its own, so ignoring them changes nothing in a native binary
+const NO_EFFECT_OPTIONS: &[&str] = &[
+    "allowImportingTsExtensions", "allowSyntheticDefaultImports", "composite", "declaration",
+    "declarationDir", "declarationMap", "emitDeclarationOnly", "esModuleInterop",
+    "forceConsistentCasingInFileNames", "incremental", "inlineSourceMap", "inlineSources",
+    "isolatedModules", "module", "moduleDetection", "moduleResolution", "newLine", "noEmit",
+    "noEmitOnError", "noFallthroughCasesInSwitch", "noImplicitReturns", "noUnusedLocals",
+    "noUnusedParameters", "outDir", "plugins", "pretty", "removeComments", "resolveJsonModule",
+    "rootDir", "rootDirs", "skipLibCheck", "sourceMap", "sourceRoot", "tsBuildInfoFile", "typeRoots",
+    "types", "verbatimModuleSyntax",
+];
+
+/// `lib` entries for host APIs perry binaries don't have
+const UNAVAILABLE_LIBS: &[&str] = &["dom", "dom.iterable", "dom.asynciterable", "webworker", "webworker.importscripts", "webworker.iterable", "scripthost"];
+
+/// Type-checking options from a tsconfig.json
+#[derive(Debug, Clone)]
+pub struct CompilerOptions {
+    /// The tsconfig.json the options were loaded from
+    pub config_path: PathBuf,
+    pub strict: Option<bool>,
+    pub no_implicit_any: Option<bool>,
+    pub strict_null_checks: Option<bool>,
+    /// `target`, lowercased (`es2022`, `esnext`)
+    pub target: Option<String>,
+    /// `lib` entries, lowercased
+    pub lib: Vec<String>,
+    /// Declared options perry ignores, with a note on each, sorted by name
+    pub unsupported: Vec<(String, String)>,
+}
+
+impl CompilerOptions {
+    /// Find the nearest tsconfig.json at or above `start` and load its options.
+    /// Returns None when there is no tsconfig.
+    pub fn find(start: &Path) -> Result<Option<Self>> {
+        let start = start.canonicalize().unwrap_or_else(|_| start.to_path_buf());
+        match start.ancestors().map(|dir| dir.join("tsconfig.json")).find(|c| c.is_file()) {
+            Some(config_path) => Self::load(&config_path).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Load options from a specific tsconfig file
+    pub fn load(config_path: &Path) -> Result<Self> {
+        let config_path = config_path
+            .canonicalize()
+            .map_err(|e| anyhow!("Failed to read {}: {}", config_path.display(), e))?;
+        let mut merged = MergedOptions::default();
+        load_config(&config_path, &mut merged, &mut Vec::new())?;
+        let declared = merged.declared;
+        let flag = |name: &str| declared.get(name).and_then(|v| v.as_bool());
+
+        let target = declared.get("target").and_then(|v| v.as_str()).map(str::to_ascii_lowercase);
+        let lib: Vec<String> = declared.get("lib")
+            .and_then(|v| v.as_array())
+            .map(|libs| libs.iter().filter_map(|v| v.as_str()).map(str::to_ascii_lowercase).collect())
+            .unwrap_or_default();
+
+        let mut unsupported: Vec<(String, String)> = declared.keys()
+            .filter(|name| !HONORED_OPTIONS.contains(&name.as_str()) && !NO_EFFECT_OPTIONS.contains(&name.as_str()))
+            .map(|name| (name.clone(), "not supported by perry and ignored".to_string()))
+            .collect();
+        if let Some(target) = target.as_deref().filter(|t| matches!(*t, "es3" | "es5")) {
+            unsupported.push((
+                format!("target: {}", target),
+                "perry doesn't down-level; programs are compiled with ES2022 semantics".to_string(),
+            ));
+        }
+        for entry in lib.iter().filter(|l| UNAVAILABLE_LIBS.contains(&l.as_str())) {
+            unsupported.push((
+                format!("lib: {}", entry),
+                "browser APIs are not available in perry binaries".to_string(),
+            ));
+        }
+        unsupported.sort();
+
+        Ok(Self {
+            config_path,
+            strict: flag("strict"),
+            no_implicit_any: flag("noImplicitAny"),
+            strict_null_checks: flag("strictNullChecks"),
+            target,
+            lib,
+            unsupported,
+        })
+    }
+
+    /// `noImplicitAny`, defaulting to `strict` like tsc
+    pub fn no_implicit_any(&self) -> bool {
+        self.no_implicit_any.or(self.strict).unwrap_or(false)
+    }
+
+    /// `strictNullChecks`, defaulting to `strict` like tsc
+    pub fn strict_null_checks(&self) -> bool {
+        self.strict_null_checks.or(self.strict).unwrap_or(false)
+    }
+}
+
+/// `compilerOptions` accumulated along an `extends` chain (later configs override)
+#[derive(Default)]
+struct MergedOptions {
+    base_url: Option<PathBuf>,
+    /// Patterns plus the directory of the config that declared them
+    paths: Option<(Vec<(String, Vec<String>)>, PathBuf)>,
+    /// Every declared option by name
+    declared: serde_json::Map<String, serde_json::Value>,
+}
+
+fn load_config(config_path: &Path, options: &mut MergedOptions, chain: &mut Vec<PathBuf>) -> Result<()> {
+    if chain.contains(&config_path.to_path_buf()) {
+        return Err(anyhow!("Circular `extends` in {}", config_path.display()));
+    }
+    chain.push(config_path.to_path_buf());
+
+    let source = fs::read_to_string(config_path)
+        .map_err(|e| anyhow!("Failed to read {}: {}", config_path.display(), e))?;
+    let json: serde_json::Value = serde_json::from_str(&strip_jsonc(&source))
+        .map_err(|e| anyhow!("Failed to parse {}: {}", config_path.display(), e))?;
+    let config_dir = config_path.parent().unwrap_or(Path::new("/")).to_path_buf();
+
+    // Base configs first, so this file's options override them
+    let extends: Vec<&str> = match json.get("extends") {
+        Some(serde_json::Value::String(s)) => vec![s.as_str()],
+        Some(serde_json::Value::Array(items)) => items.iter().filter_map(|v| v.as_str()).collect(),
+        _ => Vec::new(),
+    };
+    for base in extends {
+        let base_path = resolve_extends(base, &config_dir)
+            .ok_or_else(|| anyhow!("Cannot find base config '{}' extended by {}", base, config_path.display()))?;
+        load_config(&base_path, options, chain)?;
+    }
+
+    if let Some(compiler_options) = json.get("compilerOptions") {
+        if let Some(declared) = compiler_options.as_object() {
+            options.declared.extend(declared.iter().map(|(name, value)| (name.clone(), value.clone())));
+        }
+        if let Some(base_url) = compiler_options.get("baseUrl").and_then(|v| v.as_str()) {
+            options.base_url = Some(config_dir.join(base_url).components().collect());
+        }
+        if let Some(paths) = compiler_options.get("paths").and_then(|v| v.as_object()) {
+            let entries = paths.iter()
+                .map(|(pattern, targets)| {
+                    let targets = targets.as_array()
+                        .map(|t| t.iter().filter_map(|v| v.as_str().map(str::to_string)).collect())
+                        .unwrap_or_default();
+                    (pattern.clone(), targets)
+                })
+                .collect();
+            options.paths = Some((entries, config_dir.clone()));
+        }
+    }
+
+    chain.pop();
+    Ok(())
+}
+
+/// Resolve an `extends` value: a relative/absolute file, or a package in node_modules
+fn resolve_extends(base: &str, config_dir: &Path) -> Option<PathBuf> {
+    let with_json = |p: PathBuf| -> Option<PathBuf> {
+        if p.is_file() {
+            return p.canonicalize().ok();
+        }
+        let mut s = p.into_os_string();
+        s.push(".json");
+        let p = PathBuf::from(s);
+        if p.is_file() { p.canonicalize().ok() } else { None }
+    };
+
+    if base.starts_with("./") || base.starts_with("../") || Path::new(base).is_absolute() {
+        return with_json(config_dir.join(base));
+    }
+    config_dir.ancestors()
+        .map(|dir| dir.join("node_modules").join(base))
+        .find_map(|p| if p.is_dir() { with_json(p.join("tsconfig.json")) } else { with_json(p) })
+}
+
+/// Strip `//` and `/* */` comments and trailing commas so tsconfig files parse as JSON
+fn strip_jsonc(source: &str) -> String {
+    let mut out = String::with_capacity(source.len());
+    let mut chars = source.chars().peekable();
+    let mut in_string = false;
+
+    while let Some(c) = chars.next() {
+        if in_string {
+            out.push(c);
+            match c {
+                '\\' => {
+                    if let Some(escaped) = chars.next() {
+                        out.push(escaped);
+                    }
+                }
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' => {
+                in_string = true;
+                out.push(c);
+            }
+            '/' if chars.peek() == Some(&'/') => {
+                while chars.peek().is_some_and(|&c| c != '\n') {
+                    chars.next();
+                }
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut prev = ' ';
+                for c in chars.by_ref() {
+                    if prev == '*' && c == '/' {
+                        break;
+                    }
+                    prev = c;
+                }
+            }
+            '}' | ']' => {
+                // Drop a trailing comma before the closing bracket
+                let trimmed_len = out.trim_end().len();
+                if out[..trimmed_len].ends_with(',') {
+                    out.truncate(trimmed_len - 1);
+                }
+                out.push(c);
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_project(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("perry-tsconfig-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        for (path, contents) in files {
+            let path = dir.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+        dir.canonicalize().unwrap()
+    }
+
+    #[test]
+    fn test_strip_jsonc() {
+        let stripped = strip_jsonc("{\n  // comment\n  \"a\": \"x//y\", /* block */\n  \"b\": [1, 2,],\n}");
+        let json: serde_json::Value = serde_json::from_str(&stripped).unwrap();
+        assert_eq!(json["a"], "x//y");
+        assert_eq!(json["b"].as_array().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_paths_longest_prefix_and_exact() {
+        let dir = temp_project("match", &[(
+            "tsconfig.json",
+            r#"{ "compilerOptions": { "baseUrl": ".", "paths": {
+                "@app/*": ["src/*"],
+                "@app/core/*": ["core/*", "fallback/*"],
+                "config": ["src/config/index"]
+            } } }"#,
+        )]);
+        let aliases = PathAliases::find(&dir.join("src")).unwrap().unwrap();
+
+        let m = aliases.match_paths("@app/core/db").unwrap();
+        assert_eq!(m.pattern, "@app/core/*");
+        assert_eq!(m.candidates, vec![dir.join("core/db"), dir.join("fallback/db")]);
+        assert_eq!(aliases.match_paths("@app/util").unwrap().candidates, vec![dir.join("src/util")]);
+        assert_eq!(aliases.match_paths("config").unwrap().candidates, vec![dir.join("src/config/index")]);
+        assert!(aliases.match_paths("lodash").is_none());
+        assert_eq!(aliases.base_url_candidate("lib/x"), Some(dir.join("lib/x")));
+    }
+
+    #[test]
+    fn test_extends_and_paths_without_base_url() {
+        let dir = temp_project("extends", &[
+            ("configs/base.json", r#"{ "compilerOptions": { "paths": { "~/*": ["../src/*"] } } }"#),
+            ("tsconfig.json", r#"{ "extends": "./configs/base", "compilerOptions": { "strict": true } }"#),
+        ]);
+        let aliases = PathAliases::find(&dir).unwrap().unwrap();
+        // Relative to the config that declared `paths`
+        assert_eq!(aliases.match_paths("~/a").unwrap().candidates, vec![dir.join("configs/../src/a")]);
+        assert_eq!(aliases.base_url_candidate("a"), None);
+    }
+
+    #[test]
+    fn test_compiler_options() {
+        let dir = temp_project("options", &[
+            ("base.json", r#"{ "compilerOptions": { "strict": true, "experimentalDecorators": true, "outDir": "dist" } }"#),
+            ("tsconfig.json", r#"{ "extends": "./base.json", "compilerOptions": {
+                "strictNullChecks": false, "target": "ES5", "lib": ["ES2020", "DOM"],
+            } }"#),
+        ]);
+        let options = CompilerOptions::find(&dir).unwrap().unwrap();
+        assert!(options.no_implicit_any());
+        assert!(!options.strict_null_checks());
+        assert_eq!(options.lib, vec!["es2020", "dom"]);
+        let unsupported: Vec<&str> = options.unsupported.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(unsupported, vec!["experimentalDecorators", "lib: dom", "target: es5"]);
+    }
+
+    #[test]
+    fn test_no_aliases() {
+        let dir = temp_project("none", &[("tsconfig.json", r#"{ "compilerOptions": { "strict": true } }"#)]);
+        assert!(PathAliases::find(&dir).unwrap().is_none());
+    }
+}
@@ -56,6 +56,39 @@ enum Commands {
 
     /// Explain an error code
     Explain(commands::explain::ExplainArgs),
+
+    /// Compile a script (cached per user) and run it, passing arguments through
+    Run(commands::run::RunArgs),
+
+    /// Run a script with a node-compatible command line (for package.json scripts)
+    Node(commands::node::NodeArgs),
+
+    /// Run a program, rebuilding and restarting it whenever its sources change
+    Dev(commands::dev::DevArgs),
+
+    /// Demangle perry symbol names (reads stdin when none are given)
+    Demangle(commands::demangle::DemangleArgs),
+
+    /// Generate TypeScript declarations and linking glue for a Rust crate's C functions
+    Bindgen(commands::bindgen::BindgenArgs),
+}
+
+impl Commands {
+    /// Subcommand name, as recorded by telemetry
+    fn name(&self) -> &'static str {
+        match self {
+            Commands::Compile(_) => "compile",
+            Commands::Check(_) => "check",
+            Commands::Init(_) => "init",
+            Commands::Doctor(_) => "doctor",
+            Commands::Explain(_) => "explain",
+            Commands::Run(_) => "run",
+            Commands::Node(_) => "node",
+            Commands::Dev(_) => "dev",
+            Commands::Demangle(_) => "demangle",
+            Commands::Bindgen(_) => "bindgen",
+        }
+    }
 }
 
 /// Check if the first non-flag argument looks like a TypeScript file
@@ -72,7 +105,7 @@ fn is_legacy_invocation(args: &[String]) -> bool {
         // If it's a known subcommand, not legacy
         if matches!(
             arg.as_str(),
-            "compile" | "check" | "init" | "doctor" | "explain" | "help"
+            "compile" | "check" | "init" | "doctor" | "explain" | "run" | "node" | "dev" | "demangle" | "bindgen" | "help"
         ) {
             return false;
         }
@@ -82,6 +115,27 @@ fn is_legacy_invocation(args: &[String]) -> bool {
     false
 }
 
+/// Find the script when perry runs as a shebang interpreter (`#!/usr/bin/env perry`):
+/// the first non-flag argument is a file whose shebang line names perry
+fn script_invocation(args: &[String]) -> Option<usize> {
+    let (index, arg) = args
+        .iter()
+        .enumerate()
+        .skip(1)
+        .find(|(_, arg)| !arg.starts_with('-'))?;
+    commands::run::has_perry_shebang(std::path::Path::new(arg)).then_some(index)
+}
+
+/// Transform interpreter args (perry script a b) to `perry run script -- a b`,
+/// so the script's own arguments are never parsed as perry flags
+fn transform_script_args(args: Vec<String>, script: usize) -> Vec<String> {
+    let mut new_args = vec![args[0].clone(), "run".to_string()];
+    new_args.extend(args[1..=script].iter().cloned());
+    new_args.push("--".to_string());
+    new_args.extend(args.into_iter().skip(script + 1));
+    new_args
+}
+
 /// Transform legacy args (perry file.ts -o out) to subcommand form
 fn transform_legacy_args(args: Vec<String>) -> Vec<String> {
     let mut new_args = vec![args[0].clone(), "compile".to_string()];
@@ -92,9 +146,11 @@ fn transform_legacy_args(args: Vec<String>) -> Vec<String> {
 fn main() -> Result<()> {
     env_logger::init();
 
-    // Handle legacy invocation (perry file.ts -o out)
+    // Handle shebang scripts (perry script args...) and legacy invocation (perry file.ts -o out)
     let args: Vec<String> = std::env::args().collect();
-    let effective_args = if is_legacy_invocation(&args) {
+    let effective_args = if let Some(script) = script_invocation(&args) {
+        transform_script_args(args, script)
+    } else if is_legacy_invocation(&args) {
         transform_legacy_args(args)
     } else {
         args
@@ -113,7 +169,10 @@ fn main() -> Result<()> {
         return Ok(());
     }
 
-    match cli.command.unwrap() {
+    let command = cli.command.unwrap();
+    commands::telemetry::begin(command.name(), cli.verbose);
+
+    let result = match command {
         Commands::Compile(args) => {
             commands::compile::run(args, cli.format, use_color, cli.verbose)
         }
@@ -129,5 +188,24 @@ fn main() -> Result<()> {
         Commands::Explain(args) => {
             commands::explain::run(args, cli.format, use_color)
         }
-    }
+        Commands::Run(args) => {
+            commands::run::run(args, cli.format, use_color, cli.verbose)
+        }
+        Commands::Node(args) => {
+            commands::node::run(args, cli.format, use_color, cli.verbose)
+        }
+        Commands::Dev(args) => {
+            commands::dev::run(args, cli.format, use_color, cli.verbose)
+        }
+        Commands::Demangle(args) => {
+            commands::demangle::run(args, cli.format, use_color)
+        }
+        Commands::Bindgen(args) => {
+            commands::bindgen::run(args, cli.format, use_color)
+        }
+    };
+
+    commands::progress::finish(result.as_ref().err());
+    commands::telemetry::finish(result.as_ref().err());
+    result
 }
@@ -0,0 +1,9 @@
+// CommonJS module: named exports through module.exports and exports
+const count = 10;
+
+function add(a: number, b: number): number {
+    return a + b;
+}
+
+module.exports = { add, count: count + 1 };
+exports.label = 'lib';
@@ -0,0 +1,22 @@
+// Test require() of a CommonJS project module: named exports and the module object itself
+
+const lib = require('./lib');
+const { add } = require('./lib');
+
+// Local bindings named like the exports stay separate
+const count = 1;
+function label(): string {
+    return 'main';
+}
+
+console.log(lib.add(2, 3));        // 5
+console.log(add(4, 5));            // 9
+console.log(lib.count);            // 11
+console.log(count);                // 1
+console.log(lib.label);            // lib
+console.log(label());              // main
+
+// module.exports as a whole
+const exportsObject = lib;
+console.log(Object.keys(exportsObject).join(','));   // add,count,label
+console.log(exportsObject.count);                     // 11
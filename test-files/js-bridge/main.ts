@@ -0,0 +1,32 @@
+// Test passing compiled class instances into a JavaScript module:
+// fields are shared, methods call back into native code
+// perry compile test-files/js-bridge/main.ts --enable-js-runtime
+
+import { describe, bump, rename, keys, remember } from "./store.js";
+
+class Counter {
+    name: string;
+    count: number;
+
+    constructor(name: string) {
+        this.name = name;
+        this.count = 0;
+    }
+
+    increment(): number {
+        this.count = this.count + 1;
+        return this.count;
+    }
+}
+
+const counter = new Counter("hits");
+console.log(describe(counter));        // hits=0
+console.log(bump(counter, 3));         // 3
+console.log(counter.count);            // 3
+rename(counter, "visits");
+console.log(counter.name);             // visits
+console.log(keys(counter));            // count,name
+
+// The same instance is the same JavaScript object each time
+console.log(remember(counter));        // 0
+console.log(remember(counter));        // 0
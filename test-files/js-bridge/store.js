@@ -0,0 +1,28 @@
+// A plain JavaScript library that receives compiled class instances
+// (compile main.ts with --enable-js-runtime)
+
+export function describe(counter) {
+    return counter.name + "=" + counter.count;
+}
+
+export function bump(counter, times) {
+    for (let i = 0; i < times; i++) {
+        counter.increment();
+    }
+    return counter.count;
+}
+
+export function rename(counter, name) {
+    counter.name = name;
+}
+
+export function keys(counter) {
+    return Object.keys(counter).sort().join(",");
+}
+
+const seen = [];
+
+export function remember(counter) {
+    seen.push(counter);
+    return seen.indexOf(counter);
+}
@@ -0,0 +1,31 @@
+// Test fixed-size Buffers from the runtime's pool: acquired zero-filled, released for reuse
+
+import { acquire, release } from "perry/buffer-pool";
+
+const zeroes = Buffer.alloc(1024);
+
+// Dirty each buffer before releasing it; the next acquire must still see zeroes
+function reuse(text: string): number {
+    const buf = acquire(1024);
+    const clean = buf.equals(zeroes) ? 1 : 0;
+    buf.write(text);
+    release(buf);
+    return clean;
+}
+
+let clean = 0;
+for (let i = 0; i < 1000; i++) {
+    clean += reuse("request " + i);
+}
+console.log(clean);                    // 1000
+
+const small = acquire(5);
+small.write("hello");
+console.log(small.length);             // 5
+console.log(small.toString());         // hello
+release(small);
+
+// Larger than the biggest size class: allocated directly, then pooled in the 1 MiB class
+const large = acquire(2000000);
+console.log(large.length);             // 2000000
+release(large);
@@ -0,0 +1,43 @@
+// Test dead code elimination: unreachable functions are skipped, everything reachable
+// from top-level code, callbacks and classes still works
+
+function unused(n: number): number {
+    return helperOnlyUsedByUnused(n) * 2;
+}
+
+function helperOnlyUsedByUnused(n: number): number {
+    return n + 1;
+}
+
+function square(n: number): number {
+    return n * n;
+}
+
+// Reached only as a callback value
+function double(n: number): number {
+    return n * 2;
+}
+
+// Reached only from a class method
+function describe(value: number): string {
+    return "value=" + value;
+}
+
+// Reached only from a closure
+function increment(n: number): number {
+    return n + 1;
+}
+
+class Box {
+    constructor(public value: number) {}
+    show(): string {
+        return describe(this.value);
+    }
+}
+
+const add = (n: number) => increment(n);
+
+console.log(square(7));                        // 49
+console.log([1, 2, 3].map(double).join(","));  // 2,4,6
+console.log(new Box(5).show());                // value=5
+console.log(add(41));                          // 42
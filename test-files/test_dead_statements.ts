@@ -0,0 +1,98 @@
+// Test dead statement elimination: code after return/throw/break, untaken constant
+// branches and unused bindings are removed without changing what the program does
+
+const DEBUG = false;
+
+function sign(n: number): string {
+    if (n < 0) {
+        return "negative";
+    } else {
+        return "non-negative";
+    }
+    console.log("never printed");
+}
+console.log(sign(-3));                          // negative
+console.log(sign(4));                           // non-negative
+
+function firstEven(items: number[]): number {
+    for (let i = 0; i < items.length; i++) {
+        if (items[i] % 2 === 0) {
+            return items[i];
+            console.log("never printed");
+        }
+    }
+    throw new Error("no even number");
+    return -1;
+}
+console.log(firstEven([3, 5, 8, 10]));          // 8
+try {
+    firstEven([1, 3]);
+} catch (e) {
+    console.log("caught");                      // caught
+}
+
+// Untaken constant branches
+function describe(n: number): string {
+    const unusedCopy = n;
+    const unusedList = [n, n + 1];
+    if (DEBUG) {
+        console.log("debug: " + n);
+    }
+    while (false) {
+        console.log("never printed");
+    }
+    const label = !DEBUG ? "value" : "debug value";
+    return label + " " + n;
+}
+console.log(describe(7));                       // value 7
+
+// Bindings with side effects stay even when unused
+let calls = 0;
+function bump(): number {
+    calls++;
+    return calls;
+}
+function sideEffects(): number {
+    const ignored = bump();
+    const alsoIgnored = [bump(), 1];
+    return calls;
+}
+console.log(sideEffects());                     // 2
+
+// A closure only used by an unused binding goes with it; a used one keeps its captures
+function counter(start: number): () => number {
+    let count = start;
+    const unusedReader = () => count;
+    const next = () => ++count;
+    return next;
+}
+const next = counter(10);
+next();
+console.log(next());                            // 12
+
+// Switch cases stop at break
+function day(n: number): string {
+    switch (n) {
+        case 0:
+            return "Sunday";
+            break;
+        case 6:
+            return "Saturday";
+        default:
+            break;
+            console.log("never printed");
+    }
+    return "weekday";
+}
+console.log(day(0) + " " + day(6) + " " + day(3));   // Sunday Saturday weekday
+
+// NaN is falsy; bodies with NaN or Infinity literals are pruned like any other
+function limits(n: number): string {
+    const NOT_A_NUMBER = NaN;
+    if (NOT_A_NUMBER) {
+        return "never returned";
+    }
+    const unusedMax = Infinity;
+    return n < Infinity ? "finite" : "infinite";
+}
+console.log(limits(1) + " " + limits(Infinity));   // finite infinite
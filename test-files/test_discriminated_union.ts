@@ -0,0 +1,73 @@
+// Test switches over discriminated unions
+// `perry check` warns (T009) about describe(): it has no case for "triangle"
+
+interface Circle {
+    kind: "circle";
+    radius: number;
+}
+
+interface Square {
+    kind: "square";
+    size: number;
+}
+
+type Triangle = { kind: "triangle"; base: number; height: number };
+
+type Shape = Circle | Square | Triangle;
+
+// Exhaustive: each case sees the narrowed member
+function area(shape: Shape): number {
+    switch (shape.kind) {
+        case "circle":
+            return 3 * shape.radius * shape.radius;
+        case "square":
+            return shape.size * shape.size;
+        case "triangle":
+            return (shape.base * shape.height) / 2;
+    }
+    return 0;
+}
+
+// Labels sharing a body narrow to the union of their members
+function isRound(shape: Shape): boolean {
+    switch (shape.kind) {
+        case "square":
+        case "triangle":
+            return false;
+        default:
+            return true;
+    }
+}
+
+// Not exhaustive and no default
+function describe(shape: Shape): string {
+    switch (shape.kind) {
+        case "circle":
+            return "round";
+        case "square":
+            return "boxy";
+    }
+    return "unknown";
+}
+
+const shapes: Shape[] = [
+    { kind: "circle", radius: 2 },
+    { kind: "square", size: 3 },
+    { kind: "triangle", base: 4, height: 5 },
+];
+
+for (const shape of shapes) {
+    console.log(area(shape));
+    console.log(isRound(shape));
+    console.log(describe(shape));
+}
+// Expected:
+// 12
+// true
+// round
+// 9
+// false
+// boxy
+// 10
+// false
+// unknown
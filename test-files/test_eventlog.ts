@@ -0,0 +1,42 @@
+// Test perry/eventlog: appends, reads from an offset, reopening, compaction and segment deletion
+
+import { openLog } from "perry/eventlog";
+
+const dir = "/tmp/perry_test_eventlog_" + Date.now();
+
+// Small segments so a few records span several files
+let log = openLog(dir, { segmentBytes: 128, fsync: "never" });
+console.log(log.append("created"));            // 0
+const events: string[] = [];
+for (let i = 1; i <= 9; i++) {
+    events.push(JSON.stringify({ n: i }));
+}
+console.log(log.appendBatch(events));          // 1
+console.log(log.nextOffset);                   // 10
+console.log(log.read(0));                      // created
+console.log(log.read(42));                     // undefined
+
+const page = log.readFrom(3, 2);
+console.log(page.length);                      // 2
+console.log(page[0].offset + ":" + page[0].data);  // 3:{"n":3}
+console.log(page[1].offset);                   // 4
+log.close();
+
+// Offsets continue after reopening
+log = openLog(dir, { segmentBytes: 128, fsync: "always" });
+console.log(log.firstOffset);                  // 0
+console.log(log.append("reopened"));           // 10
+
+// Compaction drops rejected records from sealed segments; offsets stay the same
+const removed = log.compact((data: string, offset: number) => offset % 2 === 0);
+console.log(removed > 0);                      // true
+console.log(log.read(1));                      // undefined
+console.log(log.read(4));                      // {"n":4}
+console.log(log.read(10));                     // reopened
+
+// Whole segments before an offset can be dropped
+log.deleteBefore(10);
+console.log(log.firstOffset > 0);              // true
+console.log(log.readFrom(10).length);          // 1
+log.sync();
+log.close();
@@ -0,0 +1,47 @@
+// Test perry/geo: great-circle distance and bearing, point in polygon, bounding boxes
+// and geohashes
+
+import {
+    distance, bearing, destination, pointInPolygon, bbox,
+    geohashEncode, geohashDecode, geohashBounds, geohashNeighbors,
+} from 'perry/geo';
+
+const london = [-0.1278, 51.5074];
+const paris = { type: 'Feature', properties: {}, geometry: { type: 'Point', coordinates: [2.3522, 48.8566] } };
+
+console.log(distance(london, paris).toFixed(1));                        // 343.6
+console.log(distance(london, paris, { units: 'miles' }).toFixed(1));    // 213.5
+console.log(distance(london, london));                                  // 0
+console.log(bearing(london, paris).toFixed(1));                         // 148.1
+
+const east = destination(london, 100, 90);
+console.log(east[0].toFixed(4) + ' ' + east[1].toFixed(4));             // 1.3169 51.4985
+
+// Rings are [longitude, latitude] positions; later rings are holes
+const yard = [
+    [[0, 0], [4, 0], [4, 4], [0, 4], [0, 0]],
+    [[1, 1], [3, 1], [3, 3], [1, 3], [1, 1]],
+];
+console.log(pointInPolygon([0.5, 2], yard));                            // true
+console.log(pointInPolygon([2, 2], yard));                              // false
+console.log(pointInPolygon([4, 2], yard));                              // true
+console.log(pointInPolygon([4, 2], yard, { ignoreBoundary: true }));    // false
+const zone = { type: 'MultiPolygon', coordinates: [yard, [[[10, 10], [12, 10], [11, 12], [10, 10]]]] };
+console.log(pointInPolygon([11, 11], zone));                            // true
+
+const route = { type: 'LineString', coordinates: [[-0.1278, 51.5074], [2.3522, 48.8566], [4.9041, 52.3676]] };
+console.log(bbox(route).join(','));                                     // -0.1278,48.8566,4.9041,52.3676
+
+console.log(geohashEncode(london));                                     // gcpvj0duq
+console.log(geohashEncode(london, 7));                                  // gcpvj0d
+const center = geohashDecode('gcpvj0d');
+console.log(center[0].toFixed(3) + ' ' + center[1].toFixed(3));         // -0.128 51.507
+const cell = geohashBounds('gcpvj0d');
+console.log(cell[0] <= london[0] && london[0] <= cell[2]);              // true
+console.log(geohashNeighbors('gcpvj').join(','));                       // gcpvm,gcpvq,gcpvn,gcpuy,gcpuv,gcpuu,gcpvh,gcpvk
+
+try {
+    geohashDecode('gcpva');
+} catch (e) {
+    console.log('invalid geohash rejected');                            // invalid geohash rejected
+}
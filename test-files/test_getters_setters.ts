@@ -50,3 +50,46 @@ console.log(r.area);  // Should print 200
 // Test direct getter access
 console.log(r.width);   // Should print 20
 console.log(r.height);  // Should print 10
+
+// Inherited accessors
+class Person {
+    first: string;
+    last: string;
+
+    constructor(first: string, last: string) {
+        this.first = first;
+        this.last = last;
+    }
+
+    get fullName(): string {
+        return this.first + " " + this.last;
+    }
+
+    set fullName(value: string) {
+        const parts = value.split(" ");
+        this.first = parts[0];
+        this.last = parts[1];
+    }
+}
+
+class Employee extends Person {
+    title: string;
+
+    constructor(first: string, last: string, title: string) {
+        super(first, last);
+        this.title = title;
+    }
+}
+
+const e = new Employee("Ada", "Lovelace", "Engineer");
+console.log(e.fullName);  // Should print Ada Lovelace
+e.fullName = "Grace Hopper";
+console.log(e.first);     // Should print Grace
+
+// Accessors reached through a value whose class isn't known statically
+function describe(p: any): string {
+    return p.fullName;
+}
+console.log(describe(e));  // Should print Grace Hopper
+const people: Person[] = [new Person("Alan", "Turing")];
+console.log(people[0].fullName);  // Should print Alan Turing
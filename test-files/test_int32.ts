@@ -0,0 +1,48 @@
+// Test int32 fast paths: loop counters, lengths and bit operations, with overflow deopt
+
+// Counters, lengths and literals add, compare and index as int32s
+const data = [3, 1, 4, 1, 5, 9, 2, 6];
+let pairs = 0;
+for (let i = 0; i < data.length; i++) {
+    if (i + 1 < data.length && data[i] < data[i + 1]) {
+        pairs++;
+    }
+}
+console.log(pairs);                            // 4
+
+// Nested bit operations stay in registers
+let hash = 17;
+for (let i = 0; i < 100; i++) {
+    hash = ((hash << 5) - hash + (i & 0xff)) | 0;
+    hash = hash ^ (hash >>> 13);
+}
+console.log(hash);                             // 1166356687
+
+// Overflowing int32 arithmetic deopts to f64
+const big = 2147483647;
+let n = 0;
+for (let i = 1; i < 2; i++) {
+    n = i + big;
+}
+console.log(n);                                // 2147483648
+console.log(big * 2);                          // 4294967294
+console.log(-big - 2);                         // -2147483649
+
+// A zero product with a negative operand is -0
+let zero = 0;
+for (let i = 0; i < 1; i++) {
+    zero = i * -3;
+}
+console.log(1 / zero);                         // -Infinity
+
+// ToInt32 wraps and never traps
+let nan = NaN;
+let inf = Infinity;
+let huge = 4294967301;
+console.log(nan | 0);                          // 0
+console.log(inf | 0);                          // 0
+console.log(huge | 0);                         // 5
+console.log(~huge);                            // -6
+console.log(Infinity | 0);                     // 0
+console.log((-1 >>> 0) | 0);                   // -1
+console.log(-1 >>> 0);                         // 4294967295
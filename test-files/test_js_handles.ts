@@ -0,0 +1,10 @@
+// Test perry/js-handles without the V8 runtime: there are no handles to hold or release
+
+import { dispose, retain, liveHandles } from "perry/js-handles";
+
+console.log(liveHandles().length);     // 0
+console.log(dispose(42));              // false
+console.log(retain(7));                // 7
+
+const value = { name: "native" };
+console.log(dispose(value));           // false
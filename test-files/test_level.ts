@@ -0,0 +1,53 @@
+// Test the level-compatible key-value store: get/put/del, batches and range iterators
+
+import { Level } from 'level';
+
+async function main() {
+    const db = new Level('/tmp/perry_test_level_' + Date.now());
+    console.log(db.status);                              // open
+
+    await db.put('fruit:apple', 'red');
+    await db.put('fruit:banana', 'yellow');
+    await db.put('fruit:cherry', 'dark red');
+    await db.put('veg:carrot', 'orange');
+    console.log(await db.get('fruit:banana'));           // yellow
+    console.log(await db.get('fruit:durian'));           // undefined
+
+    await db.del('fruit:banana');
+    const many = await db.getMany(['fruit:apple', 'fruit:banana']);
+    console.log(many[0] + ' ' + many[1]);                // red undefined
+
+    await db.batch([
+        { type: 'put', key: 'fruit:date', value: 'brown' },
+        { type: 'del', key: 'veg:carrot' },
+    ]);
+    await db.batch().put('veg:kale', 'green').put('veg:leek', 'white').write();
+
+    // Ranges come back in key order
+    const fruits = await db.iterator({ gte: 'fruit:', lt: 'fruit;' }).all();
+    console.log(fruits.length);                          // 3
+    console.log(fruits[0][0] + '=' + fruits[0][1]);      // fruit:apple=red
+
+    const reversed = await db.keys({ reverse: true, limit: 2 }).all();
+    console.log(reversed.join(','));                     // veg:leek,veg:kale
+
+    const it = db.values({ gt: 'fruit:cherry' });
+    console.log(await it.next());                        // brown
+    const rest = await it.nextv(10);
+    console.log(rest.join(','));                         // green,white
+    console.log(await it.next());                        // undefined
+
+    // JSON values
+    const docs = new Level('/tmp/perry_test_level_docs_' + Date.now(), { valueEncoding: 'json' });
+    await docs.put('order:1', { total: 42, items: ['a', 'b'] });
+    const order = await docs.get('order:1');
+    console.log(order.total + ' ' + order.items.length); // 42 2
+
+    await db.clear({ lt: 'veg:' });
+    console.log((await db.keys().all()).length);         // 2
+    await db.close();
+    console.log(db.status);                              // closed
+    await docs.close();
+}
+
+main();
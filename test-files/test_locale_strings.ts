@@ -0,0 +1,25 @@
+// Test Unicode case mapping and locale-aware collation
+
+// Case mapping is Unicode-wide, with language rules when a locale is given
+console.log("straße".toUpperCase());                    // STRASSE
+console.log("ΟΔΟΣ".toLowerCase());                      // οδος
+console.log("istanbul".toLocaleUpperCase("tr"));        // İSTANBUL
+console.log("DIYARBAKIR".toLocaleLowerCase("tr"));      // dıyarbakır
+console.log("istanbul".toLocaleUpperCase("en"));        // ISTANBUL
+
+// Collation orders by alphabet, not by code point
+console.log("a".localeCompare("B", "en") < 0);          // true
+console.log("ä".localeCompare("z", "de") < 0);          // true
+console.log("ä".localeCompare("z", "sv") > 0);          // true
+
+// Options: sensitivity and numeric ordering
+console.log("a".localeCompare("á", "en", { sensitivity: "base" }));     // 0
+console.log("a".localeCompare("A", "en", { sensitivity: "accent" }));   // 0
+console.log("a".localeCompare("A", "en", { sensitivity: "case" }) !== 0); // true
+console.log("item2".localeCompare("item10", "en", { numeric: true }) < 0); // true
+console.log("item2".localeCompare("item10", "en") > 0);                   // true
+
+// Sorting names with a comparator
+const names = ["Zoë", "Émile", "anna", "Ölüm", "bob"];
+names.sort((a, b) => a.localeCompare(b, "en"));
+console.log(names.join(","));                           // anna,bob,Émile,Ölüm,Zoë
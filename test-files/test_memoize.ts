@@ -0,0 +1,54 @@
+// Test compiler-implemented memoization: "use memoize" on functions, @memoize on methods
+
+let calls = 0;
+
+function fib(n: number): number {
+    "use memoize";
+    calls++;
+    return n < 2 ? n : fib(n - 1) + fib(n - 2);
+}
+
+console.log(fib(70));                  // 190392490709135
+console.log(calls);                    // 71
+console.log(fib(70));                  // 190392490709135
+console.log(calls);                    // 71
+
+// Strings are compared by content; at most two results are kept
+let lookups = 0;
+function shout(word: string): string {
+    "use memoize(2)";
+    lookups++;
+    return word.toUpperCase() + "!";
+}
+
+console.log(shout("hi"));              // HI!
+console.log(shout("h" + "i"));         // HI!
+console.log(lookups);                  // 1
+shout("a");
+shout("b");                            // drops "hi"
+console.log(shout("hi"));              // HI!
+console.log(lookups);                  // 4
+
+// Methods are cached per instance: `this` is part of the key
+class Scaler {
+    factor: number;
+    runs: number = 0;
+
+    constructor(factor: number) {
+        this.factor = factor;
+    }
+
+    @memoize
+    scale(x: number): number {
+        this.runs++;
+        return x * this.factor;
+    }
+}
+
+const double = new Scaler(2);
+const triple = new Scaler(3);
+console.log(double.scale(5));          // 10
+console.log(double.scale(5));          // 10
+console.log(triple.scale(5));          // 15
+console.log(double.runs);              // 1
+console.log(triple.runs);              // 1
@@ -0,0 +1,45 @@
+// Test the minisearch-compatible full-text index: ranking, prefix/fuzzy matching and reloading
+
+import MiniSearch from 'minisearch';
+
+const docs = [
+    { id: 1, title: 'Moby Dick', text: 'Call me Ishmael. Some years ago, never mind how long precisely', year: 1851 },
+    { id: 2, title: 'Zen and the Art of Motorcycle Maintenance', text: 'I can see by my watch, without taking my hand from the motorcycle', year: 1974 },
+    { id: 3, title: 'Neuromancer', text: 'The sky above the port was the color of television, tuned to a dead channel', year: 1984 },
+    { id: 4, title: 'Zen and the Art of Archery', text: 'At first sight it must seem intolerably degrading for Zen', year: 1948 },
+];
+
+const index = new MiniSearch({ fields: ['title', 'text'], storeFields: ['title', 'year'] });
+index.addAll(docs);
+console.log(index.documentCount);                        // 4
+
+const zen = index.search('zen');
+console.log(zen.length);                                 // 2
+console.log(zen[0].id + ' ' + zen[0].title);             // 4 Zen and the Art of Archery
+console.log(zen[0].match.zen.join(','));                 // title,text
+console.log(zen[0].year);                                // 1948
+
+console.log(index.search('zen archery', { combineWith: 'AND' }).length);   // 1
+console.log(index.search('moto').length);                // 0
+console.log(index.search('moto', { prefix: true })[0].terms[0]);           // motorcycle
+console.log(index.search('neuromancr', { fuzzy: 0.2 })[0].id);             // 3
+console.log(index.search('ishmael', { fields: ['title'] }).length);        // 0
+
+// Removing and replacing documents
+index.discard(2);
+console.log(index.has(2));                               // false
+index.replace({ id: 3, title: 'Count Zero', text: 'They set a slamhound on Turner', year: 1986 });
+console.log(index.search('neuromancer').length);         // 0
+console.log(index.search('slamhound')[0].title);         // Count Zero
+
+try {
+    index.add({ id: 1, title: 'Duplicate', text: '' });
+} catch (e) {
+    console.log('duplicate rejected');                   // duplicate rejected
+}
+
+// Saved indexes load back without re-indexing
+const fromJson = MiniSearch.loadJSON(index.toJSON(), { fields: ['title', 'text'] });
+console.log(fromJson.search('zen')[0].id);               // 4
+const fromBuffer = MiniSearch.loadBuffer(index.toBuffer(), { fields: ['title', 'text'] });
+console.log(fromBuffer.documentCount);                   // 3
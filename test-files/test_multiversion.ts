@@ -0,0 +1,38 @@
+// @perry-opt: multiversion
+// Test numeric functions compiled for both the baseline and x86-64-v3; the results
+// must match whichever version the CPU runs (PERRY_CPU=baseline forces the first)
+
+function dot(ax: number, ay: number, bx: number, by: number): number {
+    return ax * bx + ay * by;
+}
+
+function quantize(value: number, step: number): number {
+    return Math.floor(value / step) * step;
+}
+
+// Recursion stays inside the version that was called
+function sumTo(n: number): number {
+    if (n <= 0) {
+        return 0.5;
+    }
+    return n + sumTo(n - 1);
+}
+
+// An array parameter: compiled once, like in any other module
+function mean(values: number[]): number {
+    let total = 0;
+    for (const value of values) {
+        total += value;
+    }
+    return total / values.length;
+}
+
+let acc = 0;
+for (let i = 0; i < 100000; i++) {
+    acc += dot(i * 0.5, 1.5, 2, i * 0.25);
+}
+console.log(acc);                       // 6874931250
+console.log(quantize(7.9, 0.5));        // 7.5
+console.log(quantize(-1.2, 1));         // -2
+console.log(sumTo(10));                 // 55.5
+console.log(mean([1, 2, 3, 4]));        // 2.5
@@ -0,0 +1,51 @@
+// Test unions of class instances: fields shared by every member are read at the offset
+// the object's class tag selects, even when the members lay them out differently
+
+class Circle {
+    kind: string;
+    radius: number;
+    label: string;
+    constructor(radius: number, label: string) {
+        this.kind = "circle";
+        this.radius = radius;
+        this.label = label;
+    }
+}
+
+class Square {
+    kind: string;
+    label: string;
+    side: number;
+    constructor(side: number, label: string) {
+        this.kind = "square";
+        this.label = label;
+        this.side = side;
+    }
+}
+
+function labelOf(shape: Circle | Square): string {
+    // `label` is field 2 of Circle but field 1 of Square
+    return shape.kind + ":" + shape.label;
+}
+
+function area(shape: Circle | Square): number {
+    if (shape instanceof Circle) {
+        return Math.PI * shape.radius * shape.radius;
+    }
+    return shape.side * shape.side;
+}
+
+const shapes: (Circle | Square)[] = [new Circle(1, "small"), new Square(3, "box"), new Circle(2, "big")];
+let total = 0;
+for (const shape of shapes) {
+    console.log(labelOf(shape));
+    total += area(shape);
+}
+console.log(Math.round(total * 100) / 100);
+
+let current: Circle | Square = new Square(2, "before");
+current.label = "after";
+console.log(current.label);
+current = new Circle(5, "round");
+current.label = "renamed";
+console.log(current.kind + " " + current.label);
@@ -0,0 +1,31 @@
+// Test inline-cached property reads on untyped objects: hits, misses and shape changes
+
+function getX(p: any): number {
+    return p.x;
+}
+
+// Objects from the same literal share a cache entry
+let sum = 0;
+for (let i = 0; i < 1000; i++) {
+    sum += getX({ x: i, y: 1 });
+}
+console.log(sum);                      // 499500
+
+// Same key at a different position, then a missing key
+console.log(getX({ y: 2, x: 7 }));     // 7
+console.log(getX({ y: 2 }));           // undefined
+console.log(getX({ x: 3, y: 4 }));     // 3
+
+// Keys added after creation
+const grown: any = {};
+grown.y = 5;
+grown.x = 6;
+console.log(getX(grown));              // 6
+grown.x = 8;
+console.log(getX(grown));              // 8
+
+// Non-objects take the slow path
+console.log(getX("text"));             // undefined
+
+const keys = Object.keys({ x: 1, y: 2 });
+console.log(keys.join(","));           // x,y
@@ -0,0 +1,67 @@
+// Test class instances typed through Readonly<C> and Required<C>: the utility types only
+// change modifiers, so fields are read at the class's offsets and methods are called on
+// the class, for parameters and locals alike
+
+class Account {
+    owner: string;
+    balance: number;
+    limit?: number;
+    constructor(owner: string, balance: number, limit?: number) {
+        this.owner = owner;
+        this.balance = balance;
+        this.limit = limit;
+    }
+
+    describe(): string {
+        return this.owner + " has " + this.balance;
+    }
+
+    canSpend(amount: number): boolean {
+        return amount <= this.balance + (this.limit ?? 0);
+    }
+
+    get initial(): string {
+        return this.owner.charAt(0);
+    }
+}
+
+// Parameters
+function summary(account: Readonly<Account>): string {
+    return account.describe() + " (" + account.initial + ")";
+}
+
+function headroom(account: Required<Account>): number {
+    return account.balance + account.limit;
+}
+
+function spendable(account: Readonly<Account>, amount: number): string {
+    return account.owner + (account.canSpend(amount) ? " can spend " : " can't spend ") + amount;
+}
+
+const alice = new Account("Alice", 120, 30);
+const bob = new Account("Bob", 40);
+
+console.log(summary(alice));                    // Alice has 120 (A)
+console.log(summary(bob));                      // Bob has 40 (B)
+console.log(headroom(alice));                   // 150
+console.log(spendable(alice, 140));             // Alice can spend 140
+console.log(spendable(bob, 50));                // Bob can't spend 50
+
+// Locals
+const frozen: Readonly<Account> = new Account("Carol", 75, 5);
+console.log(frozen.owner + " " + frozen.balance);   // Carol 75
+console.log(frozen.describe());                 // Carol has 75
+console.log(frozen.canSpend(80));               // true
+
+const complete: Required<Account> = new Account("Dave", 10, 90);
+console.log(complete.limit * 2);                // 180
+console.log(complete.describe() + " " + complete.initial);   // Dave has 10 D
+
+// Fields written through the class are seen through the utility type
+function deposit(account: Account, amount: number): Readonly<Account> {
+    account.balance += amount;
+    return account;
+}
+const updated: Readonly<Account> = deposit(bob, 25);
+console.log(updated.balance);                   // 65
+console.log(summary(updated));                  // Bob has 65 (B)
@@ -0,0 +1,44 @@
+// Test scalar replacement of objects and arrays that never leave their function
+
+// An options object read and written through constant keys is replaced by locals
+function retryDelay(attempt: number): number {
+    const opts = { base: 100, factor: 2, max: 1000 };
+    opts.factor += 1;
+    let delay = opts.base;
+    for (let i = 0; i < attempt; i++) {
+        delay = delay * opts.factor;
+    }
+    return delay > opts.max ? opts.max : delay;
+}
+console.log(retryDelay(1));                    // 300
+console.log(retryDelay(5));                    // 1000
+
+// Tuples built and taken apart in one function
+function distance(x1: number, y1: number, x2: number, y2: number): number {
+    const d = [x2 - x1, y2 - y1];
+    d[0]++;
+    return Math.sqrt(d[0] * d[0] + d[1] * d[1]) + d.length;
+}
+console.log(distance(0, 0, 2, 4));             // 7
+
+// Escaping objects are kept: returned, passed on, or used as `this`
+function makePoint(x: number, y: number) {
+    const p = { x: x, y: y };
+    return p;
+}
+const point = makePoint(1, 2);
+console.log(point.x + point.y);                // 3
+
+function describe(): string {
+    const counter = { count: 1, label: "n" };
+    const s = JSON.stringify(counter);
+    counter.count = 5;
+    return s + " " + counter.count;
+}
+console.log(describe());                       // {"count":1,"label":"n"} 5
+
+function viaMethod(): number {
+    const box = { v: 4, get() { return this.v * 2; } };
+    return box.get();
+}
+console.log(viaMethod());                      // 8
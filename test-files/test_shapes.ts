@@ -0,0 +1,46 @@
+// Test shared object shapes: literals, added keys, Object.keys copies and dictionaries
+
+function getName(o: any): string {
+    return o.name;
+}
+
+const a: any = { name: "a", size: 1 };
+const b: any = { name: "b", size: 2 };
+console.log(getName(a) + getName(b));  // ab
+
+// Adding a key to one object leaves the other's keys alone
+b.extra = true;
+console.log(Object.keys(a).join(",")); // name,size
+console.log(Object.keys(b).join(",")); // name,size,extra
+console.log(getName(b));               // b
+
+// Object.keys returns a copy
+const keys = Object.keys(a);
+keys.push("bogus");
+console.log(Object.keys(a).length);    // 2
+
+// Objects built up key by key in the same order share a shape too
+function point(x: number, y: number): any {
+    const p: any = {};
+    p.x = x;
+    p.y = y;
+    return p;
+}
+let total = 0;
+for (let i = 0; i < 100; i++) {
+    total += point(i, 1).x;
+}
+console.log(total);                    // 4950
+
+// Objects used as dictionaries stop sharing shapes but keep working
+const dict: any = {};
+for (let i = 0; i < 200; i++) {
+    dict["key" + i] = i;
+}
+console.log(Object.keys(dict).length); // 200
+console.log(dict["key150"]);           // 150
+
+// JSON.stringify tells shaped objects from arrays, nested and inside arrays
+console.log(JSON.stringify({ a: { b: 1 } }));         // {"a":{"b":1}}
+console.log(JSON.stringify([{ x: 1 }, { x: 2 }]));    // [{"x":1},{"x":2}]
+console.log(JSON.stringify({ list: [{ y: "z" }] }));  // {"list":[{"y":"z"}]}
@@ -0,0 +1,23 @@
+// Test that unbounded recursion throws a catchable RangeError instead of crashing
+
+function depth(n: number): number {
+    return depth(n + 1) + 1;
+}
+
+function countDown(n: number): number {
+    if (n === 0) return 0;
+    return countDown(n - 1) + 1;
+}
+
+for (let attempt = 1; attempt <= 2; attempt++) {
+    try {
+        depth(0);
+        console.log("unreachable");
+    } catch (e: any) {
+        // The handler stays installed, so a second overflow is caught too
+        console.log(attempt + ": " + e.name + ": " + e.message);
+    }
+}
+
+// The stack is usable again after unwinding
+console.log(countDown(1000));
@@ -0,0 +1,36 @@
+// Test process.stdout / process.stderr writes interleaved with console.log
+// Reading stdin is covered by piping into a utility such as:
+//   for await (const chunk of process.stdin) process.stdout.write(chunk.toUpperCase());
+
+process.stdout.write("partial ");
+process.stdout.write("line\n");
+console.log("logged");
+
+const ok = process.stdout.write("with callback\n", () => {
+    console.log("written");
+});
+console.log(ok);
+
+process.stdout.write("a");
+console.log("b");
+
+// stderr is a separate stream and not part of the expected output
+process.stderr.write("to stderr\n");
+
+let count = 0;
+for (let i = 0; i < 3; i++) {
+    process.stdout.write(String(i), "utf8", () => {
+        count++;
+    });
+}
+process.stdout.write("\n");
+console.log(count);
+// Expected:
+// partial line
+// logged
+// with callback
+// written
+// true
+// ab
+// 012
+// 3
@@ -0,0 +1,66 @@
+// Test switches over string literal unions, which jump on the discriminant's tag
+// instead of comparing it with every case
+
+type Action = "add" | "remove" | "clear" | "reset" | "";
+
+function reduce(total: number, action: Action, amount: number): number {
+    switch (action) {
+        case "add":
+            return total + amount;
+        case "remove":
+            return total - amount;
+        case "clear":
+        case "reset":
+            return 0;
+        case "":
+            return total;
+    }
+    return -1;
+}
+
+// The default case may come first; it still runs only when no case matches
+function label(event: string): string {
+    let result = "";
+    switch (event) {
+        default:
+            result = "other";
+            break;
+        case "click":
+            result = "pointer";
+            break;
+        case "keydown":
+        case "keyup":
+            result = "keyboard";
+    }
+    return result;
+}
+
+// Fall-through from a matched case and strings built at runtime
+function steps(from: string): number {
+    let count = 0;
+    switch (from) {
+        case "one":
+            count++;
+        case "two":
+            count++;
+        case "three":
+            count++;
+    }
+    return count;
+}
+
+let total = 0;
+const actions: Action[] = ["add", "add", "remove", "", "add"];
+for (let i = 0; i < 1000; i++) {
+    for (const action of actions) {
+        total = reduce(total, action, 2);
+    }
+}
+console.log(total);                    // 4000
+console.log(reduce(total, "clear", 1)); // 0
+console.log(label("click"));           // pointer
+console.log(label("key" + "up"));      // keyboard
+console.log(label("clicks"));          // other
+console.log(steps("o" + "ne"));        // 3
+console.log(steps("three"));           // 1
+console.log(steps("four"));            // 0
@@ -0,0 +1,44 @@
+// Test timers at the end of main: intervals, clearTimeout, unref/ref and introspection
+// The process exits once the only timers left are unref'd ones
+
+let ticks = 0;
+const poll = setInterval(() => {
+    ticks++;
+    if (ticks === 3) {
+        clearInterval(poll);
+        console.log("interval done");
+    }
+}, 10);
+
+const cancelled = setTimeout(() => {
+    console.log("never printed");
+}, 5);
+clearTimeout(cancelled);
+
+// Would keep the process alive for a minute without unref()
+const heartbeat = setInterval(() => {
+    console.log("heartbeat");
+}, 60000);
+heartbeat.unref();
+console.log(heartbeat.hasRef());
+
+const later = setTimeout(() => {
+    console.log("timeout fired");
+}, 50);
+console.log(later.hasRef());
+
+const timers = process.getActiveTimers();
+console.log(timers.length);
+console.log(timers[0].type);
+console.log(timers[timers.length - 1].ref);
+console.log(process.getActiveResourcesInfo().length);
+
+// Expected:
+// false
+// true
+// 3
+// Interval
+// false
+// 2
+// interval done
+// timeout fired
@@ -0,0 +1,79 @@
+// Test that finally blocks run however a try block is left
+
+const log: string[] = [];
+
+function early(n: number): string {
+    try {
+        if (n > 0) {
+            return "positive";
+        }
+        log.push("body");
+    } finally {
+        log.push("cleanup " + n);
+    }
+    return "other";
+}
+
+function rethrows(): void {
+    try {
+        throw new Error("first");
+    } catch (e) {
+        log.push("caught");
+        throw new Error("second");
+    } finally {
+        log.push("finally after catch");
+    }
+}
+
+function loop(): number {
+    let total = 0;
+    for (let i = 0; i < 10; i++) {
+        try {
+            if (i % 2 === 0) continue;
+            if (i > 5) break;
+            total += i;
+        } finally {
+            log.push("iteration " + i);
+        }
+    }
+    return total;
+}
+
+// A return in finally overrides the pending one
+function overrides(): string {
+    try {
+        return "try";
+    } finally {
+        return "finally";
+    }
+}
+
+// The returned value is taken before finally runs
+function snapshot(): number {
+    let x = 1;
+    try {
+        return x;
+    } finally {
+        x = 2;
+    }
+}
+
+console.log(early(1));
+console.log(early(0));
+try {
+    rethrows();
+} catch (e) {
+    console.log("outer caught");
+}
+console.log(loop());
+console.log(overrides());
+console.log(snapshot());
+console.log(log.join(", "));
+// Expected:
+// positive
+// other
+// outer caught
+// 9
+// finally
+// 1
+// cleanup 1, body, cleanup 0, caught, finally after catch, iteration 0, iteration 1, iteration 2, iteration 3, iteration 4, iteration 5, iteration 6, iteration 7
@@ -0,0 +1,64 @@
+// Test typeof / instanceof guards: inside the guarded branch the value is used as
+// a plain number, string or class instance
+
+class Vec2 {
+    x: number;
+    y: number;
+    constructor(x: number, y: number) {
+        this.x = x;
+        this.y = y;
+    }
+    length(): number {
+        return Math.sqrt(this.x * this.x + this.y * this.y);
+    }
+}
+
+function describe(value: any): string {
+    if (typeof value === "number") {
+        return "number " + (value * 2 + 1);
+    }
+    if (typeof value === "string") {
+        return "string " + value.toUpperCase() + " " + value.length;
+    }
+    if (value instanceof Vec2) {
+        return "vector " + value.length();
+    }
+    return "other";
+}
+
+console.log(describe(20));
+console.log(describe("abc"));
+console.log(describe(new Vec2(3, 4)));
+console.log(describe(null));
+
+function sum(values: any[]): number {
+    let total = 0;
+    for (const v of values) {
+        if (typeof v !== "number") {
+            continue;
+        } else {
+            total += v;
+        }
+    }
+    return total;
+}
+console.log(sum([1, "two", 3, null, 4.5]));
+
+// Assigning inside the branch keeps the original variable
+function bump(n: number | string): number | string {
+    if (typeof n === "number") {
+        n = n + 1;
+    }
+    return n;
+}
+console.log(bump(41));
+console.log(bump("x"));
+
+// Expected:
+// number 41
+// string ABC 3
+// vector 5
+// other
+// 8.5
+// 42
+// x